@@ -74,6 +74,12 @@ impl AllowList {
         self.allow.is_match(path) && !self.deny.is_match(path)
     }
 
+    /// The regexes of the allow and deny rules, such as to check that data
+    /// derived with the list is current.
+    pub fn patterns(&self) -> (&[String], &[String]) {
+        (self.allow.patterns(), self.deny.patterns())
+    }
+
     /// Build a new `Allowlist` that adds the allow and deny rules of `other` to `self`.
     pub fn extend(&self, other: &Self) -> Self {
         let allow = add_regexsets(&self.allow, &other.allow);
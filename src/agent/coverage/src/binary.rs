@@ -2,9 +2,8 @@
 // Licensed under the MIT License.
 
 use std::collections::BTreeMap;
-use std::sync::{Arc, Mutex};
 
-use anyhow::{bail, Result};
+use anyhow::Result;
 use debuggable_module::block::Blocks;
 use debuggable_module::Module;
 pub use debuggable_module::{block, path::FilePath, Offset};
@@ -13,6 +12,9 @@ use symbolic::symcache::{SymCache, SymCacheConverter};
 
 use crate::allowlist::AllowList;
 
+mod cache;
+pub use cache::{CacheStats, CachedDebugInfo, DebugInfoCache, DEFAULT_MAX_BLOCKS};
+
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct BinaryCoverage {
     pub modules: BTreeMap<FilePath, ModuleBinaryCoverage>,
@@ -36,6 +38,9 @@ impl BinaryCoverage {
 
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct ModuleBinaryCoverage {
+    /// Identity of the build of the module, if known. See [`Module::id()`].
+    pub id: Option<String>,
+
     pub offsets: BTreeMap<Offset, Count>,
 }
 
@@ -46,6 +51,8 @@ impl ModuleBinaryCoverage {
     }
 
     pub fn add(&mut self, rhs: &Self) {
+        self.id = self.id.take().or_else(|| rhs.id.clone());
+
         for (&offset, &rhs_count) in &rhs.offsets {
             let count = self.offsets.entry(offset).or_default();
             *count += rhs_count;
@@ -53,6 +60,8 @@ impl ModuleBinaryCoverage {
     }
 
     pub fn merge(&mut self, rhs: &Self) {
+        self.id = self.id.take().or_else(|| rhs.id.clone());
+
         for (&offset, &rhs_count) in &rhs.offsets {
             let count = self.offsets.entry(offset).or_default();
             *count = Count::max(*count, rhs_count)
@@ -104,118 +113,8 @@ impl std::ops::AddAssign for Count {
     }
 }
 
-/// Cache of analyzed binary metadata for a set of modules, relative to a common
-/// source allowlist.
-pub struct DebugInfoCache {
-    allowlist: Arc<AllowList>,
-    modules: Arc<Mutex<BTreeMap<FilePath, CachedDebugInfo>>>,
-}
-
-impl DebugInfoCache {
-    pub fn new(allowlist: AllowList) -> Self {
-        let allowlist = Arc::new(allowlist);
-        let modules = Arc::new(Mutex::new(BTreeMap::new()));
-
-        Self { allowlist, modules }
-    }
-
-    pub fn get_or_insert(&self, module: &dyn Module) -> Result<CachedDebugInfo> {
-        if !self.is_cached(module) {
-            self.insert(module)?;
-        }
-
-        if let Some(cached) = self.get(module.executable_path()) {
-            Ok(cached)
-        } else {
-            // Unreachable.
-            bail!("module should be cached but data is missing")
-        }
-    }
-
-    fn get(&self, path: &FilePath) -> Option<CachedDebugInfo> {
-        self.modules.lock().unwrap().get(path).cloned()
-    }
-
-    fn insert(&self, module: &dyn Module) -> Result<()> {
-        let debuginfo = module.debuginfo()?;
-
-        let mut symcache = vec![];
-        let mut converter = SymCacheConverter::new();
-        let exe = Object::parse(module.executable_data())?;
-        converter.process_object(&exe)?;
-        let di = Object::parse(module.debuginfo_data())?;
-        converter.process_object(&di)?;
-        converter.serialize(&mut std::io::Cursor::new(&mut symcache))?;
-        let symcache = SymCache::parse(&symcache)?;
-
-        let mut blocks = Blocks::new();
-
-        for function in debuginfo.functions() {
-            if let Some(location) = symcache.lookup(function.offset.0).next() {
-                if let Some(file) = location.file() {
-                    if !self.allowlist.is_allowed(file.full_path()) {
-                        debug!(
-                            "skipping sweep of `{}:{}` due to excluded source path `{}`",
-                            module.executable_path(),
-                            function.name,
-                            file.full_path(),
-                        );
-                        continue;
-                    }
-                }
-            }
-
-            let fn_blocks =
-                block::sweep_region(module, &debuginfo, function.offset, function.size)?;
-
-            for block in &fn_blocks {
-                if let Some(location) = symcache.lookup(block.offset.0).next() {
-                    if let Some(file) = location.file() {
-                        let path = file.full_path();
-
-                        // Apply allowlists per block, to account for inlining. The `location` values
-                        // here describe the top of the inline-inclusive call stack.
-                        if !self.allowlist.is_allowed(path) {
-                            continue;
-                        }
-
-                        blocks.map.insert(block.offset, *block);
-                    }
-                }
-            }
-        }
-
-        let coverage = ModuleBinaryCoverage::from((&blocks).into_iter().map(|b| b.offset));
-        let cached = CachedDebugInfo::new(blocks, coverage);
-        self.modules
-            .lock()
-            .unwrap()
-            .insert(module.executable_path().clone(), cached);
-
-        Ok(())
-    }
-
-    fn is_cached(&self, module: &dyn Module) -> bool {
-        self.get(module.executable_path()).is_some()
-    }
-}
-
-#[derive(Clone, Debug)]
-pub struct CachedDebugInfo {
-    pub blocks: Blocks,
-    pub coverage: ModuleBinaryCoverage,
-}
-
-impl CachedDebugInfo {
-    pub fn new(blocks: Blocks, coverage: ModuleBinaryCoverage) -> Self {
-        Self { blocks, coverage }
-    }
-}
-
-pub fn find_coverage_sites(
-    module: &dyn Module,
-    source_allowlist: &AllowList,
-) -> Result<ModuleBinaryCoverage> {
+/// The blocks of `module` whose source is allowed by `source_allowlist`.
+pub(crate) fn sweep_module(module: &dyn Module, source_allowlist: &AllowList) -> Result<Blocks> {
     let debuginfo = module.debuginfo()?;
 
     let mut symcache = vec![];
@@ -263,7 +162,17 @@ pub fn find_coverage_sites(
         }
     }
 
-    let coverage = ModuleBinaryCoverage::from((&blocks).into_iter().map(|b| b.offset));
+    Ok(blocks)
+}
+
+pub fn find_coverage_sites(
+    module: &dyn Module,
+    source_allowlist: &AllowList,
+) -> Result<ModuleBinaryCoverage> {
+    let blocks = sweep_module(module, source_allowlist)?;
+
+    let mut coverage = ModuleBinaryCoverage::from((&blocks).into_iter().map(|b| b.offset));
+    coverage.id = module.id();
 
     Ok(coverage)
 }
@@ -0,0 +1,377 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Result};
+use debuggable_module::block::{Block, Blocks};
+use debuggable_module::path::FilePath;
+use debuggable_module::{Module, Offset};
+
+use crate::allowlist::AllowList;
+use crate::binary::{sweep_module, ModuleBinaryCoverage};
+
+/// Default bound on the blocks cached in memory, of about 100 bytes each.
+pub const DEFAULT_MAX_BLOCKS: usize = 1 << 22;
+
+const SITES_HEADER: &str = "# onefuzz coverage sites v1";
+const SITES_EXTENSION: &str = "sites";
+
+/// Cache of analyzed binary metadata for a set of modules, relative to a common
+/// source allowlist.
+///
+/// Modules are keyed by their build identity, so that each build is analyzed
+/// once, wherever it is loaded from, and a rebuilt module is analyzed again.
+/// Modules without an identity are keyed by path. Once more than `max_blocks`
+/// blocks are cached, the least recently used modules are evicted.
+///
+/// With [`DebugInfoCache::persist_to()`], the blocks of identified modules are
+/// also saved to a directory, so that a restarted task skips their analysis.
+pub struct DebugInfoCache {
+    allowlist: AllowList,
+    modules: Mutex<CachedModules>,
+    max_blocks: usize,
+    dir: Option<PathBuf>,
+}
+
+impl DebugInfoCache {
+    pub fn new(allowlist: AllowList) -> Self {
+        Self {
+            allowlist,
+            modules: Mutex::new(CachedModules::default()),
+            max_blocks: DEFAULT_MAX_BLOCKS,
+            dir: None,
+        }
+    }
+
+    /// Bound the blocks cached in memory, such as for targets which load
+    /// hundreds of modules. The module last analyzed is always kept.
+    pub fn max_blocks(mut self, max_blocks: usize) -> Self {
+        self.max_blocks = max_blocks;
+        self
+    }
+
+    /// Save the blocks of each analyzed module in `dir`, and read them from it
+    /// instead of analyzing modules saved by earlier runs.
+    pub fn persist_to(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dir = Some(dir.into());
+        self
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.modules.lock().unwrap().stats
+    }
+
+    pub fn get_or_insert(&self, module: &dyn Module) -> Result<Arc<CachedDebugInfo>> {
+        let id = module.id();
+        let key = match &id {
+            Some(id) => CacheKey::Id(id.clone()),
+            None => CacheKey::Path(module.executable_path().clone()),
+        };
+
+        if let Some(cached) = self.modules.lock().unwrap().get(&key) {
+            return Ok(cached);
+        }
+
+        // Analyzed without holding the lock, so that other modules can be
+        // found meanwhile.
+        let persisted = id.as_deref().and_then(|id| self.load(id));
+        let loaded = persisted.is_some();
+
+        let blocks = match persisted {
+            Some(blocks) => blocks,
+            None => {
+                let blocks = sweep_module(module, &self.allowlist)?;
+
+                if let Some(id) = &id {
+                    if let Err(err) = self.save(id, &blocks) {
+                        warn!(
+                            "unable to save coverage sites of {}: {:?}",
+                            module.executable_path(),
+                            err
+                        );
+                    }
+                }
+
+                blocks
+            }
+        };
+
+        let mut coverage = ModuleBinaryCoverage::from((&blocks).into_iter().map(|b| b.offset));
+        coverage.id = id;
+        let cached = Arc::new(CachedDebugInfo::new(blocks, coverage));
+
+        let mut modules = self.modules.lock().unwrap();
+        if loaded {
+            modules.stats.loaded += 1;
+        } else {
+            modules.stats.analyzed += 1;
+        }
+        modules.insert(key, cached.clone(), self.max_blocks);
+
+        Ok(cached)
+    }
+
+    fn sites_path(&self, id: &str) -> Option<PathBuf> {
+        let dir = self.dir.as_ref()?;
+        Some(dir.join(format!("{id}.{SITES_EXTENSION}")))
+    }
+
+    fn load(&self, id: &str) -> Option<Blocks> {
+        let path = self.sites_path(id)?;
+        let text = fs::read_to_string(&path).ok()?;
+
+        match parse_sites(&text, &self.allowlist) {
+            Ok(Some(blocks)) => Some(blocks),
+            Ok(None) => {
+                debug!("ignoring sites of another allowlist: {}", path.display());
+                None
+            }
+            Err(err) => {
+                warn!("ignoring invalid sites {}: {:?}", path.display(), err);
+                None
+            }
+        }
+    }
+
+    fn save(&self, id: &str, blocks: &Blocks) -> Result<()> {
+        let Some(path) = self.sites_path(id) else {
+            return Ok(());
+        };
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        // Renamed into place, so that other tasks never read a partial file.
+        let partial = path.with_extension(format!("{SITES_EXTENSION}.{}", std::process::id()));
+        fs::write(&partial, write_sites(blocks, &self.allowlist)?)?;
+        fs::rename(&partial, &path)?;
+
+        Ok(())
+    }
+}
+
+/// Counts of the modules found by a [`DebugInfoCache`].
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct CacheStats {
+    /// Modules found in memory.
+    pub hits: usize,
+
+    /// Modules analyzed.
+    pub analyzed: usize,
+
+    /// Modules read from the cache directory.
+    pub loaded: usize,
+
+    /// Modules evicted from memory.
+    pub evicted: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct CachedDebugInfo {
+    pub blocks: Blocks,
+    pub coverage: ModuleBinaryCoverage,
+}
+
+impl CachedDebugInfo {
+    pub fn new(blocks: Blocks, coverage: ModuleBinaryCoverage) -> Self {
+        Self { blocks, coverage }
+    }
+}
+
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+enum CacheKey {
+    Id(String),
+    Path(FilePath),
+}
+
+#[derive(Default)]
+struct CachedModules {
+    modules: BTreeMap<CacheKey, CachedModule>,
+
+    // Total blocks of `modules`.
+    blocks: usize,
+
+    // Incremented on each use of a module, to find the least recently used.
+    clock: u64,
+
+    stats: CacheStats,
+}
+
+struct CachedModule {
+    cached: Arc<CachedDebugInfo>,
+    last_used: u64,
+}
+
+impl CachedModules {
+    fn get(&mut self, key: &CacheKey) -> Option<Arc<CachedDebugInfo>> {
+        let module = self.modules.get_mut(key)?;
+
+        self.clock += 1;
+        module.last_used = self.clock;
+        self.stats.hits += 1;
+
+        Some(module.cached.clone())
+    }
+
+    fn insert(&mut self, key: CacheKey, cached: Arc<CachedDebugInfo>, max_blocks: usize) {
+        self.clock += 1;
+        self.blocks += cached.blocks.map.len();
+
+        let module = CachedModule {
+            cached,
+            last_used: self.clock,
+        };
+        if let Some(old) = self.modules.insert(key.clone(), module) {
+            self.blocks -= old.cached.blocks.map.len();
+        }
+
+        while self.blocks > max_blocks {
+            let lru = self
+                .modules
+                .iter()
+                .filter(|(other, _)| **other != key)
+                .min_by_key(|(_, module)| module.last_used)
+                .map(|(other, _)| other.clone());
+
+            let Some(lru) = lru else {
+                break;
+            };
+
+            if let Some(evicted) = self.modules.remove(&lru) {
+                debug!("evicting coverage sites of {:?}", lru);
+                self.blocks -= evicted.cached.blocks.map.len();
+                self.stats.evicted += 1;
+            }
+        }
+    }
+}
+
+// Sites are saved as text, starting with the rules of the allowlist used to
+// find them, then a line per block, of its offset and size in hex.
+fn sites_header(allowlist: &AllowList) -> Result<String> {
+    let (allow, deny) = allowlist.patterns();
+
+    let mut header = format!("{SITES_HEADER}\n");
+    for pattern in allow {
+        writeln!(header, "allow {pattern}")?;
+    }
+    for pattern in deny {
+        writeln!(header, "deny {pattern}")?;
+    }
+    header.push_str("sites\n");
+
+    Ok(header)
+}
+
+fn write_sites(blocks: &Blocks, allowlist: &AllowList) -> Result<String> {
+    let mut text = sites_header(allowlist)?;
+
+    for block in blocks {
+        writeln!(text, "{:x} {:x}", block.offset.0, block.size)?;
+    }
+
+    Ok(text)
+}
+
+// The blocks of `text`, or `None` if they were found with another allowlist.
+fn parse_sites(text: &str, allowlist: &AllowList) -> Result<Option<Blocks>> {
+    let Some(sites) = text.strip_prefix(&sites_header(allowlist)?) else {
+        return Ok(None);
+    };
+
+    let mut blocks = Blocks::new();
+
+    for line in sites.lines() {
+        let Some((offset, size)) = line.split_once(' ') else {
+            bail!("invalid site: {line:?}");
+        };
+
+        let offset = Offset(u64::from_str_radix(offset, 16)?);
+        let size = u64::from_str_radix(size, 16)?;
+        blocks.map.insert(offset, Block::new(offset, size));
+    }
+
+    Ok(Some(blocks))
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn blocks(blocks: &[(u64, u64)]) -> Blocks {
+        let mut map = Blocks::new();
+        for &(offset, size) in blocks {
+            map.map
+                .insert(Offset(offset), Block::new(Offset(offset), size));
+        }
+        map
+    }
+
+    fn cached(offsets: &[(u64, u64)]) -> Arc<CachedDebugInfo> {
+        let blocks = blocks(offsets);
+        let coverage = ModuleBinaryCoverage::from((&blocks).into_iter().map(|b| b.offset));
+        Arc::new(CachedDebugInfo::new(blocks, coverage))
+    }
+
+    fn key(id: &str) -> CacheKey {
+        CacheKey::Id(id.to_owned())
+    }
+
+    #[test]
+    fn test_sites_round_trip() -> Result<()> {
+        let allowlist = AllowList::parse("src/*\n! src/vendor/*\n")?;
+        let sites = blocks(&[(0x10, 4), (0x1000, 0x20)]);
+
+        let text = write_sites(&sites, &allowlist)?;
+        let parsed = parse_sites(&text, &allowlist)?.expect("sites");
+        assert_eq!(parsed.map, sites.map);
+
+        // Sites found with another allowlist are stale.
+        assert!(parse_sites(&text, &AllowList::default())?.is_none());
+        let extended = allowlist.extend(&AllowList::parse("lib/*\n")?);
+        assert!(parse_sites(&text, &extended)?.is_none());
+
+        assert!(parse_sites(&format!("{text}10\n"), &allowlist).is_err());
+        assert!(parse_sites(&format!("{text}10 x\n"), &allowlist).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_evict_least_recently_used() {
+        let mut modules = CachedModules::default();
+
+        modules.insert(key("a"), cached(&[(1, 1), (2, 1)]), 5);
+        modules.insert(key("b"), cached(&[(1, 1), (2, 1)]), 5);
+        assert!(modules.get(&key("a")).is_some());
+
+        // `b` is the least recently used.
+        modules.insert(key("c"), cached(&[(1, 1), (2, 1)]), 5);
+        assert!(modules.get(&key("b")).is_none());
+        assert!(modules.get(&key("a")).is_some());
+        assert!(modules.get(&key("c")).is_some());
+        assert_eq!(modules.blocks, 4);
+        assert_eq!(modules.stats.evicted, 1);
+
+        // A module larger than the bound is kept alone.
+        modules.insert(
+            key("d"),
+            cached(&[(1, 1), (2, 1), (3, 1), (4, 1), (5, 1), (6, 1)]),
+            5,
+        );
+        assert_eq!(modules.modules.len(), 1);
+        assert_eq!(modules.blocks, 6);
+
+        // Replacing a module doesn't count its old blocks.
+        modules.insert(key("d"), cached(&[(1, 1)]), 5);
+        assert_eq!(modules.blocks, 1);
+    }
+}
@@ -6,12 +6,15 @@ use std::{
     iter::Sum,
 };
 
+use anyhow::{Context, Result};
 use cobertura::{
     Class, Classes, CoberturaCoverage, Line, Lines, Package, Packages, Source, Sources,
 };
 use debuggable_module::path::FilePath;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
 
-use crate::source::SourceCoverage;
+use crate::source::{self, Count, FileCoverage, SourceCoverage};
 
 // Dir -> Set<FilePath>
 type FileMap<'a> = BTreeMap<&'a str, BTreeSet<&'a FilePath>>;
@@ -145,3 +148,164 @@ impl Sum for HitCounts {
         )
     }
 }
+
+/// The source coverage of a Cobertura report, such as one written by
+/// `dotnet-coverage` for managed code, to export and merge like the coverage
+/// converted from recorded modules.
+///
+/// The lines of each `<class>` are those of the file of its `filename`, which
+/// is relative to the report's `<source>` directory if it has exactly one.
+/// Each `<method>` is a function of its class's file, at its first line.
+pub fn parse_source_coverage(xml: &str) -> Result<SourceCoverage> {
+    let mut reader = Reader::from_str(xml);
+    reader.trim_text(true);
+
+    let mut sources = vec![];
+    let mut in_source = false;
+
+    // Files by `filename`, before resolving against `sources`.
+    let mut files: BTreeMap<String, FileCoverage> = BTreeMap::new();
+
+    let mut class: Option<ReadClass> = None;
+    let mut method: Option<ReadMethod> = None;
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) => match e.name().as_ref() {
+                b"source" => in_source = true,
+                b"class" => {
+                    class = Some(ReadClass {
+                        name: attribute(&e, "name")?,
+                        filename: attribute(&e, "filename")?,
+                        file: FileCoverage::default(),
+                    });
+                }
+                b"method" => {
+                    method = Some(ReadMethod {
+                        name: attribute(&e, "name")?,
+                        first: None,
+                    });
+                }
+                b"line" => record_line(&e, class.as_mut(), method.as_mut())?,
+                _ => {}
+            },
+            Event::Empty(e) => {
+                if e.name().as_ref() == b"line" {
+                    record_line(&e, class.as_mut(), method.as_mut())?;
+                }
+            }
+            Event::Text(text) if in_source => sources.push(text.unescape()?.into_owned()),
+            Event::End(e) => match e.name().as_ref() {
+                b"source" => in_source = false,
+                b"method" => {
+                    if let (Some(method), Some(class)) = (method.take(), class.as_mut()) {
+                        if let Some((line, count)) = method.first {
+                            let name = format!("{}.{}", class.name, method.name);
+                            class.file.record_function(&name, line, count);
+                        }
+                    }
+                }
+                b"class" => {
+                    if let Some(class) = class.take() {
+                        files.entry(class.filename).or_default().merge(&class.file);
+                    }
+                }
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    // Sources which are files of the report, as written by `From<&SourceCoverage>`,
+    // aren't directories to resolve against.
+    let base = match sources.as_slice() {
+        [base] if !files.contains_key(base) => Some(base.as_str()),
+        _ => None,
+    };
+
+    let mut coverage = SourceCoverage::default();
+
+    for (filename, file) in files {
+        let path = match base {
+            Some(base) if !is_absolute(&filename) => join(base, &filename),
+            _ => filename,
+        };
+        let path = FilePath::new(path)?;
+
+        coverage.files.entry(path).or_default().merge(&file);
+    }
+
+    Ok(coverage)
+}
+
+// A `<class>` being read.
+struct ReadClass {
+    name: String,
+    filename: String,
+    file: FileCoverage,
+}
+
+// A `<method>` being read, with its first line.
+struct ReadMethod {
+    name: String,
+    first: Option<(source::Line, Count)>,
+}
+
+fn attribute(element: &BytesStart, name: &str) -> Result<String> {
+    let attribute = element.try_get_attribute(name)?.with_context(|| {
+        format!(
+            "missing `{name}` attribute of `<{}>`",
+            String::from_utf8_lossy(element.name().as_ref())
+        )
+    })?;
+
+    Ok(attribute.unescape_value()?.into_owned())
+}
+
+// Record a `<line>` in the file of its class, and as the first line of its
+// method, if it's the first. Lines outside of classes and line 0 are ignored.
+fn record_line(
+    element: &BytesStart,
+    class: Option<&mut ReadClass>,
+    method: Option<&mut ReadMethod>,
+) -> Result<()> {
+    let number: u32 = attribute(element, "number")?
+        .parse()
+        .context("parsing line number")?;
+    let hits: u64 = attribute(element, "hits")?
+        .parse()
+        .context("parsing line hits")?;
+
+    let Ok(line) = source::Line::new(number) else {
+        return Ok(());
+    };
+    let count = Count(u32::try_from(hits).unwrap_or(u32::MAX));
+
+    if let Some(class) = class {
+        class.file.record_line(line, count);
+    }
+
+    if let Some(method) = method {
+        if method.first.map_or(true, |(first, _)| line < first) {
+            method.first = Some((line, count));
+        }
+    }
+
+    Ok(())
+}
+
+// Whether `path` is absolute on any OS, as the report may be from another.
+fn is_absolute(path: &str) -> bool {
+    path.starts_with(['/', '\\']) || path.as_bytes().get(1) == Some(&b':')
+}
+
+fn join(base: &str, path: &str) -> String {
+    let sep = if base.contains('\\') { '\\' } else { '/' };
+    let base = base.trim_end_matches(['/', '\\']);
+
+    format!("{base}{sep}{path}")
+}
+
+#[cfg(test)]
+mod tests;
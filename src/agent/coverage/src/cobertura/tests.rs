@@ -0,0 +1,167 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use anyhow::Result;
+use pretty_assertions::assert_eq;
+
+use crate::source::{FunctionCoverage, Line as SourceLine};
+
+use super::*;
+
+fn line(number: u32) -> SourceLine {
+    SourceLine::new(number).unwrap()
+}
+
+fn lines(file: &FileCoverage) -> Vec<(u32, u32)> {
+    file.lines
+        .iter()
+        .map(|(line, count)| (line.number(), count.0))
+        .collect()
+}
+
+// As written by `dotnet-coverage collect --output-format cobertura`.
+const DOTNET_COVERAGE: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<coverage line-rate="0.6" branch-rate="1" complexity="3" version="1.9" timestamp="1690000000" lines-covered="3" lines-valid="5">
+  <packages>
+    <package line-rate="0.6" branch-rate="1" complexity="3" name="Target">
+      <classes>
+        <class line-rate="0.6" branch-rate="1" complexity="3" name="Target.Program" filename="C:\src\Target\Program.cs">
+          <methods>
+            <method line-rate="1" branch-rate="1" complexity="1" name="Main" signature="(string[])">
+              <lines>
+                <line number="13" hits="1" />
+                <line number="12" hits="1" />
+              </lines>
+            </method>
+            <method line-rate="0.33" branch-rate="1" complexity="2" name="Check" signature="(byte[])">
+              <lines>
+                <line number="20" hits="0" />
+                <line number="21" hits="2" />
+                <line number="22" hits="0" />
+              </lines>
+            </method>
+          </methods>
+          <lines>
+            <line number="12" hits="1" />
+            <line number="13" hits="1" />
+            <line number="20" hits="0" />
+            <line number="21" hits="2" />
+            <line number="22" hits="0" />
+          </lines>
+        </class>
+      </classes>
+    </package>
+  </packages>
+</coverage>
+"#;
+
+#[test]
+fn test_parse_dotnet_coverage() -> Result<()> {
+    let source = parse_source_coverage(DOTNET_COVERAGE)?;
+
+    let path = FilePath::new(r"C:\src\Target\Program.cs")?;
+    assert_eq!(source.files.keys().collect::<Vec<_>>(), [&path]);
+
+    let file = &source.files[&path];
+    assert_eq!(lines(file), [(12, 1), (13, 1), (20, 0), (21, 2), (22, 0)]);
+
+    // Methods are functions of their file, named by class, at their first line.
+    assert_eq!(
+        file.functions["Target.Program.Main"],
+        FunctionCoverage {
+            line: line(12),
+            count: Count(1),
+        }
+    );
+    assert_eq!(
+        file.functions["Target.Program.Check"],
+        FunctionCoverage {
+            line: line(20),
+            count: Count(0),
+        }
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_relative_to_source() -> Result<()> {
+    // Classes of the same file, such as partial classes, are merged.
+    let xml = r#"
+<coverage>
+  <sources>
+    <source>/build/src/</source>
+  </sources>
+  <packages>
+    <package name="Target">
+      <classes>
+        <class name="Target.A" filename="lib/a.cs">
+          <methods />
+          <lines>
+            <line number="1" hits="1" />
+            <line number="0" hits="4" />
+          </lines>
+        </class>
+        <class name="Target.B" filename="lib/a.cs">
+          <lines>
+            <line number="1" hits="3" />
+            <line number="2" hits="0" />
+          </lines>
+        </class>
+        <class name="Target.C" filename="/other/c.cs">
+          <lines>
+            <line number="5" hits="0" />
+          </lines>
+        </class>
+        <class name="Target.Empty" filename="lib/empty.cs" />
+      </classes>
+    </package>
+  </packages>
+</coverage>
+"#;
+    let source = parse_source_coverage(xml)?;
+
+    let paths: Vec<_> = source.files.keys().map(FilePath::as_str).collect();
+    assert_eq!(paths, ["/build/src/lib/a.cs", "/other/c.cs"]);
+
+    // Line 0 is ignored.
+    let file = &source.files[&FilePath::new("/build/src/lib/a.cs")?];
+    assert_eq!(lines(file), [(1, 3), (2, 0)]);
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_invalid() {
+    assert!(parse_source_coverage(r#"<coverage><class name="A"></class></coverage>"#).is_err());
+
+    let xml = r#"<coverage><class name="A" filename="a.cs"><line number="x" hits="1"/></class></coverage>"#;
+    assert!(parse_source_coverage(xml).is_err());
+
+    assert!(parse_source_coverage("<coverage></class>").is_err());
+}
+
+#[test]
+fn test_cobertura_round_trip() -> Result<()> {
+    let mut source = SourceCoverage::default();
+
+    for (path, counts) in [
+        ("/src/main.c", [(1, 1), (2, 0)]),
+        ("/src/lib/check.c", [(10, 2), (11, 0)]),
+    ] {
+        let file = source.files.entry(FilePath::new(path)?).or_default();
+        for (number, count) in counts {
+            file.record_line(line(number), Count(count));
+        }
+    }
+
+    let xml = CoberturaCoverage::from(&source).to_string()?;
+    assert_eq!(parse_source_coverage(&xml)?, source);
+
+    // Including for a report of a single file, whose `<source>` is its path.
+    source.files.retain(|path, _| path.file_name() == "main.c");
+    let xml = CoberturaCoverage::from(&source).to_string()?;
+    assert_eq!(parse_source_coverage(&xml)?, source);
+
+    Ok(())
+}
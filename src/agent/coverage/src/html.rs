@@ -0,0 +1,269 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Static HTML reports of source coverage.
+//!
+//! A report is an `index.html` listing each source file with its share of
+//! lines hit, and a page per file under `files/`, with the source annotated
+//! by the count of each line. Files whose source can't be found are listed in
+//! the index as unmapped, without a page.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Component, Path};
+
+use anyhow::{Context, Result};
+
+use crate::path_map::PathMap;
+use crate::source::{FileCoverage, Line, SourceCoverage};
+
+pub const INDEX_FILE: &str = "index.html";
+
+const FILES_DIR: &str = "files";
+
+const STYLE: &str = "body { font-family: sans-serif; }
+table { border-collapse: collapse; }
+th, td { padding: 0 0.5em; text-align: left; }
+td.line, td.count { color: #666; text-align: right; }
+td.code { font-family: monospace; white-space: pre; }
+tr.hit td.code { background-color: #dfd; }
+tr.miss td.code { background-color: #fdd; }
+tr.unmapped td { color: #999; }
+tr.total td { font-weight: bold; }";
+
+/// Totals over the files of a report.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Summary {
+    pub files: usize,
+
+    /// Files whose source was found.
+    pub mapped_files: usize,
+
+    pub lines: usize,
+    pub hit_lines: usize,
+}
+
+impl Summary {
+    pub fn unmapped_files(&self) -> usize {
+        self.files - self.mapped_files
+    }
+}
+
+/// Write the report of `source` to `output_dir`, replacing any report there.
+///
+/// The path of each file is rewritten by `path_map`, then read relative to
+/// `sources`, unless it is absolute. Files rewritten to the same path, such as
+/// a header recorded at the build path of each target, are reported as one,
+/// with the greatest count of each line.
+pub fn write_report(
+    source: &SourceCoverage,
+    sources: &Path,
+    path_map: &PathMap,
+    output_dir: &Path,
+) -> Result<Summary> {
+    if output_dir.exists() {
+        fs::remove_dir_all(output_dir)
+            .with_context(|| format!("removing old report {}", output_dir.display()))?;
+    }
+    fs::create_dir_all(output_dir)
+        .with_context(|| format!("creating report directory {}", output_dir.display()))?;
+
+    let mut summary = Summary::default();
+    let mut rows = String::new();
+
+    for (mapped, (names, file)) in map_files(source, path_map) {
+        // Name the file by its recorded path, unless several were merged.
+        let name = match names.as_slice() {
+            [name] => name.as_str(),
+            _ => mapped.as_str(),
+        };
+
+        let (hit, lines) = hit_lines(&file);
+        summary.files += 1;
+        summary.lines += lines;
+        summary.hit_lines += hit;
+
+        let path = sources.join(&mapped);
+        let text = match fs::read(&path) {
+            Ok(data) => String::from_utf8_lossy(&data).into_owned(),
+            Err(err) => {
+                debug!("no source for {}: {}: {}", name, path.display(), err);
+                writeln!(
+                    rows,
+                    r#"<tr class="unmapped"><td>{} (unmapped)</td><td>{hit} / {lines}</td><td>{}</td></tr>"#,
+                    escape(name),
+                    percent(hit, lines),
+                )?;
+                continue;
+            }
+        };
+        summary.mapped_files += 1;
+
+        let page = page_path(&mapped);
+        let page_file = output_dir.join(&page);
+        if let Some(dir) = page_file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&page_file, file_page(name, &page, &file, &text)?)
+            .with_context(|| format!("writing report page {}", page_file.display()))?;
+
+        writeln!(
+            rows,
+            r#"<tr><td><a href="{}">{}</a></td><td>{hit} / {lines}</td><td>{}</td></tr>"#,
+            escape(&page),
+            escape(name),
+            percent(hit, lines),
+        )?;
+    }
+
+    let index = output_dir.join(INDEX_FILE);
+    fs::write(&index, index_page(&summary, &rows)?)
+        .with_context(|| format!("writing report index {}", index.display()))?;
+
+    Ok(summary)
+}
+
+// The files of `source` by their rewritten paths, with the recorded paths of
+// each, and their merged coverage.
+fn map_files(
+    source: &SourceCoverage,
+    path_map: &PathMap,
+) -> BTreeMap<String, (Vec<String>, FileCoverage)> {
+    let mut files: BTreeMap<String, (Vec<String>, FileCoverage)> = BTreeMap::new();
+
+    for (file_path, file) in &source.files {
+        let mapped = path_map.map(file_path.as_str());
+        let (names, merged) = files.entry(mapped).or_default();
+        names.push(file_path.as_str().to_owned());
+
+        for (line, count) in &file.lines {
+            merged.record_line(*line, *count);
+        }
+        for (name, function) in &file.functions {
+            merged.record_function(name, function.line, function.count);
+        }
+    }
+
+    files
+}
+
+fn hit_lines(file: &FileCoverage) -> (usize, usize) {
+    let hit = file.lines.values().filter(|count| count.reached()).count();
+    (hit, file.lines.len())
+}
+
+fn percent(hit: usize, total: usize) -> String {
+    if total == 0 {
+        return "-".to_owned();
+    }
+
+    format!("{:.1}%", hit as f64 * 100.0 / total as f64)
+}
+
+// The path of the page of a source file, relative to the report directory.
+// Only the normal components of the path are kept, so that every page is
+// within the report.
+fn page_path(mapped: &str) -> String {
+    let mut page = FILES_DIR.to_owned();
+    for component in Path::new(mapped).components() {
+        if let Component::Normal(name) = component {
+            page.push('/');
+            page.push_str(&name.to_string_lossy());
+        }
+    }
+    page.push_str(".html");
+    page
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn header(title: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n{STYLE}\n</style>\n</head>\n<body>\n",
+        escape(title)
+    )
+}
+
+const FOOTER: &str = "</body>\n</html>\n";
+
+fn index_page(summary: &Summary, rows: &str) -> Result<String> {
+    let mut html = header("Coverage report");
+
+    writeln!(html, "<h1>Coverage report</h1>")?;
+    writeln!(
+        html,
+        r#"<p class="summary">{} of {} lines hit ({}) in {} files, {} unmapped</p>"#,
+        summary.hit_lines,
+        summary.lines,
+        percent(summary.hit_lines, summary.lines),
+        summary.files,
+        summary.unmapped_files(),
+    )?;
+    writeln!(html, r#"<table class="index">"#)?;
+    writeln!(
+        html,
+        "<tr><th>File</th><th>Lines hit</th><th>Coverage</th></tr>"
+    )?;
+    html.push_str(rows);
+    writeln!(
+        html,
+        r#"<tr class="total"><td>Total</td><td>{} / {}</td><td>{}</td></tr>"#,
+        summary.hit_lines,
+        summary.lines,
+        percent(summary.hit_lines, summary.lines),
+    )?;
+    writeln!(html, "</table>")?;
+    html.push_str(FOOTER);
+
+    Ok(html)
+}
+
+fn file_page(name: &str, page: &str, file: &FileCoverage, text: &str) -> Result<String> {
+    let (hit, lines) = hit_lines(file);
+    let index = "../".repeat(page.matches('/').count()) + INDEX_FILE;
+
+    let mut html = header(name);
+    writeln!(html, r#"<p><a href="{index}">Coverage report</a></p>"#)?;
+    writeln!(html, "<h1>{}</h1>", escape(name))?;
+    writeln!(
+        html,
+        r#"<p class="summary">{hit} of {lines} lines hit ({})</p>"#,
+        percent(hit, lines)
+    )?;
+    writeln!(html, r#"<table class="source">"#)?;
+
+    for (number, code) in (1..).zip(text.lines()) {
+        let count = Line::new(number)
+            .ok()
+            .and_then(|line| file.lines.get(&line));
+        let (class, count) = match count {
+            Some(count) if count.reached() => (r#" class="hit""#, count.0.to_string()),
+            Some(count) => (r#" class="miss""#, count.0.to_string()),
+            None => ("", String::new()),
+        };
+        writeln!(
+            html,
+            r#"<tr{class}><td class="line">{number}</td><td class="count">{count}</td><td class="code">{}</td></tr>"#,
+            escape(code),
+        )?;
+    }
+
+    writeln!(html, "</table>")?;
+    html.push_str(FOOTER);
+
+    Ok(html)
+}
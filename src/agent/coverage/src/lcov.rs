@@ -0,0 +1,58 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Source coverage as an lcov tracefile (`.info`), as read by `genhtml` and
+//! most CI coverage gates.
+//!
+//! Each source file is a record of `SF:` (its path), `FN:` and `FNDA:` (the
+//! entry line and count of each function), `DA:` (the count of each line), and
+//! the `FNF`/`FNH`/`LF`/`LH` totals, ending with `end_of_record`. See the
+//! TRACEFILE FORMAT section of `geninfo(1)`.
+
+use std::fmt::{self, Write};
+
+use crate::source::{FileCoverage, SourceCoverage};
+
+/// The lcov tracefile of `source`.
+pub struct Lcov<'a>(pub &'a SourceCoverage);
+
+impl<'a> fmt::Display for Lcov<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (file_path, file) in &self.0.files {
+            writeln!(f, "TN:")?;
+            writeln!(f, "SF:{file_path}")?;
+            write_file(f, file)?;
+            writeln!(f, "end_of_record")?;
+        }
+
+        Ok(())
+    }
+}
+
+fn write_file(f: &mut impl Write, file: &FileCoverage) -> fmt::Result {
+    // Functions are listed by line, as `genhtml` expects.
+    let mut functions: Vec<_> = file.functions.iter().collect();
+    functions.sort_by_key(|(name, function)| (function.line, *name));
+
+    for (name, function) in &functions {
+        writeln!(f, "FN:{},{}", function.line.number(), name)?;
+    }
+    for (name, function) in &functions {
+        writeln!(f, "FNDA:{},{}", function.count.0, name)?;
+    }
+    writeln!(f, "FNF:{}", functions.len())?;
+    let hit = functions
+        .iter()
+        .filter(|(_, function)| function.count.reached())
+        .count();
+    writeln!(f, "FNH:{hit}")?;
+
+    for (line, count) in &file.lines {
+        writeln!(f, "DA:{},{}", line.number(), count.0)?;
+    }
+    writeln!(f, "LF:{}", file.lines.len())?;
+    let hit = file.lines.values().filter(|count| count.reached()).count();
+    writeln!(f, "LH:{hit}")?;
+
+    Ok(())
+}
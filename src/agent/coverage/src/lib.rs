@@ -7,6 +7,11 @@ extern crate log;
 pub mod allowlist;
 pub mod binary;
 pub mod cobertura;
+pub mod html;
+pub mod lcov;
+pub mod merge;
+pub mod module_filter;
+pub mod path_map;
 pub mod record;
 pub mod source;
 mod timer;
@@ -15,4 +20,10 @@ mod timer;
 pub use allowlist::AllowList;
 
 #[doc(inline)]
-pub use record::{CoverageRecorder, Recorded};
+pub use module_filter::ModuleFilter;
+
+#[doc(inline)]
+pub use record::{ChildLimits, CoverageRecorder, Recorded};
+
+#[doc(inline)]
+pub use timer::TimerError;
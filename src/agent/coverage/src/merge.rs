@@ -0,0 +1,157 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Merging of the coverage recorded by several tasks, such as of targets built
+//! from the same sources for each OS.
+//!
+//! Modules are matched by their build identity, so that a module recorded at a
+//! different path by each task is merged, and modules which share a path but
+//! are different builds are kept apart. Modules without an identity, such as
+//! those recorded by older agents, are matched by path.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+use debuggable_module::path::FilePath;
+
+use crate::binary::{BinaryCoverage, ModuleBinaryCoverage};
+
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+enum ModuleKey {
+    Id(String),
+    Path(FilePath),
+}
+
+/// Merge the coverage of each module of `inputs`, matched by identity. The
+/// count of each offset is its greatest count in any input.
+///
+/// A merged module is keyed by the first path it was recorded at. When several
+/// modules share a path, each identified module is instead keyed by the path
+/// with its identity added as a directory, as `<dir>/<id>/<file name>`.
+pub fn merge_coverage<'a>(
+    inputs: impl IntoIterator<Item = &'a BinaryCoverage>,
+) -> Result<BinaryCoverage> {
+    let mut merged: BTreeMap<ModuleKey, (FilePath, ModuleBinaryCoverage)> = BTreeMap::new();
+
+    for coverage in inputs {
+        for (path, module) in &coverage.modules {
+            let key = match &module.id {
+                Some(id) => ModuleKey::Id(id.clone()),
+                None => ModuleKey::Path(path.clone()),
+            };
+
+            let (_, entry) = merged
+                .entry(key)
+                .or_insert_with(|| (path.clone(), ModuleBinaryCoverage::default()));
+            entry.merge(module);
+        }
+    }
+
+    let mut modules_by_path: BTreeMap<FilePath, usize> = BTreeMap::new();
+    for (path, _) in merged.values() {
+        *modules_by_path.entry(path.clone()).or_default() += 1;
+    }
+
+    let mut output = BinaryCoverage::default();
+
+    for (key, (path, module)) in merged {
+        let path = match key {
+            ModuleKey::Id(id) if modules_by_path[&path] > 1 => with_id(&path, &id)?,
+            _ => path,
+        };
+
+        output.modules.insert(path, module);
+    }
+
+    Ok(output)
+}
+
+// `path` with `id` added as the directory of its file.
+fn with_id(path: &FilePath, id: &str) -> Result<FilePath> {
+    let (dir, name) = split_path(path);
+    let sep = dir.chars().last().unwrap_or('/');
+
+    FilePath::new(format!("{dir}{id}{sep}{name}"))
+}
+
+// The directory of `path`, with its trailing separator, and its file name.
+//
+// Either separator is split on, as the path may have been recorded on another
+// OS.
+fn split_path(path: &FilePath) -> (&str, &str) {
+    let path = path.as_str();
+
+    match path.rfind(['/', '\\']) {
+        Some(i) => path.split_at(i + 1),
+        None => ("", path),
+    }
+}
+
+/// Coverage of the modules of `merged` which can be found locally, to convert
+/// to source coverage, such as for modules recorded on other machines.
+///
+/// Each module is searched for at its path, then by file name in each of
+/// `module_dirs`. An identified module is only found in a file of the same
+/// identity. The paths of the modules which weren't found are returned.
+pub fn find_modules(
+    merged: &BinaryCoverage,
+    module_dirs: &[impl AsRef<Path>],
+) -> Result<(BinaryCoverage, Vec<FilePath>)> {
+    let mut found = BinaryCoverage::default();
+    let mut missing = vec![];
+
+    for (path, module) in &merged.modules {
+        let candidates = std::iter::once(path.as_path().to_owned()).chain(
+            module_dirs
+                .iter()
+                .map(|dir| dir.as_ref().join(split_path(path).1)),
+        );
+
+        let local = candidates
+            .filter(|candidate| candidate.is_file())
+            .find(|candidate| match &module.id {
+                Some(id) => {
+                    fs::read(candidate)
+                        .ok()
+                        .and_then(|data| debuggable_module::module_id(&data))
+                        .as_ref()
+                        == Some(id)
+                }
+                None => true,
+            });
+
+        match local {
+            Some(local) => {
+                let local = FilePath::new(local.to_string_lossy())?;
+                found.modules.entry(local).or_default().merge(module);
+            }
+            None => missing.push(path.clone()),
+        }
+    }
+
+    Ok((found, missing))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_with_id() -> Result<()> {
+        let path = FilePath::new("/setup/fuzz")?;
+        assert_eq!(with_id(&path, "abcd")?.as_str(), "/setup/abcd/fuzz");
+
+        let path = FilePath::new(r"C:\setup\fuzz.exe")?;
+        assert_eq!(
+            with_id(&path, "ABCD1")?.as_str(),
+            r"C:\setup\ABCD1\fuzz.exe"
+        );
+
+        let path = FilePath::new("fuzz")?;
+        assert_eq!(with_id(&path, "abcd")?.as_str(), "abcd/fuzz");
+
+        Ok(())
+    }
+}
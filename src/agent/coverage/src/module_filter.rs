@@ -0,0 +1,126 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use anyhow::Result;
+use debuggable_module::path::FilePath;
+use regex::{RegexSet, RegexSetBuilder};
+
+/// Regexes selecting the modules to record coverage for.
+///
+/// A module is matched by a regex that matches either its file name or its
+/// full path, ignoring case on Windows. When there are no `allow` regexes,
+/// modules are allowed unless denied.
+#[derive(Clone, Debug)]
+pub struct ModuleFilter {
+    allow: RegexSet,
+    deny: RegexSet,
+}
+
+impl ModuleFilter {
+    pub fn new<A, D>(allow: &[A], deny: &[D]) -> Result<Self>
+    where
+        A: AsRef<str>,
+        D: AsRef<str>,
+    {
+        let allow = build(allow)?;
+        let deny = build(deny)?;
+
+        Ok(Self { allow, deny })
+    }
+
+    pub fn is_allowed(&self, path: &FilePath) -> bool {
+        let allowed = self.allow.is_empty() || is_match(&self.allow, path);
+        allowed && !is_match(&self.deny, path)
+    }
+}
+
+impl Default for ModuleFilter {
+    fn default() -> Self {
+        Self {
+            allow: RegexSet::empty(),
+            deny: RegexSet::empty(),
+        }
+    }
+}
+
+fn build<S: AsRef<str>>(patterns: &[S]) -> Result<RegexSet> {
+    let patterns = patterns.iter().map(|p| p.as_ref());
+    let set = RegexSetBuilder::new(patterns)
+        .case_insensitive(cfg!(windows))
+        .build()?;
+
+    Ok(set)
+}
+
+fn is_match(set: &RegexSet, path: &FilePath) -> bool {
+    set.is_match(path.file_name()) || set.is_match(path.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(path: &str) -> FilePath {
+        FilePath::new(path).unwrap()
+    }
+
+    #[test]
+    fn test_default() {
+        let filter = ModuleFilter::default();
+
+        assert!(filter.is_allowed(&path("/usr/lib/libc.so.6")));
+        assert!(filter.is_allowed(&path("/setup/fuzz.exe")));
+    }
+
+    #[test]
+    fn test_deny() -> Result<()> {
+        let none: &[&str] = &[];
+        let filter = ModuleFilter::new(none, &[r"^libc\.so", "^/usr/lib/clang/"])?;
+
+        // By file name.
+        assert!(!filter.is_allowed(&path("/usr/lib/libc.so.6")));
+
+        // By full path.
+        assert!(!filter.is_allowed(&path(
+            "/usr/lib/clang/16/lib/linux/libclang_rt.asan-x86_64.so"
+        )));
+
+        // Anchored to the file name, so other modules in the same directory are
+        // allowed.
+        assert!(filter.is_allowed(&path("/usr/lib/liblibc.so")));
+        assert!(filter.is_allowed(&path("/setup/fuzz")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_allow_deny() -> Result<()> {
+        let filter = ModuleFilter::new(&["^/setup/"], &[r"^helper\.so$"])?;
+
+        assert!(filter.is_allowed(&path("/setup/fuzz")));
+        assert!(filter.is_allowed(&path("/setup/lib/target.so")));
+        assert!(!filter.is_allowed(&path("/usr/lib/libc.so.6")));
+
+        // Denied even though allowed.
+        assert!(!filter.is_allowed(&path("/setup/helper.so")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_case() -> Result<()> {
+        let none: &[&str] = &[];
+        let filter = ModuleFilter::new(none, &[r"^kernel32\.dll$"])?;
+
+        let allowed = filter.is_allowed(&path(r"C:\Windows\System32\KERNEL32.DLL"));
+        assert_eq!(allowed, cfg!(not(windows)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid() {
+        let none: &[&str] = &[];
+        assert!(ModuleFilter::new(&["("], none).is_err());
+    }
+}
@@ -0,0 +1,185 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Rewriting of the source paths of debug info, such as to find the sources
+//! of a target built on another machine.
+
+/// A rule replacing the directory prefix `from` of a path with `to`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PathRule {
+    pub from: String,
+    pub to: String,
+}
+
+impl PathRule {
+    pub fn new(from: impl Into<String>, to: impl Into<String>) -> Self {
+        Self {
+            from: from.into(),
+            to: to.into(),
+        }
+    }
+}
+
+/// Rules to rewrite paths with. Only the rule with the longest `from` which is
+/// a directory prefix of a path is applied, or the first such rule, if several
+/// are as long.
+///
+/// Both `/` and `\` separate directories, and paths are rewritten with `/`.
+/// On Windows, prefixes are matched ignoring case, as paths are recorded in
+/// lowercase.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct PathMap {
+    rules: Vec<PathRule>,
+}
+
+impl PathMap {
+    pub fn new(rules: Vec<PathRule>) -> Self {
+        Self { rules }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Add `rules`, such as from a file, to those of the map.
+    pub fn extend(&mut self, rules: impl IntoIterator<Item = PathRule>) {
+        self.rules.extend(rules);
+    }
+
+    /// `path` rewritten by the rule matching it, or else only normalized.
+    pub fn map(&self, path: &str) -> String {
+        self.try_map(path)
+            .unwrap_or_else(|| path.replace('\\', "/"))
+    }
+
+    /// `path` rewritten by the rule matching it, if any.
+    pub fn try_map(&self, path: &str) -> Option<String> {
+        let path = path.replace('\\', "/");
+
+        let mut matched: Option<(usize, &str, &PathRule)> = None;
+
+        for rule in &self.rules {
+            let from = rule.from.replace('\\', "/");
+            let from = from.trim_end_matches('/');
+            let Some(rest) = strip_dir_prefix(&path, from) else {
+                continue;
+            };
+
+            if matched.map_or(true, |(len, _, _)| from.len() > len) {
+                matched = Some((from.len(), rest, rule));
+            }
+        }
+
+        let (_, rest, rule) = matched?;
+
+        let to = rule.to.replace('\\', "/");
+        let to = to.trim_end_matches('/');
+        let mapped = match (to.is_empty(), rest.is_empty()) {
+            (true, _) => rest.to_owned(),
+            (false, true) => to.to_owned(),
+            (false, false) => format!("{to}/{rest}"),
+        };
+
+        Some(mapped)
+    }
+}
+
+// The rest of `path` after the directory `prefix`, without a leading `/`.
+fn strip_dir_prefix<'a>(path: &'a str, prefix: &str) -> Option<&'a str> {
+    if path.len() < prefix.len() || !path.is_char_boundary(prefix.len()) {
+        return None;
+    }
+
+    let (head, rest) = path.split_at(prefix.len());
+    let matched = if cfg!(windows) {
+        head.eq_ignore_ascii_case(prefix)
+    } else {
+        head == prefix
+    };
+    if !matched {
+        return None;
+    }
+
+    if rest.is_empty() {
+        Some(rest)
+    } else {
+        rest.strip_prefix('/')
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_map() {
+        let map = PathMap::new(vec![
+            PathRule::new("/build/project/", ""),
+            PathRule::new("/build", "/src"),
+            PathRule::new(r"C:\agent\_work\1\s", "project"),
+        ]);
+
+        assert_eq!(map.map("/build/project/src/main.c"), "src/main.c");
+        assert_eq!(map.map("/build/other/lib.c"), "/src/other/lib.c");
+        assert_eq!(map.map("/build"), "/src");
+        assert_eq!(
+            map.map(r"C:\agent\_work\1\s\src\main.c"),
+            "project/src/main.c"
+        );
+
+        // Prefixes only match whole directories.
+        assert_eq!(map.map("/buildroot/main.c"), "/buildroot/main.c");
+
+        // Unmatched paths are only normalized.
+        assert_eq!(map.map("/usr/include/stdio.h"), "/usr/include/stdio.h");
+        assert_eq!(map.map(r"D:\src\main.c"), "D:/src/main.c");
+
+        assert_eq!(PathMap::default().map("src/main.c"), "src/main.c");
+    }
+
+    #[test]
+    fn test_map_longest_prefix() {
+        let map = PathMap::new(vec![
+            PathRule::new("/w", "/outer"),
+            PathRule::new("/w/1/s", "project"),
+            PathRule::new("/w/1", "/inner"),
+        ]);
+
+        // The longest matching prefix is applied, whatever the order of rules.
+        assert_eq!(map.map("/w/1/s/src/main.c"), "project/src/main.c");
+        assert_eq!(map.map("/w/1/b/gen.c"), "/inner/b/gen.c");
+        assert_eq!(map.map("/w/2/main.c"), "/outer/2/main.c");
+
+        // Trailing separators don't count towards the length of a prefix.
+        let map = PathMap::new(vec![
+            PathRule::new("/w/1/", "first"),
+            PathRule::new("/w/1", "second"),
+        ]);
+        assert_eq!(map.map("/w/1/main.c"), "first/main.c");
+    }
+
+    #[test]
+    fn test_try_map() {
+        let map = PathMap::new(vec![PathRule::new(r"D:\a\1\s", "")]);
+
+        assert_eq!(
+            map.try_map(r"D:\a\1\s\src\main.c").as_deref(),
+            Some("src/main.c")
+        );
+        assert_eq!(map.try_map(r"D:\b\main.c"), None);
+        assert_eq!(map.map(r"D:\b\main.c"), "D:/b/main.c");
+    }
+
+    #[test]
+    fn test_map_case() {
+        let map = PathMap::new(vec![PathRule::new(r"D:\a\1\s", "project")]);
+        let mapped = map.try_map(r"d:\A\1\S\main.c");
+
+        // Paths are recorded in lowercase on Windows, but not elsewhere.
+        if cfg!(windows) {
+            assert_eq!(mapped.as_deref(), Some("project/main.c"));
+        } else {
+            assert_eq!(mapped, None);
+        }
+    }
+}
@@ -1,28 +1,59 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
+use std::collections::BTreeSet;
 use std::process::{Command, Stdio};
 use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::Result;
 use debuggable_module::loader::Loader;
+use debuggable_module::path::FilePath;
 
 use crate::binary::{BinaryCoverage, DebugInfoCache};
-use crate::AllowList;
+use crate::{AllowList, ModuleFilter};
 
 #[cfg(target_os = "linux")]
 pub mod linux;
 
+#[cfg(target_os = "macos")]
+pub mod macos;
+
 #[cfg(target_os = "windows")]
 pub mod windows;
 
 pub struct CoverageRecorder {
     module_allowlist: AllowList,
+    module_filter: ModuleFilter,
     cache: Arc<DebugInfoCache>,
     cmd: Command,
     loader: Arc<Loader>,
     timeout: Duration,
+    follow_children: bool,
+    child_limits: ChildLimits,
+}
+
+/// Limits on the descendants of the target recorded when following children,
+/// so that a runaway tree of processes can't exhaust the recorder.
+///
+/// Descendants beyond the limits still run, but aren't recorded.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ChildLimits {
+    /// Greatest depth of a recorded descendant, where children of the target
+    /// have depth 1.
+    pub max_depth: usize,
+
+    /// Greatest count of recorded descendants.
+    pub max_count: usize,
+}
+
+impl Default for ChildLimits {
+    fn default() -> Self {
+        Self {
+            max_depth: 4,
+            max_count: 32,
+        }
+    }
 }
 
 impl CoverageRecorder {
@@ -35,10 +66,13 @@ impl CoverageRecorder {
 
         Self {
             module_allowlist: AllowList::default(),
+            module_filter: ModuleFilter::default(),
             cache: Arc::new(DebugInfoCache::new(AllowList::default())),
             cmd,
             loader,
             timeout,
+            follow_children: false,
+            child_limits: ChildLimits::default(),
         }
     }
 
@@ -47,6 +81,11 @@ impl CoverageRecorder {
         self
     }
 
+    pub fn module_filter(mut self, module_filter: ModuleFilter) -> Self {
+        self.module_filter = module_filter;
+        self
+    }
+
     pub fn loader(mut self, loader: impl Into<Arc<Loader>>) -> Self {
         self.loader = loader.into();
         self
@@ -62,6 +101,21 @@ impl CoverageRecorder {
         self
     }
 
+    /// Also record the processes started by the target, such as helpers it
+    /// spawns, and their descendants, merging their coverage by module.
+    ///
+    /// Recording ends when the target exits, killing any descendants still
+    /// running. Not supported on macOS.
+    pub fn follow_children(mut self, follow_children: bool) -> Self {
+        self.follow_children = follow_children;
+        self
+    }
+
+    pub fn child_limits(mut self, child_limits: ChildLimits) -> Self {
+        self.child_limits = child_limits;
+        self
+    }
+
     #[cfg(target_os = "linux")]
     pub fn record(self) -> Result<Recorded> {
         use std::sync::Mutex;
@@ -80,8 +134,16 @@ impl CoverageRecorder {
             let child_pid = child_pid.clone();
 
             timer::timed(self.timeout, move || {
-                let mut recorder = LinuxRecorder::new(&loader, self.module_allowlist, &self.cache);
+                let mut recorder = LinuxRecorder::new(
+                    &loader,
+                    self.module_allowlist,
+                    self.module_filter,
+                    &self.cache,
+                );
                 let mut dbg = Debugger::new(&mut recorder);
+                if self.follow_children {
+                    dbg.follow_children(self.child_limits);
+                }
                 let child = dbg.spawn(self.cmd)?;
 
                 // Save child PID so we can send SIGKILL on timeout.
@@ -93,8 +155,13 @@ impl CoverageRecorder {
 
                 let output = dbg.wait(child)?;
                 let coverage = recorder.coverage;
+                let skipped_modules = recorder.skipped_modules;
 
-                Ok(Recorded { coverage, output })
+                Ok(Recorded {
+                    coverage,
+                    output,
+                    skipped_modules,
+                })
             })
         };
 
@@ -118,6 +185,82 @@ impl CoverageRecorder {
         recorded?
     }
 
+    /// Only `x86_64` targets are supported, as blocks are only found in
+    /// `x86_64` code.
+    #[cfg(target_os = "macos")]
+    pub fn record(self) -> Result<Recorded> {
+        use std::sync::Mutex;
+
+        use anyhow::bail;
+
+        use crate::timer;
+        use macos::debugger::Debugger;
+        use macos::MacOSRecorder;
+
+        if !cfg!(target_arch = "x86_64") {
+            bail!("recording coverage on macOS is only supported for x86_64");
+        }
+
+        if self.follow_children {
+            warn!("following child processes is not supported on macOS, recording only the target");
+        }
+
+        let loader = self.loader.clone();
+
+        let child_pid: Arc<Mutex<Option<u32>>> = Arc::new(Mutex::new(None));
+
+        let recorded = {
+            let child_pid = child_pid.clone();
+
+            timer::timed(self.timeout, move || {
+                let mut recorder = MacOSRecorder::new(
+                    &loader,
+                    self.module_allowlist,
+                    self.module_filter,
+                    &self.cache,
+                );
+                let mut dbg = Debugger::new(&mut recorder);
+                let child = dbg.spawn(self.cmd)?;
+
+                // Save child PID so we can send SIGKILL on timeout.
+                if let Ok(mut pid) = child_pid.lock() {
+                    *pid = Some(child.id());
+                } else {
+                    bail!("couldn't lock mutex to save child PID ");
+                }
+
+                let output = dbg.wait(child)?;
+                let coverage = recorder.coverage;
+                let skipped_modules = recorder.skipped_modules;
+
+                Ok(Recorded {
+                    coverage,
+                    output,
+                    skipped_modules,
+                })
+            })
+        };
+
+        if let Err(timer::TimerError::Timeout(..)) = &recorded {
+            let Ok(pid) = child_pid.lock() else {
+                bail!("couldn't lock mutex to kill child PID");
+            };
+
+            if let Some(pid) = *pid {
+                use nix::sys::signal::{kill, SIGKILL};
+
+                let pid = nix::unistd::Pid::from_raw(pid as i32);
+
+                // Try to clean up, ignore errors due to earlier exits.
+                let _ = kill(pid, SIGKILL);
+            } else {
+                warn!("timeout before PID set for child process");
+            }
+        }
+
+        recorded?
+    }
+
     #[cfg(target_os = "windows")]
     pub fn record(self) -> Result<Recorded> {
         use anyhow::bail;
@@ -125,7 +268,11 @@ impl CoverageRecorder {
         use process_control::{ChildExt, Control};
         use windows::WindowsRecorder;
 
-        let child = Debugger::create_child(self.cmd)?;
+        let child = if self.follow_children {
+            Debugger::create_child_with_children(self.cmd)?
+        } else {
+            Debugger::create_child(self.cmd)?
+        };
 
         // Spawn a thread to wait for the target process to exit.
         let taget_process = std::thread::spawn(move || {
@@ -138,8 +285,15 @@ impl CoverageRecorder {
         });
 
         let loader = self.loader.clone();
-        let mut recorder =
-            WindowsRecorder::new(&loader, self.module_allowlist, self.cache.as_ref());
+        let mut recorder = WindowsRecorder::new(
+            &loader,
+            self.module_allowlist,
+            self.module_filter,
+            self.cache.as_ref(),
+        );
+        if self.follow_children {
+            recorder.follow_children(self.child_limits);
+        }
 
         // The debugger is initialized in the same thread that created the target process to be able to receive the debug events
         let mut dbg = Debugger::init_debugger(&mut recorder)?;
@@ -168,9 +322,11 @@ impl CoverageRecorder {
         }
 
         let coverage = recorder.coverage;
+        let skipped_modules = recorder.skipped_modules;
         Ok(Recorded {
             coverage,
             output: output.into(),
+            skipped_modules,
         })
     }
 }
@@ -179,6 +335,10 @@ impl CoverageRecorder {
 pub struct Recorded {
     pub coverage: BinaryCoverage,
     pub output: Output,
+
+    /// Modules loaded by the target but excluded from coverage by the module
+    /// allowlist or filter.
+    pub skipped_modules: BTreeSet<FilePath>,
 }
 
 #[derive(Clone, Debug, Default)]
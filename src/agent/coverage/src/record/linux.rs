@@ -1,7 +1,7 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
 use anyhow::{bail, Result};
 use debuggable_module::linux::LinuxModule;
@@ -16,30 +16,37 @@ use debugger::{DebugEventHandler, DebuggerContext, ModuleImage};
 
 use crate::allowlist::AllowList;
 use crate::binary::{BinaryCoverage, DebugInfoCache};
+use crate::module_filter::ModuleFilter;
 
 pub struct LinuxRecorder<'cache, 'data> {
     module_allowlist: AllowList,
+    module_filter: ModuleFilter,
     cache: &'cache DebugInfoCache,
     pub coverage: BinaryCoverage,
     loader: &'data Loader,
     modules: BTreeMap<FilePath, LinuxModule<'data>>,
+    pub skipped_modules: BTreeSet<FilePath>,
 }
 
 impl<'cache, 'data> LinuxRecorder<'cache, 'data> {
     pub fn new(
         loader: &'data Loader,
         module_allowlist: AllowList,
+        module_filter: ModuleFilter,
         cache: &'cache DebugInfoCache,
     ) -> Self {
         let coverage = BinaryCoverage::default();
         let modules = BTreeMap::new();
+        let skipped_modules = BTreeSet::new();
 
         Self {
             module_allowlist,
+            module_filter,
             cache,
             coverage,
             loader,
             modules,
+            skipped_modules,
         }
     }
 
@@ -82,6 +89,13 @@ impl<'cache, 'data> LinuxRecorder<'cache, 'data> {
 
         if !self.module_allowlist.is_allowed(path) {
             debug!("not inserting denylisted module: {path}");
+            self.skipped_modules.insert(path.clone());
+            return Ok(());
+        }
+
+        if !self.module_filter.is_allowed(path) {
+            debug!("not inserting filtered module: {path}");
+            self.skipped_modules.insert(path.clone());
             return Ok(());
         }
 
@@ -92,14 +106,18 @@ impl<'cache, 'data> LinuxRecorder<'cache, 'data> {
             return Ok(());
         };
 
-        let coverage = self.cache.get_or_insert(&module)?.coverage;
+        let coverage = self.cache.get_or_insert(&module)?.coverage.clone();
 
         for offset in coverage.as_ref().keys().copied() {
             let addr = image.base().offset_by(offset)?;
             context.breakpoints.set(tracee, addr)?;
         }
 
-        self.coverage.modules.insert(path.clone(), coverage);
+        // Keep the counts of a module already recorded in another process.
+        self.coverage
+            .modules
+            .entry(path.clone())
+            .or_insert(coverage);
 
         self.modules.insert(path.clone(), module);
 
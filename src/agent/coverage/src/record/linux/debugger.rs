@@ -3,16 +3,17 @@
 
 use std::collections::BTreeMap;
 use std::io::Read;
-use std::process::{Child, Command};
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Child, Command, ExitStatus};
 use std::time::Duration;
 
 use anyhow::{bail, format_err, Result};
 use debuggable_module::path::FilePath;
 use debuggable_module::Address;
-use pete::{Ptracer, Restart, Signal, Stop, Tracee};
+use pete::{Pid, Ptracer, Restart, Signal, Stop, Tracee};
 use procfs::process::{MMPermissions, MMapPath, MemoryMap, Process};
 
-use crate::record::Output;
+use crate::record::{ChildLimits, Output};
 
 pub trait DebugEventHandler {
     fn on_breakpoint(&mut self, dbg: &mut DebuggerContext, tracee: &mut Tracee) -> Result<()>;
@@ -26,34 +27,55 @@ pub trait DebugEventHandler {
 }
 
 pub struct Debugger<'eh> {
-    context: DebuggerContext,
+    tracer: Ptracer,
     event_handler: &'eh mut dyn DebugEventHandler,
+
+    // Traced processes, by PID, and the process of each traced thread.
+    processes: BTreeMap<Pid, TracedProcess>,
+    threads: BTreeMap<Pid, Pid>,
+
+    // If set, follow descendants of the target, up to these limits.
+    child_limits: Option<ChildLimits>,
+
+    // Count of followed descendants.
+    children: usize,
 }
 
 impl<'eh> Debugger<'eh> {
     pub fn new(event_handler: &'eh mut dyn DebugEventHandler) -> Self {
-        let context = DebuggerContext::new();
+        let mut tracer = Ptracer::new();
+        *tracer.poll_delay_mut() = Duration::from_millis(1);
 
         Self {
-            context,
+            tracer,
             event_handler,
+            processes: BTreeMap::new(),
+            threads: BTreeMap::new(),
+            child_limits: None,
+            children: 0,
         }
     }
 
+    /// Also record the processes started by the target, and their descendants,
+    /// within `limits`.
+    pub fn follow_children(&mut self, limits: ChildLimits) {
+        self.child_limits = Some(limits);
+    }
+
     pub fn spawn(&mut self, cmd: Command) -> Result<Child> {
-        Ok(self.context.tracer.spawn(cmd)?)
+        Ok(self.tracer.spawn(cmd)?)
     }
 
     pub fn wait(self, mut child: Child) -> Result<Output> {
-        if let Err(err) = self.wait_on_stops() {
-            // Ignore error if child already exited.
-            let _ = child.kill();
+        let status = match self.wait_on_stops() {
+            Ok(status) => status.map(Into::into),
+            Err(err) => {
+                // Ignore error if child already exited.
+                let _ = child.kill();
 
-            return Err(err);
-        }
-
-        // Currently unavailable on Linux.
-        let status = None;
+                return Err(err);
+            }
+        };
 
         let stdout = if let Some(pipe) = &mut child.stdout {
             let mut stdout = Vec::new();
@@ -91,58 +113,176 @@ impl<'eh> Debugger<'eh> {
         Ok(output)
     }
 
-    fn wait_on_stops(mut self) -> Result<()> {
+    // Returns the exit status of the root tracee, if seen.
+    fn wait_on_stops(mut self) -> Result<Option<ExitStatus>> {
         use pete::ptracer::Options;
 
         // Continue the tracee process until the return from its initial `execve()`.
-        let mut tracee = continue_to_init_execve(&mut self.context.tracer)?;
+        let mut tracee = continue_to_init_execve(&mut self.tracer)?;
+        let root = tracee.pid;
+        let mut status = None;
 
-        // Do not follow forks.
-        //
-        // After this, we assume that any new tracee is a thread in the same
-        // group as the root tracee.
         let mut options = Options::all();
-        options.remove(Options::PTRACE_O_TRACEFORK);
-        options.remove(Options::PTRACE_O_TRACEVFORK);
-        options.remove(Options::PTRACE_O_TRACEEXEC);
+
+        if self.child_limits.is_none() {
+            // Do not follow forks.
+            //
+            // After this, we assume that any new tracee is a thread in the same
+            // group as the root tracee.
+            options.remove(Options::PTRACE_O_TRACEFORK);
+            options.remove(Options::PTRACE_O_TRACEVFORK);
+            options.remove(Options::PTRACE_O_TRACEEXEC);
+        }
         tracee.set_options(options)?;
 
         // Initialize index of mapped modules now that we have a PID to query.
-        self.context.images = Some(Images::new(tracee.pid.as_raw()));
-        self.update_images(&mut tracee)?;
+        self.processes.insert(root, TracedProcess::root(root));
+        self.threads.insert(root, root);
+        self.update_images(root, &mut tracee)?;
 
         // Restart tracee and enter the main debugger loop.
-        self.context.tracer.restart(tracee, Restart::Syscall)?;
+        self.tracer.restart(tracee, Restart::Syscall)?;
+
+        while let Some(mut tracee) = self.tracer.wait()? {
+            let Some(pid) = self.process_of(&tracee) else {
+                debug!("stop of exited tracee {}: {:?}", tracee.pid, tracee.stop);
+
+                if let Err(err) = self.tracer.restart(tracee, Restart::Syscall) {
+                    error!("unable to restart tracee: {}", err);
+                }
+                continue;
+            };
 
-        while let Some(mut tracee) = self.context.tracer.wait()? {
             match tracee.stop {
                 Stop::SyscallEnter => trace!("syscall-enter: {:?}", tracee.stop),
                 Stop::SyscallExit => {
-                    self.update_images(&mut tracee)?;
+                    self.update_images(pid, &mut tracee)?;
                 }
                 Stop::SignalDelivery {
                     signal: Signal::SIGTRAP,
                 } => {
-                    self.restore_and_call_if_breakpoint(&mut tracee)?;
+                    self.restore_and_call_if_breakpoint(pid, &mut tracee)?;
                 }
                 Stop::Clone { new: pid } => {
                     // Only seen when the `VM_CLONE` flag is set, as of Linux 4.15.
                     info!("new thread: {}", pid);
                 }
+                Stop::Fork { new } | Stop::Vfork { new } => {
+                    info!("new child process: {}", new);
+                }
+                Stop::Exec { .. } => {
+                    self.on_exec(pid, &mut tracee)?;
+                }
+                Stop::Exiting { exit_code } if tracee.pid == root => {
+                    status = Some(ExitStatus::from_raw((exit_code & 0xff) << 8));
+                    self.kill_children(root);
+                }
+                Stop::Signaling { signal, .. } if tracee.pid == root => {
+                    status = Some(ExitStatus::from_raw(signal as i32));
+                }
+                Stop::Exiting { .. } => {
+                    self.threads.remove(&tracee.pid);
+
+                    if tracee.pid == pid {
+                        // The PID may be reused by a later process.
+                        self.processes.remove(&pid);
+                    }
+                }
                 _ => {
                     debug!("stop: {:?}", tracee.stop);
                 }
             }
 
-            if let Err(err) = self.context.tracer.restart(tracee, Restart::Syscall) {
+            if let Err(err) = self.tracer.restart(tracee, Restart::Syscall) {
                 error!("unable to restart tracee: {}", err);
             }
         }
 
-        Ok(())
+        Ok(status)
+    }
+
+    // The PID of the process of the tracee, tracking it if it is new, unless it
+    // has already exited.
+    fn process_of(&mut self, tracee: &Tracee) -> Option<Pid> {
+        if let Some(pid) = self.threads.get(&tracee.pid) {
+            return Some(*pid);
+        }
+
+        // The first stop of a new thread or process may be seen before the
+        // `clone()` or `fork()` stop of its parent, so ask the kernel.
+        let status = Process::new(tracee.pid.as_raw())
+            .and_then(|process| process.status())
+            .ok()?;
+        let pid = Pid::from_raw(status.tgid);
+
+        if !self.processes.contains_key(&pid) {
+            let process = self.new_child(pid, Pid::from_raw(status.ppid));
+            self.processes.insert(pid, process);
+        }
+
+        self.threads.insert(tracee.pid, pid);
+
+        Some(pid)
     }
 
-    fn restore_and_call_if_breakpoint(&mut self, tracee: &mut Tracee) -> Result<()> {
+    fn new_child(&mut self, pid: Pid, parent: Pid) -> TracedProcess {
+        // The child starts with a copy of the memory of its parent, so also of
+        // its breakpoints and images.
+        let (context, depth, followed) = match self.processes.get(&parent) {
+            Some(parent) => (
+                parent.context.forked(pid.as_raw()),
+                parent.depth + 1,
+                parent.followed,
+            ),
+            None => (DebuggerContext::new(pid.as_raw()), 1, false),
+        };
+
+        let followed = followed
+            && self.child_limits.is_some_and(|limits| {
+                depth <= limits.max_depth && self.children < limits.max_count
+            });
+
+        if followed {
+            info!("following child process {pid} of {parent}");
+            self.children += 1;
+        } else {
+            debug!("not following child process {pid} of {parent}");
+        }
+
+        TracedProcess {
+            context,
+            depth,
+            followed,
+        }
+    }
+
+    fn on_exec(&mut self, pid: Pid, tracee: &mut Tracee) -> Result<()> {
+        let Some(process) = self.processes.get_mut(&pid) else {
+            return Ok(());
+        };
+
+        // The image of the process has been replaced, and its other threads
+        // have exited.
+        process.context = DebuggerContext::new(pid.as_raw());
+        self.threads
+            .retain(|tid, owner| *owner != pid || *tid == tracee.pid);
+
+        self.update_images(pid, tracee)
+    }
+
+    // Kill the descendants of the target which outlive it, so that recording
+    // ends with the target.
+    fn kill_children(&self, root: Pid) {
+        for pid in self.processes.keys().filter(|pid| **pid != root) {
+            let _ = nix::sys::signal::kill(*pid, nix::sys::signal::SIGKILL);
+        }
+    }
+
+    fn restore_and_call_if_breakpoint(&mut self, pid: Pid, tracee: &mut Tracee) -> Result<()> {
+        let Some(process) = self.processes.get_mut(&pid) else {
+            bail!("internal error: no state for traced process {pid}");
+        };
+
         let mut regs = tracee.registers()?;
 
         #[cfg(target_arch = "x86_64")]
@@ -156,15 +296,19 @@ impl<'eh> Debugger<'eh> {
         // If we don't have a registered breakpoint, then we will not use this value.
         let pc = Address(instruction_pointer.saturating_sub(1));
 
-        if self.context.breakpoints.clear(tracee, pc)? {
+        if process.context.breakpoints.clear(tracee, pc)? {
             // We restored the original, `int3`-clobbered instruction in `clear()`. Now
             // set the tracee's registers to execute it on restart. Do this _before_ the
             // callback to simulate a hardware breakpoint.
             *instruction_pointer = pc.0;
             tracee.set_registers(regs)?;
 
-            self.event_handler
-                .on_breakpoint(&mut self.context, tracee)?;
+            // Unfollowed children may hit the breakpoints they inherited, which
+            // are only cleared.
+            if process.followed {
+                self.event_handler
+                    .on_breakpoint(&mut process.context, tracee)?;
+            }
         } else {
             warn!("no registered breakpoint for SIGTRAP delivery at {pc:x}");
 
@@ -175,45 +319,75 @@ impl<'eh> Debugger<'eh> {
         Ok(())
     }
 
-    fn update_images(&mut self, tracee: &mut Tracee) -> Result<()> {
-        let images = self
-            .context
-            .images
-            .as_mut()
-            .ok_or_else(|| format_err!("internal error: recorder images not initialized"))?;
-        let events = images.update()?;
+    fn update_images(&mut self, pid: Pid, tracee: &mut Tracee) -> Result<()> {
+        let process = self
+            .processes
+            .get_mut(&pid)
+            .ok_or_else(|| format_err!("internal error: no state for traced process {pid}"))?;
+
+        if !process.followed {
+            return Ok(());
+        }
+
+        let events = process.context.images.update()?;
 
         for (_base, image) in &events.loaded {
             self.event_handler
-                .on_module_load(&mut self.context, tracee, image)?;
+                .on_module_load(&mut process.context, tracee, image)?;
         }
 
         Ok(())
     }
 }
 
+// A traced process, and its depth in the tree of processes of the target.
+struct TracedProcess {
+    context: DebuggerContext,
+    depth: usize,
+
+    // If false, the process is traced, but not recorded.
+    followed: bool,
+}
+
+impl TracedProcess {
+    fn root(pid: Pid) -> Self {
+        let context = DebuggerContext::new(pid.as_raw());
+
+        Self {
+            context,
+            depth: 0,
+            followed: true,
+        }
+    }
+}
+
+/// The breakpoints and images of a traced process.
+#[derive(Clone, Debug)]
 pub struct DebuggerContext {
     pub breakpoints: Breakpoints,
-    pub images: Option<Images>,
-    pub tracer: Ptracer,
+    pub images: Images,
 }
 
 impl DebuggerContext {
-    #[allow(clippy::new_without_default)]
-    pub fn new() -> Self {
+    pub fn new(pid: i32) -> Self {
         let breakpoints = Breakpoints::default();
-        let images = None;
-        let mut tracer = Ptracer::new();
-        *tracer.poll_delay_mut() = Duration::from_millis(1);
+        let images = Images::new(pid);
+
         Self {
             breakpoints,
             images,
-            tracer,
         }
     }
 
+    // The context of a child forked from this process.
+    fn forked(&self, pid: i32) -> Self {
+        let mut context = self.clone();
+        context.images.pid = pid;
+        context
+    }
+
     pub fn find_image_for_addr(&self, addr: Address) -> Option<&ModuleImage> {
-        self.images.as_ref()?.find_image_for_addr(addr)
+        self.images.find_image_for_addr(addr)
     }
 }
 
@@ -0,0 +1,120 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::{bail, Result};
+use debuggable_module::load_module::LoadModule;
+use debuggable_module::loader::Loader;
+use debuggable_module::macos::MacOSModule;
+use debuggable_module::path::FilePath;
+use debuggable_module::Address;
+
+pub mod debugger;
+mod mach;
+use debugger::{DebugEventHandler, DebuggerContext, ModuleImage};
+
+use crate::allowlist::AllowList;
+use crate::binary::{BinaryCoverage, DebugInfoCache};
+use crate::module_filter::ModuleFilter;
+
+pub struct MacOSRecorder<'cache, 'data> {
+    module_allowlist: AllowList,
+    module_filter: ModuleFilter,
+    cache: &'cache DebugInfoCache,
+    pub coverage: BinaryCoverage,
+    loader: &'data Loader,
+    modules: BTreeMap<FilePath, MacOSModule<'data>>,
+    pub skipped_modules: BTreeSet<FilePath>,
+}
+
+impl<'cache, 'data> MacOSRecorder<'cache, 'data> {
+    pub fn new(
+        loader: &'data Loader,
+        module_allowlist: AllowList,
+        module_filter: ModuleFilter,
+        cache: &'cache DebugInfoCache,
+    ) -> Self {
+        let coverage = BinaryCoverage::default();
+        let modules = BTreeMap::new();
+        let skipped_modules = BTreeSet::new();
+
+        Self {
+            module_allowlist,
+            module_filter,
+            cache,
+            coverage,
+            loader,
+            modules,
+            skipped_modules,
+        }
+    }
+
+    fn do_on_breakpoint(&mut self, context: &mut DebuggerContext, pc: Address) -> Result<()> {
+        if let Some(image) = context.find_image_for_addr(pc) {
+            if let Some(coverage) = self.coverage.modules.get_mut(image.path()) {
+                let offset = pc.offset_from(image.base())?;
+                coverage.increment(offset);
+            } else {
+                bail!("coverage not initialized for module {}", image.path());
+            }
+        } else {
+            bail!("no image for addr: {pc:x}");
+        }
+
+        Ok(())
+    }
+
+    fn do_on_module_load(
+        &mut self,
+        context: &mut DebuggerContext,
+        image: &ModuleImage,
+    ) -> Result<()> {
+        info!("module load: {}", image.path());
+
+        let path = image.path();
+
+        if !self.module_allowlist.is_allowed(path) {
+            debug!("not inserting denylisted module: {path}");
+            self.skipped_modules.insert(path.clone());
+            return Ok(());
+        }
+
+        if !self.module_filter.is_allowed(path) {
+            debug!("not inserting filtered module: {path}");
+            self.skipped_modules.insert(path.clone());
+            return Ok(());
+        }
+
+        // Libraries of the dyld shared cache have no file to load.
+        let module = if let Ok(module) = MacOSModule::load(self.loader, path.clone()) {
+            module
+        } else {
+            debug!("skipping undebuggable module: {path}");
+            return Ok(());
+        };
+
+        let coverage = self.cache.get_or_insert(&module)?.coverage.clone();
+
+        for offset in coverage.as_ref().keys().copied() {
+            let addr = image.base().offset_by(offset)?;
+            context.breakpoints.set(&context.task, addr)?;
+        }
+
+        self.coverage.modules.insert(path.clone(), coverage);
+
+        self.modules.insert(path.clone(), module);
+
+        Ok(())
+    }
+}
+
+impl<'cache, 'data> DebugEventHandler for MacOSRecorder<'cache, 'data> {
+    fn on_breakpoint(&mut self, context: &mut DebuggerContext, pc: Address) -> Result<()> {
+        self.do_on_breakpoint(context, pc)
+    }
+
+    fn on_module_load(&mut self, context: &mut DebuggerContext, image: &ModuleImage) -> Result<()> {
+        self.do_on_module_load(context, image)
+    }
+}
@@ -0,0 +1,433 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! A minimal debugger for macOS, using `ptrace()` to control the target and
+//! its Mach task to access its memory and threads.
+//!
+//! Breakpoint exceptions are delivered to the debugger as `SIGTRAP` stops. The
+//! images of the target are enumerated from the `dyld_all_image_infos` of its
+//! dyld: once at the entry point of the executable, after dyld has loaded its
+//! dependencies, then whenever dyld calls its debugger notification function,
+//! such as on `dlopen()`.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Read;
+use std::ops::Range;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::process::{Child, Command, ExitStatus};
+
+use anyhow::{bail, Result};
+use debuggable_module::macos::{LoadCommands, MH_EXECUTE};
+use debuggable_module::path::FilePath;
+use debuggable_module::Address;
+use nix::sys::ptrace;
+use nix::sys::signal::Signal;
+use nix::sys::wait::{waitpid, WaitStatus};
+use nix::unistd::Pid;
+
+use super::mach::{Task, ThreadState};
+use crate::record::Output;
+
+// Maximum size of the load commands of an image in memory, to bound reads of
+// corrupt headers.
+const MAX_LOAD_COMMANDS_SIZE: usize = 1 << 20;
+
+// Maximum length of the path of an image.
+const MAX_PATH_LEN: usize = 4096;
+
+// Size of each `dyld_image_info` in `dyld_all_image_infos.infoArray`.
+const DYLD_IMAGE_INFO_SIZE: usize = 24;
+
+pub trait DebugEventHandler {
+    fn on_breakpoint(&mut self, context: &mut DebuggerContext, pc: Address) -> Result<()>;
+
+    fn on_module_load(&mut self, context: &mut DebuggerContext, image: &ModuleImage) -> Result<()>;
+}
+
+pub struct Debugger<'eh> {
+    event_handler: &'eh mut dyn DebugEventHandler,
+
+    // Internal breakpoint at the entry point of the executable.
+    entry: Option<Address>,
+
+    // Internal breakpoint at the debugger notification function of dyld.
+    notifier: Option<Address>,
+
+    // True if a thread is being stepped over the notifier, to re-arm it.
+    stepping: bool,
+}
+
+impl<'eh> Debugger<'eh> {
+    pub fn new(event_handler: &'eh mut dyn DebugEventHandler) -> Self {
+        Self {
+            event_handler,
+            entry: None,
+            notifier: None,
+            stepping: false,
+        }
+    }
+
+    pub fn spawn(&mut self, mut cmd: Command) -> Result<Child> {
+        // Stop the child with `SIGTRAP` on return from its `exec()`.
+        unsafe {
+            cmd.pre_exec(|| ptrace::traceme().map_err(std::io::Error::from));
+        }
+
+        Ok(cmd.spawn()?)
+    }
+
+    pub fn wait(self, mut child: Child) -> Result<Output> {
+        let status = match self.wait_on_stops(Pid::from_raw(child.id() as i32)) {
+            Ok(status) => status.map(Into::into),
+            Err(err) => {
+                // Ignore error if child already exited.
+                let _ = child.kill();
+
+                return Err(err);
+            }
+        };
+
+        let stdout = if let Some(pipe) = &mut child.stdout {
+            let mut stdout = Vec::new();
+            pipe.read_to_end(&mut stdout)?;
+            String::from_utf8_lossy(&stdout).into_owned()
+        } else {
+            "".into()
+        };
+
+        let stderr = if let Some(pipe) = &mut child.stderr {
+            let mut stderr = Vec::new();
+            pipe.read_to_end(&mut stderr)?;
+            String::from_utf8_lossy(&stderr).into_owned()
+        } else {
+            "".into()
+        };
+
+        // The child was reaped by `waitpid()` on exit, so we must not signal or
+        // wait on it again: its PID may have been reused.
+
+        let output = Output {
+            status,
+            stderr,
+            stdout,
+        };
+
+        Ok(output)
+    }
+
+    fn wait_on_stops(mut self, pid: Pid) -> Result<Option<ExitStatus>> {
+        match waitpid(pid, None)? {
+            WaitStatus::Stopped(_, Signal::SIGTRAP) => {}
+            status => {
+                bail!("did not see initial exec() in tracee while recording coverage: {status:?}")
+            }
+        }
+
+        let task = Task::for_pid(pid.as_raw())?;
+        let mut context = DebuggerContext::new(task);
+
+        // Only the executable and dyld are loaded, so record the executable now,
+        // and find the libraries it depends on once dyld has loaded them.
+        let (main, commands) = find_main_image(&context.task)?;
+
+        if let Some(entry) = commands.entry_point() {
+            let entry = main.base().offset_by(entry)?;
+            context.breakpoints.set_internal(&context.task, entry)?;
+            self.entry = Some(entry);
+        } else {
+            warn!("no entry point found for {}", main.path());
+        }
+
+        self.load_image(&mut context, main)?;
+
+        if self.entry.is_none() {
+            self.update_images(&mut context)?;
+        }
+
+        ptrace::cont(pid, None)?;
+
+        loop {
+            match waitpid(pid, None)? {
+                WaitStatus::Exited(_, code) => {
+                    return Ok(Some(ExitStatus::from_raw((code & 0xff) << 8)));
+                }
+                WaitStatus::Signaled(_, signal, core_dumped) => {
+                    let core = if core_dumped { 0x80 } else { 0 };
+                    return Ok(Some(ExitStatus::from_raw(signal as i32 | core)));
+                }
+                WaitStatus::Stopped(_, Signal::SIGTRAP) => {
+                    self.on_trap(&mut context)?;
+                    ptrace::cont(pid, None)?;
+                }
+                WaitStatus::Stopped(_, signal) => {
+                    // Deliver signals not raised by the debugger.
+                    debug!("stop: {signal:?}");
+                    ptrace::cont(pid, signal)?;
+                }
+                status => {
+                    debug!("wait status: {status:?}");
+                }
+            }
+        }
+    }
+
+    fn on_trap(&mut self, context: &mut DebuggerContext) -> Result<()> {
+        let mut handled = false;
+
+        // A trap stops the whole task, so find the threads which trapped.
+        for thread in context.task.threads()? {
+            let mut state = thread.state()?;
+
+            if self.stepping && state.rflags & ThreadState::TRAP_FLAG != 0 {
+                // Stepped over the notifier, so re-arm it.
+                state.rflags &= !ThreadState::TRAP_FLAG;
+                thread.set_state(&state)?;
+                self.stepping = false;
+
+                if let Some(notifier) = self.notifier {
+                    context.breakpoints.set_internal(&context.task, notifier)?;
+                }
+
+                handled = true;
+                continue;
+            }
+
+            // The PC if the thread stopped on a software breakpoint.
+            let pc = Address(state.rip.saturating_sub(1));
+
+            if !context.breakpoints.clear(&context.task, pc)? {
+                continue;
+            }
+
+            handled = true;
+
+            // Execute the restored instruction on restart.
+            state.rip = pc.0;
+            thread.set_state(&state)?;
+
+            if context.breakpoints.sites.remove(&pc) {
+                self.event_handler.on_breakpoint(context, pc)?;
+            }
+
+            if self.entry == Some(pc) {
+                self.entry = None;
+                self.update_images(context)?;
+            }
+
+            if self.notifier == Some(pc) {
+                self.update_images(context)?;
+
+                // Step the thread over the notifier, to re-arm it after.
+                state.rflags |= ThreadState::TRAP_FLAG;
+                thread.set_state(&state)?;
+                self.stepping = true;
+            }
+        }
+
+        if !handled {
+            warn!("no registered breakpoint for SIGTRAP delivery");
+        }
+
+        Ok(())
+    }
+
+    fn update_images(&mut self, context: &mut DebuggerContext) -> Result<()> {
+        let Some(all_image_infos) = context.task.all_image_info_addr()? else {
+            return Ok(());
+        };
+
+        // The start of `dyld_all_image_infos`, as of its version 1.
+        let mut header = [0u8; 24];
+        context.task.read_memory(all_image_infos, &mut header)?;
+
+        let count = u32::from_le_bytes(header[4..8].try_into()?) as usize;
+        let array = u64::from_le_bytes(header[8..16].try_into()?);
+        let notification = u64::from_le_bytes(header[16..24].try_into()?);
+
+        if self.notifier.is_none() && notification != 0 {
+            let notifier = Address(notification);
+            context.breakpoints.set_internal(&context.task, notifier)?;
+            self.notifier = Some(notifier);
+        }
+
+        // The array is unset while dyld updates it, and we will be notified of
+        // the update.
+        if array == 0 {
+            return Ok(());
+        }
+
+        let mut infos = vec![0u8; count * DYLD_IMAGE_INFO_SIZE];
+        context.task.read_memory(Address(array), &mut infos)?;
+
+        for info in infos.chunks_exact(DYLD_IMAGE_INFO_SIZE) {
+            let base = Address(u64::from_le_bytes(info[0..8].try_into()?));
+            let path = Address(u64::from_le_bytes(info[8..16].try_into()?));
+
+            if context.images.mapped.contains_key(&base) {
+                continue;
+            }
+
+            let path = context.task.read_c_string(path, MAX_PATH_LEN)?;
+
+            match ModuleImage::read(&context.task, base, &path) {
+                Ok((image, _)) => self.load_image(context, image)?,
+                Err(err) => debug!("skipping unreadable image {path}: {err}"),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn load_image(&mut self, context: &mut DebuggerContext, image: ModuleImage) -> Result<()> {
+        self.event_handler.on_module_load(context, &image)?;
+        context.images.mapped.insert(image.base(), image);
+
+        Ok(())
+    }
+}
+
+// The executable of a task stopped on return from `exec()`.
+fn find_main_image(task: &Task) -> Result<(ModuleImage, LoadCommands)> {
+    for region in task.readable_regions()? {
+        let mut header = [0u8; 32];
+
+        if task.read_memory(region, &mut header).is_err() {
+            continue;
+        }
+
+        let filetype = u32::from_le_bytes(header[12..16].try_into()?);
+
+        if LoadCommands::size(&header).is_ok() && filetype == MH_EXECUTE {
+            return ModuleImage::read(task, region, &task.path()?);
+        }
+    }
+
+    bail!("no executable image found in pid {}", task.pid());
+}
+
+pub struct DebuggerContext {
+    pub breakpoints: Breakpoints,
+    pub images: Images,
+    pub task: Task,
+}
+
+impl DebuggerContext {
+    pub fn new(task: Task) -> Self {
+        Self {
+            breakpoints: Breakpoints::default(),
+            images: Images::default(),
+            task,
+        }
+    }
+
+    pub fn find_image_for_addr(&self, addr: Address) -> Option<&ModuleImage> {
+        self.images.find_image_for_addr(addr)
+    }
+}
+
+/// Images loaded by dyld, by load address.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Images {
+    mapped: BTreeMap<Address, ModuleImage>,
+}
+
+impl Images {
+    pub fn mapped(&self) -> impl Iterator<Item = (Address, &ModuleImage)> {
+        self.mapped.iter().map(|(va, i)| (*va, i))
+    }
+
+    pub fn find_image_for_addr(&self, addr: Address) -> Option<&ModuleImage> {
+        let (_, image) = self.mapped().find(|(_, im)| im.contains(&addr))?;
+
+        Some(image)
+    }
+}
+
+/// A Mach-O image loaded in a task.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ModuleImage {
+    base: Address,
+    ranges: Vec<Range<u64>>,
+    path: FilePath,
+}
+
+impl ModuleImage {
+    /// Read the image whose header is loaded at `base`, with its load commands.
+    pub fn read(task: &Task, base: Address, path: &str) -> Result<(Self, LoadCommands)> {
+        let mut header = [0u8; 32];
+        task.read_memory(base, &mut header)?;
+
+        let size = LoadCommands::size(&header)?;
+
+        if size > MAX_LOAD_COMMANDS_SIZE {
+            bail!("invalid size of load commands: {size}");
+        }
+
+        let mut data = vec![0; size];
+        task.read_memory(base, &mut data)?;
+        let commands = LoadCommands::parse(&data)?;
+
+        // Offsets are relative to the header, as for the module on disk, so
+        // only the ranges of the image depend on its slide.
+        let slide = commands.slide(base)?;
+        let ranges = commands.loaded_ranges(slide);
+        let path = FilePath::new(path)?;
+
+        Ok((Self { base, ranges, path }, commands))
+    }
+
+    pub fn path(&self) -> &FilePath {
+        &self.path
+    }
+
+    pub fn base(&self) -> Address {
+        self.base
+    }
+
+    pub fn contains(&self, addr: &Address) -> bool {
+        self.ranges.iter().any(|range| range.contains(&addr.0))
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Breakpoints {
+    saved: BTreeMap<Address, u8>,
+
+    // Breakpoints at coverage sites, rather than only internal.
+    sites: BTreeSet<Address>,
+}
+
+impl Breakpoints {
+    pub fn set(&mut self, task: &Task, addr: Address) -> Result<()> {
+        self.sites.insert(addr);
+        self.set_internal(task, addr)
+    }
+
+    fn set_internal(&mut self, task: &Task, addr: Address) -> Result<()> {
+        // Return if the breakpoint exists. We don't want to conclude that the
+        // saved instruction byte was `0xcc`.
+        if self.saved.contains_key(&addr) {
+            return Ok(());
+        }
+
+        let mut data = [0u8];
+        task.read_memory(addr, &mut data)?;
+        self.saved.insert(addr, data[0]);
+        task.write_memory(addr, &[0xcc])?;
+
+        Ok(())
+    }
+
+    pub fn clear(&mut self, task: &Task, addr: Address) -> Result<bool> {
+        let data = self.saved.remove(&addr);
+
+        let cleared = if let Some(data) = data {
+            task.write_memory(addr, &[data])?;
+            true
+        } else {
+            false
+        };
+
+        Ok(cleared)
+    }
+}
@@ -0,0 +1,425 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Access to the Mach task of a traced process, to read and write its memory
+//! and the registers of its threads.
+
+#![allow(non_camel_case_types)]
+
+use std::ffi::c_void;
+use std::os::raw::{c_int, c_uint};
+
+use anyhow::{bail, Result};
+use debuggable_module::Address;
+
+type kern_return_t = c_int;
+type mach_port_t = c_uint;
+type natural_t = c_uint;
+type vm_prot_t = c_int;
+
+const KERN_SUCCESS: kern_return_t = 0;
+const KERN_INVALID_ADDRESS: kern_return_t = 1;
+
+const VM_PROT_READ: vm_prot_t = 0x1;
+const VM_PROT_WRITE: vm_prot_t = 0x2;
+const VM_PROT_EXECUTE: vm_prot_t = 0x4;
+const VM_PROT_COPY: vm_prot_t = 0x10;
+
+const VM_REGION_BASIC_INFO_64: c_int = 9;
+const VM_REGION_BASIC_INFO_COUNT_64: natural_t = 9;
+
+const TASK_DYLD_INFO: natural_t = 17;
+const TASK_DYLD_INFO_COUNT: natural_t = 5;
+
+const X86_THREAD_STATE64: c_int = 4;
+const X86_THREAD_STATE64_COUNT: natural_t = 42;
+
+const PAGE_SIZE: u64 = 0x1000;
+
+// Only some fields are read, but all are written.
+#[allow(dead_code)]
+#[repr(C, packed(4))]
+#[derive(Default)]
+struct vm_region_basic_info_64 {
+    protection: vm_prot_t,
+    max_protection: vm_prot_t,
+    inheritance: c_uint,
+    shared: c_uint,
+    reserved: c_uint,
+    offset: u64,
+    behavior: c_int,
+    user_wired_count: u16,
+}
+
+#[allow(dead_code)]
+#[repr(C, packed(4))]
+#[derive(Default)]
+struct task_dyld_info {
+    all_image_info_addr: u64,
+    all_image_info_size: u64,
+    all_image_info_format: c_int,
+}
+
+/// The general-purpose registers of an `x86_64` thread.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ThreadState {
+    pub rax: u64,
+    pub rbx: u64,
+    pub rcx: u64,
+    pub rdx: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rbp: u64,
+    pub rsp: u64,
+    pub r8: u64,
+    pub r9: u64,
+    pub r10: u64,
+    pub r11: u64,
+    pub r12: u64,
+    pub r13: u64,
+    pub r14: u64,
+    pub r15: u64,
+    pub rip: u64,
+    pub rflags: u64,
+    pub cs: u64,
+    pub fs: u64,
+    pub gs: u64,
+}
+
+impl ThreadState {
+    /// Trap flag of `rflags`, to single-step the thread.
+    pub const TRAP_FLAG: u64 = 0x100;
+}
+
+extern "C" {
+    static mach_task_self_: mach_port_t;
+
+    fn task_for_pid(task: mach_port_t, pid: c_int, target: *mut mach_port_t) -> kern_return_t;
+
+    fn task_info(
+        task: mach_port_t,
+        flavor: natural_t,
+        info: *mut c_int,
+        count: *mut natural_t,
+    ) -> kern_return_t;
+
+    fn task_threads(
+        task: mach_port_t,
+        threads: *mut *mut mach_port_t,
+        count: *mut natural_t,
+    ) -> kern_return_t;
+
+    fn thread_get_state(
+        thread: mach_port_t,
+        flavor: c_int,
+        state: *mut natural_t,
+        count: *mut natural_t,
+    ) -> kern_return_t;
+
+    fn thread_set_state(
+        thread: mach_port_t,
+        flavor: c_int,
+        state: *const natural_t,
+        count: natural_t,
+    ) -> kern_return_t;
+
+    fn mach_vm_region(
+        task: mach_port_t,
+        address: *mut u64,
+        size: *mut u64,
+        flavor: c_int,
+        info: *mut c_int,
+        count: *mut natural_t,
+        object_name: *mut mach_port_t,
+    ) -> kern_return_t;
+
+    fn mach_vm_read_overwrite(
+        task: mach_port_t,
+        address: u64,
+        size: u64,
+        data: u64,
+        out_size: *mut u64,
+    ) -> kern_return_t;
+
+    fn mach_vm_write(
+        task: mach_port_t,
+        address: u64,
+        data: usize,
+        count: natural_t,
+    ) -> kern_return_t;
+
+    fn mach_vm_protect(
+        task: mach_port_t,
+        address: u64,
+        size: u64,
+        set_maximum: c_int,
+        protection: vm_prot_t,
+    ) -> kern_return_t;
+
+    fn mach_vm_deallocate(task: mach_port_t, address: u64, size: u64) -> kern_return_t;
+
+    fn mach_port_deallocate(task: mach_port_t, name: mach_port_t) -> kern_return_t;
+
+    fn proc_pidpath(pid: c_int, buffer: *mut c_void, size: u32) -> c_int;
+}
+
+fn check(kr: kern_return_t, what: &str) -> Result<()> {
+    if kr != KERN_SUCCESS {
+        bail!("{what} failed: kern_return_t = {kr}");
+    }
+
+    Ok(())
+}
+
+fn task_self() -> mach_port_t {
+    unsafe { mach_task_self_ }
+}
+
+/// The Mach task of a process, which must be stopped while it is accessed.
+pub struct Task {
+    pid: i32,
+    port: mach_port_t,
+}
+
+impl Task {
+    pub fn for_pid(pid: i32) -> Result<Self> {
+        let mut port = 0;
+        let kr = unsafe { task_for_pid(task_self(), pid, &mut port) };
+
+        if kr != KERN_SUCCESS {
+            bail!(
+                "task_for_pid() failed for pid {pid} (kern_return_t = {kr}): recording coverage \
+                 on macOS requires running as root, or a recorder signed with the \
+                 `com.apple.security.cs.debugger` entitlement; the target must not use the \
+                 hardened runtime, unless signed with the `com.apple.security.get-task-allow` \
+                 entitlement"
+            );
+        }
+
+        Ok(Self { pid, port })
+    }
+
+    pub fn pid(&self) -> i32 {
+        self.pid
+    }
+
+    /// Path of the executable of the process.
+    pub fn path(&self) -> Result<String> {
+        let mut buffer = vec![0u8; 4096];
+        let len = unsafe {
+            proc_pidpath(
+                self.pid,
+                buffer.as_mut_ptr() as *mut c_void,
+                buffer.len() as u32,
+            )
+        };
+
+        if len <= 0 {
+            bail!("proc_pidpath() failed for pid {}", self.pid);
+        }
+
+        buffer.truncate(len as usize);
+        Ok(String::from_utf8_lossy(&buffer).into_owned())
+    }
+
+    pub fn read_memory(&self, addr: Address, data: &mut [u8]) -> Result<()> {
+        let mut read = 0;
+        let kr = unsafe {
+            mach_vm_read_overwrite(
+                self.port,
+                addr.0,
+                data.len() as u64,
+                data.as_mut_ptr() as u64,
+                &mut read,
+            )
+        };
+        check(kr, "mach_vm_read_overwrite()")?;
+
+        if read != data.len() as u64 {
+            bail!("short read of {read} bytes at {addr:x}");
+        }
+
+        Ok(())
+    }
+
+    /// Read a NUL-terminated string, a page at a time so as not to read past
+    /// its mapping.
+    pub fn read_c_string(&self, addr: Address, max_len: usize) -> Result<String> {
+        let mut string = vec![];
+        let mut addr = addr.0;
+
+        while string.len() < max_len {
+            let page_end = (addr & !(PAGE_SIZE - 1)) + PAGE_SIZE;
+            let size = usize::min((page_end - addr) as usize, max_len - string.len());
+
+            let mut data = vec![0; size];
+            self.read_memory(Address(addr), &mut data)?;
+
+            if let Some(nul) = data.iter().position(|b| *b == 0) {
+                string.extend(&data[..nul]);
+                break;
+            }
+
+            string.extend(data);
+            addr = page_end;
+        }
+
+        Ok(String::from_utf8_lossy(&string).into_owned())
+    }
+
+    /// Write to memory, such as that of code, whatever its protection.
+    pub fn write_memory(&self, addr: Address, data: &[u8]) -> Result<()> {
+        let size = data.len() as u64;
+
+        // Make a private, writable copy of the pages, as for code of the
+        // shared cache or a mapped file.
+        let kr = unsafe {
+            mach_vm_protect(
+                self.port,
+                addr.0,
+                size,
+                0,
+                VM_PROT_READ | VM_PROT_WRITE | VM_PROT_COPY,
+            )
+        };
+        check(kr, "mach_vm_protect()")?;
+
+        let kr = unsafe {
+            mach_vm_write(
+                self.port,
+                addr.0,
+                data.as_ptr() as usize,
+                data.len() as natural_t,
+            )
+        };
+        check(kr, "mach_vm_write()")?;
+
+        let kr =
+            unsafe { mach_vm_protect(self.port, addr.0, size, 0, VM_PROT_READ | VM_PROT_EXECUTE) };
+        check(kr, "mach_vm_protect()")
+    }
+
+    /// The mapped regions of the task which are readable, as start addresses.
+    pub fn readable_regions(&self) -> Result<Vec<Address>> {
+        let mut regions = vec![];
+        let mut addr = 0;
+
+        loop {
+            let mut size = 0;
+            let mut info = vm_region_basic_info_64::default();
+            let mut count = VM_REGION_BASIC_INFO_COUNT_64;
+            let mut object_name = 0;
+
+            let kr = unsafe {
+                mach_vm_region(
+                    self.port,
+                    &mut addr,
+                    &mut size,
+                    VM_REGION_BASIC_INFO_64,
+                    &mut info as *mut _ as *mut c_int,
+                    &mut count,
+                    &mut object_name,
+                )
+            };
+
+            if kr == KERN_INVALID_ADDRESS {
+                // No more regions.
+                break;
+            }
+            check(kr, "mach_vm_region()")?;
+
+            if info.protection & VM_PROT_READ != 0 {
+                regions.push(Address(addr));
+            }
+
+            addr = addr.saturating_add(size);
+        }
+
+        Ok(regions)
+    }
+
+    /// The address of the `dyld_all_image_infos` of the task, if dyld has
+    /// initialized it.
+    pub fn all_image_info_addr(&self) -> Result<Option<Address>> {
+        let mut info = task_dyld_info::default();
+        let mut count = TASK_DYLD_INFO_COUNT;
+
+        let kr = unsafe {
+            task_info(
+                self.port,
+                TASK_DYLD_INFO,
+                &mut info as *mut _ as *mut c_int,
+                &mut count,
+            )
+        };
+        check(kr, "task_info()")?;
+
+        let addr = info.all_image_info_addr;
+        Ok((addr != 0).then_some(Address(addr)))
+    }
+
+    pub fn threads(&self) -> Result<Vec<Thread>> {
+        let mut list = std::ptr::null_mut();
+        let mut count = 0;
+
+        let kr = unsafe { task_threads(self.port, &mut list, &mut count) };
+        check(kr, "task_threads()")?;
+
+        let ports = unsafe { std::slice::from_raw_parts(list, count as usize) };
+        let threads = ports.iter().map(|port| Thread { port: *port }).collect();
+
+        let size = count as u64 * std::mem::size_of::<mach_port_t>() as u64;
+        unsafe { mach_vm_deallocate(task_self(), list as u64, size) };
+
+        Ok(threads)
+    }
+}
+
+impl Drop for Task {
+    fn drop(&mut self) {
+        unsafe { mach_port_deallocate(task_self(), self.port) };
+    }
+}
+
+/// A thread of a stopped task.
+pub struct Thread {
+    port: mach_port_t,
+}
+
+impl Thread {
+    pub fn state(&self) -> Result<ThreadState> {
+        let mut state = ThreadState::default();
+        let mut count = X86_THREAD_STATE64_COUNT;
+
+        let kr = unsafe {
+            thread_get_state(
+                self.port,
+                X86_THREAD_STATE64,
+                &mut state as *mut _ as *mut natural_t,
+                &mut count,
+            )
+        };
+        check(kr, "thread_get_state()")?;
+
+        Ok(state)
+    }
+
+    pub fn set_state(&self, state: &ThreadState) -> Result<()> {
+        let kr = unsafe {
+            thread_set_state(
+                self.port,
+                X86_THREAD_STATE64,
+                state as *const _ as *const natural_t,
+                X86_THREAD_STATE64_COUNT,
+            )
+        };
+        check(kr, "thread_set_state()")
+    }
+}
+
+impl Drop for Thread {
+    fn drop(&mut self) {
+        unsafe { mach_port_deallocate(task_self(), self.port) };
+    }
+}
@@ -1,7 +1,7 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::path::Path;
 
 use anyhow::{anyhow, Error, Result};
@@ -14,7 +14,8 @@ use debuggable_module::{Module, Offset};
 use debugger::{BreakpointId, BreakpointType, DebugEventHandler, Debugger, ModuleLoadInfo};
 
 use crate::binary::{BinaryCoverage, DebugInfoCache};
-use crate::AllowList;
+use crate::record::ChildLimits;
+use crate::{AllowList, ModuleFilter};
 
 // For a new module image, we defer setting coverage breakpoints until exit from one of these
 // functions (when present). This avoids breaking hotpatching routines in the ASan interceptor
@@ -27,6 +28,7 @@ const LIBRARY_IMAGE_DEFERRAL_TRIGGER: &str = "DllMain(";
 
 pub struct WindowsRecorder<'cache, 'data> {
     module_allowlist: AllowList,
+    module_filter: ModuleFilter,
     breakpoints: Breakpoints,
     cache: &'cache DebugInfoCache,
     deferred_breakpoints: BTreeMap<BreakpointId, (Breakpoint, DeferralState)>,
@@ -34,12 +36,26 @@ pub struct WindowsRecorder<'cache, 'data> {
     loader: &'data Loader,
     modules: BTreeMap<FilePath, (WindowsModule<'data>, DebugInfo)>,
     pub stop_error: Option<Error>,
+    pub skipped_modules: BTreeSet<FilePath>,
+
+    // If set, record descendants of the target, up to these limits.
+    child_limits: Option<ChildLimits>,
+
+    // Depth of each debugged process, and whether it is recorded, by ID.
+    processes: BTreeMap<u32, (usize, bool)>,
+
+    // Count of recorded descendants.
+    children: usize,
+
+    // Modules whose breakpoints have been set, by process ID.
+    initialized: BTreeSet<(u32, FilePath)>,
 }
 
 impl<'cache, 'data> WindowsRecorder<'cache, 'data> {
     pub fn new(
         loader: &'data Loader,
         module_allowlist: AllowList,
+        module_filter: ModuleFilter,
         cache: &'cache DebugInfoCache,
     ) -> Self {
         let breakpoints = Breakpoints::default();
@@ -47,9 +63,11 @@ impl<'cache, 'data> WindowsRecorder<'cache, 'data> {
         let coverage = BinaryCoverage::default();
         let modules = BTreeMap::new();
         let stop_error = None;
+        let skipped_modules = BTreeSet::new();
 
         Self {
             module_allowlist,
+            module_filter,
             breakpoints,
             cache,
             deferred_breakpoints,
@@ -57,9 +75,20 @@ impl<'cache, 'data> WindowsRecorder<'cache, 'data> {
             loader,
             modules,
             stop_error,
+            skipped_modules,
+            child_limits: None,
+            processes: BTreeMap::new(),
+            children: 0,
+            initialized: BTreeSet::new(),
         }
     }
 
+    /// Also record descendants of the target, within `limits`. The target must
+    /// have been created to debug its children.
+    pub fn follow_children(&mut self, limits: ChildLimits) {
+        self.child_limits = Some(limits);
+    }
+
     pub fn module_allowlist(&self) -> &AllowList {
         &self.module_allowlist
     }
@@ -69,8 +98,15 @@ impl<'cache, 'data> WindowsRecorder<'cache, 'data> {
     }
 
     fn try_on_create_process(&mut self, dbg: &mut Debugger, module: &ModuleLoadInfo) -> Result<()> {
+        let process_id = dbg.current_target().process_id();
+
+        if !self.insert_process(process_id) {
+            debug!("not recording child process {process_id}");
+            return Ok(());
+        }
+
         // Not necessary for PDB search, but enables use of other `dbghelp` APIs.
-        if let Err(err) = dbg.target().maybe_sym_initialize() {
+        if let Err(err) = dbg.current_target().maybe_sym_initialize() {
             error!(
                 "unable to initialize symbol handler for new process {}: {:?}",
                 module.path().display(),
@@ -82,9 +118,50 @@ impl<'cache, 'data> WindowsRecorder<'cache, 'data> {
     }
 
     fn try_on_load_dll(&mut self, dbg: &mut Debugger, module: &ModuleLoadInfo) -> Result<()> {
+        let process_id = dbg.current_target().process_id();
+
+        if !self.is_recorded(process_id) {
+            return Ok(());
+        }
+
         self.insert_module(dbg, module)
     }
 
+    // Track a new process, returning true if it is to be recorded. The first
+    // process is the target, and the rest are its descendants.
+    fn insert_process(&mut self, process_id: u32) -> bool {
+        if self.processes.is_empty() {
+            self.processes.insert(process_id, (0, true));
+            return true;
+        }
+
+        let parent = win_util::process::parent_id(process_id)
+            .ok()
+            .and_then(|parent| self.processes.get(&parent));
+        let (depth, recorded) = match parent {
+            Some((depth, recorded)) => (depth + 1, *recorded),
+            None => (1, true),
+        };
+
+        let recorded = recorded
+            && self.child_limits.is_some_and(|limits| {
+                depth <= limits.max_depth && self.children < limits.max_count
+            });
+
+        if recorded {
+            info!("following child process {process_id}");
+            self.children += 1;
+        }
+
+        self.processes.insert(process_id, (depth, recorded));
+
+        recorded
+    }
+
+    fn is_recorded(&self, process_id: u32) -> bool {
+        matches!(self.processes.get(&process_id), Some((_, true)))
+    }
+
     fn try_on_breakpoint(&mut self, dbg: &mut Debugger, id: BreakpointId) -> Result<()> {
         if let Some((trigger, state)) = self.deferred_breakpoints.remove(&id) {
             match state {
@@ -161,26 +238,37 @@ impl<'cache, 'data> WindowsRecorder<'cache, 'data> {
 
     fn insert_module(&mut self, dbg: &mut Debugger, module: &ModuleLoadInfo) -> Result<()> {
         let path = FilePath::new(module.path().to_string_lossy())?;
+        let process_id = dbg.current_target().process_id();
 
-        if self.modules.contains_key(&path) {
+        if !self.initialized.insert((process_id, path.clone())) {
             warn!("module coverage already initialized, skipping");
             return Ok(());
         }
 
         if !self.module_allowlist.is_allowed(&path) {
             debug!("not inserting denylisted module: {path}");
+            self.skipped_modules.insert(path);
             return Ok(());
         }
 
-        let module = if let Ok(m) = WindowsModule::load(self.loader, path.clone()) {
-            m
-        } else {
-            debug!("skipping undebuggable module: {path}");
+        if !self.module_filter.is_allowed(&path) {
+            debug!("not inserting filtered module: {path}");
+            self.skipped_modules.insert(path);
             return Ok(());
-        };
+        }
 
-        let debuginfo = module.debuginfo()?;
-        self.modules.insert(path.clone(), (module, debuginfo));
+        // The module may already be loaded by another process.
+        if !self.modules.contains_key(&path) {
+            let module = if let Ok(m) = WindowsModule::load(self.loader, path.clone()) {
+                m
+            } else {
+                debug!("skipping undebuggable module: {path}");
+                return Ok(());
+            };
+
+            let debuginfo = module.debuginfo()?;
+            self.modules.insert(path.clone(), (module, debuginfo));
+        }
 
         self.set_or_defer_module_breakpoints(dbg, path)?;
 
@@ -241,7 +329,7 @@ impl<'cache, 'data> WindowsRecorder<'cache, 'data> {
 
     fn set_module_breakpoints(&mut self, dbg: &mut Debugger, path: FilePath) -> Result<()> {
         let (module, _) = &self.modules[&path];
-        let coverage = self.cache.get_or_insert(module)?.coverage;
+        let coverage = self.cache.get_or_insert(module)?.coverage.clone();
 
         for offset in coverage.as_ref().keys().copied() {
             let breakpoint = Breakpoint::new(path.clone(), offset);
@@ -251,7 +339,8 @@ impl<'cache, 'data> WindowsRecorder<'cache, 'data> {
         let count = coverage.offsets.len();
         debug!("set {} breakpoints for module {}", count, path);
 
-        self.coverage.modules.insert(path, coverage);
+        // Keep the counts of a module already recorded in another process.
+        self.coverage.modules.entry(path).or_insert(coverage);
 
         Ok(())
     }
@@ -319,6 +408,15 @@ impl<'cache, 'data> DebugEventHandler for WindowsRecorder<'cache, 'data> {
             self.stop(dbg, err);
         }
     }
+
+    fn on_exit_process(&mut self, dbg: &mut Debugger, _exit_code: u32) {
+        let root = dbg.target().process_id();
+
+        // End recording with the target, killing any descendants still running.
+        if self.child_limits.is_some() && dbg.current_target().process_id() == root {
+            dbg.quit_debugging();
+        }
+    }
 }
 
 enum DeferralState {
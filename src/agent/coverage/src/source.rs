@@ -24,9 +24,77 @@ pub struct SourceCoverage {
     pub files: BTreeMap<FilePath, FileCoverage>,
 }
 
+impl SourceCoverage {
+    /// Merge the coverage of each file of `other`, such as that recorded for
+    /// another input.
+    pub fn merge(&mut self, other: &SourceCoverage) {
+        for (path, file) in &other.files {
+            self.files.entry(path.clone()).or_default().merge(file);
+        }
+    }
+}
+
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct FileCoverage {
     pub lines: BTreeMap<Line, Count>,
+
+    /// Functions defined in the file, by name, where known from debug info.
+    pub functions: BTreeMap<String, FunctionCoverage>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct FunctionCoverage {
+    /// Line of the entry of the function.
+    pub line: Line,
+
+    pub count: Count,
+}
+
+impl FileCoverage {
+    /// Record the `count` of `line`, which may already be recorded for another
+    /// instruction or module.
+    pub fn record_line(&mut self, line: Line, count: Count) {
+        use std::collections::btree_map::Entry;
+
+        match self.lines.entry(line) {
+            Entry::Occupied(occupied) => {
+                let old = occupied.into_mut();
+
+                // If we miss any part of a line, count it as missed.
+                let new = u32::max(old.0, count.0);
+
+                *old = Count(new);
+            }
+            Entry::Vacant(vacant) => {
+                vacant.insert(count);
+            }
+        }
+    }
+
+    pub fn merge(&mut self, other: &FileCoverage) {
+        for (line, count) in &other.lines {
+            self.record_line(*line, *count);
+        }
+
+        for (name, function) in &other.functions {
+            self.record_function(name, function.line, function.count);
+        }
+    }
+
+    /// Record the `count` of the function `name`, which may already be
+    /// recorded for another module, such as for a function of a header.
+    pub fn record_function(&mut self, name: &str, line: Line, count: Count) {
+        match self.functions.get_mut(name) {
+            Some(function) => {
+                function.line = function.line.min(line);
+                function.count = Count(u32::max(function.count.0, count.0));
+            }
+            None => {
+                self.functions
+                    .insert(name.to_owned(), FunctionCoverage { line, count });
+            }
+        }
+    }
 }
 
 // Must be nonzero.
@@ -55,8 +123,6 @@ pub fn binary_to_source_coverage(
     binary: &BinaryCoverage,
     source_allowlist: &AllowList,
 ) -> Result<SourceCoverage> {
-    use std::collections::btree_map::Entry;
-
     use symbolic::debuginfo::Object;
     use symbolic::symcache::{SymCache, SymCacheConverter};
 
@@ -107,15 +173,49 @@ pub fn binary_to_source_coverage(
         let mut blocks = Blocks::new();
 
         for function in debuginfo.functions() {
-            for offset in coverage.as_ref().keys() {
+            // The count of a function is the greatest count of its blocks.
+            let mut function_count = None;
+
+            let end = Offset(function.offset.0.saturating_add(function.size));
+            for count in coverage
+                .as_ref()
+                .range(function.offset..end)
+                .map(|(_, c)| c)
+            {
                 // Recover function blocks if it contains any coverage offset.
-                if function.contains(offset) {
+                if function_count.is_none() {
                     let function_blocks =
                         sweep_region(&*module, &debuginfo, function.offset, function.size)?;
                     blocks.extend(&function_blocks);
-                    break;
                 }
+
+                function_count = Some(u32::max(function_count.unwrap_or(0), count.0));
             }
+
+            let Some(function_count) = function_count else {
+                continue;
+            };
+
+            // The outermost location of the entry, not that of any inlinee.
+            let Some(location) = symcache.lookup(function.offset.0).last() else {
+                continue;
+            };
+            let Ok(line_number) = location.line().try_into() else {
+                continue; // line number was 0
+            };
+            let Some(file) = location.file() else {
+                continue;
+            };
+            if !source_allowlist.is_allowed(&file.full_path()) {
+                continue;
+            }
+
+            let file_path = FilePath::new(file.full_path())?;
+            source.files.entry(file_path).or_default().record_function(
+                &function.name,
+                Line(line_number),
+                Count(function_count),
+            );
         }
 
         for (offset, count) in coverage.as_ref() {
@@ -139,21 +239,7 @@ pub fn binary_to_source_coverage(
 
                             // We have a hit.
                             let file_coverage = source.files.entry(file_path).or_default();
-                            let line = Line(line_number);
-
-                            match file_coverage.lines.entry(line) {
-                                Entry::Occupied(occupied) => {
-                                    let old = occupied.into_mut();
-
-                                    // If we miss any part of a line, count it as missed.
-                                    let new = u32::max(old.0, count.0);
-
-                                    *old = Count(new);
-                                }
-                                Entry::Vacant(vacant) => {
-                                    vacant.insert(*count);
-                                }
-                            }
+                            file_coverage.record_line(Line(line_number), *count);
                         }
                     }
                 }
@@ -0,0 +1,171 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+use coverage::html::{write_report, Summary, INDEX_FILE};
+use coverage::path_map::{PathMap, PathRule};
+use coverage::source::{Count, Line, SourceCoverage};
+use debuggable_module::path::FilePath;
+use pretty_assertions::assert_eq;
+
+// The source coverage of a build of `html/project` in `/build/project`, with
+// a file whose source isn't in the project.
+const FILES: &[(&str, &[(u32, u32)])] = &[
+    (
+        "/build/project/src/main.c",
+        &[(5, 1), (6, 1), (7, 0), (9, 1)],
+    ),
+    (
+        "/build/project/src/lib/common.c",
+        &[(1, 1), (2, 1), (3, 0), (5, 1)],
+    ),
+    ("/build/project/src/missing.c", &[(1, 0), (2, 0)]),
+];
+
+fn source_coverage() -> Result<SourceCoverage> {
+    let mut source = SourceCoverage::default();
+
+    for (path, lines) in FILES {
+        let file = source.files.entry(FilePath::new(*path)?).or_default();
+
+        for (line, count) in *lines {
+            file.record_line(Line::new(*line)?, Count(*count));
+        }
+    }
+
+    Ok(source)
+}
+
+fn project() -> &'static Path {
+    Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/html/project"))
+}
+
+fn read(path: impl AsRef<Path>) -> Result<String> {
+    Ok(fs::read_to_string(path)?)
+}
+
+#[test]
+fn test_html_report() -> Result<()> {
+    let source = source_coverage()?;
+    let path_map = PathMap::new(vec![PathRule::new("/build/project/", "")]);
+    let output = tempfile::tempdir()?;
+    let report = output.path().join("coverage-report");
+
+    // A stale report is replaced.
+    fs::create_dir_all(&report)?;
+    fs::write(report.join("stale.html"), "")?;
+
+    let summary = write_report(&source, project(), &path_map, &report)?;
+
+    assert_eq!(
+        summary,
+        Summary {
+            files: 3,
+            mapped_files: 2,
+            lines: 10,
+            hit_lines: 6,
+        }
+    );
+    assert_eq!(summary.unmapped_files(), 1);
+    assert!(!report.join("stale.html").exists());
+
+    let index = read(report.join(INDEX_FILE))?;
+    for fragment in [
+        r#"<p class="summary">6 of 10 lines hit (60.0%) in 3 files, 1 unmapped</p>"#,
+        r#"<tr><td><a href="files/src/lib/common.c.html">/build/project/src/lib/common.c</a></td><td>3 / 4</td><td>75.0%</td></tr>"#,
+        r#"<tr><td><a href="files/src/main.c.html">/build/project/src/main.c</a></td><td>3 / 4</td><td>75.0%</td></tr>"#,
+        r#"<tr class="unmapped"><td>/build/project/src/missing.c (unmapped)</td><td>0 / 2</td><td>0.0%</td></tr>"#,
+        r#"<tr class="total"><td>Total</td><td>6 / 10</td><td>60.0%</td></tr>"#,
+    ] {
+        assert!(index.contains(fragment), "missing {fragment} in:\n{index}");
+    }
+
+    let main = read(report.join("files/src/main.c.html"))?;
+    for fragment in [
+        r#"<p><a href="../../index.html">Coverage report</a></p>"#,
+        r#"<p class="summary">3 of 4 lines hit (75.0%)</p>"#,
+        r#"<tr><td class="line">1</td><td class="count"></td><td class="code">#include &lt;stdio.h&gt;</td></tr>"#,
+        r#"<tr class="hit"><td class="line">6</td><td class="count">1</td><td class="code">  if (argc &gt; 1 &amp;&amp; check(argc) &lt; 2) {</td></tr>"#,
+        r#"<tr class="miss"><td class="line">7</td><td class="count">0</td><td class="code">    printf(&quot;few\n&quot;);</td></tr>"#,
+    ] {
+        assert!(main.contains(fragment), "missing {fragment} in:\n{main}");
+    }
+
+    let common = read(report.join("files/src/lib/common.c.html"))?;
+    assert!(common.contains(r#"<p><a href="../../../index.html">Coverage report</a></p>"#));
+    assert!(common.contains(
+        r#"<tr class="miss"><td class="line">3</td><td class="count">0</td><td class="code">    return 1;</td></tr>"#
+    ));
+
+    assert!(!report.join("files/src/missing.c.html").exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_html_report_unmapped() -> Result<()> {
+    // Without a rule, the recorded paths aren't found in the project.
+    let source = source_coverage()?;
+    let output = tempfile::tempdir()?;
+
+    let summary = write_report(&source, project(), &PathMap::default(), output.path())?;
+
+    assert_eq!(summary.mapped_files, 0);
+    assert_eq!(summary.unmapped_files(), 3);
+
+    let index = read(output.path().join(INDEX_FILE))?;
+    assert!(index
+        .contains(r#"<p class="summary">6 of 10 lines hit (60.0%) in 3 files, 3 unmapped</p>"#));
+    assert!(!output.path().join("files").exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_html_report_merged_paths() -> Result<()> {
+    // `main.c`, recorded at the build paths of a Linux and a Windows target.
+    let mut source = SourceCoverage::default();
+    for (path, lines) in [
+        ("/build/project/src/main.c", [(5, 1), (6, 1), (7, 0)]),
+        (r"D:\a\1\s\src\main.c", [(5, 1), (6, 0), (7, 1)]),
+    ] {
+        let file = source.files.entry(FilePath::new(path)?).or_default();
+        for (line, count) in lines {
+            file.record_line(Line::new(line)?, Count(count));
+        }
+    }
+
+    let path_map = PathMap::new(vec![
+        PathRule::new("/build/project", ""),
+        PathRule::new(r"D:\a\1\s", ""),
+    ]);
+    let output = tempfile::tempdir()?;
+
+    let summary = write_report(&source, project(), &path_map, output.path())?;
+
+    assert_eq!(
+        summary,
+        Summary {
+            files: 1,
+            mapped_files: 1,
+            lines: 3,
+            hit_lines: 3,
+        }
+    );
+
+    // The merged file is named by its rewritten path.
+    let index = read(output.path().join(INDEX_FILE))?;
+    assert!(index.contains(
+        r#"<tr><td><a href="files/src/main.c.html">src/main.c</a></td><td>3 / 3</td><td>100.0%</td></tr>"#
+    ));
+
+    let main = read(output.path().join("files/src/main.c.html"))?;
+    assert!(main.contains(
+        r#"<tr class="hit"><td class="line">7</td><td class="count">1</td><td class="code">    printf(&quot;few\n&quot;);</td></tr>"#
+    ));
+
+    Ok(())
+}
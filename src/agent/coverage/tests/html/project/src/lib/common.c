@@ -0,0 +1,6 @@
+int check(int x) {
+  if (x > 3) {
+    return 1;
+  }
+  return 0;
+}
@@ -0,0 +1,10 @@
+#include <stdio.h>
+
+int check(int x);
+
+int main(int argc, char **argv) {
+  if (argc > 1 && check(argc) < 2) {
+    printf("few\n");
+  }
+  return 0;
+}
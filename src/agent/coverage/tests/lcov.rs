@@ -0,0 +1,96 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use anyhow::Result;
+use coverage::lcov::Lcov;
+use coverage::source::{Count, Line, SourceCoverage};
+use debuggable_module::path::FilePath;
+use pretty_assertions::assert_eq;
+
+type Lines = &'static [(u32, u32)];
+type Functions = &'static [(&'static str, u32, u32)];
+
+// The source coverage of each module of a fuzzer built from the sources in
+// `onefuzz-file-format/test-data`, as (file, lines, functions). `fuzz.h` is
+// also compiled into a second module, whose coverage is merged into that of
+// the fuzzer.
+const MODULES: &[&[(&str, Lines, Functions)]] = &[
+    &[
+        (
+            "test-data/fuzz.c",
+            &[
+                (7, 1),
+                (8, 1),
+                (10, 1),
+                (13, 1),
+                (16, 1),
+                (17, 1),
+                (21, 1),
+                (22, 1),
+                (23, 1),
+                (27, 1),
+                (28, 1),
+                (29, 0),
+                (30, 0),
+                (32, 1),
+                (33, 1),
+                (37, 1),
+                (39, 0),
+                (42, 1),
+                (44, 1),
+            ],
+            &[("LLVMFuzzerTestOneInput", 7, 1)],
+        ),
+        (
+            "test-data/fuzz.h",
+            &[(3, 1), (4, 1), (5, 0)],
+            &[("check_hit_count", 3, 1)],
+        ),
+        (
+            "test-data/lib/explode.h",
+            &[(1, 0), (2, 0), (3, 0)],
+            &[("explode", 1, 0)],
+        ),
+    ],
+    &[(
+        "test-data/fuzz.h",
+        &[(3, 2), (4, 2), (5, 1)],
+        &[("check_hit_count", 3, 2)],
+    )],
+];
+
+fn source_coverage() -> Result<SourceCoverage> {
+    let mut source = SourceCoverage::default();
+
+    for module in MODULES {
+        for (path, lines, functions) in *module {
+            let file = source.files.entry(FilePath::new(*path)?).or_default();
+
+            for (line, count) in *lines {
+                file.record_line(Line::new(*line)?, Count(*count));
+            }
+            for (name, line, count) in *functions {
+                file.record_function(name, Line::new(*line)?, Count(*count));
+            }
+        }
+    }
+
+    Ok(source)
+}
+
+#[test]
+fn test_lcov() -> Result<()> {
+    let source = source_coverage()?;
+    let expected = include_str!("lcov/fuzz.info");
+
+    assert_eq!(Lcov(&source).to_string(), expected);
+
+    Ok(())
+}
+
+#[test]
+fn test_lcov_empty() {
+    let source = SourceCoverage::default();
+
+    assert_eq!(Lcov(&source).to_string(), "");
+}
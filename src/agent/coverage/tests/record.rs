@@ -0,0 +1,445 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+#[test]
+#[cfg(all(target_os = "linux", feature = "slow-tests"))]
+fn linux_module_filter_tests() {
+    use std::path::Path;
+    use std::process::Command;
+    use std::time::Duration;
+
+    use coverage::{CoverageRecorder, ModuleFilter};
+
+    fn compile(args: &[&str], dir: &Path) {
+        let status = Command::new("cc")
+            .arg("-g")
+            .args(args)
+            .current_dir(dir)
+            .status()
+            .expect("launching compiler");
+        assert!(status.success(), "cc failed: {status}");
+    }
+
+    let fixture = Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/record"));
+    let build_in = tempfile::tempdir().expect("creating tempdir");
+    let dir = build_in.path();
+
+    let check_c = fixture.join("check.c");
+    let main_c = fixture.join("main.c");
+    compile(
+        &[
+            "-shared",
+            "-fPIC",
+            "-o",
+            "libcheck.so",
+            check_c.to_str().unwrap(),
+        ],
+        dir,
+    );
+    compile(
+        &[
+            "-o",
+            "main",
+            main_c.to_str().unwrap(),
+            "-L.",
+            "-lcheck",
+            "-Wl,--build-id",
+            &format!("-Wl,-rpath,{}", dir.display()),
+        ],
+        dir,
+    );
+
+    let record = |filter: ModuleFilter| {
+        CoverageRecorder::new(Command::new(dir.join("main")))
+            .module_filter(filter)
+            .timeout(Duration::from_secs(60))
+            .record()
+            .expect("recording coverage")
+    };
+    let recorded_names = |recorded: &coverage::Recorded| -> Vec<String> {
+        recorded
+            .coverage
+            .modules
+            .keys()
+            .map(|path| path.file_name().to_owned())
+            .collect()
+    };
+
+    // By default, both the target and its library are recorded.
+    let recorded = record(ModuleFilter::default());
+    let names = recorded_names(&recorded);
+    assert!(names.contains(&"main".to_owned()), "{names:?}");
+    assert!(names.contains(&"libcheck.so".to_owned()), "{names:?}");
+    assert!(recorded.skipped_modules.is_empty());
+
+    // Modules are recorded with their build ID.
+    let main = recorded
+        .coverage
+        .modules
+        .iter()
+        .find(|(path, _)| path.file_name() == "main")
+        .map(|(_, module)| module)
+        .unwrap();
+    let id = debuggable_module::module_id(&std::fs::read(dir.join("main")).unwrap());
+    assert!(id.is_some());
+    assert_eq!(main.id, id);
+
+    // A denylisted library has no coverage, and is reported as skipped.
+    let none: &[&str] = &[];
+    let filter = ModuleFilter::new(none, &[r"^libcheck\.so$"]).unwrap();
+    let recorded = record(filter);
+    let names = recorded_names(&recorded);
+    assert!(names.contains(&"main".to_owned()), "{names:?}");
+    assert!(!names.contains(&"libcheck.so".to_owned()), "{names:?}");
+    assert!(recorded
+        .skipped_modules
+        .iter()
+        .any(|path| path.file_name() == "libcheck.so"));
+
+    // Only allowlisted modules are recorded, matched by full path.
+    let dir = std::fs::canonicalize(dir).unwrap();
+    let allow = format!("^{}/", regex::escape(&dir.to_string_lossy()));
+    let filter = ModuleFilter::new(&[allow], &[r"^libcheck\.so$"]).unwrap();
+    let recorded = record(filter);
+    assert_eq!(recorded_names(&recorded), ["main"]);
+}
+
+#[test]
+#[cfg(all(target_os = "linux", feature = "slow-tests"))]
+fn linux_timeout_tests() {
+    use std::path::Path;
+    use std::process::Command;
+    use std::time::Duration;
+
+    use coverage::{CoverageRecorder, TimerError};
+
+    let fixture = Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/record"));
+    let build_in = tempfile::tempdir().expect("creating tempdir");
+    let dir = build_in.path();
+
+    let replay_c = fixture.join("replay.c");
+    let status = Command::new("cc")
+        .args(["-g", "-o", "replay", replay_c.to_str().unwrap()])
+        .current_dir(dir)
+        .status()
+        .expect("launching compiler");
+    assert!(status.success(), "cc failed: {status}");
+
+    let record = |data: &str| {
+        let input = dir.join(data);
+        std::fs::write(&input, data).unwrap();
+
+        let mut cmd = Command::new(dir.join("replay"));
+        cmd.arg(&input);
+        CoverageRecorder::new(cmd)
+            .timeout(Duration::from_secs(5))
+            .record()
+    };
+
+    // A hanging input is killed, and fails with a timeout.
+    let err = record("hang").expect_err("recording hanging input");
+    assert!(
+        matches!(err.downcast_ref(), Some(TimerError::Timeout(..))),
+        "{err:?}"
+    );
+
+    // A crashing input is recorded, with its unsuccessful exit status.
+    let recorded = record("crash").expect("recording crashing input");
+    let status = recorded.output.status.expect("exit status");
+    assert!(!status.success());
+
+    // Inputs after the skipped ones are still recorded.
+    let recorded = record("ok").expect("recording input");
+    assert!(recorded.output.status.expect("exit status").success());
+    assert!(!recorded.coverage.modules.is_empty());
+}
+
+#[test]
+#[cfg(all(target_os = "linux", feature = "slow-tests"))]
+fn linux_follow_children_tests() {
+    use std::path::Path;
+    use std::process::Command;
+    use std::time::Duration;
+
+    use coverage::{ChildLimits, CoverageRecorder};
+    use debuggable_module::linux::LinuxModule;
+    use debuggable_module::load_module::LoadModule;
+    use debuggable_module::loader::Loader;
+    use debuggable_module::path::FilePath;
+    use debuggable_module::Module;
+
+    let fixture = Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/record"));
+    let build_in = tempfile::tempdir().expect("creating tempdir");
+    let dir = build_in.path();
+
+    let spawn_c = fixture.join("spawn.c");
+    let status = Command::new("cc")
+        .args(["-g", "-o", "spawn", spawn_c.to_str().unwrap()])
+        .current_dir(dir)
+        .status()
+        .expect("launching compiler");
+    assert!(status.success(), "cc failed: {status}");
+
+    // The offsets of `child_function()`, only run by the child.
+    let spawn = FilePath::new(dir.join("spawn").to_string_lossy()).unwrap();
+    let loader = Loader::new();
+    let module = LinuxModule::load(&loader, spawn).unwrap();
+    let debuginfo = module.debuginfo().unwrap();
+    let child_function = debuginfo
+        .functions()
+        .find(|function| function.name == "child_function")
+        .expect("child_function debuginfo")
+        .clone();
+
+    let record = |follow_children: bool, limits: ChildLimits| {
+        let recorded = CoverageRecorder::new(Command::new(dir.join("spawn")))
+            .follow_children(follow_children)
+            .child_limits(limits)
+            .timeout(Duration::from_secs(60))
+            .record()
+            .expect("recording coverage");
+        assert!(recorded.output.status.expect("exit status").success());
+
+        let (_, coverage) = recorded
+            .coverage
+            .modules
+            .into_iter()
+            .find(|(path, _)| path.file_name() == "spawn")
+            .expect("spawn coverage");
+
+        // Whether any block of `child_function()` was hit.
+        coverage
+            .offsets
+            .iter()
+            .any(|(offset, count)| child_function.contains(offset) && count.reached())
+    };
+
+    // Without following children, only the parent is recorded.
+    assert!(!record(false, ChildLimits::default()));
+
+    // The child is recorded, and its coverage merged with that of the parent.
+    assert!(record(true, ChildLimits::default()));
+
+    // Children beyond the limits run, but aren't recorded.
+    let limits = ChildLimits {
+        max_count: 0,
+        ..ChildLimits::default()
+    };
+    assert!(!record(true, limits));
+
+    let limits = ChildLimits {
+        max_depth: 0,
+        ..ChildLimits::default()
+    };
+    assert!(!record(true, limits));
+}
+
+#[test]
+#[cfg(all(target_os = "linux", feature = "slow-tests"))]
+fn linux_analysis_cache_tests() {
+    use std::path::Path;
+    use std::process::Command;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use coverage::binary::{DebugInfoCache, ModuleBinaryCoverage};
+    use coverage::{AllowList, CoverageRecorder, ModuleFilter};
+
+    let fixture = Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/record"));
+    let build_in = tempfile::tempdir().expect("creating tempdir");
+    let dir = std::fs::canonicalize(build_in.path()).unwrap();
+    let dir = dir.as_path();
+    let cache_dir = tempfile::tempdir().expect("creating tempdir");
+
+    let replay_c = fixture.join("replay.c");
+    let compile = |build_id: &str| {
+        let status = Command::new("cc")
+            .args(["-g", "-o", "replay", replay_c.to_str().unwrap()])
+            .arg(format!("-Wl,--build-id={build_id}"))
+            .current_dir(dir)
+            .status()
+            .expect("launching compiler");
+        assert!(status.success(), "cc failed: {status}");
+    };
+
+    // Only the target is recorded, so that it is the only module analyzed.
+    let none: &[&str] = &[];
+    let allow = format!("^{}/", regex::escape(&dir.to_string_lossy()));
+    let filter = ModuleFilter::new(&[allow], none).unwrap();
+
+    let input = dir.join("input");
+    std::fs::write(&input, "ok").unwrap();
+
+    let record = |cache: &Arc<DebugInfoCache>| -> ModuleBinaryCoverage {
+        let mut cmd = Command::new(dir.join("replay"));
+        cmd.arg(&input);
+        let recorded = CoverageRecorder::new(cmd)
+            .module_filter(filter.clone())
+            .debuginfo_cache(cache.clone())
+            .timeout(Duration::from_secs(60))
+            .record()
+            .expect("recording coverage");
+
+        recorded
+            .coverage
+            .modules
+            .into_values()
+            .next()
+            .expect("replay coverage")
+    };
+
+    compile("0x01");
+    let cache = Arc::new(DebugInfoCache::new(AllowList::default()).persist_to(cache_dir.path()));
+
+    // The target is analyzed once across inputs.
+    let first = record(&cache);
+    record(&cache);
+    record(&cache);
+    let stats = cache.stats();
+    assert_eq!(stats.analyzed, 1, "{stats:?}");
+    assert_eq!(stats.hits, 2, "{stats:?}");
+
+    // A rebuilt target is analyzed again, though at the same path.
+    compile("0x02");
+    let rebuilt = record(&cache);
+    assert_eq!(cache.stats().analyzed, 2);
+    assert_ne!(rebuilt.id, first.id);
+
+    // A new cache reads the sites of a saved build, as a restarted task would.
+    compile("0x01");
+    let restarted =
+        Arc::new(DebugInfoCache::new(AllowList::default()).persist_to(cache_dir.path()));
+    let loaded = record(&restarted);
+    let stats = restarted.stats();
+    assert_eq!(stats.analyzed, 0, "{stats:?}");
+    assert_eq!(stats.loaded, 1, "{stats:?}");
+    assert_eq!(loaded.id, first.id);
+    assert_eq!(
+        loaded.offsets.keys().collect::<Vec<_>>(),
+        first.offsets.keys().collect::<Vec<_>>()
+    );
+
+    // Sites saved with another source allowlist are not reused.
+    let allowlist = AllowList::parse("*/replay.c\n").unwrap();
+    let filtered = Arc::new(DebugInfoCache::new(allowlist).persist_to(cache_dir.path()));
+    record(&filtered);
+    assert_eq!(filtered.stats().analyzed, 1);
+}
+
+#[test]
+#[cfg(all(target_os = "macos", target_arch = "x86_64", feature = "slow-tests"))]
+fn macos_record_tests() {
+    use std::path::Path;
+    use std::process::Command;
+    use std::time::Duration;
+
+    use coverage::{CoverageRecorder, ModuleFilter, TimerError};
+
+    fn compile(args: &[&str], dir: &Path) {
+        let status = Command::new("cc")
+            .arg("-g")
+            .args(args)
+            .current_dir(dir)
+            .status()
+            .expect("launching compiler");
+        assert!(status.success(), "cc failed: {status}");
+    }
+
+    let fixture = Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/record"));
+    let build_in = tempfile::tempdir().expect("creating tempdir");
+    let dir = std::fs::canonicalize(build_in.path()).unwrap();
+    let dir = dir.as_path();
+
+    let check_c = fixture.join("check.c");
+    let main_c = fixture.join("main.c");
+    let replay_c = fixture.join("replay.c");
+    compile(
+        &[
+            "-dynamiclib",
+            "-o",
+            "libcheck.dylib",
+            check_c.to_str().unwrap(),
+        ],
+        dir,
+    );
+    compile(
+        &[
+            "-o",
+            "main",
+            main_c.to_str().unwrap(),
+            "-L.",
+            "-lcheck",
+            &format!("-Wl,-rpath,{}", dir.display()),
+        ],
+        dir,
+    );
+    compile(&["-o", "replay", replay_c.to_str().unwrap()], dir);
+
+    let record = |cmd: Command, filter: ModuleFilter| {
+        CoverageRecorder::new(cmd)
+            .module_filter(filter)
+            .timeout(Duration::from_secs(60))
+            .record()
+    };
+    let module = |recorded: &coverage::Recorded, name: &str| {
+        recorded
+            .coverage
+            .modules
+            .iter()
+            .find(|(path, _)| path.file_name() == name)
+            .map(|(_, module)| module.clone())
+    };
+
+    // Both the target and its library are recorded, with offsets from their
+    // headers, whatever their slides.
+    let recorded = record(Command::new(dir.join("main")), ModuleFilter::default())
+        .expect("recording coverage");
+    assert!(recorded.output.status.expect("exit status").success());
+
+    let main = module(&recorded, "main").expect("main coverage");
+    let check = module(&recorded, "libcheck.dylib").expect("libcheck.dylib coverage");
+    assert!(main.offsets.values().any(|count| count.reached()));
+    assert!(check.offsets.values().any(|count| count.reached()));
+
+    // Modules are recorded with their UUID.
+    let id = debuggable_module::module_id(&std::fs::read(dir.join("main")).unwrap());
+    assert!(id.is_some());
+    assert_eq!(main.id, id);
+
+    // Recording is repeatable, as offsets don't depend on the slide.
+    let again = record(Command::new(dir.join("main")), ModuleFilter::default())
+        .expect("recording coverage");
+    assert_eq!(module(&again, "main").unwrap().offsets, main.offsets);
+
+    // Module filters work as on other OSes.
+    let none: &[&str] = &[];
+    let filter = ModuleFilter::new(none, &[r"^libcheck\.dylib$"]).unwrap();
+    let recorded = record(Command::new(dir.join("main")), filter).expect("recording coverage");
+    assert!(module(&recorded, "main").is_some());
+    assert!(module(&recorded, "libcheck.dylib").is_none());
+    assert!(recorded
+        .skipped_modules
+        .iter()
+        .any(|path| path.file_name() == "libcheck.dylib"));
+
+    // Hanging inputs time out, and crashing inputs have unsuccessful statuses.
+    let replay = |data: &str| {
+        let input = dir.join(data);
+        std::fs::write(&input, data).unwrap();
+
+        let mut cmd = Command::new(dir.join("replay"));
+        cmd.arg(&input);
+        CoverageRecorder::new(cmd)
+            .timeout(Duration::from_secs(5))
+            .record()
+    };
+
+    let err = replay("hang").expect_err("recording hanging input");
+    assert!(
+        matches!(err.downcast_ref(), Some(TimerError::Timeout(..))),
+        "{err:?}"
+    );
+
+    let recorded = replay("crash").expect("recording crashing input");
+    assert!(!recorded.output.status.expect("exit status").success());
+}
@@ -0,0 +1,6 @@
+int check(int x) {
+  if (x > 1) {
+    return 1;
+  }
+  return 0;
+}
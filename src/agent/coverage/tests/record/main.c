@@ -0,0 +1,5 @@
+int check(int x);
+
+int main(int argc, char **argv) {
+  return check(argc);
+}
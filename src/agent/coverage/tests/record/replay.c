@@ -0,0 +1,29 @@
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+// Replays an input file, hanging on `hang` and aborting on `crash`.
+int main(int argc, char **argv) {
+  char data[16] = {0};
+
+  if (argc < 2) {
+    return 1;
+  }
+
+  FILE *input = fopen(argv[1], "r");
+  if (input == NULL) {
+    return 1;
+  }
+  fread(data, 1, sizeof(data) - 1, input);
+  fclose(input);
+
+  if (strncmp(data, "hang", 4) == 0) {
+    for (;;) {
+    }
+  }
+  if (strncmp(data, "crash", 5) == 0) {
+    abort();
+  }
+
+  return 0;
+}
@@ -0,0 +1,33 @@
+#include <stdio.h>
+#include <string.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+// Only called in the child, as `spawn child`.
+int child_function(int x) {
+  if (x > 1) {
+    return x * 2;
+  }
+  return 0;
+}
+
+// Spawns itself as a child, and waits for it.
+int main(int argc, char **argv) {
+  if (argc > 1 && strcmp(argv[1], "child") == 0) {
+    return child_function(argc) == 4 ? 0 : 1;
+  }
+
+  pid_t pid = fork();
+  if (pid < 0) {
+    return 1;
+  }
+  if (pid == 0) {
+    execl("/proc/self/exe", argv[0], "child", (char *)NULL);
+    _exit(1);
+  }
+
+  int status = 0;
+  waitpid(pid, &status, 0);
+
+  return WIFEXITED(status) ? WEXITSTATUS(status) : 1;
+}
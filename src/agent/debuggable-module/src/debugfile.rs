@@ -0,0 +1,224 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! External debug files of modules whose debug info was split off, such as
+//! stripped release builds shipped with their `.debug` files.
+//!
+//! The debug file of an ELF module is found, as by GDB, by its GNU build ID,
+//! at `.build-id/xx/yyyy.debug` under a debug file directory, or else by its
+//! `.gnu_debuglink`, beside the module, in its `.debug` subdirectory, or under
+//! a debug file directory. The `.dSYM` bundle of a Mach-O module is found
+//! beside it, or else in a debug file directory.
+
+use std::path::{Component, Path, PathBuf};
+
+use goblin::elf::Elf;
+
+use crate::{linux, macos};
+
+/// The debug file directory of distributions, searched by default.
+pub const DEFAULT_DEBUG_FILE_DIR: &str = "/usr/lib/debug";
+
+const GNU_DEBUGLINK: &str = ".gnu_debuglink";
+
+/// Directories searched for the external debug files of modules, in order.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DebugFileDirs {
+    dirs: Vec<PathBuf>,
+}
+
+impl DebugFileDirs {
+    pub fn new(dirs: impl IntoIterator<Item = PathBuf>) -> Self {
+        let mut unique = vec![];
+        for dir in dirs {
+            if !unique.contains(&dir) {
+                unique.push(dir);
+            }
+        }
+
+        Self { dirs: unique }
+    }
+
+    pub fn dirs(&self) -> &[PathBuf] {
+        &self.dirs
+    }
+
+    /// The external debug file of the ELF module `elf`, read from `data` at
+    /// `path`, if any is found.
+    ///
+    /// Candidates found by build ID must have the same build ID, and those
+    /// found by debug link must match its CRC, so that the debug info of other
+    /// builds isn't used.
+    pub fn find_elf(&self, path: &Path, elf: &Elf, data: &[u8]) -> Option<PathBuf> {
+        if let Some(build_id) = linux::build_id(elf, data) {
+            for candidate in self.build_id_paths(&build_id) {
+                if has_build_id(&candidate, &build_id) {
+                    return Some(candidate);
+                }
+            }
+        }
+
+        let (name, crc) = debuglink(elf, data)?;
+        for candidate in self.debuglink_paths(path, &name) {
+            if candidate != path && has_crc(&candidate, crc) {
+                return Some(candidate);
+            }
+        }
+
+        None
+    }
+
+    /// The debug info in the `.dSYM` bundle of the Mach-O module at `path`, if
+    /// any is found. If `uuid` is set, the debug info must have the same UUID.
+    pub fn find_dsym(&self, path: &Path, uuid: Option<&str>) -> Option<PathBuf> {
+        let name = path.file_name()?;
+        let dir = path.parent()?;
+
+        let mut bundle_name = name.to_owned();
+        bundle_name.push(".dSYM");
+
+        std::iter::once(dir)
+            .chain(self.dirs.iter().map(PathBuf::as_path))
+            .map(|dir| {
+                dir.join(&bundle_name)
+                    .join("Contents/Resources/DWARF")
+                    .join(name)
+            })
+            .find(|candidate| match std::fs::read(candidate) {
+                Ok(data) => uuid.is_none() || macos::module_id(&data).as_deref() == uuid,
+                Err(_) => false,
+            })
+    }
+
+    fn build_id_paths(&self, build_id: &str) -> Vec<PathBuf> {
+        if build_id.len() < 3 {
+            return vec![];
+        }
+
+        let (prefix, rest) = build_id.split_at(2);
+        self.dirs
+            .iter()
+            .map(|dir| {
+                dir.join(".build-id")
+                    .join(prefix)
+                    .join(format!("{rest}.debug"))
+            })
+            .collect()
+    }
+
+    fn debuglink_paths(&self, path: &Path, name: &str) -> Vec<PathBuf> {
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+        let mut paths = vec![dir.join(name), dir.join(".debug").join(name)];
+
+        // The directory of the module is mirrored under each debug file
+        // directory, as in `/usr/lib/debug/usr/bin`.
+        let relative: PathBuf = dir
+            .components()
+            .filter(|c| matches!(c, Component::Normal(..)))
+            .collect();
+        for debug_dir in &self.dirs {
+            paths.push(debug_dir.join(&relative).join(name));
+            paths.push(debug_dir.join(name));
+        }
+
+        paths
+    }
+}
+
+/// Whether the ELF module `elf` has DWARF debug info of its own.
+pub fn has_dwarf(elf: &Elf) -> bool {
+    elf.section_headers.iter().any(|header| {
+        elf.shdr_strtab.get_at(header.sh_name) == Some(".debug_info")
+            && header.file_range().is_some_and(|range| !range.is_empty())
+    })
+}
+
+/// The file name and CRC of the debug file named by the `.gnu_debuglink` of
+/// the ELF module `elf`, if any.
+pub fn debuglink(elf: &Elf, data: &[u8]) -> Option<(String, u32)> {
+    let header = elf
+        .section_headers
+        .iter()
+        .find(|header| elf.shdr_strtab.get_at(header.sh_name) == Some(GNU_DEBUGLINK))?;
+    let section = data.get(header.file_range()?)?;
+
+    // The name is NUL-terminated and padded to 4 bytes, then followed by the
+    // CRC, of the endianness of the module.
+    let len = section.iter().position(|&b| b == 0)?;
+    let name = std::str::from_utf8(&section[..len]).ok()?;
+    let crc_offset = (len + 1 + 3) & !3;
+    let crc: [u8; 4] = section.get(crc_offset..crc_offset + 4)?.try_into().ok()?;
+    let crc = if elf.little_endian {
+        u32::from_le_bytes(crc)
+    } else {
+        u32::from_be_bytes(crc)
+    };
+
+    if name.is_empty() {
+        return None;
+    }
+
+    Some((name.to_owned(), crc))
+}
+
+fn has_build_id(path: &Path, build_id: &str) -> bool {
+    let Ok(data) = std::fs::read(path) else {
+        return false;
+    };
+    let Ok(elf) = Elf::parse(&data) else {
+        return false;
+    };
+
+    linux::build_id(&elf, &data).as_deref() == Some(build_id)
+}
+
+fn has_crc(path: &Path, crc: u32) -> bool {
+    match std::fs::read(path) {
+        Ok(data) => crc32fast::hash(&data) == crc,
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_id_paths() {
+        let dirs = DebugFileDirs::new(["/setup".into(), "/usr/lib/debug".into()]);
+
+        assert_eq!(
+            dirs.build_id_paths("abcdef"),
+            [
+                PathBuf::from("/setup/.build-id/ab/cdef.debug"),
+                PathBuf::from("/usr/lib/debug/.build-id/ab/cdef.debug"),
+            ]
+        );
+        assert!(dirs.build_id_paths("ab").is_empty());
+    }
+
+    #[test]
+    fn test_debuglink_paths() {
+        let dirs = DebugFileDirs::new(["/usr/lib/debug".into()]);
+
+        assert_eq!(
+            dirs.debuglink_paths(Path::new("/setup/bin/fuzz"), "fuzz.debug"),
+            [
+                PathBuf::from("/setup/bin/fuzz.debug"),
+                PathBuf::from("/setup/bin/.debug/fuzz.debug"),
+                PathBuf::from("/usr/lib/debug/setup/bin/fuzz.debug"),
+                PathBuf::from("/usr/lib/debug/fuzz.debug"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dirs_are_unique() {
+        let dirs = DebugFileDirs::new(["/setup".into(), "/debug".into(), "/setup".into()]);
+        assert_eq!(
+            dirs.dirs(),
+            [PathBuf::from("/setup"), PathBuf::from("/debug")]
+        );
+    }
+}
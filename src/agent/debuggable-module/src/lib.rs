@@ -10,11 +10,15 @@ use std::ops::Range;
 use anyhow::{anyhow as error, Result};
 
 pub mod block;
+pub mod debugfile;
 pub mod debuginfo;
 pub mod linux;
 pub mod load_module;
 pub mod loader;
+pub mod macos;
 pub mod path;
+pub mod symbolize;
+pub mod symsrv;
 pub mod windows;
 
 use crate::debuginfo::DebugInfo;
@@ -51,6 +55,43 @@ pub trait Module<'data> {
 
     /// Debugging information derived from the module and its debuginfo.
     fn debuginfo(&self) -> Result<DebugInfo>;
+
+    /// Identity of the build of the module, if any. See [`module_id()`].
+    fn id(&self) -> Option<String>;
+}
+
+/// Identity of the build of the executable `data`, in hex: the GNU build ID of
+/// an ELF file, the GUID and age of the PDB of a PE file, or the UUID of a
+/// Mach-O file.
+///
+/// A PE file without a PDB is identified by its timestamp and image size, as
+/// in symbol stores. Other modules without an identity, such as ELF files
+/// linked without a build ID, are identified by a hash of their code, as
+/// `text-<sha256>`. See [`text_id()`].
+///
+/// Unlike a path, the identity is the same wherever the module is run, and
+/// differs between builds.
+pub fn module_id(data: &[u8]) -> Option<String> {
+    match goblin::Object::parse(data).ok()? {
+        goblin::Object::Elf(elf) => linux::module_id(&elf, data),
+        goblin::Object::PE(pe) => windows::module_id(&pe, data),
+        goblin::Object::Mach(..) => macos::module_id(data),
+        _ => None,
+    }
+}
+
+/// Identity of a module without a build identity, from the data of its code
+/// section or segment in the file. It is the same wherever the module is
+/// loaded, as only file data is hashed.
+pub fn text_id(text: &[u8]) -> String {
+    use sha2::{Digest, Sha256};
+
+    let hash: String = Sha256::digest(text)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+
+    format!("text-{hash}")
 }
 
 /// Virtual address.
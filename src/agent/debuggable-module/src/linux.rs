@@ -10,7 +10,7 @@ use goblin::elf::{program_header::PT_LOAD, Elf, ProgramHeader};
 
 use crate::debuginfo::{DebugInfo, Function};
 use crate::path::FilePath;
-use crate::{Address, Module, Offset};
+use crate::{text_id, Address, Module, Offset};
 
 impl<'data> Module<'data> for LinuxModule<'data> {
     fn executable_path(&self) -> &FilePath {
@@ -18,7 +18,7 @@ impl<'data> Module<'data> for LinuxModule<'data> {
     }
 
     fn debuginfo_path(&self) -> &FilePath {
-        &self.path
+        &self.debuginfo_path
     }
 
     fn read(&self, offset: Offset, size: u64) -> Result<&'data [u8]> {
@@ -56,8 +56,7 @@ impl<'data> Module<'data> for LinuxModule<'data> {
     }
 
     fn debuginfo_data(&self) -> &'data [u8] {
-        // Assume embedded DWARF.
-        self.data
+        self.debuginfo_data
     }
 
     fn debuginfo(&self) -> Result<DebugInfo> {
@@ -91,6 +90,42 @@ impl<'data> Module<'data> for LinuxModule<'data> {
 
         Ok(DebugInfo::new(functions, None))
     }
+
+    fn id(&self) -> Option<String> {
+        module_id(&self.elf, self.data)
+    }
+}
+
+/// Identity of an ELF file: its build ID, or else the [`text_id()`] of its
+/// `.text` section.
+pub fn module_id(elf: &Elf, data: &[u8]) -> Option<String> {
+    build_id(elf, data).or_else(|| text_section(elf, data).map(text_id))
+}
+
+fn text_section<'a>(elf: &Elf, data: &'a [u8]) -> Option<&'a [u8]> {
+    let header = elf
+        .section_headers
+        .iter()
+        .find(|header| elf.shdr_strtab.get_at(header.sh_name) == Some(".text"))?;
+
+    data.get(header.file_range()?)
+}
+
+/// The GNU build ID of an ELF file, in lowercase hex.
+pub fn build_id(elf: &Elf, data: &[u8]) -> Option<String> {
+    use goblin::elf::note::NT_GNU_BUILD_ID;
+
+    for note in elf.iter_note_headers(data)? {
+        let Ok(note) = note else {
+            continue;
+        };
+
+        if note.n_type == NT_GNU_BUILD_ID && note.name == "GNU" && !note.desc.is_empty() {
+            return Some(note.desc.iter().map(|b| format!("{b:02x}")).collect());
+        }
+    }
+
+    None
 }
 
 pub struct LinuxModule<'data> {
@@ -98,10 +133,25 @@ pub struct LinuxModule<'data> {
     data: &'data [u8],
     elf: Elf<'data>,
     vmmap: VmMap,
+
+    debuginfo_path: FilePath,
+    debuginfo_data: &'data [u8],
 }
 
 impl<'data> LinuxModule<'data> {
+    /// A module read from `data`, with embedded debug info, if any.
     pub fn new(path: FilePath, data: &'data [u8]) -> Result<Self> {
+        Self::with_debuginfo(path.clone(), data, path, data)
+    }
+
+    /// A module read from `data`, with debug info read from the external debug
+    /// file `debuginfo_data`, such as one split off by `objcopy`.
+    pub fn with_debuginfo(
+        path: FilePath,
+        data: &'data [u8],
+        debuginfo_path: FilePath,
+        debuginfo_data: &'data [u8],
+    ) -> Result<Self> {
         let elf = Elf::parse(data)?;
         let vmmap = VmMap::new(&elf)?;
 
@@ -110,6 +160,8 @@ impl<'data> LinuxModule<'data> {
             data,
             elf,
             vmmap,
+            debuginfo_path,
+            debuginfo_data,
         })
     }
 
@@ -117,10 +169,24 @@ impl<'data> LinuxModule<'data> {
         &self.elf
     }
 
+    /// The module-relative virtual offset of the file offset `file_offset`,
+    /// such as of a frame in a mapping of the module, if it is loaded.
+    pub fn file_offset_to_offset(&self, file_offset: u64) -> Option<Offset> {
+        let segment = self
+            .vmmap
+            .segments
+            .values()
+            .find(|segment| segment.file_range.contains(&file_offset))?;
+        let addr = segment.vm_range.start + (file_offset - segment.file_range.start);
+
+        Address(addr).offset_from(Address(self.vmmap.base())).ok()
+    }
+
     fn noreturns(&self) -> Result<BTreeSet<Offset>> {
         use gimli::{AttributeValue, DW_AT_low_pc, DW_AT_noreturn, DW_TAG_subprogram, Dwarf};
 
-        let loader = |s| self.load_section(s);
+        let elf = Elf::parse(self.debuginfo_data)?;
+        let loader = |s| load_section(&elf, self.debuginfo_data, s);
         let dwarf = Dwarf::load(loader)?;
 
         let mut noreturns = BTreeSet::new();
@@ -180,24 +246,28 @@ impl<'data> LinuxModule<'data> {
 
         Ok(noreturns)
     }
+}
 
-    fn load_section(&self, section: SectionId) -> Result<EndianSlice<LittleEndian>> {
-        for shdr in &self.elf.section_headers {
-            if let Some(name) = self.elf.shdr_strtab.get_at(shdr.sh_name) {
-                if name == section.name() {
-                    if let Some(range) = shdr.file_range() {
-                        if let Some(data) = self.data.get(range) {
-                            let data = EndianSlice::new(data, LittleEndian);
-                            return Ok(data);
-                        }
+fn load_section<'data>(
+    elf: &Elf,
+    data: &'data [u8],
+    section: SectionId,
+) -> Result<EndianSlice<'data, LittleEndian>> {
+    for shdr in &elf.section_headers {
+        if let Some(name) = elf.shdr_strtab.get_at(shdr.sh_name) {
+            if name == section.name() {
+                if let Some(range) = shdr.file_range() {
+                    if let Some(data) = data.get(range) {
+                        let data = EndianSlice::new(data, LittleEndian);
+                        return Ok(data);
                     }
                 }
             }
         }
-
-        let data = EndianSlice::new(&[], LittleEndian);
-        Ok(data)
     }
+
+    let data = EndianSlice::new(&[], LittleEndian);
+    Ok(data)
 }
 
 struct VmMap {
@@ -2,13 +2,17 @@
 // Licensed under the MIT License.
 
 use std::io::Cursor;
+use std::path::PathBuf;
 
 use anyhow::{bail, Result};
-use goblin::Hint;
+use goblin::{elf::Elf, Hint};
 
+use crate::debugfile::has_dwarf;
 use crate::linux::LinuxModule;
 use crate::loader::Loader;
+use crate::macos::{dsym_path, module_id, MacOSModule};
 use crate::path::FilePath;
+use crate::symsrv::PdbKey;
 use crate::windows::WindowsModule;
 use crate::Module;
 
@@ -22,20 +26,73 @@ where
 impl<'data> LoadModule<'data> for LinuxModule<'data> {
     fn load(loader: &'data Loader, elf_path: FilePath) -> Result<Self> {
         let data = loader.load(&elf_path)?;
+
+        // Stripped modules may have their debug info in an external file.
+        let elf = Elf::parse(data)?;
+        if !has_dwarf(&elf) {
+            let found = loader
+                .debug_file_dirs()
+                .find_elf(elf_path.as_path(), &elf, data);
+
+            if let Some(debuginfo_path) = found {
+                match load_debug_file(loader, debuginfo_path) {
+                    Ok((debuginfo_path, debuginfo_data)) => {
+                        return LinuxModule::with_debuginfo(
+                            elf_path,
+                            data,
+                            debuginfo_path,
+                            debuginfo_data,
+                        );
+                    }
+                    Err(err) => {
+                        warn!("unable to load debug file of {elf_path}: {err:?}");
+                    }
+                }
+            }
+        }
+
         LinuxModule::new(elf_path, data)
     }
 }
 
 impl<'data> LoadModule<'data> for WindowsModule<'data> {
     fn load(loader: &'data Loader, pe_path: FilePath) -> Result<Self> {
-        let pdb_path = find_pdb(&pe_path)?;
-        let pdb_data = loader.load(&pdb_path)?;
         let pe_data = loader.load(&pe_path)?;
+        let pdb_path = find_pdb(loader, &pe_path, pe_data)?;
+        let pdb_data = loader.load(&pdb_path)?;
 
         WindowsModule::new(pe_path, pe_data, pdb_path, pdb_data)
     }
 }
 
+impl<'data> LoadModule<'data> for MacOSModule<'data> {
+    fn load(loader: &'data Loader, exe_path: FilePath) -> Result<Self> {
+        let exe_data = loader.load(&exe_path)?;
+
+        // Prefer the debug info linked by `dsymutil`, if any, beside the module
+        // or else in a debug file directory.
+        let adjacent = dsym_path(&exe_path)?;
+        let found = if adjacent.as_path().exists() {
+            Some(adjacent)
+        } else {
+            // Modules without a UUID are identified by their code, which
+            // isn't in their debug info.
+            let uuid = module_id(exe_data).filter(|id| !id.starts_with("text-"));
+            loader
+                .debug_file_dirs()
+                .find_dsym(exe_path.as_path(), uuid.as_deref())
+                .and_then(|path| FilePath::new(path.to_string_lossy()).ok())
+        };
+
+        if let Some(dsym_path) = found {
+            let dsym_data = loader.load(&dsym_path)?;
+            MacOSModule::new(exe_path, exe_data, dsym_path, dsym_data)
+        } else {
+            MacOSModule::new(exe_path.clone(), exe_data, exe_path, exe_data)
+        }
+    }
+}
+
 impl<'data> LoadModule<'data> for Box<dyn Module<'data> + 'data> {
     fn load(loader: &'data Loader, exe_path: FilePath) -> Result<Self> {
         let exe_data = loader.load(&exe_path)?;
@@ -52,6 +109,10 @@ impl<'data> LoadModule<'data> for Box<dyn Module<'data> + 'data> {
                 let module = WindowsModule::load(loader, exe_path)?;
                 Box::new(module)
             }
+            Hint::Mach(..) | Hint::MachFat(..) => {
+                let module = MacOSModule::load(loader, exe_path)?;
+                Box::new(module)
+            }
             _ => {
                 bail!("unknown module file format: {:x?}", hint);
             }
@@ -61,7 +122,13 @@ impl<'data> LoadModule<'data> for Box<dyn Module<'data> + 'data> {
     }
 }
 
-fn find_pdb(pe_path: &FilePath) -> Result<FilePath> {
+fn load_debug_file(loader: &Loader, path: PathBuf) -> Result<(FilePath, &[u8])> {
+    let path = FilePath::new(path.to_string_lossy())?;
+    let data = loader.load(&path)?;
+    Ok((path, data))
+}
+
+fn find_pdb(loader: &Loader, pe_path: &FilePath, pe_data: &[u8]) -> Result<FilePath> {
     // Check if the PDB is in the same dir as the PE.
     let same_dir_path = pe_path.with_extension("pdb");
 
@@ -69,5 +136,12 @@ fn find_pdb(pe_path: &FilePath) -> Result<FilePath> {
         return FilePath::new(same_dir_path);
     }
 
+    // Otherwise, look it up by the key recorded in the PE.
+    if let Some(key) = PdbKey::from_pe_data(pe_data)? {
+        if let Some(path) = loader.symbol_path().find_local(&key) {
+            return FilePath::new(path.to_string_lossy());
+        }
+    }
+
     bail!("could not find PDB for PE `{pe_path}`");
 }
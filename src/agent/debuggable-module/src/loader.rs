@@ -3,11 +3,15 @@
 
 use anyhow::Result;
 
+use crate::debugfile::DebugFileDirs;
 use crate::path::FilePath;
+use crate::symsrv::SymbolPath;
 
 #[derive(Default)]
 pub struct Loader {
     loaded: elsa::sync::FrozenMap<FilePath, Box<[u8]>>,
+    symbol_path: SymbolPath,
+    debug_file_dirs: DebugFileDirs,
 }
 
 impl Loader {
@@ -15,6 +19,32 @@ impl Loader {
         Self::default()
     }
 
+    /// A loader which also searches `symbol_path` for the PDBs of PE modules
+    /// without an adjacent PDB.
+    pub fn with_symbol_path(symbol_path: SymbolPath) -> Self {
+        Self {
+            symbol_path,
+            ..Self::default()
+        }
+    }
+
+    pub fn symbol_path(&self) -> &SymbolPath {
+        &self.symbol_path
+    }
+
+    /// Also search `dirs` for the external debug files of modules without
+    /// debug info of their own.
+    pub fn with_debug_file_dirs(self, dirs: DebugFileDirs) -> Self {
+        Self {
+            debug_file_dirs: dirs,
+            ..self
+        }
+    }
+
+    pub fn debug_file_dirs(&self) -> &DebugFileDirs {
+        &self.debug_file_dirs
+    }
+
     pub fn load(&self, path: &FilePath) -> Result<&[u8]> {
         // Note: if we ever have this callable in parallel from
         //       multiple threads, we should use some kind of
@@ -0,0 +1,412 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Mach-O modules, as loaded on macOS.
+//!
+//! Only 64-bit, little-endian images (`x86_64` and `arm64`) are supported,
+//! optionally within a universal ("fat") file. Debug info is read from the
+//! module's `.dSYM` bundle, if any, and otherwise from the module itself.
+
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use anyhow::{bail, Result};
+
+use crate::debuginfo::{DebugInfo, Function};
+use crate::path::FilePath;
+use crate::{text_id, Address, Module, Offset};
+
+pub const MH_MAGIC_64: u32 = 0xfeed_facf;
+pub const MH_EXECUTE: u32 = 0x2;
+
+pub const CPU_TYPE_X86_64: u32 = 0x0100_0007;
+pub const CPU_TYPE_ARM64: u32 = 0x0100_000c;
+
+const FAT_MAGIC: u32 = 0xcafe_babe;
+const FAT_ARCH_SIZE: usize = 20;
+
+const MACH_HEADER_64_SIZE: usize = 32;
+
+const LC_SEGMENT_64: u32 = 0x19;
+const LC_UUID: u32 = 0x1b;
+const LC_MAIN: u32 = 0x8000_0028;
+
+const PAGEZERO: &str = "__PAGEZERO";
+const TEXT: &str = "__TEXT";
+
+impl<'data> Module<'data> for MacOSModule<'data> {
+    fn executable_path(&self) -> &FilePath {
+        &self.path
+    }
+
+    fn debuginfo_path(&self) -> &FilePath {
+        &self.debuginfo_path
+    }
+
+    fn read(&self, offset: Offset, size: u64) -> Result<&'data [u8]> {
+        if size == 0 {
+            return Ok(&[]);
+        }
+
+        let addr = self.base_address().offset_by(offset)?.0;
+
+        for segment in &self.commands.segments {
+            if segment.vm_range.contains(&addr) {
+                // Segment-relative offset of the virtual address.
+                let seg_off = addr - segment.vm_range.start;
+
+                let file_size = segment.file_range.end - segment.file_range.start;
+                let available = file_size.saturating_sub(seg_off);
+                let read_size = u64::min(available, size);
+
+                let lo = (segment.file_range.start + seg_off) as usize;
+                let hi = lo.saturating_add(read_size as usize);
+
+                if hi > self.data.len() {
+                    bail!("segment {} extends past end of module", segment.name);
+                }
+
+                return Ok(&self.data[lo..hi]);
+            }
+        }
+
+        bail!("no data for VM offset: {:x}", offset.0);
+    }
+
+    fn base_address(&self) -> Address {
+        // Unwrap checked by ctor.
+        Address(self.commands.text_vmaddr().unwrap())
+    }
+
+    fn executable_data(&self) -> &'data [u8] {
+        self.data
+    }
+
+    fn debuginfo_data(&self) -> &'data [u8] {
+        self.debuginfo_data
+    }
+
+    fn debuginfo(&self) -> Result<DebugInfo> {
+        use symbolic::debuginfo::Object;
+        use symbolic::demangle::{Demangle, DemangleOptions};
+
+        let opts = DemangleOptions::complete();
+
+        let object = Object::parse(self.debuginfo_data())?;
+        let session = object.debug_session()?;
+
+        let mut functions = BTreeMap::new();
+
+        for function in session.functions() {
+            let function = function?;
+
+            let name = function.name.try_demangle(opts).into_owned();
+            let offset = Offset(function.address); // Misnamed.
+            let size = function.size;
+
+            let f = Function {
+                name,
+                noreturn: false,
+                offset,
+                size,
+            };
+            functions.insert(offset, f);
+        }
+
+        Ok(DebugInfo::new(functions, None))
+    }
+
+    fn id(&self) -> Option<String> {
+        self.commands.id(self.data)
+    }
+}
+
+pub struct MacOSModule<'data> {
+    path: FilePath,
+    data: &'data [u8],
+    commands: LoadCommands,
+
+    debuginfo_path: FilePath,
+    debuginfo_data: &'data [u8],
+}
+
+impl<'data> MacOSModule<'data> {
+    /// A module read from `data`, with debug info read from `debuginfo_data`,
+    /// which may be the same. For universal files, the image for the host
+    /// architecture is used.
+    pub fn new(
+        path: FilePath,
+        data: &'data [u8],
+        debuginfo_path: FilePath,
+        debuginfo_data: &'data [u8],
+    ) -> Result<Self> {
+        let data = host_slice(data)?;
+        let debuginfo_data = host_slice(debuginfo_data)?;
+        let commands = LoadCommands::parse(data)?;
+
+        if commands.text_vmaddr().is_none() {
+            bail!("Mach-O module has no {TEXT} segment: {path}");
+        }
+
+        Ok(Self {
+            path,
+            data,
+            commands,
+            debuginfo_path,
+            debuginfo_data,
+        })
+    }
+
+    pub fn load_commands(&self) -> &LoadCommands {
+        &self.commands
+    }
+}
+
+/// The path of the debug info of the module at `path` in its `.dSYM` bundle,
+/// as written by `dsymutil`.
+pub fn dsym_path(path: &FilePath) -> Result<FilePath> {
+    FilePath::new(format!(
+        "{}.dSYM/Contents/Resources/DWARF/{}",
+        path,
+        path.file_name()
+    ))
+}
+
+/// Identity of the Mach-O file `data`: its UUID, in lowercase hex, or else the
+/// [`text_id()`] of its `__TEXT` segment.
+pub fn module_id(data: &[u8]) -> Option<String> {
+    let data = host_slice(data).ok()?;
+    LoadCommands::parse(data).ok()?.id(data)
+}
+
+fn hex(bytes: [u8; 16]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// The CPU type of the host, as in Mach-O headers.
+pub fn host_cputype() -> Option<u32> {
+    if cfg!(target_arch = "x86_64") {
+        Some(CPU_TYPE_X86_64)
+    } else if cfg!(target_arch = "aarch64") {
+        Some(CPU_TYPE_ARM64)
+    } else {
+        None
+    }
+}
+
+/// The image of the host architecture in the Mach-O file `data`, if it is a
+/// universal file, or else `data` itself.
+pub fn host_slice(data: &[u8]) -> Result<&[u8]> {
+    match host_cputype() {
+        Some(cputype) => slice_for(data, cputype),
+        None => bail!("no Mach-O CPU type for host architecture"),
+    }
+}
+
+/// The image for `cputype` in the Mach-O file `data`, if it is a universal
+/// file, or else `data` itself.
+pub fn slice_for(data: &[u8], cputype: u32) -> Result<&[u8]> {
+    // The headers of universal files are big-endian.
+    let read_be = |offset: usize| -> Result<u32> {
+        match data.get(offset..offset + 4) {
+            Some(bytes) => Ok(u32::from_be_bytes(bytes.try_into()?)),
+            None => bail!("truncated universal Mach-O header"),
+        }
+    };
+
+    if data.len() < 4 || read_be(0)? != FAT_MAGIC {
+        return Ok(data);
+    }
+
+    let count = read_be(4)? as usize;
+
+    for index in 0..count {
+        let arch = 8 + index * FAT_ARCH_SIZE;
+
+        if read_be(arch)? != cputype {
+            continue;
+        }
+
+        let offset = read_be(arch + 8)? as usize;
+        let size = read_be(arch + 12)? as usize;
+
+        return match data.get(offset..offset.saturating_add(size)) {
+            Some(slice) => Ok(slice),
+            None => bail!("universal Mach-O slice out of bounds"),
+        };
+    }
+
+    bail!("universal Mach-O has no image for CPU type {cputype:#x}");
+}
+
+/// The load commands of a Mach-O image used to locate it in memory.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct LoadCommands {
+    pub filetype: u32,
+    pub cputype: u32,
+    pub segments: Vec<Segment>,
+    pub uuid: Option<[u8; 16]>,
+
+    /// File offset of the entry point of an executable, from `LC_MAIN`.
+    pub entry_offset: Option<u64>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Segment {
+    pub name: String,
+    pub vm_range: Range<u64>,
+    pub file_range: Range<u64>,
+}
+
+impl LoadCommands {
+    /// The size of the header and load commands of the image starting with
+    /// `header`, such as to read them from memory.
+    pub fn size(header: &[u8]) -> Result<usize> {
+        let header = Reader(header);
+
+        if header.u32(0)? != MH_MAGIC_64 {
+            bail!("not a 64-bit little-endian Mach-O image");
+        }
+
+        Ok(MACH_HEADER_64_SIZE + header.u32(20)? as usize)
+    }
+
+    /// Parse the load commands of the image at the start of `data`, which may
+    /// be a file or the image in memory.
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let end = Self::size(data)?;
+        let data = Reader(data);
+
+        let mut commands = Self {
+            cputype: data.u32(4)?,
+            filetype: data.u32(12)?,
+            ..Self::default()
+        };
+
+        let count = data.u32(16)?;
+        let mut cmd = MACH_HEADER_64_SIZE;
+
+        for _ in 0..count {
+            let kind = data.u32(cmd)?;
+            let size = data.u32(cmd + 4)? as usize;
+
+            if size < 8 || cmd + size > end {
+                bail!("invalid Mach-O load command size: {size}");
+            }
+
+            match kind {
+                LC_SEGMENT_64 => {
+                    let name = data.bytes(cmd + 8, 16)?;
+                    let name = name.split(|b| *b == 0).next().unwrap_or_default();
+                    let name = String::from_utf8_lossy(name).into_owned();
+
+                    let vmaddr = data.u64(cmd + 24)?;
+                    let vmsize = data.u64(cmd + 32)?;
+                    let fileoff = data.u64(cmd + 40)?;
+                    let filesize = data.u64(cmd + 48)?;
+
+                    commands.segments.push(Segment {
+                        name,
+                        vm_range: vmaddr..vmaddr.saturating_add(vmsize),
+                        file_range: fileoff..fileoff.saturating_add(filesize),
+                    });
+                }
+                LC_UUID => {
+                    commands.uuid = Some(data.bytes(cmd + 8, 16)?.try_into()?);
+                }
+                LC_MAIN => {
+                    commands.entry_offset = Some(data.u64(cmd + 8)?);
+                }
+                _ => {}
+            }
+
+            cmd += size;
+        }
+
+        Ok(commands)
+    }
+
+    /// Identity of the image `data` with these load commands. See
+    /// [`module_id()`].
+    pub fn id(&self, data: &[u8]) -> Option<String> {
+        if let Some(uuid) = self.uuid {
+            return Some(hex(uuid));
+        }
+
+        let text = self.segments.iter().find(|segment| segment.name == TEXT)?;
+        let lo = usize::try_from(text.file_range.start).ok()?;
+        let hi = usize::try_from(text.file_range.end).ok()?;
+
+        data.get(lo..hi).map(text_id)
+    }
+
+    /// The preferred address of the `__TEXT` segment, which starts with the
+    /// Mach-O header. Module offsets are relative to it.
+    pub fn text_vmaddr(&self) -> Option<u64> {
+        self.segments
+            .iter()
+            .find(|segment| segment.name == TEXT)
+            .map(|segment| segment.vm_range.start)
+    }
+
+    /// The ASLR slide of the image when its header is loaded at `load_address`:
+    /// the distance of each segment from its preferred address.
+    pub fn slide(&self, load_address: Address) -> Result<u64> {
+        match self.text_vmaddr() {
+            Some(vmaddr) => Ok(load_address.0.wrapping_sub(vmaddr)),
+            None => bail!("Mach-O image has no {TEXT} segment"),
+        }
+    }
+
+    /// The address ranges of the segments of the image, when loaded with
+    /// `slide`.
+    pub fn loaded_ranges(&self, slide: u64) -> Vec<Range<u64>> {
+        self.segments
+            .iter()
+            .filter(|segment| segment.name != PAGEZERO)
+            .map(|segment| {
+                let start = segment.vm_range.start.wrapping_add(slide);
+                let end = segment.vm_range.end.wrapping_add(slide);
+                start..end
+            })
+            .collect()
+    }
+
+    /// The module offset of the entry point of an executable.
+    pub fn entry_point(&self) -> Option<Offset> {
+        let entry = self.entry_offset?;
+
+        // The entry point is given as a file offset, so find its segment.
+        let text_vmaddr = self.text_vmaddr()?;
+        self.segments
+            .iter()
+            .find(|segment| segment.file_range.contains(&entry) && segment.name != PAGEZERO)
+            .map(|segment| {
+                let vmaddr = segment.vm_range.start + (entry - segment.file_range.start);
+                Offset(vmaddr - text_vmaddr)
+            })
+    }
+}
+
+// Little-endian reads, checked against the end of the data.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn bytes(&self, offset: usize, size: usize) -> Result<&'a [u8]> {
+        match self.0.get(offset..offset.saturating_add(size)) {
+            Some(bytes) => Ok(bytes),
+            None => bail!("truncated Mach-O load commands"),
+        }
+    }
+
+    fn u32(&self, offset: usize) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes(offset, 4)?.try_into()?))
+    }
+
+    fn u64(&self, offset: usize) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.bytes(offset, 8)?.try_into()?))
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,267 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use super::*;
+
+const UUID: [u8; 16] = [
+    0x1d, 0x2e, 0x3f, 0x40, 0x5a, 0x6b, 0x4c, 0x7d, 0x8e, 0x9f, 0xa0, 0xb1, 0xc2, 0xd3, 0xe4, 0xf5,
+];
+
+const TEXT_VMADDR: u64 = 0x1_0000_0000;
+
+// A minimal `x86_64` executable, as laid out by `ld64`: a `__PAGEZERO`,
+// `__TEXT` starting with the header at file offset 0, and `__DATA`.
+fn image(cputype: u32) -> Vec<u8> {
+    let mut cmds = vec![];
+    segment(&mut cmds, PAGEZERO, 0, TEXT_VMADDR, 0, 0);
+    segment(&mut cmds, TEXT, TEXT_VMADDR, 0x2000, 0, 0x2000);
+    segment(
+        &mut cmds,
+        "__DATA",
+        TEXT_VMADDR + 0x2000,
+        0x1000,
+        0x2000,
+        0x800,
+    );
+
+    cmds.extend(LC_UUID.to_le_bytes());
+    cmds.extend(24u32.to_le_bytes());
+    cmds.extend(UUID);
+
+    cmds.extend(LC_MAIN.to_le_bytes());
+    cmds.extend(24u32.to_le_bytes());
+    cmds.extend(0x1f30u64.to_le_bytes()); // entryoff
+    cmds.extend(0u64.to_le_bytes()); // stacksize
+
+    let mut data = vec![];
+    data.extend(MH_MAGIC_64.to_le_bytes());
+    data.extend(cputype.to_le_bytes());
+    data.extend(3u32.to_le_bytes()); // cpusubtype
+    data.extend(MH_EXECUTE.to_le_bytes());
+    data.extend(5u32.to_le_bytes()); // ncmds
+    data.extend((cmds.len() as u32).to_le_bytes());
+    data.extend(0u32.to_le_bytes()); // flags
+    data.extend(0u32.to_le_bytes()); // reserved
+    data.extend(cmds);
+
+    data.resize(0x2800, 0);
+    data[0x1f30] = 0x55; // push rbp
+    data[0x2000] = 0xaa;
+
+    data
+}
+
+fn segment(cmds: &mut Vec<u8>, name: &str, vmaddr: u64, vmsize: u64, fileoff: u64, filesize: u64) {
+    let mut segname = [0u8; 16];
+    segname[..name.len()].copy_from_slice(name.as_bytes());
+
+    cmds.extend(LC_SEGMENT_64.to_le_bytes());
+    cmds.extend(72u32.to_le_bytes());
+    cmds.extend(segname);
+    cmds.extend(vmaddr.to_le_bytes());
+    cmds.extend(vmsize.to_le_bytes());
+    cmds.extend(fileoff.to_le_bytes());
+    cmds.extend(filesize.to_le_bytes());
+    cmds.extend(7u32.to_le_bytes()); // maxprot
+    cmds.extend(5u32.to_le_bytes()); // initprot
+    cmds.extend(0u32.to_le_bytes()); // nsects
+    cmds.extend(0u32.to_le_bytes()); // flags
+}
+
+// A universal file of `slices`, aligned to 4 KiB.
+fn universal(slices: &[(u32, Vec<u8>)]) -> Vec<u8> {
+    let mut data = vec![];
+    data.extend(FAT_MAGIC.to_be_bytes());
+    data.extend((slices.len() as u32).to_be_bytes());
+
+    let mut offset = 0x1000u32;
+    for (cputype, slice) in slices {
+        data.extend(cputype.to_be_bytes());
+        data.extend(0u32.to_be_bytes());
+        data.extend(offset.to_be_bytes());
+        data.extend((slice.len() as u32).to_be_bytes());
+        data.extend(12u32.to_be_bytes());
+        offset += (slice.len() as u32 + 0xfff) & !0xfff;
+    }
+
+    for (_, slice) in slices {
+        data.resize((data.len() + 0xfff) & !0xfff, 0);
+        data.extend(slice);
+    }
+
+    data
+}
+
+#[test]
+fn test_parse_load_commands() -> Result<()> {
+    let data = image(CPU_TYPE_X86_64);
+    let commands = LoadCommands::parse(&data)?;
+
+    assert_eq!(commands.cputype, CPU_TYPE_X86_64);
+    assert_eq!(commands.filetype, MH_EXECUTE);
+    assert_eq!(commands.uuid, Some(UUID));
+    assert_eq!(commands.text_vmaddr(), Some(TEXT_VMADDR));
+
+    let names: Vec<_> = commands.segments.iter().map(|s| s.name.as_str()).collect();
+    assert_eq!(names, [PAGEZERO, TEXT, "__DATA"]);
+    assert_eq!(
+        commands.segments[2].vm_range,
+        TEXT_VMADDR + 0x2000..TEXT_VMADDR + 0x3000
+    );
+    assert_eq!(commands.segments[2].file_range, 0x2000..0x2800);
+
+    // The entry point is given as a file offset within `__TEXT`.
+    assert_eq!(commands.entry_point(), Some(Offset(0x1f30)));
+
+    // Only the header and load commands are needed, as when read from memory.
+    let size = LoadCommands::size(&data)?;
+    assert_eq!(LoadCommands::parse(&data[..size])?, commands);
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_invalid() {
+    let data = image(CPU_TYPE_X86_64);
+
+    assert!(LoadCommands::parse(&data[..16]).is_err());
+    assert!(LoadCommands::parse(&data[..64]).is_err());
+
+    // 32-bit and big-endian images are unsupported.
+    let mut magic = data.clone();
+    magic[..4].copy_from_slice(&0xfeed_faceu32.to_le_bytes());
+    assert!(LoadCommands::parse(&magic).is_err());
+
+    // A load command may not extend past the end of the load commands.
+    let mut size = data;
+    size[MACH_HEADER_64_SIZE + 4..MACH_HEADER_64_SIZE + 8]
+        .copy_from_slice(&0x1000u32.to_le_bytes());
+    assert!(LoadCommands::parse(&size).is_err());
+}
+
+#[test]
+fn test_slide() -> Result<()> {
+    let commands = LoadCommands::parse(&image(CPU_TYPE_X86_64))?;
+
+    // Loaded at its preferred address.
+    assert_eq!(commands.slide(Address(TEXT_VMADDR))?, 0);
+
+    let load_address = Address(0x1_0ab3_c000);
+    let slide = commands.slide(load_address)?;
+    assert_eq!(slide, 0xab3_c000);
+
+    // `__PAGEZERO` isn't mapped, and the other segments are moved by the slide.
+    assert_eq!(
+        commands.loaded_ranges(slide),
+        [0x1_0ab3_c000..0x1_0ab3_e000, 0x1_0ab3_e000..0x1_0ab3_f000]
+    );
+
+    // Addresses in the loaded image are at the same offsets as in the file.
+    let entry = commands.entry_point().unwrap();
+    let addr = load_address.offset_by(entry)?;
+    assert_eq!(addr, Address(0x1_0ab3_df30));
+    assert_eq!(addr.offset_from(load_address)?, entry);
+    assert_eq!(
+        Address(addr.0 - slide).offset_from(Address(TEXT_VMADDR))?,
+        entry
+    );
+
+    // Dylibs have no `__PAGEZERO`, may prefer to load at 0, and be slid anywhere.
+    let mut dylib = commands.clone();
+    dylib.segments.remove(0);
+    for segment in &mut dylib.segments {
+        segment.vm_range.start -= TEXT_VMADDR;
+        segment.vm_range.end -= TEXT_VMADDR;
+    }
+    assert_eq!(dylib.slide(Address(0x7ff8_1000_0000))?, 0x7ff8_1000_0000);
+
+    Ok(())
+}
+
+#[test]
+fn test_universal() -> Result<()> {
+    let x86_64 = image(CPU_TYPE_X86_64);
+    let arm64 = image(CPU_TYPE_ARM64);
+    let data = universal(&[
+        (CPU_TYPE_X86_64, x86_64.clone()),
+        (CPU_TYPE_ARM64, arm64.clone()),
+    ]);
+
+    assert_eq!(slice_for(&data, CPU_TYPE_X86_64)?, &x86_64[..]);
+    assert_eq!(slice_for(&data, CPU_TYPE_ARM64)?, &arm64[..]);
+    assert!(slice_for(&data, 0x0000_0007).is_err());
+
+    // Thin files are their own slice.
+    assert_eq!(slice_for(&x86_64, CPU_TYPE_ARM64)?, &x86_64[..]);
+
+    Ok(())
+}
+
+#[test]
+fn test_module() -> Result<()> {
+    let Some(cputype) = host_cputype() else {
+        return Ok(());
+    };
+
+    let data = universal(&[(cputype, image(cputype))]);
+    let path = FilePath::new("/setup/fuzz")?;
+    let module = MacOSModule::new(path.clone(), &data, path, &data)?;
+
+    assert_eq!(module.base_address(), Address(TEXT_VMADDR));
+    assert_eq!(
+        module.id().as_deref(),
+        Some("1d2e3f405a6b4c7d8e9fa0b1c2d3e4f5")
+    );
+    assert_eq!(module_id(&data), module.id());
+
+    assert_eq!(module.read(Offset(0x1f30), 1)?, [0x55]);
+    assert_eq!(module.read(Offset(0x2000), 2)?, [0xaa, 0]);
+
+    // Reads are limited to the data of the segment in the file.
+    assert_eq!(module.read(Offset(0x27ff), 0x10)?.len(), 1);
+    assert!(module.read(Offset(0x4000), 1).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_dsym_path() -> Result<()> {
+    let path = FilePath::new("/setup/fuzz")?;
+    assert_eq!(
+        dsym_path(&path)?.as_str(),
+        "/setup/fuzz.dSYM/Contents/Resources/DWARF/fuzz"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_module_id_without_uuid() -> Result<()> {
+    let Some(cputype) = host_cputype() else {
+        return Ok(());
+    };
+
+    // Replace `LC_UUID`, after the three segments, with an ignored command.
+    let without_uuid = |text: u8| {
+        let mut data = image(cputype);
+        let cmd = MACH_HEADER_64_SIZE + 3 * 72;
+        assert_eq!(data[cmd..cmd + 4], LC_UUID.to_le_bytes());
+        data[cmd..cmd + 4].copy_from_slice(&0x2au32.to_le_bytes());
+        data[0x1f30] = text;
+        data
+    };
+
+    let data = without_uuid(0x55);
+    let id = module_id(&data).expect("text id");
+    assert!(id.starts_with("text-"), "{id}");
+
+    let path = FilePath::new("/setup/fuzz")?;
+    let module = MacOSModule::new(path.clone(), &data, path, &data)?;
+    assert_eq!(module.id(), Some(id.clone()));
+
+    // The id is of the code of the image.
+    assert_eq!(module_id(&without_uuid(0x55)), Some(id.clone()));
+    assert_ne!(module_id(&without_uuid(0xc3)), Some(id));
+
+    Ok(())
+}
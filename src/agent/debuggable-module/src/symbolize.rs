@@ -0,0 +1,104 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Symbolization of module offsets to functions and source lines, as by
+//! `addr2line`, from the debug info of modules, which may be external.
+
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use symbolic::debuginfo::{Function, Object};
+use symbolic::demangle::{Demangle, DemangleOptions};
+
+use crate::{Module, Offset};
+
+/// The function and source line of a module offset.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Symbol {
+    pub function: String,
+
+    /// Offset from the start of `function`.
+    pub function_offset: u64,
+
+    /// The source file and line, if the debug info records lines.
+    pub file: Option<String>,
+    pub line: Option<u64>,
+}
+
+/// Symbolize the module-relative `offsets` of `module`.
+///
+/// Offsets are resolved to the functions of the DWARF debug info of the
+/// module, or else to those of its symbol table, without source lines.
+/// Offsets which resolve to neither are omitted.
+pub fn symbolize<'data>(
+    module: &dyn Module<'data>,
+    offsets: &[Offset],
+) -> Result<BTreeMap<Offset, Symbol>> {
+    let opts = DemangleOptions::complete();
+
+    let object = Object::parse(module.debuginfo_data())?;
+    let session = object.debug_session()?;
+
+    let mut symbols = BTreeMap::new();
+
+    for function in session.functions() {
+        let function = function?;
+        let range = function.address..function.address.saturating_add(function.size);
+
+        for &offset in offsets {
+            if !range.contains(&offset.0) || symbols.contains_key(&offset) {
+                continue;
+            }
+
+            let (file, line) = match find_line(&function, offset.0) {
+                Some((file, line)) => (Some(file), Some(line)),
+                None => (None, None),
+            };
+            let symbol = Symbol {
+                function: function.name.try_demangle(opts).into_owned(),
+                function_offset: offset.0 - function.address,
+                file,
+                line,
+            };
+            symbols.insert(offset, symbol);
+        }
+    }
+
+    let symbol_map = object.symbol_map();
+    for &offset in offsets {
+        if symbols.contains_key(&offset) {
+            continue;
+        }
+
+        let Some(symbol) = symbol_map.lookup(offset.0) else {
+            continue;
+        };
+        let Some(name) = symbol.name() else {
+            continue;
+        };
+        let name = symbolic::common::Name::from(name);
+        let symbol = Symbol {
+            function: name.try_demangle(opts).into_owned(),
+            function_offset: offset.0 - symbol.address,
+            file: None,
+            line: None,
+        };
+        symbols.insert(offset, symbol);
+    }
+
+    Ok(symbols)
+}
+
+// The source line of `offset` in `function`. Within functions inlined into
+// it, this is the line of the call of the inlined function, as for the name of
+// the symbol.
+fn find_line(function: &Function, offset: u64) -> Option<(String, u64)> {
+    function
+        .lines
+        .iter()
+        .find(|line| {
+            let size = line.size.unwrap_or(1);
+            (line.address..line.address.saturating_add(size)).contains(&offset)
+        })
+        .map(|line| (line.file.path_str(), line.line))
+}
@@ -0,0 +1,265 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Lookup of PDBs in symbol stores, as laid out by `symstore.exe` and served
+//! by symbol servers.
+//!
+//! A PDB is stored at `<name>/<key>/<name>`, where `<key>` is the GUID and age
+//! recorded in the CodeView debug directory of the PE that references it.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use goblin::pe::PE;
+
+/// The environment variable read by `dbghelp` for its symbol search path.
+pub const NT_SYMBOL_PATH: &str = "_NT_SYMBOL_PATH";
+
+/// Identifies the PDB matching a PE.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct PdbKey {
+    name: String,
+    guid: [u8; 16],
+    age: u32,
+}
+
+impl PdbKey {
+    /// Key of the PDB named `name`, which may be a path. `guid` is in its
+    /// in-memory layout, as recorded in PE files.
+    pub fn new(name: &str, guid: [u8; 16], age: u32) -> Self {
+        let name = name.rsplit(['/', '\\']).next().unwrap_or(name).to_owned();
+
+        Self { name, guid, age }
+    }
+
+    /// The key of the PDB referenced by `pe`, if any.
+    pub fn from_pe(pe: &PE) -> Option<Self> {
+        let codeview = pe.debug_data.as_ref()?.codeview_pdb70_debug_info.as_ref()?;
+
+        // The file name is NUL-terminated.
+        let name = codeview.filename.split(|b| *b == 0).next()?;
+        let name = std::str::from_utf8(name).ok()?;
+        if name.is_empty() {
+            return None;
+        }
+
+        Some(Self::new(name, codeview.signature, codeview.age))
+    }
+
+    /// The key of the PDB referenced by the PE file `data`, if any.
+    pub fn from_pe_data(data: &[u8]) -> Result<Option<Self>> {
+        let pe = PE::parse(data)?;
+        Ok(Self::from_pe(&pe))
+    }
+
+    /// The file name of the PDB.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The GUID and age of the PDB, as in symbol store paths. The GUID is
+    /// formatted without separators, and the age in hexadecimal.
+    pub fn id(&self) -> String {
+        let g = &self.guid;
+        let data1 = u32::from_le_bytes([g[0], g[1], g[2], g[3]]);
+        let data2 = u16::from_le_bytes([g[4], g[5]]);
+        let data3 = u16::from_le_bytes([g[6], g[7]]);
+
+        let mut id = format!("{data1:08X}{data2:04X}{data3:04X}");
+        for b in &g[8..] {
+            id.push_str(&format!("{b:02X}"));
+        }
+        id.push_str(&format!("{:X}", self.age));
+
+        id
+    }
+
+    /// The path components of the PDB within a symbol store.
+    pub fn store_components(&self) -> [String; 3] {
+        [self.name.clone(), self.id(), self.name.clone()]
+    }
+
+    /// The path of the PDB within the symbol store `store`.
+    pub fn store_path(&self, store: impl AsRef<Path>) -> PathBuf {
+        let mut path = store.as_ref().to_owned();
+        path.extend(self.store_components());
+        path
+    }
+}
+
+/// A symbol search path, in the syntax of `_NT_SYMBOL_PATH`.
+///
+/// Elements are separated by `;`. An element of the form `srv*<store>*<url>`
+/// names symbol servers, and local stores to cache their PDBs in, and one of
+/// the form `cache*<store>` a local store. Any other element is a directory
+/// searched for PDBs by file name.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SymbolPath {
+    /// Directories containing PDBs by file name.
+    pub dirs: Vec<PathBuf>,
+
+    /// Local symbol stores.
+    pub stores: Vec<PathBuf>,
+
+    /// URLs of symbol servers.
+    pub servers: Vec<String>,
+}
+
+impl SymbolPath {
+    pub fn parse(text: &str) -> Self {
+        let mut path = Self::default();
+
+        for element in text.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+            let mut parts = element.split('*');
+
+            // Unwrap-safe, `split()` yields at least one part.
+            let kind = parts.next().unwrap().to_ascii_lowercase();
+            let parts = parts.filter(|p| !p.is_empty());
+
+            match kind.as_str() {
+                "srv" | "symsrv" => {
+                    for part in parts {
+                        if is_url(part) {
+                            path.servers.push(part.to_owned());
+                        } else if !part.eq_ignore_ascii_case("symsrv.dll") {
+                            path.stores.push(part.into());
+                        }
+                    }
+                }
+                "cache" => {
+                    path.stores.extend(parts.map(PathBuf::from));
+                }
+                _ => path.dirs.push(element.into()),
+            }
+        }
+
+        path
+    }
+
+    /// The symbol path of the environment, if set.
+    pub fn from_env() -> Self {
+        std::env::var(NT_SYMBOL_PATH)
+            .map(|text| Self::parse(&text))
+            .unwrap_or_default()
+    }
+
+    /// The local path of the PDB of `key`, if found in a directory or store.
+    pub fn find_local(&self, key: &PdbKey) -> Option<PathBuf> {
+        let in_dirs = self.dirs.iter().map(|dir| dir.join(key.name()));
+        let in_stores = self.stores.iter().map(|store| key.store_path(store));
+
+        in_dirs.chain(in_stores).find(|path| path.is_file())
+    }
+}
+
+fn is_url(text: &str) -> bool {
+    let text = text.to_ascii_lowercase();
+    text.starts_with("http://") || text.starts_with("https://")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The GUID {1D2E3F40-5A6B-4C7D-8E9F-A0B1C2D3E4F5}, in memory.
+    const GUID: [u8; 16] = [
+        0x40, 0x3f, 0x2e, 0x1d, 0x6b, 0x5a, 0x7d, 0x4c, 0x8e, 0x9f, 0xa0, 0xb1, 0xc2, 0xd3, 0xe4,
+        0xf5,
+    ];
+
+    #[test]
+    fn test_pdb_key_id() {
+        let key = PdbKey::new("fuzz.pdb", GUID, 1);
+        assert_eq!(key.id(), "1D2E3F405A6B4C7D8E9FA0B1C2D3E4F51");
+
+        let key = PdbKey::new("fuzz.pdb", GUID, 0x1a);
+        assert_eq!(key.id(), "1D2E3F405A6B4C7D8E9FA0B1C2D3E4F51A");
+
+        // Leading zeros of each GUID field are kept.
+        let mut guid = [0; 16];
+        guid[0] = 1;
+        let key = PdbKey::new("fuzz.pdb", guid, 2);
+        assert_eq!(key.id(), "000000010000000000000000000000002");
+    }
+
+    #[test]
+    fn test_pdb_key_name() {
+        let key = PdbKey::new(r"C:\agent\_work\1\s\out\fuzz.pdb", GUID, 1);
+        assert_eq!(key.name(), "fuzz.pdb");
+
+        let key = PdbKey::new("/build/out/fuzz.pdb", GUID, 1);
+        assert_eq!(key.name(), "fuzz.pdb");
+    }
+
+    #[test]
+    fn test_pdb_key_store_path() {
+        let key = PdbKey::new("fuzz.pdb", GUID, 1);
+
+        assert_eq!(
+            key.store_components(),
+            ["fuzz.pdb", "1D2E3F405A6B4C7D8E9FA0B1C2D3E4F51", "fuzz.pdb"]
+        );
+
+        let expected: PathBuf = [
+            "cache",
+            "fuzz.pdb",
+            "1D2E3F405A6B4C7D8E9FA0B1C2D3E4F51",
+            "fuzz.pdb",
+        ]
+        .iter()
+        .collect();
+        assert_eq!(key.store_path("cache"), expected);
+    }
+
+    #[test]
+    fn test_symbol_path_parse() {
+        let path = SymbolPath::parse(
+            r"srv*C:\symbols*https://msdl.microsoft.com/download/symbols; C:\pdbs ;cache*D:\cache;;SRV**http://symbols.example.com/",
+        );
+
+        assert_eq!(
+            path,
+            SymbolPath {
+                dirs: vec![r"C:\pdbs".into()],
+                stores: vec![r"C:\symbols".into(), r"D:\cache".into()],
+                servers: vec![
+                    "https://msdl.microsoft.com/download/symbols".into(),
+                    "http://symbols.example.com/".into(),
+                ],
+            }
+        );
+
+        let path = SymbolPath::parse(r"symsrv*symsrv.dll*C:\symbols*https://symbols.example.com");
+        assert_eq!(path.stores, [PathBuf::from(r"C:\symbols")]);
+        assert_eq!(path.servers, ["https://symbols.example.com"]);
+
+        assert_eq!(SymbolPath::parse(""), SymbolPath::default());
+    }
+
+    #[test]
+    fn test_find_local() -> Result<()> {
+        let key = PdbKey::new("fuzz.pdb", GUID, 1);
+
+        let dir = tempfile::tempdir()?;
+        let store = tempfile::tempdir()?;
+        let path = SymbolPath {
+            dirs: vec![dir.path().to_owned()],
+            stores: vec![store.path().to_owned()],
+            servers: vec![],
+        };
+
+        assert_eq!(path.find_local(&key), None);
+
+        let stored = key.store_path(store.path());
+        std::fs::create_dir_all(stored.parent().unwrap())?;
+        std::fs::write(&stored, "")?;
+        assert_eq!(path.find_local(&key), Some(stored));
+
+        // Directories are searched first.
+        let in_dir = dir.path().join("fuzz.pdb");
+        std::fs::write(&in_dir, "")?;
+        assert_eq!(path.find_local(&key), Some(in_dir));
+
+        Ok(())
+    }
+}
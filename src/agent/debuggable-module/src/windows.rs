@@ -11,7 +11,8 @@ use pdb::{AddressMap, ImageSectionHeader, PdbInternalSectionOffset, PDB};
 
 use crate::debuginfo::{DebugInfo, Function};
 use crate::path::FilePath;
-use crate::{Address, Module, Offset};
+use crate::symsrv::PdbKey;
+use crate::{text_id, Address, Module, Offset};
 
 impl<'data> Module<'data> for WindowsModule<'data> {
     fn executable_path(&self) -> &FilePath {
@@ -48,36 +49,84 @@ impl<'data> Module<'data> for WindowsModule<'data> {
     }
 
     fn debuginfo(&self) -> Result<DebugInfo> {
-        use symbolic::debuginfo::Object;
-        use symbolic::demangle::{Demangle, DemangleOptions};
-
         let extra = self.extra_debug_info()?;
-        let opts = DemangleOptions::complete();
+        let functions = pdb_functions(self.debuginfo_data(), &extra.noreturns)?;
 
-        let object = Object::parse(self.debuginfo_data())?;
-        let session = object.debug_session()?;
+        Ok(DebugInfo::new(functions, Some(extra.labels)))
+    }
 
-        let mut functions = BTreeMap::new();
+    fn id(&self) -> Option<String> {
+        module_id(&self.pe, self.pe_data)
+    }
+}
 
-        for function in session.functions() {
-            let function = function?;
+/// Debug info of the functions of the PDB `pdb_data`, without the PE it
+/// describes, such as to symbolize the modules of a minidump. Functions are
+/// not known to be `noreturn`, and there are no labels.
+pub fn pdb_debuginfo(pdb_data: &[u8]) -> Result<DebugInfo> {
+    let functions = pdb_functions(pdb_data, &BTreeSet::new())?;
 
-            let name = function.name.try_demangle(opts).into_owned();
-            let offset = Offset(function.address); // Misnamed.
-            let size = function.size;
-            let noreturn = extra.noreturns.contains(&offset);
+    Ok(DebugInfo::new(functions, None))
+}
 
-            let f = Function {
-                name,
-                noreturn,
-                offset,
-                size,
-            };
-            functions.insert(offset, f);
-        }
+fn pdb_functions(
+    pdb_data: &[u8],
+    noreturns: &BTreeSet<Offset>,
+) -> Result<BTreeMap<Offset, Function>> {
+    use symbolic::debuginfo::Object;
+    use symbolic::demangle::{Demangle, DemangleOptions};
 
-        Ok(DebugInfo::new(functions, Some(extra.labels)))
+    let opts = DemangleOptions::complete();
+
+    let object = Object::parse(pdb_data)?;
+    let session = object.debug_session()?;
+
+    let mut functions = BTreeMap::new();
+
+    for function in session.functions() {
+        let function = function?;
+
+        let name = function.name.try_demangle(opts).into_owned();
+        let offset = Offset(function.address); // Misnamed.
+        let size = function.size;
+        let noreturn = noreturns.contains(&offset);
+
+        let f = Function {
+            name,
+            noreturn,
+            offset,
+            size,
+        };
+        functions.insert(offset, f);
+    }
+
+    Ok(functions)
+}
+
+/// Identity of a PE file: the GUID and age of its PDB, or else its timestamp
+/// and image size, formatted as in symbol stores. Images without a timestamp
+/// are identified by the [`text_id()`] of their `.text` section.
+pub fn module_id(pe: &PE, data: &[u8]) -> Option<String> {
+    if let Some(key) = PdbKey::from_pe(pe) {
+        return Some(key.id());
     }
+
+    let timestamp = pe.header.coff_header.time_date_stamp;
+    if timestamp != 0 {
+        if let Some(optional) = &pe.header.optional_header {
+            let size = optional.windows_fields.size_of_image;
+            return Some(format!("{timestamp:08X}{size:x}"));
+        }
+    }
+
+    let text = pe
+        .sections
+        .iter()
+        .find(|section| section.name().ok() == Some(".text"))?;
+    let lo = text.pointer_to_raw_data as usize;
+    let hi = lo.saturating_add(text.size_of_raw_data as usize);
+
+    data.get(lo..hi).map(text_id)
 }
 
 pub struct WindowsModule<'data> {
@@ -0,0 +1,147 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+#![cfg(all(target_os = "linux", feature = "slow-tests"))]
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use debuggable_module::debugfile::DebugFileDirs;
+use debuggable_module::linux::LinuxModule;
+use debuggable_module::load_module::LoadModule;
+use debuggable_module::loader::Loader;
+use debuggable_module::path::FilePath;
+use debuggable_module::symbolize::{symbolize, Symbol};
+use debuggable_module::{Module, Offset};
+
+fn run(program: &str, args: &[&str], dir: &Path) {
+    let status = Command::new(program)
+        .args(args)
+        .current_dir(dir)
+        .status()
+        .unwrap_or_else(|err| panic!("launching {program}: {err}"));
+    assert!(status.success(), "{program} failed: {status}");
+}
+
+// Build the fixture as `fuzz`, stripped, with its debug info split off into
+// `fuzz.debug`, linked by `.gnu_debuglink`.
+fn build(dir: &Path, define: &str) -> PathBuf {
+    let fixture = Path::new(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/debugfile/fixture.c"
+    ));
+
+    run(
+        "cc",
+        &[
+            "-g",
+            "-O0",
+            define,
+            "-Wl,--build-id",
+            "-o",
+            "fuzz",
+            fixture.to_str().unwrap(),
+        ],
+        dir,
+    );
+    run("objcopy", &["--only-keep-debug", "fuzz", "fuzz.debug"], dir);
+    run(
+        "objcopy",
+        &["--strip-all", "--add-gnu-debuglink=fuzz.debug", "fuzz"],
+        dir,
+    );
+
+    dir.join("fuzz")
+}
+
+// The offset of `check()` in the debug file, and its symbol, as resolved in
+// the module at `exe` by `loader`.
+fn symbolize_check(loader: &Loader, exe: &Path) -> Option<Symbol> {
+    let path = FilePath::new(exe.to_string_lossy()).unwrap();
+    let module = LinuxModule::load(loader, path).unwrap();
+
+    let debuginfo = module.debuginfo().unwrap();
+    let check = debuginfo.functions().find(|f| f.name == "check")?;
+
+    // The first instruction after the prologue, in the body of `check()`.
+    let offset = Offset(check.offset.0 + 4);
+    let mut symbols = symbolize(&module, &[offset]).unwrap();
+    symbols.remove(&offset)
+}
+
+fn assert_check(symbol: Option<Symbol>) {
+    let symbol = symbol.expect("symbolized `check()`");
+    assert_eq!(symbol.function, "check");
+    assert_eq!(symbol.function_offset, 4);
+    assert!(
+        symbol
+            .file
+            .as_deref()
+            .is_some_and(|file| file.ends_with("fixture.c")),
+        "{symbol:?}"
+    );
+    assert!(matches!(symbol.line, Some(4..=6)), "{symbol:?}");
+}
+
+#[test]
+fn test_debuglink() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    let exe = build(dir, "-DBUILD=1");
+
+    // Beside the module.
+    assert_check(symbolize_check(&Loader::new(), &exe));
+
+    // In its `.debug` subdirectory.
+    std::fs::create_dir(dir.join(".debug")).unwrap();
+    std::fs::rename(dir.join("fuzz.debug"), dir.join(".debug/fuzz.debug")).unwrap();
+    assert_check(symbolize_check(&Loader::new(), &exe));
+}
+
+#[test]
+fn test_build_id_dir() {
+    let tmp = tempfile::tempdir().unwrap();
+    let build_dir = tmp.path().join("build");
+    let debug_dir = tmp.path().join("debug");
+    std::fs::create_dir(&build_dir).unwrap();
+    let exe = build(&build_dir, "-DBUILD=1");
+
+    let data = std::fs::read(&exe).unwrap();
+    let build_id = debuggable_module::module_id(&data).unwrap();
+    let (prefix, rest) = build_id.split_at(2);
+    let debug_file = debug_dir.join(".build-id").join(prefix);
+    std::fs::create_dir_all(&debug_file).unwrap();
+    std::fs::rename(
+        build_dir.join("fuzz.debug"),
+        debug_file.join(format!("{rest}.debug")),
+    )
+    .unwrap();
+
+    // Not found without the debug file directory.
+    assert!(symbolize_check(&Loader::new(), &exe).is_none());
+
+    let loader = Loader::new().with_debug_file_dirs(DebugFileDirs::new([debug_dir]));
+    assert_check(symbolize_check(&loader, &exe));
+}
+
+#[test]
+fn test_missing_or_mismatched_debug_file() {
+    let tmp = tempfile::tempdir().unwrap();
+    let build_dir = tmp.path().join("build");
+    let other_dir = tmp.path().join("other");
+    std::fs::create_dir(&build_dir).unwrap();
+    std::fs::create_dir(&other_dir).unwrap();
+    let exe = build(&build_dir, "-DBUILD=1");
+    build(&other_dir, "-DBUILD=2");
+
+    // Without its debug file, the stripped module has no functions, as before.
+    std::fs::remove_file(build_dir.join("fuzz.debug")).unwrap();
+    let dirs = DebugFileDirs::new([tmp.path().to_owned()]);
+    let loader = Loader::new().with_debug_file_dirs(dirs.clone());
+    assert!(symbolize_check(&loader, &exe).is_none());
+
+    // The debug file of another build, of another CRC, isn't used.
+    std::fs::rename(other_dir.join("fuzz.debug"), build_dir.join("fuzz.debug")).unwrap();
+    let loader = Loader::new().with_debug_file_dirs(dirs);
+    assert!(symbolize_check(&loader, &exe).is_none());
+}
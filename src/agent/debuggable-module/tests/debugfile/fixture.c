@@ -0,0 +1,11 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+int check(int x) {
+  int y = x * 2;
+  return y + BUILD;
+}
+
+int main(int argc, char **argv) {
+  return check(argc);
+}
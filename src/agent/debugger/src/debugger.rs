@@ -9,6 +9,7 @@
 #![allow(clippy::redundant_closure)]
 #![allow(clippy::redundant_clone)]
 use std::{
+    collections::HashMap,
     ffi::{c_void, OsString},
     mem::MaybeUninit,
     os::windows::process::CommandExt,
@@ -30,7 +31,7 @@ use windows::Win32::{
             ContinueDebugEvent, DebugSetProcessKillOnExit, WaitForDebugEvent, ADDRESS64,
             EXCEPTION_DEBUG_INFO, RIP_INFO_TYPE,
         },
-        Threading::{DEBUG_ONLY_THIS_PROCESS, INFINITE},
+        Threading::{DEBUG_ONLY_THIS_PROCESS, DEBUG_PROCESS, INFINITE},
     },
 };
 
@@ -129,6 +130,13 @@ struct ContinueDebugEventArguments {
 
 pub struct Debugger {
     target: Target,
+
+    /// Processes started by the target, when debugging them too.
+    children: HashMap<u32, Target>,
+
+    /// The process of the last debug event.
+    current_process_id: u32,
+
     continue_args: Option<ContinueDebugEventArguments>,
     breakpoint_count: u64,
 }
@@ -162,12 +170,14 @@ impl Debugger {
 
             let base_address = info.lpBaseOfImage as u64;
             let module = target
-                .load_module(info.hFile, base_address)
+                .load_image(info.hFile, base_address)
                 .context("Loading process module")?
                 .unwrap();
 
             let mut debugger = Debugger {
+                current_process_id: target.process_id(),
                 target,
+                children: HashMap::new(),
                 continue_args: None,
                 breakpoint_count: 0,
             };
@@ -184,9 +194,18 @@ impl Debugger {
         }
     }
 
-    pub fn create_child(mut command: Command) -> Result<Child> {
+    pub fn create_child(command: Command) -> Result<Child> {
+        Self::spawn(command, DEBUG_ONLY_THIS_PROCESS.0)
+    }
+
+    /// Like `create_child`, but also debug every process started by the child.
+    pub fn create_child_with_children(command: Command) -> Result<Child> {
+        Self::spawn(command, DEBUG_PROCESS.0)
+    }
+
+    fn spawn(mut command: Command, flags: u32) -> Result<Child> {
         let child = command
-            .creation_flags(DEBUG_ONLY_THIS_PROCESS.0)
+            .creation_flags(flags)
             .spawn()
             .context("debugee failed to start")?;
 
@@ -199,16 +218,56 @@ impl Debugger {
         Ok((debugger, child))
     }
 
+    /// Like `init`, but also debug every process started by the target, such
+    /// as when the target is a script which launches the real target. Events
+    /// of all the processes are passed to `callbacks`, and `run` returns once
+    /// they have all exited.
+    pub fn init_with_children(
+        command: Command,
+        callbacks: &mut impl DebugEventHandler,
+    ) -> Result<(Self, Child)> {
+        let child = Self::create_child_with_children(command)?;
+        let debugger = Self::init_debugger(callbacks)?;
+        Ok((debugger, child))
+    }
+
+    /// The process started by the debugger.
     pub fn target(&mut self) -> &mut Target {
         &mut self.target
     }
 
+    /// The process of the current debug event, which is `target` unless
+    /// debugging its children.
+    pub fn current_target(&mut self) -> &mut Target {
+        self.current_mut()
+    }
+
+    fn current(&self) -> &Target {
+        self.children
+            .get(&self.current_process_id)
+            .unwrap_or(&self.target)
+    }
+
+    fn current_mut(&mut self) -> &mut Target {
+        self.target_of(self.current_process_id)
+    }
+
+    fn target_of(&mut self, process_id: u32) -> &mut Target {
+        match self.children.get_mut(&process_id) {
+            Some(child) => child,
+            None => &mut self.target,
+        }
+    }
+
     fn next_breakpoint_id(&mut self) -> BreakpointId {
         let id = BreakpointId(self.breakpoint_count);
         self.breakpoint_count += 1;
         id
     }
 
+    // Breakpoints are set in the process of the current debug event, which is
+    // `target` unless debugging its children.
+
     pub fn new_symbolic_breakpoint(
         &mut self,
         sym: &str,
@@ -220,7 +279,8 @@ impl Debugger {
             anyhow::bail!("no module name specified for breakpoint {}", sym);
         };
         let id = self.next_breakpoint_id();
-        self.target.new_symbolic_breakpoint(id, module, func, kind)
+        self.current_mut()
+            .new_symbolic_breakpoint(id, module, func, kind)
     }
 
     pub fn new_rva_breakpoint(
@@ -231,7 +291,7 @@ impl Debugger {
     ) -> Result<BreakpointId> {
         let id = self.next_breakpoint_id();
         let module = format!("{}", module.display());
-        self.target.new_rva_breakpoint(id, module, rva, kind)
+        self.current_mut().new_rva_breakpoint(id, module, rva, kind)
     }
 
     pub fn new_address_breakpoint(
@@ -240,7 +300,8 @@ impl Debugger {
         kind: BreakpointType,
     ) -> Result<BreakpointId> {
         let id = self.next_breakpoint_id();
-        self.target.new_absolute_breakpoint(id, address, kind)
+        self.current_mut()
+            .new_absolute_breakpoint(id, address, kind)
     }
 
     /// Return true if an event was process, false if timing out, or an error.
@@ -277,7 +338,13 @@ impl Debugger {
 
     pub fn continue_debugging(&mut self) -> Result<()> {
         if let Some(continue_args) = self.continue_args.take() {
-            self.target.prepare_to_resume()?;
+            let process_id = continue_args.process_id;
+            if self.children.get(&process_id).is_some_and(Target::exited) {
+                // This was the exit event of the child, the last for it.
+                self.children.remove(&process_id);
+            } else {
+                self.target_of(process_id).prepare_to_resume()?;
+            }
 
             if unsafe {
                 ContinueDebugEvent(
@@ -295,7 +362,7 @@ impl Debugger {
     }
 
     pub fn run(&mut self, callbacks: &mut impl DebugEventHandler) -> Result<()> {
-        while !self.target.exited() {
+        while !self.target.exited() || !self.children.is_empty() {
             // Poll between every event so a client can add generic logic instead needing to
             // handle every possible event.
             callbacks.on_poll(self);
@@ -311,9 +378,11 @@ impl Debugger {
     }
 
     pub fn quit_debugging(&self) {
-        if !self.target.exited() {
-            trace!("timeout - terminating pid: {}", self.target.process_id());
-            process::terminate(self.target.process_handle());
+        for target in std::iter::once(&self.target).chain(self.children.values()) {
+            if !target.exited() {
+                trace!("timeout - terminating pid: {}", target.process_id());
+                process::terminate(target.process_handle());
+            }
         }
     }
 
@@ -324,21 +393,45 @@ impl Debugger {
     ) -> NTSTATUS {
         let mut continue_status = DBG_CONTINUE;
 
-        if let DebugEventInfo::CreateThread(info) = de.info() {
-            self.target.create_new_thread(info.hThread, de.thread_id());
-        } else {
-            self.target.set_current_thread(de.thread_id());
+        self.current_process_id = de.process_id();
+
+        match de.info() {
+            DebugEventInfo::CreateThread(info) => {
+                self.current_mut()
+                    .create_new_thread(info.hThread, de.thread_id());
+            }
+            // The thread is added with the target.
+            DebugEventInfo::CreateProcess(_) => {}
+            _ => self.current_mut().set_current_thread(de.thread_id()),
         }
 
         match de.info() {
-            DebugEventInfo::CreateProcess(_info) => {
-                // We pass DEBUG_ONLY_THIS_PROCESS when spawning
-                // and will only see the 1 CreateProcess event handled previously.
-                unreachable!("Nested targets not supported");
+            DebugEventInfo::CreateProcess(info) => {
+                // With DEBUG_ONLY_THIS_PROCESS, only the event of the target
+                // is seen, as handled by `init_debugger`. With DEBUG_PROCESS,
+                // this is a child of the target.
+                let mut child =
+                    Target::new(de.process_id(), de.thread_id(), info.hProcess, info.hThread);
+
+                let module = child.load_image(info.hFile, info.lpBaseOfImage as u64);
+                self.children.insert(de.process_id(), child);
+
+                match module {
+                    Ok(Some(module)) => {
+                        callbacks.on_create_process(self, &module);
+                    }
+                    Ok(None) => {}
+                    Err(e) => {
+                        error!("Error loading process module: {}", e);
+                    }
+                }
             }
 
             DebugEventInfo::LoadDll(info) => {
-                match self.target.load_module(info.hFile, info.lpBaseOfDll as u64) {
+                match self
+                    .current_mut()
+                    .load_module(info.hFile, info.lpBaseOfDll as u64)
+                {
                     Ok(Some(module)) => {
                         callbacks.on_load_dll(self, &module);
                     }
@@ -350,7 +443,7 @@ impl Debugger {
             }
 
             DebugEventInfo::UnloadDll(info) => {
-                self.target.unload_module(info.lpBaseOfDll as u64);
+                self.current_mut().unload_module(info.lpBaseOfDll as u64);
 
                 callbacks.on_unload_dll(self, info.lpBaseOfDll as u64);
             }
@@ -366,7 +459,7 @@ impl Debugger {
             }
 
             DebugEventInfo::ExitProcess(info) => {
-                if let Err(err) = self.target.set_exited() {
+                if let Err(err) = self.current_mut().set_exited() {
                     error!("Error cleaning up after process exit: {}", err);
                 }
                 callbacks.on_exit_process(self, info.dwExitCode);
@@ -378,7 +471,7 @@ impl Debugger {
 
             DebugEventInfo::ExitThread(info) => {
                 callbacks.on_exit_thread(self, info.dwExitCode);
-                self.target.exit_thread(de.thread_id());
+                self.current_mut().exit_thread(de.thread_id());
             }
 
             DebugEventInfo::OutputDebugString(info) => {
@@ -386,7 +479,7 @@ impl Debugger {
                 let length = info.nDebugStringLength.saturating_sub(1) as usize;
                 if info.fUnicode != 0 {
                     if let Ok(message) = process::read_wide_string(
-                        self.target.process_handle(),
+                        self.current().process_handle(),
                         info.lpDebugStringData.0.cast(),
                         length,
                     ) {
@@ -394,7 +487,7 @@ impl Debugger {
                     }
                 } else {
                     if let Ok(message) = process::read_narrow_string(
-                        self.target.process_handle(),
+                        self.current().process_handle(),
                         info.lpDebugStringData.0.cast(),
                         length,
                     ) {
@@ -421,29 +514,29 @@ impl Debugger {
         match is_debugger_notification(
             info.ExceptionRecord.ExceptionCode,
             info.ExceptionRecord.ExceptionAddress as u64,
-            &mut self.target,
+            self.current_mut(),
         ) {
             Some(DebuggerNotification::InitialBreak) => {
-                self.target.initial_bp()?;
+                self.current_mut().initial_bp()?;
                 Ok(DBG_CONTINUE)
             }
             Some(DebuggerNotification::InitialWow64Break) => {
-                self.target.initial_wow64_bp();
+                self.current_mut().initial_wow64_bp();
                 Ok(DBG_CONTINUE)
             }
             Some(DebuggerNotification::Clr) => Ok(DBG_CONTINUE),
             Some(DebuggerNotification::Breakpoint { pc }) => {
-                if let Some(bp_id) = self.target.handle_breakpoint(pc)? {
+                if let Some(bp_id) = self.current_mut().handle_breakpoint(pc)? {
                     callbacks.on_breakpoint(self, bp_id);
                 }
                 Ok(DBG_CONTINUE)
             }
             Some(DebuggerNotification::SingleStep { thread_id }) => {
-                self.target.complete_single_step(thread_id)?;
+                self.current_mut().complete_single_step(thread_id)?;
                 Ok(DBG_CONTINUE)
             }
             None => {
-                let process_handle = self.target.process_handle();
+                let process_handle = self.current().process_handle();
                 Ok(callbacks.on_exception(self, info, process_handle))
             }
         }
@@ -456,43 +549,43 @@ impl Debugger {
         // We could retry in a loop (apparently it can fail but later
         // succeed), but symbols aren't strictly necessary, so we won't
         // be too aggressive in dealing with failures.
-        let resolve_symbols = self.target.maybe_sym_initialize().is_ok();
+        let resolve_symbols = self.current_mut().maybe_sym_initialize().is_ok();
         return stack::get_stack(
-            self.target.process_handle(),
-            self.target.current_thread_handle(),
+            self.current().process_handle(),
+            self.current().current_thread_handle(),
             resolve_symbols,
         );
     }
 
     pub fn get_module_info(&self, pc: u64) -> Result<ModuleInfo> {
         let dbghelp = dbghelp::lock()?;
-        dbghelp.sym_get_module_info(self.target.process_handle(), pc)
+        dbghelp.sym_get_module_info(self.current().process_handle(), pc)
     }
 
     pub fn get_symbol(&self, pc: u64) -> Result<SymInfo> {
         let dbghelp = dbghelp::lock()?;
-        dbghelp.sym_from_inline_context(self.target.process_handle(), pc, 0)
+        dbghelp.sym_from_inline_context(self.current().process_handle(), pc, 0)
     }
 
     pub fn get_symbol_line_info(&self, pc: u64) -> Result<SymLineInfo> {
         let dbghelp = dbghelp::lock()?;
-        dbghelp.sym_get_file_and_line(self.target.process_handle(), pc, 0)
+        dbghelp.sym_get_file_and_line(self.current().process_handle(), pc, 0)
     }
 
     pub fn get_current_thread_id(&self) -> u64 {
-        self.target.current_thread_id() as u64
+        self.current().current_thread_id() as u64
     }
 
     pub fn read_register_u64(&mut self, reg: iced_x86::Register) -> Result<u64> {
-        self.target.read_register_u64(reg)
+        self.current_mut().read_register_u64(reg)
     }
 
     pub fn read_program_counter(&mut self) -> Result<u64> {
-        self.target.read_program_counter()
+        self.current_mut().read_program_counter()
     }
 
     pub fn read_flags_register(&mut self) -> Result<u32> {
-        self.target.read_flags_register()
+        self.current_mut().read_flags_register()
     }
 
     pub fn read_memory(
@@ -500,7 +593,7 @@ impl Debugger {
         remote_address: *const c_void,
         buf: &mut [impl Copy],
     ) -> Result<()> {
-        self.target.read_memory(remote_address, buf)
+        self.current_mut().read_memory(remote_address, buf)
     }
 
     pub fn get_current_frame(&self) -> Result<StackFrame> {
@@ -509,8 +602,8 @@ impl Debugger {
         let mut return_address = ADDRESS64::default();
         let mut stack_pointer = ADDRESS64::default();
         dbghlp.stackwalk_ex(
-            self.target.process_handle(),
-            self.target.current_thread_handle(),
+            self.current().process_handle(),
+            self.current().current_thread_handle(),
             false, /* ignore inline frames */
             |frame| {
                 return_address = frame.AddrReturn;
@@ -525,7 +618,7 @@ impl Debugger {
     }
 
     pub fn step(&mut self) -> Result<bool> {
-        self.target.prepare_to_step()?;
+        self.current_mut().prepare_to_step()?;
         self.continue_debugging()?;
         Ok(true)
     }
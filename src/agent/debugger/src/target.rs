@@ -3,7 +3,12 @@
 
 #![allow(clippy::single_match)]
 
-use std::{ffi::c_void, io, num::NonZeroU64, path::Path};
+use std::{
+    ffi::c_void,
+    io,
+    num::NonZeroU64,
+    path::{Path, PathBuf},
+};
 
 use anyhow::{format_err, Result};
 use log::{debug, error, trace};
@@ -157,6 +162,9 @@ pub struct Target {
     sym_initialize_state: SymInitalizeState,
     exited: bool,
 
+    // The executable of the process, once loaded.
+    image_path: Option<PathBuf>,
+
     // Map of thread ID to thread info.
     thread_info: fnv::FnvHashMap<u32, ThreadInfo>,
 
@@ -202,6 +210,7 @@ impl Target {
             wow64,
             sym_initialize_state: SymInitalizeState::NotInitialized,
             exited: false,
+            image_path: None,
             thread_info: thread_handles,
             current_context: None,
             modules: fnv::FnvHashMap::default(),
@@ -256,6 +265,11 @@ impl Target {
         self.exited
     }
 
+    /// The path of the executable of the process, if its module was loaded.
+    pub fn image_path(&self) -> Option<&Path> {
+        self.image_path.as_deref()
+    }
+
     pub fn initial_bp(&mut self) -> Result<()> {
         self.saw_initial_bp = true;
 
@@ -517,6 +531,20 @@ impl Target {
         Ok(Some(module_load_info))
     }
 
+    /// Like `load_module`, for the executable of the process, at its creation.
+    pub fn load_image(
+        &mut self,
+        file_handle: HANDLE,
+        base_address: u64,
+    ) -> Result<Option<ModuleLoadInfo>> {
+        let module = self.load_module(file_handle, base_address)?;
+        if let Some(module) = &module {
+            self.image_path = Some(module.path().to_owned());
+        }
+
+        Ok(module)
+    }
+
     pub fn unload_module(&mut self, base_address: u64) {
         self.modules.remove(&base_address);
     }
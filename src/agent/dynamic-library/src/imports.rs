@@ -0,0 +1,527 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! The libraries a module imports, as read from its headers, resolved against
+//! the directories the dynamic loader searches, without running the module.
+//!
+//! Unlike the checks of the `linux` and `windows` modules, the module isn't
+//! run, so libraries which are missing are found before the target is first
+//! run, and without elevation on Windows.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use goblin::{elf::Elf, pe::PE, Object};
+
+// API set DLLs, such as `api-ms-win-crt-runtime-l1-1-0.dll`, are mapped to
+// other DLLs by the loader, rather than found as files.
+const API_SET_PREFIXES: &[&str] = &["api-ms-", "ext-ms-"];
+
+// Size of an `IMAGE_DELAYLOAD_DESCRIPTOR`.
+const DELAY_LOAD_DESCRIPTOR_SIZE: usize = 32;
+
+// The attribute of delay-load descriptors whose fields are RVAs, rather than
+// virtual addresses, as of descriptors written by Visual C++ 7.0 and later.
+const DELAY_LOAD_RVA_BASED: u32 = 0x1;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Format {
+    Elf,
+    Pe,
+}
+
+/// The libraries a module imports, and where its headers add to the search
+/// for them.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Imports {
+    pub format: Format,
+
+    /// Libraries loaded along with the module, of its `DT_NEEDED` entries, or
+    /// of its import table.
+    pub libraries: Vec<String>,
+
+    /// DLLs of the delay-load imports of a PE, which are only loaded once
+    /// first called.
+    pub delay_loaded: Vec<String>,
+
+    /// Directories of the `DT_RPATH` of an ELF module, as written.
+    pub rpath: Vec<String>,
+
+    /// Directories of the `DT_RUNPATH` of an ELF module, as written.
+    pub runpath: Vec<String>,
+
+    pub is_64: bool,
+}
+
+impl Imports {
+    /// Read the imports of the module `data`, or `None` if it isn't an ELF or
+    /// PE module, such as a script, or can't be parsed.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        match Object::parse(data).ok()? {
+            Object::Elf(elf) => Some(Self::from_elf(&elf)),
+            Object::PE(pe) => Some(Self::from_pe(&pe, data)),
+            _ => None,
+        }
+    }
+
+    fn from_elf(elf: &Elf) -> Self {
+        let dirs = |paths: &[&str]| {
+            paths
+                .iter()
+                .flat_map(|path| path.split(':'))
+                .filter(|dir| !dir.is_empty())
+                .map(String::from)
+                .collect()
+        };
+
+        Self {
+            format: Format::Elf,
+            libraries: elf.libraries.iter().map(|name| name.to_string()).collect(),
+            delay_loaded: vec![],
+            rpath: dirs(&elf.rpaths),
+            runpath: dirs(&elf.runpaths),
+            is_64: elf.is_64,
+        }
+    }
+
+    fn from_pe(pe: &PE, data: &[u8]) -> Self {
+        Self {
+            format: Format::Pe,
+            libraries: pe.libraries.iter().map(|name| name.to_string()).collect(),
+            delay_loaded: delay_loaded_dlls(pe, data).unwrap_or_default(),
+            rpath: vec![],
+            runpath: vec![],
+            is_64: pe.is_64,
+        }
+    }
+}
+
+// The DLLs named by the delay-load descriptors of `pe`.
+fn delay_loaded_dlls(pe: &PE, data: &[u8]) -> Option<Vec<String>> {
+    let header = pe.header.optional_header.as_ref()?;
+    let directory = header
+        .data_directories
+        .get_delay_import_descriptor()
+        .as_ref()?;
+    let image_base = header.windows_fields.image_base;
+
+    let mut dlls = vec![];
+    let mut offset = rva_offset(pe, directory.virtual_address)?;
+    loop {
+        let descriptor = data.get(offset..offset + DELAY_LOAD_DESCRIPTOR_SIZE)?;
+        let attributes = read_u32(descriptor, 0)?;
+        let name = read_u32(descriptor, 4)?;
+
+        // Ends with a zeroed descriptor.
+        if name == 0 {
+            break;
+        }
+
+        let name = if attributes & DELAY_LOAD_RVA_BASED == 0 {
+            u32::try_from(u64::from(name).checked_sub(image_base)?).ok()?
+        } else {
+            name
+        };
+        let start = rva_offset(pe, name)?;
+        let name = data.get(start..)?.split(|b| *b == 0).next()?;
+        dlls.push(String::from_utf8_lossy(name).into_owned());
+
+        offset += DELAY_LOAD_DESCRIPTOR_SIZE;
+    }
+
+    Some(dlls)
+}
+
+// The file offset of the RVA `rva` of `pe`.
+fn rva_offset(pe: &PE, rva: u32) -> Option<usize> {
+    pe.sections.iter().find_map(|section| {
+        let start = section.virtual_address;
+        let size = section.virtual_size.max(section.size_of_raw_data);
+        let end = start.checked_add(size)?;
+        if (start..end).contains(&rva) {
+            usize::try_from(rva - start + section.pointer_to_raw_data).ok()
+        } else {
+            None
+        }
+    })
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes(bytes.try_into().ok()?))
+}
+
+/// The directories the dynamic loader searches for libraries, besides those
+/// the headers of a module add.
+#[derive(Clone, Debug, Default)]
+pub struct SearchPath {
+    /// Directories of `LD_LIBRARY_PATH` on Linux, or of `PATH` on Windows.
+    pub library_path: Vec<PathBuf>,
+
+    /// The working directory of the target, which Windows searches for DLLs.
+    pub current_dir: Option<PathBuf>,
+
+    /// Directories of the system, such as `/usr/lib` or `C:\Windows\System32`.
+    pub system_dirs: Vec<PathBuf>,
+
+    /// The path of the loader cache, such as `/etc/ld.so.cache`, if any.
+    pub cache: Option<PathBuf>,
+
+    /// Libraries of the loader cache, by name, which are found without
+    /// searching the directories of the system.
+    pub cached: HashMap<String, PathBuf>,
+}
+
+impl SearchPath {
+    /// The system directories of the host, and its loader cache, if any.
+    pub fn system() -> Self {
+        if cfg!(target_os = "windows") {
+            let root = std::env::var_os("SystemRoot").unwrap_or_else(|| r"C:\Windows".into());
+            let root = PathBuf::from(root);
+
+            Self {
+                system_dirs: vec![root.join("System32"), root.clone()],
+                ..Self::default()
+            }
+        } else {
+            let cached = ldconfig_cache().unwrap_or_default();
+            let cache = (!cached.is_empty()).then(|| PathBuf::from("/etc/ld.so.cache"));
+
+            Self {
+                system_dirs: ["/lib", "/usr/lib", "/lib64", "/usr/lib64"]
+                    .map(PathBuf::from)
+                    .into(),
+                cache,
+                cached,
+                ..Self::default()
+            }
+        }
+    }
+
+    /// Also search the directories of `value`, of the library path variable.
+    pub fn library_path(self, value: impl AsRef<OsStr>) -> Self {
+        let library_path = std::env::split_paths(value.as_ref())
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .collect();
+
+        Self {
+            library_path,
+            ..self
+        }
+    }
+
+    pub fn current_dir(self, dir: impl Into<PathBuf>) -> Self {
+        Self {
+            current_dir: Some(dir.into()),
+            ..self
+        }
+    }
+
+    // The system directories searched for the imports of a module of
+    // `format`. 32-bit DLLs are found in `SysWOW64`, when run by 64-bit
+    // Windows.
+    fn system_dirs(&self, format: Format, is_64: bool) -> Vec<PathBuf> {
+        self.system_dirs
+            .iter()
+            .map(|dir| {
+                let is_system32 = dir
+                    .file_name()
+                    .is_some_and(|name| name.eq_ignore_ascii_case("System32"));
+                let wow64 = dir.with_file_name("SysWOW64");
+                if format == Format::Pe && !is_64 && is_system32 && wow64.is_dir() {
+                    wow64
+                } else {
+                    dir.clone()
+                }
+            })
+            .collect()
+    }
+}
+
+// The libraries of the loader cache, as listed by `ldconfig -p`, such as
+// `libc.so.6 (libc6,x86-64) => /lib/x86_64-linux-gnu/libc.so.6`.
+fn ldconfig_cache() -> Option<HashMap<String, PathBuf>> {
+    let output = ["ldconfig", "/sbin/ldconfig"]
+        .iter()
+        .find_map(|ldconfig| Command::new(ldconfig).arg("-p").output().ok())
+        .filter(|output| output.status.success())?;
+
+    Some(parse_ldconfig_cache(&String::from_utf8_lossy(
+        &output.stdout,
+    )))
+}
+
+fn parse_ldconfig_cache(text: &str) -> HashMap<String, PathBuf> {
+    let mut cached = HashMap::new();
+
+    for line in text.lines() {
+        let Some((entry, path)) = line.trim().split_once(" => ") else {
+            continue;
+        };
+        let Some((name, _)) = entry.split_once(" (") else {
+            continue;
+        };
+
+        // The first entry of a name is of the native architecture.
+        cached
+            .entry(name.to_owned())
+            .or_insert_with(|| PathBuf::from(path));
+    }
+
+    cached
+}
+
+/// A library imported by a module of the target, which isn't found.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct UnresolvedLibrary {
+    pub name: String,
+
+    /// The module which imports it, which is either the target or one of the
+    /// libraries it imports.
+    pub importer: PathBuf,
+
+    /// Whether it's delay-loaded, so only fails to load once first called.
+    pub delay_loaded: bool,
+
+    /// Directories searched for it, in the order the loader searches them.
+    /// Includes the path of the loader cache, if it was searched.
+    pub searched: Vec<PathBuf>,
+}
+
+impl fmt::Display for UnresolvedLibrary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let separator = if cfg!(target_os = "windows") {
+            ";"
+        } else {
+            ":"
+        };
+        let searched: Vec<_> = self
+            .searched
+            .iter()
+            .map(|dir| dir.display().to_string())
+            .collect();
+
+        write!(
+            f,
+            "{} (imported by {}, searched: {})",
+            self.name,
+            self.importer.display(),
+            searched.join(separator)
+        )
+    }
+}
+
+/// Find the libraries imported by the module `exe`, or by the libraries it
+/// imports in turn, which aren't found on `search`. Modules which aren't ELF
+/// or PE files, such as scripts, import no libraries.
+pub fn find_unresolved(exe: &Path, search: &SearchPath) -> Result<Vec<UnresolvedLibrary>> {
+    let data = fs::read(exe).with_context(|| format!("unable to read {}", exe.display()))?;
+    let Some(imports) = Imports::parse(&data) else {
+        return Ok(vec![]);
+    };
+
+    Ok(unresolved(exe, imports, search))
+}
+
+// The unresolved libraries of the module at `exe`, of `imports`.
+fn unresolved(exe: &Path, imports: Imports, search: &SearchPath) -> Vec<UnresolvedLibrary> {
+    let mut resolver = Resolver {
+        search,
+        exe: exe.to_owned(),
+        exe_imports: imports.clone(),
+        found: HashSet::new(),
+        unresolved: vec![],
+    };
+    resolver.resolve(exe.to_owned(), imports);
+
+    resolver.unresolved
+}
+
+struct Resolver<'a> {
+    search: &'a SearchPath,
+    exe: PathBuf,
+    exe_imports: Imports,
+
+    // Names of the libraries found, which are then loaded by name.
+    found: HashSet<String>,
+
+    unresolved: Vec<UnresolvedLibrary>,
+}
+
+impl Resolver<'_> {
+    // Resolve the imports of the module at `path`, and of the libraries they
+    // resolve to, breadth-first, as the loader does. Libraries of the system
+    // are assumed to have their own imports.
+    fn resolve(&mut self, path: PathBuf, imports: Imports) {
+        let mut modules = VecDeque::from([(path, imports, false)]);
+
+        while let Some((path, imports, delay_loaded)) = modules.pop_front() {
+            let libraries = imports.libraries.iter().map(|name| (name, delay_loaded));
+            let delay = imports.delay_loaded.iter().map(|name| (name, true));
+
+            for (name, delay_loaded) in libraries.chain(delay) {
+                if imports.format == Format::Pe && is_api_set(name) {
+                    continue;
+                }
+
+                if self.found.contains(name) {
+                    continue;
+                }
+
+                let searched = self.search_dirs(&path, &imports);
+                match self.find(name, &imports, &searched) {
+                    Some(Found::Module(found)) => {
+                        self.found.insert(name.clone());
+                        if let Some(found_imports) =
+                            fs::read(&found).ok().and_then(|data| Imports::parse(&data))
+                        {
+                            modules.push_back((found, found_imports, delay_loaded));
+                        }
+                    }
+                    Some(Found::System) => {
+                        self.found.insert(name.clone());
+                    }
+                    None => self.add_unresolved(name, &path, delay_loaded, searched),
+                }
+            }
+        }
+    }
+
+    fn add_unresolved(
+        &mut self,
+        name: &str,
+        importer: &Path,
+        delay_loaded: bool,
+        mut searched: Vec<PathBuf>,
+    ) {
+        // A library imported by several modules is reported once, as an
+        // error if any of them import it when loaded.
+        if let Some(unresolved) = self.unresolved.iter_mut().find(|u| u.name == name) {
+            unresolved.delay_loaded &= delay_loaded;
+            return;
+        }
+
+        if let Some(cache) = &self.search.cache {
+            searched.push(cache.clone());
+        }
+
+        self.unresolved.push(UnresolvedLibrary {
+            name: name.to_owned(),
+            importer: importer.to_owned(),
+            delay_loaded,
+            searched,
+        });
+    }
+
+    // The library `name`, of a module of `imports`, searched for in `dirs`.
+    fn find(&self, name: &str, imports: &Imports, dirs: &[PathBuf]) -> Option<Found> {
+        // A name with a slash is a path, as of an ELF module, which isn't
+        // searched for.
+        if imports.format == Format::Elf && name.contains('/') {
+            let path = match &self.search.current_dir {
+                Some(dir) => dir.join(name),
+                None => PathBuf::from(name),
+            };
+            return path.is_file().then_some(Found::Module(path));
+        }
+
+        let system_dirs = self.search.system_dirs(imports.format, imports.is_64);
+        for dir in dirs {
+            let path = dir.join(name);
+            if path.is_file() {
+                if system_dirs.contains(dir) {
+                    return Some(Found::System);
+                }
+                return Some(Found::Module(path));
+            }
+        }
+
+        if imports.format == Format::Elf && self.search.cached.contains_key(name) {
+            return Some(Found::System);
+        }
+
+        None
+    }
+
+    // The directories searched for the imports of the module at `path`, in
+    // order, other than the loader cache.
+    fn search_dirs(&self, path: &Path, imports: &Imports) -> Vec<PathBuf> {
+        let system_dirs = self.search.system_dirs(imports.format, imports.is_64);
+        let mut dirs = vec![];
+
+        match imports.format {
+            Format::Elf => {
+                // `DT_RPATH` is ignored if there's a `DT_RUNPATH`, and the
+                // `DT_RPATH` of the executable is also searched for the imports
+                // of its libraries.
+                if imports.runpath.is_empty() {
+                    dirs.extend(expand_origin(&imports.rpath, path));
+
+                    if path != self.exe && self.exe_imports.runpath.is_empty() {
+                        dirs.extend(expand_origin(&self.exe_imports.rpath, &self.exe));
+                    }
+                }
+                dirs.extend(self.search.library_path.iter().cloned());
+                dirs.extend(expand_origin(&imports.runpath, path));
+                dirs.extend(system_dirs);
+            }
+            Format::Pe => {
+                // The DLLs of the application's directory are found first, as
+                // of the safe DLL search mode.
+                dirs.extend(self.exe.parent().map(Path::to_owned));
+                dirs.extend(system_dirs);
+                dirs.extend(self.search.current_dir.iter().cloned());
+                dirs.extend(self.search.library_path.iter().cloned());
+            }
+        }
+
+        let mut unique = vec![];
+        for dir in dirs {
+            if !unique.contains(&dir) {
+                unique.push(dir);
+            }
+        }
+        unique
+    }
+}
+
+enum Found {
+    // A library of the target, whose imports are resolved in turn.
+    Module(PathBuf),
+
+    // A library of the system, or of the loader cache.
+    System,
+}
+
+fn is_api_set(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    API_SET_PREFIXES
+        .iter()
+        .any(|prefix| name.starts_with(prefix))
+}
+
+// The directories of the `DT_RPATH` or `DT_RUNPATH` of the module at `path`,
+// of which `$ORIGIN` is the directory of the module.
+fn expand_origin(dirs: &[String], path: &Path) -> Vec<PathBuf> {
+    let origin = path
+        .parent()
+        .map(|dir| dir.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    dirs.iter()
+        .map(|dir| {
+            PathBuf::from(
+                dir.replace("${ORIGIN}", &origin)
+                    .replace("$ORIGIN", &origin),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,174 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use super::*;
+
+fn elf(libraries: &[&str]) -> Imports {
+    Imports {
+        format: Format::Elf,
+        libraries: libraries.iter().map(|name| name.to_string()).collect(),
+        delay_loaded: vec![],
+        rpath: vec![],
+        runpath: vec![],
+        is_64: true,
+    }
+}
+
+fn pe(libraries: &[&str], delay_loaded: &[&str]) -> Imports {
+    Imports {
+        format: Format::Pe,
+        delay_loaded: delay_loaded.iter().map(|name| name.to_string()).collect(),
+        ..elf(libraries)
+    }
+}
+
+fn touch(path: &Path) {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, "").unwrap();
+}
+
+fn names(unresolved: &[UnresolvedLibrary]) -> Vec<(&str, bool)> {
+    unresolved
+        .iter()
+        .map(|u| (u.name.as_str(), u.delay_loaded))
+        .collect()
+}
+
+#[test]
+fn test_elf_library_path_and_rpath() {
+    let dir = tempfile::tempdir().unwrap();
+    let exe = dir.path().join("fuzz");
+    let libs = dir.path().join("libs");
+    touch(&dir.path().join("lib/libfound.so"));
+    touch(&libs.join("libpath.so"));
+
+    let imports = Imports {
+        rpath: vec!["$ORIGIN/lib".to_owned()],
+        ..elf(&["libfound.so", "libpath.so", "libc.so.6", "libmissing.so"])
+    };
+    let mut search = SearchPath::default().library_path(&libs);
+    search
+        .cached
+        .insert("libc.so.6".to_owned(), "/lib/libc.so.6".into());
+    search.cache = Some("/etc/ld.so.cache".into());
+
+    let unresolved = unresolved(&exe, imports, &search);
+    assert_eq!(names(&unresolved), [("libmissing.so", false)]);
+    assert_eq!(unresolved[0].importer, exe);
+    assert_eq!(
+        unresolved[0].searched,
+        [
+            dir.path().join("lib"),
+            libs.clone(),
+            PathBuf::from("/etc/ld.so.cache")
+        ]
+    );
+
+    let separator = if cfg!(target_os = "windows") {
+        ";"
+    } else {
+        ":"
+    };
+    assert_eq!(
+        unresolved[0].to_string(),
+        format!(
+            "libmissing.so (imported by {}, searched: {}{separator}{}{separator}/etc/ld.so.cache)",
+            exe.display(),
+            dir.path().join("lib").display(),
+            libs.display()
+        )
+    );
+}
+
+#[test]
+fn test_elf_runpath_replaces_rpath() {
+    let dir = tempfile::tempdir().unwrap();
+    let exe = dir.path().join("fuzz");
+    touch(&dir.path().join("rpath/libfound.so"));
+
+    let imports = Imports {
+        rpath: vec!["$ORIGIN/rpath".to_owned()],
+        runpath: vec!["${ORIGIN}/runpath".to_owned()],
+        ..elf(&["libfound.so"])
+    };
+
+    let unresolved = unresolved(&exe, imports, &SearchPath::default());
+    assert_eq!(names(&unresolved), [("libfound.so", false)]);
+    assert_eq!(unresolved[0].searched, [dir.path().join("runpath")]);
+}
+
+#[test]
+fn test_pe_search_order() {
+    let dir = tempfile::tempdir().unwrap();
+    let exe = dir.path().join("fuzz.exe");
+    let system = dir.path().join("Windows/System32");
+    let path = dir.path().join("tools");
+    touch(&dir.path().join("found.dll"));
+    touch(&system.join("kernel32.dll"));
+    touch(&path.join("tool.dll"));
+
+    let imports = pe(
+        &[
+            "found.dll",
+            "kernel32.dll",
+            "API-MS-WIN-CRT-RUNTIME-L1-1-0.dll",
+            "tool.dll",
+            "lost.dll",
+        ],
+        &["delayed.dll", "found.dll"],
+    );
+    let search = SearchPath {
+        system_dirs: vec![system.clone()],
+        ..SearchPath::default()
+    }
+    .library_path(&path)
+    .current_dir(dir.path().join("work"));
+
+    let unresolved = unresolved(&exe, imports, &search);
+    assert_eq!(
+        names(&unresolved),
+        [("lost.dll", false), ("delayed.dll", true)]
+    );
+    assert_eq!(
+        unresolved[0].searched,
+        [dir.path().to_owned(), system, dir.path().join("work"), path]
+    );
+}
+
+#[test]
+fn test_imported_by_several_modules() {
+    let dir = tempfile::tempdir().unwrap();
+    let exe = dir.path().join("fuzz.exe");
+
+    // An error if any module imports it when loaded.
+    let imports = pe(&["lost.dll"], &["lost.dll"]);
+    let unresolved = unresolved(&exe, imports, &SearchPath::default());
+    assert_eq!(names(&unresolved), [("lost.dll", false)]);
+}
+
+#[test]
+fn test_parse_ldconfig_cache() {
+    let text = "\
+3 libs found in cache `/etc/ld.so.cache'
+	libz.so.1 (libc6,x86-64) => /lib/x86_64-linux-gnu/libz.so.1
+	libz.so.1 (libc6) => /lib/i386-linux-gnu/libz.so.1
+	libc.so.6 (libc6,x86-64, OS ABI: Linux 3.2.0) => /lib/x86_64-linux-gnu/libc.so.6
+";
+
+    let cached = parse_ldconfig_cache(text);
+    assert_eq!(cached.len(), 2);
+    assert_eq!(
+        cached["libz.so.1"],
+        PathBuf::from("/lib/x86_64-linux-gnu/libz.so.1")
+    );
+    assert_eq!(
+        cached["libc.so.6"],
+        PathBuf::from("/lib/x86_64-linux-gnu/libc.so.6")
+    );
+}
+
+#[test]
+fn test_not_a_module() {
+    assert_eq!(Imports::parse(b"#!/bin/sh\nexec fuzz \"$@\"\n"), None);
+    assert_eq!(Imports::parse(b""), None);
+}
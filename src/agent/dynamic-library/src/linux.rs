@@ -4,6 +4,7 @@
 
 use std::collections::{HashMap, HashSet};
 use std::ffi::OsStr;
+use std::fmt;
 use std::io::{self};
 use std::path::Path;
 use std::process::Command;
@@ -37,6 +38,35 @@ pub fn find_missing(mut cmd: Command) -> Result<HashSet<MissingDynamicLibrary>,
     Ok(logs.missing())
 }
 
+/// Find missing dynamic libraries, as for `find_missing()`, along with the
+/// directories the dynamic linker searched for each.
+pub fn find_missing_searches(mut cmd: Command) -> Result<Vec<MissingLibrarySearch>, io::Error> {
+    let library_path = explicit_library_path(&cmd);
+    let linked = LinkedDynamicLibraries::search(cmd.get_program(), library_path)?;
+    let mut missing = linked.not_found();
+
+    // Even if linking fails, the search for the first missing library is
+    // logged before the dynamic linker gives up.
+    cmd.env("LD_DEBUG", "libs");
+    let output = cmd.output()?;
+    let logs = LdDebugLogs::parse(&*output.stderr);
+
+    if missing.is_empty() {
+        missing = logs.missing();
+    }
+
+    let mut searches: Vec<_> = missing
+        .into_iter()
+        .map(|lib| MissingLibrarySearch {
+            searched: logs.searched(&lib.name),
+            name: lib.name,
+        })
+        .collect();
+    searches.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(searches)
+}
+
 pub fn get_linked_library_logs(cmd: &Command) -> Result<std::process::Output, io::Error> {
     let library_path = explicit_library_path(cmd);
     let output = LinkedDynamicLibraries::run(cmd.get_program(), library_path)?;
@@ -72,12 +102,35 @@ pub struct MissingDynamicLibrary {
     pub name: String,
 }
 
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MissingLibrarySearch {
+    pub name: String,
+
+    /// Directories searched by the dynamic linker, without the subdirectories
+    /// it adds for each, in the order they were searched. Includes the path of
+    /// the loader cache, if it was searched.
+    pub searched: Vec<String>,
+}
+
+impl fmt::Display for MissingLibrarySearch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.searched.is_empty() {
+            write!(f, "{}", self.name)
+        } else {
+            write!(f, "{} (searched: {})", self.name, self.searched.join(":"))
+        }
+    }
+}
+
 /// Dynamic library searches, as extracted from the dynamic linker debug log output
 /// obtained by setting `LD_DEBUG=libs`.
 ///
 /// For more info about `LD_DEBUG`, see the docs for ld.so(8).
 pub struct LdDebugLogs {
     pub searches: HashMap<LdDebugSearchQuery, LdDebugSearchResult>,
+
+    /// Search paths logged for each query, in order.
+    pub search_paths: HashMap<LdDebugSearchQuery, Vec<String>>,
 }
 
 impl LdDebugLogs {
@@ -99,6 +152,10 @@ impl LdDebugLogs {
         use std::io::prelude::*;
 
         let mut searches = HashMap::default();
+        let mut search_paths: HashMap<LdDebugSearchQuery, Vec<String>> = HashMap::default();
+
+        // The query currently being searched for, on each thread.
+        let mut current: HashMap<u32, LdDebugSearchQuery> = HashMap::default();
 
         let reader = io::BufReader::new(readable);
 
@@ -106,10 +163,18 @@ impl LdDebugLogs {
             // If ok, line is valid UTF-8.
             if let Ok(line) = line {
                 if let Some(query) = LdDebugSearchQuery::parse(&line) {
+                    current.insert(query.pid, query.clone());
                     searches.insert(query, LdDebugSearchResult::NotFound);
                     continue;
                 }
 
+                if let Some((pid, paths)) = parse_search_paths(&line) {
+                    if let Some(query) = current.get(&pid) {
+                        search_paths.entry(query.clone()).or_default().extend(paths);
+                    }
+                    continue;
+                }
+
                 if let Some(found) = FoundLibrary::parse(&line) {
                     let query = found.query();
                     let result = LdDebugSearchResult::Found(found);
@@ -119,7 +184,29 @@ impl LdDebugLogs {
             }
         }
 
-        Self { searches }
+        Self {
+            searches,
+            search_paths,
+        }
+    }
+
+    /// The directories searched for the library `name`, on any thread.
+    pub fn searched(&self, name: &str) -> Vec<String> {
+        let mut searched: Vec<String> = vec![];
+
+        for (query, paths) in &self.search_paths {
+            if query.name != name {
+                continue;
+            }
+
+            for path in base_search_paths(paths) {
+                if !searched.contains(&path) {
+                    searched.push(path);
+                }
+            }
+        }
+
+        searched
     }
 
     pub fn missing(&self) -> HashSet<MissingDynamicLibrary> {
@@ -138,6 +225,42 @@ impl LdDebugLogs {
     }
 }
 
+// Parse the paths of a `search path=<dir>:<dir>  (<source>)` or `search
+// cache=<file>` line.
+fn parse_search_paths(text: &str) -> Option<(u32, Vec<String>)> {
+    if let Some(captures) = SEARCH_PATH_RE.captures(text) {
+        let pid = captures.get(1)?.as_str().parse().ok()?;
+        let paths = captures.get(2)?.as_str().split(':').map(String::from);
+        return Some((pid, paths.collect()));
+    }
+
+    let captures = SEARCH_CACHE_RE.captures(text)?;
+    let pid = captures.get(1)?.as_str().parse().ok()?;
+    let cache = captures.get(2)?.as_str().to_owned();
+
+    Some((pid, vec![cache]))
+}
+
+// For each directory, the dynamic linker first searches subdirectories for
+// hardware capabilities, such as `tls/x86_64` or `glibc-hwcaps/x86-64-v3`,
+// and then the directory itself. Keep only the directories they were added to.
+fn base_search_paths(paths: &[String]) -> Vec<String> {
+    let mut bases: Vec<String> = vec![];
+
+    for path in paths.iter().rev() {
+        let is_subdir = bases
+            .last()
+            .is_some_and(|base| Path::new(path).starts_with(base));
+
+        if !is_subdir {
+            bases.push(path.clone());
+        }
+    }
+
+    bases.reverse();
+    bases
+}
+
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub struct LdDebugSearchQuery {
     /// PID of the thread where the search query occurred.
@@ -217,6 +340,14 @@ lazy_static! {
     static ref INIT_LIBRARY_RE: Regex =
         Regex::new(r"(\d+):\s+calling init: (.+)").unwrap();
 
+    // Captures thread PID, colon-separated search path.
+    static ref SEARCH_PATH_RE: Regex =
+        Regex::new(r"(\d+):\s+search path=(.+?)\s+\(.+\)$").unwrap();
+
+    // Captures thread PID, path of the loader cache.
+    static ref SEARCH_CACHE_RE: Regex =
+        Regex::new(r"(\d+):\s+search cache=(.+)").unwrap();
+
     // Captures shared library name, absolute path of found library.
     static ref LDD_FOUND: Regex =
         Regex::new(r"([^\s]+) => (.+) \(0x[0-9a-f]+\)").unwrap();
@@ -74,6 +74,65 @@ fn test_ld_debug_logs_parse_missing() {
     assert!(missing.contains(&expected));
 }
 
+#[test]
+fn test_ld_debug_logs_searched() {
+    let logs = LdDebugLogs::parse(LD_DEBUG_OUTPUT_MISSING);
+
+    assert_eq!(
+        logs.searched("libmycode.so"),
+        [
+            ".",
+            "/etc/ld.so.cache",
+            "/lib/x86_64-linux-gnu",
+            "/usr/lib/x86_64-linux-gnu",
+            "/lib",
+            "/usr/lib",
+        ]
+    );
+    assert_eq!(logs.searched("libstdc++.so.6"), [".", "/etc/ld.so.cache"]);
+    assert!(logs.searched("libunknown.so").is_empty());
+}
+
+#[test]
+fn test_base_search_paths() {
+    let paths: Vec<String> = [
+        "/setup/glibc-hwcaps/x86-64-v3",
+        "/setup/tls/x86_64",
+        "/setup/x86_64",
+        "/setup",
+        "/lib/x86_64-linux-gnu/tls",
+        "/lib/x86_64-linux-gnu",
+        "/lib/tls",
+        "/lib",
+    ]
+    .iter()
+    .map(|path| path.to_string())
+    .collect();
+
+    assert_eq!(
+        base_search_paths(&paths),
+        ["/setup", "/lib/x86_64-linux-gnu", "/lib"]
+    );
+}
+
+#[test]
+fn test_missing_library_search_display() {
+    let search = MissingLibrarySearch {
+        name: "libicuuc.so.70".to_owned(),
+        searched: vec!["/setup".to_owned(), "/etc/ld.so.cache".to_owned()],
+    };
+    assert_eq!(
+        search.to_string(),
+        "libicuuc.so.70 (searched: /setup:/etc/ld.so.cache)"
+    );
+
+    let search = MissingLibrarySearch {
+        name: "libicuuc.so.70".to_owned(),
+        searched: vec![],
+    };
+    assert_eq!(search.to_string(), "libicuuc.so.70");
+}
+
 #[test]
 fn test_ld_debug_logs_parse_none_missing() {
     let logs = LdDebugLogs::parse(LD_DEBUG_OUTPUT_NONE_MISSING);
@@ -32,6 +32,27 @@ pub fn find_missing(
     Ok(handler.missing_libraries())
 }
 
+/// Find missing DLLs, as for `find_missing()`, along with the DLL search path
+/// computed by the loader.
+pub fn find_missing_searches(
+    cmd: Command,
+) -> Result<Vec<MissingLibrarySearch>, CheckDynamicLibrariesError> {
+    let mut handler = LoaderSnapsHandler::default();
+    setup_debugger(cmd, &mut handler)?;
+
+    let searched = handler.search_path();
+    let searches = handler
+        .missing_libraries()
+        .into_iter()
+        .map(|lib| MissingLibrarySearch {
+            name: lib.name,
+            searched: searched.clone(),
+        })
+        .collect();
+
+    Ok(searches)
+}
+
 pub fn get_logs(cmd: Command) -> Result<Vec<String>, CheckDynamicLibrariesError> {
     let mut handler = LoaderSnapsHandler::default();
     setup_debugger(cmd, &mut handler)?;
@@ -256,6 +277,39 @@ impl MissingDynamicLibrary {
     }
 }
 
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MissingLibrarySearch {
+    pub name: String,
+
+    /// Directories of the DLL search path, in order.
+    pub searched: Vec<String>,
+}
+
+impl fmt::Display for MissingLibrarySearch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.searched.is_empty() {
+            write!(f, "{}", self.name)
+        } else {
+            write!(f, "{} (searched: {})", self.name, self.searched.join(";"))
+        }
+    }
+}
+
+// Parse the directories of a `DLL search path computed: <dir>;<dir>` message.
+fn parse_search_path(text: &str) -> Option<Vec<String>> {
+    let captures = SEARCH_PATH_RE.captures(text)?;
+    let dirs = captures
+        .get(1)?
+        .as_str()
+        .split(';')
+        .map(str::trim)
+        .filter(|dir| !dir.is_empty())
+        .map(String::from)
+        .collect();
+
+    Some(dirs)
+}
+
 #[derive(Default)]
 pub struct LoaderSnapsHandler {
     pub debug_strings: Vec<String>,
@@ -273,6 +327,26 @@ impl LoaderSnapsHandler {
 
         missing
     }
+
+    /// The last DLL search path computed by the loader, without duplicates.
+    pub fn search_path(&self) -> Vec<String> {
+        let mut searched: Vec<String> = vec![];
+
+        if let Some(dirs) = self
+            .debug_strings
+            .iter()
+            .rev()
+            .find_map(|s| parse_search_path(s))
+        {
+            for dir in dirs {
+                if !searched.contains(&dir) {
+                    searched.push(dir);
+                }
+            }
+        }
+
+        searched
+    }
 }
 
 impl DebugEventHandler for LoaderSnapsHandler {
@@ -285,6 +359,10 @@ lazy_static! {
     static ref MISSING_DLL_RE: Regex = Regex::new(
         r#"[0-9a-f]+:[0-9a-f]+ @ [0-9a-f]+ - LdrpProcessWork - ERROR: Unable to load DLL: "(.+)", Parent Module: "(.+)", Status: 0x([0-9a-f]+)"#
     ).unwrap();
+
+    static ref SEARCH_PATH_RE: Regex = Regex::new(
+        r"[0-9a-f]+:[0-9a-f]+ @ [0-9a-f]+ - LdrpComputeLazyDllPath - INFO: DLL search path computed: (.+)"
+    ).unwrap();
 }
 
 #[cfg(test)]
@@ -313,4 +391,29 @@ mod tests {
         assert_eq!(missing.parent, r"C:\my\project\fuzz.exe");
         assert_eq!(missing.status, 0xc0000135);
     }
+
+    #[test]
+    fn test_search_path() {
+        let handler = LoaderSnapsHandler {
+            debug_strings: vec![
+                r"7c48:57c8 @ 371984000 - LdrpComputeLazyDllPath - INFO: DLL search path computed: C:\old".to_owned(),
+                r"7c48:57c8 @ 371984000 - LdrpComputeLazyDllPath - INFO: DLL search path computed: C:\my\project;C:\WINDOWS\SYSTEM32;;C:\WINDOWS;C:\my\project".to_owned(),
+                r#"7c48:57c8 @ 371984000 - LdrpProcessWork - ERROR: Unable to load DLL: "lost.dll", Parent Module: "C:\my\project\fuzz.exe", Status: 0xc0000135"#.to_owned(),
+            ],
+        };
+
+        assert_eq!(
+            handler.search_path(),
+            [r"C:\my\project", r"C:\WINDOWS\SYSTEM32", r"C:\WINDOWS"]
+        );
+
+        let search = MissingLibrarySearch {
+            name: "lost.dll".to_owned(),
+            searched: handler.search_path(),
+        };
+        assert_eq!(
+            search.to_string(),
+            r"lost.dll (searched: C:\my\project;C:\WINDOWS\SYSTEM32;C:\WINDOWS)"
+        );
+    }
 }
@@ -0,0 +1,99 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+#![cfg(feature = "slow-tests")]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use dynamic_library::imports::{find_unresolved, SearchPath};
+
+#[cfg(target_os = "linux")]
+const LIBRARY: &str = "libmissing.so";
+
+#[cfg(target_os = "windows")]
+const LIBRARY: &str = "missing.dll";
+
+fn run(program: &str, args: &[&str], dir: &Path) {
+    let status = Command::new(program)
+        .args(args)
+        .current_dir(dir)
+        .status()
+        .unwrap_or_else(|err| panic!("launching {program}: {err}"));
+    assert!(status.success(), "{program} failed: {status}");
+}
+
+fn fixture(name: &str) -> String {
+    format!("{}/tests/imports/{name}", env!("CARGO_MANIFEST_DIR"))
+}
+
+// Build the fixture target as `fuzz`, linked against the fixture library,
+// which is then deleted.
+fn build(dir: &Path, link_args: &[&str]) -> PathBuf {
+    run(
+        "rustc",
+        &[
+            "--crate-type",
+            "cdylib",
+            "-o",
+            LIBRARY,
+            &fixture("missing.rs"),
+        ],
+        dir,
+    );
+
+    // The import library of `missing.dll` is `missing.dll.lib`, but the linker
+    // looks for `missing.lib`.
+    if cfg!(target_os = "windows") {
+        fs::copy(dir.join("missing.dll.lib"), dir.join("missing.lib")).unwrap();
+    }
+
+    let exe = if cfg!(target_os = "windows") {
+        "fuzz.exe"
+    } else {
+        "fuzz"
+    };
+    let mut args = vec!["-L", ".", "-o", exe];
+    for arg in link_args {
+        args.extend(["-C", *arg]);
+    }
+    let target = fixture("target.rs");
+    args.push(&target);
+    run("rustc", &args, dir);
+
+    let exe = dir.join(exe);
+    let search = SearchPath::system().library_path(dir);
+    assert!(find_unresolved(&exe, &search).unwrap().is_empty());
+
+    fs::remove_file(dir.join(LIBRARY)).unwrap();
+    exe
+}
+
+#[test]
+fn test_missing_library() {
+    let dir = tempfile::tempdir().unwrap();
+    let exe = build(dir.path(), &[]);
+
+    let unresolved = find_unresolved(&exe, &SearchPath::system()).unwrap();
+    assert_eq!(unresolved.len(), 1);
+    assert_eq!(unresolved[0].name, LIBRARY);
+    assert_eq!(unresolved[0].importer, exe);
+    assert!(!unresolved[0].delay_loaded);
+    assert!(!unresolved[0].searched.is_empty());
+}
+
+#[cfg(target_os = "windows")]
+#[test]
+fn test_missing_delay_loaded_library() {
+    let dir = tempfile::tempdir().unwrap();
+    let exe = build(
+        dir.path(),
+        &["link-arg=/DELAYLOAD:missing.dll", "link-arg=delayimp.lib"],
+    );
+
+    let unresolved = find_unresolved(&exe, &SearchPath::system()).unwrap();
+    assert_eq!(unresolved.len(), 1);
+    assert_eq!(unresolved[0].name, LIBRARY);
+    assert!(unresolved[0].delay_loaded);
+}
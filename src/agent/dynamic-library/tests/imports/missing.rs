@@ -0,0 +1,6 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+// Deleted once the fixture target is linked against it.
+#[no_mangle]
+pub extern "C" fn missing() {}
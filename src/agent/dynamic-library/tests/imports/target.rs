@@ -0,0 +1,13 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+#[link(name = "missing")]
+extern "C" {
+    fn missing();
+}
+
+fn main() {
+    if std::env::args().len() > 1 {
+        unsafe { missing() };
+    }
+}
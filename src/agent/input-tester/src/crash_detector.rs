@@ -8,12 +8,12 @@ use std::{
     ffi::{OsStr, OsString},
     fs,
     io::Write,
-    path::Path,
+    path::{Path, PathBuf},
     process::{Command, Output, Stdio},
     time::{Duration, Instant},
 };
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use debugger::{DebugEventHandler, Debugger};
 use log::{debug, error, trace};
 use win_util::{
@@ -27,6 +27,7 @@ use windows::Win32::{
 
 use crate::{
     logging,
+    minidump::{write_dump, DumpOptions},
     test_result::{
         asan, new_exception,
         vcpp_debugger::{self, VcppDebuggerExceptionInfo},
@@ -41,6 +42,9 @@ pub struct DebuggerResult {
     pub stdout: String,
     pub stderr: String,
     pub debugger_output: String,
+
+    /// The minidump written of the crash, if one was asked for.
+    pub crash_dump: Option<PathBuf>,
 }
 
 impl DebuggerResult {
@@ -50,6 +54,7 @@ impl DebuggerResult {
         stdout: String,
         stderr: String,
         debugger_output: String,
+        crash_dump: Option<PathBuf>,
     ) -> Self {
         DebuggerResult {
             exceptions,
@@ -57,6 +62,7 @@ impl DebuggerResult {
             stdout,
             stderr,
             debugger_output,
+            crash_dump,
         }
     }
 
@@ -127,6 +133,13 @@ struct CrashDetectorEventHandler {
     stderr_buffer: Vec<u8>,
     debugger_output: String,
     exceptions: Vec<Exception>,
+
+    /// The status of the last child of the target to crash, so that it is
+    /// not lost when the target is a script which exits normally after it.
+    child_crash_code: Option<i32>,
+
+    dump: Option<DumpOptions>,
+    dump_written: bool,
 }
 
 impl CrashDetectorEventHandler {
@@ -136,6 +149,7 @@ impl CrashDetectorEventHandler {
         ignore_first_chance_exceptions: bool,
         start_time: Instant,
         max_duration: Duration,
+        dump: Option<DumpOptions>,
     ) -> Self {
         Self {
             start_time,
@@ -149,10 +163,57 @@ impl CrashDetectorEventHandler {
             stderr_buffer: vec![],
             debugger_output: String::new(),
             exceptions: vec![],
+            child_crash_code: None,
+            dump,
+            dump_written: false,
+        }
+    }
+
+    // Write the dump asked for at the first exception of its chance which may
+    // crash the target, once per run.
+    fn maybe_write_dump(&mut self, debugger: &mut Debugger, info: &EXCEPTION_DEBUG_INFO) {
+        let Some(dump) = &self.dump else {
+            return;
+        };
+
+        let first_chance = info.dwFirstChance == 1;
+        if self.dump_written || self.any_target_terminated || first_chance != dump.first_chance {
+            return;
+        }
+
+        // Other exceptions, such as of C++ and breakpoints, are raised and
+        // handled in the normal course of a run, at their first chance.
+        let code = info.ExceptionRecord.ExceptionCode;
+        if first_chance && !is_crash_code(code.0 as u32) && code != asan::EH_SANITIZER {
+            return;
+        }
+
+        match write_dump(debugger, info, &dump.path, dump.dump_type) {
+            Ok(()) => self.dump_written = true,
+            Err(err) => {
+                error!(
+                    "unable to write minidump {}: {:?}",
+                    dump.path.display(),
+                    err
+                );
+                let _ = fs::remove_file(&dump.path);
+            }
         }
     }
 }
 
+// If the event is for a child of the target, rather than the target itself.
+fn is_child_event(debugger: &mut Debugger) -> bool {
+    let target_id = debugger.target().process_id();
+    debugger.current_target().process_id() != target_id
+}
+
+// If an exit code is an error `NTSTATUS`, as when exiting by an unhandled
+// exception.
+fn is_crash_code(code: u32) -> bool {
+    code & 0xC000_0000 == 0xC000_0000
+}
+
 fn is_vcpp_notification(exception: &EXCEPTION_DEBUG_INFO, target_process_handle: HANDLE) -> bool {
     if exception.ExceptionRecord.ExceptionCode == vcpp_debugger::EXCEPTION_VISUALCPP_DEBUGGER {
         match VcppDebuggerExceptionInfo::from_exception_record(
@@ -189,6 +250,7 @@ impl DebugEventHandler for CrashDetectorEventHandler {
             // An exception might be handled, or other cleanup might occur between
             // the first chance and the second chance, so we continue execution.
             let exception_code = info.ExceptionRecord.ExceptionCode;
+            self.maybe_write_dump(debugger, info);
 
             // If we're ignoring first chance exceptions, we skip collecting the stack
             // and adding the exception to our list of results.
@@ -198,9 +260,7 @@ impl DebugEventHandler for CrashDetectorEventHandler {
                 || self.any_target_terminated)
             {
                 match debugger.get_current_stack() {
-                    Ok(stack) => self
-                        .exceptions
-                        .push(new_exception(process_handle, info, stack)),
+                    Ok(stack) => self.exceptions.push(new_exception(debugger, info, stack)),
                     Err(err) => error!("Error walking program under test stack: {}", err),
                 }
 
@@ -223,6 +283,9 @@ impl DebugEventHandler for CrashDetectorEventHandler {
                         "crash in process {} - terminating",
                         process::id(process_handle)
                     );
+                    if is_child_event(debugger) {
+                        self.child_crash_code = Some(exception_code.0);
+                    }
                     process::terminate(process_handle);
                 }
             }
@@ -232,6 +295,12 @@ impl DebugEventHandler for CrashDetectorEventHandler {
         DBG_EXCEPTION_NOT_HANDLED
     }
 
+    fn on_exit_process(&mut self, debugger: &mut Debugger, exit_code: u32) {
+        if is_child_event(debugger) && is_crash_code(exit_code) {
+            self.child_crash_code = Some(exit_code as i32);
+        }
+    }
+
     fn on_output_debug_string(&mut self, _debugger: &mut Debugger, message: String) {
         self.debugger_output.push_str(&message);
     }
@@ -262,13 +331,17 @@ impl DebugEventHandler for CrashDetectorEventHandler {
 }
 
 /// This function runs the application under a debugger to detect any crashes in
-/// the process or any children processes.
+/// the process or any children processes, writing a minidump of the first
+/// crash if `dump` is set. The file `stdin`, if set, is opened as its standard
+/// input, which is otherwise empty.
 pub fn test_process(
     app_path: impl AsRef<OsStr>,
     args: &[impl AsRef<OsStr>],
     env: &HashMap<String, String>,
     max_duration: Duration,
     ignore_first_chance_exceptions: bool,
+    dump: Option<DumpOptions>,
+    stdin: Option<&Path>,
 ) -> Result<DebuggerResult> {
     debug!("Running: {}", logging::command_invocation(&app_path, args));
 
@@ -276,10 +349,16 @@ pub fn test_process(
     // To merge streams, we could instead use:
     //     stderr_writer = stdout_writer.try_clone()?;
     let (stderr_reader, stderr_writer) = pipe()?;
+    let stdin: Stdio = match stdin {
+        Some(path) => fs::File::open(path)
+            .with_context(|| format!("unable to open input: {}", path.display()))?
+            .into(),
+        None => Stdio::null(),
+    };
     let mut command = Command::new(app_path);
     command
         .args(args)
-        .stdin(Stdio::null())
+        .stdin(stdin)
         .stdout(stdout_writer)
         .stderr(stderr_writer);
 
@@ -294,8 +373,9 @@ pub fn test_process(
         ignore_first_chance_exceptions,
         start_time,
         max_duration,
+        dump,
     );
-    let (mut debugger, mut child) = Debugger::init(command, &mut event_handler)?;
+    let (mut debugger, mut child) = Debugger::init_with_children(command, &mut event_handler)?;
     debugger.run(&mut event_handler)?;
 
     let pid = child.id();
@@ -310,17 +390,26 @@ pub fn test_process(
     let exit_status = if event_handler.timed_out {
         ExitStatus::from_timeout(max_duration.as_secs())
     } else if let Some(code) = output.status.code() {
-        ExitStatus::from_code(code)
+        match event_handler.child_crash_code {
+            Some(child_code) if !is_crash_code(code as u32) => ExitStatus::from_code(child_code),
+            _ => ExitStatus::from_code(code),
+        }
     } else {
         unreachable!("Only Unix can signal");
     };
 
+    let crash_dump = event_handler
+        .dump
+        .filter(|_| event_handler.dump_written)
+        .map(|dump| dump.path);
+
     Ok(DebuggerResult::new(
         filter_uninteresting_exceptions(event_handler.exceptions),
         exit_status,
         String::from_utf8_lossy(&output.stdout).to_string(),
         String::from_utf8_lossy(&output.stderr).to_string(),
         event_handler.debugger_output,
+        crash_dump,
     ))
 }
 
@@ -335,6 +424,7 @@ fn filter_uninteresting_exceptions(mut exceptions: Vec<Exception>) -> Vec<Except
         let prev = &exceptions[i - 1];
         let curr = &exceptions[i];
         if prev.first_chance
+            && prev.process_id == curr.process_id
             && prev.exception_code == curr.exception_code
             && prev.stack_hash == curr.stack_hash
         {
@@ -349,6 +439,7 @@ fn filter_uninteresting_exceptions(mut exceptions: Vec<Exception>) -> Vec<Except
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::minidump::DumpType;
     use crate::test_result::{ExceptionCode, ExceptionDescription};
 
     const READ_AV: NTSTATUS = NTSTATUS(0xc0000005_u32 as i32);
@@ -362,6 +453,8 @@ mod tests {
                 &HashMap::default(),
                 $timeout,
                 /*ignore first chance exceptions*/ true,
+                /*dump*/ None,
+                /*stdin*/ None,
             )
             .unwrap()
         }};
@@ -398,6 +491,128 @@ mod tests {
         assert_eq!(result.stdout.len(), expected_len);
     }
 
+    #[test]
+    fn crash_of_child_is_detected() {
+        let script = "powershell /nop /c '[Runtime.InteropServices.Marshal]::ReadInt32([IntPtr]::Zero)'; 'done'";
+        let result = runps!(Duration::from_secs(30), script);
+
+        assert!(result.any_crashes(), "{:?}", result.exit_status);
+        assert!(result.stdout.contains("done"));
+
+        // The script exits normally, but the status is of the crashed child.
+        match result.exit_status {
+            ExitStatus::Code(code) => assert!(is_crash_code(code as u32), "{code:x}"),
+            other => panic!("unexpected exit status: {other}"),
+        }
+    }
+
+    // Build the fixture `name`, of the broker and worker pair, as `name.exe`.
+    fn build_fixture(dir: &Path, name: &str) -> PathBuf {
+        let source = format!("{}/tests/children/{name}.rs", env!("CARGO_MANIFEST_DIR"));
+        let exe = dir.join(format!("{name}.exe"));
+        let status = Command::new("rustc")
+            .arg("-o")
+            .arg(&exe)
+            .arg(source)
+            .status()
+            .unwrap();
+        assert!(status.success(), "rustc failed: {status}");
+        exe
+    }
+
+    #[test]
+    fn crash_of_worker_is_annotated() {
+        let dir = tempfile::tempdir().unwrap();
+        let broker = build_fixture(dir.path(), "broker");
+        build_fixture(dir.path(), "worker");
+
+        let result = test_process(
+            &broker,
+            &[] as &[&str],
+            &HashMap::default(),
+            Duration::from_secs(30),
+            /*ignore first chance exceptions*/ true,
+            /*dump*/ None,
+            /*stdin*/ None,
+        )
+        .unwrap();
+
+        assert!(result.any_crashes(), "{:?}", result.exit_status);
+        assert!(result.stdout.contains("worker exited"));
+
+        let exception = result.exceptions.last().unwrap();
+        assert_eq!(exception.exception_code, READ_AV);
+        assert!(exception.child_process);
+        assert_eq!(
+            exception.process_image.as_deref().and_then(Path::file_name),
+            Some(OsStr::new("worker.exe"))
+        );
+        assert!(exception.to_string().contains("ChildProcess: true"));
+    }
+
+    fn crash_with_dump(first_chance: bool) -> (DebuggerResult, tempfile::TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        let dump = DumpOptions {
+            path: dir.path().join("crash.dmp"),
+            dump_type: DumpType::Mini,
+            first_chance,
+        };
+        let result = test_process(
+            r"C:\windows\system32\WindowsPowerShell\v1.0\powershell.exe",
+            &[
+                "/nop".to_string(),
+                "/c".to_string(),
+                "[Runtime.InteropServices.Marshal]::ReadInt32([IntPtr]::Zero)".to_string(),
+            ],
+            &HashMap::default(),
+            Duration::from_secs(30),
+            /*ignore first chance exceptions*/ true,
+            Some(dump),
+            /*stdin*/ None,
+        )
+        .unwrap();
+
+        (result, dir)
+    }
+
+    #[test]
+    fn crash_dump_is_written() {
+        for first_chance in [false, true] {
+            let (result, dir) = crash_with_dump(first_chance);
+            assert!(result.any_crashes(), "{:?}", result.exit_status);
+
+            let path = result.crash_dump.expect("no crash dump");
+            assert_eq!(path, dir.path().join("crash.dmp"));
+
+            let data = fs::read(&path).unwrap();
+            assert!(data.starts_with(b"MDMP"), "not a minidump");
+        }
+    }
+
+    #[test]
+    fn no_crash_dump_without_crash() {
+        let dir = tempfile::tempdir().unwrap();
+        let dump = DumpOptions {
+            path: dir.path().join("crash.dmp"),
+            dump_type: DumpType::Mini,
+            first_chance: false,
+        };
+        let result = test_process(
+            r"C:\windows\system32\WindowsPowerShell\v1.0\powershell.exe",
+            &["/nop".to_string(), "/c".to_string(), "'done'".to_string()],
+            &HashMap::default(),
+            Duration::from_secs(30),
+            /*ignore first chance exceptions*/ true,
+            Some(dump),
+            /*stdin*/ None,
+        )
+        .unwrap();
+
+        assert!(!result.any_crashes());
+        assert!(result.crash_dump.is_none());
+        assert!(!dir.path().join("crash.dmp").exists());
+    }
+
     macro_rules! exception {
         ($code: expr, $hash: expr, first) => {
             exception!($code, $hash, true)
@@ -419,6 +634,9 @@ mod tests {
                 stack_hash: $hash,
                 first_chance: $first_chance,
                 stack_frames: vec![],
+                process_id: 1,
+                process_image: None,
+                child_process: false,
             }
         }};
     }
@@ -445,6 +663,11 @@ mod tests {
             exception!(READ_AV, 1234),
         ];
 
+        // The same exception, but of another process of the tree.
+        let mut other_process = exception!(READ_AV, 1234);
+        other_process.process_id = 2;
+        let two_processes = vec![exception!(READ_AV, 1234, first), other_process];
+
         assert_eq!(filter_uninteresting_exceptions(empty).len(), 0);
         assert_eq!(filter_uninteresting_exceptions(one_first_chance).len(), 1);
         assert_eq!(filter_uninteresting_exceptions(one_second_chance).len(), 1);
@@ -453,5 +676,6 @@ mod tests {
         assert_eq!(filter_uninteresting_exceptions(atypical2).len(), 2);
         assert_eq!(filter_uninteresting_exceptions(atypical3).len(), 3);
         assert_eq!(filter_uninteresting_exceptions(atypical4).len(), 3);
+        assert_eq!(filter_uninteresting_exceptions(two_processes).len(), 2);
     }
 }
@@ -9,6 +9,7 @@
 pub mod appverifier;
 pub mod crash_detector;
 pub mod logging;
+pub mod minidump;
 pub mod summary;
 pub mod test_result;
 pub mod tester;
@@ -0,0 +1,112 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Minidumps of crashing targets, written by the debugger, to be opened in
+//! WinDbg.
+
+use std::{
+    fs::File,
+    os::windows::io::AsRawHandle,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use debugger::{
+    dbghelp::{self, FrameContext},
+    Debugger,
+};
+use windows::Win32::{
+    Foundation::{FALSE, HANDLE},
+    System::Diagnostics::Debug::{
+        MiniDumpNormal, MiniDumpWithFullMemory, MiniDumpWithHandleData, MiniDumpWriteDump, CONTEXT,
+        EXCEPTION_DEBUG_INFO, EXCEPTION_POINTERS, MINIDUMP_EXCEPTION_INFORMATION, MINIDUMP_TYPE,
+    },
+};
+
+/// What a minidump records, besides the threads, stacks and modules of the
+/// process.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum DumpType {
+    #[default]
+    Mini,
+
+    /// All of the memory of the process, so that the heap can be inspected.
+    WithFullMemory,
+
+    /// The handles the process has open.
+    WithHandleData,
+}
+
+impl DumpType {
+    pub fn minidump_type(&self) -> MINIDUMP_TYPE {
+        match self {
+            Self::Mini => MiniDumpNormal,
+            Self::WithFullMemory => MiniDumpWithFullMemory,
+            Self::WithHandleData => MiniDumpWithHandleData,
+        }
+    }
+}
+
+/// The minidump to write of a crash of the target, or of one of its children.
+#[derive(Clone, Debug)]
+pub struct DumpOptions {
+    pub path: PathBuf,
+    pub dump_type: DumpType,
+
+    /// Write the dump at the first chance of the exception, before any
+    /// handler of the target runs, rather than at its last chance.
+    pub first_chance: bool,
+}
+
+/// Write a minidump of the process of the current debug event, stopped at the
+/// exception of `info`.
+pub(crate) fn write_dump(
+    debugger: &mut Debugger,
+    info: &EXCEPTION_DEBUG_INFO,
+    path: &Path,
+    dump_type: DumpType,
+) -> Result<()> {
+    let target = debugger.current_target();
+    let process_handle = target.process_handle();
+    let process_id = target.process_id();
+    let thread_handle = target.current_thread_handle();
+    let thread_id = target.current_thread_id();
+
+    let file = File::create(path)
+        .with_context(|| format!("unable to create minidump: {}", path.display()))?;
+
+    // Read by dbghelp from our memory, rather than from the target's. The
+    // context of a WOW64 thread isn't a `CONTEXT`, so its dump has no exception
+    // stream, though it has the stacks of all threads.
+    let mut record = info.ExceptionRecord;
+    let mut frame = dbghelp::get_thread_frame(process_handle, thread_handle)?;
+    let native = matches!(frame, FrameContext::X64(_));
+    let mut pointers = EXCEPTION_POINTERS {
+        ExceptionRecord: &mut record,
+        ContextRecord: frame.as_mut_ptr() as *mut CONTEXT,
+    };
+    let exception = MINIDUMP_EXCEPTION_INFORMATION {
+        ThreadId: thread_id,
+        ExceptionPointers: &mut pointers,
+        ClientPointers: FALSE,
+    };
+    let exception = native.then_some(&exception as *const _);
+
+    // dbghelp isn't thread safe.
+    let _guard = dbghelp::lock()?;
+    unsafe {
+        MiniDumpWriteDump(
+            process_handle,
+            process_id,
+            HANDLE(file.as_raw_handle() as isize),
+            dump_type.minidump_type(),
+            exception,
+            None,
+            None,
+        )
+    }
+    .ok()
+    .context("MiniDumpWriteDump")?;
+
+    Ok(())
+}
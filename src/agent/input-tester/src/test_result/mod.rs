@@ -8,9 +8,15 @@ pub mod fast_fail;
 pub mod vcpp_debugger;
 pub mod verifier_stop;
 
-use std::{fmt, path::Path};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
 
-use debugger::stack::{DebugStack, DebugStackFrame};
+use debugger::{
+    stack::{DebugStack, DebugStackFrame},
+    Debugger,
+};
 use log::error;
 use win_util::process;
 use windows::Win32::{
@@ -163,11 +169,16 @@ pub fn new_exception_description(
     }
 }
 
+/// The exception of the current debug event of `debugger`, raised in the target
+/// or in one of its children.
 pub fn new_exception(
-    process_handle: HANDLE,
+    debugger: &mut Debugger,
     exception: &EXCEPTION_DEBUG_INFO,
     stack: DebugStack,
 ) -> Exception {
+    let target_id = debugger.target().process_id();
+    let process = debugger.current_target();
+    let process_handle = process.process_handle();
     let stack_hash = stack.stable_hash();
     Exception {
         exception_code: exception.ExceptionRecord.ExceptionCode,
@@ -175,6 +186,9 @@ pub fn new_exception(
         stack_hash,
         first_chance: exception.dwFirstChance != 0,
         stack_frames: stack.frames,
+        process_id: process.process_id(),
+        process_image: process.image_path().map(Path::to_owned),
+        child_process: process.process_id() != target_id,
     }
 }
 
@@ -214,6 +228,16 @@ pub struct Exception {
 
     /// The call stack when the exception was raised.
     pub stack_frames: Vec<DebugStackFrame>,
+
+    /// The id of the process which raised the exception.
+    pub process_id: u32,
+
+    /// The executable of the process which raised the exception, if known.
+    pub process_image: Option<PathBuf>,
+
+    /// True if the exception was raised in a process started by the target,
+    /// such as a worker spawned by a broker, rather than in the target itself.
+    pub child_process: bool,
 }
 
 impl fmt::Display for Exception {
@@ -221,6 +245,16 @@ impl fmt::Display for Exception {
         writeln!(formatter, "Exception: 0x{:8x}", self.exception_code.0)?;
         writeln!(formatter, "    Description: {}", self.description)?;
         writeln!(formatter, "    FirstChance: {}", self.first_chance)?;
+        match &self.process_image {
+            Some(image) => writeln!(
+                formatter,
+                "    Process: {} ({})",
+                self.process_id,
+                image.display()
+            )?,
+            None => writeln!(formatter, "    Process: {}", self.process_id)?,
+        }
+        writeln!(formatter, "    ChildProcess: {}", self.child_process)?;
         writeln!(formatter, "    StackHash: {}", self.stack_hash)?;
         writeln!(formatter, "    Stack:")?;
         for frame in &self.stack_frames {
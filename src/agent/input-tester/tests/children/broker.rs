@@ -0,0 +1,14 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+// Spawns `worker.exe`, from its own directory, to do its work, and exits
+// normally, however the worker exits.
+
+use std::process::Command;
+
+fn main() {
+    let exe = std::env::current_exe().unwrap();
+    let worker = exe.with_file_name("worker.exe");
+    let status = Command::new(worker).status().unwrap();
+    println!("worker exited: {status}");
+}
@@ -0,0 +1,9 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+// Reads near null, raising an access violation.
+
+fn main() {
+    let value = unsafe { std::ptr::read_volatile(0x10 as *const i32) };
+    println!("{value}");
+}
@@ -5,19 +5,25 @@
 use std::time::Duration;
 
 use anyhow::{Error, Result};
+use onefuzz::heartbeat::alert;
+use onefuzz_telemetry::{Event::agent_config_reloaded, EventData};
 use tokio::time;
+use tracing::{debug_span, field, info_span, Instrument};
 
+use crate::config::{ConfigWatcher, ReloadableConfig};
 use crate::coordinator::*;
 use crate::done::set_done_lock;
 use crate::heartbeat::{AgentHeartbeatClient, HeartbeatSender};
 use crate::reboot::*;
 use crate::scheduler::*;
 use crate::setup::*;
+use crate::spans::ids;
 use crate::work::IWorkQueue;
 use crate::worker::{IWorkerRunner, WorkerEvent};
 
 const PENDING_COMMANDS_DELAY: time::Duration = time::Duration::from_secs(10);
 const BUSY_DELAY: time::Duration = time::Duration::from_secs(1);
+const DEFAULT_SLEEP_DURATION: time::Duration = time::Duration::from_secs(30);
 
 pub struct Agent {
     coordinator: Box<dyn ICoordinator>,
@@ -32,6 +38,12 @@ pub struct Agent {
     managed: bool,
     machine_id: uuid::Uuid,
     sleep_duration: Duration,
+    config_watcher: Option<ConfigWatcher>,
+    default_log_level: log::LevelFilter,
+    set_log_level: fn(log::LevelFilter),
+
+    // The names of the heartbeat alerts as of the last check.
+    heartbeat_alerts: Vec<String>,
 }
 
 impl Agent {
@@ -62,7 +74,23 @@ impl Agent {
             last_poll_command,
             managed,
             machine_id,
-            sleep_duration: Duration::from_secs(30),
+            sleep_duration: DEFAULT_SLEEP_DURATION,
+            config_watcher: None,
+            default_log_level: log::max_level(),
+            set_log_level: log::set_max_level,
+            heartbeat_alerts: vec![],
+        }
+    }
+
+    /// Apply the reloadable settings of the agent config, and re-apply them
+    /// whenever the config file changes.
+    pub fn config_watcher(mut self, config_watcher: ConfigWatcher) -> Self {
+        let config = config_watcher.config().reloadable.clone();
+        self.apply_config(&config);
+
+        Self {
+            config_watcher: Some(config_watcher),
+            ..self
         }
     }
 
@@ -79,14 +107,14 @@ impl Agent {
         // `Free`. If it has started up after a work set-requested reboot, the
         // state will be `Ready`.
         if let Some(Scheduler::Free(..)) = &self.scheduler {
-            let event = StateUpdateEvent::Init.into();
-            self.coordinator.emit_event(event).await?;
+            self.emit_event(StateUpdateEvent::Init.into()).await?;
         }
         let mut state = self;
         let mut done = false;
         while !done {
             state.heartbeat.alive();
             if instant.elapsed() >= PENDING_COMMANDS_DELAY {
+                state.check_heartbeat_alerts();
                 state = state.execute_pending_commands().await?;
                 instant = time::Instant::now();
             }
@@ -98,20 +126,76 @@ impl Agent {
         Ok(())
     }
 
+    // Log the alerts of heartbeats which can't be delivered, by the agent or
+    // its tasks, as they're raised and cleared.
+    fn check_heartbeat_alerts(&mut self) {
+        let Ok(dir) = alert::alerts_dir() else {
+            return;
+        };
+        let alerts = alert::read_alerts(&dir);
+
+        for alert in &alerts {
+            if !self.heartbeat_alerts.contains(&alert.name) {
+                error!(
+                    "heartbeats of {} can't be delivered, {} failures since {}, see {}: {}",
+                    alert.name,
+                    alert.failures,
+                    alert.first_failure,
+                    dir.display(),
+                    alert.error
+                );
+            }
+        }
+
+        for name in &self.heartbeat_alerts {
+            if !alerts.iter().any(|alert| &alert.name == name) {
+                info!("heartbeats of {} are delivered again", name);
+            }
+        }
+
+        self.heartbeat_alerts = alerts.into_iter().map(|alert| alert.name).collect();
+    }
+
     async fn update(mut self) -> Result<(Self, bool)> {
+        self.reload_config().await;
+
         let last = self.scheduler.take().ok_or_else(scheduler_error)?;
         let previous_state = NodeState::from(&last);
-        let (next, done) = match last {
-            Scheduler::Free(s) => (self.free(s, previous_state).await?, false),
-            Scheduler::SettingUp(s) => (self.setting_up(s, previous_state).await?, false),
-            Scheduler::PendingReboot(s) => (self.pending_reboot(s, previous_state).await?, false),
-            Scheduler::Ready(s) => (self.ready(s, previous_state).await?, false),
-            Scheduler::Busy(s) => (self.busy(s, previous_state).await?, false),
-            //todo: introduce  a new prameter to allow the agent to restart after this point
-            Scheduler::Done(s) => (self.done(s, previous_state).await?, true),
+        let update = async move {
+            let (next, done) = match last {
+                Scheduler::Free(s) => (self.free(s, previous_state).await?, false),
+                Scheduler::SettingUp(s) => (self.setting_up(s, previous_state).await?, false),
+                Scheduler::PendingReboot(s) => {
+                    (self.pending_reboot(s, previous_state).await?, false)
+                }
+                Scheduler::Ready(s) => (self.ready(s, previous_state).await?, false),
+                Scheduler::Busy(s) => (self.busy(s, previous_state).await?, false),
+                //todo: introduce  a new prameter to allow the agent to restart after this point
+                Scheduler::Done(s) => (self.done(s, previous_state).await?, true),
+            };
+
+            Ok::<_, Error>((next, done))
         };
 
-        Ok((next, done))
+        // Verbose, since the agent updates every second while busy.
+        let span = debug_span!("update", state = ?previous_state);
+        update.instrument(span).await
+    }
+
+    async fn emit_event(&self, event: NodeEvent) -> Result<()> {
+        let span = info_span!(
+            "emit_event",
+            event = event_kind(&event),
+            task_id = field::Empty
+        );
+        if let NodeEvent::WorkerEvent(
+            WorkerEvent::Running { task_id } | WorkerEvent::Done { task_id, .. },
+        ) = &event
+        {
+            span.record("task_id", field::display(task_id));
+        }
+
+        self.coordinator.emit_event(event).instrument(span).await
     }
 
     async fn emit_state_update_if_changed(&self, event: StateUpdateEvent) -> Result<()> {
@@ -123,7 +207,7 @@ impl Agent {
             | (StateUpdateEvent::Ready, NodeState::Ready)
             | (StateUpdateEvent::Done { .. }, NodeState::Done) => {}
             _ => {
-                self.coordinator.emit_event(event.into()).await?;
+                self.emit_event(event.into()).await?;
             }
         }
 
@@ -144,7 +228,12 @@ impl Agent {
             if can_schedule.allowed {
                 info!("claiming work set: {:?}", msg.work_set);
 
-                match self.work_queue.claim(msg).await {
+                let span = info_span!(
+                    "claim",
+                    job_id = %ids(msg.work_set.job_ids()),
+                    task_id = %ids(msg.work_set.task_ids()),
+                );
+                match self.work_queue.claim(msg).instrument(span).await {
                     Err(err) => {
                         error!("unable to claim work set: {}", err);
 
@@ -217,10 +306,19 @@ impl Agent {
         info!("agent setting up");
 
         let tasks = state.work_set().task_ids();
+        let span = info_span!(
+            "setup",
+            job_id = %ids(state.work_set().job_ids()),
+            task_id = %ids(&tasks),
+        );
         self.emit_state_update_if_changed(StateUpdateEvent::SettingUp { tasks })
             .await?;
 
-        let scheduler = match state.finish(self.setup_runner.as_mut()).await? {
+        let scheduler = match state
+            .finish(self.setup_runner.as_mut())
+            .instrument(span)
+            .await?
+        {
             SetupDone::Ready(s) => s.into(),
             SetupDone::PendingReboot(s) => s.into(),
             SetupDone::Done(s) => s.into(),
@@ -253,10 +351,17 @@ impl Agent {
         info!("agent ready");
         self.emit_state_update_if_changed(StateUpdateEvent::Ready)
             .await?;
+
+        let span = info_span!(
+            "worker_run",
+            job_id = %ids(state.work_set().job_ids()),
+            task_id = %ids(state.work_set().task_ids()),
+        );
+        let busy = state.run(self.machine_id).instrument(span).await?;
+
         Ok(Self {
             previous_state: previous,
-
-            scheduler: Some(state.run(self.machine_id).await?.into()),
+            scheduler: Some(busy.into()),
             ..self
         })
     }
@@ -279,7 +384,7 @@ impl Agent {
             .await?;
 
         for event in events {
-            self.coordinator.emit_event(event.into()).await?;
+            self.emit_event(event.into()).await?;
         }
 
         Ok(Self {
@@ -339,7 +444,14 @@ impl Agent {
             Err(PollCommandError::RequestFailed(err)) => {
                 // If we failed to request commands, this could be the service
                 // could be down.  Log it, but keep going.
-                error!("error polling the service for commands: {:?}", err);
+                if reqwest_retry::circuit::is_circuit_open(&err) {
+                    warn!(
+                        "service unavailable, backing off polling for commands: {}",
+                        err
+                    );
+                } else {
+                    error!("error polling the service for commands: {:?}", err);
+                }
                 Ok(Self {
                     last_poll_command: result,
                     ..self
@@ -369,11 +481,57 @@ impl Agent {
         }
     }
 
+    async fn reload_config(&mut self) {
+        let Some(config_watcher) = &mut self.config_watcher else {
+            return;
+        };
+
+        let update = match config_watcher.check().await {
+            Ok(Some(update)) => update,
+            Ok(None) => return,
+            Err(err) => {
+                warn!("unable to reload agent config: {:?}", err);
+                return;
+            }
+        };
+
+        info!("reloading agent config, changed: {:?}", update.changed);
+        self.apply_config(&update.config);
+
+        let changed = update.changed.join(",");
+        event!(agent_config_reloaded; EventData::Name = changed);
+    }
+
+    fn apply_config(&mut self, config: &ReloadableConfig) {
+        // Validated when the config is loaded.
+        let log_level = config.log_level().ok().flatten();
+        (self.set_log_level)(log_level.unwrap_or(self.default_log_level));
+
+        self.sleep_duration = config.poll_interval().unwrap_or(DEFAULT_SLEEP_DURATION);
+    }
+
     async fn sleep(&self) {
         time::sleep(self.sleep_duration).await;
     }
 }
 
+// The kind of `event`, as a span field, without its potentially large data.
+fn event_kind(event: &NodeEvent) -> &'static str {
+    match event {
+        NodeEvent::StateUpdate(event) => match event {
+            StateUpdateEvent::Init => "init",
+            StateUpdateEvent::Free => "free",
+            StateUpdateEvent::SettingUp { .. } => "setting_up",
+            StateUpdateEvent::Rebooting => "rebooting",
+            StateUpdateEvent::Ready => "ready",
+            StateUpdateEvent::Busy => "busy",
+            StateUpdateEvent::Done { .. } => "done",
+        },
+        NodeEvent::WorkerEvent(WorkerEvent::Running { .. }) => "worker_running",
+        NodeEvent::WorkerEvent(WorkerEvent::Done { .. }) => "worker_done",
+    }
+}
+
 // The agent owns a `Scheduler`, which it must consume when driving its state
 // transitions in `update()`. If `self.scheduler` is ever `None` outside of
 // `update()`, then it is a fatal internal error.
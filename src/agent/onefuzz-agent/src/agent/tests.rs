@@ -13,8 +13,24 @@ use crate::worker::double::*;
 use crate::worker::WorkerEvent;
 use onefuzz::process::ExitStatus;
 
+use std::cell::Cell;
+
 use super::*;
 
+thread_local! {
+    // The log level last applied by an agent under test. Tests run on their own
+    // threads, so this keeps them from racing on the global `log::max_level()`.
+    static LOG_LEVEL: Cell<Option<log::LevelFilter>> = Cell::new(None);
+}
+
+fn set_test_log_level(level: log::LevelFilter) {
+    LOG_LEVEL.with(|log_level| log_level.set(Some(level)));
+}
+
+fn test_log_level() -> Option<log::LevelFilter> {
+    LOG_LEVEL.with(Cell::get)
+}
+
 struct Fixture;
 
 impl Fixture {
@@ -26,7 +42,7 @@ impl Fixture {
         let work_queue = Box::<WorkQueueDouble>::default();
         let worker_runner = Box::<WorkerRunnerDouble>::default();
 
-        Agent::new(
+        let agent = Agent::new(
             coordinator,
             reboot,
             scheduler,
@@ -36,7 +52,13 @@ impl Fixture {
             None,
             true,
             Uuid::new_v4(),
-        )
+        );
+
+        Agent {
+            default_log_level: log::LevelFilter::Info,
+            set_log_level: set_test_log_level,
+            ..agent
+        }
     }
 
     pub fn job_id(&self) -> Uuid {
@@ -235,3 +257,244 @@ async fn test_emitted_state_failed_setup() {
         .await
         .unwrap();
 }
+
+#[tokio::test]
+async fn test_spans() {
+    let (subscriber, closed) = crate::spans::SpanSubscriber::capturing();
+    let _subscriber = tracing::subscriber::set_default(subscriber);
+
+    let mut agent = Agent {
+        worker_runner: Box::new(WorkerRunnerDouble {
+            child: ChildDouble {
+                exit_status: Some(ExitStatus {
+                    code: Some(0),
+                    signal: None,
+                    success: true,
+                }),
+                ..ChildDouble::default()
+            },
+        }),
+        ..Fixture.agent()
+    };
+
+    agent
+        .work_queue
+        .downcast_mut::<WorkQueueDouble>()
+        .unwrap()
+        .available
+        .push(Fixture.message());
+
+    let mut done;
+    for _i in 0..10 {
+        (agent, done) = agent.update().await.unwrap();
+        if done {
+            break;
+        }
+    }
+
+    let closed = closed.lock().unwrap().clone();
+    let field = |span: &crate::spans::ClosedSpan, name: &str| {
+        span.fields
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, value)| value.clone())
+    };
+
+    // Each phase is within an update of the agent, and lasts no longer.
+    let mut phases = vec![];
+    for span in &closed {
+        if span.path == "update" {
+            for phase in phases.drain(..) {
+                assert!(span.duration >= phase.duration, "{phase:?}");
+            }
+        } else {
+            assert_eq!(span.path, format!("update/{}", span.name));
+            phases.push(span);
+        }
+    }
+    assert!(phases.is_empty(), "{phases:?}");
+
+    for name in ["claim", "setup", "worker_run"] {
+        let spans: Vec<_> = closed.iter().filter(|span| span.name == name).collect();
+        assert_eq!(spans.len(), 1, "{name}");
+        assert_eq!(
+            field(spans[0], "job_id"),
+            Some(Fixture.job_id().to_string())
+        );
+        assert_eq!(
+            field(spans[0], "task_id"),
+            Some(Fixture.task_id().to_string())
+        );
+    }
+
+    let emitted: Vec<_> = closed
+        .iter()
+        .filter(|span| span.name == "emit_event")
+        .map(|span| field(span, "event").unwrap())
+        .collect();
+    assert_eq!(
+        emitted,
+        [
+            "free",
+            "setting_up",
+            "ready",
+            "busy",
+            "worker_running",
+            "worker_done",
+            "done"
+        ]
+    );
+
+    tokio::fs::remove_file(crate::done::done_path(agent.machine_id).unwrap())
+        .await
+        .unwrap();
+}
+
+struct ConfigFile {
+    path: std::path::PathBuf,
+}
+
+impl ConfigFile {
+    async fn new(settings: serde_json::Value) -> Self {
+        let path =
+            std::env::temp_dir().join(format!("onefuzz-agent-config-{}.json", Uuid::new_v4()));
+        let config = Self { path };
+        config.write(settings).await;
+        config
+    }
+
+    async fn write(&self, settings: serde_json::Value) {
+        let mut config = serde_json::json!({
+            "client_credentials": {
+                "client_id": "a5ea6e8e-3bd5-4d3b-a4b3-8ab7b4b3f58d",
+                "client_secret": "secret",
+                "tenant": "tenant",
+            },
+            "pool_name": "pool",
+            "onefuzz_url": "https://onefuzz.contoso.com",
+            "instance_id": "5220ff9b-2ab2-4cf8-b9ad-b948c3b94f08",
+            "machine_identity": {
+                "machine_id": "3e2f3b8c-1e42-4d5a-8b55-3c2c1f3a6d5e",
+                "machine_name": "node",
+                "scaleset_name": null,
+            },
+        });
+
+        for (key, value) in settings.as_object().unwrap() {
+            config[key] = value.clone();
+        }
+
+        self.write_raw(&config.to_string()).await;
+    }
+
+    async fn write_raw(&self, data: &str) {
+        tokio::fs::write(&self.path, data).await.unwrap();
+    }
+
+    async fn watcher(&self) -> ConfigWatcher {
+        let data = tokio::fs::read(&self.path).await.unwrap();
+        let config = crate::config::StaticConfig::new(&data, None).await.unwrap();
+        ConfigWatcher::new(&self.path, config).await.unwrap()
+    }
+}
+
+impl Drop for ConfigFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+// Drive a single update from the `Free` state, which reloads the config
+// without sleeping, since work is available.
+async fn update_free(mut agent: Agent) -> Agent {
+    agent.scheduler = Some(Scheduler::new(None));
+    agent
+        .work_queue
+        .downcast_mut::<WorkQueueDouble>()
+        .unwrap()
+        .available
+        .push(Fixture.message());
+
+    let (agent, done) = agent.update().await.unwrap();
+    assert!(!done);
+    agent
+}
+
+#[tokio::test]
+async fn test_reload_poll_interval() {
+    let config = ConfigFile::new(serde_json::json!({ "poll_interval": 5 })).await;
+
+    let agent = Fixture.agent().config_watcher(config.watcher().await);
+    assert_eq!(agent.sleep_duration, Duration::from_secs(5));
+
+    config
+        .write(serde_json::json!({ "poll_interval": 10 }))
+        .await;
+    let agent = update_free(agent).await;
+    assert_eq!(agent.sleep_duration, Duration::from_secs(10));
+
+    // An invalid config is ignored, keeping the current settings.
+    config.write_raw("{ \"poll_interval\": ").await;
+    let agent = update_free(agent).await;
+    assert_eq!(agent.sleep_duration, Duration::from_secs(10));
+
+    config.write(serde_json::json!({})).await;
+    let agent = update_free(agent).await;
+    assert_eq!(agent.sleep_duration, DEFAULT_SLEEP_DURATION);
+}
+
+#[tokio::test]
+async fn test_reload_log_level() {
+    let config = ConfigFile::new(serde_json::json!({})).await;
+
+    let agent = Fixture.agent().config_watcher(config.watcher().await);
+    assert_eq!(test_log_level(), Some(log::LevelFilter::Info));
+
+    config
+        .write(serde_json::json!({ "log_level": "trace" }))
+        .await;
+    let agent = update_free(agent).await;
+    assert_eq!(test_log_level(), Some(log::LevelFilter::Trace));
+
+    // The whole config is rejected if any reloadable setting is invalid.
+    config
+        .write(serde_json::json!({ "log_level": "loud", "poll_interval": 1 }))
+        .await;
+    let agent = update_free(agent).await;
+    assert_eq!(test_log_level(), Some(log::LevelFilter::Trace));
+    assert_eq!(agent.sleep_duration, DEFAULT_SLEEP_DURATION);
+
+    config.write(serde_json::json!({})).await;
+    update_free(agent).await;
+    assert_eq!(test_log_level(), Some(log::LevelFilter::Info));
+}
+
+#[tokio::test]
+async fn test_reload_ignores_fixed_fields() {
+    let config = ConfigFile::new(serde_json::json!({})).await;
+    let mut watcher = config.watcher().await;
+
+    config
+        .write(serde_json::json!({
+            "pool_name": "other-pool",
+            "poll_interval": 3,
+        }))
+        .await;
+
+    let update = watcher.check().await.unwrap().unwrap();
+    assert_eq!(update.changed, ["poll_interval"]);
+    assert_eq!(update.config.poll_interval, Some(3));
+    assert_eq!(watcher.config().pool_name, "pool");
+
+    // Only changes to fixed fields don't produce an update.
+    config
+        .write(serde_json::json!({
+            "pool_name": "another-pool",
+            "poll_interval": 3,
+        }))
+        .await;
+    assert_eq!(watcher.check().await.unwrap(), None);
+
+    // Unchanged files aren't reloaded.
+    assert_eq!(watcher.check().await.unwrap(), None);
+}
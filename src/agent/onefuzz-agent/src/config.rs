@@ -3,13 +3,19 @@
 
 use anyhow::{Context, Result};
 use onefuzz::{
-    auth::{ClientCredentials, Credentials, ManagedIdentityCredentials},
+    auth::{
+        AccessToken, ClientCertificate, ClientCredentials, Credentials, ManagedIdentityConfig,
+        ManagedIdentityCredentials, PersistentTokenCache, UserAssignedIdentity,
+        WorkloadIdentityConfig, WorkloadIdentityCredentials,
+    },
+    cloud::{Cloud, CloudEnvironment},
     http::{is_auth_error_code, ResponseExt},
     jitter::delay_with_jitter,
+    key_vault,
     machine_id::MachineIdentity,
 };
 use onefuzz_telemetry::{InstanceTelemetryKey, MicrosoftTelemetryKey};
-use reqwest_retry::SendRetry;
+use reqwest_retry::{proxy::ProxySettings, timeout::Timeouts, SendRetry};
 use std::{
     path::{Path, PathBuf},
     time::{Duration, Instant},
@@ -42,6 +48,20 @@ pub struct StaticConfig {
     pub managed: bool,
 
     pub machine_identity: MachineIdentity,
+
+    /// The user-assigned managed identity of the node, if it has several.
+    pub managed_identity: Option<UserAssignedIdentity>,
+
+    /// The Azure cloud of the instance, such as Azure Government.
+    #[serde(default)]
+    pub cloud: Cloud,
+
+    pub proxy: Option<ProxyConfig>,
+
+    pub http_timeouts: Option<HttpTimeoutsConfig>,
+
+    #[serde(flatten)]
+    pub reloadable: ReloadableConfig,
 }
 
 fn default_as_true() -> bool {
@@ -51,16 +71,53 @@ fn default_as_true() -> bool {
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
 pub struct RawClientCredentials {
     client_id: Uuid,
-    client_secret: String,
+    client_secret: Option<String>,
+
+    // The path of a PFX or PEM file, used instead of a client secret.
+    certificate: Option<PathBuf>,
+
     tenant: String,
     multi_tenant_domain: Option<String>,
 }
 
+impl RawClientCredentials {
+    fn into_credentials(
+        self,
+        resource: String,
+        cloud: &CloudEnvironment,
+    ) -> Result<ClientCredentials> {
+        let credentials = match (self.client_secret, self.certificate) {
+            (Some(client_secret), None) => ClientCredentials::new(
+                self.client_id,
+                client_secret,
+                resource,
+                self.tenant,
+                self.multi_tenant_domain,
+            ),
+            (None, Some(certificate)) => ClientCredentials::with_certificate(
+                self.client_id,
+                ClientCertificate::new(certificate)?,
+                resource,
+                self.tenant,
+                self.multi_tenant_domain,
+            ),
+            (Some(_), Some(_)) => {
+                bail!("client credentials must have a client secret or a certificate, not both")
+            }
+            (None, None) => bail!("client credentials must have a client secret or a certificate"),
+        };
+
+        Ok(credentials.with_cloud(cloud.clone()))
+    }
+}
+
 // Temporary shim type to bridge the current service-provided config.
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
 struct RawStaticConfig {
     pub client_credentials: Option<RawClientCredentials>,
 
+    pub workload_identity: Option<WorkloadIdentityConfig>,
+
     pub pool_name: String,
 
     pub onefuzz_url: Url,
@@ -81,31 +138,173 @@ struct RawStaticConfig {
     pub managed: bool,
 
     pub machine_identity: Option<MachineIdentity>,
+
+    pub managed_identity: Option<ManagedIdentityConfig>,
+
+    #[serde(default)]
+    pub cloud: Cloud,
+
+    pub proxy: Option<ProxyConfig>,
+
+    pub http_timeouts: Option<HttpTimeoutsConfig>,
+
+    #[serde(flatten)]
+    pub reloadable: ReloadableConfig,
+}
+
+/// Seconds to wait for outbound requests, in place of the defaults.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct HttpTimeoutsConfig {
+    /// Seconds to wait to connect to a server.
+    pub connect: Option<u64>,
+
+    /// Seconds to wait for a whole request, including its response.
+    pub request: Option<u64>,
+}
+
+impl HttpTimeoutsConfig {
+    pub fn timeouts(&self) -> Timeouts {
+        let defaults = Timeouts::DEFAULT;
+
+        Timeouts {
+            connect: self
+                .connect
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.connect),
+            request: self
+                .request
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.request),
+        }
+    }
+}
+
+/// The proxy of outbound requests, such as an authenticated corporate proxy.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ProxyConfig {
+    pub url: Url,
+
+    pub username: Option<String>,
+
+    pub password: Option<SecretReference>,
+
+    // Hosts which aren't proxied, in addition to IMDS.
+    #[serde(default)]
+    pub no_proxy: Vec<String>,
+}
+
+/// The location of a secret, which isn't itself kept in the config.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum SecretReference {
+    /// The name of an environment variable.
+    Env(String),
+
+    /// The URL of a key vault secret, read with the managed identity of the
+    /// node.
+    KeyVault(Url),
+}
+
+impl SecretReference {
+    async fn get(
+        &self,
+        identity: Option<&UserAssignedIdentity>,
+        cloud: &CloudEnvironment,
+    ) -> Result<String> {
+        match self {
+            Self::Env(name) => std::env::var(name).with_context(|| format!("{name} is not set")),
+            Self::KeyVault(url) => Ok(key_vault::get_secret(url, identity, cloud).await?.expose()),
+        }
+    }
+}
+
+impl ProxyConfig {
+    /// The settings of the proxy, with its password, if any, read from where
+    /// it's stored in `cloud`, with the managed identity `identity`, if
+    /// selected.
+    pub async fn settings(
+        &self,
+        identity: Option<&UserAssignedIdentity>,
+        cloud: &CloudEnvironment,
+    ) -> Result<ProxySettings> {
+        let settings = ProxySettings::new(self.url.clone())?.with_no_proxy(&self.no_proxy);
+
+        let settings = match (&self.username, &self.password) {
+            (Some(username), password) => {
+                let password = match password {
+                    Some(password) => password
+                        .get(identity, cloud)
+                        .await
+                        .context("unable to read proxy password")?,
+                    None => String::new(),
+                };
+                settings.with_basic_auth(username.clone(), password)
+            }
+            (None, Some(_)) => bail!("proxy password requires a username"),
+            (None, None) => settings,
+        };
+
+        Ok(settings)
+    }
 }
 
 impl StaticConfig {
     pub async fn new(data: &[u8], machine_identity: Option<MachineIdentity>) -> Result<Self> {
         let config: RawStaticConfig = serde_json::from_slice(data)?;
+        config.reloadable.log_level()?;
+
+        // Remove trailing `/`, which is treated as a distinct resource.
+        let resource = config
+            .onefuzz_url
+            .to_string()
+            .trim_end_matches('/')
+            .to_owned();
+        let multi_tenant_domain = config.multi_tenant_domain.clone();
+        let managed_identity = config
+            .managed_identity
+            .as_ref()
+            .map(ManagedIdentityConfig::identity)
+            .transpose()?;
+        let cloud = config.cloud.environment();
+
+        // Queues of another cloud are most likely a config of the wrong cloud,
+        // but are still tried, since their SAS URLs don't need its endpoints.
+        for (name, url) in [
+            ("heartbeat_queue", &config.heartbeat_queue),
+            ("job_result_queue", &config.job_result_queue),
+        ] {
+            if let Some(url) = url {
+                if !cloud.is_storage_url(url) {
+                    warn!(
+                        "{} isn't a storage URL of the {} cloud (*.{}), which may need to be set",
+                        name, config.cloud, cloud.storage_suffix
+                    );
+                }
+            }
+        }
 
-        let credentials = match config.client_credentials {
-            Some(client) => ClientCredentials::new(
-                client.client_id,
-                client.client_secret,
-                config.onefuzz_url.to_string(),
-                client.tenant,
-                client.multi_tenant_domain,
-            )
-            .into(),
-            None => {
-                // Remove trailing `/`, which is treated as a distinct resource.
-                let resource = config
-                    .onefuzz_url
-                    .to_string()
-                    .trim_end_matches('/')
-                    .to_owned();
-                let managed =
-                    ManagedIdentityCredentials::new(resource, config.multi_tenant_domain.clone())?;
-                managed.into()
+        // Explicit credentials take precedence over those of the environment.
+        let credentials = match (config.client_credentials, config.workload_identity) {
+            (Some(client), _) => client
+                .into_credentials(config.onefuzz_url.to_string(), &cloud)?
+                .into(),
+            (None, Some(workload)) => {
+                WorkloadIdentityCredentials::new(workload, &cloud, resource, multi_tenant_domain)?
+                    .into()
+            }
+            (None, None) => {
+                match WorkloadIdentityCredentials::from_env(
+                    &cloud,
+                    resource.clone(),
+                    multi_tenant_domain.clone(),
+                )? {
+                    Some(workload) => workload.into(),
+                    None => ManagedIdentityCredentials::new(resource, multi_tenant_domain)?
+                        .with_identity(managed_identity.clone())
+                        .into(),
+                }
             }
         };
         let machine_identity = match machine_identity.or(config.machine_identity) {
@@ -125,6 +324,11 @@ impl StaticConfig {
             instance_id: config.instance_id,
             managed: config.managed,
             machine_identity,
+            managed_identity,
+            cloud: config.cloud,
+            proxy: config.proxy,
+            http_timeouts: config.http_timeouts,
+            reloadable: config.reloadable,
         };
 
         Ok(config)
@@ -143,13 +347,19 @@ impl StaticConfig {
     pub fn from_env() -> Result<Self> {
         let instance_id = Uuid::parse_str(&std::env::var("ONEFUZZ_INSTANCE_ID")?)?;
         let client_id = Uuid::parse_str(&std::env::var("ONEFUZZ_CLIENT_ID")?)?;
-        let client_secret = std::env::var("ONEFUZZ_CLIENT_SECRET")?;
+        let client_secret = std::env::var("ONEFUZZ_CLIENT_SECRET").ok();
+        let client_certificate = std::env::var_os("ONEFUZZ_CLIENT_CERTIFICATE").map(PathBuf::from);
         let tenant = std::env::var("ONEFUZZ_TENANT")?;
         let multi_tenant_domain = std::env::var("ONEFUZZ_MULTI_TENANT_DOMAIN").ok();
         let onefuzz_url = Url::parse(&std::env::var("ONEFUZZ_URL")?)?;
         let pool_name = std::env::var("ONEFUZZ_POOL")?;
         let is_unmanaged = std::env::var("ONEFUZZ_IS_UNMANAGED").is_ok();
         let machine_identity = MachineIdentity::from_env()?;
+        let cloud: Cloud = match std::env::var("ONEFUZZ_CLOUD") {
+            Ok(cloud) => cloud.parse()?,
+            Err(_) => Cloud::default(),
+        };
+        let environment = cloud.environment();
 
         let heartbeat_queue = if let Ok(key) = std::env::var("ONEFUZZ_HEARTBEAT") {
             Some(Url::parse(&key)?)
@@ -177,14 +387,43 @@ impl StaticConfig {
                 None
             };
 
-        let credentials = ClientCredentials::new(
-            client_id,
-            client_secret,
-            onefuzz_url.to_string(),
-            tenant,
-            multi_tenant_domain.clone(),
-        )
-        .into();
+        // Without a client secret or certificate, the client is federated with
+        // a workload identity, such as of an AKS pod.
+        let credentials = match (client_secret, client_certificate) {
+            (Some(client_secret), _) => ClientCredentials::new(
+                client_id,
+                client_secret,
+                onefuzz_url.to_string(),
+                tenant,
+                multi_tenant_domain.clone(),
+            )
+            .with_cloud(environment)
+            .into(),
+            (None, Some(certificate)) => ClientCredentials::with_certificate(
+                client_id,
+                ClientCertificate::new(certificate)?,
+                onefuzz_url.to_string(),
+                tenant,
+                multi_tenant_domain.clone(),
+            )
+            .with_cloud(environment)
+            .into(),
+            (None, None) => {
+                let config = WorkloadIdentityConfig {
+                    client_id: Some(client_id),
+                    tenant: Some(tenant),
+                    ..Default::default()
+                };
+                let resource = onefuzz_url.as_str().trim_end_matches('/').to_owned();
+                WorkloadIdentityCredentials::new(
+                    config,
+                    &environment,
+                    resource,
+                    multi_tenant_domain.clone(),
+                )?
+                .into()
+            }
+        };
 
         Ok(Self {
             credentials,
@@ -198,14 +437,230 @@ impl StaticConfig {
             instance_id,
             managed: !is_unmanaged,
             machine_identity,
+            managed_identity: None,
+            cloud,
+            proxy: None,
+            http_timeouts: None,
+            reloadable: ReloadableConfig::default(),
         })
     }
 
+    /// Names of the settings which differ from `other`, but can't be changed
+    /// while the agent is running.
+    fn fixed_changes(&self, other: &Self) -> Vec<&'static str> {
+        let mut changed = vec![];
+
+        if self.credentials != other.credentials {
+            changed.push("client_credentials");
+        }
+
+        if self.pool_name != other.pool_name {
+            changed.push("pool_name");
+        }
+
+        if self.onefuzz_url != other.onefuzz_url {
+            changed.push("onefuzz_url");
+        }
+
+        if self.multi_tenant_domain != other.multi_tenant_domain {
+            changed.push("multi_tenant_domain");
+        }
+
+        if self.instance_telemetry_key != other.instance_telemetry_key {
+            changed.push("instance_telemetry_key");
+        }
+
+        if self.microsoft_telemetry_key != other.microsoft_telemetry_key {
+            changed.push("microsoft_telemetry_key");
+        }
+
+        if self.heartbeat_queue != other.heartbeat_queue {
+            changed.push("heartbeat_queue");
+        }
+
+        if self.job_result_queue != other.job_result_queue {
+            changed.push("job_result_queue");
+        }
+
+        if self.instance_id != other.instance_id {
+            changed.push("instance_id");
+        }
+
+        if self.managed != other.managed {
+            changed.push("managed");
+        }
+
+        if self.managed_identity != other.managed_identity {
+            changed.push("managed_identity");
+        }
+
+        if self.cloud != other.cloud {
+            changed.push("cloud");
+        }
+
+        if self.http_timeouts != other.http_timeouts {
+            changed.push("http_timeouts");
+        }
+
+        changed
+    }
+
     fn register_url(&self) -> Url {
         let mut url = self.onefuzz_url.clone();
         url.set_path("/api/agents/registration");
         url
     }
+
+    /// An access token for the service, reused from the last run of the agent
+    /// if it's still valid.
+    pub async fn access_token(&self) -> Result<AccessToken> {
+        self.credentials
+            .access_token_with_cache(&self.token_cache()?)
+            .await
+    }
+
+    /// A new access token for the service, such as after the last one was
+    /// rejected.
+    pub async fn refresh_access_token(&self) -> Result<AccessToken> {
+        self.credentials
+            .refresh_access_token_with_cache(&self.token_cache()?)
+            .await
+    }
+
+    fn token_cache(&self) -> Result<PersistentTokenCache> {
+        let path = onefuzz::fs::onefuzz_etc()?
+            .join(format!("token-cache-{}", self.machine_identity.machine_id));
+        Ok(PersistentTokenCache::new(path))
+    }
+}
+
+/// Settings which are applied when the config file changes, without restarting
+/// the agent.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+pub struct ReloadableConfig {
+    /// Maximum level of logs to record and send as telemetry, such as `info` or
+    /// `debug`. If unset, the level configured at startup is used.
+    ///
+    /// This sets the global maximum level of the `log` crate. The console
+    /// filter from `RUST_LOG` is fixed at startup, so levels it excludes are
+    /// still not printed.
+    pub log_level: Option<String>,
+
+    /// Seconds to wait before polling the work queue again, when no work is
+    /// available.
+    pub poll_interval: Option<u64>,
+}
+
+impl ReloadableConfig {
+    pub fn log_level(&self) -> Result<Option<log::LevelFilter>> {
+        self.log_level
+            .as_deref()
+            .map(|level| {
+                level
+                    .parse()
+                    .map_err(|_| anyhow!("invalid log level: {level}"))
+            })
+            .transpose()
+    }
+
+    pub fn poll_interval(&self) -> Option<Duration> {
+        self.poll_interval.map(Duration::from_secs)
+    }
+
+    fn changed_fields(&self, other: &Self) -> Vec<&'static str> {
+        let mut changed = vec![];
+
+        if self.log_level != other.log_level {
+            changed.push("log_level");
+        }
+
+        if self.poll_interval != other.poll_interval {
+            changed.push("poll_interval");
+        }
+
+        changed
+    }
+}
+
+/// Watches the agent config file for changes to its `ReloadableConfig`.
+///
+/// Other settings are fixed for the lifetime of the agent. Changes to them are
+/// logged and otherwise ignored, until the agent is restarted.
+pub struct ConfigWatcher {
+    path: PathBuf,
+    data: Vec<u8>,
+    config: StaticConfig,
+}
+
+/// A change to the reloadable settings of the agent config.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ConfigUpdate {
+    pub config: ReloadableConfig,
+
+    /// Names of the settings that changed.
+    pub changed: Vec<&'static str>,
+}
+
+impl ConfigWatcher {
+    pub async fn new(path: impl Into<PathBuf>, config: StaticConfig) -> Result<Self> {
+        let path = path.into();
+        let data = fs::read(&path)
+            .await
+            .with_context(|| format!("unable to read config file: {}", path.display()))?;
+
+        Ok(Self { path, data, config })
+    }
+
+    pub fn config(&self) -> &StaticConfig {
+        &self.config
+    }
+
+    /// Re-read the config file, and return the reloadable settings if they have
+    /// changed since the last check.
+    ///
+    /// The new settings are only returned if the whole file is valid, so that a
+    /// partially-written config is never applied.
+    pub async fn check(&mut self) -> Result<Option<ConfigUpdate>> {
+        let data = fs::read(&self.path)
+            .await
+            .with_context(|| format!("unable to read config file: {}", self.path.display()))?;
+
+        if data == self.data {
+            return Ok(None);
+        }
+
+        // Only report an invalid config once, rather than on every check.
+        self.data = data;
+
+        // Parse the file as-is, to detect a changed machine identity, which
+        // `StaticConfig::new()` would otherwise replace with the current one.
+        let raw: RawStaticConfig = serde_json::from_slice(&self.data)
+            .with_context(|| format!("unable to parse config file: {}", self.path.display()))?;
+        let machine_identity = self.config.machine_identity.clone();
+        let config = StaticConfig::new(&self.data, Some(machine_identity)).await?;
+
+        let mut ignored = self.config.fixed_changes(&config);
+        if matches!(&raw.machine_identity, Some(identity) if *identity != self.config.machine_identity)
+        {
+            ignored.push("machine_identity");
+        }
+
+        for field in ignored {
+            warn!("ignoring change to agent config field `{field}`, restart the agent to apply it");
+        }
+
+        let changed = self.config.reloadable.changed_fields(&config.reloadable);
+        if changed.is_empty() {
+            return Ok(None);
+        }
+
+        self.config.reloadable = config.reloadable.clone();
+
+        Ok(Some(ConfigUpdate {
+            config: config.reloadable,
+            changed,
+        }))
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -264,7 +719,7 @@ const REGISTRATION_RETRY_PERIOD: Duration = Duration::from_secs(60);
 
 impl Registration {
     pub async fn create(config: StaticConfig, managed: bool, timeout: Duration) -> Result<Self> {
-        let token = config.credentials.access_token().await?;
+        let mut token = config.access_token().await?;
         let machine_name = &config.machine_identity.machine_name;
         let machine_id = config.machine_identity.machine_id;
 
@@ -292,7 +747,7 @@ impl Registration {
         let end_time = Instant::now() + timeout;
 
         while Instant::now() < end_time {
-            let response = reqwest::Client::new()
+            let response = reqwest_retry::proxy::client()
                 .post(url.clone())
                 .header("Content-Length", "0")
                 .bearer_auth(token.secret().expose_ref())
@@ -320,6 +775,9 @@ impl Registration {
                         REGISTRATION_RETRY_PERIOD.as_secs()
                     );
                     delay_with_jitter(REGISTRATION_RETRY_PERIOD).await;
+
+                    // The token may have been cached, but since revoked.
+                    token = config.refresh_access_token().await?;
                 }
                 Err(err) => return Err(err),
             }
@@ -349,14 +807,14 @@ impl Registration {
 
     pub async fn renew(&self) -> Result<Self> {
         info!("renewing registration");
-        let token = self.config.credentials.access_token().await?;
+        let token = self.config.access_token().await?;
 
         let machine_id = self.machine_id.to_string();
 
         let mut url = self.config.register_url();
         url.query_pairs_mut().append_pair("machine_id", &machine_id);
 
-        let response = reqwest::Client::new()
+        let response = reqwest_retry::proxy::client()
             .get(url)
             .bearer_auth(token.secret().expose_ref())
             .send_retry_default()
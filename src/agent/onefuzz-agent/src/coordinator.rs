@@ -6,10 +6,8 @@ use std::sync::Arc;
 use anyhow::{Context, Error, Result};
 use downcast_rs::Downcast;
 use onefuzz::{auth::AccessToken, http::ResponseExt, process::Output};
-use reqwest::{Client, RequestBuilder, Response, StatusCode};
-use reqwest_retry::{
-    is_auth_failure, RetryCheck, SendRetry, DEFAULT_RETRY_PERIOD, MAX_RETRY_ATTEMPTS,
-};
+use reqwest::{Client, RequestBuilder, Response};
+use reqwest_retry::{circuit, is_auth_failure, RetryBudget, RetryPolicy, SendRetry};
 use serde::Serialize;
 use tokio::sync::RwLock;
 use uuid::Uuid;
@@ -80,6 +78,18 @@ pub enum NodeEvent {
     WorkerEvent(WorkerEvent),
 }
 
+impl NodeEvent {
+    /// How long to retry emitting the event. The final events of the node
+    /// and of its tasks can't be sent again, so are retried for longest.
+    pub fn retry_budget(&self) -> RetryBudget {
+        match self {
+            NodeEvent::StateUpdate(StateUpdateEvent::Done { .. })
+            | NodeEvent::WorkerEvent(WorkerEvent::Done { .. }) => RetryBudget::CRITICAL,
+            _ => RetryBudget::STANDARD,
+        }
+    }
+}
+
 impl From<WorkerEvent> for NodeEvent {
     fn from(event: WorkerEvent) -> Self {
         NodeEvent::WorkerEvent(event)
@@ -192,8 +202,8 @@ pub struct Coordinator {
 
 impl Coordinator {
     pub async fn new(registration: Registration) -> Result<Self> {
-        let client = Client::new();
-        let token = registration.config.credentials.access_token().await?;
+        let client = reqwest_retry::proxy::client();
+        let token = registration.config.access_token().await?;
 
         Ok(Self {
             client,
@@ -214,8 +224,9 @@ impl Coordinator {
         let url = self.registration.dynamic_config.commands_url.clone();
         let request = self.client.get(url).json(&request);
 
+        // Commands are polled again soon, if this poll fails.
         let pending: PendingNodeCommand = self
-            .send_request(request)
+            .send_request(request, RetryBudget::FAST)
             .await
             .context("PollCommands")
             .map_err(PollCommandError::RequestFailed)?
@@ -233,7 +244,7 @@ impl Coordinator {
             let url = self.registration.dynamic_config.commands_url.clone();
             let request = self.client.delete(url).json(&request);
 
-            self.send_request(request)
+            self.send_request(request, RetryBudget::STANDARD)
                 .await
                 .context("ClaimCommand")
                 .map_err(PollCommandError::ClaimFailed)?;
@@ -245,6 +256,7 @@ impl Coordinator {
     }
 
     pub async fn emit_event(&self, event: NodeEvent) -> Result<()> {
+        let budget = event.retry_budget();
         let envelope = NodeEventEnvelope {
             event,
             machine_id: self.registration.machine_id,
@@ -253,7 +265,9 @@ impl Coordinator {
         let url = self.registration.dynamic_config.events_url.clone();
         let request = self.client.post(url).json(&envelope);
 
-        self.send_request(request).await.context("EmitEvent")?;
+        self.send_request(request, budget)
+            .await
+            .context("EmitEvent")?;
 
         Ok(())
     }
@@ -277,7 +291,7 @@ impl Coordinator {
         let request = self.client.post(url).json(&envelope);
 
         let can_schedule: CanSchedule = self
-            .send_request(request)
+            .send_request(request, RetryBudget::STANDARD)
             .await
             .context("CanSchedule")?
             .json()
@@ -287,30 +301,38 @@ impl Coordinator {
     }
 
     async fn get_token(&self) -> Result<AccessToken> {
-        let token = self.token.read().await;
+        let token = self.token.read().await.clone();
+        if !token.expires_soon() {
+            return Ok(token);
+        }
+
+        debug!("access token expiring, renewing");
+        let mut token = self.token.write().await;
+
+        // Unless another request already renewed it.
+        if token.expires_soon() {
+            *token = self.registration.config.access_token().await?;
+        }
+
         Ok(token.clone())
     }
 
     async fn refresh_token(&self) -> Result<AccessToken> {
         let mut token = self.token.write().await;
-        *token = self.registration.config.credentials.access_token().await?;
+        *token = self.registration.config.refresh_access_token().await?;
         Ok(token.clone())
     }
 
-    async fn send_request(&self, request: RequestBuilder) -> Result<Response> {
+    async fn send_request(&self, request: RequestBuilder, budget: RetryBudget) -> Result<Response> {
+        let policy = RetryPolicy::transient()
+            .with_budget(budget)
+            .with_circuit_breaker(circuit::service().clone());
         let token = self.get_token().await?;
         let mut response = request
             .try_clone()
             .ok_or_else(|| anyhow!("unable to clone request"))?
             .bearer_auth(token.secret().expose_ref())
-            .send_retry(
-                |code| match code {
-                    StatusCode::UNAUTHORIZED => RetryCheck::Fail,
-                    _ => RetryCheck::Retry,
-                },
-                DEFAULT_RETRY_PERIOD,
-                MAX_RETRY_ATTEMPTS,
-            )
+            .send_retry_policy(&policy)
             .await
             .context("Coordinator.send");
 
@@ -325,7 +347,7 @@ impl Coordinator {
             // And try one more time.
             response = request
                 .bearer_auth(token.secret().expose_ref())
-                .send_retry_default()
+                .send_retry_policy(&policy)
                 .await
                 .context("Coordinator.send after refreshing access token");
         };
@@ -349,3 +371,41 @@ impl Coordinator {
 
 #[cfg(test)]
 pub mod double;
+
+#[cfg(test)]
+mod tests {
+    use onefuzz::process::ExitStatus;
+
+    use super::*;
+
+    #[test]
+    fn test_final_events_have_critical_budget() {
+        let done = StateUpdateEvent::Done {
+            error: None,
+            script_output: None,
+        };
+        assert_eq!(NodeEvent::from(done).retry_budget(), RetryBudget::CRITICAL);
+
+        let worker_done = WorkerEvent::Done {
+            task_id: Uuid::new_v4(),
+            exit_status: ExitStatus {
+                code: Some(0),
+                signal: None,
+                success: true,
+            },
+            stderr: String::new(),
+            stdout: String::new(),
+        };
+        assert_eq!(
+            NodeEvent::from(worker_done).retry_budget(),
+            RetryBudget::CRITICAL
+        );
+
+        let busy = NodeEvent::from(StateUpdateEvent::Busy);
+        assert_eq!(busy.retry_budget(), RetryBudget::STANDARD);
+        let running = NodeEvent::from(WorkerEvent::Running {
+            task_id: Uuid::new_v4(),
+        });
+        assert_eq!(running.retry_budget(), RetryBudget::STANDARD);
+    }
+}
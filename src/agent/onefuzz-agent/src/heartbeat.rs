@@ -1,10 +1,12 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
+use crate::host_stats::{HostSampler, HostStats};
 use anyhow::Result;
-use onefuzz::heartbeat::HeartbeatClient;
+use onefuzz::heartbeat::{alert::DeliveryAlert, HeartbeatClient};
 use reqwest::Url;
 use serde::{self, Deserialize, Serialize};
+use std::sync::Mutex;
 use uuid::Uuid;
 
 #[derive(Debug, Deserialize, Serialize, Hash, Eq, PartialEq, Clone)]
@@ -13,17 +15,22 @@ pub enum HeartbeatData {
     MachineAlive,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
 struct Heartbeat {
     node_id: Uuid,
     machine_name: String,
     data: Vec<HeartbeatData>,
+
+    /// The resource utilization of the host, as of the heartbeat. Stats which
+    /// weren't sampled are omitted.
+    #[serde(flatten)]
+    host_stats: HostStats,
 }
 
-#[derive(Clone)]
 pub struct AgentContext {
     node_id: Uuid,
     machine_name: String,
+    sampler: Mutex<HostSampler>,
 }
 
 pub type AgentHeartbeatClient = HeartbeatClient<AgentContext, HeartbeatData>;
@@ -37,18 +44,24 @@ pub async fn init_agent_heartbeat(
         AgentContext {
             node_id,
             machine_name,
+            sampler: Mutex::new(HostSampler::new(onefuzz::fs::onefuzz_root().ok())),
         },
         queue_url,
         None,
         None,
+        DeliveryAlert::from_env("agent"),
         |context| async move {
             let data = HeartbeatClient::drain_current_messages(context.clone());
-            let _ = context
-                .queue_client
-                .enqueue(Heartbeat {
+            let host_stats = match context.state.sampler.lock() {
+                Ok(mut sampler) => sampler.sample(),
+                Err(_) => HostStats::default(),
+            };
+            context
+                .send(Heartbeat {
                     node_id: context.state.node_id,
                     data,
                     machine_name: context.state.machine_name.clone(),
+                    host_stats,
                 })
                 .await;
         },
@@ -86,3 +99,51 @@ impl HeartbeatSender for Option<AgentHeartbeatClient> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_heartbeat_round_trip() -> Result<()> {
+        let mut heartbeat = Heartbeat {
+            node_id: Uuid::nil(),
+            machine_name: "node".into(),
+            data: vec![HeartbeatData::MachineAlive],
+            host_stats: HostStats::default(),
+        };
+
+        // Without host stats, as before they were sampled.
+        let value = serde_json::to_value(&heartbeat)?;
+        assert_eq!(
+            value,
+            json!({
+                "node_id": Uuid::nil(),
+                "machine_name": "node",
+                "data": [{ "type": "MachineAlive" }],
+            })
+        );
+        assert_eq!(serde_json::from_value::<Heartbeat>(value)?, heartbeat);
+
+        heartbeat.host_stats = HostStats {
+            cpu_percent: Some(50.0),
+            free_disk_bytes: Some(1 << 30),
+            ..HostStats::default()
+        };
+        let value = serde_json::to_value(&heartbeat)?;
+        assert_eq!(
+            value,
+            json!({
+                "node_id": Uuid::nil(),
+                "machine_name": "node",
+                "data": [{ "type": "MachineAlive" }],
+                "cpu_percent": 50.0,
+                "free_disk_bytes": 1 << 30,
+            })
+        );
+        assert_eq!(serde_json::from_value::<Heartbeat>(value)?, heartbeat);
+
+        Ok(())
+    }
+}
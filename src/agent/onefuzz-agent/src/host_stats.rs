@@ -0,0 +1,396 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Sampling of the resource utilization of the host, sent with node
+//! heartbeats.
+//!
+//! Each sample only reads system-wide counters, without enumerating processes,
+//! so that it stays cheap on busy nodes.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// The resource utilization of the host. Each value is unset if it couldn't
+/// be sampled on the host.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct HostStats {
+    /// CPU utilization of all cores since the previous sample, in percent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_percent: Option<f64>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub available_memory_bytes: Option<u64>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_memory_bytes: Option<u64>,
+
+    /// Free space of the volume of the working directory of the agent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub free_disk_bytes: Option<u64>,
+
+    /// The 1, 5 and 15 minute load averages. Only sampled on Linux.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub load_average: Option<[f64; 3]>,
+
+    /// Open handles of the host: allocated file handles on Linux, or kernel
+    /// object handles on Windows.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub handle_count: Option<u64>,
+}
+
+/// Samples the resource utilization of the host, as of each heartbeat.
+pub struct HostSampler {
+    work_dir: Option<PathBuf>,
+
+    // CPU times as of the previous sample, from which utilization is measured.
+    cpu_times: Option<CpuTimes>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct CpuTimes {
+    busy: u64,
+    total: u64,
+}
+
+impl HostSampler {
+    /// A sampler of the host, and of the free space of the volume of
+    /// `work_dir`, if set.
+    pub fn new(work_dir: Option<PathBuf>) -> Self {
+        let mut sampler = Self {
+            work_dir,
+            cpu_times: None,
+        };
+
+        // Start measuring CPU utilization, for the first sample.
+        sampler.cpu_times = sample(cpu_times, "CPU times");
+        sampler
+    }
+
+    pub fn sample(&mut self) -> HostStats {
+        let cpu_times = sample(cpu_times, "CPU times");
+        let cpu_percent = match (self.cpu_times, cpu_times) {
+            (Some(previous), Some(current)) => cpu_percent(previous, current),
+            _ => None,
+        };
+        if cpu_times.is_some() {
+            self.cpu_times = cpu_times;
+        }
+
+        let stats = HostStats {
+            cpu_percent,
+            available_memory_bytes: sample(available_memory_bytes, "available memory"),
+            total_memory_bytes: sample(total_memory_bytes, "total memory"),
+            free_disk_bytes: self
+                .work_dir
+                .as_deref()
+                .and_then(|dir| sample(|| free_disk_bytes(dir), "free disk space")),
+            load_average: sample(load_average, "load average"),
+            handle_count: sample(handle_count, "handle count"),
+        };
+        debug!("sampled host stats: {:?}", stats);
+
+        stats
+    }
+}
+
+// The result of `f`, if it could be sampled. Errors are expected on platforms
+// without the stat, so are only logged at debug level.
+fn sample<T>(f: impl FnOnce() -> Result<T>, name: &str) -> Option<T> {
+    match f() {
+        Ok(value) => Some(value),
+        Err(err) => {
+            debug!("unable to sample {}: {:?}", name, err);
+            None
+        }
+    }
+}
+
+fn cpu_percent(previous: CpuTimes, current: CpuTimes) -> Option<f64> {
+    let total = current.total.checked_sub(previous.total)?;
+    let busy = current.busy.checked_sub(previous.busy)?;
+    if total == 0 {
+        return None;
+    }
+
+    Some((100.0 * busy as f64 / total as f64).clamp(0.0, 100.0))
+}
+
+#[cfg(any(target_os = "linux", target_os = "windows"))]
+fn available_memory_bytes() -> Result<u64> {
+    onefuzz::memory::available_bytes()
+}
+
+#[cfg(any(target_os = "linux", target_os = "windows"))]
+fn total_memory_bytes() -> Result<u64> {
+    onefuzz::memory::total_bytes()
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+fn available_memory_bytes() -> Result<u64> {
+    bail!("unsupported platform")
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+fn total_memory_bytes() -> Result<u64> {
+    bail!("unsupported platform")
+}
+
+#[cfg(target_os = "linux")]
+fn cpu_times() -> Result<CpuTimes> {
+    let stat = std::fs::read_to_string("/proc/stat")?;
+    parse_cpu_times(&stat)
+}
+
+// The aggregate times of the `cpu` line of `/proc/stat`. Idle and I/O wait
+// times are not busy.
+#[cfg(target_os = "linux")]
+fn parse_cpu_times(stat: &str) -> Result<CpuTimes> {
+    let line = stat
+        .lines()
+        .find(|line| line.starts_with("cpu "))
+        .ok_or_else(|| format_err!("`cpu` not found in `/proc/stat`"))?;
+
+    let times = line
+        .split_whitespace()
+        .skip(1)
+        .map(str::parse)
+        .collect::<Result<Vec<u64>, _>>()?;
+    if times.len() < 4 {
+        bail!("invalid `cpu` line in `/proc/stat`: {}", line);
+    }
+
+    // Guest times are included in user times, so aren't counted again.
+    let total: u64 = times.iter().take(8).sum();
+    let idle = times[3] + times.get(4).copied().unwrap_or_default();
+
+    Ok(CpuTimes {
+        busy: total - idle,
+        total,
+    })
+}
+
+#[cfg(target_os = "linux")]
+fn load_average() -> Result<[f64; 3]> {
+    let loadavg = std::fs::read_to_string("/proc/loadavg")?;
+    parse_load_average(&loadavg)
+}
+
+#[cfg(target_os = "linux")]
+fn parse_load_average(loadavg: &str) -> Result<[f64; 3]> {
+    let averages = loadavg
+        .split_whitespace()
+        .take(3)
+        .map(str::parse)
+        .collect::<Result<Vec<f64>, _>>()?;
+
+    averages
+        .try_into()
+        .map_err(|_| format_err!("invalid `/proc/loadavg`: {}", loadavg))
+}
+
+#[cfg(target_os = "linux")]
+fn handle_count() -> Result<u64> {
+    // The allocated, unused and maximum file handles.
+    let file_nr = std::fs::read_to_string("/proc/sys/fs/file-nr")?;
+    let allocated = file_nr
+        .split_whitespace()
+        .next()
+        .ok_or_else(|| format_err!("invalid `/proc/sys/fs/file-nr`: {}", file_nr))?;
+
+    Ok(allocated.parse()?)
+}
+
+#[cfg(target_family = "unix")]
+fn free_disk_bytes(dir: &Path) -> Result<u64> {
+    let stat = nix::sys::statvfs::statvfs(dir)?;
+
+    // The widths of the fields vary by platform.
+    #[allow(clippy::useless_conversion)]
+    let bytes = u64::from(stat.blocks_available()) * u64::from(stat.fragment_size());
+
+    Ok(bytes)
+}
+
+#[cfg(target_os = "windows")]
+fn cpu_times() -> Result<CpuTimes> {
+    use anyhow::Context;
+    use windows::Win32::{Foundation::FILETIME, System::Threading::GetSystemTimes};
+
+    let mut idle = FILETIME::default();
+    let mut kernel = FILETIME::default();
+    let mut user = FILETIME::default();
+    unsafe {
+        GetSystemTimes(
+            Some(&mut idle as *mut _),
+            Some(&mut kernel as *mut _),
+            Some(&mut user as *mut _),
+        )
+    }
+    .ok()
+    .context("error querying system times")?;
+
+    let ticks =
+        |time: FILETIME| (u64::from(time.dwHighDateTime) << 32) | u64::from(time.dwLowDateTime);
+
+    // Kernel times include idle times.
+    let total = ticks(kernel) + ticks(user);
+    Ok(CpuTimes {
+        busy: total.saturating_sub(ticks(idle)),
+        total,
+    })
+}
+
+#[cfg(target_os = "windows")]
+fn load_average() -> Result<[f64; 3]> {
+    bail!("load averages are not measured on Windows")
+}
+
+#[cfg(target_os = "windows")]
+fn handle_count() -> Result<u64> {
+    use anyhow::Context;
+    use windows::Win32::System::ProcessStatus::{GetPerformanceInfo, PERFORMANCE_INFORMATION};
+
+    let mut info = PERFORMANCE_INFORMATION::default();
+    let size = std::mem::size_of::<PERFORMANCE_INFORMATION>() as u32;
+    unsafe { GetPerformanceInfo(&mut info, size) }
+        .ok()
+        .context("error querying performance information")?;
+
+    Ok(u64::from(info.HandleCount))
+}
+
+#[cfg(target_os = "windows")]
+fn free_disk_bytes(dir: &Path) -> Result<u64> {
+    use anyhow::Context;
+    use windows::{core::HSTRING, Win32::Storage::FileSystem::GetDiskFreeSpaceExW};
+
+    let mut available = 0u64;
+    unsafe {
+        GetDiskFreeSpaceExW(
+            &HSTRING::from(dir.as_os_str()),
+            Some(&mut available as *mut _),
+            None,
+            None,
+        )
+    }
+    .ok()
+    .context("error querying free disk space")?;
+
+    Ok(available)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+fn cpu_times() -> Result<CpuTimes> {
+    bail!("unsupported platform")
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+fn load_average() -> Result<[f64; 3]> {
+    bail!("unsupported platform")
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+fn handle_count() -> Result<u64> {
+    bail!("unsupported platform")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cpu_percent() {
+        let times = |busy, total| CpuTimes { busy, total };
+
+        assert_eq!(cpu_percent(times(10, 100), times(60, 200)), Some(50.0));
+        assert_eq!(cpu_percent(times(10, 100), times(10, 200)), Some(0.0));
+        assert_eq!(cpu_percent(times(10, 100), times(10, 100)), None);
+
+        // Counters which went backwards, as after a reset, aren't measured.
+        assert_eq!(cpu_percent(times(10, 100), times(5, 50)), None);
+    }
+
+    #[test]
+    fn test_host_stats_round_trip() -> Result<()> {
+        let empty = HostStats::default();
+        assert_eq!(serde_json::to_value(&empty)?, serde_json::json!({}));
+        assert_eq!(serde_json::from_str::<HostStats>("{}")?, empty);
+
+        let stats = HostStats {
+            cpu_percent: Some(12.5),
+            available_memory_bytes: Some(1 << 30),
+            total_memory_bytes: Some(1 << 32),
+            free_disk_bytes: Some(1 << 34),
+            load_average: Some([0.5, 0.25, 0.125]),
+            handle_count: Some(1024),
+        };
+        let value = serde_json::to_value(&stats)?;
+        assert_eq!(value["load_average"], serde_json::json!([0.5, 0.25, 0.125]));
+        assert_eq!(serde_json::from_value::<HostStats>(value)?, stats);
+
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_parse_proc_files() -> Result<()> {
+        let stat = "cpu  100 10 50 800 40 0 0 0 0 0\ncpu0 50 5 25 400 20 0 0 0 0 0\n";
+        assert_eq!(
+            parse_cpu_times(stat)?,
+            CpuTimes {
+                busy: 160,
+                total: 1000
+            }
+        );
+        assert!(parse_cpu_times("intr 1 2 3\n").is_err());
+
+        assert_eq!(
+            parse_load_average("0.50 1.25 2.00 1/234 5678\n")?,
+            [0.5, 1.25, 2.0]
+        );
+        assert!(parse_load_average("0.50\n").is_err());
+
+        Ok(())
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "windows"))]
+    #[test]
+    fn test_sample_host() {
+        let mut sampler = HostSampler::new(Some(std::env::temp_dir()));
+
+        // Burn some CPU, so that utilization is measured over some ticks.
+        let start = std::time::Instant::now();
+        while start.elapsed() < std::time::Duration::from_millis(50) {
+            std::hint::black_box(start.elapsed());
+        }
+        let stats = sampler.sample();
+
+        if let Some(cpu_percent) = stats.cpu_percent {
+            assert!((0.0..=100.0).contains(&cpu_percent), "{stats:?}");
+        }
+
+        let available = stats.available_memory_bytes.expect("available memory");
+        let total = stats.total_memory_bytes.expect("total memory");
+        assert!(total > 0, "{stats:?}");
+        assert!(
+            available <= total || cfg!(target_os = "windows"),
+            "{stats:?}"
+        );
+
+        assert!(stats.free_disk_bytes.is_some(), "{stats:?}");
+        assert!(
+            stats.handle_count.is_some_and(|count| count > 0),
+            "{stats:?}"
+        );
+
+        if cfg!(target_os = "linux") {
+            let load_average = stats.load_average.expect("load average");
+            assert!(load_average.iter().all(|load| *load >= 0.0), "{stats:?}");
+        }
+
+        // Without a working directory, free disk space isn't sampled.
+        assert_eq!(HostSampler::new(None).sample().free_disk_bytes, None);
+    }
+}
@@ -20,6 +20,7 @@ use std::process::{Command, Stdio};
 
 use anyhow::{Context, Result};
 use clap::{ArgAction, Parser};
+use onefuzz::cloud::Cloud;
 use onefuzz::machine_id::MachineIdentity;
 use onefuzz::process::ExitStatus;
 use onefuzz_telemetry::{self as telemetry, EventData, Role};
@@ -35,11 +36,13 @@ pub mod debug;
 pub mod done;
 pub mod failure;
 pub mod heartbeat;
+pub mod host_stats;
 pub mod log_uploader;
 pub mod panic;
 pub mod reboot;
 pub mod scheduler;
 pub mod setup;
+pub mod spans;
 pub mod validations;
 pub mod work;
 pub mod worker;
@@ -77,6 +80,9 @@ struct RunOpt {
 
 fn main() -> Result<()> {
     env_logger::init();
+    if let Err(err) = tracing::subscriber::set_global_default(spans::SpanSubscriber::new()) {
+        warn!("unable to set tracing subscriber: {}", err);
+    }
 
     let opt = Opt::parse();
 
@@ -191,6 +197,7 @@ fn run(opt: RunOpt) -> Result<()> {
     }
     let rt = tokio::runtime::Runtime::new()?;
     let reset_lock = opt.reset_node_lock;
+    let config_path = opt.config_path.clone();
     let config = rt.block_on(load_config(opt));
 
     // We can't send telemetry, because we couldn't get a telemetry key from the config.
@@ -212,7 +219,7 @@ fn run(opt: RunOpt) -> Result<()> {
         return Ok(());
     }
 
-    let result = rt.block_on(run_agent(config, reset_lock));
+    let result = rt.block_on(run_agent(config, config_path, reset_lock));
 
     if let Err(err) = &result {
         error!("error running supervisor agent: {:?}", err);
@@ -242,6 +249,23 @@ async fn load_config(opt: RunOpt) -> Result<StaticConfig> {
         None => StaticConfig::from_env()?,
     };
 
+    // Set before any requests, which may need them, and inherited by tasks.
+    match &config.http_timeouts {
+        Some(timeouts) => reqwest_retry::timeout::set_timeouts(timeouts.timeouts()),
+        None => reqwest_retry::timeout::set_timeouts_from_env()?,
+    }
+    match &config.proxy {
+        Some(proxy) => reqwest_retry::proxy::set_proxy(
+            proxy
+                .settings(
+                    config.managed_identity.as_ref(),
+                    &config.cloud.environment(),
+                )
+                .await?,
+        )?,
+        None => reqwest_retry::proxy::set_proxy_from_env()?,
+    }
+
     init_telemetry(&config).await;
 
     Ok(config)
@@ -298,7 +322,11 @@ async fn check_existing_worksets(coordinator: &mut coordinator::Coordinator) ->
     Ok(())
 }
 
-async fn run_agent(config: StaticConfig, reset_node: bool) -> Result<()> {
+async fn run_agent(
+    config: StaticConfig,
+    config_path: Option<PathBuf>,
+    reset_node: bool,
+) -> Result<()> {
     telemetry::set_property(EventData::InstanceId(config.instance_id));
     telemetry::set_property(EventData::MachineId(config.machine_identity.machine_id));
     telemetry::set_property(EventData::Version(env!("ONEFUZZ_VERSION").to_string()));
@@ -308,6 +336,15 @@ async fn run_agent(config: StaticConfig, reset_node: bool) -> Result<()> {
         telemetry::set_property(EventData::ScalesetId(scaleset_name.to_string()));
     }
 
+    if let Some(identity) = &config.managed_identity {
+        info!("using user-assigned managed identity: {}", identity);
+        telemetry::set_property(EventData::ManagedIdentity(identity.to_string()));
+    }
+
+    if config.cloud != Cloud::Public {
+        info!("using the {} cloud", config.cloud);
+    }
+
     let registration = match config::Registration::load_existing(config.clone()).await {
         Ok(registration) => registration,
         Err(_) => {
@@ -348,7 +385,7 @@ async fn run_agent(config: StaticConfig, reset_node: bool) -> Result<()> {
         ),
         None => None,
     };
-    let agent = agent::Agent::new(
+    let mut agent = agent::Agent::new(
         Box::new(coordinator),
         Box::new(reboot),
         scheduler,
@@ -362,6 +399,12 @@ async fn run_agent(config: StaticConfig, reset_node: bool) -> Result<()> {
         config.machine_identity.machine_id,
     );
 
+    // Settings can only be reloaded from a config file, not the environment.
+    if let Some(config_path) = config_path {
+        let config_watcher = config::ConfigWatcher::new(config_path, config.clone()).await?;
+        agent = agent.config_watcher(config_watcher);
+    }
+
     info!("running agent");
 
     agent.run().await?;
@@ -377,4 +420,16 @@ async fn init_telemetry(config: &StaticConfig) {
         config.microsoft_telemetry_key.clone(),
     )
     .await;
+
+    if let Err(err) = telemetry::set_otlp_exporter_from_env().await {
+        warn!("unable to export telemetry to OTLP collector: {:?}", err);
+    }
+
+    if let Err(err) = telemetry::set_file_sink_from_env() {
+        warn!("unable to write telemetry to local files: {:?}", err);
+    }
+
+    if let Err(err) = telemetry::set_sampling_from_env() {
+        warn!("unable to set telemetry sampling: {:?}", err);
+    }
 }
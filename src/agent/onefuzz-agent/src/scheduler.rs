@@ -248,6 +248,10 @@ impl State<PendingReboot> {
 }
 
 impl State<Ready> {
+    pub fn work_set(&self) -> &WorkSet {
+        &self.ctx.work_set
+    }
+
     pub async fn run(self, machine_id: uuid::Uuid) -> Result<State<Busy>> {
         let mut workers = vec![];
         let setup_dir = self.ctx.work_set.setup_dir()?;
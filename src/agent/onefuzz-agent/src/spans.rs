@@ -0,0 +1,447 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! A `tracing` subscriber for the spans of the agent, such as those of the
+//! phases of its update loop.
+//!
+//! Spans are logged as they start and stop, indented by their depth, so that
+//! the log lines of concurrent activities can be grouped. Spans at the `INFO`
+//! level or above are also reported as `span_start` and `span_stop` events,
+//! the latter with their duration. Events recorded with the `tracing` macros
+//! are logged with the path of their span, alongside those of the `log`
+//! macros, which are unaffected.
+
+use std::collections::HashMap;
+use std::fmt::{self, Display, Write};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant};
+
+use onefuzz_telemetry::{
+    Event::{span_start, span_stop},
+    EventData,
+};
+use tracing::{
+    field::{Field, Visit},
+    span,
+    subscriber::Interest,
+    Event, Level, Metadata, Subscriber,
+};
+use uuid::Uuid;
+
+/// A span which has stopped.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClosedSpan {
+    pub name: &'static str,
+
+    /// The names of the span and its ancestors, from the root, such as
+    /// `update/claim`.
+    pub path: String,
+
+    pub fields: Vec<(&'static str, String)>,
+
+    /// The time between the creation of the span and when it stopped.
+    pub duration: Duration,
+}
+
+/// Logs and reports the spans of the agent.
+#[derive(Default)]
+pub struct SpanSubscriber {
+    spans: Mutex<Spans>,
+
+    // Receives the spans which have stopped, if set.
+    closed: Option<Arc<Mutex<Vec<ClosedSpan>>>>,
+}
+
+#[derive(Default)]
+struct Spans {
+    next_id: u64,
+    spans: HashMap<u64, SpanState>,
+
+    // The spans entered by each thread, innermost last.
+    entered: HashMap<ThreadId, Vec<u64>>,
+}
+
+struct SpanState {
+    metadata: &'static Metadata<'static>,
+    path: String,
+    depth: usize,
+    fields: Vec<(&'static str, String)>,
+    started: Instant,
+    refs: usize,
+}
+
+impl SpanSubscriber {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A subscriber which also collects the spans which have stopped.
+    #[cfg(test)]
+    pub fn capturing() -> (Self, Arc<Mutex<Vec<ClosedSpan>>>) {
+        let closed = Arc::new(Mutex::new(vec![]));
+        let subscriber = Self {
+            closed: Some(closed.clone()),
+            ..Self::default()
+        };
+        (subscriber, closed)
+    }
+
+    fn spans(&self) -> std::sync::MutexGuard<'_, Spans> {
+        // The spans are only updated by infallible operations, so are
+        // consistent even if a thread panicked while holding the lock.
+        self.spans.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl Spans {
+    fn current(&self) -> Option<u64> {
+        self.entered
+            .get(&thread::current().id())
+            .and_then(|stack| stack.last())
+            .copied()
+    }
+}
+
+impl Subscriber for SpanSubscriber {
+    // Events are enabled by the log level, which may be reloaded, so mustn't
+    // be cached.
+    fn register_callsite(&self, _metadata: &'static Metadata<'static>) -> Interest {
+        Interest::sometimes()
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.is_span() || to_log_level(metadata.level()) <= log::max_level()
+    }
+
+    fn new_span(&self, attrs: &span::Attributes<'_>) -> span::Id {
+        let mut fields = FieldVisitor::default();
+        attrs.record(&mut fields);
+
+        let metadata = attrs.metadata();
+        let mut spans = self.spans();
+        let parent = if let Some(parent) = attrs.parent() {
+            Some(parent.into_u64())
+        } else if attrs.is_contextual() {
+            spans.current()
+        } else {
+            None
+        };
+        let (path, depth) = match parent.and_then(|parent| spans.spans.get(&parent)) {
+            Some(parent) => (
+                format!("{}/{}", parent.path, metadata.name()),
+                parent.depth + 1,
+            ),
+            None => (metadata.name().to_owned(), 0),
+        };
+
+        spans.next_id += 1;
+        let id = spans.next_id;
+        drop(spans);
+
+        let span = SpanState {
+            metadata,
+            path,
+            depth,
+            fields: fields.0,
+            started: Instant::now(),
+            refs: 1,
+        };
+        span.started();
+        self.spans().spans.insert(id, span);
+
+        span::Id::from_u64(id)
+    }
+
+    fn record(&self, span: &span::Id, values: &span::Record<'_>) {
+        let mut fields = FieldVisitor::default();
+        values.record(&mut fields);
+
+        if let Some(span) = self.spans().spans.get_mut(&span.into_u64()) {
+            for (name, value) in fields.0 {
+                match span.fields.iter_mut().find(|(n, _)| *n == name) {
+                    Some(field) => field.1 = value,
+                    None => span.fields.push((name, value)),
+                }
+            }
+        }
+    }
+
+    fn record_follows_from(&self, _span: &span::Id, _follows: &span::Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = FieldVisitor::default();
+        event.record(&mut fields);
+
+        let spans = self.spans();
+        let path = spans
+            .current()
+            .and_then(|id| spans.spans.get(&id))
+            .map(|span| span.path.clone());
+        drop(spans);
+
+        let metadata = event.metadata();
+        let message = fields.message();
+        let fields = format_fields(&fields.0);
+        match path {
+            Some(path) => log::log!(
+                target: metadata.target(),
+                to_log_level(metadata.level()),
+                "{path}: {message}{fields}"
+            ),
+            None => log::log!(
+                target: metadata.target(),
+                to_log_level(metadata.level()),
+                "{message}{fields}"
+            ),
+        }
+    }
+
+    fn enter(&self, span: &span::Id) {
+        self.spans()
+            .entered
+            .entry(thread::current().id())
+            .or_default()
+            .push(span.into_u64());
+    }
+
+    fn exit(&self, span: &span::Id) {
+        let mut spans = self.spans();
+        let thread = thread::current().id();
+        if let Some(stack) = spans.entered.get_mut(&thread) {
+            if let Some(index) = stack.iter().rposition(|id| *id == span.into_u64()) {
+                stack.remove(index);
+            }
+            if stack.is_empty() {
+                spans.entered.remove(&thread);
+            }
+        }
+    }
+
+    fn clone_span(&self, span: &span::Id) -> span::Id {
+        if let Some(span) = self.spans().spans.get_mut(&span.into_u64()) {
+            span.refs += 1;
+        }
+        span.clone()
+    }
+
+    fn try_close(&self, span: span::Id) -> bool {
+        let mut spans = self.spans();
+        let id = span.into_u64();
+
+        let Some(state) = spans.spans.get_mut(&id) else {
+            return false;
+        };
+        state.refs -= 1;
+        if state.refs > 0 {
+            return false;
+        }
+        let Some(state) = spans.spans.remove(&id) else {
+            return false;
+        };
+        drop(spans);
+
+        let closed = state.stopped();
+        if let Some(sink) = &self.closed {
+            sink.lock()
+                .unwrap_or_else(|err| err.into_inner())
+                .push(closed);
+        }
+
+        true
+    }
+
+    fn current_span(&self) -> span::Current {
+        let spans = self.spans();
+        match spans
+            .current()
+            .and_then(|id| spans.spans.get(&id).map(|span| (id, span.metadata)))
+        {
+            Some((id, metadata)) => span::Current::new(span::Id::from_u64(id), metadata),
+            None => span::Current::none(),
+        }
+    }
+}
+
+impl SpanState {
+    fn started(&self) {
+        log::log!(
+            target: self.metadata.target(),
+            to_log_level(self.metadata.level()),
+            "{:indent$}> {}{}",
+            "",
+            self.metadata.name(),
+            format_fields(&self.fields),
+            indent = self.depth * 2,
+        );
+
+        if self.is_reported() {
+            onefuzz_telemetry::track_event(&span_start, &self.event_data(None));
+        }
+    }
+
+    fn stopped(self) -> ClosedSpan {
+        let duration = self.started.elapsed();
+        log::log!(
+            target: self.metadata.target(),
+            to_log_level(self.metadata.level()),
+            "{:indent$}< {} done in {}ms",
+            "",
+            self.metadata.name(),
+            duration.as_millis(),
+            indent = self.depth * 2,
+        );
+
+        if self.is_reported() {
+            onefuzz_telemetry::track_event(&span_stop, &self.event_data(Some(duration)));
+        }
+
+        ClosedSpan {
+            name: self.metadata.name(),
+            path: self.path,
+            fields: self.fields,
+            duration,
+        }
+    }
+
+    // Verbose spans, such as those of each iteration of the update loop, are
+    // only logged.
+    fn is_reported(&self) -> bool {
+        *self.metadata.level() <= Level::INFO
+    }
+
+    fn event_data(&self, duration: Option<Duration>) -> Vec<EventData> {
+        let mut data = vec![EventData::Span(self.path.clone())];
+
+        for (name, value) in &self.fields {
+            match (*name, value.parse::<Uuid>()) {
+                ("job_id", Ok(id)) => data.push(EventData::JobId(id)),
+                ("task_id", Ok(id)) => data.push(EventData::TaskId(id)),
+                _ => {}
+            }
+        }
+        if !self.fields.is_empty() {
+            let fields = format_fields(&self.fields);
+            data.push(EventData::SpanFields(fields.trim_start().to_owned()));
+        }
+        if let Some(duration) = duration {
+            data.push(EventData::DurationMs(duration.as_millis() as u64));
+        }
+
+        data
+    }
+}
+
+/// Ids as the value of a span field, such as the `task_id` of a work set.
+pub fn ids(ids: impl IntoIterator<Item = impl Display>) -> String {
+    let ids: Vec<String> = ids.into_iter().map(|id| id.to_string()).collect();
+    ids.join(",")
+}
+
+#[derive(Default)]
+struct FieldVisitor(Vec<(&'static str, String)>);
+
+impl FieldVisitor {
+    // Take the message of an event from its fields.
+    fn message(&mut self) -> String {
+        match self.0.iter().position(|(name, _)| *name == "message") {
+            Some(index) => self.0.remove(index).1,
+            None => String::new(),
+        }
+    }
+}
+
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.push((field.name(), value.to_owned()));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.push((field.name(), format!("{value:?}")));
+    }
+}
+
+// Fields as ` name=value` pairs.
+fn format_fields(fields: &[(&'static str, String)]) -> String {
+    let mut formatted = String::new();
+    for (name, value) in fields {
+        let _ = write!(formatted, " {name}={value}");
+    }
+    formatted
+}
+
+fn to_log_level(level: &Level) -> log::Level {
+    match *level {
+        Level::ERROR => log::Level::Error,
+        Level::WARN => log::Level::Warn,
+        Level::INFO => log::Level::Info,
+        Level::DEBUG => log::Level::Debug,
+        Level::TRACE => log::Level::Trace,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use onefuzz_telemetry::LoggingEvent;
+
+    use super::*;
+
+    #[test]
+    fn test_nested_spans() {
+        let (subscriber, closed) = SpanSubscriber::capturing();
+        tracing::subscriber::with_default(subscriber, || {
+            let outer = tracing::debug_span!("outer", state = "free");
+            let _outer = outer.enter();
+
+            let inner = tracing::info_span!("inner", task_id = %ids(["a", "b"]));
+            inner.in_scope(|| tracing::info!(count = 1, "in span"));
+            drop(inner);
+
+            // Explicit roots aren't nested.
+            tracing::info_span!(parent: None, "root").in_scope(|| {});
+        });
+
+        let closed = closed.lock().unwrap();
+        let paths: Vec<&str> = closed.iter().map(|span| span.path.as_str()).collect();
+        assert_eq!(paths, ["outer/inner", "root", "outer"]);
+        assert_eq!(closed[0].fields, [("task_id", "a,b".to_owned())]);
+        assert_eq!(closed[2].fields, [("state", "free".to_owned())]);
+        assert!(closed[2].duration >= closed[0].duration);
+    }
+
+    #[test]
+    fn test_span_events() {
+        let mut events = onefuzz_telemetry::subscribe_to_events().unwrap();
+        let (subscriber, _) = SpanSubscriber::capturing();
+        let task_id = Uuid::new_v4();
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("claim", task_id = %task_id, job_id = "a,b").in_scope(|| {});
+            tracing::debug_span!("update", task_id = %task_id).in_scope(|| {});
+        });
+
+        let mut reported = vec![];
+        while let Ok(event) = events.try_recv() {
+            if let LoggingEvent::Event(event) = event {
+                if event.data.contains(&EventData::TaskId(task_id)) {
+                    reported.push(event);
+                }
+            }
+        }
+
+        // Verbose spans aren't reported, and only single ids are reported as
+        // such.
+        let names: Vec<&str> = reported.iter().map(|event| event.event.as_str()).collect();
+        assert_eq!(names, ["span_start", "span_stop"]);
+        assert_eq!(
+            reported[0].data,
+            [
+                EventData::Span("claim".to_owned()),
+                EventData::TaskId(task_id),
+                EventData::SpanFields(format!("task_id={task_id} job_id=a,b")),
+            ]
+        );
+        assert_eq!(&reported[1].data[..3], &reported[0].data[..]);
+        assert!(matches!(reported[1].data[3], EventData::DurationMs(_)));
+    }
+}
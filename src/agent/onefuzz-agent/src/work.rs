@@ -33,6 +33,16 @@ impl WorkSet {
         self.work_units.iter().map(|w| w.task_id).collect()
     }
 
+    pub fn job_ids(&self) -> Vec<Uuid> {
+        let mut job_ids = vec![];
+        for work_unit in &self.work_units {
+            if !job_ids.contains(&work_unit.job_id) {
+                job_ids.push(work_unit.job_id);
+            }
+        }
+        job_ids
+    }
+
     pub fn context_path(machine_id: Uuid) -> Result<PathBuf> {
         Ok(onefuzz::fs::onefuzz_root()?.join(format!("workset_context-{machine_id}.json")))
     }
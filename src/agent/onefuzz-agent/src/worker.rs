@@ -12,6 +12,8 @@ use anyhow::{format_err, Context as AnyhowContext, Result};
 use downcast_rs::Downcast;
 use ipc_channel::ipc::{IpcOneShotServer, IpcReceiver, IpcSender};
 use onefuzz::{
+    config_validation,
+    heartbeat::alert,
     ipc::IpcMessageKind,
     machine_id::MachineIdentity,
     process::{ExitStatus, Output},
@@ -81,6 +83,23 @@ impl Worker {
     ) -> Result<Self> {
         let worker = match self {
             Worker::Ready(state) => {
+                if let Err(err) = state.validate() {
+                    error!(
+                        "not running task {} with invalid config: {:?}",
+                        state.work.task_id, err
+                    );
+                    let state = state.fail(err);
+                    let output = state.output();
+                    let event = WorkerEvent::Done {
+                        exit_status: output.exit_status,
+                        stderr: output.stderr,
+                        stdout: output.stdout,
+                        task_id: state.work.task_id,
+                    };
+                    events.push(event);
+                    return Ok(state.into());
+                }
+
                 let state = state.run(runner).await?;
                 let event = WorkerEvent::Running {
                     task_id: state.work.task_id,
@@ -168,6 +187,33 @@ struct LogConfig {
 }
 
 impl State<Ready> {
+    /// Check the task config before starting the task, so that every problem
+    /// with it is reported, and no task is started that is bound to fail.
+    pub fn validate(&self) -> Result<()> {
+        let work_config = self.work.config.expose_ref();
+        let config: Value = serde_json::from_str(work_config.as_str())?;
+        config_validation::validate_task_config(&config)?;
+        Ok(())
+    }
+
+    /// Finish without running the task, failing with `err`.
+    pub fn fail(self, err: anyhow::Error) -> State<Done> {
+        let output = Output {
+            exit_status: ExitStatus {
+                code: Some(1),
+                signal: None,
+                success: false,
+            },
+            stderr: format!("{err:#}"),
+            stdout: "".to_string(),
+        };
+
+        State {
+            ctx: Done { output },
+            work: self.work,
+        }
+    }
+
     pub async fn run(self, runner: &mut dyn IWorkerRunner) -> Result<State<Running>> {
         // Create and pass the server here
         let (from_agent_to_task_server, from_agent_to_task_endpoint) = IpcOneShotServer::new()?;
@@ -497,6 +543,17 @@ impl IWorkerRunner for WorkerRunner {
         let mut cmd = Command::new("onefuzz-task");
         cmd.current_dir(&working_dir);
 
+        // Tasks raise their heartbeat alerts beside those of the agent.
+        if let Ok(alerts_dir) = alert::alerts_dir() {
+            cmd.env(alert::ALERTS_DIR_ENV, alerts_dir);
+        }
+
+        // Tasks apply the proxy and timeouts of the agent to their own clients.
+        if let Some(proxy) = reqwest_retry::proxy::proxy() {
+            cmd.envs(proxy.env_vars()?);
+        }
+        cmd.envs(reqwest_retry::timeout::timeouts().env_vars());
+
         for (k, v) in &work.env {
             cmd.env(k, v);
         }
@@ -211,6 +211,43 @@ async fn test_worker_ready_update() {
     assert_eq!(events, vec![WorkerEvent::Running { task_id }]);
 }
 
+#[tokio::test]
+async fn test_worker_ready_update_invalid_config() {
+    let mut work = Fixture.work();
+    work.config = r#"{ "task_id" : "ed1eeec9-2f39-442d-9e70-563454b866c0", "target_timeout": 0 }"#
+        .to_owned()
+        .into();
+    let task_id = work.task_id;
+
+    let state = State {
+        ctx: Ready {
+            work_dir: PathBuf::default(),
+            setup_dir: PathBuf::default(),
+            extra_setup_dir: None,
+        },
+        work,
+    };
+    let worker = Worker::Ready(state);
+    let mut runner = Fixture.runner(Fixture.child_running());
+    let mut events = vec![];
+    let worker = worker.update(&mut events, &mut runner).await.unwrap();
+
+    assert!(matches!(worker, Worker::Done(..)));
+    assert_eq!(
+        events,
+        vec![WorkerEvent::Done {
+            task_id,
+            exit_status: ExitStatus {
+                code: Some(1),
+                signal: None,
+                success: false,
+            },
+            stderr: "invalid task config: `target_timeout` must be greater than 0".into(),
+            stdout: "".into(),
+        }]
+    );
+}
+
 #[tokio::test]
 async fn test_worker_running_update_running() {
     let connections = bootstrap_ipc().await.unwrap();
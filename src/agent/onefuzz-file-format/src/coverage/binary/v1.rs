@@ -18,6 +18,10 @@ pub struct BinaryCoverageJson {
 
 #[derive(Deserialize, Serialize)]
 pub struct ModuleCoverageJson {
+    /// Identity of the build of the module, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+
     pub blocks: BTreeMap<Hex, u32>,
 }
 
@@ -33,7 +37,10 @@ impl From<&BinaryCoverage> for BinaryCoverageJson {
             }
 
             let path = path.as_str().to_owned();
-            let module = ModuleCoverageJson { blocks };
+            let module = ModuleCoverageJson {
+                id: offsets.id.clone(),
+                blocks,
+            };
 
             modules.insert(path, module);
         }
@@ -49,7 +56,10 @@ impl TryFrom<BinaryCoverageJson> for BinaryCoverage {
         let mut process = BinaryCoverage::default();
 
         for (path, module) in json.modules {
-            let mut coverage = ModuleBinaryCoverage::default();
+            let mut coverage = ModuleBinaryCoverage {
+                id: module.id,
+                ..Default::default()
+            };
 
             for (hex, count) in module.blocks {
                 let offset = Offset(hex.0);
@@ -5,7 +5,15 @@ use pretty_assertions::assert_eq;
 
 use anyhow::Result;
 use coverage::binary::{BinaryCoverage, Count, FilePath, ModuleBinaryCoverage, Offset};
-use onefuzz_file_format::coverage::binary::BinaryCoverageJson;
+use onefuzz_file_format::coverage::binary::{
+    v1::BinaryCoverageJson as BinaryCoverageJsonV1, BinaryCoverageJson,
+};
+
+fn round_trip(coverage: &BinaryCoverage) -> Result<BinaryCoverage> {
+    let json = BinaryCoverageJson::from(coverage);
+    let text = serde_json::to_string(&json)?;
+    BinaryCoverage::try_from(BinaryCoverageJson::deserialize(&text)?)
+}
 
 fn expected_binary_coverage() -> Result<BinaryCoverage> {
     let main_exe_path = FilePath::new("/setup/main.exe")?;
@@ -43,3 +51,29 @@ fn test_binary_coverage_formats() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_binary_coverage_round_trip() -> Result<()> {
+    // Files written before modules were identified are rewritten unchanged,
+    // without identities.
+    for text in [
+        include_str!("files/binary-coverage.v0.json"),
+        include_str!("files/binary-coverage.v1.json"),
+    ] {
+        let coverage = BinaryCoverage::try_from(BinaryCoverageJson::deserialize(text)?)?;
+        assert_eq!(round_trip(&coverage)?, coverage);
+
+        let json = serde_json::to_value(BinaryCoverageJsonV1::from(&coverage))?;
+        for module in json.as_object().unwrap().values() {
+            assert!(module.get("id").is_none(), "{module}");
+        }
+    }
+
+    let mut coverage = expected_binary_coverage()?;
+    for (path, module) in &mut coverage.modules {
+        module.id = Some(format!("text-{}", path.file_name()));
+    }
+    assert_eq!(round_trip(&coverage)?, coverage);
+
+    Ok(())
+}
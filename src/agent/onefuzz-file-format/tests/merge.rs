@@ -0,0 +1,75 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use pretty_assertions::assert_eq;
+
+use anyhow::Result;
+use coverage::binary::BinaryCoverage;
+use coverage::merge::merge_coverage;
+use onefuzz_file_format::coverage::binary::{
+    v1::BinaryCoverageJson as BinaryCoverageJsonV1, BinaryCoverageJson,
+};
+
+fn parse(text: &str) -> Result<BinaryCoverage> {
+    let json = BinaryCoverageJson::deserialize(text)?;
+    BinaryCoverage::try_from(json)
+}
+
+fn to_json(coverage: &BinaryCoverage) -> Result<serde_json::Value> {
+    let json = BinaryCoverageJson::V1(BinaryCoverageJsonV1::from(coverage));
+    Ok(serde_json::to_value(json)?)
+}
+
+// Coverage of a target recorded by two Linux tasks at different paths, a
+// rebuild of the target at the path of one of them, and a Windows build.
+fn inputs() -> Result<Vec<BinaryCoverage>> {
+    Ok(vec![
+        parse(include_str!("files/merge/linux-1.json"))?,
+        parse(include_str!("files/merge/linux-2.json"))?,
+        parse(include_str!("files/merge/rebuilt.json"))?,
+        parse(include_str!("files/merge/windows.json"))?,
+    ])
+}
+
+#[test]
+fn test_merge_coverage() -> Result<()> {
+    let merged = merge_coverage(&inputs()?)?;
+
+    let expected: serde_json::Value =
+        serde_json::from_str(include_str!("files/merge/merged.json"))?;
+    assert_eq!(to_json(&merged)?, expected);
+
+    Ok(())
+}
+
+#[test]
+fn test_merge_coverage_keeps_builds_apart() -> Result<()> {
+    let inputs = inputs()?;
+    let merged = merge_coverage(&inputs)?;
+
+    // The rebuilt target shares a path with the first build, but none of its
+    // coverage.
+    let ids: Vec<_> = merged
+        .modules
+        .iter()
+        .filter(|(path, _)| path.file_name() == "fuzz")
+        .map(|(_, module)| module.id.as_deref())
+        .collect();
+    assert_eq!(ids, [Some("aa11"), Some("cc33")]);
+
+    // Merging is idempotent.
+    assert_eq!(merge_coverage([&merged, &merged])?, merged);
+
+    Ok(())
+}
+
+#[test]
+fn test_merge_coverage_single() -> Result<()> {
+    let coverage = parse(include_str!("files/merge/linux-1.json"))?;
+    assert_eq!(merge_coverage([&coverage])?, coverage);
+
+    let empty = merge_coverage(std::iter::empty())?;
+    assert!(empty.modules.is_empty());
+
+    Ok(())
+}
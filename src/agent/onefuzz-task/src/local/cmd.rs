@@ -4,7 +4,7 @@
 use super::{create_template, template};
 #[cfg(any(target_os = "linux", target_os = "windows"))]
 use crate::local::coverage;
-use crate::local::{common::add_common_config, libfuzzer_fuzz, tui::TerminalUi};
+use crate::local::{common::add_common_config, libfuzzer, libfuzzer_fuzz, tui::TerminalUi};
 use anyhow::{Context, Result};
 
 use clap::{Arg, ArgAction, Command};
@@ -18,6 +18,7 @@ use tokio::{select, time::timeout};
 enum Commands {
     #[cfg(any(target_os = "linux", target_os = "windows"))]
     Coverage,
+    Libfuzzer,
     LibfuzzerFuzz,
     Template,
     CreateTemplate,
@@ -54,6 +55,7 @@ pub async fn run(args: clap::ArgMatches) -> Result<()> {
         match command {
             #[cfg(any(target_os = "linux", target_os = "windows"))]
             Commands::Coverage => coverage::run(&sub_args, event_sender).await,
+            Commands::Libfuzzer => libfuzzer::run(&sub_args, event_sender).await,
             Commands::LibfuzzerFuzz => libfuzzer_fuzz::run(&sub_args, event_sender).await,
             Commands::Template => {
                 let config = sub_args
@@ -111,6 +113,7 @@ pub fn args(name: &'static str) -> Command {
         let app = match subcommand {
             #[cfg(any(target_os = "linux", target_os = "windows"))]
             Commands::Coverage => coverage::args(subcommand.into()),
+            Commands::Libfuzzer => libfuzzer::args(subcommand.into()),
             Commands::LibfuzzerFuzz => libfuzzer_fuzz::args(subcommand.into()),
             Commands::Template => Command::new("template")
                 .about("uses the template to generate a run")
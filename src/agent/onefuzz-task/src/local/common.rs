@@ -245,6 +245,14 @@ pub async fn build_local_context(
         logs: None,
         min_available_memory_mb: 0,
         tags: Default::default(),
+        allow_unknown_placeholders: false,
+        allow_env_placeholders: false,
+        target_options_shell: false,
+        qemu_user: None,
+        symbol_servers: vec![],
+        symbol_cache: None,
+        process_stats: Default::default(),
+        unknown_fields: Default::default(),
         from_agent_to_task_endpoint: "/".to_string(),
         from_task_to_agent_endpoint: "/".to_string(),
     };
@@ -283,7 +291,7 @@ impl DirectoryMonitorQueue {
 }
 
 async fn monitor_directory(queue_client: QueueClient, directory: PathBuf) -> Result<()> {
-    let mut monitor = DirectoryMonitor::new(&directory).await?;
+    let mut monitor = DirectoryMonitor::new(&directory, false).await?;
 
     while let Some(file_path) = monitor.next_file().await? {
         let file_url = Url::from_file_path(file_path).map_err(|_| anyhow!("invalid file path"))?;
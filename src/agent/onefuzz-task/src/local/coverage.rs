@@ -11,14 +11,17 @@ use crate::{
     },
     tasks::{
         config::CommonConfig,
-        coverage::generic::{Config, CoverageTask},
+        coverage::{
+            default_output_formats,
+            generic::{Config, CoverageTask, ModuleAllowlist, TargetType},
+        },
     },
 };
 use anyhow::Result;
 use async_trait::async_trait;
 use clap::{Arg, ArgAction, Command};
 use flume::Sender;
-use onefuzz::syncdir::SyncedDir;
+use onefuzz::{input_tester::InputVia, syncdir::SyncedDir};
 use schemars::JsonSchema;
 use storage_queue::QueueClient;
 
@@ -62,13 +65,23 @@ pub fn build_coverage_config(
         target_exe,
         target_env,
         target_options,
+        input_via: InputVia::default(),
         target_timeout,
+        target_type: TargetType::Native,
         coverage_filter: None,
         module_allowlist: None,
         source_allowlist: None,
+        module_denylist: vec![],
         input_queue,
         readonly_inputs,
         coverage,
+        output_formats: default_output_formats(),
+        sources: None,
+        source_path_map: vec![],
+        reprocess_inputs: false,
+        follow_children: false,
+        analysis_cache: None,
+        summary_interval: None,
         common,
     };
 
@@ -180,7 +193,9 @@ impl Template<Coverage> for Coverage {
             target_exe: self.target_exe.clone(),
             target_env: self.target_env.clone(),
             target_options: self.target_options.clone(),
+            input_via: InputVia::default(),
             target_timeout: None,
+            target_type: TargetType::Native,
             readonly_inputs: ri?,
             input_queue: input_q,
             common: CommonConfig {
@@ -189,8 +204,16 @@ impl Template<Coverage> for Coverage {
             },
             coverage_filter: None,
             coverage: context.to_monitored_sync_dir("coverage", self.coverage.clone())?,
-            module_allowlist: self.module_allowlist.clone(),
+            module_allowlist: self.module_allowlist.clone().map(ModuleAllowlist::File),
             source_allowlist: self.source_allowlist.clone(),
+            module_denylist: vec![],
+            output_formats: default_output_formats(),
+            sources: None,
+            source_path_map: vec![],
+            reprocess_inputs: false,
+            follow_children: false,
+            analysis_cache: None,
+            summary_interval: None,
         };
 
         context
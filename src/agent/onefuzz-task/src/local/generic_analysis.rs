@@ -55,6 +55,7 @@ impl Template<Analysis> for Analysis {
             analyzer_exe: self.analyzer_exe.clone(),
             analyzer_options: self.analyzer_options.clone(),
             analyzer_env: self.analyzer_env.clone(),
+            analyzers: vec![],
 
             target_exe: self.target_exe.clone(),
             target_options: self.target_options.clone(),
@@ -3,11 +3,20 @@
 
 use std::{collections::HashMap, path::PathBuf};
 
-use crate::tasks::{config::CommonConfig, utils::default_bool_true};
+use crate::tasks::{
+    config::CommonConfig,
+    report::{
+        bundle::NoReproBundles, crash_report::ReportRouting, minimize::default_minimize_timeout,
+        pool::default_report_concurrency, repro::ReproRetries,
+    },
+    utils::default_bool_true,
+};
 use anyhow::Result;
 use async_trait::async_trait;
 use futures::future::OptionFuture;
+use onefuzz::{crash_dump::CrashDumpConfig, input_tester::InputVia};
 use schemars::JsonSchema;
+use stacktrace_parser::StackNormalization;
 
 use super::template::{RunContext, Template};
 
@@ -71,7 +80,9 @@ impl Template<CrashReport> for CrashReport {
             target_exe: self.target_exe.clone(),
             target_env: self.target_env.clone(),
             target_options: self.target_options.clone(),
+            input_via: InputVia::default(),
             target_timeout: self.target_timeout,
+            target_memory_limit_mb: None,
 
             input_queue: input_q,
             crashes: self
@@ -98,8 +109,23 @@ impl Template<CrashReport> for CrashReport {
             check_asan_log: self.check_asan_log,
             check_debugger: self.check_debugger,
             check_retry_count: self.check_retry_count,
+            repro_retries: ReproRetries::default(),
             check_queue: self.check_queue,
             minimized_stack_depth: self.minimized_stack_depth,
+            stack_normalization: StackNormalization::default(),
+            minimized_crashes: None,
+            minimizer_exe: None,
+            minimizer_options: vec![],
+            minimize_timeout: default_minimize_timeout(),
+            source_path_map: vec![],
+            report_all_findings: false,
+            report_routing: ReportRouting::default(),
+            redaction: None,
+            no_repro_bundles: NoReproBundles::default(),
+            report_concurrency: default_report_concurrency(),
+            debug_file_dirs: vec![],
+            crash_dumps: None,
+            crash_dump: CrashDumpConfig::default(),
             common: CommonConfig {
                 task_id: uuid::Uuid::new_v4(),
                 ..context.common.clone()
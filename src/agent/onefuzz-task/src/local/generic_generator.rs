@@ -6,7 +6,7 @@ use std::{collections::HashMap, path::PathBuf};
 use crate::tasks::{config::CommonConfig, utils::default_bool_true};
 use anyhow::Result;
 use async_trait::async_trait;
-use onefuzz::syncdir::SyncedDir;
+use onefuzz::{input_tester::InputVia, syncdir::SyncedDir};
 use schemars::JsonSchema;
 
 use super::template::{RunContext, Template};
@@ -60,6 +60,7 @@ impl Template<Generator> for Generator {
             generator_exe: self.generator_exe.clone(),
             generator_env: self.generator_env.clone(),
             generator_options: self.generator_options.clone(),
+            generators: vec![],
 
             readonly_inputs: self
                 .readonly_inputs
@@ -78,6 +79,7 @@ impl Template<Generator> for Generator {
             target_exe: self.target_exe.clone(),
             target_env: self.target_env.clone(),
             target_options: self.target_options.clone(),
+            input_via: InputVia::default(),
             target_timeout: self.target_timeout,
 
             check_asan_log: self.check_asan_log,
@@ -86,6 +88,7 @@ impl Template<Generator> for Generator {
 
             rename_output: self.rename_output,
             ensemble_sync_delay: self.ensemble_sync_delay,
+            max_initial_corpus: None,
             common: CommonConfig {
                 task_id: uuid::Uuid::new_v4(),
                 ..context.common.clone()
@@ -0,0 +1,180 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Tolerant parsing of hand-written JSON configs.
+//!
+//! Accepts `//` and `/* */` comments, and trailing commas in arrays and objects.
+//! These are blanked out rather than removed, so line and column numbers in
+//! parse errors still point into the original file.
+//!
+//! Only intended for configs read from local files. Configs sent by the service
+//! are parsed strictly.
+
+use serde::de::DeserializeOwned;
+
+/// Deserialize `T` from JSON which may contain comments and trailing commas.
+pub fn from_str<T: DeserializeOwned>(text: &str) -> serde_json::Result<T> {
+    serde_json::from_str(&strip(text))
+}
+
+/// Replace comments and trailing commas with whitespace, preserving newlines
+/// and the byte offsets of everything else.
+pub fn strip(text: &str) -> String {
+    enum State {
+        Json,
+        String { escaped: bool },
+        LineComment,
+        BlockComment { star: bool },
+    }
+
+    let input = text.as_bytes();
+    let mut output = Vec::with_capacity(input.len());
+    let mut state = State::Json;
+    let mut i = 0;
+
+    while i < input.len() {
+        let byte = input[i];
+
+        state = match state {
+            State::Json => match (byte, input.get(i + 1)) {
+                (b'"', _) => {
+                    output.push(byte);
+                    State::String { escaped: false }
+                }
+                (b'/', Some(b'/')) => {
+                    output.extend_from_slice(b"  ");
+                    i += 1;
+                    State::LineComment
+                }
+                (b'/', Some(b'*')) => {
+                    output.extend_from_slice(b"  ");
+                    i += 1;
+                    State::BlockComment { star: false }
+                }
+                (b']' | b'}', _) => {
+                    blank_trailing_comma(&mut output);
+                    output.push(byte);
+                    State::Json
+                }
+                _ => {
+                    output.push(byte);
+                    State::Json
+                }
+            },
+            State::String { escaped } => {
+                output.push(byte);
+                match byte {
+                    b'"' if !escaped => State::Json,
+                    b'\\' => State::String { escaped: !escaped },
+                    _ => State::String { escaped: false },
+                }
+            }
+            State::LineComment => {
+                if byte == b'\n' {
+                    output.push(byte);
+                    State::Json
+                } else {
+                    output.push(blank(byte));
+                    State::LineComment
+                }
+            }
+            State::BlockComment { star } => {
+                output.push(blank(byte));
+                match byte {
+                    b'/' if star => State::Json,
+                    b'*' => State::BlockComment { star: true },
+                    _ => State::BlockComment { star: false },
+                }
+            }
+        };
+
+        i += 1;
+    }
+
+    // Only ASCII bytes outside of strings are replaced, and with ASCII
+    // whitespace, so the output is still valid UTF-8.
+    String::from_utf8(output).expect("stripping comments preserves UTF-8")
+}
+
+// Keep line breaks, so that line numbers are unchanged.
+fn blank(byte: u8) -> u8 {
+    match byte {
+        b'\n' | b'\r' => byte,
+        _ => b' ',
+    }
+}
+
+fn blank_trailing_comma(output: &mut [u8]) {
+    if let Some(last) = output.iter().rposition(|b| !b.is_ascii_whitespace()) {
+        if output[last] == b',' {
+            output[last] = b' ';
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_str, strip};
+    use crate::local::template::TaskGroup;
+
+    #[test]
+    fn test_strip_comments() {
+        let text = "{\n  // line\n  \"a\": 1, /* block */ \"b\": 2\n}";
+        let expected = "{\n         \n  \"a\": 1,             \"b\": 2\n}";
+        assert_eq!(strip(text), expected);
+    }
+
+    #[test]
+    fn test_strip_multiline_block_comment() {
+        let text = "[1, /* one\n two */ 2]";
+        let expected = "[1,       \n        2]";
+        assert_eq!(strip(text), expected);
+    }
+
+    #[test]
+    fn test_strip_trailing_commas() {
+        let text = "{\"a\": [1, 2,], \"b\": {\"c\": 3, /* c */ },}";
+        let value: serde_json::Value = from_str(text).unwrap();
+        assert_eq!(value, serde_json::json!({"a": [1, 2], "b": {"c": 3}}));
+    }
+
+    #[test]
+    fn test_strings_are_preserved() {
+        let text =
+            r#"{"url": "https://contoso.com/*", "escaped": "\"// not a comment", "list": ",]"}"#;
+        assert_eq!(strip(text), text);
+    }
+
+    #[test]
+    fn test_commented_task_group() {
+        let text = r#"
+        {
+            // paths are relative to the current directory
+            "setup_dir": "setup",
+            "tasks": [
+                {
+                    "type": "LibFuzzer",
+                    "inputs": "inputs",
+                    "readonly_inputs": [],
+                    "crashes": "crashes",
+                    "target_exe": "fuzz.exe",
+                    "target_env": {},
+                    "target_options": [], /* defaults */
+                    "check_fuzzer_help": true,
+                },
+            ],
+        }
+        "#;
+
+        let task_group: TaskGroup = from_str(text).unwrap();
+        assert_eq!(task_group.tasks.len(), 1);
+    }
+
+    #[test]
+    fn test_error_position() {
+        // The error is on line 3, after the text stripped from line 2.
+        let text = "{\n  \"a\": 1, // comment\n  \"b\": ]\n}";
+        let err = from_str::<serde_json::Value>(text).unwrap_err();
+        assert_eq!((err.line(), err.column()), (3, 8));
+    }
+}
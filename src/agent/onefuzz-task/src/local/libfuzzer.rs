@@ -1,19 +1,65 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
-use crate::tasks::{
-    config::CommonConfig,
-    fuzz::libfuzzer::{common::default_workers, generic::LibFuzzerFuzzTask},
-    utils::default_bool_true,
+#[cfg(any(target_os = "linux", target_os = "windows"))]
+use crate::tasks::coverage::{
+    default_output_formats,
+    generic::{CoverageTask, TargetType},
+    summary::{CoverageSummary, COVERAGE_SUMMARY_FILE},
+};
+use crate::{
+    local::common::{
+        build_local_context, get_cmd_arg, get_cmd_env, get_cmd_exe, CmdType, DirectoryMonitorQueue,
+        SyncCountDirMonitor, UiEvent, CHECK_FUZZER_HELP, TARGET_ENV, TARGET_EXE, TARGET_OPTIONS,
+        TARGET_TIMEOUT, TARGET_WORKERS,
+    },
+    tasks::{
+        config::CommonConfig,
+        fuzz::libfuzzer::{
+            common::{default_rss_limit_mb, default_workers},
+            generic::LibFuzzerFuzzTask,
+        },
+        report::{
+            bundle::NoReproBundles,
+            compat::parse_report_file,
+            crash_report::{CrashReport, CrashTestResult, ReportRouting},
+            libfuzzer_report::ReportTask,
+            minimize::default_minimize_timeout,
+            pool::default_report_concurrency,
+            repro::ReproRetries,
+        },
+        utils::default_bool_true,
+    },
 };
 use anyhow::Result;
 use async_trait::async_trait;
-use onefuzz::syncdir::SyncedDir;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use flume::Sender;
+#[cfg(any(target_os = "linux", target_os = "windows"))]
+use onefuzz::input_tester::InputVia;
+use onefuzz::{monitor::DirectoryMonitor, syncdir::SyncedDir};
 use schemars::JsonSchema;
-use std::{collections::HashMap, path::PathBuf};
+use stacktrace_parser::StackNormalization;
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::task::JoinHandle;
+use uuid::Uuid;
 
 use super::template::{RunContext, Template};
 
+const INPUTS: &str = "inputs";
+const OUTPUT_DIR: &str = "output_dir";
+#[cfg(any(target_os = "linux", target_os = "windows"))]
+const COVERAGE: &str = "coverage";
+const WATCH: &str = "watch";
+
+// How long a new report must go unchanged before it's printed, when watching.
+const WATCH_SETTLE_PERIOD: Duration = Duration::from_secs(1);
+
 #[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct LibFuzzer {
     inputs: PathBuf,
@@ -71,7 +117,20 @@ impl Template<LibFuzzer> for LibFuzzer {
             ensemble_sync_delay: self.ensemble_sync_delay,
             check_fuzzer_help: self.check_fuzzer_help,
             expect_crash_on_failure: self.expect_crash_on_failure,
-            extra: (),
+            fork_mode: false,
+            fork_ignore_crashes: false,
+            fork_ignore_ooms: true,
+            jobs_mode: false,
+            jobs: None,
+            restart_delay: None,
+            oom: None,
+            leaks: None,
+            rss_limit_mb: default_rss_limit_mb(),
+            dictionary: None,
+            dictionary_name: None,
+            max_initial_corpus: None,
+            corpus_snapshots: None,
+            extra: Default::default(),
             common: CommonConfig {
                 task_id: uuid::Uuid::new_v4(),
                 ..context.common.clone()
@@ -87,3 +146,472 @@ impl Template<LibFuzzer> for LibFuzzer {
         Ok(())
     }
 }
+
+/// The directories of a local libFuzzer run, all in its output directory,
+/// except for the seed inputs, which are only read.
+#[derive(Clone, Debug)]
+pub struct PipelineDirs {
+    pub output_dir: PathBuf,
+    pub seeds: Option<PathBuf>,
+    pub inputs: PathBuf,
+    pub crashes: PathBuf,
+    pub unique_reports: PathBuf,
+    pub no_repro: PathBuf,
+    pub coverage: Option<PathBuf>,
+}
+
+impl PipelineDirs {
+    pub fn new(output_dir: impl Into<PathBuf>, seeds: Option<PathBuf>, coverage: bool) -> Self {
+        let output_dir = output_dir.into();
+
+        Self {
+            seeds,
+            inputs: output_dir.join("inputs"),
+            crashes: output_dir.join("crashes"),
+            unique_reports: output_dir.join("unique_reports"),
+            no_repro: output_dir.join("no_repro"),
+            coverage: coverage.then(|| output_dir.join("coverage")),
+            output_dir,
+        }
+    }
+
+    fn create(&self) -> Result<()> {
+        let dirs = [
+            &self.inputs,
+            &self.crashes,
+            &self.unique_reports,
+            &self.no_repro,
+        ];
+        for dir in dirs.into_iter().chain(&self.coverage) {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        Ok(())
+    }
+}
+
+// A directory of the run, read and written by its tasks in place.
+fn local_dir(path: &Path, event_sender: &Option<Sender<UiEvent>>) -> Result<SyncedDir> {
+    SyncedDir {
+        remote_path: None,
+        local_path: path.to_owned(),
+    }
+    .monitor_count(event_sender)
+}
+
+/// The tasks of a local libFuzzer run: fuzzing, reporting the crashes found,
+/// and, if enabled, recording the coverage of the inputs found, all of local
+/// directories.
+pub struct Pipeline {
+    pub dirs: PipelineDirs,
+    tasks: Vec<JoinHandle<Result<()>>>,
+}
+
+impl Pipeline {
+    /// Start the tasks of the run of the `libfuzzer` subcommand of `args`.
+    pub async fn start(args: &ArgMatches, event_sender: Option<Sender<UiEvent>>) -> Result<Self> {
+        let context = build_local_context(args, true, event_sender.clone()).await?;
+
+        let output_dir = args
+            .get_one::<PathBuf>(OUTPUT_DIR)
+            .cloned()
+            .unwrap_or_else(|| context.job_path.clone());
+        let seeds = args.get_one::<PathBuf>(INPUTS).cloned();
+        #[cfg(any(target_os = "linux", target_os = "windows"))]
+        let coverage = args.get_flag(COVERAGE);
+        #[cfg(not(any(target_os = "linux", target_os = "windows")))]
+        let coverage = false;
+
+        let dirs = PipelineDirs::new(output_dir, seeds, coverage);
+        dirs.create()?;
+
+        let target_exe: PathBuf = get_cmd_exe(CmdType::Target, args)?.into();
+        let target_env = get_cmd_env(CmdType::Target, args)?;
+        let target_options = get_cmd_arg(CmdType::Target, args);
+        let target_timeout = args.get_one::<u64>(TARGET_TIMEOUT).copied();
+        let target_workers = args
+            .get_one::<usize>(TARGET_WORKERS)
+            .copied()
+            .unwrap_or_else(default_workers);
+        let check_fuzzer_help = args.get_flag(CHECK_FUZZER_HELP);
+
+        // Each task has its own ID, as when run by the service.
+        let common = || CommonConfig {
+            task_id: Uuid::new_v4(),
+            ..context.common_config.clone()
+        };
+        let seeds = dirs
+            .seeds
+            .as_deref()
+            .map(|seeds| local_dir(seeds, &event_sender))
+            .transpose()?;
+
+        let mut tasks = vec![];
+
+        let fuzz_config = crate::tasks::fuzz::libfuzzer::generic::Config {
+            inputs: local_dir(&dirs.inputs, &event_sender)?,
+            readonly_inputs: seeds.clone().map(|seeds| vec![seeds]),
+            crashes: local_dir(&dirs.crashes, &event_sender)?,
+            crashdumps: None,
+            target_exe: target_exe.clone(),
+            target_env: target_env.clone(),
+            target_options: target_options.clone(),
+            target_workers,
+            ensemble_sync_delay: None,
+            check_fuzzer_help,
+            expect_crash_on_failure: false,
+            fork_mode: false,
+            fork_ignore_crashes: false,
+            fork_ignore_ooms: true,
+            jobs_mode: false,
+            jobs: None,
+            restart_delay: None,
+            oom: None,
+            leaks: None,
+            rss_limit_mb: default_rss_limit_mb(),
+            dictionary: None,
+            dictionary_name: None,
+            max_initial_corpus: None,
+            corpus_snapshots: None,
+            extra: Default::default(),
+            common: common(),
+        };
+        tasks.push(tokio::spawn(async move {
+            LibFuzzerFuzzTask::new(fuzz_config)?.run().await
+        }));
+
+        // Crashes are reported as they're found.
+        let crash_monitor = DirectoryMonitorQueue::start_monitoring(&dirs.crashes).await?;
+        let report_config = crate::tasks::report::libfuzzer_report::Config {
+            target_exe: target_exe.clone(),
+            target_env: target_env.clone(),
+            target_options: target_options.clone(),
+            target_timeout,
+            input_queue: Some(crash_monitor.queue_client.clone()),
+            crashes: Some(local_dir(&dirs.crashes, &event_sender)?),
+            reports: None,
+            unique_reports: Some(local_dir(&dirs.unique_reports, &event_sender)?),
+            no_repro: Some(local_dir(&dirs.no_repro, &event_sender)?),
+            check_fuzzer_help,
+            check_retry_count: 0,
+            repro_retries: ReproRetries::default(),
+            minimized_stack_depth: None,
+            stack_normalization: StackNormalization::default(),
+            check_queue: true,
+            minimized_crashes: None,
+            minimize_timeout: default_minimize_timeout(),
+            source_path_map: vec![],
+            report_all_findings: false,
+            report_routing: ReportRouting::default(),
+            redaction: None,
+            no_repro_bundles: NoReproBundles::default(),
+            report_concurrency: default_report_concurrency(),
+            debug_file_dirs: vec![],
+            common: common(),
+        };
+        tasks.push(crash_monitor.handle);
+        tasks.push(tokio::spawn(async move {
+            ReportTask::new(report_config).managed_run().await
+        }));
+
+        // The coverage of new inputs is recorded as they're found.
+        #[cfg(any(target_os = "linux", target_os = "windows"))]
+        if let Some(coverage) = &dirs.coverage {
+            let input_monitor = DirectoryMonitorQueue::start_monitoring(&dirs.inputs).await?;
+
+            let mut target_options = target_options.clone();
+            if !target_options
+                .iter()
+                .any(|option| option.contains("{input}"))
+            {
+                target_options.push("{input}".to_owned());
+            }
+
+            let coverage_config = crate::tasks::coverage::generic::Config {
+                target_exe: target_exe.clone(),
+                target_env: target_env.clone(),
+                target_options,
+                input_via: InputVia::default(),
+                target_timeout,
+                target_type: TargetType::Native,
+                coverage_filter: None,
+                module_allowlist: None,
+                source_allowlist: None,
+                module_denylist: vec![],
+                input_queue: Some(input_monitor.queue_client.clone()),
+                readonly_inputs: std::iter::once(local_dir(&dirs.inputs, &event_sender)?)
+                    .chain(seeds)
+                    .collect(),
+                coverage: local_dir(coverage, &event_sender)?,
+                output_formats: default_output_formats(),
+                sources: None,
+                source_path_map: vec![],
+                reprocess_inputs: false,
+                follow_children: false,
+                analysis_cache: None,
+                summary_interval: None,
+                common: common(),
+            };
+            tasks.push(input_monitor.handle);
+            tasks.push(tokio::spawn(async move {
+                CoverageTask::new(coverage_config).run().await
+            }));
+        }
+
+        if args.get_flag(WATCH) {
+            tasks.push(tokio::spawn(watch_reports(dirs.unique_reports.clone())));
+
+            #[cfg(any(target_os = "linux", target_os = "windows"))]
+            if let Some(coverage) = &dirs.coverage {
+                tasks.push(tokio::spawn(watch_coverage(coverage.clone())));
+            }
+        }
+
+        Ok(Self { dirs, tasks })
+    }
+
+    /// Wait for the tasks, until one fails, or all have exited.
+    pub async fn wait(&mut self) -> Result<()> {
+        while !self.tasks.is_empty() {
+            let (result, index, _) = futures::future::select_all(self.tasks.iter_mut()).await;
+            self.tasks.remove(index);
+            result??;
+        }
+
+        Ok(())
+    }
+
+    /// Stop the tasks, and wait for them to stop, so that the targets they
+    /// were running have been killed.
+    pub async fn shutdown(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+
+        for task in self.tasks.drain(..) {
+            let _ = task.await;
+        }
+    }
+
+    /// Summarize the findings of the run so far.
+    pub async fn summary(&self) -> Result<PipelineSummary> {
+        PipelineSummary::new(&self.dirs).await
+    }
+}
+
+/// The findings of a local libFuzzer run, as saved to its directories.
+#[derive(Debug)]
+pub struct PipelineSummary {
+    pub output_dir: PathBuf,
+    pub inputs: usize,
+    pub crashes: usize,
+    pub unique_reports: Vec<CrashReport>,
+    pub no_repro: usize,
+
+    /// The last summary of the coverage task, if one was saved.
+    pub coverage: Option<String>,
+}
+
+impl PipelineSummary {
+    pub async fn new(dirs: &PipelineDirs) -> Result<Self> {
+        let mut unique_reports = vec![];
+        for path in files(&dirs.unique_reports).await? {
+            if let CrashTestResult::CrashReport(report) = parse_report_file(&path).await? {
+                unique_reports.push(*report);
+            }
+        }
+
+        #[cfg(any(target_os = "linux", target_os = "windows"))]
+        let coverage = match &dirs.coverage {
+            Some(coverage) => load_coverage_summary(&coverage.join(COVERAGE_SUMMARY_FILE))
+                .await
+                .ok()
+                .map(|summary| describe_coverage(&summary)),
+            None => None,
+        };
+        #[cfg(not(any(target_os = "linux", target_os = "windows")))]
+        let coverage = None;
+
+        Ok(Self {
+            output_dir: dirs.output_dir.clone(),
+            inputs: files(&dirs.inputs).await?.len(),
+            crashes: files(&dirs.crashes).await?.len(),
+            unique_reports,
+            no_repro: files(&dirs.no_repro).await?.len(),
+            coverage,
+        })
+    }
+}
+
+impl fmt::Display for PipelineSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "libFuzzer run in {}:", self.output_dir.display())?;
+        writeln!(f, "  inputs: {}", self.inputs)?;
+        writeln!(f, "  crashes: {}", self.crashes)?;
+        writeln!(f, "  unique crashes: {}", self.unique_reports.len())?;
+        for report in &self.unique_reports {
+            writeln!(f, "    {}", describe_report(report))?;
+        }
+        writeln!(f, "  crashes which didn't reproduce: {}", self.no_repro)?;
+        if let Some(coverage) = &self.coverage {
+            writeln!(f, "  coverage: {coverage}")?;
+        }
+
+        Ok(())
+    }
+}
+
+// The files of `dir`, in order.
+async fn files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = vec![];
+
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.file_type().await?.is_file() {
+            files.push(entry.path());
+        }
+    }
+    files.sort();
+
+    Ok(files)
+}
+
+// Such as "heap-buffer-overflow fuzz.c:12 in check (High: out-of-bounds write)".
+fn describe_report(report: &CrashReport) -> String {
+    let mut description = format!("{} {}", report.crash_type, report.crash_site);
+    if let Some(exploitability) = &report.exploitability {
+        description.push_str(&format!(" ({exploitability:?}"));
+        if let Some(rationale) = &report.exploitability_rationale {
+            description.push_str(&format!(": {rationale}"));
+        }
+        description.push(')');
+    }
+
+    description
+}
+
+#[cfg(any(target_os = "linux", target_os = "windows"))]
+fn describe_coverage(summary: &CoverageSummary) -> String {
+    let stats = &summary.stats;
+    format!(
+        "{} of {} features ({:.1}%), of {} inputs",
+        stats.covered,
+        stats.features,
+        stats.rate * 100.0,
+        summary.inputs.processed
+    )
+}
+
+#[cfg(any(target_os = "linux", target_os = "windows"))]
+async fn load_coverage_summary(path: &Path) -> Result<CoverageSummary> {
+    let json = tokio::fs::read_to_string(path).await?;
+    Ok(serde_json::from_str(&json)?)
+}
+
+// Print each new unique report saved to `dir`.
+async fn watch_reports(dir: PathBuf) -> Result<()> {
+    let mut monitor = DirectoryMonitor::new(&dir, false).await?;
+    monitor.set_settle_period(Some(WATCH_SETTLE_PERIOD));
+
+    while let Some(path) = monitor.next_file().await? {
+        match parse_report_file(&path).await {
+            Ok(CrashTestResult::CrashReport(report)) => {
+                println!("new crash: {}", describe_report(&report));
+                println!("  report: {}", path.display());
+            }
+            Ok(CrashTestResult::NoRepro(_)) => {}
+            Err(err) => warn!("{:?}", err),
+        }
+    }
+
+    Ok(())
+}
+
+// Print each summary of the coverage task saved to `dir`.
+#[cfg(any(target_os = "linux", target_os = "windows"))]
+async fn watch_coverage(dir: PathBuf) -> Result<()> {
+    let mut monitor = DirectoryMonitor::new(&dir, false).await?;
+
+    while let Some(path) = monitor.next_file().await? {
+        if path.file_name() != Some(COVERAGE_SUMMARY_FILE.as_ref()) {
+            continue;
+        }
+
+        match load_coverage_summary(&path).await {
+            Ok(summary) => println!("coverage: {}", describe_coverage(&summary)),
+            Err(err) => warn!("{:?}", err),
+        }
+    }
+
+    Ok(())
+}
+
+/// Run the tasks of a local libFuzzer run until one fails, or until
+/// interrupted, then stop them all and print a summary of the findings.
+pub async fn run(args: &ArgMatches, event_sender: Option<Sender<UiEvent>>) -> Result<()> {
+    let mut pipeline = Pipeline::start(args, event_sender).await?;
+
+    let result = tokio::select! {
+        result = pipeline.wait() => result,
+        interrupted = tokio::signal::ctrl_c() => {
+            info!("stopping the local libFuzzer tasks");
+            interrupted.map_err(Into::into)
+        }
+    };
+
+    pipeline.shutdown().await;
+    match pipeline.summary().await {
+        Ok(summary) => println!("{summary}"),
+        Err(err) => warn!("unable to summarize the run: {:?}", err),
+    }
+
+    result
+}
+
+pub fn args(name: &'static str) -> Command {
+    let args = vec![
+        Arg::new(TARGET_EXE).long(TARGET_EXE).required(true),
+        Arg::new(TARGET_ENV).long(TARGET_ENV).num_args(0..),
+        Arg::new(TARGET_OPTIONS)
+            .long(TARGET_OPTIONS)
+            .value_delimiter(' ')
+            .help("Use a quoted string with space separation to denote multiple arguments"),
+        Arg::new(TARGET_WORKERS)
+            .long(TARGET_WORKERS)
+            .value_parser(value_parser!(usize)),
+        Arg::new(TARGET_TIMEOUT)
+            .long(TARGET_TIMEOUT)
+            .value_parser(value_parser!(u64)),
+        Arg::new(CHECK_FUZZER_HELP)
+            .action(ArgAction::SetTrue)
+            .long(CHECK_FUZZER_HELP),
+        Arg::new(INPUTS)
+            .long(INPUTS)
+            .value_parser(value_parser!(PathBuf))
+            .help("Seed inputs, which are read but not written"),
+        Arg::new(OUTPUT_DIR)
+            .long(OUTPUT_DIR)
+            .value_parser(value_parser!(PathBuf))
+            .help("Where the inputs, crashes and reports found are saved, by default a directory of the job in the current directory"),
+        Arg::new(WATCH)
+            .action(ArgAction::SetTrue)
+            .long(WATCH)
+            .help("Print new crashes and coverage summaries as they're saved"),
+    ];
+
+    let cmd = Command::new(name)
+        .about(
+            "execute a local-only libfuzzer run: fuzzing, crash reporting, and optionally coverage",
+        )
+        .args(&args);
+
+    #[cfg(any(target_os = "linux", target_os = "windows"))]
+    let cmd = cmd.arg(
+        Arg::new(COVERAGE)
+            .action(ArgAction::SetTrue)
+            .long(COVERAGE)
+            .help("Also record the coverage of the inputs found"),
+    );
+
+    cmd
+}
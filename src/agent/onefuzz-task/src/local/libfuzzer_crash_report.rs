@@ -3,11 +3,19 @@
 
 use std::{collections::HashMap, path::PathBuf};
 
-use crate::tasks::{config::CommonConfig, utils::default_bool_true};
+use crate::tasks::{
+    config::CommonConfig,
+    report::{
+        bundle::NoReproBundles, crash_report::ReportRouting, minimize::default_minimize_timeout,
+        pool::default_report_concurrency, repro::ReproRetries,
+    },
+    utils::default_bool_true,
+};
 use anyhow::Result;
 use async_trait::async_trait;
 use futures::future::OptionFuture;
 use schemars::JsonSchema;
+use stacktrace_parser::StackNormalization;
 
 use super::template::{RunContext, Template};
 #[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
@@ -92,8 +100,19 @@ impl Template<LibfuzzerCrashReport> for LibfuzzerCrashReport {
 
             check_fuzzer_help: self.check_fuzzer_help,
             check_retry_count: self.check_retry_count,
+            repro_retries: ReproRetries::default(),
             minimized_stack_depth: self.minimized_stack_depth,
+            stack_normalization: StackNormalization::default(),
             check_queue: self.check_queue,
+            minimized_crashes: None,
+            minimize_timeout: default_minimize_timeout(),
+            source_path_map: vec![],
+            report_all_findings: false,
+            report_routing: ReportRouting::default(),
+            redaction: None,
+            no_repro_bundles: NoReproBundles::default(),
+            report_concurrency: default_report_concurrency(),
+            debug_file_dirs: vec![],
             common: CommonConfig {
                 task_id: uuid::Uuid::new_v4(),
                 ..context.common.clone()
@@ -11,7 +11,10 @@ use crate::{
     },
     tasks::{
         config::CommonConfig,
-        fuzz::libfuzzer::generic::{Config, LibFuzzerFuzzTask},
+        fuzz::libfuzzer::{
+            common::default_rss_limit_mb,
+            generic::{Config, LibFuzzerFuzzTask},
+        },
     },
 };
 use anyhow::Result;
@@ -59,8 +62,21 @@ pub fn build_fuzz_config(
         ensemble_sync_delay,
         check_fuzzer_help,
         expect_crash_on_failure,
+        fork_mode: false,
+        fork_ignore_crashes: false,
+        fork_ignore_ooms: true,
+        jobs_mode: false,
+        jobs: None,
+        restart_delay: None,
+        oom: None,
+        leaks: None,
+        rss_limit_mb: default_rss_limit_mb(),
+        dictionary: None,
+        dictionary_name: None,
+        max_initial_corpus: None,
+        corpus_snapshots: None,
         common,
-        extra: (),
+        extra: Default::default(),
     };
 
     Ok(config)
@@ -67,6 +67,8 @@ impl Template<LibfuzzerMerge> for LibfuzzerMerge {
             preserve_existing_outputs: self.preserve_existing_outputs,
 
             check_fuzzer_help: self.check_fuzzer_help,
+            target_timeout: None,
+            merge_quarantine: None,
 
             common: CommonConfig {
                 task_id: uuid::Uuid::new_v4(),
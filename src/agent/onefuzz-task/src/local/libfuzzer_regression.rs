@@ -90,6 +90,7 @@ impl Template<LibfuzzerRegression> for LibfuzzerRegression {
                 .clone()
                 .map(|c| context.to_monitored_sync_dir("readonly_inputs", c))
                 .transpose()?,
+            bisect: None,
 
             check_fuzzer_help: self.check_fuzzer_help,
             check_retry_count: self.check_retry_count,
@@ -3,11 +3,17 @@
 
 use anyhow::Result;
 use async_trait::async_trait;
+use coverage::path_map::PathMap;
 use onefuzz::machine_id::MachineIdentity;
 use schemars::JsonSchema;
+use stacktrace_parser::StackNormalization;
 use std::{collections::HashMap, path::PathBuf};
 
 use super::template::{RunContext, Template};
+use crate::tasks::{
+    report::{redact::Redactor, repro::ReproRetries},
+    symbols::debug_file_dirs,
+};
 
 #[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct LibfuzzerTestInput {
@@ -55,12 +61,21 @@ impl Template<LibfuzzerTestInput> for LibfuzzerTestInput {
                 job_id: uuid::Uuid::new_v4(),
                 target_timeout: c.target_timeout,
                 check_retry_count: c.check_retry_count,
+                repro_retries: &ReproRetries::none(),
                 minimized_stack_depth: c.minimized_stack_depth,
+                stack_normalization: &StackNormalization::default(),
                 machine_identity: MachineIdentity {
                     machine_id: uuid::Uuid::new_v4(),
                     machine_name: "local".to_string(),
                     scaleset_name: None,
                 },
+                allow_unknown_placeholders: false,
+                allow_env_placeholders: false,
+                target_options_shell: false,
+                qemu_user: None,
+                source_path_map: &PathMap::default(),
+                redactor: &Redactor::default(),
+                debug_file_dirs: &debug_file_dirs(&c.setup_dir, c.extra_setup_dir.as_deref(), &[]),
             };
 
             crate::tasks::report::libfuzzer_report::test_input(libfuzzer_test_input)
@@ -9,6 +9,7 @@ pub mod create_template;
 pub mod generic_analysis;
 pub mod generic_crash_report;
 pub mod generic_generator;
+pub mod jsonc;
 pub mod libfuzzer;
 pub mod libfuzzer_crash_report;
 pub mod libfuzzer_fuzz;
@@ -1,6 +1,7 @@
 use async_trait::async_trait;
 use flume::Sender;
 use onefuzz::{blob::BlobContainerUrl, syncdir::SyncedDir, utils::try_wait_all_join_handles};
+use onefuzz_telemetry::{context::CorrelationContext, EventData};
 use path_absolutize::Absolutize;
 use serde::Deserialize;
 use std::path::{Path, PathBuf};
@@ -20,7 +21,8 @@ use crate::local::{
 use crate::tasks::config::CommonConfig;
 
 use super::common::{DirectoryMonitorQueue, SyncCountDirMonitor, UiEvent};
-use anyhow::{Error, Result};
+use super::jsonc;
+use anyhow::{Context, Error, Result};
 
 use schemars::JsonSchema;
 
@@ -174,7 +176,9 @@ impl RunContext {
         &self,
         future: impl futures::Future<Output = Result<()>> + std::marker::Send + 'static,
     ) {
-        let handle = tokio::spawn(future);
+        // Local tasks share the nil task id, so are only correlated by job.
+        let context = CorrelationContext::current().with(EventData::JobId(self.common.job_id));
+        let handle = tokio::spawn(context.scope(future));
         self.add_handle(handle).await;
     }
 
@@ -183,16 +187,28 @@ impl RunContext {
     }
 }
 
+/// Load a task group from a YAML file, or a JSON file, which may contain
+/// comments and trailing commas.
+pub fn load_task_group(path: &Path) -> Result<TaskGroup> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("unable to read task group: {}", path.display()))?;
+
+    let mut value: serde_yaml::Value = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json" | "jsonc") => jsonc::from_str(&text)
+            .with_context(|| format!("unable to parse task group: {}", path.display()))?,
+        _ => serde_yaml::from_str(&text)
+            .with_context(|| format!("unable to parse task group: {}", path.display()))?,
+    };
+    value.apply_merge()?;
+
+    Ok(serde_yaml::from_value(value)?)
+}
+
 pub async fn launch(
     task_group_config: impl AsRef<Path>,
     event_sender: Option<Sender<UiEvent>>,
 ) -> Result<()> {
-    let file = std::fs::File::open(task_group_config.as_ref())?;
-    let reader = std::io::BufReader::new(file);
-    let mut value: serde_yaml::Value = serde_yaml::from_reader(reader)?;
-    value.apply_merge()?;
-
-    let task_group: TaskGroup = serde_yaml::from_value(value)?;
+    let task_group = load_task_group(task_group_config.as_ref())?;
 
     let common = CommonConfig {
         task_id: Uuid::nil(),
@@ -212,6 +228,14 @@ pub async fn launch(
             scaleset_name: None,
         },
         tags: Default::default(),
+        allow_unknown_placeholders: false,
+        allow_env_placeholders: false,
+        target_options_shell: false,
+        qemu_user: None,
+        symbol_servers: vec![],
+        symbol_cache: None,
+        process_stats: Default::default(),
+        unknown_fields: Default::default(),
         from_agent_to_task_endpoint: "/".to_string(),
         from_task_to_agent_endpoint: "/".to_string(),
         extra_output: None,
@@ -3,12 +3,18 @@
 
 use anyhow::Result;
 use async_trait::async_trait;
-use onefuzz::machine_id::MachineIdentity;
+use coverage::path_map::PathMap;
+use onefuzz::{input_tester::InputVia, machine_id::MachineIdentity};
 use schemars::JsonSchema;
+use stacktrace_parser::StackNormalization;
 use std::{collections::HashMap, path::PathBuf};
 use uuid::Uuid;
 
 use super::template::{RunContext, Template};
+use crate::tasks::{
+    report::{redact::Redactor, repro::ReproRetries},
+    symbols::debug_file_dirs,
+};
 
 #[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct TestInput {
@@ -55,20 +61,32 @@ impl Template<TestInput> for TestInput {
                 target_exe: c.target_exe.as_path(),
                 target_options: &c.target_options,
                 target_env: &c.target_env,
+                input_via: InputVia::default(),
                 setup_dir: &c.setup_dir,
                 extra_setup_dir: c.extra_setup_dir.as_deref(),
                 task_id: uuid::Uuid::new_v4(),
                 job_id: uuid::Uuid::new_v4(),
                 target_timeout: c.target_timeout,
+                target_memory_limit_mb: None,
                 check_retry_count: c.check_retry_count,
+                repro_retries: &ReproRetries::none(),
                 check_asan_log: c.check_asan_log,
                 check_debugger: c.check_debugger,
                 minimized_stack_depth: c.minimized_stack_depth,
+                stack_normalization: &StackNormalization::default(),
                 machine_identity: MachineIdentity {
                     machine_id: uuid::Uuid::new_v4(),
                     machine_name: "local".to_string(),
                     scaleset_name: None,
                 },
+                allow_unknown_placeholders: false,
+                allow_env_placeholders: false,
+                target_options_shell: false,
+                qemu_user: None,
+                source_path_map: &PathMap::default(),
+                redactor: &Redactor::default(),
+                debug_file_dirs: &debug_file_dirs(&c.setup_dir, c.extra_setup_dir.as_deref(), &[]),
+                crash_dumps: None,
             };
 
             crate::tasks::report::generic::test_input(libfuzzer_test_input)
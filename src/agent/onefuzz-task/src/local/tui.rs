@@ -211,6 +211,9 @@ impl TerminalUi {
             EventData::Features(_)
                 | EventData::Covered(_)
                 | EventData::Rate(_)
+                | EventData::Skipped(_)
+                | EventData::Processed(_)
+                | EventData::Remaining(_)
                 | EventData::Count(_)
                 | EventData::ExecsSecond(_)
                 | EventData::VirtualMemory(_)
@@ -222,6 +225,9 @@ impl TerminalUi {
                 | EventData::CoveragePathsFound(_)
                 | EventData::CoveragePathsImported(_)
                 | EventData::CoverageMaxDepth(_)
+                | EventData::CyclesDone(_)
+                | EventData::CorpusCount(_)
+                | EventData::RssMb(_)
         )
     }
 
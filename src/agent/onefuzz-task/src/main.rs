@@ -17,12 +17,14 @@ use std::io::{stdout, Write};
 mod check_for_update;
 mod local;
 mod managed;
+mod merge_coverage;
 mod tasks;
 
 const LICENSE_CMD: &str = "licenses";
 const LOCAL_CMD: &str = "local";
 const MANAGED_CMD: &str = "managed";
 const CHECK_FOR_UPDATE: &str = "check_for_update";
+const MERGE_COVERAGE_CMD: &str = "merge_coverage";
 
 const ONEFUZZ_BUILT_VERSION: &str = env!("ONEFUZZ_VERSION");
 
@@ -42,7 +44,8 @@ fn main() -> Result<()> {
         .subcommand(
             Command::new(CHECK_FOR_UPDATE)
                 .about("compares the version of onefuzz-task with the onefuzz service"),
-        );
+        )
+        .subcommand(merge_coverage::args(MERGE_COVERAGE_CMD));
 
     let matches = app.get_matches();
 
@@ -60,11 +63,16 @@ async fn run(args: ArgMatches) -> Result<()> {
     // processes of the task worker (still allowing user overrides).
     set_sanitizer_env_vars()?;
 
+    // As set by the agent, for managed tasks.
+    reqwest_retry::timeout::set_timeouts_from_env()?;
+    reqwest_retry::proxy::set_proxy_from_env()?;
+
     match args.subcommand() {
         Some((LICENSE_CMD, _)) => licenses(),
         Some((LOCAL_CMD, sub)) => local::cmd::run(sub.to_owned()).await,
         Some((MANAGED_CMD, sub)) => managed::cmd::run(sub).await,
         Some((CHECK_FOR_UPDATE, _)) => check_for_update::run(ONEFUZZ_BUILT_VERSION),
+        Some((MERGE_COVERAGE_CMD, sub)) => merge_coverage::run(sub).await,
         _ => anyhow::bail!("No command provided. Run with 'help' to see available commands."),
     }
 }
@@ -175,6 +175,18 @@ async fn init_telemetry(config: &CommonConfig) {
         config.microsoft_telemetry_key.clone(),
     )
     .await;
+
+    if let Err(err) = onefuzz_telemetry::set_otlp_exporter_from_env().await {
+        warn!("unable to export telemetry to OTLP collector: {:?}", err);
+    }
+
+    if let Err(err) = onefuzz_telemetry::set_file_sink_from_env() {
+        warn!("unable to write telemetry to local files: {:?}", err);
+    }
+
+    if let Err(err) = onefuzz_telemetry::set_sampling_from_env() {
+        warn!("unable to set telemetry sampling: {:?}", err);
+    }
 }
 
 const CONFIG_ARG: &str = "config";
@@ -0,0 +1,150 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Offline merging of the coverage files of several coverage tasks, such as
+//! the tasks of a job for each OS.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use coverage::allowlist::AllowList;
+use coverage::binary::BinaryCoverage;
+use coverage::html;
+use coverage::lcov::Lcov;
+use coverage::merge::{find_modules, merge_coverage};
+use coverage::path_map::PathMap;
+use coverage::source::binary_to_source_coverage;
+use onefuzz_file_format::coverage::binary::{
+    v1::BinaryCoverageJson as BinaryCoverageJsonV1, BinaryCoverageJson,
+};
+use tokio::fs;
+use tokio::task::spawn_blocking;
+
+const INPUTS: &str = "inputs";
+const OUTPUT: &str = "output";
+const LCOV: &str = "lcov";
+const HTML: &str = "html";
+const MODULE_DIRS: &str = "module_dir";
+const SOURCES: &str = "sources";
+
+pub fn args(name: &'static str) -> Command {
+    Command::new(name)
+        .about("merge the binary coverage files of several coverage tasks")
+        .arg(
+            Arg::new(INPUTS)
+                .required(true)
+                .num_args(1..)
+                .value_parser(value_parser!(PathBuf))
+                .help("Binary coverage files (`coverage.json`) to merge"),
+        )
+        .arg(
+            Arg::new(OUTPUT)
+                .long(OUTPUT)
+                .required(true)
+                .value_parser(value_parser!(PathBuf))
+                .help("Path to write the merged binary coverage to"),
+        )
+        .arg(
+            Arg::new(LCOV)
+                .long(LCOV)
+                .value_parser(value_parser!(PathBuf))
+                .help("Path to write the merged source coverage to, as lcov"),
+        )
+        .arg(
+            Arg::new(HTML)
+                .long(HTML)
+                .value_parser(value_parser!(PathBuf))
+                .help("Directory to write an html report of the merged source coverage to"),
+        )
+        .arg(
+            Arg::new(MODULE_DIRS)
+                .long(MODULE_DIRS)
+                .action(ArgAction::Append)
+                .value_parser(value_parser!(PathBuf))
+                .help("Directory to find modules in by file name, if not at their recorded paths"),
+        )
+        .arg(
+            Arg::new(SOURCES)
+                .long(SOURCES)
+                .default_value(".")
+                .value_parser(value_parser!(PathBuf))
+                .help("Directory of the target's sources, for the html report"),
+        )
+}
+
+pub async fn run(args: &ArgMatches) -> Result<()> {
+    let mut inputs = vec![];
+    for path in args.get_many::<PathBuf>(INPUTS).into_iter().flatten() {
+        inputs.push(read_coverage(path).await?);
+    }
+
+    let merged = merge_coverage(&inputs)?;
+    info!(
+        "merged {} modules from {} coverage files",
+        merged.modules.len(),
+        inputs.len()
+    );
+
+    let output = args
+        .get_one::<PathBuf>(OUTPUT)
+        .ok_or_else(|| format_err!("missing output path"))?;
+    write_coverage(&merged, output).await?;
+
+    let lcov = args.get_one::<PathBuf>(LCOV);
+    let html = args.get_one::<PathBuf>(HTML);
+    if lcov.is_none() && html.is_none() {
+        return Ok(());
+    }
+
+    let module_dirs: Vec<PathBuf> = args
+        .get_many::<PathBuf>(MODULE_DIRS)
+        .into_iter()
+        .flatten()
+        .cloned()
+        .collect();
+    let (found, missing) = find_modules(&merged, &module_dirs)?;
+    for path in &missing {
+        warn!("module not found, omitting its source coverage: {}", path);
+    }
+
+    let source =
+        spawn_blocking(move || binary_to_source_coverage(&found, &AllowList::default())).await??;
+
+    if let Some(path) = lcov {
+        fs::write(path, Lcov(&source).to_string())
+            .await
+            .with_context(|| format!("writing lcov coverage to {}", path.display()))?;
+    }
+
+    if let Some(path) = html {
+        let sources = args
+            .get_one::<PathBuf>(SOURCES)
+            .ok_or_else(|| format_err!("missing sources path"))?;
+        html::write_report(&source, sources, &PathMap::default(), path)
+            .with_context(|| format!("writing html coverage report to {}", path.display()))?;
+    }
+
+    Ok(())
+}
+
+async fn read_coverage(path: &Path) -> Result<BinaryCoverage> {
+    let text = fs::read_to_string(path)
+        .await
+        .with_context(|| format!("reading coverage file {}", path.display()))?;
+    let json = BinaryCoverageJson::deserialize(&text)
+        .with_context(|| format!("parsing coverage file {}", path.display()))?;
+
+    BinaryCoverage::try_from(json)
+}
+
+async fn write_coverage(coverage: &BinaryCoverage, path: &Path) -> Result<()> {
+    let json = BinaryCoverageJson::V1(BinaryCoverageJsonV1::from(coverage));
+    let text = serde_json::to_string(&json)?;
+
+    fs::write(path, text)
+        .await
+        .with_context(|| format!("writing merged coverage to {}", path.display()))?;
+
+    Ok(())
+}
@@ -2,8 +2,10 @@
 // Licensed under the MIT License.
 
 use crate::tasks::{
-    config::CommonConfig, heartbeat::HeartbeatSender, report::crash_report::monitor_reports,
-    utils::try_resolve_setup_relative_path,
+    config::CommonConfig,
+    heartbeat::HeartbeatSender,
+    report::crash_report::monitor_reports,
+    utils::{check_libraries, is_name_char, try_resolve_setup_relative_path},
 };
 use anyhow::{Context, Result};
 use onefuzz::{az_copy, blob::url::BlobUrl};
@@ -20,17 +22,30 @@ use std::{
     collections::HashMap,
     path::{Path, PathBuf},
     str,
+    time::Duration,
 };
 use storage_queue::{QueueClient, EMPTY_QUEUE_DELAY};
 use tempfile::tempdir_in;
 use tokio::{fs, process::Command};
 
+/// The file recording why an analyzer of `analyzers` failed on an input, in
+/// its output directory.
+pub const TOOL_ERROR_FILE: &str = "tool_error.txt";
+
 #[derive(Debug, Deserialize)]
 pub struct Config {
+    #[serde(default)]
     pub analyzer_exe: String,
+    #[serde(default)]
     pub analyzer_options: Vec<String>,
+    #[serde(default)]
     pub analyzer_env: HashMap<String, String>,
 
+    /// Further analyzers, run in order on each input after `analyzer_exe`,
+    /// each with its own output directory, `{analysis}/{input_stem}/{name}`.
+    #[serde(default)]
+    pub analyzers: Vec<Analyzer>,
+
     pub target_exe: PathBuf,
     pub target_options: Vec<String>,
     pub input_queue: Option<QueueClient>,
@@ -47,6 +62,109 @@ pub struct Config {
     pub common: CommonConfig,
 }
 
+/// An analyzer of a pipeline, whose failure on an input is recorded in its
+/// output directory rather than stopping the task.
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Analyzer {
+    pub name: String,
+    pub analyzer_exe: String,
+    #[serde(default)]
+    pub analyzer_options: Vec<String>,
+    #[serde(default)]
+    pub analyzer_env: HashMap<String, String>,
+
+    /// The number of seconds to let the analyzer run on each input.
+    pub timeout: Option<u64>,
+
+    /// The files the analyzer writes to its output directory, which are
+    /// missing if it failed.
+    #[serde(default)]
+    pub expected_outputs: Vec<String>,
+}
+
+impl Config {
+    /// Check that there is an analyzer, and that the names of `analyzers` are
+    /// valid as directory names and unique.
+    pub fn check_analyzers(&self) -> Result<()> {
+        if self.analyzer_exe.is_empty() && self.analyzers.is_empty() {
+            bail!("no analyzer: set `analyzer_exe` or `analyzers`");
+        }
+
+        for (i, analyzer) in self.analyzers.iter().enumerate() {
+            let name = &analyzer.name;
+            if name.is_empty() || !name.chars().all(is_name_char) {
+                bail!("invalid analyzer name: {name:?}, must only contain letters, digits, `-` and `_`");
+            }
+            if self.analyzers[..i].iter().any(|a| a.name == *name) {
+                bail!("duplicate analyzer name: {name}");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The executables of every analyzer, for telemetry.
+    pub fn tool_name(&self) -> String {
+        let mut exes = vec![];
+        if !self.analyzer_exe.is_empty() {
+            exes.push(self.analyzer_exe.as_str());
+        }
+        exes.extend(self.analyzers.iter().map(|a| a.analyzer_exe.as_str()));
+        exes.join(",")
+    }
+
+    // The analyzer set by `analyzer_exe`, which writes to the analysis
+    // directory itself.
+    fn default_analyzer(&self) -> Option<Analyzer> {
+        if self.analyzer_exe.is_empty() {
+            return None;
+        }
+
+        Some(Analyzer {
+            name: "analyzer".to_owned(),
+            analyzer_exe: self.analyzer_exe.clone(),
+            analyzer_options: self.analyzer_options.clone(),
+            analyzer_env: self.analyzer_env.clone(),
+            timeout: None,
+            expected_outputs: vec![],
+        })
+    }
+}
+
+// Check the libraries of each analyzer, once the tools are in place.
+async fn check_analyzer_libraries(config: &Config) -> Result<()> {
+    let expand = config
+        .common
+        .expand()
+        .machine_id()
+        .job_id(&config.common.job_id)
+        .task_id(&config.common.task_id)
+        .setup_dir(&config.common.setup_dir)
+        .set_optional(
+            config.tools.clone().map(|t| t.local_path),
+            Expand::tools_dir,
+        )
+        .set_optional_ref(&config.common.extra_setup_dir, Expand::extra_setup_dir);
+
+    for analyzer in config.default_analyzer().iter().chain(&config.analyzers) {
+        let Ok(analyzer_exe) = expand.evaluate_value(&analyzer.analyzer_exe) else {
+            continue;
+        };
+
+        check_libraries(
+            "analyzer_exe",
+            Path::new(&analyzer_exe),
+            &analyzer.analyzer_env,
+            &expand,
+            &config.common.setup_dir,
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
 pub async fn run(config: Config) -> Result<()> {
     let task_dir = config
         .analysis
@@ -64,6 +182,7 @@ pub async fn run(config: Config) -> Result<()> {
     if let Some(tools) = &config.tools {
         tools.init_pull().await?;
     }
+    check_analyzer_libraries(&config).await?;
 
     let job_result_client = config.common.init_job_result().await?;
 
@@ -126,7 +245,11 @@ async fn run_existing(config: &Config, reports_dir: &Option<PathBuf>) -> Result<
         let mut read_dir = fs::read_dir(&crashes.local_path).await?;
         while let Some(file) = read_dir.next_entry().await? {
             debug!("Processing file {:?}", file);
-            run_tool(file.path(), config, reports_dir).await?;
+            let crash_blob = crashes
+                .remote_path
+                .as_ref()
+                .map(|remote_path| remote_path.blob(file.file_name().to_string_lossy()));
+            run_tool(file.path(), crash_blob.as_ref(), config, reports_dir).await?;
             count += 1;
 
             // sync the analysis container after every 10 inputs
@@ -167,9 +290,9 @@ async fn poll_inputs(
                     .parse(|data| BlobUrl::parse(str::from_utf8(data)?))
                     .with_context(|| format!("unable to parse URL from queue: {message:?}"))?;
                 if !already_checked(config, &input_url).await? {
-                    let destination_path = _copy(input_url, &tmp_dir).await?;
+                    let destination_path = _copy(input_url.clone(), &tmp_dir).await?;
 
-                    run_tool(destination_path, config, reports_dir).await?;
+                    run_tool(destination_path, Some(&input_url), config, reports_dir).await?;
                     config.analysis.sync_push().await?
                 }
                 message.delete().await?;
@@ -200,20 +323,157 @@ async fn _copy(input_url: BlobUrl, destination_folder: &OwnedDir) -> Result<Path
 
 pub async fn run_tool(
     input: impl AsRef<Path>,
+    crash_blob: Option<&BlobUrl>,
+    config: &Config,
+    reports_dir: &Option<PathBuf>,
+) -> Result<()> {
+    let input = input.as_ref();
+
+    if let Some(analyzer) = config.default_analyzer() {
+        let (mut cmd, _) = analyzer_command(
+            input,
+            crash_blob,
+            config,
+            &analyzer,
+            &config.analysis.local_path,
+            reports_dir,
+        )
+        .await?;
+        let analyzer_path = cmd.as_std().get_program().to_string_lossy().to_string();
+
+        info!("analyzing input with {:?}", cmd);
+        let output = cmd
+            .spawn()
+            .with_context(|| format!("analyzer failed to start: {analyzer_path}"))?;
+
+        monitor_process(output, "analyzer".to_string(), true, None)
+            .await
+            .with_context(|| format!("analyzer failed to run: {analyzer_path}"))?;
+    }
+
+    for analyzer in &config.analyzers {
+        run_analyzer(input, crash_blob, config, analyzer, reports_dir).await?;
+    }
+
+    Ok(())
+}
+
+// Run an analyzer of `analyzers`, recording why it failed, if it did, in its
+// output directory. Only errors with the output directory itself are returned.
+async fn run_analyzer(
+    input: &Path,
+    crash_blob: Option<&BlobUrl>,
+    config: &Config,
+    analyzer: &Analyzer,
+    reports_dir: &Option<PathBuf>,
+) -> Result<()> {
+    let input_stem = input
+        .file_stem()
+        .ok_or_else(|| format_err!("missing input file stem: {}", input.display()))?;
+    let output_dir = config
+        .analysis
+        .local_path
+        .join(input_stem)
+        .join(&analyzer.name);
+    fs::create_dir_all(&output_dir)
+        .await
+        .with_context(|| format!("unable to create output dir: {}", output_dir.display()))?;
+
+    let result = analyze(
+        input,
+        crash_blob,
+        config,
+        analyzer,
+        &output_dir,
+        reports_dir,
+    )
+    .await;
+    if let Err(err) = result {
+        warn!(
+            "analyzer {} failed on {}: {:?}",
+            analyzer.name,
+            input.display(),
+            err
+        );
+        fs::write(output_dir.join(TOOL_ERROR_FILE), format!("{err:?}\n")).await?;
+    }
+
+    Ok(())
+}
+
+async fn analyze(
+    input: &Path,
+    crash_blob: Option<&BlobUrl>,
     config: &Config,
+    analyzer: &Analyzer,
+    output_dir: &Path,
     reports_dir: &Option<PathBuf>,
 ) -> Result<()> {
+    let (mut cmd, expected_outputs) =
+        analyzer_command(input, crash_blob, config, analyzer, output_dir, reports_dir).await?;
+    let analyzer_path = cmd.as_std().get_program().to_string_lossy().to_string();
+
+    info!("analyzing input with {}: {:?}", analyzer.name, cmd);
+    let child = cmd
+        .spawn()
+        .with_context(|| format!("analyzer failed to start: {analyzer_path}"))?;
+
+    // The analyzer is killed if it times out, as its child is dropped.
+    let output = match analyzer.timeout {
+        Some(timeout) => {
+            tokio::time::timeout(Duration::from_secs(timeout), child.wait_with_output())
+                .await
+                .with_context(|| format!("analyzer timed out after {timeout} seconds"))?
+        }
+        None => child.wait_with_output().await,
+    }
+    .with_context(|| format!("analyzer failed to run: {analyzer_path}"))?;
+
+    if !output.status.success() {
+        bail!(
+            "analyzer failed: {}\nstderr:\n{}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    let mut missing = vec![];
+    for path in expected_outputs {
+        if !fs::try_exists(&path).await? {
+            missing.push(path.display().to_string());
+        }
+    }
+    if !missing.is_empty() {
+        bail!("missing expected outputs: {}", missing.join(", "));
+    }
+
+    Ok(())
+}
+
+// The placeholders derived from the input are set here, so that they are
+// updated for every input. Also returns the expected outputs of the analyzer.
+async fn analyzer_command(
+    input: &Path,
+    crash_blob: Option<&BlobUrl>,
+    config: &Config,
+    analyzer: &Analyzer,
+    output_dir: &Path,
+    reports_dir: &Option<PathBuf>,
+) -> Result<(Command, Vec<PathBuf>)> {
     let target_exe =
         try_resolve_setup_relative_path(&config.common.setup_dir, &config.target_exe).await?;
 
-    let expand = Expand::new(&config.common.machine_identity)
+    let expand = config
+        .common
+        .expand()
         .machine_id()
-        .input_path(&input)
+        .input_path(input)
+        .set_optional(crash_blob, Expand::crash_blob)
         .target_exe(&target_exe)
         .target_options(&config.target_options)
-        .analyzer_exe(&config.analyzer_exe)
-        .analyzer_options(&config.analyzer_options)
-        .output_dir(&config.analysis.local_path)
+        .analyzer_exe(&analyzer.analyzer_exe)
+        .analyzer_options(&analyzer.analyzer_options)
+        .output_dir(output_dir)
         .setup_dir(&config.common.setup_dir)
         .set_optional(
             config.tools.clone().map(|t| t.local_path),
@@ -246,7 +506,7 @@ pub async fn run_tool(
                 )
         });
 
-    let analyzer_path = expand.evaluate_value(&config.analyzer_exe)?;
+    let analyzer_path = expand.evaluate_value(&analyzer.analyzer_exe)?;
 
     let mut cmd = Command::new(&analyzer_path);
     cmd.kill_on_drop(true)
@@ -255,21 +515,196 @@ pub async fn run_tool(
         .stdout(Stdio::piped())
         .stderr(Stdio::piped());
 
-    for arg in expand.evaluate(&config.analyzer_options)? {
+    for arg in expand.evaluate(&analyzer.analyzer_options)? {
         cmd.arg(arg);
     }
 
-    for (k, v) in &config.analyzer_env {
+    for (k, v) in &analyzer.analyzer_env {
         cmd.env(k, expand.evaluate_value(v)?);
     }
 
-    info!("analyzing input with {:?}", cmd);
-    let output = cmd
-        .spawn()
-        .with_context(|| format!("analyzer failed to start: {analyzer_path}"))?;
+    let expected_outputs = expand
+        .evaluate(&analyzer.expected_outputs)?
+        .into_iter()
+        .map(|path| output_dir.join(path))
+        .collect();
 
-    monitor_process(output, "analyzer".to_string(), true, None)
-        .await
-        .with_context(|| format!("analyzer failed to run: {analyzer_path}"))?;
-    Ok(())
+    Ok((cmd, expected_outputs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    use crate::tasks::config::fixture;
+
+    fn config(
+        setup_dir: &Path,
+        analysis_dir: &Path,
+        analyzers: serde_json::Value,
+    ) -> Result<Config> {
+        let mut config = fixture::config(json!({
+            "target_exe": "fuzz.exe",
+            "target_options": [],
+            "analysis": { "path": analysis_dir },
+            "setup_dir": setup_dir,
+        }));
+        fixture::merge(&mut config, analyzers);
+
+        Ok(serde_json::from_value(config)?)
+    }
+
+    #[tokio::test]
+    async fn test_analyzer_command_per_input() -> Result<()> {
+        let setup_dir = tempdir()?;
+        let analysis_dir = tempdir()?;
+        let crashes_dir = tempdir()?;
+        fs::write(setup_dir.path().join("fuzz.exe"), "").await?;
+
+        let config = config(
+            setup_dir.path(),
+            analysis_dir.path(),
+            json!({
+                "analyzer_exe": "analyzer",
+                "analyzer_options": [
+                    "-o",
+                    "{input_file_stem}.analysis.json",
+                    "--crash",
+                    "{crash_container}/{crash_name}",
+                ],
+                "analyzer_env": {},
+            }),
+        )?;
+
+        // Analyze two inputs in turn, as the task does.
+        let mut commands = vec![];
+        for name in ["crash-1.bin", "crash-2.bin"] {
+            let input = crashes_dir.path().join(name);
+            fs::write(&input, name).await?;
+            let crash_blob = BlobUrl::parse(format!(
+                "https://contoso.blob.core.windows.net/crashes/{name}"
+            ))?;
+
+            let analyzer = config.default_analyzer().unwrap();
+            let (cmd, _) = analyzer_command(
+                &input,
+                Some(&crash_blob),
+                &config,
+                &analyzer,
+                analysis_dir.path(),
+                &None,
+            )
+            .await?;
+            let args: Vec<_> = cmd
+                .as_std()
+                .get_args()
+                .map(|arg| arg.to_string_lossy().to_string())
+                .collect();
+            commands.push(args);
+        }
+
+        assert_eq!(
+            commands,
+            [
+                [
+                    "-o",
+                    "crash-1.analysis.json",
+                    "--crash",
+                    "crashes/crash-1.bin"
+                ],
+                [
+                    "-o",
+                    "crash-2.analysis.json",
+                    "--crash",
+                    "crashes/crash-2.bin"
+                ],
+            ]
+        );
+        Ok(())
+    }
+    #[cfg(target_family = "unix")]
+    #[tokio::test]
+    async fn test_analyzer_pipeline() -> Result<()> {
+        let setup_dir = tempdir()?;
+        let analysis_dir = tempdir()?;
+        let crashes_dir = tempdir()?;
+        fs::write(setup_dir.path().join("fuzz.exe"), "").await?;
+
+        // `classify` fails on `crash-1`, and `stall` times out on every input,
+        // neither stopping the analyzers after them.
+        let config = config(
+            setup_dir.path(),
+            analysis_dir.path(),
+            json!({
+                "analyzers": [
+                    {
+                        "name": "classify",
+                        "analyzer_exe": "/bin/sh",
+                        "analyzer_options": [
+                            "-c",
+                            "test {input_file_stem} = crash-1 && echo unknown >&2 && exit 3; cp {input} {output_dir}/class.txt",
+                        ],
+                        "expected_outputs": ["class.txt"],
+                    },
+                    {
+                        "name": "stall",
+                        "analyzer_exe": "/bin/sh",
+                        "analyzer_options": ["-c", "sleep 10"],
+                        "timeout": 1,
+                    },
+                    {
+                        "name": "symbolize",
+                        "analyzer_exe": "/bin/sh",
+                        "analyzer_options": ["-c", "echo $INPUT_NAME > {output_dir}/{input_file_stem}.sym"],
+                        "analyzer_env": { "INPUT_NAME": "{input_file_name}" },
+                        "expected_outputs": ["{input_file_stem}.sym"],
+                    },
+                ],
+            }),
+        )?;
+        config.check_analyzers()?;
+
+        for name in ["crash-1.bin", "crash-2.bin"] {
+            let input = crashes_dir.path().join(name);
+            fs::write(&input, name).await?;
+            run_tool(&input, None, &config, &None).await?;
+        }
+
+        let read = |path: &str| std::fs::read_to_string(analysis_dir.path().join(path));
+
+        let error = read("crash-1/classify/tool_error.txt")?;
+        assert!(error.contains("exit status: 3"), "{error}");
+        assert!(error.contains("unknown"), "{error}");
+        assert!(read("crash-1/classify/class.txt").is_err());
+        assert_eq!(read("crash-2/classify/class.txt")?, "crash-2.bin");
+        assert!(read("crash-2/classify/tool_error.txt").is_err());
+
+        for stem in ["crash-1", "crash-2"] {
+            let error = read(&format!("{stem}/stall/tool_error.txt"))?;
+            assert!(error.contains("timed out after 1 seconds"), "{error}");
+
+            let symbolized = read(&format!("{stem}/symbolize/{stem}.sym"))?;
+            assert_eq!(symbolized, format!("{stem}.bin\n"));
+            assert!(read(&format!("{stem}/symbolize/tool_error.txt")).is_err());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_analyzers() -> Result<()> {
+        let dir = tempdir()?;
+        let check = |analyzers| config(dir.path(), dir.path(), analyzers)?.check_analyzers();
+        let analyzer = |name: &str| json!({ "name": name, "analyzer_exe": "/bin/true" });
+
+        assert!(check(json!({})).is_err());
+        assert!(check(json!({ "analyzers": [analyzer("a"), analyzer("b")] })).is_ok());
+        assert!(check(json!({ "analyzers": [analyzer("a"), analyzer("a")] })).is_err());
+        assert!(check(json!({ "analyzers": [analyzer("../a")] })).is_err());
+
+        Ok(())
+    }
 }
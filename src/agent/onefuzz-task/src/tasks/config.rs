@@ -8,16 +8,25 @@ use crate::tasks::{
     analysis, fuzz,
     heartbeat::{init_task_heartbeat, TaskHeartbeatClient},
     merge, regression, report,
+    stats::process::ProcessStatsConfig,
+    utils::{check_libraries, resolve_setup_relative_path},
 };
 use anyhow::{Context, Result};
+use debuggable_module::symsrv::SymbolPath;
 use onefuzz::{
+    config_validation,
+    env::env_file_paths,
+    expand::Expand,
     machine_id::MachineIdentity,
+    qemu::QemuUser,
+    sanitizer::{add_default_options, detect_sanitizers},
+    symsrv::SymbolServers,
     syncdir::{SyncOperation, SyncedDir},
 };
 use onefuzz_result::job_result::{init_job_result, TaskJobResultClient};
 use onefuzz_telemetry::{
-    self as telemetry, Event::task_start, EventData, InstanceTelemetryKey, MicrosoftTelemetryKey,
-    Role,
+    self as telemetry, context::CorrelationContext, Event::task_start, EventData,
+    InstanceTelemetryKey, MicrosoftTelemetryKey, Role,
 };
 use reqwest::Url;
 use serde::{self, Deserialize};
@@ -29,6 +38,13 @@ use std::{
 use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
+mod fields;
+#[cfg(test)]
+pub(crate) mod fixture;
+mod platform;
+#[cfg(test)]
+mod tests;
+
 const DEFAULT_MIN_AVAILABLE_MEMORY_MB: u64 = 100;
 
 pub fn default_min_available_memory_mb() -> u64 {
@@ -81,11 +97,65 @@ pub struct CommonConfig {
     #[serde(default)]
     pub tags: HashMap<String, String>,
 
+    /// Leave unrecognized `{placeholders}` in command lines and environment
+    /// values as-is, rather than failing the expansion.
+    ///
+    /// Only intended for configs which relied on the previous pass-through
+    /// behavior; new configs should escape literal braces as `{{...}}`.
+    #[serde(default)]
+    pub allow_unknown_placeholders: bool,
+
+    /// Allow `${env:NAME}` references in command lines and environment values
+    /// to read the node's environment. Off by default, so that untrusted
+    /// configs can't read values from the agent's environment.
+    #[serde(default)]
+    pub allow_env_placeholders: bool,
+
+    /// Interpret `target_options` with a shell, `sh` or `cmd` on Windows,
+    /// instead of passing each option to the target as a separate argument.
+    ///
+    /// Only intended for targets which need shell syntax, such as redirection.
+    /// The options are joined with spaces, so must be quoted for the shell.
+    #[serde(default)]
+    pub target_options_shell: bool,
+
+    /// Run the target with QEMU user mode emulation, such as to fuzz aarch64
+    /// targets on x86_64 nodes. Only supported on Linux.
+    pub qemu_user: Option<QemuUser>,
+
+    /// Symbol servers to download the PDBs of Windows targets from, when they
+    /// aren't beside the target. Servers in `_NT_SYMBOL_PATH` are also used.
+    #[serde(default)]
+    pub symbol_servers: Vec<Url>,
+
+    /// Local symbol store to cache downloaded PDBs in. Defaults to the first
+    /// store in `_NT_SYMBOL_PATH`, or `symbols` in the OneFuzz root.
+    pub symbol_cache: Option<PathBuf>,
+
+    /// Periodic samples of the resources used by the target process tree of
+    /// fuzzing tasks.
+    #[serde(default)]
+    pub process_stats: ProcessStatsConfig,
+
     pub from_agent_to_task_endpoint: String,
     pub from_task_to_agent_endpoint: String,
+
+    /// Keys which weren't claimed by the task or common config. Checked by
+    /// `Config::from_file`, so that misspelled settings are reported instead
+    /// of silently falling back to their defaults.
+    #[serde(flatten)]
+    pub unknown_fields: HashMap<String, serde_json::Value>,
 }
 
 impl CommonConfig {
+    /// Create an `Expand` for this task, honoring the task-level expansion
+    /// settings.
+    pub fn expand(&self) -> Expand<'_> {
+        Expand::new(&self.machine_identity)
+            .allow_unknown_placeholders(self.allow_unknown_placeholders)
+            .allow_env_placeholders(self.allow_env_placeholders)
+    }
+
     pub async fn init_heartbeat(
         &self,
         initial_delay: Option<Duration>,
@@ -107,6 +177,31 @@ impl CommonConfig {
         }
     }
 
+    /// The symbol servers of the task, if any are configured.
+    pub fn symbol_servers(&self) -> Result<Option<SymbolServers>> {
+        let env = SymbolPath::from_env();
+
+        let mut servers = self.symbol_servers.clone();
+        for server in &env.servers {
+            match Url::parse(server) {
+                Ok(url) => servers.push(url),
+                Err(err) => warn!("ignoring symbol server {}: {}", server, err),
+            }
+        }
+
+        if servers.is_empty() {
+            return Ok(None);
+        }
+
+        let cache = match (&self.symbol_cache, env.stores.first()) {
+            (Some(cache), _) => cache.clone(),
+            (None, Some(store)) => store.clone(),
+            (None, None) => onefuzz::fs::onefuzz_root()?.join("symbols"),
+        };
+
+        Ok(Some(SymbolServers::new(cache, servers)))
+    }
+
     pub async fn init_job_result(&self) -> Result<Option<TaskJobResultClient>> {
         match &self.job_result_queue {
             Some(url) => {
@@ -180,7 +275,7 @@ impl Config {
         let json = std::fs::read_to_string(path)
             .with_context(|| format!("loading config from {}", path.display()))?;
 
-        let mut config = serde_json::from_str::<Self>(&json).context("deserializing Config")?;
+        let mut config = Self::parse(&json).context("deserializing Config")?;
 
         // override the setup_dir in the config file with the parameter value if specified
         config.common_mut().setup_dir = setup_dir;
@@ -189,6 +284,174 @@ impl Config {
         Ok(config)
     }
 
+    /// Deserialize a task config, rejecting keys which aren't known for its
+    /// task type.
+    ///
+    /// Platform-specific values are resolved for the current platform, and
+    /// field values are then validated first, so that every invalid value is
+    /// reported together.
+    pub fn parse(json: &str) -> Result<Self> {
+        let resolved = match serde_json::from_str::<serde_json::Value>(json) {
+            Ok(mut raw) => {
+                platform::resolve(&mut raw, platform::current_platform())?;
+                config_validation::validate_task_config(&raw)?;
+                Some(raw.to_string())
+            }
+            Err(_) => None,
+        };
+        let json = resolved.as_deref().unwrap_or(json);
+
+        let config = match serde_json::from_str::<Self>(json) {
+            Ok(config) => config,
+            Err(err) => return Err(Self::explain_error(json, err)),
+        };
+
+        let task_type = config.task_type();
+        fields::check_unknown_fields(
+            task_type,
+            fields::task_fields(task_type),
+            fields::legacy_fields(task_type),
+            &config.common().unknown_fields,
+        )?;
+
+        if config.common().target_options_shell && !config.supports_target_options_shell() {
+            bail!("`target_options_shell` is not supported by {task_type} tasks");
+        }
+
+        if config.common().qemu_user.is_some() && !config.supports_qemu_user() {
+            bail!("`qemu_user` is not supported by {task_type} tasks");
+        }
+
+        match &config {
+            Config::LibFuzzerFuzz(c) => c.check_parallel_mode()?,
+            Config::LibFuzzerDotnetFuzz(c) => c.check_parallel_mode()?,
+            _ => {}
+        }
+
+        if let Config::GenericGenerator(c) = &config {
+            c.generators()?;
+        }
+
+        if let Config::GenericAnalysis(c) = &config {
+            c.check_analyzers()?;
+        }
+
+        match &config {
+            Config::GenericReport(c) => {
+                c.check_minimizer()?;
+                c.stack_normalization.check()?;
+                c.report_routing.check()?;
+                if let Some(redaction) = &c.redaction {
+                    redaction.check()?;
+                }
+            }
+            Config::LibFuzzerReport(c) => {
+                c.stack_normalization.check()?;
+                c.report_routing.check()?;
+                if let Some(redaction) = &c.redaction {
+                    redaction.check()?;
+                }
+            }
+            _ => {}
+        }
+
+        Ok(config)
+    }
+
+    /// Add the task type, and a suggestion for any misspelled keys, to a
+    /// deserialization error.
+    ///
+    /// A misspelled required key surfaces as a missing field, so the raw
+    /// config is checked for unknown keys before falling back to rewriting
+    /// nested "unknown field" errors.
+    fn explain_error(json: &str, err: serde_json::Error) -> anyhow::Error {
+        let raw: Option<HashMap<String, serde_json::Value>> = serde_json::from_str(json).ok();
+        let Some(task_type) = raw
+            .as_ref()
+            .and_then(|raw| raw.get("task_type"))
+            .and_then(|task_type| task_type.as_str())
+            .and_then(fields::canonical_task_type)
+        else {
+            return err.into();
+        };
+
+        if let Some(mut raw) = raw {
+            raw.retain(|key, _| {
+                key != "task_type"
+                    && !fields::task_fields(task_type).contains(&key.as_str())
+                    && !fields::COMMON_FIELDS.contains(&key.as_str())
+            });
+
+            if let Err(unknown) = fields::check_unknown_fields(
+                task_type,
+                fields::task_fields(task_type),
+                fields::legacy_fields(task_type),
+                &raw,
+            ) {
+                return anyhow::Error::new(err).context(unknown);
+            }
+        }
+
+        fields::explain_unknown_field(task_type, err)
+    }
+
+    /// The name of the task type, as given in the `task_type` key.
+    pub fn task_type(&self) -> &'static str {
+        match self {
+            Config::Coverage(_) => "coverage",
+            Config::DotnetCoverage(_) => "dotnet_coverage",
+            Config::DotnetCrashReport(_) => "dotnet_crash_report",
+            Config::LibFuzzerDotnetFuzz(_) => "libfuzzer_dotnet_fuzz",
+            Config::LibFuzzerFuzz(_) => "libfuzzer_fuzz",
+            Config::LibFuzzerMerge(_) => "libfuzzer_merge",
+            Config::LibFuzzerReport(_) => "libfuzzer_crash_report",
+            Config::LibFuzzerRegression(_) => "libfuzzer_regression",
+            Config::GenericAnalysis(_) => "generic_analysis",
+            Config::GenericMerge(_) => "generic_merge",
+            Config::GenericReport(_) => "generic_crash_report",
+            Config::GenericSupervisor(_) => "generic_supervisor",
+            Config::GenericGenerator(_) => "generic_generator",
+            Config::GenericRegression(_) => "generic_regression",
+        }
+    }
+
+    /// Whether the task runs the target itself, so that its options can be
+    /// interpreted by a shell. The other tasks record coverage from the target
+    /// process, or leave running it to another tool.
+    fn supports_target_options_shell(&self) -> bool {
+        matches!(
+            self,
+            Config::LibFuzzerDotnetFuzz(_)
+                | Config::LibFuzzerFuzz(_)
+                | Config::LibFuzzerMerge(_)
+                | Config::LibFuzzerReport(_)
+                | Config::LibFuzzerRegression(_)
+                | Config::GenericGenerator(_)
+                | Config::GenericReport(_)
+                | Config::GenericRegression(_)
+        )
+    }
+
+    /// Whether the task can run the target under QEMU. Coverage is recorded by
+    /// tracing the target process, which would then be QEMU itself.
+    fn supports_qemu_user(&self) -> bool {
+        // A .NET assembly is run by the libfuzzer-dotnet harness.
+        if let Config::LibFuzzerFuzz(c) = self {
+            return c.extra.target_type == fuzz::libfuzzer::generic::TargetType::Native;
+        }
+
+        matches!(
+            self,
+            Config::LibFuzzerMerge(_)
+                | Config::LibFuzzerReport(_)
+                | Config::LibFuzzerRegression(_)
+                | Config::GenericSupervisor(_)
+                | Config::GenericGenerator(_)
+                | Config::GenericReport(_)
+                | Config::GenericRegression(_)
+        )
+    }
+
     fn common_mut(&mut self) -> &mut CommonConfig {
         match self {
             Config::Coverage(c) => &mut c.common,
@@ -208,6 +471,210 @@ impl Config {
         }
     }
 
+    /// The environment variables set by the task, by config field.
+    fn envs(&self) -> Vec<(&'static str, &HashMap<String, String>)> {
+        match self {
+            Config::Coverage(c) => vec![("target_env", &c.target_env)],
+            Config::DotnetCoverage(c) => vec![("target_env", &c.target_env)],
+            Config::DotnetCrashReport(c) => vec![("target_env", &c.target_env)],
+            Config::LibFuzzerDotnetFuzz(c) => vec![("target_env", &c.target_env)],
+            Config::LibFuzzerFuzz(c) => vec![("target_env", &c.target_env)],
+            Config::LibFuzzerMerge(c) => vec![("target_env", &c.target_env)],
+            Config::LibFuzzerReport(c) => vec![("target_env", &c.target_env)],
+            Config::LibFuzzerRegression(c) => vec![("target_env", &c.target_env)],
+            Config::GenericAnalysis(c) => {
+                let mut envs = vec![("analyzer_env", &c.analyzer_env)];
+                envs.extend(c.analyzers.iter().map(|a| ("analyzers", &a.analyzer_env)));
+                envs
+            }
+            Config::GenericMerge(c) => vec![("supervisor_env", &c.supervisor_env)],
+            Config::GenericReport(c) => vec![("target_env", &c.target_env)],
+            Config::GenericSupervisor(c) => vec![("supervisor_env", &c.supervisor_env)],
+            Config::GenericGenerator(c) => vec![
+                ("generator_env", &c.generator_env),
+                ("target_env", &c.target_env),
+            ],
+            Config::GenericRegression(c) => vec![("target_env", &c.target_env)],
+        }
+    }
+
+    /// The target executable of the task, and the environment it's run with.
+    /// The targets of .NET tasks are managed assemblies, which import no native
+    /// libraries themselves.
+    fn target_exe(&self) -> Option<(&Path, &HashMap<String, String>)> {
+        match self {
+            Config::Coverage(c) => Some((&c.target_exe, &c.target_env)),
+            Config::DotnetCoverage(_) => None,
+            Config::DotnetCrashReport(_) => None,
+            Config::LibFuzzerDotnetFuzz(_) => None,
+            Config::LibFuzzerFuzz(c) => {
+                let native = c.extra.target_type == fuzz::libfuzzer::generic::TargetType::Native;
+                native.then_some((&c.target_exe, &c.target_env))
+            }
+            Config::LibFuzzerMerge(c) => Some((&c.target_exe, &c.target_env)),
+            Config::LibFuzzerReport(c) => Some((&c.target_exe, &c.target_env)),
+            Config::LibFuzzerRegression(c) => Some((&c.target_exe, &c.target_env)),
+            Config::GenericAnalysis(c) => Some((&c.target_exe, &c.analyzer_env)),
+            Config::GenericMerge(c) => Some((&c.target_exe, &c.supervisor_env)),
+            Config::GenericReport(c) => Some((&c.target_exe, &c.target_env)),
+            Config::GenericSupervisor(c) => {
+                c.target_exe.as_deref().map(|exe| (exe, &c.supervisor_env))
+            }
+            Config::GenericGenerator(c) => Some((&c.target_exe, &c.target_env)),
+            Config::GenericRegression(c) => Some((&c.target_exe, &c.target_env)),
+        }
+    }
+
+    // The environment of `target_exe()`.
+    fn target_env_mut(&mut self) -> Option<&mut HashMap<String, String>> {
+        match self {
+            Config::Coverage(c) => Some(&mut c.target_env),
+            Config::DotnetCoverage(_) => None,
+            Config::DotnetCrashReport(_) => None,
+            Config::LibFuzzerDotnetFuzz(_) => None,
+            Config::LibFuzzerFuzz(c) => {
+                let native = c.extra.target_type == fuzz::libfuzzer::generic::TargetType::Native;
+                native.then_some(&mut c.target_env)
+            }
+            Config::LibFuzzerMerge(c) => Some(&mut c.target_env),
+            Config::LibFuzzerReport(c) => Some(&mut c.target_env),
+            Config::LibFuzzerRegression(c) => Some(&mut c.target_env),
+            Config::GenericAnalysis(c) => Some(&mut c.analyzer_env),
+            Config::GenericMerge(c) => Some(&mut c.supervisor_env),
+            Config::GenericReport(c) => Some(&mut c.target_env),
+            Config::GenericSupervisor(c) => c.target_exe.is_some().then_some(&mut c.supervisor_env),
+            Config::GenericGenerator(c) => Some(&mut c.target_env),
+            Config::GenericRegression(c) => Some(&mut c.target_env),
+        }
+    }
+
+    /// Add the default options of each sanitizer built into the target to its
+    /// environment, for the options not already set, so that its errors are
+    /// detected as crashes even if the job sets no options. The defaults
+    /// added are logged.
+    pub async fn add_sanitizer_defaults(&mut self) -> Result<()> {
+        let Some((target_exe, _)) = self.target_exe() else {
+            return Ok(());
+        };
+
+        let setup_dir = &self.common().setup_dir;
+        let Some(target_exe) = resolve_setup_relative_path(setup_dir, target_exe).await? else {
+            return Ok(());
+        };
+
+        if !tokio::fs::metadata(&target_exe)
+            .await
+            .is_ok_and(|m| m.is_file())
+        {
+            return Ok(());
+        }
+
+        let sanitizers = detect_sanitizers(&target_exe).await?;
+        let Some(env) = self.target_env_mut() else {
+            return Ok(());
+        };
+
+        for sanitizer in sanitizers {
+            let var = sanitizer.options_var();
+            let added = add_default_options(env, sanitizer);
+            if added.is_empty() {
+                info!("{sanitizer} detected in target_exe, {var} already sets every default");
+            } else {
+                info!(
+                    "{sanitizer} detected in target_exe, added defaults to {var}: {}",
+                    added.join(":")
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check that the libraries imported by the target are found once the
+    /// setup container is in place, searching as the loader does when the
+    /// task runs the target. Otherwise, each run of the target would fail, as
+    /// would be reported only as crashes or a lack of coverage.
+    ///
+    /// Targets run under QEMU are of another architecture, whose libraries are
+    /// found in its sysroot, so they aren't checked.
+    pub async fn check_target_libraries(&self) -> Result<()> {
+        let common = self.common();
+        if common.qemu_user.is_some() {
+            return Ok(());
+        }
+
+        let Some((target_exe, target_env)) = self.target_exe() else {
+            return Ok(());
+        };
+
+        let Some(target_exe) = resolve_setup_relative_path(&common.setup_dir, target_exe).await?
+        else {
+            return Ok(());
+        };
+
+        let expand = common
+            .expand()
+            .machine_id()
+            .job_id(&common.job_id)
+            .task_id(&common.task_id)
+            .setup_dir(&common.setup_dir)
+            .set_optional_ref(&common.extra_setup_dir, Expand::extra_setup_dir);
+
+        check_libraries(
+            "target_exe",
+            &target_exe,
+            target_env,
+            &expand,
+            &common.setup_dir,
+        )
+        .await
+    }
+
+    /// Check that the files named by environment variables known to reference
+    /// them, such as `LD_PRELOAD` or the `suppressions` of `ASAN_OPTIONS`,
+    /// exist once the setup container is in place. Otherwise, a typo would at
+    /// most be a warning from the dynamic linker or sanitizer.
+    ///
+    /// Values are expanded as for the task, but values with placeholders only
+    /// known when the target is run, such as `{input}`, are skipped.
+    pub async fn check_env_files(&self) -> Result<()> {
+        let common = self.common();
+        let expand = common
+            .expand()
+            .machine_id()
+            .job_id(&common.job_id)
+            .task_id(&common.task_id)
+            .setup_dir(&common.setup_dir)
+            .set_optional_ref(&common.extra_setup_dir, Expand::extra_setup_dir);
+
+        let mut missing = vec![];
+        for (field, env) in self.envs() {
+            let mut env: Vec<_> = env.iter().collect();
+            env.sort();
+
+            for (name, value) in env {
+                let Ok(value) = expand.evaluate_value(value) else {
+                    continue;
+                };
+
+                for path in env_file_paths(name, &value) {
+                    if tokio::fs::metadata(&path).await.is_err() {
+                        missing.push(format!("`{field}.{name}`: {}", path.display()));
+                    }
+                }
+            }
+        }
+
+        if !missing.is_empty() {
+            bail!(
+                "files named by environment variables do not exist: {}",
+                missing.join(", ")
+            );
+        }
+
+        Ok(())
+    }
+
     pub fn common(&self) -> &CommonConfig {
         match self {
             Config::Coverage(c) => &c.common,
@@ -247,12 +714,12 @@ impl Config {
 
         match self {
             Config::GenericGenerator(c) => {
-                event!(task_start; EventData::Type = event_type, EventData::ToolName = c.generator_exe.clone());
-                metric!(task_start; 1.0; EventData::Type = event_type, EventData::ToolName = c.generator_exe.clone());
+                event!(task_start; EventData::Type = event_type, EventData::ToolName = c.tool_name());
+                metric!(task_start; 1.0; EventData::Type = event_type, EventData::ToolName = c.tool_name());
             }
             Config::GenericAnalysis(c) => {
-                event!(task_start; EventData::Type = event_type, EventData::ToolName = c.analyzer_exe.clone());
-                metric!(task_start; 1.0; EventData::Type = event_type, EventData::ToolName = c.analyzer_exe.clone());
+                event!(task_start; EventData::Type = event_type, EventData::ToolName = c.tool_name());
+                metric!(task_start; 1.0; EventData::Type = event_type, EventData::ToolName = c.tool_name());
             }
             _ => {
                 event!(task_start; EventData::Type = event_type);
@@ -262,6 +729,20 @@ impl Config {
     }
 
     pub async fn run(self) -> Result<()> {
+        // Scoped to the task, rather than process-wide like the properties
+        // below, since local runs host several tasks in one process.
+        let context = CorrelationContext::new()
+            .with(EventData::JobId(self.common().job_id))
+            .with(EventData::TaskId(self.common().task_id))
+            .with(EventData::MachineId(
+                self.common().machine_identity.machine_id,
+            ))
+            .with(EventData::InstanceId(self.common().instance_id));
+
+        context.scope(self.run_in_context()).await
+    }
+
+    async fn run_in_context(mut self) -> Result<()> {
         telemetry::set_property(EventData::JobId(self.common().job_id));
         telemetry::set_property(EventData::TaskId(self.common().task_id));
         telemetry::set_property(EventData::MachineId(
@@ -275,6 +756,10 @@ impl Config {
             telemetry::set_property(EventData::ScalesetId(scaleset_name.to_string()));
         }
 
+        self.check_env_files().await?;
+        self.check_target_libraries().await?;
+        self.add_sanitizer_defaults().await?;
+
         info!("agent ready, dispatching task");
         self.report_event();
 
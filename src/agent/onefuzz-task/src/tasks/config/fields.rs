@@ -0,0 +1,526 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Detection of unrecognized keys in task configs.
+//!
+//! `CommonConfig` is flattened into every task config, which rules out
+//! `#[serde(deny_unknown_fields)]`. Instead, `CommonConfig` collects whatever
+//! keys are left over once the task and common fields have been deserialized,
+//! and they are checked here, so that typos can be reported along with the most
+//! likely intended key.
+
+use anyhow::{Error, Result};
+use regex::Regex;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Keys accepted by every task type, see `CommonConfig`.
+pub const COMMON_FIELDS: &[&str] = &[
+    "job_id",
+    "task_id",
+    "instance_id",
+    "heartbeat_queue",
+    "job_result_queue",
+    "instance_telemetry_key",
+    "microsoft_telemetry_key",
+    "logs",
+    "setup_dir",
+    "extra_setup_dir",
+    "extra_output",
+    "min_available_memory_mb",
+    "machine_identity",
+    "tags",
+    "allow_unknown_placeholders",
+    "allow_env_placeholders",
+    "target_options_shell",
+    "qemu_user",
+    "symbol_servers",
+    "symbol_cache",
+    "process_stats",
+    "from_agent_to_task_endpoint",
+    "from_task_to_agent_endpoint",
+];
+
+/// Keys the service sends for every task type, but which tasks don't read.
+///
+/// The `extra_setup` container is synced by the agent, and passed to the task
+/// on the command line instead.
+pub const IGNORED_FIELDS: &[&str] = &["extra_setup"];
+
+/// Maps a `task_type` value, either the variant name or its alias, to the name
+/// used in error messages.
+pub fn canonical_task_type(task_type: &str) -> Option<&'static str> {
+    let canonical = match task_type {
+        "Coverage" | "coverage" => "coverage",
+        "DotnetCoverage" | "dotnet_coverage" => "dotnet_coverage",
+        "DotnetCrashReport" | "dotnet_crash_report" => "dotnet_crash_report",
+        "LibFuzzerDotnetFuzz" | "libfuzzer_dotnet_fuzz" => "libfuzzer_dotnet_fuzz",
+        "LibFuzzerFuzz" | "libfuzzer_fuzz" => "libfuzzer_fuzz",
+        "LibFuzzerReport" | "libfuzzer_crash_report" => "libfuzzer_crash_report",
+        "LibFuzzerMerge" | "libfuzzer_merge" => "libfuzzer_merge",
+        "LibFuzzerRegression" | "libfuzzer_regression" => "libfuzzer_regression",
+        "GenericAnalysis" | "generic_analysis" => "generic_analysis",
+        "GenericGenerator" | "generic_generator" => "generic_generator",
+        "GenericSupervisor" | "generic_supervisor" => "generic_supervisor",
+        "GenericMerge" | "generic_merge" => "generic_merge",
+        "GenericReport" | "generic_crash_report" => "generic_crash_report",
+        "GenericRegression" | "generic_regression" => "generic_regression",
+        _ => return None,
+    };
+
+    Some(canonical)
+}
+
+/// Keys accepted by a task type, in addition to `COMMON_FIELDS`.
+///
+/// These must be kept in sync with the task config structs.
+pub fn task_fields(task_type: &str) -> &'static [&'static str] {
+    match task_type {
+        "coverage" => &[
+            "target_exe",
+            "target_env",
+            "target_options",
+            "target_timeout",
+            "target_type",
+            "coverage_filter",
+            "module_allowlist",
+            "module_denylist",
+            "source_allowlist",
+            "input_queue",
+            "readonly_inputs",
+            "coverage",
+            "output_formats",
+            "sources",
+            "source_path_map",
+            "reprocess_inputs",
+            "follow_children",
+            "analysis_cache",
+            "summary_interval",
+        ],
+        "dotnet_coverage" => &[
+            "target_exe",
+            "target_env",
+            "target_options",
+            "target_timeout",
+            "input_queue",
+            "readonly_inputs",
+            "coverage",
+            "tools",
+        ],
+        "dotnet_crash_report" => &[
+            "target_exe",
+            "target_env",
+            "target_options",
+            "target_timeout",
+            "input_queue",
+            "crashes",
+            "reports",
+            "unique_reports",
+            "no_repro",
+            "tools",
+            "check_fuzzer_help",
+            "check_retry_count",
+            "minimized_stack_depth",
+            "check_queue",
+        ],
+        "libfuzzer_fuzz" => &[
+            "inputs",
+            "readonly_inputs",
+            "crashes",
+            "crashdumps",
+            "target_exe",
+            "target_env",
+            "target_options",
+            "target_workers",
+            "ensemble_sync_delay",
+            "check_fuzzer_help",
+            "expect_crash_on_failure",
+            "fork_mode",
+            "fork_ignore_crashes",
+            "fork_ignore_ooms",
+            "jobs_mode",
+            "jobs",
+            "restart_delay",
+            "oom",
+            "leaks",
+            "rss_limit_mb",
+            "dictionary",
+            "dictionary_name",
+            "max_initial_corpus",
+            "corpus_snapshots",
+            "target_type",
+            "target_class",
+            "target_method",
+            "tools",
+            "dotnet_root",
+            "dotnet_settings",
+        ],
+        "libfuzzer_dotnet_fuzz" => &[
+            "inputs",
+            "readonly_inputs",
+            "crashes",
+            "crashdumps",
+            "target_exe",
+            "target_env",
+            "target_options",
+            "target_workers",
+            "ensemble_sync_delay",
+            "check_fuzzer_help",
+            "expect_crash_on_failure",
+            "fork_mode",
+            "fork_ignore_crashes",
+            "fork_ignore_ooms",
+            "jobs_mode",
+            "jobs",
+            "restart_delay",
+            "oom",
+            "leaks",
+            "rss_limit_mb",
+            "dictionary",
+            "dictionary_name",
+            "max_initial_corpus",
+            "corpus_snapshots",
+            "target_assembly",
+            "target_class",
+            "target_method",
+            "tools",
+            "dotnet_root",
+            "dotnet_settings",
+        ],
+        "libfuzzer_crash_report" => &[
+            "target_exe",
+            "target_env",
+            "target_options",
+            "target_timeout",
+            "input_queue",
+            "crashes",
+            "reports",
+            "unique_reports",
+            "no_repro",
+            "check_fuzzer_help",
+            "check_retry_count",
+            "repro_retries",
+            "repro_retry_timeouts",
+            "repro_retry_asan_options",
+            "minimized_stack_depth",
+            "check_queue",
+            "minimized_crashes",
+            "minimize_timeout",
+            "source_path_map",
+            "report_all_findings",
+            "stack_normalization",
+            "report_type_containers",
+            "suppressed_report_types",
+            "redaction",
+            "no_repro_bundles",
+            "no_repro_bundle_max_size",
+            "no_repro_bundles_max_total_size",
+            "report_concurrency",
+            "debug_file_dirs",
+        ],
+        "libfuzzer_merge" => &[
+            "target_exe",
+            "target_env",
+            "target_options",
+            "input_queue",
+            "inputs",
+            "unique_inputs",
+            "preserve_existing_outputs",
+            "check_fuzzer_help",
+            "target_timeout",
+            "merge_quarantine",
+        ],
+        "libfuzzer_regression" => &[
+            "target_exe",
+            "target_options",
+            "target_env",
+            "target_timeout",
+            "crashes",
+            "regression_reports",
+            "report_list",
+            "unique_reports",
+            "reports",
+            "no_repro",
+            "readonly_inputs",
+            "check_fuzzer_help",
+            "check_retry_count",
+            "minimized_stack_depth",
+            "bisect",
+        ],
+        "generic_analysis" => &[
+            "analyzer_exe",
+            "analyzer_options",
+            "analyzer_env",
+            "analyzers",
+            "target_exe",
+            "target_options",
+            "input_queue",
+            "crashes",
+            "analysis",
+            "tools",
+            "reports",
+            "unique_reports",
+            "no_repro",
+        ],
+        "generic_generator" => &[
+            "generator_exe",
+            "generator_env",
+            "generator_options",
+            "generators",
+            "readonly_inputs",
+            "crashes",
+            "tools",
+            "target_exe",
+            "target_env",
+            "target_options",
+            "target_timeout",
+            "check_asan_log",
+            "check_debugger",
+            "check_retry_count",
+            "rename_output",
+            "ensemble_sync_delay",
+            "max_initial_corpus",
+        ],
+        "generic_supervisor" => &[
+            "inputs",
+            "crashes",
+            "crashdumps",
+            "supervisor_exe",
+            "supervisor_env",
+            "supervisor_options",
+            "supervisor_input_marker",
+            "target_exe",
+            "target_options",
+            "tools",
+            "wait_for_files",
+            "stats_file",
+            "stats_format",
+            "stats_keys",
+            "ensemble_sync_delay",
+            "max_initial_corpus",
+            "corpus_snapshots",
+            "reports",
+            "unique_reports",
+            "no_repro",
+            "coverage",
+            "afl",
+            "honggfuzz",
+        ],
+        "generic_merge" => &[
+            "supervisor_exe",
+            "supervisor_options",
+            "supervisor_env",
+            "supervisor_input_marker",
+            "target_exe",
+            "target_options",
+            "target_options_merge",
+            "tools",
+            "input_queue",
+            "inputs",
+            "unique_inputs",
+        ],
+        "generic_crash_report" => &[
+            "target_exe",
+            "target_options",
+            "target_env",
+            "input_queue",
+            "crashes",
+            "reports",
+            "unique_reports",
+            "no_repro",
+            "target_timeout",
+            "target_memory_limit_mb",
+            "check_asan_log",
+            "check_debugger",
+            "check_retry_count",
+            "repro_retries",
+            "repro_retry_timeouts",
+            "repro_retry_asan_options",
+            "check_queue",
+            "minimized_stack_depth",
+            "minimized_crashes",
+            "minimizer_exe",
+            "minimizer_options",
+            "minimize_timeout",
+            "source_path_map",
+            "report_all_findings",
+            "stack_normalization",
+            "report_type_containers",
+            "suppressed_report_types",
+            "redaction",
+            "no_repro_bundles",
+            "no_repro_bundle_max_size",
+            "no_repro_bundles_max_total_size",
+            "report_concurrency",
+            "debug_file_dirs",
+            "crash_dumps",
+            "crash_dump",
+        ],
+        "generic_regression" => &[
+            "target_exe",
+            "target_options",
+            "target_env",
+            "target_timeout",
+            "target_memory_limit_mb",
+            "crashes",
+            "regression_reports",
+            "report_list",
+            "reports",
+            "unique_reports",
+            "no_repro",
+            "readonly_inputs",
+            "check_asan_log",
+            "check_debugger",
+            "check_retry_count",
+            "minimized_stack_depth",
+            "bisect",
+        ],
+        _ => &[],
+    }
+}
+
+/// Keys the service sends for a task type, but which the task no longer reads.
+///
+/// Accepted without error so that agents keep working with older services.
+pub fn legacy_fields(task_type: &str) -> &'static [&'static str] {
+    match task_type {
+        "dotnet_coverage" => &["coverage_filter"],
+        "generic_merge" => &[
+            "preserve_existing_outputs",
+            "stats_file",
+            "stats_format",
+            "readonly_inputs",
+        ],
+        _ => &[],
+    }
+}
+
+/// Reports every key in `unknown` which is not accepted by `task_type`.
+///
+/// `fields` are the keys the task type knows about, and are used to suggest
+/// the intended key. `ignored` are keys which are accepted without being used,
+/// such as settings that older services still send. Keys with a `null` value
+/// are always accepted, as they are equivalent to omitting the key.
+pub fn check_unknown_fields(
+    task_type: &str,
+    fields: &[&str],
+    ignored: &[&str],
+    unknown: &HashMap<String, Value>,
+) -> Result<()> {
+    let mut keys: Vec<_> = unknown
+        .iter()
+        .filter(|(key, value)| {
+            !value.is_null()
+                && !ignored.contains(&key.as_str())
+                && !IGNORED_FIELDS.contains(&key.as_str())
+        })
+        .map(|(key, _)| key.as_str())
+        .collect();
+
+    if keys.is_empty() {
+        return Ok(());
+    }
+
+    keys.sort_unstable();
+
+    let candidates: Vec<_> = fields.iter().chain(COMMON_FIELDS).copied().collect();
+    let errors: Vec<_> = keys
+        .iter()
+        .map(|key| unknown_field_message(task_type, key, &candidates))
+        .collect();
+
+    bail!(errors.join("; "))
+}
+
+/// Rewrites a serde "unknown field" error, as raised for nested definitions
+/// such as containers, to name the task type and suggest the intended key.
+///
+/// Any other error is returned unchanged.
+pub fn explain_unknown_field(task_type: &str, err: serde_json::Error) -> Error {
+    lazy_static::lazy_static! {
+        static ref UNKNOWN_FIELD: Regex =
+            Regex::new(r"^unknown field `([^`]*)`, expected (.*?)(?: at line \d+ column \d+)?$")
+                .unwrap();
+        static ref EXPECTED_FIELD: Regex = Regex::new(r"`([^`]*)`").unwrap();
+    }
+
+    let message = err.to_string();
+    let Some(captures) = UNKNOWN_FIELD.captures(&message) else {
+        return err.into();
+    };
+
+    let key = &captures[1];
+    let candidates: Vec<_> = EXPECTED_FIELD
+        .captures_iter(&captures[2])
+        .filter_map(|c| c.get(1))
+        .map(|c| c.as_str())
+        .collect();
+
+    let explained = unknown_field_message(task_type, key, &candidates);
+    Error::new(err).context(explained)
+}
+
+fn unknown_field_message(task_type: &str, key: &str, candidates: &[&str]) -> String {
+    match closest(key, candidates) {
+        Some(closest) => {
+            format!("unknown field `{key}` in {task_type} task config, did you mean `{closest}`?")
+        }
+        None => format!("unknown field `{key}` in {task_type} task config"),
+    }
+}
+
+/// Finds the candidate with the smallest edit distance to `key`, if any is
+/// similar enough to be a plausible typo.
+fn closest<'a>(key: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    let max_distance = std::cmp::max(2, key.chars().count() / 3);
+
+    candidates
+        .iter()
+        .map(|candidate| (edit_distance(key, candidate), *candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Levenshtein distance between `a` and `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{closest, edit_distance};
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("abc", ""), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("target_option", "target_options"), 1);
+        assert_eq!(edit_distance("target_optoins", "target_options"), 2);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn test_closest() {
+        let candidates = ["target_exe", "target_env", "target_options"];
+        assert_eq!(
+            closest("target_option", &candidates),
+            Some("target_options")
+        );
+        assert_eq!(closest("target_exee", &candidates), Some("target_exe"));
+        assert_eq!(closest("something_else", &candidates), None);
+    }
+}
@@ -0,0 +1,36 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Task configs for tests, as sent by the service.
+
+use serde_json::{json, Value};
+
+/// A task config with placeholder values for the fields common to every task,
+/// and the fields of `overrides`, which replace common fields of the same name.
+pub(crate) fn config(overrides: Value) -> Value {
+    let mut config = json!({
+        "job_id": "00000000-0000-0000-0000-000000000001",
+        "task_id": "00000000-0000-0000-0000-000000000002",
+        "instance_id": "00000000-0000-0000-0000-000000000003",
+        "machine_identity": {
+            "machine_id": "00000000-0000-0000-0000-000000000004",
+            "machine_name": "node",
+            "scaleset_name": null
+        },
+        "from_agent_to_task_endpoint": "/",
+        "from_task_to_agent_endpoint": "/",
+    });
+    merge(&mut config, overrides);
+    config
+}
+
+/// Replace the top-level fields of `config` with those of `overrides`.
+pub(crate) fn merge(config: &mut Value, overrides: Value) {
+    let Value::Object(overrides) = overrides else {
+        panic!("task config overrides must be an object: {overrides}");
+    };
+
+    for (key, value) in overrides {
+        config[key] = value;
+    }
+}
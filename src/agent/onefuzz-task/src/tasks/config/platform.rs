@@ -0,0 +1,268 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Resolution of platform-specific values in task configs.
+//!
+//! A job which runs on both Windows and Linux pools can share one task config
+//! between them, in either of two ways:
+//!
+//! - A top-level field can be given a value per platform, by wrapping them in
+//!   a `per_platform` object, such as
+//!   `"target_exe": { "per_platform": { "windows": "fuzz.exe", "linux": "fuzz" } }`.
+//! - The `windows_overrides`, `linux_overrides` and `macos_overrides` objects
+//!   can hold fields which replace the top-level fields of the same name, on
+//!   that platform.
+//!
+//! Overrides take precedence over the per-platform values of a field. Both are
+//! resolved on the node, using the platform the task is running on, before the
+//! config is validated.
+
+use anyhow::Result;
+use serde_json::{Map, Value};
+
+/// Platforms which may be given their own values.
+const PLATFORMS: &[&str] = &["windows", "linux", "macos"];
+
+/// Key of the object which holds the values of a field per platform.
+const PER_PLATFORM_KEY: &str = "per_platform";
+
+/// The platform the task is running on.
+pub fn current_platform() -> &'static str {
+    if cfg!(target_os = "windows") {
+        "windows"
+    } else if cfg!(target_os = "macos") {
+        "macos"
+    } else {
+        "linux"
+    }
+}
+
+fn overrides_key(platform: &str) -> String {
+    format!("{platform}_overrides")
+}
+
+/// Replace the platform-specific values of a task config with the values for
+/// `platform`.
+pub fn resolve(config: &mut Value, platform: &str) -> Result<()> {
+    let Some(config) = config.as_object_mut() else {
+        return Ok(());
+    };
+
+    let mut overrides = None;
+    for other in PLATFORMS {
+        let key = overrides_key(other);
+        let Some(value) = config.remove(&key) else {
+            continue;
+        };
+
+        let Value::Object(value) = value else {
+            bail!("`{key}` must be an object");
+        };
+
+        if *other == platform {
+            overrides = Some(value);
+        }
+    }
+
+    if let Some(overrides) = overrides {
+        config.extend(overrides);
+    }
+
+    for (field, value) in config.iter_mut() {
+        let Some(values) = per_platform(field, value)? else {
+            continue;
+        };
+
+        let Some(resolved) = values.get(platform) else {
+            bail!(
+                "`{field}` has no value for {platform}, and `{}` does not set it",
+                overrides_key(platform)
+            );
+        };
+
+        *value = resolved.clone();
+    }
+
+    Ok(())
+}
+
+// The values per platform of a field, if its value is an object with only the
+// `per_platform` key.
+fn per_platform<'a>(field: &str, value: &'a Value) -> Result<Option<&'a Map<String, Value>>> {
+    let Value::Object(wrapper) = value else {
+        return Ok(None);
+    };
+
+    let (Some(values), 1) = (wrapper.get(PER_PLATFORM_KEY), wrapper.len()) else {
+        return Ok(None);
+    };
+
+    let Value::Object(values) = values else {
+        bail!("`{field}.{PER_PLATFORM_KEY}` must be an object");
+    };
+
+    if let Some(unknown) = values.keys().find(|key| !PLATFORMS.contains(&key.as_str())) {
+        bail!(
+            "`{field}.{PER_PLATFORM_KEY}` has a value for unknown platform `{unknown}`, expected one of: {}",
+            PLATFORMS.join(", ")
+        );
+    }
+
+    Ok(Some(values))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn resolved(mut config: Value, platform: &str) -> Value {
+        resolve(&mut config, platform).unwrap();
+        config
+    }
+
+    #[test]
+    fn test_per_platform_values() {
+        let config = json!({
+            "target_exe": { "per_platform": { "windows": "fuzz.exe", "linux": "fuzz" } },
+            "target_options": { "per_platform": { "windows": ["/x"], "linux": ["-x"] } },
+            "target_env": { "ASAN_OPTIONS": "detect_leaks=0" },
+            "inputs": { "path": "inputs" },
+        });
+
+        assert_eq!(
+            resolved(config.clone(), "windows"),
+            json!({
+                "target_exe": "fuzz.exe",
+                "target_options": ["/x"],
+                "target_env": { "ASAN_OPTIONS": "detect_leaks=0" },
+                "inputs": { "path": "inputs" },
+            })
+        );
+        assert_eq!(
+            resolved(config, "linux"),
+            json!({
+                "target_exe": "fuzz",
+                "target_options": ["-x"],
+                "target_env": { "ASAN_OPTIONS": "detect_leaks=0" },
+                "inputs": { "path": "inputs" },
+            })
+        );
+    }
+
+    #[test]
+    fn test_overrides() {
+        let config = json!({
+            "target_exe": "fuzz",
+            "target_options": ["-x"],
+            "windows_overrides": { "target_exe": "fuzz.exe", "target_timeout": 60 },
+            "linux_overrides": { "target_options": ["-y"] },
+        });
+
+        assert_eq!(
+            resolved(config.clone(), "windows"),
+            json!({
+                "target_exe": "fuzz.exe",
+                "target_options": ["-x"],
+                "target_timeout": 60,
+            })
+        );
+        assert_eq!(
+            resolved(config, "linux"),
+            json!({
+                "target_exe": "fuzz",
+                "target_options": ["-y"],
+            })
+        );
+    }
+
+    #[test]
+    fn test_overrides_take_precedence() {
+        let config = json!({
+            "target_exe": { "per_platform": { "windows": "fuzz.exe", "linux": "fuzz" } },
+            "supervisor_exe": { "per_platform": { "windows": "afl.exe" } },
+            "linux_overrides": {
+                "target_exe": "fuzz-linux",
+                "supervisor_exe": "afl-fuzz",
+            },
+        });
+
+        assert_eq!(
+            resolved(config.clone(), "linux"),
+            json!({ "target_exe": "fuzz-linux", "supervisor_exe": "afl-fuzz" })
+        );
+        assert_eq!(
+            resolved(config, "windows"),
+            json!({ "target_exe": "fuzz.exe", "supervisor_exe": "afl.exe" })
+        );
+    }
+
+    #[test]
+    fn test_no_value_for_platform() {
+        let mut config = json!({
+            "target_exe": { "per_platform": { "windows": "fuzz.exe" } },
+            "windows_overrides": { "target_options": [] },
+        });
+
+        let err = resolve(&mut config, "linux").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "`target_exe` has no value for linux, and `linux_overrides` does not set it"
+        );
+    }
+
+    #[test]
+    fn test_invalid_overrides() {
+        let mut config = json!({
+            "target_exe": "fuzz",
+            "windows_overrides": ["fuzz.exe"],
+        });
+
+        // Checked on every platform, not only the one it applies to.
+        let err = resolve(&mut config, "linux").unwrap_err();
+        assert_eq!(err.to_string(), "`windows_overrides` must be an object");
+    }
+
+    #[test]
+    fn test_macos_values() {
+        let config = json!({
+            "target_exe": { "per_platform": { "linux": "fuzz", "macos": "fuzz-macos" } },
+            "macos_overrides": { "target_options": ["-x"] },
+        });
+
+        assert_eq!(
+            resolved(config, "macos"),
+            json!({ "target_exe": "fuzz-macos", "target_options": ["-x"] })
+        );
+    }
+
+    #[test]
+    fn test_invalid_per_platform_values() {
+        let mut config = json!({ "target_exe": { "per_platform": "fuzz" } });
+        let err = resolve(&mut config, "linux").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "`target_exe.per_platform` must be an object"
+        );
+
+        let mut config = json!({ "target_exe": { "per_platform": { "linx": "fuzz" } } });
+        let err = resolve(&mut config, "linux").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "`target_exe.per_platform` has a value for unknown platform `linx`, expected one of: windows, linux, macos"
+        );
+    }
+
+    #[test]
+    fn test_unchanged_without_platform_values() {
+        let config = json!({
+            "target_exe": "fuzz",
+            // Objects keyed by platform names are only resolved if wrapped.
+            "target_env": { "windows": "yes", "linux": "no" },
+            "tags": { "per_platform": { "windows": "yes" }, "os": "any" },
+        });
+
+        assert_eq!(resolved(config.clone(), "linux"), config);
+    }
+}
@@ -0,0 +1,468 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use serde_json::{json, Value};
+
+use super::{fields, fixture::config, Config};
+
+fn libfuzzer_fuzz() -> Value {
+    config(json!({
+        "task_type": "libfuzzer_fuzz",
+        "inputs": { "path": "inputs" },
+        "crashes": { "path": "crashes" },
+        "target_exe": "fuzz.exe",
+        "target_env": {},
+        "target_options": [],
+    }))
+}
+
+fn parse(config: &Value) -> anyhow::Result<Config> {
+    Config::parse(&config.to_string())
+}
+
+fn parse_err(config: &Value) -> String {
+    match parse(config) {
+        Ok(config) => panic!("expected an error, parsed {config:?}"),
+        Err(err) => format!("{err:#}"),
+    }
+}
+
+#[test]
+fn test_valid_config() {
+    let config = parse(&libfuzzer_fuzz()).unwrap();
+    assert_eq!(config.task_type(), "libfuzzer_fuzz");
+    assert!(config.common().unknown_fields.is_empty());
+}
+
+#[test]
+fn test_unknown_optional_field() {
+    let mut config = libfuzzer_fuzz();
+    config["check_fuzzer_hlep"] = json!(false);
+
+    let err = parse_err(&config);
+    assert_eq!(
+        err,
+        "unknown field `check_fuzzer_hlep` in libfuzzer_fuzz task config, did you mean `check_fuzzer_help`?"
+    );
+}
+
+#[test]
+fn test_unknown_common_field() {
+    let mut config = libfuzzer_fuzz();
+    config["min_available_memory"] = json!(0);
+
+    let err = parse_err(&config);
+    assert_eq!(
+        err,
+        "unknown field `min_available_memory` in libfuzzer_fuzz task config, did you mean `min_available_memory_mb`?"
+    );
+}
+
+#[test]
+fn test_misspelled_required_field() {
+    let mut config = libfuzzer_fuzz();
+    let target_options = config
+        .as_object_mut()
+        .unwrap()
+        .remove("target_options")
+        .unwrap();
+    config["target_optoins"] = target_options;
+
+    let err = parse_err(&config);
+    assert!(
+        err.starts_with(
+            "unknown field `target_optoins` in libfuzzer_fuzz task config, did you mean `target_options`?: "
+        ),
+        "{err}"
+    );
+    assert!(err.contains("missing field `target_options`"), "{err}");
+}
+
+#[test]
+fn test_unknown_field_without_suggestion() {
+    let mut config = config(json!({
+        "task_type": "generic_crash_report",
+        "target_exe": "fuzz.exe",
+    }));
+    config["something_else"] = json!(1);
+    config["another_thing"] = json!(2);
+
+    let err = parse_err(&config);
+    assert_eq!(
+        err,
+        "unknown field `another_thing` in generic_crash_report task config; \
+         unknown field `something_else` in generic_crash_report task config"
+    );
+}
+
+#[test]
+fn test_unknown_container_field() {
+    let mut config = libfuzzer_fuzz();
+    config["crashes"] = json!({
+        "path": "crashes",
+        "remote_pth": "https://example.com/crashes",
+    });
+
+    let err = parse_err(&config);
+    assert!(
+        err.starts_with(
+            "unknown field `remote_pth` in libfuzzer_fuzz task config, did you mean `remote_path`?: "
+        ),
+        "{err}"
+    );
+}
+
+#[test]
+fn test_fuzzer_specific_fields() {
+    let mut config = libfuzzer_fuzz();
+    config["task_type"] = json!("libfuzzer_dotnet_fuzz");
+    config["target_assembly"] = json!("fuzz.dll");
+    config["target_class"] = json!("Fuzz");
+    config["target_method"] = json!("TestInput");
+    config["tools"] = json!({ "path": "tools" });
+
+    let config = parse(&config).unwrap();
+    assert_eq!(config.task_type(), "libfuzzer_dotnet_fuzz");
+    assert!(config.common().unknown_fields.is_empty());
+}
+
+#[test]
+fn test_tolerated_fields() {
+    let mut config = config(json!({
+        "task_type": "generic_merge",
+        "supervisor_exe": "merge.exe",
+        "supervisor_options": [],
+        "supervisor_env": {},
+        "supervisor_input_marker": "{input}",
+        "target_exe": "fuzz.exe",
+        "target_options": [],
+        "target_options_merge": false,
+        "tools": { "path": "tools" },
+        "input_queue": "https://example.com/queue",
+        "inputs": { "path": "inputs" },
+        "unique_inputs": { "path": "unique_inputs" },
+    }));
+
+    // sent by the service, but no longer read by the task
+    config["stats_file"] = json!("stats.txt");
+    config["extra_setup"] = json!({ "path": "extra_setup" });
+
+    // equivalent to omitting the key
+    config["not_a_field"] = Value::Null;
+
+    let config = parse(&config).unwrap();
+    assert_eq!(config.task_type(), "generic_merge");
+}
+
+#[test]
+fn test_unknown_task_type() {
+    let mut config = libfuzzer_fuzz();
+    config["task_type"] = json!("libfuzzer_fuzzz");
+
+    let err = parse_err(&config);
+    assert!(
+        err.starts_with("unknown variant `libfuzzer_fuzzz`"),
+        "{err}"
+    );
+}
+
+#[test]
+fn test_invalid_values_reported_together() {
+    let mut config = libfuzzer_fuzz();
+    config["target_exe"] = json!("../fuzz.exe");
+    config["target_workers"] = json!(-1);
+    config["crashes"] = json!({
+        "path": "crashes",
+        "url": "https://contoso.blob.core.windows.net/crashes",
+    });
+
+    let err = parse_err(&config);
+    assert_eq!(
+        err,
+        "invalid task config: \
+         `crashes.url` must include a SAS token, or be a local path: https://contoso.blob.core.windows.net/crashes; \
+         `target_exe` must be an absolute path, or a path within the setup directory: ../fuzz.exe; \
+         `target_workers` must not be negative"
+    );
+}
+
+#[test]
+fn test_target_options_shell() {
+    let mut config = libfuzzer_fuzz();
+    config["target_options_shell"] = json!(true);
+
+    let config = parse(&config).unwrap();
+    assert!(config.common().target_options_shell);
+}
+
+#[test]
+fn test_target_options_shell_unsupported() {
+    let mut config = config(json!({
+        "task_type": "coverage",
+        "target_exe": "fuzz.exe",
+        "target_env": {},
+        "target_options": ["{input}"],
+        "readonly_inputs": [],
+        "coverage": { "path": "coverage" },
+    }));
+    assert!(parse(&config).is_ok());
+
+    config["target_options_shell"] = json!(true);
+    let err = parse_err(&config);
+    assert_eq!(
+        err,
+        "`target_options_shell` is not supported by coverage tasks"
+    );
+}
+
+#[test]
+fn test_qemu_user() {
+    let mut config = libfuzzer_fuzz();
+    config["qemu_user"] = json!({
+        "arch": "aarch64",
+        "sysroot": "/usr/aarch64-linux-gnu",
+    });
+
+    let config = parse(&config).unwrap();
+    let qemu = config.common().qemu_user.as_ref().unwrap();
+    assert_eq!(qemu.qemu_path(), std::path::Path::new("qemu-aarch64"));
+    assert_eq!(
+        qemu.sysroot.as_deref(),
+        Some(std::path::Path::new("/usr/aarch64-linux-gnu"))
+    );
+}
+
+#[test]
+fn test_qemu_user_unsupported() {
+    let mut config = config(json!({
+        "task_type": "coverage",
+        "target_exe": "fuzz.exe",
+        "target_env": {},
+        "target_options": ["{input}"],
+        "readonly_inputs": [],
+        "coverage": { "path": "coverage" },
+    }));
+    config["qemu_user"] = json!({ "arch": "aarch64" });
+
+    let err = parse_err(&config);
+    assert_eq!(err, "`qemu_user` is not supported by coverage tasks");
+}
+
+#[tokio::test]
+async fn test_check_env_files() {
+    let setup_dir = tempfile::tempdir().unwrap();
+    std::fs::write(setup_dir.path().join("libmutator.so"), "").unwrap();
+    std::fs::write(setup_dir.path().join("asan.supp"), "").unwrap();
+    std::fs::write(setup_dir.path().join("tsan.supp"), "").unwrap();
+
+    let mut config = libfuzzer_fuzz();
+    config["setup_dir"] = json!(setup_dir.path());
+    config["target_env"] = json!({
+        "LD_PRELOAD": "{setup_dir}/libmutator.so",
+        "ASAN_OPTIONS": "detect_leaks=0:suppressions={setup_dir}/asan.supp",
+        "TSAN_OPTIONS": "report_signal_unsafe=0 suppressions={setup_dir}/tsan.supp",
+        // Only known when the target is run.
+        "AFL_PRELOAD": "{input}",
+    });
+
+    let config = parse(&config).unwrap();
+    config.check_env_files().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_check_env_files_missing() {
+    let setup_dir = tempfile::tempdir().unwrap();
+
+    let mut config = config(json!({
+        "task_type": "generic_supervisor",
+        "inputs": { "path": "inputs" },
+        "crashes": { "path": "crashes" },
+        "supervisor_exe": "afl-fuzz",
+        "supervisor_env": {
+            "AFL_CUSTOM_MUTATOR_LIBRARY": "{setup_dir}/libmutator.so",
+            "LD_PRELOAD": "libc.so.6",
+        },
+        "supervisor_options": [],
+        "target_options": [],
+    }));
+    config["setup_dir"] = json!(setup_dir.path());
+
+    let config = parse(&config).unwrap();
+    let err = config.check_env_files().await.unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "files named by environment variables do not exist: `supervisor_env.AFL_CUSTOM_MUTATOR_LIBRARY`: {}",
+            setup_dir.path().join("libmutator.so").display()
+        )
+    );
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+async fn test_add_sanitizer_defaults() {
+    let setup_dir = tempfile::tempdir().unwrap();
+    let fixture = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../onefuzz/tests/triage/crash.c"
+    );
+    let status = std::process::Command::new("cc")
+        .args(["-fsanitize=address", "-o"])
+        .arg(setup_dir.path().join("fuzz.exe"))
+        .arg(fixture)
+        .status()
+        .unwrap();
+    assert!(status.success(), "cc failed: {status}");
+
+    let mut config = libfuzzer_fuzz();
+    config["setup_dir"] = json!(setup_dir.path());
+    config["target_env"] = json!({ "ASAN_OPTIONS": "abort_on_error=0:detect_leaks=0" });
+
+    let mut config = parse(&config).unwrap();
+    config.add_sanitizer_defaults().await.unwrap();
+
+    let Config::LibFuzzerFuzz(c) = &config else {
+        panic!("not a libfuzzer_fuzz config");
+    };
+    assert_eq!(
+        c.target_env["ASAN_OPTIONS"],
+        "symbolize=1:print_summary=1:abort_on_error=0:detect_leaks=0"
+    );
+    assert!(!c.target_env.contains_key("TSAN_OPTIONS"));
+}
+
+#[test]
+fn test_generator_required() {
+    let mut config = config(json!({
+        "task_type": "generic_generator",
+        "readonly_inputs": [],
+        "crashes": { "path": "crashes" },
+        "target_exe": "fuzz.exe",
+        "target_env": {},
+        "target_options": [],
+        "rename_output": false,
+    }));
+
+    let err = parse_err(&config);
+    assert_eq!(err, "no generator: set `generator_exe` or `generators`");
+
+    config["generators"] = json!([{ "name": "radamsa", "generator_exe": "radamsa" }]);
+    assert!(parse(&config).is_ok());
+}
+
+#[test]
+fn test_parallel_modes_exclusive() {
+    let mut config = libfuzzer_fuzz();
+    config["jobs_mode"] = json!(true);
+    assert!(parse(&config).is_ok());
+
+    config["fork_mode"] = json!(true);
+    let err = parse_err(&config);
+    assert_eq!(err, "`fork_mode` and `jobs_mode` can't both be set");
+}
+
+#[test]
+fn test_minimizer_required() {
+    let mut config = config(json!({
+        "task_type": "generic_crash_report",
+        "target_exe": "fuzz.exe",
+        "minimized_crashes": { "path": "minimized_crashes" },
+    }));
+
+    let err = parse_err(&config);
+    assert_eq!(err, "`minimized_crashes` requires `minimizer_exe`");
+
+    config["minimizer_exe"] = json!("afl-tmin");
+    assert!(parse(&config).is_ok());
+}
+
+// Every field listed for a task type must be claimed by its config struct.
+// The value is a type error for every field, so deserialization fails if the
+// struct reads the key, and the key is collected as unknown if it doesn't.
+#[test]
+fn test_task_fields_match_config_structs() {
+    let task_types = [
+        "coverage",
+        "dotnet_coverage",
+        "dotnet_crash_report",
+        "libfuzzer_dotnet_fuzz",
+        "libfuzzer_fuzz",
+        "libfuzzer_crash_report",
+        "libfuzzer_merge",
+        "libfuzzer_regression",
+        "generic_analysis",
+        "generic_generator",
+        "generic_supervisor",
+        "generic_merge",
+        "generic_crash_report",
+        "generic_regression",
+    ];
+
+    for task_type in task_types {
+        assert_eq!(fields::canonical_task_type(task_type), Some(task_type));
+
+        for field in fields::task_fields(task_type) {
+            // The fuzzer-specific fields are only read once the fields they
+            // are flattened alongside have been found.
+            let mut config = libfuzzer_fuzz();
+            config["task_type"] = json!(task_type);
+            config[*field] = json!([[[]]]);
+
+            let err = parse_err(&config);
+            assert!(
+                err.contains("invalid type") || err.contains("invalid length"),
+                "{task_type}.{field} is not read by the config: {err}"
+            );
+        }
+    }
+}
+
+// Configs from the service are parsed strictly, unlike local task groups.
+#[test]
+fn test_comments_rejected() {
+    let config = libfuzzer_fuzz().to_string();
+    let commented = format!("// sent by the service\n{config}");
+
+    assert!(Config::parse(&config).is_ok());
+    assert!(Config::parse(&commented).is_err());
+}
+
+#[test]
+fn test_platform_values_resolved() {
+    let mut config = libfuzzer_fuzz();
+    config["target_exe"] = json!({
+        "per_platform": { "windows": "fuzz.exe", "linux": "fuzz", "macos": "fuzz-macos" },
+    });
+    config["windows_overrides"] = json!({ "target_options": ["-x"] });
+    config["linux_overrides"] = json!({ "target_options": ["-y"] });
+    config["macos_overrides"] = json!({ "target_options": ["-z"] });
+
+    let config = parse(&config).unwrap();
+    let Config::LibFuzzerFuzz(config) = config else {
+        panic!("unexpected task type: {}", config.task_type());
+    };
+
+    let (target_exe, target_option) = match super::platform::current_platform() {
+        "windows" => ("fuzz.exe", "-x"),
+        "macos" => ("fuzz-macos", "-z"),
+        _ => ("fuzz", "-y"),
+    };
+    assert_eq!(config.target_exe, std::path::PathBuf::from(target_exe));
+    assert_eq!(config.target_options, [target_option]);
+}
+
+// Resolved before validation, so only the value for this platform is checked.
+#[test]
+fn test_platform_values_validated() {
+    let mut config = libfuzzer_fuzz();
+    config["target_exe"] = json!({
+        "per_platform": { "windows": "../fuzz.exe", "linux": "../fuzz", "macos": "../fuzz" },
+    });
+
+    let err = parse_err(&config);
+    assert!(
+        err.starts_with("invalid task config: `target_exe` must be an absolute path"),
+        "{err}"
+    );
+}
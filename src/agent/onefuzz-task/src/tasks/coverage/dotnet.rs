@@ -97,7 +97,7 @@ impl DotnetCoverageTask {
         let coverage_dir = self.config.coverage.clone();
         let dotnet_coverage_path = dotnet_coverage_path;
 
-        tokio::spawn(async move {
+        onefuzz_telemetry::context::spawn(async move {
             if let Err(e) = start_directory_monitor(
                 &intermediate_files_path,
                 &coverage_local_path,
@@ -150,7 +150,7 @@ async fn start_directory_monitor(
         "Starting dotnet coverage intermediate file directory monitor on {}",
         intermediate_files_path.to_string_lossy()
     );
-    let mut monitor = DirectoryMonitor::new(intermediate_files_path).await?;
+    let mut monitor = DirectoryMonitor::new(intermediate_files_path, false).await?;
     debug!("Started directory monitor, waiting for files");
     while (monitor.next_file().await?).is_some() {
         debug!("Found intermediate coverage file");
@@ -266,7 +266,7 @@ impl<'a> TaskContext<'a> {
         // Try to expand `target_exe` with support for `{tools_dir}`.
         //
         // Allows using `LibFuzzerDotnetLoader.exe` from a shared tools container.
-        let expand = Expand::new(&self.config.common.machine_identity).tools_dir(tools_dir);
+        let expand = self.config.common.expand().tools_dir(tools_dir);
         let expanded = expand.evaluate_value(self.config.target_exe.to_string_lossy())?;
         let expanded_path = Path::new(&expanded);
 
@@ -293,7 +293,10 @@ impl<'a> TaskContext<'a> {
     async fn command_for_input(&self, input: &Path) -> Result<Command> {
         let target_exe = self.target_exe().await?;
 
-        let expand = Expand::new(&self.config.common.machine_identity)
+        let expand = self
+            .config
+            .common
+            .expand()
             .machine_id()
             .input_path(input)
             .job_id(&self.config.common.job_id)
@@ -417,7 +420,7 @@ async fn spawn_with_timeout(
     timeout(timeout_after, cmd.spawn()?.wait()).await?
 }
 
-fn dotnet_coverage_path() -> Result<PathBuf> {
+pub fn dotnet_coverage_path() -> Result<PathBuf> {
     let tools_dir = env::var("ONEFUZZ_TOOLS")?;
     #[cfg(target_os = "windows")]
     let dotnet_coverage_executable = "dotnet-coverage.exe";
@@ -428,7 +431,7 @@ fn dotnet_coverage_path() -> Result<PathBuf> {
     Ok(dotnet_coverage)
 }
 
-fn dotnet_path() -> Result<PathBuf> {
+pub fn dotnet_path() -> Result<PathBuf> {
     let dotnet_root_dir = env::var("DOTNET_ROOT")?;
     #[cfg(target_os = "windows")]
     let dotnet_executable = "dotnet.exe";
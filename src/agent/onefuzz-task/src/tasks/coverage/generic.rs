@@ -1,26 +1,32 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::convert::TryFrom;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use anyhow::{bail, Context, Result};
 use async_trait::async_trait;
 use cobertura::{CoberturaCoverage, WriteXml};
 use coverage::allowlist::AllowList;
 use coverage::binary::{BinaryCoverage, DebugInfoCache};
+use coverage::cobertura::parse_source_coverage;
+use coverage::html;
+use coverage::lcov::Lcov;
 use coverage::record::CoverageRecorder;
 use coverage::source::{binary_to_source_coverage, SourceCoverage};
+use coverage::{ModuleFilter, TimerError};
 use debuggable_module::load_module::LoadModule;
 use debuggable_module::loader::Loader;
 use debuggable_module::path::FilePath;
+use debuggable_module::symsrv::SymbolPath;
 use debuggable_module::Module;
 use onefuzz::env::LD_LIBRARY_PATH;
 use onefuzz::expand::{Expand, PlaceHolder};
+use onefuzz::input_tester::InputVia;
 use onefuzz::syncdir::SyncedDir;
 use onefuzz_file_format::coverage::{
     binary::{v1::BinaryCoverageJson as BinaryCoverageJsonV1, BinaryCoverageJson},
@@ -28,7 +34,10 @@ use onefuzz_file_format::coverage::{
 };
 use onefuzz_result::job_result::JobResultData;
 use onefuzz_result::job_result::{JobResultSender, TaskJobResultClient};
-use onefuzz_telemetry::{event, warn, Event::coverage_data, Event::coverage_failed, EventData};
+use onefuzz_telemetry::{
+    event, warn, Event::coverage_data, Event::coverage_failed, Event::coverage_module_summary,
+    Event::coverage_summary, EventData,
+};
 use storage_queue::{Message, QueueClient};
 use tokio::fs;
 use tokio::sync::RwLock;
@@ -39,15 +48,22 @@ use url::Url;
 use crate::tasks::config::CommonConfig;
 use crate::tasks::generic::input_poller::{CallbackImpl, InputPoller, Processor};
 use crate::tasks::heartbeat::{HeartbeatSender, TaskHeartbeatClient};
+use crate::tasks::source_paths::{load_path_map, SourcePathRule};
+use crate::tasks::symbols::TargetSymbols;
 use crate::tasks::utils::try_resolve_setup_relative_path;
 
-use super::COBERTURA_COVERAGE_FILE;
+use super::dotnet::{dotnet_coverage_path, dotnet_path};
+use super::processed::{ProcessedInputs, PROCESSED_INPUTS_FILE};
+use super::skipped::{SkipReason, SkippedInputs, SKIPPED_INPUTS_FILE};
+use super::summary::{
+    CoverageStats, CoverageSummary, InputRate, InputStats, SummaryTimer, COVERAGE_SUMMARY_FILE,
+    DEFAULT_SUMMARY_INTERVAL,
+};
+use super::{default_output_formats, CoverageFormat, COVERAGE_FILE, SOURCE_COVERAGE_FILE};
 
 const MAX_COVERAGE_RECORDING_ATTEMPTS: usize = 2;
-const COVERAGE_FILE: &str = "coverage.json";
-const SOURCE_COVERAGE_FILE: &str = "source-coverage.json";
 
-const DEFAULT_TARGET_TIMEOUT: Duration = Duration::from_secs(120);
+const DEFAULT_TARGET_TIMEOUT: Duration = Duration::from_secs(45);
 
 const WINDOWS_INTERCEPTOR_DENYLIST: &str = include_str!("generic/windows-interceptor.list");
 
@@ -56,20 +72,71 @@ pub struct Config {
     pub target_exe: PathBuf,
     pub target_env: HashMap<String, String>,
     pub target_options: Vec<String>,
+
+    /// How inputs are passed to the target when replayed: as the file of
+    /// `{input}`, or on its standard input.
+    #[serde(default)]
+    pub input_via: InputVia,
+
+    /// Seconds to wait for the target to replay each input. Inputs which
+    /// exceed it are skipped.
     pub target_timeout: Option<u64>,
 
+    /// The code to record coverage of. Managed .NET targets are replayed with
+    /// `dotnet`, under `dotnet-coverage` from the tools directory.
+    #[serde(default)]
+    pub target_type: TargetType,
+
     // Deprecated.
     //
     // Retained only to informatively fail tasks that were qeueued pre-upgrade.
     pub coverage_filter: Option<String>,
 
-    pub module_allowlist: Option<String>,
+    pub module_allowlist: Option<ModuleAllowlist>,
     pub source_allowlist: Option<String>,
 
+    /// Regexes of modules not to record coverage for, matched against their
+    /// file names and full paths.
+    #[serde(default)]
+    pub module_denylist: Vec<String>,
+
     pub input_queue: Option<QueueClient>,
     pub readonly_inputs: Vec<SyncedDir>,
     pub coverage: SyncedDir,
 
+    /// Formats of the coverage to write to `coverage`. The binary coverage is
+    /// also the coverage a restarted task resumes from.
+    #[serde(default = "default_output_formats")]
+    pub output_formats: Vec<CoverageFormat>,
+
+    /// Sources of the target, for the `html` format. Defaults to the setup
+    /// container.
+    pub sources: Option<SyncedDir>,
+
+    /// Rules rewriting the source paths of debug info to paths in `sources`,
+    /// with those of the `.srcview.json` file of the setup container.
+    #[serde(default)]
+    pub source_path_map: Vec<SourcePathRule>,
+
+    /// Replay every input, ignoring the coverage, processed inputs and skipped
+    /// inputs saved by earlier runs.
+    #[serde(default)]
+    pub reprocess_inputs: bool,
+
+    /// Also record the processes started by the target, such as helpers it
+    /// spawns to parse inputs.
+    #[serde(default)]
+    pub follow_children: bool,
+
+    /// Directory to save the coverage sites of analyzed modules in, so that
+    /// modules are only analyzed once per build across tasks and restarts.
+    /// Defaults to a directory of the agent.
+    pub analysis_cache: Option<PathBuf>,
+
+    /// Seconds between summaries of the task's coverage and progress, emitted
+    /// as telemetry and saved to `coverage` as `coverage-summary.json`.
+    pub summary_interval: Option<u64>,
+
     #[serde(flatten)]
     pub common: CommonConfig,
 }
@@ -80,6 +147,60 @@ impl Config {
             .map(Duration::from_secs)
             .unwrap_or(DEFAULT_TARGET_TIMEOUT)
     }
+
+    // The standard input of a replay of `input`.
+    fn stdin(&self, input: &Path) -> Result<Stdio> {
+        match self.input_via {
+            InputVia::File => Ok(Stdio::null()),
+            InputVia::Stdin => {
+                let file = std::fs::File::open(input)
+                    .with_context(|| format!("unable to open input: {}", input.display()))?;
+                Ok(file.into())
+            }
+        }
+    }
+
+    fn sources_dir(&self) -> &Path {
+        self.sources
+            .as_ref()
+            .map(|sources| sources.local_path.as_path())
+            .unwrap_or(&self.common.setup_dir)
+    }
+
+    fn summary_interval(&self) -> Duration {
+        self.summary_interval
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_SUMMARY_INTERVAL)
+    }
+
+    fn analysis_cache_dir(&self) -> Result<PathBuf> {
+        match &self.analysis_cache {
+            Some(dir) => Ok(dir.clone()),
+            None => Ok(onefuzz::fs::onefuzz_root()?.join("coverage-cache")),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TargetType {
+    /// Native modules, whose coverage is recorded with breakpoints.
+    #[default]
+    Native,
+
+    /// A managed assembly, whose coverage is Cobertura source coverage.
+    Dotnet,
+}
+
+/// The modules to record coverage for.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum ModuleAllowlist {
+    /// Path of an allowlist file, relative to the setup directory.
+    File(String),
+
+    /// Regexes matched against the file names and full paths of modules.
+    Patterns(Vec<String>),
 }
 
 pub struct CoverageTask {
@@ -101,12 +222,19 @@ impl CoverageTask {
         }
 
         self.config.coverage.init_pull().await?;
+        if let Some(sources) = &self.config.sources {
+            sources.init_pull().await?;
+        }
 
         let coverage_file = self.config.coverage.local_path.join(COVERAGE_FILE);
 
+        let mut resumed = false;
         let coverage = {
-            if let Ok(text) = fs::read_to_string(&coverage_file).await {
+            if self.config.reprocess_inputs {
+                BinaryCoverage::default()
+            } else if let Ok(text) = fs::read_to_string(&coverage_file).await {
                 let json = BinaryCoverageJson::deserialize(&text)?;
+                resumed = true;
                 BinaryCoverage::try_from(json)?
             } else {
                 BinaryCoverage::default()
@@ -126,6 +254,8 @@ impl CoverageTask {
             .to_str()
             .ok_or_else(|| anyhow::format_err!("target_exe path is not valid unicode"))?;
 
+        let symbols = TargetSymbols::fetch(&self.config.common, &target_exe_path).await;
+
         let mut context = TaskContext::new(
             &self.config,
             coverage,
@@ -133,8 +263,21 @@ impl CoverageTask {
             heartbeat,
             job_result,
             target_exe.to_string(),
+            symbols.symbol_path,
         )?;
 
+        if self.config.reprocess_inputs {
+            info!("replaying all inputs, ignoring earlier runs");
+        } else {
+            context.load_skipped_inputs().await?;
+
+            // Inputs are only skipped as processed if their coverage was saved.
+            if resumed {
+                context.load_managed_coverage().await?;
+                context.load_processed_inputs().await;
+            }
+        }
+
         if !context.uses_input() {
             bail!("input is not specified on the command line or arguments for the target");
         }
@@ -149,6 +292,7 @@ impl CoverageTask {
 
             dir.init_pull().await?;
             let dir_count = context.record_corpus(&dir.local_path).await?;
+            debug!("module analysis: {:?}", context.cache.stats());
 
             if dir_count > 0 {
                 seen_inputs = true;
@@ -163,6 +307,9 @@ impl CoverageTask {
             context.heartbeat.alive();
         }
 
+        // Summarize the end of the corpus, whenever the last summary was.
+        context.report_summary().await;
+
         if seen_inputs {
             context.save_and_sync_coverage().await?;
         }
@@ -186,9 +333,18 @@ impl CoverageTask {
         // source files are excluded.
         let mut allowlist = TargetAllowList::default();
 
-        if let Some(modules) = &self.config.module_allowlist {
-            allowlist.modules = self.load_allowlist(modules).await?;
+        let mut module_patterns: &[String] = &[];
+        match &self.config.module_allowlist {
+            Some(ModuleAllowlist::File(modules)) => {
+                allowlist.modules = self.load_allowlist(modules).await?;
+            }
+            Some(ModuleAllowlist::Patterns(patterns)) => {
+                module_patterns = patterns;
+            }
+            None => {}
         }
+        allowlist.module_filter = ModuleFilter::new(module_patterns, &self.config.module_denylist)
+            .context("invalid module_allowlist or module_denylist regex")?;
 
         if let Some(source_files) = &self.config.source_allowlist {
             allowlist.source_files = self.load_allowlist(source_files).await?;
@@ -215,6 +371,7 @@ impl CoverageTask {
 #[derive(Clone, Debug, Default)]
 struct TargetAllowList {
     modules: AllowList,
+    module_filter: ModuleFilter,
     source_files: AllowList,
 }
 
@@ -222,10 +379,38 @@ struct TaskContext<'a> {
     config: &'a Config,
     coverage: RwLock<BinaryCoverage>,
     module_allowlist: AllowList,
+    module_filter: ModuleFilter,
     source_allowlist: Arc<AllowList>,
+
+    // Modules skipped by the allowlists, logged when first skipped.
+    skipped_modules: BTreeSet<FilePath>,
     heartbeat: Option<TaskHeartbeatClient>,
     job_result: Option<TaskJobResultClient>,
     cache: Arc<DebugInfoCache>,
+
+    // Source coverage of managed code, which has no binary coverage.
+    managed_coverage: RwLock<SourceCoverage>,
+
+    // Searched for PDBs not beside their modules.
+    symbol_path: SymbolPath,
+
+    // Inputs which timed out or crashed, and are not replayed again.
+    skipped_inputs: SkippedInputs,
+
+    // Inputs whose coverage has been recorded, by their content.
+    processed_inputs: ProcessedInputs,
+
+    // Inputs of the corpus directory being recorded, not yet replayed.
+    remaining_inputs: u64,
+    input_rate: InputRate,
+    summary_timer: SummaryTimer,
+}
+
+// The result of replaying an input.
+enum Replay {
+    Recorded(BinaryCoverage),
+    Managed(SourceCoverage),
+    Skipped(SkipReason, String),
 }
 
 impl<'a> TaskContext<'a> {
@@ -236,30 +421,106 @@ impl<'a> TaskContext<'a> {
         heartbeat: Option<TaskHeartbeatClient>,
         job_result: Option<TaskJobResultClient>,
         target_exe: String,
+        symbol_path: SymbolPath,
     ) -> Result<Self> {
-        let cache = DebugInfoCache::new(allowlist.source_files.clone());
-        let loader = Loader::new();
+        let cache = DebugInfoCache::new(allowlist.source_files.clone())
+            .persist_to(config.analysis_cache_dir()?);
+        let loader = Loader::with_symbol_path(symbol_path.clone());
 
         // Preload the cache with the target executable, to avoid counting debuginfo analysis
         // time against the exeuction timeout for the first iteration.
-        let module: Box<dyn Module> = LoadModule::load(&loader, FilePath::new(target_exe)?)?;
+        //
+        // Managed targets are not analyzed.
+        if config.target_type == TargetType::Native {
+            let module: Box<dyn Module> = LoadModule::load(&loader, FilePath::new(target_exe)?)?;
 
-        cache
-            .get_or_insert(&*module)
-            .context("Failed to load debuginfo for target_exe when populating DebugInfoCache")?;
+            cache.get_or_insert(&*module).context(
+                "Failed to load debuginfo for target_exe when populating DebugInfoCache",
+            )?;
+        }
 
         Ok(Self {
             config,
             coverage: RwLock::new(coverage),
             module_allowlist: allowlist.modules,
+            module_filter: allowlist.module_filter,
             source_allowlist: Arc::new(allowlist.source_files),
+            skipped_modules: BTreeSet::new(),
             heartbeat,
             job_result,
             cache: Arc::new(cache),
+            managed_coverage: RwLock::new(SourceCoverage::default()),
+            symbol_path,
+            skipped_inputs: SkippedInputs::default(),
+            processed_inputs: ProcessedInputs::default(),
+            remaining_inputs: 0,
+            input_rate: InputRate::new(Instant::now()),
+            summary_timer: SummaryTimer::new(config.summary_interval()),
         })
     }
 
-    pub async fn record_input(&mut self, input: &Path) -> Result<()> {
+    // The managed coverage of an earlier run is its source coverage, as it has
+    // no binary coverage to convert.
+    async fn load_managed_coverage(&mut self) -> Result<()> {
+        if self.config.target_type != TargetType::Dotnet {
+            return Ok(());
+        }
+
+        let path = self.config.coverage.local_path.join(SOURCE_COVERAGE_FILE);
+        if let Ok(text) = fs::read_to_string(&path).await {
+            let json = SourceCoverageJson::deserialize(&text)?;
+            self.managed_coverage = RwLock::new(SourceCoverage::try_from(json)?);
+        }
+
+        Ok(())
+    }
+
+    fn processed_inputs_path(&self) -> PathBuf {
+        self.config.coverage.local_path.join(PROCESSED_INPUTS_FILE)
+    }
+
+    async fn load_processed_inputs(&mut self) {
+        self.processed_inputs = ProcessedInputs::load(self.processed_inputs_path()).await;
+
+        if !self.processed_inputs.is_empty() {
+            info!(
+                "not replaying {} unchanged inputs processed by an earlier run",
+                self.processed_inputs.len()
+            );
+        }
+    }
+
+    fn skipped_inputs_path(&self) -> PathBuf {
+        self.config.coverage.local_path.join(SKIPPED_INPUTS_FILE)
+    }
+
+    async fn load_skipped_inputs(&mut self) -> Result<()> {
+        self.skipped_inputs = SkippedInputs::load(self.skipped_inputs_path()).await?;
+
+        if !self.skipped_inputs.is_empty() {
+            info!(
+                "not replaying {} inputs skipped by an earlier run",
+                self.skipped_inputs.len()
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Record the coverage of `input`, returning `false` if it was skipped,
+    /// because it was already processed, or the target timed out or crashed.
+    pub async fn record_input(&mut self, input: &Path) -> Result<bool> {
+        if self.skipped_inputs.contains(input) {
+            debug!("skipping input {}", input.display());
+            return Ok(false);
+        }
+
+        let sha256 = onefuzz::sha256::digest_file(input).await?;
+        if self.processed_inputs.contains(input, &sha256) {
+            debug!("skipping processed input {}", input.display());
+            return Ok(false);
+        }
+
         debug!("recording coverage for {}", input.display());
         let attempts = MAX_COVERAGE_RECORDING_ATTEMPTS;
 
@@ -286,42 +547,145 @@ impl<'a> TaskContext<'a> {
                     });
                 }
             } else {
-                // We successfully recorded the coverage for `input`, so stop.
-                break;
+                // We either recorded the coverage for `input` or skipped it, so stop.
+                self.input_rate.replayed();
+                if let Ok(true) = result {
+                    self.processed_inputs.insert(input, sha256);
+                }
+                return result;
             }
         }
 
-        Ok(())
+        Ok(true)
     }
 
-    async fn try_record_input(&mut self, input: &Path) -> Result<()> {
-        let coverage = self.record_impl(input).await?;
-        let mut self_coverage = RwLock::write(&self.coverage).await;
-        self_coverage.merge(&coverage);
-        Ok(())
+    async fn try_record_input(&mut self, input: &Path) -> Result<bool> {
+        match self.record_impl(input).await? {
+            Replay::Recorded(coverage) => {
+                let mut self_coverage = RwLock::write(&self.coverage).await;
+                self_coverage.merge(&coverage);
+                Ok(true)
+            }
+            Replay::Managed(coverage) => {
+                let mut managed_coverage = RwLock::write(&self.managed_coverage).await;
+                managed_coverage.merge(&coverage);
+                Ok(true)
+            }
+            Replay::Skipped(reason, detail) => {
+                warn!(
+                    "skipping input {} ({:?}): {}",
+                    input.display(),
+                    reason,
+                    detail
+                );
+                self.skipped_inputs.insert(input, reason, detail);
+                Ok(false)
+            }
+        }
     }
 
-    async fn record_impl(&mut self, input: &Path) -> Result<BinaryCoverage> {
+    async fn record_impl(&mut self, input: &Path) -> Result<Replay> {
+        if self.config.target_type == TargetType::Dotnet {
+            return self.record_managed(input).await;
+        }
+
         let module_allowlist = self.module_allowlist.clone();
+        let module_filter = self.module_filter.clone();
         let cmd = self.command_for_input(input).await?;
         let timeout = self.config.timeout();
+        let follow_children = self.config.follow_children;
         let cache = self.cache.clone();
+        let loader = Loader::with_symbol_path(self.symbol_path.clone());
         let recorded = spawn_blocking(move || {
             CoverageRecorder::new(cmd)
+                .loader(loader)
                 .debuginfo_cache(cache)
                 .module_allowlist(module_allowlist)
+                .module_filter(module_filter)
                 .timeout(timeout)
+                .follow_children(follow_children)
                 .record()
         })
-        .await??;
+        .await?;
+
+        let recorded = match recorded {
+            Ok(recorded) => recorded,
+            Err(err) => {
+                // The recorder kills the target when it times out.
+                if let Some(TimerError::Timeout(..)) = err.downcast_ref() {
+                    return Ok(Replay::Skipped(SkipReason::Timeout, err.to_string()));
+                }
+                return Err(err);
+            }
+        };
+
+        for module in recorded.skipped_modules {
+            if !self.skipped_modules.contains(&module) {
+                info!("not recording coverage for module {}", module);
+                self.skipped_modules.insert(module);
+            }
+        }
 
         if let Some(status) = recorded.output.status {
             if !status.success() {
-                bail!("coverage recording failed, child status = {}", status);
+                let detail = format!("child status = {status}");
+                return Ok(Replay::Skipped(SkipReason::Crash, detail));
+            }
+        }
+
+        Ok(Replay::Recorded(recorded.coverage))
+    }
+
+    // Replay `input` under `dotnet-coverage`, which writes the coverage of the
+    // target's managed code as a Cobertura report.
+    async fn record_managed(&self, input: &Path) -> Result<Replay> {
+        let target = self.command_for_input(input).await?;
+        let report_dir = tempfile::tempdir()?;
+        let report = report_dir.path().join("coverage.cobertura.xml");
+
+        let mut cmd = tokio::process::Command::new(dotnet_coverage_path()?);
+        cmd.arg("collect")
+            .args(["--output-format", "cobertura"])
+            .arg("-o")
+            .arg(&report)
+            .arg(managed_command_line(&dotnet_path()?, &target));
+
+        for (k, v) in target.get_envs() {
+            match v {
+                Some(v) => cmd.env(k, v),
+                None => cmd.env_remove(k),
+            };
+        }
+
+        cmd.env_remove("RUST_LOG");
+        cmd.stdin(self.config.stdin(input)?);
+        cmd.stdout(Stdio::piped());
+        cmd.stderr(Stdio::piped());
+        cmd.kill_on_drop(true);
+
+        let timeout = self.config.timeout();
+        let output = match tokio::time::timeout(timeout, cmd.output()).await {
+            Ok(output) => output.context("running dotnet-coverage")?,
+            Err(_) => {
+                let detail = format!("timed out after {}s", timeout.as_secs());
+                return Ok(Replay::Skipped(SkipReason::Timeout, detail));
             }
+        };
+
+        if !output.status.success() {
+            let detail = format!("child status = {}", output.status);
+            return Ok(Replay::Skipped(SkipReason::Crash, detail));
         }
 
-        Ok(recorded.coverage)
+        let xml = fs::read_to_string(&report)
+            .await
+            .with_context(|| format!("reading managed coverage {}", report.display()))?;
+        let mut coverage = parse_source_coverage(&xml)?;
+        coverage
+            .files
+            .retain(|path, _| self.source_allowlist.is_allowed(path.as_str()));
+
+        Ok(Replay::Managed(coverage))
     }
 
     fn uses_input(&self) -> bool {
@@ -346,7 +710,10 @@ impl<'a> TaskContext<'a> {
             try_resolve_setup_relative_path(&self.config.common.setup_dir, &self.config.target_exe)
                 .await?;
 
-        let expand = Expand::new(&self.config.common.machine_identity)
+        let expand = self
+            .config
+            .common
+            .expand()
             .machine_id()
             .input_path(input)
             .job_id(&self.config.common.job_id)
@@ -406,7 +773,7 @@ impl<'a> TaskContext<'a> {
         }
 
         cmd.env_remove("RUST_LOG");
-        cmd.stdin(Stdio::null());
+        cmd.stdin(self.config.stdin(input)?);
         cmd.stdout(Stdio::piped());
         cmd.stderr(Stdio::piped());
 
@@ -421,28 +788,14 @@ impl<'a> TaskContext<'a> {
             .map(ReadDirStream::new)
             .with_context(|| format!("unable to read corpus directory: {}", dir.display()))?;
 
-        let mut count = 0;
+        // Listed first, so that the inputs remaining can be summarized.
+        let mut inputs = vec![];
 
         while let Some(entry) = corpus.next().await {
             match entry {
                 Ok(entry) => {
                     if entry.file_type().await?.is_file() {
-                        if let Err(e) = self.record_input(&entry.path()).await {
-                            event!(coverage_failed; EventData::Path = entry.path().display().to_string());
-                            metric!(coverage_failed; 1.0; EventData::Path = entry.path().display().to_string());
-                            warn!(
-                                "ignoring error recording coverage for input: {}, error: {}",
-                                entry.path().display(),
-                                e
-                            );
-                        } else {
-                            count += 1;
-
-                            // make sure we save & sync coverage every 10 inputs
-                            if count % 10 == 0 {
-                                self.save_and_sync_coverage().await?;
-                            }
-                        }
+                        inputs.push(entry.path());
                     } else {
                         warn!("skipping non-file dir entry: {}", entry.path().display());
                     }
@@ -453,6 +806,39 @@ impl<'a> TaskContext<'a> {
             }
         }
 
+        self.remaining_inputs = inputs.len() as u64;
+        let mut count = 0;
+
+        for input in inputs {
+            let result = self.record_input(&input).await;
+            self.remaining_inputs -= 1;
+
+            match result {
+                Ok(true) => {
+                    count += 1;
+
+                    // make sure we save & sync coverage every 10 inputs
+                    if count % 10 == 0 {
+                        self.save_and_sync_coverage().await?;
+                    }
+                }
+                Ok(false) => {}
+                Err(e) => {
+                    event!(coverage_failed; EventData::Path = input.display().to_string());
+                    metric!(coverage_failed; 1.0; EventData::Path = input.display().to_string());
+                    warn!(
+                        "ignoring error recording coverage for input: {}, error: {}",
+                        input.display(),
+                        e
+                    );
+                }
+            }
+
+            if self.summary_timer.is_due(Instant::now()) {
+                self.report_summary().await;
+            }
+        }
+
         Ok(count)
     }
 
@@ -460,9 +846,13 @@ impl<'a> TaskContext<'a> {
         use EventData::*;
 
         let coverage = RwLock::read(&self.coverage).await;
-        let s = CoverageStats::new(&coverage);
-        event!(coverage_data; Covered = s.covered, Features = s.features, Rate = s.rate);
-        metric!(coverage_data; 1.0; Covered = s.covered, Features = s.features, Rate = s.rate);
+        let mut s = CoverageStats::new(&coverage);
+        s.add(&CoverageStats::source(
+            &*RwLock::read(&self.managed_coverage).await,
+        ));
+        let skipped = self.skipped_inputs.len() as u64;
+        event!(coverage_data; Covered = s.covered, Features = s.features, Rate = s.rate, Skipped = skipped);
+        metric!(coverage_data; 1.0; Covered = s.covered, Features = s.features, Rate = s.rate, Skipped = skipped);
         self.job_result
             .send_direct(
                 JobResultData::CoverageData,
@@ -470,24 +860,87 @@ impl<'a> TaskContext<'a> {
                     ("covered".to_string(), s.covered as f64),
                     ("features".to_string(), s.features as f64),
                     ("rate".to_string(), s.rate),
+                    ("skipped".to_string(), skipped as f64),
                 ]),
             )
             .await;
     }
 
+    /// Emit a summary of the coverage and progress of the task, and save it to
+    /// the `coverage` directory, to be synced with the coverage.
+    pub async fn report_summary(&self) {
+        use EventData::*;
+
+        let inputs = InputStats {
+            processed: self.processed_inputs.len() as u64,
+            skipped: self.skipped_inputs.len() as u64,
+            remaining: self.remaining_inputs,
+            inputs_per_sec: self.input_rate.per_sec(Instant::now()),
+        };
+        let mut summary = CoverageSummary::new(&*RwLock::read(&self.coverage).await, inputs);
+        let managed = CoverageStats::source(&*RwLock::read(&self.managed_coverage).await);
+        summary.stats.add(&managed);
+        let s = &summary.stats;
+
+        event!(coverage_summary; Covered = s.covered, Features = s.features, Rate = s.rate, Processed = inputs.processed, Skipped = inputs.skipped, Remaining = inputs.remaining, ExecsSecond = inputs.inputs_per_sec);
+        metric!(coverage_summary; 1.0; Covered = s.covered, Features = s.features, Rate = s.rate, Processed = inputs.processed, Skipped = inputs.skipped, Remaining = inputs.remaining, ExecsSecond = inputs.inputs_per_sec);
+        self.heartbeat
+            .stats(&[Covered(s.covered), Features(s.features), Rate(s.rate)]);
+
+        for module in &summary.modules {
+            let s = &module.stats;
+            metric!(coverage_module_summary; 1.0; EventData::Path = module.module.clone(), Covered = s.covered, Features = s.features, Rate = s.rate);
+        }
+
+        let path = self.config.coverage.local_path.join(COVERAGE_SUMMARY_FILE);
+        if let Err(err) = summary.save(&path).await {
+            warn!("{:?}", err);
+        }
+    }
+
+    /// Write each of the `output_formats` of `config` to its `coverage`
+    /// directory. Source coverage is only converted if a source format is
+    /// selected.
     pub async fn save_coverage(
         coverage: &RwLock<BinaryCoverage>,
+        managed_coverage: &RwLock<SourceCoverage>,
         source_allowlist: &Arc<AllowList>,
-        binary_coverage_path: &Path,
-        source_coverage_path: &Path,
-        copbertura_file_path: &Path,
+        config: &Config,
     ) -> Result<()> {
-        let source = Self::source_coverage(coverage, source_allowlist.clone()).await?;
-        let coverage = coverage.read().await;
+        let formats = &config.output_formats;
+        let output_dir = &config.coverage.local_path;
+
+        if formats.contains(&CoverageFormat::Binary) {
+            let path = output_dir.join(CoverageFormat::Binary.file_name());
+            Self::save_binary_coverage(&*coverage.read().await, &path)?;
+        }
+
+        if !formats.iter().any(CoverageFormat::is_source) {
+            return Ok(());
+        }
 
-        Self::save_binary_coverage(&coverage, binary_coverage_path)?;
-        Self::save_source_coverage(&source, source_coverage_path).await?;
-        Self::save_cobertura_xml(&source, copbertura_file_path).await?;
+        let mut source = Self::source_coverage(coverage, source_allowlist.clone()).await?;
+        source.merge(&*managed_coverage.read().await);
+
+        for format in formats {
+            let path = output_dir.join(format.file_name());
+
+            match format {
+                CoverageFormat::Binary => {}
+                CoverageFormat::Source => {
+                    Self::save_source_coverage(&source, &path).await?;
+                }
+                CoverageFormat::Cobertura => {
+                    Self::save_cobertura_xml(&source, &path).await?;
+                }
+                CoverageFormat::Lcov => {
+                    Self::save_lcov(&source, &path).await?;
+                }
+                CoverageFormat::Html => {
+                    Self::save_html_report(&source, config, &path).await?;
+                }
+            }
+        }
         Ok(())
     }
 
@@ -503,23 +956,23 @@ impl<'a> TaskContext<'a> {
     }
 
     pub async fn save_and_sync_coverage(&self) -> Result<()> {
-        let copbertura_file_path = self
-            .config
-            .coverage
-            .local_path
-            .join(COBERTURA_COVERAGE_FILE);
-
-        let source_coverage_path = self.config.coverage.local_path.join(SOURCE_COVERAGE_FILE);
-        let binary_coverage_path = self.config.coverage.local_path.join(COVERAGE_FILE);
-
         Self::save_coverage(
             &self.coverage,
+            &self.managed_coverage,
             &self.source_allowlist,
-            &binary_coverage_path,
-            &source_coverage_path,
-            &copbertura_file_path,
+            self.config,
         )
         .await?;
+        // Saved after the coverage of the inputs, so a restarted task never
+        // skips inputs whose coverage was lost.
+        if !self.processed_inputs.is_empty() {
+            self.processed_inputs
+                .save(self.processed_inputs_path())
+                .await?;
+        }
+        if !self.skipped_inputs.is_empty() {
+            self.skipped_inputs.save(self.skipped_inputs_path()).await?;
+        }
         self.config.coverage.sync_push().await?;
         Ok(())
     }
@@ -535,6 +988,28 @@ impl<'a> TaskContext<'a> {
         Ok(())
     }
 
+    async fn save_lcov(source: &SourceCoverage, path: &Path) -> Result<()> {
+        let lcov = Lcov(source).to_string();
+        fs::write(path, lcov)
+            .await
+            .with_context(|| format!("writing lcov coverage to {}", path.display()))?;
+        Ok(())
+    }
+
+    async fn save_html_report(source: &SourceCoverage, config: &Config, path: &Path) -> Result<()> {
+        let path_map = load_path_map(&config.source_path_map, &config.common.setup_dir).await?;
+        let summary = html::write_report(source, config.sources_dir(), &path_map, path)
+            .with_context(|| format!("writing html coverage report to {}", path.display()))?;
+        if summary.unmapped_files() > 0 {
+            debug!(
+                "{} of {} source files not found for html coverage report",
+                summary.unmapped_files(),
+                summary.files
+            );
+        }
+        Ok(())
+    }
+
     async fn save_source_coverage(source: &SourceCoverage, path: &Path) -> Result<()> {
         let json = SourceCoverageJson::V1(SourceCoverageJsonV1::from(source));
         let source_coverage_file = std::fs::File::create(path)
@@ -560,6 +1035,24 @@ impl<'a> TaskContext<'a> {
     }
 }
 
+// The command line of `target` run as a managed assembly by `dotnet`, for
+// `dotnet-coverage collect`. Arguments with whitespace are quoted.
+fn managed_command_line(dotnet: &Path, target: &Command) -> String {
+    [dotnet.as_os_str(), target.get_program()]
+        .into_iter()
+        .chain(target.get_args())
+        .map(|arg| {
+            let arg = arg.to_string_lossy();
+            if arg.contains(char::is_whitespace) {
+                format!("\"{arg}\"")
+            } else {
+                arg.into_owned()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
 #[async_trait]
 impl<'a> Processor for TaskContext<'a> {
     async fn process(&mut self, _url: Option<Url>, input: &Path) -> Result<()> {
@@ -567,37 +1060,109 @@ impl<'a> Processor for TaskContext<'a> {
 
         self.record_input(input).await?;
         self.report_coverage_stats().await;
+        if self.summary_timer.is_due(Instant::now()) {
+            self.report_summary().await;
+        }
         self.save_and_sync_coverage().await?;
 
         Ok(())
     }
 }
 
-#[derive(Default)]
-struct CoverageStats {
-    covered: u64,
-    features: u64,
-    rate: f64,
-}
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::tasks::config::fixture;
+
+    fn parse_config(dir: &Path, output_formats: Option<&[&str]>) -> Result<Config> {
+        let mut config = fixture::config(json!({
+            "target_exe": dir.join("fuzz"),
+            "target_env": {},
+            "target_options": [],
+            "readonly_inputs": [],
+            "coverage": { "path": dir.join("coverage") },
+            "setup_dir": dir,
+        }));
+
+        if let Some(formats) = output_formats {
+            config["output_formats"] = json!(formats);
+        }
 
-impl CoverageStats {
-    pub fn new(coverage: &BinaryCoverage) -> Self {
-        let mut stats = CoverageStats::default();
+        Ok(serde_json::from_value(config)?)
+    }
 
-        for (_, module) in coverage.modules.iter() {
-            for count in module.offsets.values() {
-                stats.features += 1;
+    #[test]
+    fn test_output_formats_config() -> Result<()> {
+        let dir = tempfile::tempdir()?;
 
-                if count.reached() {
-                    stats.covered += 1;
-                }
-            }
-        }
+        let config = parse_config(dir.path(), None)?;
+        assert_eq!(
+            config.output_formats,
+            [
+                CoverageFormat::Binary,
+                CoverageFormat::Source,
+                CoverageFormat::Cobertura,
+            ]
+        );
+
+        let config = parse_config(dir.path(), Some(&["binary", "lcov"]))?;
+        assert_eq!(
+            config.output_formats,
+            [CoverageFormat::Binary, CoverageFormat::Lcov]
+        );
 
-        if stats.features > 0 {
-            stats.rate = (stats.covered as f64) / (stats.features as f64)
+        assert!(parse_config(dir.path(), Some(&["binary", "gcov"])).is_err());
+
+        Ok(())
+    }
+
+    async fn saved_files(formats: &[&str]) -> Result<BTreeSet<String>> {
+        let dir = tempfile::tempdir()?;
+        let config = parse_config(dir.path(), Some(formats))?;
+        fs::create_dir_all(&config.coverage.local_path).await?;
+
+        TaskContext::save_coverage(
+            &RwLock::new(BinaryCoverage::default()),
+            &RwLock::new(SourceCoverage::default()),
+            &Arc::new(AllowList::default()),
+            &config,
+        )
+        .await?;
+
+        let mut files = BTreeSet::new();
+        let mut entries = fs::read_dir(&config.coverage.local_path).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            files.insert(entry.file_name().to_string_lossy().into_owned());
         }
 
-        stats
+        Ok(files)
+    }
+
+    #[tokio::test]
+    async fn test_save_selected_formats() -> Result<()> {
+        assert_eq!(
+            saved_files(&["binary", "source", "cobertura"]).await?,
+            BTreeSet::from([
+                COVERAGE_FILE.to_owned(),
+                SOURCE_COVERAGE_FILE.to_owned(),
+                CoverageFormat::Cobertura.file_name().to_owned(),
+            ])
+        );
+
+        assert_eq!(
+            saved_files(&["binary"]).await?,
+            BTreeSet::from([COVERAGE_FILE.to_owned()])
+        );
+
+        assert_eq!(
+            saved_files(&["lcov"]).await?,
+            BTreeSet::from([CoverageFormat::Lcov.file_name().to_owned()])
+        );
+
+        assert!(saved_files(&[]).await?.is_empty());
+
+        Ok(())
     }
 }
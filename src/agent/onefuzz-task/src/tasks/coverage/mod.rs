@@ -1,7 +1,69 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
+use std::path::Path;
+
+use serde::Deserialize;
+
+const COVERAGE_FILE: &str = "coverage.json";
+const SOURCE_COVERAGE_FILE: &str = "source-coverage.json";
 const COBERTURA_COVERAGE_FILE: &str = "cobertura-coverage.xml";
+const LCOV_COVERAGE_FILE: &str = "lcov.info";
+const HTML_REPORT_DIR: &str = "coverage-report";
+
+/// A format of coverage, written to the `coverage` container.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CoverageFormat {
+    /// The OneFuzz binary coverage JSON, which the task resumes from.
+    Binary,
+
+    /// The OneFuzz source coverage JSON.
+    Source,
+
+    Cobertura,
+    Lcov,
+    Html,
+}
+
+impl CoverageFormat {
+    /// The name of the file, or directory, the format is written to.
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            Self::Binary => COVERAGE_FILE,
+            Self::Source => SOURCE_COVERAGE_FILE,
+            Self::Cobertura => COBERTURA_COVERAGE_FILE,
+            Self::Lcov => LCOV_COVERAGE_FILE,
+            Self::Html => HTML_REPORT_DIR,
+        }
+    }
+
+    /// Whether the format is of source coverage, which is converted from binary
+    /// coverage by resolving the source of each module's blocks.
+    pub fn is_source(&self) -> bool {
+        !matches!(self, Self::Binary)
+    }
+}
+
+pub fn default_output_formats() -> Vec<CoverageFormat> {
+    vec![
+        CoverageFormat::Binary,
+        CoverageFormat::Source,
+        CoverageFormat::Cobertura,
+    ]
+}
+
+// The name inputs are recorded by in the `coverage` container.
+fn input_name(input: &Path) -> String {
+    input
+        .file_name()
+        .unwrap_or(input.as_os_str())
+        .to_string_lossy()
+        .into_owned()
+}
 
 pub mod dotnet;
 pub mod generic;
+pub mod processed;
+pub mod skipped;
+pub mod summary;
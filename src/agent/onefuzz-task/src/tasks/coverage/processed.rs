@@ -0,0 +1,144 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! The manifest of inputs whose coverage has been recorded, so that they
+//! aren't replayed again when the task restarts.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use super::input_name;
+
+pub const PROCESSED_INPUTS_FILE: &str = "coverage-processed.json";
+
+/// The SHA-256 digest of each processed input, by name.
+#[derive(Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ProcessedInputs {
+    inputs: BTreeMap<String, String>,
+}
+
+impl ProcessedInputs {
+    /// Load the manifest saved by an earlier run of the task.
+    ///
+    /// A missing or corrupt manifest is empty, so that every input is
+    /// replayed.
+    pub async fn load(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+
+        let text = match fs::read_to_string(path).await {
+            Ok(text) => text,
+            Err(err) => {
+                if err.kind() != std::io::ErrorKind::NotFound {
+                    warn!(
+                        "unable to read processed inputs {}: {}",
+                        path.display(),
+                        err
+                    );
+                }
+                return Self::default();
+            }
+        };
+
+        match serde_json::from_str(&text) {
+            Ok(processed) => processed,
+            Err(err) => {
+                warn!(
+                    "ignoring corrupt processed inputs {}: {}",
+                    path.display(),
+                    err
+                );
+                Self::default()
+            }
+        }
+    }
+
+    pub async fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let json = serde_json::to_string_pretty(self)?;
+
+        fs::write(path, json)
+            .await
+            .with_context(|| format!("writing processed inputs {}", path.display()))?;
+
+        Ok(())
+    }
+
+    /// Whether `input` was processed with the content `sha256`. Inputs whose
+    /// content has changed must be replayed.
+    pub fn contains(&self, input: &Path, sha256: &str) -> bool {
+        self.inputs.get(&input_name(input)).map(String::as_str) == Some(sha256)
+    }
+
+    pub fn insert(&mut self, input: &Path, sha256: impl Into<String>) {
+        self.inputs.insert(input_name(input), sha256.into());
+    }
+
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_save_load() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join(PROCESSED_INPUTS_FILE);
+
+        let mut processed = ProcessedInputs::default();
+        processed.insert(Path::new("corpus/a"), "aaaa");
+        processed.insert(Path::new("corpus/b"), "bbbb");
+        processed.save(&path).await?;
+
+        let loaded = ProcessedInputs::load(&path).await;
+        assert_eq!(loaded, processed);
+        assert_eq!(loaded.len(), 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_load_missing_or_corrupt() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join(PROCESSED_INPUTS_FILE);
+
+        assert!(ProcessedInputs::load(&path).await.is_empty());
+
+        fs::write(&path, r#"{"inputs": {"a": "#).await?;
+        assert!(ProcessedInputs::load(&path).await.is_empty());
+
+        fs::write(&path, r#"["a", "b"]"#).await?;
+        assert!(ProcessedInputs::load(&path).await.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_contains() {
+        let a = Path::new("corpus/a");
+        let mut processed = ProcessedInputs::default();
+        processed.insert(a, "aaaa");
+
+        assert!(processed.contains(a, "aaaa"));
+        assert!(processed.contains(Path::new("queue/a"), "aaaa"));
+
+        // New inputs are replayed.
+        assert!(!processed.contains(Path::new("corpus/b"), "aaaa"));
+
+        // So are inputs whose content has changed, until they're processed again.
+        assert!(!processed.contains(a, "cccc"));
+        processed.insert(a, "cccc");
+        assert!(processed.contains(a, "cccc"));
+        assert!(!processed.contains(a, "aaaa"));
+    }
+}
@@ -0,0 +1,153 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Inputs whose coverage wasn't recorded, because the target timed out or
+//! crashed when replaying them.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use super::input_name;
+
+pub const SKIPPED_INPUTS_FILE: &str = "coverage-skipped.json";
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    /// The target exceeded the input timeout, and was killed.
+    Timeout,
+
+    /// The target crashed, or otherwise exited unsuccessfully.
+    Crash,
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct SkippedInput {
+    /// The file name of the input.
+    pub input: String,
+
+    pub reason: SkipReason,
+
+    /// The error or exit status of the target.
+    pub detail: String,
+}
+
+/// The skipped inputs of a coverage task, by name.
+#[derive(Debug, Default)]
+pub struct SkippedInputs {
+    inputs: BTreeMap<String, SkippedInput>,
+}
+
+impl SkippedInputs {
+    /// Load the inputs skipped by an earlier run of the task, if any.
+    pub async fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+
+        let text = match fs::read_to_string(path).await {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("reading skipped inputs {}", path.display()))
+            }
+        };
+
+        let skipped: Vec<SkippedInput> = serde_json::from_str(&text)
+            .with_context(|| format!("parsing skipped inputs {}", path.display()))?;
+        let inputs = skipped
+            .into_iter()
+            .map(|skipped| (skipped.input.clone(), skipped))
+            .collect();
+
+        Ok(Self { inputs })
+    }
+
+    /// Write the skipped inputs as a JSON array, ordered by name.
+    pub async fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let skipped: Vec<_> = self.inputs.values().collect();
+        let json = serde_json::to_string_pretty(&skipped)?;
+
+        fs::write(path, json)
+            .await
+            .with_context(|| format!("writing skipped inputs {}", path.display()))?;
+
+        Ok(())
+    }
+
+    pub fn insert(&mut self, input: &Path, reason: SkipReason, detail: impl Into<String>) {
+        let name = input_name(input);
+        let skipped = SkippedInput {
+            input: name.clone(),
+            reason,
+            detail: detail.into(),
+        };
+        self.inputs.insert(name, skipped);
+    }
+
+    pub fn contains(&self, input: &Path) -> bool {
+        self.inputs.contains_key(&input_name(input))
+    }
+
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_save_load() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join(SKIPPED_INPUTS_FILE);
+
+        // Nothing skipped before the first run.
+        let mut skipped = SkippedInputs::load(&path).await?;
+        assert!(skipped.is_empty());
+
+        skipped.insert(
+            Path::new("corpus/hang"),
+            SkipReason::Timeout,
+            "function exceeded timeout of 45s",
+        );
+        skipped.insert(Path::new("corpus/crash"), SkipReason::Crash, "signal: 6");
+        assert!(skipped.contains(Path::new("inputs/hang")));
+        assert!(!skipped.contains(Path::new("corpus/ok")));
+        skipped.save(&path).await?;
+
+        let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path).await?)?;
+        assert_eq!(
+            json,
+            serde_json::json!([
+                {"input": "crash", "reason": "crash", "detail": "signal: 6"},
+                {"input": "hang", "reason": "timeout", "detail": "function exceeded timeout of 45s"},
+            ])
+        );
+
+        let loaded = SkippedInputs::load(&path).await?;
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded.inputs, skipped.inputs);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_replaces() {
+        let mut skipped = SkippedInputs::default();
+        skipped.insert(Path::new("a/input"), SkipReason::Timeout, "timeout");
+        skipped.insert(Path::new("b/input"), SkipReason::Crash, "exit code: 1");
+
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped.inputs["input"].reason, SkipReason::Crash);
+    }
+}
@@ -0,0 +1,376 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! A summary of the progress of a coverage task, emitted periodically as
+//! telemetry, and saved to the `coverage` container for dashboards which read
+//! storage.
+
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use coverage::binary::{BinaryCoverage, ModuleBinaryCoverage};
+use coverage::source::SourceCoverage;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+pub const COVERAGE_SUMMARY_FILE: &str = "coverage-summary.json";
+
+pub const DEFAULT_SUMMARY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Modules summarized individually, those with the most features first. The
+/// rest are only counted in the totals.
+pub const MAX_SUMMARY_MODULES: usize = 20;
+
+/// The features of some coverage, and how many of them are covered.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct CoverageStats {
+    pub features: u64,
+    pub covered: u64,
+    pub rate: f64,
+}
+
+impl CoverageStats {
+    pub fn new(coverage: &BinaryCoverage) -> Self {
+        let mut stats = Self::default();
+
+        for module in coverage.modules.values() {
+            stats.add(&Self::module(module));
+        }
+
+        stats
+    }
+
+    pub fn module(module: &ModuleBinaryCoverage) -> Self {
+        let mut stats = Self::default();
+
+        for count in module.offsets.values() {
+            stats.features += 1;
+
+            if count.reached() {
+                stats.covered += 1;
+            }
+        }
+
+        stats.update_rate();
+        stats
+    }
+
+    /// The stats of the lines of `source`, such as of managed code, which has
+    /// no binary coverage.
+    pub fn source(source: &SourceCoverage) -> Self {
+        let mut stats = Self::default();
+
+        for file in source.files.values() {
+            for count in file.lines.values() {
+                stats.features += 1;
+
+                if count.reached() {
+                    stats.covered += 1;
+                }
+            }
+        }
+
+        stats.update_rate();
+        stats
+    }
+
+    pub fn add(&mut self, other: &Self) {
+        self.features += other.features;
+        self.covered += other.covered;
+        self.update_rate();
+    }
+
+    fn update_rate(&mut self) {
+        self.rate = if self.features > 0 {
+            (self.covered as f64) / (self.features as f64)
+        } else {
+            0.0
+        };
+    }
+}
+
+/// The progress of a task through its inputs.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct InputStats {
+    /// Inputs whose coverage has been recorded, including by earlier runs.
+    pub processed: u64,
+
+    /// Inputs skipped because the target timed out or crashed.
+    pub skipped: u64,
+
+    /// Inputs of the current corpus directory not yet replayed.
+    pub remaining: u64,
+
+    /// Inputs replayed per second by this run.
+    pub inputs_per_sec: f64,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ModuleSummary {
+    pub module: String,
+
+    #[serde(flatten)]
+    pub stats: CoverageStats,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct CoverageSummary {
+    #[serde(flatten)]
+    pub stats: CoverageStats,
+
+    /// At most `MAX_SUMMARY_MODULES` modules.
+    pub modules: Vec<ModuleSummary>,
+
+    /// Modules omitted from `modules`.
+    pub other_modules: u64,
+
+    pub inputs: InputStats,
+}
+
+impl CoverageSummary {
+    pub fn new(coverage: &BinaryCoverage, inputs: InputStats) -> Self {
+        let mut modules: Vec<ModuleSummary> = coverage
+            .modules
+            .iter()
+            .map(|(path, module)| ModuleSummary {
+                module: path.to_string(),
+                stats: CoverageStats::module(module),
+            })
+            .collect();
+
+        let mut stats = CoverageStats::default();
+        for module in &modules {
+            stats.add(&module.stats);
+        }
+
+        // Stable, so that modules with as many features stay in path order.
+        modules.sort_by(|a, b| b.stats.features.cmp(&a.stats.features));
+
+        let other_modules = modules.len().saturating_sub(MAX_SUMMARY_MODULES) as u64;
+        modules.truncate(MAX_SUMMARY_MODULES);
+
+        Self {
+            stats,
+            modules,
+            other_modules,
+            inputs,
+        }
+    }
+
+    pub async fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let json = serde_json::to_string_pretty(self)?;
+
+        // Replaced at once, so that readers never see a partial summary, and
+        // directory monitors see each summary as a new file.
+        let temp = path.with_extension("json.tmp");
+        fs::write(&temp, json)
+            .await
+            .with_context(|| format!("writing coverage summary {}", temp.display()))?;
+        fs::rename(&temp, path)
+            .await
+            .with_context(|| format!("writing coverage summary {}", path.display()))?;
+
+        Ok(())
+    }
+}
+
+/// Counts the inputs replayed since a task started, for its rate.
+pub struct InputRate {
+    started: Instant,
+    replayed: u64,
+}
+
+impl InputRate {
+    pub fn new(started: Instant) -> Self {
+        Self {
+            started,
+            replayed: 0,
+        }
+    }
+
+    pub fn replayed(&mut self) {
+        self.replayed += 1;
+    }
+
+    pub fn per_sec(&self, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(self.started).as_secs_f64();
+
+        if elapsed > 0.0 {
+            (self.replayed as f64) / elapsed
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Whether a summary is due, at most once per interval.
+pub struct SummaryTimer {
+    interval: Duration,
+    last: Option<Instant>,
+}
+
+impl SummaryTimer {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last: None,
+        }
+    }
+
+    /// Whether a summary is due at `now`, the first time or once `interval`
+    /// has passed since the last. If so, the next is due an interval later.
+    pub fn is_due(&mut self, now: Instant) -> bool {
+        if let Some(last) = self.last {
+            if now.saturating_duration_since(last) < self.interval {
+                return false;
+            }
+        }
+
+        self.last = Some(now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use coverage::binary::{Count, FilePath, Offset};
+
+    use super::*;
+
+    fn module(hit: u32, missed: u32) -> ModuleBinaryCoverage {
+        let mut module = ModuleBinaryCoverage::default();
+
+        for i in 0..(hit + missed) {
+            let count = if i < hit { Count(1) } else { Count(0) };
+            module.offsets.insert(Offset(i.into()), count);
+        }
+
+        module
+    }
+
+    #[test]
+    fn test_summary() -> Result<()> {
+        let mut coverage = BinaryCoverage::default();
+        coverage
+            .modules
+            .insert(FilePath::new("/setup/fuzz")?, module(3, 1));
+        coverage
+            .modules
+            .insert(FilePath::new("/setup/libcheck.so")?, module(0, 2));
+        coverage
+            .modules
+            .insert(FilePath::new("/setup/libempty.so")?, module(0, 0));
+
+        let inputs = InputStats {
+            processed: 4,
+            skipped: 1,
+            remaining: 2,
+            inputs_per_sec: 0.5,
+        };
+        let summary = CoverageSummary::new(&coverage, inputs);
+
+        assert_eq!(summary.stats.features, 6);
+        assert_eq!(summary.stats.covered, 3);
+        assert_eq!(summary.stats.rate, 0.5);
+        assert_eq!(summary.inputs, inputs);
+        assert_eq!(summary.other_modules, 0);
+
+        let modules: Vec<_> = summary
+            .modules
+            .iter()
+            .map(|m| (m.module.as_str(), m.stats.features, m.stats.covered))
+            .collect();
+        assert_eq!(
+            modules,
+            [
+                ("/setup/fuzz", 4, 3),
+                ("/setup/libcheck.so", 2, 0),
+                ("/setup/libempty.so", 0, 0),
+            ]
+        );
+        assert_eq!(summary.modules[0].stats.rate, 0.75);
+        assert_eq!(summary.modules[2].stats.rate, 0.0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_summary_modules_capped() -> Result<()> {
+        let mut coverage = BinaryCoverage::default();
+        for i in 0..(MAX_SUMMARY_MODULES as u32 + 5) {
+            let path = FilePath::new(format!("/setup/lib{i:02}.so"))?;
+            coverage.modules.insert(path, module(1, i));
+        }
+
+        let summary = CoverageSummary::new(&coverage, InputStats::default());
+        assert_eq!(summary.modules.len(), MAX_SUMMARY_MODULES);
+        assert_eq!(summary.other_modules, 5);
+
+        // The largest modules are kept, and all are totaled.
+        assert_eq!(summary.modules[0].module, "/setup/lib24.so");
+        assert_eq!(summary.modules.last().unwrap().module, "/setup/lib05.so");
+        assert_eq!(summary.stats.covered, MAX_SUMMARY_MODULES as u64 + 5);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_save() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join(COVERAGE_SUMMARY_FILE);
+
+        let mut coverage = BinaryCoverage::default();
+        coverage
+            .modules
+            .insert(FilePath::new("/setup/fuzz")?, module(1, 1));
+        let summary = CoverageSummary::new(&coverage, InputStats::default());
+        summary.save(&path).await?;
+
+        let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path).await?)?;
+        assert_eq!(json["features"], 2);
+        assert_eq!(json["covered"], 1);
+        assert_eq!(json["modules"][0]["module"], "/setup/fuzz");
+        assert_eq!(json["inputs"]["remaining"], 0);
+
+        let loaded: CoverageSummary = serde_json::from_value(json)?;
+        assert_eq!(loaded, summary);
+
+        // Saved again in place, without leaving the staged file.
+        summary.save(&path).await?;
+        assert!(path.is_file());
+        assert!(!path.with_extension("json.tmp").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_input_rate() {
+        let started = Instant::now();
+        let mut rate = InputRate::new(started);
+        assert_eq!(rate.per_sec(started), 0.0);
+
+        for _ in 0..10 {
+            rate.replayed();
+        }
+        assert_eq!(rate.per_sec(started + Duration::from_secs(4)), 2.5);
+    }
+
+    #[test]
+    fn test_summary_timer() {
+        let start = Instant::now();
+        let mut timer = SummaryTimer::new(Duration::from_secs(60));
+
+        // The first summary is due at once.
+        assert!(timer.is_due(start));
+        assert!(!timer.is_due(start));
+        assert!(!timer.is_due(start + Duration::from_secs(59)));
+
+        assert!(timer.is_due(start + Duration::from_secs(60)));
+
+        // The next is due an interval after the last, not after the start.
+        assert!(!timer.is_due(start + Duration::from_secs(100)));
+        assert!(timer.is_due(start + Duration::from_secs(121)));
+    }
+}
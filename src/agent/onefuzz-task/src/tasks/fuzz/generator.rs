@@ -4,18 +4,23 @@
 use crate::tasks::{
     config::CommonConfig,
     heartbeat::{HeartbeatSender, TaskHeartbeatClient},
-    utils::{self, default_bool_true, try_resolve_setup_relative_path},
+    utils::{self, default_bool_true, is_name_char, try_resolve_setup_relative_path},
 };
 use anyhow::{Context, Result};
 use onefuzz::{
     expand::Expand,
     fs::set_executable,
-    input_tester::Tester,
+    initial_corpus::MaxInitialCorpus,
+    input_tester::{InputVia, Tester},
     process::monitor_process,
     sha256,
-    syncdir::{continuous_sync, SyncOperation::Pull, SyncedDir},
+    syncdir::{continuous_sync_pull, SyncedDir},
 };
-use onefuzz_telemetry::Event::new_result;
+use onefuzz_telemetry::{
+    Event::{generator_crash, new_result},
+    EventData,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use serde::Deserialize;
 use std::collections::HashMap;
 use std::{
@@ -28,9 +33,18 @@ use tokio::{fs, process::Command};
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Config {
+    #[serde(default)]
     pub generator_exe: String,
+    #[serde(default)]
     pub generator_env: HashMap<String, String>,
+    #[serde(default)]
     pub generator_options: Vec<String>,
+
+    /// Further generators, picked between with `generator_exe` for each batch
+    /// of inputs.
+    #[serde(default)]
+    pub generators: Vec<Generator>,
+
     pub readonly_inputs: Vec<SyncedDir>,
     pub crashes: SyncedDir,
     pub tools: Option<SyncedDir>,
@@ -38,6 +52,12 @@ pub struct Config {
     pub target_exe: PathBuf,
     pub target_env: HashMap<String, String>,
     pub target_options: Vec<String>,
+
+    /// How generated inputs are passed to the target: as the file of
+    /// `{input}`, or on its standard input.
+    #[serde(default)]
+    pub input_via: InputVia,
+
     pub target_timeout: Option<u64>,
     #[serde(default)]
     pub check_asan_log: bool,
@@ -47,10 +67,154 @@ pub struct Config {
     pub check_retry_count: u64,
     pub rename_output: bool,
     pub ensemble_sync_delay: Option<u64>,
+
+    /// Limits on the initial corpus pulled from each of the `readonly_inputs`.
+    pub max_initial_corpus: Option<MaxInitialCorpus>,
+
     #[serde(flatten)]
     pub common: CommonConfig,
 }
 
+/// A generator of inputs, picked for a batch in proportion to its weight.
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Generator {
+    /// Prefixes the names of the crashes found with its inputs, when there
+    /// are several generators.
+    pub name: String,
+    pub generator_exe: String,
+    #[serde(default)]
+    pub generator_env: HashMap<String, String>,
+    #[serde(default)]
+    pub generator_options: Vec<String>,
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+impl Config {
+    /// The generators to pick from: the one set by `generator_exe`, if any,
+    /// named after its file, then `generators`.
+    pub fn generators(&self) -> Result<Vec<Generator>> {
+        let mut generators = vec![];
+
+        if !self.generator_exe.is_empty() {
+            generators.push(Generator {
+                name: generator_name(&self.generator_exe),
+                generator_exe: self.generator_exe.clone(),
+                generator_env: self.generator_env.clone(),
+                generator_options: self.generator_options.clone(),
+                weight: default_weight(),
+            });
+        }
+        generators.extend(self.generators.iter().cloned());
+
+        if generators.is_empty() {
+            bail!("no generator: set `generator_exe` or `generators`");
+        }
+
+        for (i, generator) in generators.iter().enumerate() {
+            let name = &generator.name;
+            if name.is_empty() || !name.chars().all(is_name_char) {
+                bail!("invalid generator name: {name:?}, must only contain letters, digits, `-` and `_`");
+            }
+            if generators[..i].iter().any(|g| g.name == *name) {
+                bail!("duplicate generator name: {name}");
+            }
+        }
+
+        if generators.iter().all(|g| g.weight == 0) {
+            bail!("every generator has a weight of 0");
+        }
+
+        Ok(generators)
+    }
+
+    /// The executables of every generator, for telemetry.
+    pub fn tool_name(&self) -> String {
+        let mut exes = vec![];
+        if !self.generator_exe.is_empty() {
+            exes.push(self.generator_exe.as_str());
+        }
+        exes.extend(self.generators.iter().map(|g| g.generator_exe.as_str()));
+        exes.join(",")
+    }
+}
+
+// Name the generator of `generator_exe` after its file, such as `radamsa` for
+// `{tools_dir}/radamsa.exe`.
+fn generator_name(generator_exe: &str) -> String {
+    let name: String = Path::new(generator_exe)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .unwrap_or_default()
+        .chars()
+        .map(|c| if is_name_char(c) { c } else { '_' })
+        .collect();
+
+    if name.is_empty() {
+        "generator".to_owned()
+    } else {
+        name
+    }
+}
+
+// Prefix the name of a crash with the name of the generator of its input.
+fn crash_file_name(generator: Option<&str>, file_name: OsString) -> OsString {
+    match generator {
+        Some(name) => {
+            let mut prefixed = OsString::from(format!("{name}-"));
+            prefixed.push(file_name);
+            prefixed
+        }
+        None => file_name,
+    }
+}
+
+/// Picks a generator for each batch of inputs, in proportion to its weight.
+struct GeneratorPicker<R> {
+    weights: Vec<u32>,
+    rng: R,
+}
+
+impl<R: Rng> GeneratorPicker<R> {
+    fn new(generators: &[Generator], rng: R) -> Self {
+        let weights = generators.iter().map(|g| g.weight).collect();
+        Self { weights, rng }
+    }
+
+    /// The index of the next generator, or `None` if none can be picked.
+    fn pick(&mut self) -> Option<usize> {
+        let total: u64 = self.weights.iter().map(|w| u64::from(*w)).sum();
+        if total == 0 {
+            return None;
+        }
+
+        let mut n = self.rng.gen_range(0..total);
+        for (i, weight) in self.weights.iter().enumerate() {
+            let weight = u64::from(*weight);
+            if n < weight {
+                return Some(i);
+            }
+            n -= weight;
+        }
+
+        None
+    }
+
+    /// Stop picking the generator at `index`.
+    fn disable(&mut self, index: usize) {
+        self.weights[index] = 0;
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.weights.iter().all(|w| *w == 0)
+    }
+}
+
 pub struct GeneratorTask {
     config: Config,
 }
@@ -60,6 +224,38 @@ impl GeneratorTask {
         Self { config }
     }
 
+    // Check the libraries of each generator, once the tools are in place.
+    async fn check_generator_libraries(&self) -> Result<()> {
+        let common = &self.config.common;
+        let expand = common
+            .expand()
+            .machine_id()
+            .job_id(&common.job_id)
+            .task_id(&common.task_id)
+            .setup_dir(&common.setup_dir)
+            .set_optional_ref(&common.extra_setup_dir, Expand::extra_setup_dir)
+            .set_optional_ref(&self.config.tools, |expand, tools| {
+                expand.tools_dir(&tools.local_path)
+            });
+
+        for generator in self.config.generators()? {
+            let Ok(generator_exe) = expand.evaluate_value(&generator.generator_exe) else {
+                continue;
+            };
+
+            utils::check_libraries(
+                "generator_exe",
+                Path::new(&generator_exe),
+                &generator.generator_env,
+                &expand,
+                &common.setup_dir,
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
     pub async fn run(&self) -> Result<()> {
         self.config.crashes.init().await.with_context(|| {
             format!(
@@ -71,24 +267,27 @@ impl GeneratorTask {
             tools.init_pull().await?;
             set_executable(&tools.local_path).await?;
         }
+        self.check_generator_libraries().await?;
 
         let hb_client = self.config.common.init_heartbeat(None).await?;
         let jr_client = self.config.common.init_job_result().await?;
 
+        let mut readonly_inputs = vec![];
         for dir in &self.config.readonly_inputs {
-            dir.init_pull().await?;
+            readonly_inputs.push(
+                dir.init_pull_sampled(self.config.max_initial_corpus.as_ref())
+                    .await?,
+            );
         }
 
-        let sync_task = continuous_sync(
-            &self.config.readonly_inputs,
-            Pull,
-            self.config.ensemble_sync_delay,
-        );
+        let sync_task = continuous_sync_pull(&readonly_inputs, self.config.ensemble_sync_delay);
 
-        let crash_dir_monitor = self
-            .config
-            .crashes
-            .monitor_results(new_result, false, &jr_client);
+        let crash_dir_monitor = self.config.crashes.monitor_results(
+            new_result,
+            false,
+            self.config.common.task_id,
+            &jr_client,
+        );
 
         let fuzzer = self.fuzzing_loop(hb_client);
 
@@ -109,13 +308,22 @@ impl GeneratorTask {
             &self.config.target_env,
             self.config.common.machine_identity.clone(),
         )
+        .input_via(self.config.input_via)
         .check_asan_log(self.config.check_asan_log)
         .check_debugger(self.config.check_debugger)
         .check_retry_count(self.config.check_retry_count)
+        .allow_unknown_placeholders(self.config.common.allow_unknown_placeholders)
+        .allow_env_placeholders(self.config.common.allow_env_placeholders)
+        .target_options_shell(self.config.common.target_options_shell)
+        .set_optional(self.config.common.qemu_user.as_ref(), Tester::qemu_user)
         .set_optional(self.config.target_timeout, |tester, timeout| {
             tester.timeout(timeout)
         });
 
+        let generators = self.config.generators()?;
+        let mut picker = GeneratorPicker::new(&generators, StdRng::from_entropy());
+        let mut crashes = vec![0u64; generators.len()];
+
         loop {
             for corpus_dir in &self.config.readonly_inputs {
                 heartbeat_client.alive();
@@ -123,21 +331,72 @@ impl GeneratorTask {
                 let generated_inputs = tempdir()?;
                 let generated_inputs_path = generated_inputs.path();
 
-                self.generate_inputs(corpus_dir, &generated_inputs_path)
+                let index = self
+                    .generate(&generators, &mut picker, corpus_dir, generated_inputs_path)
                     .await
                     .context("generate inputs failed")?;
-                self.test_inputs(&generated_inputs_path, &tester)
+
+                let generator = &generators[index];
+                let prefix = (generators.len() > 1).then_some(generator.name.as_str());
+                let found = self
+                    .test_inputs(&generated_inputs_path, &tester, prefix)
                     .await
                     .context("test inputs failed")?;
+
+                if found > 0 {
+                    crashes[index] += found;
+                    event!(generator_crash; EventData::ToolName = generator.name.clone(), EventData::Count = crashes[index]);
+                    metric!(generator_crash; found as f64; EventData::ToolName = generator.name.clone());
+                }
             }
         }
     }
 
+    // Generate inputs with a generator picked by weight, returning its index.
+    // A generator which fails is not picked again, so that the task carries on
+    // with the others.
+    async fn generate<R: Rng>(
+        &self,
+        generators: &[Generator],
+        picker: &mut GeneratorPicker<R>,
+        corpus_dir: &Path,
+        output_dir: &Path,
+    ) -> Result<usize> {
+        loop {
+            let index = picker
+                .pick()
+                .ok_or_else(|| format_err!("no generator left to pick"))?;
+            let generator = &generators[index];
+
+            match self
+                .generate_inputs(corpus_dir, output_dir, generator)
+                .await
+            {
+                Ok(()) => return Ok(index),
+                Err(err) => {
+                    picker.disable(index);
+                    if picker.is_exhausted() {
+                        return Err(err);
+                    }
+                    warn!(
+                        "generator {} failed, no longer picking it: {:?}",
+                        generator.name, err
+                    );
+                }
+            }
+        }
+    }
+
+    // Move the crashing inputs to the crashes directory, returning how many
+    // there were. With several generators, each crash is named after its
+    // generator, given as `prefix`.
     async fn test_inputs(
         &self,
         generated_inputs: impl AsRef<Path>,
         tester: &Tester<'_>,
-    ) -> Result<()> {
+        prefix: Option<&str>,
+    ) -> Result<u64> {
+        let mut found = 0;
         let mut read_dir = fs::read_dir(generated_inputs).await?;
         while let Some(file) = read_dir.next_entry().await? {
             debug!("testing input: {}", file.path().display());
@@ -149,6 +408,7 @@ impl GeneratorTask {
                 file.file_name()
             };
 
+            let destination_file = crash_file_name(prefix, destination_file);
             let destination_file = self.config.crashes.local_path.join(destination_file);
             if tester
                 .is_crash(file.path())
@@ -157,19 +417,24 @@ impl GeneratorTask {
             {
                 fs::rename(file.path(), &destination_file).await?;
                 debug!("crash found {}", destination_file.display());
+                found += 1;
             }
         }
-        Ok(())
+        Ok(found)
     }
 
     async fn generate_inputs(
         &self,
         corpus_dir: impl AsRef<Path>,
         output_dir: impl AsRef<Path>,
+        generator: &Generator,
     ) -> Result<()> {
         utils::reset_tmp_dir(&output_dir).await?;
         let (mut generator, generator_path) = {
-            let expand = Expand::new(&self.config.common.machine_identity)
+            let expand = self
+                .config
+                .common
+                .expand()
                 .machine_id()
                 .setup_dir(&self.config.common.setup_dir)
                 .set_optional_ref(&self.config.common.extra_setup_dir, Expand::extra_setup_dir)
@@ -178,8 +443,8 @@ impl GeneratorTask {
                 })
                 .generated_inputs(&output_dir)
                 .input_corpus(&corpus_dir)
-                .generator_exe(&self.config.generator_exe)
-                .generator_options(&self.config.generator_options)
+                .generator_exe(&generator.generator_exe)
+                .generator_options(&generator.generator_options)
                 .job_id(&self.config.common.job_id)
                 .task_id(&self.config.common.task_id)
                 .set_optional_ref(
@@ -193,24 +458,24 @@ impl GeneratorTask {
                     expand.tools_dir(&tools.local_path)
                 });
 
-            let generator_path = expand.evaluate_value(&self.config.generator_exe)?;
+            let generator_path = expand.evaluate_value(&generator.generator_exe)?;
 
-            let mut generator = Command::new(&generator_path);
-            generator
+            let mut command = Command::new(&generator_path);
+            command
                 .kill_on_drop(true)
                 .env_remove("RUST_LOG")
                 .stdin(Stdio::null())
                 .stdout(Stdio::piped())
                 .stderr(Stdio::piped());
 
-            for arg in expand.evaluate(&self.config.generator_options)? {
-                generator.arg(arg);
+            for arg in expand.evaluate(&generator.generator_options)? {
+                command.arg(arg);
             }
 
-            for (k, v) in &self.config.generator_env {
-                generator.env(k, expand.evaluate_value(v)?);
+            for (k, v) in &generator.generator_env {
+                command.env(k, expand.evaluate_value(v)?);
             }
-            (generator, generator_path)
+            (command, generator_path)
         };
 
         info!("Generating test cases with {:?}", generator);
@@ -225,7 +490,191 @@ impl GeneratorTask {
     }
 }
 
+#[cfg(test)]
 mod tests {
+    use super::*;
+
+    fn generator(name: &str, weight: u32) -> Generator {
+        Generator {
+            name: name.to_owned(),
+            generator_exe: format!("{name}.exe"),
+            generator_env: HashMap::new(),
+            generator_options: vec![],
+            weight,
+        }
+    }
+
+    // A generator task config, with the containers in `dir`.
+    fn config(dir: &Path, task_config: serde_json::Value) -> Result<Config> {
+        use crate::tasks::config::fixture;
+        use serde_json::json;
+
+        let mut config = fixture::config(json!({
+            "readonly_inputs": [{ "path": dir.join("inputs") }],
+            "crashes": { "path": dir.join("crashes") },
+            "target_exe": "fuzz.exe",
+            "target_env": {},
+            "target_options": [],
+            "rename_output": false,
+            "setup_dir": dir,
+        }));
+        fixture::merge(&mut config, task_config);
+
+        Ok(serde_json::from_value(config)?)
+    }
+
+    #[test]
+    fn test_generators() -> Result<()> {
+        use serde_json::json;
+
+        let dir = tempfile::tempdir()?;
+
+        let config = config(
+            dir.path(),
+            json!({
+                "generator_exe": "{tools_dir}/radamsa.exe",
+                "generators": [
+                    { "name": "grammar", "generator_exe": "grammar.exe", "weight": 3 },
+                    { "name": "off", "generator_exe": "off.exe", "weight": 0 },
+                ],
+            }),
+        )?;
+        let generators = config.generators()?;
+        let names: Vec<_> = generators.iter().map(|g| g.name.as_str()).collect();
+        let weights: Vec<_> = generators.iter().map(|g| g.weight).collect();
+        assert_eq!(names, ["radamsa", "grammar", "off"]);
+        assert_eq!(weights, [1, 3, 0]);
+        assert_eq!(
+            config.tool_name(),
+            "{tools_dir}/radamsa.exe,grammar.exe,off.exe"
+        );
+
+        let cases = [
+            (vec![], "no generator: set `generator_exe` or `generators`"),
+            (
+                vec![generator("a b", 1)],
+                "invalid generator name: \"a b\", must only contain letters, digits, `-` and `_`",
+            ),
+            (
+                vec![generator("a", 1), generator("a", 2)],
+                "duplicate generator name: a",
+            ),
+            (
+                vec![generator("a", 0), generator("b", 0)],
+                "every generator has a weight of 0",
+            ),
+        ];
+        for (generators, expected) in cases {
+            let config = Config {
+                generator_exe: String::new(),
+                generators,
+                ..config.clone()
+            };
+            let err = config.generators().unwrap_err();
+            assert_eq!(err.to_string(), expected);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_generator_name() {
+        assert_eq!(generator_name("{tools_dir}/radamsa"), "radamsa");
+        assert_eq!(generator_name("/usr/bin/gen.v2.exe"), "gen_v2");
+        assert_eq!(generator_name(""), "generator");
+    }
+
+    #[test]
+    fn test_pick_weighted() {
+        let generators = [generator("a", 1), generator("b", 3), generator("c", 0)];
+        let mut picker = GeneratorPicker::new(&generators, StdRng::seed_from_u64(0));
+
+        let mut picks = [0; 3];
+        for _ in 0..10000 {
+            picks[picker.pick().unwrap()] += 1;
+        }
+
+        assert!((2250..2750).contains(&picks[0]), "{picks:?}");
+        assert!((7250..7750).contains(&picks[1]), "{picks:?}");
+        assert_eq!(picks[2], 0);
+
+        picker.disable(1);
+        assert!(!picker.is_exhausted());
+        assert!((0..100).all(|_| picker.pick() == Some(0)));
+
+        picker.disable(0);
+        assert!(picker.is_exhausted());
+        assert_eq!(picker.pick(), None);
+    }
+
+    #[test]
+    fn test_crash_file_name() {
+        assert_eq!(
+            crash_file_name(Some("grammar"), "input-1".into()),
+            OsString::from("grammar-input-1")
+        );
+        assert_eq!(
+            crash_file_name(None, "input-1".into()),
+            OsString::from("input-1")
+        );
+    }
+
+    // A generator which fails is no longer picked, and its batch is generated
+    // by another.
+    #[tokio::test]
+    #[cfg(target_family = "unix")]
+    async fn test_generate_after_failure() -> Result<()> {
+        use serde_json::json;
+
+        let dir = tempfile::tempdir()?;
+        let broken = dir.path().join("broken.sh");
+        let working = dir.path().join("working.sh");
+        fs::write(&broken, "#!/bin/sh\nexit 1\n").await?;
+        fs::write(&working, "#!/bin/sh\necho input > \"$1/input\"\n").await?;
+        set_executable(dir.path()).await?;
+
+        let config = config(
+            dir.path(),
+            json!({
+                "generators": [
+                    { "name": "broken", "generator_exe": broken, "weight": 1000 },
+                    {
+                        "name": "working",
+                        "generator_exe": working,
+                        "generator_options": ["{generated_inputs}"],
+                    },
+                ],
+            }),
+        )?;
+        let generators = config.generators()?;
+        let task = GeneratorTask::new(config);
+        let mut picker = GeneratorPicker::new(&generators, StdRng::seed_from_u64(0));
+
+        let corpus = tempfile::tempdir()?;
+        let output = tempfile::tempdir()?;
+        for _ in 0..2 {
+            let index = task
+                .generate(&generators, &mut picker, corpus.path(), output.path())
+                .await?;
+            assert_eq!(generators[index].name, "working");
+            assert!(output.path().join("input").exists());
+        }
+
+        // Once every generator has failed, the error is that of the last.
+        picker.disable(1);
+        picker.weights[0] = 1;
+        let err = task
+            .generate(&generators, &mut picker, corpus.path(), output.path())
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string().starts_with("generator failed to run: "),
+            "{err:?}"
+        );
+
+        Ok(())
+    }
+
     #[tokio::test]
     #[cfg(target_os = "linux")]
     #[ignore]
@@ -290,12 +739,15 @@ mod tests {
             target_exe: Default::default(),
             target_env: Default::default(),
             target_options: Default::default(),
+            input_via: InputVia::File,
             target_timeout: None,
             check_asan_log: false,
             check_debugger: false,
             rename_output: false,
             ensemble_sync_delay: None,
+            max_initial_corpus: None,
             generator_env: HashMap::default(),
+            generators: vec![],
             check_retry_count: 0,
             common: CommonConfig {
                 job_id: Default::default(),
@@ -316,15 +768,28 @@ mod tests {
                     scaleset_name: None,
                 },
                 tags: Default::default(),
+                allow_unknown_placeholders: Default::default(),
+                allow_env_placeholders: Default::default(),
+                target_options_shell: Default::default(),
+                qemu_user: None,
+                symbol_servers: vec![],
+                symbol_cache: None,
+                process_stats: Default::default(),
+                unknown_fields: Default::default(),
                 from_agent_to_task_endpoint: "/".to_string(),
                 from_task_to_agent_endpoint: "/".to_string(),
             },
         };
+        let generators = config.generators()?;
         let task = GeneratorTask::new(config);
 
         let generated_inputs = tempdir()?;
-        task.generate_inputs(inputs.to_path_buf(), generated_inputs.path())
-            .await?;
+        task.generate_inputs(
+            inputs.to_path_buf(),
+            generated_inputs.path(),
+            &generators[0],
+        )
+        .await?;
 
         let count = std::fs::read_dir(generated_inputs.path())?.count();
         assert_eq!(count, 100, "No inputs generated");
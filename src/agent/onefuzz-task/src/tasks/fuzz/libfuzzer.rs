@@ -4,3 +4,4 @@
 pub mod common;
 pub mod dotnet;
 pub mod generic;
+pub mod jobs;
@@ -3,7 +3,12 @@
 
 use crate::tasks::{
     config::CommonConfig,
+    fuzz::{
+        libfuzzer::jobs::{written_artifact, JobLogs},
+        snapshot::{continuous_snapshot, CorpusSnapshots},
+    },
     heartbeat::{HeartbeatSender, TaskHeartbeatClient},
+    stats::process::monitor_process_stats,
     utils::default_bool_true,
 };
 use anyhow::{Context, Result};
@@ -11,14 +16,18 @@ use arraydeque::{ArrayDeque, Wrapping};
 use async_trait::async_trait;
 use futures::future::try_join_all;
 use onefuzz::{
+    dictionary::find_dictionary,
     fs::list_files,
-    libfuzzer::{LibFuzzer, LibFuzzerLine},
+    initial_corpus::MaxInitialCorpus,
+    libfuzzer::{ArtifactKind, ForkMode, JobsMode, LibFuzzer, LibFuzzerLine},
     process::ExitStatus,
-    syncdir::{continuous_sync, SyncOperation::Pull, SyncedDir},
+    syncdir::{continuous_sync_pull, SampledDir, SyncedDir},
 };
 use onefuzz_result::job_result::{JobResultData, JobResultSender, TaskJobResultClient};
 use onefuzz_telemetry::{
-    Event::{new_coverage, new_crashdump, new_result, runtime_stats},
+    Event::{
+        fuzzer_restart, new_coverage, new_crashdump, new_leak, new_oom, new_result, runtime_stats,
+    },
     EventData,
 };
 use serde::Deserialize;
@@ -27,13 +36,13 @@ use std::{
     ffi::{OsStr, OsString},
     fmt::Debug,
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{Arc, OnceLock},
 };
 use tempfile::{tempdir_in, TempDir};
 use tokio::{
     io::{AsyncBufReadExt, BufReader},
     sync::{mpsc, Notify},
-    time::{sleep, Duration, Instant},
+    time::{interval, sleep, Duration, Instant},
 };
 use uuid::Uuid;
 
@@ -43,6 +52,17 @@ const RUNTIME_STATS_PERIOD: Duration = Duration::from_secs(60);
 // Period for minimum duration between launches of libFuzzer
 const COOLOFF_PERIOD: Duration = Duration::from_secs(10);
 
+// A libFuzzer which fails within this period of starting, without finding a
+// crash, is assumed to be unable to run at all.
+const STARTUP_FAILURE_PERIOD: Duration = Duration::from_secs(5);
+
+// Period of reading the job logs, in jobs mode.
+const JOB_LOGS_PERIOD: Duration = Duration::from_secs(1);
+
+// In jobs mode, the default number of jobs per worker, before libFuzzer exits
+// and is restarted. Jobs which crash are then replaced, until all are done.
+const DEFAULT_JOBS_PER_WORKER: usize = 10;
+
 /// Maximum number of log message to safe in case of libFuzzer failing,
 /// arbitrarily chosen
 const LOGS_BUFFER_SIZE: usize = 1024;
@@ -52,6 +72,11 @@ pub fn default_workers() -> usize {
     usize::max(1, cpus - 1)
 }
 
+/// The default of libFuzzer itself.
+pub fn default_rss_limit_mb() -> u64 {
+    2048
+}
+
 /// LibFuzzer subtypes that share custom configuration or process initialization.
 #[async_trait]
 pub trait LibFuzzerType: Send + Sync {
@@ -80,6 +105,13 @@ pub struct Config<L: LibFuzzerType + Send + Sync + ?Sized> {
     pub readonly_inputs: Option<Vec<SyncedDir>>,
     pub crashes: SyncedDir,
     pub crashdumps: Option<SyncedDir>,
+
+    /// Inputs which ran out of memory, instead of `crashes`.
+    pub oom: Option<SyncedDir>,
+
+    /// Inputs which leaked memory, instead of `crashes`.
+    pub leaks: Option<SyncedDir>,
+
     pub target_exe: PathBuf,
     pub target_env: HashMap<String, String>,
     pub target_options: Vec<String>,
@@ -94,11 +126,69 @@ pub struct Config<L: LibFuzzerType + Send + Sync + ?Sized> {
     #[serde(default)]
     pub expect_crash_on_failure: bool,
 
-    #[serde(flatten)]
-    pub common: CommonConfig,
+    /// Run a single libFuzzer with `-fork={target_workers}`, instead of one
+    /// libFuzzer per worker.
+    #[serde(default)]
+    pub fork_mode: bool,
+
+    /// In fork mode, keep fuzzing after a crash.
+    #[serde(default)]
+    pub fork_ignore_crashes: bool,
+
+    /// In fork mode, keep fuzzing after running out of memory. As with
+    /// libFuzzer, this is the default.
+    #[serde(default = "default_bool_true")]
+    pub fork_ignore_ooms: bool,
+
+    /// Run a single libFuzzer with `-jobs={jobs} -workers={target_workers}`,
+    /// instead of one libFuzzer per worker.
+    #[serde(default)]
+    pub jobs_mode: bool,
+
+    /// In jobs mode, the number of jobs to run before libFuzzer is restarted.
+    /// Defaults to `DEFAULT_JOBS_PER_WORKER` per worker.
+    pub jobs: Option<usize>,
+
+    /// Seconds to wait before restarting a libFuzzer which has exited. By
+    /// default, restarts are `COOLOFF_PERIOD` apart.
+    pub restart_delay: Option<u64>,
 
+    /// Passed as `-rss_limit_mb`, unless set by `target_options`.
+    #[serde(default = "default_rss_limit_mb")]
+    pub rss_limit_mb: u64,
+
+    /// Container of libFuzzer dictionaries. The most recently modified
+    /// `*.dict` file, or else `dictionary_name`, is passed as `-dict`.
+    pub dictionary: Option<SyncedDir>,
+
+    /// Name of the file in `dictionary` to fuzz with.
+    pub dictionary_name: Option<String>,
+
+    /// Limits on the initial corpus pulled from `inputs` and each of the
+    /// `readonly_inputs`, for seed containers too large to pull in full.
+    pub max_initial_corpus: Option<MaxInitialCorpus>,
+
+    /// Periodic snapshots of the corpus in `inputs`.
+    pub corpus_snapshots: Option<CorpusSnapshots>,
+
+    // Deserialized ahead of `common`, so that the fuzzer-specific keys are
+    // claimed before `CommonConfig` collects the remaining unknown keys.
     #[serde(flatten)]
     pub extra: L::Config,
+
+    #[serde(flatten)]
+    pub common: CommonConfig,
+}
+
+impl<L: LibFuzzerType + Send + Sync + ?Sized> Config<L> {
+    /// Check that at most one of libFuzzer's own parallel modes is set.
+    pub fn check_parallel_mode(&self) -> Result<()> {
+        if self.fork_mode && self.jobs_mode {
+            bail!("`fork_mode` and `jobs_mode` can't both be set");
+        }
+
+        Ok(())
+    }
 }
 
 pub struct LibFuzzerFuzzTask<L>
@@ -107,6 +197,15 @@ where
     Config<L>: Debug,
 {
     config: Config<L>,
+
+    /// The dictionary chosen from the `dictionary` container, by `run()`.
+    dictionary: OnceLock<Option<PathBuf>>,
+
+    /// The input directories, as pulled by `run()`.
+    synced_inputs: OnceLock<Vec<SampledDir>>,
+
+    /// The heartbeat client, as started by `run()`.
+    heartbeat: OnceLock<Option<TaskHeartbeatClient>>,
 }
 
 impl<L> LibFuzzerFuzzTask<L>
@@ -115,7 +214,12 @@ where
     Config<L>: Debug,
 {
     pub fn new(config: Config<L>) -> Result<Self> {
-        Ok(Self { config })
+        Ok(Self {
+            config,
+            dictionary: OnceLock::new(),
+            synced_inputs: OnceLock::new(),
+            heartbeat: OnceLock::new(),
+        })
     }
 
     fn workers(&self) -> usize {
@@ -125,29 +229,85 @@ where
         }
     }
 
+    fn fork_mode(&self) -> Option<ForkMode> {
+        self.config.fork_mode.then(|| ForkMode {
+            jobs: self.workers(),
+            ignore_crashes: self.config.fork_ignore_crashes,
+            ignore_ooms: self.config.fork_ignore_ooms,
+        })
+    }
+
+    fn jobs_mode(&self, log_dir: &Path) -> JobsMode {
+        let workers = self.workers();
+        JobsMode {
+            jobs: self
+                .config
+                .jobs
+                .unwrap_or(workers * DEFAULT_JOBS_PER_WORKER),
+            workers,
+            log_dir: log_dir.to_owned(),
+        }
+    }
+
+    async fn fuzzer(&self) -> Result<LibFuzzer> {
+        let fuzzer = L::from_config(&self.config)
+            .await?
+            .rss_limit_mb(self.config.rss_limit_mb);
+
+        let fuzzer = match self.fork_mode() {
+            Some(fork_mode) => fuzzer.fork_mode(fork_mode),
+            None => fuzzer,
+        };
+
+        Ok(match self.dictionary.get() {
+            Some(Some(dict)) => fuzzer.dict(dict.clone()),
+            _ => fuzzer,
+        })
+    }
+
     pub async fn run(&self) -> Result<()> {
         self.init_directories().await?;
+        self.init_dictionary().await?;
         L::extra_setup(&self.config).await?;
         self.verify().await?;
 
         let hb_client = self.config.common.init_heartbeat(None).await?;
+        let hb_client = self.heartbeat.get_or_init(|| hb_client);
         let jr_client = self.config.common.init_job_result().await?;
+        let task_id = self.config.common.task_id;
 
         // To be scheduled.
         let resync = self.continuous_sync_inputs();
 
-        let new_inputs = self
-            .config
-            .inputs
-            .monitor_results(new_coverage, true, &jr_client);
+        let new_inputs =
+            self.config
+                .inputs
+                .monitor_results(new_coverage, true, task_id, &jr_client);
         let new_crashes = self
             .config
             .crashes
-            .monitor_results(new_result, true, &jr_client);
+            .monitor_results(new_result, true, task_id, &jr_client);
         let new_crashdumps = async {
             if let Some(crashdumps) = &self.config.crashdumps {
                 crashdumps
-                    .monitor_results(new_crashdump, true, &jr_client)
+                    .monitor_results(new_crashdump, true, task_id, &jr_client)
+                    .await
+            } else {
+                Ok(())
+            }
+        };
+        let new_ooms = async {
+            if let Some(oom) = &self.config.oom {
+                oom.monitor_results(new_oom, true, task_id, &jr_client)
+                    .await
+            } else {
+                Ok(())
+            }
+        };
+        let new_leaks = async {
+            if let Some(leaks) = &self.config.leaks {
+                leaks
+                    .monitor_results(new_leak, true, task_id, &jr_client)
                     .await
             } else {
                 Ok(())
@@ -155,13 +315,15 @@ where
         };
 
         let (stats_sender, stats_receiver) = mpsc::unbounded_channel();
-        let report_stats = report_runtime_stats(stats_receiver, &hb_client, &jr_client);
+        let report_stats = report_runtime_stats(stats_receiver, hb_client, &jr_client);
         let fuzzers = self.run_fuzzers(Some(&stats_sender));
         futures::try_join!(
             resync,
             new_inputs,
             new_crashes,
             new_crashdumps,
+            new_ooms,
+            new_leaks,
             fuzzers,
             report_stats
         )?;
@@ -175,14 +337,21 @@ where
             directories.extend(readonly_inputs.iter().map(|x| -> &Path { &x.local_path }));
         }
 
-        let fuzzer = L::from_config(&self.config).await?;
+        let fuzzer = self.fuzzer().await?;
         fuzzer
             .verify(self.config.check_fuzzer_help, Some(&directories))
             .await
     }
 
     pub async fn run_fuzzers(&self, stats_sender: Option<&StatsSender>) -> Result<()> {
-        let fuzzers: Vec<_> = (0..self.workers())
+        // In fork and jobs mode, the workers are the jobs of a single libFuzzer.
+        let workers = if self.config.fork_mode || self.config.jobs_mode {
+            1
+        } else {
+            self.workers()
+        };
+
+        let fuzzers: Vec<_> = (0..workers)
             .map(|id| self.start_fuzzer_monitor(id, stats_sender))
             .collect();
 
@@ -207,14 +376,17 @@ where
 
     // The fuzzer monitor coordinates a _series_ of fuzzer runs.
     //
-    // A run is one session of continuous fuzzing, terminated by a fuzzing error
-    // or discovered fault. The monitor restarts the libFuzzer when it exits.
+    // A run is one session of continuous fuzzing, terminated by a fuzzing error,
+    // a discovered fault, or the end of a bounded run (`-runs`, `-max_total_time`).
+    // The monitor restarts the libFuzzer when it exits, with the corpus as
+    // updated by the previous run.
     pub async fn start_fuzzer_monitor(
         &self,
         worker_id: usize,
         stats_sender: Option<&StatsSender>,
     ) -> Result<()> {
         let local_input_dir = self.create_local_temp_dir().await?;
+        let mut restarts = 0u64;
         loop {
             let instant = Instant::now();
             self.run_fuzzer(&local_input_dir.path(), worker_id, stats_sender)
@@ -239,13 +411,23 @@ where
                     })?;
             }
 
-            // if libFuzzer is exiting rapidly, give some breathing room to allow the
-            // handles to be reaped.
-            let runtime = instant.elapsed();
-            if runtime < COOLOFF_PERIOD {
-                sleep(COOLOFF_PERIOD - runtime).await;
-            }
+            sleep(self.restart_delay(instant.elapsed())).await;
+
+            restarts += 1;
+            info!("restarting fuzzer, worker_id = {worker_id}, restarts = {restarts}");
+            event!(fuzzer_restart; EventData::WorkerId = worker_id, EventData::Count = restarts);
+        }
+    }
+
+    // Time to wait before restarting a libFuzzer which ran for `runtime`.
+    fn restart_delay(&self, runtime: Duration) -> Duration {
+        if let Some(delay) = self.config.restart_delay {
+            return Duration::from_secs(delay);
         }
+
+        // if libFuzzer is exiting rapidly, give some breathing room to allow the
+        // handles to be reaped.
+        COOLOFF_PERIOD.saturating_sub(runtime)
     }
 
     // Fuzz with a libFuzzer until it exits.
@@ -259,6 +441,7 @@ where
     ) -> Result<()> {
         let crash_dir = self.create_local_temp_dir().await?;
         let run_id = Uuid::new_v4();
+        let started = Instant::now();
 
         debug!("starting fuzzer run, run_id = {}", run_id);
 
@@ -271,12 +454,21 @@ where
 
         info!("config is: {:?}", self.config);
 
-        let fuzzer = L::from_config(&self.config).await?;
+        // In jobs mode, each job logs to a file of its own.
+        let log_dir = if self.config.jobs_mode {
+            Some(self.create_local_temp_dir().await?)
+        } else {
+            None
+        };
+
+        let mut fuzzer = self.fuzzer().await?;
+        if let Some(log_dir) = &log_dir {
+            fuzzer = fuzzer.jobs_mode(self.jobs_mode(log_dir.path()));
+        }
         let mut running = fuzzer.fuzz(crash_dir.path(), local_inputs, &inputs)?;
 
         info!("child is: {:?}", running);
 
-        #[cfg(target_os = "linux")]
         let pid = running.id();
 
         let notify = Arc::new(Notify::new());
@@ -288,8 +480,14 @@ where
             .ok_or_else(|| format_err!("stderr not captured"))?;
         let mut stderr = BufReader::new(stderr);
 
+        // Crashes already moved to the crashes directory during this run.
+        let mut collected = vec![];
+
         let mut libfuzzer_output: ArrayDeque<_, LOGS_BUFFER_SIZE, Wrapping> = ArrayDeque::new();
-        {
+
+        let stderr_closed = Notify::new();
+        let stop_sampling = Notify::new();
+        let read_stderr = async {
             let mut buf = vec![];
             loop {
                 buf.clear();
@@ -298,15 +496,62 @@ where
                     break;
                 }
                 let line = String::from_utf8_lossy(&buf).to_string();
-                if let Some(stats_sender) = stats_sender {
+                let parsed = LibFuzzerLine::parse(&line);
+
+                // In jobs mode, the stats are those of the job logs.
+                if let (Some(stats_sender), None) = (stats_sender, &log_dir) {
                     if let Err(err) = try_report_iter_update(stats_sender, worker_id, run_id, &line)
                     {
                         error!("could not parse fuzzing interation update: {}", err);
                     }
                 }
+
+                // In fork mode, libFuzzer keeps running after a job finds a
+                // crash, so collect them as each job finishes.
+                if matches!(parsed, Ok(Some(line)) if line.is_fork_job()) {
+                    let files = list_files(crash_dir.path()).await?;
+                    self.move_crashes(&files, &libfuzzer_output).await?;
+                    collected.extend(files);
+                }
+
                 libfuzzer_output.push_back(line);
             }
-        }
+
+            stderr_closed.notify_one();
+            stop_sampling.notify_one();
+            anyhow::Ok(())
+        };
+        let monitor_job_logs = async {
+            match &log_dir {
+                Some(log_dir) => {
+                    self.monitor_job_logs(
+                        log_dir.path(),
+                        crash_dir.path(),
+                        stats_sender,
+                        &stderr_closed,
+                    )
+                    .await
+                }
+                None => Ok(vec![]),
+            }
+        };
+        let sample_process_stats = async {
+            match pid {
+                Some(pid) => {
+                    monitor_process_stats(
+                        pid,
+                        &self.config.common.process_stats,
+                        self.heartbeat.get().and_then(Option::as_ref),
+                        Some(&stop_sampling),
+                    )
+                    .await
+                }
+                None => Ok(()),
+            }
+        };
+        let ((), job_artifacts, ()) =
+            futures::try_join!(read_stderr, monitor_job_logs, sample_process_stats)?;
+        collected.extend(job_artifacts);
 
         let exit_status = running.wait().await;
         notify.notify_one();
@@ -325,13 +570,23 @@ where
 
         let files = list_files(crash_dir.path()).await?;
 
-        info!("found {} crashes", files.len());
+        info!("found {} crashes", collected.len() + files.len());
 
         // If the target exits, crashes are required unless
         // 1. Exited cleanly (happens with -runs=N)
-        // 2. expect_crash_on_failure is disabled
-        if files.is_empty() && !exit_status.success {
-            if self.config.expect_crash_on_failure {
+        // 2. expect_crash_on_failure is disabled, and the target had started
+        if collected.is_empty() && files.is_empty() && !exit_status.success {
+            if started.elapsed() < STARTUP_FAILURE_PERIOD {
+                bail!(
+                    "libfuzzer failed on startup, after {:?}.  status:{} stderr:{:?}",
+                    started.elapsed(),
+                    serde_json::to_string(&exit_status)?,
+                    libfuzzer_output
+                        .into_iter()
+                        .collect::<Vec<String>>()
+                        .join("\n")
+                );
+            } else if self.config.expect_crash_on_failure {
                 bail!(
                     "libfuzzer exited without generating crashes.  status:{} stderr:{:?}",
                     serde_json::to_string(&exit_status)?,
@@ -359,16 +614,12 @@ where
             None
         };
 
-        // move crashing inputs to output directory
-        for file in files {
-            if let Some(filename) = file.file_name() {
-                let dest = self.config.crashes.local_path.join(filename);
-                if let Err(e) = tokio::fs::rename(file, dest.clone()).await {
-                    if !dest.exists() {
-                        bail!(e)
-                    }
-                }
-            }
+        self.move_crashes(&files, &libfuzzer_output).await?;
+
+        // In fork and jobs mode, a crash dump would be of a child process,
+        // which can't be matched to its crash.
+        if self.config.fork_mode || self.config.jobs_mode {
+            return Ok(());
         }
 
         if let Some(crashdumps) = &self.config.crashdumps {
@@ -437,31 +688,175 @@ where
         Ok(())
     }
 
+    // In jobs mode, follow the log of each job until `stopped`, reporting its
+    // stats, and moving each artifact to the directory for its kind once the
+    // job logs that it was written to `crash_dir`. Returns the artifacts moved.
+    async fn monitor_job_logs(
+        &self,
+        log_dir: &Path,
+        crash_dir: &Path,
+        stats_sender: Option<&StatsSender>,
+        stopped: &Notify,
+    ) -> Result<Vec<PathBuf>> {
+        let workers = self.workers();
+        let mut logs = JobLogs::new(log_dir);
+
+        // The run ID and recent output of each job.
+        let mut jobs: HashMap<usize, (Uuid, ArrayDeque<String, LOGS_BUFFER_SIZE, Wrapping>)> =
+            HashMap::new();
+        let mut collected = vec![];
+
+        loop {
+            let finished = tokio::select! {
+                _ = stopped.notified() => true,
+                _ = sleep(JOB_LOGS_PERIOD) => false,
+            };
+
+            for (job, line) in logs.read(finished).await? {
+                let (run_id, output) = jobs
+                    .entry(job)
+                    .or_insert_with(|| (Uuid::new_v4(), ArrayDeque::new()));
+
+                // Each worker runs one job after another, so a job is reported
+                // as a new run of a worker.
+                if let Some(stats_sender) = stats_sender {
+                    if let Err(err) =
+                        try_report_iter_update(stats_sender, job % workers, *run_id, &line)
+                    {
+                        error!("could not parse fuzzing interation update: {}", err);
+                    }
+                }
+
+                let artifact = written_artifact(&line).map(|name| crash_dir.join(name));
+                output.push_back(line);
+
+                if let Some(artifact) = artifact.filter(|path| path.exists()) {
+                    self.move_crashes(&[artifact.clone()], output).await?;
+                    collected.push(artifact);
+                }
+            }
+
+            if finished {
+                return Ok(collected);
+            }
+        }
+    }
+
+    // move crashing inputs to output directory
+    // Move new artifacts to the directory for their kind, as told by their
+    // names or by the fuzzer's output.
+    async fn move_crashes(
+        &self,
+        files: &[PathBuf],
+        libfuzzer_output: &ArrayDeque<String, LOGS_BUFFER_SIZE, Wrapping>,
+    ) -> Result<()> {
+        if files.is_empty() {
+            return Ok(());
+        }
+
+        let stderr: String = libfuzzer_output.iter().map(String::as_str).collect();
+
+        for file in files {
+            if let Some(filename) = file.file_name() {
+                let kind = ArtifactKind::classify(&filename.to_string_lossy(), &stderr);
+                let dest = self.artifacts_dir(kind).join(filename);
+                if let Err(e) = tokio::fs::rename(file, dest.clone()).await {
+                    if !dest.exists() {
+                        bail!(e)
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn artifacts_dir(&self, kind: ArtifactKind) -> &Path {
+        let dir = match kind {
+            ArtifactKind::Crash => None,
+            ArtifactKind::Oom => self.config.oom.as_ref(),
+            ArtifactKind::Leak => self.config.leaks.as_ref(),
+        };
+
+        &dir.unwrap_or(&self.config.crashes).local_path
+    }
+
     async fn init_directories(&self) -> Result<()> {
         // input directories (init_pull):
-        self.config.inputs.init_pull().await?;
+        let max_initial_corpus = self.config.max_initial_corpus.as_ref();
+        let mut synced_inputs = vec![
+            self.config
+                .inputs
+                .init_pull_sampled(max_initial_corpus)
+                .await?,
+        ];
         if let Some(readonly_inputs) = &self.config.readonly_inputs {
             for dir in readonly_inputs {
-                dir.init_pull().await?;
+                synced_inputs.push(dir.init_pull_sampled(max_initial_corpus).await?);
             }
         }
+        if self.synced_inputs.set(synced_inputs).is_err() {
+            bail!("input directories already pulled");
+        }
 
         // output directories (init):
         self.config.crashes.init().await?;
-        if let Some(crashdumps) = &self.config.crashdumps {
-            crashdumps.init().await?;
+        for dir in [
+            &self.config.crashdumps,
+            &self.config.oom,
+            &self.config.leaks,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            dir.init().await?;
         }
 
         Ok(())
     }
 
-    async fn continuous_sync_inputs(&self) -> Result<()> {
-        let mut dirs = vec![self.config.inputs.clone()];
-        if let Some(inputs) = &self.config.readonly_inputs {
-            let inputs = inputs.clone();
-            dirs.extend(inputs);
+    // Pull the `dictionary` container, and choose the dictionary to fuzz with.
+    // An empty container is only a warning, so that the dictionaries can be
+    // uploaded later.
+    async fn init_dictionary(&self) -> Result<()> {
+        let dictionary = match &self.config.dictionary {
+            Some(dir) => {
+                dir.init_pull().await?;
+                find_dictionary(&dir.local_path, self.config.dictionary_name.as_deref()).await?
+            }
+            None => None,
+        };
+
+        if let Some(dict) = &dictionary {
+            info!("fuzzing with dictionary: {}", dict.display());
         }
-        continuous_sync(&dirs, Pull, self.config.ensemble_sync_delay).await
+
+        if self.dictionary.set(dictionary).is_err() {
+            bail!("dictionary already chosen");
+        }
+
+        Ok(())
+    }
+
+    async fn continuous_sync_inputs(&self) -> Result<()> {
+        let Some(dirs) = self.synced_inputs.get() else {
+            bail!("input directories not yet pulled");
+        };
+        let snapshots = async {
+            match &self.config.corpus_snapshots {
+                Some(snapshots) => {
+                    continuous_snapshot(&self.config.inputs.local_path, snapshots).await
+                }
+                None => Ok(()),
+            }
+        };
+
+        futures::try_join!(
+            continuous_sync_pull(dirs, self.config.ensemble_sync_delay),
+            snapshots
+        )?;
+
+        Ok(())
     }
 }
 
@@ -477,18 +872,26 @@ fn try_report_iter_update(
             run_id,
             count: line.iters(),
             execs_sec: line.execs_sec(),
+            cov: line.cov(),
+            features: line.features(),
+            corpus_count: line.corpus_count(),
+            rss_mb: line.rss_mb(),
         })?;
     }
 
     Ok(())
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 pub struct RuntimeStats {
     worker_id: usize,
     run_id: Uuid,
     count: u64,
     execs_sec: f64,
+    cov: Option<u64>,
+    features: Option<u64>,
+    corpus_count: Option<u64>,
+    rss_mb: Option<u64>,
 }
 
 #[derive(Debug, Default)]
@@ -499,11 +902,17 @@ pub struct TotalStats {
 }
 
 impl TotalStats {
-    fn update(&mut self, worker_data: RuntimeStats) {
+    fn update(&mut self, mut worker_data: RuntimeStats) {
         if let Some(current) = self.worker_stats.get(&worker_data.worker_id) {
             // if it's the same run, only add the differences
             if current.run_id == worker_data.run_id {
                 self.count += worker_data.count.saturating_sub(current.count);
+
+                // not every line has every field, so keep the last value seen
+                worker_data.cov = worker_data.cov.or(current.cov);
+                worker_data.features = worker_data.features.or(current.features);
+                worker_data.corpus_count = worker_data.corpus_count.or(current.corpus_count);
+                worker_data.rss_mb = worker_data.rss_mb.or(current.rss_mb);
             } else {
                 self.count += worker_data.count;
             }
@@ -516,27 +925,67 @@ impl TotalStats {
         self.execs_sec = self.worker_stats.values().map(|x| x.execs_sec).sum();
     }
 
+    // Workers fuzz the same target with a shared corpus, so the best of their
+    // coverage is that of the task. Their memory use adds up.
+    fn cov(&self) -> Option<u64> {
+        self.worker_stats.values().filter_map(|x| x.cov).max()
+    }
+
+    fn features(&self) -> Option<u64> {
+        self.worker_stats.values().filter_map(|x| x.features).max()
+    }
+
+    fn corpus_count(&self) -> Option<u64> {
+        self.worker_stats
+            .values()
+            .filter_map(|x| x.corpus_count)
+            .max()
+    }
+
+    fn rss_mb(&self) -> Option<u64> {
+        let mut rss = self
+            .worker_stats
+            .values()
+            .filter_map(|x| x.rss_mb)
+            .peekable();
+        rss.peek().is_some().then(|| rss.sum())
+    }
+
+    fn events(&self) -> Vec<EventData> {
+        let mut events = vec![
+            EventData::Count(self.count),
+            EventData::ExecsSecond(self.execs_sec),
+        ];
+        events.extend(self.cov().map(EventData::Covered));
+        events.extend(self.features().map(EventData::Features));
+        events.extend(self.corpus_count().map(EventData::CorpusCount));
+        events.extend(self.rss_mb().map(EventData::RssMb));
+        events
+    }
+
     async fn report(&self, jr_client: &Option<TaskJobResultClient>) {
-        event!(
-            runtime_stats;
-            EventData::Count = self.count,
-            EventData::ExecsSecond = self.execs_sec
-        );
-        metric!(
-            runtime_stats;
-            1.0;
-            EventData::Count = self.count,
-            EventData::ExecsSecond = self.execs_sec
-        );
+        let events = self.events();
+        log_events!(runtime_stats; events);
+        log_metrics!(runtime_stats; 1.0; events);
+
         if let Some(jr_client) = jr_client {
+            let mut values = HashMap::from([
+                ("total_count".to_string(), self.count as f64),
+                ("execs_sec".to_string(), self.execs_sec),
+            ]);
+            for (name, value) in [
+                ("coverage", self.cov()),
+                ("features", self.features()),
+                ("corpus_count", self.corpus_count()),
+                ("rss_mb", self.rss_mb()),
+            ] {
+                if let Some(value) = value {
+                    values.insert(name.to_string(), value as f64);
+                }
+            }
+
             let _ = jr_client
-                .send_direct(
-                    JobResultData::RuntimeStats,
-                    HashMap::from([
-                        ("total_count".to_string(), self.count as f64),
-                        ("execs_sec".to_string(), self.execs_sec),
-                    ]),
-                )
+                .send_direct(JobResultData::RuntimeStats, values)
                 .await;
         }
     }
@@ -544,28 +993,14 @@ impl TotalStats {
 
 type StatsSender = mpsc::UnboundedSender<RuntimeStats>;
 
-#[derive(Clone, Copy, Debug)]
-struct Timer {
-    interval: Duration,
-}
-
-impl Timer {
-    pub fn new(interval: Duration) -> Self {
-        Self { interval }
-    }
-
-    async fn wait(&self) {
-        sleep(self.interval).await;
-    }
-}
-
-// Report runtime stats, as delivered via the `stats` channel, with a periodic trigger to
-// guarantee a minimum reporting frequency.
+// Report runtime stats, as delivered via the `stats` channel, once per period.
 //
-// The minimum frequency is to aid metric visualization. The libFuzzer binary runtime's `pulse`
-// event is triggered by a doubling of the last (locally) logged total iteration count. For long-
-// running worker runs, this can result in misleading gaps and binning artifacts. In effect, we
-// are approximating nearest-neighbor interpolation on the runtime stats time series.
+// Stats are aggregated over the period, rather than reported for every line of
+// libFuzzer output. Reporting on a fixed period also aids metric visualization:
+// the libFuzzer runtime's `pulse` event is triggered by a doubling of the
+// (locally) logged iteration count, so long-running workers would otherwise
+// leave misleading gaps and binning artifacts. In effect, we are approximating
+// nearest-neighbor interpolation on the runtime stats time series.
 async fn report_runtime_stats(
     mut stats_channel: mpsc::UnboundedReceiver<RuntimeStats>,
     heartbeat_client: &Option<TaskHeartbeatClient>,
@@ -574,22 +1009,20 @@ async fn report_runtime_stats(
     // Cache the last-reported stats for a given worker.
     //
     // When logging stats, the most recently reported runtime stats will be used for any
-    // missing data. For time-triggered logging, it will be used for all workers.
+    // missing data.
     let mut total = TotalStats::default();
 
-    // report all zeros to start
-    total.report(jr_client).await;
-
-    let timer = Timer::new(RUNTIME_STATS_PERIOD);
+    // The first tick is immediate, to report all zeros to start.
+    let mut period = interval(RUNTIME_STATS_PERIOD);
 
     loop {
         tokio::select! {
             Some(stats) = stats_channel.recv() => {
                 heartbeat_client.alive();
                 total.update(stats);
-                total.report(jr_client).await
             }
-            _ = timer.wait() => {
+            _ = period.tick() => {
+                heartbeat_client.stats(&total.events());
                 total.report(jr_client).await
             }
         }
@@ -598,10 +1031,16 @@ async fn report_runtime_stats(
 
 #[cfg(test)]
 mod tests {
-    use super::{RuntimeStats, TotalStats};
+    use super::{RuntimeStats, TotalStats, COOLOFF_PERIOD};
     use anyhow::Result;
+    use onefuzz_telemetry::EventData;
+    use std::path::PathBuf;
+    use tokio::time::{Duration, Instant};
     use uuid::Uuid;
 
+    #[cfg(target_family = "unix")]
+    use crate::tasks::fuzz::libfuzzer::generic;
+
     #[test]
     fn test_total_stats() -> Result<()> {
         let mut total = TotalStats::default();
@@ -610,6 +1049,7 @@ mod tests {
             run_id: Uuid::new_v4(),
             count: 0,
             execs_sec: 0.0,
+            ..Default::default()
         };
 
         total.update(a.clone());
@@ -647,6 +1087,7 @@ mod tests {
             run_id: Uuid::new_v4(),
             count: 10,
             execs_sec: 2.0,
+            ..Default::default()
         };
         total.update(b.clone());
         assert!(total.count == 30);
@@ -662,4 +1103,474 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_total_stats_fields() {
+        let mut total = TotalStats::default();
+        assert_eq!(
+            total.events(),
+            [EventData::Count(0), EventData::ExecsSecond(0.0)]
+        );
+
+        let mut a = RuntimeStats {
+            worker_id: 0,
+            run_id: Uuid::new_v4(),
+            count: 10,
+            execs_sec: 1.0,
+            cov: Some(5),
+            features: Some(7),
+            corpus_count: Some(2),
+            rss_mb: Some(30),
+        };
+        total.update(a.clone());
+
+        // fork mode lines have no `rss:`, so the last value is kept
+        a.count = 20;
+        a.cov = Some(6);
+        a.rss_mb = None;
+        total.update(a.clone());
+
+        let b = RuntimeStats {
+            worker_id: 1,
+            run_id: Uuid::new_v4(),
+            count: 5,
+            execs_sec: 2.0,
+            cov: Some(4),
+            features: Some(9),
+            corpus_count: None,
+            rss_mb: Some(40),
+        };
+        total.update(b);
+
+        // coverage is the best of any worker, and memory the sum
+        assert_eq!(
+            total.events(),
+            [
+                EventData::Count(25),
+                EventData::ExecsSecond(3.0),
+                EventData::Covered(6),
+                EventData::Features(9),
+                EventData::CorpusCount(2),
+                EventData::RssMb(70),
+            ]
+        );
+
+        // a new run doesn't inherit the values of the previous one
+        a.run_id = Uuid::new_v4();
+        a.cov = None;
+        a.features = None;
+        a.corpus_count = None;
+        total.update(a);
+        assert_eq!(total.cov(), Some(4));
+        assert_eq!(total.corpus_count(), None);
+        assert_eq!(total.rss_mb(), Some(40));
+    }
+
+    // Create a task which fuzzes with the fake libFuzzer `script`, with its
+    // containers in a new directory. The script can write to the file named by
+    // `$FAKE_LIBFUZZER_LOG`, which is `fuzzer.log` within that directory.
+    #[cfg(target_family = "unix")]
+    async fn fake_libfuzzer_task(
+        script: &str,
+        task_config: serde_json::Value,
+    ) -> Result<(tempfile::TempDir, generic::LibFuzzerFuzzTask)> {
+        use crate::tasks::config::fixture;
+        use onefuzz::fs::set_executable;
+        use serde_json::json;
+        use tokio::fs;
+
+        let dir = tempfile::tempdir()?;
+        let setup_dir = dir.path().join("setup");
+        for path in [
+            &setup_dir,
+            &dir.path().join("inputs"),
+            &dir.path().join("crashes"),
+        ] {
+            fs::create_dir_all(path).await?;
+        }
+
+        let target_exe = setup_dir.join("fuzz.sh");
+        fs::write(&target_exe, script).await?;
+        set_executable(&target_exe).await?;
+
+        let mut config = fixture::config(json!({
+            "inputs": { "path": dir.path().join("inputs") },
+            "crashes": { "path": dir.path().join("crashes") },
+            "target_exe": "fuzz.sh",
+            "target_env": { "FAKE_LIBFUZZER_LOG": dir.path().join("fuzzer.log") },
+            "target_options": [],
+            "setup_dir": setup_dir,
+        }));
+        fixture::merge(&mut config, task_config);
+
+        let task = generic::LibFuzzerFuzzTask::new(serde_json::from_value(config)?)?;
+        Ok((dir, task))
+    }
+
+    // Progress output of an LLVM 14 libFuzzer, interleaved with other output.
+    #[cfg(target_family = "unix")]
+    const FAKE_LIBFUZZER: &str = r#"#!/bin/sh
+cat >&2 <<'END'
+INFO: Running with entropic power schedule (0xFF, 100).
+INFO: Seed: 3718522933
+INFO: A corpus is not provided, starting from an empty corpus
+#2	INITED cov: 3 ft: 3 corp: 1/1b exec/s: 0 rss: 26Mb
+#3	NEW    cov: 4 ft: 4 corp: 2/2b lim: 4 exec/s: 0 rss: 26Mb L: 1/1 MS: 1 ChangeBit-
+==4242==WARNING: unrelated sanitizer output
+#1024	pulse  cov: 6 ft: 8 corp: 3/6b lim: 8 exec/s: 512 rss: 27Mb
+#2048	DONE   cov: 6 ft: 9 corp: 3/6b lim: 8 exec/s: 1024 rss: 28Mb
+Done 2048 runs in 2 second(s)
+END
+"#;
+
+    #[cfg(target_family = "unix")]
+    #[tokio::test]
+    async fn test_runtime_stats_from_stderr() -> Result<()> {
+        let (_dir, task) = fake_libfuzzer_task(FAKE_LIBFUZZER, serde_json::json!({})).await?;
+
+        let (stats_sender, mut stats_receiver) = tokio::sync::mpsc::unbounded_channel();
+        let local_inputs = tempfile::tempdir()?;
+        task.run_fuzzer(local_inputs.path(), 0, Some(&stats_sender))
+            .await?;
+        drop(stats_sender);
+
+        let mut total = TotalStats::default();
+        let mut lines = 0;
+        while let Some(stats) = stats_receiver.recv().await {
+            total.update(stats);
+            lines += 1;
+        }
+
+        assert_eq!(lines, 4);
+        assert_eq!(
+            total.events(),
+            [
+                EventData::Count(2048),
+                EventData::ExecsSecond(1024.0),
+                EventData::Covered(6),
+                EventData::Features(9),
+                EventData::CorpusCount(3),
+                EventData::RssMb(28),
+            ]
+        );
+
+        Ok(())
+    }
+
+    // Simulates a libFuzzer run with `-runs`, which exits cleanly twice, and
+    // then fails to start.
+    #[cfg(target_family = "unix")]
+    const FAKE_BOUNDED_LIBFUZZER: &str = r#"#!/bin/sh
+echo run >> "$FAKE_LIBFUZZER_LOG"
+if [ "$(wc -l < "$FAKE_LIBFUZZER_LOG")" -ge 3 ]; then
+  echo 'ERROR: unable to start' >&2
+  exit 1
+fi
+echo 'Done 100 runs in 0 second(s)' >&2
+"#;
+
+    // The clock is paused, and advanced by the restart delay.
+    #[cfg(target_family = "unix")]
+    #[tokio::test(start_paused = true)]
+    async fn test_clean_exits_restart_until_startup_failure() -> Result<()> {
+        let (dir, task) = fake_libfuzzer_task(
+            FAKE_BOUNDED_LIBFUZZER,
+            serde_json::json!({ "restart_delay": 60 }),
+        )
+        .await?;
+
+        let start = Instant::now();
+        let err = task.start_fuzzer_monitor(0, None).await.unwrap_err();
+        assert!(
+            err.to_string().starts_with("libfuzzer failed on startup"),
+            "{err}"
+        );
+
+        let log = tokio::fs::read_to_string(dir.path().join("fuzzer.log")).await?;
+        assert_eq!(log, "run\nrun\nrun\n");
+        assert!(start.elapsed() >= Duration::from_secs(120));
+
+        Ok(())
+    }
+
+    #[cfg(target_family = "unix")]
+    #[tokio::test]
+    async fn test_restart_delay() -> Result<()> {
+        let (_dir, task) = fake_libfuzzer_task(FAKE_LIBFUZZER, serde_json::json!({})).await?;
+        assert_eq!(
+            task.restart_delay(Duration::from_secs(4)),
+            COOLOFF_PERIOD - Duration::from_secs(4)
+        );
+        assert_eq!(task.restart_delay(COOLOFF_PERIOD * 2), Duration::ZERO);
+
+        let (_dir, task) =
+            fake_libfuzzer_task(FAKE_LIBFUZZER, serde_json::json!({ "restart_delay": 1 })).await?;
+        assert_eq!(task.restart_delay(Duration::ZERO), Duration::from_secs(1));
+        assert_eq!(
+            task.restart_delay(COOLOFF_PERIOD * 2),
+            Duration::from_secs(1)
+        );
+
+        Ok(())
+    }
+
+    // Simulates a libFuzzer which finds one artifact of every kind, and logs
+    // its memory limit.
+    #[cfg(target_family = "unix")]
+    const FAKE_OOM_LIBFUZZER: &str = r#"#!/bin/sh
+for arg in "$@"; do
+  case "$arg" in
+    -artifact_prefix=*) prefix="${arg#-artifact_prefix=}" ;;
+    -rss_limit_mb=*) echo "$arg" >> "$FAKE_LIBFUZZER_LOG" ;;
+  esac
+done
+echo crash > "${prefix}crash-1"
+echo oom > "${prefix}oom-1"
+echo leak > "${prefix}leak-1"
+echo leak > "${prefix}exact"
+echo '==4242==ERROR: LeakSanitizer: detected memory leaks' >&2
+exit 1
+"#;
+
+    #[cfg(target_family = "unix")]
+    #[tokio::test]
+    async fn test_artifacts_moved_by_kind() -> Result<()> {
+        use onefuzz::fs::list_files;
+
+        let dir = tempfile::tempdir()?;
+        let (oom, leaks) = (dir.path().join("oom"), dir.path().join("leaks"));
+        for path in [&oom, &leaks] {
+            tokio::fs::create_dir_all(path).await?;
+        }
+
+        let (task_dir, task) = fake_libfuzzer_task(
+            FAKE_OOM_LIBFUZZER,
+            serde_json::json!({
+                "oom": { "path": oom },
+                "leaks": { "path": leaks },
+                "rss_limit_mb": 1024,
+            }),
+        )
+        .await?;
+
+        let local_inputs = tempfile::tempdir()?;
+        task.run_fuzzer(local_inputs.path(), 0, None).await?;
+
+        let names = |dir: std::path::PathBuf| async move {
+            let mut names: Vec<_> = list_files(dir)
+                .await?
+                .iter()
+                .filter_map(|path| path.file_name())
+                .map(|name| name.to_string_lossy().into_owned())
+                .collect();
+            names.sort();
+            anyhow::Ok(names)
+        };
+
+        assert_eq!(names(task_dir.path().join("crashes")).await?, ["crash-1"]);
+        assert_eq!(names(oom).await?, ["oom-1"]);
+        assert_eq!(names(leaks).await?, ["exact", "leak-1"]);
+
+        assert_eq!(
+            tokio::fs::read_to_string(task_dir.path().join("fuzzer.log")).await?,
+            "-rss_limit_mb=1024\n"
+        );
+
+        Ok(())
+    }
+
+    // Simulates a libFuzzer in fork mode, which finds a crash in its first
+    // job, and another as it exits. Logs its fork mode arguments, and whether
+    // the first crash was collected while it was still running.
+    #[cfg(target_family = "unix")]
+    const FAKE_FORK_LIBFUZZER: &str = r#"#!/bin/sh
+for arg in "$@"; do
+  case "$arg" in
+    -artifact_prefix=*) prefix="${arg#-artifact_prefix=}" ;;
+    -fork=*|-ignore_*) echo "$arg" >> "$FAKE_LIBFUZZER_LOG" ;;
+  esac
+done
+echo 'INFO: -fork=2: fuzzing in separate process(s)' >&2
+echo crash > "${prefix}crash-1"
+echo '#100: cov: 10 ft: 12 corp: 3 exec/s 50 oom/timeout/crash: 0/0/1 time: 1s job: 1 dft_time: 0' >&2
+i=0
+while [ -e "${prefix}crash-1" ] && [ $i -lt 100 ]; do sleep 0.1; i=$((i+1)); done
+if [ -e "${prefix}crash-1" ]; then
+  echo 'not collected' >> "$FAKE_LIBFUZZER_LOG"
+else
+  echo collected >> "$FAKE_LIBFUZZER_LOG"
+fi
+echo crash > "${prefix}crash-2"
+echo 'INFO: exiting: 1 time: 2s' >&2
+exit 1
+"#;
+
+    #[cfg(target_family = "unix")]
+    #[tokio::test]
+    async fn test_fork_mode_collects_crashes_during_run() -> Result<()> {
+        use onefuzz::fs::list_files;
+
+        let (dir, task) = fake_libfuzzer_task(
+            FAKE_FORK_LIBFUZZER,
+            serde_json::json!({
+                "target_workers": 2,
+                "fork_mode": true,
+                "fork_ignore_crashes": true,
+            }),
+        )
+        .await?;
+
+        let local_inputs = tempfile::tempdir()?;
+        task.run_fuzzer(local_inputs.path(), 0, None).await?;
+
+        let mut found: Vec<_> = list_files(dir.path().join("crashes"))
+            .await?
+            .iter()
+            .filter_map(|path| path.file_name())
+            .map(|name| name.to_string_lossy().into_owned())
+            .collect();
+        found.sort();
+        assert_eq!(found, ["crash-1", "crash-2"]);
+
+        assert_eq!(
+            tokio::fs::read_to_string(dir.path().join("fuzzer.log")).await?,
+            "-fork=2\n-ignore_crashes=1\n-ignore_ooms=1\ncollected\n"
+        );
+
+        Ok(())
+    }
+
+    // Simulates a libFuzzer in jobs mode, whose two jobs log their progress and
+    // an artifact each, and which leaves an artifact unlogged as it exits. Logs
+    // its jobs mode arguments.
+    #[cfg(target_family = "unix")]
+    const FAKE_JOBS_LIBFUZZER: &str = r#"#!/bin/sh
+for arg in "$@"; do
+  case "$arg" in
+    -artifact_prefix=*) prefix="${arg#-artifact_prefix=}" ;;
+    -jobs=*|-workers=*) echo "$arg" >> "$FAKE_LIBFUZZER_LOG" ;;
+  esac
+done
+printf '#1024\tpulse  cov: 6 ft: 8 corp: 3/6b lim: 8 exec/s: 512 rss: 27Mb\n' > fuzz-0.log
+printf '#2048\tpulse  cov: 7 ft: 9 corp: 4/8b lim: 8 exec/s: 256 rss: 30Mb\n' > fuzz-1.log
+echo '==4242== ERROR: libFuzzer: out-of-memory (used: 2049Mb; exceeds: 2048Mb)' >> fuzz-0.log
+echo oom > "${prefix}oom-1"
+echo "artifact_prefix='${prefix}'; Test unit written to ${prefix}oom-1" >> fuzz-0.log
+echo crash > "${prefix}crash-1"
+echo "artifact_prefix='${prefix}'; Test unit written to ${prefix}crash-1" >> fuzz-1.log
+echo crash > "${prefix}crash-2"
+echo '================== Job 1 exited with exit code 1 ============' >&2
+exit 1
+"#;
+
+    #[cfg(target_family = "unix")]
+    #[tokio::test]
+    async fn test_jobs_mode_collects_artifacts_from_job_logs() -> Result<()> {
+        use onefuzz::fs::list_files;
+
+        let dir = tempfile::tempdir()?;
+        let oom = dir.path().join("oom");
+        tokio::fs::create_dir_all(&oom).await?;
+
+        let (task_dir, task) = fake_libfuzzer_task(
+            FAKE_JOBS_LIBFUZZER,
+            serde_json::json!({
+                "oom": { "path": oom },
+                "target_workers": 2,
+                "jobs_mode": true,
+            }),
+        )
+        .await?;
+
+        let (stats_sender, mut stats_receiver) = tokio::sync::mpsc::unbounded_channel();
+        let local_inputs = tempfile::tempdir()?;
+        task.run_fuzzer(local_inputs.path(), 0, Some(&stats_sender))
+            .await?;
+        drop(stats_sender);
+
+        let names = |dir: PathBuf| async move {
+            let mut names: Vec<_> = list_files(dir)
+                .await?
+                .iter()
+                .filter_map(|path| path.file_name())
+                .map(|name| name.to_string_lossy().into_owned())
+                .collect();
+            names.sort();
+            anyhow::Ok(names)
+        };
+        assert_eq!(
+            names(task_dir.path().join("crashes")).await?,
+            ["crash-1", "crash-2"]
+        );
+        assert_eq!(names(oom).await?, ["oom-1"]);
+
+        // Each job is a worker of its own.
+        let mut total = TotalStats::default();
+        while let Some(stats) = stats_receiver.recv().await {
+            total.update(stats);
+        }
+        assert_eq!(total.count, 3072);
+        assert_eq!(total.execs_sec, 768.0);
+
+        // The job logs are written in a directory of their own.
+        assert!(!task_dir.path().join("fuzz-0.log").exists());
+        assert_eq!(
+            tokio::fs::read_to_string(task_dir.path().join("fuzzer.log")).await?,
+            "-jobs=20\n-workers=2\n"
+        );
+
+        Ok(())
+    }
+
+    // Logs the arguments of each run.
+    #[cfg(target_family = "unix")]
+    const FAKE_ARGS_LIBFUZZER: &str = r#"#!/bin/sh
+echo "$@" >> "$FAKE_LIBFUZZER_LOG"
+echo 'Done 100 runs in 0 second(s)' >&2
+"#;
+
+    #[cfg(target_family = "unix")]
+    #[tokio::test]
+    async fn test_dictionary() -> Result<()> {
+        let dicts = tempfile::tempdir()?;
+        let dict = dicts.path().join("parser.dict");
+        tokio::fs::write(&dict, "kw=\"if\"\n").await?;
+
+        let (dir, task) = fake_libfuzzer_task(
+            FAKE_ARGS_LIBFUZZER,
+            serde_json::json!({ "dictionary": { "path": dicts.path() } }),
+        )
+        .await?;
+        task.init_dictionary().await?;
+
+        let local_inputs = tempfile::tempdir()?;
+        task.run_fuzzer(local_inputs.path(), 0, None).await?;
+
+        let log = tokio::fs::read_to_string(dir.path().join("fuzzer.log")).await?;
+        let expected = format!("-dict={}", dict.display());
+        assert!(log.split_whitespace().any(|arg| arg == expected), "{log}");
+
+        Ok(())
+    }
+
+    #[cfg(target_family = "unix")]
+    #[tokio::test]
+    async fn test_dictionary_empty_container() -> Result<()> {
+        let dicts = tempfile::tempdir()?;
+
+        let (dir, task) = fake_libfuzzer_task(
+            FAKE_ARGS_LIBFUZZER,
+            serde_json::json!({ "dictionary": { "path": dicts.path() } }),
+        )
+        .await?;
+        task.init_dictionary().await?;
+
+        let local_inputs = tempfile::tempdir()?;
+        task.run_fuzzer(local_inputs.path(), 0, None).await?;
+
+        let log = tokio::fs::read_to_string(dir.path().join("fuzzer.log")).await?;
+        assert!(!log.contains("-dict="), "{log}");
+
+        Ok(())
+    }
 }
@@ -1,16 +1,21 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
 use anyhow::Result;
 use async_trait::async_trait;
 use onefuzz::fs::set_executable;
 use onefuzz::libfuzzer::LibFuzzer;
 use onefuzz::syncdir::SyncedDir;
+use tokio::fs;
 use tokio::process::Command;
 
 use crate::tasks::fuzz::libfuzzer::common;
+use crate::tasks::report::dotnet::common::{
+    ENABLE_MINIDUMP_VAR, MINIDUMP_ENABLE, MINIDUMP_NAME_VAR, MINIDUMP_TYPE_FULL, MINIDUMP_TYPE_VAR,
+};
 use crate::tasks::utils::try_resolve_setup_relative_path;
 
 #[cfg(target_os = "linux")]
@@ -31,6 +36,9 @@ const SHARPFUZZ_PATH: &str = "sharpfuzz/SharpFuzz.CommandLine";
 #[cfg(target_os = "windows")]
 const SHARPFUZZ_PATH: &str = "sharpfuzz/SharpFuzz.CommandLine.exe";
 
+// In `crashdumps`, named by the runtime after the crashed process.
+const MINIDUMP_NAME: &str = "dotnet.%p.dmp";
+
 #[derive(Debug)]
 pub struct LibFuzzerDotnet;
 
@@ -40,9 +48,47 @@ pub struct LibFuzzerDotnetConfig {
     pub target_class: String,
     pub target_method: String,
     pub tools: SyncedDir,
+
+    /// Directory of the .NET runtime, set as `DOTNET_ROOT`.
+    pub dotnet_root: Option<PathBuf>,
+
+    /// .NET runtime configuration knobs, each set as `COMPlus_<name>`.
+    #[serde(default)]
+    pub dotnet_settings: HashMap<String, String>,
 }
 
 impl LibFuzzerDotnetConfig {
+    fn target(&self) -> DotnetTarget<'_> {
+        DotnetTarget {
+            assembly: Path::new(&self.target_assembly),
+            class: &self.target_class,
+            method: &self.target_method,
+            tools: &self.tools,
+            dotnet_root: self.dotnet_root.as_deref(),
+            dotnet_settings: &self.dotnet_settings,
+        }
+    }
+}
+
+/// A method of a .NET assembly, fuzzed with the libfuzzer-dotnet harness.
+///
+/// The assembly is not run directly. Instead, the harness runs the loader,
+/// which calls the target method of the assembly.
+#[derive(Debug)]
+pub struct DotnetTarget<'a> {
+    /// The assembly, relative to the setup directory.
+    pub assembly: &'a Path,
+    pub class: &'a str,
+    pub method: &'a str,
+
+    /// The harness, loader and SharpFuzz.
+    pub tools: &'a SyncedDir,
+
+    pub dotnet_root: Option<&'a Path>,
+    pub dotnet_settings: &'a HashMap<String, String>,
+}
+
+impl DotnetTarget<'_> {
     fn libfuzzer_dotnet_path(&self) -> PathBuf {
         self.tools.local_path.join(LIBFUZZER_DOTNET_PATH)
     }
@@ -54,35 +100,26 @@ impl LibFuzzerDotnetConfig {
     fn sharpfuzz_path(&self) -> PathBuf {
         self.tools.local_path.join(SHARPFUZZ_PATH)
     }
-}
 
-#[async_trait]
-impl common::LibFuzzerType for LibFuzzerDotnet {
-    type Config = LibFuzzerDotnetConfig;
+    async fn assembly_path(&self, setup_dir: &Path) -> Result<PathBuf> {
+        try_resolve_setup_relative_path(setup_dir, self.assembly).await
+    }
 
-    async fn from_config(config: &common::Config<Self>) -> Result<LibFuzzer> {
-        let target_assembly = config.target_assembly().await?;
-
-        // Configure loader to fuzz user target DLL.
-        let mut env = config.target_env.clone();
-        env.insert("LIBFUZZER_DOTNET_TARGET_ASSEMBLY".into(), target_assembly);
-        env.insert(
-            "LIBFUZZER_DOTNET_TARGET_CLASS".into(),
-            config.extra.target_class.clone(),
-        );
-        env.insert(
-            "LIBFUZZER_DOTNET_TARGET_METHOD".into(),
-            config.extra.target_method.clone(),
-        );
+    /// The harness, configured to run the loader for the target method.
+    pub async fn fuzzer<L: common::LibFuzzerType>(
+        &self,
+        config: &common::Config<L>,
+    ) -> Result<LibFuzzer> {
+        let assembly = self.assembly_path(&config.common.setup_dir).await?;
+        let env = self.env(config, &assembly);
 
+        // libFuzzer ignores flags which start with `--`, so this is only read
+        // by the harness, including when checking `-help=1`.
         let mut options = config.target_options.clone();
-        options.push(format!(
-            "--target_path={}",
-            config.extra.loader_path().display()
-        ));
+        options.push(format!("--target_path={}", self.loader_path().display()));
 
         Ok(LibFuzzer::new(
-            config.extra.libfuzzer_dotnet_path(),
+            self.libfuzzer_dotnet_path(),
             options,
             env,
             config.common.setup_dir.clone(),
@@ -93,21 +130,82 @@ impl common::LibFuzzerType for LibFuzzerDotnet {
                 .as_ref()
                 .map(|x| x.local_path.clone()),
             config.common.machine_identity.clone(),
-        ))
+        )
+        .allow_unknown_placeholders(config.common.allow_unknown_placeholders)
+        .allow_env_placeholders(config.common.allow_env_placeholders)
+        .target_options_shell(config.common.target_options_shell)
+        .dotnet_assembly(assembly))
     }
 
-    async fn extra_setup(config: &common::Config<Self>) -> Result<()> {
+    // The environment of the harness, which the loader inherits.
+    fn env<L: common::LibFuzzerType>(
+        &self,
+        config: &common::Config<L>,
+        assembly: &Path,
+    ) -> HashMap<String, String> {
+        let mut env = HashMap::new();
+
+        // The runtime dumps a crashed loader, such as on a stack overflow, as
+        // the OS would a native target. `target_env` may change the dump.
+        if let Some(crashdumps) = &config.crashdumps {
+            let dump = crashdumps.local_path.join(MINIDUMP_NAME);
+            env.insert(ENABLE_MINIDUMP_VAR.into(), MINIDUMP_ENABLE.into());
+            env.insert(MINIDUMP_TYPE_VAR.into(), MINIDUMP_TYPE_FULL.into());
+            env.insert(MINIDUMP_NAME_VAR.into(), dump.to_string_lossy().into());
+        }
+
+        env.extend(config.target_env.clone());
+
+        // The settings of the target take precedence over any `target_env` of
+        // the same name.
+        if let Some(dotnet_root) = self.dotnet_root {
+            env.insert("DOTNET_ROOT".into(), dotnet_root.to_string_lossy().into());
+        }
+
+        for (name, value) in self.dotnet_settings {
+            env.insert(format!("COMPlus_{name}"), value.clone());
+        }
+
+        env.insert(
+            "LIBFUZZER_DOTNET_TARGET_ASSEMBLY".into(),
+            assembly.to_string_lossy().into(),
+        );
+        env.insert("LIBFUZZER_DOTNET_TARGET_CLASS".into(), self.class.into());
+        env.insert("LIBFUZZER_DOTNET_TARGET_METHOD".into(), self.method.into());
+
+        env
+    }
+
+    /// Download the tools, and statically instrument the assembly with
+    /// SharpFuzz.
+    pub async fn setup(&self, setup_dir: &Path) -> Result<()> {
         // Download dotnet fuzzing tools.
-        config.extra.tools.init_pull().await?;
+        self.tools.init_pull().await?;
 
         // Ensure tools are executable.
-        set_executable(&config.extra.tools.local_path).await?;
+        set_executable(&self.tools.local_path).await?;
 
-        // Use SharpFuzz to statically instrument the target assembly.
-        let mut cmd = Command::new(config.extra.sharpfuzz_path());
+        // Otherwise, verification would only fail to run the harness.
+        for tool in [LIBFUZZER_DOTNET_PATH, LOADER_PATH, SHARPFUZZ_PATH] {
+            if fs::metadata(self.tools.local_path.join(tool))
+                .await
+                .is_err()
+            {
+                anyhow::bail!("missing `{tool}` in the `tools` container");
+            }
+        }
+
+        let target_assembly = self.assembly_path(setup_dir).await?;
+        if fs::metadata(&target_assembly).await.is_err() {
+            anyhow::bail!(
+                "missing target assembly `{}` in the setup container",
+                self.assembly.display()
+            );
+        }
 
-        let target_assembly = config.target_assembly().await?;
-        cmd.arg(target_assembly);
+        // Use SharpFuzz to statically instrument the target assembly.
+        let mut cmd = Command::new(self.sharpfuzz_path());
+        cmd.arg(&target_assembly);
 
         cmd.stdout(std::process::Stdio::piped());
         cmd.stderr(std::process::Stdio::piped());
@@ -118,7 +216,7 @@ impl common::LibFuzzerType for LibFuzzerDotnet {
         if !output.status.success() {
             anyhow::bail!(
                 "error instrumenting assembly `{}`: {:?}",
-                config.extra.target_assembly,
+                self.assembly.display(),
                 output,
             );
         }
@@ -127,17 +225,128 @@ impl common::LibFuzzerType for LibFuzzerDotnet {
     }
 }
 
-impl common::Config<LibFuzzerDotnet> {
-    async fn target_assembly(&self) -> Result<String> {
-        let resolved =
-            try_resolve_setup_relative_path(&self.common.setup_dir, &self.extra.target_assembly)
-                .await?
-                .to_string_lossy()
-                .into_owned();
+#[async_trait]
+impl common::LibFuzzerType for LibFuzzerDotnet {
+    type Config = LibFuzzerDotnetConfig;
 
-        Ok(resolved)
+    async fn from_config(config: &common::Config<Self>) -> Result<LibFuzzer> {
+        config.extra.target().fuzzer(config).await
+    }
+
+    async fn extra_setup(config: &common::Config<Self>) -> Result<()> {
+        config.extra.target().setup(&config.common.setup_dir).await
     }
 }
 
 pub type Config = common::Config<LibFuzzerDotnet>;
 pub type LibFuzzerDotnetFuzzTask = common::LibFuzzerFuzzTask<LibFuzzerDotnet>;
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::ffi::OsStr;
+
+    use common::LibFuzzerType;
+    use serde_json::json;
+
+    use super::*;
+    use crate::tasks::config::fixture;
+
+    #[tokio::test]
+    async fn test_harness_command() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let setup_dir = dir.path().join("setup");
+        let tools = dir.path().join("tools");
+        tokio::fs::create_dir_all(&setup_dir).await?;
+        tokio::fs::write(setup_dir.join("Fuzz.dll"), "").await?;
+
+        let config: Config = serde_json::from_value(fixture::config(json!({
+            "inputs": { "path": dir.path().join("inputs") },
+            "crashes": { "path": dir.path().join("crashes") },
+            "target_exe": "Fuzz.dll",
+            "target_env": {
+                "DOTNET_ROOT": "/opt/dotnet",
+                "LIBFUZZER_DOTNET_TARGET_CLASS": "Other",
+            },
+            "target_options": ["-max_len=100"],
+            "target_assembly": "Fuzz.dll",
+            "target_class": "Fuzz.Target",
+            "target_method": "TestInput",
+            "tools": { "path": tools },
+            "setup_dir": setup_dir,
+        })))?;
+
+        let fuzzer = LibFuzzerDotnet::from_config(&config).await?;
+        let cmd = fuzzer.build_std_command(None, None, None, None, None)?;
+
+        assert_eq!(cmd.get_program(), tools.join(LIBFUZZER_DOTNET_PATH));
+
+        let args: Vec<_> = cmd.get_args().collect();
+        let target_path = format!("--target_path={}", tools.join(LOADER_PATH).display());
+        assert_eq!(
+            &args[args.len() - 2..],
+            [OsStr::new("-max_len=100"), OsStr::new(&target_path)]
+        );
+
+        let env: HashMap<_, _> = cmd
+            .get_envs()
+            .filter_map(|(key, value)| Some((key.to_str()?, value?.to_str()?)))
+            .collect();
+        let assembly = setup_dir.join("Fuzz.dll");
+        assert_eq!(
+            env["LIBFUZZER_DOTNET_TARGET_ASSEMBLY"],
+            assembly.to_string_lossy()
+        );
+        assert_eq!(env["LIBFUZZER_DOTNET_TARGET_CLASS"], "Fuzz.Target");
+        assert_eq!(env["LIBFUZZER_DOTNET_TARGET_METHOD"], "TestInput");
+        assert_eq!(env["DOTNET_ROOT"], "/opt/dotnet");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_runtime_env() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let setup_dir = dir.path().join("setup");
+        let crashdumps = dir.path().join("crashdumps");
+        tokio::fs::create_dir_all(&setup_dir).await?;
+
+        let config: Config = serde_json::from_value(fixture::config(json!({
+            "inputs": { "path": dir.path().join("inputs") },
+            "crashes": { "path": dir.path().join("crashes") },
+            "crashdumps": { "path": crashdumps },
+            "target_exe": "Fuzz.dll",
+            "target_env": {
+                "DOTNET_ROOT": "/opt/dotnet",
+                "COMPlus_DbgMiniDumpType": "2",
+            },
+            "target_options": [],
+            "target_assembly": "Fuzz.dll",
+            "target_class": "Fuzz.Target",
+            "target_method": "TestInput",
+            "tools": { "path": dir.path().join("tools") },
+            "dotnet_root": "/usr/share/dotnet",
+            "dotnet_settings": { "TieredCompilation": "0" },
+            "setup_dir": setup_dir,
+        })))?;
+
+        let fuzzer = LibFuzzerDotnet::from_config(&config).await?;
+        let cmd = fuzzer.build_std_command(None, None, None, None, None)?;
+        let env: HashMap<_, _> = cmd
+            .get_envs()
+            .filter_map(|(key, value)| Some((key.to_str()?, value?.to_str()?)))
+            .collect();
+
+        assert_eq!(env["DOTNET_ROOT"], "/usr/share/dotnet");
+        assert_eq!(env["COMPlus_TieredCompilation"], "0");
+
+        // Crashes of the loader are dumped to `crashdumps`, as `target_env`
+        // allows.
+        let dump = crashdumps.join(MINIDUMP_NAME);
+        assert_eq!(env["COMPlus_DbgEnableMiniDump"], "1");
+        assert_eq!(env["COMPlus_DbgMiniDumpType"], "2");
+        assert_eq!(env["COMPlus_DbgMiniDumpName"], dump.to_string_lossy());
+
+        Ok(())
+    }
+}
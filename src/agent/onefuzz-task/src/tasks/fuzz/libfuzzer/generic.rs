@@ -1,31 +1,90 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
+use std::collections::HashMap;
 use std::path::PathBuf;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use async_trait::async_trait;
 use onefuzz::libfuzzer::LibFuzzer;
+use onefuzz::syncdir::SyncedDir;
+use serde::Deserialize;
 
 use crate::tasks::fuzz::libfuzzer::common;
+use crate::tasks::fuzz::libfuzzer::dotnet::DotnetTarget;
 use crate::tasks::utils::try_resolve_setup_relative_path;
 
-/// Generic LibFuzzer with no special extra configuration.
+/// Generic LibFuzzer with no special extra configuration, unless its target is
+/// a .NET assembly.
 ///
 /// Its configuration is fully controlled by the user, up to the constraints of the
 /// `LibFuzzer` wrapper itself.
 #[derive(Debug)]
 pub struct GenericLibFuzzer;
 
+/// The kind of program that `target_exe` is.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TargetType {
+    /// A libFuzzer, run directly.
+    #[default]
+    Native,
+
+    /// A .NET assembly, fuzzed with the libfuzzer-dotnet harness, as in a
+    /// `libfuzzer_dotnet_fuzz` task.
+    Dotnet,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct GenericLibFuzzerConfig {
+    #[serde(default)]
+    pub target_type: TargetType,
+
+    // Of `dotnet` targets, as for `libfuzzer_dotnet_fuzz`, with `target_exe`
+    // as the assembly.
+    pub target_class: Option<String>,
+    pub target_method: Option<String>,
+    pub tools: Option<SyncedDir>,
+    pub dotnet_root: Option<PathBuf>,
+    #[serde(default)]
+    pub dotnet_settings: HashMap<String, String>,
+}
+
+impl common::Config<GenericLibFuzzer> {
+    // The method to fuzz, if `target_exe` is a .NET assembly.
+    fn dotnet_target(&self) -> Result<Option<DotnetTarget<'_>>> {
+        if self.extra.target_type != TargetType::Dotnet {
+            return Ok(None);
+        }
+
+        Ok(Some(DotnetTarget {
+            assembly: &self.target_exe,
+            class: required(self.extra.target_class.as_deref(), "target_class")?,
+            method: required(self.extra.target_method.as_deref(), "target_method")?,
+            tools: required(self.extra.tools.as_ref(), "tools")?,
+            dotnet_root: self.extra.dotnet_root.as_deref(),
+            dotnet_settings: &self.extra.dotnet_settings,
+        }))
+    }
+}
+
+fn required<'a, T: ?Sized>(value: Option<&'a T>, key: &str) -> Result<&'a T> {
+    value.with_context(|| format!("`{key}` is required for `dotnet` targets"))
+}
+
 #[async_trait]
 impl common::LibFuzzerType for GenericLibFuzzer {
-    type Config = ();
+    type Config = GenericLibFuzzerConfig;
 
     async fn from_config(config: &common::Config<Self>) -> Result<LibFuzzer> {
+        if let Some(target) = config.dotnet_target()? {
+            return target.fuzzer(config).await;
+        }
+
         let target_exe =
             try_resolve_setup_relative_path(&config.common.setup_dir, &config.target_exe).await?;
 
-        Ok(LibFuzzer::new(
+        let fuzzer = LibFuzzer::new(
             target_exe,
             config.target_options.clone(),
             config.target_env.clone(),
@@ -37,10 +96,22 @@ impl common::LibFuzzerType for GenericLibFuzzer {
                 .as_ref()
                 .map(|x| x.local_path.clone()),
             config.common.machine_identity.clone(),
-        ))
+        )
+        .allow_unknown_placeholders(config.common.allow_unknown_placeholders)
+        .allow_env_placeholders(config.common.allow_env_placeholders)
+        .target_options_shell(config.common.target_options_shell);
+
+        Ok(match &config.common.qemu_user {
+            Some(qemu) => fuzzer.qemu_user(qemu.clone()),
+            None => fuzzer,
+        })
     }
 
     async fn extra_setup(config: &common::Config<Self>) -> Result<()> {
+        if let Some(target) = config.dotnet_target()? {
+            return target.setup(&config.common.setup_dir).await;
+        }
+
         // this is needed on Windows, but we do it unconditionally
         let target_exe =
             try_resolve_setup_relative_path(&config.common.setup_dir, &config.target_exe).await?;
@@ -81,9 +152,89 @@ pub type LibFuzzerFuzzTask = common::LibFuzzerFuzzTask<GenericLibFuzzer>;
 
 #[cfg(test)]
 mod test {
+    use std::collections::HashMap;
+    use std::ffi::OsStr;
     use std::path::PathBuf;
 
-    use super::add_dotlocal_extension;
+    use anyhow::Result;
+    use serde_json::{json, Value};
+
+    use super::{add_dotlocal_extension, Config, GenericLibFuzzer};
+    use crate::tasks::config::fixture;
+    use crate::tasks::fuzz::libfuzzer::common::LibFuzzerType;
+
+    fn dotnet_config(dir: &std::path::Path, target_method: Value) -> Result<Config> {
+        Ok(serde_json::from_value(fixture::config(json!({
+            "inputs": { "path": dir.join("inputs") },
+            "crashes": { "path": dir.join("crashes") },
+            "target_exe": "Fuzz.dll",
+            "target_env": {},
+            "target_options": ["-max_len=100"],
+            "target_type": "dotnet",
+            "target_class": "Fuzz.Target",
+            "target_method": target_method,
+            "tools": { "path": dir.join("tools") },
+            "dotnet_root": "/usr/share/dotnet",
+            "setup_dir": dir.join("setup"),
+        })))?)
+    }
+
+    #[tokio::test]
+    async fn dotnet_target_command() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let setup_dir = dir.path().join("setup");
+        let tools = dir.path().join("tools");
+        tokio::fs::create_dir_all(&setup_dir).await?;
+        tokio::fs::write(setup_dir.join("Fuzz.dll"), "").await?;
+
+        let config = dotnet_config(dir.path(), json!("TestInput"))?;
+        let fuzzer = GenericLibFuzzer::from_config(&config).await?;
+        let cmd = fuzzer.build_std_command(None, None, None, None, None)?;
+
+        // The harness is run, instead of `target_exe`.
+        let harness = if cfg!(target_os = "windows") {
+            "libfuzzer-dotnet/libfuzzer-dotnet.exe"
+        } else {
+            "libfuzzer-dotnet/libfuzzer-dotnet"
+        };
+        assert_eq!(cmd.get_program(), tools.join(harness));
+
+        let args: Vec<_> = cmd.get_args().collect();
+        let target_path = args.last().unwrap().to_string_lossy();
+        assert!(target_path.starts_with("--target_path="), "{target_path}");
+        assert_eq!(args[args.len() - 2], OsStr::new("-max_len=100"));
+
+        let env: HashMap<_, _> = cmd
+            .get_envs()
+            .filter_map(|(key, value)| Some((key.to_str()?, value?.to_str()?)))
+            .collect();
+        let assembly = setup_dir.join("Fuzz.dll");
+        assert_eq!(
+            env["LIBFUZZER_DOTNET_TARGET_ASSEMBLY"],
+            assembly.to_string_lossy()
+        );
+        assert_eq!(env["LIBFUZZER_DOTNET_TARGET_CLASS"], "Fuzz.Target");
+        assert_eq!(env["LIBFUZZER_DOTNET_TARGET_METHOD"], "TestInput");
+        assert_eq!(env["DOTNET_ROOT"], "/usr/share/dotnet");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn dotnet_target_requires_method() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let config = dotnet_config(dir.path(), Value::Null)?;
+
+        let Err(err) = GenericLibFuzzer::from_config(&config).await else {
+            panic!("`target_method` is not required");
+        };
+        assert_eq!(
+            err.to_string(),
+            "`target_method` is required for `dotnet` targets"
+        );
+
+        Ok(())
+    }
 
     #[test]
     fn dotlocal_with_extension() {
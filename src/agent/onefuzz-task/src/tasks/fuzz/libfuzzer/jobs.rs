@@ -0,0 +1,153 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! The job logs of libFuzzer's `-jobs` mode, in which each job writes its
+//! output to a log of its own, `fuzz-<job>.log`.
+
+use anyhow::Result;
+use onefuzz::fs::list_files;
+use std::{
+    collections::BTreeMap,
+    io::SeekFrom,
+    path::{Path, PathBuf},
+};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+/// The job whose log is at `path`, from its name, `fuzz-<job>.log`.
+pub fn log_job(path: &Path) -> Option<usize> {
+    let name = path.file_name()?.to_str()?;
+    name.strip_prefix("fuzz-")?
+        .strip_suffix(".log")?
+        .parse()
+        .ok()
+}
+
+/// The file name of the artifact a libFuzzer output line says was written, as
+/// in `artifact_prefix='<prefix>'; Test unit written to <prefix>crash-<sha1>`.
+pub fn written_artifact(line: &str) -> Option<PathBuf> {
+    let (_, path) = line.split_once("Test unit written to ")?;
+    Path::new(path.trim_end()).file_name().map(PathBuf::from)
+}
+
+/// The logs of the jobs of a libFuzzer, read as they are written.
+pub struct JobLogs {
+    dir: PathBuf,
+    logs: BTreeMap<usize, JobLog>,
+}
+
+#[derive(Default)]
+struct JobLog {
+    // Bytes read so far.
+    offset: u64,
+
+    // The last line read, if not yet complete.
+    partial: Vec<u8>,
+}
+
+impl JobLogs {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            logs: BTreeMap::new(),
+        }
+    }
+
+    /// The lines written to each job log since the last read, by job, with
+    /// their line endings. Logs created since are found.
+    ///
+    /// An incomplete last line is held back until the next read, unless
+    /// `finished`, as when the libFuzzer has exited.
+    pub async fn read(&mut self, finished: bool) -> Result<Vec<(usize, String)>> {
+        let mut paths: Vec<_> = list_files(&self.dir)
+            .await?
+            .into_iter()
+            .filter_map(|path| Some((log_job(&path)?, path)))
+            .collect();
+        paths.sort();
+
+        let mut lines = vec![];
+        for (job, path) in paths {
+            let log = self.logs.entry(job).or_default();
+
+            let mut file = tokio::fs::File::open(&path).await?;
+            file.seek(SeekFrom::Start(log.offset)).await?;
+            let read = file.read_to_end(&mut log.partial).await?;
+            log.offset += read as u64;
+
+            while let Some(end) = log.partial.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = log.partial.drain(..=end).collect();
+                lines.push((job, String::from_utf8_lossy(&line).into_owned()));
+            }
+
+            if finished && !log.partial.is_empty() {
+                let line = std::mem::take(&mut log.partial);
+                lines.push((job, String::from_utf8_lossy(&line).into_owned()));
+            }
+        }
+
+        Ok(lines)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_job() {
+        assert_eq!(log_job(Path::new("logs/fuzz-0.log")), Some(0));
+        assert_eq!(log_job(Path::new("fuzz-12.log")), Some(12));
+        assert_eq!(log_job(Path::new("fuzz-.log")), None);
+        assert_eq!(log_job(Path::new("fuzz-1.txt")), None);
+        assert_eq!(log_job(Path::new("crash-1")), None);
+    }
+
+    #[test]
+    fn test_written_artifact() {
+        assert_eq!(
+            written_artifact(
+                "artifact_prefix='/crashes/'; Test unit written to /crashes/crash-da39a3ee5e6b4b0d3255bfef95601890afd80709\n"
+            ),
+            Some(PathBuf::from("crash-da39a3ee5e6b4b0d3255bfef95601890afd80709"))
+        );
+        assert_eq!(
+            written_artifact("artifact_prefix='./'; Test unit written to ./oom-1"),
+            Some(PathBuf::from("oom-1"))
+        );
+        assert_eq!(
+            written_artifact("#1024\tpulse  cov: 6 ft: 8 corp: 3/6b exec/s: 512 rss: 27Mb"),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_job_logs() -> Result<()> {
+        use std::io::Write;
+
+        let dir = tempfile::tempdir()?;
+        let mut logs = JobLogs::new(dir.path());
+        assert!(logs.read(false).await?.is_empty());
+
+        let mut log0 = std::fs::File::create(dir.path().join("fuzz-0.log"))?;
+        log0.write_all(b"INFO: Seed: 1\n#2\tINITED")?;
+        std::fs::write(dir.path().join("crash-1"), "not a log")?;
+
+        assert_eq!(logs.read(false).await?, [(0, "INFO: Seed: 1\n".to_owned())]);
+
+        log0.write_all(b" cov: 3\nDone\n")?;
+        std::fs::write(dir.path().join("fuzz-1.log"), "INFO: Seed: 2\nexiting")?;
+
+        assert_eq!(
+            logs.read(false).await?,
+            [
+                (0, "#2\tINITED cov: 3\n".to_owned()),
+                (0, "Done\n".to_owned()),
+                (1, "INFO: Seed: 2\n".to_owned()),
+            ]
+        );
+        assert_eq!(logs.read(true).await?, [(1, "exiting".to_owned())]);
+        assert!(logs.read(true).await?.is_empty());
+
+        Ok(())
+    }
+}
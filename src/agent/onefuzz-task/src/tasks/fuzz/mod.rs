@@ -3,4 +3,5 @@
 
 pub mod generator;
 pub mod libfuzzer;
+pub mod snapshot;
 pub mod supervisor;
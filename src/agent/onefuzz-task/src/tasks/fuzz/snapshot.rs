@@ -0,0 +1,461 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Periodic snapshots of the corpus of a fuzzing task, to roll back to.
+//!
+//! Each snapshot is a copy of the local corpus, pushed to
+//! `snapshots/<timestamp>/` in the snapshot container. The timestamp is the
+//! UTC time of the snapshot, such as `20231101T100000Z`, so that snapshots
+//! sort by name in the order they were taken.
+
+use std::{collections::BTreeSet, num::NonZeroUsize, path::Path, time::Duration};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use futures::stream::{self, StreamExt, TryStreamExt};
+use onefuzz::{
+    az_copy,
+    blob::{BlobClient, BlobContainerUrl, BlobUrl},
+    fs::{exists, sync, SyncPath},
+    jitter::delay_with_jitter,
+    syncdir::SyncedDir,
+};
+use serde::Deserialize;
+use tokio::fs;
+
+/// Directory of the snapshot container which holds the snapshots.
+pub const SNAPSHOTS_DIR: &str = "snapshots";
+
+const TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+// Number of blobs deleted at once, when deleting a snapshot.
+const MAX_CONCURRENT_DELETES: usize = 32;
+
+pub fn default_snapshot_interval() -> u64 {
+    6 * 60 * 60
+}
+
+pub fn default_snapshot_keep() -> NonZeroUsize {
+    NonZeroUsize::new(10).unwrap()
+}
+
+/// Periodic snapshots of the `inputs` of a fuzzing task.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct CorpusSnapshots {
+    /// Container to push the snapshots to.
+    pub container: SyncedDir,
+
+    /// Seconds between snapshots. `0` disables snapshots.
+    #[serde(default = "default_snapshot_interval")]
+    pub interval: u64,
+
+    /// Number of snapshots to keep. Older snapshots are deleted.
+    #[serde(default = "default_snapshot_keep")]
+    pub keep: NonZeroUsize,
+
+    /// Limit on the upload rate of a snapshot, in megabits per second.
+    pub cap_mbps: Option<u64>,
+}
+
+/// The name of the snapshot taken at `time`.
+pub fn snapshot_name(time: DateTime<Utc>) -> String {
+    time.format(TIMESTAMP_FORMAT).to_string()
+}
+
+/// The path of the snapshot `name`, relative to the root of the container.
+pub fn snapshot_path(name: &str) -> String {
+    format!("{SNAPSHOTS_DIR}/{name}")
+}
+
+fn is_snapshot_name(name: &str) -> bool {
+    NaiveDateTime::parse_from_str(name, TIMESTAMP_FORMAT).is_ok()
+}
+
+/// The snapshots of `names` to delete, so that only the newest `keep` are
+/// left. Names which are not snapshot timestamps are never deleted.
+pub fn expired(names: &[String], keep: NonZeroUsize) -> Vec<String> {
+    let names: BTreeSet<&str> = names
+        .iter()
+        .map(String::as_str)
+        .filter(|name| is_snapshot_name(name))
+        .collect();
+    let expired = names.len().saturating_sub(keep.get());
+
+    names.into_iter().take(expired).map(str::to_owned).collect()
+}
+
+/// Storage for the snapshots of a corpus.
+#[async_trait]
+pub trait SnapshotStore: Send + Sync {
+    /// The names of the snapshots in the store, in any order.
+    async fn list(&self) -> Result<Vec<String>>;
+
+    /// Push the files of the directory `corpus` as the snapshot `name`.
+    async fn push(&self, corpus: &Path, name: &str) -> Result<()>;
+
+    /// Delete the snapshot `name`, and every file in it.
+    async fn delete(&self, name: &str) -> Result<()>;
+}
+
+/// Snapshots in a container, or in a local directory standing in for one.
+pub struct ContainerStore {
+    container: BlobContainerUrl,
+    cap_mbps: Option<u64>,
+}
+
+impl ContainerStore {
+    pub fn new(config: &CorpusSnapshots) -> Result<Self> {
+        Ok(Self {
+            container: config.container.remote_url()?,
+            cap_mbps: config.cap_mbps,
+        })
+    }
+}
+
+#[async_trait]
+impl SnapshotStore for ContainerStore {
+    async fn list(&self) -> Result<Vec<String>> {
+        if let Some(dir) = self.container.as_file_path() {
+            let dir = dir.join(SNAPSHOTS_DIR);
+            if !exists(&dir).await? {
+                return Ok(vec![]);
+            }
+
+            let mut names = vec![];
+            let mut entries = fs::read_dir(&dir).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                if entry.file_type().await?.is_dir() {
+                    if let Some(name) = entry.file_name().to_str() {
+                        names.push(name.to_owned());
+                    }
+                }
+            }
+            return Ok(names);
+        }
+
+        let prefix = format!("{SNAPSHOTS_DIR}/");
+        let blobs = BlobClient::new()
+            .list_blobs_with_prefix(&self.container.url()?, &prefix)
+            .await
+            .with_context(|| format!("unable to list snapshots of {}", self.container))?;
+        let names: BTreeSet<&str> = blobs
+            .iter()
+            .filter_map(|blob| blob.name.strip_prefix(&prefix)?.split_once('/'))
+            .map(|(name, _)| name)
+            .collect();
+
+        Ok(names.into_iter().map(str::to_owned).collect())
+    }
+
+    async fn push(&self, corpus: &Path, name: &str) -> Result<()> {
+        match self.container.blob(snapshot_path(name)) {
+            BlobUrl::LocalFile(dir) => {
+                fs::create_dir_all(&dir).await?;
+                sync(SyncPath::dir(corpus), SyncPath::dir(&dir), false).await
+            }
+            blob @ BlobUrl::AzureBlob(_) => {
+                // The files of the corpus, rather than the directory itself.
+                let files = corpus.join("*");
+                az_copy::copy_with_cap(&files, blob.url().as_str(), true, self.cap_mbps).await
+            }
+        }
+    }
+
+    async fn delete(&self, name: &str) -> Result<()> {
+        if let BlobUrl::LocalFile(dir) = self.container.blob(snapshot_path(name)) {
+            return fs::remove_dir_all(&dir)
+                .await
+                .with_context(|| format!("unable to delete snapshot {}", dir.display()));
+        }
+
+        let prefix = format!("{}/", snapshot_path(name));
+        let client = BlobClient::new();
+        let blobs = client
+            .list_blobs_with_prefix(&self.container.url()?, &prefix)
+            .await
+            .with_context(|| format!("unable to list blobs of snapshot {name}"))?;
+
+        stream::iter(blobs)
+            .map(|blob| {
+                let client = &client;
+                async move {
+                    let url = self.container.blob(&blob.name).url();
+                    client
+                        .delete(url)
+                        .await
+                        .with_context(|| format!("unable to delete blob {}", blob.name))?;
+                    Ok::<_, anyhow::Error>(())
+                }
+            })
+            .buffer_unordered(MAX_CONCURRENT_DELETES)
+            .try_collect()
+            .await
+    }
+}
+
+/// Push `corpus` to `store` as the snapshot taken at `time`, then delete the
+/// snapshots older than the newest `keep`. Returns the name of the snapshot.
+pub async fn snapshot(
+    store: &dyn SnapshotStore,
+    corpus: &Path,
+    time: DateTime<Utc>,
+    keep: NonZeroUsize,
+) -> Result<String> {
+    let name = snapshot_name(time);
+    store
+        .push(corpus, &name)
+        .await
+        .with_context(|| format!("unable to push snapshot {name}"))?;
+
+    for expired in expired(&store.list().await?, keep) {
+        store
+            .delete(&expired)
+            .await
+            .with_context(|| format!("unable to delete snapshot {expired}"))?;
+    }
+
+    Ok(name)
+}
+
+/// Snapshot `corpus` every `interval` seconds of `config`, from one interval
+/// after being started. A failed snapshot is only logged, so that fuzzing
+/// continues, and is retried at the next interval.
+pub async fn continuous_snapshot(corpus: &Path, config: &CorpusSnapshots) -> Result<()> {
+    if config.interval == 0 {
+        return Ok(());
+    }
+
+    let store = ContainerStore::new(config)?;
+    let interval = Duration::from_secs(config.interval);
+
+    loop {
+        delay_with_jitter(interval).await;
+
+        match snapshot(&store, corpus, Utc::now(), config.keep).await {
+            Ok(name) => info!(
+                "pushed snapshot {} of corpus {}",
+                snapshot_path(&name),
+                corpus.display()
+            ),
+            Err(err) => warn!("unable to snapshot corpus {}: {:?}", corpus.display(), err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use chrono::TimeZone;
+    use onefuzz::fs::list_files;
+
+    use super::*;
+
+    // A container held in memory, as the names of its blobs.
+    #[derive(Default)]
+    struct FakeStore {
+        blobs: Mutex<BTreeSet<String>>,
+    }
+
+    impl FakeStore {
+        fn blobs(&self) -> Vec<String> {
+            self.blobs.lock().unwrap().iter().cloned().collect()
+        }
+    }
+
+    #[async_trait]
+    impl SnapshotStore for FakeStore {
+        async fn list(&self) -> Result<Vec<String>> {
+            let prefix = format!("{SNAPSHOTS_DIR}/");
+            let names: BTreeSet<String> = self
+                .blobs()
+                .iter()
+                .filter_map(|blob| blob.strip_prefix(&prefix)?.split_once('/'))
+                .map(|(name, _)| name.to_owned())
+                .collect();
+            Ok(names.into_iter().collect())
+        }
+
+        async fn push(&self, corpus: &Path, name: &str) -> Result<()> {
+            let files = list_files(corpus).await?;
+            let mut blobs = self.blobs.lock().unwrap();
+            for file in files {
+                let file = file.file_name().unwrap().to_string_lossy();
+                blobs.insert(format!("{}/{}", snapshot_path(name), file));
+            }
+            Ok(())
+        }
+
+        async fn delete(&self, name: &str) -> Result<()> {
+            let prefix = format!("{}/", snapshot_path(name));
+            let mut blobs = self.blobs.lock().unwrap();
+            if !blobs.iter().any(|blob| blob.starts_with(&prefix)) {
+                bail!("no such snapshot: {name}");
+            }
+            blobs.retain(|blob| !blob.starts_with(&prefix));
+            Ok(())
+        }
+    }
+
+    fn keep(keep: usize) -> NonZeroUsize {
+        NonZeroUsize::new(keep).unwrap()
+    }
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| (*name).to_owned()).collect()
+    }
+
+    #[test]
+    fn test_parse() -> Result<()> {
+        let config: CorpusSnapshots =
+            serde_json::from_str(r#"{ "container": { "path": "snapshots" } }"#)?;
+        assert_eq!(config.interval, 6 * 60 * 60);
+        assert_eq!(config.keep, keep(10));
+        assert_eq!(config.cap_mbps, None);
+
+        let config: CorpusSnapshots = serde_json::from_str(
+            r#"{ "container": { "path": "snapshots" }, "interval": 60, "keep": 3, "cap_mbps": 100 }"#,
+        )?;
+        assert_eq!(config.interval, 60);
+        assert_eq!(config.keep, keep(3));
+        assert_eq!(config.cap_mbps, Some(100));
+
+        assert!(serde_json::from_str::<CorpusSnapshots>(
+            r#"{ "container": { "path": "snapshots" }, "keep": 0 }"#
+        )
+        .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot_path() {
+        let time = Utc.with_ymd_and_hms(2023, 11, 1, 9, 5, 7).unwrap();
+        let name = snapshot_name(time);
+        assert_eq!(name, "20231101T090507Z");
+        assert_eq!(snapshot_path(&name), "snapshots/20231101T090507Z");
+        assert!(is_snapshot_name(&name));
+
+        // Names sort in the order the snapshots were taken.
+        let later = snapshot_name(Utc.with_ymd_and_hms(2023, 11, 1, 10, 0, 0).unwrap());
+        assert!(name < later);
+
+        for other in ["README.md", "20231101", "20231101T090507", "2023-11-01"] {
+            assert!(!is_snapshot_name(other), "{other}");
+        }
+    }
+
+    #[test]
+    fn test_expired() {
+        let listed = names(&[
+            "20231101T120000Z",
+            "20231101T000000Z",
+            "notes",
+            "20231101T180000Z",
+            "20231101T060000Z",
+        ]);
+
+        assert_eq!(
+            expired(&listed, keep(2)),
+            names(&["20231101T000000Z", "20231101T060000Z"])
+        );
+        assert_eq!(
+            expired(&listed, keep(1)),
+            names(&["20231101T000000Z", "20231101T060000Z", "20231101T120000Z"])
+        );
+        assert!(expired(&listed, keep(4)).is_empty());
+        assert!(expired(&listed, keep(10)).is_empty());
+        assert!(expired(&[], keep(1)).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_retention() -> Result<()> {
+        let corpus = tempfile::tempdir()?;
+        let store = FakeStore::default();
+        store.blobs.lock().unwrap().insert("README.md".to_owned());
+        let start = Utc.with_ymd_and_hms(2023, 11, 1, 0, 0, 0).unwrap();
+
+        for i in 0..5 {
+            std::fs::write(corpus.path().join(format!("input-{i}")), "data")?;
+            let time = start + chrono::Duration::hours(6 * i);
+            snapshot(&store, corpus.path(), time, keep(3)).await?;
+        }
+
+        assert_eq!(
+            store.list().await?,
+            names(&["20231101T120000Z", "20231101T180000Z", "20231102T000000Z"])
+        );
+
+        // Each snapshot is of the corpus as it was when taken, and blobs
+        // outside of the snapshots are left alone.
+        let blobs = store.blobs();
+        assert_eq!(
+            blobs[..4],
+            names(&[
+                "README.md",
+                "snapshots/20231101T120000Z/input-0",
+                "snapshots/20231101T120000Z/input-1",
+                "snapshots/20231101T120000Z/input-2",
+            ])
+        );
+        assert_eq!(blobs.len(), 1 + 3 + 4 + 5);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_container_store_list_and_delete() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let container = dir.path().join("container");
+        for name in ["20231101T000000Z", "20231101T060000Z"] {
+            let snapshot = container.join(SNAPSHOTS_DIR).join(name);
+            std::fs::create_dir_all(&snapshot)?;
+            std::fs::write(snapshot.join("input"), "data")?;
+        }
+        std::fs::write(container.join(SNAPSHOTS_DIR).join("README.md"), "notes")?;
+
+        let store = ContainerStore::new(&CorpusSnapshots {
+            container: SyncedDir {
+                local_path: dir.path().join("local"),
+                remote_path: Some(BlobContainerUrl::parse(
+                    url::Url::from_directory_path(&container).unwrap(),
+                )?),
+            },
+            interval: default_snapshot_interval(),
+            keep: keep(1),
+            cap_mbps: None,
+        })?;
+
+        let mut listed = store.list().await?;
+        listed.sort();
+        assert_eq!(listed, names(&["20231101T000000Z", "20231101T060000Z"]));
+
+        store.delete("20231101T000000Z").await?;
+        assert_eq!(store.list().await?, names(&["20231101T060000Z"]));
+        assert!(container.join(SNAPSHOTS_DIR).join("README.md").exists());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_container_store_empty() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let store = ContainerStore::new(&CorpusSnapshots {
+            container: SyncedDir {
+                local_path: dir.path().join("local"),
+                remote_path: Some(BlobContainerUrl::parse(
+                    url::Url::from_directory_path(dir.path().join("container")).unwrap(),
+                )?),
+            },
+            interval: default_snapshot_interval(),
+            keep: keep(1),
+            cap_mbps: None,
+        })?;
+
+        assert!(store.list().await?.is_empty());
+
+        Ok(())
+    }
+}
@@ -4,18 +4,25 @@
 #![allow(clippy::too_many_arguments)]
 use crate::tasks::{
     config::{CommonConfig, ContainerType},
+    fuzz::snapshot::{continuous_snapshot, CorpusSnapshots},
     heartbeat::{HeartbeatSender, TaskHeartbeatClient},
     report::crash_report::monitor_reports,
-    stats::common::{monitor_stats, StatsFormat},
+    stats::{
+        common::{monitor_stats, StatsFormat, StatsKeys},
+        process::monitor_process_stats,
+    },
     utils::{try_resolve_setup_relative_path, CheckNotify},
 };
 use anyhow::{Context, Error, Result};
 use onefuzz::{
     expand::Expand,
     fs::{has_files, set_executable, OwnedDir},
+    initial_corpus::MaxInitialCorpus,
     jitter::delay_with_jitter,
     process::monitor_process,
+    qemu::QEMU_LD_PREFIX,
     syncdir::{
+        continuous_sync_pull, SampledDir,
         SyncOperation::{Pull, Push},
         SyncedDir,
     },
@@ -35,7 +42,10 @@ use tokio::{
 };
 use tokio_util::sync::CancellationToken;
 
-use futures::TryFutureExt;
+use futures::{future::try_join_all, TryFutureExt};
+
+pub mod afl;
+pub mod honggfuzz;
 
 #[derive(Debug, Deserialize)]
 pub struct SupervisorConfig {
@@ -52,11 +62,35 @@ pub struct SupervisorConfig {
     pub wait_for_files: Option<ContainerType>,
     pub stats_file: Option<String>,
     pub stats_format: Option<StatsFormat>,
+
+    /// The keys of a `KeyValue` or `Json` `stats_file` to report as stats.
+    #[serde(default)]
+    pub stats_keys: StatsKeys,
+
     pub ensemble_sync_delay: Option<u64>,
+
+    /// Limits on the initial corpus pulled from `inputs`, for seed containers
+    /// too large to pull in full.
+    pub max_initial_corpus: Option<MaxInitialCorpus>,
+
+    /// Periodic snapshots of the corpus in `inputs`.
+    pub corpus_snapshots: Option<CorpusSnapshots>,
+
     pub reports: Option<SyncedDir>,
     pub unique_reports: Option<SyncedDir>,
     pub no_repro: Option<SyncedDir>,
     pub coverage: Option<SyncedDir>,
+
+    /// Run `supervisor_exe` as AFL++'s `afl-fuzz`, building its command line
+    /// from `target_exe` and `target_options`. `supervisor_options` are then
+    /// extra arguments to `afl-fuzz`.
+    pub afl: Option<afl::AflConfig>,
+
+    /// Run `supervisor_exe` as `honggfuzz`, building its command line from
+    /// `target_exe` and `target_options`. `supervisor_options` are then extra
+    /// arguments to `honggfuzz`.
+    pub honggfuzz: Option<honggfuzz::HonggfuzzConfig>,
+
     #[serde(flatten)]
     pub common: CommonConfig,
 }
@@ -64,6 +98,15 @@ pub struct SupervisorConfig {
 const HEARTBEAT_PERIOD: Duration = Duration::from_secs(60);
 
 pub async fn spawn(config: SupervisorConfig) -> Result<(), Error> {
+    // Otherwise, the target is run by the supervisor, so we don't know how to
+    // wrap it.
+    if config.common.qemu_user.is_some() && config.afl.is_none() {
+        bail!("`qemu_user` is only supported by generic_supervisor tasks with `afl`");
+    }
+    if config.afl.is_some() && config.honggfuzz.is_some() {
+        bail!("`afl` and `honggfuzz` can't both be set");
+    }
+
     let runtime_dir = OwnedDir::new(config.common.task_id.to_string());
     runtime_dir.create_if_missing().await?;
 
@@ -82,7 +125,8 @@ pub async fn spawn(config: SupervisorConfig) -> Result<(), Error> {
 
     let jr_client = config.common.init_job_result().await?;
 
-    let monitor_crashes = crashes.monitor_results(new_result, false, &jr_client);
+    let monitor_crashes =
+        crashes.monitor_results(new_result, false, config.common.task_id, &jr_client);
 
     // setup crashdumps
     let (crashdump_dir, monitor_crashdumps) = {
@@ -98,11 +142,12 @@ pub async fn spawn(config: SupervisorConfig) -> Result<(), Error> {
         };
 
         let monitor_dir = crashdump_dir.clone();
+        let task_id = config.common.task_id;
         let monitor_jr_client = config.common.init_job_result().await?;
         let monitor_crashdumps = async move {
             if let Some(crashdumps) = monitor_dir {
                 crashdumps
-                    .monitor_results(new_crashdump, false, &monitor_jr_client)
+                    .monitor_results(new_crashdump, false, task_id, &monitor_jr_client)
                     .await
             } else {
                 Ok(())
@@ -149,7 +194,15 @@ pub async fn spawn(config: SupervisorConfig) -> Result<(), Error> {
         remote_path: config.inputs.remote_path.clone(),
     };
 
-    inputs.init().await?;
+    // Only pulled up front when sampling the initial corpus. Otherwise, the
+    // inputs are pulled by `inputs_sync_task`.
+    let sampled_inputs: Option<SampledDir> = match &config.max_initial_corpus {
+        Some(max) => Some(inputs.init_pull_sampled(Some(max)).await?),
+        None => {
+            inputs.init().await?;
+            None
+        }
+    };
     if let Some(context) = &config.wait_for_files {
         let dir = match context {
             ContainerType::Inputs => &inputs,
@@ -157,52 +210,188 @@ pub async fn spawn(config: SupervisorConfig) -> Result<(), Error> {
 
         let delay = std::time::Duration::from_secs(10);
         loop {
-            dir.sync_pull().await?;
+            match &sampled_inputs {
+                Some(sampled) => sampled.sync_pull().await?,
+                None => dir.sync_pull().await?,
+            }
             if has_files(&dir.local_path).await? {
                 break;
             }
             delay_with_jitter(delay).await;
         }
     }
-    let monitor_inputs = inputs.monitor_results(new_coverage, false, &jr_client);
+    let monitor_inputs =
+        inputs.monitor_results(new_coverage, false, config.common.task_id, &jr_client);
     let inputs_sync_cancellation = CancellationToken::new(); // never actually cancelled
-    let inputs_sync_task =
-        inputs.continuous_sync(Pull, config.ensemble_sync_delay, &inputs_sync_cancellation);
-
-    let process = start_supervisor(
-        &runtime_dir.path(),
-        &config,
-        &crashes,
-        crashdump_dir.as_ref(),
-        &inputs,
-        reports_dir.path().to_path_buf(),
-    )
-    .await?;
+    let inputs_sync_task = async {
+        let sync = async {
+            match &sampled_inputs {
+                Some(sampled) => {
+                    continuous_sync_pull(std::slice::from_ref(sampled), config.ensemble_sync_delay)
+                        .await
+                }
+                None => {
+                    inputs
+                        .continuous_sync(
+                            Pull,
+                            config.ensemble_sync_delay,
+                            &inputs_sync_cancellation,
+                        )
+                        .await
+                }
+            }
+        };
+        let snapshots = async {
+            match &config.corpus_snapshots {
+                Some(snapshots) => continuous_snapshot(&inputs.local_path, snapshots).await,
+                None => Ok(()),
+            }
+        };
+
+        futures::try_join!(sync, snapshots).map(|_| ())
+    };
+
+    let afl_instances = config.afl.as_ref().map(afl::AflConfig::instances);
+    let afl_dir = runtime_dir.path().join("afl");
+    let afl_staging_dir = runtime_dir.path().join("afl-staging");
+    let honggfuzz_dir = runtime_dir.path().join("honggfuzz");
+    let honggfuzz_staging_dir = runtime_dir.path().join("honggfuzz-staging");
+
+    let processes = if let Some(instances) = &afl_instances {
+        start_afl(
+            &runtime_dir.path(),
+            &config,
+            instances,
+            &afl_dir,
+            &crashes,
+            crashdump_dir.as_ref(),
+            &inputs,
+            reports_dir.path().to_path_buf(),
+        )
+        .await?
+    } else if let Some(honggfuzz) = &config.honggfuzz {
+        let process = start_honggfuzz(
+            &runtime_dir.path(),
+            &config,
+            honggfuzz,
+            &honggfuzz_dir,
+            &crashes,
+            crashdump_dir.as_ref(),
+            &inputs,
+            reports_dir.path().to_path_buf(),
+        )
+        .await?;
+        vec![process]
+    } else {
+        let process = start_supervisor(
+            &runtime_dir.path(),
+            &config,
+            &crashes,
+            crashdump_dir.as_ref(),
+            &inputs,
+            reports_dir.path().to_path_buf(),
+        )
+        .await?;
+        vec![process]
+    };
+
+    let pids: Vec<_> = processes.iter().filter_map(Child::id).collect();
 
     let stopped = Notify::new();
     let monitor_supervisor =
-        monitor_process(process, "supervisor".to_string(), true, Some(&stopped));
+        try_join_all(processes.into_iter().map(|process| {
+            monitor_process(process, "supervisor".to_string(), true, Some(&stopped))
+        }));
     let hb = config.common.init_heartbeat(None).await?;
 
-    let heartbeat_process = heartbeat_process(&stopped, hb);
-
-    let monitor_path = if let Some(stats_file) = &config.stats_file {
-        Some(
-            Expand::new(&config.common.machine_identity)
-                .machine_id()
-                .runtime_dir(runtime_dir.path())
-                .evaluate_value(stats_file)?,
+    let heartbeat_process = heartbeat_process(&stopped, &hb);
+
+    let sample_process_stats =
+        try_join_all(pids.into_iter().map(|pid| {
+            monitor_process_stats(pid, &config.common.process_stats, hb.as_ref(), None)
+        }));
+
+    let monitor_paths = if let Some(instances) = &afl_instances {
+        instances
+            .iter()
+            .map(|instance| {
+                let path = instance.stats_file(&afl_dir);
+                (
+                    Some(path.to_string_lossy().into_owned()),
+                    Some(StatsFormat::Afl),
+                )
+            })
+            .collect()
+    } else if config.honggfuzz.is_some() {
+        let path = honggfuzz::stats_file(&honggfuzz_dir);
+        vec![(
+            Some(path.to_string_lossy().into_owned()),
+            Some(StatsFormat::Honggfuzz),
+        )]
+    } else if let Some(stats_file) = &config.stats_file {
+        let path = supervisor_expand(
+            runtime_dir.path(),
+            &config,
+            &crashes,
+            crashdump_dir.as_ref(),
+            &inputs,
+            reports_dir.path().to_path_buf(),
+            &None,
         )
+        .evaluate_value(stats_file)?;
+        vec![(Some(path), config.stats_format.clone())]
     } else {
         debug!("no stats file to monitor");
-        None
+        vec![]
     };
 
-    let monitor_stats = monitor_stats(monitor_path, config.stats_format);
+    let monitor_stats =
+        try_join_all(monitor_paths.into_iter().map(|(path, format)| {
+            monitor_stats(path, format, &config.stats_keys, &hb, &jr_client)
+        }));
+
+    let afl_outputs = afl_instances.as_ref().map(|instances| afl::Outputs {
+        output_dir: &afl_dir,
+        instances,
+        crashes: &crashes.local_path,
+        inputs: &inputs.local_path,
+        staging: &afl_staging_dir,
+    });
+    let monitor_afl_outputs = async {
+        if let Some(outputs) = &afl_outputs {
+            outputs.monitor().await
+        } else {
+            Ok(())
+        }
+    };
+
+    // Reports are only written if they are to be uploaded.
+    let honggfuzz_reports_dir =
+        (config.reports.is_some() || config.unique_reports.is_some()).then_some(reports_dir.path());
+    let honggfuzz_outputs = match (&config.honggfuzz, &config.target_exe) {
+        (Some(_), Some(target_exe)) => Some(honggfuzz::Outputs {
+            workspace: &honggfuzz_dir,
+            crashes: &crashes,
+            staging: &honggfuzz_staging_dir,
+            reports_dir: honggfuzz_reports_dir,
+            target_exe,
+            task_id: config.common.task_id,
+            job_id: config.common.job_id,
+        }),
+        _ => None,
+    };
+    let monitor_honggfuzz_outputs = async {
+        if let Some(outputs) = &honggfuzz_outputs {
+            outputs.monitor().await
+        } else {
+            Ok(())
+        }
+    };
 
     futures::try_join!(
         heartbeat_process.map_err(|e| e.context("Failure in heartbeat")),
         monitor_supervisor.map_err(|e| e.context("Failure in monitor_supervisor")),
+        sample_process_stats.map_err(|e| e.context("Failure in sample_process_stats")),
         monitor_stats.map_err(|e| e.context("Failure in monitor_stats")),
         monitor_crashes.map_err(|e| e.context("Failure in monitor_crashes")),
         monitor_crashdumps.map_err(|e| e.context("Failure in monitor_crashdumps")),
@@ -210,6 +399,8 @@ pub async fn spawn(config: SupervisorConfig) -> Result<(), Error> {
         inputs_sync_task.map_err(|e| e.context("Failure in continuous_sync_task")),
         monitor_reports_future.map_err(|e| e.context("Failure in monitor_reports_future")),
         monitor_coverage_future.map_err(|e| e.context("Failure in monitor_coverage_future")),
+        monitor_afl_outputs.map_err(|e| e.context("Failure in monitor_afl_outputs")),
+        monitor_honggfuzz_outputs.map_err(|e| e.context("Failure in monitor_honggfuzz_outputs")),
     )?;
 
     Ok(())
@@ -230,7 +421,7 @@ async fn monitor_coverage(
 
 async fn heartbeat_process(
     stopped: &Notify,
-    heartbeat_client: Option<TaskHeartbeatClient>,
+    heartbeat_client: &Option<TaskHeartbeatClient>,
 ) -> Result<()> {
     while !stopped.is_notified(HEARTBEAT_PERIOD).await {
         heartbeat_client.alive();
@@ -252,11 +443,208 @@ async fn start_supervisor(
         None
     };
 
-    let expand = Expand::new(&config.common.machine_identity)
+    let expand = supervisor_expand(
+        runtime_dir.as_ref(),
+        config,
+        crashes,
+        crashdumps,
+        inputs,
+        reports_dir,
+        &target_exe,
+    );
+
+    let supervisor_path = expand.evaluate_value(&config.supervisor_exe)?;
+    let mut cmd = Command::new(supervisor_path);
+    let cmd = cmd
+        .kill_on_drop(true)
+        .env_remove("RUST_LOG")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    let args = expand.evaluate(&config.supervisor_options)?;
+    cmd.args(&args);
+
+    for (k, v) in &config.supervisor_env {
+        cmd.env(k, expand.evaluate_value(v)?);
+    }
+
+    info!("starting supervisor '{:?}'", cmd);
+    let child = cmd
+        .spawn()
+        .with_context(|| format!("supervisor failed to start: {cmd:?}"))?;
+    Ok(child)
+}
+
+/// Start one `afl-fuzz` process per instance, all sharing `output_dir`.
+async fn start_afl(
+    runtime_dir: impl AsRef<Path>,
+    config: &SupervisorConfig,
+    instances: &[afl::Instance],
+    output_dir: &Path,
+    crashes: &SyncedDir,
+    crashdumps: Option<&SyncedDir>,
+    inputs: &SyncedDir,
+    reports_dir: PathBuf,
+) -> Result<Vec<Child>> {
+    let Some(target_exe) = &config.target_exe else {
+        bail!("`target_exe` is required to run AFL");
+    };
+    let target_exe = try_resolve_setup_relative_path(&config.common.setup_dir, target_exe).await?;
+
+    let expand = supervisor_expand(
+        runtime_dir.as_ref(),
+        config,
+        crashes,
+        crashdumps,
+        inputs,
+        reports_dir,
+        &Some(target_exe.clone()),
+    )
+    .set_optional(
+        config
+            .supervisor_input_marker
+            .is_none()
+            .then_some(afl::INPUT_MARKER),
+        Expand::input_marker,
+    );
+
+    let supervisor_path = expand.evaluate_value(&config.supervisor_exe)?;
+    let mut options = expand.evaluate(&config.supervisor_options)?;
+    if config.common.qemu_user.is_some() {
+        // AFL++'s QEMU mode runs the target with its own build of QEMU,
+        // `afl-qemu-trace`, to record coverage.
+        options.insert(0, afl::QEMU_MODE.to_owned());
+    }
+    let target_options = expand.evaluate(config.target_options.as_deref().unwrap_or_default())?;
+
+    tokio::fs::create_dir_all(output_dir).await?;
+
+    let mut children = vec![];
+    for instance in instances {
+        let mut cmd = Command::new(&supervisor_path);
+        let cmd = cmd
+            .kill_on_drop(true)
+            .env_remove("RUST_LOG")
+            // The status screen needs a terminal.
+            .env("AFL_NO_UI", "1")
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        let sysroot = config
+            .common
+            .qemu_user
+            .as_ref()
+            .and_then(|q| q.sysroot.as_ref());
+        if let Some(sysroot) = sysroot {
+            cmd.env(QEMU_LD_PREFIX, sysroot);
+        }
+
+        cmd.args(instance.args(
+            &inputs.local_path,
+            output_dir,
+            &options,
+            &target_exe,
+            &target_options,
+        ));
+
+        for (k, v) in &config.supervisor_env {
+            cmd.env(k, expand.evaluate_value(v)?);
+        }
+
+        info!("starting AFL instance {} '{:?}'", instance.name, cmd);
+        let child = cmd
+            .spawn()
+            .with_context(|| format!("AFL instance failed to start: {cmd:?}"))?;
+        children.push(child);
+    }
+
+    Ok(children)
+}
+
+/// Start `honggfuzz`, with `workspace` as its workspace.
+async fn start_honggfuzz(
+    runtime_dir: impl AsRef<Path>,
+    config: &SupervisorConfig,
+    honggfuzz: &honggfuzz::HonggfuzzConfig,
+    workspace: &Path,
+    crashes: &SyncedDir,
+    crashdumps: Option<&SyncedDir>,
+    inputs: &SyncedDir,
+    reports_dir: PathBuf,
+) -> Result<Child> {
+    let Some(target_exe) = &config.target_exe else {
+        bail!("`target_exe` is required to run honggfuzz");
+    };
+    let target_exe = try_resolve_setup_relative_path(&config.common.setup_dir, target_exe).await?;
+
+    let expand = supervisor_expand(
+        runtime_dir.as_ref(),
+        config,
+        crashes,
+        crashdumps,
+        inputs,
+        reports_dir,
+        &Some(target_exe.clone()),
+    )
+    .set_optional(
+        config
+            .supervisor_input_marker
+            .is_none()
+            .then_some(honggfuzz::INPUT_MARKER),
+        Expand::input_marker,
+    );
+
+    let supervisor_path = expand.evaluate_value(&config.supervisor_exe)?;
+    let options = expand.evaluate(&config.supervisor_options)?;
+    let target_options = expand.evaluate(config.target_options.as_deref().unwrap_or_default())?;
+
+    tokio::fs::create_dir_all(workspace).await?;
+
+    let mut cmd = Command::new(supervisor_path);
+    let cmd = cmd
+        .kill_on_drop(true)
+        .env_remove("RUST_LOG")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    cmd.args(honggfuzz.args(
+        &inputs.local_path,
+        workspace,
+        &options,
+        &target_exe,
+        &target_options,
+    ));
+
+    for (k, v) in &config.supervisor_env {
+        cmd.env(k, expand.evaluate_value(v)?);
+    }
+
+    info!("starting honggfuzz '{:?}'", cmd);
+    let child = cmd
+        .spawn()
+        .with_context(|| format!("honggfuzz failed to start: {cmd:?}"))?;
+    Ok(child)
+}
+
+fn supervisor_expand<'a>(
+    runtime_dir: &Path,
+    config: &'a SupervisorConfig,
+    crashes: &SyncedDir,
+    crashdumps: Option<&SyncedDir>,
+    inputs: &SyncedDir,
+    reports_dir: PathBuf,
+    target_exe: &Option<PathBuf>,
+) -> Expand<'a> {
+    config
+        .common
+        .expand()
         .machine_id()
         .supervisor_exe(&config.supervisor_exe)
         .supervisor_options(&config.supervisor_options)
-        .runtime_dir(&runtime_dir)
+        .runtime_dir(runtime_dir)
         .crashes(&crashes.local_path)
         .set_optional_ref(&crashdumps, |expand, crashdumps| {
             expand.crashdumps(&crashdumps.local_path)
@@ -276,7 +664,7 @@ async fn start_supervisor(
         .set_optional_ref(&config.coverage, |expand, coverage| {
             expand.coverage_dir(&coverage.local_path)
         })
-        .set_optional_ref(&target_exe, |expand, target_exe| {
+        .set_optional_ref(target_exe, |expand, target_exe| {
             expand.target_exe(target_exe)
         })
         .set_optional_ref(&config.supervisor_input_marker, |expand, input_marker| {
@@ -302,29 +690,7 @@ async fn start_supervisor(
                 .clone()
                 .and_then(|u| u.container()),
             |expand, container| expand.crashes_container(container),
-        );
-
-    let supervisor_path = expand.evaluate_value(&config.supervisor_exe)?;
-    let mut cmd = Command::new(supervisor_path);
-    let cmd = cmd
-        .kill_on_drop(true)
-        .env_remove("RUST_LOG")
-        .stdin(Stdio::null())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped());
-
-    let args = expand.evaluate(&config.supervisor_options)?;
-    cmd.args(&args);
-
-    for (k, v) in &config.supervisor_env {
-        cmd.env(k, expand.evaluate_value(v)?);
-    }
-
-    info!("starting supervisor '{:?}'", cmd);
-    let child = cmd
-        .spawn()
-        .with_context(|| format!("supervisor failed to start: {cmd:?}"))?;
-    Ok(child)
+        )
 }
 
 #[cfg(test)]
@@ -442,11 +808,16 @@ mod tests {
             wait_for_files: None,
             stats_file: None,
             stats_format: None,
+            stats_keys: Default::default(),
             ensemble_sync_delay: None,
+            max_initial_corpus: None,
+            corpus_snapshots: None,
             reports: None,
             unique_reports: None,
             no_repro: None,
             coverage: None,
+            afl: None,
+            honggfuzz: None,
             common: CommonConfig {
                 job_id: Default::default(),
                 task_id: Default::default(),
@@ -466,6 +837,14 @@ mod tests {
                     scaleset_name: None,
                 },
                 tags: Default::default(),
+                allow_unknown_placeholders: Default::default(),
+                allow_env_placeholders: Default::default(),
+                target_options_shell: Default::default(),
+                qemu_user: None,
+                symbol_servers: vec![],
+                symbol_cache: None,
+                process_stats: Default::default(),
+                unknown_fields: Default::default(),
                 from_agent_to_task_endpoint: "/".to_string(),
                 from_task_to_agent_endpoint: "/".to_string(),
             },
@@ -0,0 +1,469 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Running AFL++ as the supervisor of a `generic_supervisor` task.
+//!
+//! Instead of a hand-written `supervisor_options`, the `afl-fuzz` command line
+//! is built from the task's `target_exe` and `target_options`. Each instance
+//! writes its findings to `<output>/<instance>/{crashes,queue}`, next to its
+//! `fuzzer_stats`. New crashes and queue entries are copied into the task's
+//! crashes and inputs directories, prefixed with the instance name, so that
+//! they are synced to their containers without AFL's own bookkeeping files.
+//! afl-fuzz writes findings in place, so they are only copied once their size
+//! and modification time are unchanged between two scans.
+
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsString,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Context, Result};
+use onefuzz::jitter::delay_with_jitter;
+use serde::Deserialize;
+use tokio::fs;
+
+/// Input marker which afl-fuzz replaces with the path of the input file.
+pub const INPUT_MARKER: &str = "@@";
+
+/// Flag for afl-fuzz to run a binary-only target under QEMU.
+pub const QEMU_MODE: &str = "-Q";
+
+const COLLECT_DELAY: Duration = Duration::from_secs(10);
+
+/// Written by afl-fuzz to every crashes directory, to explain its contents.
+const CRASHES_README: &str = "README.txt";
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct AflConfig {
+    /// Number of afl-fuzz instances to run: one main instance, and the rest
+    /// secondary. Defaults to the number of CPUs on the node.
+    pub workers: Option<usize>,
+}
+
+impl AflConfig {
+    pub fn instances(&self) -> Vec<Instance> {
+        let workers = self.workers.unwrap_or_else(|| {
+            std::thread::available_parallelism()
+                .map(usize::from)
+                .unwrap_or(1)
+        });
+
+        instances(workers)
+    }
+}
+
+/// A single afl-fuzz process.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Instance {
+    /// Name of the instance's directory within the output directory.
+    pub name: String,
+    mode: Vec<String>,
+}
+
+fn instances(workers: usize) -> Vec<Instance> {
+    if workers <= 1 {
+        // Without `-M` or `-S`, afl-fuzz names its instance `default`.
+        return vec![Instance {
+            name: "default".to_owned(),
+            mode: vec![],
+        }];
+    }
+
+    (0..workers)
+        .map(|i| {
+            let (flag, name) = if i == 0 {
+                ("-M", "main".to_owned())
+            } else {
+                ("-S", format!("secondary_{i}"))
+            };
+
+            Instance {
+                mode: vec![flag.to_owned(), name.clone()],
+                name,
+            }
+        })
+        .collect()
+}
+
+impl Instance {
+    /// Arguments to afl-fuzz for this instance.
+    ///
+    /// `options` are extra arguments to afl-fuzz itself, and `target_options`
+    /// are passed to the target as is.
+    pub fn args(
+        &self,
+        input_corpus: &Path,
+        output_dir: &Path,
+        options: &[String],
+        target_exe: &Path,
+        target_options: &[String],
+    ) -> Vec<OsString> {
+        let mut args: Vec<OsString> = vec![
+            "-i".into(),
+            input_corpus.into(),
+            "-o".into(),
+            output_dir.into(),
+        ];
+        args.extend(self.mode.iter().map(OsString::from));
+        args.extend(options.iter().map(OsString::from));
+        args.push("--".into());
+        args.push(target_exe.into());
+        args.extend(target_options.iter().map(OsString::from));
+        args
+    }
+
+    pub fn stats_file(&self, output_dir: &Path) -> PathBuf {
+        output_dir.join(&self.name).join("fuzzer_stats")
+    }
+}
+
+/// Where the findings of every instance are copied.
+pub struct Outputs<'a> {
+    pub output_dir: &'a Path,
+    pub instances: &'a [Instance],
+    pub crashes: &'a Path,
+    pub inputs: &'a Path,
+
+    /// Directory outside of `crashes` and `inputs`, on the same volume, to
+    /// copy to before moving files into place. Otherwise, the sync of those
+    /// directories could see a partially copied file.
+    pub staging: &'a Path,
+}
+
+/// The findings already copied, and those seen but not yet settled.
+#[derive(Debug, Default)]
+pub struct Collected {
+    copied: HashSet<PathBuf>,
+
+    // Size and modification time of findings as of the scan which found them.
+    pending: HashMap<PathBuf, (u64, Option<SystemTime>)>,
+}
+
+impl<'a> Outputs<'a> {
+    /// Copy new findings until the task is stopped.
+    pub async fn monitor(&self) -> Result<()> {
+        fs::create_dir_all(self.staging).await?;
+
+        let mut collected = Collected::default();
+        loop {
+            self.collect(&mut collected).await?;
+            delay_with_jitter(COLLECT_DELAY).await;
+        }
+    }
+
+    /// Copy any settled findings not already in `collected`, and add them to
+    /// it.
+    ///
+    /// A finding is settled if it is unchanged since the previous call, so a
+    /// new one is copied by the next call after it is found.
+    pub async fn collect(&self, collected: &mut Collected) -> Result<()> {
+        for instance in self.instances {
+            let instance_dir = self.output_dir.join(&instance.name);
+
+            self.collect_dir(
+                instance,
+                &instance_dir.join("crashes"),
+                self.crashes,
+                collected,
+            )
+            .await?;
+            self.collect_dir(
+                instance,
+                &instance_dir.join("queue"),
+                self.inputs,
+                collected,
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn collect_dir(
+        &self,
+        instance: &Instance,
+        src: &Path,
+        dst: &Path,
+        collected: &mut Collected,
+    ) -> Result<()> {
+        // Not created until afl-fuzz has started.
+        let Ok(mut entries) = fs::read_dir(src).await else {
+            return Ok(());
+        };
+
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if collected.copied.contains(&path) || !entry.file_type().await?.is_file() {
+                continue;
+            }
+
+            let name = entry.file_name();
+            if !is_finding(&name.to_string_lossy()) {
+                continue;
+            }
+
+            // Wait for afl-fuzz to finish writing the file.
+            let metadata = entry.metadata().await?;
+            let state = (metadata.len(), metadata.modified().ok());
+            if collected.pending.get(&path) != Some(&state) {
+                collected.pending.insert(path, state);
+                continue;
+            }
+
+            let mut dst_name = OsString::from(format!("{}-", instance.name));
+            dst_name.push(&name);
+
+            let staged = self.staging.join(&dst_name);
+            fs::copy(&path, &staged)
+                .await
+                .with_context(|| format!("unable to copy AFL output: {}", path.display()))?;
+            fs::rename(&staged, dst.join(&dst_name)).await?;
+
+            collected.pending.remove(&path);
+            collected.copied.insert(path);
+        }
+
+        Ok(())
+    }
+}
+
+// Whether an entry of a `crashes` or `queue` directory was found by afl-fuzz.
+//
+// Seeds are copied into the queue as `id:...,orig:<name>`, and are already in
+// the inputs container.
+fn is_finding(name: &str) -> bool {
+    name != CRASHES_README && !name.starts_with('.') && !name.contains(",orig:")
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+
+    fn strings(args: Vec<OsString>) -> Vec<String> {
+        args.into_iter()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_single_instance_args() {
+        let instances = instances(1);
+        assert_eq!(instances.len(), 1);
+        assert_eq!(instances[0].name, "default");
+
+        let args = instances[0].args(
+            Path::new("inputs"),
+            Path::new("afl"),
+            &["-m".to_owned(), "none".to_owned()],
+            Path::new("/setup/fuzz"),
+            &[INPUT_MARKER.to_owned(), "-x y".to_owned()],
+        );
+
+        assert_eq!(
+            strings(args),
+            [
+                "-i",
+                "inputs",
+                "-o",
+                "afl",
+                "-m",
+                "none",
+                "--",
+                "/setup/fuzz",
+                "@@",
+                "-x y"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_multiple_instance_args() {
+        let instances = instances(3);
+        let names: Vec<_> = instances.iter().map(|i| i.name.as_str()).collect();
+        assert_eq!(names, ["main", "secondary_1", "secondary_2"]);
+
+        let args: Vec<_> = instances
+            .iter()
+            .map(|instance| {
+                strings(instance.args(
+                    Path::new("inputs"),
+                    Path::new("afl"),
+                    &[],
+                    Path::new("fuzz"),
+                    &[],
+                ))
+            })
+            .collect();
+
+        assert_eq!(
+            args,
+            [
+                ["-i", "inputs", "-o", "afl", "-M", "main", "--", "fuzz"],
+                [
+                    "-i",
+                    "inputs",
+                    "-o",
+                    "afl",
+                    "-S",
+                    "secondary_1",
+                    "--",
+                    "fuzz"
+                ],
+                [
+                    "-i",
+                    "inputs",
+                    "-o",
+                    "afl",
+                    "-S",
+                    "secondary_2",
+                    "--",
+                    "fuzz"
+                ],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_workers() {
+        assert_eq!(instances(0), instances(1));
+        assert_eq!(
+            AflConfig { workers: Some(4) }.instances().len(),
+            4,
+            "explicit worker count"
+        );
+        assert!(!AflConfig::default().instances().is_empty());
+    }
+
+    #[test]
+    fn test_stats_file() {
+        let instance = &instances(2)[1];
+        assert_eq!(
+            instance.stats_file(Path::new("afl")),
+            Path::new("afl").join("secondary_1").join("fuzzer_stats")
+        );
+    }
+
+    #[test]
+    fn test_is_finding() {
+        assert!(is_finding(
+            "id:000000,sig:11,src:000000,time:12,op:havoc,rep:4"
+        ));
+        assert!(is_finding(
+            "id:000003,src:000001,time:40,op:flip1,pos:0,+cov"
+        ));
+        assert!(!is_finding("README.txt"));
+        assert!(!is_finding(".state"));
+        assert!(!is_finding("id:000000,time:0,execs:0,orig:seed.txt"));
+    }
+
+    // `:` is not allowed in Windows file names.
+    #[cfg(target_family = "unix")]
+    #[tokio::test]
+    async fn test_collect() -> Result<()> {
+        let afl = tempdir()?;
+        let crashes = tempdir()?;
+        let inputs = tempdir()?;
+        let staging = tempdir()?;
+
+        let instances = instances(2);
+        for (dir, name) in [
+            ("main/crashes", "README.txt"),
+            ("main/crashes", "id:000000,sig:11"),
+            ("main/queue", "id:000000,time:0,execs:0,orig:seed"),
+            ("main/queue", "id:000001,src:000000,+cov"),
+            ("main/queue/.state/auto_extras", "auto_000000"),
+            ("secondary_1/crashes", "id:000000,sig:06"),
+            ("main/hangs", "id:000000,src:000000"),
+        ] {
+            let dir = afl.path().join(dir);
+            fs::create_dir_all(&dir).await?;
+            fs::write(dir.join(name), name).await?;
+        }
+
+        let outputs = Outputs {
+            output_dir: afl.path(),
+            instances: &instances,
+            crashes: crashes.path(),
+            inputs: inputs.path(),
+            staging: staging.path(),
+        };
+
+        // Findings are copied by the scan after the one which found them.
+        let mut collected = Collected::default();
+        outputs.collect(&mut collected).await?;
+        assert!(list(crashes.path()).await?.is_empty());
+        assert!(list(inputs.path()).await?.is_empty());
+
+        outputs.collect(&mut collected).await?;
+        assert_eq!(
+            list(crashes.path()).await?,
+            ["main-id:000000,sig:11", "secondary_1-id:000000,sig:06"]
+        );
+        assert_eq!(
+            list(inputs.path()).await?,
+            ["main-id:000001,src:000000,+cov"]
+        );
+        assert!(list(staging.path()).await?.is_empty());
+
+        // Files are only copied once, even if removed after syncing.
+        fs::remove_file(crashes.path().join("main-id:000000,sig:11")).await?;
+        outputs.collect(&mut collected).await?;
+        assert_eq!(
+            list(crashes.path()).await?,
+            ["secondary_1-id:000000,sig:06"]
+        );
+
+        Ok(())
+    }
+
+    #[cfg(target_family = "unix")]
+    #[tokio::test]
+    async fn test_collect_waits_for_writes() -> Result<()> {
+        let afl = tempdir()?;
+        let crashes = tempdir()?;
+        let inputs = tempdir()?;
+        let staging = tempdir()?;
+
+        let instances = instances(1);
+        let queue = afl.path().join("default/queue");
+        fs::create_dir_all(&queue).await?;
+        let finding = queue.join("id:000000,src:000000,+cov");
+        fs::write(&finding, "partial").await?;
+
+        let outputs = Outputs {
+            output_dir: afl.path(),
+            instances: &instances,
+            crashes: crashes.path(),
+            inputs: inputs.path(),
+            staging: staging.path(),
+        };
+
+        let mut collected = Collected::default();
+        outputs.collect(&mut collected).await?;
+
+        // Still being written when scanned again.
+        fs::write(&finding, "partial, then complete").await?;
+        outputs.collect(&mut collected).await?;
+        assert!(list(inputs.path()).await?.is_empty());
+
+        outputs.collect(&mut collected).await?;
+        let copied = inputs.path().join("default-id:000000,src:000000,+cov");
+        assert_eq!(fs::read(copied).await?, b"partial, then complete");
+
+        Ok(())
+    }
+
+    async fn list(dir: &Path) -> Result<Vec<String>> {
+        let mut names = vec![];
+        let mut entries = fs::read_dir(dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            names.push(entry.file_name().to_string_lossy().into_owned());
+        }
+        names.sort();
+        Ok(names)
+    }
+}
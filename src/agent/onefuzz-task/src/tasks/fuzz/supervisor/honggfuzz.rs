@@ -0,0 +1,597 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Running honggfuzz as the supervisor of a `generic_supervisor` task.
+//!
+//! Instead of a hand-written `supervisor_options`, the `honggfuzz` command
+//! line is built from the task's `target_exe` and `target_options`. honggfuzz
+//! saves new inputs to the inputs directory, and crashes to its workspace,
+//! where it also appends a report of each crash to `HONGGFUZZ.REPORT.TXT`.
+//! Crashes are copied from the workspace into the task's crashes directory,
+//! and the report of each is written to the reports directory as a crash
+//! report, classified by the signal the target died of.
+
+use std::{
+    collections::HashSet,
+    ffi::OsString,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use coverage::path_map::PathMap;
+use onefuzz::{jitter::delay_with_jitter, sha256, syncdir::SyncedDir};
+use serde::Deserialize;
+use stacktrace_parser::{CrashLog, StackEntry, StackNormalization};
+use tokio::fs;
+use uuid::Uuid;
+
+use crate::tasks::report::crash_report::{CrashReport, InputBlob};
+
+/// Input marker which honggfuzz replaces with the path of the input file.
+pub const INPUT_MARKER: &str = "___FILE___";
+
+/// Written by honggfuzz to its workspace, with a section for each crash.
+pub const REPORT_FILE: &str = "HONGGFUZZ.REPORT.TXT";
+
+/// Written to the workspace, as passed with `--statsfile`.
+const STATS_FILE: &str = "honggfuzz.stats";
+
+/// Extension of the crashes saved in the workspace.
+const CRASH_EXTENSION: &str = "fuzz";
+
+/// Directory within the reports directory, to write reports to before moving
+/// them into place.
+const REPORTS_STAGING: &str = ".honggfuzz";
+
+const TOOL_NAME: &str = "honggfuzz";
+
+const COLLECT_DELAY: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct HonggfuzzConfig {
+    /// Number of fuzzing threads, passed as `--threads`. Defaults to that of
+    /// honggfuzz, which is half the number of CPUs on the node.
+    pub threads: Option<usize>,
+}
+
+impl HonggfuzzConfig {
+    /// Arguments to honggfuzz.
+    ///
+    /// `options` are extra arguments to honggfuzz itself, and `target_options`
+    /// are passed to the target as is.
+    pub fn args(
+        &self,
+        input_corpus: &Path,
+        workspace: &Path,
+        options: &[String],
+        target_exe: &Path,
+        target_options: &[String],
+    ) -> Vec<OsString> {
+        let mut args: Vec<OsString> = vec![
+            "-i".into(),
+            input_corpus.into(),
+            "-W".into(),
+            workspace.into(),
+            "--statsfile".into(),
+            stats_file(workspace).into(),
+        ];
+        if let Some(threads) = self.threads {
+            args.push("--threads".into());
+            args.push(threads.to_string().into());
+        }
+        args.extend(options.iter().map(OsString::from));
+        args.push("--".into());
+        args.push(target_exe.into());
+        args.extend(target_options.iter().map(OsString::from));
+        args
+    }
+}
+
+pub fn stats_file(workspace: &Path) -> PathBuf {
+    workspace.join(STATS_FILE)
+}
+
+/// The report of a single crash, from `HONGGFUZZ.REPORT.TXT`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct CrashSection {
+    /// The section, from its `CRASH:` line.
+    pub text: String,
+
+    pub description: Option<String>,
+
+    /// Path of the crashing input, as saved in the workspace.
+    pub fuzz_fname: String,
+
+    /// Name of the signal the target died of, such as `SIGSEGV`.
+    pub signal: Option<String>,
+
+    pub signal_number: Option<i32>,
+    pub pc: Option<u64>,
+    pub fault_address: Option<u64>,
+    pub instruction: Option<String>,
+    pub stack_hash: Option<String>,
+    pub stack: Vec<StackEntry>,
+}
+
+/// Parse the crash sections of a honggfuzz report. Other sections, such as
+/// the fuzzer arguments, and any partially written last section are skipped.
+pub fn parse_report(text: &str) -> Vec<CrashSection> {
+    let mut sections = vec![];
+    let mut current: Option<(CrashSection, Vec<&str>)> = None;
+    let mut in_stack = false;
+
+    for line in text.lines() {
+        if line == "CRASH:" {
+            current = Some((CrashSection::default(), vec![line]));
+            in_stack = false;
+            continue;
+        }
+
+        let Some((section, lines)) = &mut current else {
+            continue;
+        };
+
+        if line.starts_with("=====") {
+            let (mut section, lines) = current.take().unwrap();
+            if !section.fuzz_fname.is_empty() {
+                section.text = lines.join("\n");
+                sections.push(section);
+            }
+            continue;
+        }
+
+        lines.push(line);
+
+        if in_stack {
+            if let Some(entry) = parse_stack_entry(line) {
+                section.stack.push(entry);
+            }
+            continue;
+        }
+
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        let non_empty = || (!value.is_empty()).then(|| value.to_owned());
+
+        match name {
+            "DESCRIPTION" => section.description = non_empty(),
+            "FUZZ_FNAME" => section.fuzz_fname = value.to_owned(),
+            "SIGNAL" => {
+                // Such as `SIGSEGV (11)`.
+                let (signal, number) = match value.split_once(' ') {
+                    Some((signal, number)) => (signal, Some(number)),
+                    None => (value, None),
+                };
+                section.signal = Some(signal.to_owned());
+                section.signal_number =
+                    number.and_then(|n| n.strip_prefix('(')?.strip_suffix(')')?.parse().ok());
+            }
+            "PC" => section.pc = parse_hex(value),
+            "FAULT ADDRESS" => section.fault_address = parse_hex(value),
+            "INSTRUCTION" => section.instruction = non_empty(),
+            "STACK HASH" => section.stack_hash = non_empty(),
+            "STACK" => in_stack = true,
+            _ => {}
+        }
+    }
+
+    sections
+}
+
+// An address, such as `0x5555555551a9`. A null address is `(nil)`.
+fn parse_hex(value: &str) -> Option<u64> {
+    if value == "(nil)" {
+        return Some(0);
+    }
+
+    u64::from_str_radix(value.strip_prefix("0x")?, 16).ok()
+}
+
+// A frame, such as:
+//
+//  <0x00005555555551a9> [func:LLVMFuzzerTestOneInput file:/src/fuzz.c line:12 module:/setup/fuzz]
+//
+// Unknown fields are empty, or `UNKNOWN` for the function, and `0` for the line.
+fn parse_stack_entry(line: &str) -> Option<StackEntry> {
+    let line = line.trim();
+    let (address, details) = line.strip_prefix('<')?.split_once('>')?;
+    let details = details.trim().strip_prefix('[')?.strip_suffix(']')?;
+
+    let field = |name: &str| {
+        let start = details.find(&format!("{name}:"))? + name.len() + 1;
+        let rest = &details[start..];
+        // Fields are separated by spaces, but there are no spaces before the
+        // first field, nor in the names of the other fields.
+        let end = ["func:", "file:", "line:", "module:"]
+            .iter()
+            .filter_map(|next| rest.find(&format!(" {next}")))
+            .min()
+            .unwrap_or(rest.len());
+        Some(rest[..end].trim()).filter(|value| !value.is_empty())
+    };
+
+    Some(StackEntry {
+        line: line.to_owned(),
+        address: parse_hex(address),
+        function_name: field("func")
+            .filter(|func| *func != "UNKNOWN")
+            .map(str::to_owned),
+        source_file_path: field("file").map(str::to_owned),
+        source_file_name: field("file")
+            .and_then(|file| Path::new(file).file_name())
+            .map(|name| name.to_string_lossy().into_owned()),
+        source_file_line: field("line")
+            .and_then(|line| line.parse().ok())
+            .filter(|line| *line != 0),
+        module_path: field("module").map(str::to_owned),
+        ..StackEntry::default()
+    })
+}
+
+impl CrashSection {
+    /// The name of the crashing input, as copied to the crashes directory.
+    pub fn input_name(&self) -> Option<String> {
+        Path::new(&self.fuzz_fname)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+    }
+
+    pub fn crash_log(&self) -> Result<CrashLog> {
+        let fault_type = self
+            .signal
+            .clone()
+            .unwrap_or_else(|| "unknown signal".to_owned());
+
+        CrashLog::new(
+            Some(self.text.clone()),
+            self.description.clone(),
+            TOOL_NAME.to_owned(),
+            fault_type,
+            None,
+            None,
+            self.stack.clone(),
+        )
+    }
+}
+
+/// Where the crashes and reports of honggfuzz are copied.
+pub struct Outputs<'a> {
+    pub workspace: &'a Path,
+    pub crashes: &'a SyncedDir,
+
+    /// Directory outside of `crashes`, on the same volume, to copy to before
+    /// moving files into place. Otherwise, the sync of `crashes` could see a
+    /// partially copied file.
+    pub staging: &'a Path,
+
+    /// If set, where to write the crash reports.
+    pub reports_dir: Option<&'a Path>,
+
+    pub target_exe: &'a Path,
+    pub task_id: Uuid,
+    pub job_id: Uuid,
+}
+
+/// The outputs already copied.
+#[derive(Debug, Default)]
+pub struct Collected {
+    crashes: HashSet<String>,
+    reports: HashSet<String>,
+}
+
+impl<'a> Outputs<'a> {
+    /// Copy new crashes and reports until the task is stopped.
+    pub async fn monitor(&self) -> Result<()> {
+        fs::create_dir_all(self.staging).await?;
+        if let Some(reports_dir) = self.reports_dir {
+            fs::create_dir_all(reports_dir.join(REPORTS_STAGING)).await?;
+        }
+
+        let mut collected = Collected::default();
+        loop {
+            self.collect(&mut collected).await?;
+            delay_with_jitter(COLLECT_DELAY).await;
+        }
+    }
+
+    /// Copy any crashes and reports not already in `collected`.
+    pub async fn collect(&self, collected: &mut Collected) -> Result<()> {
+        self.collect_crashes(collected).await?;
+
+        if let Some(reports_dir) = self.reports_dir {
+            self.collect_reports(reports_dir, collected).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn collect_crashes(&self, collected: &mut Collected) -> Result<()> {
+        // Not created until honggfuzz has started.
+        let Ok(mut entries) = fs::read_dir(self.workspace).await else {
+            return Ok(());
+        };
+
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if collected.crashes.contains(&name)
+                || path.extension() != Some(CRASH_EXTENSION.as_ref())
+                || !entry.file_type().await?.is_file()
+            {
+                continue;
+            }
+
+            let staged = self.staging.join(&name);
+            fs::copy(&path, &staged)
+                .await
+                .with_context(|| format!("unable to copy honggfuzz crash: {}", path.display()))?;
+            fs::rename(&staged, self.crashes.local_path.join(&name)).await?;
+
+            collected.crashes.insert(name);
+        }
+
+        Ok(())
+    }
+
+    async fn collect_reports(&self, reports_dir: &Path, collected: &mut Collected) -> Result<()> {
+        let Ok(text) = fs::read_to_string(self.workspace.join(REPORT_FILE)).await else {
+            return Ok(());
+        };
+
+        for section in parse_report(&text) {
+            let Some(name) = section.input_name() else {
+                continue;
+            };
+
+            // The report may be appended before the crash is copied.
+            if collected.reports.contains(&name) || !collected.crashes.contains(&name) {
+                continue;
+            }
+
+            let report = self.crash_report(&section, &name).await?;
+            let staged = reports_dir
+                .join(REPORTS_STAGING)
+                .join(format!("{name}.json"));
+            fs::write(&staged, serde_json::to_vec(&report)?).await?;
+            fs::rename(&staged, reports_dir.join(format!("{name}.json"))).await?;
+
+            collected.reports.insert(name);
+        }
+
+        Ok(())
+    }
+
+    async fn crash_report(&self, section: &CrashSection, name: &str) -> Result<CrashReport> {
+        let input_sha256 = sha256::digest_file(self.crashes.local_path.join(name)).await?;
+        let input_blob = self
+            .crashes
+            .remote_path
+            .as_ref()
+            .map(|url| InputBlob::from(url.blob(name)));
+
+        Ok(CrashReport::new(
+            section.crash_log()?,
+            self.task_id,
+            self.job_id,
+            self.target_exe,
+            input_blob,
+            input_sha256,
+            None,
+            &StackNormalization::default(),
+            &PathMap::default(),
+            TOOL_NAME.to_owned(),
+            String::new(),
+            env!("ONEFUZZ_VERSION").to_string(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+
+    fn strings(args: Vec<OsString>) -> Vec<String> {
+        args.into_iter()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_args() {
+        let config = HonggfuzzConfig::default();
+        let args = config.args(
+            Path::new("inputs"),
+            Path::new("workspace"),
+            &["--timeout".to_owned(), "5".to_owned()],
+            Path::new("/setup/fuzz"),
+            &[INPUT_MARKER.to_owned(), "-x y".to_owned()],
+        );
+
+        assert_eq!(
+            strings(args),
+            [
+                "-i",
+                "inputs",
+                "-W",
+                "workspace",
+                "--statsfile",
+                &Path::new("workspace")
+                    .join("honggfuzz.stats")
+                    .to_string_lossy(),
+                "--timeout",
+                "5",
+                "--",
+                "/setup/fuzz",
+                "___FILE___",
+                "-x y"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_args_threads() {
+        let config = HonggfuzzConfig { threads: Some(4) };
+        let args = strings(config.args(
+            Path::new("inputs"),
+            Path::new("workspace"),
+            &[],
+            Path::new("fuzz"),
+            &[],
+        ));
+
+        assert_eq!(args[6..], ["--threads", "4", "--", "fuzz"]);
+    }
+
+    const REPORT: &str = include_str!("../../../../data/honggfuzz-report.txt");
+
+    #[test]
+    fn test_parse_report() -> Result<()> {
+        let sections = parse_report(REPORT);
+        assert_eq!(sections.len(), 2);
+
+        let segv = &sections[0];
+        assert_eq!(
+            segv.input_name().as_deref(),
+            Some("SIGSEGV.PC.5555555551a9.STACK.18a2b4c07c.CODE.1.ADDR.0.INSTR.mov____%eax,(%rdx).fuzz")
+        );
+        assert_eq!(segv.signal.as_deref(), Some("SIGSEGV"));
+        assert_eq!(segv.signal_number, Some(11));
+        assert_eq!(segv.pc, Some(0x5555555551a9));
+        assert_eq!(segv.fault_address, Some(0));
+        assert_eq!(segv.instruction.as_deref(), Some("mov____%eax,(%rdx)"));
+        assert_eq!(segv.stack_hash.as_deref(), Some("00000018a2b4c07c"));
+        assert_eq!(segv.description, None);
+        assert!(segv.text.starts_with("CRASH:\n"));
+        assert!(segv
+            .text
+            .ends_with("module:/lib/x86_64-linux-gnu/libc.so.6]"));
+
+        assert_eq!(segv.stack.len(), 3);
+        let frame = &segv.stack[0];
+        assert_eq!(frame.address, Some(0x5555555551a9));
+        assert_eq!(
+            frame.function_name.as_deref(),
+            Some("LLVMFuzzerTestOneInput")
+        );
+        assert_eq!(frame.source_file_path.as_deref(), Some("/src/fuzz.c"));
+        assert_eq!(frame.source_file_name.as_deref(), Some("fuzz.c"));
+        assert_eq!(frame.source_file_line, Some(12));
+        assert_eq!(frame.module_path.as_deref(), Some("/setup/fuzz"));
+
+        // Unknown fields.
+        let frame = &segv.stack[2];
+        assert_eq!(frame.function_name, None);
+        assert_eq!(frame.source_file_path, None);
+        assert_eq!(frame.source_file_line, None);
+
+        let abort = &sections[1];
+        assert_eq!(abort.signal.as_deref(), Some("SIGABRT"));
+        assert_eq!(abort.signal_number, Some(6));
+        assert_eq!(abort.fault_address, Some(0x1000));
+        assert_eq!(abort.description.as_deref(), Some("assertion failed"));
+
+        let crash_log = abort.crash_log()?;
+        assert_eq!(crash_log.sanitizer, "honggfuzz");
+        assert_eq!(crash_log.fault_type, "SIGABRT");
+        assert_eq!(crash_log.summary, "assertion failed");
+        assert_eq!(crash_log.text.as_deref(), Some(abort.text.as_str()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_partial_report() {
+        // The second crash is still being written.
+        let partial = REPORT.rsplit_once("STACK HASH").unwrap().0;
+        let sections = parse_report(partial);
+        assert_eq!(sections.len(), 1);
+        assert_eq!(sections[0].signal.as_deref(), Some("SIGSEGV"));
+
+        assert!(parse_report("").is_empty());
+    }
+
+    // The crash names are not valid Windows file names.
+    #[cfg(target_family = "unix")]
+    #[tokio::test]
+    async fn test_collect() -> Result<()> {
+        let workspace = tempdir()?;
+        let crashes = tempdir()?;
+        let staging = tempdir()?;
+        let reports = tempdir()?;
+        fs::create_dir_all(reports.path().join(REPORTS_STAGING)).await?;
+
+        let sections = parse_report(REPORT);
+        let segv = sections[0].input_name().unwrap();
+        let abort = sections[1].input_name().unwrap();
+
+        fs::write(workspace.path().join(&segv), "segv").await?;
+        fs::write(workspace.path().join(STATS_FILE), "# unix_time\n").await?;
+        let report_path = workspace.path().join(REPORT_FILE);
+        // Only the first crash is reported so far.
+        fs::write(&report_path, REPORT.rsplit_once("TIME: ").unwrap().0).await?;
+
+        let crashes_dir = SyncedDir {
+            local_path: crashes.path().to_owned(),
+            remote_path: None,
+        };
+        let outputs = Outputs {
+            workspace: workspace.path(),
+            crashes: &crashes_dir,
+            staging: staging.path(),
+            reports_dir: Some(reports.path()),
+            target_exe: Path::new("/setup/fuzz"),
+            task_id: Uuid::new_v4(),
+            job_id: Uuid::new_v4(),
+        };
+
+        let mut collected = Collected::default();
+        outputs.collect(&mut collected).await?;
+        assert_eq!(list(crashes.path()).await?, [segv.clone()]);
+        assert!(list(reports.path()).await?.is_empty());
+
+        // The second crash, and both reports.
+        fs::write(workspace.path().join(&abort), "abort").await?;
+        fs::write(&report_path, REPORT).await?;
+        outputs.collect(&mut collected).await?;
+
+        let mut expected = vec![abort.clone(), segv.clone()];
+        expected.sort();
+        assert_eq!(list(crashes.path()).await?, expected);
+        assert!(list(staging.path()).await?.is_empty());
+
+        let mut reports_list = list(reports.path()).await?;
+        reports_list.retain(|name| name != REPORTS_STAGING);
+        assert_eq!(
+            reports_list,
+            [format!("{abort}.json"), format!("{segv}.json")]
+        );
+
+        let report: CrashReport =
+            serde_json::from_slice(&fs::read(reports.path().join(format!("{segv}.json"))).await?)?;
+        assert_eq!(report.crash_type, "SIGSEGV");
+        assert_eq!(report.input_sha256, sha256::digest(b"segv"));
+        assert_eq!(report.tool_name.as_deref(), Some("honggfuzz"));
+
+        // Files are only copied once, even if removed after syncing.
+        fs::remove_file(crashes.path().join(&segv)).await?;
+        outputs.collect(&mut collected).await?;
+        assert_eq!(list(crashes.path()).await?, [abort]);
+
+        Ok(())
+    }
+
+    async fn list(dir: &Path) -> Result<Vec<String>> {
+        let mut names = vec![];
+        let mut entries = fs::read_dir(dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            names.push(entry.file_name().to_string_lossy().into_owned());
+        }
+        names.sort();
+        Ok(names)
+    }
+}
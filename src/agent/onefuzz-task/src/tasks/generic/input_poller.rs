@@ -1,10 +1,14 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
-use std::{fmt, path::PathBuf};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
 
 use anyhow::{Context, Result};
-use onefuzz::{blob::BlobUrl, jitter::delay_with_jitter, syncdir::SyncedDir};
+use onefuzz::{blob::BlobUrl, jitter::delay_with_jitter, sha256, syncdir::SyncedDir};
+use onefuzz_telemetry::{context::with_entry, EventData};
 use reqwest::Url;
 use tempfile::{tempdir, TempDir};
 use tokio::{fs, time::Duration};
@@ -12,11 +16,58 @@ use tokio::{fs, time::Duration};
 mod callback;
 pub use callback::*;
 
-const POLL_INTERVAL: Duration = Duration::from_secs(10);
+pub const POLL_INTERVAL: Duration = Duration::from_secs(10);
 
 #[cfg(test)]
 mod tests;
 
+/// The URL of the blob of the input at `path`, batch-downloaded from
+/// `to_process`, if it is synced with a container.
+pub fn batch_input_url(to_process: &SyncedDir, path: &Path) -> Result<Option<Url>> {
+    // Compute the file name relative to the synced directory, and thus the
+    // container.
+    let blob_name = {
+        let dir_path = to_process.local_path.canonicalize()?;
+        let input_path = path.canonicalize()?;
+        let dir_relative = input_path.strip_prefix(&dir_path)?;
+        dir_relative.display().to_string()
+    };
+    Ok(to_process.try_url().map(|x| x.blob(blob_name).url()))
+}
+
+// Process `input` with its hash in the telemetry context, so that the events
+// of processing it can be attributed to it.
+async fn process_input(
+    processor: &mut dyn Processor,
+    url: Option<Url>,
+    input: &Path,
+) -> Result<()> {
+    match sha256::digest_file(input).await {
+        Ok(digest) => {
+            with_entry(
+                EventData::InputSha256(digest),
+                processor.process(url, input),
+            )
+            .await
+        }
+        Err(err) => {
+            debug!("unable to hash input {}: {:?}", input.display(), err);
+            processor.process(url, input).await
+        }
+    }
+}
+
+/// Check if the input of `url` was batch-downloaded from `batch_dir`.
+pub fn seen_in_batch(batch_dir: &SyncedDir, url: &Url) -> bool {
+    if let Ok(blob) = BlobUrl::new(url.clone()) {
+        batch_dir.try_url().and_then(|u| u.account()) == blob.account()
+            && batch_dir.try_url().and_then(|u| u.container()) == blob.container()
+            && batch_dir.local_path.join(blob.name()).exists()
+    } else {
+        false
+    }
+}
+
 #[derive(Debug)]
 pub enum State<M> {
     Ready,
@@ -147,18 +198,9 @@ impl<M> InputPoller<M> {
                 path.display()
             );
 
-            // Compute the file name relative to the synced directory, and thus the
-            // container.
-            let blob_name = {
-                let dir_path = to_process.local_path.canonicalize()?;
-                let input_path = path.canonicalize()?;
-                let dir_relative = input_path.strip_prefix(&dir_path)?;
-                dir_relative.display().to_string()
-            };
-            let url = to_process.try_url().map(|x| x.blob(blob_name).url());
-
-            processor
-                .process(url, &path)
+            let url = batch_input_url(to_process, &path)?;
+
+            process_input(processor, url, &path)
                 .await
                 .with_context(|| format!("process input failed: {}", path.display()))?;
         }
@@ -168,13 +210,7 @@ impl<M> InputPoller<M> {
     /// Check if an input was already processed via batch-processing its container.
     pub async fn seen_in_batch(&self, url: &Url) -> Result<bool> {
         let result = if let Some(batch_dir) = &self.batch_dir {
-            if let Ok(blob) = BlobUrl::new(url.clone()) {
-                batch_dir.try_url().and_then(|u| u.account()) == blob.account()
-                    && batch_dir.try_url().and_then(|u| u.container()) == blob.container()
-                    && batch_dir.local_path.join(blob.name()).exists()
-            } else {
-                false
-            }
+            seen_in_batch(batch_dir, url)
         } else {
             false
         };
@@ -299,7 +335,7 @@ impl<M> InputPoller<M> {
             // deleted automatically upon going out of scope.  Keep it in-scope until
             // here.
             (Downloaded(msg, url, input, _download_dir), Process(processor)) => {
-                processor.process(Some(url), &input).await?;
+                process_input(processor, Some(url), &input).await?;
 
                 self.set_state(Processed(msg));
             }
@@ -2,10 +2,11 @@
 // Licensed under the MIT License.
 
 use anyhow::Result;
-use onefuzz::heartbeat::HeartbeatClient;
+use onefuzz::heartbeat::{alert::DeliveryAlert, HeartbeatClient};
+use onefuzz_telemetry::EventData;
 use reqwest::Url;
 use serde::{self, Deserialize, Serialize};
-use std::time::Duration;
+use std::{collections::BTreeMap, sync::Mutex, time::Duration};
 use uuid::Uuid;
 
 #[derive(Debug, Deserialize, Serialize, Hash, Eq, PartialEq, Clone)]
@@ -22,14 +23,140 @@ struct Heartbeat {
     machine_id: Uuid,
     machine_name: String,
     data: Vec<HeartbeatData>,
+
+    /// Metrics of the progress of the task, recorded since the last heartbeat.
+    /// Omitted if none were, as before metrics were sent.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    metrics: BTreeMap<String, f64>,
+}
+
+/// Names of the metrics of tasks, sent with heartbeats.
+///
+/// Counts which only grow are aggregated by their max between heartbeats, and
+/// other metrics, such as rates, by the last value recorded. See
+/// [`Aggregation::of`].
+pub mod metrics {
+    /// Executions of the target per second. Last.
+    pub const EXECS_PER_SEC: &str = "execs_per_sec";
+
+    /// Total executions of the target. Max.
+    pub const EXECS: &str = "execs";
+
+    /// Inputs in the corpus of the fuzzer. Max.
+    pub const CORPUS_COUNT: &str = "corpus_count";
+
+    /// Coverage counters or blocks covered, as counted by the fuzzer or the
+    /// coverage task. Max.
+    pub const COVERED: &str = "covered";
+
+    /// libFuzzer features, or features of the coverage task. Max.
+    pub const FEATURES: &str = "features";
+
+    /// Crashes found by the fuzzer. Max.
+    pub const CRASHES: &str = "crashes";
+
+    /// Percentage of the AFL coverage bitmap set. Last.
+    pub const BITMAP_COVERAGE: &str = "bitmap_coverage";
+
+    /// Fraction of the coverable code covered, of the coverage task. Last.
+    pub const COVERAGE_RATE: &str = "coverage_rate";
+
+    /// Memory used by the fuzzer, in MiB. Last.
+    pub const RSS_MB: &str = "rss_mb";
+
+    /// Resident memory of the target process tree, in bytes. Last.
+    pub const TARGET_RSS_BYTES: &str = "target_rss_bytes";
+
+    /// Virtual memory of the target process tree, in bytes. Last.
+    pub const TARGET_VIRTUAL_BYTES: &str = "target_virtual_bytes";
+
+    /// Threads of the target process tree. Last.
+    pub const TARGET_THREADS: &str = "target_threads";
+
+    /// Open file descriptors or handles of the target process tree. Last.
+    pub const TARGET_HANDLES: &str = "target_handles";
+
+    /// CPU utilization of the target process tree, in percent of one core.
+    /// Last.
+    pub const TARGET_CPU_PERCENT: &str = "target_cpu_percent";
+}
+
+/// How the values of a metric recorded between heartbeats are aggregated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Aggregation {
+    Last,
+    Max,
+}
+
+impl Aggregation {
+    /// The aggregation of the metric `name`. Metrics not named in [`metrics`]
+    /// are aggregated by their last value.
+    pub fn of(name: &str) -> Self {
+        match name {
+            metrics::EXECS
+            | metrics::CORPUS_COUNT
+            | metrics::COVERED
+            | metrics::FEATURES
+            | metrics::CRASHES => Self::Max,
+            _ => Self::Last,
+        }
+    }
+}
+
+/// The metrics recorded since the last heartbeat.
+#[derive(Debug, Default, PartialEq)]
+pub struct HeartbeatMetrics {
+    values: BTreeMap<String, f64>,
+}
+
+impl HeartbeatMetrics {
+    /// Record `value` of the metric `name`, aggregated as by
+    /// [`Aggregation::of`]. Values which aren't finite are ignored.
+    pub fn record(&mut self, name: &str, value: f64) {
+        if !value.is_finite() {
+            return;
+        }
+
+        match (Aggregation::of(name), self.values.get_mut(name)) {
+            (Aggregation::Max, Some(max)) => *max = max.max(value),
+            (_, Some(last)) => *last = value,
+            (_, None) => {
+                self.values.insert(name.to_owned(), value);
+            }
+        }
+    }
+
+    /// Take the metrics recorded, for the next heartbeat.
+    pub fn take(&mut self) -> BTreeMap<String, f64> {
+        std::mem::take(&mut self.values)
+    }
+}
+
+// The metric of a runtime or coverage stat, if it has one.
+fn stat_metric(stat: &EventData) -> Option<(&'static str, f64)> {
+    match stat {
+        EventData::ExecsSecond(x) => Some((metrics::EXECS_PER_SEC, *x)),
+        EventData::Count(x) => Some((metrics::EXECS, *x as f64)),
+        // Inputs in the corpus, as named by AFL.
+        EventData::CorpusCount(x) | EventData::CoveragePaths(x) => {
+            Some((metrics::CORPUS_COUNT, *x as f64))
+        }
+        EventData::Covered(x) => Some((metrics::COVERED, *x as f64)),
+        EventData::Features(x) => Some((metrics::FEATURES, *x as f64)),
+        EventData::Crashes(x) => Some((metrics::CRASHES, *x as f64)),
+        EventData::Coverage(x) => Some((metrics::BITMAP_COVERAGE, *x)),
+        EventData::Rate(x) => Some((metrics::COVERAGE_RATE, *x)),
+        EventData::RssMb(x) => Some((metrics::RSS_MB, *x as f64)),
+        _ => None,
+    }
 }
 
-#[derive(Clone)]
 pub struct TaskContext {
     task_id: Uuid,
     job_id: Uuid,
     machine_id: Uuid,
     machine_name: String,
+    metrics: Mutex<HeartbeatMetrics>,
 }
 
 pub type TaskHeartbeatClient = HeartbeatClient<TaskContext, HeartbeatData>;
@@ -48,10 +175,12 @@ pub async fn init_task_heartbeat(
             job_id,
             machine_id,
             machine_name,
+            metrics: Mutex::default(),
         },
         queue_url,
         initial_delay,
         None,
+        DeliveryAlert::from_env(format!("task-{task_id}")),
         |context| async move {
             let task_id = context.state.task_id;
             let machine_id = context.state.machine_id;
@@ -59,14 +188,18 @@ pub async fn init_task_heartbeat(
             let job_id = context.state.job_id;
 
             let data = HeartbeatClient::<TaskContext, _>::drain_current_messages(context.clone());
-            let _ = context
-                .queue_client
-                .enqueue(Heartbeat {
+            let metrics = match context.state.metrics.lock() {
+                Ok(mut metrics) => metrics.take(),
+                Err(_) => BTreeMap::new(),
+            };
+            context
+                .send(Heartbeat {
                     task_id,
                     job_id,
                     machine_id,
                     machine_name,
                     data,
+                    metrics,
                 })
                 .await;
         },
@@ -77,11 +210,30 @@ pub async fn init_task_heartbeat(
 pub trait HeartbeatSender {
     fn send(&self, data: HeartbeatData) -> Result<()>;
 
+    /// Record `value` of the metric `name`, sent with the next heartbeat.
+    fn send_metric(&self, name: &str, value: f64) -> Result<()>;
+
     fn alive(&self) {
         if let Err(error) = self.send(HeartbeatData::TaskAlive) {
             error!("failed to send heartbeat: {}", error);
         }
     }
+
+    /// Record `value` of the metric `name`, such as one of [`metrics`],
+    /// aggregated with the values recorded since the last heartbeat as by
+    /// [`Aggregation::of`].
+    fn metric(&self, name: &str, value: f64) {
+        if let Err(error) = self.send_metric(name, value) {
+            error!("failed to record heartbeat metric: {}", error);
+        }
+    }
+
+    /// Record the metrics of runtime or coverage `stats`.
+    fn stats(&self, stats: &[EventData]) {
+        for (name, value) in stats.iter().filter_map(stat_metric) {
+            self.metric(name, value);
+        }
+    }
 }
 
 impl HeartbeatSender for TaskHeartbeatClient {
@@ -94,6 +246,16 @@ impl HeartbeatSender for TaskHeartbeatClient {
         messages_lock.insert(data);
         Ok(())
     }
+
+    fn send_metric(&self, name: &str, value: f64) -> Result<()> {
+        self.context
+            .state
+            .metrics
+            .lock()
+            .map_err(|_| anyhow::format_err!("Unable to acquire the lock"))?
+            .record(name, value);
+        Ok(())
+    }
 }
 
 impl HeartbeatSender for Option<TaskHeartbeatClient> {
@@ -103,4 +265,105 @@ impl HeartbeatSender for Option<TaskHeartbeatClient> {
             None => Ok(()),
         }
     }
+
+    fn send_metric(&self, name: &str, value: f64) -> Result<()> {
+        match self {
+            Some(client) => client.send_metric(name, value),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_metric_aggregation() {
+        let mut metrics = HeartbeatMetrics::default();
+        metrics.record(metrics::EXECS_PER_SEC, 100.0);
+        metrics.record(metrics::EXECS_PER_SEC, 50.0);
+        metrics.record(metrics::EXECS, 1000.0);
+        metrics.record(metrics::EXECS, 900.0);
+        metrics.record(metrics::COVERED, 3.0);
+        metrics.record(metrics::COVERED, 7.0);
+        metrics.record("custom", 1.0);
+        metrics.record("custom", 0.5);
+        metrics.record(metrics::RSS_MB, f64::NAN);
+
+        assert_eq!(
+            metrics.take(),
+            BTreeMap::from([
+                (metrics::COVERED.to_owned(), 7.0),
+                ("custom".to_owned(), 0.5),
+                (metrics::EXECS.to_owned(), 1000.0),
+                (metrics::EXECS_PER_SEC.to_owned(), 50.0),
+            ])
+        );
+
+        // Metrics are aggregated only until they are taken.
+        assert!(metrics.take().is_empty());
+        metrics.record(metrics::EXECS, 10.0);
+        assert_eq!(metrics.take()[metrics::EXECS], 10.0);
+    }
+
+    #[test]
+    fn test_stats_metrics() {
+        let stats = [
+            EventData::ExecsSecond(2.5),
+            EventData::Count(12),
+            EventData::CoveragePaths(4),
+            EventData::Mode("default".into()),
+        ];
+        let metrics: Vec<_> = stats.iter().filter_map(stat_metric).collect();
+        assert_eq!(
+            metrics,
+            [
+                (metrics::EXECS_PER_SEC, 2.5),
+                (metrics::EXECS, 12.0),
+                (metrics::CORPUS_COUNT, 4.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_heartbeat_shape() -> Result<()> {
+        let mut heartbeat = Heartbeat {
+            task_id: Uuid::nil(),
+            job_id: Uuid::nil(),
+            machine_id: Uuid::nil(),
+            machine_name: "node".into(),
+            data: vec![HeartbeatData::TaskAlive],
+            metrics: BTreeMap::new(),
+        };
+        let nil = Uuid::nil().to_string();
+
+        // Without metrics, as before they were sent.
+        assert_eq!(
+            serde_json::to_value(&heartbeat)?,
+            json!({
+                "task_id": nil,
+                "job_id": nil,
+                "machine_id": nil,
+                "machine_name": "node",
+                "data": [{ "type": "TaskAlive" }],
+            })
+        );
+
+        heartbeat.metrics = BTreeMap::from([(metrics::EXECS_PER_SEC.to_owned(), 10.5)]);
+        assert_eq!(
+            serde_json::to_value(&heartbeat)?,
+            json!({
+                "task_id": nil,
+                "job_id": nil,
+                "machine_id": nil,
+                "machine_name": "node",
+                "data": [{ "type": "TaskAlive" }],
+                "metrics": { "execs_per_sec": 10.5 },
+            })
+        );
+
+        Ok(())
+    }
 }
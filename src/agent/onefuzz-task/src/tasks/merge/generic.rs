@@ -113,7 +113,7 @@ async fn process_message(config: &Config, input_url: &Url, tmp_dir: &Path) -> Re
 }
 
 async fn try_delete_blob(input_url: Url) -> Result<()> {
-    let http_client = reqwest::Client::new();
+    let http_client = reqwest_retry::proxy::client();
     http_client
         .delete(input_url)
         .send_retry_default()
@@ -129,7 +129,9 @@ async fn merge(config: &Config, output_dir: impl AsRef<Path>) -> Result<()> {
     let target_exe =
         try_resolve_setup_relative_path(&config.common.setup_dir, &config.target_exe).await?;
 
-    let expand = Expand::new(&config.common.machine_identity)
+    let expand = config
+        .common
+        .expand()
         .machine_id()
         .input_marker(&config.supervisor_input_marker)
         .input_corpus(&config.unique_inputs.local_path)
@@ -10,7 +10,7 @@ use anyhow::{Context, Result};
 use onefuzz::{
     http::ResponseExt,
     jitter::delay_with_jitter,
-    libfuzzer::{LibFuzzer, LibFuzzerMergeOutput},
+    libfuzzer::{LibFuzzer, LibFuzzerMergeOutput, LibFuzzerMergeResult},
     syncdir::{SyncOperation, SyncedDir},
 };
 use reqwest::Url;
@@ -22,6 +22,12 @@ use std::{
 };
 use storage_queue::{QueueClient, EMPTY_QUEUE_DELAY};
 
+// Used as the quarantine if there is no `merge_quarantine` container.
+const QUARANTINE_DIR: &str = "merge-quarantine";
+
+// Number of inputs which may fail a merge before the merge itself fails.
+const MAX_QUARANTINED_INPUTS: usize = 10;
+
 #[derive(Debug, Deserialize)]
 pub struct Config {
     pub target_exe: PathBuf,
@@ -35,6 +41,13 @@ pub struct Config {
     #[serde(default = "default_bool_true")]
     pub check_fuzzer_help: bool,
 
+    /// Seconds each input may run for during the merge, passed as `-timeout`.
+    pub target_timeout: Option<u64>,
+
+    /// Where to move inputs which fail the merge, so that it can resume without
+    /// them.
+    pub merge_quarantine: Option<SyncedDir>,
+
     #[serde(flatten)]
     pub common: CommonConfig,
 }
@@ -52,7 +65,14 @@ pub async fn spawn(config: Config) -> Result<()> {
             .as_ref()
             .map(|x| x.local_path.clone()),
         config.common.machine_identity.clone(),
-    );
+    )
+    .allow_unknown_placeholders(config.common.allow_unknown_placeholders)
+    .allow_env_placeholders(config.common.allow_env_placeholders)
+    .target_options_shell(config.common.target_options_shell);
+    let fuzzer = match &config.common.qemu_user {
+        Some(qemu) => fuzzer.qemu_user(qemu.clone()),
+        None => fuzzer,
+    };
     fuzzer.verify(config.check_fuzzer_help, None).await?;
 
     config.unique_inputs.init().await?;
@@ -155,11 +175,79 @@ async fn sync_and_merge(
     }
 }
 
+// Merge the candidates into the unique inputs.
+//
+// If an input crashes or hangs the target, the merge stops. The input is then
+// moved to the quarantine, and the merge is run again without it.
 pub async fn merge_inputs(
     config: &Config,
     candidates: Vec<impl AsRef<Path>>,
 ) -> Result<LibFuzzerMergeOutput> {
     info!("Merging corpus");
+    let merger = merger(config);
+    let quarantine = config.merge_quarantine.as_ref().map_or_else(
+        || PathBuf::from(QUARANTINE_DIR),
+        |dir| dir.local_path.clone(),
+    );
+
+    let mut quarantined = vec![];
+    let output = loop {
+        // Each attempt starts over, since the inputs have changed.
+        let control_dir = tempfile::tempdir()?;
+        let control_file = control_dir.path().join("merge_control_file");
+
+        let (input, timeout) = match merger
+            .merge_with_control_file(&config.unique_inputs.local_path, &candidates, &control_file)
+            .await?
+        {
+            LibFuzzerMergeResult::Merged(output) => break output,
+            LibFuzzerMergeResult::Failed { input, timeout } => (input, timeout),
+        };
+
+        if quarantined.len() == MAX_QUARANTINED_INPUTS {
+            bail!(
+                "merge failed on {} too many inputs, last on {}. quarantined: {:?}",
+                quarantined.len() + 1,
+                input.display(),
+                quarantined
+            );
+        }
+
+        let reason = if timeout { "timed out" } else { "failed" };
+        warn!("merge {reason} on {}, quarantining it", input.display());
+        quarantined.push(quarantine_input(&input, &quarantine).await?);
+    };
+
+    if !quarantined.is_empty() {
+        info!(
+            "quarantined {} inputs which failed the merge: {:?}",
+            quarantined.len(),
+            quarantined
+        );
+        if let Some(merge_quarantine) = &config.merge_quarantine {
+            merge_quarantine.sync_push().await?;
+        }
+    }
+
+    Ok(output)
+}
+
+// Move `input` into `quarantine`, and return its new path.
+async fn quarantine_input(input: &Path, quarantine: &Path) -> Result<PathBuf> {
+    let file_name = input
+        .file_name()
+        .ok_or_else(|| format_err!("invalid merge input: {}", input.display()))?;
+    let dest = quarantine.join(file_name);
+
+    tokio::fs::create_dir_all(quarantine).await?;
+    tokio::fs::rename(input, &dest)
+        .await
+        .with_context(|| format!("unable to quarantine merge input: {}", input.display()))?;
+
+    Ok(dest)
+}
+
+fn merger(config: &Config) -> LibFuzzer {
     let merger = LibFuzzer::new(
         config.target_exe.clone(),
         config.target_options.clone(),
@@ -172,14 +260,23 @@ pub async fn merge_inputs(
             .as_ref()
             .map(|x| x.local_path.clone()),
         config.common.machine_identity.clone(),
-    );
-    merger
-        .merge(&config.unique_inputs.local_path, &candidates)
-        .await
+    )
+    .allow_unknown_placeholders(config.common.allow_unknown_placeholders)
+    .allow_env_placeholders(config.common.allow_env_placeholders)
+    .target_options_shell(config.common.target_options_shell);
+    let merger = match &config.common.qemu_user {
+        Some(qemu) => merger.qemu_user(qemu.clone()),
+        None => merger,
+    };
+
+    match config.target_timeout {
+        Some(timeout) => merger.timeout(timeout),
+        None => merger,
+    }
 }
 
 async fn try_delete_blob(input_url: Url) -> Result<()> {
-    let http_client = reqwest::Client::new();
+    let http_client = reqwest_retry::proxy::client();
     http_client
         .delete(input_url)
         .send_retry_default()
@@ -191,3 +288,110 @@ async fn try_delete_blob(input_url: Url) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Simulates `-merge=1`, writing a control file as libFuzzer does. Every
+    // new input is added to the corpus, except for those containing `hang`,
+    // on which it times out.
+    #[cfg(target_family = "unix")]
+    const FAKE_MERGE_LIBFUZZER: &str = r#"#!/bin/sh
+set -e
+dirs=""
+for arg in "$@"; do
+  case "$arg" in
+    -merge_control_file=*) control="${arg#-merge_control_file=}" ;;
+    -timeout=*) echo "$arg" >> "$FAKE_LIBFUZZER_LOG" ;;
+    -*) ;;
+    *) dirs="$dirs $arg" ;;
+  esac
+done
+set -- $dirs
+corpus="$1"
+files=$(find "$@" -type f | sort)
+echo "$files" | grep -c . > "$control"
+find "$corpus" -type f | grep -c . >> "$control" || true
+echo "$files" >> "$control"
+i=0
+added=0
+for f in $files; do
+  echo "STARTED $i 1" >> "$control"
+  if grep -q hang "$f"; then
+    echo "==1== ERROR: libFuzzer: timeout after 1 seconds" >&2
+    exit 70
+  fi
+  echo "FT $i $i" >> "$control"
+  echo "COV $i $i" >> "$control"
+  case "$f" in
+    "$corpus"/*) ;;
+    *) cp "$f" "$corpus/"; added=$((added+1)) ;;
+  esac
+  i=$((i+1))
+done
+echo "MERGE-OUTER: $added new files with $added new features added; $added new coverage edges" >&2
+"#;
+
+    #[cfg(target_family = "unix")]
+    #[tokio::test]
+    async fn test_merge_quarantines_failing_inputs() -> Result<()> {
+        use crate::tasks::config::fixture;
+        use onefuzz::fs::{list_files, set_executable};
+        use serde_json::json;
+        use tokio::fs;
+
+        let dir = tempfile::tempdir()?;
+        let setup_dir = dir.path().join("setup");
+        let candidates = dir.path().join("inputs");
+        let unique_inputs = dir.path().join("unique_inputs");
+        let quarantine = dir.path().join("quarantine");
+        let log = dir.path().join("fuzzer.log");
+        for path in [&setup_dir, &candidates, &unique_inputs] {
+            fs::create_dir_all(path).await?;
+        }
+
+        let target_exe = setup_dir.join("fuzz.sh");
+        fs::write(&target_exe, FAKE_MERGE_LIBFUZZER).await?;
+        set_executable(&target_exe).await?;
+
+        fs::write(unique_inputs.join("seed"), "seed").await?;
+        for (name, data) in [("a", "a"), ("b", "hang"), ("c", "c"), ("d", "hang")] {
+            fs::write(candidates.join(name), data).await?;
+        }
+
+        let config: Config = serde_json::from_value(fixture::config(json!({
+            "target_exe": "fuzz.sh",
+            "target_env": { "FAKE_LIBFUZZER_LOG": log },
+            "target_options": [],
+            "target_timeout": 1,
+            "inputs": [{ "path": candidates }],
+            "unique_inputs": { "path": unique_inputs },
+            "merge_quarantine": { "path": quarantine },
+            "preserve_existing_outputs": false,
+            "setup_dir": setup_dir,
+        })))?;
+
+        let output = merge_inputs(&config, vec![&candidates]).await?;
+        assert_eq!(output.added_files_count, 2);
+
+        let names = |dir: PathBuf| async move {
+            let mut names: Vec<_> = list_files(dir)
+                .await?
+                .iter()
+                .filter_map(|path| path.file_name())
+                .map(|name| name.to_string_lossy().into_owned())
+                .collect();
+            names.sort();
+            anyhow::Ok(names)
+        };
+        assert_eq!(names(unique_inputs).await?, ["a", "c", "seed"]);
+        assert_eq!(names(quarantine).await?, ["b", "d"]);
+        assert_eq!(names(candidates).await?, ["a", "c"]);
+
+        // One attempt per quarantined input, and the last, which succeeded.
+        assert_eq!(fs::read_to_string(&log).await?, "-timeout=1\n".repeat(3));
+
+        Ok(())
+    }
+}
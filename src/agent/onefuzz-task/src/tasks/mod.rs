@@ -11,5 +11,7 @@ pub mod heartbeat;
 pub mod merge;
 pub mod regression;
 pub mod report;
+pub mod source_paths;
 pub mod stats;
+pub mod symbols;
 pub mod utils;
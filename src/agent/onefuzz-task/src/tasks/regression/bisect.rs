@@ -0,0 +1,611 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Bisection of historical builds of the target, for the first build which
+//! reproduces a crash.
+
+use super::common::RegressionHandler;
+use crate::tasks::report::{
+    crash_report::CrashTestResult,
+    repro::{ReproRetries, Reproduction},
+};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use onefuzz::{
+    blob::BlobClient,
+    fs::{exists, set_executable},
+    syncdir::SyncedDir,
+};
+use onefuzz_telemetry::{Event::regression_bisect, EventData};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Ordering,
+    collections::BTreeSet,
+    future::Future,
+    path::{Component, Path, PathBuf},
+};
+use tokio::fs;
+
+#[derive(Debug, Deserialize)]
+pub struct BisectConfig {
+    /// Historical builds of the target, each the blobs under the prefix
+    /// `{build}/`, laid out as the setup container. Builds are downloaded as
+    /// they are tested, each to its own directory.
+    pub builds: SyncedDir,
+
+    /// The builds to bisect, oldest first. Defaults to the builds of `builds`,
+    /// ordered by version.
+    pub build_list: Option<Vec<String>>,
+
+    /// Where the result of bisecting each report or input is saved.
+    pub bisect_reports: SyncedDir,
+
+    /// Retries of inputs which don't reproduce in a build.
+    #[serde(flatten)]
+    pub repro_retries: ReproRetries,
+}
+
+/// Historical builds of the target.
+#[async_trait]
+pub trait BuildProvider {
+    /// The identifiers of the builds, oldest first.
+    async fn builds(&self) -> Result<Vec<String>>;
+
+    /// The setup directory of `build`, downloaded if it has not been yet.
+    async fn setup_dir(&self, build: &str) -> Result<PathBuf>;
+}
+
+/// Whether the crash reproduced in a build.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct BuildVerdict {
+    pub build: String,
+    pub reproduced: bool,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reproduction: Option<Reproduction>,
+}
+
+impl BuildVerdict {
+    fn new(build: &str, result: &CrashTestResult) -> Self {
+        let (reproduced, reproduction) = match result {
+            CrashTestResult::CrashReport(report) => (true, report.reproduction.clone()),
+            CrashTestResult::NoRepro(no_repro) => (false, no_repro.reproduction.clone()),
+        };
+
+        Self {
+            build: build.to_owned(),
+            reproduced,
+            reproduction,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Bisection {
+    /// The first build which reproduces the crash, if any does.
+    pub first_bad: Option<String>,
+
+    /// The build before `first_bad`, if it was tested and did not reproduce
+    /// the crash. If no build reproduces the crash, the newest.
+    pub last_good: Option<String>,
+
+    /// The verdicts of the builds tested, oldest first.
+    pub verdicts: Vec<BuildVerdict>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BisectReport {
+    #[serde(flatten)]
+    pub bisection: Bisection,
+    pub original_crash_test_result: Option<CrashTestResult>,
+}
+
+/// Binary search `builds`, oldest first, for the first which reproduces a
+/// crash, as tested by `test`. Builds after one which reproduces the crash are
+/// assumed to reproduce it too, and the newest build is tested first.
+pub async fn bisect<F, Fut>(builds: &[String], mut test: F) -> Result<Bisection>
+where
+    F: FnMut(String) -> Fut,
+    Fut: Future<Output = Result<BuildVerdict>>,
+{
+    let mut verdicts = vec![];
+    let Some(newest) = builds.len().checked_sub(1) else {
+        return Ok(Bisection::default());
+    };
+
+    let verdict = test(builds[newest].clone()).await?;
+    let reproduced = verdict.reproduced;
+    verdicts.push((newest, verdict));
+    if !reproduced {
+        return Ok(Bisection {
+            first_bad: None,
+            last_good: Some(builds[newest].clone()),
+            verdicts: verdicts.into_iter().map(|(_, verdict)| verdict).collect(),
+        });
+    }
+
+    // `builds[bad]` reproduces the crash, and `builds[good]` was tested and
+    // does not.
+    let (mut good, mut bad) = (None, newest);
+    let mut low = 0;
+    while low < bad {
+        let mid = low + (bad - low) / 2;
+        let verdict = test(builds[mid].clone()).await?;
+        if verdict.reproduced {
+            bad = mid;
+        } else {
+            good = Some(mid);
+            low = mid + 1;
+        }
+        verdicts.push((mid, verdict));
+    }
+
+    verdicts.sort_by_key(|(index, _)| *index);
+    Ok(Bisection {
+        first_bad: Some(builds[bad].clone()),
+        last_good: good.map(|good| builds[good].clone()),
+        verdicts: verdicts.into_iter().map(|(_, verdict)| verdict).collect(),
+    })
+}
+
+/// Bisect the builds of `provider` for the first which reproduces the crash of
+/// `input`, as tested by `handler`.
+pub async fn bisect_input(
+    provider: &impl BuildProvider,
+    handler: &impl RegressionHandler,
+    input: &Path,
+    input_url: &Url,
+    repro_retries: &ReproRetries,
+) -> Result<Bisection> {
+    let builds = provider.builds().await?;
+
+    bisect(&builds, |build| async move {
+        let setup_dir = provider.setup_dir(&build).await?;
+        let result = handler
+            .get_build_crash_result(
+                input.to_owned(),
+                input_url.clone(),
+                &setup_dir,
+                repro_retries,
+            )
+            .await
+            .with_context(|| format!("unable to test build: {build}"))?;
+
+        Ok(BuildVerdict::new(&build, &result))
+    })
+    .await
+}
+
+impl BisectConfig {
+    /// Bisect the crash of `input`, then save the result to `bisect_reports`
+    /// as `name`.
+    pub async fn bisect_and_save(
+        &self,
+        handler: &impl RegressionHandler,
+        input: &Path,
+        input_url: &Url,
+        name: &str,
+        original_crash_test_result: Option<CrashTestResult>,
+    ) -> Result<()> {
+        let bisection = bisect_input(self, handler, input, input_url, &self.repro_retries).await?;
+
+        info!(
+            "bisected {}: first bad build: {:?}, last good build: {:?}",
+            name, bisection.first_bad, bisection.last_good
+        );
+        event!(
+            regression_bisect;
+            EventData::Path = name.to_owned(),
+            EventData::FirstBadBuild = bisection.first_bad.clone().unwrap_or_default(),
+            EventData::LastGoodBuild = bisection.last_good.clone().unwrap_or_default()
+        );
+
+        let report = BisectReport {
+            bisection,
+            original_crash_test_result,
+        };
+        self.bisect_reports.upload(name, &report).await?;
+
+        Ok(())
+    }
+
+    // The directory of builds which are already local, if there is no
+    // container to download them from.
+    fn local_builds(&self) -> Option<PathBuf> {
+        match &self.builds.remote_path {
+            None => Some(self.builds.local_path.clone()),
+            Some(url) => url.as_file_path(),
+        }
+    }
+}
+
+#[async_trait]
+impl BuildProvider for BisectConfig {
+    async fn builds(&self) -> Result<Vec<String>> {
+        if let Some(build_list) = &self.build_list {
+            return Ok(build_list.clone());
+        }
+
+        let mut builds = BTreeSet::new();
+        match (self.local_builds(), &self.builds.remote_path) {
+            (Some(dir), _) => {
+                let mut entries = fs::read_dir(&dir)
+                    .await
+                    .with_context(|| format!("unable to list builds: {}", dir.display()))?;
+                while let Some(entry) = entries.next_entry().await? {
+                    if entry.file_type().await?.is_dir() {
+                        builds.insert(entry.file_name().to_string_lossy().to_string());
+                    }
+                }
+            }
+            (None, Some(url)) => {
+                for blob in BlobClient::new().list_blobs(&url.url()?).await? {
+                    if let Some((build, _)) = blob.name.split_once('/') {
+                        builds.insert(build.to_owned());
+                    }
+                }
+            }
+            (None, None) => unreachable!("builds without a container are local"),
+        }
+
+        let mut builds: Vec<_> = builds.into_iter().collect();
+        builds.sort_by(|a, b| compare_versions(a, b));
+        Ok(builds)
+    }
+
+    async fn setup_dir(&self, build: &str) -> Result<PathBuf> {
+        let name = Path::new(build);
+        if !is_relative_name(name) || name.components().count() > 1 {
+            bail!("invalid build name: {}", build);
+        }
+
+        if let Some(dir) = self.local_builds() {
+            let setup_dir = dir.join(build);
+            if !setup_dir.is_dir() {
+                bail!("missing build: {}", setup_dir.display());
+            }
+            return Ok(setup_dir);
+        }
+
+        let setup_dir = self.builds.local_path.join(build);
+        if exists(&setup_dir).await? {
+            return Ok(setup_dir);
+        }
+
+        // Downloaded to a temporary directory first, so that a build which was
+        // only partly downloaded is downloaded again.
+        let partial = self.builds.local_path.join(format!("{build}.partial"));
+        if exists(&partial).await? {
+            fs::remove_dir_all(&partial).await?;
+        }
+
+        let container = self.builds.remote_url()?;
+        let client = BlobClient::new();
+        let prefix = format!("{build}/");
+        let blobs = client
+            .list_blobs_with_prefix(&container.url()?, &prefix)
+            .await
+            .with_context(|| format!("unable to list blobs of build: {build}"))?;
+        if blobs.is_empty() {
+            bail!("missing build: {}", build);
+        }
+
+        for blob in blobs {
+            let Some(relative) = blob.name.strip_prefix(&prefix).map(Path::new) else {
+                continue;
+            };
+            if !is_relative_name(relative) {
+                warn!("skipping blob with invalid name: {}", blob.name);
+                continue;
+            }
+
+            let destination = partial.join(relative);
+            if let Some(parent) = destination.parent() {
+                fs::create_dir_all(parent).await?;
+            }
+            client
+                .get_file(&container.blob(&blob.name).url(), &destination)
+                .await
+                .with_context(|| format!("unable to download blob {}", blob.name))?;
+        }
+
+        set_executable(&partial).await?;
+        fs::rename(&partial, &setup_dir).await?;
+        info!("downloaded build {} to {}", build, setup_dir.display());
+
+        Ok(setup_dir)
+    }
+}
+
+// Whether `path` is a relative path which does not escape its directory.
+fn is_relative_name(path: &Path) -> bool {
+    path.components().next().is_some()
+        && path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+}
+
+// Compare build names as versions, comparing runs of digits as numbers, so
+// that `1.10` is after `1.9`.
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    version_parts(a)
+        .cmp(&version_parts(b))
+        .then_with(|| a.cmp(b))
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum VersionPart<'a> {
+    Number(u64),
+    Text(&'a str),
+}
+
+fn version_parts(name: &str) -> Vec<VersionPart<'_>> {
+    let mut parts = vec![];
+    let mut rest = name;
+
+    while let Some(first) = rest.chars().next() {
+        let digits = first.is_ascii_digit();
+        let end = rest
+            .find(|c: char| c.is_ascii_digit() != digits)
+            .unwrap_or(rest.len());
+        let (part, tail) = rest.split_at(end);
+
+        parts.push(match part.parse() {
+            Ok(number) if digits => VersionPart::Number(number),
+            _ => VersionPart::Text(part),
+        });
+        rest = tail;
+    }
+
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tasks::report::{
+        compat::REPORT_SCHEMA_VERSION,
+        crash_report::{CrashReport, NoCrash},
+    };
+    use onefuzz::input_tester::{TestOutcome, TestResult};
+    use stacktrace_parser::CrashLog;
+    use std::{
+        collections::{BTreeMap, HashMap},
+        sync::{Arc, Mutex},
+    };
+    use uuid::Uuid;
+
+    // Builds `v1` to `v10`, of which those from `v{flip}` reproduce the crash,
+    // and `v{flaky}` only from its second attempt.
+    struct FakeBuilds {
+        flip: usize,
+        flaky: Option<usize>,
+        downloaded: Mutex<Vec<String>>,
+        attempts: Mutex<HashMap<String, usize>>,
+    }
+
+    impl FakeBuilds {
+        fn new(flip: usize, flaky: Option<usize>) -> Arc<Self> {
+            Arc::new(Self {
+                flip,
+                flaky,
+                downloaded: Mutex::new(vec![]),
+                attempts: Mutex::new(HashMap::new()),
+            })
+        }
+
+        fn crashes(&self, build: &str) -> bool {
+            let version: usize = build.trim_start_matches('v').parse().unwrap();
+
+            let mut attempts = self.attempts.lock().unwrap();
+            let attempt = attempts.entry(build.to_owned()).or_default();
+            *attempt += 1;
+
+            version >= self.flip && (Some(version) != self.flaky || *attempt >= 2)
+        }
+    }
+
+    #[async_trait]
+    impl BuildProvider for Arc<FakeBuilds> {
+        async fn builds(&self) -> Result<Vec<String>> {
+            Ok((1..=10).map(|version| format!("v{version}")).collect())
+        }
+
+        async fn setup_dir(&self, build: &str) -> Result<PathBuf> {
+            self.downloaded.lock().unwrap().push(build.to_owned());
+            Ok(PathBuf::from(build))
+        }
+    }
+
+    #[async_trait]
+    impl RegressionHandler for Arc<FakeBuilds> {
+        async fn get_crash_result(
+            &self,
+            _input: PathBuf,
+            _input_url: Url,
+        ) -> Result<CrashTestResult> {
+            unimplemented!("only builds are tested")
+        }
+
+        async fn build(&self) -> Option<String> {
+            unimplemented!("only builds are tested")
+        }
+
+        async fn get_build_crash_result(
+            &self,
+            _input: PathBuf,
+            _input_url: Url,
+            setup_dir: &Path,
+            repro_retries: &ReproRetries,
+        ) -> Result<CrashTestResult> {
+            let build = setup_dir.to_string_lossy().to_string();
+            let (result, reproduction) = repro_retries
+                .test(None, &HashMap::new(), |_, _| {
+                    let crash_log = self.crashes(&build).then(|| {
+                        CrashLog::new(
+                            None,
+                            None,
+                            "SIGSEGV".into(),
+                            "SIGSEGV".into(),
+                            None,
+                            None,
+                            vec![],
+                        )
+                        .unwrap()
+                    });
+                    let outcome = match crash_log {
+                        Some(_) => TestOutcome::Crash,
+                        None => TestOutcome::CleanExit,
+                    };
+                    std::future::ready(Ok(TestResult {
+                        crash_log,
+                        error: None,
+                        outcome,
+                        crash_dump: None,
+                        runs: vec![],
+                    }))
+                })
+                .await?;
+
+            Ok(if result.crash_log.is_some() {
+                CrashReport {
+                    reproduction: Some(reproduction),
+                    ..CrashReport::default()
+                }
+                .into()
+            } else {
+                NoCrash {
+                    schema_version: REPORT_SCHEMA_VERSION,
+                    input_sha256: String::new(),
+                    input_blob: None,
+                    executable: PathBuf::new(),
+                    task_id: Uuid::nil(),
+                    job_id: Uuid::nil(),
+                    tries: reproduction.attempts.len() as u64,
+                    error: None,
+                    reproduction: Some(reproduction),
+                    bundle: None,
+                    extra: BTreeMap::new(),
+                }
+                .into()
+            })
+        }
+    }
+
+    async fn bisect_fake(builds: &Arc<FakeBuilds>, retries: &ReproRetries) -> Result<Bisection> {
+        let url = Url::parse("https://fuzz.blob.core.windows.net/crashes/crash-1")?;
+        bisect_input(builds, builds, Path::new("crash-1"), &url, retries).await
+    }
+
+    fn tested(bisection: &Bisection) -> Vec<(&str, bool)> {
+        bisection
+            .verdicts
+            .iter()
+            .map(|verdict| (verdict.build.as_str(), verdict.reproduced))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_bisect() -> Result<()> {
+        let builds = FakeBuilds::new(6, None);
+        let bisection = bisect_fake(&builds, &ReproRetries::none()).await?;
+
+        assert_eq!(bisection.first_bad.as_deref(), Some("v6"));
+        assert_eq!(bisection.last_good.as_deref(), Some("v5"));
+        assert_eq!(
+            tested(&bisection),
+            [
+                ("v5", false),
+                ("v6", true),
+                ("v7", true),
+                ("v8", true),
+                ("v10", true)
+            ]
+        );
+
+        // Only the builds tested are downloaded, newest first.
+        assert_eq!(
+            *builds.downloaded.lock().unwrap(),
+            ["v10", "v5", "v8", "v7", "v6"]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_bisect_flaky_build() -> Result<()> {
+        // Without retries, the flaky first bad build is taken to be good.
+        let builds = FakeBuilds::new(6, Some(6));
+        let bisection = bisect_fake(&builds, &ReproRetries::none()).await?;
+        assert_eq!(bisection.first_bad.as_deref(), Some("v7"));
+
+        let builds = FakeBuilds::new(6, Some(6));
+        let bisection = bisect_fake(&builds, &ReproRetries::default()).await?;
+        assert_eq!(bisection.first_bad.as_deref(), Some("v6"));
+        assert_eq!(bisection.last_good.as_deref(), Some("v5"));
+
+        let flaky = bisection
+            .verdicts
+            .iter()
+            .find(|verdict| verdict.build == "v6")
+            .unwrap();
+        let reproduction = flaky.reproduction.as_ref().unwrap();
+        assert!(flaky.reproduced);
+        assert!(reproduction.flaky);
+        assert_eq!(reproduction.summary, "reproduced 1/2 attempts");
+
+        // Good builds are retried before they are taken to be good.
+        let good = &bisection.verdicts[0];
+        assert_eq!(good.build, "v5");
+        assert_eq!(
+            good.reproduction.as_ref().unwrap().summary,
+            "reproduced 0/4 attempts"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_bisect_ends() -> Result<()> {
+        // Every build reproduces the crash.
+        let bisection = bisect_fake(&FakeBuilds::new(1, None), &ReproRetries::none()).await?;
+        assert_eq!(bisection.first_bad.as_deref(), Some("v1"));
+        assert_eq!(bisection.last_good, None);
+
+        // No build does.
+        let bisection = bisect_fake(&FakeBuilds::new(11, None), &ReproRetries::none()).await?;
+        assert_eq!(bisection.first_bad, None);
+        assert_eq!(bisection.last_good.as_deref(), Some("v10"));
+        assert_eq!(tested(&bisection), [("v10", false)]);
+
+        let bisection = bisect(&[], |_| async { unreachable!() }).await?;
+        assert_eq!(bisection, Bisection::default());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_local_builds() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        for build in ["1.10.0", "1.9.2", "1.9.10", "2.0.0"] {
+            fs::create_dir(dir.path().join(build)).await?;
+        }
+        fs::write(dir.path().join("README"), "builds").await?;
+
+        let config: BisectConfig = serde_json::from_value(serde_json::json!({
+            "builds": { "path": dir.path() },
+            "bisect_reports": { "path": dir.path().join("bisect_reports") },
+        }))?;
+        assert_eq!(config.repro_retries, ReproRetries::default());
+        assert_eq!(
+            config.builds().await?,
+            ["1.9.2", "1.9.10", "1.10.0", "2.0.0"]
+        );
+        assert_eq!(config.setup_dir("1.9.10").await?, dir.path().join("1.9.10"));
+        assert!(config.setup_dir("../1.9.10").await.is_err());
+        assert!(config.setup_dir("3.0.0").await.is_err());
+
+        Ok(())
+    }
+}
@@ -1,17 +1,22 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
+use super::{bisect::BisectConfig, verdict::RegressionVerdict};
 use crate::tasks::{
     config::CommonConfig,
     heartbeat::{HeartbeatSender, TaskHeartbeatClient},
-    report::crash_report::{parse_report_file, CrashTestResult, RegressionReport},
+    report::{
+        compat::parse_report_file,
+        crash_report::{CrashTestResult, RegressionReport},
+        repro::ReproRetries,
+    },
 };
 use anyhow::{Context, Result};
 use async_trait::async_trait;
 use onefuzz::syncdir::SyncedDir;
 use onefuzz_result::job_result::TaskJobResultClient;
 use reqwest::Url;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 /// Defines implementation-provided callbacks for all implementers of regression tasks.
 ///
@@ -22,6 +27,23 @@ pub trait RegressionHandler {
     /// * `input` - path to the input to test
     /// * `input_url` - input url
     async fn get_crash_result(&self, input: PathBuf, input_url: Url) -> Result<CrashTestResult>;
+
+    /// Test the provided input against the build of the target in `setup_dir`
+    /// * `input` - path to the input to test
+    /// * `input_url` - input url
+    /// * `setup_dir` - setup directory of the build
+    /// * `repro_retries` - retries of an input which doesn't reproduce
+    async fn get_build_crash_result(
+        &self,
+        input: PathBuf,
+        input_url: Url,
+        setup_dir: &Path,
+        repro_retries: &ReproRetries,
+    ) -> Result<CrashTestResult>;
+
+    /// The identity of the build tested by `get_crash_result`, as recorded in
+    /// verdicts.
+    async fn build(&self) -> Option<String>;
 }
 
 /// Runs the regression task
@@ -32,10 +54,15 @@ pub async fn run(
     report_dirs: &[&SyncedDir],
     report_list: &Option<Vec<String>>,
     readonly_inputs: &Option<SyncedDir>,
+    bisect: &Option<BisectConfig>,
     handler: &impl RegressionHandler,
 ) -> Result<()> {
     info!("starting regression task");
     regression_reports.init().await?;
+    if let Some(bisect) = bisect {
+        bisect.builds.init().await?;
+        bisect.bisect_reports.init().await?;
+    }
 
     let heartbeat_client = common_config.init_heartbeat(None).await?;
     let job_result_client = common_config.init_job_result().await?;
@@ -46,6 +73,7 @@ pub async fn run(
         report_dirs,
         report_list,
         regression_reports,
+        bisect.as_ref(),
         &heartbeat_client,
         &job_result_client,
     )
@@ -57,6 +85,7 @@ pub async fn run(
             handler,
             readonly_inputs,
             regression_reports,
+            bisect.as_ref(),
             &heartbeat_client,
             &job_result_client,
         )
@@ -72,15 +101,18 @@ pub async fn run(
 /// * `handler` - regression handler
 /// * `readonly_inputs` - location of the input files
 /// * `regression_reports` - where reports should be saved
+/// * `bisect` - builds to bisect, instead of testing the current build
 /// * `heartbeat_client` - heartbeat client
 pub async fn handle_inputs(
     handler: &impl RegressionHandler,
     readonly_inputs: &SyncedDir,
     regression_reports: &SyncedDir,
+    bisect: Option<&BisectConfig>,
     heartbeat_client: &Option<TaskHeartbeatClient>,
     job_result_client: &Option<TaskJobResultClient>,
 ) -> Result<()> {
     readonly_inputs.init_pull().await?;
+    let build = handler.build().await;
     let mut input_files = tokio::fs::read_dir(&readonly_inputs.local_path).await?;
     while let Some(file) = input_files.next_entry().await? {
         heartbeat_client.alive();
@@ -98,13 +130,29 @@ pub async fn handle_inputs(
 
         let input_url = readonly_inputs.remote_url()?.url()?.join(&file_name)?;
 
-        let crash_test_result = handler.get_crash_result(file_path, input_url).await?;
-        RegressionReport {
-            crash_test_result,
-            original_crash_test_result: None,
+        if let Some(bisect) = bisect {
+            let name = format!("{file_name}.json");
+            bisect
+                .bisect_and_save(handler, &file_path, &input_url, &name, None)
+                .await?;
+            continue;
         }
-        .save(None, regression_reports, job_result_client)
-        .await?
+
+        let result = handler.get_crash_result(file_path, input_url).await;
+        let verdict = RegressionVerdict::new(&result, None, build.clone());
+        let report = match result {
+            Ok(crash_test_result) => Some(RegressionReport {
+                crash_test_result,
+                original_crash_test_result: None,
+            }),
+            Err(err) => {
+                warn!("unable to test input {}: {:?}", file_name, err);
+                None
+            }
+        };
+        verdict
+            .save(report, None, regression_reports, job_result_client)
+            .await?
     }
 
     Ok(())
@@ -116,6 +164,7 @@ pub async fn handle_crash_reports(
     report_dirs: &[&SyncedDir],
     report_list: &Option<Vec<String>>,
     regression_reports: &SyncedDir,
+    bisect: Option<&BisectConfig>,
     heartbeat_client: &Option<TaskHeartbeatClient>,
     job_result_client: &Option<TaskJobResultClient>,
 ) -> Result<()> {
@@ -125,6 +174,7 @@ pub async fn handle_crash_reports(
     }
 
     crashes.init_pull().await?;
+    let build = handler.build().await;
 
     for possible_dir in report_dirs {
         possible_dir.init_pull().await?;
@@ -161,14 +211,44 @@ pub async fn handle_crash_reports(
 
             let input_url = crashes.remote_url()?.url()?;
             let input = crashes.local_path.join(&input_blob.name);
-            let crash_test_result = handler.get_crash_result(input, input_url).await?;
 
-            RegressionReport {
-                crash_test_result,
-                original_crash_test_result: Some(original_crash_test_result),
+            if let Some(bisect) = bisect {
+                bisect
+                    .bisect_and_save(
+                        handler,
+                        &input,
+                        &input_url,
+                        &file_name,
+                        Some(original_crash_test_result),
+                    )
+                    .await?;
+                continue;
             }
-            .save(Some(file_name), regression_reports, job_result_client)
-            .await?
+
+            let result = handler.get_crash_result(input, input_url).await;
+            let verdict = RegressionVerdict::new(
+                &result,
+                Some((&file_name, &original_crash_test_result)),
+                build.clone(),
+            );
+            let report = match result {
+                Ok(crash_test_result) => Some(RegressionReport {
+                    crash_test_result,
+                    original_crash_test_result: Some(original_crash_test_result),
+                }),
+                Err(err) => {
+                    warn!("unable to test input of report {}: {:?}", file_name, err);
+                    None
+                }
+            };
+            verdict
+                .save(
+                    report,
+                    Some(file_name),
+                    regression_reports,
+                    job_result_client,
+                )
+                .await?
         }
     }
 
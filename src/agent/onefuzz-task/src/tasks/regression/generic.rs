@@ -3,17 +3,27 @@
 
 use crate::tasks::{
     config::CommonConfig,
-    report::{crash_report::CrashTestResult, generic},
+    report::{crash_report::CrashTestResult, generic, redact::Redactor, repro::ReproRetries},
+    symbols::debug_file_dirs,
     utils::{default_bool_true, try_resolve_setup_relative_path},
 };
 use anyhow::Result;
 use async_trait::async_trait;
-use onefuzz::syncdir::SyncedDir;
+use coverage::path_map::PathMap;
+use onefuzz::{input_tester::InputVia, syncdir::SyncedDir};
 use reqwest::Url;
 use serde::Deserialize;
-use std::{collections::HashMap, path::PathBuf};
+use stacktrace_parser::StackNormalization;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 
-use super::common::{self, RegressionHandler};
+use super::{
+    bisect::BisectConfig,
+    common::{self, RegressionHandler},
+    verdict::build_id,
+};
 
 #[derive(Debug, Deserialize)]
 pub struct Config {
@@ -25,8 +35,15 @@ pub struct Config {
     #[serde(default)]
     pub target_env: HashMap<String, String>,
 
+    /// How crashing inputs are passed to the target: as the file of
+    /// `{input}`, or on its standard input.
+    #[serde(default)]
+    pub input_via: InputVia,
+
     pub target_timeout: Option<u64>,
 
+    pub target_memory_limit_mb: Option<u64>,
+
     pub crashes: SyncedDir,
     pub regression_reports: SyncedDir,
     pub report_list: Option<Vec<String>>,
@@ -35,6 +52,10 @@ pub struct Config {
     pub no_repro: Option<SyncedDir>,
     pub readonly_inputs: Option<SyncedDir>,
 
+    /// Bisect historical builds for the first which reproduces each crash,
+    /// instead of checking it against the current build.
+    pub bisect: Option<BisectConfig>,
+
     #[serde(default)]
     pub check_asan_log: bool,
     #[serde(default = "default_bool_true")]
@@ -56,30 +77,62 @@ pub struct GenericRegressionTask {
 #[async_trait]
 impl RegressionHandler for GenericRegressionTask {
     async fn get_crash_result(&self, input: PathBuf, input_url: Url) -> Result<CrashTestResult> {
+        self.get_build_crash_result(
+            input,
+            input_url,
+            &self.config.common.setup_dir,
+            &ReproRetries::none(),
+        )
+        .await
+    }
+
+    async fn get_build_crash_result(
+        &self,
+        input: PathBuf,
+        input_url: Url,
+        setup_dir: &Path,
+        repro_retries: &ReproRetries,
+    ) -> Result<CrashTestResult> {
         let target_exe =
-            try_resolve_setup_relative_path(&self.config.common.setup_dir, &self.config.target_exe)
-                .await?;
+            try_resolve_setup_relative_path(setup_dir, &self.config.target_exe).await?;
 
         let extra_setup_dir = self.config.common.extra_setup_dir.as_deref();
+        let debug_file_dirs = debug_file_dirs(setup_dir, extra_setup_dir, &[]);
         let args = generic::TestInputArgs {
             input_url: Some(input_url),
             input: &input,
             target_exe: &target_exe,
             target_options: &self.config.target_options,
             target_env: &self.config.target_env,
-            setup_dir: &self.config.common.setup_dir,
+            input_via: self.config.input_via,
+            setup_dir,
             extra_setup_dir,
             task_id: self.config.common.task_id,
             job_id: self.config.common.job_id,
             target_timeout: self.config.target_timeout,
+            target_memory_limit_mb: self.config.target_memory_limit_mb,
             check_retry_count: self.config.check_retry_count,
+            repro_retries,
             check_asan_log: self.config.check_asan_log,
             check_debugger: self.config.check_debugger,
             minimized_stack_depth: self.config.minimized_stack_depth,
+            stack_normalization: &StackNormalization::default(),
             machine_identity: self.config.common.machine_identity.clone(),
+            allow_unknown_placeholders: self.config.common.allow_unknown_placeholders,
+            allow_env_placeholders: self.config.common.allow_env_placeholders,
+            target_options_shell: self.config.common.target_options_shell,
+            qemu_user: self.config.common.qemu_user.as_ref(),
+            source_path_map: &PathMap::default(),
+            redactor: &Redactor::default(),
+            debug_file_dirs: &debug_file_dirs,
+            crash_dumps: None,
         };
         generic::test_input(args).await
     }
+
+    async fn build(&self) -> Option<String> {
+        build_id(&self.config.common.setup_dir, &self.config.target_exe).await
+    }
 }
 
 impl GenericRegressionTask {
@@ -108,6 +161,7 @@ impl GenericRegressionTask {
             &report_dirs,
             &self.config.report_list,
             &self.config.readonly_inputs,
+            &self.config.bisect,
             self,
         )
         .await?;
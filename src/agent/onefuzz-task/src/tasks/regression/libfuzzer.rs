@@ -3,18 +3,30 @@
 
 use crate::tasks::{
     config::CommonConfig,
-    report::{crash_report::CrashTestResult, libfuzzer_report},
+    report::{
+        crash_report::CrashTestResult, libfuzzer_report, redact::Redactor, repro::ReproRetries,
+    },
+    symbols::debug_file_dirs,
     utils::{default_bool_true, try_resolve_setup_relative_path},
 };
 
 use anyhow::{Context, Result};
 use reqwest::Url;
 
-use super::common::{self, RegressionHandler};
+use super::{
+    bisect::BisectConfig,
+    common::{self, RegressionHandler},
+    verdict::build_id,
+};
 use async_trait::async_trait;
+use coverage::path_map::PathMap;
 use onefuzz::syncdir::SyncedDir;
 use serde::Deserialize;
-use std::{collections::HashMap, path::PathBuf};
+use stacktrace_parser::StackNormalization;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 
 #[derive(Debug, Deserialize)]
 pub struct Config {
@@ -36,6 +48,10 @@ pub struct Config {
     pub no_repro: Option<SyncedDir>,
     pub readonly_inputs: Option<SyncedDir>,
 
+    /// Bisect historical builds for the first which reproduces each crash,
+    /// instead of checking it against the current build.
+    pub bisect: Option<BisectConfig>,
+
     #[serde(default = "default_bool_true")]
     pub check_fuzzer_help: bool,
     #[serde(default)]
@@ -55,18 +71,35 @@ pub struct LibFuzzerRegressionTask {
 #[async_trait]
 impl RegressionHandler for LibFuzzerRegressionTask {
     async fn get_crash_result(&self, input: PathBuf, input_url: Url) -> Result<CrashTestResult> {
+        self.get_build_crash_result(
+            input,
+            input_url,
+            &self.config.common.setup_dir,
+            &ReproRetries::none(),
+        )
+        .await
+    }
+
+    async fn get_build_crash_result(
+        &self,
+        input: PathBuf,
+        input_url: Url,
+        setup_dir: &Path,
+        repro_retries: &ReproRetries,
+    ) -> Result<CrashTestResult> {
         let target_exe =
-            try_resolve_setup_relative_path(&self.config.common.setup_dir, &self.config.target_exe)
-                .await?;
+            try_resolve_setup_relative_path(setup_dir, &self.config.target_exe).await?;
 
+        let extra_setup_dir = self.config.common.extra_setup_dir.as_deref();
+        let debug_file_dirs = debug_file_dirs(setup_dir, extra_setup_dir, &[]);
         let args = libfuzzer_report::TestInputArgs {
             input_url: Some(input_url),
             input: &input,
             target_exe: &target_exe,
             target_options: &self.config.target_options,
             target_env: &self.config.target_env,
-            setup_dir: &self.config.common.setup_dir,
-            extra_setup_dir: self.config.common.extra_setup_dir.as_deref(),
+            setup_dir,
+            extra_setup_dir,
             extra_output_dir: self
                 .config
                 .common
@@ -77,12 +110,25 @@ impl RegressionHandler for LibFuzzerRegressionTask {
             job_id: self.config.common.job_id,
             target_timeout: self.config.target_timeout,
             check_retry_count: self.config.check_retry_count,
+            repro_retries,
             minimized_stack_depth: self.config.minimized_stack_depth,
+            stack_normalization: &StackNormalization::default(),
             machine_identity: self.config.common.machine_identity.clone(),
+            allow_unknown_placeholders: self.config.common.allow_unknown_placeholders,
+            allow_env_placeholders: self.config.common.allow_env_placeholders,
+            target_options_shell: self.config.common.target_options_shell,
+            qemu_user: self.config.common.qemu_user.as_ref(),
+            source_path_map: &PathMap::default(),
+            redactor: &Redactor::default(),
+            debug_file_dirs: &debug_file_dirs,
         };
 
         libfuzzer_report::test_input(args).await
     }
+
+    async fn build(&self) -> Option<String> {
+        build_id(&self.config.common.setup_dir, &self.config.target_exe).await
+    }
 }
 
 impl LibFuzzerRegressionTask {
@@ -110,6 +156,7 @@ impl LibFuzzerRegressionTask {
             &report_dirs,
             &self.config.report_list,
             &self.config.readonly_inputs,
+            &self.config.bisect,
             self,
         )
         .await
@@ -117,3 +164,215 @@ impl LibFuzzerRegressionTask {
         Ok(())
     }
 }
+
+#[cfg(test)]
+#[cfg(target_family = "unix")]
+mod tests {
+    use super::*;
+    use crate::tasks::config::fixture;
+    use crate::tasks::regression::verdict::{RegressionVerdict, Verdict};
+    use crate::tasks::report::{
+        compat::REPORT_SCHEMA_VERSION,
+        crash_report::{CrashReport, InputBlob},
+    };
+    use onefuzz::fs::set_executable;
+    use serde_json::json;
+
+    // Crashes by printing an ASAN log, if `crashes`, or else exits cleanly.
+    fn fake_libfuzzer(crashes: bool) -> String {
+        let asan_log = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../stacktrace-parser/data/stack-traces/libfuzzer-asan-log.txt");
+
+        if crashes {
+            format!("#!/bin/sh\ncat '{}' >&2\nexit 1\n", asan_log.display())
+        } else {
+            "#!/bin/sh\nexit 0\n".to_owned()
+        }
+    }
+
+    async fn task(dir: &Path, script: &str) -> Result<LibFuzzerRegressionTask> {
+        let target_exe = dir.join("fuzz.sh");
+        tokio::fs::write(&target_exe, script).await?;
+        set_executable(&target_exe).await?;
+
+        for name in ["crashes", "reports", "inputs", "regression_reports"] {
+            tokio::fs::create_dir(dir.join(name)).await?;
+        }
+
+        let config = serde_json::from_value(fixture::config(json!({
+            "target_exe": target_exe,
+            "target_env": {},
+            "target_options": [],
+            "check_fuzzer_help": false,
+            "crashes": { "path": dir.join("crashes") },
+            "reports": { "path": dir.join("reports") },
+            "readonly_inputs": { "path": dir.join("inputs") },
+            "regression_reports": { "path": dir.join("regression_reports") },
+            "setup_dir": dir,
+        })))?;
+
+        Ok(LibFuzzerRegressionTask::new(config))
+    }
+
+    // Save the report `original.json` of the crash of the input `crash-1`.
+    async fn save_original_report(dir: &Path) -> Result<()> {
+        let report = CrashReport {
+            schema_version: REPORT_SCHEMA_VERSION,
+            input_blob: Some(InputBlob {
+                account: None,
+                container: None,
+                name: "crash-1".into(),
+            }),
+            call_stack_sha256: "original-stack".into(),
+            ..CrashReport::default()
+        };
+        let data = serde_json::to_vec(&report)?;
+        tokio::fs::write(dir.join("reports/original.json"), data).await?;
+        Ok(())
+    }
+
+    async fn handle_reports(task: &LibFuzzerRegressionTask) -> Result<()> {
+        let reports = task.config.reports.as_ref().unwrap();
+        common::handle_crash_reports(
+            task,
+            &task.config.crashes,
+            &[reports],
+            &None,
+            &task.config.regression_reports,
+            None,
+            &None,
+            &None,
+        )
+        .await
+    }
+
+    async fn load_verdict(dir: &Path, name: &str) -> Result<RegressionVerdict> {
+        let path = dir.join("regression_reports").join(name);
+        Ok(serde_json::from_slice(&tokio::fs::read(path).await?)?)
+    }
+
+    fn build(script: &str) -> Option<String> {
+        Some(format!(
+            "file-{}",
+            onefuzz::sha256::digest(script.as_bytes())
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_verdict_reproduced() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let script = fake_libfuzzer(true);
+        let task = task(dir.path(), &script).await?;
+        tokio::fs::write(dir.path().join("crashes/crash-1"), "crashing input").await?;
+        save_original_report(dir.path()).await?;
+
+        handle_reports(&task).await?;
+
+        let verdict = load_verdict(dir.path(), "original.verdict.json").await?;
+        assert_eq!(verdict.verdict, Verdict::Reproduced);
+        assert_eq!(verdict.original_report.as_deref(), Some("original.json"));
+        assert_eq!(
+            verdict.original_unique_key.as_deref(),
+            Some("original-stack")
+        );
+        assert_eq!(
+            verdict.input_sha256,
+            Some(onefuzz::sha256::digest(b"crashing input"))
+        );
+        assert_eq!(verdict.build, build(&script));
+        assert_eq!(verdict.attempts, 1);
+        assert_eq!(verdict.exit_status.and_then(|status| status.code), Some(1));
+        assert_eq!(verdict.error, None);
+        assert_eq!(verdict.regression_report.as_deref(), Some("original.json"));
+        assert!(dir
+            .path()
+            .join("regression_reports/original.json")
+            .is_file());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_verdict_fixed() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let script = fake_libfuzzer(false);
+        let task = task(dir.path(), &script).await?;
+        tokio::fs::write(dir.path().join("crashes/crash-1"), "crashing input").await?;
+        save_original_report(dir.path()).await?;
+
+        handle_reports(&task).await?;
+
+        let verdict = load_verdict(dir.path(), "original.verdict.json").await?;
+        assert_eq!(verdict.verdict, Verdict::Fixed);
+        assert_eq!(verdict.original_report.as_deref(), Some("original.json"));
+        assert_eq!(
+            verdict.original_unique_key.as_deref(),
+            Some("original-stack")
+        );
+        assert_eq!(verdict.build, build(&script));
+        assert_eq!(verdict.attempts, 1);
+        assert_eq!(verdict.exit_status.and_then(|status| status.code), Some(0));
+        assert_eq!(verdict.regression_report.as_deref(), Some("original.json"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_verdict_no_repro() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let script = fake_libfuzzer(false);
+        let task = task(dir.path(), &script).await?;
+        tokio::fs::write(dir.path().join("inputs/input-1"), "benign input").await?;
+
+        common::handle_inputs(
+            &task,
+            task.config.readonly_inputs.as_ref().unwrap(),
+            &task.config.regression_reports,
+            None,
+            &None,
+            &None,
+        )
+        .await?;
+
+        let sha256 = onefuzz::sha256::digest(b"benign input");
+        let verdict = load_verdict(dir.path(), &format!("{sha256}.verdict.json")).await?;
+        assert_eq!(verdict.verdict, Verdict::NoRepro);
+        assert_eq!(verdict.original_report, None);
+        assert_eq!(verdict.original_unique_key, None);
+        assert_eq!(verdict.input_sha256.as_deref(), Some(sha256.as_str()));
+        assert_eq!(verdict.build, build(&script));
+        assert_eq!(verdict.attempts, 1);
+        assert_eq!(verdict.exit_status.and_then(|status| status.code), Some(0));
+        assert_eq!(verdict.regression_report, Some(format!("{sha256}.json")));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_verdict_error() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let script = fake_libfuzzer(true);
+        let task = task(dir.path(), &script).await?;
+        // The input of the report is missing, so it can't be tested.
+        save_original_report(dir.path()).await?;
+
+        handle_reports(&task).await?;
+
+        let verdict = load_verdict(dir.path(), "original.verdict.json").await?;
+        assert_eq!(verdict.verdict, Verdict::Error);
+        assert_eq!(verdict.original_report.as_deref(), Some("original.json"));
+        assert_eq!(
+            verdict.original_unique_key.as_deref(),
+            Some("original-stack")
+        );
+        assert_eq!(verdict.input_sha256, None);
+        assert_eq!(verdict.build, build(&script));
+        assert_eq!(verdict.attempts, 0);
+        assert_eq!(verdict.exit_status, None);
+        assert!(verdict.error.is_some_and(|error| error.contains("crash-1")));
+        assert_eq!(verdict.regression_report, None);
+        assert!(!dir.path().join("regression_reports/original.json").exists());
+
+        Ok(())
+    }
+}
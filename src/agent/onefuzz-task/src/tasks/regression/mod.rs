@@ -1,6 +1,8 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
+pub mod bisect;
 pub mod common;
 pub mod generic;
 pub mod libfuzzer;
+pub mod verdict;
@@ -0,0 +1,230 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! The verdict of checking an input against the current build of the target,
+//! saved beside its regression report and linked to the report it came from.
+
+use crate::tasks::{
+    report::crash_report::{CrashTestResult, RegressionReport},
+    utils::try_resolve_setup_relative_path,
+};
+use anyhow::Result;
+use onefuzz::{process::ExitStatus, sha256, syncdir::SyncedDir};
+use onefuzz_result::job_result::TaskJobResultClient;
+use onefuzz_telemetry::{
+    Event::{regression_error, regression_report, regression_unable_to_reproduce},
+    EventData,
+};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum Verdict {
+    /// The crash reproduced.
+    Reproduced,
+
+    /// The crash of the original report no longer reproduces.
+    Fixed,
+
+    /// The input didn't crash, and wasn't known to.
+    NoRepro,
+
+    /// The input couldn't be tested.
+    Error,
+}
+
+impl Verdict {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Reproduced => "reproduced",
+            Self::Fixed => "fixed",
+            Self::NoRepro => "no_repro",
+            Self::Error => "error",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct RegressionVerdict {
+    pub verdict: Verdict,
+
+    /// The name of the report the input came from, and the unique key of its
+    /// crash, as in the names of unique reports.
+    pub original_report: Option<String>,
+    pub original_unique_key: Option<String>,
+
+    pub input_sha256: Option<String>,
+
+    /// The identity of the build tested, that of its target executable. See
+    /// [`build_id()`].
+    pub build: Option<String>,
+
+    /// Attempts to reproduce the crash, and the exit status of the last run of
+    /// the target, if it ran outside the debugger.
+    pub attempts: u64,
+    pub exit_status: Option<ExitStatus>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+
+    /// The name of the regression report, unless the input couldn't be tested.
+    pub regression_report: Option<String>,
+}
+
+impl RegressionVerdict {
+    /// The verdict of `result`, the test of an input which came from the
+    /// report `original`, if any.
+    pub fn new(
+        result: &Result<CrashTestResult>,
+        original: Option<(&str, &CrashTestResult)>,
+        build: Option<String>,
+    ) -> Self {
+        let (original_report, original_unique_key) = match original {
+            Some((name, CrashTestResult::CrashReport(report))) => {
+                (Some(name.to_owned()), Some(report.unique_key().to_owned()))
+            }
+            Some((name, CrashTestResult::NoRepro(_))) => (Some(name.to_owned()), None),
+            None => (None, None),
+        };
+        let was_crash = original_unique_key.is_some();
+
+        let mut verdict = Self {
+            verdict: Verdict::Error,
+            original_report,
+            original_unique_key,
+            input_sha256: None,
+            build,
+            attempts: 0,
+            exit_status: None,
+            error: None,
+            regression_report: None,
+        };
+
+        let (reproduction, error) = match result {
+            Ok(CrashTestResult::CrashReport(report)) => {
+                verdict.verdict = Verdict::Reproduced;
+                verdict.input_sha256 = Some(report.input_sha256.clone());
+                (report.reproduction.as_ref(), None)
+            }
+            Ok(CrashTestResult::NoRepro(no_repro)) => {
+                verdict.verdict = match (&no_repro.error, was_crash) {
+                    (Some(_), _) => Verdict::Error,
+                    (None, true) => Verdict::Fixed,
+                    (None, false) => Verdict::NoRepro,
+                };
+                verdict.input_sha256 = Some(no_repro.input_sha256.clone());
+                (no_repro.reproduction.as_ref(), no_repro.error.clone())
+            }
+            Err(err) => (None, Some(format!("{err:#}"))),
+        };
+
+        if let Some(reproduction) = reproduction {
+            verdict.attempts = reproduction.attempts.len() as u64;
+            verdict.exit_status = reproduction
+                .attempts
+                .last()
+                .and_then(|attempt| attempt.runs.last())
+                .and_then(|run| run.output.as_ref())
+                .map(|output| output.exit_status);
+        }
+        verdict.error = error;
+
+        verdict
+    }
+
+    /// Save the regression report `report`, if the input was tested, and then
+    /// the verdict, as `{name}.verdict.json` for the report `{name}.json`.
+    pub async fn save(
+        mut self,
+        report: Option<RegressionReport>,
+        report_name: Option<String>,
+        regression_reports: &SyncedDir,
+        jr_client: &Option<TaskJobResultClient>,
+    ) -> Result<()> {
+        if let Some(report) = report {
+            let name = report.name(report_name);
+            report.save(&name, regression_reports, jr_client).await?;
+            self.regression_report = Some(name);
+        }
+
+        let name = self.blob_name();
+        if regression_reports.upload(&name, &self).await? {
+            self.send_event(&name);
+        }
+
+        Ok(())
+    }
+
+    // Named for the regression report, or else for the original report or the
+    // input.
+    fn blob_name(&self) -> String {
+        let stem = self
+            .regression_report
+            .as_deref()
+            .or(self.original_report.as_deref())
+            .or(self.input_sha256.as_deref())
+            .unwrap_or("unknown");
+        let stem = stem.strip_suffix(".json").unwrap_or(stem);
+        format!("{stem}.verdict.json")
+    }
+
+    fn send_event(&self, name: &str) {
+        let event = match self.verdict {
+            Verdict::Reproduced => regression_report,
+            Verdict::Fixed | Verdict::NoRepro => regression_unable_to_reproduce,
+            Verdict::Error => regression_error,
+        };
+        let path = self.regression_report.as_deref().unwrap_or(name).to_owned();
+        let exit_status = self.exit_status.map(describe_exit_status);
+
+        event!(
+            event;
+            EventData::Path = path.clone(),
+            EventData::Verdict = self.verdict.as_str().to_owned(),
+            EventData::OriginalReport = self.original_report.clone().unwrap_or_default(),
+            EventData::UniqueKey = self.original_unique_key.clone().unwrap_or_default(),
+            EventData::Build = self.build.clone().unwrap_or_default(),
+            EventData::Attempts = self.attempts,
+            EventData::ProcessStatus = exit_status.unwrap_or_default()
+        );
+        metric!(
+            event;
+            1.0;
+            EventData::Path = path,
+            EventData::Verdict = self.verdict.as_str().to_owned()
+        );
+    }
+}
+
+fn describe_exit_status(status: ExitStatus) -> String {
+    match (status.code, status.signal) {
+        (Some(code), _) => format!("exit code {code}"),
+        (None, Some(signal)) => format!("signal {signal}"),
+        (None, None) => "unknown".to_owned(),
+    }
+}
+
+/// The identity of the build of `target_exe`, relative to `setup_dir`: its
+/// module ID, or else, as for scripts, `file-<sha256>` of its contents.
+pub async fn build_id(setup_dir: &Path, target_exe: &Path) -> Option<String> {
+    let data = async {
+        let path = try_resolve_setup_relative_path(setup_dir, target_exe).await?;
+        Ok::<_, anyhow::Error>(tokio::fs::read(path).await?)
+    }
+    .await;
+
+    match data {
+        Ok(data) => Some(
+            debuggable_module::module_id(&data)
+                .unwrap_or_else(|| format!("file-{}", sha256::digest(&data))),
+        ),
+        Err(err) => {
+            warn!(
+                "unable to identify build of {}: {:?}",
+                target_exe.display(),
+                err
+            );
+            None
+        }
+    }
+}
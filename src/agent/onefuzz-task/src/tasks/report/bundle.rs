@@ -0,0 +1,342 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Bundles of the runs of inputs which don't reproduce, with the output of the
+//! target, saved to `no_repro` alongside their reports, to explain why.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::Result;
+use onefuzz::{input_tester::TestRun, process::ExitStatus, syncdir::SyncedDir};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    crash_report::{InputBlob, NoCrash},
+    redact::Redactor,
+};
+use crate::tasks::utils::default_bool_true;
+
+/// Default size cap of a bundle, in bytes.
+pub const DEFAULT_BUNDLE_MAX_SIZE: u64 = 1 << 20;
+
+/// Default size cap of the bundles of a task run, in bytes.
+pub const DEFAULT_BUNDLES_MAX_TOTAL_SIZE: u64 = 64 << 20;
+
+pub fn default_bundle_max_size() -> u64 {
+    DEFAULT_BUNDLE_MAX_SIZE
+}
+
+pub fn default_bundles_max_total_size() -> u64 {
+    DEFAULT_BUNDLES_MAX_TOTAL_SIZE
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct NoReproBundles {
+    /// Save a bundle of the runs of each input which doesn't reproduce.
+    #[serde(rename = "no_repro_bundles", default = "default_bool_true")]
+    pub enabled: bool,
+
+    /// The size cap of a bundle, of which the outputs of the target are
+    /// truncated to fit, keeping their ends.
+    #[serde(
+        rename = "no_repro_bundle_max_size",
+        default = "default_bundle_max_size"
+    )]
+    pub max_size: u64,
+
+    /// The size cap of the bundles of a task run, after which bundles are no
+    /// longer saved.
+    #[serde(
+        rename = "no_repro_bundles_max_total_size",
+        default = "default_bundles_max_total_size"
+    )]
+    pub max_total_size: u64,
+}
+
+impl Default for NoReproBundles {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_size: DEFAULT_BUNDLE_MAX_SIZE,
+            max_total_size: DEFAULT_BUNDLES_MAX_TOTAL_SIZE,
+        }
+    }
+}
+
+/// The runs of an input which didn't reproduce.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct NoReproBundle {
+    pub input_sha256: String,
+
+    /// The blob of the input, if it was one, else the name of the copy of the
+    /// input saved with the bundle.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_blob: Option<InputBlob>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input: Option<String>,
+
+    pub runs: Vec<BundleRun>,
+
+    /// Set if outputs of the target were truncated to the size cap.
+    pub truncated: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BundleRun {
+    /// The reproduction attempt of the run, from 0, of which there may be
+    /// several runs, of `check_retry_count`.
+    pub attempt: usize,
+
+    pub command: Vec<String>,
+    pub env: BTreeMap<String, String>,
+    pub duration_ms: u64,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_status: Option<ExitStatus>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stdout: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stderr: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl BundleRun {
+    fn new(attempt: usize, run: &TestRun, redactor: &Redactor) -> Self {
+        let output = run.output.as_ref();
+        Self {
+            attempt,
+            command: run.command.iter().map(|arg| redactor.redact(arg)).collect(),
+            env: run
+                .env
+                .iter()
+                .map(|(name, value)| (name.clone(), redactor.redact(value)))
+                .collect(),
+            duration_ms: u64::try_from(run.duration.as_millis()).unwrap_or(u64::MAX),
+            exit_status: output.map(|output| output.exit_status),
+            stdout: output.map(|output| redactor.redact(&output.stdout)),
+            stderr: output.map(|output| redactor.redact(&output.stderr)),
+            error: run.error.as_deref().map(|error| redactor.redact(error)),
+        }
+    }
+
+    fn outputs(&mut self) -> impl Iterator<Item = &mut String> {
+        self.stdout.iter_mut().chain(&mut self.stderr)
+    }
+}
+
+impl NoReproBundle {
+    fn size(&self) -> Result<u64> {
+        Ok(serde_json::to_vec(self)?.len() as u64)
+    }
+
+    // Truncate the outputs of the runs, keeping their ends, which are the most
+    // likely to tell why the target exited, until the bundle fits `max_size`.
+    fn truncate(&mut self, max_size: u64) -> Result<()> {
+        let size = self.size()?;
+        if size <= max_size {
+            return Ok(());
+        }
+
+        let output_size: u64 = self
+            .runs
+            .iter_mut()
+            .flat_map(BundleRun::outputs)
+            .map(|output| output.len() as u64)
+            .sum();
+        let excess = size - max_size;
+        let outputs = self
+            .runs
+            .iter_mut()
+            .flat_map(BundleRun::outputs)
+            .filter(|output| !output.is_empty())
+            .count() as u64;
+        if outputs == 0 || excess > output_size {
+            bail!("bundle of {size} bytes is over {max_size} bytes without its outputs");
+        }
+
+        // Each output keeps an equal share of the size left for outputs.
+        let share = (output_size - excess) / outputs;
+        for output in self.runs.iter_mut().flat_map(BundleRun::outputs) {
+            keep_tail(output, share as usize);
+        }
+        self.truncated = true;
+
+        // Outputs may be longer as JSON, when escaped.
+        let size = self.size()?;
+        if size > max_size {
+            bail!("truncated bundle of {size} bytes is over {max_size} bytes");
+        }
+
+        Ok(())
+    }
+}
+
+// Keep the last `len` bytes of `text`, or fewer, to split it at a character.
+fn keep_tail(text: &mut String, len: usize) {
+    let Some(mut start) = text.len().checked_sub(len) else {
+        return;
+    };
+    while !text.is_char_boundary(start) {
+        start += 1;
+    }
+    text.drain(..start);
+}
+
+/// Saves the bundles of inputs which don't reproduce, within the size cap of
+/// the bundles of the task run.
+pub struct NoReproBundler {
+    config: NoReproBundles,
+    total_size: u64,
+}
+
+impl NoReproBundler {
+    pub fn new(config: &NoReproBundles) -> Self {
+        Self {
+            config: config.clone(),
+            total_size: 0,
+        }
+    }
+
+    /// Save the bundle of the runs of `no_repro`, of `input`, to `container`,
+    /// redacted by `redactor`, and link it in `no_repro` as `bundle`. Bundles
+    /// which can't be saved, such as over the size cap, are skipped.
+    pub async fn save(
+        &mut self,
+        no_repro: &mut NoCrash,
+        input: &Path,
+        container: &SyncedDir,
+        redactor: &Redactor,
+    ) {
+        if !self.config.enabled {
+            return;
+        }
+
+        match self.try_save(no_repro, input, container, redactor).await {
+            Ok(name) => no_repro.bundle = name,
+            Err(err) => warn!(
+                "unable to save no-repro bundle of {}: {:?}",
+                input.display(),
+                err
+            ),
+        }
+    }
+
+    async fn try_save(
+        &mut self,
+        no_repro: &NoCrash,
+        input: &Path,
+        container: &SyncedDir,
+        redactor: &Redactor,
+    ) -> Result<Option<String>> {
+        let runs: Vec<_> = no_repro
+            .reproduction
+            .iter()
+            .flat_map(|reproduction| reproduction.attempts.iter().enumerate())
+            .flat_map(|(attempt, repro)| {
+                repro
+                    .runs
+                    .iter()
+                    .map(move |run| BundleRun::new(attempt, run, redactor))
+            })
+            .collect();
+        if runs.is_empty() {
+            return Ok(None);
+        }
+
+        // Inputs which aren't blobs, such as of local runs, are copied, if
+        // they fit.
+        let input_size = tokio::fs::metadata(input).await?.len();
+        let copy_input = no_repro.input_blob.is_none() && input_size < self.config.max_size;
+        let input_name = format!("{}.input", no_repro.input_sha256);
+
+        let mut bundle = NoReproBundle {
+            input_sha256: no_repro.input_sha256.clone(),
+            input_blob: no_repro.input_blob.clone(),
+            input: copy_input.then(|| input_name.clone()),
+            runs,
+            truncated: false,
+        };
+        let max_size = if copy_input {
+            self.config.max_size - input_size
+        } else {
+            self.config.max_size
+        };
+        bundle.truncate(max_size)?;
+
+        let size = bundle.size()? + if copy_input { input_size } else { 0 };
+        if self.total_size + size > self.config.max_total_size {
+            bail!(
+                "over the {} byte cap of the bundles of the task",
+                self.config.max_total_size
+            );
+        }
+
+        if copy_input {
+            container.upload_file(input, &input_name).await?;
+        }
+        let name = format!("{}.bundle.json", no_repro.input_sha256);
+        container.upload(&name, &bundle).await?;
+        self.total_size += size;
+
+        Ok(Some(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(stdout: &str, stderr: &str) -> BundleRun {
+        BundleRun {
+            attempt: 0,
+            command: vec!["fuzz".to_string()],
+            env: BTreeMap::new(),
+            duration_ms: 1,
+            exit_status: None,
+            stdout: Some(stdout.to_string()),
+            stderr: Some(stderr.to_string()),
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_truncate() -> Result<()> {
+        let mut bundle = NoReproBundle {
+            input_sha256: "abc".to_string(),
+            input_blob: None,
+            input: None,
+            runs: vec![run(&"a".repeat(1000), "fault"), run(&"b".repeat(1000), "")],
+            truncated: false,
+        };
+        let size = bundle.size()?;
+
+        bundle.truncate(size)?;
+        assert!(!bundle.truncated);
+
+        bundle.truncate(size - 1000)?;
+        assert!(bundle.truncated);
+        assert!(bundle.size()? <= size - 1000);
+
+        // The ends of outputs are kept.
+        let stdout = bundle.runs[0].stdout.as_ref().unwrap();
+        assert!(stdout.len() < 1000 && stdout.chars().all(|c| c == 'a'));
+        assert_eq!(bundle.runs[0].stderr.as_deref(), Some("fault"));
+
+        assert!(bundle.truncate(10).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_keep_tail() {
+        let mut text = "héllo".to_string();
+        keep_tail(&mut text, 4);
+        assert_eq!(text, "llo");
+
+        let mut text = "abc".to_string();
+        keep_tail(&mut text, 5);
+        assert_eq!(text, "abc");
+    }
+}
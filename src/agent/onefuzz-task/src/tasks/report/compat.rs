@@ -0,0 +1,262 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Loading of reports from containers, as saved by earlier and later versions
+//! of the agent, such as by the regression task, which re-reads the reports of
+//! past jobs.
+//!
+//! Reports record the `schema_version` of their fields. Reports of earlier
+//! versions are upgraded to the current schema, with the defaults below. The
+//! fields of a report which this version doesn't know, such as of a later
+//! schema, are kept in its `extra` fields, so that they are saved again as they
+//! were loaded.
+//!
+//! Versions:
+//!
+//! - 0: reports saved before `schema_version`. Their missing `report_type` is
+//!   `crash`, and their `tool_name`, `tool_version` and `onefuzz_version`, if
+//!   missing, are unknown. Their empty `minimized_stack_function_names` and
+//!   `minimized_stack_function_lines`, without hashes, are dropped, as reports
+//!   of later versions don't record them.
+//! - 1: the current schema.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde_json::{Map, Value};
+
+use super::crash_report::{CrashReport, CrashTestResult, NoCrash};
+
+/// The schema version of the reports of this version of the agent.
+pub const REPORT_SCHEMA_VERSION: u32 = 1;
+
+const SCHEMA_VERSION: &str = "schema_version";
+
+// The upgrades of the reports of each version before the current to the next.
+const UPGRADES: [fn(&mut Map<String, Value>); REPORT_SCHEMA_VERSION as usize] = [upgrade_v0];
+
+/// Load the report, or no-repro report, saved at `path`.
+pub async fn parse_report_file(path: impl AsRef<Path>) -> Result<CrashTestResult> {
+    let path = path.as_ref();
+    let raw = tokio::fs::read_to_string(path)
+        .await
+        .with_context(|| format_err!("unable to open crash report: {}", path.display()))?;
+
+    let json: Value = serde_json::from_str(&raw)
+        .with_context(|| format_err!("invalid json: {} - {:?}", path.display(), raw))?;
+
+    parse_report(json)
+        .with_context(|| format_err!("unable to parse report: {} - {:?}", path.display(), raw))
+}
+
+/// Parse the JSON of a report, or no-repro report, of any schema version,
+/// upgraded to the current schema.
+pub fn parse_report(json: Value) -> Result<CrashTestResult> {
+    let json = upgrade_report(json)?;
+
+    let report_err = match serde_json::from_value::<CrashReport>(json.clone()) {
+        Ok(report) => return Ok(CrashTestResult::CrashReport(Box::new(report))),
+        Err(err) => err,
+    };
+
+    let no_repro_err = match serde_json::from_value::<NoCrash>(json) {
+        Ok(no_repro) => return Ok(CrashTestResult::NoRepro(Box::new(no_repro))),
+        Err(err) => err,
+    };
+
+    bail!(
+        "report error: {:?} no_repro error: {:?}",
+        report_err,
+        no_repro_err
+    )
+}
+
+/// Upgrade the JSON of a report of an earlier schema version to the current
+/// one. Reports of later versions are unchanged, and parsed as of the current
+/// version, with the fields it doesn't know kept.
+pub fn upgrade_report(mut json: Value) -> Result<Value> {
+    let Some(fields) = json.as_object_mut() else {
+        bail!("report is not a JSON object");
+    };
+
+    let version = schema_version(fields)?;
+    if version > REPORT_SCHEMA_VERSION {
+        debug!(
+            "report of schema version {} is later than {}",
+            version, REPORT_SCHEMA_VERSION
+        );
+        return Ok(json);
+    }
+
+    for upgrade in &UPGRADES[version as usize..] {
+        upgrade(fields);
+    }
+    fields.insert(SCHEMA_VERSION.to_string(), REPORT_SCHEMA_VERSION.into());
+
+    Ok(json)
+}
+
+// Reports without a `schema_version` are of version 0.
+fn schema_version(fields: &Map<String, Value>) -> Result<u32> {
+    let Some(version) = fields.get(SCHEMA_VERSION) else {
+        return Ok(0);
+    };
+
+    version
+        .as_u64()
+        .and_then(|version| u32::try_from(version).ok())
+        .ok_or_else(|| format_err!("invalid report schema version: {}", version))
+}
+
+fn upgrade_v0(fields: &mut Map<String, Value>) {
+    for (stack, sha256) in [
+        (
+            "minimized_stack_function_names",
+            "minimized_stack_function_names_sha256",
+        ),
+        (
+            "minimized_stack_function_lines",
+            "minimized_stack_function_lines_sha256",
+        ),
+    ] {
+        let empty = fields
+            .get(stack)
+            .and_then(Value::as_array)
+            .is_some_and(Vec::is_empty);
+        let hashed = fields.get(sha256).is_some_and(|sha256| !sha256.is_null());
+
+        if empty && !hashed {
+            fields.remove(stack);
+            fields.remove(sha256);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use stacktrace_parser::ReportType;
+
+    use super::*;
+
+    async fn report(path: &str) -> Result<CrashReport> {
+        match parse_report_file(path).await? {
+            CrashTestResult::CrashReport(report) => Ok(*report),
+            CrashTestResult::NoRepro(_) => bail!("expected CrashReport: {path}"),
+        }
+    }
+
+    // Reports saved by this version are loaded as saved.
+    fn assert_round_trip(report: &CrashReport) -> Result<()> {
+        let saved = serde_json::to_value(report)?;
+        let CrashTestResult::CrashReport(loaded) = parse_report(saved.clone())? else {
+            bail!("expected CrashReport");
+        };
+        assert_eq!(serde_json::to_value(&loaded)?, saved);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_parse_fake_crash_report() -> Result<()> {
+        let report = report("data/fake-crash-report.json").await?;
+
+        assert_eq!(report.schema_version, REPORT_SCHEMA_VERSION);
+        assert_eq!(report.tool_name.as_deref(), Some("libfuzzer"));
+        assert_eq!(report.onefuzz_version.as_deref(), Some("1.2.3"));
+        assert_eq!(report.call_stack, ["#0 fake", "#1 call", "#2 stack"]);
+        assert_eq!(report.minimized_stack, Some(vec![]));
+        assert!(report.minimized_stack_function_names.is_none());
+        assert!(report.extra.is_empty());
+
+        assert_round_trip(&report)
+    }
+
+    #[tokio::test]
+    async fn test_parse_fake_crash_report_old() -> Result<()> {
+        let report = report("data/fake-crash-report-old.json").await?;
+
+        assert_eq!(report.schema_version, REPORT_SCHEMA_VERSION);
+        assert!(report.onefuzz_version.is_none());
+        assert!(report.tool_name.is_none());
+        assert!(report.tool_version.is_none());
+        assert_eq!(report.report_type, ReportType::Crash);
+        assert!(report.extra.is_empty());
+
+        assert_round_trip(&report)
+    }
+
+    #[tokio::test]
+    async fn test_parse_fake_no_repro_report() -> Result<()> {
+        let CrashTestResult::NoRepro(no_repro) =
+            parse_report_file("data/fake-no-repro-report.json").await?
+        else {
+            bail!("expected NoRepro");
+        };
+
+        assert_eq!(no_repro.schema_version, REPORT_SCHEMA_VERSION);
+        assert_eq!(no_repro.tries, 3);
+        assert!(no_repro.extra.is_empty());
+
+        let saved = serde_json::to_value(&no_repro)?;
+        let CrashTestResult::NoRepro(loaded) = parse_report(saved.clone())? else {
+            bail!("expected NoRepro");
+        };
+        assert_eq!(serde_json::to_value(&loaded)?, saved);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_unknown_fields_are_kept() -> Result<()> {
+        let mut json: Value =
+            serde_json::from_str(&tokio::fs::read_to_string("data/fake-crash-report.json").await?)?;
+        json["schema_version"] = json!(REPORT_SCHEMA_VERSION + 1);
+        json["triage"] = json!({"owner": "fuzzing", "priority": 1});
+
+        let CrashTestResult::CrashReport(report) = parse_report(json)? else {
+            bail!("expected CrashReport");
+        };
+        assert_eq!(report.schema_version, REPORT_SCHEMA_VERSION + 1);
+        assert_eq!(
+            report.extra.get("triage"),
+            Some(&json!({"owner": "fuzzing", "priority": 1}))
+        );
+
+        let saved = serde_json::to_value(&report)?;
+        assert_eq!(saved["schema_version"], json!(REPORT_SCHEMA_VERSION + 1));
+        assert_eq!(saved["triage"]["owner"], "fuzzing");
+
+        assert_round_trip(&report)
+    }
+
+    #[test]
+    fn test_upgrade_v0() -> Result<()> {
+        let upgraded = upgrade_report(json!({
+            "minimized_stack_function_names": [],
+            "minimized_stack_function_names_sha256": null,
+            "minimized_stack_function_lines": ["f a.c:1"],
+            "minimized_stack_function_lines_sha256": "abc",
+        }))?;
+
+        assert_eq!(
+            upgraded,
+            json!({
+                "schema_version": REPORT_SCHEMA_VERSION,
+                "minimized_stack_function_lines": ["f a.c:1"],
+                "minimized_stack_function_lines_sha256": "abc",
+            })
+        );
+
+        // Reports of the current version aren't upgraded again.
+        assert_eq!(upgrade_report(upgraded.clone())?, upgraded);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_schema_version() {
+        assert!(upgrade_report(json!({"schema_version": "1"})).is_err());
+        assert!(upgrade_report(json!({"schema_version": -1})).is_err());
+        assert!(upgrade_report(json!([])).is_err());
+    }
+}
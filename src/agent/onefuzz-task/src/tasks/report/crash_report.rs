@@ -1,38 +1,90 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
+use super::{
+    compat::{parse_report_file, REPORT_SCHEMA_VERSION},
+    minidump::MinidumpException,
+    redact::Redactor,
+    repro::Reproduction,
+};
 use anyhow::{Context, Result};
-use onefuzz::{blob::BlobUrl, monitor::DirectoryMonitor, syncdir::SyncedDir};
+use chrono::{DateTime, SecondsFormat, Utc};
+use coverage::path_map::PathMap;
+use onefuzz::{
+    blob::{BlobClient, BlobProvenance, BlobUrl},
+    libfuzzer::ArtifactKind,
+    monitor::DirectoryMonitor,
+    sha256,
+    syncdir::SyncedDir,
+};
 use onefuzz_result::job_result::{JobResultData, JobResultSender, TaskJobResultClient};
 use onefuzz_telemetry::{
-    Event::{
-        new_report, new_unable_to_reproduce, new_unique_report, regression_report,
-        regression_unable_to_reproduce,
-    },
+    Event::{new_report, new_unable_to_reproduce, new_unique_report},
     EventData,
 };
+use reqwest::Url;
 use serde::{Deserialize, Serialize};
-use stacktrace_parser::CrashLog;
-use std::collections::HashMap;
+use stacktrace_parser::{CrashLog, DataRace, Exploitability, ReportType, StackNormalization};
+use std::collections::{BTreeMap, HashMap};
 use std::path::{Path, PathBuf};
 use uuid::Uuid;
 
 #[derive(Debug, Deserialize, Serialize, Default)]
 pub struct CrashReport {
+    /// The version of the schema of the report. Reports of earlier versions
+    /// are upgraded when loaded, as by [`super::compat`].
+    #[serde(default)]
+    pub schema_version: u32,
+
     pub input_sha256: String,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub input_blob: Option<InputBlob>,
 
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_size: Option<u64>,
+
+    /// The URL of the input blob, without its SAS, and, if recorded in its
+    /// metadata, the task which uploaded it and when the input was written.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_task_id: Option<Uuid>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_timestamp: Option<String>,
+
+    /// Set if the input was minimized.
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub minimized_input_blob: Option<InputBlob>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub minimized_input_sha256: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub minimized_input_size: Option<u64>,
+
     pub executable: PathBuf,
 
     pub crash_type: String,
 
+    /// The type of finding, such as `oom` or `leak`, as parsed from the log,
+    /// or as told by the name libFuzzer gave the input. Reports of earlier
+    /// versions, without one, are of crashes.
+    #[serde(default)]
+    pub report_type: ReportType,
+
     pub crash_site: String,
 
     pub call_stack: Vec<String>,
     pub call_stack_sha256: String,
 
+    /// The call stack as normalized, if the task's `stack_normalization` asks
+    /// for it, in which case its hash deduplicates unique reports. The raw
+    /// `call_stack` and its hash are kept.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub normalized_call_stack: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub normalized_call_stack_sha256: Option<String>,
+
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub minimized_stack: Option<Vec<String>>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -47,9 +99,35 @@ pub struct CrashReport {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub minimized_stack_function_lines_sha256: Option<String>,
 
+    /// The scheme of the `minimized_stack*` hashes: the depth of the stacks
+    /// hashed, and the normalization of their frames, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub minimized_stack_depth: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stack_normalization: Option<StackNormalization>,
+
     #[serde(skip_serializing_if = "Option::is_none")]
     pub asan_log: Option<String>,
 
+    /// The conflicting accesses of a data race, whose stacks are deduplicated
+    /// together, as by `call_stack_sha256`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_race: Option<DataRace>,
+
+    /// The exception record of the crash, if reported from a minidump.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub minidump_exception: Option<MinidumpException>,
+
+    /// The minidump of the crash, written by the debugger on Windows when it
+    /// reproduced, if the task's `crash_dumps` is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crash_dump_blob: Option<InputBlob>,
+
+    /// The attempts to reproduce the crash, flagged as `flaky` if it
+    /// reproduced only when retried.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reproduction: Option<Reproduction>,
+
     pub task_id: Uuid,
 
     pub job_id: Uuid,
@@ -60,6 +138,13 @@ pub struct CrashReport {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub scariness_description: Option<String>,
 
+    /// A first-pass, heuristic rating of the crash for triage, and, in
+    /// `exploitability_rationale`, the rule that rated it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exploitability: Option<Exploitability>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exploitability_rationale: Option<String>,
+
     #[serde(skip_serializing_if = "Option::is_none")]
     pub onefuzz_version: Option<String>,
 
@@ -68,10 +153,19 @@ pub struct CrashReport {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tool_version: Option<String>,
+
+    /// The fields of the report this version doesn't know, such as of a later
+    /// schema, kept so that they are saved again.
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_json::Value>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct NoCrash {
+    /// As [`CrashReport::schema_version`].
+    #[serde(default)]
+    pub schema_version: u32,
+
     pub input_sha256: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub input_blob: Option<InputBlob>,
@@ -81,6 +175,17 @@ pub struct NoCrash {
     pub tries: u64,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reproduction: Option<Reproduction>,
+
+    /// The name of the bundle of the runs of the input, with the output of the
+    /// target, saved alongside the report.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bundle: Option<String>,
+
+    /// As [`CrashReport::extra`].
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_json::Value>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -109,27 +214,25 @@ pub struct RegressionReport {
 }
 
 impl RegressionReport {
+    /// The name of the report: `report_name`, that of the original report, or
+    /// else named as the reports of its result.
+    pub fn name(&self, report_name: Option<String>) -> String {
+        report_name.unwrap_or_else(|| match &self.crash_test_result {
+            CrashTestResult::CrashReport(report) => report.unique_blob_name(),
+            CrashTestResult::NoRepro(report) => report.blob_name(),
+        })
+    }
+
+    /// Save the report to `regression_reports` as `name`, returning whether it
+    /// wasn't saved already. Its events are sent with its verdict.
     pub async fn save(
-        self,
-        report_name: Option<String>,
+        &self,
+        name: &str,
         regression_reports: &SyncedDir,
         jr_client: &Option<TaskJobResultClient>,
-    ) -> Result<()> {
-        let (event, name) = match &self.crash_test_result {
-            CrashTestResult::CrashReport(report) => {
-                let name = report_name.unwrap_or_else(|| report.unique_blob_name());
-                (regression_report, name)
-            }
-            CrashTestResult::NoRepro(report) => {
-                let name = report_name.unwrap_or_else(|| report.blob_name());
-                (regression_unable_to_reproduce, name)
-            }
-        };
-
-        if upload_or_save_local(&self, &name, regression_reports).await? {
-            event!(event; EventData::Path = name.clone());
-            metric!(event; 1.0; EventData::Path = name.clone());
-
+    ) -> Result<bool> {
+        let saved = upload_or_save_local(self, name, regression_reports).await?;
+        if saved {
             if let Some(jr_client) = jr_client {
                 let _ = jr_client
                     .send_direct(
@@ -139,6 +242,43 @@ impl RegressionReport {
                     .await;
             }
         }
+        Ok(saved)
+    }
+}
+
+/// Where the reports of findings other than crashes, such as OOMs and leaks,
+/// are saved. Reports of types without a container are saved as crashes.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct ReportRouting {
+    /// Containers for the reports of each type other than `crash`, in place of
+    /// `unique_reports` and `reports`. Reports are deduplicated as by
+    /// `unique_reports`.
+    #[serde(default)]
+    pub report_type_containers: HashMap<ReportType, SyncedDir>,
+
+    /// Types of reports which are not saved.
+    #[serde(default)]
+    pub suppressed_report_types: Vec<ReportType>,
+}
+
+impl ReportRouting {
+    /// Check that reports of crashes are saved as configured.
+    pub fn check(&self) -> Result<()> {
+        if self.report_type_containers.contains_key(&ReportType::Crash) {
+            bail!("`report_type_containers` can't route `crash` reports");
+        }
+        if self.suppressed_report_types.contains(&ReportType::Crash) {
+            bail!("`suppressed_report_types` can't suppress `crash` reports");
+        }
+
+        Ok(())
+    }
+
+    pub async fn init(&self) -> Result<()> {
+        for container in self.report_type_containers.values() {
+            container.init().await?;
+        }
+
         Ok(())
     }
 }
@@ -222,6 +362,72 @@ impl CrashTestResult {
         }
         Ok(())
     }
+
+    /// Saves the crash result as by [`CrashTestResult::save()`], unless it is
+    /// a report of a type which `routing` saves elsewhere, or suppresses.
+    pub async fn save_routed(
+        &self,
+        routing: &ReportRouting,
+        unique_reports: &Option<SyncedDir>,
+        reports: &Option<SyncedDir>,
+        no_repro: &Option<SyncedDir>,
+        jr_client: &Option<TaskJobResultClient>,
+    ) -> Result<()> {
+        let Self::CrashReport(report) = self else {
+            return self
+                .save(unique_reports, reports, no_repro, jr_client)
+                .await;
+        };
+
+        if routing
+            .suppressed_report_types
+            .contains(&report.report_type)
+        {
+            debug!(
+                "suppressing {:?} report: {}",
+                report.report_type,
+                report.unique_blob_name()
+            );
+            return Ok(());
+        }
+
+        match routing.report_type_containers.get(&report.report_type) {
+            Some(container) => {
+                self.save(&Some(container.clone()), &None, &None, jr_client)
+                    .await
+            }
+            None => {
+                self.save(unique_reports, reports, no_repro, jr_client)
+                    .await
+            }
+        }
+    }
+
+    /// Saves the reports of the other findings of a reproduced crash, if any,
+    /// to `unique_reports`, or as routed by `routing`.
+    pub async fn save_other_findings(
+        &self,
+        minimized_stack_depth: Option<usize>,
+        stack_normalization: &StackNormalization,
+        source_path_map: &PathMap,
+        routing: &ReportRouting,
+        unique_reports: &Option<SyncedDir>,
+        jr_client: &Option<TaskJobResultClient>,
+    ) -> Result<()> {
+        let Self::CrashReport(report) = self else {
+            return Ok(());
+        };
+
+        for other in
+            report.other_findings(minimized_stack_depth, stack_normalization, source_path_map)
+        {
+            Self::from(other)
+                .save_routed(routing, unique_reports, &None, &None, jr_client)
+                .await?;
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -242,20 +448,62 @@ impl From<BlobUrl> for InputBlob {
 }
 
 impl CrashReport {
+    /// The report of `crash_log`, with its minimized stacks normalized by
+    /// `stack_normalization`, and the source paths of its stacks rewritten by
+    /// `source_path_map`.
+    ///
+    /// The stacks are hashed as normalized, but before the rewrite, so that
+    /// reports of the same crash in builds at different paths are
+    /// deduplicated as before the rewrite, unless the `map_source_paths` of
+    /// `stack_normalization` asks to hash them as rewritten.
+    ///
+    /// The log, and the path of the executable, are redacted by `redactor`
+    /// before anything is hashed, or rewritten, so that the hashes are of the
+    /// stacks as recorded, and the rules of `source_path_map` match redacted
+    /// paths.
     #[allow(clippy::too_many_arguments)]
     pub fn new(
-        crash_log: CrashLog,
+        mut crash_log: CrashLog,
         task_id: Uuid,
         job_id: Uuid,
         executable: impl Into<PathBuf>,
         input_blob: Option<InputBlob>,
         input_sha256: String,
         minimized_stack_depth: Option<usize>,
+        stack_normalization: &StackNormalization,
+        source_path_map: &PathMap,
+        redactor: &Redactor,
         tool_name: String,
         tool_version: String,
         onefuzz_version: String,
     ) -> Self {
+        redactor.redact_log(&mut crash_log);
+        let executable = redactor.redact_path(&executable.into());
+
         let call_stack_sha256 = crash_log.call_stack_sha256();
+        let exploitability = crash_log.exploitability();
+        let report_type = crash_log.report_type();
+
+        let map_before_hashing = stack_normalization.map_source_paths;
+        if map_before_hashing && !source_path_map.is_empty() {
+            crash_log.map_source_paths(|path| source_path_map.try_map(path));
+        }
+
+        // The options are checked when the task config is loaded.
+        if let Err(err) = crash_log.normalize_minimized_stack(stack_normalization) {
+            warn!("unable to normalize minimized stack: {:?}", err);
+        }
+
+        let normalized_call_stack = crash_log
+            .normalized_call_stack(stack_normalization)
+            .unwrap_or_else(|err| {
+                warn!("unable to normalize call stack: {:?}", err);
+                None
+            });
+        let normalized_call_stack_sha256 = normalized_call_stack
+            .as_ref()
+            .map(|stack| stacktrace_parser::digest_iter(stack, None));
+
         let minimized_stack_sha256 = if crash_log.minimized_stack.is_empty() {
             None
         } else {
@@ -276,6 +524,10 @@ impl CrashReport {
                 Some(crash_log.minimized_stack_function_names_sha256(minimized_stack_depth))
             };
 
+        if !map_before_hashing && !source_path_map.is_empty() {
+            crash_log.map_source_paths(|path| source_path_map.try_map(path));
+        }
+
         let minimized_stack_function_lines = if crash_log.minimized_stack_function_lines.is_empty()
         {
             None
@@ -291,36 +543,153 @@ impl CrashReport {
         };
 
         Self {
+            schema_version: REPORT_SCHEMA_VERSION,
             input_sha256,
             input_blob,
-            executable: executable.into(),
+            input_size: None,
+            input_url: None,
+            input_task_id: None,
+            input_timestamp: None,
+            minimized_input_blob: None,
+            minimized_input_sha256: None,
+            minimized_input_size: None,
+            executable,
             crash_type: crash_log.fault_type,
+            report_type,
             crash_site: crash_log.summary,
             call_stack_sha256,
+            normalized_call_stack,
+            normalized_call_stack_sha256,
             minimized_stack: Some(crash_log.minimized_stack),
             minimized_stack_sha256,
             minimized_stack_function_names,
             minimized_stack_function_names_sha256,
             minimized_stack_function_lines,
             minimized_stack_function_lines_sha256,
+            minimized_stack_depth,
+            stack_normalization: (!stack_normalization.is_empty())
+                .then(|| stack_normalization.clone()),
             call_stack: crash_log.call_stack,
             asan_log: crash_log.text,
+            data_race: crash_log.data_race,
+            minidump_exception: None,
+            crash_dump_blob: None,
+            reproduction: None,
             scariness_score: crash_log.scariness_score,
             scariness_description: crash_log.scariness_description,
+            exploitability: Some(exploitability.exploitability),
+            exploitability_rationale: Some(exploitability.rationale),
             task_id,
             job_id,
             onefuzz_version: Some(onefuzz_version),
             tool_name: Some(tool_name),
             tool_version: Some(tool_version),
+            extra: BTreeMap::new(),
         }
     }
 
+    /// Reports of the findings of the log of the report after its first, such
+    /// as of UBSan run with `halt_on_error=0`, deduplicated. Since they are of
+    /// the same input, they are only saved as unique reports.
+    pub fn other_findings(
+        &self,
+        minimized_stack_depth: Option<usize>,
+        stack_normalization: &StackNormalization,
+        source_path_map: &PathMap,
+    ) -> Vec<CrashReport> {
+        let Some(Ok(findings)) = self.asan_log.clone().map(CrashLog::parse_all) else {
+            return vec![];
+        };
+
+        findings
+            .into_iter()
+            .skip(1)
+            .map(|crash_log| {
+                let report = Self::new(
+                    crash_log,
+                    self.task_id,
+                    self.job_id,
+                    &self.executable,
+                    self.input_blob.clone(),
+                    self.input_sha256.clone(),
+                    minimized_stack_depth,
+                    stack_normalization,
+                    source_path_map,
+                    // The log of the report is redacted.
+                    &Redactor::default(),
+                    self.tool_name.clone().unwrap_or_default(),
+                    self.tool_version.clone().unwrap_or_default(),
+                    self.onefuzz_version.clone().unwrap_or_default(),
+                );
+
+                Self {
+                    input_size: self.input_size,
+                    input_url: self.input_url.clone(),
+                    input_task_id: self.input_task_id,
+                    input_timestamp: self.input_timestamp.clone(),
+                    ..report
+                }
+            })
+            .collect()
+    }
+
+    /// Classify a report of a crash by the name of its input, if libFuzzer
+    /// named it as an artifact of another finding, such as `oom-<sha1>`, even
+    /// if it reproduced as a crash, such as without the fuzzer's limits.
+    pub fn classify_artifact(&mut self, input: &Path) {
+        if self.report_type != ReportType::Crash {
+            return;
+        }
+
+        let Some(file_name) = input.file_name() else {
+            return;
+        };
+        if let Some(report_type) = ArtifactKind::report_type(&file_name.to_string_lossy()) {
+            self.report_type = report_type;
+        }
+    }
+
+    /// Record the provenance of the input blob, from its metadata. Inputs
+    /// which are not blobs in a container, such as local files, have none.
+    pub async fn record_input_provenance(&mut self, input_blob: &BlobUrl) {
+        let BlobUrl::AzureBlob(url) = input_blob else {
+            return;
+        };
+
+        let provenance = match BlobClient::new().get_provenance(url).await {
+            Ok(provenance) => provenance,
+            Err(err) => {
+                warn!("unable to get the provenance of input blob: {:?}", err);
+                BlobProvenance::default()
+            }
+        };
+        self.set_input_provenance(url, provenance);
+    }
+
+    fn set_input_provenance(&mut self, url: &Url, provenance: BlobProvenance) {
+        let mut url = url.clone();
+        url.set_query(None);
+
+        self.input_url = Some(url.to_string());
+        self.input_task_id = provenance.task_id;
+        self.input_timestamp = provenance.timestamp.map(|timestamp| {
+            DateTime::<Utc>::from(timestamp).to_rfc3339_opts(SecondsFormat::Secs, true)
+        });
+    }
+
     pub fn blob_name(&self) -> String {
         format!("{}.json", self.input_sha256)
     }
 
+    /// The key by which reports of the same crash are deduplicated.
+    pub fn unique_key(&self) -> &str {
+        self.normalized_call_stack_sha256
+            .as_ref()
+            .unwrap_or(&self.call_stack_sha256)
+    }
+
     pub fn unique_blob_name(&self) -> String {
-        format!("{}.json", self.call_stack_sha256)
+        format!("{}.json", self.unique_key())
     }
 }
 
@@ -330,33 +699,13 @@ impl NoCrash {
     }
 }
 
-pub async fn parse_report_file(path: PathBuf) -> Result<CrashTestResult> {
-    let raw = std::fs::read_to_string(&path)
-        .with_context(|| format_err!("unable to open crash report: {}", path.display()))?;
-
-    let json: serde_json::Value = serde_json::from_str(&raw)
-        .with_context(|| format_err!("invalid json: {} - {:?}", path.display(), raw))?;
-
-    let report: Result<CrashReport, serde_json::Error> = serde_json::from_value(json.clone());
-
-    let report_err = match report {
-        Ok(report) => return Ok(CrashTestResult::CrashReport(Box::new(report))),
-        Err(err) => err,
-    };
-    let no_repro: Result<NoCrash, serde_json::Error> = serde_json::from_value(json);
-
-    let no_repro_err = match no_repro {
-        Ok(no_repro) => return Ok(CrashTestResult::NoRepro(Box::new(no_repro))),
-        Err(err) => err,
-    };
-
-    bail!(
-        "unable to parse report: {} - {:?} - report error: {:?} no_repo error: {:?}",
-        path.display(),
-        raw,
-        report_err,
-        no_repro_err
-    )
+/// The SHA-256 and the size in bytes of the input, read once.
+pub async fn digest_input(input: &Path) -> Result<(String, u64)> {
+    let data = tokio::fs::read(input)
+        .await
+        .with_context(|| format_err!("unable to sha256 digest input file: {}", input.display()))?;
+
+    Ok((sha256::digest(&data), data.len() as u64))
 }
 
 pub async fn monitor_reports(
@@ -371,7 +720,7 @@ pub async fn monitor_reports(
         return Ok(());
     }
 
-    let mut monitor = DirectoryMonitor::new(base_dir).await?;
+    let mut monitor = DirectoryMonitor::new(base_dir, false).await?;
 
     while let Some(file) = monitor.next_file().await? {
         let result = parse_report_file(file).await?;
@@ -386,26 +735,457 @@ pub async fn monitor_reports(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::tasks::report::redact::Redaction;
     use anyhow::Result;
+    use coverage::path_map::PathRule;
+
+    #[test]
+    fn test_stack_normalization() -> Result<()> {
+        let log = |arg: &str| {
+            format!(
+                "\
+==1==ERROR: AddressSanitizer: heap-use-after-free on address 0x602000000010 at pc 0x4abe12 bp 0x7ffd sp 0x7ffd
+READ of size 4 at 0x602000000010 thread T0
+    #0 0x4abe12 in Table<{arg}>::lookup() /src/table.h:40:7
+    #1 0x4abf00 in LLVMFuzzerTestOneInput /src/fuzz.cc:12:3
+SUMMARY: AddressSanitizer: heap-use-after-free /src/table.h:40:7 in Table<{arg}>::lookup()
+"
+            )
+        };
+        let report = |arg: &str, normalization: &StackNormalization| -> Result<CrashReport> {
+            Ok(CrashReport::new(
+                CrashLog::parse(log(arg))?,
+                Uuid::new_v4(),
+                Uuid::new_v4(),
+                "fuzz",
+                None,
+                "abc".to_owned(),
+                Some(1),
+                normalization,
+                &PathMap::default(),
+                &Redactor::default(),
+                "generic".to_owned(),
+                "1.0".to_owned(),
+                "1.0".to_owned(),
+            ))
+        };
+
+        let default = StackNormalization::default();
+        let (int, long) = (report("int", &default)?, report("long", &default)?);
+        assert_ne!(int.minimized_stack_sha256, long.minimized_stack_sha256);
+        assert_eq!(int.minimized_stack_depth, Some(1));
+        assert!(int.stack_normalization.is_none());
+
+        let normalization = StackNormalization {
+            strip_template_args: true,
+            ..Default::default()
+        };
+        let (int, long) = (
+            report("int", &normalization)?,
+            report("long", &normalization)?,
+        );
+        assert_eq!(int.minimized_stack_sha256, long.minimized_stack_sha256);
+        assert_eq!(int.stack_normalization, Some(normalization));
+
+        // The call stack is hashed as logged.
+        assert_ne!(int.call_stack_sha256, long.call_stack_sha256);
+
+        Ok(())
+    }
+
+    // Two captures of the same crash, from runs of a PIE target with different
+    // ASLR bases, built at different paths.
+    #[test]
+    fn test_normalized_call_stack() -> Result<()> {
+        let log = |base: &str, build: &str| {
+            format!(
+                "\
+==1==ERROR: AddressSanitizer: heap-buffer-overflow on address 0x602000000014 at pc 0x{base}be12 bp 0x7ffd sp 0x7ffd
+READ of size 4 at 0x602000000014 thread T0
+    #0 0x{base}be12 in read_u32 {build}/buf.h:12:10
+    #1 0x{base}be12 in parse_header {build}/parse.c:40:7
+    #2 0x{base}bf00 in LLVMFuzzerTestOneInput {build}/fuzz.cc:12:3
+    #3 0x{base}0c44  (/out/fuzz+0x10c44)
+SUMMARY: AddressSanitizer: heap-buffer-overflow {build}/buf.h:12:10 in read_u32
+"
+            )
+        };
+        let path_map = PathMap::new(vec![
+            PathRule::new("/mnt/build-1", "src"),
+            PathRule::new("/mnt/build-2", "src"),
+        ]);
+        let report = |log: String, normalization: &StackNormalization| -> Result<CrashReport> {
+            Ok(CrashReport::new(
+                CrashLog::parse(log)?,
+                Uuid::new_v4(),
+                Uuid::new_v4(),
+                "fuzz",
+                None,
+                "abc".to_owned(),
+                None,
+                normalization,
+                &path_map,
+                &Redactor::default(),
+                "generic".to_owned(),
+                "1.0".to_owned(),
+                "1.0".to_owned(),
+            ))
+        };
+        let a = log("55d0c1a2", "/mnt/build-1");
+        let b = log("5612f3e4", "/mnt/build-2");
+
+        let default = StackNormalization::default();
+        let (raw_a, raw_b) = (report(a.clone(), &default)?, report(b.clone(), &default)?);
+        assert_eq!(raw_a.normalized_call_stack, None);
+        assert_ne!(raw_a.unique_blob_name(), raw_b.unique_blob_name());
+
+        let normalization = StackNormalization {
+            strip_offsets: true,
+            collapse_inlined_frames: true,
+            map_source_paths: true,
+            normalize_call_stack: true,
+            ..Default::default()
+        };
+        let (a, b) = (report(a, &normalization)?, report(b, &normalization)?);
+        assert_eq!(
+            a.normalized_call_stack.as_deref(),
+            Some(
+                &[
+                    "#0 in parse_header src/parse.c:40:7".to_owned(),
+                    "#1 in LLVMFuzzerTestOneInput src/fuzz.cc:12:3".to_owned(),
+                    "#2  (/out/fuzz)".to_owned(),
+                ][..]
+            )
+        );
+        assert_eq!(a.normalized_call_stack, b.normalized_call_stack);
+        assert_eq!(
+            a.normalized_call_stack_sha256,
+            b.normalized_call_stack_sha256
+        );
+        assert_eq!(a.minimized_stack_sha256, b.minimized_stack_sha256);
+        assert_eq!(a.unique_blob_name(), b.unique_blob_name());
+
+        // The raw call stack is kept, with its hash.
+        assert_eq!(a.call_stack_sha256, raw_a.call_stack_sha256);
+        assert_ne!(a.call_stack_sha256, b.call_stack_sha256);
+        assert_eq!(
+            a.call_stack[0],
+            "#0 0x55d0c1a2be12 in read_u32 src/buf.h:12:10"
+        );
+
+        Ok(())
+    }
+
+    // Redacted before hashing, so that reports of the same crash on the
+    // machines of different users are deduplicated together.
+    #[test]
+    fn test_redaction() -> Result<()> {
+        let log = |user: &str| {
+            format!(
+                "\
+==1==ERROR: AddressSanitizer: heap-use-after-free on address 0x602000000010 at pc 0x4abe12 bp 0x7ffd sp 0x7ffd
+READ of size 4 at 0x602000000010 thread T0
+    #0 0x4abe12 in lookup /home/{user}/src/table.c:40:7
+    #1 0x4abf00 in LLVMFuzzerTestOneInput (/home/{user}/out/fuzz+0x4abf00)
+SUMMARY: AddressSanitizer: heap-use-after-free /home/{user}/src/table.c:40:7 in lookup
+"
+            )
+        };
+        let redaction: Redaction = serde_json::from_str(
+            r#"{"rules": [{"prefix": "/home/bob/src", "replacement": "<SRCROOT>"}]}"#,
+        )?;
+        let redactor = Redactor::new(Some(&redaction))?;
+        let report = |user: &str, redactor: &Redactor| -> Result<CrashReport> {
+            Ok(CrashReport::new(
+                CrashLog::parse(log(user))?,
+                Uuid::new_v4(),
+                Uuid::new_v4(),
+                format!("/home/{user}/out/fuzz"),
+                None,
+                "abc".to_owned(),
+                None,
+                &StackNormalization::default(),
+                &PathMap::default(),
+                redactor,
+                "generic".to_owned(),
+                "1.0".to_owned(),
+                "1.0".to_owned(),
+            ))
+        };
+
+        let (alice, carol) = (report("alice", &redactor)?, report("carol", &redactor)?);
+        assert_eq!(
+            alice.call_stack[0],
+            "#0 0x4abe12 in lookup /home/<USER>/src/table.c:40:7"
+        );
+        assert_eq!(
+            alice.crash_site,
+            "AddressSanitizer: heap-use-after-free /home/<USER>/src/table.c:40:7 in lookup"
+        );
+        assert_eq!(alice.executable, PathBuf::from("/home/<USER>/out/fuzz"));
+        assert_eq!(alice.call_stack_sha256, carol.call_stack_sha256);
+        assert_eq!(alice.minimized_stack_sha256, carol.minimized_stack_sha256);
+        assert_eq!(alice.unique_blob_name(), carol.unique_blob_name());
+        assert!(!serde_json::to_string(&alice)?.contains("alice"));
+
+        // Custom rules are applied before the default rules.
+        let bob = report("bob", &redactor)?;
+        assert_eq!(
+            bob.call_stack[0],
+            "#0 0x4abe12 in lookup <SRCROOT>/table.c:40:7"
+        );
+        assert_eq!(
+            bob.call_stack[1],
+            "#1 0x4abf00 in LLVMFuzzerTestOneInput (/home/<USER>/out/fuzz+0x4abf00)"
+        );
+
+        // Without redaction, the stacks are hashed as logged.
+        let unredacted = report("alice", &Redactor::default())?;
+        assert!(unredacted.asan_log.unwrap().contains("/home/alice/"));
+        assert_ne!(unredacted.call_stack_sha256, alice.call_stack_sha256);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_other_findings() -> Result<()> {
+        let log = "\
+a.c:3:5: runtime error: signed integer overflow: 2147483647 + 1 cannot be represented in type 'int'
+    #0 0x4abe12 in check /src/a.c:3:5
+SUMMARY: UndefinedBehaviorSanitizer: undefined-behavior a.c:3:5 in
+a.c:9:7: runtime error: load of null pointer of type 'int'
+    #0 0x4abe5a in lookup /src/a.c:9:7
+SUMMARY: UndefinedBehaviorSanitizer: undefined-behavior a.c:9:7 in
+";
+        let report = CrashReport::new(
+            CrashLog::parse(log.to_owned())?,
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            "fuzz",
+            None,
+            "abc".to_owned(),
+            None,
+            &StackNormalization::default(),
+            &PathMap::default(),
+            &Redactor::default(),
+            "generic".to_owned(),
+            "1.0".to_owned(),
+            "1.0".to_owned(),
+        );
+        assert_eq!(report.crash_type, "signed-integer-overflow");
+
+        let others =
+            report.other_findings(None, &StackNormalization::default(), &PathMap::default());
+        assert_eq!(others.len(), 1);
+        assert_eq!(others[0].crash_type, "null-pointer-use");
+        assert_eq!(others[0].input_sha256, "abc");
+        assert_ne!(others[0].unique_blob_name(), report.unique_blob_name());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_data_race() -> Result<()> {
+        let log = "\
+WARNING: ThreadSanitizer: data race (pid=1)
+  Write of size 4 at 0x7b0400000010 by thread T1:
+    #0 Counter::Increment() /build/counter.cc:18:12 (counter+0xd1d95)
+
+  Previous read of size 4 at 0x7b0400000010 by main thread:
+    #0 Counter::Get() const /build/counter.cc:23:12 (counter+0xd1e42)
+";
+        let swapped_log = "\
+WARNING: ThreadSanitizer: data race (pid=2)
+  Read of size 4 at 0x7b0400000010 by main thread:
+    #0 Counter::Get() const /build/counter.cc:23:12 (counter+0xd1e42)
+
+  Previous write of size 4 at 0x7b0400000010 by thread T1:
+    #0 Counter::Increment() /build/counter.cc:18:12 (counter+0xd1d95)
+";
+        let report = |log: &str| -> Result<CrashReport> {
+            let path_map = PathMap::new(vec![PathRule::new("/build", "src")]);
+
+            Ok(CrashReport::new(
+                CrashLog::parse(log.to_owned())?,
+                Uuid::new_v4(),
+                Uuid::new_v4(),
+                "fuzz",
+                None,
+                "abc".to_owned(),
+                None,
+                &StackNormalization::default(),
+                &path_map,
+                &Redactor::default(),
+                "generic".to_owned(),
+                "1.0".to_owned(),
+                "1.0".to_owned(),
+            ))
+        };
+
+        let reported = report(log)?;
+        assert_eq!(reported.crash_type, "data-race");
+        assert_eq!(
+            reported.crash_site,
+            "ThreadSanitizer: data-race /build/counter.cc:18:12 in Counter::Increment()"
+        );
+
+        let race = reported.data_race.as_ref().unwrap();
+        assert_eq!(race.accesses.len(), 2);
+        assert_eq!(
+            race.accesses[1].stack[0].source_file_path.as_deref(),
+            Some("src/counter.cc")
+        );
+
+        // The same race, reported from its other access.
+        let swapped = report(swapped_log)?;
+        assert_eq!(swapped.crash_site, reported.crash_site);
+        assert_eq!(swapped.unique_blob_name(), reported.unique_blob_name());
+
+        Ok(())
+    }
 
     #[tokio::test]
-    async fn test_parse_fake_crash_report() -> Result<()> {
-        let path = std::path::PathBuf::from("data/fake-crash-report.json");
-        parse_report_file(path).await?;
+    async fn test_digest_input() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("crash");
+        tokio::fs::write(&input, "crashing input").await?;
+
+        let (sha256, size) = digest_input(&input).await?;
+        assert_eq!(sha256, sha256::digest(b"crashing input"));
+        assert_eq!(sha256, sha256::digest_file(&input).await?);
+        assert_eq!(size, 14);
+
         Ok(())
     }
 
     #[tokio::test]
-    async fn test_parse_fake_crash_report_old() -> Result<()> {
-        let path = std::path::PathBuf::from("data/fake-crash-report-old.json");
-        if let CrashTestResult::CrashReport(report) = parse_report_file(path).await? {
-            assert!(report.onefuzz_version.is_none());
-            assert!(report.tool_name.is_none());
-            assert!(report.tool_version.is_none());
-        } else {
-            panic!("expected CrashReport");
+    async fn test_input_provenance() -> Result<()> {
+        let url = Url::parse(
+            "https://fuzz.blob.core.windows.net/crashes/crash-1?sv=2021-08-06&sig=secret",
+        )?;
+        let task_id = Uuid::new_v4();
+
+        let mut report = CrashReport::default();
+        report.set_input_provenance(
+            &url,
+            BlobProvenance {
+                task_id: Some(task_id),
+                timestamp: Some(
+                    std::time::SystemTime::UNIX_EPOCH
+                        + std::time::Duration::from_secs(1_698_832_800),
+                ),
+            },
+        );
+        assert_eq!(
+            report.input_url.as_deref(),
+            Some("https://fuzz.blob.core.windows.net/crashes/crash-1")
+        );
+        assert_eq!(report.input_task_id, Some(task_id));
+        assert_eq!(
+            report.input_timestamp.as_deref(),
+            Some("2023-11-01T10:00:00Z")
+        );
+
+        // Blobs uploaded by other tools have no provenance metadata.
+        let mut report = CrashReport::default();
+        report.set_input_provenance(&url, BlobProvenance::default());
+        assert!(report.input_url.is_some());
+        assert!(report.input_task_id.is_none());
+        assert!(report.input_timestamp.is_none());
+
+        let json = serde_json::to_value(&report)?;
+        assert!(json.get("input_task_id").is_none());
+        assert!(json.get("input_timestamp").is_none());
+
+        // Nor do local files.
+        let mut report = CrashReport::default();
+        report
+            .record_input_provenance(&BlobUrl::LocalFile("crash-1".into()))
+            .await;
+        assert!(report.input_url.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_classify_artifact() {
+        let mut report = CrashReport::default();
+        report.classify_artifact(Path::new("crashes/oom-1"));
+        assert_eq!(report.report_type, ReportType::Oom);
+
+        // The type parsed from the log takes precedence.
+        let mut report = CrashReport {
+            report_type: ReportType::Leak,
+            ..CrashReport::default()
+        };
+        report.classify_artifact(Path::new("crashes/timeout-1"));
+        assert_eq!(report.report_type, ReportType::Leak);
+
+        let mut report = CrashReport::default();
+        report.classify_artifact(Path::new("crashes/input"));
+        assert_eq!(report.report_type, ReportType::Crash);
+    }
+
+    #[tokio::test]
+    async fn test_save_routed() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let synced_dir = |name: &str| -> Result<SyncedDir> {
+            let local_path = dir.path().join(name);
+            std::fs::create_dir(&local_path)?;
+            Ok(SyncedDir {
+                local_path,
+                remote_path: None,
+            })
+        };
+        let names = |name: &str| -> Result<Vec<String>> {
+            let mut names = std::fs::read_dir(dir.path().join(name))?
+                .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+                .collect::<Result<Vec<_>>>()?;
+            names.sort();
+            Ok(names)
+        };
+
+        let unique_reports = Some(synced_dir("unique_reports")?);
+        let reports = Some(synced_dir("reports")?);
+        let routing: ReportRouting = serde_json::from_value(serde_json::json!({
+            "report_type_containers": { "oom": { "path": dir.path().join("oom") } },
+            "suppressed_report_types": ["leak"],
+        }))?;
+        std::fs::create_dir(dir.path().join("oom"))?;
+        routing.check()?;
+
+        for (report_type, name) in [
+            (ReportType::Crash, "crash"),
+            (ReportType::Oom, "oom"),
+            (ReportType::Leak, "leak"),
+        ] {
+            let report = CrashReport {
+                input_sha256: format!("{name}-input"),
+                call_stack_sha256: format!("{name}-stack"),
+                report_type,
+                ..CrashReport::default()
+            };
+            CrashTestResult::from(report)
+                .save_routed(&routing, &unique_reports, &reports, &None, &None)
+                .await?;
         }
 
+        assert_eq!(names("unique_reports")?, ["crash-stack.json"]);
+        assert_eq!(names("reports")?, ["crash-input.json"]);
+        assert_eq!(names("oom")?, ["oom-stack.json"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_report_routing_check() -> Result<()> {
+        let routing: ReportRouting = serde_json::from_value(serde_json::json!({
+            "suppressed_report_types": ["crash"],
+        }))?;
+        assert!(routing.check().is_err());
+
+        assert!(ReportRouting::default().check().is_ok());
+
         Ok(())
     }
 }
@@ -44,12 +44,12 @@ pub async fn collect_exception_info(
 const DUMP_FILE_NAME: &str = "tmp.dmp";
 
 // See: https://docs.microsoft.com/en-us/dotnet/core/diagnostics/dumps
-const ENABLE_MINIDUMP_VAR: &str = "COMPlus_DbgEnableMiniDump";
-const MINIDUMP_TYPE_VAR: &str = "COMPlus_DbgMiniDumpType";
-const MINIDUMP_NAME_VAR: &str = "COMPlus_DbgMiniDumpName";
+pub(crate) const ENABLE_MINIDUMP_VAR: &str = "COMPlus_DbgEnableMiniDump";
+pub(crate) const MINIDUMP_TYPE_VAR: &str = "COMPlus_DbgMiniDumpType";
+pub(crate) const MINIDUMP_NAME_VAR: &str = "COMPlus_DbgMiniDumpName";
 
-const MINIDUMP_ENABLE: &str = "1";
-const MINIDUMP_TYPE_FULL: &str = "4";
+pub(crate) const MINIDUMP_ENABLE: &str = "1";
+pub(crate) const MINIDUMP_TYPE_FULL: &str = "4";
 
 // Invoke target with .NET runtime environment vars set to create minidumps.
 //
@@ -2,12 +2,13 @@
 // Licensed under the MIT License.
 
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     env,
     path::{Path, PathBuf},
     sync::Arc,
 };
 
+use crate::tasks::report::compat::REPORT_SCHEMA_VERSION;
 use crate::tasks::report::crash_report::*;
 use crate::tasks::report::dotnet::common::collect_exception_info;
 use crate::tasks::{
@@ -135,7 +136,7 @@ impl AsanProcessor {
         // Try to expand `target_exe` with support for `{tools_dir}`.
         //
         // Allows using `LibFuzzerDotnetLoader.exe` from a shared tools container.
-        let expand = Expand::new(&self.config.common.machine_identity).tools_dir(tools_dir);
+        let expand = self.config.common.expand().tools_dir(tools_dir);
         let expanded = expand.evaluate_value(self.config.target_exe.to_string_lossy())?;
         let expanded_path = Path::new(&expanded);
 
@@ -183,7 +184,10 @@ impl AsanProcessor {
         let mut args = vec![target_exe];
         args.extend(self.config.target_options.clone());
 
-        let expand = Expand::new(&self.config.common.machine_identity)
+        let expand = self
+            .config
+            .common
+            .expand()
             .input_path(input)
             .setup_dir(&self.config.common.setup_dir)
             .set_optional_ref(&self.config.common.extra_setup_dir, Expand::extra_setup_dir)
@@ -209,32 +213,53 @@ impl AsanProcessor {
                 let call_stack_sha256 = stacktrace_parser::digest_iter(&exception.call_stack, None);
 
                 let crash_report = CrashReport {
+                    schema_version: REPORT_SCHEMA_VERSION,
                     input_sha256,
                     input_blob,
+                    input_size: None,
+                    input_url: None,
+                    input_task_id: None,
+                    input_timestamp: None,
+                    minimized_input_blob: None,
+                    minimized_input_sha256: None,
+                    minimized_input_size: None,
                     executable,
                     crash_type: exception.exception,
+                    report_type: stacktrace_parser::ReportType::Crash,
                     crash_site: exception.call_stack.first().cloned().unwrap_or_default(),
                     call_stack: exception.call_stack,
                     call_stack_sha256,
+                    normalized_call_stack: None,
+                    normalized_call_stack_sha256: None,
                     minimized_stack: None,
                     minimized_stack_sha256: None,
                     minimized_stack_function_names: None,
                     minimized_stack_function_names_sha256: None,
                     minimized_stack_function_lines: None,
                     minimized_stack_function_lines_sha256: None,
+                    minimized_stack_depth: None,
+                    stack_normalization: None,
                     asan_log: None,
+                    data_race: None,
+                    minidump_exception: None,
+                    crash_dump_blob: None,
+                    reproduction: None,
                     task_id,
                     job_id,
                     scariness_score: None,
                     scariness_description: None,
+                    exploitability: None,
+                    exploitability_rationale: None,
                     onefuzz_version: Some(env!("ONEFUZZ_VERSION").to_owned()),
                     tool_name: Some(DOTNET_DUMP_TOOL_NAME.to_owned()),
                     tool_version: None,
+                    extra: BTreeMap::new(),
                 };
 
                 crash_report.into()
             } else {
                 let no_repro = NoCrash {
+                    schema_version: REPORT_SCHEMA_VERSION,
                     input_sha256,
                     input_blob,
                     executable,
@@ -242,6 +267,9 @@ impl AsanProcessor {
                     task_id,
                     tries: 1,
                     error: None,
+                    reproduction: None,
+                    bundle: None,
+                    extra: BTreeMap::new(),
                 };
 
                 no_repro.into()
@@ -1,26 +1,50 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
-use super::crash_report::{CrashReport, CrashTestResult, InputBlob, NoCrash};
+use super::{
+    bundle::{NoReproBundler, NoReproBundles},
+    compat::REPORT_SCHEMA_VERSION,
+    crash_report::{digest_input, CrashReport, CrashTestResult, InputBlob, NoCrash, ReportRouting},
+    minidump::{is_minidump, test_dump},
+    minimize::{default_minimize_timeout, minimize_crash},
+    pool::{default_report_concurrency, ReportPool, ReportProcessor},
+    redact::{Redaction, Redactor},
+    repro::ReproRetries,
+};
 use crate::tasks::{
     config::CommonConfig,
-    generic::input_poller::{CallbackImpl, InputPoller, Processor},
+    generic::input_poller::CallbackImpl,
     heartbeat::{HeartbeatSender, TaskHeartbeatClient},
+    source_paths::{load_path_map, SourcePathRule},
+    symbols::{debug_file_dirs, TargetSymbols},
     utils::{default_bool_true, try_resolve_setup_relative_path},
 };
 use anyhow::{Context, Result};
 use async_trait::async_trait;
+use coverage::path_map::PathMap;
+use debuggable_module::debugfile::DebugFileDirs;
 use onefuzz::{
-    blob::BlobUrl, input_tester::Tester, machine_id::MachineIdentity, sha256, syncdir::SyncedDir,
+    blob::BlobUrl,
+    crash_dump::CrashDumpConfig,
+    expand::Expand,
+    input_tester::{InputVia, TestOutcome, Tester},
+    machine_id::MachineIdentity,
+    qemu::QemuUser,
+    sanitizer::add_debug_file_dirs,
+    symsrv::SymbolServers,
+    syncdir::SyncedDir,
 };
 use onefuzz_result::job_result::TaskJobResultClient;
 use reqwest::Url;
 use serde::Deserialize;
+use stacktrace_parser::{CrashLog, ReportType, StackNormalization};
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     path::{Path, PathBuf},
+    process::Stdio,
 };
-use storage_queue::{Message, QueueClient};
+use storage_queue::QueueClient;
+use tokio::{process::Command, sync::Mutex};
 use uuid::Uuid;
 
 const GENERIC_TOOL_NAME: &str = "generic";
@@ -35,6 +59,11 @@ pub struct Config {
     #[serde(default)]
     pub target_env: HashMap<String, String>,
 
+    /// How crashing inputs are passed to the target: as the file of
+    /// `{input}`, or on its standard input.
+    #[serde(default)]
+    pub input_via: InputVia,
+
     pub input_queue: Option<QueueClient>,
     pub crashes: Option<SyncedDir>,
     pub reports: Option<SyncedDir>,
@@ -43,6 +72,10 @@ pub struct Config {
 
     pub target_timeout: Option<u64>,
 
+    /// Memory, in MiB, which the target and its descendants may use, before a
+    /// run is killed and reported as out of memory.
+    pub target_memory_limit_mb: Option<u64>,
+
     #[serde(default)]
     pub check_asan_log: bool,
     #[serde(default = "default_bool_true")]
@@ -50,32 +83,117 @@ pub struct Config {
     #[serde(default)]
     pub check_retry_count: u64,
 
+    /// Retries of inputs which don't reproduce, each checked as by
+    /// `check_retry_count`.
+    #[serde(flatten)]
+    pub repro_retries: ReproRetries,
+
     #[serde(default = "default_bool_true")]
     pub check_queue: bool,
 
     #[serde(default)]
     pub minimized_stack_depth: Option<usize>,
 
+    /// Options normalizing the frames of minimized stacks before they are
+    /// hashed, as recorded in each report.
+    #[serde(default)]
+    pub stack_normalization: StackNormalization,
+
+    /// Container for minimized crashing inputs. If set, each reproduced crash
+    /// is minimized with `minimizer_exe`, and linked in its report.
+    pub minimized_crashes: Option<SyncedDir>,
+
+    /// Minimizes `{input}`, writing the minimized input to `{minimized_input}`,
+    /// such as `afl-tmin`.
+    pub minimizer_exe: Option<String>,
+
+    #[serde(default)]
+    pub minimizer_options: Vec<String>,
+
+    /// Time budget for minimizing a crash, in seconds.
+    #[serde(default = "default_minimize_timeout")]
+    pub minimize_timeout: u64,
+
+    /// Rules rewriting the source paths of the stacks of reports, with those
+    /// of the `.srcview.json` file of the setup container.
+    #[serde(default)]
+    pub source_path_map: Vec<SourcePathRule>,
+
+    /// Also report the findings of a crash log after its first, such as of
+    /// UBSan run with `halt_on_error=0`, to `unique_reports`.
+    #[serde(default)]
+    pub report_all_findings: bool,
+
+    /// Where reports of findings other than crashes, such as OOMs, are saved,
+    /// if not to `unique_reports` and `reports`.
+    #[serde(flatten)]
+    pub report_routing: ReportRouting,
+
+    /// Rules redacting the user names and internal paths of reports, if set.
+    #[serde(default)]
+    pub redaction: Option<Redaction>,
+
+    /// Bundles of the runs of inputs which don't reproduce, saved to
+    /// `no_repro`.
+    #[serde(flatten)]
+    pub no_repro_bundles: NoReproBundles,
+
+    /// The number of inputs tested at once, each by its own run of the
+    /// target. Reports are saved one at a time.
+    #[serde(default = "default_report_concurrency")]
+    pub report_concurrency: usize,
+
+    /// Directories searched for the external debug files of stripped targets,
+    /// relative to the setup directory, before the setup directories and the
+    /// debug file directory of the system.
+    #[serde(default)]
+    pub debug_file_dirs: Vec<PathBuf>,
+
+    /// Container for minidumps of crashes caught by the debugger on Windows.
+    /// If set, a dump is written of each reproduced crash, as configured by
+    /// `crash_dump`, and linked in its report.
+    pub crash_dumps: Option<SyncedDir>,
+
+    #[serde(default)]
+    pub crash_dump: CrashDumpConfig,
+
     #[serde(flatten)]
     pub common: CommonConfig,
 }
 
+impl Config {
+    /// Check that crashes can be minimized, if `minimized_crashes` is set.
+    pub fn check_minimizer(&self) -> Result<()> {
+        if self.minimized_crashes.is_some() && self.minimizer_exe.is_none() {
+            bail!("`minimized_crashes` requires `minimizer_exe`");
+        }
+
+        Ok(())
+    }
+}
+
 pub struct ReportTask {
     config: Config,
-    poller: InputPoller<Message>,
 }
 
 impl ReportTask {
     pub fn new(config: Config) -> Self {
-        let poller = InputPoller::new("crash-report");
-        Self { config, poller }
+        Self { config }
     }
 
     pub async fn managed_run(&mut self) -> Result<()> {
         info!("Starting generic crash report task");
+
+        let target_exe =
+            try_resolve_setup_relative_path(&self.config.common.setup_dir, &self.config.target_exe)
+                .await?;
+        TargetSymbols::fetch(&self.config.common, &target_exe)
+            .await
+            .export();
+
         let heartbeat_client = self.config.common.init_heartbeat(None).await?;
         let job_result_client = self.config.common.init_job_result().await?;
-        let mut processor =
+        let processor =
             GenericReportProcessor::new(&self.config, heartbeat_client, job_result_client);
 
         #[allow(clippy::manual_flatten)]
@@ -88,11 +206,19 @@ impl ReportTask {
                 tokio::fs::create_dir_all(&entry.local_path).await?;
             }
         }
+        if let Some(minimized_crashes) = &self.config.minimized_crashes {
+            minimized_crashes.init().await?;
+        }
+        if let Some(crash_dumps) = &self.config.crash_dumps {
+            crash_dumps.init().await?;
+        }
+        self.config.report_routing.init().await?;
+
+        let mut pool = ReportPool::new("crash-report", &processor, self.config.report_concurrency);
 
         info!("processing existing crashes");
         if let Some(crashes) = &self.config.crashes {
-            self.poller
-                .batch_process(&mut processor, crashes)
+            pool.batch_process(crashes)
                 .await
                 .context("batch processing failed")?;
         }
@@ -100,9 +226,9 @@ impl ReportTask {
         info!("processing crashes from queue");
         if self.config.check_queue {
             if let Some(queue) = &self.config.input_queue {
-                let callback = CallbackImpl::new(queue.clone(), processor)
+                let mut callback = CallbackImpl::new(queue.clone(), &processor)
                     .context("processing from queue failed")?;
-                self.poller.run(callback).await.context("poller failed")?;
+                pool.run(&mut callback).await.context("poller failed")?;
             }
         }
         Ok(())
@@ -115,47 +241,92 @@ pub struct TestInputArgs<'a> {
     pub target_exe: &'a Path,
     pub target_options: &'a [String],
     pub target_env: &'a HashMap<String, String>,
+    pub input_via: InputVia,
     pub setup_dir: &'a Path,
     pub extra_setup_dir: Option<&'a Path>,
     pub task_id: Uuid,
     pub job_id: Uuid,
     pub target_timeout: Option<u64>,
+    pub target_memory_limit_mb: Option<u64>,
     pub check_retry_count: u64,
+    pub repro_retries: &'a ReproRetries,
     pub check_asan_log: bool,
     pub check_debugger: bool,
     pub minimized_stack_depth: Option<usize>,
+    pub stack_normalization: &'a StackNormalization,
     pub machine_identity: MachineIdentity,
+    pub allow_unknown_placeholders: bool,
+    pub allow_env_placeholders: bool,
+    pub target_options_shell: bool,
+    pub qemu_user: Option<&'a QemuUser>,
+    pub source_path_map: &'a PathMap,
+    pub redactor: &'a Redactor,
+    pub debug_file_dirs: &'a DebugFileDirs,
+
+    /// Where to write and upload the dumps of crashes, and how.
+    pub crash_dumps: Option<(&'a CrashDumpConfig, &'a SyncedDir)>,
 }
 
 pub async fn test_input(args: TestInputArgs<'_>) -> Result<CrashTestResult> {
-    let extra_setup_dir = args.extra_setup_dir;
-    let tester = Tester::new(
-        args.setup_dir,
-        extra_setup_dir,
-        args.target_exe,
-        args.target_options,
-        args.target_env,
-        args.machine_identity.clone(),
-    )
-    .check_asan_log(args.check_asan_log)
-    .check_debugger(args.check_debugger)
-    .check_retry_count(args.check_retry_count)
-    .set_optional(args.target_timeout, |tester, timeout| {
-        tester.timeout(timeout)
-    });
-
-    let input_sha256 = sha256::digest_file(args.input).await?;
+    let args = &args;
+    let crash_blob = args
+        .input_url
+        .as_ref()
+        .and_then(|u| BlobUrl::new(u.clone()).ok());
+
+    let (input_sha256, input_size) = digest_input(args.input).await?;
     let task_id = args.task_id;
     let job_id = args.job_id;
-    let input_blob = args
-        .input_url
-        .and_then(|u| BlobUrl::new(u).ok())
-        .map(InputBlob::from);
+    let input_blob = crash_blob.clone().map(InputBlob::from);
+
+    let mut target_env = args.target_env.clone();
+    add_debug_file_dirs(&mut target_env, args.debug_file_dirs);
+
+    let crash_blob_ref = crash_blob.as_ref();
+    let (test_report, mut reproduction) = args
+        .repro_retries
+        .test(
+            args.target_timeout,
+            &target_env,
+            |timeout, env| async move {
+                Tester::new(
+                    args.setup_dir,
+                    args.extra_setup_dir,
+                    args.target_exe,
+                    args.target_options,
+                    &env,
+                    args.machine_identity.clone(),
+                )
+                .input_via(args.input_via)
+                .check_asan_log(args.check_asan_log)
+                .check_debugger(args.check_debugger)
+                .check_retry_count(args.check_retry_count)
+                .allow_unknown_placeholders(args.allow_unknown_placeholders)
+                .allow_env_placeholders(args.allow_env_placeholders)
+                .target_options_shell(args.target_options_shell)
+                .set_optional(args.qemu_user, Tester::qemu_user)
+                .set_optional(crash_blob_ref, Tester::crash_blob)
+                .debug_file_dirs(args.debug_file_dirs)
+                .set_optional(timeout, |tester, timeout| tester.timeout(timeout))
+                .set_optional(args.target_memory_limit_mb, Tester::memory_limit_mb)
+                .set_optional(args.crash_dumps, |tester, (config, dir)| {
+                    tester.crash_dump(config, &dir.local_path)
+                })
+                .test_input(args.input)
+                .await
+            },
+        )
+        .await?;
+    args.redactor.redact_reproduction(&mut reproduction);
 
-    let test_report = tester.test_input(args.input).await?;
+    // Runs killed for exceeding a limit are findings, even without a crash.
+    let crash_log = match test_report.crash_log {
+        Some(crash_log) => Some(crash_log),
+        None => limit_crash_log(test_report.outcome)?,
+    };
 
-    if let Some(crash_log) = test_report.crash_log {
-        let crash_report = CrashReport::new(
+    if let Some(crash_log) = crash_log {
+        let mut crash_report = CrashReport::new(
             crash_log,
             task_id,
             job_id,
@@ -163,30 +334,98 @@ pub async fn test_input(args: TestInputArgs<'_>) -> Result<CrashTestResult> {
             input_blob,
             input_sha256,
             args.minimized_stack_depth,
+            args.stack_normalization,
+            args.source_path_map,
+            args.redactor,
             GENERIC_TOOL_NAME.into(),
             env!("ONEFUZZ_VERSION").to_string(),
             env!("ONEFUZZ_VERSION").to_string(),
         );
+        if test_report.outcome == TestOutcome::Timeout {
+            crash_report.report_type = ReportType::Timeout;
+        }
+        crash_report.classify_artifact(args.input);
+        crash_report.input_size = Some(input_size);
+        crash_report.reproduction = Some(reproduction);
+        if let Some(crash_blob) = &crash_blob {
+            crash_report.record_input_provenance(crash_blob).await;
+        }
+        if let (Some(crash_dump), Some((_, crash_dumps))) =
+            (&test_report.crash_dump, args.crash_dumps)
+        {
+            upload_crash_dump(&mut crash_report, crash_dump, crash_dumps).await;
+        }
         Ok(CrashTestResult::CrashReport(Box::new(crash_report)))
     } else {
         let no_repro = NoCrash {
+            schema_version: REPORT_SCHEMA_VERSION,
             input_blob,
             input_sha256,
-            executable: PathBuf::from(args.target_exe),
+            executable: args.redactor.redact_path(args.target_exe),
             task_id,
             job_id,
-            tries: 1 + args.check_retry_count,
-            error: test_report.error.map(|e| format!("{e}")),
+            tries: (1 + args.check_retry_count) * reproduction.attempts.len() as u64,
+            error: test_report
+                .error
+                .map(|e| args.redactor.redact(&format!("{e}"))),
+            reproduction: Some(reproduction),
+            bundle: None,
+            extra: BTreeMap::new(),
         };
 
         Ok(CrashTestResult::NoRepro(Box::new(no_repro)))
     }
 }
 
+// Upload the dump of the crash of `report`, and link it in the report, unless
+// it fails, in which case the report is saved without it.
+async fn upload_crash_dump(report: &mut CrashReport, crash_dump: &Path, crash_dumps: &SyncedDir) {
+    let Some(name) = crash_dump.file_name().and_then(|name| name.to_str()) else {
+        return;
+    };
+
+    match crash_dumps.upload_file(crash_dump, name).await {
+        Ok(blob) => report.crash_dump_blob = Some(InputBlob::from(blob)),
+        Err(err) => warn!(
+            "unable to upload crash dump {}: {:?}",
+            crash_dump.display(),
+            err
+        ),
+    }
+}
+
+// The crash log of a run killed for exceeding a limit, which has no stack, so
+// that all such runs of a target are one unique finding per limit.
+fn limit_crash_log(outcome: TestOutcome) -> Result<Option<CrashLog>> {
+    let fault_type = match outcome {
+        TestOutcome::Timeout => "timeout",
+        TestOutcome::MemoryLimit => "out-of-memory",
+        TestOutcome::CleanExit | TestOutcome::Crash => return Ok(None),
+    };
+
+    let crash_log = CrashLog::new(
+        None,
+        None,
+        GENERIC_TOOL_NAME.into(),
+        fault_type.into(),
+        None,
+        None,
+        vec![],
+    )?;
+
+    Ok(Some(crash_log))
+}
+
 pub struct GenericReportProcessor<'a> {
     config: &'a Config,
     heartbeat_client: Option<TaskHeartbeatClient>,
     job_result_client: Option<TaskJobResultClient>,
+
+    /// Used to symbolize the stacks of minidumps.
+    symbol_servers: Option<SymbolServers>,
+
+    /// Locked by the saves of reports, one at a time.
+    bundler: Mutex<NoReproBundler>,
 }
 
 impl<'a> GenericReportProcessor<'a> {
@@ -195,10 +434,17 @@ impl<'a> GenericReportProcessor<'a> {
         heartbeat_client: Option<TaskHeartbeatClient>,
         job_result_client: Option<TaskJobResultClient>,
     ) -> Self {
+        let symbol_servers = config.common.symbol_servers().unwrap_or_else(|err| {
+            warn!("unable to configure symbol servers: {:?}", err);
+            None
+        });
+
         Self {
             config,
             heartbeat_client,
             job_result_client,
+            symbol_servers,
+            bundler: Mutex::new(NoReproBundler::new(&config.no_repro_bundles)),
         }
     }
 
@@ -213,6 +459,15 @@ impl<'a> GenericReportProcessor<'a> {
             try_resolve_setup_relative_path(&self.config.common.setup_dir, &self.config.target_exe)
                 .await?;
 
+        let source_path_map =
+            load_path_map(&self.config.source_path_map, &self.config.common.setup_dir).await?;
+        let redactor = Redactor::new(self.config.redaction.as_ref())?;
+        let debug_file_dirs = debug_file_dirs(
+            &self.config.common.setup_dir,
+            self.config.common.extra_setup_dir.as_deref(),
+            &self.config.debug_file_dirs,
+        );
+
         let extra_setup_dir = self.config.common.extra_setup_dir.as_deref();
         let args = TestInputArgs {
             input_url,
@@ -220,37 +475,174 @@ impl<'a> GenericReportProcessor<'a> {
             target_exe: &target_exe,
             target_options: &self.config.target_options,
             target_env: &self.config.target_env,
+            input_via: self.config.input_via,
             setup_dir: &self.config.common.setup_dir,
             extra_setup_dir,
             task_id: self.config.common.task_id,
             job_id: self.config.common.job_id,
             target_timeout: self.config.target_timeout,
+            target_memory_limit_mb: self.config.target_memory_limit_mb,
             check_retry_count: self.config.check_retry_count,
+            repro_retries: &self.config.repro_retries,
             check_asan_log: self.config.check_asan_log,
             check_debugger: self.config.check_debugger,
             minimized_stack_depth: self.config.minimized_stack_depth,
+            stack_normalization: &self.config.stack_normalization,
             machine_identity: self.config.common.machine_identity.clone(),
+            allow_unknown_placeholders: self.config.common.allow_unknown_placeholders,
+            allow_env_placeholders: self.config.common.allow_env_placeholders,
+            target_options_shell: self.config.common.target_options_shell,
+            qemu_user: self.config.common.qemu_user.as_ref(),
+            source_path_map: &source_path_map,
+            redactor: &redactor,
+            debug_file_dirs: &debug_file_dirs,
+            crash_dumps: self
+                .config
+                .crash_dumps
+                .as_ref()
+                .map(|crash_dumps| (&self.config.crash_dump, crash_dumps)),
         };
-        test_input(args).await.context("test input failed")
+
+        // Minidumps record crashes, rather than reproducing them.
+        if is_minidump(input) {
+            return test_dump(&args, self.symbol_servers.as_ref())
+                .await
+                .context("test minidump failed");
+        }
+
+        let mut result = test_input(args).await.context("test input failed")?;
+
+        if let (
+            CrashTestResult::CrashReport(report),
+            Some(minimized_crashes),
+            Some(minimizer_exe),
+        ) = (
+            &mut result,
+            &self.config.minimized_crashes,
+            &self.config.minimizer_exe,
+        ) {
+            let timeout = self.config.minimize_timeout;
+            minimize_crash(
+                report,
+                input,
+                minimized_crashes,
+                timeout,
+                |output| async move {
+                    self.minimize(minimizer_exe, &target_exe, input, &output)
+                        .await
+                },
+            )
+            .await;
+        }
+
+        Ok(result)
+    }
+
+    async fn minimize(
+        &self,
+        minimizer_exe: &str,
+        target_exe: &Path,
+        input: &Path,
+        output: &Path,
+    ) -> Result<()> {
+        let expand = self
+            .config
+            .common
+            .expand()
+            .machine_id()
+            .input_path(input)
+            .minimized_input(output)
+            .target_exe(target_exe)
+            .target_options(&self.config.target_options)
+            .setup_dir(&self.config.common.setup_dir)
+            .set_optional_ref(&self.config.common.extra_setup_dir, Expand::extra_setup_dir)
+            .job_id(&self.config.common.job_id)
+            .task_id(&self.config.common.task_id);
+
+        let minimizer_path = expand.evaluate_value(minimizer_exe)?;
+
+        let mut cmd = Command::new(&minimizer_path);
+        cmd.kill_on_drop(true)
+            .env_remove("RUST_LOG")
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        for arg in expand.evaluate(&self.config.minimizer_options)? {
+            cmd.arg(arg);
+        }
+
+        // The minimizer runs the target.
+        for (k, v) in &self.config.target_env {
+            cmd.env(k, expand.evaluate_value(v)?);
+        }
+
+        let output = cmd
+            .spawn()
+            .with_context(|| format!("minimizer failed to start: {minimizer_path}"))?
+            .wait_with_output()
+            .await
+            .with_context(|| format!("minimizer failed to run: {minimizer_path}"))?;
+
+        if !output.status.success() {
+            bail!(
+                "minimizer failed. status:{} stderr:{}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+
+        Ok(())
     }
 }
 
 #[async_trait]
-impl<'a> Processor for GenericReportProcessor<'a> {
-    async fn process(&mut self, url: Option<Url>, input: &Path) -> Result<()> {
+impl<'a> ReportProcessor for GenericReportProcessor<'a> {
+    async fn test(&self, url: Option<Url>, input: &Path) -> Result<CrashTestResult> {
         debug!("generating crash report for: {}", input.display());
-        let report = self
-            .test_input(url, input)
+        self.test_input(url, input)
             .await
-            .context("test input failed")?;
+            .context("test input failed")
+    }
+
+    async fn save(&self, input: &Path, mut report: CrashTestResult) -> Result<()> {
+        if let (CrashTestResult::NoRepro(no_repro), Some(container)) =
+            (&mut report, &self.config.no_repro)
+        {
+            let redactor = Redactor::new(self.config.redaction.as_ref())?;
+            self.bundler
+                .lock()
+                .await
+                .save(no_repro, input, container, &redactor)
+                .await;
+        }
         report
-            .save(
+            .save_routed(
+                &self.config.report_routing,
                 &self.config.unique_reports,
                 &self.config.reports,
                 &self.config.no_repro,
                 &self.job_result_client,
             )
             .await
-            .context("saving report failed")
+            .context("saving report failed")?;
+
+        if self.config.report_all_findings {
+            let source_path_map =
+                load_path_map(&self.config.source_path_map, &self.config.common.setup_dir).await?;
+            report
+                .save_other_findings(
+                    self.config.minimized_stack_depth,
+                    &self.config.stack_normalization,
+                    &source_path_map,
+                    &self.config.report_routing,
+                    &self.config.unique_reports,
+                    &self.job_result_client,
+                )
+                .await
+                .context("saving reports of other findings failed")?;
+        }
+
+        Ok(())
     }
 }
@@ -1,27 +1,42 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
-use super::crash_report::*;
+use super::{
+    bundle::{NoReproBundler, NoReproBundles},
+    compat::REPORT_SCHEMA_VERSION,
+    crash_report::*,
+    minimize::{default_minimize_timeout, minimize_crash},
+    pool::{default_report_concurrency, ReportPool, ReportProcessor},
+    redact::{Redaction, Redactor},
+    repro::ReproRetries,
+};
 use crate::tasks::{
     config::CommonConfig,
-    generic::input_poller::*,
+    generic::input_poller::CallbackImpl,
     heartbeat::{HeartbeatSender, TaskHeartbeatClient},
+    source_paths::{load_path_map, SourcePathRule},
+    symbols::{debug_file_dirs, TargetSymbols},
     utils::{default_bool_true, try_resolve_setup_relative_path},
 };
-use anyhow::{Context, Result};
+use anyhow::Result;
 use async_trait::async_trait;
+use coverage::path_map::PathMap;
+use debuggable_module::debugfile::DebugFileDirs;
 use onefuzz::{
-    blob::BlobUrl, libfuzzer::LibFuzzer, machine_id::MachineIdentity, sha256, syncdir::SyncedDir,
+    blob::BlobUrl, libfuzzer::LibFuzzer, machine_id::MachineIdentity, qemu::QemuUser,
+    sanitizer::add_debug_file_dirs, syncdir::SyncedDir,
 };
 use onefuzz_result::job_result::TaskJobResultClient;
 use reqwest::Url;
 use serde::Deserialize;
+use stacktrace_parser::StackNormalization;
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     path::{Path, PathBuf},
     sync::Arc,
 };
-use storage_queue::{Message, QueueClient};
+use storage_queue::QueueClient;
+use tokio::sync::Mutex;
 
 const LIBFUZZER_TOOL_NAME: &str = "libfuzzer";
 
@@ -44,27 +59,102 @@ pub struct Config {
     #[serde(default)]
     pub check_retry_count: u64,
 
+    /// Retries of inputs which don't reproduce, each checked as by
+    /// `check_retry_count`.
+    #[serde(flatten)]
+    pub repro_retries: ReproRetries,
+
     #[serde(default)]
     pub minimized_stack_depth: Option<usize>,
 
+    /// Options normalizing the frames of minimized stacks before they are
+    /// hashed, as recorded in each report.
+    #[serde(default)]
+    pub stack_normalization: StackNormalization,
+
     #[serde(default = "default_bool_true")]
     pub check_queue: bool,
 
+    /// Container for minimized crashing inputs. If set, each reproduced crash
+    /// is minimized with `-minimize_crash=1`, and linked in its report.
+    pub minimized_crashes: Option<SyncedDir>,
+
+    /// Time budget for minimizing a crash, in seconds.
+    #[serde(default = "default_minimize_timeout")]
+    pub minimize_timeout: u64,
+
+    /// Rules rewriting the source paths of the stacks of reports, with those
+    /// of the `.srcview.json` file of the setup container.
+    #[serde(default)]
+    pub source_path_map: Vec<SourcePathRule>,
+
+    /// Also report the findings of a crash log after its first, such as of
+    /// UBSan run with `halt_on_error=0`, to `unique_reports`.
+    #[serde(default)]
+    pub report_all_findings: bool,
+
+    /// Where reports of findings other than crashes, such as OOMs, are saved,
+    /// if not to `unique_reports` and `reports`.
+    #[serde(flatten)]
+    pub report_routing: ReportRouting,
+
+    /// Rules redacting the user names and internal paths of reports, if set.
+    #[serde(default)]
+    pub redaction: Option<Redaction>,
+
+    /// Bundles of the runs of inputs which don't reproduce, saved to
+    /// `no_repro`.
+    #[serde(flatten)]
+    pub no_repro_bundles: NoReproBundles,
+
+    /// The number of inputs tested at once, each by its own run of the
+    /// target. Reports are saved one at a time.
+    #[serde(default = "default_report_concurrency")]
+    pub report_concurrency: usize,
+
+    /// Directories searched for the external debug files of stripped targets,
+    /// relative to the setup directory, before the setup directories and the
+    /// debug file directory of the system.
+    #[serde(default)]
+    pub debug_file_dirs: Vec<PathBuf>,
+
     #[serde(flatten)]
     pub common: CommonConfig,
 }
 
+fn libfuzzer(config: &Config, target_exe: PathBuf) -> LibFuzzer {
+    let fuzzer = LibFuzzer::new(
+        target_exe,
+        config.target_options.clone(),
+        config.target_env.clone(),
+        config.common.setup_dir.clone(),
+        config.common.extra_setup_dir.clone(),
+        config
+            .common
+            .extra_output
+            .as_ref()
+            .map(|x| x.local_path.clone()),
+        config.common.machine_identity.clone(),
+    )
+    .allow_unknown_placeholders(config.common.allow_unknown_placeholders)
+    .allow_env_placeholders(config.common.allow_env_placeholders)
+    .target_options_shell(config.common.target_options_shell);
+
+    match &config.common.qemu_user {
+        Some(qemu) => fuzzer.qemu_user(qemu.clone()),
+        None => fuzzer,
+    }
+}
+
 pub struct ReportTask {
     config: Arc<Config>,
-    pub poller: InputPoller<Message>,
 }
 
 impl ReportTask {
     pub fn new(config: Config) -> Self {
-        let poller = InputPoller::new("libfuzzer-crash-report");
         let config = Arc::new(config);
 
-        Self { config, poller }
+        Self { config }
     }
 
     pub async fn verify(&self) -> Result<()> {
@@ -72,27 +162,22 @@ impl ReportTask {
             try_resolve_setup_relative_path(&self.config.common.setup_dir, &self.config.target_exe)
                 .await?;
 
-        let fuzzer = LibFuzzer::new(
-            target_exe,
-            self.config.target_options.clone(),
-            self.config.target_env.clone(),
-            self.config.common.setup_dir.clone(),
-            self.config.common.extra_setup_dir.clone(),
-            self.config
-                .common
-                .extra_output
-                .as_ref()
-                .map(|x| x.local_path.clone()),
-            self.config.common.machine_identity.clone(),
-        );
-
-        fuzzer.verify(self.config.check_fuzzer_help, None).await
+        libfuzzer(&self.config, target_exe)
+            .verify(self.config.check_fuzzer_help, None)
+            .await
     }
 
     pub async fn managed_run(&mut self) -> Result<()> {
         info!("Starting libFuzzer crash report task");
         self.verify().await?;
 
+        let target_exe =
+            try_resolve_setup_relative_path(&self.config.common.setup_dir, &self.config.target_exe)
+                .await?;
+        TargetSymbols::fetch(&self.config.common, &target_exe)
+            .await
+            .export();
+
         if let Some(unique_reports) = &self.config.unique_reports {
             unique_reports.init().await?;
         }
@@ -102,17 +187,26 @@ impl ReportTask {
         if let Some(no_repro) = &self.config.no_repro {
             no_repro.init().await?;
         }
+        if let Some(minimized_crashes) = &self.config.minimized_crashes {
+            minimized_crashes.init().await?;
+        }
+        self.config.report_routing.init().await?;
 
-        let mut processor = AsanProcessor::new(self.config.clone()).await?;
+        let processor = AsanProcessor::new(self.config.clone()).await?;
+        let mut pool = ReportPool::new(
+            "libfuzzer-crash-report",
+            &processor,
+            self.config.report_concurrency,
+        );
 
         if let Some(crashes) = &self.config.crashes {
-            self.poller.batch_process(&mut processor, crashes).await?;
+            pool.batch_process(crashes).await?;
         }
 
         if self.config.check_queue {
             if let Some(url) = &self.config.input_queue {
-                let callback = CallbackImpl::new(url.clone(), processor)?;
-                self.poller.run(callback).await?;
+                let mut callback = CallbackImpl::new(url.clone(), &processor)?;
+                pool.run(&mut callback).await?;
             }
         }
         Ok(())
@@ -132,39 +226,73 @@ pub struct TestInputArgs<'a> {
     pub job_id: uuid::Uuid,
     pub target_timeout: Option<u64>,
     pub check_retry_count: u64,
+    pub repro_retries: &'a ReproRetries,
     pub minimized_stack_depth: Option<usize>,
+    pub stack_normalization: &'a StackNormalization,
     pub machine_identity: MachineIdentity,
+    pub allow_unknown_placeholders: bool,
+    pub allow_env_placeholders: bool,
+    pub target_options_shell: bool,
+    pub qemu_user: Option<&'a QemuUser>,
+    pub source_path_map: &'a PathMap,
+    pub redactor: &'a Redactor,
+    pub debug_file_dirs: &'a DebugFileDirs,
 }
 
 pub async fn test_input(args: TestInputArgs<'_>) -> Result<CrashTestResult> {
-    let fuzzer = LibFuzzer::new(
-        args.target_exe.to_owned(),
-        args.target_options.to_vec(),
-        args.target_env.clone(),
-        args.setup_dir.to_owned(),
-        args.extra_setup_dir.map(PathBuf::from),
-        args.extra_output_dir.map(PathBuf::from),
-        args.machine_identity,
-    );
+    let args = &args;
+    let crash_blob = args
+        .input_url
+        .as_ref()
+        .and_then(|u| BlobUrl::new(u.clone()).ok());
 
     let task_id = args.task_id;
     let job_id = args.job_id;
-    let input_blob = args
-        .input_url
-        .and_then(|u| BlobUrl::new(u).ok())
-        .map(InputBlob::from);
-    let input = args.input;
-    let input_sha256 = sha256::digest_file(args.input)
-        .await
-        .with_context(|| format_err!("unable to sha256 digest input file: {}", input.display()))?;
-
-    let test_report = fuzzer
-        .repro(args.input, args.target_timeout, args.check_retry_count)
+    let input_blob = crash_blob.clone().map(InputBlob::from);
+    let (input_sha256, input_size) = digest_input(args.input).await?;
+
+    let mut target_env = args.target_env.clone();
+    add_debug_file_dirs(&mut target_env, args.debug_file_dirs);
+
+    let crash_blob_ref = crash_blob.as_ref();
+    let (test_report, mut reproduction) = args
+        .repro_retries
+        .test(
+            args.target_timeout,
+            &target_env,
+            |timeout, env| async move {
+                let mut fuzzer = LibFuzzer::new(
+                    args.target_exe.to_owned(),
+                    args.target_options.to_vec(),
+                    env,
+                    args.setup_dir.to_owned(),
+                    args.extra_setup_dir.map(PathBuf::from),
+                    args.extra_output_dir.map(PathBuf::from),
+                    args.machine_identity.clone(),
+                )
+                .allow_unknown_placeholders(args.allow_unknown_placeholders)
+                .allow_env_placeholders(args.allow_env_placeholders)
+                .target_options_shell(args.target_options_shell);
+
+                if let Some(qemu) = args.qemu_user {
+                    fuzzer = fuzzer.qemu_user(qemu.clone());
+                }
+
+                if let Some(crash_blob) = crash_blob_ref {
+                    fuzzer = fuzzer.crash_blob(crash_blob.clone());
+                }
+
+                fuzzer
+                    .repro(args.input, timeout, args.check_retry_count)
+                    .await
+            },
+        )
         .await?;
+    args.redactor.redact_reproduction(&mut reproduction);
 
     match test_report.crash_log {
         Some(crash_log) => {
-            let crash_report = CrashReport::new(
+            let mut crash_report = CrashReport::new(
                 crash_log,
                 task_id,
                 job_id,
@@ -172,21 +300,36 @@ pub async fn test_input(args: TestInputArgs<'_>) -> Result<CrashTestResult> {
                 input_blob,
                 input_sha256,
                 args.minimized_stack_depth,
+                args.stack_normalization,
+                args.source_path_map,
+                args.redactor,
                 LIBFUZZER_TOOL_NAME.into(),
                 env!("ONEFUZZ_VERSION").to_string(),
                 env!("ONEFUZZ_VERSION").to_string(),
             );
+            crash_report.classify_artifact(args.input);
+            crash_report.input_size = Some(input_size);
+            crash_report.reproduction = Some(reproduction);
+            if let Some(crash_blob) = &crash_blob {
+                crash_report.record_input_provenance(crash_blob).await;
+            }
             Ok(CrashTestResult::CrashReport(Box::new(crash_report)))
         }
         None => {
             let no_repro = NoCrash {
+                schema_version: REPORT_SCHEMA_VERSION,
                 input_blob,
                 input_sha256,
-                executable: PathBuf::from(&args.target_exe),
+                executable: args.redactor.redact_path(args.target_exe),
                 task_id,
                 job_id,
-                tries: 1 + args.check_retry_count,
-                error: test_report.error.map(|e| format!("{e}")),
+                tries: (1 + args.check_retry_count) * reproduction.attempts.len() as u64,
+                error: test_report
+                    .error
+                    .map(|e| args.redactor.redact(&format!("{e}"))),
+                reproduction: Some(reproduction),
+                bundle: None,
+                extra: BTreeMap::new(),
             };
 
             Ok(CrashTestResult::NoRepro(Box::new(no_repro)))
@@ -198,17 +341,22 @@ pub struct AsanProcessor {
     config: Arc<Config>,
     heartbeat_client: Option<TaskHeartbeatClient>,
     job_result_client: Option<TaskJobResultClient>,
+
+    /// Locked by the saves of reports, one at a time.
+    bundler: Mutex<NoReproBundler>,
 }
 
 impl AsanProcessor {
     pub async fn new(config: Arc<Config>) -> Result<Self> {
         let heartbeat_client = config.common.init_heartbeat(None).await?;
         let job_result_client = config.common.init_job_result().await?;
+        let bundler = Mutex::new(NoReproBundler::new(&config.no_repro_bundles));
 
         Ok(Self {
             config,
             heartbeat_client,
             job_result_client,
+            bundler,
         })
     }
 
@@ -222,6 +370,14 @@ impl AsanProcessor {
         let target_exe =
             try_resolve_setup_relative_path(&self.config.common.setup_dir, &self.config.target_exe)
                 .await?;
+        let source_path_map =
+            load_path_map(&self.config.source_path_map, &self.config.common.setup_dir).await?;
+        let redactor = Redactor::new(self.config.redaction.as_ref())?;
+        let debug_file_dirs = debug_file_dirs(
+            &self.config.common.setup_dir,
+            self.config.common.extra_setup_dir.as_deref(),
+            &self.config.debug_file_dirs,
+        );
 
         let args = TestInputArgs {
             input_url,
@@ -241,28 +397,372 @@ impl AsanProcessor {
             job_id: self.config.common.job_id,
             target_timeout: self.config.target_timeout,
             check_retry_count: self.config.check_retry_count,
+            repro_retries: &self.config.repro_retries,
             minimized_stack_depth: self.config.minimized_stack_depth,
+            stack_normalization: &self.config.stack_normalization,
             machine_identity: self.config.common.machine_identity.clone(),
+            allow_unknown_placeholders: self.config.common.allow_unknown_placeholders,
+            allow_env_placeholders: self.config.common.allow_env_placeholders,
+            target_options_shell: self.config.common.target_options_shell,
+            qemu_user: self.config.common.qemu_user.as_ref(),
+            source_path_map: &source_path_map,
+            redactor: &redactor,
+            debug_file_dirs: &debug_file_dirs,
         };
 
-        let result = test_input(args).await?;
+        let mut result = test_input(args).await?;
+
+        if let (CrashTestResult::CrashReport(report), Some(minimized_crashes)) =
+            (&mut result, &self.config.minimized_crashes)
+        {
+            let fuzzer = libfuzzer(&self.config, target_exe.clone());
+            let timeout = self.config.minimize_timeout;
+            minimize_crash(
+                report,
+                input,
+                minimized_crashes,
+                timeout,
+                |output| async move { fuzzer.minimize_crash(input, output, timeout).await },
+            )
+            .await;
+        }
 
         Ok(result)
     }
 }
 
 #[async_trait]
-impl Processor for AsanProcessor {
-    async fn process(&mut self, url: Option<Url>, input: &Path) -> Result<()> {
+impl ReportProcessor for AsanProcessor {
+    async fn test(&self, url: Option<Url>, input: &Path) -> Result<CrashTestResult> {
         debug!("processing libfuzzer crash url:{:?} path:{:?}", url, input);
-        let report = self.test_input(url, input).await?;
+        self.test_input(url, input).await
+    }
+
+    async fn save(&self, input: &Path, mut report: CrashTestResult) -> Result<()> {
+        if let (CrashTestResult::NoRepro(no_repro), Some(container)) =
+            (&mut report, &self.config.no_repro)
+        {
+            let redactor = Redactor::new(self.config.redaction.as_ref())?;
+            self.bundler
+                .lock()
+                .await
+                .save(no_repro, input, container, &redactor)
+                .await;
+        }
         report
-            .save(
+            .save_routed(
+                &self.config.report_routing,
                 &self.config.unique_reports,
                 &self.config.reports,
                 &self.config.no_repro,
                 &self.job_result_client,
             )
-            .await
+            .await?;
+
+        if self.config.report_all_findings {
+            let source_path_map =
+                load_path_map(&self.config.source_path_map, &self.config.common.setup_dir).await?;
+            report
+                .save_other_findings(
+                    self.config.minimized_stack_depth,
+                    &self.config.stack_normalization,
+                    &source_path_map,
+                    &self.config.report_routing,
+                    &self.config.unique_reports,
+                    &self.job_result_client,
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Reproduces a crash by printing an ASAN log, and minimizes a crash by
+    // keeping the first 4 bytes of the input.
+    #[cfg(target_family = "unix")]
+    fn fake_libfuzzer() -> String {
+        let asan_log = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../stacktrace-parser/data/stack-traces/libfuzzer-asan-log.txt");
+
+        format!(
+            r#"#!/bin/sh
+for arg in "$@"; do
+    case "$arg" in
+        -minimize_crash=1) minimize=1 ;;
+        -exact_artifact_path=*) artifact="${{arg#-exact_artifact_path=}}" ;;
+        -*) ;;
+        *) input="$arg" ;;
+    esac
+done
+if [ -n "$minimize" ]; then
+    head -c 4 "$input" > "$artifact"
+    exit 0
+fi
+cat '{}' >&2
+exit 1
+"#,
+            asan_log.display()
+        )
+    }
+
+    // Reproduces a crash as `fake_libfuzzer` does, but only from its `n`th
+    // run, counting runs in `dir`.
+    #[cfg(target_family = "unix")]
+    fn flaky_libfuzzer(dir: &Path, n: u32) -> String {
+        let asan_log = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../stacktrace-parser/data/stack-traces/libfuzzer-asan-log.txt");
+
+        format!(
+            r#"#!/bin/sh
+runs=$(cat '{dir}/runs' 2>/dev/null || echo 0)
+runs=$((runs + 1))
+echo "$runs" > '{dir}/runs'
+if [ "$runs" -lt {n} ]; then
+    exit 0
+fi
+cat '{asan_log}' >&2
+exit 1
+"#,
+            dir = dir.display(),
+            asan_log = asan_log.display(),
+        )
+    }
+
+    #[cfg(target_family = "unix")]
+    async fn processor(
+        dir: &Path,
+        script: String,
+        options: serde_json::Value,
+    ) -> Result<AsanProcessor> {
+        use crate::tasks::config::fixture;
+        use onefuzz::fs::set_executable;
+        use serde_json::json;
+
+        let target_exe = dir.join("fuzz.sh");
+        tokio::fs::write(&target_exe, script).await?;
+        set_executable(&target_exe).await?;
+
+        let mut config = fixture::config(json!({
+            "target_exe": target_exe,
+            "target_env": {},
+            "target_options": [],
+            "setup_dir": dir,
+        }));
+        fixture::merge(&mut config, options);
+
+        AsanProcessor::new(Arc::new(serde_json::from_value(config)?)).await
+    }
+
+    #[tokio::test]
+    #[cfg(target_family = "unix")]
+    async fn test_minimized_crash_report() -> Result<()> {
+        use serde_json::json;
+
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("crash-1");
+        tokio::fs::write(&input, "crashing input").await?;
+
+        let processor = processor(
+            dir.path(),
+            fake_libfuzzer(),
+            json!({
+                "minimized_crashes": { "path": dir.path().join("minimized_crashes") },
+                "minimize_timeout": 10,
+            }),
+        )
+        .await?;
+
+        let CrashTestResult::CrashReport(report) = processor.test_input(None, &input).await? else {
+            panic!("expected a crash report");
+        };
+
+        let name = format!("minimized-{}", report.input_sha256);
+        assert_eq!(report.input_size, Some(14));
+        assert_eq!(report.minimized_input_size, Some(4));
+        assert_eq!(report.minimized_input_blob.as_ref().unwrap().name, name);
+        assert_eq!(
+            report.minimized_input_sha256,
+            Some(onefuzz::sha256::digest(b"cras"))
+        );
+        assert_eq!(
+            tokio::fs::read_to_string(dir.path().join("minimized_crashes").join(name)).await?,
+            "cras"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg(target_family = "unix")]
+    async fn test_flaky_crash_report() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("crash-1");
+        tokio::fs::write(&input, "crashing input").await?;
+
+        let processor = processor(
+            dir.path(),
+            flaky_libfuzzer(dir.path(), 2),
+            serde_json::json!({}),
+        )
+        .await?;
+
+        let CrashTestResult::CrashReport(report) = processor.test_input(None, &input).await? else {
+            panic!("expected a crash report");
+        };
+
+        let reproduction = report.reproduction.unwrap();
+        assert!(reproduction.flaky);
+        assert_eq!(reproduction.summary, "reproduced 1/2 attempts");
+        assert_eq!(
+            reproduction
+                .attempts
+                .iter()
+                .map(|attempt| attempt.reproduced)
+                .collect::<Vec<_>>(),
+            [false, true]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg(target_family = "unix")]
+    async fn test_no_repro_after_retries() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("crash-1");
+        tokio::fs::write(&input, "crashing input").await?;
+
+        let processor = processor(
+            dir.path(),
+            flaky_libfuzzer(dir.path(), 3),
+            serde_json::json!({ "repro_retries": 1 }),
+        )
+        .await?;
+
+        let CrashTestResult::NoRepro(no_repro) = processor.test_input(None, &input).await? else {
+            panic!("expected no repro");
+        };
+
+        let reproduction = no_repro.reproduction.unwrap();
+        assert!(!reproduction.flaky);
+        assert_eq!(reproduction.summary, "reproduced 0/2 attempts");
+        assert_eq!(no_repro.tries, 2);
+
+        Ok(())
+    }
+
+    // Never crashes, logging its run, counted in `dir`, to stdout and stderr.
+    #[cfg(target_family = "unix")]
+    fn exiting_libfuzzer(dir: &Path) -> String {
+        format!(
+            r#"#!/bin/sh
+runs=$(cat '{dir}/runs' 2>/dev/null || echo 0)
+runs=$((runs + 1))
+echo "$runs" > '{dir}/runs'
+echo "run $runs"
+echo "no crash in run $runs" >&2
+exit 0
+"#,
+            dir = dir.display(),
+        )
+    }
+
+    #[tokio::test]
+    #[cfg(target_family = "unix")]
+    async fn test_no_repro_bundle() -> Result<()> {
+        use super::super::bundle::NoReproBundle;
+
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("crash-1");
+        tokio::fs::write(&input, "crashing input").await?;
+        let no_repro_dir = dir.path().join("no_repro");
+        tokio::fs::create_dir(&no_repro_dir).await?;
+
+        let processor = processor(
+            dir.path(),
+            exiting_libfuzzer(dir.path()),
+            serde_json::json!({
+                "repro_retries": 2,
+                "no_repro": { "path": no_repro_dir },
+            }),
+        )
+        .await?;
+        let report = processor.test(None, &input).await?;
+        processor.save(&input, report).await?;
+
+        let sha256 = onefuzz::sha256::digest(b"crashing input");
+        let no_repro: NoCrash = serde_json::from_slice(
+            &tokio::fs::read(no_repro_dir.join(format!("{sha256}.json"))).await?,
+        )?;
+        let name = format!("{sha256}.bundle.json");
+        assert_eq!(no_repro.bundle.as_ref(), Some(&name));
+
+        let bundle: NoReproBundle =
+            serde_json::from_slice(&tokio::fs::read(no_repro_dir.join(&name)).await?)?;
+        assert_eq!(bundle.input_sha256, sha256);
+        assert!(!bundle.truncated);
+        assert_eq!(bundle.runs.len(), 3);
+        for (i, run) in bundle.runs.iter().enumerate() {
+            assert_eq!(run.attempt, i);
+            assert_eq!(run.command.last(), Some(&input.display().to_string()));
+            assert_eq!(run.exit_status.map(|status| status.code), Some(Some(0)));
+            assert_eq!(run.stdout.as_deref(), Some(&*format!("run {}\n", i + 1)));
+            assert_eq!(
+                run.stderr.as_deref(),
+                Some(&*format!("no crash in run {}\n", i + 1))
+            );
+            assert!(run.error.is_none());
+        }
+
+        // The input is copied, as it isn't a blob.
+        let input_name = bundle.input.unwrap();
+        assert_eq!(
+            tokio::fs::read_to_string(no_repro_dir.join(input_name)).await?,
+            "crashing input"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg(target_family = "unix")]
+    async fn test_no_repro_bundles_over_total_size() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("crash-1");
+        tokio::fs::write(&input, "crashing input").await?;
+        let no_repro_dir = dir.path().join("no_repro");
+        tokio::fs::create_dir(&no_repro_dir).await?;
+
+        let processor = processor(
+            dir.path(),
+            exiting_libfuzzer(dir.path()),
+            serde_json::json!({
+                "repro_retries": 0,
+                "no_repro": { "path": no_repro_dir },
+                "no_repro_bundles_max_total_size": 1,
+            }),
+        )
+        .await?;
+        let report = processor.test(None, &input).await?;
+        processor.save(&input, report).await?;
+
+        // The report is saved, without a bundle.
+        let sha256 = onefuzz::sha256::digest(b"crashing input");
+        let no_repro: NoCrash = serde_json::from_slice(
+            &tokio::fs::read(no_repro_dir.join(format!("{sha256}.json"))).await?,
+        )?;
+        assert_eq!(no_repro.bundle, None);
+        assert!(
+            tokio::fs::metadata(no_repro_dir.join(format!("{sha256}.bundle.json")))
+                .await
+                .is_err()
+        );
+
+        Ok(())
     }
 }
@@ -0,0 +1,628 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Reports of crashes submitted as Windows minidumps, such as those written by
+//! Windows Error Reporting, rather than as inputs to reproduce.
+
+pub mod format;
+
+use self::format::{Exception, Frame, FrameTrust, Minidump, Module, PdbInfo};
+use super::{
+    compat::REPORT_SCHEMA_VERSION,
+    crash_report::{digest_input, CrashReport, CrashTestResult, InputBlob, NoCrash},
+    generic::TestInputArgs,
+};
+use anyhow::{Context, Result};
+use debuggable_module::{
+    debuginfo::DebugInfo,
+    symsrv::{PdbKey, SymbolPath},
+    windows::pdb_debuginfo,
+    Offset,
+};
+use onefuzz::{blob::BlobUrl, symsrv::SymbolServers};
+use serde::{Deserialize, Serialize};
+use stacktrace_parser::{CrashLog, StackEntry};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+use tokio::fs;
+
+const MINIDUMP_TOOL_NAME: &str = "minidump";
+
+const MAX_FRAMES: usize = 64;
+
+// As named by the debugger, when reproducing crashes.
+const UNKNOWN_MODULE: &str = "<UnknownModule>";
+
+const EXCEPTION_ACCESS_VIOLATION: u32 = 0xC0000005;
+const EXCEPTION_IN_PAGE_ERROR: u32 = 0xC0000006;
+
+/// Whether the input `path` is a minidump, by its `.dmp` extension.
+pub fn is_minidump(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("dmp"))
+}
+
+/// The exception record of a minidump, as reported.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct MinidumpException {
+    pub code: String,
+    pub address: String,
+
+    /// The address accessed by the faulting instruction, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fault_address: Option<String>,
+
+    pub thread_id: u32,
+}
+
+impl From<&Exception> for MinidumpException {
+    fn from(exception: &Exception) -> Self {
+        Self {
+            code: format!("0x{:08x}", exception.code),
+            address: format!("0x{:x}", exception.address),
+            fault_address: fault_address(exception).map(|address| format!("0x{address:x}")),
+            thread_id: exception.thread_id,
+        }
+    }
+}
+
+fn fault_address(exception: &Exception) -> Option<u64> {
+    match exception.code {
+        EXCEPTION_ACCESS_VIOLATION | EXCEPTION_IN_PAGE_ERROR => {
+            exception.parameters.get(1).copied()
+        }
+        _ => None,
+    }
+}
+
+/// The crash type of an exception, named as when the debugger reproduces the
+/// crash of an input.
+#[allow(clippy::unreadable_literal)]
+pub fn crash_type(exception: &Exception) -> String {
+    let crash_type = match exception.code {
+        EXCEPTION_ACCESS_VIOLATION => match exception.parameters[..] {
+            [access, address, ..] => match (access != 0, address == 0) {
+                (true, true) => "WriteToNull",
+                (true, false) => "WriteAccessViolation",
+                (false, true) => "ReadFromNull",
+                (false, false) => "ReadAccessViolation",
+            },
+            _ => "UnknownAccessViolation",
+        },
+        0xC000008C => "ArrayBoundsExceeded",
+        0x80000003 | 0x4000001F => "Breakpoint",
+        0x80000002 => "MisalignedData",
+        0xC000008D => "FltDenormalOperand",
+        0xC000008E => "FltDivByZero",
+        0xC000008F => "FltInexactResult",
+        0xC0000090 => "FltInvalidOperation",
+        0xC0000091 => "FltOverflow",
+        0xC0000092 => "FltStackCheck",
+        0xC0000093 => "FltUnderflow",
+        0xC000001D => "IllegalInstruction",
+        0xC0000094 => "IntDivByZero",
+        0xC0000095 => "IntOverflow",
+        0xC0000026 => "InvalidDisposition",
+        EXCEPTION_IN_PAGE_ERROR => "InPageError",
+        0xC0000025 => "NonContinuableException",
+        0xC0000096 => "PrivilegedInstruction",
+        0x80000004 => "SingleStep",
+        0xC00000FD => "StackOverflow",
+        0xE0434352 => "ClrException",
+        0xE06D7363 => "CppException",
+        _ => "UnknownExceptionCode",
+    };
+
+    crash_type.to_owned()
+}
+
+/// Report the crash of the minidump `args.input`.
+///
+/// Dumps which can't be parsed, such as truncated uploads, are reported as not
+/// reproducing, with the parse error, rather than failing the task.
+pub async fn test_dump(
+    args: &TestInputArgs<'_>,
+    symbol_servers: Option<&SymbolServers>,
+) -> Result<CrashTestResult> {
+    let crash_blob = args
+        .input_url
+        .as_ref()
+        .and_then(|u| BlobUrl::new(u.clone()).ok());
+    let input_blob = crash_blob.clone().map(InputBlob::from);
+    let (input_sha256, input_size) = digest_input(args.input).await?;
+
+    let data = fs::read(args.input)
+        .await
+        .with_context(|| format!("unable to read minidump: {}", args.input.display()))?;
+    let dump = match Minidump::parse(&data) {
+        Ok(dump) => dump,
+        Err(err) => {
+            warn!("unparseable minidump {}: {:#}", args.input.display(), err);
+            let no_repro = NoCrash {
+                schema_version: REPORT_SCHEMA_VERSION,
+                input_blob,
+                input_sha256,
+                executable: args.redactor.redact_path(args.target_exe),
+                task_id: args.task_id,
+                job_id: args.job_id,
+                tries: 0,
+                error: Some(format!("unparseable minidump: {err:#}")),
+                reproduction: None,
+                bundle: None,
+                extra: BTreeMap::new(),
+            };
+            return Ok(no_repro.into());
+        }
+    };
+
+    let frames = dump.stack_walk(MAX_FRAMES);
+    let debuginfo = load_debuginfo(&dump, &frames, args.setup_dir, symbol_servers).await;
+    let crash_log = crash_log(&dump, &frames, &debuginfo)?;
+
+    let mut crash_report = CrashReport::new(
+        crash_log,
+        args.task_id,
+        args.job_id,
+        args.target_exe,
+        input_blob,
+        input_sha256,
+        args.minimized_stack_depth,
+        args.stack_normalization,
+        args.source_path_map,
+        args.redactor,
+        MINIDUMP_TOOL_NAME.into(),
+        env!("ONEFUZZ_VERSION").to_string(),
+        env!("ONEFUZZ_VERSION").to_string(),
+    );
+    crash_report.input_size = Some(input_size);
+    crash_report.minidump_exception = Some((&dump.exception).into());
+    if let Some(crash_blob) = &crash_blob {
+        crash_report.record_input_provenance(crash_blob).await;
+    }
+
+    Ok(crash_report.into())
+}
+
+/// The crash log of the faulting thread of `dump`, whose stack is walked as
+/// `frames`, symbolized with `debuginfo`, of modules by their base address.
+///
+/// Only frames of the thread context are in the stack of the log, by which
+/// crashes are deduplicated. Frames found by scanning the stack may be stale,
+/// so are only listed in the text of the log.
+fn crash_log(
+    dump: &Minidump,
+    frames: &[Frame],
+    debuginfo: &HashMap<u64, DebugInfo>,
+) -> Result<CrashLog> {
+    let (stack, scanned): (Vec<_>, Vec<_>) = frames
+        .iter()
+        .map(|frame| (frame.trust, stack_entry(dump, frame.address, debuginfo)))
+        .partition(|(trust, _)| *trust == FrameTrust::Context);
+    let stack = stack.into_iter().map(|(_, entry)| entry).collect();
+
+    let text = (!scanned.is_empty()).then(|| {
+        let mut text = "frames found by scanning the stack, which may be stale:\n".to_owned();
+        for (_, entry) in &scanned {
+            text.push_str(&format!("    {}\n", entry.line));
+        }
+        text
+    });
+
+    let fault_type = crash_type(&dump.exception);
+    let sanitizer = fault_type.clone();
+
+    CrashLog::new(text, None, sanitizer, fault_type, None, None, stack)
+}
+
+fn stack_entry(dump: &Minidump, address: u64, debuginfo: &HashMap<u64, DebugInfo>) -> StackEntry {
+    match dump.module(address) {
+        Some(module) => module_frame(module, address, debuginfo.get(&module.base)),
+        None => StackEntry {
+            line: format!("{UNKNOWN_MODULE}+0x{address:x}"),
+            address: Some(address),
+            ..Default::default()
+        },
+    }
+}
+
+fn module_frame(module: &Module, address: u64, debuginfo: Option<&DebugInfo>) -> StackEntry {
+    // Named by the file stem, as by the debugger.
+    let name = module.name();
+    let name = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+
+    let module_offset = address - module.base;
+    let function = debuginfo.and_then(|debuginfo| debuginfo.find_function(Offset(module_offset)));
+    let function_offset = function.map(|function| module_offset - function.offset.0);
+
+    let line = match (function, function_offset) {
+        (Some(function), Some(function_offset)) => {
+            format!("{name}!{}+0x{function_offset:x}", function.name)
+        }
+        _ => format!("{name}+0x{module_offset:x}"),
+    };
+
+    StackEntry {
+        line,
+        address: Some(address),
+        function_name: function.map(|function| function.name.clone()),
+        function_offset,
+        module_path: Some(module.path.clone()),
+        module_offset: Some(module_offset),
+        ..Default::default()
+    }
+}
+
+/// Debug info of the modules of `frames`, by their base address.
+///
+/// Modules whose PDB isn't found, or can't be read, are skipped, leaving their
+/// frames unsymbolized.
+async fn load_debuginfo(
+    dump: &Minidump,
+    frames: &[Frame],
+    setup_dir: &Path,
+    symbol_servers: Option<&SymbolServers>,
+) -> HashMap<u64, DebugInfo> {
+    let symbol_path = SymbolPath::from_env();
+    let mut debuginfo = HashMap::new();
+
+    for module in &dump.modules {
+        if debuginfo.contains_key(&module.base)
+            || !frames.iter().any(|frame| module.contains(frame.address))
+        {
+            continue;
+        }
+
+        let Some(pdb) = &module.pdb else {
+            debug!("no PDB recorded for {}", module.path);
+            continue;
+        };
+        let Some(path) = find_pdb(pdb, setup_dir, &symbol_path, symbol_servers).await else {
+            debug!("PDB not found for {}: {}", module.path, pdb.name);
+            continue;
+        };
+
+        let loaded = match fs::read(&path).await {
+            Ok(data) => pdb_debuginfo(&data),
+            Err(err) => Err(err.into()),
+        };
+        match loaded {
+            Ok(loaded) => {
+                debuginfo.insert(module.base, loaded);
+            }
+            Err(err) => {
+                warn!("unable to load PDB {}: {:?}", path.display(), err);
+            }
+        }
+    }
+
+    debuginfo
+}
+
+// PDBs are searched for in the setup directory, then on the symbol path, and
+// then on the symbol servers.
+async fn find_pdb(
+    pdb: &PdbInfo,
+    setup_dir: &Path,
+    symbol_path: &SymbolPath,
+    symbol_servers: Option<&SymbolServers>,
+) -> Option<PathBuf> {
+    let key = PdbKey::new(&pdb.name, pdb.guid, pdb.age);
+
+    let adjacent = setup_dir.join(key.name());
+    if fs::metadata(&adjacent).await.is_ok() {
+        return Some(adjacent);
+    }
+
+    if let Some(path) = symbol_path.find_local(&key) {
+        return Some(path);
+    }
+
+    symbol_servers?.fetch(&key).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tasks::report::redact::Redactor;
+    use coverage::path_map::PathMap;
+    use debuggable_module::{debugfile::DebugFileDirs, debuginfo::Function};
+    use onefuzz::{input_tester::InputVia, machine_id::MachineIdentity};
+    use stacktrace_parser::StackNormalization;
+    use uuid::Uuid;
+
+    const APP_BASE: u64 = 0x7ff6_1000_0000;
+    const NTDLL_BASE: u64 = 0x7ffc_2000_0000;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("data/minidumps")
+            .join(name)
+    }
+
+    fn exception(code: u32, parameters: Vec<u64>) -> Exception {
+        Exception {
+            thread_id: 1,
+            code,
+            address: 0x1000,
+            parameters,
+        }
+    }
+
+    async fn test_fixture(name: &str) -> Result<CrashTestResult> {
+        let input = fixture(name);
+        let setup_dir = tempfile::tempdir()?;
+        let args = TestInputArgs {
+            input_url: None,
+            input: &input,
+            target_exe: Path::new("app.exe"),
+            target_options: &[],
+            target_env: &HashMap::new(),
+            input_via: InputVia::File,
+            setup_dir: setup_dir.path(),
+            extra_setup_dir: None,
+            task_id: Uuid::new_v4(),
+            job_id: Uuid::new_v4(),
+            target_timeout: None,
+            target_memory_limit_mb: None,
+            check_retry_count: 0,
+            repro_retries: &Default::default(),
+            check_asan_log: false,
+            check_debugger: true,
+            minimized_stack_depth: None,
+            stack_normalization: &StackNormalization::default(),
+            machine_identity: MachineIdentity {
+                machine_id: Uuid::new_v4(),
+                machine_name: "test".to_string(),
+                scaleset_name: None,
+            },
+            allow_unknown_placeholders: false,
+            allow_env_placeholders: false,
+            target_options_shell: false,
+            qemu_user: None,
+            source_path_map: &PathMap::default(),
+            redactor: &Redactor::default(),
+            debug_file_dirs: &DebugFileDirs::default(),
+            crash_dumps: None,
+        };
+
+        test_dump(&args, None).await
+    }
+
+    #[test]
+    fn test_is_minidump() {
+        assert!(is_minidump(Path::new("crashes/app.exe.1234.dmp")));
+        assert!(is_minidump(Path::new("crashes/APP.DMP")));
+        assert!(!is_minidump(Path::new("crashes/crash-1234")));
+        assert!(!is_minidump(Path::new("crashes/dmp")));
+    }
+
+    #[test]
+    fn test_parse_fixture() -> Result<()> {
+        let dump = Minidump::parse(&std::fs::read(fixture("access-violation.dmp"))?)?;
+
+        assert_eq!(dump.architecture, Some(format::Architecture::Amd64));
+        assert_eq!(dump.exception.code, EXCEPTION_ACCESS_VIOLATION);
+        assert_eq!(dump.exception.address, APP_BASE + 0x1234);
+        assert_eq!(dump.exception.parameters, [1, 0]);
+
+        assert_eq!(dump.modules.len(), 2);
+        assert_eq!(dump.modules[0].name(), "app.exe");
+        assert_eq!(dump.modules[1].path, r"C:\Windows\System32\ntdll.dll");
+        let pdb = dump.modules[0].pdb.as_ref().unwrap();
+        assert_eq!(pdb.name, "app.pdb");
+        assert_eq!(pdb.age, 1);
+
+        let frames = dump.stack_walk(MAX_FRAMES);
+        let addresses: Vec<_> = frames.iter().map(|frame| frame.address).collect();
+        assert_eq!(
+            addresses,
+            [APP_BASE + 0x1234, APP_BASE + 0x5678, NTDLL_BASE + 0x9abc]
+        );
+        let trust: Vec<_> = frames.iter().map(|frame| frame.trust).collect();
+        assert_eq!(
+            trust,
+            [FrameTrust::Context, FrameTrust::Scan, FrameTrust::Scan]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_invalid() -> Result<()> {
+        let err = Minidump::parse(&std::fs::read(fixture("truncated.dmp"))?).unwrap_err();
+        assert!(err.to_string().starts_with("truncated minidump"), "{err}");
+
+        let err = Minidump::parse(b"not a minidump, but long enough to have a header").unwrap_err();
+        assert_eq!(err.to_string(), "not a minidump: invalid signature");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_crash_type() {
+        let cases = [
+            (
+                exception(EXCEPTION_ACCESS_VIOLATION, vec![0, 0]),
+                "ReadFromNull",
+            ),
+            (
+                exception(EXCEPTION_ACCESS_VIOLATION, vec![1, 0]),
+                "WriteToNull",
+            ),
+            (
+                exception(EXCEPTION_ACCESS_VIOLATION, vec![0, 0x41414141]),
+                "ReadAccessViolation",
+            ),
+            (
+                exception(EXCEPTION_ACCESS_VIOLATION, vec![8, 0x41414141]),
+                "WriteAccessViolation",
+            ),
+            (
+                exception(EXCEPTION_ACCESS_VIOLATION, vec![]),
+                "UnknownAccessViolation",
+            ),
+            (exception(0xC00000FD, vec![]), "StackOverflow"),
+            (exception(0xE06D7363, vec![]), "CppException"),
+            (exception(0x12345678, vec![]), "UnknownExceptionCode"),
+        ];
+
+        for (exception, expected) in cases {
+            assert_eq!(crash_type(&exception), expected);
+        }
+
+        let exception =
+            MinidumpException::from(&exception(EXCEPTION_ACCESS_VIOLATION, vec![0, 0x41414141]));
+        assert_eq!(exception.code, "0xc0000005");
+        assert_eq!(exception.fault_address.as_deref(), Some("0x41414141"));
+    }
+
+    #[test]
+    fn test_symbolized_crash_log() -> Result<()> {
+        let dump = Minidump::parse(&std::fs::read(fixture("access-violation.dmp"))?)?;
+        let frames = dump.stack_walk(MAX_FRAMES);
+
+        let main = Function {
+            name: "main".to_owned(),
+            offset: Offset(0x1200),
+            size: 0x100,
+            noreturn: false,
+        };
+        let debuginfo = HashMap::from([(
+            APP_BASE,
+            DebugInfo::new(BTreeMap::from([(main.offset, main)]), None),
+        )]);
+
+        let crash_log = crash_log(&dump, &frames, &debuginfo)?;
+        assert_eq!(crash_log.fault_type, "WriteToNull");
+        assert_eq!(crash_log.call_stack, ["app!main+0x34"]);
+        let text = crash_log.text.as_deref().unwrap();
+        assert!(text.contains("    app+0x5678\n"), "{text}");
+        assert!(text.contains("    ntdll+0x9abc\n"), "{text}");
+
+        // Scanned frames don't affect how the crash is deduplicated.
+        let unscanned = super::crash_log(&dump, &frames[..1], &debuginfo)?;
+        assert_eq!(crash_log.call_stack_sha256(), unscanned.call_stack_sha256());
+        assert!(unscanned.text.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_dump_report() -> Result<()> {
+        let CrashTestResult::CrashReport(report) = test_fixture("access-violation.dmp").await?
+        else {
+            bail!("expected a crash report");
+        };
+        assert_eq!(report.crash_type, "WriteToNull");
+        assert_eq!(report.tool_name.as_deref(), Some(MINIDUMP_TOOL_NAME));
+        assert_eq!(report.call_stack.len(), 1);
+        assert!(report.asan_log.unwrap().contains("ntdll+0x9abc"));
+
+        let exception = report.minidump_exception.unwrap();
+        assert_eq!(exception.address, "0x7ff610001234");
+        assert_eq!(exception.fault_address.as_deref(), Some("0x0"));
+
+        Ok(())
+    }
+
+    // Dumps of crashes written by the debugger, as configured by `config`.
+    #[cfg(target_family = "windows")]
+    async fn debugger_dump(
+        config: &onefuzz::crash_dump::CrashDumpConfig,
+        dir: &Path,
+    ) -> Result<onefuzz::input_tester::TestResult> {
+        use onefuzz::input_tester::Tester;
+
+        let setup_dir = tempfile::tempdir()?;
+        let input = setup_dir.path().join("input");
+        std::fs::write(&input, "input")?;
+
+        let arguments = [
+            "/nop".to_owned(),
+            "/c".to_owned(),
+            "[Runtime.InteropServices.Marshal]::ReadInt32([IntPtr]::Zero)".to_owned(),
+        ];
+        let env = HashMap::new();
+        let machine_identity = MachineIdentity {
+            machine_id: Uuid::new_v4(),
+            machine_name: "test".to_owned(),
+            scaleset_name: None,
+        };
+
+        Tester::new(
+            setup_dir.path(),
+            None,
+            Path::new(r"C:\windows\system32\WindowsPowerShell\v1.0\powershell.exe"),
+            &arguments,
+            &env,
+            machine_identity,
+        )
+        .check_debugger(true)
+        .timeout(30)
+        .crash_dump(config, dir)
+        .test_input(&input)
+        .await
+    }
+
+    #[cfg(target_family = "windows")]
+    #[tokio::test]
+    async fn test_debugger_dump_parses() -> Result<()> {
+        use onefuzz::crash_dump::{CrashDumpConfig, DumpType};
+
+        let dir = tempfile::tempdir()?;
+        for dump_type in [DumpType::Mini, DumpType::WithHandleData] {
+            let config = CrashDumpConfig {
+                dump_type,
+                ..CrashDumpConfig::default()
+            };
+            let result = debugger_dump(&config, dir.path()).await?;
+            assert!(result.crash_log.is_some());
+
+            let path = result.crash_dump.expect("no crash dump");
+            assert_eq!(path.parent(), Some(dir.path()));
+
+            let dump = Minidump::parse(&fs::read(&path).await?)?;
+            assert_eq!(dump.exception.code, EXCEPTION_ACCESS_VIOLATION);
+            assert!(!dump.modules.is_empty());
+        }
+
+        Ok(())
+    }
+
+    #[cfg(target_family = "windows")]
+    #[tokio::test]
+    async fn test_debugger_dumps_are_capped() -> Result<()> {
+        use onefuzz::crash_dump::CrashDumpConfig;
+
+        let dir = tempfile::tempdir()?;
+        std::fs::write(dir.path().join("old.dmp"), [0; 16])?;
+
+        // Too small for any dump, which is then evicted, as are older ones.
+        let config = CrashDumpConfig {
+            max_total_size: 1,
+            ..CrashDumpConfig::default()
+        };
+        let result = debugger_dump(&config, dir.path()).await?;
+        assert!(result.crash_log.is_some());
+        assert!(result.crash_dump.is_none());
+        assert_eq!(std::fs::read_dir(dir.path())?.count(), 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_unparseable_dump() -> Result<()> {
+        let CrashTestResult::NoRepro(no_repro) = test_fixture("truncated.dmp").await? else {
+            bail!("expected an unparseable dump");
+        };
+        assert_eq!(no_repro.tries, 0);
+        assert!(no_repro
+            .error
+            .unwrap()
+            .starts_with("unparseable minidump: truncated minidump"));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,492 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! A reader of the parts of Windows minidumps needed to report their crash:
+//! the exception record, the context and stack of the faulting thread, and the
+//! module list.
+//!
+//! See https://learn.microsoft.com/en-us/windows/win32/api/minidumpapiset/
+
+use anyhow::{Context, Result};
+
+const SIGNATURE: u32 = 0x504d_444d; // "MDMP"
+const CODEVIEW_PDB70_SIGNATURE: u32 = 0x5344_5352; // "RSDS"
+
+const THREAD_LIST_STREAM: u32 = 3;
+const MODULE_LIST_STREAM: u32 = 4;
+const EXCEPTION_STREAM: u32 = 6;
+const SYSTEM_INFO_STREAM: u32 = 7;
+
+const HEADER_SIZE: usize = 32;
+const DIRECTORY_ENTRY_SIZE: usize = 12;
+const THREAD_SIZE: usize = 48;
+const MODULE_SIZE: usize = 108;
+
+// The number of exception parameters of a `MINIDUMP_EXCEPTION`.
+const EXCEPTION_MAXIMUM_PARAMETERS: usize = 15;
+
+/// The processor architecture of the dumped process.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Architecture {
+    X86,
+    Amd64,
+    Arm64,
+}
+
+impl Architecture {
+    fn from_processor_architecture(value: u16) -> Option<Self> {
+        match value {
+            0 => Some(Self::X86),
+            9 => Some(Self::Amd64),
+            12 => Some(Self::Arm64),
+            _ => None,
+        }
+    }
+
+    // The architecture of a thread context of `size` bytes, for dumps without
+    // system info.
+    fn from_context_size(size: usize) -> Option<Self> {
+        match size {
+            0x2cc => Some(Self::X86),
+            0x4d0 => Some(Self::Amd64),
+            0x390 => Some(Self::Arm64),
+            _ => None,
+        }
+    }
+
+    pub fn pointer_size(&self) -> usize {
+        match self {
+            Self::X86 => 4,
+            Self::Amd64 | Self::Arm64 => 8,
+        }
+    }
+
+    // The offsets of the instruction and stack pointers in a `CONTEXT`.
+    fn context_offsets(&self) -> (usize, usize) {
+        match self {
+            Self::X86 => (0xb8, 0xc4),
+            Self::Amd64 => (0xf8, 0x98),
+            Self::Arm64 => (0x108, 0x100),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Exception {
+    pub thread_id: u32,
+    pub code: u32,
+    pub address: u64,
+    pub parameters: Vec<u64>,
+}
+
+/// The registers of the faulting thread needed to walk its stack.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Context {
+    pub instruction_pointer: u64,
+    pub stack_pointer: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Module {
+    pub base: u64,
+    pub size: u32,
+
+    /// The path of the module, as loaded.
+    pub path: String,
+
+    /// The PDB the module was linked with, if recorded.
+    pub pdb: Option<PdbInfo>,
+}
+
+impl Module {
+    /// The file name of the module.
+    pub fn name(&self) -> &str {
+        self.path.rsplit(['/', '\\']).next().unwrap_or(&self.path)
+    }
+
+    pub fn contains(&self, address: u64) -> bool {
+        address >= self.base && address - self.base < u64::from(self.size)
+    }
+}
+
+/// The CodeView record of a module, identifying its PDB.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PdbInfo {
+    pub name: String,
+    pub guid: [u8; 16],
+    pub age: u32,
+}
+
+/// The memory of the stack of a thread, from its stack pointer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StackMemory {
+    pub start: u64,
+    pub data: Vec<u8>,
+}
+
+/// A frame of a stack walk.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub address: u64,
+    pub trust: FrameTrust,
+}
+
+/// How the address of a frame was found.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameTrust {
+    /// The instruction pointer of the thread context.
+    Context,
+
+    /// A word of the stack within a module, which may not be a return address.
+    Scan,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Minidump {
+    pub architecture: Option<Architecture>,
+    pub exception: Exception,
+    pub context: Option<Context>,
+    pub modules: Vec<Module>,
+    pub stack: Option<StackMemory>,
+}
+
+impl Minidump {
+    /// Parse a minidump, which must have an exception stream.
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let dump = Reader(data);
+
+        if data.len() < HEADER_SIZE {
+            bail!("truncated minidump: header");
+        }
+        if dump.u32(0, "signature")? != SIGNATURE {
+            bail!("not a minidump: invalid signature");
+        }
+        let stream_count = dump.u32(8, "stream count")? as usize;
+        let directory = dump.u32(12, "stream directory")? as usize;
+
+        let mut streams = vec![];
+        for i in 0..stream_count {
+            let entry = directory + i * DIRECTORY_ENTRY_SIZE;
+            let stream_type = dump.u32(entry, "stream directory")?;
+            let location = dump.location(entry + 4, "stream directory")?;
+            streams.push((stream_type, location));
+        }
+        let stream = |stream_type| {
+            streams
+                .iter()
+                .find(|(t, _)| *t == stream_type)
+                .map(|(_, location)| *location)
+        };
+
+        let architecture = match stream(SYSTEM_INFO_STREAM) {
+            Some(location) => {
+                let system_info = dump.slice(location, "system info")?;
+                Architecture::from_processor_architecture(system_info.u16(0, "system info")?)
+            }
+            None => None,
+        };
+
+        let (exception, exception_context) = match stream(EXCEPTION_STREAM) {
+            Some(location) => parse_exception(&dump.slice(location, "exception stream")?)?,
+            None => bail!("not a crash dump: no exception stream"),
+        };
+
+        let thread = match stream(THREAD_LIST_STREAM) {
+            Some(location) => {
+                find_thread(&dump.slice(location, "thread list")?, exception.thread_id)?
+            }
+            None => None,
+        };
+
+        // The context of the exception is that of the faulting instruction,
+        // whereas that of the thread may be of the dump being written.
+        let context_location = match exception_context {
+            Some(location) => Some(location),
+            None => thread.map(|thread| thread.context),
+        };
+        let context_data = context_location
+            .map(|location| dump.bytes(location, "thread context"))
+            .transpose()?;
+
+        let architecture = architecture
+            .or_else(|| context_data.and_then(|c| Architecture::from_context_size(c.len())));
+        let context = match (architecture, context_data) {
+            (Some(architecture), Some(data)) => Some(parse_context(architecture, data)?),
+            _ => None,
+        };
+
+        let modules = match stream(MODULE_LIST_STREAM) {
+            Some(location) => parse_modules(&dump, &dump.slice(location, "module list")?)?,
+            None => vec![],
+        };
+
+        let stack = match thread {
+            Some(thread) => Some(StackMemory {
+                start: thread.stack_start,
+                data: dump.bytes(thread.stack, "thread stack")?.to_vec(),
+            }),
+            None => None,
+        };
+
+        Ok(Self {
+            architecture,
+            exception,
+            context,
+            modules,
+            stack,
+        })
+    }
+
+    pub fn module(&self, address: u64) -> Option<&Module> {
+        self.modules.iter().find(|module| module.contains(address))
+    }
+
+    /// The frames of the faulting thread, starting with the faulting
+    /// instruction.
+    ///
+    /// Frames after the first are found by scanning the stack for addresses
+    /// within modules, as candidate return addresses. Without the unwind info
+    /// of the modules, these may include stale addresses left on the stack, so
+    /// are marked as [`FrameTrust::Scan`].
+    pub fn stack_walk(&self, max_frames: usize) -> Vec<Frame> {
+        let instruction_pointer = self
+            .context
+            .map(|c| c.instruction_pointer)
+            .unwrap_or(self.exception.address);
+        let mut frames = vec![Frame {
+            address: instruction_pointer,
+            trust: FrameTrust::Context,
+        }];
+
+        let (Some(architecture), Some(context), Some(stack)) =
+            (self.architecture, self.context, &self.stack)
+        else {
+            return frames;
+        };
+
+        let pointer_size = architecture.pointer_size();
+        let Some(offset) = context.stack_pointer.checked_sub(stack.start) else {
+            return frames;
+        };
+        let Ok(offset) = usize::try_from(offset) else {
+            return frames;
+        };
+
+        let words = stack
+            .data
+            .get(offset..)
+            .unwrap_or_default()
+            .chunks_exact(pointer_size);
+        for word in words {
+            if frames.len() >= max_frames {
+                break;
+            }
+
+            let address = match pointer_size {
+                4 => u64::from(u32::from_le_bytes(word.try_into().unwrap())),
+                _ => u64::from_le_bytes(word.try_into().unwrap()),
+            };
+            if self.module(address).is_some() {
+                frames.push(Frame {
+                    address,
+                    trust: FrameTrust::Scan,
+                });
+            }
+        }
+
+        frames
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Location {
+    size: usize,
+    rva: usize,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Thread {
+    stack_start: u64,
+    stack: Location,
+    context: Location,
+}
+
+// MINIDUMP_EXCEPTION_STREAM, and the location of the context of the faulting
+// thread, if any.
+fn parse_exception(stream: &Reader) -> Result<(Exception, Option<Location>)> {
+    let thread_id = stream.u32(0, "exception thread")?;
+    let code = stream.u32(8, "exception code")?;
+    let address = stream.u64(24, "exception address")?;
+
+    let count =
+        (stream.u32(32, "exception parameters")? as usize).min(EXCEPTION_MAXIMUM_PARAMETERS);
+    let parameters = (0..count)
+        .map(|i| stream.u64(40 + i * 8, "exception parameters"))
+        .collect::<Result<_>>()?;
+
+    let context = stream.location(40 + EXCEPTION_MAXIMUM_PARAMETERS * 8, "exception context")?;
+    let context = (context.size > 0).then_some(context);
+
+    let exception = Exception {
+        thread_id,
+        code,
+        address,
+        parameters,
+    };
+
+    Ok((exception, context))
+}
+
+// The MINIDUMP_THREAD of `thread_id`.
+fn find_thread(list: &Reader, thread_id: u32) -> Result<Option<Thread>> {
+    let count = list.u32(0, "thread count")? as usize;
+
+    for i in 0..count {
+        let thread = 4 + i * THREAD_SIZE;
+        if list.u32(thread, "thread")? != thread_id {
+            continue;
+        }
+
+        return Ok(Some(Thread {
+            stack_start: list.u64(thread + 24, "thread stack")?,
+            stack: list.location(thread + 32, "thread stack")?,
+            context: list.location(thread + 40, "thread context")?,
+        }));
+    }
+
+    Ok(None)
+}
+
+fn parse_context(architecture: Architecture, data: &[u8]) -> Result<Context> {
+    let context = Reader(data);
+    let (ip, sp) = architecture.context_offsets();
+
+    let (instruction_pointer, stack_pointer) = match architecture.pointer_size() {
+        4 => (
+            u64::from(context.u32(ip, "instruction pointer")?),
+            u64::from(context.u32(sp, "stack pointer")?),
+        ),
+        _ => (
+            context.u64(ip, "instruction pointer")?,
+            context.u64(sp, "stack pointer")?,
+        ),
+    };
+
+    Ok(Context {
+        instruction_pointer,
+        stack_pointer,
+    })
+}
+
+fn parse_modules(dump: &Reader, list: &Reader) -> Result<Vec<Module>> {
+    let count = list.u32(0, "module count")? as usize;
+
+    let mut modules = vec![];
+    for i in 0..count {
+        let module = 4 + i * MODULE_SIZE;
+        let base = list.u64(module, "module")?;
+        let size = list.u32(module + 8, "module")?;
+        let path = dump
+            .string(list.u32(module + 20, "module name")? as usize)
+            .with_context(|| format!("name of module at 0x{base:x}"))?;
+
+        let codeview = list.location(module + 76, "module codeview record")?;
+        let pdb = if codeview.size > 0 {
+            parse_codeview(dump.bytes(codeview, "module codeview record")?)
+        } else {
+            None
+        };
+
+        modules.push(Module {
+            base,
+            size,
+            path,
+            pdb,
+        });
+    }
+
+    Ok(modules)
+}
+
+// A CodeView PDB 7.0 record. Older formats are not supported, and ignored.
+fn parse_codeview(data: &[u8]) -> Option<PdbInfo> {
+    let record = Reader(data);
+    if record.u32(0, "codeview signature").ok()? != CODEVIEW_PDB70_SIGNATURE {
+        return None;
+    }
+
+    let guid = data.get(4..20)?.try_into().ok()?;
+    let age = record.u32(20, "codeview age").ok()?;
+
+    // The file name is NUL-terminated.
+    let name = data.get(24..)?.split(|b| *b == 0).next()?;
+    let name = String::from_utf8_lossy(name).into_owned();
+    if name.is_empty() {
+        return None;
+    }
+
+    Some(PdbInfo { name, guid, age })
+}
+
+// Little-endian reads, failing on data past the end.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn get<const N: usize>(&self, offset: usize, what: &str) -> Result<[u8; N]> {
+        offset
+            .checked_add(N)
+            .and_then(|end| self.0.get(offset..end))
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| format_err!("truncated minidump: {what} at 0x{offset:x}"))
+    }
+
+    fn u16(&self, offset: usize, what: &str) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.get(offset, what)?))
+    }
+
+    fn u32(&self, offset: usize, what: &str) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.get(offset, what)?))
+    }
+
+    fn u64(&self, offset: usize, what: &str) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.get(offset, what)?))
+    }
+
+    // A MINIDUMP_LOCATION_DESCRIPTOR.
+    fn location(&self, offset: usize, what: &str) -> Result<Location> {
+        Ok(Location {
+            size: self.u32(offset, what)? as usize,
+            rva: self.u32(offset + 4, what)? as usize,
+        })
+    }
+
+    fn bytes(&self, location: Location, what: &str) -> Result<&'a [u8]> {
+        location
+            .rva
+            .checked_add(location.size)
+            .and_then(|end| self.0.get(location.rva..end))
+            .ok_or_else(|| format_err!("truncated minidump: {what} at 0x{:x}", location.rva))
+    }
+
+    fn slice(&self, location: Location, what: &str) -> Result<Reader<'a>> {
+        Ok(Reader(self.bytes(location, what)?))
+    }
+
+    // A MINIDUMP_STRING, of UTF-16 code units.
+    fn string(&self, offset: usize) -> Result<String> {
+        let size = self.u32(offset, "string")? as usize;
+        let units: Vec<u16> = self
+            .bytes(
+                Location {
+                    size,
+                    rva: offset + 4,
+                },
+                "string",
+            )?
+            .chunks_exact(2)
+            .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+            .collect();
+
+        Ok(String::from_utf16_lossy(&units))
+    }
+}
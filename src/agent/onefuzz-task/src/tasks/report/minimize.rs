@@ -0,0 +1,212 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Minimization of crashing inputs, before they are reported.
+
+use super::crash_report::{CrashReport, InputBlob};
+use anyhow::Result;
+use onefuzz::{sha256, syncdir::SyncedDir};
+use std::{
+    future::Future,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::sync::Semaphore;
+
+/// Default time budget for minimizing a crash, in seconds.
+pub const DEFAULT_MINIMIZE_TIMEOUT: u64 = 5 * 60;
+
+/// Grace period after the time budget, for the minimizer to exit by itself.
+const MINIMIZE_GRACE_PERIOD: Duration = Duration::from_secs(30);
+
+lazy_static::lazy_static! {
+    // Crashes are minimized one at a time per agent, so that minimizing does
+    // not starve the fuzzers on the node.
+    static ref MINIMIZE_SLOTS: Semaphore = Semaphore::new(1);
+}
+
+pub fn default_minimize_timeout() -> u64 {
+    DEFAULT_MINIMIZE_TIMEOUT
+}
+
+/// Minimize the crashing `input` of `report`, then upload the minimized
+/// input to `minimized_crashes`, and link it in the report.
+///
+/// `minimize` is given the path to write the minimized input to, and should
+/// stop after `timeout` seconds. If it fails, runs for too long, or does not
+/// make the input smaller, the report is left as the report of the original
+/// input.
+pub async fn minimize_crash<F, Fut>(
+    report: &mut CrashReport,
+    input: &Path,
+    minimized_crashes: &SyncedDir,
+    timeout: u64,
+    minimize: F,
+) where
+    F: FnOnce(PathBuf) -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    if let Err(err) = try_minimize_crash(report, input, minimized_crashes, timeout, minimize).await
+    {
+        warn!(
+            "unable to minimize crash, reporting the original input: {}: {:?}",
+            input.display(),
+            err
+        );
+    }
+}
+
+async fn try_minimize_crash<F, Fut>(
+    report: &mut CrashReport,
+    input: &Path,
+    minimized_crashes: &SyncedDir,
+    timeout: u64,
+    minimize: F,
+) -> Result<()>
+where
+    F: FnOnce(PathBuf) -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    let _slot = MINIMIZE_SLOTS.acquire().await?;
+
+    let dir = tempfile::tempdir()?;
+    let output = dir.path().join("minimized");
+
+    let budget = Duration::from_secs(timeout) + MINIMIZE_GRACE_PERIOD;
+    match tokio::time::timeout(budget, minimize(output.clone())).await {
+        Ok(result) => result?,
+        Err(_) => bail!("minimization timed out after {} seconds", budget.as_secs()),
+    }
+
+    let input_size = tokio::fs::metadata(input).await?.len();
+    let minimized_size = match tokio::fs::metadata(&output).await {
+        Ok(metadata) => metadata.len(),
+        Err(_) => bail!("no minimized input was written"),
+    };
+    if minimized_size >= input_size {
+        bail!("the input was not made smaller");
+    }
+
+    let minimized_sha256 = sha256::digest_file(&output).await?;
+    let name = format!("minimized-{}", report.input_sha256);
+    let blob = minimized_crashes.upload_file(&output, &name).await?;
+
+    info!(
+        "minimized crash {} from {} to {} bytes",
+        input.display(),
+        input_size,
+        minimized_size
+    );
+
+    report.input_size = Some(input_size);
+    report.minimized_input_blob = Some(InputBlob::from(blob));
+    report.minimized_input_sha256 = Some(minimized_sha256);
+    report.minimized_input_size = Some(minimized_size);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn minimized_crashes(dir: &Path) -> SyncedDir {
+        SyncedDir {
+            local_path: dir.join("minimized_crashes"),
+            remote_path: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_minimize_crash() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("crash");
+        tokio::fs::write(&input, "crashing input").await?;
+
+        let mut report = CrashReport {
+            input_sha256: sha256::digest_file(&input).await?,
+            ..CrashReport::default()
+        };
+        let minimized_crashes = minimized_crashes(dir.path());
+
+        minimize_crash(
+            &mut report,
+            &input,
+            &minimized_crashes,
+            10,
+            |output| async move { Ok::<_, anyhow::Error>(tokio::fs::write(output, "cras").await?) },
+        )
+        .await;
+
+        let name = format!("minimized-{}", report.input_sha256);
+        let blob = report.minimized_input_blob.as_ref().unwrap();
+        assert_eq!(blob.name, name);
+        assert_eq!(report.input_size, Some(14));
+        assert_eq!(report.minimized_input_size, Some(4));
+        assert_eq!(report.minimized_input_sha256, Some(sha256::digest(b"cras")));
+        assert_eq!(
+            tokio::fs::read_to_string(minimized_crashes.local_path.join(name)).await?,
+            "cras"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_minimize_crash_fallback() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("crash");
+        tokio::fs::write(&input, "crashing input").await?;
+        let minimized_crashes = minimized_crashes(dir.path());
+
+        let mut report = CrashReport::default();
+        minimize_crash(&mut report, &input, &minimized_crashes, 10, |_| async {
+            Err::<(), _>(anyhow::anyhow!("minimizer failed"))
+        })
+        .await;
+        assert!(report.minimized_input_blob.is_none());
+
+        // Not made any smaller.
+        minimize_crash(
+            &mut report,
+            &input,
+            &minimized_crashes,
+            10,
+            |output| async move {
+                Ok::<_, anyhow::Error>(tokio::fs::write(output, "crashing input").await?)
+            },
+        )
+        .await;
+        assert!(report.minimized_input_blob.is_none());
+
+        // Nothing written.
+        minimize_crash(&mut report, &input, &minimized_crashes, 10, |_| async {
+            Ok::<_, anyhow::Error>(())
+        })
+        .await;
+        assert!(report.minimized_input_blob.is_none());
+
+        assert!(report.input_size.is_none());
+        assert!(!minimized_crashes.local_path.exists());
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_minimize_crash_timeout() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("crash");
+        tokio::fs::write(&input, "crashing input").await?;
+        let minimized_crashes = minimized_crashes(dir.path());
+
+        let mut report = CrashReport::default();
+        minimize_crash(&mut report, &input, &minimized_crashes, 10, |_| async {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            Ok::<_, anyhow::Error>(())
+        })
+        .await;
+        assert!(report.minimized_input_blob.is_none());
+
+        Ok(())
+    }
+}
@@ -1,7 +1,14 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
+pub mod bundle;
+pub mod compat;
 pub mod crash_report;
 pub mod dotnet;
 pub mod generic;
 pub mod libfuzzer_report;
+pub mod minidump;
+pub mod minimize;
+pub mod pool;
+pub mod redact;
+pub mod repro;
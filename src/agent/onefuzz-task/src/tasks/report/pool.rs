@@ -0,0 +1,522 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Concurrent processing of the inputs of report tasks, so that a crashes
+//! container with a backlog of inputs isn't tested one input at a time.
+//!
+//! Up to `report_concurrency` inputs are tested at once, each by its own run
+//! of the target, and, if downloaded from the input queue, in its own temporary
+//! directory. Their reports are saved one at a time, as their tests finish, so
+//! that saves to the report containers never race, such as of two inputs of
+//! the same unique report. The inputs of the crashes container and of the
+//! input queue are processed by the same pool, one after the other.
+
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use futures::stream::{self, Stream, StreamExt, TryStreamExt};
+use onefuzz::{jitter::delay_with_jitter, syncdir::SyncedDir};
+use onefuzz_telemetry::{Event::report_throughput, EventData};
+use reqwest::Url;
+use tempfile::{tempdir, TempDir};
+use tokio::{
+    fs,
+    sync::{mpsc, Mutex},
+    time::Duration,
+};
+use tokio_stream::wrappers::ReadDirStream;
+
+use super::crash_report::CrashTestResult;
+use crate::tasks::generic::input_poller::{
+    batch_input_url, seen_in_batch, Callback, Processor, POLL_INTERVAL,
+};
+
+// How often the throughput of a pool is reported, as it processes inputs.
+const THROUGHPUT_INTERVAL: Duration = Duration::from_secs(60);
+
+pub fn default_report_concurrency() -> usize {
+    (num_cpus::get() / 2).max(1)
+}
+
+/// The processor of the inputs of a report task, of which inputs are tested
+/// concurrently, and their reports saved one at a time.
+#[async_trait]
+pub trait ReportProcessor: Sync {
+    async fn test(&self, url: Option<Url>, input: &Path) -> Result<CrashTestResult>;
+
+    async fn save(&self, input: &Path, report: CrashTestResult) -> Result<()>;
+}
+
+// Processes one input at a time, as by the input poller.
+#[async_trait]
+impl<P: ReportProcessor> Processor for &P {
+    async fn process(&mut self, url: Option<Url>, input: &Path) -> Result<()> {
+        let report = self.test(url, input).await?;
+        self.save(input, report).await
+    }
+}
+
+// An input to test, with the directory it was downloaded to, if any, deleted
+// once the input is processed, and its message, if from a queue, deleted once
+// its report is saved.
+struct Job<M> {
+    url: Option<Url>,
+    input: PathBuf,
+    msg: Option<M>,
+    _dir: Option<TempDir>,
+}
+
+pub struct ReportPool<'a, P> {
+    name: String,
+    processor: &'a P,
+    concurrency: usize,
+    batch_dir: Option<SyncedDir>,
+    throughput: Throughput,
+}
+
+impl<'a, P: ReportProcessor> ReportPool<'a, P> {
+    pub fn new(name: impl AsRef<str>, processor: &'a P, concurrency: usize) -> Self {
+        Self {
+            name: name.as_ref().to_owned(),
+            processor,
+            concurrency: concurrency.max(1),
+            batch_dir: None,
+            throughput: Throughput::new(),
+        }
+    }
+
+    /// Process the inputs of `to_process`, failing on the first input which
+    /// can't be processed.
+    pub async fn batch_process(&mut self, to_process: &SyncedDir) -> Result<()> {
+        self.batch_dir = Some(to_process.clone());
+        to_process
+            .init_pull()
+            .await
+            .with_context(|| format!("pulling to_process {}", to_process.local_path.display()))?;
+        info!(
+            "batch processing directory: {} - {}",
+            self.name,
+            to_process.local_path.display()
+        );
+
+        let read_dir = fs::read_dir(&to_process.local_path)
+            .await
+            .with_context(|| {
+                format!("read directory failed: {}", to_process.local_path.display())
+            })?;
+        let jobs = ReadDirStream::new(read_dir).map(|entry| -> Result<Job<()>> {
+            let input = entry?.path();
+            let url = batch_input_url(to_process, &input)?;
+            Ok(Job {
+                url,
+                input,
+                msg: None,
+                _dir: None,
+            })
+        });
+
+        self.process(jobs, |job, result| async move {
+            result.with_context(|| format!("process input failed: {}", job.input.display()))
+        })
+        .await?;
+        self.throughput.report(&self.name);
+
+        Ok(())
+    }
+
+    /// Process the inputs of the queue of `cb`, deleting the message of each
+    /// input once its report is saved. Inputs which can't be processed are
+    /// retried once their messages are visible again.
+    pub async fn run<M, C>(&mut self, cb: &mut C) -> Result<()>
+    where
+        M: Send,
+        C: Callback<M> + Send,
+    {
+        info!("starting input queue polling: {}", self.name);
+
+        let cb = &Mutex::new(cb);
+        let batch_dir = self.batch_dir.clone();
+        let batch_dir = batch_dir.as_ref();
+        let jobs = stream::unfold((), move |()| async move {
+            Some((Ok::<_, anyhow::Error>(next_job(cb, batch_dir).await), ()))
+        });
+
+        self.process(jobs, move |job, result| async move {
+            match result {
+                Ok(()) => {
+                    if let Some(msg) = job.msg {
+                        if let Err(err) = cb.lock().await.queue().delete(msg).await {
+                            warn!("unable to delete message of processed input: {:?}", err);
+                        }
+                    }
+                }
+                Err(err) => warn!(
+                    "unable to process input, to be retried: {}: {:?}",
+                    job.input.display(),
+                    err
+                ),
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    // Test the inputs of `jobs`, up to `concurrency` at once, and save their
+    // reports, one at a time, passing the result of saving each to `saved`.
+    async fn process<M, F>(
+        &mut self,
+        jobs: impl Stream<Item = Result<Job<M>>>,
+        mut saved: impl FnMut(Job<M>, Result<()>) -> F,
+    ) -> Result<()>
+    where
+        F: Future<Output = Result<()>>,
+    {
+        let processor = self.processor;
+        let name = &self.name;
+        let throughput = &mut self.throughput;
+        let (tx, mut rx) = mpsc::channel(self.concurrency);
+
+        let testing = jobs
+            .map_ok(|job| async move {
+                info!("processing {} input: {}", name, job.input.display());
+                let result = processor.test(job.url.clone(), &job.input).await;
+                Ok((job, result))
+            })
+            .try_buffer_unordered(self.concurrency)
+            .try_for_each(move |tested| {
+                let tx = tx.clone();
+                async move {
+                    tx.send(tested)
+                        .await
+                        .map_err(|_| format_err!("report writer stopped"))
+                }
+            });
+
+        let writing = async {
+            while let Some((job, result)) = rx.recv().await {
+                let result = match result {
+                    Ok(report) => processor.save(&job.input, report).await,
+                    Err(err) => Err(err),
+                };
+                throughput.record(name);
+                saved(job, result).await?;
+            }
+            Ok::<_, anyhow::Error>(())
+        };
+
+        futures::try_join!(testing, writing)?;
+        Ok(())
+    }
+}
+
+// The next input of the queue of `cb`, waiting for one if the queue is empty.
+// Inputs seen when batch processing `batch_dir` are skipped.
+async fn next_job<M, C>(cb: &Mutex<&mut C>, batch_dir: Option<&SyncedDir>) -> Job<M>
+where
+    C: Callback<M>,
+{
+    loop {
+        match try_next_job(cb, batch_dir).await {
+            Ok(Some(job)) => return job,
+            Ok(None) => {
+                debug!("Input queue empty, sleeping");
+                delay_with_jitter(POLL_INTERVAL).await;
+            }
+            Err(err) => {
+                warn!("unable to get input from queue: {:?}", err);
+                delay_with_jitter(POLL_INTERVAL).await;
+            }
+        }
+    }
+}
+
+async fn try_next_job<M, C>(
+    cb: &Mutex<&mut C>,
+    batch_dir: Option<&SyncedDir>,
+) -> Result<Option<Job<M>>>
+where
+    C: Callback<M>,
+{
+    let mut cb = cb.lock().await;
+    loop {
+        let Some(msg) = cb.queue().pop().await? else {
+            return Ok(None);
+        };
+        let url = cb.parser().parse(&msg)?;
+
+        if batch_dir.is_some_and(|batch_dir| seen_in_batch(batch_dir, &url)) {
+            debug!("url was seen during batch processing: {:?}", url);
+            cb.queue().delete(msg).await?;
+            continue;
+        }
+
+        let dir = tempdir()?;
+        let input = cb.downloader().download(url.clone(), dir.path()).await?;
+
+        return Ok(Some(Job {
+            url: Some(url),
+            input,
+            msg: Some(msg),
+            _dir: Some(dir),
+        }));
+    }
+}
+
+// The count of the inputs processed by a pool, and their rate since it was
+// last reported.
+struct Throughput {
+    processed: u64,
+    reported: u64,
+    reported_at: Instant,
+}
+
+impl Throughput {
+    fn new() -> Self {
+        Self {
+            processed: 0,
+            reported: 0,
+            reported_at: Instant::now(),
+        }
+    }
+
+    fn record(&mut self, name: &str) {
+        self.processed += 1;
+        if self.reported_at.elapsed() >= THROUGHPUT_INTERVAL {
+            self.report(name);
+        }
+    }
+
+    fn report(&mut self, name: &str) {
+        let elapsed = self.reported_at.elapsed().as_secs_f64();
+        let rate = if elapsed > 0.0 {
+            (self.processed - self.reported) as f64 / elapsed
+        } else {
+            0.0
+        };
+
+        info!(
+            "{} processed {} inputs, {:.2} per second",
+            name, self.processed, rate
+        );
+        event!(report_throughput; EventData::Processed = self.processed, EventData::Rate = rate);
+        metric!(report_throughput; rate; EventData::Processed = self.processed);
+
+        self.reported = self.processed;
+        self.reported_at = Instant::now();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex as StdMutex};
+
+    use super::*;
+    use crate::tasks::generic::input_poller::{Downloader, Parser, Queue};
+    use crate::tasks::report::crash_report::CrashReport;
+
+    // Tests inputs as a target would run, checking how many are tested at
+    // once, and that reports are saved one at a time.
+    #[derive(Default)]
+    struct StubProcessor {
+        fail_on: Option<&'static str>,
+        testing: AtomicUsize,
+        max_testing: AtomicUsize,
+        saving: AtomicBool,
+        saved: StdMutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl ReportProcessor for StubProcessor {
+        async fn test(&self, _url: Option<Url>, input: &Path) -> Result<CrashTestResult> {
+            let testing = self.testing.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_testing.fetch_max(testing, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            self.testing.fetch_sub(1, Ordering::SeqCst);
+
+            let name = input.file_name().unwrap().to_string_lossy().into_owned();
+            if self.fail_on == Some(name.as_str()) {
+                bail!("target failed to run");
+            }
+
+            Ok(CrashTestResult::CrashReport(Box::new(CrashReport {
+                input_sha256: name,
+                ..CrashReport::default()
+            })))
+        }
+
+        async fn save(&self, _input: &Path, report: CrashTestResult) -> Result<()> {
+            assert!(!self.saving.swap(true, Ordering::SeqCst));
+            tokio::time::sleep(Duration::from_millis(1)).await;
+
+            let CrashTestResult::CrashReport(report) = report else {
+                bail!("expected CrashReport");
+            };
+            self.saved.lock().unwrap().push(report.input_sha256);
+
+            self.saving.store(false, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    async fn backlog(count: usize) -> Result<tempfile::TempDir> {
+        let dir = tempfile::tempdir()?;
+        for i in 0..count {
+            fs::write(dir.path().join(format!("crash-{i}")), format!("input {i}")).await?;
+        }
+        Ok(dir)
+    }
+
+    fn names(count: usize, except: &[usize]) -> Vec<String> {
+        let mut names: Vec<_> = (0..count)
+            .filter(|i| !except.contains(i))
+            .map(|i| format!("crash-{i}"))
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn saved(processor: &StubProcessor) -> Vec<String> {
+        let mut saved = processor.saved.lock().unwrap().clone();
+        saved.sort();
+        saved
+    }
+
+    #[tokio::test]
+    async fn test_batch_process_backlog() -> Result<()> {
+        let dir = backlog(50).await?;
+        let crashes = SyncedDir {
+            local_path: dir.path().to_owned(),
+            remote_path: None,
+        };
+
+        let processor = StubProcessor::default();
+        let mut pool = ReportPool::new("test", &processor, 4);
+        pool.batch_process(&crashes).await?;
+
+        assert_eq!(saved(&processor), names(50, &[]));
+        let max_testing = processor.max_testing.load(Ordering::SeqCst);
+        assert!((2..=4).contains(&max_testing), "{max_testing}");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_batch_process_failure() -> Result<()> {
+        let dir = backlog(10).await?;
+        let crashes = SyncedDir {
+            local_path: dir.path().to_owned(),
+            remote_path: None,
+        };
+
+        let processor = StubProcessor {
+            fail_on: Some("crash-3"),
+            ..StubProcessor::default()
+        };
+        let mut pool = ReportPool::new("test", &processor, 4);
+        assert!(pool.batch_process(&crashes).await.is_err());
+        assert!(!saved(&processor).contains(&"crash-3".to_string()));
+
+        Ok(())
+    }
+
+    // A queue of the inputs of a directory, of which message `i` is of
+    // `crash-{i}`.
+    struct StubQueue {
+        inputs: PathBuf,
+        pending: Vec<u64>,
+        deleted: Arc<StdMutex<Vec<u64>>>,
+    }
+
+    impl Callback<u64> for StubQueue {
+        fn queue(&mut self) -> &mut dyn Queue<u64> {
+            self
+        }
+
+        fn parser(&mut self) -> &mut dyn Parser<u64> {
+            self
+        }
+
+        fn downloader(&mut self) -> &mut dyn Downloader {
+            self
+        }
+
+        fn processor(&mut self) -> &mut dyn Processor {
+            unreachable!("inputs are processed by the pool")
+        }
+    }
+
+    #[async_trait]
+    impl Queue<u64> for StubQueue {
+        async fn pop(&mut self) -> Result<Option<u64>> {
+            Ok(self.pending.pop())
+        }
+
+        async fn delete(&mut self, msg: u64) -> Result<()> {
+            self.deleted.lock().unwrap().push(msg);
+            Ok(())
+        }
+    }
+
+    impl Parser<u64> for StubQueue {
+        fn parse(&mut self, msg: &u64) -> Result<Url> {
+            Url::from_file_path(self.inputs.join(format!("crash-{msg}")))
+                .map_err(|_| format_err!("invalid input path"))
+        }
+    }
+
+    #[async_trait]
+    impl Downloader for StubQueue {
+        async fn download(&mut self, url: Url, dir: &Path) -> Result<PathBuf> {
+            let path = url
+                .to_file_path()
+                .map_err(|_| format_err!("invalid input url"))?;
+            let input = dir.join(path.file_name().unwrap());
+            fs::copy(&path, &input).await?;
+            Ok(input)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_queue_backlog() -> Result<()> {
+        let dir = backlog(30).await?;
+        let deleted = Arc::new(StdMutex::new(vec![]));
+        let mut queue = StubQueue {
+            inputs: dir.path().to_owned(),
+            pending: (0..30).collect(),
+            deleted: deleted.clone(),
+        };
+
+        let processor = StubProcessor {
+            fail_on: Some("crash-7"),
+            ..StubProcessor::default()
+        };
+        let mut pool = ReportPool::new("test", &processor, 3);
+
+        // The queue is polled until the task stops, so wait for the messages
+        // of the inputs which don't fail to be deleted.
+        let deleted_all = async {
+            while deleted.lock().unwrap().len() < 29 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        tokio::select! {
+            result = pool.run(&mut queue) => bail!("queue polling stopped: {:?}", result),
+            waited = tokio::time::timeout(Duration::from_secs(60), deleted_all) => waited?,
+        }
+
+        assert_eq!(saved(&processor), names(30, &[7]));
+        let max_testing = processor.max_testing.load(Ordering::SeqCst);
+        assert!((2..=3).contains(&max_testing), "{max_testing}");
+
+        // The message of the input which failed is left to be retried.
+        let mut deleted = deleted.lock().unwrap().clone();
+        deleted.sort();
+        assert_eq!(deleted, (0..30).filter(|&i| i != 7).collect::<Vec<_>>());
+
+        Ok(())
+    }
+}
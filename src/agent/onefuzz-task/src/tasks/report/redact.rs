@@ -0,0 +1,247 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Redaction of the user names and internal paths of reports, such as of
+//! reports of developer-provisioned pools, which may be shared externally.
+//!
+//! Crash logs are redacted before their stacks are hashed, so that the hashes
+//! of a report are of its stacks as recorded, and reports of the same crash on
+//! the machines of different users are deduplicated together.
+
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::Deserialize;
+use stacktrace_parser::CrashLog;
+
+use super::repro::Reproduction;
+use crate::tasks::utils::default_bool_true;
+
+// The user directories of Windows, such as `C:\Users\jsmith`, also as escaped
+// or with `/`, and of Linux and macOS, such as `/home/alice` and
+// `/Users/alice`. Names starting with `<` are not matched, so that redacted
+// text is unchanged by redacting it again.
+const DEFAULT_RULES: &[(&str, &str)] = &[
+    (
+        r#"(?i)\b([a-z]:[\\/]+(?:users|documents and settings)[\\/]+)[^\\/\s:*?"'<>|]+"#,
+        "${1}<USER>",
+    ),
+    (r#"(/(?:home|Users)/)[^/\s:"'<>]+"#, "${1}<USER>"),
+];
+
+/// Options redacting the strings of reports.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+pub struct Redaction {
+    /// Redact the user names of user directories, as `<USER>`.
+    #[serde(default = "default_bool_true")]
+    pub default_rules: bool,
+
+    /// Rules applied in order, before the default rules.
+    #[serde(default)]
+    pub rules: Vec<RedactionRule>,
+}
+
+impl Redaction {
+    /// Check that the regexes of the rules are valid.
+    pub fn check(&self) -> Result<()> {
+        Redactor::new(Some(self))?;
+        Ok(())
+    }
+}
+
+/// A rule replacing either the matches of a regex, whose replacement may refer
+/// to its groups as `$1`, or a literal prefix, such as the source root of a
+/// build with `<SRCROOT>`.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(untagged)]
+pub enum RedactionRule {
+    Regex { regex: String, replacement: String },
+    Prefix { prefix: String, replacement: String },
+}
+
+impl RedactionRule {
+    fn compile(&self) -> Result<(Regex, String)> {
+        match self {
+            Self::Regex { regex, replacement } => {
+                let compiled = Regex::new(regex)
+                    .with_context(|| format!("invalid redaction regex: {regex}"))?;
+                Ok((compiled, replacement.clone()))
+            }
+            Self::Prefix {
+                prefix,
+                replacement,
+            } => Ok((
+                Regex::new(&regex::escape(prefix))?,
+                replacement.replace('$', "$$"),
+            )),
+        }
+    }
+}
+
+/// The compiled rules of a [`Redaction`]. The default redactor, of no
+/// redaction, redacts nothing.
+#[derive(Clone, Debug, Default)]
+pub struct Redactor {
+    rules: Vec<(Regex, String)>,
+}
+
+impl Redactor {
+    pub fn new(redaction: Option<&Redaction>) -> Result<Self> {
+        let Some(redaction) = redaction else {
+            return Ok(Self::default());
+        };
+
+        let mut rules = redaction
+            .rules
+            .iter()
+            .map(RedactionRule::compile)
+            .collect::<Result<Vec<_>>>()?;
+
+        if redaction.default_rules {
+            for (regex, replacement) in DEFAULT_RULES {
+                rules.push((Regex::new(regex)?, replacement.to_string()));
+            }
+        }
+
+        Ok(Self { rules })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn redact(&self, text: &str) -> String {
+        let mut text = Cow::Borrowed(text);
+        for (regex, replacement) in &self.rules {
+            if let Cow::Owned(redacted) = regex.replace_all(&text, replacement.as_str()) {
+                text = Cow::Owned(redacted);
+            }
+        }
+        text.into_owned()
+    }
+
+    pub fn redact_path(&self, path: &Path) -> PathBuf {
+        if self.is_empty() {
+            return path.to_owned();
+        }
+        PathBuf::from(self.redact(&path.to_string_lossy()))
+    }
+
+    /// Redact the text, summary and stacks of `crash_log`.
+    pub fn redact_log(&self, crash_log: &mut CrashLog) {
+        if !self.is_empty() {
+            crash_log.redact(|text| self.redact(text));
+        }
+    }
+
+    /// Redact the errors, logged by the target, of the attempts to reproduce a
+    /// crash.
+    pub fn redact_reproduction(&self, reproduction: &mut Reproduction) {
+        for error in reproduction
+            .attempts
+            .iter_mut()
+            .filter_map(|attempt| attempt.error.as_mut())
+        {
+            *error = self.redact(error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(config: &str) -> Redactor {
+        let redaction: Redaction = serde_json::from_str(config).unwrap();
+        Redactor::new(Some(&redaction)).unwrap()
+    }
+
+    #[test]
+    fn test_default_rules() {
+        let redactor = parse("{}");
+
+        for (text, redacted) in [
+            (
+                r"C:\Users\jsmith\src\fuzz.c:10",
+                r"C:\Users\<USER>\src\fuzz.c:10",
+            ),
+            (
+                r"(c:\users\jsmith\build\fuzz.exe+0x1234)",
+                r"(c:\users\<USER>\build\fuzz.exe+0x1234)",
+            ),
+            (r#""C:\\Users\\jsmith\\src""#, r#""C:\\Users\\<USER>\\src""#),
+            ("C:/Users/jsmith/src", "C:/Users/<USER>/src"),
+            (
+                r"D:\Documents and Settings\jsmith\fuzz.c",
+                r"D:\Documents and Settings\<USER>\fuzz.c",
+            ),
+            (
+                "#0 0x4abe12 in f /home/alice/src/a.c:3:5",
+                "#0 0x4abe12 in f /home/<USER>/src/a.c:3:5",
+            ),
+            (
+                "(/Users/alice/out/fuzz+0x10)",
+                "(/Users/<USER>/out/fuzz+0x10)",
+            ),
+            ("/home/alice", "/home/<USER>"),
+        ] {
+            assert_eq!(redactor.redact(text), redacted);
+
+            // Redacted text is unchanged by redacting it again.
+            assert_eq!(redactor.redact(redacted), redacted);
+        }
+
+        for text in ["/root/src/a.c", "/usr/home.c", "src/users/a.c"] {
+            assert_eq!(redactor.redact(text), text);
+        }
+    }
+
+    #[test]
+    fn test_custom_rules() {
+        let redactor = parse(
+            r#"{
+                "rules": [
+                    {"prefix": "/home/alice/work/src", "replacement": "<SRCROOT>"},
+                    {"regex": "corp-(\\w+)-build", "replacement": "<HOST:$1>"}
+                ]
+            }"#,
+        );
+
+        assert_eq!(
+            redactor.redact("/home/alice/work/src/a.c on corp-eu-build"),
+            "<SRCROOT>/a.c on <HOST:eu>"
+        );
+        assert_eq!(
+            redactor.redact("/home/alice/work/out/fuzz"),
+            "/home/<USER>/work/out/fuzz"
+        );
+
+        let redactor = parse(
+            r#"{
+                "default_rules": false,
+                "rules": [{"prefix": "C:\\build", "replacement": "$SRC"}]
+            }"#,
+        );
+        assert_eq!(
+            redactor.redact(r"C:\build\a.c in /home/alice"),
+            r"$SRC\a.c in /home/alice"
+        );
+    }
+
+    #[test]
+    fn test_no_redaction() -> Result<()> {
+        let redactor = Redactor::new(None)?;
+        assert!(redactor.is_empty());
+        assert_eq!(redactor.redact("/home/alice"), "/home/alice");
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_regex() {
+        let redaction: Redaction =
+            serde_json::from_str(r#"{"rules": [{"regex": "(", "replacement": ""}]}"#).unwrap();
+        assert!(redaction.check().is_err());
+    }
+}
@@ -0,0 +1,279 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Retries of crashing inputs which don't reproduce, before they are reported
+//! as not reproducing.
+
+use anyhow::Result;
+use onefuzz::input_tester::{TestResult, TestRun};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, future::Future};
+
+/// Default number of times to retry an input which doesn't reproduce.
+pub const DEFAULT_REPRO_RETRIES: u64 = 3;
+
+pub fn default_repro_retries() -> u64 {
+    DEFAULT_REPRO_RETRIES
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct ReproRetries {
+    /// The number of times to retry an input which doesn't reproduce.
+    #[serde(rename = "repro_retries", default = "default_repro_retries")]
+    pub count: u64,
+
+    /// The timeouts of successive retries, in seconds. Retries after the last
+    /// use the last, and the target timeout is used if there are none.
+    #[serde(rename = "repro_retry_timeouts", default)]
+    pub timeouts: Vec<u64>,
+
+    /// The `ASAN_OPTIONS` of successive retries, appended to those of the
+    /// target environment. Retries after the last use the last.
+    #[serde(rename = "repro_retry_asan_options", default)]
+    pub asan_options: Vec<String>,
+}
+
+impl Default for ReproRetries {
+    fn default() -> Self {
+        Self {
+            count: DEFAULT_REPRO_RETRIES,
+            timeouts: vec![],
+            asan_options: vec![],
+        }
+    }
+}
+
+/// The outcome of an attempt to reproduce a crash.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ReproAttempt {
+    pub reproduced: bool,
+
+    /// The timeout and the appended `ASAN_OPTIONS` of the attempt, if varied.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub asan_options: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+
+    /// The runs of the target, with their output, which are saved with inputs
+    /// which don't reproduce, rather than in reports.
+    #[serde(skip)]
+    pub runs: Vec<TestRun>,
+}
+
+/// The attempts to reproduce a crash, up to the first which reproduced it.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Reproduction {
+    /// Such as `reproduced 1/3 attempts`.
+    pub summary: String,
+
+    /// Set if the crash reproduced, but not on the first attempt.
+    pub flaky: bool,
+
+    pub attempts: Vec<ReproAttempt>,
+}
+
+impl Reproduction {
+    fn new(attempts: Vec<ReproAttempt>) -> Self {
+        let reproduced = attempts.iter().filter(|attempt| attempt.reproduced).count();
+
+        Self {
+            summary: format!("reproduced {reproduced}/{} attempts", attempts.len()),
+            flaky: reproduced > 0 && reproduced < attempts.len(),
+            attempts,
+        }
+    }
+}
+
+impl ReproRetries {
+    /// No retries, as for tasks which check a crash only as configured.
+    pub fn none() -> Self {
+        Self {
+            count: 0,
+            ..Self::default()
+        }
+    }
+
+    /// Test an input with `test`, given the timeout and environment of each
+    /// attempt, first as configured by `timeout` and `env`, then retried while
+    /// it doesn't reproduce.
+    pub async fn test<F, Fut>(
+        &self,
+        timeout: Option<u64>,
+        env: &HashMap<String, String>,
+        mut test: F,
+    ) -> Result<(TestResult, Reproduction)>
+    where
+        F: FnMut(Option<u64>, HashMap<String, String>) -> Fut,
+        Fut: Future<Output = Result<TestResult>>,
+    {
+        let mut attempts = vec![];
+
+        loop {
+            let attempt = attempts.len() as u64;
+            let (retry_timeout, asan_options) = match attempt.checked_sub(1) {
+                Some(retry) => (
+                    retry_option(&self.timeouts, retry).copied(),
+                    retry_option(&self.asan_options, retry).cloned(),
+                ),
+                None => (None, None),
+            };
+
+            let mut env = env.clone();
+            if let Some(asan_options) = &asan_options {
+                match env.get_mut("ASAN_OPTIONS") {
+                    Some(options) => {
+                        options.push(':');
+                        options.push_str(asan_options);
+                    }
+                    None => {
+                        env.insert("ASAN_OPTIONS".to_string(), asan_options.clone());
+                    }
+                }
+            }
+
+            let mut result = test(retry_timeout.or(timeout), env).await?;
+            let reproduced = result.crash_log.is_some();
+            attempts.push(ReproAttempt {
+                reproduced,
+                timeout: retry_timeout,
+                asan_options,
+                error: result.error.as_ref().map(|e| format!("{e}")),
+                runs: std::mem::take(&mut result.runs),
+            });
+
+            if reproduced || attempt == self.count {
+                return Ok((result, Reproduction::new(attempts)));
+            }
+
+            debug!(
+                "input did not reproduce, retrying: attempt {}/{}",
+                attempt + 1,
+                self.count + 1
+            );
+        }
+    }
+}
+
+fn retry_option<T>(options: &[T], retry: u64) -> Option<&T> {
+    usize::try_from(retry)
+        .ok()
+        .and_then(|retry| options.get(retry))
+        .or(options.last())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use onefuzz::input_tester::TestOutcome;
+    use stacktrace_parser::CrashLog;
+
+    fn crash_on(attempt: usize) -> impl FnMut(Option<u64>, HashMap<String, String>) -> Fut {
+        let mut count = 0;
+        move |_, _| {
+            count += 1;
+            let crash_log = (count == attempt).then(|| {
+                CrashLog::new(
+                    None,
+                    None,
+                    "SIGSEGV".into(),
+                    "SIGSEGV".into(),
+                    None,
+                    None,
+                    vec![],
+                )
+                .unwrap()
+            });
+            let outcome = match crash_log {
+                Some(_) => TestOutcome::Crash,
+                None => TestOutcome::CleanExit,
+            };
+            std::future::ready(Ok(TestResult {
+                crash_log,
+                error: None,
+                outcome,
+                crash_dump: None,
+                runs: vec![TestRun::default()],
+            }))
+        }
+    }
+
+    type Fut = std::future::Ready<Result<TestResult>>;
+
+    #[tokio::test]
+    async fn test_reproduced_first() -> Result<()> {
+        let (result, reproduction) = ReproRetries::default()
+            .test(None, &HashMap::new(), crash_on(1))
+            .await?;
+        assert!(result.crash_log.is_some());
+        assert_eq!(reproduction.summary, "reproduced 1/1 attempts");
+        assert!(!reproduction.flaky);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_not_reproduced() -> Result<()> {
+        let (result, reproduction) = ReproRetries::default()
+            .test(None, &HashMap::new(), crash_on(0))
+            .await?;
+        assert!(result.crash_log.is_none());
+        assert_eq!(reproduction.summary, "reproduced 0/4 attempts");
+        assert!(!reproduction.flaky);
+
+        // The runs of each attempt are kept, but not reported.
+        assert!(reproduction.attempts.iter().all(|a| a.runs.len() == 1));
+        assert!(!serde_json::to_string(&reproduction)?.contains("runs"));
+
+        let (_, reproduction) = ReproRetries::none()
+            .test(None, &HashMap::new(), crash_on(0))
+            .await?;
+        assert_eq!(reproduction.attempts.len(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_varied_retries() -> Result<()> {
+        let retries = ReproRetries {
+            count: 3,
+            timeouts: vec![20, 60],
+            asan_options: vec!["detect_leaks=0".to_string()],
+        };
+        let env = HashMap::from([("ASAN_OPTIONS".to_string(), "abort_on_error=1".to_string())]);
+
+        let mut seen = vec![];
+        let (_, reproduction) = retries
+            .test(Some(10), &env, |timeout, env| {
+                seen.push((timeout, env["ASAN_OPTIONS"].clone()));
+                std::future::ready(Ok(TestResult {
+                    crash_log: None,
+                    error: None,
+                    outcome: TestOutcome::CleanExit,
+                    crash_dump: None,
+                    runs: vec![],
+                }))
+            })
+            .await?;
+
+        assert_eq!(
+            seen,
+            [
+                (Some(10), "abort_on_error=1".to_string()),
+                (Some(20), "abort_on_error=1:detect_leaks=0".to_string()),
+                (Some(60), "abort_on_error=1:detect_leaks=0".to_string()),
+                (Some(60), "abort_on_error=1:detect_leaks=0".to_string()),
+            ]
+        );
+        assert_eq!(reproduction.attempts[0].timeout, None);
+        assert_eq!(reproduction.attempts[3].timeout, Some(60));
+        assert_eq!(
+            reproduction.attempts[1].asan_options.as_deref(),
+            Some("detect_leaks=0")
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,116 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Rules rewriting the source paths of debug info, such as from the paths of
+//! a CI build to those of the sources in the setup container, for coverage
+//! reports and the stacks of crash reports.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use coverage::path_map::{PathMap, PathRule};
+use serde::Deserialize;
+use tokio::fs;
+
+/// File of rules shipped with the target, read from the root of the setup
+/// directory if it exists.
+pub const SRCVIEW_FILE: &str = ".srcview.json";
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+pub struct SourcePathRule {
+    pub from: String,
+    pub to: String,
+}
+
+/// The rules of a `.srcview.json` file: either a list of rules, as in task
+/// configs, or an object of prefixes to their replacements, as in the
+/// `sourceMap` option of compilers, such as `{ "/w/1/s": "" }`.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum SrcviewFile {
+    Rules(Vec<SourcePathRule>),
+    Prefixes(BTreeMap<String, String>),
+}
+
+impl SrcviewFile {
+    fn into_rules(self) -> Vec<PathRule> {
+        match self {
+            Self::Rules(rules) => rules
+                .into_iter()
+                .map(|rule| PathRule::new(rule.from, rule.to))
+                .collect(),
+            Self::Prefixes(prefixes) => prefixes
+                .into_iter()
+                .map(|(from, to)| PathRule::new(from, to))
+                .collect(),
+        }
+    }
+}
+
+/// The path map of the `rules` of a task config, and of the `.srcview.json`
+/// file of `setup_dir`, if any.
+pub async fn load_path_map(rules: &[SourcePathRule], setup_dir: &Path) -> Result<PathMap> {
+    let mut path_map = PathMap::new(
+        rules
+            .iter()
+            .map(|rule| PathRule::new(&rule.from, &rule.to))
+            .collect(),
+    );
+
+    let path = setup_dir.join(SRCVIEW_FILE);
+    if fs::metadata(&path).await.is_ok() {
+        let text = fs::read_to_string(&path)
+            .await
+            .with_context(|| format!("reading source path rules {}", path.display()))?;
+        let file: SrcviewFile = serde_json::from_str(&text)
+            .with_context(|| format!("parsing source path rules {}", path.display()))?;
+        path_map.extend(file.into_rules());
+    }
+
+    Ok(path_map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_load_path_map() -> Result<()> {
+        let setup = tempfile::tempdir()?;
+        let rules = vec![SourcePathRule {
+            from: "/w/1/s".into(),
+            to: "config".into(),
+        }];
+
+        // Without a file, only the rules of the config are used.
+        let path_map = load_path_map(&rules, setup.path()).await?;
+        assert_eq!(path_map.map("/w/1/s/main.c"), "config/main.c");
+        assert_eq!(path_map.map("/build/main.c"), "/build/main.c");
+
+        fs::write(
+            setup.path().join(SRCVIEW_FILE),
+            r#"{ "/build": "", "/w/1/s/src": "file" }"#,
+        )
+        .await?;
+        let path_map = load_path_map(&rules, setup.path()).await?;
+        assert_eq!(path_map.map("/build/main.c"), "main.c");
+
+        // The rules of the file and config are matched by length together.
+        assert_eq!(path_map.map("/w/1/s/src/lib.c"), "file/lib.c");
+        assert_eq!(path_map.map("/w/1/s/main.c"), "config/main.c");
+
+        fs::write(
+            setup.path().join(SRCVIEW_FILE),
+            r#"[{ "from": "/build", "to": "src" }]"#,
+        )
+        .await?;
+        let path_map = load_path_map(&[], setup.path()).await?;
+        assert_eq!(path_map.map("/build/main.c"), "src/main.c");
+
+        fs::write(setup.path().join(SRCVIEW_FILE), "/build").await?;
+        assert!(load_path_map(&[], setup.path()).await.is_err());
+
+        Ok(())
+    }
+}
@@ -19,15 +19,19 @@ pub async fn read_stats(output_path: impl AsRef<Path>) -> Result<Vec<EventData>,
     let reader = tokio::io::BufReader::new(f);
     let mut lines = reader.lines();
     while let Ok(Some(line)) = lines.next_line().await {
-        let mut name_value = line.split(':');
-        let name = name_value.next().unwrap().trim();
-        let value = name_value.next().unwrap().trim();
+        // The value may itself contain `:`, such as in `command_line`.
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let name = name.trim();
+        let value = value.trim();
 
         match name {
             "target_mode" => {
                 stats.push(EventData::Mode(value.to_string()));
             }
-            "paths_total" => {
+            // renamed to `corpus_count` in AFL++
+            "paths_total" | "corpus_count" => {
                 if let Ok(value) = value.parse::<u64>() {
                     stats.push(EventData::CoveragePaths(value));
                 } else {
@@ -48,21 +52,24 @@ pub async fn read_stats(output_path: impl AsRef<Path>) -> Result<Vec<EventData>,
                     error!("unable to parse telemetry: {:?} {:?}", name, value);
                 }
             }
-            "paths_favored" => {
+            // renamed to `corpus_favored` in AFL++
+            "paths_favored" | "corpus_favored" => {
                 if let Ok(value) = value.parse::<u64>() {
                     stats.push(EventData::CoveragePathsFavored(value));
                 } else {
                     error!("unable to parse telemetry: {:?} {:?}", name, value);
                 }
             }
-            "paths_found" => {
+            // renamed to `corpus_found` in AFL++
+            "paths_found" | "corpus_found" => {
                 if let Ok(value) = value.parse::<u64>() {
                     stats.push(EventData::CoveragePathsFound(value));
                 } else {
                     error!("unable to parse telemetry: {:?} {:?}", name, value);
                 }
             }
-            "paths_imported" => {
+            // renamed to `corpus_imported` in AFL++
+            "paths_imported" | "corpus_imported" => {
                 if let Ok(value) = value.parse::<u64>() {
                     stats.push(EventData::CoveragePathsImported(value));
                 } else {
@@ -84,14 +91,36 @@ pub async fn read_stats(output_path: impl AsRef<Path>) -> Result<Vec<EventData>,
                     error!("unable to parse telemetry: {:?} {:?}", name, value);
                 }
             }
+            "cycles_done" => {
+                if let Ok(value) = value.parse::<u64>() {
+                    stats.push(EventData::CyclesDone(value));
+                } else {
+                    error!("unable to parse telemetry: {:?} {:?}", name, value);
+                }
+            }
             "command_line" => {
                 stats.push(EventData::CommandLine(value.to_string()));
             }
+            // renamed to `saved_crashes` in AFL++
+            "unique_crashes" | "saved_crashes" => {
+                if let Ok(value) = value.parse::<u64>() {
+                    stats.push(EventData::Crashes(value));
+                } else {
+                    error!("unable to parse telemetry: {:?} {:?}", name, value);
+                }
+            }
             // ignored telemetry
-            "cycles_done" | "afl_banner" | "afl_version" | "start_time" | "last_update"
-            | "stability" | "unique_crashes" | "unique_hangs" | "pending_favs"
-            | "pending_total" | "variable_paths" | "last_path" | "last_crash" | "last_hang"
-            | "execs_since_crash" | "max_depth" | "cur_path" | "exec_timeout" => {}
+            "afl_banner" | "afl_version" | "start_time" | "last_update" | "stability"
+            | "unique_hangs" | "pending_favs" | "pending_total" | "variable_paths"
+            | "last_path" | "last_crash" | "last_hang" | "execs_since_crash" | "max_depth"
+            | "cur_path" | "exec_timeout" => {}
+            // ignored AFL++ telemetry
+            "run_time" | "cycles_wo_finds" | "time_wo_finds" | "fuzz_time" | "calibration_time"
+            | "cmplog_time" | "sync_time" | "trim_time" | "execs_ps_last_min"
+            | "corpus_variable" | "cur_item" | "saved_hangs" | "last_find" | "slowest_exec_ms"
+            | "peak_rss_mb" | "cpu_affinity" | "edges_found" | "total_edges" | "var_byte_count"
+            | "havoc_expansion" | "auto_dict_entries" | "testcache_size" | "testcache_count"
+            | "testcache_evict" => {}
             _ => {
                 warn!("unsupported telemetry: {} {}", name, value);
             }
@@ -111,5 +140,43 @@ mod tests {
         assert!(results.contains(&EventData::Pid(26515)));
         assert!(results.contains(&EventData::ExecsSecond(2666.67)));
         assert!(results.contains(&EventData::Mode("default".to_string())));
+        assert!(results.contains(&EventData::CyclesDone(8)));
+        assert!(results.contains(&EventData::Crashes(0)));
+    }
+
+    #[tokio::test]
+    async fn test_aflpp_stats_parse() {
+        let results = read_stats("data/aflpp-fuzzer_stats.txt").await.unwrap();
+        assert_eq!(
+            results,
+            [
+                EventData::Pid(4242),
+                EventData::CyclesDone(3),
+                EventData::Count(250000),
+                EventData::ExecsSecond(2083.33),
+                EventData::CoveragePaths(57),
+                EventData::CoveragePathsFavored(12),
+                EventData::CoveragePathsFound(55),
+                EventData::CoveragePathsImported(3),
+                EventData::Coverage(1.24),
+                EventData::Crashes(2),
+                EventData::Mode("shmem_testcase default".to_string()),
+                EventData::CommandLine(
+                    "afl-fuzz -i inputs -o afl -M main -- /setup/fuzz @@".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stats_parse_partial_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("fuzzer_stats");
+        tokio::fs::write(&path, "fuzzer_pid : 1\nexecs_d")
+            .await
+            .unwrap();
+
+        let results = read_stats(&path).await.unwrap();
+        assert_eq!(results, [EventData::Pid(1)]);
     }
 }
@@ -1,33 +1,296 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
-use super::afl;
-use anyhow::{Error, Result};
+use super::{afl, honggfuzz, json, keyvalue};
+use crate::tasks::heartbeat::{HeartbeatSender, TaskHeartbeatClient};
+use anyhow::{Context, Error, Result};
 use onefuzz::jitter::delay_with_jitter;
-use onefuzz_telemetry::Event::runtime_stats;
+use onefuzz_result::job_result::{JobResultData, JobResultSender, TaskJobResultClient};
+use onefuzz_telemetry::{Event::runtime_stats, EventData};
 use serde::Deserialize;
+use std::{collections::HashMap, path::PathBuf, str::FromStr, time::Duration};
+use tokio::time::Instant;
+
 pub const STATS_DELAY: std::time::Duration = std::time::Duration::from_secs(30);
 
+/// How long the stats file may not exist, as the fuzzer may still be starting,
+/// before it is reported as an error.
+pub const STATS_GRACE_PERIOD: Duration = Duration::from_secs(5 * 60);
+
 // TODO - remove unkonwn_lints once GitHub build agents are at 1.51.0 or later
 #[derive(Debug, Deserialize, Clone)]
 pub enum StatsFormat {
-    #[serde(alias = "AFL")]
+    #[serde(alias = "AFL", alias = "afl")]
     Afl,
+    /// Lines of `key=value` or `key: value`.
+    #[serde(alias = "keyvalue")]
+    KeyValue,
+    /// A JSON object.
+    #[serde(alias = "json")]
+    Json,
+    /// Lines of comma-separated values, as written by honggfuzz.
+    #[serde(alias = "honggfuzz")]
+    Honggfuzz,
 }
 
-pub async fn monitor_stats(path: Option<String>, format: Option<StatsFormat>) -> Result<(), Error> {
-    if let Some(path) = path {
-        if let Some(format) = format {
-            loop {
-                let stats = match format {
-                    StatsFormat::Afl => afl::read_stats(&path).await,
-                };
-                if let Ok(stats) = stats {
-                    log_events!(runtime_stats; stats);
+/// The keys of a `KeyValue` or `Json` stats file to report as each stat. For
+/// `Json`, a key may be a `.`-separated path to a nested value.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct StatsKeys {
+    /// Executions per second, reported as `execs_sec`.
+    pub execs_sec: String,
+
+    /// Inputs in the corpus, reported as `coverage_paths`.
+    pub paths: String,
+
+    /// Crashes found, reported as `crashes`.
+    pub crashes: String,
+}
+
+impl Default for StatsKeys {
+    fn default() -> Self {
+        Self {
+            execs_sec: "execs_per_sec".to_owned(),
+            paths: "paths_total".to_owned(),
+            crashes: "unique_crashes".to_owned(),
+        }
+    }
+}
+
+impl StatsKeys {
+    /// The stats for the keys found by `value`. Keys not found are skipped.
+    pub fn events(&self, value: impl Fn(&str) -> Option<String>) -> Vec<EventData> {
+        let mut stats = vec![];
+        stats.extend(
+            parse_stat(&self.execs_sec, value(&self.execs_sec)).map(EventData::ExecsSecond),
+        );
+        stats.extend(parse_stat(&self.paths, value(&self.paths)).map(EventData::CoveragePaths));
+        stats.extend(parse_stat(&self.crashes, value(&self.crashes)).map(EventData::Crashes));
+        stats
+    }
+}
+
+fn parse_stat<T: FromStr>(key: &str, value: Option<String>) -> Option<T> {
+    let value = value?;
+    let parsed = value.parse().ok();
+    if parsed.is_none() {
+        error!("unable to parse telemetry: {:?} {:?}", key, value);
+    }
+    parsed
+}
+
+/// A stats file, written by the fuzzer.
+pub struct StatsFile {
+    path: PathBuf,
+    format: StatsFormat,
+    keys: StatsKeys,
+    started: Instant,
+
+    // Once read, the file is expected to keep existing.
+    seen: bool,
+}
+
+impl StatsFile {
+    pub fn new(path: impl Into<PathBuf>, format: StatsFormat, keys: StatsKeys) -> Self {
+        Self {
+            path: path.into(),
+            format,
+            keys,
+            started: Instant::now(),
+            seen: false,
+        }
+    }
+
+    /// Read the stats. Returns `None` if the file has not been written yet,
+    /// within `STATS_GRACE_PERIOD` of creating `self`.
+    pub async fn read(&mut self) -> Result<Option<Vec<EventData>>> {
+        if !self.seen
+            && self.started.elapsed() < STATS_GRACE_PERIOD
+            && !tokio::fs::try_exists(&self.path).await?
+        {
+            return Ok(None);
+        }
+
+        let stats = match self.format {
+            StatsFormat::Afl => afl::read_stats(&self.path).await?,
+            StatsFormat::KeyValue => {
+                keyvalue::parse_stats(&self.read_to_string().await?, &self.keys)
+            }
+            StatsFormat::Json => json::parse_stats(&self.read_to_string().await?, &self.keys)
+                .with_context(|| format!("invalid stats: {}", self.path.display()))?,
+            StatsFormat::Honggfuzz => honggfuzz::parse_stats(&self.read_to_string().await?),
+        };
+        self.seen = true;
+
+        Ok(Some(stats))
+    }
+
+    async fn read_to_string(&self) -> Result<String> {
+        tokio::fs::read_to_string(&self.path)
+            .await
+            .with_context(|| format!("unable to read stats: {}", self.path.display()))
+    }
+}
+
+pub async fn monitor_stats(
+    path: Option<String>,
+    format: Option<StatsFormat>,
+    keys: &StatsKeys,
+    heartbeat_client: &Option<TaskHeartbeatClient>,
+    jr_client: &Option<TaskJobResultClient>,
+) -> Result<(), Error> {
+    let (Some(path), Some(format)) = (path, format) else {
+        return Ok(());
+    };
+
+    let mut stats_file = StatsFile::new(path, format, keys.clone());
+
+    // Only warn once, until the stats can be read again.
+    let mut warned = false;
+
+    loop {
+        match stats_file.read().await {
+            Ok(Some(stats)) => {
+                warned = false;
+                heartbeat_client.stats(&stats);
+                report_stats(&stats, jr_client).await;
+            }
+            Ok(None) => {
+                debug!("stats file not written yet: {}", stats_file.path.display());
+            }
+            Err(err) => {
+                if !warned {
+                    warn!("unable to read stats: {:?}", err);
+                    warned = true;
                 }
-                delay_with_jitter(STATS_DELAY).await;
             }
         }
+        delay_with_jitter(STATS_DELAY).await;
+    }
+}
+
+async fn report_stats(stats: &[EventData], jr_client: &Option<TaskJobResultClient>) {
+    if stats.is_empty() {
+        return;
+    }
+
+    log_events!(runtime_stats; stats);
+    log_metrics!(runtime_stats; 1.0; stats);
+
+    if let Some(jr_client) = jr_client {
+        let values = job_result_values(stats);
+        if !values.is_empty() {
+            jr_client
+                .send_direct(JobResultData::RuntimeStats, values)
+                .await;
+        }
+    }
+}
+
+// The runtime stats job result values, named as for libFuzzer tasks.
+fn job_result_values(stats: &[EventData]) -> HashMap<String, f64> {
+    stats
+        .iter()
+        .filter_map(|stat| match stat {
+            EventData::ExecsSecond(x) => Some(("execs_sec", *x)),
+            EventData::Count(x) => Some(("total_count", *x as f64)),
+            EventData::CoveragePaths(x) => Some(("coverage_paths", *x as f64)),
+            EventData::Crashes(x) => Some(("crashes", *x as f64)),
+            _ => None,
+        })
+        .map(|(name, value)| (name.to_owned(), value))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats_keys() {
+        let values = HashMap::from([
+            ("execs_per_sec", "2.5"),
+            ("paths_total", "12"),
+            ("unique_crashes", "not a number"),
+        ]);
+        let stats = StatsKeys::default().events(|key| values.get(key).map(|x| x.to_string()));
+        assert_eq!(
+            stats,
+            [EventData::ExecsSecond(2.5), EventData::CoveragePaths(12)]
+        );
+    }
+
+    #[test]
+    fn test_job_result_values() {
+        let values = job_result_values(&[
+            EventData::Pid(1),
+            EventData::ExecsSecond(2.5),
+            EventData::CoveragePaths(12),
+            EventData::Crashes(3),
+        ]);
+        assert_eq!(
+            values,
+            HashMap::from([
+                ("execs_sec".to_owned(), 2.5),
+                ("coverage_paths".to_owned(), 12.0),
+                ("crashes".to_owned(), 3.0),
+            ])
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stats_file_grace_period() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("stats");
+        let mut stats_file = StatsFile::new(&path, StatsFormat::KeyValue, StatsKeys::default());
+
+        // Not written yet.
+        assert_eq!(stats_file.read().await?, None);
+        tokio::time::advance(STATS_GRACE_PERIOD / 2).await;
+        assert_eq!(stats_file.read().await?, None);
+
+        tokio::fs::write(&path, "paths_total=3\n").await?;
+        assert_eq!(
+            stats_file.read().await?,
+            Some(vec![EventData::CoveragePaths(3)])
+        );
+
+        // Once read, the file is expected to exist.
+        tokio::fs::remove_file(&path).await?;
+        assert!(stats_file.read().await.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stats_file_never_written() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("stats");
+        let mut stats_file = StatsFile::new(&path, StatsFormat::Json, StatsKeys::default());
+
+        assert_eq!(stats_file.read().await?, None);
+        tokio::time::advance(STATS_GRACE_PERIOD).await;
+
+        let err = stats_file.read().await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("unable to read stats: {}", path.display())
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_stats_file_afl() -> Result<()> {
+        let mut stats_file = StatsFile::new(
+            "data/afl-fuzzer_stats.txt",
+            StatsFormat::Afl,
+            StatsKeys::default(),
+        );
+        let stats = stats_file.read().await?.unwrap();
+        assert!(stats.contains(&EventData::Pid(26515)));
+
+        Ok(())
     }
-    Ok(())
 }
@@ -0,0 +1,85 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Stats files written by honggfuzz with `--statsfile`.
+//!
+//! The file starts with a `#` line naming the comma-separated columns, and a
+//! line of values is appended on every status update. Only the last complete
+//! line is reported.
+
+use onefuzz_telemetry::EventData;
+
+pub fn parse_stats(text: &str) -> Vec<EventData> {
+    let mut names: Vec<&str> = vec![];
+    let mut last = None;
+
+    for line in text.lines() {
+        if let Some(header) = line.strip_prefix('#') {
+            names = header.split(',').map(str::trim).collect();
+            continue;
+        }
+
+        let values: Vec<&str> = line.split(',').map(str::trim).collect();
+        // Skips a partially written last line.
+        if !names.is_empty() && values.len() == names.len() {
+            last = Some(values);
+        }
+    }
+
+    let Some(values) = last else {
+        return vec![];
+    };
+
+    let mut stats = vec![];
+    for (name, value) in names.into_iter().zip(values) {
+        let stat = match name {
+            "total_exec" => value.parse().ok().map(EventData::Count),
+            "exec_per_sec" => value.parse().ok().map(EventData::ExecsSecond),
+            "unique_crashes" => value.parse().ok().map(EventData::Crashes),
+            // ignored telemetry
+            "unix_time" | "last_cov_update" | "crashes" | "hangs" | "edge_cov" | "block_cov" => {
+                continue
+            }
+            _ => {
+                warn!("unsupported telemetry: {} {}", name, value);
+                continue;
+            }
+        };
+
+        match stat {
+            Some(stat) => stats.push(stat),
+            None => error!("unable to parse telemetry: {:?} {:?}", name, value),
+        }
+    }
+
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_stats() {
+        let text = "\
+# unix_time, last_cov_update, total_exec, exec_per_sec, crashes, unique_crashes, hangs, edge_cov, block_cov
+1697200000, 1697199990, 1500, 1500, 0, 0, 0, 120/3075, 0/0
+1697200060, 1697200055, 91500, 1525, 4, 2, 0, 183/3075, 0/0
+1697200120, 16972";
+
+        assert_eq!(
+            parse_stats(text),
+            [
+                EventData::Count(91500),
+                EventData::ExecsSecond(1525.0),
+                EventData::Crashes(2),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_stats_empty() {
+        assert!(parse_stats("").is_empty());
+        assert!(parse_stats("# unix_time, total_exec\n").is_empty());
+    }
+}
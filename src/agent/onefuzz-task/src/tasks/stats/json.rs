@@ -0,0 +1,79 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Stats files holding a JSON object.
+
+use super::common::StatsKeys;
+use anyhow::Result;
+use onefuzz_telemetry::EventData;
+use serde_json::Value;
+
+/// The stats for `keys` in the JSON object `text`.
+pub fn parse_stats(text: &str, keys: &StatsKeys) -> Result<Vec<EventData>> {
+    let stats: Value = serde_json::from_str(text)?;
+    Ok(keys.events(|key| lookup(&stats, key)))
+}
+
+// The number or string at `key`, either a member of `stats` or a `.`-separated
+// path to a nested member.
+fn lookup(stats: &Value, key: &str) -> Option<String> {
+    let value = match stats.get(key) {
+        Some(value) => value,
+        None => key
+            .split('.')
+            .try_fold(stats, |value, name| value.get(name))?,
+    };
+
+    match value {
+        Value::Number(x) => Some(x.to_string()),
+        Value::String(x) => Some(x.clone()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_stats() -> Result<()> {
+        let text = r#"{"execs_per_sec": 1234.5, "paths_total": "42", "unique_crashes": 7}"#;
+
+        assert_eq!(
+            parse_stats(text, &StatsKeys::default())?,
+            [
+                EventData::ExecsSecond(1234.5),
+                EventData::CoveragePaths(42),
+                EventData::Crashes(7),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_stats_nested() -> Result<()> {
+        let keys = StatsKeys {
+            execs_sec: "fuzzer.speed".to_owned(),
+            paths: "corpus.size".to_owned(),
+            crashes: "crashes.total".to_owned(),
+        };
+        let text = r#"{
+            "fuzzer": {"speed": 10},
+            "corpus.size": 3,
+            "crashes": {"total": null}
+        }"#;
+
+        assert_eq!(
+            parse_stats(text, &keys)?,
+            [EventData::ExecsSecond(10.0), EventData::CoveragePaths(3)]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_stats_invalid() {
+        assert!(parse_stats(r#"{"execs_per_sec": 1"#, &StatsKeys::default()).is_err());
+    }
+}
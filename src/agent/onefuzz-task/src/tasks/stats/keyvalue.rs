@@ -0,0 +1,66 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Stats files of `key=value` or `key: value` lines.
+
+use super::common::StatsKeys;
+use onefuzz_telemetry::EventData;
+use std::collections::HashMap;
+
+/// The stats for `keys` in `text`. Lines without a `=` or `:` are skipped, such
+/// as a partially written last line.
+pub fn parse_stats(text: &str, keys: &StatsKeys) -> Vec<EventData> {
+    let mut values = HashMap::new();
+
+    for line in text.lines() {
+        // The value may itself contain `=` or `:`, such as a timestamp.
+        let Some(split) = line.find(|c: char| c == '=' || c == ':') else {
+            continue;
+        };
+        let name = line[..split].trim();
+        let value = line[split + 1..].trim();
+        values.insert(name, value);
+    }
+
+    keys.events(|key| values.get(key).map(|value| value.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_stats() {
+        let text = "\
+# comment
+execs_per_sec = 1234.5
+paths_total: 42
+unique_crashes=7
+last_update=12:00:00
+paths_t";
+
+        assert_eq!(
+            parse_stats(text, &StatsKeys::default()),
+            [
+                EventData::ExecsSecond(1234.5),
+                EventData::CoveragePaths(42),
+                EventData::Crashes(7),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_stats_keys() {
+        let keys = StatsKeys {
+            execs_sec: "speed".to_owned(),
+            paths: "corpus".to_owned(),
+            crashes: "crashes".to_owned(),
+        };
+        let text = "speed=10\ncorpus=3\npaths_total=42\n";
+
+        assert_eq!(
+            parse_stats(text, &keys),
+            [EventData::ExecsSecond(10.0), EventData::CoveragePaths(3)]
+        );
+    }
+}
@@ -3,3 +3,7 @@
 
 pub mod afl;
 pub mod common;
+pub mod honggfuzz;
+pub mod json;
+pub mod keyvalue;
+pub mod process;
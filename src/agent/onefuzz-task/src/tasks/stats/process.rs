@@ -0,0 +1,227 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Periodic samples of the resources used by the target process tree while
+//! fuzzing, so that memory growth and handle leaks are visible before they
+//! take down the node.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{DateTime, SecondsFormat, Utc};
+use onefuzz::{
+    jitter::delay_with_jitter,
+    process_stats::{ProcessSampler, ProcessStats},
+};
+use onefuzz_telemetry::{Event::process_stats, EventData};
+use serde::Deserialize;
+use tokio::{fs::OpenOptions, io::AsyncWriteExt, sync::Notify};
+
+use crate::tasks::{
+    heartbeat::{metrics, HeartbeatSender, TaskHeartbeatClient},
+    utils::CheckNotify,
+};
+
+const CSV_HEADER: &str =
+    "timestamp,pid,processes,rss_bytes,virtual_bytes,threads,handles,cpu_percent\n";
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProcessStatsConfig {
+    /// Seconds between samples of the target process tree. Sampling is
+    /// disabled if 0.
+    pub interval_secs: u64,
+
+    /// A local CSV file to append each sample to, for post-mortem debugging.
+    pub csv: Option<PathBuf>,
+}
+
+impl Default for ProcessStatsConfig {
+    fn default() -> Self {
+        Self {
+            interval_secs: 60,
+            csv: None,
+        }
+    }
+}
+
+/// Sample the process tree of `pid` as set by `config`, until the process
+/// exits or `stopped` is notified. Samples are reported as telemetry and as
+/// metrics of the next heartbeat.
+pub async fn monitor_process_stats(
+    pid: u32,
+    config: &ProcessStatsConfig,
+    heartbeat: Option<&TaskHeartbeatClient>,
+    stopped: Option<&Notify>,
+) -> Result<()> {
+    if config.interval_secs == 0 {
+        return Ok(());
+    }
+
+    let interval = Duration::from_secs(config.interval_secs);
+    let mut sampler = ProcessSampler::new(pid);
+
+    loop {
+        match stopped {
+            Some(stopped) => {
+                if stopped.is_notified(interval).await {
+                    break;
+                }
+            }
+            None => delay_with_jitter(interval).await,
+        }
+
+        let stats = match sampler.sample() {
+            Ok(Some(stats)) => stats,
+            Ok(None) => {
+                debug!("process exited, stopped sampling, pid = {}", pid);
+                break;
+            }
+            Err(err) => {
+                warn!("unable to sample process, pid = {}: {:?}", pid, err);
+                continue;
+            }
+        };
+
+        report(pid, &stats, heartbeat);
+
+        if let Some(csv) = &config.csv {
+            if let Err(err) = append_csv(csv, &csv_row(Utc::now(), pid, &stats)).await {
+                warn!(
+                    "unable to append process stats to {}: {:?}",
+                    csv.display(),
+                    err
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn report(pid: u32, stats: &ProcessStats, heartbeat: Option<&TaskHeartbeatClient>) {
+    let mut events = vec![
+        EventData::Pid(pid),
+        EventData::Processes(stats.processes),
+        EventData::PhysicalMemory(stats.rss_bytes),
+        EventData::VirtualMemory(stats.virtual_bytes),
+        EventData::Threads(stats.threads),
+        EventData::Handles(stats.handles),
+    ];
+    events.extend(stats.cpu_percent.map(|cpu| EventData::CpuUsage(cpu as f32)));
+    log_events!(process_stats; events);
+    log_metrics!(process_stats; 1.0; events);
+
+    if let Some(heartbeat) = heartbeat {
+        heartbeat.metric(metrics::TARGET_RSS_BYTES, stats.rss_bytes as f64);
+        heartbeat.metric(metrics::TARGET_VIRTUAL_BYTES, stats.virtual_bytes as f64);
+        heartbeat.metric(metrics::TARGET_THREADS, stats.threads as f64);
+        heartbeat.metric(metrics::TARGET_HANDLES, stats.handles as f64);
+        if let Some(cpu_percent) = stats.cpu_percent {
+            heartbeat.metric(metrics::TARGET_CPU_PERCENT, cpu_percent);
+        }
+    }
+}
+
+fn csv_row(timestamp: DateTime<Utc>, pid: u32, stats: &ProcessStats) -> String {
+    let cpu_percent = stats
+        .cpu_percent
+        .map(|cpu| format!("{cpu:.1}"))
+        .unwrap_or_default();
+
+    format!(
+        "{},{},{},{},{},{},{},{}\n",
+        timestamp.to_rfc3339_opts(SecondsFormat::Secs, true),
+        pid,
+        stats.processes,
+        stats.rss_bytes,
+        stats.virtual_bytes,
+        stats.threads,
+        stats.handles,
+        cpu_percent,
+    )
+}
+
+// Append `row` to the CSV file `path`, starting it with a header if it's new.
+async fn append_csv(path: &Path, row: &str) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+
+    // Written at once, so that rows appended by concurrent workers aren't
+    // interleaved.
+    let mut data = String::new();
+    if file.metadata().await?.len() == 0 {
+        data.push_str(CSV_HEADER);
+    }
+    data.push_str(row);
+    file.write_all(data.as_bytes()).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[tokio::test]
+    async fn test_append_csv() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("stats.csv");
+        let timestamp = Utc.with_ymd_and_hms(2023, 9, 1, 12, 0, 0).unwrap();
+
+        let mut stats = ProcessStats {
+            processes: 2,
+            rss_bytes: 1 << 20,
+            virtual_bytes: 1 << 30,
+            threads: 3,
+            handles: 8,
+            cpu_percent: None,
+        };
+        append_csv(&path, &csv_row(timestamp, 42, &stats)).await?;
+
+        stats.cpu_percent = Some(12.345);
+        append_csv(&path, &csv_row(timestamp, 42, &stats)).await?;
+
+        assert_eq!(
+            tokio::fs::read_to_string(&path).await?,
+            format!(
+                "{CSV_HEADER}\
+                 2023-09-01T12:00:00Z,42,2,1048576,1073741824,3,8,\n\
+                 2023-09-01T12:00:00Z,42,2,1048576,1073741824,3,8,12.3\n"
+            )
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_disabled() -> Result<()> {
+        let config = ProcessStatsConfig {
+            interval_secs: 0,
+            csv: None,
+        };
+
+        // Returns at once, rather than sampling.
+        monitor_process_stats(std::process::id(), &config, None, None).await
+    }
+
+    #[test]
+    fn test_config() -> Result<()> {
+        let config: ProcessStatsConfig = serde_json::from_str("{}")?;
+        assert_eq!(config, ProcessStatsConfig::default());
+
+        let config: ProcessStatsConfig =
+            serde_json::from_str(r#"{"interval_secs": 10, "csv": "stats.csv"}"#)?;
+        assert_eq!(config.interval_secs, 10);
+        assert_eq!(config.csv, Some(PathBuf::from("stats.csv")));
+
+        assert!(serde_json::from_str::<ProcessStatsConfig>(r#"{"interval": 10}"#).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,142 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! PDBs of Windows targets, downloaded from symbol servers before recording
+//! coverage or symbolizing stack traces, and the external debug files of
+//! stripped targets.
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use debuggable_module::debugfile::{DebugFileDirs, DEFAULT_DEBUG_FILE_DIR};
+use debuggable_module::symsrv::{SymbolPath, NT_SYMBOL_PATH};
+use onefuzz::symsrv::SymbolServers;
+use tokio::fs;
+
+use crate::tasks::config::CommonConfig;
+
+pub struct TargetSymbols {
+    /// Searches the symbol cache and `_NT_SYMBOL_PATH`.
+    pub symbol_path: SymbolPath,
+
+    /// Directories of the downloaded PDBs.
+    pub pdb_dirs: Vec<PathBuf>,
+}
+
+impl TargetSymbols {
+    /// Download the PDBs of `target_exe` and the modules beside it.
+    ///
+    /// PDBs which aren't found, or fail to download, are skipped, leaving the
+    /// modules without debug info as before.
+    pub async fn fetch(common: &CommonConfig, target_exe: &Path) -> Self {
+        let mut symbol_path = SymbolPath::from_env();
+        let mut pdb_dirs = vec![];
+
+        let servers = match common.symbol_servers() {
+            Ok(Some(servers)) => servers,
+            Ok(None) => return Self::new(symbol_path, pdb_dirs),
+            Err(err) => {
+                warn!("unable to configure symbol servers: {:?}", err);
+                return Self::new(symbol_path, pdb_dirs);
+            }
+        };
+        symbol_path.stores.push(servers.cache().to_owned());
+
+        for module in target_modules(target_exe).await {
+            if has_adjacent_pdb(&module).await {
+                continue;
+            }
+
+            if let Some(pdb) = servers.fetch_for_module(&module).await {
+                // Unwrap-safe, store paths have a parent.
+                pdb_dirs.push(pdb.parent().unwrap().to_owned());
+            }
+        }
+
+        info!(
+            "{} PDBs found on symbol servers, cached in {}",
+            pdb_dirs.len(),
+            servers.cache().display()
+        );
+
+        Self::new(symbol_path, pdb_dirs)
+    }
+
+    fn new(symbol_path: SymbolPath, pdb_dirs: Vec<PathBuf>) -> Self {
+        Self {
+            symbol_path,
+            pdb_dirs,
+        }
+    }
+
+    /// Prepend the directories of the downloaded PDBs to `_NT_SYMBOL_PATH`, so
+    /// that `dbghelp` finds them, both when debugging the target and within
+    /// targets symbolizing their own stack traces.
+    pub fn export(&self) {
+        if self.pdb_dirs.is_empty() {
+            return;
+        }
+
+        let mut path = OsString::new();
+        for dir in &self.pdb_dirs {
+            path.push(dir);
+            path.push(";");
+        }
+        if let Some(old) = std::env::var_os(NT_SYMBOL_PATH) {
+            path.push(old);
+        }
+
+        std::env::set_var(NT_SYMBOL_PATH, path);
+    }
+}
+
+/// Directories searched for the external debug files of stripped targets, such
+/// as `.debug` files, in order: `dirs`, relative to the setup directory, then
+/// the setup directories, and then the debug file directory of the system.
+pub fn debug_file_dirs(
+    setup_dir: &Path,
+    extra_setup_dir: Option<&Path>,
+    dirs: &[PathBuf],
+) -> DebugFileDirs {
+    let dirs = dirs
+        .iter()
+        .map(|dir| setup_dir.join(dir))
+        .chain(std::iter::once(setup_dir.to_owned()))
+        .chain(extra_setup_dir.map(Path::to_owned))
+        .chain(std::iter::once(PathBuf::from(DEFAULT_DEBUG_FILE_DIR)));
+
+    DebugFileDirs::new(dirs)
+}
+
+// The target and the PE files in its directory.
+async fn target_modules(target_exe: &Path) -> Vec<PathBuf> {
+    let mut modules = vec![target_exe.to_owned()];
+
+    let Some(dir) = target_exe.parent() else {
+        return modules;
+    };
+    let Ok(mut entries) = fs::read_dir(dir).await else {
+        return modules;
+    };
+
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let path = entry.path();
+        if path != target_exe && is_pe_file(&path) {
+            modules.push(path);
+        }
+    }
+
+    modules
+}
+
+fn is_pe_file(path: &Path) -> bool {
+    let extension = path.extension().map(|e| e.to_ascii_lowercase());
+    matches!(
+        extension.as_ref().and_then(|e| e.to_str()),
+        Some("exe" | "dll")
+    )
+}
+
+async fn has_adjacent_pdb(module: &Path) -> bool {
+    fs::metadata(module.with_extension("pdb")).await.is_ok()
+}
@@ -3,9 +3,16 @@
 
 use anyhow::{Context, Result};
 use async_trait::async_trait;
-use onefuzz::{http::ResponseExt, jitter::delay_with_jitter};
-use reqwest::{Client, Url};
+use dynamic_library::imports::{find_unresolved, SearchPath};
+use onefuzz::{
+    env::{get_path_with_directory, update_path},
+    expand::Expand,
+    http::ResponseExt,
+    jitter::delay_with_jitter,
+};
+use reqwest::Url;
 use reqwest_retry::SendRetry;
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::time::Duration;
 use tokio::{fs, io};
@@ -20,7 +27,7 @@ pub async fn download_input(input_url: Url, dst: impl AsRef<Path>) -> Result<Pat
             .map_err(|_| anyhow!("Invalid file Url"))?;
         fs::copy(&input_file_path, &file_path).await?;
     } else {
-        let resp = Client::new()
+        let resp = reqwest_retry::proxy::client()
             .get(input_url)
             .send_retry_default()
             .await
@@ -93,6 +100,12 @@ pub fn parse_key_value(value: &str) -> Result<(String, String)> {
     Ok((value[..offset].to_string(), value[offset + 1..].to_string()))
 }
 
+/// Whether `c` may be part of the name of a tool, such as of a generator,
+/// which is used in file names.
+pub fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '_'
+}
+
 pub fn default_bool_true() -> bool {
     true
 }
@@ -166,12 +179,79 @@ async fn exists(path: impl AsRef<Path>) -> bool {
     fs::metadata(path).await.is_ok()
 }
 
+// The variable of the directories searched for libraries, to which tasks add
+// the setup directory.
+#[cfg(target_os = "windows")]
+const LIBRARY_PATH: &str = onefuzz::env::PATH;
+
+#[cfg(not(target_os = "windows"))]
+const LIBRARY_PATH: &str = onefuzz::env::LD_LIBRARY_PATH;
+
+/// Check that the libraries imported by `exe`, the executable of the config
+/// field `field`, are found as when the task runs it with the environment
+/// `env`, so that a missing library fails the task before it starts, rather
+/// than each run of `exe`.
+///
+/// The library path variable of `env` is expanded by `expand`, and ignored if
+/// it has placeholders only known when `exe` is run. Executables which aren't
+/// files, such as those found on `PATH`, aren't checked. Missing delay-loaded DLLs are only warned
+/// about, since they may never be loaded.
+pub async fn check_libraries(
+    field: &str,
+    exe: &Path,
+    env: &HashMap<String, String>,
+    expand: &Expand<'_>,
+    setup_dir: &Path,
+) -> Result<()> {
+    if !fs::metadata(exe).await.is_ok_and(|m| m.is_file()) {
+        return Ok(());
+    }
+
+    let setup_dir = setup_dir.to_path_buf();
+    let library_path = env
+        .get(LIBRARY_PATH)
+        .and_then(|value| expand.evaluate_value(value).ok());
+    let library_path = match library_path {
+        Some(value) => update_path(value.into(), &setup_dir)?,
+        None => get_path_with_directory(LIBRARY_PATH, &setup_dir)?,
+    };
+    let mut search = SearchPath::system().library_path(library_path);
+    if let Ok(dir) = std::env::current_dir() {
+        search = search.current_dir(dir);
+    }
+
+    let path = exe.to_owned();
+    let unresolved = tokio::task::spawn_blocking(move || find_unresolved(&path, &search))
+        .await?
+        .with_context(|| format!("unable to check the libraries of `{field}`"))?;
+
+    let (delay_loaded, missing): (Vec<_>, Vec<_>) =
+        unresolved.into_iter().partition(|lib| lib.delay_loaded);
+
+    for lib in delay_loaded {
+        warn!("delay-loaded library of `{field}` not found: {lib}");
+    }
+
+    if !missing.is_empty() {
+        let missing: Vec<_> = missing.iter().map(|lib| lib.to_string()).collect();
+        bail!(
+            "libraries of `{field}` not found: {}: {}",
+            exe.display(),
+            missing.join(", ")
+        );
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use std::path::Path;
 
     use anyhow::Result;
+    use onefuzz::machine_id::MachineIdentity;
     use tempfile::TempDir;
+    use uuid::Uuid;
 
     use super::*;
 
@@ -249,4 +329,36 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_check_libraries() -> Result<()> {
+        let machine_identity = MachineIdentity {
+            machine_id: Uuid::new_v4(),
+            machine_name: "test".to_string(),
+            scaleset_name: None,
+        };
+        let expand = Expand::new(&machine_identity);
+        let env = HashMap::new();
+        let setup_dir = TempDir::new()?;
+
+        // The libraries of this test are found, since it's running.
+        let exe = std::env::current_exe()?;
+        check_libraries("target_exe", &exe, &env, &expand, setup_dir.path()).await?;
+
+        // Scripts import no libraries, and executables found on `PATH` aren't
+        // checked.
+        let script = setup_dir.path().join("fuzz.sh");
+        std::fs::write(&script, "#!/bin/sh\n")?;
+        check_libraries("target_exe", &script, &env, &expand, setup_dir.path()).await?;
+        check_libraries(
+            "target_exe",
+            Path::new("fuzz"),
+            &env,
+            &expand,
+            setup_dir.path(),
+        )
+        .await?;
+
+        Ok(())
+    }
 }
@@ -0,0 +1,89 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+#![cfg(target_os = "linux")]
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+use anyhow::{ensure, Result};
+use onefuzz_task_lib::local::{cmd, libfuzzer::Pipeline};
+use tokio::time::{sleep, timeout};
+
+// Build the fixture target, which crashes on inputs which start with `x`.
+fn build_target(dir: &Path) -> Result<PathBuf> {
+    let source = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/targets/crash/fuzz.c");
+    let exe = dir.join("fuzz.exe");
+
+    let status = Command::new("clang")
+        .args(["-g", "-fsanitize=fuzzer,address", "-o"])
+        .arg(&exe)
+        .arg(&source)
+        .status()?;
+    ensure!(
+        status.success(),
+        "building the fixture target failed: {status}"
+    );
+
+    Ok(exe)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+async fn test_local_libfuzzer_reports_crash() -> Result<()> {
+    let _ = env_logger::builder().is_test(true).try_init();
+
+    let dir = tempfile::tempdir()?;
+    let exe = build_target(dir.path())?;
+    let seeds = dir.path().join("seeds");
+    std::fs::create_dir(&seeds)?;
+    std::fs::write(seeds.join("crash"), "x")?;
+    let output_dir = dir.path().join("output");
+
+    let matches = cmd::args("local").try_get_matches_from([
+        "local".to_owned(),
+        "libfuzzer".to_owned(),
+        "--target_exe".to_owned(),
+        exe.display().to_string(),
+        "--inputs".to_owned(),
+        seeds.display().to_string(),
+        "--output_dir".to_owned(),
+        output_dir.display().to_string(),
+        "--target_workers".to_owned(),
+        "1".to_owned(),
+        "--watch".to_owned(),
+    ])?;
+    let (_, args) = matches.subcommand().unwrap();
+
+    let mut pipeline = Pipeline::start(args, None).await?;
+    let unique_reports = pipeline.dirs.unique_reports.clone();
+    let reported = timeout(Duration::from_secs(120), async {
+        while std::fs::read_dir(&unique_reports)?.next().is_none() {
+            sleep(Duration::from_secs(1)).await;
+        }
+        Ok::<_, std::io::Error>(())
+    });
+
+    let reported = tokio::select! {
+        result = pipeline.wait() => panic!("the tasks exited before reporting a crash: {result:?}"),
+        reported = reported => reported,
+    };
+    pipeline.shutdown().await;
+    reported.expect("no crash was reported within 120 seconds")?;
+
+    let summary = pipeline.summary().await?;
+    assert!(summary.crashes > 0, "{summary}");
+    assert_eq!(summary.unique_reports.len(), 1, "{summary}");
+    assert_eq!(summary.unique_reports[0].crash_type, "heap-buffer-overflow");
+    assert!(
+        summary.to_string().contains("unique crashes: 1"),
+        "{summary}"
+    );
+
+    // The seeds are only read.
+    assert_eq!(std::fs::read_dir(&seeds)?.count(), 1);
+    assert_eq!(summary.output_dir, output_dir);
+
+    Ok(())
+}
@@ -0,0 +1,16 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+#include <stdint.h>
+#include <stdlib.h>
+
+// Overflows a heap buffer on inputs which start with `x`.
+int LLVMFuzzerTestOneInput(const uint8_t *data, size_t size) {
+  if (size > 0 && data[0] == 'x') {
+    volatile char *buffer = malloc(1);
+    buffer[size] = 0;
+    free((char *)buffer);
+  }
+
+  return 0;
+}
@@ -0,0 +1,110 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Correlation context, such as the job, task and machine ids, merged into all
+//! events and metrics tracked within it.
+//!
+//! The context is scoped to a tokio task. Futures run with `scope()` see it,
+//! and tasks started with `spawn()` inherit the context of their parent, so
+//! that events deep within a task can be correlated without threading ids
+//! through every call.
+
+use std::borrow::Cow;
+use std::future::Future;
+use std::mem::discriminant;
+use std::sync::Arc;
+
+use tokio::task::JoinHandle;
+
+use crate::EventData;
+
+tokio::task_local! {
+    static CONTEXT: CorrelationContext;
+}
+
+/// Entries merged into the properties of every event and metric tracked within
+/// the context. Explicit properties take precedence over them.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CorrelationContext {
+    entries: Arc<Vec<EventData>>,
+}
+
+impl CorrelationContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The context of the current task, which is empty outside of any scope.
+    pub fn current() -> Self {
+        CONTEXT.try_with(Clone::clone).unwrap_or_default()
+    }
+
+    /// This context with `entry`, replacing any entry of the same kind.
+    pub fn with(mut self, entry: EventData) -> Self {
+        let entries = Arc::make_mut(&mut self.entries);
+        match entries
+            .iter_mut()
+            .find(|e| discriminant(*e) == discriminant(&entry))
+        {
+            Some(existing) => *existing = entry,
+            None => entries.push(entry),
+        }
+        self
+    }
+
+    pub fn entries(&self) -> &[EventData] {
+        &self.entries
+    }
+
+    /// Run `fut` within this context.
+    pub async fn scope<F: Future>(self, fut: F) -> F::Output {
+        CONTEXT.scope(self, fut).await
+    }
+
+    /// Run `f` within this context, for synchronous code.
+    pub fn sync_scope<R>(self, f: impl FnOnce() -> R) -> R {
+        CONTEXT.sync_scope(self, f)
+    }
+}
+
+/// Run `fut` within the current context with `entry`, such as the hash of the
+/// input being processed.
+pub async fn with_entry<F: Future>(entry: EventData, fut: F) -> F::Output {
+    CorrelationContext::current().with(entry).scope(fut).await
+}
+
+/// Spawn a tokio task which inherits the current context.
+pub fn spawn<F>(fut: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    tokio::spawn(CorrelationContext::current().scope(fut))
+}
+
+// The properties of a record, with the entries of the current context which
+// they don't already set.
+pub(crate) fn merged(properties: &[EventData]) -> Cow<'_, [EventData]> {
+    let Ok(context) = CONTEXT.try_with(|context| context.entries.clone()) else {
+        return Cow::Borrowed(properties);
+    };
+
+    let missing: Vec<&EventData> = context
+        .iter()
+        .filter(|entry| {
+            !properties
+                .iter()
+                .any(|p| discriminant(p) == discriminant(*entry))
+        })
+        .collect();
+    if missing.is_empty() {
+        return Cow::Borrowed(properties);
+    }
+
+    let mut merged = properties.to_vec();
+    merged.extend(missing.into_iter().cloned());
+    Cow::Owned(merged)
+}
+
+#[cfg(test)]
+mod tests;
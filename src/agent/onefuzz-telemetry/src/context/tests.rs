@@ -0,0 +1,166 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use std::time::Duration;
+
+use anyhow::Result;
+use tokio::sync::broadcast::Receiver;
+use uuid::Uuid;
+
+use super::*;
+use crate::{subscribe_to_events, track_event, Event, LoggingEvent};
+
+// Events are broadcast to all subscribers, including those of concurrent
+// tests, so each test tags its events with a unique name.
+fn marker() -> EventData {
+    EventData::Name(Uuid::new_v4().to_string())
+}
+
+async fn next_event(events: &mut Receiver<LoggingEvent>, marker: &EventData) -> Vec<EventData> {
+    let find = async {
+        loop {
+            if let Ok(LoggingEvent::Event(event)) = events.recv().await {
+                if event.data.contains(marker) {
+                    return event.data;
+                }
+            }
+        }
+    };
+
+    tokio::time::timeout(Duration::from_secs(10), find)
+        .await
+        .expect("event")
+}
+
+#[test]
+fn test_with_replaces_entries_of_the_same_kind() {
+    let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+    let context = CorrelationContext::new()
+        .with(EventData::JobId(a))
+        .with(EventData::TaskId(a))
+        .with(EventData::JobId(b));
+
+    assert_eq!(
+        context.entries(),
+        [EventData::JobId(b), EventData::TaskId(a)]
+    );
+}
+
+#[test]
+fn test_merged() {
+    let task_id = Uuid::new_v4();
+    let properties = [EventData::Count(1), EventData::TaskId(task_id)];
+
+    // Without a context, properties are left as they are.
+    assert!(matches!(merged(&properties), Cow::Borrowed(_)));
+
+    let job_id = Uuid::new_v4();
+    let context = CorrelationContext::new()
+        .with(EventData::JobId(job_id))
+        .with(EventData::TaskId(Uuid::new_v4()));
+    context.sync_scope(|| {
+        assert_eq!(
+            merged(&properties).as_ref(),
+            [
+                EventData::Count(1),
+                EventData::TaskId(task_id),
+                EventData::JobId(job_id),
+            ]
+        );
+    });
+}
+
+#[tokio::test]
+async fn test_events_of_nested_tasks() -> Result<()> {
+    let mut events = subscribe_to_events()?;
+    let (job_id, task_id) = (Uuid::new_v4(), Uuid::new_v4());
+    let context = CorrelationContext::new()
+        .with(EventData::JobId(job_id))
+        .with(EventData::TaskId(task_id));
+
+    let (outer, inner) = (marker(), marker());
+    context
+        .scope(async {
+            track_event(&Event::task_start, &[outer.clone()]);
+
+            let inner = inner.clone();
+            spawn(async move {
+                spawn(async move { track_event(&Event::task_start, &[inner]) }).await
+            })
+            .await
+        })
+        .await??;
+
+    for marker in [outer, inner] {
+        let data = next_event(&mut events, &marker).await;
+        assert!(data.contains(&EventData::JobId(job_id)), "{data:?}");
+        assert!(data.contains(&EventData::TaskId(task_id)), "{data:?}");
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_input_hash_override() -> Result<()> {
+    let mut events = subscribe_to_events()?;
+    let task_id = Uuid::new_v4();
+    let context = CorrelationContext::new().with(EventData::TaskId(task_id));
+
+    let input_sha256 = EventData::InputSha256("0123abcd".to_owned());
+    let (during, after, explicit) = (marker(), marker(), marker());
+    context
+        .scope(async {
+            with_entry(input_sha256.clone(), async {
+                let during = during.clone();
+                spawn(async move { track_event(&Event::task_start, &[during]) }).await
+            })
+            .await?;
+
+            track_event(&Event::task_start, &[after.clone()]);
+
+            let other_task = EventData::TaskId(Uuid::new_v4());
+            track_event(&Event::task_start, &[explicit.clone(), other_task]);
+
+            Ok::<_, anyhow::Error>(())
+        })
+        .await?;
+
+    let data = next_event(&mut events, &during).await;
+    assert!(data.contains(&input_sha256), "{data:?}");
+    assert!(data.contains(&EventData::TaskId(task_id)), "{data:?}");
+
+    // The override ends with its scope.
+    let data = next_event(&mut events, &after).await;
+    assert!(!data.contains(&input_sha256), "{data:?}");
+    assert!(data.contains(&EventData::TaskId(task_id)), "{data:?}");
+
+    // Explicit properties take precedence.
+    let data = next_event(&mut events, &explicit).await;
+    let task_ids = data
+        .iter()
+        .filter(|e| matches!(e, EventData::TaskId(_)))
+        .count();
+    assert_eq!(task_ids, 1, "{data:?}");
+    assert!(!data.contains(&EventData::TaskId(task_id)), "{data:?}");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_events_outside_of_a_scope() -> Result<()> {
+    let mut events = subscribe_to_events()?;
+    let outside = marker();
+
+    // Tasks spawned directly by tokio don't inherit the context.
+    let context = CorrelationContext::new().with(EventData::JobId(Uuid::new_v4()));
+    let event = outside.clone();
+    context
+        .scope(tokio::spawn(async move {
+            track_event(&Event::task_start, &[event])
+        }))
+        .await?;
+
+    assert_eq!(next_event(&mut events, &outside).await, [outside]);
+
+    Ok(())
+}
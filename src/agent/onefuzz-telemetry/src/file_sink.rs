@@ -0,0 +1,307 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! A sink of events and metrics to local files, one JSON object per line, for
+//! nodes which can't send telemetry anywhere. The files can be imported later.
+//!
+//! Each process writes its own files, named for the process and its ID, so the
+//! agent and the tasks of a node can share a directory. When the current file
+//! would exceed its size limit, it's rotated, as `{name}.1.jsonl`, and so on,
+//! keeping only a limited number of files per process.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::{bail, Context, Result};
+use chrono::{SecondsFormat, Utc};
+use serde::Serialize;
+
+use crate::{Event, EventData};
+
+/// The directory to write telemetry files to. Unless set, none are written.
+pub const DIR_ENV: &str = "ONEFUZZ_TELEMETRY_FILE_DIR";
+
+/// The size limit, in bytes, of each file. Defaults to 10 MiB.
+pub const MAX_SIZE_ENV: &str = "ONEFUZZ_TELEMETRY_FILE_MAX_SIZE";
+
+/// The number of files kept by each process, including the one written to.
+/// Defaults to 5.
+pub const MAX_FILES_ENV: &str = "ONEFUZZ_TELEMETRY_FILE_MAX_FILES";
+
+const DEFAULT_MAX_SIZE: u64 = 10 * 1024 * 1024;
+const DEFAULT_MAX_FILES: usize = 5;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileSinkConfig {
+    pub dir: PathBuf,
+    pub max_size: u64,
+    pub max_files: usize,
+}
+
+impl FileSinkConfig {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            max_size: DEFAULT_MAX_SIZE,
+            max_files: DEFAULT_MAX_FILES,
+        }
+    }
+
+    /// The config set by `ONEFUZZ_TELEMETRY_FILE_DIR` and its limits, if a
+    /// directory is set.
+    pub fn from_env() -> Result<Option<Self>> {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Option<Self>> {
+        let Some(dir) = var(DIR_ENV).filter(|dir| !dir.is_empty()) else {
+            return Ok(None);
+        };
+        let mut config = Self::new(dir);
+
+        if let Some(max_size) = var(MAX_SIZE_ENV) {
+            config.max_size = max_size
+                .parse()
+                .with_context(|| format!("invalid {MAX_SIZE_ENV}: {max_size}"))?;
+        }
+
+        if let Some(max_files) = var(MAX_FILES_ENV) {
+            config.max_files = max_files
+                .parse()
+                .with_context(|| format!("invalid {MAX_FILES_ENV}: {max_files}"))?;
+        }
+
+        if config.max_files == 0 {
+            bail!("{} must be at least 1", MAX_FILES_ENV);
+        }
+
+        Ok(Some(config))
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum RecordType {
+    Event,
+    Metric,
+}
+
+// A line of a telemetry file.
+#[derive(Debug, Serialize)]
+struct Record<'a> {
+    timestamp: String,
+    #[serde(rename = "type")]
+    record_type: RecordType,
+    name: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value: Option<f64>,
+    properties: BTreeMap<&'a str, String>,
+
+    // The properties set by `crate::set_property`, such as the role, machine
+    // and task.
+    common: &'a BTreeMap<String, String>,
+}
+
+/// Appends events and metrics to the rotating telemetry files of the process.
+pub struct FileSink {
+    state: Mutex<State>,
+}
+
+struct State {
+    writer: Writer,
+    common: BTreeMap<String, String>,
+}
+
+impl FileSink {
+    /// Write to files in the directory of `config`, which is created when the
+    /// first record is written.
+    pub fn new(config: FileSinkConfig) -> Self {
+        let exe = std::env::current_exe().ok();
+        let process = exe
+            .as_deref()
+            .and_then(Path::file_stem)
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("onefuzz");
+
+        Self::with_name(config, format!("{}-{}", process, std::process::id()))
+    }
+
+    fn with_name(config: FileSinkConfig, name: String) -> Self {
+        let state = State {
+            writer: Writer {
+                config,
+                name,
+                file: None,
+                size: 0,
+                failing: false,
+            },
+            common: BTreeMap::new(),
+        };
+
+        Self {
+            state: Mutex::new(state),
+        }
+    }
+
+    /// The path of the file being written to.
+    pub fn path(&self) -> Option<PathBuf> {
+        let state = self.state.lock().ok()?;
+        Some(state.writer.path(0))
+    }
+
+    /// Set the common property of `entry`, for the records written after.
+    pub fn set_property(&self, entry: &EventData) {
+        let (key, value) = entry.as_values();
+        if let Ok(mut state) = self.state.lock() {
+            state.common.insert(key.to_owned(), value);
+        }
+    }
+
+    pub fn event(&self, event: &Event, properties: &[EventData]) {
+        self.write(RecordType::Event, event, None, properties);
+    }
+
+    pub fn metric(&self, metric: &Event, value: f64, properties: &[EventData]) {
+        self.write(RecordType::Metric, metric, Some(value), properties);
+    }
+
+    fn write(
+        &self,
+        record_type: RecordType,
+        event: &Event,
+        value: Option<f64>,
+        properties: &[EventData],
+    ) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        let State { writer, common } = &mut *state;
+
+        let record = Record {
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true),
+            record_type,
+            name: event.as_str(),
+            value,
+            properties: properties.iter().map(EventData::as_values).collect(),
+            common,
+        };
+
+        // Each record is written whole, by a single write, so that no partial
+        // line is left if the process exits.
+        let line = match serde_json::to_vec(&record) {
+            Ok(mut line) => {
+                line.push(b'\n');
+                line
+            }
+            Err(err) => {
+                log::warn!("unable to serialize telemetry record: {:?}", err);
+                return;
+            }
+        };
+
+        writer.write(&line);
+    }
+}
+
+struct Writer {
+    config: FileSinkConfig,
+    name: String,
+    file: Option<File>,
+    size: u64,
+
+    // Set while the files can't be written, so that only the first failure is
+    // logged.
+    failing: bool,
+}
+
+impl Writer {
+    // The path of the file `index` rotations old.
+    fn path(&self, index: usize) -> PathBuf {
+        let file_name = match index {
+            0 => format!("{}.jsonl", self.name),
+            _ => format!("{}.{}.jsonl", self.name, index),
+        };
+        self.config.dir.join(file_name)
+    }
+
+    fn write(&mut self, line: &[u8]) {
+        match self.try_write(line) {
+            Ok(()) => self.failing = false,
+            Err(err) => {
+                if !self.failing {
+                    log::warn!("unable to write telemetry file: {:?}", err);
+                }
+                self.failing = true;
+            }
+        }
+    }
+
+    fn try_write(&mut self, line: &[u8]) -> Result<()> {
+        let len = line.len() as u64;
+        let mut file = match self.file.take() {
+            Some(file) => file,
+            None => self.open()?,
+        };
+
+        if self.size > 0 && self.size + len > self.config.max_size {
+            drop(file);
+            self.rotate()?;
+            file = self.open()?;
+        }
+
+        file.write_all(line)?;
+        self.file = Some(file);
+        self.size += len;
+
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<File> {
+        fs::create_dir_all(&self.config.dir)
+            .with_context(|| format!("unable to create {}", self.config.dir.display()))?;
+
+        let path = self.path(0);
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("unable to open {}", path.display()))?;
+        self.size = file.metadata()?.len();
+
+        Ok(file)
+    }
+
+    fn rotate(&self) -> Result<()> {
+        // The oldest file is removed first, as renaming over it fails on
+        // Windows.
+        let last = self.config.max_files.saturating_sub(1);
+        remove_if_exists(&self.path(last))?;
+        for index in (1..=last).rev() {
+            let from = self.path(index - 1);
+            match fs::rename(&from, self.path(index)) {
+                Err(err) if err.kind() != ErrorKind::NotFound => {
+                    return Err(err)
+                        .with_context(|| format!("unable to rotate {}", from.display()));
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn remove_if_exists(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != ErrorKind::NotFound => {
+            Err(err).with_context(|| format!("unable to remove {}", path.display()))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests;
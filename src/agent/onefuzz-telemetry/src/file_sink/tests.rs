@@ -0,0 +1,210 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use serde_json::Value;
+use uuid::Uuid;
+
+use super::*;
+use crate::Role;
+
+fn read_lines(path: &Path) -> Result<Vec<Value>> {
+    let text = fs::read_to_string(path)?;
+    let lines = text
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<Result<_, _>>()?;
+    Ok(lines)
+}
+
+#[test]
+fn test_record_format() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let sink = FileSink::with_name(FileSinkConfig::new(dir.path()), "task".into());
+
+    let task_id = Uuid::new_v4();
+    sink.set_property(&EventData::Role(Role::Agent));
+    sink.set_property(&EventData::TaskId(task_id));
+    sink.event(
+        &Event::new_report,
+        &[EventData::Path("crash-1.json".into())],
+    );
+    sink.metric(&Event::runtime_stats, 2.5, &[EventData::Count(10)]);
+
+    let lines = read_lines(&dir.path().join("task.jsonl"))?;
+    assert_eq!(lines.len(), 2);
+
+    let common = serde_json::json!({
+        "role": "agent",
+        "task_id": task_id.to_string(),
+    });
+
+    let event = &lines[0];
+    assert_eq!(event["type"], "event");
+    assert_eq!(event["name"], "new_report");
+    assert!(event.get("value").is_none());
+    assert_eq!(
+        event["properties"],
+        serde_json::json!({"path": "crash-1.json"})
+    );
+    assert_eq!(event["common"], common);
+    let timestamp = event["timestamp"].as_str().unwrap();
+    assert!(chrono::DateTime::parse_from_rfc3339(timestamp).is_ok());
+
+    let metric = &lines[1];
+    assert_eq!(metric["type"], "metric");
+    assert_eq!(metric["name"], "runtime_stats");
+    assert_eq!(metric["value"], 2.5);
+    assert_eq!(metric["properties"], serde_json::json!({"count": "10"}));
+    assert_eq!(metric["common"], common);
+
+    Ok(())
+}
+
+#[test]
+fn test_rotation() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let config = FileSinkConfig {
+        max_size: 512,
+        max_files: 3,
+        ..FileSinkConfig::new(dir.path())
+    };
+    let sink = FileSink::with_name(config, "task".into());
+
+    for count in 0..100 {
+        sink.event(&Event::new_report, &[EventData::Count(count)]);
+    }
+
+    let mut names: Vec<_> = fs::read_dir(dir.path())?
+        .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+        .collect::<Result<_>>()?;
+    names.sort();
+    assert_eq!(names, ["task.1.jsonl", "task.2.jsonl", "task.jsonl"]);
+
+    let mut counts = vec![];
+    for name in ["task.2.jsonl", "task.1.jsonl", "task.jsonl"] {
+        let path = dir.path().join(name);
+        assert!(fs::metadata(&path)?.len() <= 512, "{name}");
+
+        for line in read_lines(&path)? {
+            counts.push(
+                line["properties"]["count"]
+                    .as_str()
+                    .unwrap()
+                    .parse::<u64>()?,
+            );
+        }
+    }
+
+    // The newest records are kept, in order.
+    let first = *counts.first().unwrap();
+    assert!(first > 0);
+    assert_eq!(counts, (first..100).collect::<Vec<_>>());
+
+    Ok(())
+}
+
+#[test]
+fn test_rotation_of_existing_file() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    fs::write(dir.path().join("task.jsonl"), vec![b' '; 1024])?;
+
+    let config = FileSinkConfig {
+        max_size: 512,
+        max_files: 1,
+        ..FileSinkConfig::new(dir.path())
+    };
+    let sink = FileSink::with_name(config, "task".into());
+    sink.event(&Event::new_report, &[]);
+
+    assert_eq!(read_lines(&dir.path().join("task.jsonl"))?.len(), 1);
+    assert!(!dir.path().join("task.1.jsonl").exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_concurrent_writes() -> Result<()> {
+    const THREADS: u64 = 8;
+    const RECORDS: u64 = 250;
+
+    let dir = tempfile::tempdir()?;
+
+    // As if the sinks of two processes on a node.
+    let sinks: Vec<_> = ["agent", "task"]
+        .into_iter()
+        .map(|name| {
+            Arc::new(FileSink::with_name(
+                FileSinkConfig::new(dir.path()),
+                name.into(),
+            ))
+        })
+        .collect();
+
+    let threads: Vec<_> = (0..THREADS)
+        .map(|thread| {
+            let sink = sinks[thread as usize % sinks.len()].clone();
+            std::thread::spawn(move || {
+                for count in 0..RECORDS {
+                    sink.metric(
+                        &Event::runtime_stats,
+                        count as f64,
+                        &[EventData::Count(thread), EventData::Path("x".repeat(100))],
+                    );
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    let mut total = 0;
+    for name in ["agent.jsonl", "task.jsonl"] {
+        let lines = read_lines(&dir.path().join(name))?;
+        for line in &lines {
+            assert_eq!(line["name"], "runtime_stats");
+        }
+        total += lines.len() as u64;
+    }
+    assert_eq!(total, THREADS * RECORDS);
+
+    Ok(())
+}
+
+#[test]
+fn test_config() -> Result<()> {
+    let config = |vars: &[(&str, &str)]| {
+        let vars: HashMap<_, _> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        FileSinkConfig::from_vars(|name| vars.get(name).cloned())
+    };
+
+    assert_eq!(config(&[])?, None);
+    assert_eq!(config(&[(DIR_ENV, "")])?, None);
+    assert_eq!(
+        config(&[(DIR_ENV, "/telemetry")])?,
+        Some(FileSinkConfig::new("/telemetry"))
+    );
+    assert_eq!(
+        config(&[
+            (DIR_ENV, "/telemetry"),
+            (MAX_SIZE_ENV, "1024"),
+            (MAX_FILES_ENV, "2"),
+        ])?,
+        Some(FileSinkConfig {
+            dir: "/telemetry".into(),
+            max_size: 1024,
+            max_files: 2,
+        })
+    );
+
+    assert!(config(&[(DIR_ENV, "/telemetry"), (MAX_SIZE_ENV, "big")]).is_err());
+    assert!(config(&[(DIR_ENV, "/telemetry"), (MAX_FILES_ENV, "0")]).is_err());
+
+    Ok(())
+}
@@ -5,7 +5,7 @@ use chrono::DateTime;
 use serde::{Deserialize, Serialize};
 use std::fmt;
 use std::sync::{LockResult, RwLockReadGuard, RwLockWriteGuard};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use uuid::Uuid;
 
 pub use chrono::Utc;
@@ -16,6 +16,11 @@ use tokio::sync::broadcast::{self, Receiver};
 #[macro_use]
 extern crate lazy_static;
 
+pub mod context;
+pub mod file_sink;
+pub mod otlp;
+pub mod sampling;
+
 const DEAFAULT_CHANNEL_CLOSING_TIMEOUT: Duration = Duration::from_secs(30);
 
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
@@ -76,15 +81,33 @@ pub enum Event {
     task_start,
     coverage_data,
     coverage_failed,
+    coverage_summary,
+    coverage_module_summary,
     new_result,
     new_crashdump,
+    new_oom,
+    new_leak,
     new_coverage,
     runtime_stats,
+    fuzzer_restart,
     new_report,
     new_unique_report,
     new_unable_to_reproduce,
+    report_throughput,
     regression_report,
     regression_unable_to_reproduce,
+    regression_bisect,
+    regression_error,
+    agent_config_reloaded,
+    generator_crash,
+    initial_corpus_sampled,
+    telemetry_dropped,
+    directory_monitor_polling,
+    process_stats,
+    heartbeat_undelivered,
+    circuit_breaker,
+    span_start,
+    span_stop,
 }
 
 impl Event {
@@ -93,15 +116,33 @@ impl Event {
             Self::task_start => "task_start",
             Self::coverage_data => "coverage_data",
             Self::coverage_failed => "coverage_failed",
+            Self::coverage_summary => "coverage_summary",
+            Self::coverage_module_summary => "coverage_module_summary",
             Self::new_coverage => "new_coverage",
             Self::new_crashdump => "new_crashdump",
+            Self::new_oom => "new_oom",
+            Self::new_leak => "new_leak",
             Self::new_result => "new_result",
             Self::runtime_stats => "runtime_stats",
+            Self::fuzzer_restart => "fuzzer_restart",
             Self::new_report => "new_report",
             Self::new_unique_report => "new_unique_report",
             Self::new_unable_to_reproduce => "new_unable_to_reproduce",
+            Self::report_throughput => "report_throughput",
             Self::regression_report => "regression_report",
             Self::regression_unable_to_reproduce => "regression_unable_to_reproduce",
+            Self::regression_bisect => "regression_bisect",
+            Self::regression_error => "regression_error",
+            Self::agent_config_reloaded => "agent_config_reloaded",
+            Self::generator_crash => "generator_crash",
+            Self::initial_corpus_sampled => "initial_corpus_sampled",
+            Self::telemetry_dropped => "telemetry_dropped",
+            Self::directory_monitor_polling => "directory_monitor_polling",
+            Self::process_stats => "process_stats",
+            Self::heartbeat_undelivered => "heartbeat_undelivered",
+            Self::circuit_breaker => "circuit_breaker",
+            Self::span_start => "span_start",
+            Self::span_stop => "span_stop",
         }
     }
 }
@@ -113,6 +154,7 @@ pub enum EventData {
     JobId(Uuid),
     TaskId(Uuid),
     ScalesetId(String),
+    ManagedIdentity(String),
     MachineId(Uuid),
     Version(String),
     CommandLine(String),
@@ -122,6 +164,9 @@ pub enum EventData {
     Features(u64),
     Covered(u64),
     Rate(f64),
+    Skipped(u64),
+    Processed(u64),
+    Remaining(u64),
     Count(u64),
     ExecsSecond(f64),
     RunId(Uuid),
@@ -137,9 +182,28 @@ pub enum EventData {
     CoveragePathsFound(u64),
     CoveragePathsImported(u64),
     CoverageMaxDepth(u64),
+    CyclesDone(u64),
+    CorpusCount(u64),
+    Crashes(u64),
+    RssMb(u64),
     ToolName(String),
     Region(String),
     Role(Role),
+    FirstBadBuild(String),
+    LastGoodBuild(String),
+    Verdict(String),
+    OriginalReport(String),
+    UniqueKey(String),
+    Build(String),
+    Attempts(u64),
+    Processes(u64),
+    Threads(u64),
+    Handles(u64),
+    InputSha256(String),
+    Span(String),
+    CircuitState(String),
+    SpanFields(String),
+    DurationMs(u64),
 }
 
 impl EventData {
@@ -150,6 +214,7 @@ impl EventData {
             Self::JobId(x) => ("job_id", x.to_string()),
             Self::TaskId(x) => ("task_id", x.to_string()),
             Self::ScalesetId(x) => ("scaleset_id", x.to_string()),
+            Self::ManagedIdentity(x) => ("managed_identity", x.to_owned()),
             Self::MachineId(x) => ("machine_id", x.to_string()),
             Self::CommandLine(x) => ("command_line", x.to_owned()),
             Self::Type(x) => ("event_type", x.to_owned()),
@@ -158,6 +223,9 @@ impl EventData {
             Self::Features(x) => ("features", x.to_string()),
             Self::Covered(x) => ("covered", x.to_string()),
             Self::Rate(x) => ("rate", x.to_string()),
+            Self::Skipped(x) => ("skipped", x.to_string()),
+            Self::Processed(x) => ("processed", x.to_string()),
+            Self::Remaining(x) => ("remaining", x.to_string()),
             Self::Count(x) => ("count", x.to_string()),
             Self::ExecsSecond(x) => ("execs_sec", x.to_string()),
             Self::WorkerId(x) => ("worker_id", x.to_string()),
@@ -173,10 +241,29 @@ impl EventData {
             Self::CoveragePathsFound(x) => ("coverage_paths_found", x.to_string()),
             Self::CoveragePathsImported(x) => ("coverage_paths_imported", x.to_string()),
             Self::CoverageMaxDepth(x) => ("coverage_paths_depth", x.to_string()),
+            Self::CyclesDone(x) => ("cycles_done", x.to_string()),
+            Self::CorpusCount(x) => ("corpus_count", x.to_string()),
+            Self::Crashes(x) => ("crashes", x.to_string()),
+            Self::RssMb(x) => ("rss_mb", x.to_string()),
             Self::Coverage(x) => ("coverage", x.to_string()),
             Self::ToolName(x) => ("tool_name", x.to_owned()),
             Self::Region(x) => ("region", x.to_owned()),
             Self::Role(x) => ("role", x.as_str().to_owned()),
+            Self::FirstBadBuild(x) => ("first_bad_build", x.to_owned()),
+            Self::LastGoodBuild(x) => ("last_good_build", x.to_owned()),
+            Self::Verdict(x) => ("verdict", x.to_owned()),
+            Self::OriginalReport(x) => ("original_report", x.to_owned()),
+            Self::UniqueKey(x) => ("unique_key", x.to_owned()),
+            Self::Build(x) => ("build", x.to_owned()),
+            Self::Attempts(x) => ("attempts", x.to_string()),
+            Self::Processes(x) => ("processes", x.to_string()),
+            Self::Threads(x) => ("threads", x.to_string()),
+            Self::Handles(x) => ("handles", x.to_string()),
+            Self::InputSha256(x) => ("input_sha256", x.to_owned()),
+            Self::Span(x) => ("span", x.to_owned()),
+            Self::CircuitState(x) => ("circuit_state", x.to_owned()),
+            Self::SpanFields(x) => ("span_fields", x.to_owned()),
+            Self::DurationMs(x) => ("duration_ms", x.to_string()),
         }
     }
 
@@ -188,6 +275,7 @@ impl EventData {
             Self::JobId(_) => true,
             Self::MachineId(_) => true,
             Self::ScalesetId(_) => false,
+            Self::ManagedIdentity(_) => false,
             Self::CommandLine(_) => false,
             Self::Path(_) => false,
             Self::Type(_) => true,
@@ -195,6 +283,9 @@ impl EventData {
             Self::Features(_) => true,
             Self::Covered(_) => true,
             Self::Rate(_) => true,
+            Self::Skipped(_) => true,
+            Self::Processed(_) => true,
+            Self::Remaining(_) => true,
             Self::Count(_) => true,
             Self::ExecsSecond(_) => true,
             Self::WorkerId(_) => true,
@@ -210,10 +301,29 @@ impl EventData {
             Self::CoveragePathsFound(_) => true,
             Self::CoveragePathsImported(_) => true,
             Self::CoverageMaxDepth(_) => true,
+            Self::CyclesDone(_) => true,
+            Self::CorpusCount(_) => true,
+            Self::Crashes(_) => true,
+            Self::RssMb(_) => true,
             Self::Coverage(_) => true,
             Self::ToolName(_) => true,
             Self::Region(_) => false,
             Self::Role(_) => true,
+            Self::FirstBadBuild(_) => false,
+            Self::LastGoodBuild(_) => false,
+            Self::Verdict(_) => true,
+            Self::OriginalReport(_) => false,
+            Self::UniqueKey(_) => false,
+            Self::Build(_) => false,
+            Self::Attempts(_) => true,
+            Self::Processes(_) => true,
+            Self::Threads(_) => true,
+            Self::Handles(_) => true,
+            Self::InputSha256(_) => false,
+            Self::Span(_) => true,
+            Self::CircuitState(_) => true,
+            Self::SpanFields(_) => false,
+            Self::DurationMs(_) => true,
         }
     }
 }
@@ -241,7 +351,7 @@ pub struct LogEvent {
 mod global {
     use std::sync::{
         atomic::{AtomicUsize, Ordering},
-        RwLock,
+        Mutex, RwLock,
     };
 
     use tokio::sync::broadcast::Sender;
@@ -259,6 +369,12 @@ mod global {
         microsoft: None,
     };
 
+    lazy_static! {
+        pub static ref OTLP_EXPORTER: RwLock<Option<otlp::OtlpExporter>> = RwLock::new(None);
+        pub static ref FILE_SINK: RwLock<Option<file_sink::FileSink>> = RwLock::new(None);
+        pub static ref SAMPLER: Mutex<Option<sampling::Sampler>> = Mutex::new(None);
+    }
+
     lazy_static! {
         pub static ref EVENT_SOURCE: RwLock<Option<Sender<LoggingEvent>>> = {
             let (telemetry_event_source, _) = broadcast::channel::<_>(5000);
@@ -360,6 +476,117 @@ pub async fn set_appinsights_clients(
     global::set_clients(instance_client, microsoft_client);
 }
 
+/// Export telemetry to the OTLP collector of `config`, in addition to any
+/// Application Insights clients. Must be called within a Tokio runtime.
+pub fn set_otlp_exporter(config: otlp::OtlpConfig) -> Result<()> {
+    let exporter = otlp::OtlpExporter::new(config)?;
+    match global::OTLP_EXPORTER.write() {
+        Ok(mut global) => *global = Some(exporter),
+        Err(e) => bail!("failed to acquire OTLP exporter lock: {}", e),
+    }
+
+    Ok(())
+}
+
+/// Export telemetry as by [`set_otlp_exporter`], to the collector named by
+/// `ONEFUZZ_TELEMETRY_ENDPOINT` and `ONEFUZZ_TELEMETRY_PROTOCOL`, if set.
+pub async fn set_otlp_exporter_from_env() -> Result<()> {
+    if let Some(config) = otlp::OtlpConfig::from_env()? {
+        set_otlp_exporter(config)?;
+    }
+
+    Ok(())
+}
+
+/// Write telemetry to local files, as configured by `config`, in addition to
+/// any other clients and exporters.
+pub fn set_file_sink(config: file_sink::FileSinkConfig) -> Result<()> {
+    let sink = file_sink::FileSink::new(config);
+    match global::FILE_SINK.write() {
+        Ok(mut global) => *global = Some(sink),
+        Err(e) => bail!("failed to acquire telemetry file sink lock: {}", e),
+    }
+
+    Ok(())
+}
+
+/// Write telemetry as by [`set_file_sink`], to the directory named by
+/// `ONEFUZZ_TELEMETRY_FILE_DIR`, if set.
+pub fn set_file_sink_from_env() -> Result<()> {
+    if let Some(config) = file_sink::FileSinkConfig::from_env()? {
+        set_file_sink(config)?;
+    }
+
+    Ok(())
+}
+
+/// Sample and rate limit the events and metrics exported, by the rules of
+/// `config`.
+pub fn set_sampling(config: sampling::SamplingConfig) -> Result<()> {
+    let sampler = sampling::Sampler::new(config, Instant::now());
+    match global::SAMPLER.lock() {
+        Ok(mut global) => *global = Some(sampler),
+        Err(e) => bail!("failed to acquire telemetry sampler lock: {}", e),
+    }
+
+    Ok(())
+}
+
+/// Sample telemetry as by [`set_sampling`], by the rules of
+/// `ONEFUZZ_TELEMETRY_SAMPLING`, if set.
+pub fn set_sampling_from_env() -> Result<()> {
+    if let Some(config) = sampling::SamplingConfig::from_env()? {
+        set_sampling(config)?;
+    }
+
+    Ok(())
+}
+
+// Whether a record of `event` is exported. Summarizes the records dropped, if
+// due.
+fn sample(event: &Event) -> bool {
+    let now = Instant::now();
+    let (sampled, dropped) = match global::SAMPLER.lock().as_deref_mut() {
+        Ok(Some(sampler)) => (sampler.sample(event, now), sampler.summary(now)),
+        _ => (true, vec![]),
+    };
+
+    track_dropped(dropped);
+
+    sampled
+}
+
+fn track_dropped(dropped: Vec<(&'static str, u64)>) {
+    for (name, count) in dropped {
+        track_event(
+            &Event::telemetry_dropped,
+            &[EventData::Type(name.to_owned()), EventData::Count(count)],
+        );
+    }
+}
+
+fn with_file_sink(f: impl FnOnce(&file_sink::FileSink)) {
+    if let Some(sink) = global::FILE_SINK
+        .read()
+        .ok()
+        .as_ref()
+        .and_then(|s| s.as_ref())
+    {
+        f(sink);
+    }
+}
+
+fn with_otlp_exporter(f: impl FnOnce(&otlp::OtlpExporter)) {
+    if let Some(exporter) = global::OTLP_EXPORTER
+        .read()
+        .ok()
+        .as_ref()
+        .and_then(|e| e.as_ref())
+    {
+        f(exporter);
+    }
+}
+
 pub async fn try_flush_and_close() {
     _try_flush_and_close(DEAFAULT_CHANNEL_CLOSING_TIMEOUT).await
 }
@@ -370,12 +597,28 @@ pub async fn try_flush_and_close() {
 /// After calling this function, any existing telemetry client will be dropped,
 /// and subsequent telemetry submission will be a silent no-op.
 pub async fn _try_flush_and_close(timeout: Duration) {
+    let sampler = global::SAMPLER.lock().ok().and_then(|mut s| s.take());
+    if let Some(mut sampler) = sampler {
+        track_dropped(sampler.take_dropped());
+    }
+
     let clients = global::take_clients();
     for client in clients {
         if let Err(e) = tokio::time::timeout(timeout, client.close_channel()).await {
             log::warn!("Failed to close telemetry client: {}", e);
         }
     }
+
+    let exporter = global::OTLP_EXPORTER
+        .write()
+        .ok()
+        .and_then(|mut e| e.take());
+    if let Some(exporter) = exporter {
+        exporter.flush_and_close(timeout).await;
+    }
+
+    let _file_sink = global::FILE_SINK.write().ok().and_then(|mut s| s.take());
+
     // dropping the broadcast sender to make sure all pending events are sent
     let _global_event_source = global::EVENT_SOURCE.write().unwrap().take();
 }
@@ -414,6 +657,9 @@ pub fn property(client_type: ClientType, key: impl AsRef<str>) -> Option<String>
 }
 
 pub fn set_property(entry: EventData) {
+    with_otlp_exporter(|exporter| exporter.set_property(&entry));
+    with_file_sink(|sink| sink.set_property(&entry));
+
     let (key, value) = entry.as_values();
 
     if entry.can_share_with_microsoft() {
@@ -490,6 +736,16 @@ pub fn subscribe_to_events() -> Result<Receiver<LoggingEvent>> {
 }
 
 pub fn track_event(event: &Event, properties: &[EventData]) {
+    let properties = context::merged(properties);
+
+    if sample(event) {
+        export_event(event, &properties);
+    }
+
+    try_broadcast_event(chrono::Utc::now(), event, &properties);
+}
+
+fn export_event(event: &Event, properties: &[EventData]) {
     use appinsights::telemetry::Telemetry;
 
     if let Some(client) = client(ClientType::Instance) {
@@ -514,12 +770,20 @@ pub fn track_event(event: &Event, properties: &[EventData]) {
         }
         client.track(evt);
     }
-    try_broadcast_event(chrono::Utc::now(), event, properties);
+
+    with_otlp_exporter(|exporter| exporter.event(event, properties));
+    with_file_sink(|sink| sink.event(event, properties));
 }
 
 pub fn track_metric(metric: &Event, value: f64, properties: &[EventData]) {
     use appinsights::telemetry::Telemetry;
 
+    if !sample(metric) {
+        return;
+    }
+    let properties = context::merged(properties);
+    let properties = properties.as_ref();
+
     if let Some(client) = client(ClientType::Instance) {
         let mut mtr = appinsights::telemetry::MetricTelemetry::new(metric.as_str(), value);
         let props = mtr.properties_mut();
@@ -542,6 +806,9 @@ pub fn track_metric(metric: &Event, value: f64, properties: &[EventData]) {
         }
         client.track(mtr);
     }
+
+    with_otlp_exporter(|exporter| exporter.metric(metric, value, properties));
+    with_file_sink(|sink| sink.metric(metric, value, properties));
 }
 
 pub fn to_log_level(level: &appinsights::telemetry::SeverityLevel) -> log::Level {
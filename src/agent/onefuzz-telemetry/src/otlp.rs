@@ -0,0 +1,582 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Export of events and metrics to an OpenTelemetry collector, over OTLP, as
+//! an alternative to, or alongside, Application Insights.
+//!
+//! Events are exported as log records, named by their body, and metrics as
+//! gauges. Their data are attributes of each record, and the properties set by
+//! [`crate::set_property`], such as the role, machine and task, are attributes
+//! of the resource of every record.
+//!
+//! Records are queued, then batched and sent by a background task, so that a
+//! collector which is slow or unreachable never blocks the agent or task.
+//! Records are dropped if the queue is full, or if they couldn't be sent.
+
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, bail, Context, Result};
+use hyper::body::HttpBody;
+use hyper::client::HttpConnector;
+use hyper::HeaderMap;
+use hyper_tls::HttpsConnector;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::{Event, EventData};
+
+/// The base URL of the OTLP endpoint of the collector, such as
+/// `http://localhost:4318`.
+pub const ENDPOINT_ENV: &str = "ONEFUZZ_TELEMETRY_ENDPOINT";
+
+/// The protocol of the endpoint: `http/protobuf`, the default, or `grpc`.
+pub const PROTOCOL_ENV: &str = "ONEFUZZ_TELEMETRY_PROTOCOL";
+
+const SCOPE_NAME: &str = "onefuzz";
+const SERVICE_NAME: &str = "onefuzz";
+
+// Records queued beyond this are dropped, rather than block their sender.
+const QUEUE_SIZE: usize = 4096;
+
+// Batches are sent when full, and otherwise on each period.
+const MAX_BATCH_SIZE: usize = 512;
+const BATCH_PERIOD: Duration = Duration::from_secs(5);
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OtlpProtocol {
+    Grpc,
+    HttpProtobuf,
+}
+
+impl FromStr for OtlpProtocol {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "grpc" => Ok(Self::Grpc),
+            "http/protobuf" => Ok(Self::HttpProtobuf),
+            _ => bail!("unsupported OTLP protocol: {}", s),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OtlpConfig {
+    pub endpoint: String,
+    pub protocol: OtlpProtocol,
+}
+
+impl OtlpConfig {
+    /// The config set by `ONEFUZZ_TELEMETRY_ENDPOINT` and
+    /// `ONEFUZZ_TELEMETRY_PROTOCOL`, if an endpoint is set.
+    pub fn from_env() -> Result<Option<Self>> {
+        let Some(endpoint) = std::env::var(ENDPOINT_ENV).ok().filter(|e| !e.is_empty()) else {
+            return Ok(None);
+        };
+
+        let protocol = match std::env::var(PROTOCOL_ENV) {
+            Ok(protocol) => protocol.parse()?,
+            Err(_) => OtlpProtocol::HttpProtobuf,
+        };
+
+        Ok(Some(Self { endpoint, protocol }))
+    }
+
+    fn url(&self, signal: Signal) -> String {
+        let endpoint = self.endpoint.trim_end_matches('/');
+        match (self.protocol, signal) {
+            (OtlpProtocol::HttpProtobuf, Signal::Logs) => format!("{endpoint}/v1/logs"),
+            (OtlpProtocol::HttpProtobuf, Signal::Metrics) => format!("{endpoint}/v1/metrics"),
+            (OtlpProtocol::Grpc, Signal::Logs) => {
+                format!("{endpoint}/opentelemetry.proto.collector.logs.v1.LogsService/Export")
+            }
+            (OtlpProtocol::Grpc, Signal::Metrics) => {
+                format!("{endpoint}/opentelemetry.proto.collector.metrics.v1.MetricsService/Export")
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Signal {
+    Logs,
+    Metrics,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Record {
+    Log {
+        time: u64,
+        name: &'static str,
+        attributes: Vec<(String, String)>,
+    },
+    Metric {
+        time: u64,
+        name: &'static str,
+        value: f64,
+        attributes: Vec<(String, String)>,
+    },
+}
+
+/// Exports events and metrics to a collector, from a background task.
+pub struct OtlpExporter {
+    sender: mpsc::Sender<Record>,
+    resource: Arc<Mutex<BTreeMap<String, String>>>,
+    process: JoinHandle<()>,
+}
+
+impl OtlpExporter {
+    /// Start exporting to the collector of `config`. Must be called within a
+    /// Tokio runtime, as the records are sent by a spawned task.
+    pub fn new(config: OtlpConfig) -> Result<Self> {
+        let client = Client::new(config.protocol).context("unable to build OTLP client")?;
+
+        let resource = Arc::new(Mutex::new(BTreeMap::from([(
+            "service.name".to_owned(),
+            SERVICE_NAME.to_owned(),
+        )])));
+
+        let (sender, receiver) = mpsc::channel(QUEUE_SIZE);
+        let sink = Sink {
+            config,
+            client,
+            resource: resource.clone(),
+            failing: false,
+        };
+        let process = tokio::spawn(sink.run(receiver));
+
+        Ok(Self {
+            sender,
+            resource,
+            process,
+        })
+    }
+
+    /// Set the resource attribute of `entry`, for the records sent after.
+    pub fn set_property(&self, entry: &EventData) {
+        let (key, value) = entry.as_values();
+        if let Ok(mut resource) = self.resource.lock() {
+            resource.insert(key.to_owned(), value);
+        }
+    }
+
+    pub fn event(&self, event: &Event, properties: &[EventData]) {
+        self.send(Record::Log {
+            time: now(),
+            name: event.as_str(),
+            attributes: attributes(properties),
+        });
+    }
+
+    pub fn metric(&self, metric: &Event, value: f64, properties: &[EventData]) {
+        self.send(Record::Metric {
+            time: now(),
+            name: metric.as_str(),
+            value,
+            attributes: attributes(properties),
+        });
+    }
+
+    fn send(&self, record: Record) {
+        // Dropped if the queue is full, or the exporter was closed.
+        let _ = self.sender.try_send(record);
+    }
+
+    /// Send the records queued, waiting up to `timeout`.
+    pub async fn flush_and_close(self, timeout: Duration) {
+        let Self {
+            sender, process, ..
+        } = self;
+        drop(sender);
+
+        if tokio::time::timeout(timeout, process).await.is_err() {
+            log::warn!("timed out sending telemetry to OTLP collector");
+        }
+    }
+}
+
+fn attributes(properties: &[EventData]) -> Vec<(String, String)> {
+    properties
+        .iter()
+        .map(|property| {
+            let (key, value) = property.as_values();
+            (key.to_owned(), value)
+        })
+        .collect()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_nanos() as u64)
+        .unwrap_or_default()
+}
+
+// gRPC responses are read with hyper, as their status may only be sent in the
+// trailers, which reqwest doesn't expose.
+enum Client {
+    Http(reqwest::Client),
+    Grpc(hyper::Client<HttpsConnector<HttpConnector>>),
+}
+
+impl Client {
+    fn new(protocol: OtlpProtocol) -> Result<Self> {
+        let client = match protocol {
+            OtlpProtocol::HttpProtobuf => Self::Http(
+                reqwest::Client::builder()
+                    .timeout(REQUEST_TIMEOUT)
+                    .build()?,
+            ),
+            OtlpProtocol::Grpc => {
+                // Over TLS, collectors only accept gRPC if HTTP/2 is negotiated.
+                let tls = native_tls::TlsConnector::builder()
+                    .request_alpns(&["h2"])
+                    .build()?;
+                let mut http = HttpConnector::new();
+                http.enforce_http(false);
+                let connector =
+                    HttpsConnector::from((http, tokio_native_tls::TlsConnector::from(tls)));
+                Self::Grpc(hyper::Client::builder().http2_only(true).build(connector))
+            }
+        };
+
+        Ok(client)
+    }
+}
+
+struct Sink {
+    config: OtlpConfig,
+    client: Client,
+    resource: Arc<Mutex<BTreeMap<String, String>>>,
+
+    // Set while the collector can't be reached, so that only the first failure
+    // is logged.
+    failing: bool,
+}
+
+impl Sink {
+    async fn run(mut self, mut receiver: mpsc::Receiver<Record>) {
+        let mut batch = vec![];
+        let mut period = tokio::time::interval(BATCH_PERIOD);
+
+        loop {
+            tokio::select! {
+                record = receiver.recv() => match record {
+                    Some(record) => {
+                        batch.push(record);
+                        if batch.len() >= MAX_BATCH_SIZE {
+                            self.export(std::mem::take(&mut batch)).await;
+                        }
+                    }
+                    None => break,
+                },
+                _ = period.tick() => {
+                    self.export(std::mem::take(&mut batch)).await;
+                }
+            }
+        }
+
+        self.export(batch).await;
+    }
+
+    async fn export(&mut self, batch: Vec<Record>) {
+        if batch.is_empty() {
+            return;
+        }
+
+        let resource = match self.resource.lock() {
+            Ok(resource) => resource.clone(),
+            Err(_) => BTreeMap::new(),
+        };
+        let (logs, metrics): (Vec<_>, Vec<_>) = batch
+            .into_iter()
+            .partition(|record| matches!(record, Record::Log { .. }));
+
+        let mut result = Ok(());
+        if !logs.is_empty() {
+            let request = encode_logs(&resource, &logs);
+            result = result.and(self.post(Signal::Logs, request).await);
+        }
+        if !metrics.is_empty() {
+            let request = encode_metrics(&resource, &metrics);
+            result = result.and(self.post(Signal::Metrics, request).await);
+        }
+
+        match result {
+            Ok(()) => self.failing = false,
+            Err(err) => {
+                if !self.failing {
+                    log::warn!("unable to send telemetry to OTLP collector: {:?}", err);
+                }
+                self.failing = true;
+            }
+        }
+    }
+
+    async fn post(&self, signal: Signal, request: Vec<u8>) -> Result<()> {
+        let url = self.config.url(signal);
+        match &self.client {
+            Client::Http(client) => {
+                client
+                    .post(&url)
+                    .header("content-type", "application/x-protobuf")
+                    .body(request)
+                    .send()
+                    .await?
+                    .error_for_status()?;
+                Ok(())
+            }
+            Client::Grpc(client) => {
+                let call = post_grpc(client, &url, request);
+                tokio::time::timeout(REQUEST_TIMEOUT, call)
+                    .await
+                    .context("OTLP export timed out")?
+            }
+        }
+    }
+}
+
+async fn post_grpc(
+    client: &hyper::Client<HttpsConnector<HttpConnector>>,
+    url: &str,
+    request: Vec<u8>,
+) -> Result<()> {
+    let request = hyper::Request::post(url)
+        .header("content-type", "application/grpc")
+        .header("te", "trailers")
+        .body(hyper::Body::from(grpc_frame(&request)))?;
+
+    let response = client.request(request).await?;
+    if !response.status().is_success() {
+        bail!("OTLP export failed with HTTP status {}", response.status());
+    }
+
+    // The status of a call is sent in the trailers, after the response message,
+    // or in the headers of a response without one.
+    let status = match grpc_status(response.headers()) {
+        Some(status) => Some(status),
+        None => {
+            let mut body = response.into_body();
+            while let Some(data) = body.data().await {
+                data?;
+            }
+            body.trailers()
+                .await?
+                .and_then(|trailers| grpc_status(&trailers))
+        }
+    };
+
+    status.unwrap_or_else(|| Err(anyhow!("OTLP export response has no gRPC status")))
+}
+
+fn grpc_status(headers: &HeaderMap) -> Option<Result<()>> {
+    let status = headers.get("grpc-status")?;
+    if status == "0" {
+        return Some(Ok(()));
+    }
+
+    let message = headers
+        .get("grpc-message")
+        .map(|message| String::from_utf8_lossy(message.as_bytes()))
+        .unwrap_or_default();
+    Some(Err(anyhow!(
+        "OTLP export failed with gRPC status {}: {}",
+        String::from_utf8_lossy(status.as_bytes()),
+        message
+    )))
+}
+
+// A gRPC message: uncompressed, and prefixed with its length.
+fn grpc_frame(message: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(5 + message.len());
+    frame.push(0);
+    frame.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    frame.extend_from_slice(message);
+    frame
+}
+
+/// Encoding of the OTLP protobuf messages, of only the fields exported.
+///
+/// See `opentelemetry/proto/{collector,common,resource,logs,metrics}/v1` in
+/// <https://github.com/open-telemetry/opentelemetry-proto>.
+#[derive(Default)]
+struct Message(Vec<u8>);
+
+const VARINT: u32 = 0;
+const FIXED64: u32 = 1;
+const LENGTH_DELIMITED: u32 = 2;
+
+impl Message {
+    fn tag(&mut self, field: u32, wire_type: u32) {
+        self.varint(u64::from((field << 3) | wire_type));
+    }
+
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.0.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        self.0.push(value as u8);
+    }
+
+    fn uint(&mut self, field: u32, value: u64) {
+        self.tag(field, VARINT);
+        self.varint(value);
+    }
+
+    fn fixed64(&mut self, field: u32, value: u64) {
+        self.tag(field, FIXED64);
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn double(&mut self, field: u32, value: f64) {
+        self.fixed64(field, value.to_bits());
+    }
+
+    fn bytes(&mut self, field: u32, value: &[u8]) {
+        self.tag(field, LENGTH_DELIMITED);
+        self.varint(value.len() as u64);
+        self.0.extend_from_slice(value);
+    }
+
+    fn string(&mut self, field: u32, value: &str) {
+        self.bytes(field, value.as_bytes());
+    }
+
+    fn message(&mut self, field: u32, message: Message) {
+        self.bytes(field, &message.0);
+    }
+}
+
+// `AnyValue { string_value = 1 }`
+fn string_value(value: &str) -> Message {
+    let mut any = Message::default();
+    any.string(1, value);
+    any
+}
+
+// `KeyValue { key = 1, value = 2 }`
+fn key_value(key: &str, value: &str) -> Message {
+    let mut kv = Message::default();
+    kv.string(1, key);
+    kv.message(2, string_value(value));
+    kv
+}
+
+// `Resource { attributes = 1 }`
+fn resource(attributes: &BTreeMap<String, String>) -> Message {
+    let mut resource = Message::default();
+    for (key, value) in attributes {
+        resource.message(1, key_value(key, value));
+    }
+    resource
+}
+
+// `InstrumentationScope { name = 1, version = 2 }`
+fn scope() -> Message {
+    let mut scope = Message::default();
+    scope.string(1, SCOPE_NAME);
+    scope.string(2, env!("CARGO_PKG_VERSION"));
+    scope
+}
+
+// `SeverityNumber.SEVERITY_NUMBER_INFO`
+const SEVERITY_INFO: u64 = 9;
+
+// `ExportLogsServiceRequest { resource_logs = 1 }` of
+// `ResourceLogs { resource = 1, scope_logs = 2 }` of
+// `ScopeLogs { scope = 1, log_records = 2 }`.
+fn encode_logs(attributes: &BTreeMap<String, String>, records: &[Record]) -> Vec<u8> {
+    let mut scope_logs = Message::default();
+    scope_logs.message(1, scope());
+
+    for record in records {
+        let Record::Log {
+            time,
+            name,
+            attributes,
+        } = record
+        else {
+            continue;
+        };
+
+        // `LogRecord { time_unix_nano = 1, severity_number = 2,
+        // severity_text = 3, body = 5, attributes = 6,
+        // observed_time_unix_nano = 11 }`
+        let mut log = Message::default();
+        log.fixed64(1, *time);
+        log.uint(2, SEVERITY_INFO);
+        log.string(3, "INFO");
+        log.message(5, string_value(name));
+        for (key, value) in attributes {
+            log.message(6, key_value(key, value));
+        }
+        log.fixed64(11, *time);
+
+        scope_logs.message(2, log);
+    }
+
+    let mut resource_logs = Message::default();
+    resource_logs.message(1, resource(attributes));
+    resource_logs.message(2, scope_logs);
+
+    let mut request = Message::default();
+    request.message(1, resource_logs);
+    request.0
+}
+
+// `ExportMetricsServiceRequest { resource_metrics = 1 }` of
+// `ResourceMetrics { resource = 1, scope_metrics = 2 }` of
+// `ScopeMetrics { scope = 1, metrics = 2 }`.
+fn encode_metrics(attributes: &BTreeMap<String, String>, records: &[Record]) -> Vec<u8> {
+    let mut scope_metrics = Message::default();
+    scope_metrics.message(1, scope());
+
+    for record in records {
+        let Record::Metric {
+            time,
+            name,
+            value,
+            attributes,
+        } = record
+        else {
+            continue;
+        };
+
+        // `NumberDataPoint { time_unix_nano = 3, as_double = 4,
+        // attributes = 7 }`
+        let mut point = Message::default();
+        point.fixed64(3, *time);
+        point.double(4, *value);
+        for (key, value) in attributes {
+            point.message(7, key_value(key, value));
+        }
+
+        // `Gauge { data_points = 1 }`
+        let mut gauge = Message::default();
+        gauge.message(1, point);
+
+        // `Metric { name = 1, gauge = 5 }`
+        let mut metric = Message::default();
+        metric.string(1, name);
+        metric.message(5, gauge);
+
+        scope_metrics.message(2, metric);
+    }
+
+    let mut resource_metrics = Message::default();
+    resource_metrics.message(1, resource(attributes));
+    resource_metrics.message(2, scope_metrics);
+
+    let mut request = Message::default();
+    request.message(1, resource_metrics);
+    request.0
+}
+
+#[cfg(test)]
+mod tests;
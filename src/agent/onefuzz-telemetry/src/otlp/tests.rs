@@ -0,0 +1,365 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use std::convert::Infallible;
+use std::time::Instant;
+
+use hyper::service::{make_service_fn, service_fn};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use uuid::Uuid;
+
+use super::*;
+use crate::Role;
+
+// A request received by the mock collector.
+#[derive(Debug)]
+struct Request {
+    path: String,
+    content_type: String,
+    body: Vec<u8>,
+}
+
+// Receive OTLP/HTTP requests, answering each with an empty success.
+async fn mock_collector() -> Result<(String, mpsc::UnboundedReceiver<Request>)> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let endpoint = format!("http://{}", listener.local_addr()?);
+    let (sender, receiver) = mpsc::unbounded_channel();
+
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let sender = sender.clone();
+            tokio::spawn(async move {
+                let mut stream = BufReader::new(stream);
+                while let Ok(Some(request)) = read_request(&mut stream).await {
+                    let _ = sender.send(request);
+                    let response = "HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n";
+                    if stream
+                        .get_mut()
+                        .write_all(response.as_bytes())
+                        .await
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+
+    Ok((endpoint, receiver))
+}
+
+async fn read_request(stream: &mut BufReader<tokio::net::TcpStream>) -> Result<Option<Request>> {
+    let mut request_line = String::new();
+    if stream.read_line(&mut request_line).await? == 0 {
+        return Ok(None);
+    }
+    let path = request_line
+        .split_whitespace()
+        .nth(1)
+        .unwrap_or_default()
+        .to_owned();
+
+    let mut content_length = 0;
+    let mut content_type = String::new();
+    loop {
+        let mut line = String::new();
+        stream.read_line(&mut line).await?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = line.split_once(':') {
+            match name.to_ascii_lowercase().as_str() {
+                "content-length" => content_length = value.trim().parse()?,
+                "content-type" => content_type = value.trim().to_owned(),
+                _ => {}
+            }
+        }
+    }
+
+    let mut body = vec![0; content_length];
+    stream.read_exact(&mut body).await?;
+
+    Ok(Some(Request {
+        path,
+        content_type,
+        body,
+    }))
+}
+
+// A minimal protobuf decoder, of the fields of a message.
+#[derive(Debug)]
+enum Field {
+    Varint(u64),
+    Fixed64(u64),
+    Bytes(Vec<u8>),
+}
+
+fn decode(mut data: &[u8]) -> Vec<(u32, Field)> {
+    fn varint(data: &mut &[u8]) -> u64 {
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let byte = data[0];
+            *data = &data[1..];
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return value;
+            }
+            shift += 7;
+        }
+    }
+
+    let mut fields = vec![];
+    while !data.is_empty() {
+        let tag = varint(&mut data);
+        let field = match tag & 7 {
+            0 => Field::Varint(varint(&mut data)),
+            1 => {
+                let (value, rest) = data.split_at(8);
+                data = rest;
+                Field::Fixed64(u64::from_le_bytes(value.try_into().unwrap()))
+            }
+            2 => {
+                let len = varint(&mut data) as usize;
+                let (value, rest) = data.split_at(len);
+                data = rest;
+                Field::Bytes(value.to_vec())
+            }
+            wire_type => panic!("unexpected wire type: {wire_type}"),
+        };
+        fields.push(((tag >> 3) as u32, field));
+    }
+
+    fields
+}
+
+fn messages(data: &[u8], number: u32) -> Vec<Vec<u8>> {
+    decode(data)
+        .into_iter()
+        .filter_map(|(n, field)| match field {
+            Field::Bytes(bytes) if n == number => Some(bytes),
+            _ => None,
+        })
+        .collect()
+}
+
+fn message(data: &[u8], number: u32) -> Vec<u8> {
+    let mut messages = messages(data, number);
+    assert_eq!(messages.len(), 1, "field {number}");
+    messages.remove(0)
+}
+
+fn string(data: &[u8], number: u32) -> String {
+    String::from_utf8(message(data, number)).unwrap()
+}
+
+fn fixed64(data: &[u8], number: u32) -> u64 {
+    decode(data)
+        .into_iter()
+        .find_map(|(n, field)| match field {
+            Field::Fixed64(value) if n == number => Some(value),
+            _ => None,
+        })
+        .unwrap_or_else(|| panic!("missing field {number}"))
+}
+
+// The `KeyValue` attributes of field `number`, of string values.
+fn attributes(data: &[u8], number: u32) -> BTreeMap<String, String> {
+    messages(data, number)
+        .into_iter()
+        .map(|kv| (string(&kv, 1), string(&message(&kv, 2), 1)))
+        .collect()
+}
+
+#[tokio::test]
+async fn test_export_to_collector() -> Result<()> {
+    let (endpoint, mut requests) = mock_collector().await?;
+    let exporter = OtlpExporter::new(OtlpConfig {
+        endpoint,
+        protocol: OtlpProtocol::HttpProtobuf,
+    })?;
+
+    let task_id = Uuid::new_v4();
+    exporter.set_property(&EventData::Role(Role::Agent));
+    exporter.set_property(&EventData::TaskId(task_id));
+    exporter.event(
+        &Event::new_report,
+        &[EventData::Path("crash-1.json".into())],
+    );
+    exporter.metric(
+        &Event::runtime_stats,
+        2.5,
+        &[EventData::ExecsSecond(2.5), EventData::Count(10)],
+    );
+    exporter.flush_and_close(Duration::from_secs(30)).await;
+
+    let mut received = BTreeMap::new();
+    while let Ok(request) = requests.try_recv() {
+        assert_eq!(request.content_type, "application/x-protobuf");
+        received.insert(request.path.clone(), request.body);
+    }
+
+    let resource_attributes = BTreeMap::from([
+        ("role".to_owned(), "agent".to_owned()),
+        ("service.name".to_owned(), "onefuzz".to_owned()),
+        ("task_id".to_owned(), task_id.to_string()),
+    ]);
+
+    // `ExportLogsServiceRequest`
+    let logs = &received["/v1/logs"];
+    let resource_logs = message(logs, 1);
+    assert_eq!(
+        attributes(&message(&resource_logs, 1), 1),
+        resource_attributes
+    );
+    let scope_logs = message(&resource_logs, 2);
+    assert_eq!(string(&message(&scope_logs, 1), 1), "onefuzz");
+    let log = message(&scope_logs, 2);
+    assert_eq!(string(&message(&log, 5), 1), "new_report");
+    assert_eq!(
+        attributes(&log, 6),
+        BTreeMap::from([("path".to_owned(), "crash-1.json".to_owned())])
+    );
+    assert!(fixed64(&log, 1) > 0);
+
+    // `ExportMetricsServiceRequest`
+    let metrics = &received["/v1/metrics"];
+    let resource_metrics = message(metrics, 1);
+    assert_eq!(
+        attributes(&message(&resource_metrics, 1), 1),
+        resource_attributes
+    );
+    let metric = message(&message(&resource_metrics, 2), 2);
+    assert_eq!(string(&metric, 1), "runtime_stats");
+    let point = message(&message(&metric, 5), 1);
+    assert_eq!(f64::from_bits(fixed64(&point, 4)), 2.5);
+    assert_eq!(
+        attributes(&point, 7),
+        BTreeMap::from([
+            ("count".to_owned(), "10".to_owned()),
+            ("execs_sec".to_owned(), "2.5".to_owned()),
+        ])
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_unreachable_collector() -> Result<()> {
+    // A port with no listener.
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let endpoint = format!("http://{}", listener.local_addr()?);
+    drop(listener);
+
+    let exporter = OtlpExporter::new(OtlpConfig {
+        endpoint,
+        protocol: OtlpProtocol::HttpProtobuf,
+    })?;
+
+    // Records beyond the queue are dropped, without blocking.
+    let start = Instant::now();
+    for _ in 0..QUEUE_SIZE * 2 {
+        exporter.event(&Event::new_report, &[]);
+    }
+    assert!(start.elapsed() < Duration::from_secs(5));
+
+    exporter.flush_and_close(Duration::from_secs(30)).await;
+
+    Ok(())
+}
+
+// Answer OTLP/gRPC calls with an empty response message, and `status` in the
+// trailers.
+async fn mock_grpc_collector(status: &'static str) -> Result<String> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let endpoint = format!("http://{}", listener.local_addr()?);
+
+    let service = make_service_fn(move |_| async move {
+        Ok::<_, Infallible>(service_fn(
+            move |request: hyper::Request<hyper::Body>| async move {
+                hyper::body::to_bytes(request.into_body()).await?;
+
+                let (mut sender, body) = hyper::Body::channel();
+                tokio::spawn(async move {
+                    let mut trailers = HeaderMap::new();
+                    trailers.insert("grpc-status", status.parse().unwrap());
+                    trailers.insert("grpc-message", "collector unavailable".parse().unwrap());
+                    sender.send_data(grpc_frame(b"").into()).await?;
+                    sender.send_trailers(trailers).await
+                });
+
+                let response = hyper::Response::builder()
+                    .header("content-type", "application/grpc")
+                    .body(body)?;
+                Ok::<_, anyhow::Error>(response)
+            },
+        ))
+    });
+    let server = hyper::Server::from_tcp(listener)?
+        .http2_only(true)
+        .serve(service);
+    tokio::spawn(server);
+
+    Ok(endpoint)
+}
+
+fn grpc_sink(endpoint: String) -> Result<Sink> {
+    Ok(Sink {
+        config: OtlpConfig {
+            endpoint,
+            protocol: OtlpProtocol::Grpc,
+        },
+        client: Client::new(OtlpProtocol::Grpc)?,
+        resource: Default::default(),
+        failing: false,
+    })
+}
+
+#[tokio::test]
+async fn test_grpc_status_in_trailers() -> Result<()> {
+    let sink = grpc_sink(mock_grpc_collector("0").await?)?;
+    sink.post(Signal::Logs, vec![]).await?;
+
+    let sink = grpc_sink(mock_grpc_collector("14").await?)?;
+    let err = sink.post(Signal::Logs, vec![]).await.unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "OTLP export failed with gRPC status 14: collector unavailable"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_config() -> Result<()> {
+    let config = OtlpConfig {
+        endpoint: "http://collector:4317/".into(),
+        protocol: "grpc".parse()?,
+    };
+    assert_eq!(
+        config.url(Signal::Logs),
+        "http://collector:4317/opentelemetry.proto.collector.logs.v1.LogsService/Export"
+    );
+
+    let config = OtlpConfig {
+        protocol: "http/protobuf".parse()?,
+        ..config
+    };
+    assert_eq!(
+        config.url(Signal::Metrics),
+        "http://collector:4317/v1/metrics"
+    );
+
+    assert!("http/json".parse::<OtlpProtocol>().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_grpc_frame() {
+    assert_eq!(grpc_frame(b"\x0a\x00"), b"\x00\x00\x00\x00\x02\x0a\x00");
+}
@@ -0,0 +1,194 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Sampling and rate limiting of verbose events and metrics, by name, before
+//! they're exported.
+//!
+//! Records dropped are counted, and summarized periodically by a
+//! `telemetry_dropped` event for each name, so that the loss is visible.
+//! Critical events, such as those of crashes and errors, are never dropped.
+
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::Event;
+
+/// The sampling rules of events, as JSON, such as
+/// `{"coverage_data": {"max_per_minute": 60, "sample_ratio": 0.1}}`.
+pub const SAMPLING_ENV: &str = "ONEFUZZ_TELEMETRY_SAMPLING";
+
+const RATE_LIMIT_PERIOD: Duration = Duration::from_secs(60);
+
+// Drops are summarized at most once per period.
+pub(crate) const SUMMARY_PERIOD: Duration = Duration::from_secs(60);
+
+/// Limits on the records, events and metrics, of a name.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SamplingRule {
+    /// The most records exported per minute, after sampling.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_per_minute: Option<u64>,
+
+    /// The fraction of records exported, from 0 to 1.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sample_ratio: Option<f64>,
+}
+
+/// The sampling rules of each event name. Events without a rule, and critical
+/// events, are always exported.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(transparent)]
+pub struct SamplingConfig {
+    pub rules: BTreeMap<String, SamplingRule>,
+}
+
+impl SamplingConfig {
+    /// The config set by `ONEFUZZ_TELEMETRY_SAMPLING`, if any.
+    pub fn from_env() -> Result<Option<Self>> {
+        match std::env::var(SAMPLING_ENV) {
+            Ok(rules) if !rules.is_empty() => Ok(Some(rules.parse()?)),
+            _ => Ok(None),
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        for (name, rule) in &self.rules {
+            if let Some(ratio) = rule.sample_ratio {
+                if !(0.0..=1.0).contains(&ratio) {
+                    bail!("sample ratio of {} must be from 0 to 1: {}", name, ratio);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for SamplingConfig {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let config: Self = serde_json::from_str(s).context("invalid telemetry sampling rules")?;
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+/// Whether `event` is exported regardless of sampling.
+pub fn is_exempt(event: &Event) -> bool {
+    matches!(
+        event,
+        Event::task_start
+            | Event::coverage_failed
+            | Event::new_crashdump
+            | Event::new_oom
+            | Event::new_leak
+            | Event::new_report
+            | Event::new_unique_report
+            | Event::new_unable_to_reproduce
+            | Event::regression_report
+            | Event::regression_error
+            | Event::generator_crash
+            | Event::telemetry_dropped
+            | Event::heartbeat_undelivered
+    )
+}
+
+// The records seen of a name.
+#[derive(Debug)]
+struct Counts {
+    seen: u64,
+    sampled: u64,
+    window_start: Instant,
+    in_window: u64,
+}
+
+pub(crate) struct Sampler {
+    config: SamplingConfig,
+    counts: HashMap<&'static str, Counts>,
+    dropped: BTreeMap<&'static str, u64>,
+    last_summary: Instant,
+}
+
+impl Sampler {
+    pub fn new(config: SamplingConfig, now: Instant) -> Self {
+        Self {
+            config,
+            counts: HashMap::new(),
+            dropped: BTreeMap::new(),
+            last_summary: now,
+        }
+    }
+
+    /// Whether a record of `event`, at `now`, is exported.
+    pub fn sample(&mut self, event: &Event, now: Instant) -> bool {
+        if is_exempt(event) {
+            return true;
+        }
+
+        let name = event.as_str();
+        let Some(rule) = self.config.rules.get(name) else {
+            return true;
+        };
+
+        let counts = self.counts.entry(name).or_insert(Counts {
+            seen: 0,
+            sampled: 0,
+            window_start: now,
+            in_window: 0,
+        });
+
+        // Sampled evenly, starting with the first record.
+        counts.seen += 1;
+        let mut pass = match rule.sample_ratio {
+            Some(ratio) => (counts.sampled as f64) < ratio * counts.seen as f64,
+            None => true,
+        };
+        if pass {
+            counts.sampled += 1;
+        }
+
+        if let Some(max_per_minute) = rule.max_per_minute {
+            if now.saturating_duration_since(counts.window_start) >= RATE_LIMIT_PERIOD {
+                counts.window_start = now;
+                counts.in_window = 0;
+            }
+
+            if pass {
+                pass = counts.in_window < max_per_minute;
+                if pass {
+                    counts.in_window += 1;
+                }
+            }
+        }
+
+        if !pass {
+            *self.dropped.entry(name).or_default() += 1;
+        }
+
+        pass
+    }
+
+    /// The records dropped of each name since the last summary, if a summary
+    /// is due at `now`.
+    pub fn summary(&mut self, now: Instant) -> Vec<(&'static str, u64)> {
+        if now.saturating_duration_since(self.last_summary) < SUMMARY_PERIOD {
+            return vec![];
+        }
+
+        self.last_summary = now;
+        self.take_dropped()
+    }
+
+    /// The records dropped of each name since the last summary.
+    pub fn take_dropped(&mut self) -> Vec<(&'static str, u64)> {
+        std::mem::take(&mut self.dropped).into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests;
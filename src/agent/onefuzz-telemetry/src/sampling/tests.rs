@@ -0,0 +1,159 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use super::*;
+
+fn sampler(rules: &str, start: Instant) -> Result<Sampler> {
+    Ok(Sampler::new(rules.parse()?, start))
+}
+
+// Fire `count` records of `event`, one every `interval` from `start`, and
+// count those which pass.
+fn burst(
+    sampler: &mut Sampler,
+    event: &Event,
+    count: u32,
+    start: Instant,
+    interval: Duration,
+) -> u32 {
+    (0..count)
+        .filter(|i| sampler.sample(event, start + interval * *i))
+        .count() as u32
+}
+
+#[test]
+fn test_rate_limit() -> Result<()> {
+    let start = Instant::now();
+    let mut sampler = sampler(r#"{"coverage_data": {"max_per_minute": 10}}"#, start)?;
+
+    // A burst of 100 within a minute.
+    let passed = burst(
+        &mut sampler,
+        &Event::coverage_data,
+        100,
+        start,
+        Duration::from_millis(100),
+    );
+    assert_eq!(passed, 10);
+
+    // Not yet due.
+    assert!(sampler.summary(start + Duration::from_secs(30)).is_empty());
+
+    // The limit resets each minute.
+    let next = start + Duration::from_secs(61);
+    let passed = burst(
+        &mut sampler,
+        &Event::coverage_data,
+        20,
+        next,
+        Duration::from_millis(10),
+    );
+    assert_eq!(passed, 10);
+
+    assert_eq!(
+        sampler.summary(next + Duration::from_secs(1)),
+        [("coverage_data", 100)]
+    );
+
+    // Summarized only once.
+    assert!(sampler.summary(next + Duration::from_secs(2)).is_empty());
+    assert!(sampler.take_dropped().is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_sample_ratio() -> Result<()> {
+    let start = Instant::now();
+    let mut sampler = sampler(r#"{"coverage_data": {"sample_ratio": 0.1}}"#, start)?;
+
+    // The first of each 10 passes.
+    let passed: Vec<_> = (0..30)
+        .filter(|_| sampler.sample(&Event::coverage_data, start))
+        .collect();
+    assert_eq!(passed, [0, 10, 20]);
+
+    assert_eq!(sampler.take_dropped(), [("coverage_data", 27)]);
+
+    Ok(())
+}
+
+#[test]
+fn test_sample_ratio_and_rate_limit() -> Result<()> {
+    let start = Instant::now();
+    let mut sampler = sampler(
+        r#"{"runtime_stats": {"sample_ratio": 0.5, "max_per_minute": 5}}"#,
+        start,
+    )?;
+
+    // Of 100, 50 are sampled, of which 5 are within the limit.
+    let passed = burst(
+        &mut sampler,
+        &Event::runtime_stats,
+        100,
+        start,
+        Duration::from_millis(100),
+    );
+    assert_eq!(passed, 5);
+    assert_eq!(sampler.take_dropped(), [("runtime_stats", 95)]);
+
+    Ok(())
+}
+
+#[test]
+fn test_unlimited_events() -> Result<()> {
+    let start = Instant::now();
+    let mut sampler = sampler(
+        r#"{"coverage_data": {"max_per_minute": 0}, "new_report": {"max_per_minute": 0}}"#,
+        start,
+    )?;
+
+    // Without a rule.
+    let passed = burst(
+        &mut sampler,
+        &Event::runtime_stats,
+        100,
+        start,
+        Duration::ZERO,
+    );
+    assert_eq!(passed, 100);
+
+    // Critical, despite a rule.
+    let passed = burst(&mut sampler, &Event::new_report, 100, start, Duration::ZERO);
+    assert_eq!(passed, 100);
+
+    let passed = burst(
+        &mut sampler,
+        &Event::coverage_data,
+        100,
+        start,
+        Duration::ZERO,
+    );
+    assert_eq!(passed, 0);
+
+    assert_eq!(
+        sampler.summary(start + SUMMARY_PERIOD),
+        [("coverage_data", 100)]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_config() -> Result<()> {
+    let config: SamplingConfig =
+        r#"{"coverage_data": {"max_per_minute": 60, "sample_ratio": 0.25}}"#.parse()?;
+    assert_eq!(
+        config.rules["coverage_data"],
+        SamplingRule {
+            max_per_minute: Some(60),
+            sample_ratio: Some(0.25),
+        }
+    );
+
+    assert!(r#"{"coverage_data": {"sample_ratio": 2}}"#.parse::<SamplingConfig>().is_err());
+    assert!(r#"{"coverage_data": {"max_per_second": 1}}"#.parse::<SamplingConfig>().is_err());
+    assert!("[]".parse::<SamplingConfig>().is_err());
+
+    Ok(())
+}
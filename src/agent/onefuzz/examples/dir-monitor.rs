@@ -9,13 +9,16 @@ use onefuzz::monitor::DirectoryMonitor;
 struct Opt {
     #[arg(short, long)]
     path: String,
+
+    #[arg(short, long)]
+    recursive: bool,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let opt = Opt::parse();
 
-    let mut monitor = DirectoryMonitor::new(opt.path).await?;
+    let mut monitor = DirectoryMonitor::new(opt.path, opt.recursive).await?;
     monitor.set_report_directories(true);
 
     while let Some(created) = monitor.next_file().await? {
@@ -2,13 +2,30 @@
 // Licensed under the MIT License.
 
 use std::fmt;
+use std::time::{Duration, SystemTime};
 
+use crate::cloud::CloudEnvironment;
 use crate::http::ResponseExt;
 use anyhow::{Context, Result};
-use reqwest_retry::SendRetry;
+use reqwest::StatusCode;
+use reqwest_retry::{RetryCheck, RetryPolicy, SendRetry};
 use url::Url;
 use uuid::Uuid;
 
+mod certificate;
+mod token_cache;
+mod workload_identity;
+pub use certificate::*;
+pub use token_cache::*;
+pub use workload_identity::*;
+
+// Access tokens are refreshed this long before they expire, so that requests
+// made with them don't fail in flight.
+const REFRESH_MARGIN: Duration = Duration::from_secs(5 * 60);
+
+// The type of the client assertions of certificates and federated tokens.
+const CLIENT_ASSERTION_TYPE: &str = "urn:ietf:params:oauth:client-assertion-type:jwt-bearer";
+
 #[derive(Clone, Deserialize, Eq, PartialEq, Serialize)]
 pub struct Secret<T>(T);
 
@@ -47,18 +64,41 @@ impl<T> fmt::Display for Secret<T> {
 #[derive(Clone, Deserialize, Eq, PartialEq)]
 pub struct AccessToken {
     secret: Secret<String>,
+
+    #[serde(default)]
+    expires_at: Option<SystemTime>,
 }
 
 impl AccessToken {
     pub fn secret(&self) -> &Secret<String> {
         &self.secret
     }
+
+    /// When the token expires, if the token endpoint said.
+    pub fn expires_at(&self) -> Option<SystemTime> {
+        self.expires_at
+    }
+
+    /// Whether the token expires within 5 minutes, and should be refreshed
+    /// before it's used. Tokens without a known expiry are used until they're
+    /// rejected.
+    pub fn expires_soon(&self) -> bool {
+        self.expires_within(REFRESH_MARGIN, SystemTime::now())
+    }
+
+    fn expires_within(&self, margin: Duration, now: SystemTime) -> bool {
+        match self.expires_at {
+            Some(expires_at) => expires_at <= now + margin,
+            None => false,
+        }
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
 pub enum Credentials {
     Client(ClientCredentials),
     ManagedIdentity(ManagedIdentityCredentials),
+    WorkloadIdentity(WorkloadIdentityCredentials),
 }
 
 impl Credentials {
@@ -66,7 +106,70 @@ impl Credentials {
         match self {
             Credentials::Client(credentials) => credentials.access_token().await,
             Credentials::ManagedIdentity(credentials) => credentials.access_token().await,
+            Credentials::WorkloadIdentity(credentials) => credentials.access_token().await,
+        }
+    }
+
+    /// An access token from `cache`, if it has one for these credentials which
+    /// doesn't expire soon. Otherwise, one from the token endpoint, which is
+    /// then saved to `cache`.
+    pub async fn access_token_with_cache(
+        &self,
+        cache: &PersistentTokenCache,
+    ) -> Result<AccessToken> {
+        let key = self.cache_key();
+
+        if let Some(token) = cache.load(&key).await {
+            return Ok(token);
+        }
+
+        self.fetch_and_save(cache, &key).await
+    }
+
+    /// An access token from the token endpoint, such as after the cached one
+    /// was rejected, which then replaces the one in `cache`.
+    pub async fn refresh_access_token_with_cache(
+        &self,
+        cache: &PersistentTokenCache,
+    ) -> Result<AccessToken> {
+        let key = self.cache_key();
+        self.fetch_and_save(cache, &key).await
+    }
+
+    async fn fetch_and_save(&self, cache: &PersistentTokenCache, key: &str) -> Result<AccessToken> {
+        let token = self.access_token().await?;
+
+        // The token is still usable, even if it can't be saved for the next run.
+        if let Err(err) = cache.save(key, &token).await {
+            warn!("unable to save access token to cache: {:?}", err);
         }
+
+        Ok(token)
+    }
+
+    // Identifies the tokens of these credentials, without their secrets, so
+    // that a cached token isn't used once the credentials are reconfigured.
+    fn cache_key(&self) -> String {
+        let identity = match self {
+            Credentials::Client(credentials) => format!(
+                "client:{}:{}:{}:{}:{}",
+                credentials.cloud.authority_host,
+                credentials.tenant,
+                credentials.client_id,
+                credentials.resource,
+                credentials
+                    .multi_tenant_domain
+                    .as_deref()
+                    .unwrap_or_default(),
+            ),
+            Credentials::ManagedIdentity(credentials) => match &credentials.identity {
+                Some(identity) => format!("managed_identity:{}:{}", credentials.resource, identity),
+                None => format!("managed_identity:{}", credentials.resource),
+            },
+            Credentials::WorkloadIdentity(credentials) => credentials.cache_identity(),
+        };
+
+        crate::sha256::digest(identity)
     }
 }
 
@@ -82,13 +185,29 @@ impl From<ManagedIdentityCredentials> for Credentials {
     }
 }
 
+impl From<WorkloadIdentityCredentials> for Credentials {
+    fn from(credentials: WorkloadIdentityCredentials) -> Self {
+        Credentials::WorkloadIdentity(credentials)
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
 pub struct ClientCredentials {
     client_id: Uuid,
-    client_secret: Secret<String>,
+    credential: ClientCredential,
     resource: String,
     tenant: String,
     multi_tenant_domain: Option<String>,
+
+    #[serde(default)]
+    cloud: CloudEnvironment,
+}
+
+// How the client authenticates.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+enum ClientCredential {
+    Secret(Secret<String>),
+    Certificate(ClientCertificate),
 }
 
 impl ClientCredentials {
@@ -99,50 +218,74 @@ impl ClientCredentials {
         tenant: String,
         multi_tenant_domain: Option<String>,
     ) -> Self {
-        let client_secret = client_secret.into();
+        let credential = ClientCredential::Secret(client_secret.into());
 
         Self {
             client_id,
-            client_secret,
+            credential,
             resource,
             tenant,
             multi_tenant_domain,
+            cloud: CloudEnvironment::default(),
         }
     }
 
+    pub fn with_certificate(
+        client_id: Uuid,
+        certificate: ClientCertificate,
+        resource: String,
+        tenant: String,
+        multi_tenant_domain: Option<String>,
+    ) -> Self {
+        let credential = ClientCredential::Certificate(certificate);
+
+        Self {
+            client_id,
+            credential,
+            resource,
+            tenant,
+            multi_tenant_domain,
+            cloud: CloudEnvironment::default(),
+        }
+    }
+
+    /// Request tokens from the authority of `cloud`, rather than of the
+    /// public cloud.
+    pub fn with_cloud(mut self, cloud: CloudEnvironment) -> Self {
+        self.cloud = cloud;
+        self
+    }
+
     pub async fn access_token(&self) -> Result<AccessToken> {
-        let (authority, scope) = {
-            let url = Url::parse(&self.resource.clone())?;
-            let port = url.port().map(|p| format!(":{p}")).unwrap_or_default();
-            let host_name = url.host_str().ok_or_else(|| {
-                anyhow::format_err!("resource URL does not have a host string: {}", url)
-            })?;
-            let host = format!("{host_name}{port}");
-            if let Some(domain) = &self.multi_tenant_domain {
-                let instance: Vec<&str> = host.split('.').collect();
-                (
-                    String::from("common"),
-                    format!("api://{}/{}/", &domain, instance[0]),
-                )
-            } else {
-                (self.tenant.clone(), format!("api://{host}/"))
-            }
+        let scope = token_scope(&self.resource, self.multi_tenant_domain.as_deref())?;
+        let authority = if self.multi_tenant_domain.is_some() {
+            String::from("common")
+        } else {
+            self.tenant.clone()
         };
 
-        let mut url = Url::parse("https://login.microsoftonline.com")?;
-        url.path_segments_mut()
-            .expect("Authority URL is cannot-be-a-base")
-            .extend(&[&authority.clone(), "oauth2", "v2.0", "token"]);
+        let url = self.cloud.token_url(&authority)?;
+
+        let mut form = vec![
+            ("client_id", self.client_id.hyphenated().to_string()),
+            ("grant_type", "client_credentials".into()),
+            ("tenant", authority),
+            ("scope", format!("{scope}.default")),
+        ];
+        match &self.credential {
+            ClientCredential::Secret(secret) => {
+                form.push(("client_secret", secret.expose_ref().to_string()));
+            }
+            ClientCredential::Certificate(certificate) => {
+                let assertion = certificate.assertion(self.client_id, &url)?;
+                form.push(("client_assertion_type", CLIENT_ASSERTION_TYPE.into()));
+                form.push(("client_assertion", assertion.expose()));
+            }
+        }
 
-        let response = reqwest::Client::new()
+        let response = reqwest_retry::proxy::client()
             .post(url)
-            .form(&[
-                ("client_id", self.client_id.hyphenated().to_string()),
-                ("client_secret", self.client_secret.expose_ref().to_string()),
-                ("grant_type", "client_credentials".into()),
-                ("tenant", authority),
-                ("scope", format!("{scope}.default")),
-            ])
+            .form(&form)
             .send_retry_default()
             .await
             .context("access_token request")?
@@ -156,18 +299,61 @@ impl ClientCredentials {
     }
 }
 
+// The scope of access tokens for `resource`, without the trailing `.default`.
+fn token_scope(resource: &str, multi_tenant_domain: Option<&str>) -> Result<String> {
+    let url = Url::parse(resource)?;
+    let port = url.port().map(|p| format!(":{p}")).unwrap_or_default();
+    let host_name = url
+        .host_str()
+        .ok_or_else(|| anyhow::format_err!("resource URL does not have a host string: {}", url))?;
+    let host = format!("{host_name}{port}");
+
+    if let Some(domain) = multi_tenant_domain {
+        let instance: Vec<&str> = host.split('.').collect();
+        Ok(format!("api://{}/{}/", domain, instance[0]))
+    } else {
+        Ok(format!("api://{host}/"))
+    }
+}
+
 // See: https://docs.microsoft.com/en-us/azure/active-directory/develop
 //      /v2-oauth2-client-creds-grant-flow#successful-response-1
 #[derive(Clone, Debug, Deserialize)]
 struct ClientAccessTokenBody {
     // Bearer token for authenticating HTTP requests.
     access_token: Secret<String>,
+
+    // Seconds until the access token expires.
+    expires_in: Option<ExpiresIn>,
 }
 
 impl From<ClientAccessTokenBody> for AccessToken {
     fn from(body: ClientAccessTokenBody) -> Self {
         let secret = body.access_token;
-        AccessToken { secret }
+        let expires_at = body
+            .expires_in
+            .and_then(|expires_in| expires_in.seconds().ok())
+            .map(|seconds| SystemTime::now() + Duration::from_secs(seconds));
+        AccessToken { secret, expires_at }
+    }
+}
+
+// Some token endpoints return numbers of seconds as strings.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+enum ExpiresIn {
+    Number(u64),
+    String(String),
+}
+
+impl ExpiresIn {
+    fn seconds(&self) -> Result<u64> {
+        match self {
+            Self::Number(seconds) => Ok(*seconds),
+            Self::String(seconds) => seconds
+                .parse()
+                .with_context(|| format!("invalid expiry: {seconds:?}")),
+        }
     }
 }
 
@@ -175,11 +361,75 @@ impl From<ClientAccessTokenBody> for AccessToken {
 pub struct ManagedIdentityCredentials {
     resource: String,
     multi_tenant_domain: Option<String>,
+
+    #[serde(default)]
+    identity: Option<UserAssignedIdentity>,
+
+    #[serde(skip, default = "managed_identity_url")]
+    endpoint: Url,
 }
 
 const MANAGED_IDENTITY_URL: &str =
     "http://169.254.169.254/metadata/identity/oauth2/token?api-version=2018-02-01";
 
+fn managed_identity_url() -> Url {
+    Url::parse(MANAGED_IDENTITY_URL).unwrap()
+}
+
+/// A user-assigned managed identity of the node, by one of its IDs. Without
+/// one, IMDS chooses the system-assigned identity, or the only user-assigned
+/// one, if there's just one.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UserAssignedIdentity {
+    ClientId(Uuid),
+    ObjectId(Uuid),
+
+    /// The Azure resource ID of the identity.
+    MsiResId(String),
+}
+
+impl UserAssignedIdentity {
+    // The IMDS query parameter which selects the identity.
+    fn query_pair(&self) -> (&'static str, String) {
+        match self {
+            Self::ClientId(id) => ("client_id", id.hyphenated().to_string()),
+            Self::ObjectId(id) => ("object_id", id.hyphenated().to_string()),
+            Self::MsiResId(id) => ("msi_res_id", id.clone()),
+        }
+    }
+}
+
+impl fmt::Display for UserAssignedIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (name, value) = self.query_pair();
+        write!(f, "{name}={value}")
+    }
+}
+
+/// Selects a user-assigned managed identity, by exactly one of its IDs.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ManagedIdentityConfig {
+    pub client_id: Option<Uuid>,
+    pub object_id: Option<Uuid>,
+    pub msi_res_id: Option<String>,
+}
+
+impl ManagedIdentityConfig {
+    pub fn identity(&self) -> Result<UserAssignedIdentity> {
+        match (self.client_id, self.object_id, &self.msi_res_id) {
+            (Some(id), None, None) => Ok(UserAssignedIdentity::ClientId(id)),
+            (None, Some(id), None) => Ok(UserAssignedIdentity::ObjectId(id)),
+            (None, None, Some(id)) => Ok(UserAssignedIdentity::MsiResId(id.clone())),
+            (None, None, None) => {
+                bail!("managed identity requires one of client_id, object_id or msi_res_id")
+            }
+            _ => bail!("managed identity must set only one of client_id, object_id or msi_res_id"),
+        }
+    }
+}
+
 impl ManagedIdentityCredentials {
     pub fn new(resource: String, multi_tenant_domain: Option<String>) -> Result<Self> {
         let resource = {
@@ -198,27 +448,76 @@ impl ManagedIdentityCredentials {
         Ok(Self {
             resource,
             multi_tenant_domain,
+            identity: None,
+            endpoint: managed_identity_url(),
         })
     }
 
+    /// Credentials of an Azure resource, such as Key Vault, whose resource ID
+    /// is used as it is, rather than as an application ID URI.
+    pub fn for_azure_resource(resource: impl Into<String>) -> Self {
+        Self {
+            resource: resource.into(),
+            multi_tenant_domain: None,
+            identity: None,
+            endpoint: managed_identity_url(),
+        }
+    }
+
+    /// Request tokens of `identity`, rather than the one chosen by IMDS.
+    pub fn with_identity(mut self, identity: Option<UserAssignedIdentity>) -> Self {
+        self.identity = identity;
+        self
+    }
+
+    pub fn identity(&self) -> Option<&UserAssignedIdentity> {
+        self.identity.as_ref()
+    }
+
     fn url(&self) -> Url {
-        let mut url = Url::parse(MANAGED_IDENTITY_URL).unwrap();
+        let mut url = self.endpoint.clone();
 
         url.query_pairs_mut()
             .append_pair("resource", &self.resource);
+        if let Some(identity) = &self.identity {
+            let (name, value) = identity.query_pair();
+            url.query_pairs_mut().append_pair(name, &value);
+        }
         url
     }
 
     pub async fn access_token(&self) -> Result<AccessToken> {
-        let response = reqwest::Client::new()
+        // IMDS rejects identities which aren't assigned to the VM with `400 Bad
+        // Request`, which retrying won't fix.
+        let policy = RetryPolicy::default().with_check_status(|status| {
+            if status == StatusCode::BAD_REQUEST {
+                RetryCheck::Fail
+            } else {
+                RetryCheck::Retry
+            }
+        });
+
+        let response = reqwest_retry::proxy::client()
             .get(self.url())
             .header("Metadata", "true")
-            .send_retry_default()
+            .send_retry_policy(&policy)
             .await
-            .context("ManagedIdentityCredentials.access_token")?
-            .error_for_status_with_body()
-            .await
-            .context("ManagedIdentityCredentials.access_token status body")?;
+            .context("ManagedIdentityCredentials.access_token")?;
+
+        let status = response.status();
+        let response = match response.error_for_status_with_body().await {
+            Ok(response) => response,
+            Err(err) => {
+                let context = match &self.identity {
+                    Some(identity) if status == StatusCode::BAD_REQUEST => format!(
+                        "IMDS rejected the managed identity {identity}, \
+                        which may not be assigned to this VM"
+                    ),
+                    _ => "ManagedIdentityCredentials.access_token status body".to_owned(),
+                };
+                return Err(err.context(context));
+            }
+        };
 
         let body: ManagedIdentityAccessTokenBody = response.json().await?;
 
@@ -238,12 +537,19 @@ impl ManagedIdentityCredentials {
 struct ManagedIdentityAccessTokenBody {
     access_token: Secret<String>,
     resource: String,
+
+    // When the access token expires, in seconds since the Unix epoch.
+    expires_on: Option<ExpiresIn>,
 }
 
 impl From<ManagedIdentityAccessTokenBody> for AccessToken {
     fn from(body: ManagedIdentityAccessTokenBody) -> Self {
         let secret = body.access_token;
-        AccessToken { secret }
+        let expires_at = body
+            .expires_on
+            .and_then(|expires_on| expires_on.seconds().ok())
+            .map(|seconds| SystemTime::UNIX_EPOCH + Duration::from_secs(seconds));
+        AccessToken { secret, expires_at }
     }
 }
 
@@ -293,4 +599,153 @@ mod tests {
 
         Ok(())
     }
+
+    const IDENTITY_ID: &str = "8f1a3c2e-5b7d-4e9f-a1b2-c3d4e5f6a7b8";
+    const IDENTITY_RES_ID: &str = "/subscriptions/sub/resourceGroups/rg/providers/Microsoft.ManagedIdentity/userAssignedIdentities/onefuzz";
+
+    fn query(credentials: &ManagedIdentityCredentials) -> Vec<(String, String)> {
+        credentials.url().query_pairs().into_owned().collect()
+    }
+
+    #[test]
+    fn test_managed_identity_query() -> Result<()> {
+        let id: Uuid = IDENTITY_ID.parse()?;
+        let credentials = ManagedIdentityCredentials::for_azure_resource("https://vault.azure.net");
+
+        let mut expected = vec![
+            ("api-version".to_owned(), "2018-02-01".to_owned()),
+            ("resource".to_owned(), "https://vault.azure.net".to_owned()),
+        ];
+        assert_eq!(query(&credentials), expected);
+
+        let selectors = [
+            (UserAssignedIdentity::ClientId(id), "client_id", IDENTITY_ID),
+            (UserAssignedIdentity::ObjectId(id), "object_id", IDENTITY_ID),
+            (
+                UserAssignedIdentity::MsiResId(IDENTITY_RES_ID.to_owned()),
+                "msi_res_id",
+                IDENTITY_RES_ID,
+            ),
+        ];
+        for (identity, name, value) in selectors {
+            let credentials = credentials.clone().with_identity(Some(identity));
+            expected.push((name.to_owned(), value.to_owned()));
+            assert_eq!(query(&credentials), expected);
+            expected.pop();
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_managed_identity_config() -> Result<()> {
+        let config: ManagedIdentityConfig =
+            serde_json::from_value(serde_json::json!({ "object_id": IDENTITY_ID }))?;
+        assert_eq!(
+            config.identity()?,
+            UserAssignedIdentity::ObjectId(IDENTITY_ID.parse()?)
+        );
+
+        let config: ManagedIdentityConfig =
+            serde_json::from_value(serde_json::json!({ "msi_res_id": IDENTITY_RES_ID }))?;
+        assert_eq!(
+            config.identity()?.to_string(),
+            format!("msi_res_id={IDENTITY_RES_ID}")
+        );
+
+        assert!(ManagedIdentityConfig::default().identity().is_err());
+        let config: ManagedIdentityConfig = serde_json::from_value(
+            serde_json::json!({ "client_id": IDENTITY_ID, "object_id": IDENTITY_ID }),
+        )?;
+        assert!(config.identity().is_err());
+
+        Ok(())
+    }
+
+    mod imds {
+        use wiremock::matchers::{header, method, path, query_param};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        use super::*;
+
+        const TOKEN_PATH: &str = "/metadata/identity/oauth2/token";
+
+        fn credentials(
+            server: &MockServer,
+            identity: UserAssignedIdentity,
+        ) -> ManagedIdentityCredentials {
+            let mut credentials =
+                ManagedIdentityCredentials::for_azure_resource("https://vault.azure.net")
+                    .with_identity(Some(identity));
+            credentials.endpoint = Url::parse(&format!(
+                "{}{TOKEN_PATH}?api-version=2018-02-01",
+                server.uri()
+            ))
+            .unwrap();
+            credentials
+        }
+
+        #[tokio::test]
+        async fn test_token_of_selected_identity() -> Result<()> {
+            let server = MockServer::start().await;
+            Mock::given(method("GET"))
+                .and(path(TOKEN_PATH))
+                .and(header("Metadata", "true"))
+                .and(query_param("client_id", IDENTITY_ID))
+                .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                    "access_token": "access",
+                    "resource": "https://vault.azure.net",
+                    "expires_on": "1700000000",
+                })))
+                .expect(1)
+                .mount(&server)
+                .await;
+
+            let identity = UserAssignedIdentity::ClientId(IDENTITY_ID.parse()?);
+            let token = credentials(&server, identity).access_token().await?;
+            assert_eq!(token.secret().expose_ref(), "access");
+            assert_eq!(
+                token.expires_at(),
+                Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000))
+            );
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_unassigned_identity_is_not_retried() -> Result<()> {
+            let server = MockServer::start().await;
+            Mock::given(method("GET"))
+                .and(path(TOKEN_PATH))
+                .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+                    "error": "invalid_request",
+                    "error_description": "Identity not found",
+                })))
+                .expect(1)
+                .mount(&server)
+                .await;
+
+            let identity = UserAssignedIdentity::ObjectId(IDENTITY_ID.parse()?);
+            let err = credentials(&server, identity)
+                .access_token()
+                .await
+                .err()
+                .expect("error");
+
+            // The hint, followed by the response of IMDS, as it was.
+            let err = format!("{err:?}");
+            assert!(
+                err.contains(&format!("managed identity object_id={IDENTITY_ID}")),
+                "{err}"
+            );
+            assert!(err.contains("may not be assigned to this VM"), "{err}");
+            assert!(err.contains("400 Bad Request"), "{err}");
+            assert!(
+                err.contains(r#""error_description":"Identity not found""#),
+                "{err}"
+            );
+
+            Ok(())
+        }
+    }
 }
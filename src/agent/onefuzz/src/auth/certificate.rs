@@ -0,0 +1,240 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Client certificate credentials, for tenants which forbid client secrets.
+//!
+//! The client authenticates with an assertion, a JWT signed by the private key
+//! of its certificate, which is identified by its SHA-256 thumbprint. The key
+//! is read from its file for each assertion, rather than kept in memory, and
+//! the buffers holding it are zeroed once it's parsed.
+
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use url::Url;
+use uuid::Uuid;
+
+use super::Secret;
+
+/// The password of the client certificate file, if it's encrypted.
+pub const CERTIFICATE_PASSWORD_ENV: &str = "ONEFUZZ_CLIENT_CERTIFICATE_PASSWORD";
+
+// Assertions are only used once, so only need to outlive their request.
+const ASSERTION_LIFETIME: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+pub struct ClientCertificate {
+    path: PathBuf,
+}
+
+impl ClientCertificate {
+    /// The certificate and private key of the PFX (`.pfx` or `.p12`) or PEM
+    /// file at `path`, decrypted with `ONEFUZZ_CLIENT_CERTIFICATE_PASSWORD`
+    /// if set. The certificate must be valid now.
+    pub fn new(path: impl Into<PathBuf>) -> Result<Self> {
+        let certificate = Self { path: path.into() };
+
+        // Fail on startup, rather than on the first request for a token.
+        platform::SigningKey::load(&certificate.path)?;
+
+        Ok(certificate)
+    }
+
+    /// An assertion of the identity of the client `client_id`, for the token
+    /// endpoint `audience`.
+    pub fn assertion(&self, client_id: Uuid, audience: &Url) -> Result<Secret<String>> {
+        self.assertion_at(client_id, audience, SystemTime::now())
+    }
+
+    fn assertion_at(
+        &self,
+        client_id: Uuid,
+        audience: &Url,
+        now: SystemTime,
+    ) -> Result<Secret<String>> {
+        let key = platform::SigningKey::load(&self.path)?;
+
+        let now = now.duration_since(UNIX_EPOCH)?.as_secs();
+        let header = serde_json::json!({
+            "alg": "PS256",
+            "typ": "JWT",
+            "x5t#S256": URL_SAFE_NO_PAD.encode(key.thumbprint()),
+        });
+        let claims = serde_json::json!({
+            "aud": audience.as_str(),
+            "iss": client_id,
+            "sub": client_id,
+            "jti": Uuid::new_v4(),
+            "nbf": now,
+            "iat": now,
+            "exp": now + ASSERTION_LIFETIME.as_secs(),
+        });
+
+        let message = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(serde_json::to_vec(&header)?),
+            URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims)?),
+        );
+        let signature = key.sign(message.as_bytes())?;
+
+        Ok(format!("{message}.{}", URL_SAFE_NO_PAD.encode(signature)).into())
+    }
+}
+
+#[cfg(target_family = "unix")]
+mod platform {
+    use std::cmp::Ordering;
+    use std::path::Path;
+
+    use anyhow::{Context, Result};
+    use openssl::{
+        asn1::Asn1Time,
+        hash::MessageDigest,
+        pkcs12::Pkcs12,
+        pkey::{Id, PKey, Private},
+        rsa::{Padding, RsaPssSaltlen},
+        sign::Signer,
+        x509::X509,
+    };
+    use sha2::{Digest, Sha256};
+
+    use super::CERTIFICATE_PASSWORD_ENV;
+
+    pub struct SigningKey {
+        thumbprint: Vec<u8>,
+
+        // Cleared by OpenSSL when dropped.
+        key: PKey<Private>,
+    }
+
+    impl SigningKey {
+        pub fn load(path: &Path) -> Result<Self> {
+            let mut data = std::fs::read(path).with_context(|| {
+                format!("unable to read client certificate: {}", path.display())
+            })?;
+            let mut password = std::env::var(CERTIFICATE_PASSWORD_ENV)
+                .ok()
+                .map(String::into_bytes);
+
+            let parsed = parse(path, &data, password.as_deref());
+            zeroize(&mut data);
+            if let Some(password) = &mut password {
+                zeroize(password);
+            }
+            let (certificate, key) = parsed?;
+
+            let now = Asn1Time::days_from_now(0)?;
+            if certificate.not_after().compare(&now)? == Ordering::Less {
+                bail!(
+                    "client certificate expired at {}: {}",
+                    certificate.not_after(),
+                    path.display()
+                );
+            }
+            if certificate.not_before().compare(&now)? == Ordering::Greater {
+                bail!(
+                    "client certificate is not valid until {}: {}",
+                    certificate.not_before(),
+                    path.display()
+                );
+            }
+            if key.id() != Id::RSA {
+                bail!(
+                    "client certificate key must be an RSA key: {}",
+                    path.display()
+                );
+            }
+
+            let thumbprint = Sha256::digest(certificate.to_der()?).to_vec();
+
+            Ok(Self { thumbprint, key })
+        }
+
+        /// The SHA-256 thumbprint of the certificate.
+        pub fn thumbprint(&self) -> &[u8] {
+            &self.thumbprint
+        }
+
+        pub fn sign(&self, message: &[u8]) -> Result<Vec<u8>> {
+            let mut signer = Signer::new(MessageDigest::sha256(), &self.key)?;
+            signer.set_rsa_padding(Padding::PKCS1_PSS)?;
+            signer.set_rsa_pss_saltlen(RsaPssSaltlen::DIGEST_LENGTH)?;
+            signer.update(message)?;
+            Ok(signer.sign_to_vec()?)
+        }
+    }
+
+    // Errors of OpenSSL describe why the data is invalid, without including it.
+    fn parse(path: &Path, data: &[u8], password: Option<&[u8]>) -> Result<(X509, PKey<Private>)> {
+        if is_pfx(path) {
+            let password = std::str::from_utf8(password.unwrap_or_default())
+                .context("client certificate password must be UTF-8")?;
+            let pfx = Pkcs12::from_der(data).context("invalid PFX client certificate")?;
+            let parsed = pfx
+                .parse2(password)
+                .context("unable to decrypt PFX client certificate")?;
+
+            let certificate = parsed
+                .cert
+                .ok_or_else(|| format_err!("PFX client certificate has no certificate"))?;
+            let key = parsed
+                .pkey
+                .ok_or_else(|| format_err!("PFX client certificate has no private key"))?;
+
+            Ok((certificate, key))
+        } else {
+            let certificate = X509::from_pem(data).context("invalid PEM client certificate")?;
+            let key = match password {
+                Some(password) => PKey::private_key_from_pem_passphrase(data, password),
+                None => PKey::private_key_from_pem(data),
+            }
+            .context("invalid PEM client certificate private key")?;
+
+            Ok((certificate, key))
+        }
+    }
+
+    fn is_pfx(path: &Path) -> bool {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("pfx") || ext.eq_ignore_ascii_case("p12"))
+    }
+
+    // Overwrite `data`, such as key material which is no longer needed, in a way
+    // which isn't optimized away.
+    fn zeroize(data: &mut [u8]) {
+        for byte in data.iter_mut() {
+            // Safety: `byte` is a valid, aligned reference.
+            unsafe { std::ptr::write_volatile(byte, 0) };
+        }
+        std::sync::atomic::compiler_fence(std::sync::atomic::Ordering::SeqCst);
+    }
+}
+
+#[cfg(target_family = "windows")]
+mod platform {
+    use std::path::Path;
+
+    use anyhow::Result;
+
+    pub struct SigningKey;
+
+    impl SigningKey {
+        pub fn load(_path: &Path) -> Result<Self> {
+            bail!("client certificate credentials are not supported on Windows")
+        }
+
+        pub fn thumbprint(&self) -> &[u8] {
+            &[]
+        }
+
+        pub fn sign(&self, _message: &[u8]) -> Result<Vec<u8>> {
+            bail!("client certificate credentials are not supported on Windows")
+        }
+    }
+}
+
+#[cfg(all(test, target_family = "unix"))]
+mod tests;
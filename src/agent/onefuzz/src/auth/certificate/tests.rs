@@ -0,0 +1,225 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use std::path::Path;
+
+use anyhow::Result;
+use openssl::{
+    asn1::Asn1Time,
+    bn::BigNum,
+    hash::MessageDigest,
+    pkcs12::Pkcs12,
+    pkey::{PKey, Private},
+    rsa::{Padding, Rsa, RsaPssSaltlen},
+    sign::Verifier,
+    x509::{X509NameBuilder, X509},
+};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tempfile::TempDir;
+
+use super::*;
+
+const CLIENT_ID: &str = "a5ea6e8e-3bd5-4d3b-a4b3-8ab7b4b3f58d";
+const TOKEN_URL: &str = "https://login.microsoftonline.com/contoso/oauth2/v2.0/token";
+
+const DAY: i64 = 24 * 60 * 60;
+
+struct Fixture {
+    dir: TempDir,
+    key: PKey<Private>,
+    certificate: X509,
+}
+
+impl Fixture {
+    // A self-signed certificate, valid between the given days from now.
+    fn new(not_before: i64, not_after: i64) -> Result<Self> {
+        let key = PKey::from_rsa(Rsa::generate(2048)?)?;
+
+        let mut name = X509NameBuilder::new()?;
+        name.append_entry_by_text("CN", "onefuzz-agent")?;
+        let name = name.build();
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        let mut builder = X509::builder()?;
+        builder.set_version(2)?;
+        builder.set_serial_number(&BigNum::from_u32(1)?.to_asn1_integer()?)?;
+        builder.set_subject_name(&name)?;
+        builder.set_issuer_name(&name)?;
+        builder.set_pubkey(&key)?;
+        builder.set_not_before(&Asn1Time::from_unix(now + not_before * DAY)?)?;
+        builder.set_not_after(&Asn1Time::from_unix(now + not_after * DAY)?)?;
+        builder.sign(&key, MessageDigest::sha256())?;
+
+        Ok(Self {
+            dir: tempfile::tempdir()?,
+            key,
+            certificate: builder.build(),
+        })
+    }
+
+    fn valid() -> Result<Self> {
+        Self::new(-1, 365)
+    }
+
+    // The certificate and key, concatenated in a PEM file.
+    fn pem(&self) -> Result<PathBuf> {
+        let path = self.dir.path().join("client.pem");
+        let mut data = self.certificate.to_pem()?;
+        data.extend(self.key.private_key_to_pem_pkcs8()?);
+        std::fs::write(&path, data)?;
+        Ok(path)
+    }
+
+    fn pfx(&self, password: &str) -> Result<PathBuf> {
+        let path = self.dir.path().join("client.pfx");
+        let pfx = Pkcs12::builder()
+            .name("onefuzz-agent")
+            .pkey(&self.key)
+            .cert(&self.certificate)
+            .build2(password)?;
+        std::fs::write(&path, pfx.to_der()?)?;
+        Ok(path)
+    }
+
+    fn thumbprint(&self) -> Result<String> {
+        let digest = Sha256::digest(self.certificate.to_der()?);
+        Ok(URL_SAFE_NO_PAD.encode(digest))
+    }
+
+    // The header and claims of `assertion`, once its signature is verified.
+    fn verify(&self, assertion: &str) -> Result<(Value, Value)> {
+        let segments: Vec<&str> = assertion.split('.').collect();
+        assert_eq!(segments.len(), 3, "{assertion}");
+
+        let message = format!("{}.{}", segments[0], segments[1]);
+        let signature = URL_SAFE_NO_PAD.decode(segments[2])?;
+        let mut verifier = Verifier::new(MessageDigest::sha256(), &self.key)?;
+        verifier.set_rsa_padding(Padding::PKCS1_PSS)?;
+        verifier.set_rsa_pss_saltlen(RsaPssSaltlen::DIGEST_LENGTH)?;
+        assert!(verifier.verify_oneshot(&signature, message.as_bytes())?);
+
+        let header = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(segments[0])?)?;
+        let claims = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(segments[1])?)?;
+        Ok((header, claims))
+    }
+}
+
+fn assertion(path: &Path, now: SystemTime) -> Result<String> {
+    let certificate = ClientCertificate::new(path)?;
+    let assertion = certificate.assertion_at(CLIENT_ID.parse()?, &Url::parse(TOKEN_URL)?, now)?;
+    Ok(assertion.expose())
+}
+
+#[test]
+fn test_pem_assertion() -> Result<()> {
+    let fixture = Fixture::valid()?;
+    let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+    let (header, claims) = fixture.verify(&assertion(&fixture.pem()?, now)?)?;
+    assert_eq!(
+        header,
+        serde_json::json!({
+            "alg": "PS256",
+            "typ": "JWT",
+            "x5t#S256": fixture.thumbprint()?,
+        })
+    );
+
+    assert_eq!(claims["aud"], TOKEN_URL);
+    assert_eq!(claims["iss"], CLIENT_ID);
+    assert_eq!(claims["sub"], CLIENT_ID);
+    assert_eq!(claims["nbf"], 1_700_000_000);
+    assert_eq!(claims["iat"], 1_700_000_000);
+    assert_eq!(claims["exp"], 1_700_000_600);
+    let jti: Uuid = serde_json::from_value(claims["jti"].clone())?;
+    assert!(!jti.is_nil());
+
+    Ok(())
+}
+
+#[test]
+fn test_assertions_are_unique() -> Result<()> {
+    let fixture = Fixture::valid()?;
+    let path = fixture.pem()?;
+    let now = SystemTime::now();
+
+    let (_, first) = fixture.verify(&assertion(&path, now)?)?;
+    let (_, second) = fixture.verify(&assertion(&path, now)?)?;
+    assert_ne!(first["jti"], second["jti"]);
+
+    Ok(())
+}
+
+#[test]
+fn test_pfx_assertion() -> Result<()> {
+    // The only test which sets the password, since PEM files are unencrypted.
+    let fixture = Fixture::valid()?;
+    let path = fixture.pfx("hunter2")?;
+
+    std::env::set_var(CERTIFICATE_PASSWORD_ENV, "hunter2");
+    let (header, _) = fixture.verify(&assertion(&path, SystemTime::now())?)?;
+    assert_eq!(header["x5t#S256"], fixture.thumbprint()?);
+
+    std::env::set_var(CERTIFICATE_PASSWORD_ENV, "not-hunter2");
+    let err = ClientCertificate::new(&path).err().expect("error");
+    assert!(
+        err.to_string()
+            .contains("unable to decrypt PFX client certificate"),
+        "{err:?}"
+    );
+
+    // Neither the password nor the key are included in errors.
+    let err = format!("{err:?}");
+    assert!(
+        !err.contains("not-hunter2") && !err.contains("PRIVATE KEY"),
+        "{err}"
+    );
+
+    std::env::remove_var(CERTIFICATE_PASSWORD_ENV);
+
+    Ok(())
+}
+
+#[test]
+fn test_expired_certificate() -> Result<()> {
+    let fixture = Fixture::new(-30, -1)?;
+
+    let err = ClientCertificate::new(fixture.pem()?).err().expect("error");
+    assert!(
+        err.to_string().contains("client certificate expired at"),
+        "{err:?}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_certificate_not_yet_valid() -> Result<()> {
+    let fixture = Fixture::new(1, 30)?;
+
+    let err = ClientCertificate::new(fixture.pem()?).err().expect("error");
+    assert!(
+        err.to_string()
+            .contains("client certificate is not valid until"),
+        "{err:?}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_missing_certificate() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+
+    let err = ClientCertificate::new(dir.path().join("missing.pem"))
+        .err()
+        .expect("error");
+    assert!(
+        err.to_string()
+            .contains("unable to read client certificate"),
+        "{err:?}"
+    );
+
+    Ok(())
+}
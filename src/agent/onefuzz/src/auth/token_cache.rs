@@ -0,0 +1,258 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! A file of the last access token of some credentials, so that a restarted
+//! agent doesn't request a new token while its last one is still valid.
+//!
+//! On Windows, the file is encrypted with DPAPI, for the user of the agent.
+//! On Linux, it's readable only by its owner.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result};
+
+use super::{AccessToken, REFRESH_MARGIN};
+
+// Saved by other versions of the agent, which are ignored.
+const VERSION: u32 = 1;
+
+#[derive(Deserialize, Serialize)]
+struct CacheFile {
+    version: u32,
+
+    // Identifies the credentials of the token.
+    key: String,
+
+    access_token: String,
+
+    // Seconds since the Unix epoch.
+    expires_at: u64,
+}
+
+/// The persisted access token of some credentials.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PersistentTokenCache {
+    path: PathBuf,
+}
+
+impl PersistentTokenCache {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The cached token of the credentials identified by `key`, unless it
+    /// expires soon. Any failure to read the cache is logged, but treated as
+    /// a miss, since a new token can always be requested.
+    pub async fn load(&self, key: &str) -> Option<AccessToken> {
+        self.load_at(key, SystemTime::now()).await
+    }
+
+    async fn load_at(&self, key: &str, now: SystemTime) -> Option<AccessToken> {
+        match self.try_load(key, now).await {
+            Ok(token) => token,
+            Err(err) => {
+                debug!("ignoring token cache {}: {:?}", self.path.display(), err);
+                None
+            }
+        }
+    }
+
+    async fn try_load(&self, key: &str, now: SystemTime) -> Result<Option<AccessToken>> {
+        let data = match tokio::fs::read(&self.path).await {
+            Ok(data) => data,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        platform::check_permissions(&self.path).await?;
+
+        let data = platform::unprotect(&data).context("unable to decrypt token cache")?;
+        let file: CacheFile = serde_json::from_slice(&data).context("malformed token cache")?;
+
+        if file.version != VERSION || file.key != key {
+            return Ok(None);
+        }
+
+        let token = AccessToken {
+            secret: file.access_token.into(),
+            expires_at: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(file.expires_at)),
+        };
+        if token.expires_within(REFRESH_MARGIN, now) {
+            return Ok(None);
+        }
+
+        Ok(Some(token))
+    }
+
+    /// Replace the cached token with `token`, of the credentials identified
+    /// by `key`. Tokens without a known expiry aren't cached, since they
+    /// couldn't be validated when loaded.
+    pub async fn save(&self, key: &str, token: &AccessToken) -> Result<()> {
+        let Some(expires_at) = token.expires_at else {
+            return Ok(());
+        };
+
+        let file = CacheFile {
+            version: VERSION,
+            key: key.to_owned(),
+            access_token: token.secret().expose_ref().clone(),
+            expires_at: expires_at
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+        };
+        let data = serde_json::to_vec(&file)?;
+        let data = platform::protect(&data).context("unable to encrypt token cache")?;
+
+        // Written aside and renamed, so that a crash never leaves a partial file.
+        let temp = self.path.with_extension("tmp");
+        platform::write_private(&temp, &data)
+            .await
+            .with_context(|| format!("unable to write token cache: {}", temp.display()))?;
+        tokio::fs::rename(&temp, &self.path)
+            .await
+            .with_context(|| format!("unable to replace token cache: {}", self.path.display()))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(target_family = "unix")]
+mod platform {
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::Path;
+
+    use anyhow::Result;
+    use tokio::io::AsyncWriteExt;
+
+    // The file is protected by its mode.
+    pub fn protect(data: &[u8]) -> Result<Vec<u8>> {
+        Ok(data.to_vec())
+    }
+
+    pub fn unprotect(data: &[u8]) -> Result<Vec<u8>> {
+        Ok(data.to_vec())
+    }
+
+    pub async fn write_private(path: &Path, data: &[u8]) -> Result<()> {
+        let mut file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(path)
+            .await?;
+
+        // The mode is only applied to new files.
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))
+            .await?;
+        file.write_all(data).await?;
+        file.sync_all().await?;
+
+        Ok(())
+    }
+
+    // A token readable by other users may have been leaked, or planted.
+    pub async fn check_permissions(path: &Path) -> Result<()> {
+        let mode = tokio::fs::metadata(path).await?.permissions().mode();
+        if mode & 0o077 != 0 {
+            bail!(
+                "token cache is accessible to other users: {:o}",
+                mode & 0o777
+            );
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(target_family = "windows")]
+mod platform {
+    use std::path::Path;
+
+    use anyhow::Result;
+    use windows::core::PCWSTR;
+    use windows::Win32::{
+        Foundation::HLOCAL,
+        Security::Cryptography::{
+            CryptProtectData, CryptUnprotectData, CRYPTPROTECT_UI_FORBIDDEN, CRYPT_INTEGER_BLOB,
+        },
+        System::Memory::LocalFree,
+    };
+
+    // Encrypted for the user of the agent, so the file needs no other
+    // protection.
+    pub fn protect(data: &[u8]) -> Result<Vec<u8>> {
+        let input = blob(data);
+        let mut output = CRYPT_INTEGER_BLOB::default();
+
+        unsafe {
+            CryptProtectData(
+                &input,
+                PCWSTR::null(),
+                None,
+                None,
+                None,
+                CRYPTPROTECT_UI_FORBIDDEN,
+                &mut output,
+            )
+        }
+        .ok()?;
+
+        Ok(unsafe { take(output) })
+    }
+
+    pub fn unprotect(data: &[u8]) -> Result<Vec<u8>> {
+        let input = blob(data);
+        let mut output = CRYPT_INTEGER_BLOB::default();
+
+        unsafe {
+            CryptUnprotectData(
+                &input,
+                None,
+                None,
+                None,
+                None,
+                CRYPTPROTECT_UI_FORBIDDEN,
+                &mut output,
+            )
+        }
+        .ok()?;
+
+        Ok(unsafe { take(output) })
+    }
+
+    pub async fn write_private(path: &Path, data: &[u8]) -> Result<()> {
+        tokio::fs::write(path, data).await?;
+        Ok(())
+    }
+
+    pub async fn check_permissions(_path: &Path) -> Result<()> {
+        Ok(())
+    }
+
+    // DPAPI only reads the input blob.
+    fn blob(data: &[u8]) -> CRYPT_INTEGER_BLOB {
+        CRYPT_INTEGER_BLOB {
+            cbData: data.len() as u32,
+            pbData: data.as_ptr() as *mut u8,
+        }
+    }
+
+    // Copy the data of `blob`, allocated by DPAPI, and free it.
+    unsafe fn take(blob: CRYPT_INTEGER_BLOB) -> Vec<u8> {
+        let data = std::slice::from_raw_parts(blob.pbData, blob.cbData as usize).to_vec();
+
+        // Returns `Err(OK)` on success, and there's no recovering from failure.
+        let _ = LocalFree(HLOCAL(blob.pbData as _));
+
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests;
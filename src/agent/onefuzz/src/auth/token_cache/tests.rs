@@ -0,0 +1,147 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use anyhow::Result;
+use tempfile::TempDir;
+
+use super::*;
+
+const KEY: &str = "credentials";
+
+const HOUR: Duration = Duration::from_secs(60 * 60);
+
+fn token(secret: &str, expires_at: SystemTime) -> AccessToken {
+    AccessToken {
+        secret: secret.to_owned().into(),
+        expires_at: Some(expires_at),
+    }
+}
+
+fn cache(dir: &TempDir) -> PersistentTokenCache {
+    PersistentTokenCache::new(dir.path().join("token-cache"))
+}
+
+// Expiries are saved to the second.
+fn truncate(time: SystemTime) -> SystemTime {
+    let secs = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+#[tokio::test]
+async fn test_round_trip() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let cache = cache(&dir);
+    let expires_at = truncate(SystemTime::now() + HOUR);
+
+    assert!(cache.load(KEY).await.is_none());
+
+    cache.save(KEY, &token("access-1", expires_at)).await?;
+    let loaded = cache.load(KEY).await.expect("cached token");
+    assert_eq!(loaded.secret().expose_ref(), "access-1");
+    assert_eq!(loaded.expires_at(), Some(expires_at));
+
+    // Saving replaces the cached token.
+    cache.save(KEY, &token("access-2", expires_at)).await?;
+    let loaded = cache.load(KEY).await.expect("cached token");
+    assert_eq!(loaded.secret().expose_ref(), "access-2");
+    assert!(!dir.path().join("token-cache.tmp").exists());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_expiring_tokens_are_not_loaded() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let cache = cache(&dir);
+    let now = SystemTime::now();
+
+    cache.save(KEY, &token("access", now + HOUR)).await?;
+    assert!(cache.load_at(KEY, now).await.is_some());
+
+    // Within the refresh margin of expiring.
+    let soon = now + HOUR - REFRESH_MARGIN + Duration::from_secs(1);
+    assert!(cache.load_at(KEY, soon).await.is_none());
+    assert!(cache.load_at(KEY, now + 2 * HOUR).await.is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_tokens_without_expiry_are_not_saved() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let cache = cache(&dir);
+
+    let token = AccessToken {
+        secret: "access".to_owned().into(),
+        expires_at: None,
+    };
+    cache.save(KEY, &token).await?;
+    assert!(!cache.path().exists());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_tokens_of_other_credentials_are_not_loaded() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let cache = cache(&dir);
+
+    cache
+        .save(KEY, &token("access", SystemTime::now() + HOUR))
+        .await?;
+    assert!(cache.load("other-credentials").await.is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_corrupt_cache_is_a_miss() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let cache = cache(&dir);
+
+    cache
+        .save(KEY, &token("access", SystemTime::now() + HOUR))
+        .await?;
+    let mut data = tokio::fs::read(cache.path()).await?;
+    data.truncate(data.len() / 2);
+    tokio::fs::write(cache.path(), &data).await?;
+    assert!(cache.load(KEY).await.is_none());
+
+    tokio::fs::write(cache.path(), b"\0not a token cache").await?;
+    assert!(cache.load(KEY).await.is_none());
+
+    // A corrupt cache is replaced by the next token.
+    cache
+        .save(KEY, &token("access-2", SystemTime::now() + HOUR))
+        .await?;
+    assert!(cache.load(KEY).await.is_some());
+
+    Ok(())
+}
+
+#[cfg(target_family = "unix")]
+#[tokio::test]
+async fn test_cache_is_private() -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir()?;
+    let cache = cache(&dir);
+
+    cache
+        .save(KEY, &token("access", SystemTime::now() + HOUR))
+        .await?;
+    let mode = tokio::fs::metadata(cache.path())
+        .await?
+        .permissions()
+        .mode();
+    assert_eq!(mode & 0o777, 0o600);
+
+    // A cache which others could have written is ignored.
+    tokio::fs::set_permissions(cache.path(), std::fs::Permissions::from_mode(0o644)).await?;
+    assert!(cache.load(KEY).await.is_none());
+
+    Ok(())
+}
@@ -0,0 +1,266 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Credentials of workload identity federation, for nodes such as AKS pods or
+//! GitHub-hosted runners, which have neither a client secret nor a managed
+//! identity, but a federated token, exchanged for an access token.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result};
+use reqwest::StatusCode;
+use reqwest_retry::{RetryCheck, SendRetry, DEFAULT_RETRY_PERIOD, MAX_RETRY_ATTEMPTS};
+use tokio::{sync::Mutex, time::Instant};
+use url::Url;
+use uuid::Uuid;
+
+use super::{token_scope, AccessToken, ExpiresIn, Secret, CLIENT_ASSERTION_TYPE, REFRESH_MARGIN};
+use crate::cloud::CloudEnvironment;
+use crate::http::ResponseExt;
+
+/// The file of the federated token, as set for AKS workload identity.
+pub const FEDERATED_TOKEN_FILE_ENV: &str = "AZURE_FEDERATED_TOKEN_FILE";
+pub const CLIENT_ID_ENV: &str = "AZURE_CLIENT_ID";
+pub const TENANT_ID_ENV: &str = "AZURE_TENANT_ID";
+pub const AUTHORITY_HOST_ENV: &str = "AZURE_AUTHORITY_HOST";
+
+/// Settings of workload identity. Each one which isn't set is read from its
+/// environment variable, such as `AZURE_FEDERATED_TOKEN_FILE`, except that the
+/// authority host defaults to that of the cloud of the instance.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct WorkloadIdentityConfig {
+    pub client_id: Option<Uuid>,
+    pub tenant: Option<String>,
+    pub token_file: Option<PathBuf>,
+    pub authority_host: Option<Url>,
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+pub struct WorkloadIdentityCredentials {
+    client_id: Uuid,
+    tenant: String,
+    token_file: PathBuf,
+    resource: String,
+    multi_tenant_domain: Option<String>,
+    authority_host: Url,
+
+    #[serde(skip)]
+    cache: TokenCache,
+}
+
+impl WorkloadIdentityCredentials {
+    pub fn new(
+        config: WorkloadIdentityConfig,
+        cloud: &CloudEnvironment,
+        resource: String,
+        multi_tenant_domain: Option<String>,
+    ) -> Result<Self> {
+        let client_id = match config.client_id {
+            Some(client_id) => client_id,
+            None => env(CLIENT_ID_ENV)?
+                .parse()
+                .with_context(|| format!("invalid {CLIENT_ID_ENV}"))?,
+        };
+        let tenant = match config.tenant {
+            Some(tenant) => tenant,
+            None => env(TENANT_ID_ENV)?,
+        };
+        let token_file = match config.token_file {
+            Some(token_file) => token_file,
+            None => env(FEDERATED_TOKEN_FILE_ENV)?.into(),
+        };
+        let authority_host = match config.authority_host {
+            Some(authority_host) => authority_host,
+            None => match std::env::var(AUTHORITY_HOST_ENV) {
+                Ok(host) => {
+                    Url::parse(&host).with_context(|| format!("invalid {AUTHORITY_HOST_ENV}"))?
+                }
+                Err(_) => cloud.authority_host.clone(),
+            },
+        };
+
+        // Validated up front, rather than by the first request.
+        token_scope(&resource, multi_tenant_domain.as_deref())?;
+
+        Ok(Self {
+            client_id,
+            tenant,
+            token_file,
+            resource,
+            multi_tenant_domain,
+            authority_host,
+            cache: TokenCache::default(),
+        })
+    }
+
+    /// The credentials of the environment, if it has a federated token, as
+    /// for AKS workload identity.
+    pub fn from_env(
+        cloud: &CloudEnvironment,
+        resource: String,
+        multi_tenant_domain: Option<String>,
+    ) -> Result<Option<Self>> {
+        if std::env::var_os(FEDERATED_TOKEN_FILE_ENV).is_none() {
+            return Ok(None);
+        }
+
+        let credentials = Self::new(
+            WorkloadIdentityConfig::default(),
+            cloud,
+            resource,
+            multi_tenant_domain,
+        )?;
+        Ok(Some(credentials))
+    }
+
+    /// An access token, exchanged for the federated token when the last one
+    /// is about to expire.
+    pub async fn access_token(&self) -> Result<AccessToken> {
+        // Held while refreshing, so that concurrent requests share a refresh.
+        let mut cached = self.cache.0.lock().await;
+
+        if let Some(cached) = &*cached {
+            if Instant::now() < cached.refresh_at {
+                return Ok(cached.token.clone());
+            }
+        }
+
+        let token = self.exchange().await?;
+        let token = cached.insert(token);
+
+        Ok(token.token.clone())
+    }
+
+    // The identity of the credentials, as keyed in a persistent token cache.
+    pub(super) fn cache_identity(&self) -> String {
+        format!(
+            "workload_identity:{}:{}:{}:{}:{}",
+            self.authority_host,
+            self.tenant,
+            self.client_id,
+            self.resource,
+            self.multi_tenant_domain.as_deref().unwrap_or_default(),
+        )
+    }
+
+    async fn exchange(&self) -> Result<CachedToken> {
+        // Re-read for each exchange, since the file is rotated before the
+        // federated token expires.
+        let assertion = read_federated_token(&self.token_file).await?;
+        let scope = token_scope(&self.resource, self.multi_tenant_domain.as_deref())?;
+
+        let mut url = self.authority_host.clone();
+        url.path_segments_mut()
+            .map_err(|_| format_err!("invalid authority host: {}", self.authority_host))?
+            .pop_if_empty()
+            .extend(&[&self.tenant, "oauth2", "v2.0", "token"]);
+
+        let requested = Instant::now();
+        let response = reqwest_retry::proxy::client()
+            .post(url)
+            .form(&[
+                ("client_id", self.client_id.hyphenated().to_string()),
+                ("grant_type", "client_credentials".into()),
+                ("scope", format!("{scope}.default")),
+                ("client_assertion_type", CLIENT_ASSERTION_TYPE.into()),
+                ("client_assertion", assertion.expose()),
+            ])
+            .send_retry(
+                // A rejected assertion won't be accepted by retrying.
+                |status: StatusCode| {
+                    if status.is_client_error() {
+                        RetryCheck::Fail
+                    } else {
+                        RetryCheck::Retry
+                    }
+                },
+                DEFAULT_RETRY_PERIOD,
+                MAX_RETRY_ATTEMPTS,
+            )
+            .await
+            .context("WorkloadIdentityCredentials.access_token")?
+            .error_for_status_with_body()
+            .await
+            .context("WorkloadIdentityCredentials.access_token status body")?;
+
+        let body: FederatedAccessTokenBody = response.json().await?;
+        let expires_in = Duration::from_secs(body.expires_in.seconds()?);
+
+        Ok(CachedToken {
+            token: AccessToken {
+                secret: body.access_token,
+                expires_at: Some(SystemTime::now() + expires_in),
+            },
+            refresh_at: requested + expires_in.saturating_sub(REFRESH_MARGIN),
+        })
+    }
+}
+
+fn env(name: &str) -> Result<String> {
+    std::env::var(name).with_context(|| format!("workload identity requires {name}"))
+}
+
+// Read the federated token, a JWT, from `path`.
+async fn read_federated_token(path: &Path) -> Result<Secret<String>> {
+    let data = tokio::fs::read_to_string(path)
+        .await
+        .with_context(|| format!("unable to read federated token file: {}", path.display()))?;
+    let token = data.trim();
+
+    // The base64url-encoded header, payload and signature.
+    let segments: Vec<&str> = token.split('.').collect();
+    let well_formed = segments.len() == 3
+        && segments.iter().all(|segment| {
+            !segment.is_empty()
+                && segment
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+        });
+    if !well_formed {
+        bail!("malformed federated token file: {}", path.display());
+    }
+
+    Ok(token.to_owned().into())
+}
+
+#[derive(Clone)]
+struct CachedToken {
+    token: AccessToken,
+    refresh_at: Instant,
+}
+
+// The access token of the credentials, shared by their clones, and ignored
+// when comparing them.
+#[derive(Clone, Default)]
+struct TokenCache(Arc<Mutex<Option<CachedToken>>>);
+
+impl PartialEq for TokenCache {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for TokenCache {}
+
+impl fmt::Debug for TokenCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "TokenCache")
+    }
+}
+
+// See: https://learn.microsoft.com/en-us/entra/identity-platform
+//      /v2-oauth2-client-creds-grant-flow#third-case-access-token-request-with-a-federated-credential
+#[derive(Clone, Debug, Deserialize)]
+struct FederatedAccessTokenBody {
+    access_token: Secret<String>,
+
+    // Seconds until the access token expires.
+    expires_in: ExpiresIn,
+}
+
+#[cfg(test)]
+mod tests;
@@ -89,6 +89,11 @@ async fn az_impl(mode: Mode, src: &OsStr, dst: &OsStr, args: &[&str]) -> Result<
         .arg(dst)
         .args(args);
 
+    // azcopy doesn't share the clients of the agent, so is given their proxy.
+    if let Some(proxy) = reqwest_retry::proxy::proxy() {
+        cmd.envs(proxy.env_vars()?);
+    }
+
     let output = cmd
         .spawn()
         .context("azcopy failed to start")?
@@ -200,11 +205,25 @@ pub async fn sync(src: impl AsRef<OsStr>, dst: impl AsRef<OsStr>, delete_dst: bo
 }
 
 pub async fn copy(src: impl AsRef<OsStr>, dst: impl AsRef<OsStr>, recursive: bool) -> Result<()> {
-    let args = if recursive {
-        vec!["--recursive=true"]
-    } else {
-        vec![]
-    };
+    copy_with_cap(src, dst, recursive, None).await
+}
+
+/// Copy as `copy` does, limiting the transfer rate to `cap_mbps` megabits per
+/// second, if given.
+pub async fn copy_with_cap(
+    src: impl AsRef<OsStr>,
+    dst: impl AsRef<OsStr>,
+    recursive: bool,
+    cap_mbps: Option<u64>,
+) -> Result<()> {
+    let mut args = vec![];
+    if recursive {
+        args.push("--recursive=true".to_owned());
+    }
+    if let Some(cap_mbps) = cap_mbps {
+        args.push(format!("--cap-mbps={cap_mbps}"));
+    }
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
 
     retry_az_impl(Mode::Copy, src.as_ref(), dst.as_ref(), &args).await
 }
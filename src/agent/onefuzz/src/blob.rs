@@ -2,7 +2,10 @@
 // Licensed under the MIT License.
 
 pub mod client;
+pub mod list;
+pub mod provenance;
 pub mod url;
 
 pub use self::client::BlobClient;
+pub use self::provenance::BlobProvenance;
 pub use self::url::{BlobContainerUrl, BlobUrl};
@@ -11,6 +11,9 @@ use serde::Serialize;
 use tokio::{fs, io};
 use tokio_util::codec;
 
+use super::list::{list_pages, BlobItem, BlobListPage};
+use super::provenance::BlobProvenance;
+
 #[derive(Clone)]
 pub struct BlobClient {
     client: reqwest::Client,
@@ -24,7 +27,7 @@ impl Default for BlobClient {
 
 impl BlobClient {
     pub fn new() -> Self {
-        let client = reqwest::Client::new();
+        let client = reqwest_retry::proxy::client();
 
         Self { client }
     }
@@ -44,6 +47,21 @@ impl BlobClient {
         Ok(r)
     }
 
+    /// The provenance of the blob at `url`, from its metadata, without
+    /// downloading it.
+    pub async fn get_provenance(&self, url: &Url) -> Result<BlobProvenance> {
+        let r = self
+            .client
+            .head(url.clone())
+            .send_retry_default()
+            .await
+            .context("BlobClient.get_provenance")?
+            .error_for_status()
+            .context("BlobClient.get_provenance status")?;
+
+        Ok(BlobProvenance::from_headers(r.headers()))
+    }
+
     pub async fn get_data(&self, url: &Url) -> Result<Vec<u8>> {
         let r = self.get(url).await?;
         let b = r.bytes().await?;
@@ -60,6 +78,52 @@ impl BlobClient {
         Ok(dst.to_owned())
     }
 
+    /// List every blob of the container at `container_url`, following the
+    /// `NextMarker` of each page of results.
+    pub async fn list_blobs(&self, container_url: &Url) -> Result<Vec<BlobItem>> {
+        self.list_blobs_with_prefix(container_url, "").await
+    }
+
+    /// List the blobs of the container at `container_url` whose names start
+    /// with `prefix`.
+    pub async fn list_blobs_with_prefix(
+        &self,
+        container_url: &Url,
+        prefix: &str,
+    ) -> Result<Vec<BlobItem>> {
+        list_pages(move |marker| async move {
+            let mut url = container_url.clone();
+            url.query_pairs_mut()
+                .append_pair("restype", "container")
+                .append_pair("comp", "list");
+            if !prefix.is_empty() {
+                url.query_pairs_mut().append_pair("prefix", prefix);
+            }
+            if let Some(marker) = &marker {
+                url.query_pairs_mut().append_pair("marker", marker);
+            }
+
+            let xml = self
+                .get(&url)
+                .await
+                .context("BlobClient.list_blobs")?
+                .text()
+                .await?;
+            BlobListPage::parse(&xml)
+        })
+        .await
+    }
+
+    pub async fn delete(&self, url: Url) -> Result<Response> {
+        self.client
+            .delete(url)
+            .send_retry_default()
+            .await
+            .context("BlobClient.delete")?
+            .error_for_status()
+            .context("BlobClient.delete status")
+    }
+
     pub fn put(&self, url: Url) -> RequestBuilder {
         self.client.put(url).header("x-ms-blob-type", "BlockBlob")
     }
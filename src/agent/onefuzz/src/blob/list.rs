@@ -0,0 +1,238 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Listing the blobs of a container, page by page.
+
+use std::{future::Future, path::Path, time::SystemTime};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::fs::list_files;
+
+/// A blob, as listed.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BlobItem {
+    pub name: String,
+    pub size: u64,
+    pub last_modified: SystemTime,
+}
+
+/// A page of a blob listing.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct BlobListPage {
+    pub blobs: Vec<BlobItem>,
+
+    /// Marker to request the next page with, unless this is the last page.
+    pub next_marker: Option<String>,
+}
+
+impl BlobListPage {
+    /// Parse the XML response of a List Blobs request.
+    ///
+    /// https://learn.microsoft.com/en-us/rest/api/storageservices/list-blobs
+    pub fn parse(xml: &str) -> Result<Self> {
+        let results: EnumerationResults =
+            quick_xml::de::from_str(xml).context("invalid blob listing")?;
+
+        let blobs = results
+            .blobs
+            .blob
+            .into_iter()
+            .map(|blob| {
+                let last_modified = httpdate::parse_http_date(&blob.properties.last_modified)
+                    .with_context(|| {
+                        format!(
+                            "invalid Last-Modified of blob {}: {}",
+                            blob.name, blob.properties.last_modified
+                        )
+                    })?;
+
+                Ok(BlobItem {
+                    name: blob.name,
+                    size: blob.properties.content_length,
+                    last_modified,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        // The last page has an empty `<NextMarker />`.
+        let next_marker = results.next_marker.filter(|marker| !marker.is_empty());
+
+        Ok(Self { blobs, next_marker })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct EnumerationResults {
+    #[serde(default)]
+    blobs: Blobs,
+
+    #[serde(default)]
+    next_marker: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+struct Blobs {
+    #[serde(rename = "Blob", default)]
+    blob: Vec<Blob>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Blob {
+    name: String,
+    properties: Properties,
+}
+
+#[derive(Deserialize)]
+struct Properties {
+    #[serde(rename = "Last-Modified")]
+    last_modified: String,
+
+    #[serde(rename = "Content-Length")]
+    content_length: u64,
+}
+
+/// List every blob, requesting each page with the marker of the page before.
+pub async fn list_pages<F, Fut>(mut list_page: F) -> Result<Vec<BlobItem>>
+where
+    F: FnMut(Option<String>) -> Fut,
+    Fut: Future<Output = Result<BlobListPage>>,
+{
+    let mut blobs = vec![];
+    let mut marker = None;
+
+    loop {
+        let page = list_page(marker.take()).await?;
+        blobs.extend(page.blobs);
+
+        match page.next_marker {
+            Some(next) => marker = Some(next),
+            None => return Ok(blobs),
+        }
+    }
+}
+
+/// List the files of a local directory, as blobs.
+pub async fn list_dir(path: impl AsRef<Path>) -> Result<Vec<BlobItem>> {
+    let mut blobs = vec![];
+
+    for file in list_files(path).await? {
+        let Some(name) = file.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+
+        let metadata = tokio::fs::metadata(&file).await?;
+        blobs.push(BlobItem {
+            name: name.to_owned(),
+            size: metadata.len(),
+            last_modified: metadata.modified()?,
+        });
+    }
+
+    Ok(blobs)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_parse() -> Result<()> {
+        let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+<EnumerationResults ServiceEndpoint="https://account.blob.core.windows.net/" ContainerName="inputs">
+  <Marker>page-1</Marker>
+  <MaxResults>2</MaxResults>
+  <Blobs>
+    <Blob>
+      <Name>seed-1</Name>
+      <Properties>
+        <Creation-Time>Wed, 01 Nov 2023 10:00:00 GMT</Creation-Time>
+        <Last-Modified>Wed, 01 Nov 2023 10:00:00 GMT</Last-Modified>
+        <Etag>0x8DBDAB0123456789</Etag>
+        <Content-Length>42</Content-Length>
+        <Content-Type>application/octet-stream</Content-Type>
+        <BlobType>BlockBlob</BlobType>
+      </Properties>
+      <OrMetadata />
+    </Blob>
+    <Blob>
+      <Name>a&amp;b</Name>
+      <Properties>
+        <Last-Modified>Wed, 01 Nov 2023 10:00:01 GMT</Last-Modified>
+        <Content-Length>0</Content-Length>
+      </Properties>
+    </Blob>
+  </Blobs>
+  <NextMarker>page-2</NextMarker>
+</EnumerationResults>"#;
+
+        let page = BlobListPage::parse(xml)?;
+        let modified = httpdate::parse_http_date("Wed, 01 Nov 2023 10:00:00 GMT")?;
+        assert_eq!(
+            page,
+            BlobListPage {
+                blobs: vec![
+                    BlobItem {
+                        name: "seed-1".to_owned(),
+                        size: 42,
+                        last_modified: modified,
+                    },
+                    BlobItem {
+                        name: "a&b".to_owned(),
+                        size: 0,
+                        last_modified: modified + Duration::from_secs(1),
+                    },
+                ],
+                next_marker: Some("page-2".to_owned()),
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_last_page() -> Result<()> {
+        let xml = r#"<EnumerationResults ContainerName="inputs"><Blobs /><NextMarker /></EnumerationResults>"#;
+        assert_eq!(BlobListPage::parse(xml)?, BlobListPage::default());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_list_pages() -> Result<()> {
+        let pages = [(None, Some("b")), (Some("b"), Some("c")), (Some("c"), None)];
+
+        let mut requested = vec![];
+        let blobs = list_pages(|marker| {
+            requested.push(marker.clone());
+            let (_, next) = pages
+                .iter()
+                .find(|(page, _)| page.map(String::from) == marker)
+                .unwrap();
+            let page = BlobListPage {
+                blobs: vec![BlobItem {
+                    name: marker.unwrap_or_else(|| "a".to_owned()),
+                    size: 1,
+                    last_modified: SystemTime::UNIX_EPOCH,
+                }],
+                next_marker: next.map(String::from),
+            };
+            async move { Ok(page) }
+        })
+        .await?;
+
+        let names: Vec<_> = blobs.iter().map(|blob| blob.name.as_str()).collect();
+        assert_eq!(names, ["a", "b", "c"]);
+        assert_eq!(
+            requested,
+            [None, Some("b".to_owned()), Some("c".to_owned())]
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,124 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! The provenance of blobs uploaded by tasks, recorded in their metadata.
+
+use std::time::SystemTime;
+
+use reqwest::header::HeaderMap;
+use uuid::Uuid;
+
+// https://learn.microsoft.com/en-us/rest/api/storageservices/setting-and-retrieving-properties-and-metadata-for-blob-resources
+const METADATA_HEADER_PREFIX: &str = "x-ms-meta-";
+
+const TASK_ID: &str = "onefuzz_task_id";
+const TIMESTAMP: &str = "onefuzz_timestamp";
+
+/// The task which uploaded a blob, and when the uploaded file was written.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct BlobProvenance {
+    pub task_id: Option<Uuid>,
+    pub timestamp: Option<SystemTime>,
+}
+
+impl BlobProvenance {
+    /// The headers of a Put Blob request setting the provenance as the
+    /// metadata of the blob.
+    pub fn headers(&self) -> Vec<(String, String)> {
+        let mut headers = vec![];
+        if let Some(task_id) = self.task_id {
+            headers.push((
+                format!("{METADATA_HEADER_PREFIX}{TASK_ID}"),
+                task_id.to_string(),
+            ));
+        }
+        if let Some(timestamp) = self.timestamp {
+            headers.push((
+                format!("{METADATA_HEADER_PREFIX}{TIMESTAMP}"),
+                httpdate::fmt_http_date(timestamp),
+            ));
+        }
+
+        headers
+    }
+
+    /// The provenance of a blob, from the metadata headers of the response
+    /// to a Get Blob or Get Blob Properties request. Metadata which is missing
+    /// or invalid, such as of blobs uploaded by other tools, is `None`.
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let metadata = |name: &str| {
+            headers
+                .get(format!("{METADATA_HEADER_PREFIX}{name}"))
+                .and_then(|value| value.to_str().ok())
+        };
+
+        Self {
+            task_id: metadata(TASK_ID).and_then(|value| value.parse().ok()),
+            timestamp: metadata(TIMESTAMP).and_then(|value| httpdate::parse_http_date(value).ok()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use anyhow::Result;
+    use reqwest::header::{HeaderName, HeaderValue};
+
+    use super::*;
+
+    fn header_map(headers: &[(String, String)]) -> Result<HeaderMap> {
+        let mut map = HeaderMap::new();
+        for (name, value) in headers {
+            map.insert(HeaderName::try_from(name)?, HeaderValue::try_from(value)?);
+        }
+        Ok(map)
+    }
+
+    #[test]
+    fn test_round_trip() -> Result<()> {
+        let provenance = BlobProvenance {
+            task_id: Some(Uuid::new_v4()),
+            timestamp: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_698_832_800)),
+        };
+
+        let headers = provenance.headers();
+        assert_eq!(
+            headers[1],
+            (
+                "x-ms-meta-onefuzz_timestamp".to_string(),
+                "Wed, 01 Nov 2023 10:00:00 GMT".to_string()
+            )
+        );
+        assert_eq!(
+            BlobProvenance::from_headers(&header_map(&headers)?),
+            provenance
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_or_invalid() -> Result<()> {
+        assert_eq!(
+            BlobProvenance::from_headers(&HeaderMap::new()),
+            BlobProvenance::default()
+        );
+        assert!(BlobProvenance::default().headers().is_empty());
+
+        let headers = header_map(&[
+            ("x-ms-meta-onefuzz_task_id".to_string(), "fuzz".to_string()),
+            (
+                "x-ms-meta-onefuzz_timestamp".to_string(),
+                "yesterday".to_string(),
+            ),
+        ])?;
+        assert_eq!(
+            BlobProvenance::from_headers(&headers),
+            BlobProvenance::default()
+        );
+
+        Ok(())
+    }
+}
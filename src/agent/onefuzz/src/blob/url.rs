@@ -10,6 +10,8 @@ use anyhow::Result;
 use reqwest::Url;
 use serde::{de, Serialize, Serializer};
 
+use crate::cloud::CloudEnvironment;
+
 #[derive(Clone, Eq, PartialEq)]
 pub enum BlobUrl {
     AzureBlob(Url),
@@ -28,12 +30,13 @@ impl BlobUrl {
         bail!("Invalid blob URL: {}", url)
     }
 
-    pub fn from_blob_info(account: &str, container: &str, name: &str) -> Result<Self> {
-        // format https://docs.microsoft.com/en-us/rest/api/storageservices/naming-and-referencing-containers--blobs--and-metadata#resource-uri-syntax
-        let url = Url::parse(&format!(
-            "https://{account}.blob.core.windows.net/{container}/{name}"
-        ))?;
-        Self::new(url)
+    pub fn from_blob_info(
+        cloud: &CloudEnvironment,
+        account: &str,
+        container: &str,
+        name: &str,
+    ) -> Result<Self> {
+        Self::new(cloud.blob_url(account, container, name)?)
     }
 
     pub fn parse(url: impl AsRef<str>) -> Result<Self> {
@@ -0,0 +1,292 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! The Azure cloud of an instance, such as Azure Government, whose endpoints
+//! differ from those of the public cloud.
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::Result;
+use url::Url;
+
+/// A cloud, by name, or by its endpoints, for clouds without a preset, such as
+/// Azure Stack.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Cloud {
+    #[default]
+    Public,
+
+    #[serde(rename = "usgov")]
+    UsGov,
+
+    China,
+
+    Custom(CloudEnvironment),
+}
+
+impl Cloud {
+    pub fn environment(&self) -> CloudEnvironment {
+        match self {
+            Self::Public => CloudEnvironment::public(),
+            Self::UsGov => CloudEnvironment::us_gov(),
+            Self::China => CloudEnvironment::china(),
+            Self::Custom(environment) => environment.clone(),
+        }
+    }
+}
+
+impl fmt::Display for Cloud {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Public => "public",
+            Self::UsGov => "usgov",
+            Self::China => "china",
+            Self::Custom(_) => "custom",
+        };
+        f.write_str(name)
+    }
+}
+
+// Only presets, since the endpoints of a custom cloud have no string form.
+impl FromStr for Cloud {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "public" => Ok(Self::Public),
+            "usgov" => Ok(Self::UsGov),
+            "china" => Ok(Self::China),
+            _ => bail!("unknown cloud: {s:?}, expected public, usgov, or china"),
+        }
+    }
+}
+
+/// The endpoints of a cloud, which all URLs of Azure services are built from,
+/// or checked against.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct CloudEnvironment {
+    /// The host of Azure AD, which issues access tokens, such as
+    /// `https://login.microsoftonline.com/`.
+    pub authority_host: Url,
+
+    /// The DNS suffix of key vaults, such as `vault.azure.net`.
+    pub key_vault_suffix: String,
+
+    /// The DNS suffix of storage accounts, such as `core.windows.net`.
+    pub storage_suffix: String,
+}
+
+impl CloudEnvironment {
+    pub fn public() -> Self {
+        Self::preset(
+            "https://login.microsoftonline.com/",
+            "vault.azure.net",
+            "core.windows.net",
+        )
+    }
+
+    pub fn us_gov() -> Self {
+        Self::preset(
+            "https://login.microsoftonline.us/",
+            "vault.usgovcloudapi.net",
+            "core.usgovcloudapi.net",
+        )
+    }
+
+    pub fn china() -> Self {
+        Self::preset(
+            "https://login.chinacloudapi.cn/",
+            "vault.azure.cn",
+            "core.chinacloudapi.cn",
+        )
+    }
+
+    fn preset(authority_host: &str, key_vault_suffix: &str, storage_suffix: &str) -> Self {
+        Self {
+            authority_host: Url::parse(authority_host).expect("invalid preset authority host"),
+            key_vault_suffix: key_vault_suffix.to_owned(),
+            storage_suffix: storage_suffix.to_owned(),
+        }
+    }
+
+    /// The token endpoint of the authority of `tenant`.
+    pub fn token_url(&self, tenant: &str) -> Result<Url> {
+        let mut url = self.authority_host.clone();
+        url.path_segments_mut()
+            .map_err(|_| format_err!("invalid authority host: {}", self.authority_host))?
+            .pop_if_empty()
+            .extend(&[tenant, "oauth2", "v2.0", "token"]);
+
+        Ok(url)
+    }
+
+    /// The resource of key vault access tokens, such as `https://vault.azure.net`.
+    pub fn key_vault_resource(&self) -> String {
+        format!("https://{}", self.key_vault_suffix)
+    }
+
+    pub fn is_key_vault_url(&self, url: &Url) -> bool {
+        has_suffix(url, &self.key_vault_suffix)
+    }
+
+    /// Whether `url` is of a storage account of the cloud, of any service.
+    pub fn is_storage_url(&self, url: &Url) -> bool {
+        has_suffix(url, &self.storage_suffix)
+    }
+
+    /// The URL of the blob `name`, in `container` of the storage `account`.
+    pub fn blob_url(&self, account: &str, container: &str, name: &str) -> Result<Url> {
+        // format https://docs.microsoft.com/en-us/rest/api/storageservices/naming-and-referencing-containers--blobs--and-metadata#resource-uri-syntax
+        let url = Url::parse(&format!(
+            "https://{account}.blob.{}/{container}/{name}",
+            self.storage_suffix
+        ))?;
+        Ok(url)
+    }
+}
+
+impl Default for CloudEnvironment {
+    fn default() -> Self {
+        Self::public()
+    }
+}
+
+// Whether the host of `url` is a subdomain of `suffix`.
+fn has_suffix(url: &Url, suffix: &str) -> bool {
+    let Some(host) = url.host_str() else {
+        return false;
+    };
+
+    let host = host.to_ascii_lowercase();
+    let suffix = suffix.trim_matches('.').to_ascii_lowercase();
+
+    host.strip_suffix(&suffix)
+        .map(|name| name.len() > 1 && name.ends_with('.'))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(url: &str) -> Url {
+        Url::parse(url).unwrap()
+    }
+
+    #[test]
+    fn test_public() -> Result<()> {
+        let cloud: Cloud = serde_json::from_str(r#""public""#)?;
+        assert_eq!(cloud, Cloud::Public);
+        assert_eq!(cloud, Cloud::default());
+
+        let env = cloud.environment();
+        assert_eq!(
+            env.token_url("contoso")?.as_str(),
+            "https://login.microsoftonline.com/contoso/oauth2/v2.0/token"
+        );
+        assert_eq!(env.key_vault_resource(), "https://vault.azure.net");
+        assert!(env.is_key_vault_url(&url("https://kv.vault.azure.net/secrets/x")));
+        assert!(env.is_storage_url(&url("https://acct.queue.core.windows.net/q")));
+        assert_eq!(
+            env.blob_url("acct", "container", "a/b")?.as_str(),
+            "https://acct.blob.core.windows.net/container/a/b"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_us_gov() -> Result<()> {
+        let cloud: Cloud = serde_json::from_str(r#""usgov""#)?;
+        assert_eq!(cloud, Cloud::UsGov);
+
+        let env = cloud.environment();
+        assert_eq!(
+            env.token_url("contoso")?.as_str(),
+            "https://login.microsoftonline.us/contoso/oauth2/v2.0/token"
+        );
+        assert_eq!(env.key_vault_resource(), "https://vault.usgovcloudapi.net");
+        assert!(env.is_key_vault_url(&url("https://kv.vault.usgovcloudapi.net/secrets/x")));
+        assert!(env.is_storage_url(&url("https://acct.queue.core.usgovcloudapi.net/q")));
+        assert!(!env.is_storage_url(&url("https://acct.queue.core.windows.net/q")));
+        assert_eq!(
+            env.blob_url("acct", "container", "name")?.as_str(),
+            "https://acct.blob.core.usgovcloudapi.net/container/name"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_china() -> Result<()> {
+        let cloud: Cloud = serde_json::from_str(r#""china""#)?;
+        assert_eq!(cloud, Cloud::China);
+
+        let env = cloud.environment();
+        assert_eq!(
+            env.token_url("contoso")?.as_str(),
+            "https://login.chinacloudapi.cn/contoso/oauth2/v2.0/token"
+        );
+        assert_eq!(env.key_vault_resource(), "https://vault.azure.cn");
+        assert!(env.is_key_vault_url(&url("https://kv.vault.azure.cn/secrets/x")));
+        assert!(!env.is_key_vault_url(&url("https://kv.vault.azure.net/secrets/x")));
+        assert!(env.is_storage_url(&url("https://acct.blob.core.chinacloudapi.cn/c")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_custom_round_trip() -> Result<()> {
+        let json = r#"{
+            "custom": {
+                "authority_host": "https://login.contoso.local/adfs/",
+                "key_vault_suffix": "vault.contoso.local",
+                "storage_suffix": "contoso.local"
+            }
+        }"#;
+        let cloud: Cloud = serde_json::from_str(json)?;
+        assert_eq!(cloud.to_string(), "custom");
+
+        let env = cloud.environment();
+        assert_eq!(
+            env.token_url("tenant")?.as_str(),
+            "https://login.contoso.local/adfs/tenant/oauth2/v2.0/token"
+        );
+        assert_eq!(env.key_vault_resource(), "https://vault.contoso.local");
+        assert!(env.is_storage_url(&url("https://acct.blob.contoso.local/c")));
+
+        let serialized = serde_json::to_string(&cloud)?;
+        assert_eq!(serde_json::from_str::<Cloud>(&serialized)?, cloud);
+
+        // Each endpoint must be given.
+        let partial = r#"{"custom": {"authority_host": "https://login.contoso.local/"}}"#;
+        assert!(serde_json::from_str::<Cloud>(partial).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_str() -> Result<()> {
+        for cloud in [Cloud::Public, Cloud::UsGov, Cloud::China] {
+            assert_eq!(cloud.to_string().parse::<Cloud>()?, cloud);
+        }
+        assert!("custom".parse::<Cloud>().is_err());
+        assert!("azure".parse::<Cloud>().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_suffix_is_of_subdomains() {
+        let env = CloudEnvironment::public();
+
+        assert!(env.is_storage_url(&url("https://ACCT.blob.core.windows.net/c")));
+        assert!(!env.is_storage_url(&url("https://core.windows.net/c")));
+        assert!(!env.is_storage_url(&url("https://evilcore.windows.net/c")));
+        assert!(!env.is_storage_url(&url("https://acct.blob.core.windows.net.evil.com/c")));
+        assert!(!env.is_storage_url(&url("file:///tmp/c")));
+    }
+}
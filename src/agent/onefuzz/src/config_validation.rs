@@ -0,0 +1,403 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Checks of task configs which are independent of the task type.
+//!
+//! Run before a config is deserialized, so that every problem with it can be
+//! reported at once, instead of one field at a time.
+
+use std::{
+    fmt,
+    path::{Component, Path},
+};
+
+use serde_json::{Map, Value};
+use url::Url;
+
+use crate::blob::{url::redact_query_sas_sig, BlobContainerUrl};
+
+/// Fields naming the executables run by a task.
+const EXE_FIELDS: &[&str] = &[
+    "target_exe",
+    "supervisor_exe",
+    "generator_exe",
+    "analyzer_exe",
+    "minimizer_exe",
+];
+
+/// Fields naming the containers synced by a task.
+const CONTAINER_FIELDS: &[&str] = &[
+    "analysis",
+    "bisect_reports",
+    "builds",
+    "coverage",
+    "crash_dumps",
+    "crashdumps",
+    "crashes",
+    "dictionary",
+    "extra_output",
+    "inputs",
+    "leaks",
+    "merge_quarantine",
+    "minimized_crashes",
+    "no_repro",
+    "oom",
+    "readonly_inputs",
+    "regression_reports",
+    "reports",
+    "sources",
+    "tools",
+    "unique_inputs",
+    "unique_reports",
+];
+
+/// A single problem with a field of a task config.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Violation {
+    /// Path to the field, such as `readonly_inputs[0].url`.
+    pub field: String,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}` {}", self.field, self.message)
+    }
+}
+
+/// Every problem found with a task config.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ValidationError {
+    pub violations: Vec<Violation>,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid task config: ")?;
+
+        for (i, violation) in self.violations.iter().enumerate() {
+            if i > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{violation}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// Check a task config, as sent by the service.
+///
+/// Checks that container URLs are local paths or include a SAS token, that
+/// executables are absolute or setup-relative paths, and that timeouts and
+/// worker counts are in range. Worker counts above the number of CPUs are only
+/// logged as a warning. Fields with an unexpected type are skipped, and
+/// left to be reported by deserialization.
+pub fn validate_task_config(config: &Value) -> Result<(), ValidationError> {
+    let cpu_count = std::thread::available_parallelism()
+        .map(usize::from)
+        .unwrap_or(1);
+
+    let violations = violations(config, cpu_count);
+
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(ValidationError { violations })
+    }
+}
+
+fn violations(config: &Value, cpu_count: usize) -> Vec<Violation> {
+    let mut violations = vec![];
+
+    let Some(config) = config.as_object() else {
+        return violations;
+    };
+
+    for (field, value) in config {
+        let mut violation = |message: String| {
+            violations.push(Violation {
+                field: field.clone(),
+                message,
+            })
+        };
+
+        if EXE_FIELDS.contains(&field.as_str()) {
+            if let Some(path) = value.as_str() {
+                if let Err(message) = check_exe_path(path) {
+                    violation(message);
+                }
+            }
+        } else if field == "generators" {
+            // Each generator names its own executable.
+            for (i, generator) in value.as_array().into_iter().flatten().enumerate() {
+                let exe = generator.get("generator_exe").and_then(Value::as_str);
+                if let Some(Err(message)) = exe.map(check_exe_path) {
+                    violations.push(Violation {
+                        field: format!("{field}[{i}].generator_exe"),
+                        message,
+                    });
+                }
+            }
+        } else if field.ends_with("_timeout") {
+            if let Some(timeout) = value.as_f64() {
+                if timeout <= 0.0 {
+                    violation("must be greater than 0".to_owned());
+                }
+            }
+        } else if field == "target_workers" {
+            // 0 selects the task's default. More workers than CPUs only
+            // oversubscribes the node, so it is allowed, but worth a warning.
+            if let Some(workers) = value.as_f64() {
+                if workers < 0.0 {
+                    violation("must not be negative".to_owned());
+                } else if workers > cpu_count as f64 {
+                    warn!(
+                        "target_workers ({workers}) is more than the number of CPUs ({cpu_count})"
+                    );
+                }
+            }
+        } else if CONTAINER_FIELDS.contains(&field.as_str()) {
+            check_containers(field, value, &mut violations);
+        }
+    }
+
+    violations.sort_by(|a, b| a.field.cmp(&b.field));
+    violations
+}
+
+// Containers are objects with a local path and an optional remote URL, which
+// may appear alone or in a list.
+fn check_containers(field: &str, value: &Value, violations: &mut Vec<Violation>) {
+    match value {
+        Value::Object(container) if is_container(container) => {
+            for key in ["url", "remote_path"] {
+                if let Some(url) = container.get(key).and_then(Value::as_str) {
+                    if let Err(message) = check_container_url(url) {
+                        violations.push(Violation {
+                            field: format!("{field}.{key}"),
+                            message,
+                        });
+                    }
+                }
+            }
+        }
+        Value::Array(values) => {
+            for (i, value) in values.iter().enumerate() {
+                check_containers(&format!("{field}[{i}]"), value, violations);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn is_container(value: &Map<String, Value>) -> bool {
+    ["path", "local_path", "url", "remote_path"]
+        .iter()
+        .any(|key| value.contains_key(*key))
+}
+
+fn check_container_url(url: &str) -> Result<(), String> {
+    let url = Url::parse(url).map_err(|err| format!("is not a valid URL: {err}"))?;
+    let redacted = redact_query_sas_sig(&url);
+
+    match BlobContainerUrl::new(url) {
+        Ok(BlobContainerUrl::Path(_)) => Ok(()),
+        Ok(BlobContainerUrl::BlobContainer(url)) => {
+            if url.query_pairs().any(|(key, _)| key == "sig") {
+                Ok(())
+            } else {
+                Err(format!(
+                    "must include a SAS token, or be a local path: {redacted}"
+                ))
+            }
+        }
+        Err(_) => Err(format!("is not a blob container URL: {redacted}")),
+    }
+}
+
+fn check_exe_path(path: &str) -> Result<(), String> {
+    if path.is_empty() {
+        return Err("must not be empty".to_owned());
+    }
+
+    // Placeholders such as `{tools_dir}` are expanded when the task runs.
+    if path.contains('{') {
+        return Ok(());
+    }
+
+    let path = Path::new(path);
+    if path.is_absolute() {
+        return Ok(());
+    }
+
+    // A relative path must stay within the setup directory.
+    let mut depth = 0usize;
+    for component in path.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir if depth > 0 => depth -= 1,
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                return Err(format!(
+                    "must be an absolute path, or a path within the setup directory: {}",
+                    path.display()
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    const CPU_COUNT: usize = 4;
+
+    const SAS_URL: &str = "https://contoso.blob.core.windows.net/inputs?sv=2020&sig=secret";
+
+    fn check(config: Value) -> Vec<String> {
+        violations(&config, CPU_COUNT)
+            .into_iter()
+            .map(|v| v.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_fields() {
+        let cases = [
+            json!({ "target_exe": "fuzz.exe" }),
+            json!({ "target_exe": "bin/../fuzz.exe" }),
+            json!({ "target_exe": "./fuzz.exe" }),
+            json!({ "target_exe": "/setup/fuzz.exe" }),
+            json!({ "generator_exe": "{tools_dir}/radamsa" }),
+            json!({ "generators": [{ "name": "radamsa", "generator_exe": "{tools_dir}/radamsa" }] }),
+            json!({ "target_timeout": 30 }),
+            json!({ "target_timeout": null }),
+            json!({ "target_workers": 0 }),
+            json!({ "target_workers": 1 }),
+            json!({ "target_workers": CPU_COUNT }),
+            json!({ "target_workers": CPU_COUNT + 1 }),
+            json!({ "inputs": { "path": "inputs" } }),
+            json!({ "inputs": { "path": "inputs", "url": SAS_URL } }),
+            json!({ "inputs": { "local_path": "inputs", "remote_path": "file:///tmp/inputs" } }),
+            json!({ "readonly_inputs": [{ "path": "a", "url": SAS_URL }] }),
+            json!({ "input_queue": "https://contoso.queue.core.windows.net/queue" }),
+            json!({ "target_env": { "url": "not a container" } }),
+            json!({ "supervisor_env": { "path": "not a container" } }),
+            json!({ "target_options": [{ "url": "not a container" }] }),
+        ];
+
+        for config in cases {
+            assert_eq!(check(config.clone()), Vec::<String>::new(), "{config}");
+        }
+    }
+
+    #[test]
+    fn test_invalid_fields() {
+        let cases = [
+            (
+                json!({ "target_exe": "" }),
+                "`target_exe` must not be empty",
+            ),
+            (
+                json!({ "supervisor_exe": "../fuzz.exe" }),
+                "`supervisor_exe` must be an absolute path, or a path within the setup directory: ../fuzz.exe",
+            ),
+            (
+                json!({ "analyzer_exe": "bin/../../fuzz.exe" }),
+                "`analyzer_exe` must be an absolute path, or a path within the setup directory: bin/../../fuzz.exe",
+            ),
+            (
+                json!({ "generators": [{ "generator_exe": "gen" }, { "generator_exe": "../gen" }] }),
+                "`generators[1].generator_exe` must be an absolute path, or a path within the setup directory: ../gen",
+            ),
+            (
+                json!({ "target_timeout": 0 }),
+                "`target_timeout` must be greater than 0",
+            ),
+            (
+                json!({ "target_timeout": -5 }),
+                "`target_timeout` must be greater than 0",
+            ),
+            (
+                json!({ "target_workers": -1 }),
+                "`target_workers` must not be negative",
+            ),
+            (
+                json!({ "crashes": { "path": "crashes", "url": "not a url" } }),
+                "`crashes.url` is not a valid URL: relative URL without a base",
+            ),
+            (
+                json!({ "crashes": { "path": "crashes", "url": "http://contoso.com/crashes?sig=secret" } }),
+                "`crashes.url` is not a blob container URL: http://contoso.com/crashes?sig=REDACTED",
+            ),
+            (
+                json!({ "crashes": { "path": "crashes", "url": "https://contoso.blob.core.windows.net/crashes" } }),
+                "`crashes.url` must include a SAS token, or be a local path: https://contoso.blob.core.windows.net/crashes",
+            ),
+            (
+                json!({ "readonly_inputs": [{ "path": "a", "url": SAS_URL }, { "path": "b", "remote_path": "https://contoso.blob.core.windows.net/b" }] }),
+                "`readonly_inputs[1].remote_path` must include a SAS token, or be a local path: https://contoso.blob.core.windows.net/b",
+            ),
+        ];
+
+        for (config, expected) in cases {
+            assert_eq!(check(config.clone()), [expected], "{config}");
+        }
+    }
+
+    #[test]
+    fn test_unexpected_types_are_skipped() {
+        let config = json!({
+            "target_exe": 1,
+            "target_timeout": "30",
+            "target_workers": [],
+            "crashes": "crashes",
+        });
+
+        assert_eq!(check(config), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_violations_are_aggregated() {
+        let config = json!({
+            "target_exe": "../fuzz.exe",
+            "target_timeout": 0,
+            "crashes": { "path": "crashes", "url": "https://contoso.blob.core.windows.net/crashes" },
+        });
+
+        let err = ValidationError {
+            violations: violations(&config, CPU_COUNT),
+        };
+
+        assert_eq!(err.violations.len(), 3);
+        assert_eq!(
+            err.to_string(),
+            "invalid task config: \
+             `crashes.url` must include a SAS token, or be a local path: https://contoso.blob.core.windows.net/crashes; \
+             `target_exe` must be an absolute path, or a path within the setup directory: ../fuzz.exe; \
+             `target_timeout` must be greater than 0"
+        );
+    }
+
+    #[test]
+    fn test_validate_task_config() {
+        assert!(validate_task_config(&json!({ "target_exe": "fuzz.exe" })).is_ok());
+
+        let err = validate_task_config(&json!({ "target_timeout": 0 })).unwrap_err();
+        assert_eq!(
+            err.violations,
+            [Violation {
+                field: "target_timeout".to_owned(),
+                message: "must be greater than 0".to_owned(),
+            }]
+        );
+    }
+}
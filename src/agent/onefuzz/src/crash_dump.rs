@@ -0,0 +1,214 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Minidumps of crashes caught by the debugger on Windows, written to a
+//! directory of the task, whose total size is capped by evicting the oldest.
+
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::{Context, Result};
+use tokio::fs;
+
+/// Default cap of the total size of the dumps of a task, in bytes.
+pub const DEFAULT_MAX_TOTAL_SIZE: u64 = 1024 * 1024 * 1024;
+
+const DUMP_EXTENSION: &str = "dmp";
+
+pub fn default_max_total_size() -> u64 {
+    DEFAULT_MAX_TOTAL_SIZE
+}
+
+/// When a dump of a crashing process is written.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DumpTrigger {
+    /// At the first chance of the exception, before any handler of the target
+    /// runs. Exceptions which could be handled, such as access violations, are
+    /// dumped even if the target then handles them.
+    FirstChance,
+
+    /// At the last chance of the exception, once no handler of the target has
+    /// handled it.
+    #[default]
+    LastChance,
+}
+
+/// What a dump records, besides the threads, stacks and modules of the
+/// process.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DumpType {
+    #[default]
+    Mini,
+
+    /// All of the memory of the process, so that the heap can be inspected.
+    WithFullMemory,
+
+    /// The handles the process has open.
+    WithHandleData,
+}
+
+#[cfg(target_family = "windows")]
+impl From<DumpType> for input_tester::minidump::DumpType {
+    fn from(dump_type: DumpType) -> Self {
+        match dump_type {
+            DumpType::Mini => Self::Mini,
+            DumpType::WithFullMemory => Self::WithFullMemory,
+            DumpType::WithHandleData => Self::WithHandleData,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct CrashDumpConfig {
+    #[serde(default)]
+    pub trigger: DumpTrigger,
+
+    #[serde(default)]
+    pub dump_type: DumpType,
+
+    /// The cap of the total size of the dumps of the directory, in bytes. Once
+    /// exceeded, the oldest dumps are deleted.
+    #[serde(default = "default_max_total_size")]
+    pub max_total_size: u64,
+}
+
+impl Default for CrashDumpConfig {
+    fn default() -> Self {
+        Self {
+            trigger: DumpTrigger::default(),
+            dump_type: DumpType::default(),
+            max_total_size: DEFAULT_MAX_TOTAL_SIZE,
+        }
+    }
+}
+
+/// The name of the dump of a crash of the input with the hash `input_sha256`.
+pub fn dump_name(input_sha256: &str) -> String {
+    format!("{input_sha256}.{DUMP_EXTENSION}")
+}
+
+/// Delete the oldest dumps of `dir`, by when they were written, until the
+/// total size of those left is within `max_total_size`. Returns the dumps
+/// deleted.
+pub async fn evict_oldest(dir: &Path, max_total_size: u64) -> Result<Vec<PathBuf>> {
+    let mut dumps = vec![];
+    let mut entries = fs::read_dir(dir)
+        .await
+        .with_context(|| format!("unable to list crash dumps: {}", dir.display()))?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if path.extension().map_or(true, |ext| ext != DUMP_EXTENSION) {
+            continue;
+        }
+
+        let metadata = entry.metadata().await?;
+        if metadata.is_file() {
+            dumps.push((metadata.modified()?, metadata.len(), path));
+        }
+    }
+
+    let evicted = evictions(dumps, max_total_size);
+    for path in &evicted {
+        fs::remove_file(path)
+            .await
+            .with_context(|| format!("unable to delete crash dump: {}", path.display()))?;
+    }
+
+    Ok(evicted)
+}
+
+// The oldest of `dumps`, of when each was written, its size and its path,
+// whose eviction leaves a total size within `max_total_size`.
+fn evictions(mut dumps: Vec<(SystemTime, u64, PathBuf)>, max_total_size: u64) -> Vec<PathBuf> {
+    dumps.sort();
+
+    let mut total: u64 = dumps.iter().map(|(_, size, _)| size).sum();
+    let mut evicted = vec![];
+    for (_, size, path) in dumps {
+        if total <= max_total_size {
+            break;
+        }
+
+        total -= size;
+        evicted.push(path);
+    }
+
+    evicted
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn dump(secs: u64, size: u64, name: &str) -> (SystemTime, u64, PathBuf) {
+        (
+            SystemTime::UNIX_EPOCH + Duration::from_secs(secs),
+            size,
+            PathBuf::from(name),
+        )
+    }
+
+    #[test]
+    fn test_evictions_are_oldest_first() {
+        let dumps = vec![
+            dump(30, 100, "c.dmp"),
+            dump(10, 100, "a.dmp"),
+            dump(20, 100, "b.dmp"),
+        ];
+
+        assert!(evictions(dumps.clone(), 300).is_empty());
+        assert_eq!(evictions(dumps.clone(), 299), ["a.dmp"].map(PathBuf::from));
+        assert_eq!(
+            evictions(dumps.clone(), 100),
+            ["a.dmp", "b.dmp"].map(PathBuf::from)
+        );
+
+        // A dump larger than the cap isn't kept either.
+        assert_eq!(
+            evictions(dumps, 99),
+            ["a.dmp", "b.dmp", "c.dmp"].map(PathBuf::from)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_evict_oldest() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        for name in ["old.dmp", "new.dmp"] {
+            std::fs::write(dir.path().join(name), [0; 64])?;
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        std::fs::write(dir.path().join("other.txt"), [0; 64])?;
+
+        assert!(evict_oldest(dir.path(), 128).await?.is_empty());
+
+        let evicted = evict_oldest(dir.path(), 100).await?;
+        assert_eq!(evicted, [dir.path().join("old.dmp")]);
+        assert!(dir.path().join("new.dmp").exists());
+        assert!(dir.path().join("other.txt").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_defaults() -> Result<()> {
+        let config: CrashDumpConfig = serde_json::from_str("{}")?;
+        assert_eq!(config, CrashDumpConfig::default());
+        assert_eq!(config.trigger, DumpTrigger::LastChance);
+        assert_eq!(config.dump_type, DumpType::Mini);
+
+        let config: CrashDumpConfig = serde_json::from_str(
+            r#"{"trigger": "first_chance", "dump_type": "with_full_memory", "max_total_size": 1}"#,
+        )?;
+        assert_eq!(config.trigger, DumpTrigger::FirstChance);
+        assert_eq!(config.dump_type, DumpType::WithFullMemory);
+        assert_eq!(config.max_total_size, 1);
+
+        assert_eq!(dump_name("abc"), "abc.dmp");
+
+        Ok(())
+    }
+}
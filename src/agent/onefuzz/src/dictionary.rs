@@ -0,0 +1,276 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! libFuzzer dictionaries, as passed with `-dict`.
+//!
+//! Each line is either blank, a `#` comment, or an entry: an optional name and
+//! `=`, then a quoted string, such as `kw1="if"`. Within the quotes, `\\`,
+//! `\"` and `\xAB` are escapes, and every other byte must be printable.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::fs::list_files;
+
+/// Extension of the dictionaries chosen from a container.
+pub const DICTIONARY_EXTENSION: &str = "dict";
+
+/// Parse the entries of a dictionary, failing as libFuzzer would.
+pub fn parse(data: &[u8]) -> Result<Vec<Vec<u8>>> {
+    if data.is_empty() {
+        bail!("dictionary is empty");
+    }
+
+    let mut entries = vec![];
+
+    for (i, line) in data.split(|b| *b == b'\n').enumerate() {
+        let text = line.trim_ascii_start();
+        if text.is_empty() || text.starts_with(b"#") {
+            continue;
+        }
+
+        match parse_entry(line) {
+            Some(entry) => entries.push(entry),
+            None => bail!(
+                "invalid dictionary entry on line {}: {}",
+                i + 1,
+                String::from_utf8_lossy(line)
+            ),
+        }
+    }
+
+    if entries.is_empty() {
+        bail!("dictionary has no entries");
+    }
+
+    Ok(entries)
+}
+
+// The bytes within the quotes of an entry, with escapes replaced.
+fn parse_entry(line: &[u8]) -> Option<Vec<u8>> {
+    let line = line.trim_ascii();
+
+    // The name before the opening quote, if any, is not checked.
+    let quoted = line.strip_suffix(b"\"")?;
+    let start = quoted.iter().position(|b| *b == b'"')?;
+
+    // Empty entries are invalid.
+    let body = &quoted[start + 1..];
+    if body.is_empty() {
+        return None;
+    }
+
+    let mut body = body.iter().copied();
+
+    let mut entry = vec![];
+    while let Some(b) = body.next() {
+        if !(b.is_ascii_graphic() || b.is_ascii_whitespace()) {
+            return None;
+        }
+
+        if b != b'\\' {
+            entry.push(b);
+            continue;
+        }
+
+        match body.next()? {
+            escaped @ (b'\\' | b'"') => entry.push(escaped),
+            b'x' => {
+                let hex = [body.next()?, body.next()?];
+                if !hex.iter().all(u8::is_ascii_hexdigit) {
+                    return None;
+                }
+                let hex = std::str::from_utf8(&hex).ok()?;
+                entry.push(u8::from_str_radix(hex, 16).ok()?);
+            }
+            _ => return None,
+        }
+    }
+
+    Some(entry)
+}
+
+/// Choose a dictionary from `dir`: the file `name`, if given, or else the most
+/// recently modified `*.dict` file. The dictionary is checked to be valid.
+///
+/// If `name` is not given and there is no `*.dict` file, returns `None`, so
+/// that fuzzing can proceed without a dictionary.
+pub async fn find_dictionary(dir: &Path, name: Option<&str>) -> Result<Option<PathBuf>> {
+    let path = match name {
+        Some(name) => dir.join(name),
+        None => {
+            let mut newest = None;
+
+            for file in list_files(dir).await? {
+                if file.extension() != Some(DICTIONARY_EXTENSION.as_ref()) {
+                    continue;
+                }
+
+                let modified = tokio::fs::metadata(&file).await?.modified()?;
+                if newest.as_ref().is_none_or(|(time, _)| modified > *time) {
+                    newest = Some((modified, file));
+                }
+            }
+
+            match newest {
+                Some((_, file)) => file,
+                None => {
+                    warn!(
+                        "no dictionary found, fuzzing without one: no *.{} files in {}",
+                        DICTIONARY_EXTENSION,
+                        dir.display()
+                    );
+                    return Ok(None);
+                }
+            }
+        }
+    };
+
+    let data = tokio::fs::read(&path)
+        .await
+        .with_context(|| format!("unable to read dictionary: {}", path.display()))?;
+    parse(&data).with_context(|| format!("invalid dictionary: {}", path.display()))?;
+
+    Ok(Some(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::*;
+
+    #[test]
+    fn test_parse() -> Result<()> {
+        let data = br#"
+# Lines starting with '#' and empty lines are ignored.
+
+# Adds "blah" (w/o quotes) to the dictionary.
+kw1="blah"
+# Use \\ for backslash and \" for quotes.
+kw2="\"ac\\dc\""
+# Use \xAB for hex values
+kw3="\xF7\xF8"
+# the name of the keyword followed by '=' may be omitted:
+"foo\x0Abar"
+  kw4 = "  spaced  "
+crlf="line"
+"#;
+        let data = [&data[..], b"crlf2=\"end\"\r\n"].concat();
+
+        let entries = parse(&data)?;
+        assert_eq!(
+            entries,
+            [
+                b"blah".to_vec(),
+                b"\"ac\\dc\"".to_vec(),
+                b"\xF7\xF8".to_vec(),
+                b"foo\nbar".to_vec(),
+                b"  spaced  ".to_vec(),
+                b"line".to_vec(),
+                b"end".to_vec(),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        let cases: &[(&[u8], &str)] = &[
+            (b"", "dictionary is empty"),
+            (b"# only a comment\n\n", "dictionary has no entries"),
+            (
+                b"kw1=\"ok\"\nkw2=blah\n",
+                "invalid dictionary entry on line 2: kw2=blah",
+            ),
+            (
+                b"kw=\"unterminated\n",
+                "invalid dictionary entry on line 1: kw=\"unterminated",
+            ),
+            (
+                b"kw=\"\\q\"",
+                "invalid dictionary entry on line 1: kw=\"\\q\"",
+            ),
+            (
+                b"kw=\"\\xZZ\"",
+                "invalid dictionary entry on line 1: kw=\"\\xZZ\"",
+            ),
+            (
+                b"kw=\"\\x4\"",
+                "invalid dictionary entry on line 1: kw=\"\\x4\"",
+            ),
+            (
+                b"kw=\"\x01\"",
+                "invalid dictionary entry on line 1: kw=\"\x01\"",
+            ),
+            (b"\"\"", "invalid dictionary entry on line 1: \"\""),
+            (b"kw=\"\"", "invalid dictionary entry on line 1: kw=\"\""),
+            (
+                b"kw=\"\\x+F\"",
+                "invalid dictionary entry on line 1: kw=\"\\x+F\"",
+            ),
+        ];
+
+        for (data, expected) in cases {
+            let err = parse(data).unwrap_err();
+            assert_eq!(err.to_string(), *expected, "{data:?}");
+        }
+    }
+
+    fn write_dictionary(path: &Path, data: &str, age: Duration) -> Result<()> {
+        std::fs::write(path, data)?;
+        let file = std::fs::File::options().write(true).open(path)?;
+        file.set_modified(SystemTime::now() - age)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_find_dictionary() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let old = dir.path().join("old.dict");
+        let new = dir.path().join("new.dict");
+        let other = dir.path().join("newest.txt");
+        write_dictionary(&old, "kw=\"old\"\n", Duration::from_secs(120))?;
+        write_dictionary(&new, "kw=\"new\"\n", Duration::from_secs(60))?;
+        write_dictionary(&other, "not a dictionary\n", Duration::ZERO)?;
+
+        assert_eq!(find_dictionary(dir.path(), None).await?, Some(new));
+        assert_eq!(
+            find_dictionary(dir.path(), Some("old.dict")).await?,
+            Some(old)
+        );
+
+        let err = find_dictionary(dir.path(), Some("newest.txt"))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            format!("{err:#}"),
+            format!(
+                "invalid dictionary: {}: invalid dictionary entry on line 1: not a dictionary",
+                other.display()
+            )
+        );
+
+        let err = find_dictionary(dir.path(), Some("missing.dict"))
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string().starts_with("unable to read dictionary: "),
+            "{err}"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_find_dictionary_empty_container() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        std::fs::write(dir.path().join("README.md"), "no dictionaries here")?;
+
+        assert_eq!(find_dictionary(dir.path(), None).await?, None);
+
+        Ok(())
+    }
+}
@@ -8,6 +8,22 @@ use std::path::PathBuf;
 pub const PATH: &str = "PATH";
 pub const LD_LIBRARY_PATH: &str = "LD_LIBRARY_PATH";
 
+/// Variables listing libraries for the dynamic linker to preload. Entries are
+/// separated by colons or whitespace.
+const PRELOAD_VARS: &[&str] = &["LD_PRELOAD", "AFL_PRELOAD"];
+
+/// Variables listing AFL++ custom mutator libraries, separated by semicolons.
+const MUTATOR_VARS: &[&str] = &["AFL_CUSTOM_MUTATOR_LIBRARY"];
+
+/// Sanitizer option variables, whose `suppressions` option names a file.
+const SANITIZER_OPTIONS_VARS: &[&str] = &[
+    "ASAN_OPTIONS",
+    "LSAN_OPTIONS",
+    "MSAN_OPTIONS",
+    "TSAN_OPTIONS",
+    "UBSAN_OPTIONS",
+];
+
 #[allow(clippy::ptr_arg)]
 pub fn update_path(path: OsString, to_add: &PathBuf) -> Result<OsString> {
     let mut paths: Vec<_> = std::env::split_paths(&path).collect();
@@ -24,3 +40,144 @@ pub fn get_path_with_directory(variable: &str, to_add: &PathBuf) -> Result<OsStr
         None => Ok(to_add.clone().into()),
     }
 }
+
+/// The files named by the value of the environment variable `name`, for the
+/// variables known to reference files, such as `LD_PRELOAD`.
+///
+/// Only absolute paths are returned. Other entries are either found by the
+/// dynamic linker, or depend on the working directory of the target.
+pub fn env_file_paths(name: &str, value: &str) -> Vec<PathBuf> {
+    let paths: Vec<String> = if PRELOAD_VARS.contains(&name) {
+        value
+            .split(|c: char| c == ':' || c.is_whitespace())
+            .map(String::from)
+            .collect()
+    } else if MUTATOR_VARS.contains(&name) {
+        value.split(';').map(String::from).collect()
+    } else if SANITIZER_OPTIONS_VARS.contains(&name) {
+        sanitizer_options(value)
+            .into_iter()
+            .filter(|(option, _)| option == "suppressions")
+            .map(|(_, path)| path)
+            .collect()
+    } else {
+        vec![]
+    };
+
+    paths
+        .into_iter()
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .collect()
+}
+
+// Parse sanitizer options as the sanitizer runtimes do: `name=value` pairs,
+// separated by spaces, commas, colons, tabs or newlines. Values may be quoted,
+// such as to include a Windows path.
+pub(crate) fn sanitizer_options(options: &str) -> Vec<(String, String)> {
+    let mut parsed = vec![];
+    let mut name = String::new();
+    let mut value = String::new();
+    let mut in_value = false;
+    let mut quote = None;
+
+    for c in options.chars() {
+        if let Some(q) = quote {
+            if c == q {
+                quote = None;
+            } else if in_value {
+                value.push(c);
+            } else {
+                name.push(c);
+            }
+            continue;
+        }
+
+        match c {
+            '\'' | '"' => quote = Some(c),
+            ' ' | ',' | ':' | '\t' | '\n' => {
+                if !name.is_empty() {
+                    parsed.push((std::mem::take(&mut name), std::mem::take(&mut value)));
+                }
+                in_value = false;
+            }
+            '=' if !in_value => in_value = true,
+            _ if in_value => value.push(c),
+            _ => name.push(c),
+        }
+    }
+
+    if !name.is_empty() {
+        parsed.push((name, value));
+    }
+
+    parsed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(target_family = "unix")]
+    fn test_env_file_paths() {
+        let cases = [
+            ("LD_PRELOAD", "/setup/libhook.so", vec!["/setup/libhook.so"]),
+            (
+                "LD_PRELOAD",
+                "/setup/liba.so:/setup/libb.so libc.so /setup/libc.so",
+                vec!["/setup/liba.so", "/setup/libb.so", "/setup/libc.so"],
+            ),
+            ("LD_PRELOAD", "libjemalloc.so", vec![]),
+            (
+                "AFL_PRELOAD",
+                "/setup/libdesock.so",
+                vec!["/setup/libdesock.so"],
+            ),
+            (
+                "AFL_CUSTOM_MUTATOR_LIBRARY",
+                "/setup/a.so;/setup/b.so",
+                vec!["/setup/a.so", "/setup/b.so"],
+            ),
+            (
+                "ASAN_OPTIONS",
+                "detect_leaks=0:suppressions=/setup/asan.supp:allocator_may_return_null=1",
+                vec!["/setup/asan.supp"],
+            ),
+            (
+                "LSAN_OPTIONS",
+                "suppressions='/setup/lsan supp.txt',print_suppressions=0",
+                vec!["/setup/lsan supp.txt"],
+            ),
+            (
+                "TSAN_OPTIONS",
+                "halt_on_error=1 suppressions=/setup/tsan.supp",
+                vec!["/setup/tsan.supp"],
+            ),
+            ("UBSAN_OPTIONS", "print_stacktrace=1", vec![]),
+            ("ASAN_OPTIONS", "suppressions=asan.supp", vec![]),
+            ("TARGET_CONFIG", "/setup/config.json", vec![]),
+        ];
+
+        for (name, value, expected) in cases {
+            let expected: Vec<PathBuf> = expected.into_iter().map(PathBuf::from).collect();
+            assert_eq!(env_file_paths(name, value), expected, "{name}={value}");
+        }
+    }
+
+    #[test]
+    fn test_sanitizer_options() {
+        let options = sanitizer_options(" a=1,b='x:y' c=\"z\"\td:e=");
+
+        assert_eq!(
+            options,
+            [
+                ("a".to_owned(), "1".to_owned()),
+                ("b".to_owned(), "x:y".to_owned()),
+                ("c".to_owned(), "z".to_owned()),
+                ("d".to_owned(), "".to_owned()),
+                ("e".to_owned(), "".to_owned()),
+            ]
+        );
+    }
+}
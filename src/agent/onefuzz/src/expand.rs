@@ -1,7 +1,7 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
-use crate::{machine_id::MachineIdentity, sha256::digest_file_blocking};
+use crate::{blob::BlobUrl, machine_id::MachineIdentity, sha256::digest_file_blocking};
 use anyhow::{format_err, Context, Result};
 use onefuzz_telemetry::{InstanceTelemetryKey, MicrosoftTelemetryKey};
 use regex::Regex;
@@ -11,6 +11,10 @@ use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
 use uuid::Uuid;
 
+/// Default limit on how deeply placeholder values may reference other
+/// placeholders, see [`Expand::max_depth`].
+pub const DEFAULT_MAX_EXPANSION_DEPTH: usize = 16;
+
 pub enum ExpandedValue<'a> {
     Path(String),
     Scalar(String),
@@ -34,6 +38,7 @@ pub enum PlaceHolder {
     OutputDir,
     InputFileNameNoExt,
     InputFileName,
+    InputFileStem,
     RuntimeDir,
     ToolsDir,
     CoverageDir,
@@ -48,11 +53,17 @@ pub enum PlaceHolder {
     JobId,
     TaskId,
     MachineId,
+    CpuCount,
+    RamMb,
+    Os,
     CrashesContainer,
     CrashesAccount,
+    CrashContainer,
+    CrashName,
     MicrosoftTelemetryKey,
     InstanceTelemetryKey,
     InputFileSha256,
+    MinimizedInput,
 }
 
 impl PlaceHolder {
@@ -70,6 +81,7 @@ impl PlaceHolder {
             Self::OutputDir => "{output_dir}",
             Self::InputFileNameNoExt => "{input_file_name_no_ext}",
             Self::InputFileName => "{input_file_name}",
+            Self::InputFileStem => "{input_file_stem}",
             Self::RuntimeDir => "{runtime_dir}",
             Self::ToolsDir => "{tools_dir}",
             Self::CoverageDir => "{coverage_dir}",
@@ -84,11 +96,17 @@ impl PlaceHolder {
             Self::JobId => "{job_id}",
             Self::TaskId => "{task_id}",
             Self::MachineId => "{machine_id}",
+            Self::CpuCount => "{cpu_count}",
+            Self::RamMb => "{ram_mb}",
+            Self::Os => "{os}",
             Self::CrashesContainer => "{crashes_container}",
             Self::CrashesAccount => "{crashes_account}",
+            Self::CrashContainer => "{crash_container}",
+            Self::CrashName => "{crash_name}",
             Self::MicrosoftTelemetryKey => "{microsoft_telemetry_key}",
             Self::InstanceTelemetryKey => "{instance_telemetry_key}",
             Self::InputFileSha256 => "{input_file_sha256}",
+            Self::MinimizedInput => "{minimized_input}",
         }
     }
 }
@@ -96,11 +114,37 @@ impl PlaceHolder {
 pub struct Expand<'a> {
     values: HashMap<&'static str, ExpandedValue<'a>>,
     machine_identity: &'a MachineIdentity,
+    allow_unknown_placeholders: bool,
+    allow_env_placeholders: bool,
+    max_depth: usize,
 }
 
 impl<'a> Expand<'a> {
+    /// Creates an expander with the node-level placeholders (`{machine_id}`,
+    /// `{cpu_count}`, `{ram_mb}` and `{os}`) and the `{input}`-derived
+    /// placeholders already defined.
+    ///
+    /// These defaults are only fallbacks: a value set explicitly for the same
+    /// placeholder (via `set_value` or one of the named setters) replaces the
+    /// default, regardless of the order of the calls.
     pub fn new(machine_identity: &'a MachineIdentity) -> Self {
         let mut values = HashMap::new();
+        values.insert(
+            PlaceHolder::MachineId.get_string(),
+            ExpandedValue::Mapping(Box::new(Expand::machine_id_value)),
+        );
+        values.insert(
+            PlaceHolder::CpuCount.get_string(),
+            ExpandedValue::Mapping(Box::new(Expand::cpu_count_value)),
+        );
+        values.insert(
+            PlaceHolder::RamMb.get_string(),
+            ExpandedValue::Mapping(Box::new(Expand::ram_mb_value)),
+        );
+        values.insert(
+            PlaceHolder::Os.get_string(),
+            ExpandedValue::Scalar(std::env::consts::OS.to_string()),
+        );
         values.insert(
             PlaceHolder::InputFileNameNoExt.get_string(),
             ExpandedValue::Mapping(Box::new(Expand::extract_file_name_no_ext)),
@@ -109,6 +153,10 @@ impl<'a> Expand<'a> {
             PlaceHolder::InputFileName.get_string(),
             ExpandedValue::Mapping(Box::new(Expand::extract_file_name)),
         );
+        values.insert(
+            PlaceHolder::InputFileStem.get_string(),
+            ExpandedValue::Mapping(Box::new(Expand::extract_file_stem)),
+        );
         values.insert(
             PlaceHolder::InputFileSha256.get_string(),
             ExpandedValue::Mapping(Box::new(Expand::input_file_sha256)),
@@ -117,6 +165,35 @@ impl<'a> Expand<'a> {
         Self {
             values,
             machine_identity,
+            allow_unknown_placeholders: false,
+            allow_env_placeholders: false,
+            max_depth: DEFAULT_MAX_EXPANSION_DEPTH,
+        }
+    }
+
+    /// Limits how many placeholders may be nested inside each other while
+    /// evaluating a single value, e.g. `{supervisor_options}` containing
+    /// `{target_options}` containing `{input}` has a depth of 3.
+    ///
+    /// Path and list values are expanded recursively, scalar values are used
+    /// as-is. Since values are resolved when they are referenced rather than
+    /// when they are set, the result does not depend on the order in which the
+    /// values were set.
+    pub fn max_depth(self, value: usize) -> Self {
+        Self {
+            max_depth: value,
+            ..self
+        }
+    }
+
+    /// Restores the legacy behavior of leaving unrecognized placeholders in
+    /// the expanded string verbatim, instead of failing the expansion.
+    ///
+    /// Placeholders that are known but have no value set are still errors.
+    pub fn allow_unknown_placeholders(self, value: bool) -> Self {
+        Self {
+            allow_unknown_placeholders: value,
+            ..self
         }
     }
 
@@ -126,6 +203,24 @@ impl<'a> Expand<'a> {
         self.set_value(PlaceHolder::MachineId, ExpandedValue::Scalar(value))
     }
 
+    fn machine_id_value(&self) -> Result<ExpandedValue<'a>> {
+        let value = self.machine_identity.machine_id.to_string();
+        Ok(ExpandedValue::Scalar(value))
+    }
+
+    fn cpu_count_value(&self) -> Result<ExpandedValue<'a>> {
+        let count = std::thread::available_parallelism()
+            .context("unable to determine the number of cpus")?;
+        Ok(ExpandedValue::Scalar(count.to_string()))
+    }
+
+    fn ram_mb_value(&self) -> Result<ExpandedValue<'a>> {
+        const BYTES_PER_MB: u64 = 1024 * 1024;
+
+        let total = crate::memory::total_bytes().context("unable to determine total memory")?;
+        Ok(ExpandedValue::Scalar((total / BYTES_PER_MB).to_string()))
+    }
+
     fn input_file_sha256(&self) -> Result<ExpandedValue<'a>> {
         let Some(val) = self.values.get(PlaceHolder::Input.get_string()) else {
             bail!(
@@ -149,18 +244,28 @@ impl<'a> Expand<'a> {
     }
 
     fn extract_file_name_no_ext(&self) -> Result<ExpandedValue<'a>> {
+        self.input_file_stem(PlaceHolder::InputFileNameNoExt)
+    }
+
+    fn extract_file_stem(&self) -> Result<ExpandedValue<'a>> {
+        self.input_file_stem(PlaceHolder::InputFileStem)
+    }
+
+    // `{input_file_stem}` and `{input_file_name_no_ext}` are the same value,
+    // but errors name the placeholder which was used.
+    fn input_file_stem(&self, placeholder: PlaceHolder) -> Result<ExpandedValue<'a>> {
         let Some(val) = self.values.get(PlaceHolder::Input.get_string()) else {
             bail!(
                 "no value found for {}, unable to evaluate {}",
                 PlaceHolder::Input.get_string(),
-                PlaceHolder::InputFileNameNoExt.get_string(),
+                placeholder.get_string(),
             )
         };
 
         let ExpandedValue::Path(fp) = val else {
             bail!(
                 "{} must be used with a path value for {}",
-                PlaceHolder::InputFileNameNoExt.get_string(),
+                placeholder.get_string(),
                 PlaceHolder::Input.get_string()
             )
         };
@@ -198,13 +303,51 @@ impl<'a> Expand<'a> {
         Ok(ExpandedValue::Scalar(name_as_str))
     }
 
+    /// Enables `${env:NAME}` and `${env:NAME:-default}` references, which are
+    /// replaced with the value of `NAME` in the environment of the current
+    /// process.
+    ///
+    /// This exposes the node's environment to whoever controls the expanded
+    /// strings, so it is off by default.
+    pub fn allow_env_placeholders(self, value: bool) -> Self {
+        Self {
+            allow_env_placeholders: value,
+            ..self
+        }
+    }
+
+    fn env_value(&self, reference: &str) -> Result<String> {
+        if !self.allow_env_placeholders {
+            bail!("environment variable references are not enabled for this task: {reference}");
+        }
+
+        let body = reference
+            .strip_prefix("${env:")
+            .and_then(|r| r.strip_suffix('}'))
+            .ok_or_else(|| format_err!("invalid environment variable reference: {reference}"))?;
+
+        let (name, default) = match body.split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (body, None),
+        };
+
+        if name.is_empty() {
+            bail!("invalid environment variable reference: {reference}");
+        }
+
+        match (std::env::var(name), default) {
+            (Ok(value), _) => Ok(value),
+            (Err(std::env::VarError::NotPresent), Some(default)) => Ok(default.to_string()),
+            (Err(e), _) => Err(e).with_context(|| {
+                format!("unable to read environment variable {name} for {reference}")
+            }),
+        }
+    }
+
     pub fn set_value(self, name: PlaceHolder, value: ExpandedValue<'a>) -> Self {
         let mut values = self.values;
         values.insert(name.get_string(), value);
-        Self {
-            values,
-            machine_identity: self.machine_identity,
-        }
+        Self { values, ..self }
     }
 
     pub fn set_optional_ref<'l, T: 'l>(
@@ -251,6 +394,12 @@ impl<'a> Expand<'a> {
         self.set_value(PlaceHolder::Input, ExpandedValue::Path(path))
     }
 
+    pub fn minimized_input(self, arg: impl AsRef<Path>) -> Self {
+        let arg = arg.as_ref();
+        let path = String::from(arg.to_string_lossy());
+        self.set_value(PlaceHolder::MinimizedInput, ExpandedValue::Path(path))
+    }
+
     pub fn input_marker(self, arg: &str) -> Self {
         self.set_value(PlaceHolder::Input, ExpandedValue::Scalar(String::from(arg)))
     }
@@ -375,6 +524,21 @@ impl<'a> Expand<'a> {
         )
     }
 
+    pub fn cpu_count(self, arg: usize) -> Self {
+        self.set_value(
+            PlaceHolder::CpuCount,
+            ExpandedValue::Scalar(arg.to_string()),
+        )
+    }
+
+    pub fn ram_mb(self, arg: u64) -> Self {
+        self.set_value(PlaceHolder::RamMb, ExpandedValue::Scalar(arg.to_string()))
+    }
+
+    pub fn os(self, arg: &str) -> Self {
+        self.set_value(PlaceHolder::Os, ExpandedValue::Scalar(String::from(arg)))
+    }
+
     pub fn crashes_account(self, arg: &str) -> Self {
         self.set_value(
             PlaceHolder::CrashesAccount,
@@ -389,6 +553,31 @@ impl<'a> Expand<'a> {
         )
     }
 
+    pub fn crash_container(self, arg: &str) -> Self {
+        self.set_value(
+            PlaceHolder::CrashContainer,
+            ExpandedValue::Scalar(String::from(arg)),
+        )
+    }
+
+    pub fn crash_name(self, arg: &str) -> Self {
+        self.set_value(
+            PlaceHolder::CrashName,
+            ExpandedValue::Scalar(String::from(arg)),
+        )
+    }
+
+    /// Sets `{crash_container}` and `{crash_name}` from the blob that the
+    /// current input was downloaded from.
+    ///
+    /// Local files have no container, so only `{crash_name}` is set for them.
+    pub fn crash_blob(self, blob: &BlobUrl) -> Self {
+        self.set_optional(blob.container(), |expand, container| {
+            expand.crash_container(&container)
+        })
+        .crash_name(&blob.name())
+    }
+
     fn get_value(
         &self,
         ev: &ExpandedValue<'a>,
@@ -421,7 +610,10 @@ impl<'a> Expand<'a> {
         eval_stack: &mut Vec<&'static str>,
     ) -> Result<String> {
         lazy_static::lazy_static! {
-            static ref VAR_RE: Regex = Regex::new(r"\{[^}]+?\}").unwrap();
+            // `${env:...}` is an environment variable reference, `{{...}}` is an
+            // escaped literal, `{...}` is a placeholder
+            static ref VAR_RE: Regex =
+                Regex::new(r"\$\{env:[^}]*\}|\{\{[^{}]*\}\}|\{[^}]+?\}").unwrap();
         }
 
         let arg = arg.as_ref().to_owned();
@@ -429,16 +621,41 @@ impl<'a> Expand<'a> {
 
         let result = VAR_RE.replace_all(&arg, |captures: &regex::Captures<'_>| -> String {
             let matched = captures.get(0).unwrap().as_str(); // capture 0 must always be present here
+            if matched.starts_with("${env:") {
+                return match self.env_value(matched) {
+                    Ok(v) => v,
+                    Err(e) => {
+                        errors.push(format!("{e:#}"));
+                        String::new()
+                    }
+                };
+            }
+
+            if let Some(literal) = matched.strip_prefix('{').and_then(|m| m.strip_suffix('}')) {
+                if literal.starts_with('{') && literal.ends_with('}') {
+                    return literal.to_string();
+                }
+            }
+
             match self.values.get_key_value(matched) {
                 Some((placeholder, ev)) => {
                     if eval_stack.contains(placeholder) {
                         eval_stack.push(placeholder);
-                        let path = eval_stack.join("->");
+                        let path = eval_stack.join(" -> ");
                         errors.push(format!(
                             "attempting to replace {placeholder} with a value that contains itself (replacements {path})"
                         ));
                         eval_stack.pop();
                         String::new()
+                    } else if eval_stack.len() >= self.max_depth {
+                        eval_stack.push(placeholder);
+                        let path = eval_stack.join(" -> ");
+                        errors.push(format!(
+                            "exceeded the maximum expansion depth of {} while replacing {placeholder} (replacements {path})",
+                            self.max_depth
+                        ));
+                        eval_stack.pop();
+                        String::new()
                     } else {
                         eval_stack.push(placeholder);
                         let result = self.get_value(ev, eval_stack)
@@ -457,12 +674,15 @@ impl<'a> Expand<'a> {
                 None => {
                     if PlaceHolder::iter().any(|v| v.get_string() == matched) {
                         // this is a known replacement but no value is defined:
-                        errors.push(format!("replacement {matched} is not available"))
+                        errors.push(format!("replacement {matched} is not available"));
+                        String::new()
+                    } else if self.allow_unknown_placeholders {
+                        matched.to_string()
                     } else {
                         // probably a typo, we don't know this placeholder:
-                        errors.push(format!("unknown variable replacement {matched}"))
+                        errors.push(format!("unknown variable replacement {matched} in `{arg}`"));
+                        String::new()
                     }
-                    String::new()
                 }
             }
         });
@@ -483,14 +703,28 @@ impl<'a> Expand<'a> {
         args: &[impl AsRef<str>],
         eval_stack: &mut Vec<&'static str>,
     ) -> Result<Vec<String>> {
+        // evaluate every argument before failing, so that all of the problems
+        // are reported at once
         let mut result = Vec::new();
+        let mut errors = Vec::new();
         for arg in args {
-            let arg = self
+            match self
                 .evaluate_value_checked(arg, eval_stack)
-                .with_context(|| format!("evaluating argument failed: {}", arg.as_ref()))?;
-            result.push(arg);
+                .with_context(|| format!("evaluating argument failed: {}", arg.as_ref()))
+            {
+                Ok(arg) => result.push(arg),
+                Err(e) => errors.push(e),
+            }
+        }
+
+        match errors.len() {
+            0 => Ok(result),
+            1 => Err(errors.remove(0)),
+            _ => {
+                let errors: Vec<_> = errors.iter().map(|e| format!("{e:#}")).collect();
+                bail!(errors.join("; "))
+            }
         }
-        Ok(result)
     }
 
     pub fn evaluate(&self, args: &[impl AsRef<str>]) -> Result<Vec<String>> {
@@ -500,7 +734,7 @@ impl<'a> Expand<'a> {
 
 #[cfg(test)]
 mod tests {
-    use crate::machine_id::MachineIdentity;
+    use crate::{blob::BlobUrl, machine_id::MachineIdentity};
 
     use super::Expand;
     use anyhow::{Context, Result};
@@ -553,6 +787,78 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_expand_nested_order_independent() -> Result<()> {
+        let generator_options = vec!["{input_file_name}".to_string()];
+        let target_options = vec!["-x".to_string(), "{generator_options}".to_string()];
+        let supervisor_options = vec!["{target_options}".to_string(), "-y".to_string()];
+
+        let inner_first = Expand::new(&test_machine_identity())
+            .input_path("src/lib.rs")
+            .generator_options(&generator_options)
+            .target_options(&target_options)
+            .supervisor_options(&supervisor_options)
+            .evaluate(&["{supervisor_options}"])?;
+
+        let outer_first = Expand::new(&test_machine_identity())
+            .supervisor_options(&supervisor_options)
+            .target_options(&target_options)
+            .generator_options(&generator_options)
+            .input_path("src/lib.rs")
+            .evaluate(&["{supervisor_options}"])?;
+
+        assert_eq!(inner_first, vec!["-x lib.rs -y"]);
+        assert_eq!(inner_first, outer_first);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cycle_through_several_values() {
+        let result = Expand::new(&test_machine_identity())
+            .target_options(&["{generator_options}".to_string()])
+            .generator_options(&["{supervisor_options}".to_string()])
+            .supervisor_options(&["{target_options}".to_string()])
+            .evaluate_value("{target_options}");
+
+        let e = format!("{:#}", result.err().unwrap());
+        assert!(
+            e.ends_with(
+                "attempting to replace {target_options} with a value that contains itself \
+                (replacements {target_options} -> {generator_options} -> \
+                {supervisor_options} -> {target_options})"
+            ),
+            "{e}"
+        );
+    }
+
+    #[test]
+    fn test_max_depth() -> Result<()> {
+        let generator_options = vec!["g".to_string()];
+        let target_options = vec!["{generator_options}".to_string()];
+        let supervisor_options = vec!["{target_options}".to_string()];
+
+        let expand = |depth| {
+            Expand::new(&test_machine_identity())
+                .max_depth(depth)
+                .generator_options(&generator_options)
+                .target_options(&target_options)
+                .supervisor_options(&supervisor_options)
+                .evaluate_value("{supervisor_options}")
+        };
+
+        assert_eq!(expand(3)?, "g");
+
+        let e = format!("{:#}", expand(2).err().unwrap());
+        assert!(
+            e.ends_with(
+                "exceeded the maximum expansion depth of 2 while replacing {generator_options} \
+                (replacements {supervisor_options} -> {target_options} -> {generator_options})"
+            ),
+            "{e}"
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_self_referential_list() -> Result<()> {
         let result = Expand::new(&test_machine_identity())
@@ -566,7 +872,7 @@ mod tests {
             unable to get value of {supervisor_options}: \
             evaluating argument failed: {supervisor_options}: \
             attempting to replace {supervisor_options} with a value that contains itself \
-            (replacements {supervisor_options}->{supervisor_options})"
+            (replacements {supervisor_options} -> {supervisor_options})"
         );
         Ok(())
     }
@@ -583,7 +889,7 @@ mod tests {
             "evaluating argument failed: {target_exe}: \
             unable to get value of {target_exe}: \
             attempting to replace {target_exe} with a value that contains itself \
-            (replacements {target_exe}->{target_exe})"
+            (replacements {target_exe} -> {target_exe})"
         );
     }
 
@@ -602,7 +908,7 @@ mod tests {
             unable to get value of {supervisor_options}: \
             evaluating argument failed: {target_exe}: \
             attempting to replace {target_exe} with a value that contains itself \
-            (replacements {target_exe}->{supervisor_options}->{target_exe})"
+            (replacements {target_exe} -> {supervisor_options} -> {target_exe})"
         );
     }
 
@@ -677,6 +983,51 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_arguments_are_not_split() -> Result<()> {
+        let args = ["--path", "a b", "\"c d\"", "{{e}}", "50%", "'f'"];
+
+        let result = Expand::new(&test_machine_identity()).evaluate(&args)?;
+
+        assert_eq!(result, ["--path", "a b", "\"c d\"", "{e}", "50%", "'f'"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_input_file_names() -> Result<()> {
+        let result = Expand::new(&test_machine_identity())
+            .input_path("src/lib.rs")
+            .evaluate(&[
+                "{input_file_name}",
+                "{input_file_stem}",
+                "{input_file_name_no_ext}",
+            ])?;
+
+        assert_eq!(result, ["lib.rs", "lib", "lib"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_crash_blob() -> Result<()> {
+        let blob = BlobUrl::parse("https://contoso.blob.core.windows.net/crashes/dir/crash-1")?;
+        let result = Expand::new(&test_machine_identity())
+            .crash_blob(&blob)
+            .evaluate(&["{crash_container}", "{crash_name}"])?;
+        assert_eq!(result, ["crashes", "dir/crash-1"]);
+
+        let blob = BlobUrl::LocalFile("crashes/crash-2".into());
+        let expand = Expand::new(&test_machine_identity()).crash_blob(&blob);
+        assert_eq!(expand.evaluate_value("{crash_name}")?, "crash-2");
+        assert_eq!(
+            format!(
+                "{:#}",
+                expand.evaluate_value("{crash_container}").unwrap_err()
+            ),
+            "replacement {crash_container} is not available"
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_expand_in_string() -> Result<()> {
         let result = Expand::new(&test_machine_identity())
@@ -702,17 +1053,74 @@ mod tests {
             .evaluate_value("a {input_paht} b");
         assert_eq!(
             format!("{:#}", result.err().unwrap()),
-            "unknown variable replacement {input_paht}"
+            "unknown variable replacement {input_paht} in `a {input_paht} b`"
         );
     }
 
+    #[test]
+    fn typoed_variables_in_arguments() {
+        let result = Expand::new(&test_machine_identity())
+            .input_path("src/lib.rs")
+            .evaluate(&["{target_optons}", "-x", "{input} {inptu}"]);
+        assert_eq!(
+            format!("{:#}", result.err().unwrap()),
+            "evaluating argument failed: {target_optons}: \
+            unknown variable replacement {target_optons} in `{target_optons}`; \
+            evaluating argument failed: {input} {inptu}: \
+            unknown variable replacement {inptu} in `{input} {inptu}`"
+        );
+    }
+
+    #[test]
+    fn escaped_braces() -> Result<()> {
+        let result = Expand::new(&test_machine_identity())
+            .input_path("src/lib.rs")
+            .evaluate(&[
+                "{{literal}}",
+                "-dict={{input}}",
+                "{{}}",
+                "{input_file_name}",
+            ])?;
+        assert_eq!(result, vec!["{literal}", "-dict={input}", "{}", "lib.rs"]);
+        Ok(())
+    }
+
+    #[test]
+    fn escaped_braces_in_nested_value() -> Result<()> {
+        let result = Expand::new(&test_machine_identity())
+            .target_options(&["{{a}}".to_string(), "b".to_string()])
+            .evaluate(&["{target_options}"])?;
+        assert_eq!(result, vec!["{a} b"]);
+        Ok(())
+    }
+
+    #[test]
+    fn allow_unknown_placeholders() -> Result<()> {
+        let result = Expand::new(&test_machine_identity())
+            .allow_unknown_placeholders(true)
+            .input_path("src/lib.rs")
+            .evaluate(&["{target_optons}", "{input_file_name}", "{{x}}"])?;
+        assert_eq!(result, vec!["{target_optons}", "lib.rs", "{x}"]);
+
+        // known placeholders without a value are still an error
+        let result = Expand::new(&test_machine_identity())
+            .allow_unknown_placeholders(true)
+            .evaluate_value("{input}");
+        assert_eq!(
+            format!("{:#}", result.err().unwrap()),
+            "replacement {input} is not available"
+        );
+        Ok(())
+    }
+
     #[test]
     fn multiple_errors() {
         let result =
             Expand::new(&test_machine_identity()).evaluate_value("a {input_paht} {input} b");
         assert_eq!(
             format!("{:#}", result.err().unwrap()),
-            "unknown variable replacement {input_paht}; replacement {input} is not available"
+            "unknown variable replacement {input_paht} in `a {input_paht} {input} b`; \
+            replacement {input} is not available"
         );
     }
 
@@ -722,6 +1130,7 @@ mod tests {
             "{input_file_sha256}",
             "{input_file_name}",
             "{input_file_name_no_ext}",
+            "{input_file_stem}",
         ] {
             let result = Expand::new(&test_machine_identity()).evaluate_value(mapping_fn);
 
@@ -741,6 +1150,7 @@ mod tests {
             "{input_file_sha256}",
             "{input_file_name}",
             "{input_file_name_no_ext}",
+            "{input_file_stem}",
         ] {
             let result = Expand::new(&test_machine_identity())
                 .input_marker("not a path") // this inserts {input} with a Scalar type
@@ -756,6 +1166,61 @@ mod tests {
         }
     }
 
+    #[test]
+    fn env_placeholders() -> Result<()> {
+        std::env::set_var("ONEFUZZ_EXPAND_TEST_SET", "/opt/sdk");
+        std::env::remove_var("ONEFUZZ_EXPAND_TEST_UNSET");
+
+        let result = Expand::new(&test_machine_identity())
+            .allow_env_placeholders(true)
+            .evaluate(&[
+                "--sdk=${env:ONEFUZZ_EXPAND_TEST_SET}",
+                "${env:ONEFUZZ_EXPAND_TEST_SET:-unused}",
+                "${env:ONEFUZZ_EXPAND_TEST_UNSET:-fallback}",
+                "${env:ONEFUZZ_EXPAND_TEST_UNSET:-}",
+            ])?;
+        assert_eq!(result, vec!["--sdk=/opt/sdk", "/opt/sdk", "fallback", ""]);
+        Ok(())
+    }
+
+    #[test]
+    fn env_placeholder_unset() {
+        std::env::remove_var("ONEFUZZ_EXPAND_TEST_MISSING");
+
+        let result = Expand::new(&test_machine_identity())
+            .allow_env_placeholders(true)
+            .evaluate_value("${env:ONEFUZZ_EXPAND_TEST_MISSING}");
+        assert_eq!(
+            format!("{:#}", result.err().unwrap()),
+            "unable to read environment variable ONEFUZZ_EXPAND_TEST_MISSING \
+            for ${env:ONEFUZZ_EXPAND_TEST_MISSING}: environment variable not found"
+        );
+
+        let result = Expand::new(&test_machine_identity())
+            .allow_env_placeholders(true)
+            .evaluate_value("${env:}");
+        assert_eq!(
+            format!("{:#}", result.err().unwrap()),
+            "invalid environment variable reference: ${env:}"
+        );
+    }
+
+    #[test]
+    fn env_placeholders_require_opt_in() {
+        std::env::set_var("ONEFUZZ_EXPAND_TEST_GATED", "secret");
+
+        for allow_unknown in [false, true] {
+            let result = Expand::new(&test_machine_identity())
+                .allow_unknown_placeholders(allow_unknown)
+                .evaluate_value("${env:ONEFUZZ_EXPAND_TEST_GATED}");
+            assert_eq!(
+                format!("{:#}", result.err().unwrap()),
+                "environment variable references are not enabled for this task: \
+                ${env:ONEFUZZ_EXPAND_TEST_GATED}"
+            );
+        }
+    }
+
     #[tokio::test]
     async fn test_expand_machine_id() -> Result<()> {
         let machine_identity = &test_machine_identity();
@@ -767,4 +1232,63 @@ mod tests {
         assert_eq!(expanded_machine_id, machine_id);
         Ok(())
     }
+
+    #[test]
+    fn test_expand_machine_id_default() -> Result<()> {
+        let machine_identity = &test_machine_identity();
+        let expanded = Expand::new(machine_identity).evaluate_value("{machine_id}")?;
+        assert_eq!(Uuid::parse_str(&expanded)?, machine_identity.machine_id);
+        Ok(())
+    }
+
+    #[test]
+    fn test_expand_cpu_count() -> Result<()> {
+        let expanded = Expand::new(&test_machine_identity()).evaluate_value("-jobs={cpu_count}")?;
+        let expected = std::thread::available_parallelism()?.get();
+        assert_eq!(expanded, format!("-jobs={expected}"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_expand_ram_mb() -> Result<()> {
+        let expanded = Expand::new(&test_machine_identity()).evaluate_value("{ram_mb}")?;
+        let ram_mb: u64 = expanded.parse()?;
+        assert!(ram_mb > 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_expand_os() -> Result<()> {
+        let expanded = Expand::new(&test_machine_identity()).evaluate_value("{os}")?;
+        assert_eq!(expanded, std::env::consts::OS);
+        Ok(())
+    }
+
+    #[test]
+    fn test_explicit_values_take_precedence() -> Result<()> {
+        let result = Expand::new(&test_machine_identity())
+            .cpu_count(3)
+            .ram_mb(2048)
+            .os("plan9")
+            .evaluate(&["{cpu_count}", "{ram_mb}", "{os}"])?;
+        assert_eq!(result, vec!["3", "2048", "plan9"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_machine_placeholders_with_input() -> Result<()> {
+        let machine_identity = &test_machine_identity();
+        let result = Expand::new(machine_identity)
+            .input_path("src/lib.rs")
+            .evaluate_value("{machine_id}-{input_file_name}")?;
+        assert_eq!(result, format!("{}-lib.rs", machine_identity.machine_id));
+
+        let result = Expand::new(machine_identity)
+            .input_path("src/lib.rs")
+            .evaluate(&["-jobs={cpu_count}", "{input}"])?;
+        assert_eq!(result.len(), 2);
+        assert!(result[0].starts_with("-jobs="));
+        assert!(result[1].ends_with("lib.rs"));
+        Ok(())
+    }
 }
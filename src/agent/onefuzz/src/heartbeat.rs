@@ -2,24 +2,52 @@
 // Licensed under the MIT License.
 
 use crate::{jitter::random_delay, utils::CheckNotify};
+use alert::DeliveryAlert;
 use anyhow::Result;
 use futures::Future;
 use reqwest::Url;
+use reqwest_retry::RetryBudget;
+use serde::Serialize;
 use std::{
     collections::HashSet,
     sync::{Arc, Mutex},
     time::Duration,
 };
 use storage_queue::QueueClient;
-use tokio::{sync::Notify, task, task::JoinHandle, time::sleep};
+use tokio::{sync::Notify, task::JoinHandle, time::sleep};
+
+pub mod alert;
 
 const DEFAULT_HEARTBEAT_PERIOD: Duration = Duration::from_secs(60 * 5);
 
+/// Heartbeats are sent again within a period, so aren't retried for long.
+pub const HEARTBEAT_RETRY_BUDGET: RetryBudget = RetryBudget::FAST;
+
 pub struct HeartbeatContext<TContext, T> {
     pub state: TContext,
     pub queue_client: QueueClient,
     pub pending_messages: Mutex<HashSet<T>>,
     pub cancelled: Notify,
+    pub delivery: Mutex<DeliveryAlert>,
+}
+
+impl<TContext, T> HeartbeatContext<TContext, T> {
+    /// Record the result of enqueueing a heartbeat, raising or clearing the
+    /// alert of undelivered heartbeats.
+    pub fn delivered(&self, result: Result<()>) {
+        if let Ok(mut delivery) = self.delivery.lock() {
+            delivery.record(&result);
+        }
+    }
+
+    /// Enqueue `heartbeat`, recording whether it was delivered.
+    pub async fn send(&self, heartbeat: impl Serialize) {
+        let result = self
+            .queue_client
+            .enqueue_with_budget(heartbeat, HEARTBEAT_RETRY_BUDGET)
+            .await;
+        self.delivered(result);
+    }
 }
 
 pub struct HeartbeatClient<TContext, T>
@@ -75,6 +103,7 @@ where
         queue_url: Url,
         initial_delay: Option<Duration>,
         heartbeat_period: Option<Duration>,
+        delivery: DeliveryAlert,
         flush: F,
     ) -> Result<HeartbeatClient<TContext, T>>
     where
@@ -90,10 +119,11 @@ where
             queue_client: QueueClient::new(queue_url)?,
             pending_messages: Mutex::new(HashSet::<T>::new()),
             cancelled: Notify::new(),
+            delivery: Mutex::new(delivery),
         });
 
         let flush_context = context.clone();
-        let heartbeat_process = task::spawn(async move {
+        let heartbeat_process = onefuzz_telemetry::context::spawn(async move {
             if let Some(initial_delay) = initial_delay {
                 sleep(initial_delay).await;
             } else {
@@ -0,0 +1,312 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Local alerts of heartbeats which can't be delivered, such as when the SAS
+//! of the heartbeat queue has expired.
+//!
+//! The service considers a node without heartbeats to be dead, and may
+//! reimage it, so alerts are written to files on the node which outlive the
+//! process, recording why. They're removed once heartbeats are delivered
+//! again.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use onefuzz_telemetry::{Event::heartbeat_undelivered, EventData, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// The consecutive failures to deliver heartbeats before an alert is raised.
+pub const ALERT_THRESHOLD_ENV: &str = "ONEFUZZ_HEARTBEAT_ALERT_THRESHOLD";
+
+/// The directory of alerts, as set by the agent for its tasks.
+pub const ALERTS_DIR_ENV: &str = "ONEFUZZ_HEARTBEAT_ALERTS_DIR";
+
+const DEFAULT_ALERT_THRESHOLD: u32 = 3;
+
+/// An alert of heartbeats which couldn't be delivered, as written to its file.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct HeartbeatAlert {
+    /// The sender of the heartbeats, such as `agent` or `task-<task_id>`.
+    pub name: String,
+
+    /// Consecutive failures to deliver heartbeats.
+    pub failures: u32,
+
+    /// When the first and the last of the failures were, as RFC 3339.
+    pub first_failure: String,
+    pub last_failure: String,
+
+    /// The error of the last failure, without SAS signatures.
+    pub error: String,
+}
+
+/// Tracks the delivery of heartbeats, and raises an alert once they've failed
+/// `threshold` times in a row.
+pub struct DeliveryAlert {
+    name: String,
+    threshold: u32,
+    path: Option<PathBuf>,
+    failures: u32,
+    first_failure: Option<String>,
+    alert: Option<HeartbeatAlert>,
+}
+
+impl DeliveryAlert {
+    /// Alerts of the heartbeats of `name`, written to `path` if set.
+    pub fn new(name: impl Into<String>, threshold: u32, path: Option<PathBuf>) -> Self {
+        Self {
+            name: name.into(),
+            threshold: threshold.max(1),
+            path,
+            failures: 0,
+            first_failure: None,
+            alert: None,
+        }
+    }
+
+    /// Alerts of the heartbeats of `name`, in the directory of `alerts_dir()`,
+    /// after the failures set by `ONEFUZZ_HEARTBEAT_ALERT_THRESHOLD`.
+    pub fn from_env(name: impl Into<String>) -> Self {
+        let name = name.into();
+
+        let threshold = match std::env::var(ALERT_THRESHOLD_ENV) {
+            Ok(threshold) => threshold.parse().unwrap_or_else(|_| {
+                warn!(
+                    "invalid {}, using {}: {:?}",
+                    ALERT_THRESHOLD_ENV, DEFAULT_ALERT_THRESHOLD, threshold
+                );
+                DEFAULT_ALERT_THRESHOLD
+            }),
+            Err(_) => DEFAULT_ALERT_THRESHOLD,
+        };
+        let path = alerts_dir()
+            .map(|dir| alert_path(&dir, &name))
+            .map_err(|err| warn!("unable to find the heartbeat alerts directory: {:?}", err))
+            .ok();
+
+        Self::new(name, threshold, path)
+    }
+
+    /// The alert raised, if heartbeats are failing.
+    pub fn alert(&self) -> Option<&HeartbeatAlert> {
+        self.alert.as_ref()
+    }
+
+    /// Record the result of delivering a heartbeat.
+    pub fn record(&mut self, result: &Result<()>) {
+        match result {
+            Ok(()) => self.delivered(),
+            Err(err) => self.failed(err),
+        }
+    }
+
+    fn delivered(&mut self) {
+        self.failures = 0;
+        self.first_failure = None;
+
+        let Some(alert) = self.alert.take() else {
+            return;
+        };
+        info!(
+            "heartbeats of {} delivered again, after {} failures since {}",
+            self.name, alert.failures, alert.first_failure
+        );
+
+        if let Some(path) = &self.path {
+            if let Err(err) = std::fs::remove_file(path) {
+                if err.kind() != std::io::ErrorKind::NotFound {
+                    warn!(
+                        "unable to remove heartbeat alert {}: {:?}",
+                        path.display(),
+                        err
+                    );
+                }
+            }
+        }
+    }
+
+    fn failed(&mut self, err: &anyhow::Error) {
+        let now = Utc::now().to_rfc3339();
+        self.failures += 1;
+        let first_failure = self.first_failure.get_or_insert_with(|| now.clone());
+
+        if self.failures < self.threshold {
+            warn!("failed to deliver heartbeat of {}: {:?}", self.name, err);
+            return;
+        }
+
+        let alert = HeartbeatAlert {
+            name: self.name.clone(),
+            failures: self.failures,
+            first_failure: first_failure.clone(),
+            last_failure: now,
+            error: redact_sas(&format!("{err:?}")),
+        };
+
+        if self.alert.is_none() {
+            error!(
+                "heartbeats of {} have failed {} times since {}, the service may consider this node dead: {}",
+                alert.name, alert.failures, alert.first_failure, alert.error
+            );
+            event!(heartbeat_undelivered; EventData::Name = &alert.name, EventData::Count = alert.failures);
+        }
+
+        if let Some(path) = &self.path {
+            if let Err(err) = write_alert(path, &alert) {
+                warn!(
+                    "unable to write heartbeat alert {}: {:?}",
+                    path.display(),
+                    err
+                );
+            }
+        }
+
+        self.alert = Some(alert);
+    }
+}
+
+/// The directory of heartbeat alerts, as set by `ONEFUZZ_HEARTBEAT_ALERTS_DIR`,
+/// or else in the OneFuzz root.
+pub fn alerts_dir() -> Result<PathBuf> {
+    match std::env::var_os(ALERTS_DIR_ENV) {
+        Some(dir) => Ok(PathBuf::from(dir)),
+        None => Ok(crate::fs::onefuzz_root()?.join("alerts")),
+    }
+}
+
+fn alert_path(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("heartbeat-{name}.json"))
+}
+
+fn write_alert(path: &Path, alert: &HeartbeatAlert) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    // Replaced at once, so that readers never see a partial alert.
+    let temp = path.with_extension("json.tmp");
+    std::fs::write(&temp, serde_json::to_vec_pretty(alert)?)?;
+    std::fs::rename(&temp, path)?;
+
+    Ok(())
+}
+
+/// The heartbeat alerts in `dir`, by name. Files which can't be read, such as
+/// those being written, are skipped.
+pub fn read_alerts(dir: &Path) -> Vec<HeartbeatAlert> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return vec![];
+    };
+
+    let mut alerts: Vec<HeartbeatAlert> = entries
+        .flatten()
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|entry| {
+            let data = std::fs::read(entry.path()).ok()?;
+            serde_json::from_slice(&data).ok()
+        })
+        .collect();
+    alerts.sort_by(|a, b| a.name.cmp(&b.name));
+
+    alerts
+}
+
+// Errors of requests to queues may include their SAS URLs.
+fn redact_sas(text: &str) -> String {
+    SAS_SIG.replace_all(text, "${1}REDACTED").into_owned()
+}
+
+lazy_static::lazy_static! {
+    static ref SAS_SIG: Regex = Regex::new(r"(?i)(\bsig=)[^&\s)]+").unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A sender of heartbeats which fails while `failing` is set.
+    struct FakeSender {
+        failing: bool,
+    }
+
+    impl FakeSender {
+        fn send(&self) -> Result<()> {
+            if self.failing {
+                bail!("error sending request for url (https://account.queue.core.windows.net/heartbeat/messages?sv=2021&sig=c2VjcmV0)");
+            }
+
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_alert_lifecycle() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = alert_path(dir.path(), "task-1");
+        let mut alert = DeliveryAlert::new("task-1", 3, Some(path.clone()));
+        let mut sender = FakeSender { failing: true };
+
+        // Below the threshold.
+        alert.record(&sender.send());
+        alert.record(&sender.send());
+        assert_eq!(alert.alert(), None);
+        assert!(!path.exists());
+
+        alert.record(&sender.send());
+        let raised = alert.alert().cloned().expect("alert");
+        assert_eq!(raised.name, "task-1");
+        assert_eq!(raised.failures, 3);
+        assert!(raised.first_failure <= raised.last_failure);
+        assert!(raised.error.contains("sig=REDACTED"), "{}", raised.error);
+        assert!(!raised.error.contains("c2VjcmV0"), "{}", raised.error);
+        assert_eq!(read_alerts(dir.path()), [raised.clone()]);
+
+        // Updated by each further failure.
+        alert.record(&sender.send());
+        let updated = read_alerts(dir.path());
+        assert_eq!(updated.len(), 1);
+        assert_eq!(updated[0].failures, 4);
+        assert_eq!(updated[0].first_failure, raised.first_failure);
+
+        // Cleared by recovery.
+        sender.failing = false;
+        alert.record(&sender.send());
+        assert_eq!(alert.alert(), None);
+        assert!(!path.exists());
+        assert!(read_alerts(dir.path()).is_empty());
+
+        // Failures are counted again from recovery.
+        sender.failing = true;
+        alert.record(&sender.send());
+        alert.record(&sender.send());
+        assert_eq!(alert.alert(), None);
+        alert.record(&sender.send());
+        assert_eq!(alert.alert().map(|alert| alert.failures), Some(3));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_alert_without_file() {
+        let mut alert = DeliveryAlert::new("agent", 1, None);
+
+        alert.record(&Err(anyhow!("connection refused")));
+        assert_eq!(alert.alert().map(|alert| alert.failures), Some(1));
+
+        alert.record(&Ok(()));
+        assert_eq!(alert.alert(), None);
+    }
+
+    #[test]
+    fn test_read_alerts_skips_invalid_files() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        std::fs::write(dir.path().join("heartbeat-a.json"), "{")?;
+        std::fs::write(dir.path().join("notes.txt"), "")?;
+
+        assert!(read_alerts(dir.path()).is_empty());
+        assert!(read_alerts(&dir.path().join("missing")).is_empty());
+
+        Ok(())
+    }
+}
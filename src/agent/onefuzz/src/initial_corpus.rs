@@ -0,0 +1,328 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Sampling of initial corpora too large to pull in full.
+
+use std::cmp::Reverse;
+
+use serde::Deserialize;
+
+use crate::{blob::list::BlobItem, sha256};
+
+/// Limits on the blobs pulled from a seed container when a task starts.
+///
+/// The limits apply to each container. Blobs are considered in the order of
+/// `sample`, and a blob is skipped if it would exceed the remaining `bytes`.
+/// Blobs added to the container later are all pulled, as they are synced.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct MaxInitialCorpus {
+    /// Maximum number of blobs to pull.
+    pub count: Option<u64>,
+
+    /// Maximum total size of the blobs to pull, in bytes.
+    pub bytes: Option<u64>,
+
+    #[serde(default)]
+    pub sample: Sample,
+}
+
+/// Order in which blobs are considered for the initial corpus.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Sample {
+    /// The most recently modified blobs first.
+    #[default]
+    Newest,
+
+    /// A pseudorandom order, which only depends on `seed` and the blob names,
+    /// so that every task of a job pulls the same sample.
+    Random {
+        #[serde(default)]
+        seed: u64,
+    },
+
+    /// The blobs named by the lines of the blob `name`, in order. Blank lines
+    /// and lines starting with `#` are ignored.
+    Manifest { name: String },
+}
+
+/// The blobs selected from a listing.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Selection {
+    pub blobs: Vec<BlobItem>,
+
+    /// Number of blobs listed, not counting any manifest.
+    pub listed: usize,
+}
+
+impl Selection {
+    pub fn skipped(&self) -> usize {
+        self.listed - self.blobs.len()
+    }
+
+    /// Fraction of the listed blobs that were not selected.
+    pub fn skipped_fraction(&self) -> f64 {
+        if self.listed == 0 {
+            return 0.0;
+        }
+
+        self.skipped() as f64 / self.listed as f64
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.blobs.iter().map(|blob| blob.size).sum()
+    }
+}
+
+impl MaxInitialCorpus {
+    /// The name of the manifest blob, if sampling with one.
+    pub fn manifest(&self) -> Option<&str> {
+        match &self.sample {
+            Sample::Manifest { name } => Some(name),
+            _ => None,
+        }
+    }
+
+    /// Select the blobs to pull from `blobs`, as listed in any order.
+    /// `manifest` is the content of the manifest blob, if sampling with one.
+    pub fn select(&self, blobs: &[BlobItem], manifest: Option<&str>) -> Selection {
+        let candidates: Vec<&BlobItem> = blobs
+            .iter()
+            .filter(|blob| Some(blob.name.as_str()) != self.manifest())
+            .collect();
+        let listed = candidates.len();
+
+        let candidates = match &self.sample {
+            Sample::Newest => {
+                let mut candidates = candidates;
+                // Ties are broken by name, so that the order is stable.
+                candidates.sort_by(|a, b| {
+                    (Reverse(a.last_modified), &a.name).cmp(&(Reverse(b.last_modified), &b.name))
+                });
+                candidates
+            }
+            Sample::Random { seed } => {
+                let mut candidates = candidates;
+                let seed = seed.to_le_bytes();
+                candidates.sort_by_cached_key(|blob| {
+                    sha256::digest_iter([&seed[..], blob.name.as_bytes()])
+                });
+                candidates
+            }
+            Sample::Manifest { .. } => {
+                let by_name: std::collections::HashMap<&str, &BlobItem> = candidates
+                    .iter()
+                    .map(|blob| (blob.name.as_str(), *blob))
+                    .collect();
+                let mut seen = std::collections::HashSet::new();
+
+                manifest
+                    .unwrap_or_default()
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                    .filter(|name| seen.insert(*name))
+                    .filter_map(|name| by_name.get(name).copied())
+                    .collect()
+            }
+        };
+
+        let mut selected = vec![];
+        let mut remaining_bytes = self.bytes;
+
+        for blob in candidates {
+            if self
+                .count
+                .is_some_and(|count| selected.len() as u64 >= count)
+            {
+                break;
+            }
+
+            if let Some(remaining) = &mut remaining_bytes {
+                if blob.size > *remaining {
+                    continue;
+                }
+                *remaining -= blob.size;
+            }
+
+            selected.push(blob.clone());
+        }
+
+        Selection {
+            blobs: selected,
+            listed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use rand::seq::SliceRandom;
+
+    use super::*;
+
+    const LISTED: u64 = 100_000;
+
+    // Blob `i` is `i % 100` bytes, and was modified `i` seconds after the epoch.
+    fn listing() -> Vec<BlobItem> {
+        (0..LISTED)
+            .map(|i| BlobItem {
+                name: format!("seed-{i:06}"),
+                size: i % 100,
+                last_modified: SystemTime::UNIX_EPOCH + Duration::from_secs(i),
+            })
+            .collect()
+    }
+
+    fn names(selection: &Selection) -> Vec<&str> {
+        selection
+            .blobs
+            .iter()
+            .map(|blob| blob.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        let max: MaxInitialCorpus = serde_json::from_str(r#"{ "count": 10 }"#)?;
+        assert_eq!(
+            max,
+            MaxInitialCorpus {
+                count: Some(10),
+                bytes: None,
+                sample: Sample::Newest,
+            }
+        );
+
+        let max: MaxInitialCorpus =
+            serde_json::from_str(r#"{ "bytes": 1024, "sample": { "random": { "seed": 7 } } }"#)?;
+        assert_eq!(max.sample, Sample::Random { seed: 7 });
+
+        let max: MaxInitialCorpus = serde_json::from_str(
+            r#"{ "count": 1, "sample": { "manifest": { "name": "seeds.txt" } } }"#,
+        )?;
+        assert_eq!(max.manifest(), Some("seeds.txt"));
+
+        assert!(serde_json::from_str::<MaxInitialCorpus>(r#"{ "cuont": 1 }"#).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_select_newest() {
+        let max = MaxInitialCorpus {
+            count: Some(3),
+            ..MaxInitialCorpus::default()
+        };
+        let selection = max.select(&listing(), None);
+
+        assert_eq!(
+            names(&selection),
+            ["seed-099999", "seed-099998", "seed-099997"]
+        );
+        assert_eq!(selection.listed, LISTED as usize);
+        assert_eq!(selection.skipped(), LISTED as usize - 3);
+    }
+
+    #[test]
+    fn test_select_bytes() {
+        let max = MaxInitialCorpus {
+            bytes: Some(1000),
+            ..MaxInitialCorpus::default()
+        };
+        let selection = max.select(&listing(), None);
+
+        // Newest first: 99 + 98 + ... + 90 = 945 bytes, then 55 bytes are left
+        // for smaller blobs further down.
+        assert_eq!(selection.blobs[0].name, "seed-099999");
+        assert_eq!(selection.blobs[10].name, "seed-099955");
+        assert_eq!(selection.bytes(), 1000);
+    }
+
+    #[test]
+    fn test_select_random_is_deterministic() {
+        let max = MaxInitialCorpus {
+            count: Some(1000),
+            bytes: Some(40_000),
+            sample: Sample::Random { seed: 1 },
+        };
+
+        let mut shuffled = listing();
+        shuffled.shuffle(&mut rand::thread_rng());
+
+        let selection = max.select(&listing(), None);
+        assert_eq!(selection.blobs.len(), 1000);
+        assert!(selection.bytes() <= 40_000);
+
+        // The sample only depends on the seed and the names, not on the
+        // order of the listing.
+        assert_eq!(max.select(&shuffled, None), selection);
+
+        let other = MaxInitialCorpus {
+            sample: Sample::Random { seed: 2 },
+            ..max.clone()
+        };
+        assert_ne!(other.select(&listing(), None), selection);
+
+        // Not simply the newest or oldest blobs.
+        let newest = MaxInitialCorpus {
+            sample: Sample::Newest,
+            ..max
+        };
+        assert_ne!(names(&newest.select(&listing(), None)), names(&selection));
+        assert!(selection
+            .blobs
+            .iter()
+            .any(|blob| blob.name < *"seed-050000"));
+        assert!(selection
+            .blobs
+            .iter()
+            .any(|blob| blob.name >= *"seed-050000"));
+    }
+
+    #[test]
+    fn test_select_manifest() {
+        let max = MaxInitialCorpus {
+            count: Some(3),
+            bytes: None,
+            sample: Sample::Manifest {
+                name: "manifest.txt".to_owned(),
+            },
+        };
+
+        let mut blobs = listing();
+        blobs.push(BlobItem {
+            name: "manifest.txt".to_owned(),
+            size: 1,
+            last_modified: SystemTime::now(),
+        });
+
+        let manifest = "# seeds\nseed-000042\n\n  seed-000007  \nmissing\nseed-000042\nseed-012345\nseed-000001\n";
+        let selection = max.select(&blobs, Some(manifest));
+
+        assert_eq!(
+            names(&selection),
+            ["seed-000042", "seed-000007", "seed-012345"]
+        );
+        assert_eq!(selection.listed, LISTED as usize);
+
+        // Without the manifest, nothing is selected.
+        assert!(max.select(&blobs, None).blobs.is_empty());
+    }
+
+    #[test]
+    fn test_skipped_fraction() {
+        let max = MaxInitialCorpus {
+            count: Some(LISTED / 4),
+            ..MaxInitialCorpus::default()
+        };
+        assert_eq!(max.select(&listing(), None).skipped_fraction(), 0.75);
+
+        let unlimited = MaxInitialCorpus::default();
+        assert_eq!(unlimited.select(&listing(), None).skipped_fraction(), 0.0);
+        assert_eq!(unlimited.select(&[], None).skipped_fraction(), 0.0);
+    }
+}
@@ -5,38 +5,76 @@
 
 use crate::{
     asan::{add_asan_log_env, check_asan_path, check_asan_string},
+    blob::BlobUrl,
+    crash_dump::{dump_name, evict_oldest, CrashDumpConfig},
     env::{get_path_with_directory, update_path, LD_LIBRARY_PATH, PATH},
     expand::Expand,
     machine_id::MachineIdentity,
-    process::run_cmd,
+    process::{run_command_with_input, run_command_with_limits, Limit, Output, RunLimits},
+    qemu::{QemuExit, QemuUser},
+    shell::target_command,
 };
 use anyhow::{Context, Error, Result};
+use debuggable_module::debugfile::DebugFileDirs;
 #[cfg(target_os = "linux")]
-use nix::sys::signal::{kill, Signal};
+use nix::sys::signal::{killpg, Signal};
 use stacktrace_parser::CrashLog;
 #[cfg(any(target_os = "linux", target_family = "windows"))]
 use stacktrace_parser::StackEntry;
 use std::ffi::OsStr;
 #[cfg(target_os = "linux")]
 use std::process::Stdio;
-use std::{collections::HashMap, path::Path, time::Duration};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
 use tempfile::tempdir;
 
 const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
 
+// How often the limits of a target run under the debugger are checked.
+#[cfg(target_os = "linux")]
+const LIMIT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How the target is given each input.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InputVia {
+    /// By the path of the input file, as substituted for `{input}` in the
+    /// target options.
+    #[default]
+    File,
+
+    /// On its standard input, for targets which don't take a path. The input
+    /// is piped to the target, or, if it's run under a debugger, the input
+    /// file is opened as its standard input. `{input}` is still substituted.
+    Stdin,
+}
+
 pub struct Tester<'a> {
     setup_dir: &'a Path,
     extra_setup_dir: Option<&'a Path>,
     exe_path: &'a Path,
     arguments: &'a [String],
     environ: &'a HashMap<String, String>,
+    input_via: InputVia,
     timeout: Duration,
+    memory_limit: Option<u64>,
     check_asan_log: bool,
     check_asan_stderr: bool,
     check_debugger: bool,
     check_retry_count: u64,
     add_setup_to_ld_library_path: bool,
     add_setup_to_path: bool,
+    allow_unknown_placeholders: bool,
+    allow_env_placeholders: bool,
+    target_options_shell: bool,
+    qemu_user: Option<&'a QemuUser>,
+    crash_blob: Option<&'a BlobUrl>,
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    debug_file_dirs: Option<&'a DebugFileDirs>,
+    crash_dump: Option<(&'a CrashDumpConfig, &'a Path)>,
     machine_identity: MachineIdentity,
 }
 
@@ -47,10 +85,55 @@ pub struct Crash {
     pub crash_site: String,
 }
 
+/// How the last run of an input ended.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum TestOutcome {
+    /// The target exited without crashing, or failed to run, as explained by
+    /// the error of the result.
+    #[default]
+    CleanExit,
+
+    /// A crash was found, from the crash log of the result.
+    Crash,
+
+    /// The target ran for longer than its timeout, so was killed, with its
+    /// descendants.
+    Timeout,
+
+    /// The target used more memory than its limit, so was killed, with its
+    /// descendants.
+    MemoryLimit,
+}
+
 #[derive(Debug)]
 pub struct TestResult {
     pub crash_log: Option<CrashLog>,
     pub error: Option<Error>,
+    pub outcome: TestOutcome,
+
+    /// The minidump of the crash, if one was asked for, and written.
+    pub crash_dump: Option<PathBuf>,
+
+    /// The runs of the target, of each of the `check_retry_count` retries.
+    pub runs: Vec<TestRun>,
+}
+
+/// A run of the target, as recorded to explain inputs which don't crash.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct TestRun {
+    /// The program run and its arguments, including any shell or emulator
+    /// running the target.
+    pub command: Vec<String>,
+
+    /// The environment set for the target.
+    pub env: HashMap<String, String>,
+
+    pub duration: Duration,
+
+    /// Unset if the target ran under the debugger, or failed to run.
+    pub output: Option<Output>,
+
+    pub error: Option<String>,
 }
 
 impl<'a> Tester<'a> {
@@ -68,17 +151,33 @@ impl<'a> Tester<'a> {
             exe_path,
             arguments,
             environ,
+            input_via: InputVia::default(),
             timeout: DEFAULT_TIMEOUT,
+            memory_limit: None,
             check_asan_log: false,
             check_asan_stderr: false,
             check_debugger: false,
             check_retry_count: 0,
             add_setup_to_ld_library_path: false,
             add_setup_to_path: false,
+            allow_unknown_placeholders: false,
+            allow_env_placeholders: false,
+            target_options_shell: false,
+            qemu_user: None,
+            crash_blob: None,
+            debug_file_dirs: None,
+            crash_dump: None,
             machine_identity,
         }
     }
 
+    pub fn input_via(self, value: InputVia) -> Self {
+        Self {
+            input_via: value,
+            ..self
+        }
+    }
+
     pub fn timeout(self, value: u64) -> Self {
         Self {
             timeout: Duration::from_secs(value),
@@ -86,6 +185,16 @@ impl<'a> Tester<'a> {
         }
     }
 
+    /// Kill the target, with its descendants, once they use more than `value`
+    /// MiB of memory together. Not enforced while the debugger runs the
+    /// target on Windows.
+    pub fn memory_limit_mb(self, value: u64) -> Self {
+        Self {
+            memory_limit: Some(value.saturating_mul(1024 * 1024)),
+            ..self
+        }
+    }
+
     pub fn check_asan_log(self, value: bool) -> Self {
         Self {
             check_asan_log: value,
@@ -128,6 +237,64 @@ impl<'a> Tester<'a> {
         }
     }
 
+    pub fn allow_unknown_placeholders(self, value: bool) -> Self {
+        Self {
+            allow_unknown_placeholders: value,
+            ..self
+        }
+    }
+
+    pub fn allow_env_placeholders(self, value: bool) -> Self {
+        Self {
+            allow_env_placeholders: value,
+            ..self
+        }
+    }
+
+    pub fn target_options_shell(self, value: bool) -> Self {
+        Self {
+            target_options_shell: value,
+            ..self
+        }
+    }
+
+    /// Run the target under QEMU user mode emulation. The debugger can't be
+    /// attached to the target, so `check_debugger` instead checks whether QEMU
+    /// reports that the target was killed by a signal.
+    pub fn qemu_user(self, value: &'a QemuUser) -> Self {
+        Self {
+            qemu_user: Some(value),
+            ..self
+        }
+    }
+
+    /// The blob that inputs were downloaded from, for `{crash_container}` and
+    /// `{crash_name}`.
+    pub fn crash_blob(self, value: &'a BlobUrl) -> Self {
+        Self {
+            crash_blob: Some(value),
+            ..self
+        }
+    }
+
+    /// Directories searched for the external debug files of stripped modules,
+    /// when symbolizing the stacks of crashes caught by the debugger.
+    pub fn debug_file_dirs(self, value: &'a DebugFileDirs) -> Self {
+        Self {
+            debug_file_dirs: Some(value),
+            ..self
+        }
+    }
+
+    /// Write a minidump of each crash caught by the debugger on Windows to
+    /// `dir`, named after the hash of the input, as configured by `config`.
+    pub fn crash_dump(self, config: &'a CrashDumpConfig, dir: &'a Path) -> Self {
+        Self {
+            crash_dump: Some((config, dir)),
+            ..self
+        }
+    }
+
     pub fn set_optional<T>(self, value: Option<T>, setter: impl FnOnce(Self, T) -> Self) -> Self {
         if let Some(value) = value {
             setter(self, value)
@@ -136,19 +303,42 @@ impl<'a> Tester<'a> {
         }
     }
 
+    // The crash caught by the debugger, if any, and the limit the target was
+    // killed for, if it exceeded one.
     #[cfg(target_family = "windows")]
     async fn test_input_debugger(
         &self,
         argv: &[impl AsRef<OsStr>],
         env: &HashMap<String, String>,
-    ) -> Result<Option<CrashLog>> {
+        stdin: Option<&Path>,
+        dump_path: Option<&Path>,
+    ) -> Result<(Option<CrashLog>, Option<Limit>)> {
+        use crate::crash_dump::DumpTrigger;
+        use input_tester::minidump::DumpOptions;
+
         const IGNORE_FIRST_CHANCE_EXCEPTIONS: bool = true;
+
+        let dump = self
+            .crash_dump
+            .zip(dump_path)
+            .map(|((config, _), path)| DumpOptions {
+                path: path.to_owned(),
+                dump_type: config.dump_type.into(),
+                first_chance: config.trigger == DumpTrigger::FirstChance,
+            });
+
+        // Runs any wrapper script through its interpreter. The target it
+        // launches is debugged as a child.
+        let cmd = target_command(self.exe_path, argv, &[], false)?;
+        let args: Vec<_> = cmd.get_args().collect();
         let report = input_tester::crash_detector::test_process(
-            self.exe_path,
-            argv,
+            cmd.get_program(),
+            &args,
             env,
             self.timeout,
             IGNORE_FIRST_CHANCE_EXCEPTIONS,
+            dump,
+            stdin,
         )?;
 
         let crash = if let Some(exception) = report.exceptions.last() {
@@ -184,14 +374,19 @@ impl<'a> Tester<'a> {
             let fault_type = exception.description.to_string();
             let sanitizer = fault_type.to_string();
 
+            // Crashes of children of the target, such as of a worker spawned
+            // by a broker, are logged with the process which crashed.
+            let text = exception.child_process.then(|| exception.to_string());
+
             Some(CrashLog::new(
-                None, None, sanitizer, fault_type, None, None, call_stack,
+                text, None, sanitizer, fault_type, None, None, call_stack,
             )?)
         } else {
             None
         };
+        let exceeded = report.exit_status.is_timeout().then_some(Limit::Timeout);
 
-        Ok(crash)
+        Ok((crash, exceeded))
     }
 
     #[cfg(target_os = "linux")]
@@ -199,12 +394,27 @@ impl<'a> Tester<'a> {
         &self,
         args: &[impl AsRef<OsStr>],
         env: &HashMap<String, String>,
-    ) -> Result<Option<CrashLog>> {
+        stdin: Option<&Path>,
+        // Dumps are only written on Windows.
+        _dump_path: Option<&Path>,
+    ) -> Result<(Option<CrashLog>, Option<Limit>)> {
+        use std::os::unix::process::CommandExt;
+
+        let stdin: Stdio = match stdin {
+            Some(path) => std::fs::File::open(path)
+                .with_context(|| format!("unable to open input: {}", path.display()))?
+                .into(),
+            None => Stdio::null(),
+        };
+
+        // In a process group of its own, so that it's killed with any
+        // processes it starts.
         let mut cmd = std::process::Command::new(self.exe_path);
-        cmd.args(args).stdin(Stdio::null());
+        cmd.args(args).stdin(stdin).process_group(0);
         cmd.envs(env);
 
         let (sender, receiver) = tokio::sync::oneshot::channel();
+        let debug_file_dirs = self.debug_file_dirs.cloned().unwrap_or_default();
 
         // Create two async tasks: one off-thread task for the blocking triage run,
         // and one task that will kill the triage target if we time out.
@@ -212,7 +422,7 @@ impl<'a> Tester<'a> {
             // Spawn a triage run, but stop it before execing.
             //
             // This calls a blocking `wait()` internally, on the forked child.
-            let triage = crate::triage::TriageCommand::new(cmd)?;
+            let triage = crate::triage::TriageCommand::new(cmd)?.debug_file_dirs(debug_file_dirs);
 
             // Share the new child ID with main thread.
             let Ok(()) = sender.send(triage.pid()) else {
@@ -236,17 +446,34 @@ impl<'a> Tester<'a> {
             }
         };
 
-        let timeout = tokio::time::timeout(self.timeout, triage).await;
-        if timeout.is_err() {
-            // Yes. Try to kill the target process, if hung.
-            kill(target_pid, Signal::SIGKILL)?;
-            bail!("process timed out");
-        }
+        // Wait for the run, checking its limits meanwhile.
+        let started = Instant::now();
+        let mut triage = triage;
+        let report = loop {
+            if let Ok(report) = tokio::time::timeout(LIMIT_POLL_INTERVAL, &mut triage).await {
+                break report??;
+            }
+
+            let exceeded = if started.elapsed() >= self.timeout {
+                Some(Limit::Timeout)
+            } else {
+                self.memory_limit
+                    .filter(|limit| crate::process::group_memory(target_pid.as_raw()) > *limit)
+                    .map(|_| Limit::Memory)
+            };
+
+            if let Some(limit) = exceeded {
+                // Killed with its descendants, whether or not it's hung.
+                killpg(target_pid, Signal::SIGKILL)?;
+                return Ok((None, Some(limit)));
+            }
+        };
 
-        let report = timeout???;
+        // Kill any processes the target left running.
+        _ = killpg(target_pid, Signal::SIGKILL);
 
         let Some(crash) = report.crashes.last() else {
-            return Ok(None);
+            return Ok((None, None));
         };
 
         let crash_thread = crash
@@ -265,22 +492,31 @@ impl<'a> Tester<'a> {
                 function_offset: frame.function.as_ref().map(|x| x.offset),
                 module_path: frame.module.as_ref().map(|x| x.name.clone()),
                 module_offset: frame.module.as_ref().map(|x| x.offset),
-                source_file_name: None,
-                source_file_line: None,
-                source_file_path: None,
+                source_file_name: frame.source.as_ref().and_then(|x| {
+                    Path::new(&x.file)
+                        .file_name()
+                        .map(|name| name.to_string_lossy().into_owned())
+                }),
+                source_file_line: frame.source.as_ref().map(|x| x.line),
+                source_file_path: frame.source.as_ref().map(|x| x.file.clone()),
             })
             .collect();
 
-        let crash_type = crash.signal.to_string();
-        let sanitizer = crash_type.clone();
-        let fault_type = crash_type;
-
-        Ok(Some(CrashLog::new(
-            None, None, sanitizer, fault_type, None, None, call_stack,
-        )?))
+        let crash_log = CrashLog::from_signal(
+            crash.text(),
+            crash.signal.to_string(),
+            crash.fault_type(),
+            call_stack,
+        )?;
+        Ok((Some(crash_log), None))
     }
 
     pub async fn test_input(&self, input_file: impl AsRef<Path>) -> Result<TestResult> {
+        let input_file = input_file.as_ref();
+        if self.target_options_shell && self.check_debugger && self.qemu_user.is_none() {
+            bail!("`target_options_shell` can't be used with `check_debugger`, since the debugger would be attached to the shell");
+        }
+
         let asan_dir = if self.check_asan_log {
             Some(tempdir()?)
         } else {
@@ -289,12 +525,14 @@ impl<'a> Tester<'a> {
 
         let (argv, env) = {
             let expand = Expand::new(&self.machine_identity)
-                .machine_id()
+                .allow_unknown_placeholders(self.allow_unknown_placeholders)
+                .allow_env_placeholders(self.allow_env_placeholders)
                 .input_path(input_file)
                 .target_exe(self.exe_path)
                 .target_options(self.arguments)
                 .setup_dir(self.setup_dir)
-                .set_optional(self.extra_setup_dir, Expand::extra_setup_dir);
+                .set_optional(self.extra_setup_dir, Expand::extra_setup_dir)
+                .set_optional(self.crash_blob, Expand::crash_blob);
 
             let argv = expand.evaluate(self.arguments)?;
             let mut env: HashMap<String, String> = HashMap::new();
@@ -329,31 +567,122 @@ impl<'a> Tester<'a> {
             (argv, env)
         };
 
+        let dump_path = match self.crash_dump {
+            Some((_, dir))
+                if cfg!(target_family = "windows")
+                    && self.check_debugger
+                    && self.qemu_user.is_none() =>
+            {
+                let input_sha256 = crate::sha256::digest_file(input_file).await?;
+                Some(dir.join(dump_name(&input_sha256)))
+            }
+            _ => None,
+        };
+
+        // Read once, for each retry.
+        let stdin = match self.input_via {
+            InputVia::File => None,
+            InputVia::Stdin => Some(
+                tokio::fs::read(input_file)
+                    .await
+                    .with_context(|| format!("unable to read input: {}", input_file.display()))?,
+            ),
+        };
+
         let mut error = None;
         let mut crash_log = None;
+        let mut outcome = TestOutcome::CleanExit;
+        let mut runs = vec![];
+        let limits = RunLimits {
+            timeout: self.timeout,
+            memory: self.memory_limit,
+        };
 
         let attempts = 1 + self.check_retry_count;
         for _ in 0..attempts {
-            let result = if self.check_debugger {
-                match self.test_input_debugger(&argv, &env).await {
-                    Ok(crash) => (crash, None, None),
-                    Err(error) => (None, Some(error), None),
+            let started = Instant::now();
+            let mut command = vec![self.exe_path.display().to_string()];
+            let result = if self.check_debugger && self.qemu_user.is_none() {
+                command.extend(argv.iter().cloned());
+                // Of this run only, rather than of an earlier test of the input.
+                if let Some(dump_path) = &dump_path {
+                    remove_crash_dump(dump_path).await;
+                }
+
+                let stdin = stdin.is_some().then_some(input_file);
+                match self
+                    .test_input_debugger(&argv, &env, stdin, dump_path.as_deref())
+                    .await
+                {
+                    Ok((crash, exceeded)) => (crash, None, None, exceeded),
+                    Err(error) => (None, Some(error), None, None),
                 }
             } else {
-                match run_cmd(self.exe_path, argv.clone(), &env, self.timeout).await {
-                    Ok(output) => (None, None, Some(output)),
-                    Err(error) => (None, Some(error), None),
+                let cmd = match self.qemu_user {
+                    Some(qemu) => qemu.target_command(
+                        self.exe_path,
+                        &[] as &[&OsStr],
+                        &argv,
+                        self.target_options_shell,
+                    )?,
+                    None => target_command(
+                        self.exe_path,
+                        &[] as &[&OsStr],
+                        &argv,
+                        self.target_options_shell,
+                    )?,
+                };
+                command = std::iter::once(cmd.get_program())
+                    .chain(cmd.get_args())
+                    .map(|arg| arg.to_string_lossy().into_owned())
+                    .collect();
+                let limited = match &stdin {
+                    Some(input) => run_command_with_input(cmd, &env, input.clone(), limits).await,
+                    None => run_command_with_limits(cmd, &env, limits).await,
+                };
+                match limited {
+                    Ok(limited) => (None, None, Some(limited.output), limited.exceeded),
+                    Err(error) => (None, Some(error), None, None),
                 }
             };
 
             crash_log = result.0;
             error = result.1;
             let output = result.2;
+            let exceeded = result.3;
+
+            // Recorded as errors too, to explain runs which don't crash.
+            match exceeded {
+                Some(Limit::Timeout) => {
+                    error = Some(format_err!(
+                        "process timed out after {}s",
+                        self.timeout.as_secs()
+                    ));
+                }
+                Some(Limit::Memory) => {
+                    error = Some(format_err!(
+                        "process exceeded the memory limit of {} MiB",
+                        self.memory_limit.unwrap_or_default() / (1024 * 1024)
+                    ));
+                }
+                None => {}
+            }
+
+            runs.push(TestRun {
+                command,
+                env: env.clone(),
+                duration: started.elapsed(),
+                output: output.clone(),
+                error: error.as_ref().map(|e| format!("{e}")),
+            });
 
             // order of operations for checking for crashes:
             // 1. if we ran under a debugger, and that caught a crash
             // 2. if we have an ASAN log in our temp directory
-            // 3. if we have an ASAN log to STDERR
+            // 3. if we have an ASAN log to STDERR, or else an uncaught Java
+            //    exception, as logged by Jazzer
+            // 4. if we ran under QEMU instead of a debugger, and the target
+            //    was killed by a signal
             if crash_log.is_none() {
                 crash_log = if let Some(asan_dir) = &asan_dir {
                     check_asan_path(asan_dir.path())
@@ -365,19 +694,50 @@ impl<'a> Tester<'a> {
             }
 
             if crash_log.is_none() && self.check_asan_stderr {
-                if let Some(output) = output {
-                    crash_log = check_asan_string(output.stderr)
+                if let Some(output) = &output {
+                    crash_log = check_asan_string(output.stderr.clone())
                         .await
                         .context("parsing STDERR as ASAN failed")?;
                 }
             }
 
+            // Unless QEMU itself was killed for a limit.
+            if let (Some(qemu), Some(output), None) = (self.qemu_user, &output, exceeded) {
+                match qemu.exit(&output.exit_status, &output.stderr) {
+                    Ok(QemuExit::Signal(signal)) if crash_log.is_none() && self.check_debugger => {
+                        crash_log = Some(signal_crash_log(signal)?);
+                    }
+                    Ok(_) => {}
+                    Err(err) => error = Some(err),
+                }
+            }
+
+            outcome = match (&crash_log, exceeded) {
+                (Some(_), _) => TestOutcome::Crash,
+                (None, Some(Limit::Timeout)) => TestOutcome::Timeout,
+                (None, Some(Limit::Memory)) => TestOutcome::MemoryLimit,
+                (None, None) => TestOutcome::CleanExit,
+            };
+
             if crash_log.is_some() {
                 break;
             }
         }
 
-        Ok(TestResult { crash_log, error })
+        let crash_dump = match (dump_path, self.crash_dump) {
+            (Some(dump_path), Some((config, dir))) => {
+                keep_crash_dump(dump_path, crash_log.is_some(), config, dir).await
+            }
+            _ => None,
+        };
+
+        Ok(TestResult {
+            crash_log,
+            error,
+            outcome,
+            crash_dump,
+            runs,
+        })
     }
 
     pub async fn is_crash(&self, input_file: impl AsRef<Path>) -> Result<bool> {
@@ -389,3 +749,61 @@ impl<'a> Tester<'a> {
         Ok(test_result.crash_log.is_some())
     }
 }
+
+// The dump written of a run, kept if it's of a crash, and within the cap of the
+// dumps of `dir`.
+async fn keep_crash_dump(
+    dump_path: PathBuf,
+    crashed: bool,
+    config: &CrashDumpConfig,
+    dir: &Path,
+) -> Option<PathBuf> {
+    if !crashed || !dump_path.exists() {
+        remove_crash_dump(&dump_path).await;
+        return None;
+    }
+
+    match evict_oldest(dir, config.max_total_size).await {
+        Ok(evicted) if evicted.contains(&dump_path) => {
+            warn!(
+                "crash dump is larger than the cap of {} bytes: {}",
+                config.max_total_size,
+                dump_path.display()
+            );
+            None
+        }
+        Ok(_) => Some(dump_path),
+        Err(err) => {
+            warn!("unable to cap crash dumps: {:?}", err);
+            Some(dump_path)
+        }
+    }
+}
+
+async fn remove_crash_dump(dump_path: &Path) {
+    match tokio::fs::remove_file(dump_path).await {
+        Ok(()) => {}
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+        Err(err) => warn!(
+            "unable to remove crash dump {}: {:?}",
+            dump_path.display(),
+            err
+        ),
+    }
+}
+
+// A crash found from the signal which killed the target, with no call stack.
+// Named as by the debugger.
+fn signal_crash_log(signal: i32) -> Result<CrashLog> {
+    #[cfg(target_os = "linux")]
+    let name =
+        Signal::try_from(signal).map_or_else(|_| format!("signal {signal}"), |s| s.to_string());
+    #[cfg(not(target_os = "linux"))]
+    let name = format!("signal {signal}");
+
+    CrashLog::new(None, None, name.clone(), name, None, None, vec![])
+}
+
+#[cfg(test)]
+#[cfg(target_os = "linux")]
+mod tests;
@@ -0,0 +1,302 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use uuid::Uuid;
+
+use super::*;
+
+// Test `script` as the target, run by `sh`.
+async fn test_script(script: &str, configure: impl Fn(Tester) -> Tester) -> Result<TestResult> {
+    let arguments = vec!["-c".to_owned(), script.to_owned()];
+    test_target(Path::new("/bin/sh"), &arguments, "input", configure).await
+}
+
+// Test `exe`, run with `arguments`, of an input of `data`.
+async fn test_target(
+    exe: &Path,
+    arguments: &[String],
+    data: &str,
+    configure: impl Fn(Tester) -> Tester,
+) -> Result<TestResult> {
+    let setup_dir = tempfile::tempdir()?;
+    let input = setup_dir.path().join("input");
+    std::fs::write(&input, data)?;
+
+    let env = HashMap::new();
+    let machine_identity = MachineIdentity {
+        machine_id: Uuid::new_v4(),
+        machine_name: "test".to_owned(),
+        scaleset_name: None,
+    };
+
+    let tester = Tester::new(
+        setup_dir.path(),
+        None,
+        exe,
+        arguments,
+        &env,
+        machine_identity,
+    );
+
+    configure(tester).test_input(&input).await
+}
+
+fn via_stdin(tester: Tester<'_>) -> Tester<'_> {
+    tester.input_via(InputVia::Stdin)
+}
+
+// Build the fixture target, which crashes on an input starting with `x`.
+fn build_crash(dir: &Path) -> Result<std::path::PathBuf> {
+    let fixture = Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/triage/crash.c"));
+    let exe = dir.join("crash");
+    let status = std::process::Command::new("cc")
+        .args(["-g", "-O0", "-fno-omit-frame-pointer", "-o"])
+        .arg(&exe)
+        .arg(fixture)
+        .status()?;
+    assert!(status.success(), "cc failed: {status}");
+
+    Ok(exe)
+}
+
+// Whether `pid` is running, rather than exited, waiting to be reaped.
+fn is_running(pid: i32) -> bool {
+    let Ok(stat) = std::fs::read_to_string(format!("/proc/{pid}/stat")) else {
+        return false;
+    };
+
+    let state = stat
+        .rsplit_once(')')
+        .and_then(|(_, rest)| rest.trim().chars().next());
+    state != Some('Z')
+}
+
+// Whether `pid` exits soon, once killed.
+async fn exits(pid: i32) -> bool {
+    for _ in 0..50 {
+        if !is_running(pid) {
+            return true;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    false
+}
+
+#[tokio::test]
+async fn test_clean_exit() -> Result<()> {
+    let result = test_script("exit 0", |tester| tester).await?;
+
+    assert_eq!(result.outcome, TestOutcome::CleanExit);
+    assert!(result.crash_log.is_none());
+    assert!(result.error.is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_crash() -> Result<()> {
+    let script =
+        "echo 'SUMMARY: AddressSanitizer: heap-use-after-free /src/main.c:10 in main' >&2; exit 1";
+    let result = test_script(script, |tester| tester.check_asan_stderr(true)).await?;
+
+    assert_eq!(result.outcome, TestOutcome::Crash);
+    let crash_log = result.crash_log.unwrap();
+    assert_eq!(crash_log.fault_type, "heap-use-after-free");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_timeout_kills_descendants() -> Result<()> {
+    let pid_dir = tempfile::tempdir()?;
+    let pid_file = pid_dir.path().join("pid");
+    let script = format!("sleep 120 & echo $! > {}; sleep 120", pid_file.display());
+
+    let started = Instant::now();
+    let result = test_script(&script, |tester| tester.timeout(1)).await?;
+    assert!(started.elapsed() < Duration::from_secs(60));
+
+    assert_eq!(result.outcome, TestOutcome::Timeout);
+    assert!(result.crash_log.is_none());
+    assert_eq!(
+        result.error.unwrap().to_string(),
+        "process timed out after 1s"
+    );
+
+    let pid: i32 = std::fs::read_to_string(&pid_file)?.trim().parse()?;
+    assert!(exits(pid).await, "descendant {pid} is still running");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_memory_limit() -> Result<()> {
+    // Holds 256 MiB, well over the limit, until killed.
+    let script = "x=$(head -c 268435456 /dev/zero | tr '\\0' a); sleep 120";
+
+    let result = test_script(script, |tester| tester.timeout(120).memory_limit_mb(64)).await?;
+
+    assert_eq!(result.outcome, TestOutcome::MemoryLimit);
+    assert!(result.crash_log.is_none());
+    assert_eq!(
+        result.error.unwrap().to_string(),
+        "process exceeded the memory limit of 64 MiB"
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_input_via_stdin() -> Result<()> {
+    let script = "read -r line; case $line in x*) \
+        echo 'SUMMARY: AddressSanitizer: heap-use-after-free /src/main.c:10 in main' >&2; \
+        exit 1;; esac";
+    let arguments = vec!["-c".to_owned(), script.to_owned()];
+    let sh = Path::new("/bin/sh");
+
+    let result = test_target(sh, &arguments, "x\n", |tester| {
+        via_stdin(tester).check_asan_stderr(true)
+    })
+    .await?;
+    assert_eq!(result.outcome, TestOutcome::Crash);
+    let crash_log = result.crash_log.unwrap();
+    assert_eq!(crash_log.fault_type, "heap-use-after-free");
+
+    let result = test_target(sh, &arguments, "y\n", |tester| {
+        via_stdin(tester).check_asan_stderr(true)
+    })
+    .await?;
+    assert_eq!(result.outcome, TestOutcome::CleanExit);
+
+    // Otherwise, the standard input of the target is empty.
+    let result = test_target(sh, &arguments, "x\n", |tester| {
+        tester.check_asan_stderr(true)
+    })
+    .await?;
+    assert_eq!(result.outcome, TestOutcome::CleanExit);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_input_via_stdin_unread() -> Result<()> {
+    // Larger than the buffer of a pipe, so that writing it blocks until the
+    // target reads it.
+    let data = "x".repeat(16 * 1024 * 1024);
+    let sh = Path::new("/bin/sh");
+
+    let arguments = vec!["-c".to_owned(), "exit 0".to_owned()];
+    let result = test_target(sh, &arguments, &data, via_stdin).await?;
+    assert_eq!(result.outcome, TestOutcome::CleanExit);
+    assert!(result.error.is_none());
+
+    let arguments = vec!["-c".to_owned(), "sleep 120".to_owned()];
+    let started = Instant::now();
+    let result = test_target(sh, &arguments, &data, |tester| via_stdin(tester).timeout(1)).await?;
+    assert!(started.elapsed() < Duration::from_secs(60));
+    assert_eq!(result.outcome, TestOutcome::Timeout);
+
+    Ok(())
+}
+
+// The fault type of the write through a null pointer of `crash.c`, of its
+// faulting instruction, which is only decoded on x86-64.
+const NULL_WRITE_FAULT_TYPE: &str = if cfg!(target_arch = "x86_64") {
+    "write-access-violation"
+} else {
+    "SIGSEGV"
+};
+
+#[tokio::test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+async fn test_debugger_triages_unsanitized_crash() -> Result<()> {
+    let build_dir = tempfile::tempdir()?;
+    let exe = build_crash(build_dir.path())?;
+
+    let arguments = vec!["{input}".to_owned()];
+    let result = test_target(&exe, &arguments, "x", |tester| tester.check_debugger(true)).await?;
+
+    assert_eq!(result.outcome, TestOutcome::Crash);
+    let crash_log = result.crash_log.unwrap();
+    assert_eq!(crash_log.fault_type, NULL_WRITE_FAULT_TYPE);
+    assert_eq!(
+        crash_log.minimized_stack_function_names[..2],
+        ["check", "main"]
+    );
+    assert!(
+        crash_log
+            .summary
+            .starts_with(&format!("{NULL_WRITE_FAULT_TYPE} "))
+            && crash_log.summary.ends_with(" in check"),
+        "{}",
+        crash_log.summary
+    );
+
+    let frame = &crash_log.minimized_stack_details[0];
+    assert_eq!(frame.source_file_name.as_deref(), Some("crash.c"));
+    assert_eq!(frame.source_file_line, Some(12));
+
+    let text = crash_log.text.as_deref().unwrap();
+    assert!(
+        text.contains("SIGSEGV on unknown address 0x000000000000 (pc 0x"),
+        "{text}"
+    );
+    assert!(text.contains("Register values:"), "{text}");
+    if cfg!(target_arch = "x86_64") {
+        assert!(
+            text.contains("The signal is caused by a WRITE memory access."),
+            "{text}"
+        );
+        assert!(text.contains("The faulting instruction is `mov"), "{text}");
+    }
+
+    // Near null, as rated from the faulting address of the log.
+    assert_eq!(
+        crash_log.exploitability().rationale,
+        "near-null dereference"
+    );
+
+    // Without a crash, there's no crash log.
+    let result = test_target(&exe, &arguments, "y", |tester| tester.check_debugger(true)).await?;
+    assert_eq!(result.outcome, TestOutcome::CleanExit);
+    assert!(result.crash_log.is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+async fn test_debugger_triages_stdin_crash() -> Result<()> {
+    let build_dir = tempfile::tempdir()?;
+    let exe = build_crash(build_dir.path())?;
+
+    let result = test_target(&exe, &[], "x", |tester| {
+        via_stdin(tester).check_debugger(true)
+    })
+    .await?;
+    assert_eq!(result.outcome, TestOutcome::Crash);
+    let crash_log = result.crash_log.unwrap();
+    assert_eq!(crash_log.fault_type, NULL_WRITE_FAULT_TYPE);
+    assert_eq!(
+        crash_log.minimized_stack_function_names[..2],
+        ["check", "main"]
+    );
+
+    let result = test_target(&exe, &[], "y", |tester| {
+        via_stdin(tester).check_debugger(true)
+    })
+    .await?;
+    assert_eq!(result.outcome, TestOutcome::CleanExit);
+
+    // Without its input, on stdin or by its path, the target doesn't crash.
+    let result = test_target(&exe, &[], "x", |tester| tester.check_debugger(true)).await?;
+    assert_eq!(result.outcome, TestOutcome::CleanExit);
+
+    Ok(())
+}
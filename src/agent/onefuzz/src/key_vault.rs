@@ -0,0 +1,66 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Secrets of Azure Key Vault, read with the managed identity of the node.
+
+use anyhow::{Context, Result};
+use reqwest_retry::SendRetry;
+use url::Url;
+
+use crate::auth::{ManagedIdentityCredentials, Secret, UserAssignedIdentity};
+use crate::cloud::CloudEnvironment;
+use crate::http::ResponseExt;
+
+const API_VERSION: &str = "7.4";
+
+/// The value of the secret at `url`, such as
+/// `https://contoso.vault.azure.net/secrets/proxy-password`, optionally with
+/// its version, of a key vault of `cloud`, read with `identity`, or the identity
+/// chosen by IMDS.
+pub async fn get_secret(
+    url: &Url,
+    identity: Option<&UserAssignedIdentity>,
+    cloud: &CloudEnvironment,
+) -> Result<Secret<String>> {
+    if !url.path().starts_with("/secrets/") {
+        bail!("invalid key vault secret URL: {url}");
+    }
+
+    // The managed identity would be sent a token for another cloud.
+    if !cloud.is_key_vault_url(url) {
+        bail!(
+            "key vault secret URL isn't of a key vault of the cloud (*.{}): {url}",
+            cloud.key_vault_suffix
+        );
+    }
+
+    let token = ManagedIdentityCredentials::for_azure_resource(&cloud.key_vault_resource())
+        .with_identity(identity.cloned())
+        .access_token()
+        .await
+        .context("key vault access token")?;
+
+    let mut url = url.clone();
+    url.query_pairs_mut()
+        .append_pair("api-version", API_VERSION);
+
+    let response = reqwest_retry::proxy::client()
+        .get(url)
+        .bearer_auth(token.secret().expose_ref())
+        .send_retry_default()
+        .await
+        .context("key_vault.get_secret")?
+        .error_for_status_with_body()
+        .await
+        .context("key_vault.get_secret status body")?;
+
+    let body: SecretBody = response.json().await?;
+
+    Ok(body.value)
+}
+
+// See: https://learn.microsoft.com/en-us/rest/api/keyvault/secrets/get-secret/get-secret
+#[derive(Deserialize)]
+struct SecretBody {
+    value: Secret<String>,
+}
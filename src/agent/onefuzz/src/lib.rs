@@ -11,21 +11,31 @@ pub mod asan;
 pub mod auth;
 pub mod az_copy;
 pub mod blob;
+pub mod cloud;
+pub mod config_validation;
+pub mod crash_dump;
+pub mod dictionary;
 pub mod env;
 pub mod expand;
 pub mod fs;
 pub mod heartbeat;
 pub mod http;
+pub mod initial_corpus;
 pub mod input_tester;
 pub mod ipc;
 pub mod jitter;
+pub mod key_vault;
 pub mod libfuzzer;
 pub mod machine_id;
 pub mod memory;
 pub mod monitor;
 pub mod process;
+pub mod process_stats;
+pub mod qemu;
 pub mod sanitizer;
 pub mod sha256;
+pub mod shell;
+pub mod symsrv;
 pub mod syncdir;
 pub mod utils;
 
@@ -2,15 +2,19 @@
 // Licensed under the MIT License.
 
 use crate::{
+    blob::BlobUrl,
     env::{get_path_with_directory, LD_LIBRARY_PATH, PATH},
     expand::Expand,
     fs::{list_files, write_file},
     input_tester::{TestResult, Tester},
     machine_id::MachineIdentity,
+    qemu::QemuUser,
+    shell::target_command,
 };
 use anyhow::{Context, Result};
 use rand::seq::SliceRandom;
 use rand::thread_rng;
+use stacktrace_parser::{CrashLog, ReportType};
 use std::{
     collections::HashMap,
     ffi::{OsStr, OsString},
@@ -26,8 +30,137 @@ const DEFAULT_MAX_TOTAL_SECONDS: i32 = 10 * 60;
 use lazy_static::lazy_static;
 
 lazy_static! {
-    static ref LIBFUZZERLINEREGEX: regex::Regex =
-        regex::Regex::new(r"#(\d+)\s*(?:pulse|INITED|NEW|REDUCE).*exec/s: (\d+)").unwrap();
+    // Not anchored, since sanitizer output may be interleaved on the same line.
+    static ref LIBFUZZERLINEREGEX: regex::Regex = regex::Regex::new(
+        r"#(\d+)\s*(?:pulse|INITED|NEW|REDUCE|RELOAD|DONE)\s.*?exec/s: (\d+)"
+    )
+    .unwrap();
+
+    // Printed by the parent process in fork mode, as each job finishes.
+    static ref LIBFUZZERFORKLINEREGEX: regex::Regex =
+        regex::Regex::new(r"#(\d+): cov: \d+ ft: \d+ corp: \d+ exec/s:? (\d+)").unwrap();
+}
+
+/// Options for libFuzzer's `-fork` mode, in which a single libFuzzer process
+/// fuzzes in a series of child processes.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ForkMode {
+    /// Number of child processes to run at once.
+    pub jobs: usize,
+
+    /// Keep fuzzing after a child crashes.
+    pub ignore_crashes: bool,
+
+    /// Keep fuzzing after a child runs out of memory.
+    pub ignore_ooms: bool,
+}
+
+impl ForkMode {
+    /// Flags used in fork mode, which libFuzzer must list in its `-help=1`.
+    const FLAGS: &'static [&'static str] = &["fork", "ignore_crashes", "ignore_ooms"];
+
+    fn args(&self) -> Vec<OsString> {
+        vec![
+            format!("-fork={}", self.jobs).into(),
+            format!("-ignore_crashes={}", u8::from(self.ignore_crashes)).into(),
+            format!("-ignore_ooms={}", u8::from(self.ignore_ooms)).into(),
+        ]
+    }
+}
+
+/// Options for libFuzzer's `-jobs` mode, in which a single libFuzzer process
+/// runs a series of independent fuzzing jobs, each in a worker process of its
+/// own. Each job writes its output to `fuzz-<job>.log`, in the working
+/// directory of the libFuzzer.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct JobsMode {
+    /// Number of jobs to run, after which libFuzzer exits.
+    pub jobs: usize,
+
+    /// Number of jobs to run at once.
+    pub workers: usize,
+
+    /// Directory to run libFuzzer in, to which the job logs are written.
+    pub log_dir: PathBuf,
+}
+
+impl JobsMode {
+    fn args(&self) -> Vec<OsString> {
+        vec![
+            format!("-jobs={}", self.jobs).into(),
+            format!("-workers={}", self.workers).into(),
+        ]
+    }
+}
+
+/// What a libFuzzer artifact was written for.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ArtifactKind {
+    /// A crash, timeout, or any other failure.
+    Crash,
+
+    /// The input made the fuzzer exceed `-rss_limit_mb` or `-malloc_limit_mb`.
+    Oom,
+
+    /// LeakSanitizer found a memory leak after running the input.
+    Leak,
+}
+
+impl ArtifactKind {
+    /// Classify an artifact by the prefix which libFuzzer gives its file name,
+    /// such as `oom-<sha1>`. If the name has no known prefix, as when written
+    /// to an `-exact_artifact_path`, use the error reported in `stderr`.
+    pub fn classify(file_name: &str, stderr: &str) -> Self {
+        Self::from_file_name(file_name)
+            .or_else(|| Self::from_stderr(stderr))
+            .unwrap_or(Self::Crash)
+    }
+
+    /// The type of the report of an artifact, if told by the prefix of its
+    /// file name, as by [`ArtifactKind::classify()`]. Of crashes, timeouts are
+    /// also told apart.
+    pub fn report_type(file_name: &str) -> Option<ReportType> {
+        if file_name.starts_with("timeout-") {
+            return Some(ReportType::Timeout);
+        }
+
+        let report_type = match Self::from_file_name(file_name)? {
+            Self::Crash => ReportType::Crash,
+            Self::Oom => ReportType::Oom,
+            Self::Leak => ReportType::Leak,
+        };
+
+        Some(report_type)
+    }
+
+    fn from_file_name(file_name: &str) -> Option<Self> {
+        let (prefix, _) = file_name.split_once('-')?;
+        match prefix {
+            "crash" | "timeout" | "slow" => Some(Self::Crash),
+            "oom" => Some(Self::Oom),
+            "leak" => Some(Self::Leak),
+            _ => None,
+        }
+    }
+
+    fn from_stderr(stderr: &str) -> Option<Self> {
+        // Only the last error is of the input that was written.
+        stderr.lines().rev().find_map(|line| {
+            // Also printed for `-malloc_limit_mb`, as `out-of-memory (malloc(N))`.
+            if line.contains("ERROR: libFuzzer: out-of-memory") {
+                Some(Self::Oom)
+            } else if line.contains("ERROR: LeakSanitizer: detected memory leaks") {
+                Some(Self::Leak)
+            } else if line.contains("ERROR: AddressSanitizer:")
+                || line.contains("ERROR: libFuzzer: deadly signal")
+                || line.contains("ERROR: libFuzzer: timeout")
+            {
+                Some(Self::Crash)
+            } else {
+                None
+            }
+        })
+    }
 }
 
 #[derive(Debug)]
@@ -36,6 +169,18 @@ pub struct LibFuzzerMergeOutput {
     pub added_feature_count: i32,
 }
 
+#[derive(Debug)]
+pub enum LibFuzzerMergeResult {
+    Merged(LibFuzzerMergeOutput),
+
+    /// The merge failed while running `input`, which crashed the target, or
+    /// ran for longer than `-timeout`.
+    Failed {
+        input: PathBuf,
+        timeout: bool,
+    },
+}
+
 pub struct LibFuzzer {
     setup_dir: PathBuf,
     extra_setup_dir: Option<PathBuf>,
@@ -44,6 +189,17 @@ pub struct LibFuzzer {
     options: Vec<String>,
     env: HashMap<String, String>,
     machine_identity: MachineIdentity,
+    allow_unknown_placeholders: bool,
+    allow_env_placeholders: bool,
+    target_options_shell: bool,
+    crash_blob: Option<BlobUrl>,
+    fork_mode: Option<ForkMode>,
+    jobs_mode: Option<JobsMode>,
+    rss_limit_mb: Option<u64>,
+    timeout: Option<u64>,
+    dotnet_assembly: Option<PathBuf>,
+    qemu_user: Option<QemuUser>,
+    dict: Option<PathBuf>,
 }
 
 impl LibFuzzer {
@@ -64,6 +220,109 @@ impl LibFuzzer {
             extra_setup_dir,
             extra_output_dir,
             machine_identity,
+            allow_unknown_placeholders: false,
+            allow_env_placeholders: false,
+            target_options_shell: false,
+            crash_blob: None,
+            fork_mode: None,
+            jobs_mode: None,
+            rss_limit_mb: None,
+            timeout: None,
+            dotnet_assembly: None,
+            qemu_user: None,
+            dict: None,
+        }
+    }
+
+    pub fn allow_unknown_placeholders(self, value: bool) -> Self {
+        Self {
+            allow_unknown_placeholders: value,
+            ..self
+        }
+    }
+
+    pub fn allow_env_placeholders(self, value: bool) -> Self {
+        Self {
+            allow_env_placeholders: value,
+            ..self
+        }
+    }
+
+    pub fn target_options_shell(self, value: bool) -> Self {
+        Self {
+            target_options_shell: value,
+            ..self
+        }
+    }
+
+    /// The blob that inputs were downloaded from, for `{crash_container}` and
+    /// `{crash_name}`.
+    pub fn crash_blob(self, value: BlobUrl) -> Self {
+        Self {
+            crash_blob: Some(value),
+            ..self
+        }
+    }
+
+    /// Fuzz in fork mode. Only changes how `fuzz()` runs the fuzzer.
+    pub fn fork_mode(self, value: ForkMode) -> Self {
+        Self {
+            fork_mode: Some(value),
+            ..self
+        }
+    }
+
+    /// Fuzz in jobs mode. Only changes how `fuzz()` runs the fuzzer, which is
+    /// then run in the log directory of `value`.
+    pub fn jobs_mode(self, value: JobsMode) -> Self {
+        Self {
+            jobs_mode: Some(value),
+            ..self
+        }
+    }
+
+    /// Pass `-rss_limit_mb`, unless it is set by the target options.
+    pub fn rss_limit_mb(self, value: u64) -> Self {
+        Self {
+            rss_limit_mb: Some(value),
+            ..self
+        }
+    }
+
+    /// Pass `-timeout`, the seconds an input may run for, unless it is set by
+    /// the target options.
+    pub fn timeout(self, value: u64) -> Self {
+        Self {
+            timeout: Some(value),
+            ..self
+        }
+    }
+
+    /// Fuzz a .NET assembly, which the libfuzzer-dotnet harness `exe` runs in
+    /// a loader process, started by `LLVMFuzzerInitialize()`.
+    pub fn dotnet_assembly(self, value: PathBuf) -> Self {
+        Self {
+            dotnet_assembly: Some(value),
+            ..self
+        }
+    }
+
+    /// Run the target under QEMU user mode emulation, when fuzzing, verifying
+    /// and reproducing inputs alike.
+    pub fn qemu_user(self, value: QemuUser) -> Self {
+        Self {
+            qemu_user: Some(value),
+            ..self
+        }
+    }
+
+    /// Fuzz with the dictionary at `value`, passed as `-dict`. Only changes how
+    /// `fuzz()` runs the fuzzer. A `-dict` in the target options takes
+    /// precedence.
+    pub fn dict(self, value: PathBuf) -> Self {
+        Self {
+            dict: Some(value),
+            ..self
         }
     }
 
@@ -102,60 +361,80 @@ impl LibFuzzer {
         extra_args: Option<&[&OsStr]>,
         custom_arg_filter: Option<&dyn Fn(String) -> Option<String>>,
     ) -> Result<std::process::Command> {
-        let mut cmd = std::process::Command::new(&self.exe);
-        cmd.env(PATH, get_path_with_directory(PATH, &self.setup_dir)?)
-            .env_remove("RUST_LOG")
-            .stdin(Stdio::null())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .arg("-workers=1");
+        self.build_target_command(
+            fault_dir,
+            corpus_dir,
+            extra_corpus_dirs,
+            extra_args,
+            custom_arg_filter,
+            self.target_options_shell,
+        )
+    }
 
-        if cfg!(target_family = "unix") {
-            cmd.env(
-                LD_LIBRARY_PATH,
-                get_path_with_directory(LD_LIBRARY_PATH, &self.setup_dir)?,
-            );
-        }
+    fn build_target_command(
+        &self,
+        fault_dir: Option<&Path>,
+        corpus_dir: Option<&Path>,
+        extra_corpus_dirs: Option<&[&Path]>,
+        extra_args: Option<&[&OsStr]>,
+        custom_arg_filter: Option<&dyn Fn(String) -> Option<String>>,
+        shell: bool,
+    ) -> Result<std::process::Command> {
+        let mut args: Vec<OsString> = vec!["-workers=1".into()];
 
         let expand = Expand::new(&self.machine_identity)
-            .machine_id()
+            .allow_unknown_placeholders(self.allow_unknown_placeholders)
+            .allow_env_placeholders(self.allow_env_placeholders)
             .target_exe(&self.exe)
             .target_options(&self.options)
             .setup_dir(&self.setup_dir)
             .set_optional_ref(&self.extra_setup_dir, Expand::extra_setup_dir)
             .set_optional_ref(&self.extra_output_dir, Expand::extra_output_dir)
             .set_optional(corpus_dir, Expand::input_corpus)
-            .set_optional(fault_dir, Expand::crashes);
-
-        // Expand and set environment variables:
-        for (k, v) in &self.env {
-            cmd.env(k, expand.evaluate_value(v)?);
-        }
+            .set_optional(fault_dir, Expand::crashes)
+            .set_optional_ref(&self.crash_blob, Expand::crash_blob);
 
         // Set the read/written main corpus directory:
         if let Some(corpus_dir) = corpus_dir {
-            cmd.arg(corpus_dir);
+            args.push(corpus_dir.into());
         }
 
         // Set extra (readonly) corpus directories that will also be used:
         if let Some(extra_corpus_dirs) = extra_corpus_dirs {
-            cmd.args(extra_corpus_dirs);
+            args.extend(extra_corpus_dirs.iter().map(|dir| dir.into()));
         }
 
         // Pass any extra arguments that we need; this is done in this function
         // rather than the caller so that they can come before any custom options
         // that might interfere (e.g. -help=1 must come before -ignore_remaining_args=1).
         if let Some(extra_args) = extra_args {
-            cmd.args(extra_args);
+            args.extend(extra_args.iter().map(|arg| arg.into()));
         }
 
-        // Check if a max time is already set by the custom options, and set if not:
-        if !self
-            .options
-            .iter()
-            .any(|o| o.starts_with("-max_total_time"))
+        // Check if a max time is already set by the custom options or the
+        // extra arguments, and set if not:
+        let sets_max_total_time = |arg: &str| arg.starts_with("-max_total_time");
+        if !self.options.iter().any(|o| sets_max_total_time(o))
+            && !extra_args
+                .unwrap_or_default()
+                .iter()
+                .any(|arg| sets_max_total_time(&arg.to_string_lossy()))
         {
-            cmd.arg(format!("-max_total_time={DEFAULT_MAX_TOTAL_SECONDS}"));
+            args.push(format!("-max_total_time={DEFAULT_MAX_TOTAL_SECONDS}").into());
+        }
+
+        // As with the max time, options set by the task give way to the target
+        // options.
+        for (flag, value) in [
+            ("rss_limit_mb", self.rss_limit_mb),
+            ("timeout", self.timeout),
+        ] {
+            let prefix = format!("-{flag}=");
+            if let Some(value) = value {
+                if !self.options.iter().any(|o| o.starts_with(&prefix)) {
+                    args.push(format!("{prefix}{value}").into());
+                }
+            }
         }
 
         // Pass custom option arguments last, to lessen the chance that they
@@ -163,12 +442,33 @@ impl LibFuzzer {
         // and also to allow last-one-wins overriding if needed.
         //
         // We also allow filtering out parameters as well:
-        cmd.args(
-            expand
-                .evaluate(&self.options)?
-                .into_iter()
-                .filter_map(custom_arg_filter.unwrap_or(&Some)),
-        );
+        let options: Vec<String> = expand
+            .evaluate(&self.options)?
+            .into_iter()
+            .filter_map(custom_arg_filter.unwrap_or(&Some))
+            .collect();
+
+        let mut cmd = match &self.qemu_user {
+            Some(qemu) => qemu.target_command(&self.exe, &args, &options, shell)?,
+            None => target_command(&self.exe, &args, &options, shell)?,
+        };
+        cmd.env(PATH, get_path_with_directory(PATH, &self.setup_dir)?)
+            .env_remove("RUST_LOG")
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        if cfg!(target_family = "unix") {
+            cmd.env(
+                LD_LIBRARY_PATH,
+                get_path_with_directory(LD_LIBRARY_PATH, &self.setup_dir)?,
+            );
+        }
+
+        // Expand and set environment variables:
+        for (k, v) in &self.env {
+            cmd.env(k, expand.evaluate_value(v)?);
+        }
 
         Ok(cmd)
     }
@@ -276,7 +576,22 @@ impl LibFuzzer {
             .await
             .with_context(|| format_err!("libfuzzer failed to run: {}", self.exe.display()))?;
 
+        self.check_qemu_exit(&result)?;
         if !result.status.success() {
+            // A seed may be checked without first checking `-help=1`, so the
+            // target may have failed to load at all.
+            if self.qemu_user.is_none() {
+                if let Ok(missing) = self.find_missing_libraries().await {
+                    if !missing.is_empty() {
+                        bail!(
+                            "{}. stderr: {:?}",
+                            missing_libraries_error(&missing),
+                            String::from_utf8_lossy(&result.stderr),
+                        );
+                    }
+                }
+            }
+
             bail!(
                 "libFuzzer failed when parsing an initial seed {:?}: cmd:{:?} exit_code: {:?} stdout:{:?} stderr:{:?}",
                 input.file_name().unwrap_or_else(|| input.as_ref()),
@@ -303,16 +618,44 @@ impl LibFuzzer {
             .await
             .with_context(|| format_err!("libfuzzer failed to run: {}", self.exe.display()))?;
 
+        if result.status.success() && self.fork_mode.is_some() {
+            // An unknown flag is only a warning, so a libFuzzer without fork
+            // mode would silently fuzz without it.
+            let help = String::from_utf8_lossy(&result.stderr);
+            let missing = missing_help_flags(&help, ForkMode::FLAGS);
+            if !missing.is_empty() {
+                bail!(
+                    "fuzzer does not support fork mode: '-help=1' does not list: {}",
+                    missing.join(", ")
+                );
+            }
+        }
+
+        self.check_qemu_exit(&result)?;
         if !result.status.success() {
+            // The libraries of the target are loaded by QEMU, from the sysroot,
+            // so can't be checked for on the host.
+            if self.qemu_user.is_some() {
+                bail!("fuzzer does not respond to '-help=1'. output: {:?}", result);
+            }
+
+            // The harness only fails to respond if its loader does, which
+            // can't load or bind the target method of the assembly.
+            if let Some(assembly) = &self.dotnet_assembly {
+                bail!("{}", dotnet_loader_error(assembly, &result));
+            }
+
             // To provide user-actionable errors, try to identify any missing shared libraries.
             match self.find_missing_libraries().await {
                 Ok(missing) => {
                     if missing.is_empty() {
                         bail!("fuzzer does not respond to '-help=1'. no missing shared libraries detected. output: {:?}", result);
                     } else {
-                        let missing = missing.join(", ");
-
-                        bail!("fuzzer does not respond to '-help=1'. missing shared libraries: {}. output: {:?}", missing, result);
+                        bail!(
+                            "{}. fuzzer does not respond to '-help=1'. output: {:?}",
+                            missing_libraries_error(&missing),
+                            result
+                        );
                     }
                 }
                 Err(err) => {
@@ -324,17 +667,34 @@ impl LibFuzzer {
         Ok(())
     }
 
+    // Fail with the reason QEMU gives, if it was unable to run the target.
+    fn check_qemu_exit(&self, output: &std::process::Output) -> Result<()> {
+        if let Some(qemu) = &self.qemu_user {
+            qemu.exit(
+                &output.status.into(),
+                &String::from_utf8_lossy(&output.stderr),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    // Each missing library, with the directories searched for it.
     async fn find_missing_libraries(&self) -> Result<Vec<String>> {
-        let cmd = self.build_std_command(None, None, None, None, None)?;
+        // The libraries are those of the target, so run it directly, even if
+        // its options are otherwise interpreted by a shell. Ask for help, so
+        // that the target exits promptly if it does load.
+        let cmd =
+            self.build_target_command(None, None, None, Some(&["-help=1".as_ref()]), None, false)?;
 
         #[cfg(target_os = "linux")]
-        let blocking = move || dynamic_library::linux::find_missing(cmd);
+        let blocking = move || dynamic_library::linux::find_missing_searches(cmd);
 
         #[cfg(target_os = "windows")]
-        let blocking = move || dynamic_library::windows::find_missing(cmd);
+        let blocking = move || dynamic_library::windows::find_missing_searches(cmd);
 
         let missing = tokio::task::spawn_blocking(blocking).await??;
-        let missing = missing.into_iter().map(|m| m.name).collect();
+        let missing = missing.into_iter().map(|m| m.to_string()).collect();
 
         Ok(missing)
     }
@@ -345,22 +705,54 @@ impl LibFuzzer {
         corpus_dir: impl AsRef<Path>,
         extra_corpus_dirs: &[impl AsRef<Path>],
     ) -> Result<Child> {
-        let extra_corpus_dirs: Vec<&Path> = extra_corpus_dirs.iter().map(|x| x.as_ref()).collect();
+        let mut fault_dir = fault_dir.as_ref().to_owned();
+        let mut corpus_dir = corpus_dir.as_ref().to_owned();
+        let mut extra_corpus_dirs: Vec<PathBuf> = extra_corpus_dirs
+            .iter()
+            .map(|x| x.as_ref().to_owned())
+            .collect();
+
+        // In jobs mode, libFuzzer runs in the log directory, so the directories
+        // it is given must not be relative to ours.
+        if self.jobs_mode.is_some() {
+            fault_dir = std::path::absolute(&fault_dir)?;
+            corpus_dir = std::path::absolute(&corpus_dir)?;
+            for dir in &mut extra_corpus_dirs {
+                *dir = std::path::absolute(&dir)?;
+            }
+        }
+        let extra_corpus_dirs: Vec<&Path> =
+            extra_corpus_dirs.iter().map(PathBuf::as_path).collect();
 
         // When writing a new faulting input, the libFuzzer runtime _exactly_
         // prepends the value of `-artifact_prefix` to the new file name. To
         // specify that a new file `crash-<digest>` should be written to a
         // _directory_ `<corpus_dir>`, we must ensure that the prefix includes a
         // trailing path separator.
-        let artifact_prefix = artifact_prefix(fault_dir.as_ref());
+        let mut extra_args = vec![artifact_prefix(&fault_dir)];
+        if let Some(fork_mode) = &self.fork_mode {
+            extra_args.extend(fork_mode.args());
+        }
+        if let Some(jobs_mode) = &self.jobs_mode {
+            extra_args.extend(jobs_mode.args());
+        }
+        if let Some(dict) = &self.dict {
+            let mut arg = OsString::from("-dict=");
+            arg.push(dict);
+            extra_args.push(arg);
+        }
+        let extra_args: Vec<&OsStr> = extra_args.iter().map(|arg| arg.as_os_str()).collect();
 
         let mut cmd = self.build_command(
-            Some(fault_dir.as_ref()),
-            Some(corpus_dir.as_ref()),
+            Some(&fault_dir),
+            Some(&corpus_dir),
             Some(&extra_corpus_dirs),
-            Some(&[&artifact_prefix]),
+            Some(&extra_args),
             None,
         )?;
+        if let Some(jobs_mode) = &self.jobs_mode {
+            cmd.current_dir(&jobs_mode.log_dir);
+        }
 
         debug!("Running command: {:?}", &cmd);
 
@@ -390,6 +782,11 @@ impl LibFuzzer {
         .check_asan_stderr(true)
         .check_retry_count(retry)
         .add_setup_to_path(true)
+        .allow_unknown_placeholders(self.allow_unknown_placeholders)
+        .allow_env_placeholders(self.allow_env_placeholders)
+        .target_options_shell(self.target_options_shell)
+        .set_optional(self.qemu_user.as_ref(), Tester::qemu_user)
+        .set_optional(self.crash_blob.as_ref(), Tester::crash_blob)
         .set_optional(timeout, Tester::timeout);
 
         if cfg!(target_family = "unix") {
@@ -399,48 +796,194 @@ impl LibFuzzer {
         tester.test_input(test_input).await
     }
 
+    /// Minimize the crashing `input` with `-minimize_crash=1`, for at most
+    /// `max_total_time` seconds. If the input could be minimized, the smallest
+    /// crashing input found is written to `output`.
+    pub async fn minimize_crash(
+        &self,
+        input: impl AsRef<Path>,
+        output: impl AsRef<Path>,
+        max_total_time: u64,
+    ) -> Result<()> {
+        let mut artifact_path = OsString::from("-exact_artifact_path=");
+        artifact_path.push(output.as_ref());
+        let max_total_time = OsString::from(format!("-max_total_time={max_total_time}"));
+        let args: [&OsStr; 4] = [
+            OsStr::new("-minimize_crash=1"),
+            &artifact_path,
+            &max_total_time,
+            input.as_ref().as_os_str(),
+        ];
+
+        let mut cmd = self.build_command(None, None, None, Some(&args), None)?;
+
+        let output = cmd
+            .spawn()
+            .with_context(|| format_err!("libfuzzer failed to start: {}", self.exe.display()))?
+            .wait_with_output()
+            .await
+            .with_context(|| format_err!("libfuzzer failed to run: {}", self.exe.display()))?;
+
+        self.check_qemu_exit(&output)?;
+        if !output.status.success() {
+            bail!(
+                "libfuzzer failed to minimize crash. status:{} stderr:{}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+
+        Ok(())
+    }
+
     pub async fn merge(
         &self,
         corpus_dir: impl AsRef<Path>,
         extra_corpus_dirs: &[impl AsRef<Path>],
     ) -> Result<LibFuzzerMergeOutput> {
+        let output = self
+            .run_merge(corpus_dir.as_ref(), extra_corpus_dirs, &[])
+            .await?;
+
+        Ok(parse_merge_output(&String::from_utf8_lossy(&output.stderr)))
+    }
+
+    /// Merge as with `merge()`, keeping track of the progress of the merge in
+    /// `control_file`, which must not exist yet. If the merge fails, the
+    /// control file tells which input it failed on.
+    pub async fn merge_with_control_file(
+        &self,
+        corpus_dir: impl AsRef<Path>,
+        extra_corpus_dirs: &[impl AsRef<Path>],
+        control_file: impl AsRef<Path>,
+    ) -> Result<LibFuzzerMergeResult> {
+        let control_file = control_file.as_ref();
+        let control_arg = OsString::from(format!("-merge_control_file={}", control_file.display()));
+        let output = self
+            .run_merge(
+                corpus_dir.as_ref(),
+                extra_corpus_dirs,
+                &[control_arg.as_os_str()],
+            )
+            .await?;
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if output.status.success() {
+            return Ok(LibFuzzerMergeResult::Merged(parse_merge_output(&stderr)));
+        }
+
+        let control = tokio::fs::read_to_string(control_file)
+            .await
+            .unwrap_or_default();
+        let Some(input) = merge_failed_input(&control) else {
+            bail!(
+                "libfuzzer merge failed before running any input. status:{} stderr:{}",
+                output.status,
+                stderr
+            );
+        };
+
+        Ok(LibFuzzerMergeResult::Failed {
+            timeout: stderr.contains("ERROR: libFuzzer: timeout"),
+            input,
+        })
+    }
+
+    async fn run_merge(
+        &self,
+        corpus_dir: &Path,
+        extra_corpus_dirs: &[impl AsRef<Path>],
+        extra_args: &[&OsStr],
+    ) -> Result<std::process::Output> {
         let extra_corpus_dirs: Vec<&Path> = extra_corpus_dirs.iter().map(|x| x.as_ref()).collect();
+        let mut args = vec![OsStr::new("-merge=1")];
+        args.extend(extra_args);
+
         let mut cmd = self.build_command(
             None,
-            Some(corpus_dir.as_ref()),
+            Some(corpus_dir),
             Some(&extra_corpus_dirs),
-            Some(&["-merge=1".as_ref()]),
+            Some(&args),
             None,
         )?;
 
-        let output = cmd
-            .spawn()
+        cmd.spawn()
             .with_context(|| format_err!("libfuzzer failed to start: {}", self.exe.display()))?
             .wait_with_output()
             .await
-            .with_context(|| format_err!("libfuzzer failed to run: {}", self.exe.display()))?;
+            .with_context(|| format_err!("libfuzzer failed to run: {}", self.exe.display()))
+    }
+}
 
-        let output_text = String::from_utf8_lossy(&output.stderr);
-        let pat = r"MERGE-OUTER: (\d+) new files with (\d+) new features added";
-        let re = regex::Regex::new(pat).unwrap();
-        let captures = re.captures_iter(&output_text).next();
-        match captures {
-            Some(captures) => {
-                let added_files_count = captures.get(1).unwrap().as_str().parse::<i32>().unwrap();
-                let added_feature_count = captures.get(2).unwrap().as_str().parse::<i32>().unwrap();
-                Ok(LibFuzzerMergeOutput {
-                    added_files_count,
-                    added_feature_count,
-                })
+fn parse_merge_output(stderr: &str) -> LibFuzzerMergeOutput {
+    let pat = r"MERGE-OUTER: (\d+) new files with (\d+) new features added";
+    let re = regex::Regex::new(pat).unwrap();
+    let captures = re.captures_iter(stderr).next();
+    match captures {
+        Some(captures) => {
+            let added_files_count = captures.get(1).unwrap().as_str().parse::<i32>().unwrap();
+            let added_feature_count = captures.get(2).unwrap().as_str().parse::<i32>().unwrap();
+            LibFuzzerMergeOutput {
+                added_files_count,
+                added_feature_count,
             }
-            None => Ok(LibFuzzerMergeOutput {
-                added_files_count: 0,
-                added_feature_count: 0,
-            }),
         }
+        None => LibFuzzerMergeOutput {
+            added_files_count: 0,
+            added_feature_count: 0,
+        },
     }
 }
 
+// The input a merge was running when it failed, from its control file.
+//
+// The control file lists the number of inputs, the number of those in the
+// first (output) corpus, and the input paths. Then, for every input run by the
+// merge, a `STARTED <index> <size>` line is followed by `FT <index> ...` and
+// `COV <index> ...` lines once the input has run.
+fn merge_failed_input(control: &str) -> Option<PathBuf> {
+    let mut lines = control.lines();
+    let count: usize = lines.next()?.trim().parse().ok()?;
+    let _first_corpus: usize = lines.next()?.trim().parse().ok()?;
+    let inputs: Vec<&str> = lines.by_ref().take(count).collect();
+    if inputs.len() < count {
+        return None;
+    }
+
+    let mut running = None;
+    for line in lines {
+        let mut fields = line.split_whitespace();
+        let (Some(kind), Some(index)) = (fields.next(), fields.next()) else {
+            continue;
+        };
+        let Ok(index) = index.parse::<usize>() else {
+            continue;
+        };
+
+        match kind {
+            "STARTED" => running = Some(index),
+            "FT" if running == Some(index) => running = None,
+            _ => {}
+        }
+    }
+
+    inputs.get(running?).map(PathBuf::from)
+}
+
+// Flags are listed one per line, indented, and followed by their default value
+// and description.
+fn missing_help_flags<'a>(help: &str, flags: &[&'a str]) -> Vec<&'a str> {
+    flags
+        .iter()
+        .filter(|flag| {
+            !help
+                .lines()
+                .any(|line| line.split_whitespace().next() == Some(**flag))
+        })
+        .copied()
+        .collect()
+}
+
 #[cfg(target_os = "windows")]
 fn artifact_prefix(fault_dir: &Path) -> OsString {
     if fault_dir.is_absolute() {
@@ -455,10 +998,22 @@ fn artifact_prefix(fault_dir: &Path) -> OsString {
     format!("-artifact_prefix={}/", fault_dir.display()).into()
 }
 
+/// A progress line of libFuzzer's stderr, such as:
+///
+/// `#2097152 pulse  cov: 11 ft: 11 corp: 6/21b lim: 4096 exec/s: 699050 rss: 562Mb`
+///
+/// Only the iteration count and `exec/s` are required. The other fields have
+/// been added or renamed across LLVM versions, and are `None` if missing.
 pub struct LibFuzzerLine {
     _line: String,
     iters: u64,
     execs_sec: f64,
+    fork_job: bool,
+    cov: Option<u64>,
+    features: Option<u64>,
+    corpus_count: Option<u64>,
+    corpus_bytes: Option<u64>,
+    rss_mb: Option<u64>,
 }
 
 impl LibFuzzerLine {
@@ -467,19 +1022,73 @@ impl LibFuzzerLine {
             iters,
             _line: line,
             execs_sec,
+            fork_job: false,
+            cov: None,
+            features: None,
+            corpus_count: None,
+            corpus_bytes: None,
+            rss_mb: None,
         }
     }
 
     pub fn parse(line: &str) -> Result<Option<Self>> {
-        let caps = match LIBFUZZERLINEREGEX.captures(line) {
-            Some(caps) => caps,
-            None => return Ok(None),
+        let (caps, fork_job) = if let Some(caps) = LIBFUZZERLINEREGEX.captures(line) {
+            (caps, false)
+        } else if let Some(caps) = LIBFUZZERFORKLINEREGEX.captures(line) {
+            (caps, true)
+        } else {
+            return Ok(None);
         };
 
         let iters = caps[1].parse()?;
         let execs_sec = caps[2].parse()?;
 
-        Ok(Some(Self::new(line.to_string(), iters, execs_sec)))
+        // The fields follow the iteration count, and anything before it is
+        // unrelated output.
+        let fields = &line[caps.get(0).map_or(0, |m| m.start())..];
+
+        Ok(Some(
+            Self {
+                fork_job,
+                ..Self::new(line.to_string(), iters, execs_sec)
+            }
+            .parse_fields(fields),
+        ))
+    }
+
+    fn parse_fields(mut self, fields: &str) -> Self {
+        let mut tokens = fields.split_whitespace();
+        while let Some(name) = tokens.next() {
+            if !matches!(name, "cov:" | "ft:" | "corp:" | "units:" | "rss:") {
+                continue;
+            }
+            let Some(value) = tokens.next() else {
+                break;
+            };
+
+            match name {
+                "cov:" => self.cov = value.parse().ok(),
+                "ft:" => self.features = value.parse().ok(),
+                // `<count>/<size>`, or only the count in fork mode.
+                "corp:" => {
+                    let (count, size) = value.split_once('/').unwrap_or((value, ""));
+                    self.corpus_count = count.parse().ok();
+                    self.corpus_bytes = parse_size(size);
+                }
+                // Before `corp:`, in LLVM 5 and earlier.
+                "units:" => self.corpus_count = value.parse().ok(),
+                "rss:" => self.rss_mb = value.strip_suffix("Mb").and_then(|v| v.parse().ok()),
+                _ => {}
+            }
+        }
+
+        self
+    }
+
+    /// Whether the line reports a finished fork mode job, after which any new
+    /// artifacts have been written.
+    pub fn is_fork_job(&self) -> bool {
+        self.fork_job
     }
 
     pub fn iters(&self) -> u64 {
@@ -489,6 +1098,68 @@ impl LibFuzzerLine {
     pub fn execs_sec(&self) -> f64 {
         self.execs_sec
     }
+
+    /// Number of coverage points (`cov:`).
+    pub fn cov(&self) -> Option<u64> {
+        self.cov
+    }
+
+    /// Number of features (`ft:`).
+    pub fn features(&self) -> Option<u64> {
+        self.features
+    }
+
+    /// Number of inputs in the in-memory corpus.
+    pub fn corpus_count(&self) -> Option<u64> {
+        self.corpus_count
+    }
+
+    /// Total size of the in-memory corpus.
+    pub fn corpus_bytes(&self) -> Option<u64> {
+        self.corpus_bytes
+    }
+
+    /// Peak resident memory of the fuzzer, in megabytes.
+    pub fn rss_mb(&self) -> Option<u64> {
+        self.rss_mb
+    }
+}
+
+// Sizes as printed by libFuzzer, such as `21b`, `12Kb` or `3Mb`.
+fn parse_size(size: &str) -> Option<u64> {
+    let (value, shift) = if let Some(value) = size.strip_suffix("Mb") {
+        (value, 20)
+    } else if let Some(value) = size.strip_suffix("Kb") {
+        (value, 10)
+    } else {
+        (size.strip_suffix('b')?, 0)
+    };
+
+    value.parse::<u64>().ok().map(|value| value << shift)
+}
+
+// The failure of the libfuzzer-dotnet loader, with the unhandled exception it
+// exited with, if it reported one.
+fn dotnet_loader_error(assembly: &Path, output: &std::process::Output) -> String {
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    match CrashLog::parse(stderr) {
+        Ok(log) if log.sanitizer == ".NET" => format!(
+            "the libfuzzer-dotnet loader failed to load `{}`: {}. output: {:?}",
+            assembly.display(),
+            log.summary,
+            output
+        ),
+        _ => format!(
+            "the libfuzzer-dotnet harness does not respond to '-help=1' for `{}`. output: {:?}",
+            assembly.display(),
+            output
+        ),
+    }
+}
+
+// Reported verbatim as the task error, so should say what to fix first.
+fn missing_libraries_error(missing: &[String]) -> String {
+    format!("target failed to start: missing {}", missing.join(", "))
 }
 
 #[cfg(test)]
@@ -511,6 +1182,576 @@ mod tests {
         assert!((execs_sec - expected).abs() < f64::EPSILON);
     }
 
+    // (line, (iters, exec/s, cov, ft, corpus count, corpus bytes, rss))
+    type Expected = (
+        u64,
+        f64,
+        Option<u64>,
+        Option<u64>,
+        Option<u64>,
+        Option<u64>,
+        Option<u64>,
+    );
+
+    #[test]
+    fn test_libfuzzer_line_versions() {
+        let cases: &[(&str, &str, Expected)] = &[
+            (
+                "LLVM 5",
+                "#2\tINITED cov: 24 bits: 24 units: 1 exec/s: 0",
+                (2, 0.0, Some(24), None, Some(1), None, None),
+            ),
+            (
+                "LLVM 8",
+                "#2\tINITED cov: 3 ft: 3 corp: 1/1b exec/s: 0 rss: 26Mb",
+                (2, 0.0, Some(3), Some(3), Some(1), Some(1), Some(26)),
+            ),
+            (
+                "LLVM 10",
+                "#3\tNEW    cov: 4 ft: 4 corp: 2/2b lim: 4 exec/s: 0 rss: 26Mb L: 1/1 MS: 1 ChangeBit-",
+                (3, 0.0, Some(4), Some(4), Some(2), Some(2), Some(26)),
+            ),
+            (
+                "LLVM 14",
+                "#524288\tpulse  cov: 5 ft: 6 corp: 3/6b lim: 8 exec/s: 262144 rss: 27Mb",
+                (524288, 262144.0, Some(5), Some(6), Some(3), Some(6), Some(27)),
+            ),
+            (
+                "LLVM 16",
+                "#1211\tREDUCE cov: 7 ft: 9 corp: 4/10b lim: 14 exec/s: 0 rss: 28Mb L: 3/4 MS: 2 EraseBytes-CopyPart-",
+                (1211, 0.0, Some(7), Some(9), Some(4), Some(10), Some(28)),
+            ),
+            (
+                "corpus in Kb",
+                "#65536\tpulse  cov: 120 ft: 300 corp: 50/12Kb lim: 4096 exec/s: 21845 rss: 512Mb",
+                (65536, 21845.0, Some(120), Some(300), Some(50), Some(12 << 10), Some(512)),
+            ),
+            (
+                "done",
+                "#100000\tDONE   cov: 120 ft: 300 corp: 50/2Mb lim: 4096 exec/s: 33333 rss: 512Mb",
+                (100000, 33333.0, Some(120), Some(300), Some(50), Some(2 << 20), Some(512)),
+            ),
+            (
+                "fork mode",
+                "#38144: cov: 211 ft: 514 corp: 73 exec/s 1907 oom/timeout/crash: 0/0/2 time: 21s job: 5 dft_time: 0",
+                (38144, 1907.0, Some(211), Some(514), Some(73), None, None),
+            ),
+            (
+                "interleaved sanitizer output",
+                "==4242==WARNING: MemorySanitizer: use-of-uninitialized-value#8\tNEW    cov: 4 ft: 5 corp: 3/3b exec/s: 0 rss: 30Mb",
+                (8, 0.0, Some(4), Some(5), Some(3), Some(3), Some(30)),
+            ),
+            (
+                "truncated",
+                "#16\tpulse  cov: 4 ft: 5 corp: 3/3b exec/s: 8 rss:",
+                (16, 8.0, Some(4), Some(5), Some(3), Some(3), None),
+            ),
+        ];
+
+        for (version, line, expected) in cases {
+            let parsed = LibFuzzerLine::parse(line)
+                .unwrap_or_else(|err| panic!("{version}: {err}"))
+                .unwrap_or_else(|| panic!("{version}: not parsed"));
+
+            let actual = (
+                parsed.iters(),
+                parsed.execs_sec(),
+                parsed.cov(),
+                parsed.features(),
+                parsed.corpus_count(),
+                parsed.corpus_bytes(),
+                parsed.rss_mb(),
+            );
+            assert_eq!(&actual, expected, "{version}");
+        }
+    }
+
+    #[test]
+    fn test_libfuzzer_line_other_output() {
+        let lines = [
+            "INFO: Seed: 3718522933",
+            "INFO: Loaded 1 modules   (9 inline 8-bit counters): 9 [0x5d6ea0, 0x5d6ea9),",
+            "==4242==ERROR: AddressSanitizer: heap-buffer-overflow on address 0x602000000011",
+            "    #0 0x4f2a1b in LLVMFuzzerTestOneInput /src/fuzz.c:10:5",
+            "#1\tNEW    cov: 4 ft: 4",
+            "",
+        ];
+
+        for line in lines {
+            assert!(LibFuzzerLine::parse(line).unwrap().is_none(), "{line}");
+        }
+    }
+
+    #[test]
+    fn test_libfuzzer_line_fork_job() {
+        let line = "#38144: cov: 211 ft: 514 corp: 73 exec/s 1907 oom/timeout/crash: 0/0/2 time: 21s job: 5 dft_time: 0";
+
+        let parsed = LibFuzzerLine::parse(line)
+            .expect("parse error")
+            .expect("no captures");
+
+        assert_eq!(parsed.iters(), 38144);
+        assert!((parsed.execs_sec() - 1907.0).abs() < f64::EPSILON);
+        assert!(parsed.is_fork_job());
+
+        let line = r"#2097152        pulse  cov: 11 ft: 11 corp: 6/21b lim: 4096 exec/s: 699050 rss: 562Mb";
+        assert!(!LibFuzzerLine::parse(line).unwrap().unwrap().is_fork_job());
+    }
+
+    #[test]
+    fn test_fork_mode_args() {
+        let fork_mode = ForkMode {
+            jobs: 4,
+            ignore_crashes: true,
+            ignore_ooms: false,
+        };
+
+        assert_eq!(
+            fork_mode.args(),
+            ["-fork=4", "-ignore_crashes=1", "-ignore_ooms=0"]
+        );
+    }
+
+    #[test]
+    fn test_jobs_mode_args() {
+        let jobs_mode = JobsMode {
+            jobs: 40,
+            workers: 4,
+            log_dir: PathBuf::from("logs"),
+        };
+
+        assert_eq!(jobs_mode.args(), ["-jobs=40", "-workers=4"]);
+    }
+
+    #[test]
+    fn test_artifact_kind_from_file_name() {
+        for (name, kind) in [
+            (
+                "crash-da39a3ee5e6b4b0d3255bfef95601890afd80709",
+                ArtifactKind::Crash,
+            ),
+            (
+                "timeout-da39a3ee5e6b4b0d3255bfef95601890afd80709",
+                ArtifactKind::Crash,
+            ),
+            (
+                "slow-unit-da39a3ee5e6b4b0d3255bfef95601890afd80709",
+                ArtifactKind::Crash,
+            ),
+            (
+                "oom-da39a3ee5e6b4b0d3255bfef95601890afd80709",
+                ArtifactKind::Oom,
+            ),
+            (
+                "leak-da39a3ee5e6b4b0d3255bfef95601890afd80709",
+                ArtifactKind::Leak,
+            ),
+        ] {
+            assert_eq!(ArtifactKind::classify(name, ""), kind, "{name}");
+        }
+
+        // The name takes precedence over the output.
+        let stderr = "==1==ERROR: LeakSanitizer: detected memory leaks\n";
+        assert_eq!(
+            ArtifactKind::classify("oom-da39a3ee5e6b4b0d3255bfef95601890afd80709", stderr),
+            ArtifactKind::Oom
+        );
+    }
+
+    #[test]
+    fn test_artifact_report_type() {
+        for (name, report_type) in [
+            ("crash-1", Some(ReportType::Crash)),
+            ("timeout-1", Some(ReportType::Timeout)),
+            ("slow-unit-1", Some(ReportType::Crash)),
+            ("oom-1", Some(ReportType::Oom)),
+            ("leak-1", Some(ReportType::Leak)),
+            ("input", None),
+        ] {
+            assert_eq!(ArtifactKind::report_type(name), report_type, "{name}");
+        }
+    }
+
+    #[test]
+    fn test_artifact_kind_from_stderr() {
+        let oom = "\
+==4242== ERROR: libFuzzer: out-of-memory (used: 2113Mb; exceeds: 2048Mb)
+   To change the out-of-memory limit use -rss_limit_mb=<N>
+
+Live Heap Allocations: 2198335172 bytes in 14 chunks; quarantined: 8968 bytes in 1 chunks
+SUMMARY: libFuzzer: out-of-memory
+";
+        let malloc = "\
+==4242== ERROR: libFuzzer: out-of-memory (malloc(4294967295))
+   To change the out-of-memory limit use -rss_limit_mb=<N>
+";
+        let leak = "\
+INFO: a leak has been found in the initial corpus.
+
+=================================================================
+==4242==ERROR: LeakSanitizer: detected memory leaks
+
+Direct leak of 64 byte(s) in 1 object(s) allocated from:
+    #0 0x4c5c4d in malloc (/setup/fuzz+0x4c5c4d)
+SUMMARY: AddressSanitizer: 64 byte(s) leaked in 1 allocation(s).
+";
+        let crash = "\
+==4242==ERROR: AddressSanitizer: heap-buffer-overflow on address 0x602000000011
+READ of size 1 at 0x602000000011 thread T0
+SUMMARY: AddressSanitizer: heap-buffer-overflow (/setup/fuzz+0x4f1ead) in LLVMFuzzerTestOneInput
+";
+
+        for (stderr, kind) in [
+            (oom, ArtifactKind::Oom),
+            (malloc, ArtifactKind::Oom),
+            (leak, ArtifactKind::Leak),
+            (crash, ArtifactKind::Crash),
+            ("", ArtifactKind::Crash),
+        ] {
+            assert_eq!(ArtifactKind::classify("input", stderr), kind, "{stderr}");
+        }
+
+        // In fork mode, the output of earlier jobs is still in the buffer.
+        let stderr = format!("{oom}#100: cov: 10 ft: 12 corp: 3 exec/s 50\n{leak}");
+        assert_eq!(ArtifactKind::classify("input", &stderr), ArtifactKind::Leak);
+    }
+
+    #[test]
+    fn test_rss_limit_mb() -> Result<()> {
+        let args = |options: &[&str], rss_limit_mb: Option<u64>| -> Result<Vec<String>> {
+            let mut fuzzer = LibFuzzer::new(
+                PathBuf::from("fuzz"),
+                options.iter().map(|o| o.to_string()).collect(),
+                HashMap::new(),
+                PathBuf::from("setup"),
+                None,
+                None,
+                MachineIdentity {
+                    machine_id: uuid::Uuid::new_v4(),
+                    machine_name: "node".to_owned(),
+                    scaleset_name: None,
+                },
+            );
+            if let Some(rss_limit_mb) = rss_limit_mb {
+                fuzzer = fuzzer.rss_limit_mb(rss_limit_mb);
+            }
+
+            let cmd = fuzzer.build_std_command(None, None, None, None, None)?;
+            Ok(cmd
+                .get_args()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect())
+        };
+
+        assert!(!args(&[], None)?
+            .iter()
+            .any(|a| a.starts_with("-rss_limit_mb")));
+        assert!(args(&[], Some(4096))?.contains(&"-rss_limit_mb=4096".to_owned()));
+
+        let args = args(&["-rss_limit_mb=0"], Some(4096))?;
+        let limits: Vec<_> = args
+            .iter()
+            .filter(|a| a.starts_with("-rss_limit_mb"))
+            .collect();
+        assert_eq!(limits, ["-rss_limit_mb=0"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_timeout() -> Result<()> {
+        let fuzzer = |options: &[&str]| {
+            LibFuzzer::new(
+                PathBuf::from("fuzz"),
+                options.iter().map(|o| o.to_string()).collect(),
+                HashMap::new(),
+                PathBuf::from("setup"),
+                None,
+                None,
+                MachineIdentity {
+                    machine_id: uuid::Uuid::new_v4(),
+                    machine_name: "node".to_owned(),
+                    scaleset_name: None,
+                },
+            )
+            .timeout(30)
+        };
+        let timeouts = |fuzzer: LibFuzzer| -> Result<Vec<String>> {
+            let cmd = fuzzer.build_std_command(None, None, None, None, None)?;
+            Ok(cmd
+                .get_args()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .filter(|arg| arg.starts_with("-timeout"))
+                .collect())
+        };
+
+        assert_eq!(timeouts(fuzzer(&[]))?, ["-timeout=30"]);
+        assert_eq!(
+            timeouts(fuzzer(&["-timeout_exitcode=1"]))?,
+            ["-timeout=30", "-timeout_exitcode=1"]
+        );
+        assert_eq!(timeouts(fuzzer(&["-timeout=5"]))?, ["-timeout=5"]);
+
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_qemu_user() -> Result<()> {
+        let fuzzer = LibFuzzer::new(
+            PathBuf::from("/setup/fuzz"),
+            vec!["-max_len=10".to_owned()],
+            HashMap::new(),
+            PathBuf::from("/setup"),
+            None,
+            None,
+            MachineIdentity {
+                machine_id: uuid::Uuid::new_v4(),
+                machine_name: "node".to_owned(),
+                scaleset_name: None,
+            },
+        )
+        .qemu_user(QemuUser {
+            arch: "aarch64".to_owned(),
+            qemu_path: None,
+            sysroot: Some("/usr/aarch64-linux-gnu".into()),
+        });
+
+        let cmd = fuzzer.build_std_command(None, None, None, Some(&["-help=1".as_ref()]), None)?;
+        assert_eq!(cmd.get_program(), "qemu-aarch64");
+
+        let args: Vec<_> = cmd.get_args().collect();
+        assert_eq!(args[0], "/setup/fuzz");
+        assert_eq!(args[1], "-workers=1");
+        assert_eq!(args[2], "-help=1");
+        assert_eq!(args.last().unwrap(), &"-max_len=10");
+
+        let env: HashMap<_, _> = cmd.get_envs().collect();
+        assert_eq!(
+            env[OsStr::new("QEMU_LD_PREFIX")],
+            Some(OsStr::new("/usr/aarch64-linux-gnu"))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_failed_input() {
+        let header = "3\n1\n/corpus/a\n/inputs/b\n/inputs/c\n";
+
+        // Failed on the second input, after the first had run.
+        let control = format!("{header}STARTED 0 10\nFT 0 1 2\nCOV 0 3\nSTARTED 1 20\n");
+        assert_eq!(
+            merge_failed_input(&control),
+            Some(PathBuf::from("/inputs/b"))
+        );
+
+        // Every started input has run.
+        let control = format!("{header}STARTED 0 10\nFT 0 1 2\nCOV 0 3\n");
+        assert_eq!(merge_failed_input(&control), None);
+
+        // Failed before running any input, or before writing the header.
+        assert_eq!(merge_failed_input(header), None);
+        assert_eq!(merge_failed_input("3\n1\n/corpus/a\n"), None);
+        assert_eq!(merge_failed_input(""), None);
+    }
+
+    #[test]
+    fn test_parse_merge_output() {
+        let output = parse_merge_output(
+            "MERGE-OUTER: the control file has 71 bytes\n\
+             MERGE-OUTER: 2 new files with 14 new features added; 9 new coverage edges\n",
+        );
+        assert_eq!(output.added_files_count, 2);
+        assert_eq!(output.added_feature_count, 14);
+
+        let output = parse_merge_output("INFO: Seed: 1\n");
+        assert_eq!(output.added_files_count, 0);
+    }
+
+    #[test]
+    fn test_missing_help_flags() {
+        let help = "Usage:\n\
+                    Flags: (strictly in form -flag=value)\n \
+                    verbosity\t1\tVerbosity level.\n \
+                    fork\t0\tExperimental mode where fuzzing happens in a subprocess.\n \
+                    ignore_ooms\t1\tfork mode: ignore OOMs.\n";
+
+        assert_eq!(
+            missing_help_flags(help, ForkMode::FLAGS),
+            ["ignore_crashes"]
+        );
+        assert!(missing_help_flags(help, &["fork", "verbosity"]).is_empty());
+    }
+
+    // Simulates the `-help=1` output of a libFuzzer, with or without fork mode.
+    #[cfg(target_family = "unix")]
+    async fn fake_libfuzzer(dir: &Path, fork_flags: bool) -> Result<PathBuf> {
+        let flags = if fork_flags {
+            " fork\t0\tfork mode\n ignore_crashes\t0\tfork mode\n ignore_ooms\t1\tfork mode\n"
+        } else {
+            ""
+        };
+        let script = format!(
+            "#!/bin/sh\n\
+             if [ \"$2\" = \"-help=1\" ]; then\n\
+             printf 'Flags:\\n runs\\t-1\\truns\\n{flags}' >&2\n\
+             fi\n\
+             exit 0\n"
+        );
+
+        let path = dir.join(if fork_flags { "fork.sh" } else { "no-fork.sh" });
+        write_file(&path, &script).await?;
+        crate::fs::set_executable(&path).await?;
+        Ok(path)
+    }
+
+    #[tokio::test]
+    #[cfg(target_family = "unix")]
+    async fn test_minimize_crash() -> Result<()> {
+        let dir = tempdir()?;
+
+        // Keeps the first 4 bytes of the input, if minimizing.
+        let exe = dir.path().join("minimize.sh");
+        let script = "#!/bin/sh\n\
+             for arg in \"$@\"; do\n\
+             case \"$arg\" in\n\
+             -minimize_crash=1) minimize=1 ;;\n\
+             -exact_artifact_path=*) artifact=\"${arg#-exact_artifact_path=}\" ;;\n\
+             -max_total_time=*) echo \"$arg\" >> \"$artifact.args\" ;;\n\
+             -*) ;;\n\
+             *) input=\"$arg\" ;;\n\
+             esac\n\
+             done\n\
+             [ -n \"$minimize\" ] || exit 1\n\
+             head -c 4 \"$input\" > \"$artifact\"\n";
+        write_file(&exe, script).await?;
+        crate::fs::set_executable(&exe).await?;
+
+        let input = dir.path().join("crash");
+        write_file(&input, "crashing input").await?;
+        let output = dir.path().join("minimized");
+
+        let fuzzer = LibFuzzer::new(
+            exe,
+            vec![],
+            HashMap::new(),
+            dir.path().to_owned(),
+            None,
+            None,
+            MachineIdentity {
+                machine_id: uuid::Uuid::new_v4(),
+                machine_name: "test-input".into(),
+                scaleset_name: None,
+            },
+        );
+        fuzzer.minimize_crash(&input, &output, 30).await?;
+
+        assert_eq!(tokio::fs::read_to_string(&output).await?, "cras");
+        // The time budget replaces the default `-max_total_time`.
+        assert_eq!(
+            tokio::fs::read_to_string(dir.path().join("minimized.args")).await?,
+            "-max_total_time=30\n"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg(target_family = "unix")]
+    async fn verify_fork_mode_flags() -> Result<()> {
+        let temp_setup_dir = tempdir()?;
+        let fork_mode = ForkMode {
+            jobs: 2,
+            ignore_crashes: true,
+            ignore_ooms: true,
+        };
+
+        let libfuzzer = |exe| {
+            LibFuzzer::new(
+                exe,
+                vec![],
+                HashMap::new(),
+                temp_setup_dir.path().to_owned(),
+                None,
+                None,
+                MachineIdentity {
+                    machine_id: uuid::Uuid::new_v4(),
+                    machine_name: "test-input".into(),
+                    scaleset_name: None,
+                },
+            )
+        };
+
+        let with_fork = fake_libfuzzer(temp_setup_dir.path(), true).await?;
+        let without_fork = fake_libfuzzer(temp_setup_dir.path(), false).await?;
+
+        assert!(libfuzzer(with_fork.clone())
+            .fork_mode(fork_mode)
+            .verify_once(true, &[])
+            .await
+            .is_ok());
+
+        let err = libfuzzer(without_fork.clone())
+            .fork_mode(fork_mode)
+            .verify_once(true, &[])
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "fuzzer does not support fork mode: '-help=1' does not list: fork, ignore_crashes, ignore_ooms"
+        );
+
+        // The flags are only required in fork mode.
+        assert!(libfuzzer(without_fork).verify_once(true, &[]).await.is_ok());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg(target_family = "unix")]
+    async fn verify_dotnet_loader_failure() -> Result<()> {
+        let temp_setup_dir = tempdir()?;
+
+        // The loader's unhandled exception, relayed by the harness.
+        let harness = temp_setup_dir.path().join("libfuzzer-dotnet.sh");
+        let script = "#!/bin/sh\n\
+                      printf 'Unhandled exception. System.Exception: unable to resolve type: Fuzz.Target\\n' >&2\n\
+                      printf '   at LibFuzzerDotnetLoader.Program.TryMain()\\n' >&2\n\
+                      printf '   at LibFuzzerDotnetLoader.Program.Main(String[] args)\\n' >&2\n\
+                      exit 1\n";
+        write_file(&harness, script).await?;
+        crate::fs::set_executable(&harness).await?;
+
+        let err = LibFuzzer::new(
+            harness,
+            vec![],
+            HashMap::new(),
+            temp_setup_dir.path().to_owned(),
+            None,
+            None,
+            MachineIdentity {
+                machine_id: uuid::Uuid::new_v4(),
+                machine_name: "test-input".into(),
+                scaleset_name: None,
+            },
+        )
+        .dotnet_assembly("/setup/Fuzz.dll".into())
+        .verify_once(true, &[])
+        .await
+        .unwrap_err();
+
+        assert!(
+            err.to_string().starts_with(
+                "the libfuzzer-dotnet loader failed to load `/setup/Fuzz.dll`: \
+                 Unhandled exception. System.Exception: unable to resolve type: Fuzz.Target. output:"
+            ),
+            "{err}"
+        );
+
+        Ok(())
+    }
+
     #[tokio::test]
     #[cfg(target_family = "unix")]
     async fn verify_initial_inputs() -> Result<()> {
@@ -594,4 +1835,109 @@ mod tests {
 
         Ok(())
     }
+
+    fn compile(dir: &Path, compiler: &str, args: &[&str]) -> Result<()> {
+        let status = std::process::Command::new(compiler)
+            .args(args)
+            .current_dir(dir)
+            .status()
+            .with_context(|| format!("failed to run {compiler}"))?;
+
+        if !status.success() {
+            bail!("{compiler} failed: {status}");
+        }
+
+        Ok(())
+    }
+
+    // Build a target linked to `libonefuzz_missing.so`, then delete the library.
+    #[cfg(target_os = "linux")]
+    async fn missing_library_target(dir: &Path) -> Result<PathBuf> {
+        write_file(dir.join("missing.c"), "int missing(void) { return 0; }\n").await?;
+        write_file(
+            dir.join("target.c"),
+            "int missing(void);\nint main(void) { return missing(); }\n",
+        )
+        .await?;
+
+        let args = [
+            "-shared",
+            "-fPIC",
+            "-o",
+            "libonefuzz_missing.so",
+            "missing.c",
+        ];
+        compile(dir, "cc", &args)?;
+        let args = ["-o", "target", "target.c", "-L.", "-lonefuzz_missing"];
+        compile(dir, "cc", &args)?;
+
+        tokio::fs::remove_file(dir.join("libonefuzz_missing.so")).await?;
+        Ok(dir.join("target"))
+    }
+
+    // Build a target linked to `onefuzz_missing.dll`, then delete the DLL.
+    // Requires `cl.exe` on the `PATH`, as in a developer prompt.
+    #[cfg(target_os = "windows")]
+    async fn missing_library_target(dir: &Path) -> Result<PathBuf> {
+        write_file(
+            dir.join("onefuzz_missing.c"),
+            "__declspec(dllexport) int missing(void) { return 0; }\n",
+        )
+        .await?;
+        write_file(
+            dir.join("target.c"),
+            "__declspec(dllimport) int missing(void);\nint main(void) { return missing(); }\n",
+        )
+        .await?;
+
+        let args = ["/nologo", "/LD", "onefuzz_missing.c"];
+        compile(dir, "cl.exe", &args)?;
+        let args = ["/nologo", "target.c", "onefuzz_missing.lib"];
+        compile(dir, "cl.exe", &args)?;
+
+        tokio::fs::remove_file(dir.join("onefuzz_missing.dll")).await?;
+        Ok(dir.join("target.exe"))
+    }
+
+    #[tokio::test]
+    #[cfg_attr(not(feature = "integration_test"), ignore)]
+    async fn verify_missing_library() -> Result<()> {
+        let temp_setup_dir = tempdir()?;
+        let setup_dir = temp_setup_dir.path();
+        let target = missing_library_target(setup_dir).await?;
+
+        let fuzzer = LibFuzzer::new(
+            target,
+            vec![],
+            HashMap::new(),
+            setup_dir.to_owned(),
+            None,
+            None,
+            MachineIdentity {
+                machine_id: uuid::Uuid::new_v4(),
+                machine_name: "test-input".into(),
+                scaleset_name: None,
+            },
+        );
+
+        // The setup directory is added to the library search path.
+        #[cfg(target_os = "linux")]
+        let expected = format!(
+            "target failed to start: missing libonefuzz_missing.so (searched: {}:",
+            setup_dir.display()
+        );
+        #[cfg(target_os = "windows")]
+        let expected = "target failed to start: missing onefuzz_missing.dll";
+
+        // With and without `-help=1`, which is checked first.
+        for check_fuzzer_help in [true, false] {
+            let err = fuzzer
+                .verify_once(check_fuzzer_help, &[])
+                .await
+                .unwrap_err();
+            assert!(format!("{err:?}").contains(&*expected), "{err:?}");
+        }
+
+        Ok(())
+    }
 }
@@ -58,7 +58,7 @@ impl MachineIdentity {
         let body = match fs::read_to_string(&path).await {
             Ok(body) => body,
             Err(_) => {
-                let resp = reqwest::Client::new()
+                let resp = reqwest_retry::proxy::client()
                     .get(IMS_ID_URL)
                     .timeout(Duration::from_millis(500))
                     .header("Metadata", "true")
@@ -80,7 +80,7 @@ impl MachineIdentity {
         let body = match fs::read_to_string(&path).await {
             Ok(body) => body,
             Err(_) => {
-                let resp = reqwest::Client::new()
+                let resp = reqwest_retry::proxy::client()
                     .get(VM_NAME_URL)
                     .timeout(Duration::from_millis(500))
                     .header("Metadata", "true")
@@ -102,7 +102,7 @@ impl MachineIdentity {
             return Ok(Some(scaleset_name));
         }
 
-        if let Ok(resp) = reqwest::Client::new()
+        if let Ok(resp) = reqwest_retry::proxy::client()
             .get(VM_SCALESET_NAME)
             .timeout(Duration::from_millis(500))
             .header("Metadata", "true")
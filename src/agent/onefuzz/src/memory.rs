@@ -19,6 +19,15 @@ pub fn available_bytes() -> Result<u64> {
     Ok(bytes)
 }
 
+#[cfg(target_os = "windows")]
+pub fn total_bytes() -> Result<u64> {
+    let info = get_performance_info()?;
+    let bytes = info.PhysicalTotal * info.PageSize;
+    let bytes = u64::try_from(bytes)?;
+
+    Ok(bytes)
+}
+
 #[cfg(target_os = "windows")]
 fn get_performance_info() -> Result<PERFORMANCE_INFORMATION> {
     use anyhow::Context;
@@ -49,6 +58,17 @@ pub fn available_bytes() -> Result<u64> {
     Ok(available_bytes)
 }
 
+#[cfg(target_os = "linux")]
+pub fn total_bytes() -> Result<u64> {
+    const BYTES_PER_KB: u64 = 1024;
+
+    let meminfo = std::fs::read_to_string("/proc/meminfo")?;
+    let total_kb = parse_total_kb(&meminfo)?;
+    let total_bytes = total_kb * BYTES_PER_KB;
+
+    Ok(total_bytes)
+}
+
 #[cfg(target_os = "linux")]
 fn parse_available_kb(meminfo: &str) -> Result<u64> {
     let captures = AVAILABLE_KB
@@ -64,9 +84,25 @@ fn parse_available_kb(meminfo: &str) -> Result<u64> {
     Ok(available_kb)
 }
 
+#[cfg(target_os = "linux")]
+fn parse_total_kb(meminfo: &str) -> Result<u64> {
+    let captures = TOTAL_KB
+        .captures(meminfo)
+        .ok_or_else(|| format_err!("`MemTotal` not found in `/proc/meminfo`"))?;
+
+    let total_kb = captures
+        .get(1)
+        .ok_or_else(|| format_err!("`MemTotal` not found in `/proc/meminfo`"))?
+        .as_str()
+        .parse()?;
+
+    Ok(total_kb)
+}
+
 #[cfg(target_os = "linux")]
 lazy_static::lazy_static! {
     static ref AVAILABLE_KB: Regex = Regex::new(r"MemAvailable:\s*(\d+) kB").unwrap();
+    static ref TOTAL_KB: Regex = Regex::new(r"MemTotal:\s*(\d+) kB").unwrap();
 }
 
 #[cfg(test)]
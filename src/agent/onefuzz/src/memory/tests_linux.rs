@@ -1,6 +1,6 @@
 use anyhow::Result;
 
-use super::parse_available_kb;
+use super::{parse_available_kb, parse_total_kb};
 
 #[test]
 fn test_parse_available_kb() -> Result<()> {
@@ -49,6 +49,16 @@ fn test_parse_available_kb_missing() {
     assert!(parse_available_kb("MemFree: 198308 kB").is_err());
 }
 
+#[test]
+fn test_parse_total_kb() -> Result<()> {
+    assert_eq!(parse_total_kb(MEMINFO)?, 16036984);
+    assert_eq!(parse_total_kb("MemTotal:       16036984 kB")?, 16036984);
+    assert!(parse_total_kb("MemAvailable:    1001092 kB").is_err());
+    assert!(parse_total_kb("MemTotal: 16036984 MB").is_err());
+
+    Ok(())
+}
+
 const MEMINFO: &str = "MemTotal:       16036984 kB
 MemFree:          198308 kB
 MemAvailable:    1001092 kB
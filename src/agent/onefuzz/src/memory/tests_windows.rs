@@ -1,4 +1,4 @@
-use super::available_bytes;
+use super::{available_bytes, total_bytes};
 
 #[test]
 fn can_read_available_memory() -> anyhow::Result<()> {
@@ -6,3 +6,10 @@ fn can_read_available_memory() -> anyhow::Result<()> {
     assert!(available_bytes? > 0);
     Ok(())
 }
+
+#[test]
+fn can_read_total_memory() -> anyhow::Result<()> {
+    let total_bytes = total_bytes();
+    assert!(total_bytes? > 0);
+    Ok(())
+}
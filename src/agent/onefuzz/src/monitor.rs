@@ -1,35 +1,150 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
+use std::collections::{HashSet, VecDeque};
 use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
 
 use anyhow::{format_err, Result};
 use notify::{
-    event::{CreateKind, ModifyKind, RenameMode},
-    Event, EventKind, Watcher,
+    event::{AccessKind, AccessMode, CreateKind, ModifyKind, RenameMode},
+    Event, EventKind, RecursiveMode, Watcher,
 };
+use onefuzz_telemetry::{Event::directory_monitor_polling, EventData};
 use tokio::{
     fs,
-    sync::mpsc::{unbounded_channel, UnboundedReceiver},
+    sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
 };
 
+mod poll;
+mod settle;
+
+use poll::Poller;
+use settle::Settler;
+
 const DEFAULT_REPORT_DIRECTORIES: bool = false;
 
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+// On falling back to polling, files changed since shortly before the last
+// event received are emitted, unless they were recently, as their events may
+// have been lost.
+const CATCH_UP_PERIOD: Duration = Duration::from_secs(2);
+const RECENT_CAPACITY: usize = 4096;
+
+/// Creates the watcher of a [`DirectoryMonitor`], which sends its events to
+/// the sink it's given.
+pub type WatcherFactory =
+    Box<dyn FnOnce(EventSink) -> notify::Result<Box<dyn Watcher + Send>> + Send>;
+
+/// Receives the events of the watcher of a [`DirectoryMonitor`].
+#[derive(Clone)]
+pub struct EventSink {
+    sender: UnboundedSender<notify::Result<Event>>,
+}
+
+impl notify::EventHandler for EventSink {
+    fn handle_event(&mut self, event_or_err: notify::Result<Event>) {
+        // pre-filter the events here
+        let result = match event_or_err {
+            // events may have been dropped, as by an inotify queue overflow
+            Ok(ev) if ev.need_rescan() => Some(Ok(ev)),
+            Ok(ev) => match ev.kind {
+                // we are interested in:
+                // - create
+                // - remove
+                // - modify name
+                // - close after writing
+                EventKind::Create(_)
+                | EventKind::Remove(_)
+                | EventKind::Modify(ModifyKind::Name(_))
+                | EventKind::Access(AccessKind::Close(AccessMode::Write)) => Some(Ok(ev)),
+                // we are not interested in:
+                // - other access
+                // - modify something else (data, metadata)
+                // - any other events
+                EventKind::Access(_) | EventKind::Modify(_) | EventKind::Any | EventKind::Other => {
+                    None
+                }
+            },
+            Err(err) => Some(Err(err)),
+        };
+
+        if let Some(to_send) = result {
+            // A send error only occurs when the channel is closed. No remedial
+            // action is needed (or possible), so ignore it.
+            let _ = self.sender.send(to_send);
+        }
+    }
+}
+
+enum Backend {
+    Watcher(Box<dyn Watcher + Send>),
+    Polling(Poller),
+    Stopped,
+}
+
 /// Watches a directory, and on file creation, emits the path to the file.
+///
+/// A recursive monitor also watches the subdirectories of the directory,
+/// including those created or moved into it after it started, and emits the
+/// files created in them. The files of new subdirectories are emitted once,
+/// even if they were created before the subdirectory was watched. Paths are
+/// emitted as paths of the canonicalized directory, as in `dir.join(relative)`.
+///
+/// If the directory can't be watched, as when its watcher fails to start, or
+/// later fails, such as when the inotify watch limit is reached, the monitor
+/// falls back to polling the directory for changes.
 pub struct DirectoryMonitor {
     dir: PathBuf,
     notify_events: UnboundedReceiver<notify::Result<Event>>,
-    watcher: notify::RecommendedWatcher,
+    backend: Backend,
     report_directories: bool,
+    recursive: bool,
+
+    // Set if the watcher can't watch recursively, so that each subdirectory is
+    // watched itself, as it's found.
+    manual_recursion: bool,
+    watched_subdirs: Vec<PathBuf>,
+
+    // Paths found by scanning new subdirectories, to ignore if their events
+    // arrive later, as they've been emitted.
+    scanned: HashSet<PathBuf>,
+
+    // Paths to emit, in order.
+    found: VecDeque<PathBuf>,
+
+    // Set if files are emitted only once they've settled.
+    settler: Option<Settler>,
+
+    poll_interval: Duration,
+
+    // Events found by the last poll, to handle in order.
+    polled: VecDeque<Event>,
+
+    // When the last event of the watcher was received, and the paths emitted
+    // most recently, to find the files whose events may have been lost, if it
+    // fails.
+    last_event: SystemTime,
+    recent: VecDeque<PathBuf>,
 }
 
 impl DirectoryMonitor {
-    /// Create a new directory monitor.
+    /// Create a new directory monitor, of `dir`, and if `recursive` is set, of
+    /// its subdirectories.
     ///
     /// The path `dir` must name a directory, not a file.
-    pub async fn new(dir: impl AsRef<Path>) -> Result<Self> {
-        use notify::RecursiveMode;
+    pub async fn new(dir: impl AsRef<Path>, recursive: bool) -> Result<Self> {
+        Self::with_watcher_factory(dir, recursive, Box::new(recommended_watcher)).await
+    }
 
+    /// Create a new directory monitor, as by [`DirectoryMonitor::new`], whose
+    /// watcher is created by `factory`.
+    pub async fn with_watcher_factory(
+        dir: impl AsRef<Path>,
+        recursive: bool,
+        factory: WatcherFactory,
+    ) -> Result<Self> {
         // Canonicalize so we can compare the watched dir to paths in the events.
         let dir = fs::canonicalize(dir).await?;
 
@@ -41,166 +156,473 @@ impl DirectoryMonitor {
         }
 
         let (sender, notify_events) = unbounded_channel();
-        let mut watcher =
-            notify::recommended_watcher(move |event_or_err: notify::Result<Event>| {
-                // pre-filter the events here
-                let result = match event_or_err {
-                    Ok(ev) => match ev.kind {
-                        // we are interested in:
-                        // - create
-                        // - remove
-                        // - modify name
-                        EventKind::Create(_)
-                        | EventKind::Remove(_)
-                        | EventKind::Modify(ModifyKind::Name(_)) => Some(Ok(ev)),
-                        // we are not interested in:
-                        // - access
-                        // - modify something else (data, metadata)
-                        // - any other events
-                        EventKind::Access(_)
-                        | EventKind::Modify(_)
-                        | EventKind::Any
-                        | EventKind::Other => None,
-                    },
-                    Err(err) => Some(Err(err)),
-                };
-
-                if let Some(to_send) = result {
-                    // A send error only occurs when the channel is closed. No remedial
-                    // action is needed (or possible), so ignore it.
-                    let _ = sender.send(to_send);
-                }
-            })?;
-
-        watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+        let watcher = factory(EventSink { sender }).and_then(|mut watcher| {
+            let manual_recursion = watch(watcher.as_mut(), &dir, recursive)?;
+            Ok((watcher, manual_recursion))
+        });
 
-        Ok(Self {
+        let mut monitor = Self {
             dir,
             notify_events,
-            watcher,
+            backend: Backend::Stopped,
             report_directories: DEFAULT_REPORT_DIRECTORIES,
-        })
+            recursive,
+            manual_recursion: false,
+            watched_subdirs: vec![],
+            scanned: HashSet::new(),
+            found: VecDeque::new(),
+            settler: None,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            polled: VecDeque::new(),
+            last_event: SystemTime::now(),
+            recent: VecDeque::new(),
+        };
+
+        match watcher {
+            Ok((watcher, manual_recursion)) => {
+                monitor.backend = Backend::Watcher(watcher);
+                monitor.manual_recursion = manual_recursion;
+
+                // Watch the existing subdirectories, without emitting their files.
+                if manual_recursion {
+                    monitor.scan(monitor.dir.clone(), false).await;
+                }
+            }
+            Err(err) => {
+                // No files were created since the monitor started.
+                monitor.fall_back(err.to_string(), None).await;
+            }
+        }
+
+        Ok(monitor)
     }
 
     pub fn set_report_directories(&mut self, report_directories: bool) {
         self.report_directories = report_directories;
     }
 
+    /// Emit new files only once they've settled, so that a file still being
+    /// written isn't emitted: once closed after writing, where supported, as
+    /// on Linux, or once its size and modification time haven't changed for
+    /// `period`. Repeated events of a settling file are coalesced.
+    ///
+    /// If `period` is `None`, files are emitted as they're created.
+    pub fn set_settle_period(&mut self, period: Option<Duration>) {
+        self.settler = period.map(Settler::new);
+    }
+
+    /// Poll the directory every `interval`, if the monitor falls back to
+    /// polling.
+    pub fn set_poll_interval(&mut self, interval: Duration) {
+        self.poll_interval = interval;
+
+        if let Backend::Polling(poller) = &mut self.backend {
+            poller.reschedule(Instant::now() + interval);
+        }
+    }
+
+    /// Whether the monitor fell back to polling the directory.
+    pub fn is_polling(&self) -> bool {
+        matches!(self.backend, Backend::Polling(_))
+    }
+
     pub fn stop(&mut self) -> Result<()> {
-        self.watcher.unwatch(&self.dir)?;
+        match &mut self.backend {
+            Backend::Watcher(watcher) => {
+                for subdir in self.watched_subdirs.drain(..) {
+                    let _ = watcher.unwatch(&subdir);
+                }
+
+                watcher.unwatch(&self.dir)?;
+            }
+            Backend::Polling(_) => {
+                self.backend = Backend::Stopped;
+            }
+            Backend::Stopped => {}
+        }
+
         Ok(())
     }
 
     pub async fn next_file(&mut self) -> Result<Option<PathBuf>> {
         loop {
-            let event = match self.notify_events.recv().await {
-                Some(Ok(event)) => event,
-                Some(Err(err)) => {
-                    // A low-level watch error has occurred. Treat as fatal.
-                    warn!(
-                        "error watching for new files. path = {}, error = {}",
-                        self.dir.display(),
-                        err
-                    );
-
-                    // Make sure we try to stop our `Watcher` if we return early.
-                    let _ = self.stop();
-                    return Ok(None);
+            if let Some(path) = self.next_found().await {
+                if matches!(self.backend, Backend::Watcher(_)) {
+                    self.recent.push_back(path.clone());
+                    if self.recent.len() > RECENT_CAPACITY {
+                        self.recent.pop_front();
+                    }
                 }
-                None => {
+
+                return Ok(Some(path));
+            }
+
+            let event = match self.next_event().await {
+                Received::Event(event) => event,
+                Received::Timeout => continue,
+                Received::Closed => {
                     // Make sure we try to stop our `Watcher` if we return early.
                     let _ = self.stop();
                     return Ok(None);
                 }
             };
 
-            let mut paths = event.paths.into_iter();
+            if !self.handle_event(event).await? {
+                return Ok(None);
+            }
+        }
+    }
+
+    // Wait for the next event, or else for a file to settle or the next poll.
+    async fn next_event(&mut self) -> Received {
+        if let Some(event) = self.polled.pop_front() {
+            return Received::Event(event);
+        }
 
-            match event.kind {
-                EventKind::Create(create_kind) => {
-                    let path = paths
-                        .next()
-                        .ok_or_else(|| format_err!("missing path for file create event"))?;
+        let settle_deadline = self.settler.as_ref().and_then(Settler::deadline);
 
-                    match create_kind {
-                        CreateKind::File => {
-                            return Ok(Some(path));
+        match &mut self.backend {
+            Backend::Watcher(_) => {
+                let received = match settle_deadline {
+                    Some(deadline) => {
+                        let deadline = tokio::time::Instant::from_std(deadline);
+                        match tokio::time::timeout_at(deadline, self.notify_events.recv()).await {
+                            Ok(received) => received,
+                            Err(_) => return Received::Timeout,
                         }
-                        CreateKind::Folder => {
-                            if self.report_directories {
-                                return Ok(Some(path));
-                            }
+                    }
+                    None => self.notify_events.recv().await,
+                };
+
+                match received {
+                    Some(Ok(event)) if event.need_rescan() => {
+                        let reason = "events were dropped".to_owned();
+                        self.fall_back(reason, Some(self.last_event)).await;
+                        Received::Timeout
+                    }
+                    Some(Ok(event)) => {
+                        self.last_event = SystemTime::now();
+                        Received::Event(event)
+                    }
+                    Some(Err(err)) => {
+                        // A low-level watch error has occurred.
+                        self.fall_back(err.to_string(), Some(self.last_event)).await;
+                        Received::Timeout
+                    }
+                    None => Received::Closed,
+                }
+            }
+            Backend::Polling(poller) => {
+                let deadline = match settle_deadline {
+                    Some(deadline) => deadline.min(poller.deadline()),
+                    None => poller.deadline(),
+                };
+                tokio::time::sleep_until(tokio::time::Instant::from_std(deadline)).await;
+
+                let now = Instant::now();
+                if poller.deadline() <= now {
+                    self.polled = poller
+                        .poll(&self.dir, now + self.poll_interval)
+                        .await
+                        .into();
+                }
+
+                Received::Timeout
+            }
+            Backend::Stopped => Received::Closed,
+        }
+    }
+
+    // Poll the directory instead of watching it, as watching failed for
+    // `reason`. If it had been watched, the files changed since `last_event`
+    // are emitted, as their events may have been lost.
+    async fn fall_back(&mut self, reason: String, last_event: Option<SystemTime>) {
+        warn!(
+            "unable to watch {}, polling every {:?} instead: {}",
+            self.dir.display(),
+            self.poll_interval,
+            reason
+        );
+        event!(
+            directory_monitor_polling;
+            EventData::Path = self.dir.display().to_string()
+        );
+
+        // Stop the watcher, and drop the events it sent after it failed.
+        let _ = self.stop();
+        self.backend = Backend::Stopped;
+        self.notify_events.close();
+        while self.notify_events.try_recv().is_ok() {}
+        self.manual_recursion = false;
+        self.watched_subdirs.clear();
+
+        let since = last_event.and_then(|last| last.checked_sub(CATCH_UP_PERIOD));
+        let next_poll = Instant::now() + self.poll_interval;
+        let (poller, changed) = Poller::new(&self.dir, self.recursive, next_poll, since).await;
+        self.backend = Backend::Polling(poller);
+
+        for (path, is_dir) in changed {
+            if self.recent.contains(&path) || self.found.contains(&path) {
+                continue;
+            }
+
+            if !is_dir || self.report_directories {
+                self.found.push_back(path);
+            }
+        }
+        self.recent.clear();
+    }
+
+    // Handle an event, returning whether the monitor continues.
+    async fn handle_event(&mut self, event: Event) -> Result<bool> {
+        let mut paths = event.paths.into_iter();
+
+        match event.kind {
+            EventKind::Create(create_kind) => {
+                let path = paths
+                    .next()
+                    .ok_or_else(|| format_err!("missing path for file create event"))?;
+
+                match create_kind {
+                    CreateKind::File => {
+                        self.report(path);
+                    }
+                    CreateKind::Folder => {
+                        self.new_dir(path).await;
+                    }
+                    CreateKind::Any | CreateKind::Other => {
+                        // Short-circuit and report this path if we're reporting everything.
+                        if self.report_directories && !self.recursive {
+                            self.found.push_back(path);
+                            return Ok(true);
                         }
-                        CreateKind::Any | CreateKind::Other => {
-                            // Short-circuit and report this path if we're reporting everything.
-                            if self.report_directories {
-                                return Ok(Some(path));
-                            }
 
-                            match fs::metadata(&path).await {
-                                Ok(metadata) => {
-                                    // We're only reporting files, so make sure this is a file first.
-                                    if metadata.is_file() {
-                                        return Ok(Some(path));
-                                    }
-                                }
-                                Err(e) => {
-                                    warn!("failed to get metadata for {}: {:?}", path.display(), e);
+                        match fs::metadata(&path).await {
+                            Ok(metadata) => {
+                                // We're only reporting files, so make sure this is a file first.
+                                if metadata.is_file() {
+                                    self.report(path);
+                                } else if metadata.is_dir() {
+                                    self.new_dir(path).await;
                                 }
                             }
+                            Err(e) => {
+                                warn!("failed to get metadata for {}: {:?}", path.display(), e);
+                            }
                         }
                     }
                 }
-                EventKind::Modify(ModifyKind::Name(rename_mode)) => {
-                    match rename_mode {
-                        RenameMode::To => {
-                            let path = paths.next().ok_or_else(|| {
-                                format_err!("missing 'to' path for file rename-to event")
-                            })?;
-
-                            return Ok(Some(path));
-                        }
-                        RenameMode::Both => {
-                            let _from = paths.next().ok_or_else(|| {
-                                format_err!("missing 'from' path for file rename event")
-                            })?;
+            }
+            EventKind::Modify(ModifyKind::Name(rename_mode)) => {
+                match rename_mode {
+                    RenameMode::To => {
+                        let path = paths.next().ok_or_else(|| {
+                            format_err!("missing 'to' path for file rename-to event")
+                        })?;
 
-                            let to = paths.next().ok_or_else(|| {
-                                format_err!("missing 'to' path for file rename event")
-                            })?;
+                        self.moved_in(path).await;
+                    }
+                    RenameMode::Both => {
+                        let from = paths.next().ok_or_else(|| {
+                            format_err!("missing 'from' path for file rename event")
+                        })?;
 
-                            return Ok(Some(to));
-                        }
-                        RenameMode::From => {
-                            // ignore rename-from
-                        }
-                        RenameMode::Any | RenameMode::Other => {
-                            // something unusual, ignore
-                            info!(
-                                "unknown rename event: ignoring {:?} for path {:?}",
-                                rename_mode,
-                                paths.next()
-                            );
+                        let to = paths.next().ok_or_else(|| {
+                            format_err!("missing 'to' path for file rename event")
+                        })?;
+
+                        self.moved_out(&from);
+                        self.moved_in(to).await;
+                    }
+                    RenameMode::From => {
+                        if let Some(path) = paths.next() {
+                            self.moved_out(&path);
                         }
                     }
+                    RenameMode::Any | RenameMode::Other => {
+                        // something unusual, ignore
+                        info!(
+                            "unknown rename event: ignoring {:?} for path {:?}",
+                            rename_mode,
+                            paths.next()
+                        );
+                    }
                 }
-                EventKind::Remove(..) => {
-                    let path = paths
-                        .next()
-                        .ok_or_else(|| format_err!("missing path for file remove event"))?;
-
-                    if path == self.dir {
-                        // The directory we were watching was removed; we're done.
-                        let _ = self.stop();
-                        return Ok(None);
-                    } else {
-                        // Some file _inside_ the watched directory was removed. Ignore.
+            }
+            EventKind::Remove(..) => {
+                let path = paths
+                    .next()
+                    .ok_or_else(|| format_err!("missing path for file remove event"))?;
+
+                if path == self.dir {
+                    // The directory we were watching was removed; we're done.
+                    let _ = self.stop();
+                    return Ok(false);
+                } else {
+                    // Some file _inside_ the watched directory was removed, so
+                    // it's new if it's created again.
+                    self.scanned.remove(&path);
+
+                    if let Some(settler) = &mut self.settler {
+                        settler.removed(&path);
                     }
                 }
-                EventKind::Access(_) | EventKind::Modify(_) | EventKind::Other | EventKind::Any => {
-                    unreachable!() // these events have already been filtered out
+            }
+            EventKind::Access(AccessKind::Close(AccessMode::Write)) => {
+                if let (Some(settler), Some(path)) = (&mut self.settler, paths.next()) {
+                    settler.closed(&path);
                 }
             }
+            EventKind::Access(_) | EventKind::Modify(_) | EventKind::Other | EventKind::Any => {
+                unreachable!() // these events have already been filtered out
+            }
+        }
+
+        Ok(true)
+    }
+
+    // The next path to emit: the next found, unless files are emitted once
+    // they've settled, and then the next which has.
+    async fn next_found(&mut self) -> Option<PathBuf> {
+        let Some(settler) = &mut self.settler else {
+            return self.found.pop_front();
+        };
+
+        let now = Instant::now();
+        for path in self.found.drain(..) {
+            settler.add(path, now).await;
+        }
+
+        settler.next(now).await
+    }
+
+    // Emit the path of an event, unless it was found by a scan, and emitted.
+    fn report(&mut self, path: PathBuf) {
+        if !self.scanned.remove(&path) {
+            self.found.push_back(path);
+        }
+    }
+
+    async fn new_dir(&mut self, path: PathBuf) {
+        if self.report_directories {
+            self.report(path.clone());
+        }
+
+        // Files may have been created before the directory was watched.
+        if self.recursive {
+            self.scan(path, true).await;
+        }
+    }
+
+    async fn moved_in(&mut self, path: PathBuf) {
+        if !self.recursive {
+            self.found.push_back(path);
+            return;
+        }
+
+        match fs::metadata(&path).await {
+            // Its files are emitted at their new paths, and if watching each
+            // subdirectory, it's watched anew.
+            Ok(metadata) if metadata.is_dir() => self.new_dir(path).await,
+            Ok(_) => self.report(path),
+            Err(e) => {
+                warn!("failed to get metadata for {}: {:?}", path.display(), e);
+            }
+        }
+    }
+
+    fn moved_out(&mut self, path: &Path) {
+        self.scanned.remove(path);
+
+        if let Some(settler) = &mut self.settler {
+            settler.removed(path);
+        }
+
+        if let (true, Backend::Watcher(watcher)) = (self.manual_recursion, &mut self.backend) {
+            if let Some(index) = self.watched_subdirs.iter().position(|dir| dir == path) {
+                let _ = watcher.unwatch(path);
+                self.watched_subdirs.swap_remove(index);
+            }
+        }
+    }
+
+    // Find the files and subdirectories of the directory `root`, watching the
+    // subdirectories if needed, and if `report` is set, emitting the paths.
+    async fn scan(&mut self, root: PathBuf, report: bool) {
+        let mut dirs = vec![root];
+
+        while let Some(dir) = dirs.pop() {
+            // Watched before it's read, so that no file is missed.
+            if let (true, Backend::Watcher(watcher)) = (self.manual_recursion, &mut self.backend) {
+                if dir != self.dir && !self.watched_subdirs.contains(&dir) {
+                    match watcher.watch(&dir, RecursiveMode::NonRecursive) {
+                        Ok(()) => self.watched_subdirs.push(dir.clone()),
+                        Err(err) => warn!("unable to watch {}: {}", dir.display(), err),
+                    }
+                }
+            }
+
+            // The directory may have been removed since.
+            let Ok(mut entries) = fs::read_dir(&dir).await else {
+                continue;
+            };
+
+            while let Ok(Some(entry)) = entries.next_entry().await {
+                let path = entry.path();
+
+                // Symlinks to directories aren't followed, to avoid cycles.
+                let is_dir = entry.file_type().await.is_ok_and(|t| t.is_dir());
+                if is_dir {
+                    dirs.push(path.clone());
+                }
+
+                let is_file = !is_dir && fs::metadata(&path).await.is_ok_and(|m| m.is_file());
+                if report && (is_file || (is_dir && self.report_directories)) {
+                    self.found_by_scan(path);
+                }
+            }
+        }
+    }
+
+    fn found_by_scan(&mut self, path: PathBuf) {
+        if self.scanned.insert(path.clone()) {
+            self.found.push_back(path);
+        }
+    }
+}
+
+enum Received {
+    Event(Event),
+
+    // No event, but a file may have settled, or the directory been polled.
+    Timeout,
+
+    Closed,
+}
+
+fn recommended_watcher(sink: EventSink) -> notify::Result<Box<dyn Watcher + Send>> {
+    Ok(Box::new(notify::recommended_watcher(sink)?))
+}
+
+// Watch `dir` with `watcher`, recursively if `recursive` is set, returning
+// whether its subdirectories must be watched individually, as the watcher
+// can't watch recursively.
+fn watch(watcher: &mut (dyn Watcher + Send), dir: &Path, recursive: bool) -> notify::Result<bool> {
+    if !recursive {
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+        return Ok(false);
+    }
+
+    match watcher.watch(dir, RecursiveMode::Recursive) {
+        Ok(()) => Ok(false),
+        Err(err) => {
+            debug!(
+                "unable to watch {} recursively, watching subdirectories individually: {}",
+                dir.display(),
+                err
+            );
+            watcher.watch(dir, RecursiveMode::NonRecursive)?;
+            Ok(true)
         }
     }
 }
@@ -0,0 +1,165 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use std::collections::{HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime};
+
+use notify::{
+    event::{CreateKind, RemoveKind},
+    Event, EventKind,
+};
+use tokio::fs;
+
+/// Finds the changes to a directory by diffing snapshots of it, taken
+/// periodically, as the events of a watcher.
+pub(super) struct Poller {
+    recursive: bool,
+    snapshot: HashSet<PathBuf>,
+    next_poll: Instant,
+}
+
+struct Entry {
+    path: PathBuf,
+    is_dir: bool,
+    changed: Option<SystemTime>,
+}
+
+impl Poller {
+    /// Take the first snapshot of `root`, and of its subdirectories if
+    /// `recursive` is set, to poll again at `next_poll`. If `since` is set,
+    /// the paths of the files and directories changed since are returned, with
+    /// whether each is a directory.
+    pub async fn new(
+        root: &Path,
+        recursive: bool,
+        next_poll: Instant,
+        since: Option<SystemTime>,
+    ) -> (Self, Vec<(PathBuf, bool)>) {
+        let entries = walk(root, recursive).await.unwrap_or_default();
+
+        let changed = match since {
+            Some(since) => entries
+                .iter()
+                .filter(|entry| entry.changed.is_some_and(|changed| changed >= since))
+                .map(|entry| (entry.path.clone(), entry.is_dir))
+                .collect(),
+            None => vec![],
+        };
+
+        let poller = Self {
+            recursive,
+            snapshot: entries.into_iter().map(|entry| entry.path).collect(),
+            next_poll,
+        };
+
+        (poller, changed)
+    }
+
+    pub fn deadline(&self) -> Instant {
+        self.next_poll
+    }
+
+    /// Poll by `at`, if not due sooner.
+    pub fn reschedule(&mut self, at: Instant) {
+        self.next_poll = self.next_poll.min(at);
+    }
+
+    /// The events of the changes to `root` since the last snapshot, to poll
+    /// again at `next_poll`. Paths created are in the order they were found,
+    /// directories before their contents.
+    pub async fn poll(&mut self, root: &Path, next_poll: Instant) -> Vec<Event> {
+        self.next_poll = next_poll;
+
+        let Some(entries) = walk(root, self.recursive).await else {
+            let removed = Event::new(EventKind::Remove(RemoveKind::Folder));
+            return vec![removed.add_path(root.to_owned())];
+        };
+
+        let mut events = vec![];
+        let mut snapshot = HashSet::with_capacity(entries.len());
+        for entry in entries {
+            if !self.snapshot.contains(&entry.path) {
+                let kind = if entry.is_dir {
+                    CreateKind::Folder
+                } else {
+                    CreateKind::File
+                };
+                events.push(Event::new(EventKind::Create(kind)).add_path(entry.path.clone()));
+            }
+            snapshot.insert(entry.path);
+        }
+
+        for path in self.snapshot.difference(&snapshot) {
+            events.push(Event::new(EventKind::Remove(RemoveKind::Any)).add_path(path.clone()));
+        }
+        self.snapshot = snapshot;
+
+        events
+    }
+}
+
+// The files and directories of `root`, breadth first, or `None` if it can't be
+// read, as if it was removed.
+async fn walk(root: &Path, recursive: bool) -> Option<Vec<Entry>> {
+    let mut entries = vec![];
+    let mut dirs = VecDeque::from([root.to_owned()]);
+
+    while let Some(dir) = dirs.pop_front() {
+        let mut dir_entries = match fs::read_dir(&dir).await {
+            Ok(dir_entries) => dir_entries,
+            Err(_) if dir == root => return None,
+            // Removed since it was found.
+            Err(_) => continue,
+        };
+
+        while let Ok(Some(dir_entry)) = dir_entries.next_entry().await {
+            let path = dir_entry.path();
+
+            // Symlinks to directories aren't followed, to avoid cycles.
+            let is_dir = dir_entry.file_type().await.is_ok_and(|t| t.is_dir());
+            let metadata = if is_dir {
+                dir_entry.metadata().await
+            } else {
+                fs::metadata(&path).await
+            };
+            let Ok(metadata) = metadata else {
+                continue;
+            };
+            if !is_dir && !metadata.is_file() {
+                continue;
+            }
+
+            if is_dir && recursive {
+                dirs.push_back(path.clone());
+            }
+
+            entries.push(Entry {
+                path,
+                is_dir,
+                changed: changed(&metadata),
+            });
+        }
+    }
+
+    Some(entries)
+}
+
+// When a file was last modified, or on Unix, last changed, as by a rename.
+fn changed(metadata: &std::fs::Metadata) -> Option<SystemTime> {
+    let modified = metadata.modified().ok();
+
+    #[cfg(unix)]
+    let modified = {
+        use std::os::unix::fs::MetadataExt;
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let ctime = Duration::new(
+            metadata.ctime().max(0) as u64,
+            metadata.ctime_nsec().clamp(0, 999_999_999) as u32,
+        );
+        modified.max(UNIX_EPOCH.checked_add(ctime))
+    };
+
+    modified
+}
@@ -0,0 +1,161 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use tokio::fs;
+
+// Files settling beyond this are emitted early, oldest first, so that the
+// state of a directory with many new files is bounded.
+const DEFAULT_CAPACITY: usize = 4096;
+
+/// Holds new files until they've settled: once they've been closed after
+/// writing, or their size and modification time haven't changed for a period.
+pub(super) struct Settler {
+    period: Duration,
+    capacity: usize,
+    files: HashMap<PathBuf, Settling>,
+
+    // Files by when they're next checked, and the order they were scheduled.
+    deadlines: BTreeMap<(Instant, u64), PathBuf>,
+    scheduled: u64,
+
+    // Paths to emit, in order.
+    ready: VecDeque<PathBuf>,
+}
+
+struct Settling {
+    size: u64,
+    modified: Option<SystemTime>,
+    deadline: (Instant, u64),
+}
+
+impl Settler {
+    pub fn new(period: Duration) -> Self {
+        Self::with_capacity(period, DEFAULT_CAPACITY)
+    }
+
+    pub fn with_capacity(period: Duration, capacity: usize) -> Self {
+        Self {
+            period,
+            capacity,
+            files: HashMap::new(),
+            deadlines: BTreeMap::new(),
+            scheduled: 0,
+            ready: VecDeque::new(),
+        }
+    }
+
+    /// Hold the new path `path`, unless it's a directory. If it's already
+    /// held, its events are coalesced, and its period restarts.
+    pub async fn add(&mut self, path: PathBuf, now: Instant) {
+        let metadata = match fs::metadata(&path).await {
+            Ok(metadata) => metadata,
+            Err(err) => {
+                // Removed since.
+                debug!("failed to get metadata for {}: {:?}", path.display(), err);
+                return;
+            }
+        };
+
+        if metadata.is_dir() {
+            self.ready.push_back(path);
+            return;
+        }
+
+        let size = metadata.len();
+        let modified = metadata.modified().ok();
+
+        if let Some(settling) = self.files.get_mut(&path) {
+            self.deadlines.remove(&settling.deadline);
+            settling.size = size;
+            settling.modified = modified;
+            settling.deadline = (now + self.period, self.scheduled);
+            self.deadlines.insert(settling.deadline, path);
+            self.scheduled += 1;
+            return;
+        }
+
+        if self.files.len() >= self.capacity {
+            if let Some((_, oldest)) = self.deadlines.pop_first() {
+                debug!("too many files settling, emitting {}", oldest.display());
+                self.files.remove(&oldest);
+                self.ready.push_back(oldest);
+            }
+        }
+
+        let deadline = (now + self.period, self.scheduled);
+        self.scheduled += 1;
+        self.deadlines.insert(deadline, path.clone());
+        self.files.insert(
+            path,
+            Settling {
+                size,
+                modified,
+                deadline,
+            },
+        );
+    }
+
+    /// Emit the held file `path`, as it was closed after writing.
+    pub fn closed(&mut self, path: &Path) {
+        if let Some(settling) = self.files.remove(path) {
+            self.deadlines.remove(&settling.deadline);
+            self.ready.push_back(path.to_owned());
+        }
+    }
+
+    /// Stop holding `path`, which was removed or moved away.
+    pub fn removed(&mut self, path: &Path) {
+        if let Some(settling) = self.files.remove(path) {
+            self.deadlines.remove(&settling.deadline);
+        }
+    }
+
+    /// When the next held file is due to be checked.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadlines
+            .first_key_value()
+            .map(|((deadline, _), _)| *deadline)
+    }
+
+    /// The next file to emit, which is ready or has settled by `now`.
+    pub async fn next(&mut self, now: Instant) -> Option<PathBuf> {
+        if let Some(path) = self.ready.pop_front() {
+            return Some(path);
+        }
+
+        while let Some(entry) = self.deadlines.first_entry() {
+            if entry.key().0 > now {
+                break;
+            }
+            let path = entry.remove();
+
+            let Ok(metadata) = fs::metadata(&path).await else {
+                self.files.remove(&path);
+                continue;
+            };
+            let size = metadata.len();
+            let modified = metadata.modified().ok();
+
+            let Some(settling) = self.files.get_mut(&path) else {
+                continue;
+            };
+            if settling.size == size && settling.modified == modified {
+                self.files.remove(&path);
+                return Some(path);
+            }
+
+            // Still being written.
+            settling.size = size;
+            settling.modified = modified;
+            settling.deadline = (now + self.period, self.scheduled);
+            self.scheduled += 1;
+            self.deadlines.insert(settling.deadline, path);
+        }
+
+        None
+    }
+}
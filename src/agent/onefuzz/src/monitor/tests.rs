@@ -1,13 +1,18 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use anyhow::Result;
 use tempfile::tempdir;
 use tokio::fs;
 
-use crate::monitor::DirectoryMonitor;
+use notify::{EventHandler, Watcher};
+
+use crate::monitor::{settle, DirectoryMonitor, EventSink, WatcherFactory};
 
 const TEST_TIMEOUT: Duration = Duration::from_millis(1000);
 
@@ -34,7 +39,7 @@ macro_rules! expected_timeout_test {
 }
 
 timed_test!(test_monitor_empty_path, async move {
-    let monitor = DirectoryMonitor::new("").await;
+    let monitor = DirectoryMonitor::new("", false).await;
 
     assert!(monitor.is_err());
 
@@ -42,7 +47,7 @@ timed_test!(test_monitor_empty_path, async move {
 });
 
 timed_test!(test_monitor_nonexistent_path, async move {
-    let monitor = DirectoryMonitor::new("some-nonexistent-path").await;
+    let monitor = DirectoryMonitor::new("some-nonexistent-path", false).await;
 
     assert!(monitor.is_err());
 
@@ -56,7 +61,7 @@ timed_test!(test_monitor_file, async move {
     let file_path = dir.path().join("some-file.txt");
     tokio::fs::write(&file_path, "aaaaaa").await?;
 
-    let monitor = DirectoryMonitor::new(&file_path).await;
+    let monitor = DirectoryMonitor::new(&file_path, false).await;
 
     // Ctor must fail.
     assert!(monitor.is_err());
@@ -68,7 +73,7 @@ timed_test!(test_monitor_dir, async move {
     let dir = tempdir()?;
 
     // Ctor must succeed.
-    let mut monitor = DirectoryMonitor::new(dir.path()).await?;
+    let mut monitor = DirectoryMonitor::new(dir.path(), false).await?;
 
     let _ = monitor.stop();
 
@@ -90,7 +95,7 @@ timed_test!(test_monitor_dir_symlink, async move {
     fs::symlink_dir(&child, &symlink).await?;
 
     // Ctor must succeed.
-    let mut monitor = DirectoryMonitor::new(&symlink).await?;
+    let mut monitor = DirectoryMonitor::new(&symlink, false).await?;
 
     let _ = monitor.stop();
 
@@ -101,7 +106,7 @@ timed_test!(test_monitor_dir_create_files, async move {
     use std::fs::canonicalize;
 
     let dir = tempdir()?;
-    let mut monitor = DirectoryMonitor::new(dir.path()).await?;
+    let mut monitor = DirectoryMonitor::new(dir.path(), false).await?;
 
     let file_a = dir.path().join("a.txt");
     let file_b = dir.path().join("b.txt");
@@ -130,7 +135,7 @@ timed_test!(test_monitor_dir_create_files, async move {
 
 expected_timeout_test!(test_monitor_default_ignores_dir, async move {
     let dir = tempdir().unwrap();
-    let mut monitor = DirectoryMonitor::new(dir.path()).await?;
+    let mut monitor = DirectoryMonitor::new(dir.path(), false).await?;
 
     let sub_dir = dir.path().join("test");
     dbg!(&sub_dir);
@@ -144,7 +149,7 @@ timed_test!(test_monitor_set_report_directories, async move {
     use std::fs::canonicalize;
 
     let dir = tempdir().unwrap();
-    let mut monitor = DirectoryMonitor::new(dir.path()).await?;
+    let mut monitor = DirectoryMonitor::new(dir.path(), false).await?;
     monitor.set_report_directories(true);
 
     let sub_dir = dir.path().join("test");
@@ -167,7 +172,7 @@ timed_test!(test_rename_into_dir, async move {
     fs::write(&file1, &"xxx").await?;
 
     // start watching
-    let mut monitor = DirectoryMonitor::new(dir2.path()).await?;
+    let mut monitor = DirectoryMonitor::new(dir2.path(), false).await?;
 
     // move into watched dir
     let file2 = dir2.path().join("testfile");
@@ -188,7 +193,7 @@ timed_test!(test_rename_inside_dir, async move {
     fs::write(&file1, &"xxx").await?;
 
     // start watching
-    let mut monitor = DirectoryMonitor::new(dir.path()).await?;
+    let mut monitor = DirectoryMonitor::new(dir.path(), false).await?;
 
     // rename inside watched dir
     let file2 = dir.path().join("testfile_2");
@@ -209,7 +214,7 @@ timed_test!(test_rename_out_of_dir, async move {
     fs::write(&file1, &"xxx").await?;
 
     // start watching
-    let mut monitor = DirectoryMonitor::new(dir1.path()).await?;
+    let mut monitor = DirectoryMonitor::new(dir1.path(), false).await?;
 
     // move _out_ of watched dir
     let file2 = dir2.path().join("testfile");
@@ -228,3 +233,327 @@ timed_test!(test_rename_out_of_dir, async move {
 
     Ok(())
 });
+
+// Receive the files expected, asserting that each is emitted once.
+async fn expect_files(monitor: &mut DirectoryMonitor, expected: &[PathBuf]) -> Result<()> {
+    let mut remaining: HashSet<_> = expected.iter().cloned().collect();
+
+    while !remaining.is_empty() {
+        let file = monitor
+            .next_file()
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("monitor stopped"))?;
+        assert!(
+            remaining.remove(&file),
+            "unexpected file: {}",
+            file.display()
+        );
+    }
+
+    // No more, as none were emitted twice.
+    let next = tokio::time::timeout(Duration::from_millis(200), monitor.next_file()).await;
+    assert!(next.is_err(), "unexpected file: {next:?}");
+
+    Ok(())
+}
+
+expected_timeout_test!(test_monitor_ignores_nested_files, async move {
+    let dir = tempdir().unwrap();
+    let mut monitor = DirectoryMonitor::new(dir.path(), false).await?;
+
+    let sub_dir = dir.path().join("test");
+    fs::create_dir(&sub_dir).await?;
+    fs::write(sub_dir.join("file"), "aaa").await?;
+
+    monitor.next_file().await?;
+    anyhow::Ok(())
+});
+
+timed_test!(test_recursive_monitor_nested_files, async move {
+    let dir = tempdir()?;
+    let root = dir.path().canonicalize()?;
+
+    let existing = root.join("existing");
+    fs::create_dir(&existing).await?;
+
+    let mut monitor = DirectoryMonitor::new(dir.path(), true).await?;
+
+    // Created as quickly as possible, racing the watches of the new
+    // subdirectories.
+    let nested = root.join("a").join("b").join("c");
+    fs::create_dir_all(&nested).await?;
+    let mut expected = vec![
+        root.join("top.txt"),
+        root.join("a").join("a.txt"),
+        root.join("a").join("b").join("b.txt"),
+        nested.join("c1.txt"),
+        nested.join("c2.txt"),
+        existing.join("existing.txt"),
+    ];
+    for file in &expected {
+        fs::write(file, "aaa").await?;
+    }
+
+    expect_files(&mut monitor, &expected).await?;
+
+    // Files of subdirectories created after the monitor started are emitted
+    // as they're created.
+    expected.clear();
+    for i in 0..10 {
+        let file = nested.join(format!("later-{i}.txt"));
+        fs::write(&file, "bbb").await?;
+        expected.push(file);
+    }
+
+    expect_files(&mut monitor, &expected).await?;
+
+    let _ = monitor.stop();
+
+    Ok(())
+});
+
+timed_test!(test_recursive_monitor_dir_moved_in, async move {
+    let outside = tempdir()?;
+    let dir = tempdir()?;
+    let root = dir.path().canonicalize()?;
+
+    let tree = outside.path().join("tree");
+    fs::create_dir_all(tree.join("sub")).await?;
+    fs::write(tree.join("one.txt"), "aaa").await?;
+    fs::write(tree.join("sub").join("two.txt"), "bbb").await?;
+
+    let mut monitor = DirectoryMonitor::new(dir.path(), true).await?;
+
+    // Its files are emitted at their new paths.
+    fs::rename(&tree, root.join("tree")).await?;
+    let moved = root.join("tree");
+    expect_files(
+        &mut monitor,
+        &[moved.join("one.txt"), moved.join("sub").join("two.txt")],
+    )
+    .await?;
+
+    // And it's watched.
+    let three = moved.join("sub").join("three.txt");
+    fs::write(&three, "ccc").await?;
+    expect_files(&mut monitor, &[three]).await?;
+
+    let _ = monitor.stop();
+
+    Ok(())
+});
+
+timed_test!(test_recursive_monitor_report_directories, async move {
+    let dir = tempdir()?;
+    let root = dir.path().canonicalize()?;
+
+    let mut monitor = DirectoryMonitor::new(dir.path(), true).await?;
+    monitor.set_report_directories(true);
+
+    let sub_dir = root.join("a");
+    let nested = sub_dir.join("b");
+    fs::create_dir_all(&nested).await?;
+    let file = nested.join("file.txt");
+    fs::write(&file, "aaa").await?;
+
+    expect_files(&mut monitor, &[sub_dir, nested, file]).await?;
+
+    let _ = monitor.stop();
+
+    Ok(())
+});
+
+const SETTLE_PERIOD: Duration = Duration::from_millis(300);
+
+#[tokio::test]
+async fn test_settled_file_written_in_chunks() -> Result<()> {
+    use tokio::io::AsyncWriteExt;
+
+    let dir = tempdir()?;
+    let mut monitor = DirectoryMonitor::new(dir.path(), false).await?;
+    monitor.set_settle_period(Some(SETTLE_PERIOD));
+
+    let path = dir.path().canonicalize()?.join("crash");
+    let writer = tokio::spawn({
+        let path = path.clone();
+        async move {
+            let mut file = fs::File::create(&path).await?;
+            for _ in 0..5 {
+                file.write_all(&[b'a'; 1024]).await?;
+                file.flush().await?;
+                tokio::time::sleep(SETTLE_PERIOD / 3).await;
+            }
+            anyhow::Ok(())
+        }
+    });
+
+    let emitted = tokio::time::timeout(Duration::from_secs(5), monitor.next_file()).await??;
+    assert_eq!(emitted, Some(path.clone()));
+    assert_eq!(fs::metadata(&path).await?.len(), 5 * 1024);
+    writer.await??;
+
+    // Emitted once.
+    let next = tokio::time::timeout(SETTLE_PERIOD * 2, monitor.next_file()).await;
+    assert!(next.is_err(), "unexpected file: {next:?}");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_settled_file_left_open() -> Result<()> {
+    let dir = tempdir()?;
+    let mut monitor = DirectoryMonitor::new(dir.path(), false).await?;
+    monitor.set_settle_period(Some(SETTLE_PERIOD));
+
+    // Not closed until emitted, so it settles by the quiet period alone.
+    let path = dir.path().canonicalize()?.join("crash");
+    let mut file = std::fs::File::create(&path)?;
+    std::io::Write::write_all(&mut file, b"aaa")?;
+
+    let start = std::time::Instant::now();
+    let emitted = tokio::time::timeout(Duration::from_secs(5), monitor.next_file()).await??;
+    assert_eq!(emitted, Some(path));
+    assert!(start.elapsed() >= SETTLE_PERIOD / 2);
+    drop(file);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_settled_files_are_bounded() -> Result<()> {
+    let dir = tempdir()?;
+    let root = dir.path().canonicalize()?;
+    let mut monitor = DirectoryMonitor::new(dir.path(), false).await?;
+
+    // Too long to settle within the test.
+    monitor.settler = Some(settle::Settler::with_capacity(Duration::from_secs(60), 2));
+
+    let mut files = vec![];
+    for i in 0..5 {
+        let path = root.join(format!("file-{i}"));
+        files.push(std::fs::File::create(&path)?);
+    }
+
+    // Those beyond the capacity are emitted early, oldest first. Without
+    // closes, as the files are still open, the rest are held.
+    for i in 0..3 {
+        let emitted = tokio::time::timeout(Duration::from_secs(5), monitor.next_file()).await??;
+        assert_eq!(emitted, Some(root.join(format!("file-{i}"))));
+    }
+
+    let next = tokio::time::timeout(SETTLE_PERIOD, monitor.next_file()).await;
+    assert!(next.is_err(), "unexpected file: {next:?}");
+
+    Ok(())
+}
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+fn failing_watcher(_: EventSink) -> notify::Result<Box<dyn Watcher + Send>> {
+    Err(notify::Error::generic("watch limit reached"))
+}
+
+timed_test!(test_polling_if_watcher_fails_to_start, async move {
+    let dir = tempdir()?;
+    let root = dir.path().canonicalize()?;
+
+    let mut monitor =
+        DirectoryMonitor::with_watcher_factory(dir.path(), true, Box::new(failing_watcher)).await?;
+    monitor.set_poll_interval(POLL_INTERVAL);
+    assert!(monitor.is_polling());
+
+    let nested = root.join("a").join("b");
+    fs::create_dir_all(&nested).await?;
+    let files = [root.join("top.txt"), nested.join("nested.txt")];
+    for file in &files {
+        fs::write(file, "aaa").await?;
+    }
+
+    expect_files(&mut monitor, &files).await?;
+
+    // Files created since the last poll are found by the next.
+    let later = root.join("a").join("later.txt");
+    fs::write(&later, "bbb").await?;
+    expect_files(&mut monitor, &[later]).await?;
+
+    // As is the removal of the directory.
+    #[cfg(not(target_os = "windows"))]
+    {
+        dir.close()?;
+        assert_eq!(monitor.next_file().await?, None);
+    }
+
+    Ok(())
+});
+
+timed_test!(test_polling_if_watcher_fails, async move {
+    let dir = tempdir()?;
+    let root = dir.path().canonicalize()?;
+
+    // A watcher, whose sink is kept to inject its failure.
+    let sink = Arc::new(Mutex::new(None));
+    let factory: WatcherFactory = Box::new({
+        let sink = sink.clone();
+        move |event_sink: EventSink| -> notify::Result<Box<dyn Watcher + Send>> {
+            *sink.lock().unwrap() = Some(event_sink.clone());
+            Ok(Box::new(notify::recommended_watcher(event_sink)?))
+        }
+    });
+    let mut monitor = DirectoryMonitor::with_watcher_factory(dir.path(), false, factory).await?;
+    monitor.set_poll_interval(POLL_INTERVAL);
+    assert!(!monitor.is_polling());
+
+    let before = root.join("before.txt");
+    fs::write(&before, "aaa").await?;
+    expect_files(&mut monitor, &[before]).await?;
+
+    // Files created after the failure, before the monitor switches to polling,
+    // are emitted, once, as are those created after.
+    let mut event_sink = sink.lock().unwrap().take().unwrap();
+    event_sink.handle_event(Err(notify::Error::generic("watch limit reached")));
+    let during = root.join("during.txt");
+    fs::write(&during, "bbb").await?;
+
+    expect_files(&mut monitor, &[during]).await?;
+    assert!(monitor.is_polling());
+
+    let after = root.join("after.txt");
+    fs::write(&after, "ccc").await?;
+    expect_files(&mut monitor, &[after]).await?;
+
+    Ok(())
+});
+
+timed_test!(test_polling_settles_files, async move {
+    use tokio::io::AsyncWriteExt;
+
+    let dir = tempdir()?;
+    let root = dir.path().canonicalize()?;
+
+    let mut monitor =
+        DirectoryMonitor::with_watcher_factory(dir.path(), false, Box::new(failing_watcher))
+            .await?;
+    monitor.set_poll_interval(POLL_INTERVAL);
+    monitor.set_settle_period(Some(POLL_INTERVAL * 4));
+
+    // Found by a poll while it's being written, but emitted once settled.
+    let path = root.join("crash");
+    let writer = tokio::spawn({
+        let path = path.clone();
+        async move {
+            let mut file = fs::File::create(&path).await?;
+            for _ in 0..3 {
+                file.write_all(&[b'a'; 1024]).await?;
+                file.flush().await?;
+                tokio::time::sleep(POLL_INTERVAL * 2).await;
+            }
+            anyhow::Ok(())
+        }
+    });
+
+    assert_eq!(monitor.next_file().await?, Some(path.clone()));
+    assert_eq!(fs::metadata(&path).await?.len(), 3 * 1024);
+    writer.await??;
+
+    Ok(())
+});
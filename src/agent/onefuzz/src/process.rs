@@ -2,7 +2,6 @@
 // Licensed under the MIT License.
 
 use anyhow::{Context, Result};
-use process_control::{self, ChildExt, Control};
 use std::path::Path;
 use std::process::Command;
 use std::time::Duration;
@@ -113,31 +112,396 @@ pub async fn run_cmd<S: ::std::hash::BuildHasher>(
     );
 
     let mut cmd = Command::new(program);
+    cmd.args(argv);
+
+    run_command(cmd, env, timeout).await
+}
+
+/// Run a prepared command with a timeout, as for [`run_cmd`].
+///
+/// The command is run with its descendants, such as the target of a wrapper
+/// script, as for [`run_command_with_limits`], and all are killed with it.
+pub async fn run_command<S: ::std::hash::BuildHasher>(
+    cmd: Command,
+    env: &HashMap<String, String, S>,
+    timeout: Duration,
+) -> Result<Output> {
+    let limits = RunLimits {
+        timeout,
+        memory: None,
+    };
+
+    let limited = run_command_with_limits(cmd, env, limits).await?;
+    if limited.exceeded.is_some() {
+        bail!("process timed out");
+    }
+
+    Ok(limited.output)
+}
+
+/// Limits of a run of a command, which apply to it and its descendants
+/// together.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RunLimits {
+    pub timeout: Duration,
+
+    /// Bytes of memory: resident on Linux, and committed on Windows. Not
+    /// enforced on other platforms.
+    pub memory: Option<u64>,
+}
+
+/// A limit which a run exceeded, so was killed for.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Limit {
+    Timeout,
+    Memory,
+}
+
+/// The output of a run of a command, including that of a run killed for
+/// exceeding one of its limits.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LimitedOutput {
+    pub output: Output,
+    pub exceeded: Option<Limit>,
+}
+
+/// Run a prepared command, killing it and all of its descendants once either
+/// exceeds `limits`, or once it exits, so that none are left running.
+///
+/// On Windows, the command is run in a job object. On Unix, it's run in a
+/// process group of its own, which descendants that start their own session
+/// can escape.
+pub async fn run_command_with_limits<S: ::std::hash::BuildHasher>(
+    cmd: Command,
+    env: &HashMap<String, String, S>,
+    limits: RunLimits,
+) -> Result<LimitedOutput> {
+    run_limited(cmd, env, None, limits).await
+}
+
+/// Run a prepared command as [`run_command_with_limits`] does, piping `input`
+/// to its standard input, which is then closed.
+///
+/// The input is written by a thread of its own, which the run doesn't wait
+/// for, so that a command which exits or closes its standard input before
+/// reading all of it, or never reads it, can't block the run. The write then
+/// fails once the command and its descendants have exited, or been killed for
+/// exceeding `limits`.
+pub async fn run_command_with_input<S: ::std::hash::BuildHasher>(
+    cmd: Command,
+    env: &HashMap<String, String, S>,
+    input: Vec<u8>,
+    limits: RunLimits,
+) -> Result<LimitedOutput> {
+    run_limited(cmd, env, Some(input), limits).await
+}
+
+async fn run_limited<S: ::std::hash::BuildHasher>(
+    mut cmd: Command,
+    env: &HashMap<String, String, S>,
+    input: Option<Vec<u8>>,
+    limits: RunLimits,
+) -> Result<LimitedOutput> {
+    let stdin = if input.is_some() {
+        Stdio::piped()
+    } else {
+        Stdio::null()
+    };
     cmd.env_remove("RUST_LOG")
-        .stdin(Stdio::null())
+        .stdin(stdin)
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
-        .args(argv)
         .envs(env);
 
     // make a stringified version to save in the context of spawn_blocking
-    let program_name = program.display().to_string();
-
-    let runner = tokio::task::spawn_blocking(move || {
-        let child = cmd
-            .spawn()
-            .with_context(|| format!("process failed to start: {program_name}"))?;
-
-        child
-            .controlled_with_output()
-            .time_limit(timeout)
-            .terminate_for_timeout()
-            .wait()?
-            .ok_or_else(|| format_err!("process timed out"))
+    let program_name = Path::new(cmd.get_program()).display().to_string();
+
+    #[cfg(target_family = "unix")]
+    let runner =
+        tokio::task::spawn_blocking(move || run_in_group(cmd, &program_name, input, limits));
+
+    #[cfg(target_family = "windows")]
+    let runner = tokio::task::spawn_blocking(move || run_in_job(cmd, &program_name, input, limits));
+
+    runner.await?
+}
+
+// Write `input` to `stream` from a thread of its own, then close it, ignoring
+// any error, such as once the reader has closed it without reading all of the
+// input.
+fn write_all(stream: Option<std::process::ChildStdin>, input: Option<Vec<u8>>) {
+    use std::io::Write;
+
+    let (Some(mut stream), Some(input)) = (stream, input) else {
+        return;
+    };
+
+    std::thread::spawn(move || {
+        _ = stream.write_all(&input);
     });
+}
+
+// Read the output as `wait_with_output` does, but without waiting for the
+// pipes to close, since processes left running by the target may hold them
+// open.
+fn read_all(
+    stream: Option<impl std::io::Read + Send + 'static>,
+) -> std::thread::JoinHandle<Vec<u8>> {
+    use std::io::Read;
+
+    std::thread::spawn(move || {
+        let mut data = vec![];
+        if let Some(mut stream) = stream {
+            _ = stream.read_to_end(&mut data);
+        }
+        data
+    })
+}
+
+#[cfg(target_family = "unix")]
+const LIMIT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// How often the memory of a process group is summed, which reads the status
+// of every process.
+#[cfg(target_family = "unix")]
+const MEMORY_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// How long to wait for output, after the group is killed, from any process
+// which escaped it, but still holds the pipes open.
+#[cfg(target_family = "unix")]
+const OUTPUT_GRACE_PERIOD: Duration = Duration::from_secs(1);
+
+#[cfg(target_family = "unix")]
+fn run_in_group(
+    mut cmd: Command,
+    program_name: &str,
+    input: Option<Vec<u8>>,
+    limits: RunLimits,
+) -> Result<LimitedOutput> {
+    use nix::sys::signal::{killpg, Signal};
+    use nix::unistd::Pid;
+    use std::os::unix::process::CommandExt;
+    use std::time::Instant;
+
+    // Its own group, whose ID is its process ID.
+    let mut child = cmd
+        .process_group(0)
+        .spawn()
+        .with_context(|| format!("process failed to start: {program_name}"))?;
+    let group = Pid::from_raw(child.id() as i32);
+
+    write_all(child.stdin.take(), input);
+    let stdout = read_all(child.stdout.take());
+    let stderr = read_all(child.stderr.take());
+
+    let started = Instant::now();
+    let mut memory_checked = started;
+    let mut exceeded = None;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+
+        let now = Instant::now();
+        if now.duration_since(started) >= limits.timeout {
+            exceeded = Some(Limit::Timeout);
+        } else if let Some(memory) = limits.memory {
+            if now.duration_since(memory_checked) >= MEMORY_POLL_INTERVAL {
+                memory_checked = now;
+                if group_memory(group.as_raw()) > memory {
+                    exceeded = Some(Limit::Memory);
+                }
+            }
+        }
+
+        if exceeded.is_some() {
+            _ = killpg(group, Signal::SIGKILL);
+            break child.wait()?;
+        }
+
+        std::thread::sleep(LIMIT_POLL_INTERVAL);
+    };
+
+    // Kill whatever is left: the whole group on exceeding a limit, or else
+    // any processes the target left running.
+    _ = killpg(group, Signal::SIGKILL);
+
+    let deadline = Instant::now() + OUTPUT_GRACE_PERIOD;
+    let stdout = join_output(stdout, deadline)?;
+    let stderr = join_output(stderr, deadline)?;
+
+    let output = Output {
+        exit_status: status.into(),
+        stderr: String::from_utf8_lossy(&stderr).to_string(),
+        stdout: String::from_utf8_lossy(&stdout).to_string(),
+    };
+
+    Ok(LimitedOutput { output, exceeded })
+}
+
+// The output read by `reader`, or none, if it's still open at `deadline`.
+#[cfg(target_family = "unix")]
+fn join_output(
+    reader: std::thread::JoinHandle<Vec<u8>>,
+    deadline: std::time::Instant,
+) -> Result<Vec<u8>> {
+    while !reader.is_finished() {
+        if std::time::Instant::now() >= deadline {
+            warn!("output of process left open by an escaped descendant, discarding it");
+            return Ok(vec![]);
+        }
+        std::thread::sleep(LIMIT_POLL_INTERVAL);
+    }
+
+    reader
+        .join()
+        .map_err(|_| format_err!("output reader panicked"))
+}
+
+/// Resident bytes of the processes of the process group `group`.
+#[cfg(target_os = "linux")]
+pub fn group_memory(group: i32) -> u64 {
+    use nix::unistd::{sysconf, SysconfVar};
+
+    let page_size = match sysconf(SysconfVar::PAGE_SIZE) {
+        Ok(Some(size)) => size as u64,
+        _ => 4096,
+    };
+
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return 0;
+    };
+
+    entries
+        .flatten()
+        .filter(|entry| {
+            let name = entry.file_name();
+            name.to_str()
+                .map_or(false, |name| name.parse::<u32>().is_ok())
+        })
+        // Processes may exit while being read.
+        .filter_map(|entry| std::fs::read_to_string(entry.path().join("stat")).ok())
+        .filter_map(|stat| parse_stat(&stat))
+        .filter(|(pgrp, _)| *pgrp == group)
+        .map(|(_, rss)| rss.saturating_mul(page_size))
+        .sum()
+}
+
+#[cfg(all(target_family = "unix", not(target_os = "linux")))]
+pub fn group_memory(_group: i32) -> u64 {
+    0
+}
+
+// The process group and resident pages of a `/proc/<pid>/stat` line.
+#[cfg(target_os = "linux")]
+fn parse_stat(stat: &str) -> Option<(i32, u64)> {
+    // The name may contain spaces and parentheses, so fields are counted from
+    // its end: state is the 3rd field, pgrp the 5th, and rss the 24th.
+    let (_, fields) = stat.rsplit_once(')')?;
+    let fields: Vec<&str> = fields.split_whitespace().collect();
+
+    let pgrp = fields.get(2)?.parse().ok()?;
+    let rss = fields.get(21)?.parse().ok()?;
 
-    // convert processcontrol::Output into our Output
-    runner.await?.map(|result| result.into())
+    Some((pgrp, rss))
+}
+
+#[cfg(target_family = "windows")]
+fn run_in_job(
+    mut cmd: Command,
+    program_name: &str,
+    input: Option<Vec<u8>>,
+    limits: RunLimits,
+) -> Result<LimitedOutput> {
+    use process_control::{ChildExt, Control};
+    use std::os::windows::{io::AsRawHandle, process::CommandExt};
+    use win_util::job::{resume_process, Job};
+    use windows::Win32::{Foundation::HANDLE, System::Threading::CREATE_SUSPENDED};
+
+    // Started suspended, so that it can't start any process outside the job.
+    let mut child = cmd
+        .creation_flags(CREATE_SUSPENDED.0)
+        .spawn()
+        .with_context(|| format!("process failed to start: {program_name}"))?;
+
+    let job = Job::new()?;
+    let started = limits
+        .memory
+        .map_or(Ok(()), |memory| job.set_memory_limit(memory))
+        .and_then(|()| job.assign(HANDLE(child.as_raw_handle() as isize)))
+        .map(|()| job.monitor())
+        .and_then(|report| resume_process(child.id()).map(|()| report));
+    let report = match started {
+        Ok(report) => report,
+        Err(err) => {
+            _ = child.kill();
+            return Err(err.context(format!("process failed to start: {program_name}")));
+        }
+    };
+
+    write_all(child.stdin.take(), input);
+    let stdout = read_all(child.stdout.take());
+    let stderr = read_all(child.stderr.take());
+
+    let status = child.controlled().time_limit(limits.timeout).wait()?;
+
+    // Kill whatever is left: the whole tree on a timeout, or else any
+    // processes the target left running.
+    job.terminate(1)?;
+
+    let report = report
+        .join()
+        .map_err(|_| format_err!("job monitor panicked"))?;
+    let stdout = stdout
+        .join()
+        .map_err(|_| format_err!("stdout reader panicked"))?;
+    let stderr = stderr
+        .join()
+        .map_err(|_| format_err!("stderr reader panicked"))?;
+
+    let (mut exit_status, exceeded) = match status {
+        Some(status) => (
+            ExitStatus::from(status),
+            report.memory_limit_exceeded.then_some(Limit::Memory),
+        ),
+        None => (ExitStatus::from(child.wait()?), Some(Limit::Timeout)),
+    };
+
+    if let Some(code) = propagated_exit_code(&report.exits, child.id()) {
+        exit_status = ExitStatus {
+            code: Some(code as i32),
+            signal: None,
+            success: false,
+        };
+    }
+
+    let output = Output {
+        exit_status,
+        stderr: String::from_utf8_lossy(&stderr).to_string(),
+        stdout: String::from_utf8_lossy(&stdout).to_string(),
+    };
+
+    Ok(LimitedOutput { output, exceeded })
+}
+
+// The exit code of the last process of the job to exit abnormally, such as
+// with an unhandled exception, unless the process `root` did itself. So that
+// the crash of a target launched by a wrapper script is not lost to the exit
+// code of the script.
+#[cfg(target_family = "windows")]
+fn propagated_exit_code(exits: &[win_util::job::ProcessExit], root: u32) -> Option<u32> {
+    if exits
+        .iter()
+        .any(|exit| exit.process_id == root && exit.abnormal)
+    {
+        return None;
+    }
+
+    exits
+        .iter()
+        .rev()
+        .find(|exit| exit.process_id != root && exit.abnormal)
+        .and_then(|exit| exit.exit_code)
 }
 
 async fn monitor_stream(name: &str, context: &str, stream: impl AsyncRead + Unpin) -> Result<()> {
@@ -230,3 +594,207 @@ pub async fn monitor_process(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_family = "windows")]
+    fn wrapper_script(dir: &Path, script: &str) -> Result<std::path::PathBuf> {
+        let path = dir.join("wrapper.bat");
+        std::fs::write(&path, format!("@echo off\r\n{script}\r\n"))?;
+        Ok(path)
+    }
+
+    #[cfg(target_family = "windows")]
+    #[tokio::test]
+    async fn test_run_command_propagates_status_of_wrapped_target() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        // The wrapped target exits as if by an access violation, but the
+        // script exits successfully after it.
+        let wrapper = wrapper_script(dir.path(), "cmd /D /C exit -1073741819\r\necho done")?;
+
+        let output = run_command(
+            Command::new(wrapper),
+            &HashMap::new(),
+            Duration::from_secs(30),
+        )
+        .await?;
+
+        assert_eq!(output.exit_status.code, Some(0xC0000005_u32 as i32));
+        assert!(!output.exit_status.success);
+        assert_eq!(output.stdout.trim(), "done");
+
+        Ok(())
+    }
+
+    #[cfg(target_family = "windows")]
+    #[tokio::test]
+    async fn test_run_command_timeout_kills_wrapped_target() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let wrapper = wrapper_script(dir.path(), "ping -n 120 127.0.0.1")?;
+
+        let start = std::time::Instant::now();
+        let err = run_command(
+            Command::new(wrapper),
+            &HashMap::new(),
+            Duration::from_secs(1),
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(err.to_string(), "process timed out");
+
+        // If `ping` were left running, its output would still be read.
+        assert!(start.elapsed() < Duration::from_secs(60));
+
+        Ok(())
+    }
+
+    #[cfg(target_family = "unix")]
+    fn shell(script: &str) -> Command {
+        let mut cmd = Command::new("/bin/sh");
+        cmd.args(["-c", script]);
+        cmd
+    }
+
+    #[cfg(target_family = "unix")]
+    fn limits(timeout: u64, memory: Option<u64>) -> RunLimits {
+        RunLimits {
+            timeout: Duration::from_secs(timeout),
+            memory,
+        }
+    }
+
+    // Whether the process `pid` has exited, allowing it a moment to be reaped
+    // once orphaned.
+    #[cfg(target_family = "unix")]
+    async fn is_killed(pid: &str) -> bool {
+        use nix::{sys::signal::kill, unistd::Pid};
+
+        let pid = Pid::from_raw(pid.trim().parse().unwrap());
+        for _ in 0..50 {
+            if kill(pid, None).is_err() {
+                return true;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        false
+    }
+
+    #[cfg(target_family = "unix")]
+    #[tokio::test]
+    async fn test_run_with_limits_exits() -> Result<()> {
+        let limited =
+            run_command_with_limits(shell("echo out; exit 3"), &HashMap::new(), limits(30, None))
+                .await?;
+
+        assert_eq!(limited.exceeded, None);
+        assert_eq!(limited.output.exit_status.code, Some(3));
+        assert_eq!(limited.output.stdout.trim(), "out");
+
+        Ok(())
+    }
+
+    #[cfg(target_family = "unix")]
+    #[tokio::test]
+    async fn test_run_with_limits_timeout_kills_descendants() -> Result<()> {
+        let start = std::time::Instant::now();
+        let limited = run_command_with_limits(
+            shell("sleep 120 & echo $!; wait"),
+            &HashMap::new(),
+            limits(1, None),
+        )
+        .await?;
+
+        assert_eq!(limited.exceeded, Some(Limit::Timeout));
+        assert_eq!(limited.output.exit_status.signal, Some(9));
+        assert!(start.elapsed() < Duration::from_secs(60));
+        assert!(is_killed(&limited.output.stdout).await);
+
+        let err = run_command(shell("sleep 120"), &HashMap::new(), Duration::from_secs(1))
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "process timed out");
+
+        Ok(())
+    }
+
+    #[cfg(target_family = "unix")]
+    #[tokio::test]
+    async fn test_run_with_limits_kills_leftover_descendants() -> Result<()> {
+        // The shell exits, leaving `sleep` running with its output.
+        let limited = run_command_with_limits(
+            shell("sleep 120 & echo $!"),
+            &HashMap::new(),
+            limits(30, None),
+        )
+        .await?;
+
+        assert_eq!(limited.exceeded, None);
+        assert!(limited.output.exit_status.success);
+        assert!(is_killed(&limited.output.stdout).await);
+
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_run_with_limits_memory() -> Result<()> {
+        // The shell holds 256 MiB of output in a variable.
+        let script = "x=$(head -c 268435456 /dev/zero | tr '\\0' a); sleep 120";
+        let limited = run_command_with_limits(
+            shell(script),
+            &HashMap::new(),
+            limits(60, Some(64 * 1024 * 1024)),
+        )
+        .await?;
+
+        assert_eq!(limited.exceeded, Some(Limit::Memory));
+        assert_eq!(limited.output.exit_status.signal, Some(9));
+
+        Ok(())
+    }
+
+    #[cfg(target_family = "unix")]
+    #[tokio::test]
+    async fn test_run_with_input() -> Result<()> {
+        let limited = run_command_with_input(
+            shell("read line; echo \"got $line\""),
+            &HashMap::new(),
+            b"hello\n".to_vec(),
+            limits(30, None),
+        )
+        .await?;
+        assert_eq!(limited.output.stdout.trim(), "got hello");
+
+        // Far more than a pipe buffers, with stdin closed before any is read.
+        let input = vec![b'a'; 16 * 1024 * 1024];
+        let limited = run_command_with_input(
+            shell("exec 0<&-; echo done"),
+            &HashMap::new(),
+            input.clone(),
+            limits(30, None),
+        )
+        .await?;
+        assert_eq!(limited.exceeded, None);
+        assert_eq!(limited.output.stdout.trim(), "done");
+
+        // Or never read, until killed.
+        let limited =
+            run_command_with_input(shell("sleep 120"), &HashMap::new(), input, limits(1, None))
+                .await?;
+        assert_eq!(limited.exceeded, Some(Limit::Timeout));
+
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_parse_stat() {
+        let stat = "1234 (fuzz (x) y) S 1 1230 1230 0 -1 4194560 100 0 0 0 1 2 0 0 20 0 \
+                    1 0 100 10485760 2560 18446744073709551615";
+        assert_eq!(parse_stat(stat), Some((1230, 2560)));
+        assert_eq!(parse_stat("1234 (fuzz) S 1"), None);
+    }
+}
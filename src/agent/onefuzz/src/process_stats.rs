@@ -0,0 +1,542 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Sampling of the resources used by a process and its descendants, such as a
+//! fuzzer and the targets it runs.
+//!
+//! Each sample only reads the counters of the processes in the tree: procfs on
+//! Linux, and queries needing just `PROCESS_QUERY_LIMITED_INFORMATION` on
+//! Windows. Processes may exit at any point during a sample, and are then
+//! skipped.
+
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// The resources used by a process tree, summed over its processes.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct ProcessStats {
+    /// Processes in the tree, the sampled process and its descendants.
+    pub processes: u64,
+
+    /// Resident memory, or the working set on Windows.
+    pub rss_bytes: u64,
+
+    /// Virtual memory, or the committed private memory on Windows.
+    pub virtual_bytes: u64,
+
+    pub threads: u64,
+
+    /// Open file descriptors, or handles on Windows.
+    pub handles: u64,
+
+    /// CPU utilization since the previous sample, in percent of one core, so
+    /// may exceed 100 for multithreaded trees. Unset for the first sample.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_percent: Option<f64>,
+}
+
+/// Samples the resources used by the tree of a process.
+pub struct ProcessSampler {
+    pid: u32,
+
+    // When the previous sample was taken, and the CPU times of the processes
+    // then, from which utilization is measured.
+    previous: Option<(Instant, HashMap<u32, Duration>)>,
+}
+
+// A sample of one process.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Sample {
+    rss_bytes: u64,
+    virtual_bytes: u64,
+    threads: u64,
+    handles: u64,
+    cpu_time: Duration,
+}
+
+impl ProcessSampler {
+    /// A sampler of the process `pid` and its descendants.
+    pub fn new(pid: u32) -> Self {
+        let mut sampler = Self {
+            pid,
+            previous: None,
+        };
+
+        // Start measuring CPU utilization, for the first sample.
+        if let Err(err) = sampler.sample() {
+            debug!("unable to sample process {}: {:?}", pid, err);
+        }
+
+        sampler
+    }
+
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    /// Sample the process tree, or `None` if the process has exited.
+    pub fn sample(&mut self) -> Result<Option<ProcessStats>> {
+        let now = Instant::now();
+        let samples = sample_tree(self.pid)?;
+        if samples.is_empty() {
+            self.previous = None;
+            return Ok(None);
+        }
+
+        let cpu_times: HashMap<_, _> = samples
+            .iter()
+            .map(|(pid, sample)| (*pid, sample.cpu_time))
+            .collect();
+        let cpu_percent = self.previous.as_ref().and_then(|(then, previous)| {
+            cpu_percent(now.saturating_duration_since(*then), previous, &cpu_times)
+        });
+        self.previous = Some((now, cpu_times));
+
+        let mut stats = ProcessStats {
+            cpu_percent,
+            ..ProcessStats::default()
+        };
+        for (_, sample) in samples {
+            stats.processes += 1;
+            stats.rss_bytes += sample.rss_bytes;
+            stats.virtual_bytes += sample.virtual_bytes;
+            stats.threads += sample.threads;
+            stats.handles += sample.handles;
+        }
+
+        Ok(Some(stats))
+    }
+}
+
+// Processes started since the previous sample count all of their CPU time,
+// and the time of those which exited since is lost.
+fn cpu_percent(
+    elapsed: Duration,
+    previous: &HashMap<u32, Duration>,
+    current: &HashMap<u32, Duration>,
+) -> Option<f64> {
+    if elapsed.is_zero() {
+        return None;
+    }
+
+    let busy: Duration = current
+        .iter()
+        .map(|(pid, time)| {
+            let previous = previous.get(pid).copied().unwrap_or_default();
+            time.saturating_sub(previous)
+        })
+        .sum();
+
+    Some(100.0 * busy.as_secs_f64() / elapsed.as_secs_f64())
+}
+
+// The processes of the tree of `root`, by breadth, as found by `children`,
+// and sampled by `sample`. Processes which exited are skipped, as are their
+// children, and if `root` exited, the tree is empty.
+fn walk_tree(
+    root: u32,
+    mut children: impl FnMut(u32) -> Vec<u32>,
+    mut sample: impl FnMut(u32) -> Option<Sample>,
+) -> Vec<(u32, Sample)> {
+    let mut samples = vec![];
+    let mut seen = HashSet::new();
+    let mut pending = std::collections::VecDeque::from([root]);
+
+    while let Some(pid) = pending.pop_front() {
+        // Guard against cycles, as of reused pids.
+        if !seen.insert(pid) {
+            continue;
+        }
+
+        let Some(process) = sample(pid) else {
+            continue;
+        };
+        pending.extend(children(pid));
+        samples.push((pid, process));
+    }
+
+    samples
+}
+
+#[cfg(target_os = "linux")]
+fn sample_tree(root: u32) -> Result<Vec<(u32, Sample)>> {
+    Ok(walk_tree(root, children, sample_process))
+}
+
+// The children of `pid`, as listed by each of its threads. Requires a kernel
+// with `CONFIG_PROC_CHILDREN`, otherwise only `pid` itself is sampled.
+#[cfg(target_os = "linux")]
+fn children(pid: u32) -> Vec<u32> {
+    let Ok(tasks) = std::fs::read_dir(format!("/proc/{pid}/task")) else {
+        return vec![];
+    };
+
+    let mut children = vec![];
+    for task in tasks.flatten() {
+        let Ok(list) = std::fs::read_to_string(task.path().join("children")) else {
+            continue;
+        };
+        children.extend(list.split_whitespace().filter_map(|pid| pid.parse().ok()));
+    }
+
+    children
+}
+
+#[cfg(target_os = "linux")]
+fn sample_process(pid: u32) -> Option<Sample> {
+    // Exited, if it can't be read.
+    let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    let stat = match parse_stat(&stat) {
+        Ok(stat) => stat,
+        Err(err) => {
+            debug!("unable to parse stat of process {}: {:?}", pid, err);
+            return None;
+        }
+    };
+
+    // Exited, but not yet reaped.
+    if matches!(stat.state, 'Z' | 'X') {
+        return None;
+    }
+
+    // Only readable by the owner of the process, so best effort.
+    let handles = std::fs::read_dir(format!("/proc/{pid}/fd"))
+        .map(|fds| fds.count() as u64)
+        .unwrap_or_default();
+
+    let ticks = stat.utime + stat.stime;
+    Some(Sample {
+        rss_bytes: stat.rss_pages * *PAGE_SIZE,
+        virtual_bytes: stat.vsize,
+        threads: stat.threads,
+        handles,
+        cpu_time: Duration::from_secs_f64(ticks as f64 / *CLOCK_TICKS as f64),
+    })
+}
+
+#[cfg(target_os = "linux")]
+#[derive(Debug, PartialEq)]
+struct Stat {
+    state: char,
+    utime: u64,
+    stime: u64,
+    threads: u64,
+    vsize: u64,
+    rss_pages: u64,
+}
+
+// The fields of `/proc/<pid>/stat` which are sampled. See `proc(5)`.
+#[cfg(target_os = "linux")]
+fn parse_stat(stat: &str) -> Result<Stat> {
+    // The command name may contain spaces and parentheses, so fields are
+    // split after its closing parenthesis. The first is then field 3.
+    let (_, fields) = stat
+        .rsplit_once(')')
+        .ok_or_else(|| format_err!("invalid `stat`: {}", stat))?;
+    let fields: Vec<&str> = fields.split_whitespace().collect();
+    let field = |number: usize| {
+        fields
+            .get(number - 3)
+            .copied()
+            .ok_or_else(|| format_err!("field {} not found in `stat`: {}", number, stat))
+    };
+
+    let state = field(3)?
+        .chars()
+        .next()
+        .ok_or_else(|| format_err!("invalid state in `stat`: {}", stat))?;
+    let rss_pages: i64 = field(24)?.parse()?;
+
+    Ok(Stat {
+        state,
+        utime: field(14)?.parse()?,
+        stime: field(15)?.parse()?,
+        threads: field(20)?.parse()?,
+        vsize: field(23)?.parse()?,
+        rss_pages: rss_pages.max(0) as u64,
+    })
+}
+
+#[cfg(target_os = "linux")]
+fn sysconf(var: nix::unistd::SysconfVar, default: u64) -> u64 {
+    match nix::unistd::sysconf(var) {
+        Ok(Some(value)) if value > 0 => value as u64,
+        _ => default,
+    }
+}
+
+#[cfg(target_os = "linux")]
+lazy_static::lazy_static! {
+    static ref PAGE_SIZE: u64 = sysconf(nix::unistd::SysconfVar::PAGE_SIZE, 4096);
+    static ref CLOCK_TICKS: u64 = sysconf(nix::unistd::SysconfVar::CLK_TCK, 100);
+}
+
+#[cfg(target_os = "windows")]
+fn sample_tree(root: u32) -> Result<Vec<(u32, Sample)>> {
+    let processes = snapshot_processes()?;
+
+    let mut children: HashMap<u32, Vec<u32>> = HashMap::new();
+    let mut threads = HashMap::new();
+    for (pid, parent, count) in processes {
+        children.entry(parent).or_default().push(pid);
+        threads.insert(pid, count);
+    }
+
+    Ok(walk_tree(
+        root,
+        |pid| children.remove(&pid).unwrap_or_default(),
+        |pid| sample_process(pid, *threads.get(&pid)?),
+    ))
+}
+
+// The pid, parent pid and thread count of each process.
+#[cfg(target_os = "windows")]
+fn snapshot_processes() -> Result<Vec<(u32, u32, u64)>> {
+    use anyhow::Context;
+    use windows::Win32::{
+        Foundation::CloseHandle,
+        System::Diagnostics::ToolHelp::{
+            CreateToolhelp32Snapshot, Process32FirstW, Process32NextW, PROCESSENTRY32W,
+            TH32CS_SNAPPROCESS,
+        },
+    };
+
+    let snapshot = unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0) }
+        .context("error snapshotting processes")?;
+
+    let mut entry = PROCESSENTRY32W {
+        dwSize: std::mem::size_of::<PROCESSENTRY32W>() as u32,
+        ..Default::default()
+    };
+    let mut processes = vec![];
+    let mut found = unsafe { Process32FirstW(snapshot, &mut entry) }.as_bool();
+    while found {
+        processes.push((
+            entry.th32ProcessID,
+            entry.th32ParentProcessID,
+            u64::from(entry.cntThreads),
+        ));
+        found = unsafe { Process32NextW(snapshot, &mut entry) }.as_bool();
+    }
+
+    unsafe { CloseHandle(snapshot) };
+
+    Ok(processes)
+}
+
+#[cfg(target_os = "windows")]
+fn sample_process(pid: u32, threads: u64) -> Option<Sample> {
+    use windows::Win32::{
+        Foundation::CloseHandle,
+        System::Threading::{OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION},
+    };
+
+    // Exited since the snapshot, if it can't be opened.
+    let process = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid) }.ok()?;
+    let sample = query_process(process, threads);
+    unsafe { CloseHandle(process) };
+
+    sample
+}
+
+#[cfg(target_os = "windows")]
+fn query_process(process: windows::Win32::Foundation::HANDLE, threads: u64) -> Option<Sample> {
+    use windows::Win32::{
+        Foundation::{FILETIME, STILL_ACTIVE},
+        System::{
+            ProcessStatus::{
+                GetProcessMemoryInfo, PROCESS_MEMORY_COUNTERS, PROCESS_MEMORY_COUNTERS_EX,
+            },
+            Threading::{GetExitCodeProcess, GetProcessHandleCount, GetProcessTimes},
+        },
+    };
+
+    // Exited, but still open, as by its parent.
+    let mut exit_code = 0;
+    if !unsafe { GetExitCodeProcess(process, &mut exit_code) }.as_bool()
+        || exit_code != STILL_ACTIVE.0 as u32
+    {
+        return None;
+    }
+
+    let mut counters = PROCESS_MEMORY_COUNTERS_EX::default();
+    let size = std::mem::size_of::<PROCESS_MEMORY_COUNTERS_EX>() as u32;
+    let counters_ptr = &mut counters as *mut PROCESS_MEMORY_COUNTERS_EX;
+    if !unsafe { GetProcessMemoryInfo(process, counters_ptr as *mut PROCESS_MEMORY_COUNTERS, size) }
+        .as_bool()
+    {
+        return None;
+    }
+
+    let mut handles = 0;
+    if !unsafe { GetProcessHandleCount(process, &mut handles) }.as_bool() {
+        handles = 0;
+    }
+
+    let mut creation = FILETIME::default();
+    let mut exit = FILETIME::default();
+    let mut kernel = FILETIME::default();
+    let mut user = FILETIME::default();
+    if !unsafe { GetProcessTimes(process, &mut creation, &mut exit, &mut kernel, &mut user) }
+        .as_bool()
+    {
+        return None;
+    }
+
+    // In units of 100ns.
+    let ticks =
+        |time: FILETIME| (u64::from(time.dwHighDateTime) << 32) | u64::from(time.dwLowDateTime);
+
+    Some(Sample {
+        rss_bytes: counters.WorkingSetSize as u64,
+        virtual_bytes: counters.PrivateUsage as u64,
+        threads,
+        handles: u64::from(handles),
+        cpu_time: Duration::from_nanos((ticks(kernel) + ticks(user)).saturating_mul(100)),
+    })
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+fn sample_tree(_root: u32) -> Result<Vec<(u32, Sample)>> {
+    bail!("unsupported platform")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cpu_percent() {
+        let times = |times: &[(u32, u64)]| -> HashMap<u32, Duration> {
+            times
+                .iter()
+                .map(|(pid, millis)| (*pid, Duration::from_millis(*millis)))
+                .collect()
+        };
+        let elapsed = Duration::from_secs(1);
+
+        assert_eq!(
+            cpu_percent(elapsed, &times(&[(1, 100)]), &times(&[(1, 600)])),
+            Some(50.0)
+        );
+
+        // Across cores, and with a process started since.
+        assert_eq!(
+            cpu_percent(elapsed, &times(&[(1, 0)]), &times(&[(1, 1000), (2, 500)])),
+            Some(150.0)
+        );
+
+        // Exited processes aren't counted.
+        assert_eq!(
+            cpu_percent(elapsed, &times(&[(1, 0), (2, 500)]), &times(&[(1, 250)])),
+            Some(25.0)
+        );
+
+        assert_eq!(cpu_percent(Duration::ZERO, &times(&[]), &times(&[])), None);
+    }
+
+    #[test]
+    fn test_walk_tree() {
+        let children = |pid| match pid {
+            1 => vec![2, 3],
+            2 => vec![4],
+            // A reused pid.
+            4 => vec![1],
+            _ => vec![],
+        };
+        let sample = |pid| {
+            // Exited mid-sample, before its children were listed.
+            (pid != 3).then(|| Sample {
+                threads: u64::from(pid),
+                ..Sample::default()
+            })
+        };
+
+        let pids: Vec<_> = walk_tree(1, children, sample)
+            .into_iter()
+            .map(|(pid, sample)| (pid, sample.threads))
+            .collect();
+        assert_eq!(pids, [(1, 1), (2, 2), (4, 4)]);
+
+        // The root exited.
+        assert!(walk_tree(3, children, sample).is_empty());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_parse_stat() -> Result<()> {
+        let stat = "1234 (a (b) c) S 1 1234 1234 0 -1 4194304 100 0 0 0 25 5 0 0 20 0 3 0 \
+                    12345 8192000 150 18446744073709551615 1 1 0 0 0 0 0 0 0 0 0 0 17 2 0 0 \
+                    0 0 0\n";
+        assert_eq!(
+            parse_stat(stat)?,
+            Stat {
+                state: 'S',
+                utime: 25,
+                stime: 5,
+                threads: 3,
+                vsize: 8192000,
+                rss_pages: 150,
+            }
+        );
+
+        assert!(parse_stat("1234 (a) S 1\n").is_err());
+        assert!(parse_stat("").is_err());
+
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_sample_process_tree() -> Result<()> {
+        use std::process::{Command, Stdio};
+
+        // A shell, and a child of it.
+        let mut child = Command::new("sh")
+            .args(["-c", "sleep 10 & wait"])
+            .stdin(Stdio::null())
+            .spawn()?;
+        let mut sampler = ProcessSampler::new(child.id());
+        assert_eq!(sampler.pid(), child.id());
+
+        let start = Instant::now();
+        let stats = loop {
+            let stats = sampler.sample()?.expect("process exited");
+            if stats.processes >= 2 || start.elapsed() > Duration::from_secs(5) {
+                break stats;
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        };
+
+        child.kill()?;
+        child.wait()?;
+
+        assert_eq!(stats.processes, 2, "{stats:?}");
+        assert!(stats.rss_bytes > 0, "{stats:?}");
+        assert!(stats.virtual_bytes >= stats.rss_bytes, "{stats:?}");
+        assert!(stats.threads >= 2, "{stats:?}");
+        assert!(stats.handles >= 2, "{stats:?}");
+        assert!(stats.cpu_percent.is_some_and(|cpu| cpu >= 0.0), "{stats:?}");
+
+        // The killed shell is gone, so its orphaned child isn't sampled.
+        assert_eq!(sampler.sample()?, None);
+
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_sample_exited_process() -> Result<()> {
+        let mut child = std::process::Command::new("true").spawn()?;
+        let pid = child.id();
+        child.wait()?;
+
+        let mut sampler = ProcessSampler::new(pid);
+        assert_eq!(sampler.sample()?, None);
+
+        Ok(())
+    }
+}
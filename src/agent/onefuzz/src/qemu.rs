@@ -0,0 +1,267 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Running targets built for another architecture, such as aarch64 targets on
+//! x86_64 nodes, with QEMU user mode emulation.
+//!
+//! The target is run as the first argument of `qemu-<arch>`, followed by its
+//! own arguments. The exit status is then that of QEMU, which differs from the
+//! target's own in a few ways:
+//!
+//! - If the target is killed by a signal, QEMU reports it as `qemu: uncaught
+//!   target signal N` before killing itself with the same signal. Some setups
+//!   only see an exit code of `128 + N`.
+//! - If QEMU itself can't be run, a shell exits with `127` when it is not
+//!   found, or `126` when it is not executable.
+//! - If QEMU can't load the target, it exits with `1`, after `Error while
+//!   loading`.
+
+use std::{
+    ffi::{OsStr, OsString},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::Result;
+use serde::Deserialize;
+
+use crate::{process::ExitStatus, shell};
+
+/// Read by QEMU as the root of the target's filesystem, where it looks up the
+/// target's dynamic loader and shared libraries.
+pub const QEMU_LD_PREFIX: &str = "QEMU_LD_PREFIX";
+
+const NOT_EXECUTABLE: i32 = 126;
+const NOT_FOUND: i32 = 127;
+
+const UNCAUGHT_SIGNAL: &str = "qemu: uncaught target signal ";
+const LOAD_ERROR: &str = "Error while loading ";
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+pub struct QemuUser {
+    /// The architecture of the target, such as `aarch64`.
+    pub arch: String,
+
+    /// The QEMU binary. Defaults to `qemu-{arch}`, found in the `PATH`.
+    pub qemu_path: Option<PathBuf>,
+
+    /// The root of the target's filesystem, set as `QEMU_LD_PREFIX`.
+    pub sysroot: Option<PathBuf>,
+}
+
+/// How the target exited, as told by the exit status of QEMU.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum QemuExit {
+    Success,
+    Code(i32),
+    Signal(i32),
+}
+
+impl QemuUser {
+    pub fn qemu_path(&self) -> PathBuf {
+        self.qemu_path
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(format!("qemu-{}", self.arch)))
+    }
+
+    /// Build a command which runs `program` under QEMU, as for
+    /// [`shell::target_command`].
+    pub fn target_command(
+        &self,
+        program: &Path,
+        args: &[impl AsRef<OsStr>],
+        options: &[String],
+        shell: bool,
+    ) -> Result<Command> {
+        if cfg!(not(target_os = "linux")) {
+            bail!("QEMU user mode emulation is only supported on Linux");
+        }
+
+        let mut qemu_args: Vec<OsString> = vec![program.into()];
+        qemu_args.extend(args.iter().map(|arg| arg.as_ref().to_owned()));
+
+        let mut cmd = shell::target_command(&self.qemu_path(), &qemu_args, options, shell)?;
+        if let Some(sysroot) = &self.sysroot {
+            cmd.env(QEMU_LD_PREFIX, sysroot);
+        }
+
+        Ok(cmd)
+    }
+
+    /// Interpret the exit status of QEMU, given its stderr.
+    ///
+    /// Fails if QEMU didn't run the target at all, since that is a problem
+    /// with the task's config, rather than the input.
+    pub fn exit(&self, status: &ExitStatus, stderr: &str) -> Result<QemuExit> {
+        if status.success {
+            return Ok(QemuExit::Success);
+        }
+
+        if let Some(signal) = status.signal {
+            return Ok(QemuExit::Signal(signal));
+        }
+
+        let Some(code) = status.code else {
+            bail!("{} exited without a status", self.qemu_path().display());
+        };
+
+        match code {
+            NOT_FOUND => bail!(
+                "unable to run {}: not found. stderr: {stderr:?}",
+                self.qemu_path().display()
+            ),
+            NOT_EXECUTABLE => bail!(
+                "unable to run {}: not executable. stderr: {stderr:?}",
+                self.qemu_path().display()
+            ),
+            1 if stderr.lines().any(|line| line.starts_with(LOAD_ERROR)) => bail!(
+                "{} is unable to load the target. stderr: {stderr:?}",
+                self.qemu_path().display()
+            ),
+            _ => {}
+        }
+
+        match uncaught_signal(stderr) {
+            Some(signal) if code == 128 + signal => Ok(QemuExit::Signal(signal)),
+            _ => Ok(QemuExit::Code(code)),
+        }
+    }
+}
+
+// The signal of the last `qemu: uncaught target signal N (Name) - ...` line.
+fn uncaught_signal(stderr: &str) -> Option<i32> {
+    stderr
+        .lines()
+        .rev()
+        .find_map(|line| line.strip_prefix(UNCAUGHT_SIGNAL))
+        .and_then(|rest| rest.split_whitespace().next())
+        .and_then(|signal| signal.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn qemu(sysroot: Option<&str>) -> QemuUser {
+        QemuUser {
+            arch: "aarch64".into(),
+            qemu_path: None,
+            sysroot: sysroot.map(PathBuf::from),
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_target_command() -> Result<()> {
+        let cmd = qemu(Some("/usr/aarch64-linux-gnu")).target_command(
+            Path::new("/setup/fuzz"),
+            &["-runs=1"],
+            &["-max_len=10".to_owned(), "a b".to_owned()],
+            false,
+        )?;
+
+        assert_eq!(cmd.get_program(), "qemu-aarch64");
+        let args: Vec<_> = cmd.get_args().collect();
+        assert_eq!(args, ["/setup/fuzz", "-runs=1", "-max_len=10", "a b"]);
+
+        let envs: Vec<_> = cmd.get_envs().collect();
+        assert_eq!(
+            envs,
+            [(
+                OsStr::new(QEMU_LD_PREFIX),
+                Some(OsStr::new("/usr/aarch64-linux-gnu"))
+            )]
+        );
+
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_target_command_shell() -> Result<()> {
+        let qemu = QemuUser {
+            qemu_path: Some("/opt/qemu/bin/qemu-aarch64".into()),
+            ..qemu(None)
+        };
+        let cmd = qemu.target_command(
+            Path::new("/setup/fuzz"),
+            &["-runs=1"],
+            &["> out.txt".to_owned()],
+            true,
+        )?;
+
+        // QEMU is run by the shell, with the target as its first argument.
+        assert_eq!(cmd.get_program(), "sh");
+        let args: Vec<_> = cmd.get_args().collect();
+        assert_eq!(
+            args,
+            [
+                "-c",
+                "exec \"$0\" \"$@\" > out.txt",
+                "/opt/qemu/bin/qemu-aarch64",
+                "/setup/fuzz",
+                "-runs=1",
+            ]
+        );
+        assert_eq!(cmd.get_envs().count(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_exit() {
+        let status = |code, signal| ExitStatus {
+            code,
+            signal,
+            success: code == Some(0),
+        };
+        let segv = "qemu: uncaught target signal 11 (Segmentation fault) - core dumped\n";
+
+        let table = [
+            (status(Some(0), None), "", QemuExit::Success),
+            (status(Some(1), None), "", QemuExit::Code(1)),
+            (status(Some(77), None), "", QemuExit::Code(77)),
+            (status(None, Some(11)), segv, QemuExit::Signal(11)),
+            (status(None, Some(6)), "", QemuExit::Signal(6)),
+            (status(Some(139), None), segv, QemuExit::Signal(11)),
+            (status(Some(139), None), "", QemuExit::Code(139)),
+            (status(Some(134), None), segv, QemuExit::Code(134)),
+        ];
+
+        for (status, stderr, expected) in table {
+            assert_eq!(
+                qemu(None).exit(&status, stderr).unwrap(),
+                expected,
+                "{status:?} {stderr:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_exit_errors() {
+        let status = |code| ExitStatus {
+            code: Some(code),
+            signal: None,
+            success: false,
+        };
+
+        let table = [
+            (127, "sh: 1: exec: qemu-aarch64: not found", "not found"),
+            (
+                126,
+                "sh: 1: exec: qemu-aarch64: Permission denied",
+                "not executable",
+            ),
+            (
+                1,
+                "Error while loading /setup/fuzz: No such file or directory",
+                "unable to load the target",
+            ),
+        ];
+
+        for (code, stderr, expected) in table {
+            let err = qemu(None).exit(&status(code), stderr).unwrap_err();
+            assert!(err.to_string().contains(expected), "{code}: {err}");
+        }
+    }
+}
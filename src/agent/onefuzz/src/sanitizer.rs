@@ -1,10 +1,19 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
-use std::collections::HashMap;
-use std::path::Path;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
 
 use anyhow::{bail, Context, Result};
+use debuggable_module::debugfile::DebugFileDirs;
+use goblin::Object;
+
+use crate::env::sanitizer_options;
+
+/// Options of `llvm-symbolizer`, read from its environment, as inherited from
+/// the target running it.
+pub const LLVM_SYMBOLIZER_OPTS: &str = "LLVM_SYMBOLIZER_OPTS";
 
 pub fn default_llvm_symbolizer_path() -> Result<String> {
     std::env::var("LLVM_SYMBOLIZER_PATH")
@@ -67,6 +76,207 @@ impl AsRef<str> for LlvmSymbolizer {
     }
 }
 
+/// Add `dirs` to the debug file directories of `llvm-symbolizer`, as run by
+/// sanitizers of targets run with `env`, so that their stacks are symbolized
+/// from the external debug files of stripped modules. On macOS, the `.dSYM`
+/// bundles in `dirs` are passed as hints. Any options already set, by `env` or
+/// else the environment of the agent, are kept.
+pub fn add_debug_file_dirs(env: &mut HashMap<String, String>, dirs: &DebugFileDirs) {
+    if dirs.dirs().is_empty() {
+        return;
+    }
+
+    let mut options = env
+        .get(LLVM_SYMBOLIZER_OPTS)
+        .cloned()
+        .or_else(|| std::env::var(LLVM_SYMBOLIZER_OPTS).ok())
+        .unwrap_or_default();
+
+    for dir in dirs.dirs() {
+        if !options.is_empty() {
+            options.push(' ');
+        }
+        options.push_str(&format!("--debug-file-directory=\"{}\"", dir.display()));
+
+        if cfg!(target_os = "macos") {
+            for dsym in dsym_bundles(dir) {
+                options.push_str(&format!(" --dsym-hint=\"{}\"", dsym.display()));
+            }
+        }
+    }
+
+    env.insert(LLVM_SYMBOLIZER_OPTS.to_owned(), options);
+}
+
+/// A sanitizer built into a target, whose runtime reads its options from the
+/// environment.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Sanitizer {
+    Address,
+    Memory,
+    Thread,
+}
+
+impl Sanitizer {
+    pub const ALL: [Self; 3] = [Self::Address, Self::Memory, Self::Thread];
+
+    /// The variable the runtime reads its options from.
+    pub fn options_var(&self) -> &'static str {
+        match self {
+            Self::Address => "ASAN_OPTIONS",
+            Self::Memory => "MSAN_OPTIONS",
+            Self::Thread => "TSAN_OPTIONS",
+        }
+    }
+
+    /// The options OneFuzz recommends, so that each error is a crash of the
+    /// target, reported with a symbolized stack.
+    pub fn default_options(&self) -> &'static str {
+        match self {
+            Self::Address => "abort_on_error=1:symbolize=1:print_summary=1",
+            Self::Memory => "abort_on_error=1:symbolize=1:print_summary=1",
+            Self::Thread => "abort_on_error=1:halt_on_error=1:symbolize=1",
+        }
+    }
+
+    // The name of the runtime, as in its symbols and libraries.
+    fn short_name(&self) -> &'static str {
+        match self {
+            Self::Address => "asan",
+            Self::Memory => "msan",
+            Self::Thread => "tsan",
+        }
+    }
+
+    // Whether `library` is a shared runtime of the sanitizer, such as
+    // `libasan.so.8` of GCC, `libclang_rt.asan-x86_64.so` of Clang, or
+    // `clang_rt.asan_dynamic-x86_64.dll` of MSVC.
+    fn is_runtime(&self, library: &str) -> bool {
+        let library = library.to_ascii_lowercase();
+        let name = library.strip_prefix("lib").unwrap_or(&library);
+        let short_name = self.short_name();
+
+        name.starts_with(&format!("{short_name}."))
+            || name.starts_with(&format!("clang_rt.{short_name}"))
+    }
+}
+
+impl fmt::Display for Sanitizer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Address => "AddressSanitizer",
+            Self::Memory => "MemorySanitizer",
+            Self::Thread => "ThreadSanitizer",
+        };
+        f.write_str(name)
+    }
+}
+
+/// The sanitizers built into the module at `exe`, found by the initializer of
+/// their runtime, such as `__asan_init`, among the symbols it defines or
+/// imports, or by their runtime among the libraries it imports. Files which
+/// aren't ELF or PE modules, such as scripts, have none.
+pub async fn detect_sanitizers(exe: &Path) -> Result<Vec<Sanitizer>> {
+    let data = tokio::fs::read(exe)
+        .await
+        .with_context(|| format!("unable to read {}", exe.display()))?;
+
+    Ok(sanitizers(&data))
+}
+
+fn sanitizers(data: &[u8]) -> Vec<Sanitizer> {
+    match Object::parse(data) {
+        Ok(Object::Elf(elf)) => {
+            let dynsyms = elf
+                .dynsyms
+                .iter()
+                .filter_map(|sym| elf.dynstrtab.get_at(sym.st_name));
+            let syms = elf
+                .syms
+                .iter()
+                .filter_map(|sym| elf.strtab.get_at(sym.st_name));
+
+            detected(&dynsyms.chain(syms).collect(), &elf.libraries)
+        }
+        Ok(Object::PE(pe)) => {
+            let exports = pe.exports.iter().filter_map(|export| export.name);
+            let imports = pe.imports.iter().map(|import| import.name.as_ref());
+
+            detected(&exports.chain(imports).collect(), &pe.libraries)
+        }
+        _ => vec![],
+    }
+}
+
+fn detected(symbols: &HashSet<&str>, libraries: &[&str]) -> Vec<Sanitizer> {
+    Sanitizer::ALL
+        .into_iter()
+        .filter(|sanitizer| {
+            let init = format!("__{}_init", sanitizer.short_name());
+            symbols.contains(init.as_str())
+                || libraries
+                    .iter()
+                    .any(|library| sanitizer.is_runtime(library))
+        })
+        .collect()
+}
+
+/// Add the default options of `sanitizer` to its variable of `env`, for each
+/// option not already set, by `env` or else the environment of the agent.
+/// Returns the options added.
+///
+/// Options already set always win: the defaults are placed before them, since
+/// the runtime uses the last value of an option. So options set by a file
+/// included by `include` also win, though they aren't known to be set.
+pub fn add_default_options(env: &mut HashMap<String, String>, sanitizer: Sanitizer) -> Vec<String> {
+    let var = sanitizer.options_var();
+    let options = env
+        .get(var)
+        .cloned()
+        .or_else(|| std::env::var(var).ok())
+        .unwrap_or_default();
+
+    let set: HashSet<_> = sanitizer_options(&options)
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect();
+    let added: Vec<_> = sanitizer
+        .default_options()
+        .split(':')
+        .filter(|option| {
+            let (name, _) = option.split_once('=').unwrap_or((*option, ""));
+            !set.contains(name)
+        })
+        .map(String::from)
+        .collect();
+
+    if added.is_empty() {
+        return added;
+    }
+
+    let mut merged = added.join(":");
+    if !options.is_empty() {
+        merged.push(':');
+        merged.push_str(&options);
+    }
+    env.insert(var.to_owned(), merged);
+
+    added
+}
+
+fn dsym_bundles(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return vec![];
+    };
+
+    let mut bundles: Vec<_> = entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "dSYM"))
+        .collect();
+    bundles.sort();
+    bundles
+}
+
 fn is_valid_symbolizer_path(symbolizer: &str) -> bool {
     let file_name = Path::new(&symbolizer).file_name().and_then(|n| n.to_str());
 
@@ -122,6 +332,138 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_add_debug_file_dirs() {
+        let dirs = DebugFileDirs::new(["/setup".into(), "/usr/lib/debug".into()]);
+
+        let mut env = HashMap::new();
+        env.insert(LLVM_SYMBOLIZER_OPTS.to_owned(), "--demangle".to_owned());
+        add_debug_file_dirs(&mut env, &dirs);
+        assert_eq!(
+            env[LLVM_SYMBOLIZER_OPTS],
+            r#"--demangle --debug-file-directory="/setup" --debug-file-directory="/usr/lib/debug""#
+        );
+
+        let mut env = HashMap::new();
+        add_debug_file_dirs(&mut env, &DebugFileDirs::default());
+        assert!(env.is_empty());
+    }
+
+    #[test]
+    fn test_is_runtime() {
+        assert!(Sanitizer::Address.is_runtime("libasan.so.8"));
+        assert!(Sanitizer::Address.is_runtime("libclang_rt.asan-x86_64.so"));
+        assert!(Sanitizer::Address.is_runtime("clang_rt.asan_dynamic-x86_64.dll"));
+        assert!(Sanitizer::Address.is_runtime("CLANG_RT.ASAN_DYNAMIC-I386.DLL"));
+        assert!(Sanitizer::Thread.is_runtime("libtsan.so.2"));
+        assert!(Sanitizer::Memory.is_runtime("libclang_rt.msan.so"));
+
+        assert!(!Sanitizer::Address.is_runtime("libtsan.so.2"));
+        assert!(!Sanitizer::Address.is_runtime("libasanhook.so"));
+        assert!(!Sanitizer::Thread.is_runtime("libc.so.6"));
+    }
+
+    #[test]
+    fn test_detected() {
+        let symbols = HashSet::from(["main", "__asan_init"]);
+        assert_eq!(detected(&symbols, &["libc.so.6"]), [Sanitizer::Address]);
+
+        let symbols = HashSet::from(["main"]);
+        assert_eq!(
+            detected(&symbols, &["libtsan.so.2", "libc.so.6"]),
+            [Sanitizer::Thread]
+        );
+        assert!(detected(&symbols, &["libc.so.6"]).is_empty());
+
+        assert!(sanitizers(b"#!/bin/sh\n").is_empty());
+    }
+
+    #[test]
+    fn test_add_default_options() {
+        // Defaults are added when nothing is set.
+        let mut env = HashMap::new();
+        let added = add_default_options(&mut env, Sanitizer::Memory);
+        assert_eq!(
+            added,
+            ["abort_on_error=1", "symbolize=1", "print_summary=1"]
+        );
+        assert_eq!(
+            env["MSAN_OPTIONS"],
+            "abort_on_error=1:symbolize=1:print_summary=1"
+        );
+
+        // Options already set win, including with other separators, and are
+        // kept last.
+        let mut env = HashMap::new();
+        env.insert(
+            "MSAN_OPTIONS".to_owned(),
+            "abort_on_error=0 print_summary=0,halt_on_error=0".to_owned(),
+        );
+        let added = add_default_options(&mut env, Sanitizer::Memory);
+        assert_eq!(added, ["symbolize=1"]);
+        assert_eq!(
+            env["MSAN_OPTIONS"],
+            "symbolize=1:abort_on_error=0 print_summary=0,halt_on_error=0"
+        );
+
+        // Nothing is added once every default is set.
+        let before = env.clone();
+        assert!(add_default_options(&mut env, Sanitizer::Memory).is_empty());
+        assert_eq!(env, before);
+
+        // Defaults precede options of included files, which then win.
+        let mut env = HashMap::new();
+        env.insert(
+            "TSAN_OPTIONS".to_owned(),
+            "include='/setup/tsan options.txt'".to_owned(),
+        );
+        add_default_options(&mut env, Sanitizer::Thread);
+        assert_eq!(
+            env["TSAN_OPTIONS"],
+            "abort_on_error=1:halt_on_error=1:symbolize=1:include='/setup/tsan options.txt'"
+        );
+    }
+
+    // Build the crashing fixture with `flags`, such as to enable a sanitizer.
+    #[cfg(target_os = "linux")]
+    fn build_fixture(dir: &Path, flags: &[&str]) -> PathBuf {
+        let fixture = Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/triage/crash.c"));
+        let exe = dir.join(format!("crash{}", flags.join("")));
+        let status = std::process::Command::new("cc")
+            .args(flags)
+            .arg("-o")
+            .arg(&exe)
+            .arg(fixture)
+            .status()
+            .unwrap();
+        assert!(status.success(), "cc failed: {status}");
+
+        exe
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    #[cfg_attr(not(feature = "integration_test"), ignore)]
+    async fn test_detect_sanitizers() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+
+        // The runtime is imported, as is `__asan_init`.
+        let exe = build_fixture(dir.path(), &["-fsanitize=address"]);
+        assert_eq!(detect_sanitizers(&exe).await?, [Sanitizer::Address]);
+
+        // The runtime is linked in, defining `__asan_init`.
+        let exe = build_fixture(dir.path(), &["-fsanitize=address", "-static-libasan"]);
+        assert_eq!(detect_sanitizers(&exe).await?, [Sanitizer::Address]);
+
+        let exe = build_fixture(dir.path(), &["-fsanitize=thread"]);
+        assert_eq!(detect_sanitizers(&exe).await?, [Sanitizer::Thread]);
+
+        let exe = build_fixture(dir.path(), &[]);
+        assert!(detect_sanitizers(&exe).await?.is_empty());
+
+        Ok(())
+    }
+
     #[test]
     fn test_sanitizer_env_vars() -> Result<()> {
         const SYMBOLIZER_PATH: &str = "/my/llvm-symbolizer";
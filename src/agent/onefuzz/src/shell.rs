@@ -0,0 +1,278 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Building the command line used to run a target.
+//!
+//! Target options are passed to the target as separate arguments, exactly as
+//! they are after placeholder expansion. They are never split on spaces, and
+//! quotes, `%` and braces have no special meaning.
+//!
+//! For the rare target which needs a shell, such as for redirection, a task
+//! can set `target_options_shell`. The options are then joined with spaces and
+//! interpreted by `sh`, or by `cmd` on Windows. Any other arguments are still
+//! passed to the target as is.
+//!
+//! On Windows, a target can be a wrapper script which sets up and launches the
+//! real target. `.bat` and `.cmd` scripts are run by `cmd`, and `.ps1` scripts
+//! by `powershell`, with the script path and arguments passed through.
+
+use std::{ffi::OsStr, path::Path, process::Command};
+
+use anyhow::Result;
+
+/// Build a command which runs `program` with `args`, followed by `options`.
+///
+/// If `shell` is set, `options` are interpreted by the platform shell.
+/// Otherwise, they are passed to the program as is, like `args`.
+pub fn target_command(
+    program: &Path,
+    args: &[impl AsRef<OsStr>],
+    options: &[String],
+    shell: bool,
+) -> Result<Command> {
+    if shell {
+        return shell_command(program, args, options);
+    }
+
+    let mut cmd = match script_interpreter(program) {
+        Some(interpreter) => {
+            let mut cmd = Command::new(interpreter[0]);
+            cmd.args(&interpreter[1..]).arg(program);
+            cmd
+        }
+        None => Command::new(program),
+    };
+    cmd.args(args).args(options);
+    Ok(cmd)
+}
+
+/// The command line prefix to run `program` with, if it is a script which
+/// can't be run directly.
+#[cfg(target_family = "unix")]
+fn script_interpreter(_program: &Path) -> Option<&'static [&'static str]> {
+    // Scripts are run by the interpreter named by their `#!` line.
+    None
+}
+
+/// The command line prefix to run `program` with, if it is a script which
+/// can't be run directly.
+#[cfg(target_family = "windows")]
+fn script_interpreter(program: &Path) -> Option<&'static [&'static str]> {
+    const POWERSHELL: &[&str] = &[
+        "powershell.exe",
+        "-NoLogo",
+        "-NoProfile",
+        "-NonInteractive",
+        "-ExecutionPolicy",
+        "Bypass",
+        "-File",
+    ];
+
+    // `.bat` and `.cmd` scripts are run through `cmd` by `Command` itself.
+    let extension = program.extension()?;
+    extension.eq_ignore_ascii_case("ps1").then_some(POWERSHELL)
+}
+
+#[cfg(target_family = "unix")]
+fn shell_command(
+    program: &Path,
+    args: &[impl AsRef<OsStr>],
+    options: &[String],
+) -> Result<Command> {
+    // The program and the literal arguments are passed to the script as `$0`
+    // and `$@`, so that only the options are interpreted by the shell.
+    let script = format!("exec \"$0\" \"$@\" {}", options.join(" "));
+
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg(script).arg(program).args(args);
+    Ok(cmd)
+}
+
+#[cfg(target_family = "windows")]
+fn shell_command(
+    program: &Path,
+    args: &[impl AsRef<OsStr>],
+    options: &[String],
+) -> Result<Command> {
+    use std::os::windows::process::CommandExt;
+
+    let mut line = String::new();
+    for word in script_interpreter(program).unwrap_or_default() {
+        line.push_str(word);
+        line.push(' ');
+    }
+    line.push_str(&cmd_quote(program.as_os_str())?);
+    for arg in args {
+        line.push(' ');
+        line.push_str(&cmd_quote(arg.as_ref())?);
+    }
+    for option in options {
+        line.push(' ');
+        line.push_str(option);
+    }
+
+    // With `/S`, `cmd` removes only the outermost quotes, and runs the rest of
+    // the line as is. Arguments are not quoted by `Command`, since `cmd` does
+    // not follow the usual quoting rules.
+    let mut cmd = Command::new("cmd.exe");
+    cmd.raw_arg(format!("/D /S /C \"{line}\""));
+    Ok(cmd)
+}
+
+// Quote a literal argument, so that `cmd` passes it to the program unchanged.
+#[cfg(target_family = "windows")]
+fn cmd_quote(arg: &OsStr) -> Result<String> {
+    let Some(arg) = arg.to_str() else {
+        bail!("argument is not valid Unicode: {arg:?}");
+    };
+
+    // Neither can be escaped within a quoted argument.
+    if arg.contains(['"', '%']) {
+        bail!("argument can't be passed through cmd unchanged: {arg}");
+    }
+
+    Ok(format!("\"{arg}\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options() -> Vec<String> {
+        ["a b", "\"c\"", "{d}", "%e%"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_options_are_passed_as_is() -> Result<()> {
+        let cmd = target_command(Path::new("fuzz.exe"), &["-x"], &options(), false)?;
+
+        assert_eq!(cmd.get_program(), "fuzz.exe");
+        let args: Vec<_> = cmd.get_args().collect();
+        assert_eq!(args, ["-x", "a b", "\"c\"", "{d}", "%e%"]);
+        Ok(())
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn test_shell_command() -> Result<()> {
+        let cmd = target_command(Path::new("fuzz.exe"), &["-x y"], &options(), true)?;
+
+        assert_eq!(cmd.get_program(), "sh");
+        let args: Vec<_> = cmd.get_args().collect();
+        assert_eq!(
+            args,
+            [
+                "-c",
+                "exec \"$0\" \"$@\" a b \"c\" {d} %e%",
+                "fuzz.exe",
+                "-x y"
+            ]
+        );
+        Ok(())
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn test_run_without_shell() -> Result<()> {
+        let mut cmd = target_command(Path::new("printf"), &["%s|"], &options(), false)?;
+
+        let output = cmd.output()?;
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "a b|\"c\"|{d}|%e%|"
+        );
+        Ok(())
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn test_run_with_shell() -> Result<()> {
+        let options = ["'a b'", "\"c d\"", "e", "{f}", ">&2"].map(String::from);
+        let mut cmd = target_command(Path::new("printf"), &["%s|"], &options, true)?;
+
+        let output = cmd.output()?;
+        assert!(output.stdout.is_empty());
+        assert_eq!(String::from_utf8_lossy(&output.stderr), "a b|c d|e|{f}|");
+        Ok(())
+    }
+
+    #[cfg(target_family = "windows")]
+    #[test]
+    fn test_shell_command() -> Result<()> {
+        let cmd = target_command(
+            Path::new("C:\\setup dir\\fuzz.exe"),
+            &["-x y"],
+            &options(),
+            true,
+        )?;
+
+        assert_eq!(cmd.get_program(), "cmd.exe");
+        let args: Vec<_> = cmd.get_args().collect();
+        assert_eq!(
+            args,
+            ["/D /S /C \"\"C:\\setup dir\\fuzz.exe\" \"-x y\" a b \"c\" {d} %e%\""]
+        );
+        Ok(())
+    }
+
+    #[cfg(target_family = "windows")]
+    #[test]
+    fn test_powershell_script() -> Result<()> {
+        let cmd = target_command(
+            Path::new("C:\\setup\\launch.PS1"),
+            &["-x"],
+            &options(),
+            false,
+        )?;
+
+        assert_eq!(cmd.get_program(), "powershell.exe");
+        let args: Vec<_> = cmd.get_args().collect();
+        assert_eq!(
+            args,
+            [
+                "-NoLogo",
+                "-NoProfile",
+                "-NonInteractive",
+                "-ExecutionPolicy",
+                "Bypass",
+                "-File",
+                "C:\\setup\\launch.PS1",
+                "-x",
+                "a b",
+                "\"c\"",
+                "{d}",
+                "%e%"
+            ]
+        );
+
+        let cmd = target_command(Path::new("C:\\setup\\launch.ps1"), &["-x"], &[], true)?;
+        let args: Vec<_> = cmd.get_args().collect();
+        assert_eq!(
+            args,
+            ["/D /S /C \"powershell.exe -NoLogo -NoProfile -NonInteractive -ExecutionPolicy Bypass -File \"C:\\setup\\launch.ps1\" \"-x\"\""]
+        );
+        Ok(())
+    }
+
+    #[cfg(target_family = "windows")]
+    #[test]
+    fn test_run_batch_script() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let script = dir.path().join("launch.bat");
+        std::fs::write(&script, "@echo %1\r\n")?;
+
+        let output = target_command(&script, &["hello"], &[], false)?.output()?;
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "hello");
+        Ok(())
+    }
+
+    #[cfg(target_family = "windows")]
+    #[test]
+    fn test_unquotable_argument() {
+        let result = target_command(Path::new("fuzz.exe"), &["%PATH%"], &options(), true);
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,166 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Download of PDBs from symbol servers, into a local symbol store.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use debuggable_module::symsrv::{PdbKey, SymbolPath};
+use reqwest::{StatusCode, Url};
+use tokio::fs;
+
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Symbol servers, with a local store caching the PDBs found on them.
+///
+/// Lookups which fail are remembered, so each PDB is requested at most once.
+pub struct SymbolServers {
+    cache: PathBuf,
+    servers: Vec<Url>,
+    client: reqwest::Client,
+    misses: Mutex<HashSet<PdbKey>>,
+}
+
+impl SymbolServers {
+    pub fn new(cache: impl Into<PathBuf>, servers: Vec<Url>) -> Self {
+        Self {
+            cache: cache.into(),
+            servers,
+            client: reqwest_retry::proxy::client(),
+            misses: Mutex::default(),
+        }
+    }
+
+    /// The local store the PDBs are cached in.
+    pub fn cache(&self) -> &Path {
+        &self.cache
+    }
+
+    /// A symbol path searching the local store.
+    pub fn symbol_path(&self) -> SymbolPath {
+        SymbolPath {
+            stores: vec![self.cache.clone()],
+            ..SymbolPath::default()
+        }
+    }
+
+    /// The cached path of the PDB of `key`, downloading it if needed.
+    ///
+    /// Returns `None` if no server has the PDB, or it couldn't be downloaded.
+    pub async fn fetch(&self, key: &PdbKey) -> Option<PathBuf> {
+        let path = key.store_path(&self.cache);
+        if fs::metadata(&path).await.is_ok() {
+            return Some(path);
+        }
+
+        if self.is_miss(key) {
+            return None;
+        }
+
+        for server in &self.servers {
+            match self.download(server, key, &path).await {
+                Ok(true) => {
+                    info!("downloaded {} from {}", key.name(), server);
+                    return Some(path);
+                }
+                Ok(false) => {
+                    debug!("{} not found on {}", key.name(), server);
+                }
+                Err(err) => {
+                    warn!(
+                        "unable to download {} from {}: {:?}",
+                        key.name(),
+                        server,
+                        err
+                    );
+                }
+            }
+        }
+
+        self.misses.lock().unwrap().insert(key.clone());
+        None
+    }
+
+    /// The cached path of the PDB referenced by the PE file `pe_path`, if any,
+    /// downloading it if needed.
+    pub async fn fetch_for_module(&self, pe_path: impl AsRef<Path>) -> Option<PathBuf> {
+        let pe_path = pe_path.as_ref();
+
+        let key = match read_pdb_key(pe_path).await {
+            Ok(Some(key)) => key,
+            Ok(None) => {
+                debug!("no PDB referenced by {}", pe_path.display());
+                return None;
+            }
+            Err(err) => {
+                debug!("unable to read {}: {:?}", pe_path.display(), err);
+                return None;
+            }
+        };
+
+        self.fetch(&key).await
+    }
+
+    fn is_miss(&self, key: &PdbKey) -> bool {
+        self.misses.lock().unwrap().contains(key)
+    }
+
+    // Download the PDB of `key` from `server` to `path`, returning `false` if
+    // the server doesn't have it.
+    async fn download(&self, server: &Url, key: &PdbKey, path: &Path) -> Result<bool> {
+        let url = pdb_url(server, key)?;
+
+        let response = self
+            .client
+            .get(url)
+            .timeout(DOWNLOAD_TIMEOUT)
+            .send()
+            .await?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(false);
+        }
+
+        let data = response.error_for_status()?.bytes().await?;
+
+        // Unwrap-safe, store paths have at least 3 components.
+        let dir = path.parent().unwrap();
+        fs::create_dir_all(dir)
+            .await
+            .with_context(|| format!("creating symbol cache directory {}", dir.display()))?;
+
+        // Write to a temporary file first, so that a partial download is never
+        // mistaken for the PDB.
+        let tmp = dir.join(format!(".{}.tmp", key.name()));
+        fs::write(&tmp, &data).await?;
+        fs::rename(&tmp, path).await?;
+
+        Ok(true)
+    }
+}
+
+/// The URL of the PDB of `key` on the symbol server `server`.
+pub fn pdb_url(server: &Url, key: &PdbKey) -> Result<Url> {
+    let mut url = server.clone();
+
+    match url.path_segments_mut() {
+        Ok(mut segments) => {
+            segments.pop_if_empty().extend(key.store_components());
+        }
+        Err(()) => bail!("invalid symbol server URL: {}", server),
+    }
+
+    Ok(url)
+}
+
+async fn read_pdb_key(pe_path: &Path) -> Result<Option<PdbKey>> {
+    let data = fs::read(pe_path).await?;
+    PdbKey::from_pe_data(&data)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,173 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use anyhow::Result;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use super::*;
+
+const GUID: [u8; 16] = [
+    0x40, 0x3f, 0x2e, 0x1d, 0x6b, 0x5a, 0x7d, 0x4c, 0x8e, 0x9f, 0xa0, 0xb1, 0xc2, 0xd3, 0xe4, 0xf5,
+];
+
+const PDB_PATH: &str = "/symbols/fuzz.pdb/1D2E3F405A6B4C7D8E9FA0B1C2D3E4F51/fuzz.pdb";
+
+fn key() -> PdbKey {
+    PdbKey::new("fuzz.pdb", GUID, 1)
+}
+
+fn server_url(server: &MockServer) -> Result<Url> {
+    Ok(Url::parse(&format!("{}/symbols", server.uri()))?)
+}
+
+#[test]
+fn test_pdb_url() -> Result<()> {
+    let expected =
+        "https://symbols.example.com/symbols/fuzz.pdb/1D2E3F405A6B4C7D8E9FA0B1C2D3E4F51/fuzz.pdb";
+
+    let server = Url::parse("https://symbols.example.com/symbols")?;
+    assert_eq!(pdb_url(&server, &key())?.as_str(), expected);
+
+    // With a trailing slash.
+    let server = Url::parse("https://symbols.example.com/symbols/")?;
+    assert_eq!(pdb_url(&server, &key())?.as_str(), expected);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_fetch_miss_then_hit() -> Result<()> {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path(PDB_PATH))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(b"pdb".to_vec()))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let cache = tempfile::tempdir()?;
+    let servers = SymbolServers::new(cache.path(), vec![server_url(&server)?]);
+
+    // Downloaded into the store on a miss.
+    let fetched = servers.fetch(&key()).await;
+    let expected = key().store_path(cache.path());
+    assert_eq!(fetched.as_ref(), Some(&expected));
+    assert_eq!(std::fs::read(&expected)?, b"pdb");
+
+    // Then read from the store, without another request.
+    assert_eq!(servers.fetch(&key()).await, Some(expected.clone()));
+
+    // Also by a loader searching the store.
+    assert_eq!(servers.symbol_path().find_local(&key()), Some(expected));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_fetch_cached() -> Result<()> {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&server)
+        .await;
+
+    let cache = tempfile::tempdir()?;
+    let cached = key().store_path(cache.path());
+    std::fs::create_dir_all(cached.parent().unwrap())?;
+    std::fs::write(&cached, b"cached")?;
+
+    let servers = SymbolServers::new(cache.path(), vec![server_url(&server)?]);
+    assert_eq!(servers.fetch(&key()).await, Some(cached));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_fetch_not_found() -> Result<()> {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(404))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let cache = tempfile::tempdir()?;
+    let servers = SymbolServers::new(cache.path(), vec![server_url(&server)?]);
+
+    // The miss is remembered, so the server is only asked once.
+    assert_eq!(servers.fetch(&key()).await, None);
+    assert_eq!(servers.fetch(&key()).await, None);
+    assert!(!key().store_path(cache.path()).exists());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_fetch_fallback() -> Result<()> {
+    let failing = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&failing)
+        .await;
+
+    let missing = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(404))
+        .expect(1)
+        .mount(&missing)
+        .await;
+
+    let found = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path(PDB_PATH))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(b"pdb".to_vec()))
+        .expect(1)
+        .mount(&found)
+        .await;
+
+    let cache = tempfile::tempdir()?;
+    let servers = SymbolServers::new(
+        cache.path(),
+        vec![
+            server_url(&failing)?,
+            server_url(&missing)?,
+            server_url(&found)?,
+        ],
+    );
+
+    // Errors and misses fall through to the next server.
+    let expected = key().store_path(cache.path());
+    assert_eq!(servers.fetch(&key()).await, Some(expected));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_fetch_unreachable() -> Result<()> {
+    // Nothing listens on the port of a dropped server.
+    let url = {
+        let server = MockServer::start().await;
+        server_url(&server)?
+    };
+
+    let cache = tempfile::tempdir()?;
+    let servers = SymbolServers::new(cache.path(), vec![url]);
+    assert_eq!(servers.fetch(&key()).await, None);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_fetch_for_module_not_pe() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let exe = dir.path().join("fuzz.exe");
+    std::fs::write(&exe, b"not a PE")?;
+
+    let servers = SymbolServers::new(dir.path(), vec![]);
+    assert_eq!(servers.fetch_for_module(&exe).await, None);
+
+    Ok(())
+}
@@ -3,23 +3,35 @@
 
 use crate::{
     az_copy,
-    blob::{BlobClient, BlobContainerUrl},
+    blob::{
+        list::{list_dir, BlobItem},
+        BlobClient, BlobContainerUrl, BlobUrl,
+    },
     fs::{exists, sync, SyncPath},
+    initial_corpus::MaxInitialCorpus,
     jitter::delay_with_jitter,
     monitor::DirectoryMonitor,
     uploader::BlobUploader,
 };
 use anyhow::{Context, Result};
 use dunce::canonicalize;
+use futures::stream::{self, StreamExt, TryStreamExt};
 use onefuzz_result::job_result::{JobResultData, JobResultSender, TaskJobResultClient};
 use onefuzz_telemetry::{Event, EventData};
 use reqwest::{StatusCode, Url};
 use reqwest_retry::{RetryCheck, SendRetry, DEFAULT_RETRY_PERIOD, MAX_RETRY_ATTEMPTS};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::{env::current_dir, path::PathBuf, str, time::Duration};
+use std::collections::{HashMap, HashSet};
+use std::{
+    env::current_dir,
+    path::{Component, Path, PathBuf},
+    str,
+    sync::Mutex,
+    time::Duration,
+};
 use tokio::{fs, select};
 use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
 
 #[derive(Debug, Clone, Copy)]
 pub enum SyncOperation {
@@ -30,7 +42,11 @@ pub enum SyncOperation {
 const DELAY: Duration = Duration::from_secs(10);
 const DEFAULT_CONTINUOUS_SYNC_DELAY_SECONDS: u64 = 60;
 
+// Number of blobs downloaded at once, when pulling blobs one by one.
+const MAX_CONCURRENT_DOWNLOADS: usize = 32;
+
 #[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
 pub struct SyncedDir {
     #[serde(alias = "local_path", alias = "path")]
     pub local_path: PathBuf,
@@ -123,6 +139,134 @@ impl SyncedDir {
             .context("pull failed")
     }
 
+    /// Pull the directory as `init_pull` does, unless `max` limits the
+    /// initial corpus, in which case only a sample of the remote blobs is
+    /// pulled. Either way, the returned directory can then be synced.
+    pub async fn init_pull_sampled(&self, max: Option<&MaxInitialCorpus>) -> Result<SampledDir> {
+        let Some(max) = max else {
+            self.init_pull().await?;
+            return Ok(SampledDir {
+                dir: self.clone(),
+                listed: None,
+            });
+        };
+
+        self.init().await.context("init failed")?;
+
+        let blobs = self.list_remote().await?;
+        let manifest = match max.manifest() {
+            Some(name) => Some(self.read_remote(name).await?),
+            None => None,
+        };
+
+        let selection = max.select(&blobs, manifest.as_deref());
+        self.download(selection.blobs.iter().map(|blob| blob.name.as_str()))
+            .await?;
+
+        info!(
+            "sampled initial corpus of {}: pulled {} of {} blobs ({} bytes), skipping {:.1}%",
+            self.local_path.display(),
+            selection.blobs.len(),
+            selection.listed,
+            selection.bytes(),
+            selection.skipped_fraction() * 100.0,
+        );
+        event!(
+            Event::initial_corpus_sampled;
+            EventData::Count = selection.blobs.len() as u64,
+            EventData::CorpusCount = selection.listed as u64,
+            EventData::Rate = selection.skipped_fraction()
+        );
+
+        let listed = blobs.into_iter().map(|blob| blob.name).collect();
+        Ok(SampledDir {
+            dir: self.clone(),
+            listed: Some(Mutex::new(listed)),
+        })
+    }
+
+    // The blobs of the remote container, or of the local directory if there
+    // is none.
+    async fn list_remote(&self) -> Result<Vec<BlobItem>> {
+        match &self.remote_path {
+            None => list_dir(&self.local_path).await,
+            Some(url) => match url.as_file_path() {
+                Some(path) => list_dir(path).await,
+                None => BlobClient::new()
+                    .list_blobs(&url.url()?)
+                    .await
+                    .with_context(|| format!("unable to list blobs of {url}")),
+            },
+        }
+    }
+
+    async fn read_remote(&self, name: &str) -> Result<String> {
+        let data = match self.remote_path.as_ref().map(|url| url.blob(name)) {
+            None => fs::read(self.local_path.join(name)).await?,
+            Some(BlobUrl::LocalFile(path)) => fs::read(path).await?,
+            Some(blob @ BlobUrl::AzureBlob(_)) => BlobClient::new().get_data(&blob.url()).await?,
+        };
+
+        String::from_utf8(data).with_context(|| format!("blob is not UTF-8: {name}"))
+    }
+
+    // Download the blobs `names` from the remote container, skipping those
+    // that already exist locally.
+    async fn download(&self, names: impl IntoIterator<Item = &str>) -> Result<()> {
+        let Some(url) = &self.remote_path else {
+            return Ok(());
+        };
+        let client = BlobClient::new();
+
+        stream::iter(names)
+            .map(|name| {
+                let client = &client;
+                async move {
+                    // Blob names may contain `/`, but may not escape the
+                    // directory.
+                    let relative = Path::new(name);
+                    if !relative
+                        .components()
+                        .all(|c| matches!(c, Component::Normal(_)))
+                    {
+                        warn!("skipping blob with invalid name: {}", name);
+                        return Ok(());
+                    }
+
+                    let destination = self.local_path.join(relative);
+                    if exists(&destination).await? {
+                        return Ok(());
+                    }
+                    if let Some(parent) = destination.parent() {
+                        fs::create_dir_all(parent).await?;
+                    }
+
+                    match url.blob(name) {
+                        BlobUrl::LocalFile(path) => {
+                            fs::copy(&path, &destination).await.with_context(|| {
+                                format!(
+                                    "unable to copy {} to {}",
+                                    path.display(),
+                                    destination.display()
+                                )
+                            })?;
+                        }
+                        blob @ BlobUrl::AzureBlob(_) => {
+                            client
+                                .get_file(&blob.url(), &destination)
+                                .await
+                                .with_context(|| format!("unable to download blob {name}"))?;
+                        }
+                    }
+
+                    Ok::<_, anyhow::Error>(())
+                }
+            })
+            .buffer_unordered(MAX_CONCURRENT_DOWNLOADS)
+            .try_collect()
+            .await
+    }
+
     pub async fn init(&self) -> Result<()> {
         if let Some(remote_path) = self.remote_path.clone().and_then(|u| u.as_file_path()) {
             fs::create_dir_all(&remote_path).await.with_context(|| {
@@ -238,16 +382,52 @@ impl SyncedDir {
         }
     }
 
+    /// Upload the file at `path` as `name`, unless there already is a file of
+    /// that name, returning the URL of the uploaded file.
+    pub async fn upload_file(&self, path: &Path, name: &str) -> Result<BlobUrl> {
+        let url = self.remote_url()?;
+
+        match url.as_file_path() {
+            Some(dir) => {
+                fs::create_dir_all(&dir).await?;
+                let destination = dir.join(name);
+                if !exists(&destination).await? {
+                    fs::copy(path, &destination).await.with_context(|| {
+                        format!(
+                            "unable to copy {} to {}",
+                            path.display(),
+                            destination.display()
+                        )
+                    })?;
+                }
+            }
+            None => {
+                // `BlobUploader` names blobs after the uploaded file.
+                let dir = tempfile::tempdir()?;
+                let renamed = dir.path().join(name);
+                fs::copy(path, &renamed).await?;
+
+                BlobUploader::new(url.url()?)
+                    .upload(&renamed)
+                    .await
+                    .context("SyncedDir.upload_file")?;
+            }
+        }
+
+        Ok(url.blob(name))
+    }
+
     async fn file_monitor_event(
         path: PathBuf,
         url: BlobContainerUrl,
         event: Event,
         ignore_dotfiles: bool,
+        task_id: Uuid,
         jr_client: &Option<TaskJobResultClient>,
     ) -> Result<()> {
         debug!("monitoring {}", path.display());
 
-        let mut monitor = DirectoryMonitor::new(path.clone()).await?;
+        let mut monitor = DirectoryMonitor::new(path.clone(), false).await?;
 
         if let Some(path) = url.as_file_path() {
             fs::create_dir_all(&path).await?;
@@ -317,7 +497,7 @@ impl SyncedDir {
                 }
             }
         } else {
-            let mut uploader = BlobUploader::new(url.url()?);
+            let mut uploader = BlobUploader::new(url.url()?).task_id(task_id);
 
             while let Some(item) = monitor.next_file().await? {
                 let file_name = item
@@ -392,10 +572,15 @@ impl SyncedDir {
     /// The intent of this is to support use cases where we usually want a directory
     /// to be initialized, but a user-supplied binary, (such as AFL) logically owns
     /// a directory, and may reset it.
+    ///
+    /// Uploaded blobs record `task_id` as their [`BlobProvenance`].
+    ///
+    /// [`BlobProvenance`]: crate::blob::BlobProvenance
     pub async fn monitor_results(
         &self,
         event: Event,
         ignore_dotfiles: bool,
+        task_id: Uuid,
         job_result_client: &Option<TaskJobResultClient>,
     ) -> Result<()> {
         if let Some(url) = self.remote_path.clone() {
@@ -416,6 +601,7 @@ impl SyncedDir {
                     url.clone(),
                     event.clone(),
                     ignore_dotfiles,
+                    task_id,
                     job_result_client,
                 )
                 .await?;
@@ -426,6 +612,78 @@ impl SyncedDir {
     }
 }
 
+/// A directory pulled by `SyncedDir::init_pull_sampled`.
+#[derive(Debug)]
+pub struct SampledDir {
+    pub dir: SyncedDir,
+
+    // The blobs listed when the directory was last pulled, if it was sampled.
+    // Only blobs that were not listed before are pulled, so that the skipped
+    // part of the initial corpus stays skipped.
+    listed: Option<Mutex<HashSet<String>>>,
+}
+
+impl SampledDir {
+    /// Pull the blobs added to the remote container since the last pull, or
+    /// every blob, if the directory was not sampled.
+    pub async fn sync_pull(&self) -> Result<()> {
+        let Some(listed) = &self.listed else {
+            return self.dir.sync_pull().await;
+        };
+
+        let blobs = self.dir.list_remote().await?;
+        let new: Vec<String> = {
+            let listed = listed
+                .lock()
+                .map_err(|_| anyhow!("sampled directory lock poisoned"))?;
+            blobs
+                .into_iter()
+                .map(|blob| blob.name)
+                .filter(|name| !listed.contains(name))
+                .collect()
+        };
+
+        if new.is_empty() {
+            return Ok(());
+        }
+
+        debug!(
+            "pulling {} new blobs to {}",
+            new.len(),
+            self.dir.local_path.display()
+        );
+        self.dir
+            .download(new.iter().map(String::as_str))
+            .await
+            .context("sync pull failed")?;
+
+        listed
+            .lock()
+            .map_err(|_| anyhow!("sampled directory lock poisoned"))?
+            .extend(new);
+
+        Ok(())
+    }
+}
+
+/// Keep pulling `dirs`, as `continuous_sync` does, but only pulling the blobs
+/// added since the last pull for sampled directories.
+pub async fn continuous_sync_pull(dirs: &[SampledDir], delay_seconds: Option<u64>) -> Result<()> {
+    let delay_seconds = delay_seconds.unwrap_or(DEFAULT_CONTINUOUS_SYNC_DELAY_SECONDS);
+    if delay_seconds == 0 {
+        return Ok(());
+    }
+
+    let delay = Duration::from_secs(delay_seconds);
+
+    loop {
+        for dir in dirs {
+            dir.sync_pull().await?;
+        }
+        delay_with_jitter(delay).await;
+    }
+}
+
 pub async fn continuous_sync(
     dirs: &[SyncedDir],
     operation: SyncOperation,
@@ -449,6 +707,7 @@ pub async fn continuous_sync(
 #[cfg(test)]
 mod tests {
     use super::SyncedDir;
+    use crate::blob::BlobUrl;
     use anyhow::{anyhow, Result};
     use dunce::canonicalize;
     use std::env::current_dir;
@@ -469,4 +728,77 @@ mod tests {
         assert_eq!(expected, blob_path);
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_upload_file() -> Result<()> {
+        let temp = tempfile::tempdir()?;
+        let file = temp.path().join("file");
+        std::fs::write(&file, "new")?;
+
+        let remote = temp.path().join("remote");
+        let dir = SyncedDir {
+            local_path: temp.path().join("local"),
+            remote_path: Some(crate::blob::BlobContainerUrl::new(
+                reqwest::Url::from_directory_path(&remote).unwrap(),
+            )?),
+        };
+
+        let url = dir.upload_file(&file, "uploaded").await?;
+        assert!(matches!(url, BlobUrl::LocalFile(path) if path == remote.join("uploaded")));
+        assert_eq!(std::fs::read_to_string(remote.join("uploaded"))?, "new");
+
+        // Existing files are kept.
+        std::fs::write(&file, "newer")?;
+        dir.upload_file(&file, "uploaded").await?;
+        assert_eq!(std::fs::read_to_string(remote.join("uploaded"))?, "new");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_init_pull_sampled() -> Result<()> {
+        use crate::initial_corpus::{MaxInitialCorpus, Sample};
+        use std::time::{Duration, SystemTime};
+
+        let temp = tempfile::tempdir()?;
+        let remote = temp.path().join("remote");
+        std::fs::create_dir(&remote)?;
+        for i in 0..10u64 {
+            let path = remote.join(format!("seed-{i}"));
+            std::fs::write(&path, "seed")?;
+            let file = std::fs::File::options().write(true).open(&path)?;
+            file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(i))?;
+        }
+
+        let local = temp.path().join("local");
+        let dir = SyncedDir {
+            local_path: local.clone(),
+            remote_path: Some(crate::blob::BlobContainerUrl::new(
+                reqwest::Url::from_directory_path(&remote).unwrap(),
+            )?),
+        };
+        let max = MaxInitialCorpus {
+            count: Some(3),
+            bytes: None,
+            sample: Sample::Newest,
+        };
+
+        let local_files = || -> Result<Vec<String>> {
+            let mut names = std::fs::read_dir(&local)?
+                .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+                .collect::<Result<Vec<_>>>()?;
+            names.sort();
+            Ok(names)
+        };
+
+        let sampled = dir.init_pull_sampled(Some(&max)).await?;
+        assert_eq!(local_files()?, ["seed-7", "seed-8", "seed-9"]);
+
+        // Only new blobs are pulled, not the blobs skipped at first.
+        std::fs::write(remote.join("new"), "new")?;
+        sampled.sync_pull().await?;
+        assert_eq!(local_files()?, ["new", "seed-7", "seed-8", "seed-9"]);
+
+        Ok(())
+    }
 }
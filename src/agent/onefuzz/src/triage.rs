@@ -3,6 +3,10 @@
 
 #![allow(clippy::trivially_copy_pass_by_ref)]
 use anyhow::Result;
+use debuggable_module::{
+    debugfile::DebugFileDirs, linux::LinuxModule, load_module::LoadModule, loader::Loader,
+    path::FilePath, symbolize::symbolize, Offset,
+};
 use pete::{
     Pid, Ptracer, Restart, Siginfo,
     Signal::{self, *},
@@ -15,10 +19,15 @@ use std::collections::BTreeMap;
 use std::fmt;
 use std::process::Command;
 
+pub mod fault;
+
+use fault::{Access, FaultingInstruction};
+
 pub struct TriageCommand {
     tracer: Ptracer,
     tracee: Tracee,
     pid: Pid,
+    debug_file_dirs: DebugFileDirs,
     _kill_on_drop: KillOnDrop,
 }
 impl TriageCommand {
@@ -39,6 +48,7 @@ impl TriageCommand {
             tracer,
             tracee,
             pid,
+            debug_file_dirs: DebugFileDirs::default(),
             _kill_on_drop,
         })
     }
@@ -47,19 +57,33 @@ impl TriageCommand {
         self.pid
     }
 
+    /// Also search `dirs` for the external debug files of stripped modules,
+    /// when symbolizing the stacks of crashes.
+    pub fn debug_file_dirs(self, dirs: DebugFileDirs) -> Self {
+        Self {
+            debug_file_dirs: dirs,
+            ..self
+        }
+    }
+
     pub fn run(mut self) -> Result<TriageReport> {
         self.tracer.restart(self.tracee, Restart::Continue)?;
 
         let mut crashes = vec![];
         let mut exit_status = None;
 
-        while let Some(tracee) = self.tracer.wait()? {
+        while let Some(mut tracee) = self.tracer.wait()? {
             match tracee.stop {
                 Stop::SignalDelivery { signal } => {
                     if CRASH_SIGNALS.contains(&signal) {
                         // Can unwrap due to signal-delivery-stop.
                         let siginfo = tracee.siginfo()?.unwrap();
-                        crashes.push(Crash::new(self.pid, signal, siginfo)?);
+                        let registers = registers(&tracee)?;
+                        let mut crash = Crash::new(self.pid, signal, siginfo, registers)?;
+                        if matches!(signal, SIGSEGV | SIGBUS) {
+                            crash.faulting_instruction = faulting_instruction(&mut tracee, &crash);
+                        }
+                        crashes.push(crash);
                     }
                 }
                 Stop::Exiting { exit_code } => {
@@ -77,6 +101,11 @@ impl TriageCommand {
         // We must observe either a normal or signaled exit for the parent.
         let exit_status = exit_status.unwrap();
 
+        let loader = Loader::new().with_debug_file_dirs(self.debug_file_dirs.clone());
+        for crash in &mut crashes {
+            crash.symbolize(&loader);
+        }
+
         Ok(TriageReport {
             exit_status,
             crashes,
@@ -142,10 +171,21 @@ pub struct Crash {
 
     /// All active threads at time of crash, including the crashing thread.
     pub threads: BTreeMap<i32, ThreadInfo>,
+
+    /// Registers of the signaled thread, at time of crash.
+    pub registers: Vec<Register>,
+
+    /// The instruction of a memory access violation, if analyzed.
+    pub faulting_instruction: Option<FaultingInstruction>,
 }
 
 impl Crash {
-    pub fn new(tid: Pid, signal: Signal, siginfo: Siginfo) -> Result<Self> {
+    pub fn new(
+        tid: Pid,
+        signal: Signal,
+        siginfo: Siginfo,
+        registers: Vec<Register>,
+    ) -> Result<Self> {
         let mut stacktrace = rstack::TraceOptions::new();
         stacktrace
             .snapshot(true)
@@ -189,6 +229,7 @@ impl Crash {
                     addr,
                     module,
                     function,
+                    source: None,
                 });
             }
 
@@ -209,8 +250,144 @@ impl Crash {
             crashing_access,
             tid,
             threads,
+            registers,
+            faulting_instruction: None,
         })
     }
+
+    /// The value of the register `name` of the signaled thread, if captured.
+    pub fn register(&self, name: &str) -> Option<u64> {
+        self.registers
+            .iter()
+            .find(|r| r.name == name)
+            .map(|r| r.value.0)
+    }
+
+    /// The fault type of the crash, such as `write-access-violation`, if its
+    /// faulting instruction was analyzed, or else the signal.
+    pub fn fault_type(&self) -> String {
+        self.faulting_instruction
+            .as_ref()
+            .and_then(|instruction| instruction.fault_type())
+            .map(|fault_type| fault_type.to_owned())
+            .unwrap_or_else(|| self.signal.to_string())
+    }
+
+    /// A log of the crash, in the form of a sanitizer report: the signal and
+    /// the address it faulted at, the stack of the signaled thread, and its
+    /// registers.
+    pub fn text(&self) -> String {
+        use std::fmt::Write;
+
+        let pid = self.tid.as_raw();
+        let mut text = String::new();
+
+        // Such as "SEGV on unknown address 0x000000000010", as ASan reports it,
+        // but of the signal, as sanitizers aren't involved.
+        let _ = write!(text, "=={pid}==ERROR: {}", self.signal);
+        if let Some(access) = &self.crashing_access {
+            let _ = write!(text, " on unknown address 0x{:012x}", access.0);
+        }
+        let registers = [
+            ("pc", PC_REGISTER),
+            ("bp", BP_REGISTER),
+            ("sp", SP_REGISTER),
+        ];
+        let registers: Vec<_> = registers
+            .iter()
+            .filter_map(|(label, name)| Some(format!("{label} 0x{:012x}", self.register(name)?)))
+            .collect();
+        let _ = writeln!(text, " ({} T{pid})", registers.join(" "));
+
+        if let Some(instruction) = &self.faulting_instruction {
+            instruction_text(&mut text, pid, instruction);
+        }
+
+        if let Some(thread) = self.threads.get(&self.tid.as_raw()) {
+            for (index, frame) in thread.callstack.iter().enumerate() {
+                let _ = writeln!(text, "    #{index} {frame}");
+            }
+        }
+
+        if !self.registers.is_empty() {
+            let _ = writeln!(text, "\nRegister values:");
+            for row in self.registers.chunks(4) {
+                let row: Vec<_> = row
+                    .iter()
+                    .map(|r| format!("{:>6} = 0x{:016x}", r.name, r.value.0))
+                    .collect();
+                let _ = writeln!(text, "{}", row.join("  "));
+            }
+        }
+
+        text
+    }
+
+    /// Resolve the functions and source lines of frames from the debug info of
+    /// their modules, which may be in external debug files, as of stripped
+    /// modules, whose functions `rstack` can't resolve from their symbols.
+    fn symbolize(&mut self, loader: &Loader) {
+        let mut frames: BTreeMap<String, Vec<(&mut Frame, bool)>> = BTreeMap::new();
+
+        for thread in self.threads.values_mut() {
+            for (index, frame) in thread.callstack.iter_mut().enumerate() {
+                if let Some(module) = &frame.module {
+                    let is_caller = index > 0;
+                    frames
+                        .entry(module.name.clone())
+                        .or_default()
+                        .push((frame, is_caller));
+                }
+            }
+        }
+
+        for (path, frames) in frames {
+            if let Err(err) = symbolize_frames(loader, &path, frames) {
+                debug!("unable to symbolize frames of {}: {:?}", path, err);
+            }
+        }
+    }
+}
+
+// Symbolize `frames` of the module at `path`, of which those of callers are
+// looked up at the call, which precedes their return address.
+fn symbolize_frames(loader: &Loader, path: &str, frames: Vec<(&mut Frame, bool)>) -> Result<()> {
+    let module = LinuxModule::load(loader, FilePath::new(path)?)?;
+
+    let lookup_offset = |frame: &Frame, is_caller: bool| {
+        let offset = module.file_offset_to_offset(frame.module.as_ref()?.offset)?;
+        let adjust = u64::from(is_caller);
+        Some(Offset(offset.0.checked_sub(adjust)?))
+    };
+
+    let offsets: Vec<_> = frames
+        .iter()
+        .filter_map(|(frame, is_caller)| lookup_offset(frame, *is_caller))
+        .collect();
+    let symbols = symbolize(&module, &offsets)?;
+
+    for (frame, is_caller) in frames {
+        let Some(symbol) = lookup_offset(frame, is_caller).and_then(|offset| symbols.get(&offset))
+        else {
+            continue;
+        };
+
+        if frame.function.is_none() {
+            frame.function = Some(Rva {
+                name: symbol.function.clone(),
+                offset: symbol.function_offset + u64::from(is_caller),
+            });
+        }
+
+        if let (Some(file), Some(line)) = (&symbol.file, symbol.line) {
+            frame.source = Some(SourceLine {
+                file: file.clone(),
+                line,
+            });
+        }
+    }
+
+    Ok(())
 }
 
 #[derive(Debug, Serialize)]
@@ -238,16 +415,25 @@ pub struct Frame {
 
     /// Function-relative address of `addr`, if resolved.
     pub function: Option<Rva>,
+
+    /// Source line of `addr`, if resolved from debug info.
+    pub source: Option<SourceLine>,
 }
 
 impl fmt::Display for Frame {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         if let (Some(function), Some(module)) = (&self.function, &self.module) {
-            return write!(
+            write!(
                 f,
                 "0x{:x} in {}+0x{:x} ({}+0x{:x})",
                 self.addr.0, function.name, function.offset, module.name, module.offset,
-            );
+            )?;
+
+            if let Some(source) = &self.source {
+                write!(f, " {}:{}", source.file, source.line)?;
+            }
+
+            return Ok(());
         }
 
         if let Some(module) = &self.module {
@@ -272,6 +458,179 @@ pub struct Rva {
     pub offset: u64,
 }
 
+/// Source file and line of an address.
+#[derive(Debug, Serialize)]
+pub struct SourceLine {
+    pub file: String,
+    pub line: u64,
+}
+
+// Describe the faulting `instruction` of the crash, as ASan does of a SEGV.
+fn instruction_text(text: &mut String, pid: i32, instruction: &FaultingInstruction) {
+    use std::fmt::Write;
+
+    match instruction.access {
+        Some(Access::Read) => {
+            let _ = writeln!(
+                text,
+                "=={pid}==The signal is caused by a READ memory access."
+            );
+        }
+        Some(Access::Write) => {
+            let _ = writeln!(
+                text,
+                "=={pid}==The signal is caused by a WRITE memory access."
+            );
+        }
+        Some(Access::Execute) => {
+            let _ = writeln!(
+                text,
+                "=={pid}==Hint: PC is at a non-executable region. Maybe a wild jump?"
+            );
+        }
+        None => {}
+    }
+
+    if let Some(disassembly) = &instruction.disassembly {
+        let _ = write!(text, "=={pid}==The faulting instruction is `{disassembly}`");
+        if !instruction.address_registers.is_empty() {
+            let _ = write!(
+                text,
+                ", of an address computed from {}",
+                instruction.address_registers.join(", ")
+            );
+        }
+        let _ = writeln!(text, ".");
+    }
+
+    if instruction.branch_target {
+        let _ = writeln!(
+            text,
+            "=={pid}==Hint: the value read is the target of a call or jump."
+        );
+    }
+}
+
+// Analyze the instruction the signaled `tracee` of `crash` faulted at.
+#[cfg(target_arch = "x86_64")]
+fn faulting_instruction(tracee: &mut Tracee, crash: &Crash) -> Option<FaultingInstruction> {
+    let pc = crash.register(PC_REGISTER)?;
+    let code = read_code(tracee, pc);
+    let fault_address = crash.crashing_access.as_ref().map(|a| a.0);
+
+    Some(fault::analyze(&code, pc, fault_address, &crash.registers))
+}
+
+// Only x86-64 instructions are decoded.
+#[cfg(target_arch = "aarch64")]
+fn faulting_instruction(_tracee: &mut Tracee, _crash: &Crash) -> Option<FaultingInstruction> {
+    None
+}
+
+// Read the code at `pc`, of up to the longest instruction. Only the rest of its
+// page is read if the next page can't be, as when the instruction is at the
+// end of the last mapped page.
+#[cfg(target_arch = "x86_64")]
+fn read_code(tracee: &mut Tracee, pc: u64) -> Vec<u8> {
+    const PAGE_SIZE: u64 = 4096;
+
+    let mut code = vec![0; fault::MAX_INSTRUCTION_LEN];
+    let on_page = (PAGE_SIZE - pc % PAGE_SIZE).min(code.len() as u64) as usize;
+
+    if tracee.read_memory_mut(pc, &mut code[..on_page]).is_err() {
+        return vec![];
+    }
+
+    if on_page < code.len()
+        && tracee
+            .read_memory_mut(pc + on_page as u64, &mut code[on_page..])
+            .is_err()
+    {
+        code.truncate(on_page);
+    }
+
+    code
+}
+
+/// Value of a register, by its name, such as `rip`.
+#[derive(Debug, Serialize)]
+pub struct Register {
+    pub name: String,
+    pub value: Address,
+}
+
+impl Register {
+    fn new(name: impl Into<String>, value: u64) -> Self {
+        Self {
+            name: name.into(),
+            value: value.into(),
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+const PC_REGISTER: &str = "rip";
+#[cfg(target_arch = "x86_64")]
+const BP_REGISTER: &str = "rbp";
+#[cfg(target_arch = "x86_64")]
+const SP_REGISTER: &str = "rsp";
+
+#[cfg(target_arch = "aarch64")]
+const PC_REGISTER: &str = "pc";
+#[cfg(target_arch = "aarch64")]
+const BP_REGISTER: &str = "x29";
+#[cfg(target_arch = "aarch64")]
+const SP_REGISTER: &str = "sp";
+
+// The general-purpose registers of the stopped `tracee`.
+#[cfg(target_arch = "x86_64")]
+fn registers(tracee: &Tracee) -> Result<Vec<Register>> {
+    let regs = tracee.registers()?;
+
+    let registers = [
+        ("rax", regs.rax),
+        ("rbx", regs.rbx),
+        ("rcx", regs.rcx),
+        ("rdx", regs.rdx),
+        ("rsi", regs.rsi),
+        ("rdi", regs.rdi),
+        ("rbp", regs.rbp),
+        ("rsp", regs.rsp),
+        ("r8", regs.r8),
+        ("r9", regs.r9),
+        ("r10", regs.r10),
+        ("r11", regs.r11),
+        ("r12", regs.r12),
+        ("r13", regs.r13),
+        ("r14", regs.r14),
+        ("r15", regs.r15),
+        ("rip", regs.rip),
+        ("eflags", regs.eflags),
+    ];
+
+    Ok(registers
+        .into_iter()
+        .map(|(name, value)| Register::new(name, value))
+        .collect())
+}
+
+#[cfg(target_arch = "aarch64")]
+fn registers(tracee: &Tracee) -> Result<Vec<Register>> {
+    let regs = tracee.registers()?;
+
+    let mut registers: Vec<_> = regs
+        .regs
+        .iter()
+        .enumerate()
+        .map(|(index, value)| Register::new(format!("x{index}"), *value))
+        .collect();
+    registers.push(Register::new("sp", regs.sp));
+    registers.push(Register::new("pc", regs.pc));
+    registers.push(Register::new("pstate", regs.pstate));
+
+    Ok(registers)
+}
+
 /// Virtual memory address, which may not be valid (e.g. in canonical form) with
 /// respect to the architecture.
 #[derive(Debug, Serialize)]
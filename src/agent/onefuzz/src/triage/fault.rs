@@ -0,0 +1,408 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Analysis of the x86-64 instruction a crash faulted at: whether it read,
+//! wrote or executed the address it faulted at, and which registers the
+//! address was computed from.
+
+use iced_x86::{
+    Decoder, DecoderOptions, FlowControl, Formatter, Instruction, InstructionInfoFactory,
+    IntelFormatter, OpAccess, Register as X86Register, UsedMemory,
+};
+use serde::Serialize;
+
+use super::Register;
+
+/// The length of the longest x86-64 instruction.
+pub const MAX_INSTRUCTION_LEN: usize = 15;
+
+/// How the faulting instruction accessed the address it faulted at.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Access {
+    Read,
+    Write,
+    Execute,
+}
+
+impl Access {
+    /// The fault type of an access violation of this access, such as
+    /// `write-access-violation`.
+    pub fn fault_type(&self) -> &'static str {
+        match self {
+            Self::Read => "read-access-violation",
+            Self::Write => "write-access-violation",
+            Self::Execute => "execute-access-violation",
+        }
+    }
+}
+
+/// The instruction a crash faulted at, as far as it could be read and decoded.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct FaultingInstruction {
+    /// The instruction, such as `mov dword ptr [rax],1`, if it could be read
+    /// and decoded.
+    pub disassembly: Option<String>,
+
+    /// How the instruction accessed the address it faulted at, if known.
+    pub access: Option<Access>,
+
+    /// The registers the faulting address was computed from, such as `rax`.
+    pub address_registers: Vec<String>,
+
+    /// The value read is the target of a call or jump, as of `call [rax]`.
+    pub branch_target: bool,
+}
+
+impl FaultingInstruction {
+    /// Whether the faulting address was computed from only the stack or frame
+    /// pointers, as of a push or call, or of a local variable, as faults when
+    /// the stack is exhausted.
+    pub fn stack_access(&self) -> bool {
+        self.access.is_some()
+            && !self.address_registers.is_empty()
+            && self
+                .address_registers
+                .iter()
+                .all(|name| STACK_REGISTERS.contains(&name.as_str()))
+    }
+
+    /// The fault type of the crash, such as `write-access-violation`, or
+    /// `stack-overflow` of an access of the stack, if the access is known.
+    pub fn fault_type(&self) -> Option<&'static str> {
+        if self.stack_access() {
+            return Some("stack-overflow");
+        }
+
+        self.access.map(|access| access.fault_type())
+    }
+}
+
+const STACK_REGISTERS: &[&str] = &["rsp", "esp", "rbp", "ebp"];
+
+/// Analyze the instruction of `code`, read at `pc`, which faulted at
+/// `fault_address`, if known, of the thread with `registers`. Code which
+/// couldn't be read, or doesn't decode, leaves the access unknown, unless the
+/// fault was at `pc`, so of executing it.
+pub fn analyze(
+    code: &[u8],
+    pc: u64,
+    fault_address: Option<u64>,
+    registers: &[Register],
+) -> FaultingInstruction {
+    if fault_address == Some(pc) {
+        return FaultingInstruction {
+            access: Some(Access::Execute),
+            ..FaultingInstruction::default()
+        };
+    }
+
+    let mut decoder = Decoder::with_ip(64, code, pc, DecoderOptions::NONE);
+    if !decoder.can_decode() {
+        return FaultingInstruction::default();
+    }
+
+    let instruction = decoder.decode();
+    if instruction.is_invalid() {
+        return FaultingInstruction::default();
+    }
+
+    let mut disassembly = String::new();
+    IntelFormatter::new().format(&instruction, &mut disassembly);
+
+    let mut factory = InstructionInfoFactory::new();
+    let used: Vec<UsedMemory> = factory
+        .info(&instruction)
+        .used_memory()
+        .iter()
+        .filter(|used| access(used.access()).is_some())
+        .copied()
+        .collect();
+
+    // Of several accesses, such as of `movs`, the one of the faulting address,
+    // or else the first.
+    let faulting = used
+        .iter()
+        .find(|used| {
+            fault_address.is_some()
+                && used.virtual_address(0, |register, _, _| register_value(registers, register))
+                    == fault_address
+        })
+        .or_else(|| used.first());
+
+    let Some(faulting) = faulting else {
+        return FaultingInstruction {
+            disassembly: Some(disassembly),
+            ..FaultingInstruction::default()
+        };
+    };
+
+    let address_registers = [faulting.base(), faulting.index()]
+        .into_iter()
+        .filter(|register| *register != X86Register::None)
+        .map(register_name)
+        .collect();
+
+    let access = access(faulting.access());
+    FaultingInstruction {
+        disassembly: Some(disassembly),
+        access,
+        address_registers,
+        branch_target: access == Some(Access::Read) && is_indirect_branch(&instruction),
+    }
+}
+
+fn access(access: OpAccess) -> Option<Access> {
+    match access {
+        OpAccess::Read | OpAccess::CondRead => Some(Access::Read),
+        OpAccess::Write | OpAccess::CondWrite | OpAccess::ReadWrite | OpAccess::ReadCondWrite => {
+            Some(Access::Write)
+        }
+        _ => None,
+    }
+}
+
+fn is_indirect_branch(instruction: &Instruction) -> bool {
+    matches!(
+        instruction.flow_control(),
+        FlowControl::IndirectBranch | FlowControl::IndirectCall
+    )
+}
+
+fn register_name(register: X86Register) -> String {
+    format!("{register:?}").to_lowercase()
+}
+
+// The value of `register` of `registers`, truncated to its size, or the base
+// of a segment register, which is 0 except of `fs` and `gs`, whose bases aren't
+// captured.
+fn register_value(registers: &[Register], register: X86Register) -> Option<u64> {
+    if register.is_segment_register() {
+        return match register {
+            X86Register::FS | X86Register::GS => None,
+            _ => Some(0),
+        };
+    }
+
+    let name = register_name(register.full_register());
+    let value = registers.iter().find(|r| r.name == name)?.value.0;
+    match register.size() {
+        8 => Some(value),
+        size => Some(value & ((1 << (size * 8)) - 1)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PC: u64 = 0x5555_5555_1000;
+
+    fn registers(values: &[(&str, u64)]) -> Vec<Register> {
+        values
+            .iter()
+            .map(|(name, value)| Register::new(*name, *value))
+            .collect()
+    }
+
+    #[test]
+    fn test_analyze() {
+        struct Case {
+            name: &'static str,
+            code: &'static [u8],
+            pc: u64,
+            fault_address: Option<u64>,
+            registers: &'static [(&'static str, u64)],
+            // The start of the disassembly, of its mnemonic.
+            disassembly: Option<&'static str>,
+            access: Option<Access>,
+            address_registers: &'static [&'static str],
+            branch_target: bool,
+            fault_type: Option<&'static str>,
+        }
+
+        let cases = [
+            Case {
+                name: "write through a null pointer",
+                code: &[0xc7, 0x00, 0x01, 0x00, 0x00, 0x00],
+                pc: PC,
+                fault_address: Some(0),
+                registers: &[("rax", 0)],
+                disassembly: Some("mov"),
+                access: Some(Access::Write),
+                address_registers: &["rax"],
+                branch_target: false,
+                fault_type: Some("write-access-violation"),
+            },
+            Case {
+                name: "read through an indexed pointer",
+                code: &[0x8b, 0x04, 0x8b],
+                pc: PC,
+                fault_address: Some(0x4141_4140),
+                registers: &[("rbx", 0x4141_0000), ("rcx", 0x1050)],
+                disassembly: Some("mov"),
+                access: Some(Access::Read),
+                address_registers: &["rbx", "rcx"],
+                branch_target: false,
+                fault_type: Some("read-access-violation"),
+            },
+            Case {
+                name: "read of a call target",
+                code: &[0xff, 0x10],
+                pc: PC,
+                fault_address: Some(0x4141_4141),
+                registers: &[("rax", 0x4141_4141)],
+                disassembly: Some("call"),
+                access: Some(Access::Read),
+                address_registers: &["rax"],
+                branch_target: true,
+                fault_type: Some("read-access-violation"),
+            },
+            Case {
+                name: "read of a string copy",
+                code: &[0xf3, 0xa4],
+                pc: PC,
+                fault_address: Some(0x2000),
+                registers: &[("rsi", 0x2000), ("rdi", 0x3000), ("rcx", 8)],
+                disassembly: Some("rep movsb"),
+                access: Some(Access::Read),
+                address_registers: &["rsi"],
+                branch_target: false,
+                fault_type: Some("read-access-violation"),
+            },
+            Case {
+                name: "write of a string copy",
+                code: &[0xf3, 0xa4],
+                pc: PC,
+                fault_address: Some(0x3000),
+                registers: &[("rsi", 0x2000), ("rdi", 0x3000), ("rcx", 8)],
+                disassembly: Some("rep movsb"),
+                access: Some(Access::Write),
+                address_registers: &["rdi"],
+                branch_target: false,
+                fault_type: Some("write-access-violation"),
+            },
+            Case {
+                name: "push to an exhausted stack",
+                code: &[0x50],
+                pc: PC,
+                fault_address: Some(0x7ffc_ffff_eff8),
+                registers: &[("rsp", 0x7ffc_ffff_f000), ("rax", 0)],
+                disassembly: Some("push"),
+                access: Some(Access::Write),
+                address_registers: &["rsp"],
+                branch_target: false,
+                fault_type: Some("stack-overflow"),
+            },
+            Case {
+                name: "jump to a wild address",
+                code: &[],
+                pc: 0x4141_4141,
+                fault_address: Some(0x4141_4141),
+                registers: &[],
+                disassembly: None,
+                access: Some(Access::Execute),
+                address_registers: &[],
+                branch_target: false,
+                fault_type: Some("execute-access-violation"),
+            },
+            Case {
+                name: "unreadable code",
+                code: &[],
+                pc: PC,
+                fault_address: Some(0),
+                registers: &[],
+                disassembly: None,
+                access: None,
+                address_registers: &[],
+                branch_target: false,
+                fault_type: None,
+            },
+            Case {
+                name: "invalid instruction",
+                code: &[0xff, 0xff],
+                pc: PC,
+                fault_address: Some(0),
+                registers: &[],
+                disassembly: None,
+                access: None,
+                address_registers: &[],
+                branch_target: false,
+                fault_type: None,
+            },
+            Case {
+                name: "instruction without a memory operand",
+                code: &[0x0f, 0x0b],
+                pc: PC,
+                fault_address: None,
+                registers: &[],
+                disassembly: Some("ud2"),
+                access: None,
+                address_registers: &[],
+                branch_target: false,
+                fault_type: None,
+            },
+        ];
+
+        for case in cases {
+            let registers = registers(case.registers);
+            let analyzed = analyze(case.code, case.pc, case.fault_address, &registers);
+
+            match (&analyzed.disassembly, case.disassembly) {
+                (Some(disassembly), Some(mnemonic)) => {
+                    assert!(disassembly.starts_with(mnemonic), "{}", case.name)
+                }
+                (disassembly, mnemonic) => {
+                    assert_eq!(disassembly.as_deref(), mnemonic, "{}", case.name)
+                }
+            }
+            assert_eq!(analyzed.access, case.access, "{}", case.name);
+            assert_eq!(
+                analyzed.address_registers, case.address_registers,
+                "{}",
+                case.name
+            );
+            assert_eq!(analyzed.branch_target, case.branch_target, "{}", case.name);
+            assert_eq!(analyzed.fault_type(), case.fault_type, "{}", case.name);
+        }
+    }
+
+    #[test]
+    fn test_stack_access() {
+        let instruction = |registers: &[&str]| FaultingInstruction {
+            access: Some(Access::Read),
+            address_registers: registers.iter().map(|name| name.to_string()).collect(),
+            ..FaultingInstruction::default()
+        };
+
+        assert!(instruction(&["rsp"]).stack_access());
+        assert!(instruction(&["rbp"]).stack_access());
+        assert!(!instruction(&["rsp", "rax"]).stack_access());
+        assert!(!instruction(&["rax"]).stack_access());
+        assert!(!instruction(&[]).stack_access());
+
+        let unknown = FaultingInstruction {
+            access: None,
+            ..instruction(&["rsp"])
+        };
+        assert!(!unknown.stack_access());
+    }
+
+    #[test]
+    fn test_register_value() {
+        let registers = registers(&[("rax", 0x1122_3344_5566_7788)]);
+
+        assert_eq!(
+            register_value(&registers, X86Register::RAX),
+            Some(0x1122_3344_5566_7788)
+        );
+        assert_eq!(
+            register_value(&registers, X86Register::EAX),
+            Some(0x5566_7788)
+        );
+        assert_eq!(register_value(&registers, X86Register::AX), Some(0x7788));
+        assert_eq!(register_value(&registers, X86Register::DS), Some(0));
+        assert_eq!(register_value(&registers, X86Register::FS), None);
+        assert_eq!(register_value(&registers, X86Register::RBX), None);
+    }
+}
@@ -2,28 +2,48 @@
 // Licensed under the MIT License.
 
 use std::path::Path;
+use std::time::Duration;
 
 use anyhow::{Context, Result};
 use futures::stream::TryStreamExt;
 use reqwest::{Body, Client, Response, StatusCode, Url};
 use reqwest_retry::{
-    send_retry_reqwest, RetryCheck, SendRetry, DEFAULT_RETRY_PERIOD, MAX_RETRY_ATTEMPTS,
+    retry_transient, send_retry_reqwest_policy, RetryCheck, RetryPolicy, SendRetry,
 };
 use serde::Serialize;
 use tokio::{fs, io};
 use tokio_util::codec;
+use uuid::Uuid;
+
+use crate::blob::BlobProvenance;
+
+// Files, such as crash dumps and coverage, may take far longer to upload than
+// the request timeout of the client allows.
+const UPLOAD_TIMEOUT: Duration = Duration::from_secs(30 * 60);
 
 #[derive(Clone)]
 pub struct BlobUploader {
     client: Client,
     url: Url,
+    task_id: Option<Uuid>,
 }
 
 impl BlobUploader {
     pub fn new(url: Url) -> Self {
-        let client = Client::new();
+        let client = reqwest_retry::proxy::client();
+
+        Self {
+            client,
+            url,
+            task_id: None,
+        }
+    }
 
-        Self { client, url }
+    /// Record `task_id` in the metadata of uploaded files, along with when
+    /// each file was last written, as their [`BlobProvenance`].
+    pub fn task_id(mut self, task_id: Uuid) -> Self {
+        self.task_id = Some(task_id);
+        self
     }
 
     pub async fn upload(&mut self, file_path: impl AsRef<Path>) -> Result<Response> {
@@ -43,8 +63,25 @@ impl BlobUploader {
         };
 
         let content_length = format!("{file_len}");
-
-        let resp = send_retry_reqwest(
+        let provenance = self
+            .task_id
+            .map(|task_id| BlobProvenance {
+                task_id: Some(task_id),
+                timestamp: metadata.modified().ok(),
+            })
+            .unwrap_or_default()
+            .headers();
+
+        // An existing blob isn't replaced, so it's treated as uploaded.
+        let policy = RetryPolicy::transient().with_check_status(|status| {
+            if status == StatusCode::PRECONDITION_FAILED || status == StatusCode::CONFLICT {
+                RetryCheck::Succeed
+            } else {
+                retry_transient(status)
+            }
+        });
+
+        let resp = send_retry_reqwest_policy(
             || {
                 let file = fs::File::from_std(std::fs::File::open(file_path)?);
                 let reader = io::BufReader::new(file);
@@ -53,7 +90,7 @@ impl BlobUploader {
                     .map_ok(bytes::BytesMut::freeze)
                     .into_stream();
 
-                let request_builder = self
+                let mut request_builder = self
                     .client
                     .put(url.clone())
                     // https://learn.microsoft.com/en-us/rest/api/storageservices/put-blob-from-url#request-headers
@@ -61,19 +98,15 @@ impl BlobUploader {
                     .header("x-ms-blob-type", "BlockBlob")
                     // upload only if the the destination blob does not exist
                     .header("If-None-Match", "*")
+                    .timeout(UPLOAD_TIMEOUT)
                     .body(Body::wrap_stream(file_stream));
+                for (name, value) in &provenance {
+                    request_builder = request_builder.header(name, value);
+                }
 
                 Ok(request_builder)
             },
-            |status| {
-                if status == StatusCode::PRECONDITION_FAILED || status == StatusCode::CONFLICT {
-                    RetryCheck::Succeed
-                } else {
-                    RetryCheck::Retry
-                }
-            },
-            DEFAULT_RETRY_PERIOD,
-            MAX_RETRY_ATTEMPTS,
+            &policy,
         )
         .await
         .context("BlobUploader.upload")?;
@@ -99,7 +132,7 @@ impl BlobUploader {
             .put(url)
             .header("x-ms-blob-type", "BlockBlob")
             .json(&data)
-            .send_retry_default()
+            .send_retry_policy(&RetryPolicy::transient())
             .await
             .context("BlobUploader.upload_json")?;
 
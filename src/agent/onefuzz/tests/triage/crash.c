@@ -0,0 +1,32 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+// Dereferences a null pointer when run with an input starting with `x`, read
+// from the file of its argument, or else from its standard input. Built without
+// a sanitizer, so that its crash is only triaged by the debugger.
+
+#include <stdio.h>
+
+__attribute__((noinline)) void check(int *p, char c) {
+  if (c == 'x') {
+    *p = 1;
+  }
+}
+
+int main(int argc, char **argv) {
+  int c;
+  if (argc < 2) {
+    c = getchar();
+  } else {
+    FILE *input = fopen(argv[1], "rb");
+    if (input == NULL) {
+      return 1;
+    }
+
+    c = fgetc(input);
+    fclose(input);
+  }
+
+  check(NULL, (char)c);
+  return 0;
+}
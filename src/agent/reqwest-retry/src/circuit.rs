@@ -0,0 +1,308 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Circuit breakers, which stop the requests of a node to a server which keeps
+//! failing, so that the retries of every node don't slow its recovery.
+//!
+//! A breaker is shared by all of the requests made with a [`RetryPolicy`]
+//! which has it. Once enough consecutive attempts fail, the circuit opens, and
+//! requests wait for it to close, or fail fast with [`CircuitOpen`], if they
+//! can't wait that long. After a cooldown, a single request probes the server,
+//! closing the circuit if it succeeds, or reopening it for twice as long.
+//!
+//! [`RetryPolicy`]: crate::RetryPolicy
+
+use std::fmt;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+use onefuzz_telemetry::{event, log_events, warn, Event::circuit_breaker, EventData};
+use tokio::time::Instant;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CircuitConfig {
+    /// Consecutive failed attempts, within `window`, which open the circuit.
+    pub failure_threshold: usize,
+    pub window: Duration,
+
+    /// The first cooldown of an open circuit, doubled after each failed probe,
+    /// up to `max_cooldown`.
+    pub base_cooldown: Duration,
+    pub max_cooldown: Duration,
+
+    /// The largest fraction of each cooldown added to it at random, so that
+    /// nodes don't probe in lockstep.
+    pub jitter: f64,
+
+    /// How long to wait for a probe, before letting another request probe in
+    /// case it was abandoned.
+    pub probe_timeout: Duration,
+}
+
+impl CircuitConfig {
+    pub const DEFAULT: Self = Self {
+        failure_threshold: 5,
+        window: Duration::from_secs(60),
+        base_cooldown: Duration::from_secs(30),
+        max_cooldown: Duration::from_secs(10 * 60),
+        jitter: 0.25,
+        probe_timeout: Duration::from_secs(2 * 60),
+    };
+}
+
+impl Default for CircuitConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CircuitState {
+    /// Requests are sent.
+    Closed,
+
+    /// Requests aren't sent, until the cooldown ends.
+    Open,
+
+    /// A single request is probing the server.
+    HalfOpen,
+}
+
+impl CircuitState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Closed => "closed",
+            Self::Open => "open",
+            Self::HalfOpen => "half_open",
+        }
+    }
+}
+
+impl fmt::Display for CircuitState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A request which wasn't sent, since its circuit is open.
+#[derive(Debug, thiserror::Error)]
+#[error("circuit of {name} is open, retry in {retry_in:?}")]
+pub struct CircuitOpen {
+    pub name: &'static str,
+    pub retry_in: Duration,
+}
+
+/// Whether a request failed fast, since its circuit was open.
+pub fn is_circuit_open(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<CircuitOpen>().is_some()
+}
+
+#[derive(Clone)]
+pub struct CircuitBreaker {
+    name: &'static str,
+    config: CircuitConfig,
+    state: Arc<Mutex<State>>,
+}
+
+enum State {
+    Closed {
+        failures: usize,
+        first_failure: Option<Instant>,
+    },
+    Open {
+        until: Instant,
+        cooldown: Duration,
+    },
+    HalfOpen {
+        probe_deadline: Instant,
+        cooldown: Duration,
+    },
+}
+
+impl State {
+    fn closed() -> Self {
+        Self::Closed {
+            failures: 0,
+            first_failure: None,
+        }
+    }
+
+    fn circuit_state(&self) -> CircuitState {
+        match self {
+            Self::Closed { .. } => CircuitState::Closed,
+            Self::Open { .. } => CircuitState::Open,
+            Self::HalfOpen { .. } => CircuitState::HalfOpen,
+        }
+    }
+}
+
+impl CircuitBreaker {
+    /// A closed circuit, of the server `name`, as logged.
+    pub fn new(name: &'static str, config: CircuitConfig) -> Self {
+        Self {
+            name,
+            config,
+            state: Arc::new(Mutex::new(State::closed())),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn state(&self) -> CircuitState {
+        self.lock().circuit_state()
+    }
+
+    /// Whether a request may be sent now. Once the cooldown of an open circuit
+    /// ends, the first request to check it is the probe.
+    pub fn check(&self) -> Result<(), CircuitOpen> {
+        let now = Instant::now();
+        let mut state = self.lock();
+
+        match *state {
+            State::Closed { .. } => Ok(()),
+            State::Open { until, cooldown } => {
+                if now < until {
+                    return Err(self.open_error(until - now));
+                }
+
+                *state = State::HalfOpen {
+                    probe_deadline: now + self.config.probe_timeout,
+                    cooldown,
+                };
+                self.transitioned(CircuitState::HalfOpen, None);
+                Ok(())
+            }
+            State::HalfOpen {
+                probe_deadline,
+                cooldown,
+            } => {
+                if now < probe_deadline {
+                    return Err(self.open_error(self.config.base_cooldown));
+                }
+
+                // The probe never reported, so this request takes its place.
+                *state = State::HalfOpen {
+                    probe_deadline: now + self.config.probe_timeout,
+                    cooldown,
+                };
+                Ok(())
+            }
+        }
+    }
+
+    /// Record an attempt which reached the server, closing the circuit.
+    pub fn record_success(&self) {
+        let mut state = self.lock();
+
+        let previous = state.circuit_state();
+        *state = State::closed();
+
+        if previous != CircuitState::Closed {
+            self.transitioned(CircuitState::Closed, None);
+        }
+    }
+
+    /// Record an attempt which failed, as if the server is unavailable.
+    pub fn record_failure(&self) {
+        let now = Instant::now();
+        let mut state = self.lock();
+
+        match *state {
+            State::Closed {
+                failures,
+                first_failure,
+            } => {
+                let (failures, first_failure) = match first_failure {
+                    Some(first) if now.duration_since(first) <= self.config.window => {
+                        (failures + 1, first)
+                    }
+                    _ => (1, now),
+                };
+
+                if failures >= self.config.failure_threshold {
+                    *state = self.open(now, self.config.base_cooldown);
+                } else {
+                    *state = State::Closed {
+                        failures,
+                        first_failure: Some(first_failure),
+                    };
+                }
+            }
+            State::HalfOpen { cooldown, .. } => {
+                let cooldown = cooldown.saturating_mul(2).min(self.config.max_cooldown);
+                *state = self.open(now, cooldown);
+            }
+            // A request which was sent before the circuit opened.
+            State::Open { .. } => {}
+        }
+    }
+
+    fn open(&self, now: Instant, cooldown: Duration) -> State {
+        let jitter = rand::random::<f64>() * self.config.jitter;
+        let until = now + cooldown.mul_f64(1.0 + jitter);
+
+        self.transitioned(CircuitState::Open, Some(until - now));
+
+        State::Open { until, cooldown }
+    }
+
+    fn open_error(&self, retry_in: Duration) -> CircuitOpen {
+        CircuitOpen {
+            name: self.name,
+            retry_in,
+        }
+    }
+
+    fn transitioned(&self, state: CircuitState, cooldown: Option<Duration>) {
+        match cooldown {
+            Some(cooldown) => {
+                warn!(
+                    "circuit of {} is open, sending no requests for {:?}",
+                    self.name, cooldown
+                );
+                event!(circuit_breaker;
+                    EventData::Name = self.name,
+                    EventData::CircuitState = state.as_str(),
+                    EventData::DurationMs = cooldown.as_millis() as u64
+                );
+            }
+            None => {
+                event!(circuit_breaker;
+                    EventData::Name = self.name,
+                    EventData::CircuitState = state.as_str()
+                );
+            }
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl fmt::Debug for CircuitBreaker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CircuitBreaker")
+            .field("name", &self.name)
+            .field("state", &self.state())
+            .finish()
+    }
+}
+
+/// The circuit of the OneFuzz service, shared by the requests of the process.
+pub fn service() -> &'static CircuitBreaker {
+    static SERVICE: OnceLock<CircuitBreaker> = OnceLock::new();
+    SERVICE.get_or_init(|| CircuitBreaker::new("service", CircuitConfig::DEFAULT))
+}
+
+/// The circuit of the storage queues of the instance, shared by the requests
+/// of the process.
+pub fn storage() -> &'static CircuitBreaker {
+    static STORAGE: OnceLock<CircuitBreaker> = OnceLock::new();
+    STORAGE.get_or_init(|| CircuitBreaker::new("storage", CircuitConfig::DEFAULT))
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,186 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use anyhow::Result;
+use wiremock::matchers::path;
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use super::*;
+use crate::{RetryBudget, RetryPolicy, SendRetry};
+
+const CONFIG: CircuitConfig = CircuitConfig {
+    failure_threshold: 3,
+    window: Duration::from_secs(60),
+    base_cooldown: Duration::from_secs(10),
+    max_cooldown: Duration::from_secs(25),
+    jitter: 0.0,
+    probe_timeout: Duration::from_secs(120),
+};
+
+fn secs(secs: u64) -> Duration {
+    Duration::from_secs(secs)
+}
+
+// The outcome of each request, as of a client which fails as scripted.
+fn run(circuit: &CircuitBreaker, script: &[bool]) {
+    for &succeeds in script {
+        circuit.check().expect("closed circuit");
+        if succeeds {
+            circuit.record_success();
+        } else {
+            circuit.record_failure();
+        }
+    }
+}
+
+fn retry_in(circuit: &CircuitBreaker) -> Duration {
+    circuit.check().err().expect("open circuit").retry_in
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_transitions() {
+    let circuit = CircuitBreaker::new("test", CONFIG);
+
+    // A success resets the consecutive failures.
+    run(&circuit, &[false, false, true, false, false]);
+    assert_eq!(circuit.state(), CircuitState::Closed);
+
+    run(&circuit, &[false]);
+    assert_eq!(circuit.state(), CircuitState::Open);
+    assert_eq!(retry_in(&circuit), secs(10));
+
+    tokio::time::advance(secs(4)).await;
+    assert_eq!(retry_in(&circuit), secs(6));
+
+    // Once cooled down, only the probe is let through.
+    tokio::time::advance(secs(6)).await;
+    assert!(circuit.check().is_ok());
+    assert_eq!(circuit.state(), CircuitState::HalfOpen);
+    assert!(circuit.check().is_err());
+
+    circuit.record_success();
+    assert_eq!(circuit.state(), CircuitState::Closed);
+    assert!(circuit.check().is_ok());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_failed_probes_grow_the_cooldown() {
+    let circuit = CircuitBreaker::new("test", CONFIG);
+    run(&circuit, &[false, false, false]);
+    assert_eq!(retry_in(&circuit), secs(10));
+
+    for cooldown in [20, 25, 25] {
+        tokio::time::advance(retry_in(&circuit)).await;
+        circuit.check().expect("probe");
+        circuit.record_failure();
+
+        assert_eq!(circuit.state(), CircuitState::Open);
+        assert_eq!(retry_in(&circuit), secs(cooldown));
+    }
+
+    // A successful probe resets the cooldown.
+    tokio::time::advance(secs(25)).await;
+    circuit.check().expect("probe");
+    circuit.record_success();
+    run(&circuit, &[false, false, false]);
+    assert_eq!(retry_in(&circuit), secs(10));
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_failures_outside_the_window() {
+    let circuit = CircuitBreaker::new("test", CONFIG);
+
+    run(&circuit, &[false, false]);
+    tokio::time::advance(secs(61)).await;
+    run(&circuit, &[false, false]);
+    assert_eq!(circuit.state(), CircuitState::Closed);
+
+    run(&circuit, &[false]);
+    assert_eq!(circuit.state(), CircuitState::Open);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_abandoned_probes_are_replaced() {
+    let circuit = CircuitBreaker::new("test", CONFIG);
+    run(&circuit, &[false, false, false]);
+
+    tokio::time::advance(secs(10)).await;
+    circuit.check().expect("probe");
+    assert!(circuit.check().is_err());
+
+    tokio::time::advance(CONFIG.probe_timeout).await;
+    circuit.check().expect("another probe");
+    assert_eq!(circuit.state(), CircuitState::HalfOpen);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_jitter_is_bounded() {
+    let config = CircuitConfig {
+        jitter: 0.5,
+        ..CONFIG
+    };
+
+    for _ in 0..20 {
+        let circuit = CircuitBreaker::new("test", config);
+        run(&circuit, &[false, false, false]);
+
+        let retry_in = retry_in(&circuit);
+        assert!(retry_in >= secs(10) && retry_in <= secs(15), "{retry_in:?}");
+    }
+}
+
+#[tokio::test]
+async fn test_open_circuit_fails_fast() -> Result<()> {
+    let server = MockServer::start().await;
+    Mock::given(path("/503"))
+        .respond_with(ResponseTemplate::new(503))
+        .expect(3)
+        .mount(&server)
+        .await;
+    Mock::given(path("/404"))
+        .respond_with(ResponseTemplate::new(404))
+        .mount(&server)
+        .await;
+
+    let circuit = CircuitBreaker::new("test", CONFIG);
+    let client = reqwest::Client::new();
+
+    // Client errors show that the server is up.
+    let policy = RetryPolicy::transient().with_circuit_breaker(circuit.clone());
+    let response = client
+        .get(format!("{}/404", server.uri()))
+        .send_retry_policy(&policy)
+        .await;
+    assert!(response.is_err());
+    assert_eq!(circuit.state(), CircuitState::Closed);
+
+    // A budget which can't wait out the cooldown.
+    let policy = policy.with_budget(RetryBudget {
+        max_attempts: 10,
+        max_elapsed: secs(5),
+        base_delay: Duration::from_millis(1),
+        max_delay: Duration::from_millis(1),
+    });
+
+    // The failures of one request open the circuit, and it fails fast, rather
+    // than making the rest of its attempts.
+    let err = client
+        .get(format!("{}/503", server.uri()))
+        .send_retry_policy(&policy)
+        .await
+        .err()
+        .expect("error");
+    assert_eq!(circuit.state(), CircuitState::Open);
+    assert!(is_circuit_open(&err), "{err:?}");
+
+    // As do the other requests which share the circuit, without being sent.
+    let err = client
+        .get(format!("{}/503", server.uri()))
+        .send_retry_policy(&policy)
+        .await
+        .err()
+        .expect("error");
+    assert!(is_circuit_open(&err), "{err:?}");
+
+    Ok(())
+}
@@ -1,15 +1,19 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, Result};
 use async_trait::async_trait;
-use backoff::{self, future::retry_notify, ExponentialBackoff};
 use onefuzz_telemetry::debug;
 use reqwest::{Response, StatusCode};
-use std::{
-    sync::atomic::{AtomicUsize, Ordering},
-    time::Duration,
-};
+use std::time::Duration;
+use tokio::time::Instant;
+
+pub mod circuit;
+mod policy;
+pub mod proxy;
+pub mod timeout;
+
+pub use policy::{retry_transient, RetryBudget, RetryPolicy};
 
 pub const DEFAULT_RETRY_PERIOD: Duration = Duration::from_secs(5);
 pub const MAX_RETRY_ATTEMPTS: usize = 5;
@@ -30,6 +34,8 @@ pub enum ReqwestRetryError {
     },
     #[error("request failed to be sent")]
     SendError { source: anyhow::Error },
+    #[error("request timed out")]
+    TimedOut { source: anyhow::Error },
 }
 
 impl ReqwestRetryError {
@@ -44,6 +50,10 @@ impl ReqwestRetryError {
     fn send_error(source: anyhow::Error) -> Self {
         Self::SendError { source }
     }
+
+    fn timed_out(source: anyhow::Error) -> Self {
+        Self::TimedOut { source }
+    }
 }
 
 fn always_retry(_: StatusCode) -> RetryCheck {
@@ -55,13 +65,7 @@ pub async fn send_retry_reqwest_default<
 >(
     build_request: F,
 ) -> Result<Response> {
-    send_retry_reqwest(
-        build_request,
-        |_| RetryCheck::Retry,
-        DEFAULT_RETRY_PERIOD,
-        MAX_RETRY_ATTEMPTS,
-    )
-    .await
+    send_retry_reqwest_policy(build_request, &RetryPolicy::default()).await
 }
 
 pub async fn send_retry_reqwest<F, R>(
@@ -72,86 +76,150 @@ pub async fn send_retry_reqwest<F, R>(
 ) -> Result<Response>
 where
     F: Fn() -> Result<reqwest::RequestBuilder> + Send + Sync,
-    R: Fn(StatusCode) -> RetryCheck + Send + Sync,
+    R: Fn(StatusCode) -> RetryCheck + Send + Sync + 'static,
 {
-    let counter = AtomicUsize::new(0);
-    let op = || async {
-        let attempt_count = counter.fetch_add(1, Ordering::SeqCst);
-        let request = build_request()
-            .map_err(|err| backoff::Error::Permanent(ReqwestRetryError::send_error(err)))?;
-        let result = request
-            .send()
-            .await
-            .with_context(|| format!("request attempt {} failed", attempt_count + 1));
-        match result {
-            Err(x) => {
-                if attempt_count >= max_retry {
-                    Err(backoff::Error::Permanent(ReqwestRetryError::send_error(x)))
-                } else {
-                    Err(backoff::Error::transient(ReqwestRetryError::send_error(x)))
+    let policy = RetryPolicy::new()
+        .with_check_status(check_status)
+        .with_retry_period(retry_period)
+        .with_max_retry(max_retry);
+
+    send_retry_reqwest_policy(build_request, &policy).await
+}
+
+pub async fn send_retry_reqwest_policy<F>(
+    build_request: F,
+    policy: &RetryPolicy,
+) -> Result<Response>
+where
+    F: Fn() -> Result<reqwest::RequestBuilder> + Send + Sync,
+{
+    let budget = policy.budget();
+    let start = Instant::now();
+    let mut attempts = 0;
+
+    loop {
+        if let Some(circuit) = policy.circuit() {
+            if let Err(open) = circuit.check() {
+                // Wait out the cooldown, if the budget allows, rather than fail.
+                if start.elapsed().saturating_add(open.retry_in) > budget.max_elapsed {
+                    return Err(open.into());
                 }
+
+                debug!("{}, waiting", open);
+                tokio::time::sleep(open.retry_in).await;
+                continue;
             }
-            Ok(x) => {
-                if x.status().is_success() {
-                    Ok(x)
-                } else {
-                    let status = x.status();
-                    let result = check_status(status);
-                    let url = x.url().clone();
-
-                    match result {
-                        RetryCheck::Succeed => Ok(x),
-                        RetryCheck::Fail => {
-                            let content = x.text().await.unwrap_or_else(|_| "".to_string());
-                            let e = anyhow!(
-                                "request attempt {} failed with status code {} and content {}",
-                                attempt_count + 1,
-                                status,
-                                content
-                            );
-
-                            Err(backoff::Error::Permanent(
-                                ReqwestRetryError::response_error(status, url, e),
-                            ))
-                        }
-                        RetryCheck::Retry => {
-                            let content = x.text().await.unwrap_or_else(|_| "".to_string());
-                            let e = anyhow!(
-                                "request attempt {} failed with status code {} and content {}",
-                                attempt_count + 1,
-                                status,
-                                content
-                            );
-
-                            if attempt_count >= max_retry {
-                                Err(backoff::Error::Permanent(
-                                    ReqwestRetryError::response_error(status, url, e),
-                                ))
-                            } else {
-                                Err(backoff::Error::transient(
-                                    ReqwestRetryError::response_error(status, url, e),
-                                ))
-                            }
-                        }
+        }
+
+        let (err, retry_after) = match send_attempt(&build_request, policy, attempts).await {
+            Ok(response) => return Ok(response),
+            Err(backoff::Error::Permanent(err)) => return Err(err.into()),
+            Err(backoff::Error::Transient { err, retry_after }) => (err, retry_after),
+        };
+        attempts += 1;
+
+        // The elapsed time includes that of the attempt, and of prior waits.
+        let Some(delay) = budget.next_delay(attempts, start.elapsed(), retry_after) else {
+            return Err(err.into());
+        };
+
+        debug!(
+            "request attempt failed, retrying after {:?}: {:?}",
+            delay, err
+        );
+        tokio::time::sleep(delay).await;
+    }
+}
+
+// Make attempt `attempt_count + 1` of the request, which failed permanently,
+// or may be retried, after the wait requested by the server, if any.
+async fn send_attempt<F>(
+    build_request: &F,
+    policy: &RetryPolicy,
+    attempt_count: usize,
+) -> Result<Response, backoff::Error<ReqwestRetryError>>
+where
+    F: Fn() -> Result<reqwest::RequestBuilder> + Send + Sync,
+{
+    let request = build_request()
+        .map_err(|err| backoff::Error::Permanent(ReqwestRetryError::send_error(err)))?;
+    let result = request.send().await;
+
+    // Only failures which suggest the server is unavailable count against its
+    // circuit. Any other response shows that it's up.
+    if let Some(circuit) = policy.circuit() {
+        let unavailable = match &result {
+            Ok(response) => matches!(retry_transient(response.status()), RetryCheck::Retry),
+            Err(_) => true,
+        };
+        if unavailable {
+            circuit.record_failure();
+        } else {
+            circuit.record_success();
+        }
+    }
+
+    match result {
+        Err(err) => {
+            let retry = policy.check_send_error(&err);
+            let timed_out = err.is_timeout();
+            let x = anyhow::Error::new(err)
+                .context(format!("request attempt {} failed", attempt_count + 1));
+            let x = if timed_out {
+                ReqwestRetryError::timed_out(x)
+            } else {
+                ReqwestRetryError::send_error(x)
+            };
+            if retry {
+                Err(backoff::Error::transient(x))
+            } else {
+                Err(backoff::Error::Permanent(x))
+            }
+        }
+        Ok(x) => {
+            if x.status().is_success() {
+                return Ok(x);
+            }
+
+            let status = x.status();
+            let result = policy.check_status(status);
+            let url = x.url().clone();
+
+            match result {
+                RetryCheck::Succeed => Ok(x),
+                RetryCheck::Fail => {
+                    let content = x.text().await.unwrap_or_else(|_| "".to_string());
+                    let e = anyhow!(
+                        "request attempt {} failed with status code {} and content {}",
+                        attempt_count + 1,
+                        status,
+                        content
+                    );
+
+                    Err(backoff::Error::Permanent(
+                        ReqwestRetryError::response_error(status, url, e),
+                    ))
+                }
+                RetryCheck::Retry => {
+                    // Read before the body consumes the response.
+                    let retry_after = policy.retry_after(&x);
+
+                    let content = x.text().await.unwrap_or_else(|_| "".to_string());
+                    let e = anyhow!(
+                        "request attempt {} failed with status code {} and content {}",
+                        attempt_count + 1,
+                        status,
+                        content
+                    );
+                    let err = ReqwestRetryError::response_error(status, url, e);
+
+                    match retry_after {
+                        Some(retry_after) => Err(backoff::Error::retry_after(err, retry_after)),
+                        None => Err(backoff::Error::transient(err)),
                     }
                 }
             }
         }
-    };
-    let result = retry_notify(
-        ExponentialBackoff {
-            current_interval: retry_period,
-            initial_interval: retry_period,
-            ..ExponentialBackoff::default()
-        },
-        op,
-        |err: ReqwestRetryError, dur| debug!("request attempt failed after {:?}: {:?}", dur, err),
-    )
-    .await;
-
-    match result {
-        Ok(response) => Ok(response),
-        Err(error) => Err(error.into()),
     }
 }
 
@@ -164,8 +232,9 @@ pub trait SendRetry {
         max_retry: usize,
     ) -> Result<Response>
     where
-        R: Fn(StatusCode) -> RetryCheck + Send + Sync;
+        R: Fn(StatusCode) -> RetryCheck + Send + Sync + 'static;
     async fn send_retry_default(self) -> Result<Response>;
+    async fn send_retry_policy(self, policy: &RetryPolicy) -> Result<Response>;
 }
 
 #[async_trait]
@@ -182,17 +251,24 @@ impl SendRetry for reqwest::RequestBuilder {
         max_retry: usize,
     ) -> Result<Response>
     where
-        R: Fn(StatusCode) -> RetryCheck + Send + Sync,
+        R: Fn(StatusCode) -> RetryCheck + Send + Sync + 'static,
     {
-        let result = send_retry_reqwest(
+        let policy = RetryPolicy::new()
+            .with_check_status(check_status)
+            .with_retry_period(retry_period)
+            .with_max_retry(max_retry);
+
+        self.send_retry_policy(&policy).await
+    }
+
+    async fn send_retry_policy(self, policy: &RetryPolicy) -> Result<Response> {
+        let result = send_retry_reqwest_policy(
             || {
                 self.try_clone().ok_or_else(|| {
                     anyhow::Error::msg("This request cannot be retried because it cannot be cloned")
                 })
             },
-            check_status,
-            retry_period,
-            max_retry,
+            policy,
         )
         .await?;
 
@@ -200,6 +276,15 @@ impl SendRetry for reqwest::RequestBuilder {
     }
 }
 
+/// Whether the last attempt of a request failed because it timed out, rather
+/// than the server responding with an error.
+pub fn is_timeout(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<ReqwestRetryError>(),
+        Some(ReqwestRetryError::TimedOut { .. })
+    )
+}
+
 pub fn is_auth_failure(response: &Result<Response>) -> bool {
     // Check both cases to support `error_for_status()`.
     match response {
@@ -375,4 +460,147 @@ mod test {
 
         Ok(())
     }
+
+    fn fast_transient() -> RetryPolicy {
+        RetryPolicy::transient()
+            .with_retry_period(Duration::from_millis(1))
+            .with_max_retry(2)
+    }
+
+    #[tokio::test]
+    async fn retry_after_is_honored() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(path("/throttled"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(path("/throttled"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let start = std::time::Instant::now();
+        reqwest::Client::new()
+            .get(format!("{}/throttled", &server.uri()))
+            .send_retry_policy(&fast_transient())
+            .await?
+            .error_for_status()?;
+        assert!(start.elapsed() >= Duration::from_secs(1));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn retry_after_is_capped() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(path("/throttled"))
+            .respond_with(ResponseTemplate::new(503).insert_header("Retry-After", "3600"))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(path("/throttled"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+
+        let policy = fast_transient().honor_retry_after(Duration::from_millis(10));
+        let start = std::time::Instant::now();
+        reqwest::Client::new()
+            .get(format!("{}/throttled", &server.uri()))
+            .send_retry_policy(&policy)
+            .await?
+            .error_for_status()?;
+        assert!(start.elapsed() < Duration::from_secs(10));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn retry_non_retryable_status_fails_fast() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(path("/403"))
+            .respond_with(ResponseTemplate::new(403))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let resp = reqwest::Client::new()
+            .get(format!("{}/403", &server.uri()))
+            .send_retry_policy(&fast_transient())
+            .await;
+
+        let as_text = format!("{resp:?}");
+        assert!(as_text.contains("request attempt 1 failed"), "{}", as_text);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn retry_policy_exhausted() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(path("/503"))
+            .respond_with(ResponseTemplate::new(503))
+            .expect(3)
+            .mount(&server)
+            .await;
+
+        let resp = reqwest::Client::new()
+            .get(format!("{}/503", &server.uri()))
+            .send_retry_policy(&fast_transient())
+            .await;
+
+        let as_text = format!("{resp:?}");
+        assert!(as_text.contains("request attempt 3 failed"), "{}", as_text);
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retry_budget_max_elapsed() -> Result<()> {
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")?.local_addr()?;
+        let budget = RetryBudget {
+            max_attempts: 100,
+            max_elapsed: Duration::from_secs(60),
+            base_delay: Duration::from_secs(10),
+            max_delay: Duration::from_secs(10),
+        };
+
+        // The clock only advances while waiting between attempts.
+        let start = Instant::now();
+        let resp = reqwest::Client::new()
+            .get(format!("http://{addr}/"))
+            .send_retry_policy(&RetryPolicy::new().with_budget(budget))
+            .await;
+
+        let as_text = format!("{resp:?}");
+        assert!(as_text.contains("request attempt 7 failed"), "{}", as_text);
+        assert_eq!(start.elapsed(), Duration::from_secs(60));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn retry_send_errors() -> Result<()> {
+        // Nothing listens on the port once the listener is dropped.
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")?.local_addr()?;
+        let url = format!("http://{addr}/");
+
+        let resp = reqwest::Client::new()
+            .get(&url)
+            .send_retry_policy(&fast_transient())
+            .await;
+        let as_text = format!("{resp:?}");
+        assert!(as_text.contains("request attempt 3 failed"), "{}", as_text);
+
+        let policy = fast_transient().with_check_send_error(|_| false);
+        let resp = reqwest::Client::new()
+            .get(&url)
+            .send_retry_policy(&policy)
+            .await;
+        let as_text = format!("{resp:?}");
+        assert!(as_text.contains("request attempt 1 failed"), "{}", as_text);
+
+        Ok(())
+    }
 }
@@ -0,0 +1,330 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use reqwest::{header::RETRY_AFTER, Response, StatusCode};
+
+use crate::circuit::CircuitBreaker;
+use crate::{RetryCheck, DEFAULT_RETRY_PERIOD, MAX_RETRY_ATTEMPTS};
+
+/// How many attempts of a request may be made, over how long.
+///
+/// Waits between attempts double from `base_delay`, up to `max_delay`, unless
+/// a `Retry-After` wait is honored. Either way, no attempt is made once
+/// `max_elapsed` would be exceeded by waiting for it.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RetryBudget {
+    /// The most attempts, including the first.
+    pub max_attempts: usize,
+    pub max_elapsed: Duration,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryBudget {
+    /// For requests which are soon made again anyway, such as heartbeats.
+    pub const FAST: Self = Self {
+        max_attempts: 3,
+        max_elapsed: Duration::from_secs(30),
+        base_delay: Duration::from_secs(1),
+        max_delay: Duration::from_secs(5),
+    };
+
+    /// The budget of `send_retry_default()`.
+    pub const STANDARD: Self = Self {
+        max_attempts: MAX_RETRY_ATTEMPTS + 1,
+        max_elapsed: Duration::from_secs(15 * 60),
+        base_delay: DEFAULT_RETRY_PERIOD,
+        max_delay: Duration::from_secs(60),
+    };
+
+    /// For requests whose loss can't be recovered from, such as the final
+    /// events of a node or task.
+    pub const CRITICAL: Self = Self {
+        max_attempts: 30,
+        max_elapsed: Duration::from_secs(30 * 60),
+        base_delay: DEFAULT_RETRY_PERIOD,
+        max_delay: Duration::from_secs(2 * 60),
+    };
+
+    /// The wait before the next attempt, after `attempts` have failed over
+    /// `elapsed`, or `None` if the budget is spent. `retry_after` is the wait
+    /// requested by the server, if honored.
+    pub fn next_delay(
+        &self,
+        attempts: usize,
+        elapsed: Duration,
+        retry_after: Option<Duration>,
+    ) -> Option<Duration> {
+        if attempts >= self.max_attempts {
+            return None;
+        }
+
+        let delay = retry_after.unwrap_or_else(|| self.backoff(attempts));
+        if elapsed.saturating_add(delay) > self.max_elapsed {
+            return None;
+        }
+
+        Some(delay)
+    }
+
+    // The wait after the failure of attempt `attempts`, counting from 1.
+    fn backoff(&self, attempts: usize) -> Duration {
+        let exponent = u32::try_from(attempts.saturating_sub(1)).unwrap_or(u32::MAX);
+        let factor = 1u32.checked_shl(exponent).unwrap_or(u32::MAX);
+        self.base_delay.saturating_mul(factor).min(self.max_delay)
+    }
+}
+
+impl Default for RetryBudget {
+    fn default() -> Self {
+        Self::STANDARD
+    }
+}
+
+// Longest wait, requested by a `Retry-After` header, which is honored by
+// `RetryPolicy::transient()`.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(5 * 60);
+
+/// Which failed requests are retried, and how long to wait between attempts.
+///
+/// The default policy retries every failure, within the standard budget, as
+/// `send_retry_default()` always has.
+#[derive(Clone)]
+pub struct RetryPolicy {
+    check_status: Arc<dyn Fn(StatusCode) -> RetryCheck + Send + Sync>,
+    check_send_error: Arc<dyn Fn(&reqwest::Error) -> bool + Send + Sync>,
+    budget: RetryBudget,
+    max_retry_after: Option<Duration>,
+    circuit: Option<CircuitBreaker>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            check_status: Arc::new(|_| RetryCheck::Retry),
+            check_send_error: Arc::new(|_| true),
+            budget: RetryBudget::STANDARD,
+            max_retry_after: None,
+            circuit: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Retry only transient failures: responses of `retry_transient()`, and
+    /// requests which couldn't connect or timed out, waiting as requested by
+    /// `Retry-After`, for up to 5 minutes.
+    pub fn transient() -> Self {
+        Self::default()
+            .with_check_status(retry_transient)
+            .with_check_send_error(|err| err.is_connect() || err.is_timeout())
+            .honor_retry_after(MAX_RETRY_AFTER)
+    }
+
+    /// Decide whether to retry, fail, or succeed on an unsuccessful status.
+    pub fn with_check_status(
+        mut self,
+        check_status: impl Fn(StatusCode) -> RetryCheck + Send + Sync + 'static,
+    ) -> Self {
+        self.check_status = Arc::new(check_status);
+        self
+    }
+
+    /// Decide whether to retry a request which couldn't be sent, or whose
+    /// response couldn't be received.
+    pub fn with_check_send_error(
+        mut self,
+        check_send_error: impl Fn(&reqwest::Error) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.check_send_error = Arc::new(check_send_error);
+        self
+    }
+
+    pub fn with_budget(mut self, budget: RetryBudget) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    /// The initial wait between attempts, which then backs off exponentially.
+    pub fn with_retry_period(mut self, retry_period: Duration) -> Self {
+        self.budget.base_delay = retry_period;
+        self
+    }
+
+    /// The most attempts after the first, of any failure.
+    pub fn with_max_retry(mut self, max_retry: usize) -> Self {
+        self.budget.max_attempts = max_retry.saturating_add(1);
+        self
+    }
+
+    /// Wait as long as the `Retry-After` header of a retried response asks,
+    /// instead of backing off, for up to `max`.
+    pub fn honor_retry_after(mut self, max: Duration) -> Self {
+        self.max_retry_after = Some(max);
+        self
+    }
+
+    /// Share the circuit of `circuit` with the other requests which have it.
+    /// While it's open, attempts wait for it to close, if the budget allows,
+    /// or fail fast.
+    pub fn with_circuit_breaker(mut self, circuit: CircuitBreaker) -> Self {
+        self.circuit = Some(circuit);
+        self
+    }
+
+    pub fn budget(&self) -> RetryBudget {
+        self.budget
+    }
+
+    pub(crate) fn circuit(&self) -> Option<&CircuitBreaker> {
+        self.circuit.as_ref()
+    }
+
+    pub(crate) fn check_status(&self, status: StatusCode) -> RetryCheck {
+        (self.check_status)(status)
+    }
+
+    pub(crate) fn check_send_error(&self, err: &reqwest::Error) -> bool {
+        (self.check_send_error)(err)
+    }
+
+    // The wait requested by `response`, if it's honored.
+    pub(crate) fn retry_after(&self, response: &Response) -> Option<Duration> {
+        let max = self.max_retry_after?;
+        let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?;
+        Some(parse_retry_after(value, SystemTime::now())?.min(max))
+    }
+}
+
+/// Retry statuses of failures which may not recur: timeouts, throttling, and
+/// server errors other than `501 Not Implemented`. Fail on any other.
+pub fn retry_transient(status: StatusCode) -> RetryCheck {
+    match status {
+        StatusCode::REQUEST_TIMEOUT
+        | StatusCode::TOO_MANY_REQUESTS
+        | StatusCode::INTERNAL_SERVER_ERROR
+        | StatusCode::BAD_GATEWAY
+        | StatusCode::SERVICE_UNAVAILABLE
+        | StatusCode::GATEWAY_TIMEOUT => RetryCheck::Retry,
+        _ => RetryCheck::Fail,
+    }
+}
+
+// A `Retry-After` value, either seconds or an HTTP date, as the wait from `now`.
+fn parse_retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    let value = value.trim();
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = httpdate::parse_http_date(value).ok()?;
+
+    // A date in the past asks to retry immediately.
+    Some(date.duration_since(now).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_retry_after() {
+        let now = httpdate::parse_http_date("Wed, 21 Oct 2015 07:28:00 GMT").unwrap();
+
+        assert_eq!(
+            parse_retry_after("120", now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(parse_retry_after(" 0 ", now), Some(Duration::from_secs(0)));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:30 GMT", now),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:27:00 GMT", now),
+            Some(Duration::ZERO)
+        );
+
+        assert_eq!(parse_retry_after("-1", now), None);
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn test_budget_backoff() {
+        let budget = RetryBudget {
+            max_attempts: 10,
+            max_elapsed: secs(3600),
+            base_delay: secs(2),
+            max_delay: secs(20),
+        };
+
+        let delays: Vec<_> = (1..10)
+            .map(|attempts| budget.next_delay(attempts, Duration::ZERO, None))
+            .collect();
+        assert_eq!(
+            delays,
+            [2, 4, 8, 16, 20, 20, 20, 20, 20].map(|delay| Some(secs(delay)))
+        );
+
+        // Spent once the last attempt fails.
+        assert_eq!(budget.next_delay(10, Duration::ZERO, None), None);
+
+        // Extreme attempt counts saturate, rather than overflow.
+        let budget = RetryBudget {
+            max_attempts: usize::MAX,
+            ..budget
+        };
+        assert_eq!(budget.next_delay(100, Duration::ZERO, None), Some(secs(20)));
+    }
+
+    #[test]
+    fn test_budget_max_elapsed() {
+        let budget = RetryBudget {
+            max_attempts: 100,
+            max_elapsed: secs(60),
+            base_delay: secs(10),
+            max_delay: secs(10),
+        };
+
+        assert_eq!(budget.next_delay(5, secs(50), None), Some(secs(10)));
+        assert_eq!(budget.next_delay(6, secs(51), None), None);
+
+        // Waits requested by the server count against the budget, and aren't
+        // limited by the longest backoff.
+        assert_eq!(
+            budget.next_delay(1, secs(0), Some(secs(45))),
+            Some(secs(45))
+        );
+        assert_eq!(budget.next_delay(2, secs(45), Some(secs(30))), None);
+        assert_eq!(budget.next_delay(2, secs(45), Some(secs(0))), Some(secs(0)));
+    }
+
+    #[test]
+    fn test_budget_presets() {
+        // A heartbeat gives up well before the next one is due.
+        let fast = RetryBudget::FAST;
+        let total: Duration = (1..fast.max_attempts)
+            .map(|attempts| fast.next_delay(attempts, Duration::ZERO, None).unwrap())
+            .sum();
+        assert!(total <= fast.max_elapsed);
+        assert!(fast.max_elapsed < RetryBudget::STANDARD.max_elapsed);
+
+        assert!(RetryBudget::STANDARD.max_elapsed < RetryBudget::CRITICAL.max_elapsed);
+        assert!(RetryBudget::STANDARD.max_attempts < RetryBudget::CRITICAL.max_attempts);
+
+        assert_eq!(RetryPolicy::default().budget(), RetryBudget::STANDARD);
+        assert_eq!(RetryPolicy::transient().budget(), RetryBudget::STANDARD);
+    }
+}
@@ -0,0 +1,208 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! The proxy of outbound HTTP requests, set once per process and applied to
+//! each client built by [`client`] or [`client_builder`], along with the
+//! [timeouts](crate::timeout) of the process.
+//!
+//! Without a proxy, clients use the proxy of the standard environment
+//! variables, if any, as `reqwest` does by default.
+
+use std::fmt;
+use std::sync::RwLock;
+
+use anyhow::{anyhow, bail, Context, Result};
+use percent_encoding::percent_decode_str;
+use reqwest::{NoProxy, Url};
+
+/// The address of the Azure Instance Metadata Service, which is link-local, so
+/// is never proxied.
+pub const IMDS_HOST: &str = "169.254.169.254";
+
+// Read in order, by `ProxySettings::from_env()`, and set, by `env_vars()`.
+const PROXY_ENV: [&str; 4] = ["HTTPS_PROXY", "https_proxy", "HTTP_PROXY", "http_proxy"];
+const NO_PROXY_ENV: [&str; 2] = ["NO_PROXY", "no_proxy"];
+
+static PROXY: RwLock<Option<ProxySettings>> = RwLock::new(None);
+
+#[derive(Clone, Eq, PartialEq)]
+pub struct ProxySettings {
+    url: Url,
+    credentials: Option<(String, String)>,
+    no_proxy: Vec<String>,
+}
+
+impl ProxySettings {
+    /// A proxy of both HTTP and HTTPS requests. Basic credentials, if any, are
+    /// taken from the userinfo of `url`.
+    pub fn new(mut url: Url) -> Result<Self> {
+        if !matches!(url.scheme(), "http" | "https") {
+            bail!("invalid proxy URL scheme: {}", url.scheme());
+        }
+
+        let credentials = if url.username().is_empty() {
+            None
+        } else {
+            let username = percent_decode_str(url.username()).decode_utf8()?;
+            let password = percent_decode_str(url.password().unwrap_or_default()).decode_utf8()?;
+            Some((username.into_owned(), password.into_owned()))
+        };
+        url.set_username("")
+            .and_then(|_| url.set_password(None))
+            .map_err(|_| anyhow!("invalid proxy URL"))?;
+
+        Ok(Self {
+            url,
+            credentials,
+            no_proxy: vec![],
+        })
+    }
+
+    /// The proxy of the standard environment variables, such as `HTTPS_PROXY`
+    /// and `NO_PROXY`, if set.
+    pub fn from_env() -> Result<Option<Self>> {
+        let Some((name, url)) = first_env(&PROXY_ENV) else {
+            return Ok(None);
+        };
+        let url = Url::parse(&url).with_context(|| format!("invalid {name}"))?;
+
+        let mut settings = Self::new(url).with_context(|| format!("invalid {name}"))?;
+        if let Some((_, no_proxy)) = first_env(&NO_PROXY_ENV) {
+            settings = settings.with_no_proxy(no_proxy.split(','));
+        }
+
+        Ok(Some(settings))
+    }
+
+    /// Authenticate to the proxy with basic credentials.
+    pub fn with_basic_auth(mut self, username: String, password: String) -> Self {
+        self.credentials = Some((username, password));
+        self
+    }
+
+    /// Bypass the proxy for these hosts, domains (matching their subdomains),
+    /// IP addresses or CIDR ranges, in addition to IMDS.
+    pub fn with_no_proxy<S: AsRef<str>>(mut self, hosts: impl IntoIterator<Item = S>) -> Self {
+        let hosts = hosts
+            .into_iter()
+            .map(|host| host.as_ref().trim().to_owned())
+            .filter(|host| !host.is_empty());
+        self.no_proxy.extend(hosts);
+        self
+    }
+
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// The hosts which bypass the proxy, as a `NO_PROXY`-style list.
+    pub fn no_proxy(&self) -> String {
+        let mut hosts = vec![IMDS_HOST];
+        hosts.extend(
+            self.no_proxy
+                .iter()
+                .map(String::as_str)
+                .filter(|host| *host != IMDS_HOST),
+        );
+        hosts.join(",")
+    }
+
+    /// The environment variables of the proxy, for child processes, such as
+    /// azcopy, which don't share the clients of this process.
+    pub fn env_vars(&self) -> Result<Vec<(&'static str, String)>> {
+        let mut url = self.url.clone();
+        if let Some((username, password)) = &self.credentials {
+            url.set_username(username)
+                .and_then(|_| url.set_password(Some(password)))
+                .map_err(|_| anyhow!("invalid proxy URL"))?;
+        }
+
+        let mut vars: Vec<_> = PROXY_ENV
+            .iter()
+            .map(|name| (*name, url.to_string()))
+            .collect();
+        let no_proxy = self.no_proxy();
+        vars.extend(NO_PROXY_ENV.iter().map(|name| (*name, no_proxy.clone())));
+
+        Ok(vars)
+    }
+
+    fn reqwest_proxy(&self) -> Result<reqwest::Proxy> {
+        let mut proxy = reqwest::Proxy::all(self.url.clone())?;
+        if let Some((username, password)) = &self.credentials {
+            proxy = proxy.basic_auth(username, password);
+        }
+        Ok(proxy.no_proxy(NoProxy::from_string(&self.no_proxy())))
+    }
+}
+
+// Credentials of the proxy are redacted.
+impl fmt::Debug for ProxySettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProxySettings")
+            .field("url", &self.url.as_str())
+            .field(
+                "username",
+                &self.credentials.as_ref().map(|(username, _)| username),
+            )
+            .field("no_proxy", &self.no_proxy)
+            .finish()
+    }
+}
+
+fn first_env(names: &[&'static str]) -> Option<(&'static str, String)> {
+    names.iter().find_map(|name| match std::env::var(name) {
+        Ok(value) if !value.trim().is_empty() => Some((*name, value)),
+        _ => None,
+    })
+}
+
+/// Proxy the requests of all clients built from now on.
+pub fn set_proxy(settings: ProxySettings) -> Result<()> {
+    // Fail now, rather than when building clients.
+    settings.reqwest_proxy().context("invalid proxy")?;
+
+    *PROXY.write().unwrap_or_else(|err| err.into_inner()) = Some(settings);
+
+    Ok(())
+}
+
+/// Proxy requests as set by the standard environment variables, as for child
+/// processes of the agent.
+pub fn set_proxy_from_env() -> Result<()> {
+    if let Some(settings) = ProxySettings::from_env()? {
+        set_proxy(settings)?;
+    }
+
+    Ok(())
+}
+
+/// The proxy of the process, if set.
+pub fn proxy() -> Option<ProxySettings> {
+    PROXY.read().unwrap_or_else(|err| err.into_inner()).clone()
+}
+
+/// A client builder, with the proxy and timeouts of the process.
+pub fn client_builder() -> reqwest::ClientBuilder {
+    let builder = crate::timeout::timeouts().apply(reqwest::Client::builder());
+
+    match proxy().map(|settings| settings.reqwest_proxy()) {
+        Some(Ok(proxy)) => builder.proxy(proxy),
+        // Validated when set.
+        Some(Err(_)) | None => builder,
+    }
+}
+
+/// A client with the proxy of the process, in place of `reqwest::Client::new()`.
+///
+/// # Panics
+///
+/// As `reqwest::Client::new()`, if the TLS backend can't be initialized.
+pub fn client() -> reqwest::Client {
+    client_builder()
+        .build()
+        .expect("unable to build HTTP client")
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,98 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! The timeouts of outbound HTTP requests, set once per process and applied to
+//! each client built by [`crate::proxy::client`], so that no request waits on
+//! an unresponsive server for as long as the OS allows.
+//!
+//! Requests which legitimately take longer, such as uploads of large files,
+//! override the request timeout of the client with
+//! `reqwest::RequestBuilder::timeout()`.
+
+use std::sync::RwLock;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+
+// Seconds of each timeout, read by `set_timeouts_from_env()`, and set by
+// `env_vars()`, for child processes.
+pub const CONNECT_TIMEOUT_ENV: &str = "ONEFUZZ_HTTP_CONNECT_TIMEOUT";
+pub const REQUEST_TIMEOUT_ENV: &str = "ONEFUZZ_HTTP_REQUEST_TIMEOUT";
+
+static TIMEOUTS: RwLock<Timeouts> = RwLock::new(Timeouts::DEFAULT);
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Timeouts {
+    /// The longest wait to connect to a server, including any TLS handshake.
+    pub connect: Duration,
+
+    /// The longest wait for a whole request, from when it's sent until its
+    /// response body is read.
+    pub request: Duration,
+}
+
+impl Timeouts {
+    pub const DEFAULT: Self = Self {
+        connect: Duration::from_secs(30),
+        request: Duration::from_secs(2 * 60),
+    };
+
+    /// The timeouts of the environment, as set for child processes, or the
+    /// default timeouts, for those which aren't set.
+    pub fn from_env() -> Result<Self> {
+        Ok(Self {
+            connect: env_secs(CONNECT_TIMEOUT_ENV)?.unwrap_or(Self::DEFAULT.connect),
+            request: env_secs(REQUEST_TIMEOUT_ENV)?.unwrap_or(Self::DEFAULT.request),
+        })
+    }
+
+    /// Variables of the timeouts, to be read by child processes.
+    pub fn env_vars(&self) -> Vec<(&'static str, String)> {
+        vec![
+            (CONNECT_TIMEOUT_ENV, self.connect.as_secs().to_string()),
+            (REQUEST_TIMEOUT_ENV, self.request.as_secs().to_string()),
+        ]
+    }
+
+    pub(crate) fn apply(&self, builder: reqwest::ClientBuilder) -> reqwest::ClientBuilder {
+        builder.connect_timeout(self.connect).timeout(self.request)
+    }
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+fn env_secs(name: &str) -> Result<Option<Duration>> {
+    let Ok(value) = std::env::var(name) else {
+        return Ok(None);
+    };
+
+    let secs = value
+        .trim()
+        .parse()
+        .with_context(|| format!("invalid {name}: {value:?}"))?;
+
+    Ok(Some(Duration::from_secs(secs)))
+}
+
+/// Apply `timeouts` to all clients built from now on.
+pub fn set_timeouts(timeouts: Timeouts) {
+    *TIMEOUTS.write().unwrap_or_else(|err| err.into_inner()) = timeouts;
+}
+
+/// Apply the timeouts of the environment, as for child processes of the agent.
+pub fn set_timeouts_from_env() -> Result<()> {
+    set_timeouts(Timeouts::from_env()?);
+    Ok(())
+}
+
+/// The timeouts of the process.
+pub fn timeouts() -> Timeouts {
+    *TIMEOUTS.read().unwrap_or_else(|err| err.into_inner())
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,156 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use tokio::net::TcpListener;
+
+use super::*;
+use crate::{is_timeout, RetryPolicy, SendRetry};
+
+// How much later than its timeout a request may fail.
+const TOLERANCE: Duration = Duration::from_secs(2);
+
+// A server which accepts connections, but never responds to them.
+struct Silent {
+    addr: SocketAddr,
+    connections: Arc<AtomicUsize>,
+}
+
+impl Silent {
+    async fn start() -> Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let connections = Arc::new(AtomicUsize::new(0));
+
+        let accepted = connections.clone();
+        tokio::spawn(async move {
+            // Held open, so that requests wait for responses.
+            let mut streams = vec![];
+            while let Ok((stream, _)) = listener.accept().await {
+                accepted.fetch_add(1, Ordering::SeqCst);
+                streams.push(stream);
+            }
+        });
+
+        Ok(Self { addr, connections })
+    }
+
+    fn url(&self) -> String {
+        format!("http://{}/api/agents/events", self.addr)
+    }
+
+    fn connections(&self) -> usize {
+        self.connections.load(Ordering::SeqCst)
+    }
+}
+
+fn client(request: Duration) -> Result<reqwest::Client> {
+    let timeouts = Timeouts {
+        connect: Duration::from_secs(1),
+        request,
+    };
+    Ok(timeouts
+        .apply(reqwest::Client::builder())
+        .no_proxy()
+        .build()?)
+}
+
+fn assert_elapsed(start: Instant, timeout: Duration) {
+    let elapsed = start.elapsed();
+    assert!(elapsed >= timeout, "{elapsed:?} < {timeout:?}");
+    assert!(elapsed <= timeout + TOLERANCE, "{elapsed:?} > {timeout:?}");
+}
+
+#[tokio::test]
+async fn test_request_timeout_is_respected() -> Result<()> {
+    let server = Silent::start().await?;
+    let timeout = Duration::from_millis(500);
+
+    let start = Instant::now();
+    let err = client(timeout)?
+        .get(server.url())
+        .send()
+        .await
+        .err()
+        .expect("error");
+    assert!(err.is_timeout(), "{err:?}");
+    assert_elapsed(start, timeout);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_per_call_timeout_overrides_client() -> Result<()> {
+    let server = Silent::start().await?;
+    let timeout = Duration::from_millis(300);
+
+    let start = Instant::now();
+    let err = client(Duration::from_secs(60))?
+        .get(server.url())
+        .timeout(timeout)
+        .send()
+        .await
+        .err()
+        .expect("error");
+    assert!(err.is_timeout(), "{err:?}");
+    assert_elapsed(start, timeout);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_timeouts_are_transient() -> Result<()> {
+    let server = Silent::start().await?;
+    let timeout = Duration::from_millis(200);
+    let policy = RetryPolicy::transient()
+        .with_retry_period(Duration::from_millis(10))
+        .with_max_retry(2);
+
+    let start = Instant::now();
+    let err = client(timeout)?
+        .post(server.url())
+        .send_retry_policy(&policy)
+        .await
+        .err()
+        .expect("error");
+    assert!(is_timeout(&err), "{err:?}");
+    assert_eq!(server.connections(), 3);
+    assert!(start.elapsed() <= 3 * timeout + TOLERANCE);
+
+    Ok(())
+}
+
+#[test]
+fn test_env_vars() -> Result<()> {
+    let timeouts = Timeouts {
+        connect: Duration::from_secs(5),
+        request: Duration::from_secs(600),
+    };
+    let vars = timeouts.env_vars();
+    assert_eq!(
+        vars,
+        [
+            (CONNECT_TIMEOUT_ENV, "5".to_owned()),
+            (REQUEST_TIMEOUT_ENV, "600".to_owned()),
+        ]
+    );
+
+    // The only test which sets the variables.
+    for (name, value) in &vars {
+        std::env::set_var(name, value);
+    }
+    assert_eq!(Timeouts::from_env()?, timeouts);
+
+    std::env::set_var(REQUEST_TIMEOUT_ENV, "soon");
+    assert!(Timeouts::from_env().is_err());
+
+    std::env::remove_var(CONNECT_TIMEOUT_ENV);
+    std::env::remove_var(REQUEST_TIMEOUT_ENV);
+    assert_eq!(Timeouts::from_env()?, Timeouts::DEFAULT);
+
+    Ok(())
+}
@@ -179,10 +179,85 @@ pub(crate) fn parse_summary(text: &str) -> Option<CrashLogSummary> {
     .find_map(|f| f(text))
 }
 
+// The fault types of the reports of sanitizers and libFuzzer of the abort of a
+// process, such as by a language runtime of its panics.
+const ABORT_FAULT_TYPES: &[&str] = &["ABRT", "deadly signal"];
+
+/// Whether a sanitizer or libFuzzer reports a crash other than an abort. If
+/// not, a panic logged by a language runtime is the crash of the log.
+pub(crate) fn is_reported_crash(text: &str) -> bool {
+    parse_summary(text).map_or(false, |summary| {
+        !ABORT_FAULT_TYPES.contains(&summary.fault_type.as_str())
+    })
+}
+
+/// The summary of an MSan report logged without a crash site, or with an
+/// unsymbolized one, at the first frame of its stack, as MSan would summarize
+/// it. Other summaries are kept.
+pub(crate) fn with_crash_site(summary: CrashLogSummary, stack: &[StackEntry]) -> CrashLogSummary {
+    const UNSYMBOLIZED_SITE: &str = " ??:0 ??";
+
+    if summary.sanitizer != "MemorySanitizer" {
+        return summary;
+    }
+
+    let bare = format!("{}: {}", summary.sanitizer, summary.fault_type);
+    let site = summary
+        .summary
+        .strip_suffix(UNSYMBOLIZED_SITE)
+        .unwrap_or(&summary.summary);
+    if site != bare {
+        return summary;
+    }
+
+    let Some(site) = stack.first().and_then(crash_site) else {
+        return summary;
+    };
+
+    CrashLogSummary {
+        summary: format!("{bare} {site}"),
+        ..summary
+    }
+}
+
+/// The crash site of a frame, as sanitizers summarize it, such as
+/// `/src/fuzz.c:10:3 in check` or `(/setup/fuzz+0x1234)`.
+pub(crate) fn crash_site(frame: &StackEntry) -> Option<String> {
+    let mut parts = vec![];
+    match (&frame.source_file_path, &frame.module_path) {
+        (Some(path), _) => {
+            let mut location = path.clone();
+            if let Some(line) = frame.source_file_line {
+                location.push_str(&format!(":{line}"));
+
+                // The column, as parsed from the frame.
+                if let Some(column) = frame.function_offset {
+                    location.push_str(&format!(":{column}"));
+                }
+            }
+            parts.push(location);
+        }
+        (None, Some(module)) => {
+            let offset = frame.module_offset.unwrap_or_default();
+            parts.push(format!("({module}+0x{offset:x})"));
+        }
+        (None, None) => {}
+    }
+    if let Some(function_name) = &frame.function_name {
+        parts.push(format!("in {function_name}"));
+    }
+
+    if parts.is_empty() {
+        None
+    } else {
+        Some(parts.join(" "))
+    }
+}
+
 // Unfortunately, we can't just use Path's split as we want to
 // parse stack frames from OSes other than OS the app is running
 // on
-fn get_call_stack_file_name(file_path: &str) -> String {
+pub(crate) fn get_call_stack_file_name(file_path: &str) -> String {
     let split: Vec<_> = file_path.rsplitn(2, '/').collect();
     if split.len() == 2 {
         return split[0].to_string();
@@ -231,6 +231,69 @@ System.Exception: This is the outermost exception
    at StackOverflow44227962StackTraces.Program.Main(String[] args) in C:\Users\Simon\source\repos\StackOverflow44227962StackTraces\Program.cs:line 12
 "#;
 
+    // A managed exception in an async method, as printed by libfuzzer-dotnet.
+    // Async frames are separated by markers which, unlike the end of an inner
+    // exception, are part of the same stack trace.
+    const SAMPLE_ASYNC: &str = r#"
+INFO: libFuzzer ignores flags that start with '--'
+INFO: Seed: 1893124457
+./libfuzzer-dotnet: Running 1 inputs 1 time(s) each.
+Running: crash-5ba93c9db0cff93f52b521d7420e43f6eda2784f
+System.FormatException: The input string 'x' was not in a correct format.
+   at System.Number.ThrowFormatException[TChar](ReadOnlySpan`1 value)
+   at Parser.Document.ReadHeaderAsync(Stream stream) in /src/Parser/Document.cs:line 42
+--- End of stack trace from previous location ---
+   at Parser.Fuzz.TestInput(ReadOnlySpan`1 data) in /src/Parser.Fuzz/Program.cs:line 17
+==4182== ERROR: libFuzzer: deadly signal
+SUMMARY: libFuzzer: deadly signal"#;
+
+    #[test]
+    pub fn check_parse_async_sample_summary() {
+        let result = super::parse_summary(SAMPLE_ASYNC).unwrap();
+        assert_eq!(
+            result.summary,
+            "System.FormatException: The input string 'x' was not in a correct format."
+        );
+        assert_eq!(result.sanitizer, ".NET");
+        assert_eq!(result.fault_type, "Unhandled exception");
+    }
+
+    #[test]
+    pub fn check_parse_async_sample_stack() {
+        let result = super::parse_dotnet_callstack(SAMPLE_ASYNC);
+        let frames: Vec<_> = result
+            .iter()
+            .map(|entry| {
+                (
+                    entry.function_name.as_deref().unwrap(),
+                    entry.source_file_name.as_deref(),
+                    entry.source_file_line,
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            frames,
+            vec![
+                (
+                    "System.Number.ThrowFormatException[TChar](ReadOnlySpan`1 value)",
+                    None,
+                    None
+                ),
+                (
+                    "Parser.Document.ReadHeaderAsync(Stream stream)",
+                    Some("Document.cs"),
+                    Some(42)
+                ),
+                (
+                    "Parser.Fuzz.TestInput(ReadOnlySpan`1 data)",
+                    Some("Program.cs"),
+                    Some(17)
+                ),
+            ]
+        );
+    }
+
     #[test]
     pub fn check_parse_double_nested_sample_summary() {
         let result = super::parse_summary(DOUBLE_NESTED_SAMPLE).unwrap();
@@ -0,0 +1,618 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use regex::{Regex, RegexSet};
+use serde::{Deserialize, Serialize};
+
+use crate::{CrashLog, StackEntry};
+
+use Exploitability::{High, Low, Medium, Unknown};
+
+// Faults below this address, the default `vm.mmap_min_addr` of Linux, are
+// taken to be dereferences of a null pointer plus a small offset.
+const NEAR_NULL_LIMIT: u64 = 0x10000;
+
+/// A first-pass rating of how likely a crash is to be exploitable.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Exploitability {
+    High,
+    Medium,
+    Low,
+    Unknown,
+}
+
+/// The rating of a crash, and why.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExploitabilityAssessment {
+    pub exploitability: Exploitability,
+    pub rationale: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Access {
+    Read,
+    Write,
+    Execute,
+
+    // A read of the target of a call or jump, as of `call [rax]`.
+    Branch,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Address {
+    NearNull,
+    Wild,
+}
+
+// Rates crashes of a fault type and, when set, of a sanitizer, access, and
+// address. Rules are checked in order and the first match is used.
+struct Rule {
+    sanitizer: Option<&'static str>,
+    fault_type: &'static str,
+    access: Option<Access>,
+    address: Option<Address>,
+    exploitability: Exploitability,
+    rationale: &'static str,
+}
+
+const fn rule(
+    fault_type: &'static str,
+    exploitability: Exploitability,
+    rationale: &'static str,
+) -> Rule {
+    Rule {
+        sanitizer: None,
+        fault_type,
+        access: None,
+        address: None,
+        exploitability,
+        rationale,
+    }
+}
+
+const OUT_OF_BOUNDS: &str = r"^(?:(?:heap|stack|global|dynamic-stack)-buffer-(?:overflow|underflow)|(?:container|intra-object)-overflow|out-of-bounds-index)$";
+const STACK_USE_AFTER_SCOPE: &str = r"^stack-use-after-(?:return|scope)$";
+// Of sanitizers, and of the signals of crashes caught by a debugger, or the
+// accesses of their faulting instructions.
+const WILD_ACCESS: &str =
+    r"^(?:(?:SIG)?SEGV|(?:(?:read|write|execute)-)?access-violation|(?:SIG)?BUS|unknown-crash)$";
+
+// The fault type of LeakSanitizer reports, whose summaries are of the size of
+// the leaks.
+const MEMORY_LEAK: &str = "memory-leak";
+
+const RULES: &[Rule] = &[
+    Rule {
+        access: Some(Access::Write),
+        ..rule(OUT_OF_BOUNDS, High, "out-of-bounds write")
+    },
+    Rule {
+        access: Some(Access::Read),
+        ..rule(OUT_OF_BOUNDS, Medium, "out-of-bounds read")
+    },
+    rule(OUT_OF_BOUNDS, Medium, "out-of-bounds access"),
+    rule(
+        r"^al?location-tail-overwritten$",
+        High,
+        "write past the end of an allocation",
+    ),
+    rule(r"use-after-(?:free|poison)$", High, "use of freed memory"),
+    Rule {
+        access: Some(Access::Write),
+        ..rule(
+            STACK_USE_AFTER_SCOPE,
+            High,
+            "write to out-of-scope stack memory",
+        )
+    },
+    rule(
+        STACK_USE_AFTER_SCOPE,
+        Medium,
+        "use of out-of-scope stack memory",
+    ),
+    rule(r"^double-free$", High, "double free"),
+    rule(
+        r"^(?:bad-free|alloc-dealloc-mismatch|new-delete-type-mismatch)$",
+        Medium,
+        "free of an invalid pointer",
+    ),
+    Rule {
+        address: Some(Address::NearNull),
+        ..rule(WILD_ACCESS, Low, "near-null dereference")
+    },
+    Rule {
+        access: Some(Access::Execute),
+        ..rule(WILD_ACCESS, High, "execution of a wild address")
+    },
+    Rule {
+        access: Some(Access::Branch),
+        ..rule(WILD_ACCESS, High, "read of a wild branch target")
+    },
+    Rule {
+        access: Some(Access::Write),
+        ..rule(WILD_ACCESS, High, "write to a wild address")
+    },
+    Rule {
+        access: Some(Access::Read),
+        ..rule(WILD_ACCESS, Medium, "read of a wild address")
+    },
+    rule(WILD_ACCESS, Medium, "access of a wild address"),
+    rule(
+        r"^use-of-uninitialized-value$",
+        Medium,
+        "use of uninitialized memory",
+    ),
+    rule(
+        r"^(?:dynamic|function)-type-mismatch$",
+        Medium,
+        "type confusion",
+    ),
+    rule(
+        r"^(?:negative-size-param|memcpy-param-overlap|unexpected format specifier)$",
+        Medium,
+        "invalid argument of a memory operation",
+    ),
+    rule(
+        r"^(?:null-pointer-use|nullptr-with(?:-nonzero)?-offset)$",
+        Low,
+        "null pointer use",
+    ),
+    rule(r"^stack-overflow$", Low, "stack exhaustion"),
+    rule(
+        r"^(?:(?:SIG)?FPE|(?:int|integer|float)-divide-by-zero)$",
+        Low,
+        "division by zero",
+    ),
+    rule(
+        r"^(?:allocation-size-too-big|out-of-memory|malloc-exceeds-limit|rss-limit-exceeded|calloc-overflow|pvalloc-overflow|invalid-allocation-alignment|timeout)$",
+        Low,
+        "resource exhaustion",
+    ),
+    rule(r"^memory-leak$", Low, "memory leak"),
+    rule(r"^data-race$", Low, "data race"),
+    rule(
+        r"^(?:(?:SIG)?(?:ABRT|ILL|TRAP)|breakpoint|CHECK failed)$",
+        Low,
+        "abort or trap of a check",
+    ),
+    Rule {
+        sanitizer: Some("UndefinedBehaviorSanitizer"),
+        ..rule(r"", Low, "undefined behavior")
+    },
+    Rule {
+        sanitizer: Some("Rust"),
+        ..rule(r"", Low, "panic of a memory-safe language")
+    },
+    Rule {
+        sanitizer: Some("Go"),
+        ..rule(r"", Low, "panic of a memory-safe language")
+    },
+    Rule {
+        sanitizer: Some("Java"),
+        ..rule(r"", Low, "exception of a memory-safe language")
+    },
+    Rule {
+        sanitizer: Some(".NET"),
+        ..rule(r"", Low, "exception of a memory-safe language")
+    },
+];
+
+// Frames of known-benign crashes: failed assertions, handlers of failed
+// allocations, and aborts. The first pattern matching any crash frame is used.
+const BENIGN_FRAMES: &[(&str, &str)] = &[
+    (
+        r"^(?:__assert_fail|__assert_perror_fail|__assert_rtn|__assert2|_wassert)$",
+        "failed assertion",
+    ),
+    (
+        r"^(?:std::(?:__1::)?__throw_bad_alloc|(?:base|partition_alloc)::(?:internal::OnNoMemory|TerminateBecauseOutOfMemory)|WTF::PartitionOutOfMemory|alloc::alloc::handle_alloc_error|__rust_alloc_error_handler)",
+        "abort of a failed allocation",
+    ),
+    (
+        r"^(?:abort|__GI_abort|raise|__GI_raise|gsignal|__pthread_kill_implementation|std::terminate|std::__terminate|__cxxabiv1::__terminate)$",
+        "abort",
+    ),
+];
+
+lazy_static::lazy_static! {
+    static ref RULE_FAULT_TYPES: Vec<Regex> =
+        RULES.iter().map(|r| Regex::new(r.fault_type).unwrap()).collect();
+
+    static ref BENIGN_FRAME_SET: RegexSet =
+        RegexSet::new(BENIGN_FRAMES.iter().map(|(pattern, _)| pattern)).unwrap();
+
+    // "READ of size 4 at 0x602000000010 thread T0", of ASan, and of SEGVs,
+    // "The signal is caused by a WRITE memory access."
+    static ref ACCESS_REGEX: Regex = Regex::new(
+        r"(?m)(?:^\s*(?P<access>READ|WRITE) of size \d+ at |The signal is caused by a (?P<signal_access>READ|WRITE) memory access)"
+    )
+    .unwrap();
+
+    static ref WILD_ACCESS_REGEX: Regex = Regex::new(WILD_ACCESS).unwrap();
+
+    static ref WILD_JUMP_REGEX: Regex =
+        Regex::new(r"Hint: PC is at a non-executable region").unwrap();
+
+    // Of the faulting instructions of crashes caught by a debugger.
+    static ref BRANCH_TARGET_REGEX: Regex =
+        Regex::new(r"Hint: the value read is the target of a call or jump").unwrap();
+
+    // "SEGV on unknown address 0x000000000008 (pc 0x55d1c8a2c5ac bp ..."
+    static ref ADDRESS_REGEX: Regex = Regex::new(
+        r"on unknown address (?:0x)?(?P<address>[0-9a-fA-F]+)(?: \(pc (?:0x)?(?P<pc>[0-9a-fA-F]+))?"
+    )
+    .unwrap();
+
+    static ref LEAK_REGEX: Regex = Regex::new(r"leaked in \d+ allocation").unwrap();
+}
+
+impl ExploitabilityAssessment {
+    fn new(exploitability: Exploitability, rationale: &str) -> Self {
+        Self {
+            exploitability,
+            rationale: rationale.to_string(),
+        }
+    }
+}
+
+/// Rate `log` by its fault type and sanitizer, and by the access and address
+/// of the crash, as logged. A crash in a known-benign frame is rated low,
+/// unless its rule rates it higher.
+pub(crate) fn assess(log: &CrashLog) -> ExploitabilityAssessment {
+    let text = log.text.as_deref().unwrap_or_default();
+    let fault_type = if LEAK_REGEX.is_match(&log.summary) {
+        MEMORY_LEAK
+    } else {
+        log.fault_type.as_str()
+    };
+    let (access, address) = parse_access(text, fault_type);
+
+    let rated = RULES.iter().enumerate().find(|(i, rule)| {
+        rule.sanitizer.is_none_or(|s| s == log.sanitizer)
+            && RULE_FAULT_TYPES[*i].is_match(fault_type)
+            && rule.access.is_none_or(|a| access == Some(a))
+            && rule.address.is_none_or(|a| address == Some(a))
+    });
+
+    if let Some((_, rule)) = rated {
+        if matches!(rule.exploitability, High | Medium) {
+            return ExploitabilityAssessment::new(rule.exploitability, rule.rationale);
+        }
+    }
+
+    if let Some(rationale) = benign_frame(crash_frames(log)) {
+        return ExploitabilityAssessment::new(Low, rationale);
+    }
+
+    match rated {
+        Some((_, rule)) => ExploitabilityAssessment::new(rule.exploitability, rule.rationale),
+        None => ExploitabilityAssessment::new(Unknown, "no rule for the crash"),
+    }
+}
+
+fn parse_access(text: &str, fault_type: &str) -> (Option<Access>, Option<Address>) {
+    let mut access = ACCESS_REGEX.captures(text).and_then(|captures| {
+        match captures
+            .name("access")
+            .or_else(|| captures.name("signal_access"))?
+            .as_str()
+        {
+            "READ" => Some(Access::Read),
+            "WRITE" => Some(Access::Write),
+            _ => None,
+        }
+    });
+
+    let mut address = None;
+    if let Some(captures) = ADDRESS_REGEX.captures(text) {
+        let fault_address = u64::from_str_radix(&captures["address"], 16).ok();
+        let pc = captures
+            .name("pc")
+            .and_then(|pc| u64::from_str_radix(pc.as_str(), 16).ok());
+
+        address = fault_address.map(|x| {
+            if x < NEAR_NULL_LIMIT {
+                Address::NearNull
+            } else {
+                Address::Wild
+            }
+        });
+
+        // A fault at the pc is a jump to the faulting address.
+        if WILD_ACCESS_REGEX.is_match(fault_type) && fault_address.is_some() && fault_address == pc
+        {
+            access = Some(Access::Execute);
+        }
+    }
+
+    if WILD_JUMP_REGEX.is_match(text) {
+        access = Some(Access::Execute);
+    }
+
+    if access == Some(Access::Read) && BRANCH_TARGET_REGEX.is_match(text) {
+        access = Some(Access::Branch);
+    }
+
+    (access, address)
+}
+
+// The frames of the full stack up to and including the first frame of the
+// minimized stack, so that runtime frames such as `abort` are kept. All frames,
+// if there is no minimized stack.
+fn crash_frames(log: &CrashLog) -> &[StackEntry] {
+    let stack = &log.full_stack_details;
+    let first = log.minimized_stack_details.first();
+    match first.and_then(|first| stack.iter().position(|x| x.line == first.line)) {
+        Some(i) => &stack[..=i],
+        None => stack,
+    }
+}
+
+fn benign_frame(frames: &[StackEntry]) -> Option<&'static str> {
+    let names = frames.iter().filter_map(|x| x.function_name.as_deref());
+    let mut matches: Vec<usize> = names
+        .flat_map(|name| BENIGN_FRAME_SET.matches(name).into_iter())
+        .collect();
+    matches.sort_unstable();
+    matches.first().map(|i| BENIGN_FRAMES[*i].1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::fs;
+    use std::path::Path;
+
+    const CORPUS: &[(&str, Exploitability, &str)] = &[
+        (
+            "libclusterfuzz/asan_heap_overflow_write.txt",
+            High,
+            "out-of-bounds write",
+        ),
+        (
+            "libclusterfuzz/asan_heap_overflow_read.txt",
+            Medium,
+            "out-of-bounds read",
+        ),
+        (
+            "libclusterfuzz/ubsan_index_oob.txt",
+            Medium,
+            "out-of-bounds access",
+        ),
+        (
+            "libclusterfuzz/hwasan_allocation_tail_overwritten.txt",
+            High,
+            "write past the end of an allocation",
+        ),
+        ("libclusterfuzz/asan_uaf.txt", High, "use of freed memory"),
+        (
+            "libclusterfuzz/variable_length_write.txt",
+            High,
+            "write to out-of-scope stack memory",
+        ),
+        ("libclusterfuzz/asan_double_free.txt", High, "double free"),
+        (
+            "libclusterfuzz/asan_invalid_free.txt",
+            Medium,
+            "free of an invalid pointer",
+        ),
+        (
+            "libclusterfuzz/asan_null_dereference_read.txt",
+            Low,
+            "near-null dereference",
+        ),
+        (
+            "libclusterfuzz/asan_null_dereference_win_write.txt",
+            Low,
+            "near-null dereference",
+        ),
+        (
+            "libclusterfuzz/unknown_module.txt",
+            High,
+            "execution of a wild address",
+        ),
+        (
+            "libclusterfuzz/asan_unknown_write.txt",
+            High,
+            "write to a wild address",
+        ),
+        (
+            "libclusterfuzz/asan_unknown_win_read.txt",
+            Medium,
+            "read of a wild address",
+        ),
+        (
+            "libclusterfuzz/msan_uninitialized_value.txt",
+            Medium,
+            "use of uninitialized memory",
+        ),
+        (
+            "libclusterfuzz/ubsan_bad_cast_member_call.txt",
+            Medium,
+            "type confusion",
+        ),
+        (
+            "libclusterfuzz/ubsan_null_pointer_write.txt",
+            Low,
+            "null pointer use",
+        ),
+        (
+            "libclusterfuzz/asan_stack_overflow.txt",
+            Low,
+            "stack exhaustion",
+        ),
+        ("libclusterfuzz/asan_fpe.txt", Low, "division by zero"),
+        (
+            "libclusterfuzz/libfuzzer_oom.txt",
+            Low,
+            "resource exhaustion",
+        ),
+        (
+            "libclusterfuzz/libfuzzer_timeout.txt",
+            Low,
+            "resource exhaustion",
+        ),
+        ("libclusterfuzz/lsan_direct_leak.txt", Low, "memory leak"),
+        ("libclusterfuzz/tsan_data_race.txt", Low, "data race"),
+        ("libclusterfuzz/assert_glibc.txt", Low, "failed assertion"),
+        ("libclusterfuzz/assert_failure_google.txt", Low, "abort"),
+        (
+            "libclusterfuzz/rust_oom.txt",
+            Low,
+            "abort of a failed allocation",
+        ),
+        (
+            "libclusterfuzz/v8_check_trap.txt",
+            Low,
+            "abort or trap of a check",
+        ),
+        (
+            "libclusterfuzz/ubsan_integer_overflow_negation.txt",
+            Low,
+            "undefined behavior",
+        ),
+        (
+            "stacktrace-parser/rust-1.75-cargo-fuzz-panic-backtrace.txt",
+            Low,
+            "panic of a memory-safe language",
+        ),
+        (
+            "stacktrace-parser/go-1.21-panic-sigsegv.txt",
+            Low,
+            "panic of a memory-safe language",
+        ),
+        (
+            "stacktrace-parser/jazzer-exception-caused-by-lambda.txt",
+            Low,
+            "exception of a memory-safe language",
+        ),
+        (
+            "stacktrace-parser/dotnet-unhandled-exception.txt",
+            Low,
+            "exception of a memory-safe language",
+        ),
+        (
+            "libclusterfuzz/libfuzzer_deadly_signal.txt",
+            Unknown,
+            "no rule for the crash",
+        ),
+    ];
+
+    fn parse(file: &str) -> CrashLog {
+        let (krate, name) = file.split_once('/').unwrap();
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("..")
+            .join(krate)
+            .join("data/stack-traces")
+            .join(name);
+        let text = fs::read_to_string(&path).unwrap().replace("\r\n", "\n");
+        CrashLog::parse(text).unwrap()
+    }
+
+    #[test]
+    fn test_corpus() {
+        for (file, exploitability, rationale) in CORPUS {
+            let assessment = parse(file).exploitability();
+            assert_eq!(
+                (file, assessment),
+                (
+                    file,
+                    ExploitabilityAssessment::new(*exploitability, rationale)
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn test_benign_frame_does_not_lower_rating() {
+        // An overflow found through an `abort` frame is still rated by its
+        // access.
+        let mut log = parse("libclusterfuzz/asan_heap_overflow_write.txt");
+        log.full_stack_details[0].function_name = Some("abort".to_string());
+        assert_eq!(log.exploitability().exploitability, High);
+    }
+
+    #[test]
+    fn test_signals() {
+        let log = |text: &str, signal: &str| {
+            CrashLog::from_signal(
+                text.to_owned(),
+                signal.to_owned(),
+                signal.to_owned(),
+                vec![],
+            )
+            .unwrap()
+        };
+        let instruction_log = |text: &str, fault_type: &str| {
+            CrashLog::from_signal(
+                text.to_owned(),
+                "SIGSEGV".to_owned(),
+                fault_type.to_owned(),
+                vec![],
+            )
+            .unwrap()
+        };
+
+        let near_null = log(
+            "==7==ERROR: SIGSEGV on unknown address 0x000000000010 (pc 0x55d1c8a2c5ac T7)",
+            "SIGSEGV",
+        );
+        assert_eq!(
+            near_null.exploitability(),
+            ExploitabilityAssessment::new(Low, "near-null dereference")
+        );
+
+        let jump = log(
+            "==7==ERROR: SIGSEGV on unknown address 0x41414141 (pc 0x41414141 T7)",
+            "SIGSEGV",
+        );
+        assert_eq!(
+            jump.exploitability(),
+            ExploitabilityAssessment::new(High, "execution of a wild address")
+        );
+
+        let write = instruction_log(
+            "==7==ERROR: SIGSEGV on unknown address 0x414141414141 (pc 0x55d1c8a2c5ac T7)\n\
+             ==7==The signal is caused by a WRITE memory access.",
+            "write-access-violation",
+        );
+        assert_eq!(
+            write.exploitability(),
+            ExploitabilityAssessment::new(High, "write to a wild address")
+        );
+
+        let branch = instruction_log(
+            "==7==ERROR: SIGSEGV on unknown address 0x414141414141 (pc 0x55d1c8a2c5ac T7)\n\
+             ==7==The signal is caused by a READ memory access.\n\
+             ==7==Hint: the value read is the target of a call or jump.",
+            "read-access-violation",
+        );
+        assert_eq!(
+            branch.exploitability(),
+            ExploitabilityAssessment::new(High, "read of a wild branch target")
+        );
+
+        let stack_overflow = instruction_log(
+            "==7==ERROR: SIGSEGV on unknown address 0x7ffd4f3a2ff8 (pc 0x55d1c8a2c5ac T7)\n\
+             ==7==The signal is caused by a WRITE memory access.",
+            "stack-overflow",
+        );
+        assert_eq!(
+            stack_overflow.exploitability(),
+            ExploitabilityAssessment::new(Low, "stack exhaustion")
+        );
+
+        let abort = log("==7==ERROR: SIGABRT (pc 0x7f0000001000 T7)", "SIGABRT");
+        assert_eq!(
+            abort.exploitability(),
+            ExploitabilityAssessment::new(Low, "abort or trap of a check")
+        );
+    }
+
+    #[test]
+    fn test_rules_compile() {
+        assert_eq!(RULE_FAULT_TYPES.len(), RULES.len());
+        assert_eq!(BENIGN_FRAME_SET.len(), BENIGN_FRAMES.len());
+    }
+}
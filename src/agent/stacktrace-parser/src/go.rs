@@ -0,0 +1,428 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use anyhow::Result;
+use regex::{Regex, RegexSet};
+use serde::{Deserialize, Serialize};
+
+use crate::{asan, StackEntry};
+
+pub(crate) const SANITIZER: &str = "Go";
+
+// The kinds of panics and fatal errors without a known message.
+const PANIC_KIND: &str = "panic";
+const RUNTIME_ERROR_KIND: &str = "runtime-error";
+const FATAL_ERROR_KIND: &str = "fatal-error";
+
+const RUNTIME_ERROR_PREFIX: &str = "runtime error: ";
+
+/// Frames of fuzzing harnesses, which call Go fuzz targets of `testing.F`.
+pub(crate) const STACK_FRAME_IGNORE_REGEXES: &[&str] = &[
+    r"^testing\.",
+    r"^reflect\.Value\.[Cc]all$",
+    r"^github\.com/AdamKorcz/go-118-fuzz-build/",
+    r"^go-fuzz-dep\.",
+];
+
+// Messages of panics and fatal errors of the Go runtime, without the prefix of
+// runtime errors, and the kinds of their crashes. The first match is used.
+const KINDS: &[(&str, &str)] = &[
+    (r"^index out of range", "index-out-of-range"),
+    (r"^slice bounds out of range", "slice-bounds-out-of-range"),
+    (
+        r"^invalid memory address or nil pointer dereference",
+        "nil-dereference",
+    ),
+    (r"^integer divide by zero", "integer-divide-by-zero"),
+    (
+        r"^makeslice: (?:len|cap) out of range",
+        "makeslice-out-of-range",
+    ),
+    (r"^assignment to entry in nil map", "nil-map-write"),
+    (r"^interface conversion", "interface-conversion"),
+    (r"^concurrent map", "concurrent-map-access"),
+    (r"^stack overflow", "stack-overflow"),
+    (r"^all goroutines are asleep", "deadlock"),
+    (r"^out of memory", "out-of-memory"),
+    (r"^unexpected signal", "unexpected-signal"),
+];
+
+lazy_static::lazy_static! {
+    pub(crate) static ref STACK_FILTER: RegexSet =
+        RegexSet::new(STACK_FRAME_IGNORE_REGEXES).unwrap();
+
+    static ref KIND_SET: RegexSet = RegexSet::new(KINDS.iter().map(|(pattern, _)| pattern)).unwrap();
+
+    // "panic: runtime error: index out of range [5] with length 3", or
+    // "fatal error: concurrent map writes"
+    static ref HEADER_REGEX: Regex = Regex::new(
+        r"(?m)^(?P<header>(?P<kind>panic|fatal error): (?P<message>.*?))(?: \[recovered\])?\s*$"
+    )
+    .unwrap();
+
+    // "goroutine 17 [running, locked to thread]:"
+    static ref GOROUTINE_REGEX: Regex = Regex::new(
+        r"^goroutine (?P<id>\d+) \[(?P<state>[^\]]*)\]:$"
+    )
+    .unwrap();
+
+    // "main.parse({0x10c000014a80, 0x3, 0x3})", or "created by main.main in goroutine 1"
+    static ref FUNCTION_REGEX: Regex = Regex::new(
+        r"^(?:created by (?P<creator>\S+?)(?: in goroutine \d+)?|(?P<function>\S+?)\([^()]*\))$"
+    )
+    .unwrap();
+
+    // "/src/parse.go:12 +0x1d4", followed in tracebacks of the runtime by
+    // "fp=0x10c000047f28 sp=0x10c000047ef8 pc=0x43a2dc"
+    static ref LOCATION_REGEX: Regex = Regex::new(
+        r"^(?P<file_path>\S+?):(?P<file_line>\d+)(?: \+0x[0-9a-fA-F]+)?(?: fp=0x[0-9a-fA-F]+ sp=0x[0-9a-fA-F]+ pc=0x(?P<pc>[0-9a-fA-F]+))?$"
+    )
+    .unwrap();
+}
+
+const ELIDED_FRAMES: &str = "...additional frames elided...";
+
+/// The first panic or fatal error of a log.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Panic {
+    /// The line of the panic, such as `panic: runtime error: integer divide by
+    /// zero`, without noting that it was recovered.
+    pub header: String,
+
+    fatal: bool,
+    message: String,
+}
+
+impl Panic {
+    pub fn kind(&self) -> &'static str {
+        let runtime_error = self.message.strip_prefix(RUNTIME_ERROR_PREFIX);
+        let message = runtime_error.unwrap_or(&self.message);
+
+        if let Some(i) = KIND_SET.matches(message).iter().next() {
+            return KINDS[i].1;
+        }
+
+        if self.fatal {
+            FATAL_ERROR_KIND
+        } else if runtime_error.is_some() {
+            RUNTIME_ERROR_KIND
+        } else {
+            PANIC_KIND
+        }
+    }
+}
+
+/// The panic or fatal error of `text`, if it is the crash of the log, and the
+/// traceback of its goroutines is logged. A panic aborts targets run by
+/// libFuzzer, which sanitizers report as the crash.
+pub(crate) fn parse_panic(text: &str) -> Option<Panic> {
+    if asan::is_reported_crash(text) {
+        return None;
+    }
+
+    let captures = HEADER_REGEX.captures(text)?;
+    let rest = &text[captures.get(0)?.end()..];
+    if !rest
+        .lines()
+        .any(|line| GOROUTINE_REGEX.is_match(line.trim()))
+    {
+        return None;
+    }
+
+    Some(Panic {
+        header: captures["header"].to_string(),
+        fatal: &captures["kind"] == "fatal error",
+        message: captures["message"].to_string(),
+    })
+}
+
+/// A goroutine of a traceback.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Goroutine {
+    pub id: u64,
+
+    /// Such as `running`, or `chan receive, 2 minutes`.
+    pub state: String,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stack: Vec<StackEntry>,
+}
+
+impl Goroutine {
+    pub fn is_running(&self) -> bool {
+        self.state.starts_with("running")
+    }
+}
+
+/// The goroutines of the traceback of `text`, the first running one, which
+/// crashed, first.
+///
+/// Frames are logged without their arguments, offsets and goroutine, so that
+/// the stacks of crashes are the same of other runs.
+pub(crate) fn parse_goroutines(text: &str) -> Result<Vec<Goroutine>> {
+    let mut goroutines = vec![];
+    let mut current: Option<Goroutine> = None;
+
+    for line in text.lines().map(str::trim) {
+        if let Some(captures) = GOROUTINE_REGEX.captures(line) {
+            goroutines.extend(current.take());
+            current = Some(Goroutine {
+                id: captures["id"].parse()?,
+                state: captures["state"].to_string(),
+                stack: vec![],
+            });
+            continue;
+        }
+
+        let Some(goroutine) = &mut current else {
+            continue;
+        };
+
+        if let Some(captures) = FUNCTION_REGEX.captures(line) {
+            let (function, name) = match captures.name("creator") {
+                Some(creator) => (creator.as_str(), format!("created by {}", creator.as_str())),
+                None => (&captures["function"], captures["function"].to_string()),
+            };
+
+            goroutine.stack.push(StackEntry {
+                line: format!("#{} {name}", goroutine.stack.len()),
+                function_name: Some(function.to_string()),
+                ..StackEntry::default()
+            });
+        } else if let Some(captures) = LOCATION_REGEX.captures(line) {
+            let Some(entry) = goroutine.stack.last_mut() else {
+                continue;
+            };
+            if entry.source_file_path.is_some() {
+                continue;
+            }
+
+            let path = &captures["file_path"];
+            let file_line = &captures["file_line"];
+            entry.line.push_str(&format!(" {path}:{file_line}"));
+            entry.source_file_path = Some(path.to_string());
+            entry.source_file_name = Some(asan::get_call_stack_file_name(path));
+            entry.source_file_line = Some(file_line.parse()?);
+
+            if let Some(pc) = captures.name("pc") {
+                entry.address = Some(u64::from_str_radix(pc.as_str(), 16)?);
+            }
+        } else if line != ELIDED_FRAMES {
+            goroutines.extend(current.take());
+        }
+    }
+    goroutines.extend(current);
+
+    if let Some(i) = goroutines.iter().position(Goroutine::is_running) {
+        let crashed = goroutines.remove(i);
+        goroutines.insert(0, crashed);
+    }
+
+    Ok(goroutines)
+}
+
+/// The names of the functions of a stack. Go logs functions without their
+/// arguments, but methods with their receivers in parentheses, such as
+/// `net/http.(*conn).serve`, so names are kept whole.
+pub(crate) fn stack_names(stack: &[StackEntry]) -> Vec<String> {
+    stack
+        .iter()
+        .filter_map(|entry| entry.function_name.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CrashLog;
+    use pretty_assertions::assert_eq;
+
+    const FATAL_ERROR: &str =
+        include_str!("../data/stack-traces/go-1.21-fatal-error-concurrent-map-writes.txt");
+    const FUZZ_PANIC: &str =
+        include_str!("../data/stack-traces/go-1.21-libfuzzer-panic-index-out-of-range.txt");
+    const SIGSEGV: &str = include_str!("../data/stack-traces/go-1.21-panic-sigsegv.txt");
+
+    fn parse(text: &str) -> CrashLog {
+        CrashLog::parse(text.to_owned()).unwrap()
+    }
+
+    fn panic(header: &str) -> Panic {
+        let text = format!("{header}\n\ngoroutine 1 [running]:\nmain.main()\n");
+        parse_panic(&text).unwrap()
+    }
+
+    #[test]
+    fn test_panic_kinds() {
+        let test_cases = [
+            (
+                "panic: runtime error: index out of range [5] with length 3",
+                "index-out-of-range",
+            ),
+            (
+                "panic: runtime error: slice bounds out of range [:9] with capacity 4",
+                "slice-bounds-out-of-range",
+            ),
+            (
+                "panic: runtime error: invalid memory address or nil pointer dereference",
+                "nil-dereference",
+            ),
+            (
+                "panic: runtime error: integer divide by zero",
+                "integer-divide-by-zero",
+            ),
+            (
+                "panic: interface conversion: interface {} is string, not int",
+                "interface-conversion",
+            ),
+            ("panic: assignment to entry in nil map", "nil-map-write"),
+            (
+                "panic: runtime error: hash of unhashable type []uint8",
+                RUNTIME_ERROR_KIND,
+            ),
+            ("panic: bad hex char", PANIC_KIND),
+            ("panic: bad hex char [recovered]", PANIC_KIND),
+            (
+                "fatal error: concurrent map writes",
+                "concurrent-map-access",
+            ),
+            (
+                "fatal error: all goroutines are asleep - deadlock!",
+                "deadlock",
+            ),
+            ("fatal error: stack overflow", "stack-overflow"),
+            ("fatal error: error message here", FATAL_ERROR_KIND),
+        ];
+
+        for (header, expected) in test_cases {
+            assert_eq!(panic(header).kind(), expected, "{header}");
+        }
+
+        assert_eq!(
+            panic("panic: bad hex char [recovered]").header,
+            "panic: bad hex char"
+        );
+    }
+
+    #[test]
+    fn test_not_go_panic() {
+        // Without a traceback.
+        assert!(parse_panic("panic: bad hex char\n").is_none());
+
+        // Of a crash other than an abort.
+        let text = format!(
+            "panic: bad hex char\n\ngoroutine 1 [running]:\n{}",
+            include_str!("../data/stack-traces/libfuzzer-asan-log.txt")
+        );
+        assert!(parse_panic(&text).is_none());
+    }
+
+    #[test]
+    fn test_fatal_error() {
+        let log = parse(FATAL_ERROR);
+        assert_eq!(log.sanitizer, SANITIZER);
+        assert_eq!(log.fault_type, "concurrent-map-access");
+        assert_eq!(
+            log.summary,
+            "fatal error: concurrent map writes /src/cache/cache.go:31 in github.com/contoso/cache.(*Cache).Put"
+        );
+
+        let goroutines: Vec<_> = log
+            .goroutines
+            .iter()
+            .map(|g| (g.id, g.state.as_str(), g.stack.len()))
+            .collect();
+        assert_eq!(
+            goroutines,
+            [(7, "running", 3), (1, "semacquire", 4), (8, "runnable", 2)]
+        );
+
+        // Only the crashed goroutine is of the minimized stack, but all are of
+        // the full stack.
+        assert_eq!(
+            log.minimized_stack,
+            [
+                "#0 github.com/contoso/cache.(*Cache).Put /src/cache/cache.go:31",
+                "#1 github.com/contoso/cache.(*Cache).Fill /src/cache/cache.go:48",
+                "#2 created by github.com/contoso/cache.FillAll /src/cache/cache.go:62",
+            ]
+            .map(String::from)
+            .to_vec()
+        );
+        assert_eq!(log.full_stack_details.len(), 9);
+        assert_eq!(log.call_stack.len(), 9);
+        assert_eq!(
+            log.call_stack[3],
+            "#0 sync.runtime_Semacquire /usr/local/go/src/runtime/sema.go:62"
+        );
+    }
+
+    #[test]
+    fn test_stable_call_stack() {
+        let log = parse(FATAL_ERROR);
+
+        // Of another run, with other goroutines, arguments and offsets.
+        let text = FATAL_ERROR
+            .replace("goroutine 7 [running]", "goroutine 21 [running]")
+            .replace(
+                "goroutine 1 [semacquire]",
+                "goroutine 1 [semacquire, 2 minutes]",
+            )
+            .replace("0x10c0000a2000", "0x10c000118000")
+            .replace("+0x14c", "+0x150");
+        let text = text.split("\n\ngoroutine 8 ").next().unwrap();
+        let other = parse(text);
+
+        assert_eq!(other.goroutines.len(), 2);
+        assert_ne!(other.call_stack, log.call_stack);
+        assert_eq!(other.call_stack_sha256(), log.call_stack_sha256());
+        assert_eq!(
+            other.minimized_stack_function_lines_sha256(None),
+            log.minimized_stack_function_lines_sha256(None)
+        );
+    }
+
+    #[test]
+    fn test_libfuzzer_panic() {
+        let log = parse(FUZZ_PANIC);
+        assert_eq!(log.sanitizer, SANITIZER);
+        assert_eq!(log.fault_type, "index-out-of-range");
+
+        // Without the frames of the harness, shim, and runtime.
+        assert_eq!(
+            log.minimized_stack_function_names,
+            [
+                "github.com/contoso/header.parseField",
+                "github.com/contoso/header.Parse",
+                "github.com/contoso/header.FuzzParse",
+            ]
+            .map(String::from)
+            .to_vec()
+        );
+        assert_eq!(
+            log.minimized_stack_function_lines[0],
+            "github.com/contoso/header.parseField field.go:42"
+        );
+    }
+
+    #[test]
+    fn test_sigsegv() {
+        let log = parse(SIGSEGV);
+        assert_eq!(log.fault_type, "nil-dereference");
+        assert_eq!(
+            log.summary,
+            "panic: runtime error: invalid memory address or nil pointer dereference /src/decode/decoder.go:88 in github.com/contoso/decode.(*Decoder).readUint32"
+        );
+        assert_eq!(log.goroutines.len(), 1);
+
+        // Named with their receivers.
+        assert_eq!(
+            log.minimized_stack_function_names[..2],
+            [
+                "github.com/contoso/decode.(*Decoder).readUint32",
+                "github.com/contoso/decode.(*Decoder).Header",
+            ]
+        );
+    }
+}
@@ -0,0 +1,432 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use anyhow::Result;
+use regex::{NoExpand, Regex, RegexSet};
+
+use crate::StackEntry;
+
+pub(crate) const SANITIZER: &str = "Java";
+
+// The header of the exception of a finding of Jazzer.
+const JAZZER_HEADER: &str = "== Java Exception: ";
+
+const CAUSED_BY: &str = "Caused by: ";
+const SUPPRESSED: &str = "Suppressed: ";
+
+/// Frames of Jazzer, of reflection, of constructing exceptions of the JDK, and
+/// of the synthetic classes of lambdas, which are on the stacks of most
+/// exceptions of JVM fuzzing targets.
+pub(crate) const STACK_FRAME_IGNORE_REGEXES: &[&str] = &[
+    r"^com\.code_intelligence\.jazzer\.",
+    r"^java\.lang\.reflect\.",
+    r"^java\.lang\.invoke\.",
+    r"^jdk\.internal\.reflect\.",
+    r"^sun\.reflect\.",
+    r"^java\.lang\.[\w$]+(?:Exception|Error)\.",
+    r"\$\$Lambda\.",
+];
+
+lazy_static::lazy_static! {
+    pub(crate) static ref STACK_FILTER: RegexSet =
+        RegexSet::new(STACK_FRAME_IGNORE_REGEXES).unwrap();
+
+    // "== Java Exception: java.lang.IllegalStateException: message", as of
+    // Jazzer, "Exception in thread "main" java.lang.Error", or the bare
+    // "com.example.FooException: message"
+    static ref HEADER_REGEX: Regex = Regex::new(
+        r#"^(?:== Java Exception: |Exception in thread "[^"]*" )?(?P<class>[\w$]+(?:\.[\w$]+)+)(?:: (?P<message>.*?))?\s*$"#
+    )
+    .unwrap();
+
+    // "\tat com.example.Foo.bar(Foo.java:12)", of which the class may be
+    // prefixed by its class loader and module, as in
+    // "\tat java.base/java.lang.Integer.parseInt(Integer.java:668)"
+    static ref FRAME_REGEX: Regex = Regex::new(
+        r"^\s+at (?P<prefix>(?:[^\s/()]*/){1,2})?(?P<function>[^\s/()]+)\((?P<source>[^()]*)\)(?:\s.*)?$"
+    )
+    .unwrap();
+
+    // "\t... 9 more", of the frames in common with the exception caused
+    static ref MORE_REGEX: Regex = Regex::new(r"^\s+\.\.\. (?P<more>\d+) more\s*$").unwrap();
+
+    // "Foo.java:12", or "Foo.java", but not "Native Method" or "Unknown Source"
+    static ref SOURCE_REGEX: Regex = Regex::new(r"^(?P<file>[^\s:]+)(?::(?P<line>\d+))?$").unwrap();
+
+    // The synthetic classes of lambdas, and of the accessors of reflection,
+    // whose names are numbered or addressed differently by each run.
+    static ref LAMBDA_REGEX: Regex =
+        Regex::new(r"\$\$Lambda(?:\$\d+)?/(?:0x)?[0-9a-fA-F]+").unwrap();
+    static ref ACCESSOR_REGEX: Regex =
+        Regex::new(r"(?P<accessor>Generated(?:Serialization)?(?:Method|Constructor)Accessor)\d+").unwrap();
+}
+
+/// An exception of a Java stack trace.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Exception {
+    pub class: String,
+
+    /// The first line of the message of the exception.
+    pub message: Option<String>,
+
+    /// The frames of the exception, including those only logged of the
+    /// exception it caused.
+    pub stack: Vec<StackEntry>,
+}
+
+impl Exception {
+    pub fn headline(&self) -> String {
+        match &self.message {
+            Some(message) => format!("{}: {message}", self.class),
+            None => self.class.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Frame {
+    function: String,
+    module: Option<String>,
+    source: String,
+}
+
+// An exception as logged, of which the last `more` frames are those of the
+// exception it caused.
+struct Logged<'a> {
+    class: &'a str,
+    message: Option<&'a str>,
+    frames: Vec<Frame>,
+    more: usize,
+}
+
+/// The first uncaught exception of `text`, as logged by Jazzer or by the JVM,
+/// and the chain of its causes, outermost first. The last is where the
+/// exception was first thrown.
+///
+/// Suppressed exceptions and the notes Jazzer logs after the exception, such
+/// as of its reproducer, are not of the chain.
+pub(crate) fn parse_exceptions(text: &str) -> Result<Vec<Exception>> {
+    let lines: Vec<&str> = text.lines().collect();
+    let Some(start) = find_header(&lines) else {
+        return Ok(vec![]);
+    };
+    let Some(thrown) = parse_header(lines[start]) else {
+        return Ok(vec![]);
+    };
+
+    let mut chain = vec![thrown];
+    let mut suppressed = false;
+    for line in lines[start + 1..].iter().copied() {
+        if let Some(cause) = line.strip_prefix(CAUSED_BY) {
+            let Some(cause) = parse_header(cause) else {
+                break;
+            };
+            chain.push(cause);
+            suppressed = false;
+            continue;
+        }
+
+        let Some(current) = chain.last_mut() else {
+            break;
+        };
+        if suppressed && line.starts_with(char::is_whitespace) {
+            continue;
+        }
+
+        if let Some(frame) = parse_frame(line) {
+            current.frames.push(frame);
+        } else if let Some(captures) = MORE_REGEX.captures(line) {
+            current.more = captures["more"].parse()?;
+        } else if line.trim_start().starts_with(SUPPRESSED) {
+            suppressed = true;
+        } else if !current.frames.is_empty() || current.more > 0 {
+            break;
+        }
+        // Otherwise, a line of a message of several lines.
+    }
+
+    let mut exceptions = vec![];
+    let mut enclosing: Vec<Frame> = vec![];
+    for logged in chain {
+        let mut frames = logged.frames;
+        let common = logged.more.min(enclosing.len());
+        frames.extend_from_slice(&enclosing[enclosing.len() - common..]);
+
+        exceptions.push(Exception {
+            class: logged.class.to_string(),
+            message: logged.message.map(String::from),
+            stack: stack(&frames)?,
+        });
+        enclosing = frames;
+    }
+
+    Ok(exceptions)
+}
+
+// The header of the exception of Jazzer, or else of the first frame logged.
+fn find_header(lines: &[&str]) -> Option<usize> {
+    if let Some(start) = lines
+        .iter()
+        .position(|line| line.starts_with(JAZZER_HEADER))
+    {
+        return Some(start);
+    }
+
+    let first_frame = lines.iter().position(|line| parse_frame(line).is_some())?;
+    lines[..first_frame]
+        .iter()
+        .rposition(|line| HEADER_REGEX.is_match(line))
+}
+
+fn parse_header(line: &str) -> Option<Logged> {
+    let captures = HEADER_REGEX.captures(line)?;
+    Some(Logged {
+        class: captures.name("class")?.as_str(),
+        message: captures.name("message").map(|x| x.as_str()),
+        frames: vec![],
+        more: 0,
+    })
+}
+
+fn parse_frame(line: &str) -> Option<Frame> {
+    // The names of synthetic classes of lambdas may have a '/', as of their
+    // address, which would be parsed as of a module.
+    let line = LAMBDA_REGEX.replace_all(line, NoExpand("$$Lambda"));
+    let line = ACCESSOR_REGEX.replace_all(&line, "$accessor");
+    let captures = FRAME_REGEX.captures(&line)?;
+
+    // Of "loader/module/", or of "module/".
+    let module = captures
+        .name("prefix")
+        .and_then(|prefix| prefix.as_str().split('/').rev().nth(1))
+        .filter(|module| !module.is_empty());
+
+    Some(Frame {
+        function: captures["function"].to_string(),
+        module: module.map(String::from),
+        source: captures["source"].to_string(),
+    })
+}
+
+// The frames numbered and logged without the class loaders and modules of
+// their classes, which are of the version of the JVM.
+fn stack(frames: &[Frame]) -> Result<Vec<StackEntry>> {
+    let mut stack = vec![];
+    for (i, frame) in frames.iter().enumerate() {
+        let mut line = format!("#{i} {}", frame.function);
+
+        let mut entry = StackEntry {
+            function_name: Some(frame.function.clone()),
+            module_path: frame.module.clone(),
+            ..StackEntry::default()
+        };
+
+        if let Some(captures) = SOURCE_REGEX.captures(&frame.source) {
+            let file = &captures["file"];
+            line.push_str(&format!(" {file}"));
+            entry.source_file_path = Some(file.to_string());
+            entry.source_file_name = Some(file.to_string());
+
+            if let Some(file_line) = captures.name("line") {
+                line.push_str(&format!(":{}", file_line.as_str()));
+                entry.source_file_line = Some(file_line.as_str().parse()?);
+            }
+        }
+
+        entry.line = line;
+        stack.push(entry);
+    }
+
+    Ok(stack)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CrashLog;
+    use pretty_assertions::assert_eq;
+
+    const JAZZER_CAUSED_BY: &str =
+        include_str!("../data/stack-traces/jazzer-exception-caused-by-lambda.txt");
+    const UNCAUGHT_SUPPRESSED: &str =
+        include_str!("../data/stack-traces/java-uncaught-exception-suppressed.txt");
+
+    fn function_names(stack: &[StackEntry]) -> Vec<&str> {
+        stack
+            .iter()
+            .filter_map(|entry| entry.function_name.as_deref())
+            .collect()
+    }
+
+    #[test]
+    fn test_jazzer_caused_by() -> Result<()> {
+        let exceptions = parse_exceptions(JAZZER_CAUSED_BY)?;
+
+        let headlines: Vec<_> = exceptions.iter().map(Exception::headline).collect();
+        assert_eq!(
+            headlines,
+            [
+                "java.lang.RuntimeException: com.example.config.ConfigException: invalid entry at line 2",
+                "com.example.config.ConfigException: invalid entry at line 2",
+                "java.lang.NumberFormatException: For input string: \"0x1g\"",
+            ]
+        );
+
+        // The frames elided as in common with the exception caused are of
+        // the stack of each cause.
+        let stack_sizes: Vec<_> = exceptions.iter().map(|x| x.stack.len()).collect();
+        assert_eq!(stack_sizes, [10, 12, 15]);
+
+        let innermost = &exceptions[2].stack;
+        assert_eq!(
+            function_names(&innermost[3..7]),
+            [
+                "com.example.config.Entry.parse",
+                "com.example.config.ConfigParser$$Lambda.apply",
+                "com.example.config.ConfigParser.lambda$parse$0",
+                "java.util.stream.ReferencePipeline$3$1.accept",
+            ]
+        );
+        assert_eq!(
+            innermost.last().map(|x| x.line.as_str()),
+            Some(
+                "#14 com.example.ConfigParserFuzzer.fuzzerTestOneInput ConfigParserFuzzer.java:17"
+            )
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jazzer_crash_log() -> Result<()> {
+        let log = CrashLog::parse(JAZZER_CAUSED_BY.to_owned())?;
+        assert_eq!(log.sanitizer, SANITIZER);
+        assert_eq!(log.fault_type, "java.lang.RuntimeException");
+        assert_eq!(
+            log.summary,
+            "java.lang.RuntimeException: com.example.config.ConfigException: invalid entry at line 2 Integer.java:668 in java.lang.Integer.parseInt"
+        );
+
+        // Of the innermost cause, without the frames of constructing the
+        // exception, or of the synthetic class of the lambda.
+        assert_eq!(log.call_stack.len(), 15);
+        assert_eq!(
+            log.minimized_stack_function_names[..5],
+            [
+                "java.lang.Integer.parseInt",
+                "java.lang.Integer.parseInt",
+                "com.example.config.Entry.parse",
+                "com.example.config.ConfigParser.lambda$parse$0",
+                "java.util.stream.ReferencePipeline$3$1.accept",
+            ]
+        );
+
+        // Of the reproducer noted by Jazzer, nothing is of the stack.
+        assert!(!log.call_stack.iter().any(|line| line.contains("Crash_")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_suppressed_and_modules() -> Result<()> {
+        let exceptions = parse_exceptions(UNCAUGHT_SUPPRESSED)?;
+
+        let headlines: Vec<_> = exceptions.iter().map(Exception::headline).collect();
+        assert_eq!(
+            headlines,
+            [
+                "java.lang.IllegalStateException: corrupt header",
+                "java.nio.BufferUnderflowException",
+            ]
+        );
+
+        // Without the frames of the suppressed exception.
+        let thrown = &exceptions[0].stack;
+        assert_eq!(thrown.len(), 9);
+        assert!(!function_names(thrown).contains(&"org.example.codec.Decoder.close"));
+
+        let main = &thrown[8];
+        assert_eq!(
+            main.line,
+            "#8 org.example.fuzz.Launcher.main Launcher.java:12"
+        );
+        assert_eq!(main.module_path, None);
+
+        let accessor = &thrown[5];
+        assert_eq!(
+            accessor.function_name.as_deref(),
+            Some("jdk.internal.reflect.DirectMethodHandleAccessor.invoke")
+        );
+        assert_eq!(accessor.module_path.as_deref(), Some("java.base"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_synthetic_frames() -> Result<()> {
+        let trace = |lambda: &str, accessor: &str| {
+            format!(
+                "java.lang.ArithmeticException: / by zero\n\
+                 \tat com.example.Math.lambda$divide$0(Math.java:9)\n\
+                 \tat com.example.Math$${lambda}.applyAsInt(Unknown Source)\n\
+                 \tat com.example.Math.divide(Math.java:10)\n\
+                 \tat jdk.internal.reflect.{accessor}.invoke(Unknown Source)\n\
+                 \tat com.example.Main.main(Native Method)\n"
+            )
+        };
+
+        let first = parse_exceptions(&trace(
+            "Lambda$14/0x0000000800c03000",
+            "GeneratedMethodAccessor12",
+        ))?;
+        let second = parse_exceptions(&trace(
+            "Lambda/0x000071b3c8003a00",
+            "GeneratedMethodAccessor3",
+        ))?;
+        assert_eq!(first, second);
+
+        let lines: Vec<_> = first[0].stack.iter().map(|x| x.line.as_str()).collect();
+        assert_eq!(
+            lines,
+            [
+                "#0 com.example.Math.lambda$divide$0 Math.java:9",
+                "#1 com.example.Math$$Lambda.applyAsInt",
+                "#2 com.example.Math.divide Math.java:10",
+                "#3 jdk.internal.reflect.GeneratedMethodAccessor.invoke",
+                "#4 com.example.Main.main",
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_of_several_lines() -> Result<()> {
+        let text = "== Java Exception: com.code_intelligence.jazzer.api.FuzzerSecurityIssueCritical: Remote Code Execution\n\
+                    Unrestricted class/object creation based on externally controlled data may allow\n\
+                    remote code execution depending on available classes on the classpath.\n\
+                    \tat jaz.Zer.reportFinding(Zer.java:108)\n\
+                    \tat com.example.ObjectStreamFuzzer.fuzzerTestOneInput(ObjectStreamFuzzer.java:25)\n\
+                    DEDUP_TOKEN: 3a4e0f4d1b19ce2a\n";
+
+        let exceptions = parse_exceptions(text)?;
+        assert_eq!(exceptions.len(), 1);
+        assert_eq!(
+            exceptions[0].headline(),
+            "com.code_intelligence.jazzer.api.FuzzerSecurityIssueCritical: Remote Code Execution"
+        );
+        assert_eq!(exceptions[0].stack.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_no_exception() -> Result<()> {
+        assert!(
+            parse_exceptions("INFO: Seed: 1492063514\nDone 100 runs in 1 second(s)\n")?.is_empty()
+        );
+        assert!(CrashLog::parse("INFO: Seed: 1492063514\n".to_owned()).is_err());
+
+        Ok(())
+    }
+}
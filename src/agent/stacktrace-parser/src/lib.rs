@@ -9,6 +9,20 @@ use sha2::{Digest, Sha256};
 
 mod asan;
 mod dotnet;
+mod exploitability;
+mod go;
+mod java;
+mod normalize;
+mod report_type;
+mod rust;
+mod tsan;
+mod ubsan;
+
+pub use exploitability::{Exploitability, ExploitabilityAssessment};
+pub use go::Goroutine;
+pub use normalize::StackNormalization;
+pub use report_type::ReportType;
+pub use tsan::{DataRace, RaceAccess};
 
 #[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct StackEntry {
@@ -40,6 +54,35 @@ pub struct StackEntry {
 }
 
 impl StackEntry {
+    /// Rewrite the source path of the entry, in both its fields and its line.
+    fn map_source_path(&mut self, map: impl Fn(&str) -> Option<String>) {
+        let Some(path) = &self.source_file_path else {
+            return;
+        };
+        let Some(mapped) = map(path) else {
+            return;
+        };
+
+        self.line = self.line.replacen(path.as_str(), &mapped, 1);
+        self.source_file_path = Some(mapped);
+    }
+
+    /// Redact the strings of the entry with `redact`.
+    fn redact(&mut self, redact: impl Fn(&str) -> String) {
+        self.line = redact(&self.line);
+        for field in [
+            &mut self.function_name,
+            &mut self.source_file_name,
+            &mut self.source_file_path,
+            &mut self.module_path,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            *field = redact(field);
+        }
+    }
+
     fn function_line_entry(&self) -> Option<String> {
         let mut parts = vec![];
         if let Some(function_name) = &self.function_name {
@@ -102,6 +145,14 @@ pub struct CrashLog {
     #[serde(default)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub scariness_description: Option<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data_race: Option<DataRace>,
+    /// The goroutines of a Go traceback, the crashed one first. The stacks of
+    /// the others are only of the full stack.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub goroutines: Vec<Goroutine>,
 }
 
 fn function_without_args(func: &str) -> String {
@@ -139,7 +190,11 @@ fn filter_funcs(entry: &StackEntry, stack_filter: &RegexSet) -> Option<StackEntr
                 return Some(entry);
             }
         }
-        if stack_filter.is_match(name) {
+        if stack_filter.is_match(name)
+            || rust::STACK_FILTER.is_match(name)
+            || go::STACK_FILTER.is_match(name)
+            || java::STACK_FILTER.is_match(name)
+        {
             return None;
         }
     }
@@ -153,6 +208,38 @@ fn filter_funcs(entry: &StackEntry, stack_filter: &RegexSet) -> Option<StackEntr
     Some(entry)
 }
 
+fn minimize_stack(stack: &[StackEntry]) -> Vec<StackEntry> {
+    let stack_filter = get_stack_filter();
+    let mut minimized_stack_details: Vec<StackEntry> = stack
+        .iter()
+        .filter_map(|x| filter_funcs(x, stack_filter))
+        .collect();
+    // if we don't have a minimized stack, if one of these functions is on
+    // the stack, use it
+    for entry in [
+        "LLVMFuzzerTestOneInput",
+        "fuzzer::RunOneTest(fuzzer::Fuzzer*, char const*, unsigned long)",
+        "main",
+    ] {
+        if !minimized_stack_details.is_empty() {
+            break;
+        }
+        let value = Some(entry.to_string());
+        minimized_stack_details = stack
+            .iter()
+            .filter_map(|x| {
+                if x.function_name == value {
+                    Some(x.clone())
+                } else {
+                    None
+                }
+            })
+            .collect();
+    }
+
+    minimized_stack_details
+}
+
 impl CrashLog {
     pub fn new(
         text: Option<String>,
@@ -163,33 +250,7 @@ impl CrashLog {
         scariness_description: Option<String>,
         stack: Vec<StackEntry>,
     ) -> Result<Self> {
-        let stack_filter = get_stack_filter();
-        let mut minimized_stack_details: Vec<StackEntry> = stack
-            .iter()
-            .filter_map(|x| filter_funcs(x, stack_filter))
-            .collect();
-        // if we don't have a minimized stack, if one of these functions is on
-        // the stack, use it
-        for entry in [
-            "LLVMFuzzerTestOneInput",
-            "fuzzer::RunOneTest(fuzzer::Fuzzer*, char const*, unsigned long)",
-            "main",
-        ] {
-            if !minimized_stack_details.is_empty() {
-                break;
-            }
-            let value = Some(entry.to_string());
-            minimized_stack_details = stack
-                .iter()
-                .filter_map(|x| {
-                    if x.function_name == value {
-                        Some(x.clone())
-                    } else {
-                        None
-                    }
-                })
-                .collect();
-        }
+        let minimized_stack_details = minimize_stack(&stack);
 
         let call_stack = stack_lines(&stack);
         let full_stack_names = stack_names(&stack);
@@ -221,12 +282,81 @@ impl CrashLog {
             minimized_stack_function_names,
             minimized_stack_details,
             minimized_stack_function_lines,
+            data_race: None,
+            goroutines: vec![],
         })
     }
 
+    /// A crash of a target without a sanitizer, caught by a debugger as the
+    /// delivery of `signal`, such as `SIGSEGV`, of the `text` the debugger
+    /// logs, sited at the first frame of its minimized stack. Its fault type
+    /// is the signal, or the access of its faulting instruction, such as
+    /// `write-access-violation`.
+    pub fn from_signal(
+        text: String,
+        signal: String,
+        fault_type: String,
+        stack: Vec<StackEntry>,
+    ) -> Result<Self> {
+        // The offsets of the frames are into their functions, rather than the
+        // columns of their source lines, as those of sanitizers are.
+        let site = minimize_stack(&stack).first().and_then(|frame| {
+            asan::crash_site(&StackEntry {
+                function_offset: None,
+                ..frame.clone()
+            })
+        });
+        let summary = match site {
+            Some(site) => format!("{fault_type} {site}"),
+            None => fault_type.clone(),
+        };
+
+        Self::new(
+            Some(text),
+            Some(summary),
+            signal,
+            fault_type,
+            None,
+            None,
+            stack,
+        )
+    }
+
+    /// Parse the crash of a log. Of a log of several UBSan findings, such as
+    /// of targets run with `halt_on_error=0`, the crash is the first. Of a
+    /// Rust or Go panic, which sanitizers may report as an abort, the crash is
+    /// the panic. Logs without a sanitizer report are parsed as of an uncaught
+    /// Java exception, such as of Jazzer.
     pub fn parse(text: String) -> Result<Self> {
-        let summary = parse_summary(&text)?;
-        let stack = parse_call_stack(&text).unwrap_or_default();
+        if let Some(race) = tsan::parse_data_race(&text)? {
+            return Self::parse_data_race(text, race);
+        }
+
+        if let Some(panic) = rust::parse_panic(&text) {
+            return Self::parse_panic(text, panic);
+        }
+
+        if let Some(panic) = go::parse_panic(&text) {
+            return Self::parse_go_panic(text, panic);
+        }
+
+        let summary = match parse_summary(&text) {
+            Ok(summary) => summary,
+            Err(err) => {
+                let exceptions = java::parse_exceptions(&text)?;
+                if exceptions.is_empty() {
+                    return Err(err);
+                }
+                return Self::parse_java_exception(text, exceptions);
+            }
+        };
+
+        // Only the stack of the first finding, which may have none.
+        let findings = ubsan::findings(&text);
+        let stack_text = findings.first().copied().unwrap_or(text.as_str());
+        let stack = parse_call_stack(stack_text).unwrap_or_default();
+
+        let summary = asan::with_crash_site(summary, &stack);
         let (scariness_score, scariness_description) = parse_scariness(&text);
         Self::new(
             Some(text),
@@ -239,10 +369,289 @@ impl CrashLog {
         )
     }
 
+    /// A data race, of the stack of its write, sited at the first frame of
+    /// its minimized stack.
+    fn parse_data_race(text: String, race: DataRace) -> Result<Self> {
+        let stack = race
+            .write()
+            .map(|access| access.stack.clone())
+            .unwrap_or_default();
+
+        let summary = match minimize_stack(&stack).first().and_then(asan::crash_site) {
+            Some(site) => format!("{}: {} {site}", tsan::SANITIZER, tsan::DATA_RACE),
+            None => format!("{}: {}", tsan::SANITIZER, tsan::DATA_RACE),
+        };
+
+        let mut log = Self::new(
+            Some(text),
+            Some(summary),
+            tsan::SANITIZER.to_string(),
+            tsan::DATA_RACE.to_string(),
+            None,
+            None,
+            stack,
+        )?;
+        log.data_race = Some(race);
+
+        Ok(log)
+    }
+
+    /// A panic, of the stack of its backtrace, or else of the sanitizer
+    /// report of its abort.
+    fn parse_panic(text: String, panic: rust::Panic) -> Result<Self> {
+        let mut stack = rust::parse_backtrace(&text)?;
+        if stack.is_empty() {
+            stack = parse_call_stack(&text).unwrap_or_default();
+        }
+
+        let summary = rust::summary(&panic);
+        let (scariness_score, scariness_description) = parse_scariness(&text);
+        Self::new(
+            Some(text),
+            Some(summary.summary),
+            summary.sanitizer,
+            summary.fault_type,
+            scariness_score,
+            scariness_description,
+            stack,
+        )
+    }
+
+    /// A Go panic or fatal error, of the stack of its goroutine, sited at the
+    /// first frame of its minimized stack.
+    fn parse_go_panic(text: String, panic: go::Panic) -> Result<Self> {
+        let goroutines = go::parse_goroutines(&text)?;
+        let stack = goroutines
+            .first()
+            .map(|goroutine| goroutine.stack.clone())
+            .unwrap_or_default();
+
+        let summary = match minimize_stack(&stack).first().and_then(asan::crash_site) {
+            Some(site) => format!("{} {site}", panic.header),
+            None => panic.header.clone(),
+        };
+
+        let (scariness_score, scariness_description) = parse_scariness(&text);
+        let mut log = Self::new(
+            Some(text),
+            Some(summary),
+            go::SANITIZER.to_string(),
+            panic.kind().to_string(),
+            scariness_score,
+            scariness_description,
+            stack,
+        )?;
+
+        for goroutine in goroutines.iter().skip(1) {
+            log.full_stack_details.extend_from_slice(&goroutine.stack);
+        }
+        log.call_stack = stack_lines(&log.full_stack_details);
+        log.full_stack_names = go::stack_names(&log.full_stack_details);
+        log.minimized_stack_function_names = go::stack_names(&log.minimized_stack_details);
+        log.goroutines = goroutines;
+
+        Ok(log)
+    }
+
+    /// An uncaught Java exception, of the stack of its innermost cause, where
+    /// it was first thrown, sited at the first frame of its minimized stack.
+    fn parse_java_exception(text: String, exceptions: Vec<java::Exception>) -> Result<Self> {
+        let thrown = exceptions
+            .first()
+            .ok_or_else(|| anyhow::format_err!("no Java exception"))?;
+        let stack = exceptions
+            .last()
+            .map(|cause| cause.stack.clone())
+            .unwrap_or_default();
+
+        let summary = match minimize_stack(&stack).first().and_then(asan::crash_site) {
+            Some(site) => format!("{} {site}", thrown.headline()),
+            None => thrown.headline(),
+        };
+
+        Self::new(
+            Some(text),
+            Some(summary),
+            java::SANITIZER.to_string(),
+            thrown.class.clone(),
+            None,
+            None,
+            stack,
+        )
+    }
+
+    /// Parse each finding of a log, deduplicated by their summaries and
+    /// stacks. UBSan logs several findings, as does TSan of several races, and
+    /// each is parsed from its own part of the log. Other logs are parsed as
+    /// by [`CrashLog::parse()`].
+    pub fn parse_all(text: String) -> Result<Vec<Self>> {
+        let mut findings = ubsan::findings(&text);
+        if findings.is_empty() {
+            findings = tsan::reports(&text);
+        }
+        if findings.len() < 2 {
+            return Ok(vec![Self::parse(text)?]);
+        }
+
+        let mut logs: Vec<Self> = vec![];
+        for finding in findings {
+            let log = Self::parse(finding.to_string())?;
+
+            // Races are the same of either of their accesses, but are sited
+            // at only one.
+            let duplicate = logs.iter().any(|other| {
+                other.call_stack_sha256() == log.call_stack_sha256()
+                    && (log.data_race.is_some() || other.summary == log.summary)
+            });
+            if !duplicate {
+                logs.push(log);
+            }
+        }
+
+        Ok(logs)
+    }
+
+    /// Rewrite the source paths of the stacks with `map`, such as from the
+    /// paths of a CI build to those of a checkout of its sources. Paths `map`
+    /// returns `None` for are kept.
+    ///
+    /// The hashes of the stacks are of the rewritten lines, so to deduplicate
+    /// crashes by the stacks as logged, hash them before rewriting.
+    pub fn map_source_paths(&mut self, map: impl Fn(&str) -> Option<String>) {
+        let race_stacks = self
+            .data_race
+            .iter_mut()
+            .flat_map(|race| &mut race.accesses)
+            .flat_map(|access| &mut access.stack);
+        let goroutine_stacks = self
+            .goroutines
+            .iter_mut()
+            .flat_map(|goroutine| &mut goroutine.stack);
+
+        for entry in self
+            .full_stack_details
+            .iter_mut()
+            .chain(&mut self.minimized_stack_details)
+            .chain(race_stacks)
+            .chain(goroutine_stacks)
+        {
+            entry.map_source_path(&map);
+        }
+
+        self.call_stack = stack_lines(&self.full_stack_details);
+        self.minimized_stack = stack_lines(&self.minimized_stack_details);
+    }
+
+    /// Redact the strings of the log with `redact`, such as the user names of
+    /// paths, in its text, summary and stacks.
+    ///
+    /// As with [`CrashLog::map_source_paths()`], the hashes of the stacks are
+    /// of the redacted lines.
+    pub fn redact(&mut self, redact: impl Fn(&str) -> String) {
+        self.text = self.text.as_deref().map(&redact);
+        self.summary = redact(&self.summary);
+        self.scariness_description = self.scariness_description.as_deref().map(&redact);
+
+        for line in self
+            .call_stack
+            .iter_mut()
+            .chain(&mut self.full_stack_names)
+            .chain(&mut self.minimized_stack)
+            .chain(&mut self.minimized_stack_function_names)
+            .chain(&mut self.minimized_stack_function_lines)
+        {
+            *line = redact(line);
+        }
+
+        let race_stacks = self
+            .data_race
+            .iter_mut()
+            .flat_map(|race| &mut race.accesses)
+            .flat_map(|access| &mut access.stack);
+        let goroutine_stacks = self
+            .goroutines
+            .iter_mut()
+            .flat_map(|goroutine| &mut goroutine.stack);
+
+        for entry in self
+            .full_stack_details
+            .iter_mut()
+            .chain(&mut self.minimized_stack_details)
+            .chain(race_stacks)
+            .chain(goroutine_stacks)
+        {
+            entry.redact(&redact);
+        }
+    }
+
+    /// Hash of the call stack, or of a data race, of the minimized stacks of
+    /// both its accesses, as by [`DataRace::stacks_sha256()`].
     pub fn call_stack_sha256(&self) -> String {
+        if let Some(race) = &self.data_race {
+            return race.stacks_sha256();
+        }
+
+        // Only the stack of the crashed goroutine, of which the others may be
+        // of any state.
+        if let Some(goroutine) = self.goroutines.first() {
+            return digest_iter(stack_lines(&goroutine.stack), None);
+        }
+
         digest_iter(&self.call_stack, None)
     }
 
+    /// Normalize the minimized stacks by `normalization`, before they are
+    /// hashed, to deduplicate crashes which differ in only the normalized
+    /// details of their frames.
+    pub fn normalize_minimized_stack(&mut self, normalization: &StackNormalization) -> Result<()> {
+        if normalization.is_empty() {
+            return Ok(());
+        }
+
+        self.minimized_stack_details = normalization.normalize(&self.minimized_stack_details)?;
+        self.minimized_stack = stack_lines(&self.minimized_stack_details);
+        self.minimized_stack_function_names = if self.goroutines.is_empty() {
+            stack_names(&self.minimized_stack_details)
+        } else {
+            go::stack_names(&self.minimized_stack_details)
+        };
+        self.minimized_stack_function_lines = stack_function_lines(&self.minimized_stack_details);
+
+        Ok(())
+    }
+
+    /// The call stack normalized by `normalization`, as lines, if its
+    /// `normalize_call_stack` is set. Of a Go traceback, only the stack of the
+    /// crashed goroutine, as hashed by [`CrashLog::call_stack_sha256()`]. Data
+    /// races have none, as their stacks are hashed as minimized.
+    pub fn normalized_call_stack(
+        &self,
+        normalization: &StackNormalization,
+    ) -> Result<Option<Vec<String>>> {
+        if !normalization.normalize_call_stack || self.data_race.is_some() {
+            return Ok(None);
+        }
+
+        let stack = match self.goroutines.first() {
+            Some(goroutine) => &goroutine.stack,
+            None => &self.full_stack_details,
+        };
+
+        Ok(Some(stack_lines(&normalization.normalize(stack)?)))
+    }
+
+    /// A first-pass rating of how likely the crash is to be exploitable, by
+    /// its fault type, and by the access and address of the crash, as logged.
+    pub fn exploitability(&self) -> ExploitabilityAssessment {
+        exploitability::assess(self)
+    }
+
+    /// The type of finding the log reports: a crash, or else a finding such
+    /// as an OOM or a leak, by its sanitizer and fault type.
+    pub fn report_type(&self) -> ReportType {
+        report_type::classify(self)
+    }
+
     pub fn minimized_stack_sha256(&self, depth: Option<usize>) -> String {
         digest_iter(&self.minimized_stack, depth)
     }
@@ -284,6 +693,7 @@ fn parse_summary(text: &str) -> Result<CrashLogSummary> {
     // dotnet should be parsed first to try to extract a .NET exception stack trace
     // since this is a specialization of an ASAN dump
     dotnet::parse_summary(text)
+        .or_else(|| ubsan::parse_summary(text))
         .or_else(|| asan::parse_summary(text))
         .ok_or(anyhow::format_err!("unable to parse crash log summary"))
 }
@@ -328,7 +738,7 @@ pub fn digest_iter(
 
 #[cfg(test)]
 mod tests {
-    use super::CrashLog;
+    use super::{CrashLog, StackEntry};
     use anyhow::Context;
     use std::ffi::OsStr;
     use std::fs;
@@ -379,6 +789,107 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_map_source_paths() {
+        let text = include_str!("../data/stack-traces/libfuzzer-asan-log.txt");
+        let mut parsed = CrashLog::parse(text.to_owned()).unwrap();
+        let original = parsed.clone();
+
+        parsed.map_source_paths(|path| {
+            path.strip_prefix("/home/testuser/projects/onefuzz/")
+                .map(|rest| format!("src/{rest}"))
+        });
+
+        assert_eq!(
+            parsed.full_stack_details[0].source_file_path.as_deref(),
+            Some("src/samples/asan/fuzz.c")
+        );
+        assert_eq!(
+            parsed.call_stack[0],
+            "#0 0x527475 in LLVMFuzzerTestOneInput src/samples/asan/fuzz.c:45:51"
+        );
+        assert_eq!(parsed.minimized_stack[0], parsed.call_stack[0]);
+
+        // Unmatched paths, and the paths of modules, are kept.
+        assert_eq!(parsed.call_stack[1..], original.call_stack[1..]);
+    }
+
+    #[test]
+    fn test_from_signal() {
+        let frame = |function: &str, path: Option<&str>| StackEntry {
+            line: format!("0x1000 in {function}"),
+            function_name: Some(function.to_owned()),
+            source_file_path: path.map(str::to_owned),
+            source_file_line: path.map(|_| 6),
+            function_offset: Some(0x10),
+            module_path: Some("/setup/fuzz".to_owned()),
+            ..Default::default()
+        };
+
+        // Sited past the frames of the abort.
+        let stack = vec![
+            frame("abort", None),
+            frame("check", Some("/src/fuzz.c")),
+            frame("main", Some("/src/fuzz.c")),
+        ];
+        let log = CrashLog::from_signal(
+            "log".to_owned(),
+            "SIGABRT".to_owned(),
+            "SIGABRT".to_owned(),
+            stack.clone(),
+        )
+        .unwrap();
+        assert_eq!(log.summary, "SIGABRT /src/fuzz.c:6 in check");
+        assert_eq!(log.fault_type, "SIGABRT");
+        assert_eq!(log.sanitizer, "SIGABRT");
+        assert_eq!(log.text.as_deref(), Some("log"));
+
+        let log = CrashLog::from_signal(
+            "log".to_owned(),
+            "SIGSEGV".to_owned(),
+            "SIGSEGV".to_owned(),
+            vec![],
+        )
+        .unwrap();
+        assert_eq!(log.summary, "SIGSEGV");
+
+        // Of the access of the faulting instruction.
+        let log = CrashLog::from_signal(
+            "log".to_owned(),
+            "SIGSEGV".to_owned(),
+            "write-access-violation".to_owned(),
+            stack,
+        )
+        .unwrap();
+        assert_eq!(log.summary, "write-access-violation /src/fuzz.c:6 in check");
+        assert_eq!(log.fault_type, "write-access-violation");
+        assert_eq!(log.sanitizer, "SIGSEGV");
+    }
+
+    #[test]
+    fn test_redact() {
+        let text = include_str!("../data/stack-traces/libfuzzer-asan-log.txt");
+        let mut parsed = CrashLog::parse(text.to_owned()).unwrap();
+        let original = parsed.clone();
+
+        parsed.redact(|text| text.replace("/home/testuser/", "/home/<USER>/"));
+
+        assert!(!parsed.text.as_ref().unwrap().contains("testuser"));
+        assert_eq!(
+            parsed.call_stack[0],
+            "#0 0x527475 in LLVMFuzzerTestOneInput /home/<USER>/projects/onefuzz/samples/asan/fuzz.c:45:51"
+        );
+        assert_eq!(parsed.full_stack_details[0].line, parsed.call_stack[0]);
+        assert_eq!(
+            parsed.minimized_stack_details[0]
+                .source_file_path
+                .as_deref(),
+            Some("/home/<USER>/projects/onefuzz/samples/asan/fuzz.c")
+        );
+        assert_eq!(parsed.minimized_stack[0], parsed.call_stack[0]);
+        assert_ne!(parsed.call_stack_sha256(), original.call_stack_sha256());
+    }
+
     #[test]
     fn test_asan_log_parse() {
         let src_dir = "../data/stack-traces";
@@ -418,9 +929,6 @@ mod tests {
             "missing_library_linux.txt",
             "oom.txt",
             "stack_filtering.txt",
-            // java
-            "java_IllegalStateException.txt",
-            "java_fatal_exception.txt",
             // cdb
             "cdb_divide_by_zero.txt",
             "cdb_integer_overflow.txt",
@@ -447,14 +955,6 @@ mod tests {
             "v8_unimplemented_code.txt",
             "v8_unknown_fatal_error.txt",
             "v8_unreachable_code.txt",
-            // golang
-            "golang_panic_custom_short_message.txt",
-            "golang_panic_runtime_error_index_out_of_range.txt",
-            "golang_panic_runtime_error_integer_divide_by_zero.txt",
-            "golang_panic_runtime_error_invalid_memory_address.txt",
-            "golang_panic_runtime_error_makeslice_len_out_of_range.txt",
-            "golang_panic_with_type_assertions_in_frames.txt",
-            "golang_sigsegv_panic.txt",
             // linux kernel
             "android_kernel.txt",
             "android_kernel_no_parens.txt",
@@ -471,7 +971,7 @@ mod tests {
             "cfi_invalid_vtable.txt",
             "cfi_nodebug.txt",
             "cfi_unrelated_vtable.txt",
-            // UBSAN - TODO - these should be handled
+            // UBSAN without summaries, checked by `ubsan::tests`
             "ubsan_bad_cast_downcast.txt",
             "ubsan_integer_overflow_addition.txt",
             "ubsan_non_positive_vla_bound_value.txt",
@@ -483,8 +983,6 @@ mod tests {
             "hwasan_tag_mismatch.txt",
             // TODO - needs fixed
             "android_asan_uaf.txt",
-            // java (from jazzer)
-            "java_severity_medium_exception.txt",
         ]
         .map(OsStr::new);
 
@@ -498,14 +996,8 @@ mod tests {
             // TODO: address these:
             "fuchsia_ignore.txt",
             "fuchsia_reproducible_crash.txt",
-            // TODO: add parsing for golang traces
-            "golang_fatal_error_stack_overflow.txt",
-            "golang_generic_fatal_error_and_asan_abrt.txt",
-            "golang_generic_panic_and_asan_abrt.txt",
+            // not a known Go crash, parsed as the sanitizer abort
             "golang_new_crash_type_and_asan_abrt.txt",
-            "golang_panic_runtime_error_index_out_of_range_with_msan.txt",
-            "golang_asan_panic.txt",
-            "golang_panic_runtime_error_slice_bounds_out_of_range.txt",
             "v8_check_symbolized.txt",
             "v8_dcheck_symbolized.txt",
             // TODO - needs fixed, multi-line ASAN entry
@@ -515,4 +1007,51 @@ mod tests {
 
         check_dir(src_dir, expected_dir, &skip_files, &skip_minimized_check);
     }
+
+    #[test]
+    fn test_dotnet_exception_parse() {
+        // A managed exception, caught by the harness, which is built without
+        // a sanitizer.
+        let text = "\
+INFO: libFuzzer ignores flags that start with '--'
+INFO: Seed: 3918206239
+INFO: 65536 Extra Counters
+System.InvalidOperationException: unable to read row
+ ---> System.ArgumentOutOfRangeException: Index was out of range. (Parameter 'index')
+   at System.Collections.Generic.List`1.get_Item(Int32 index)
+   at Parser.Table.ReadRow(Int32 index) in /src/Parser/Table.cs:line 31
+   --- End of inner exception stack trace ---
+   at Parser.Table.ReadRow(Int32 index) in /src/Parser/Table.cs:line 34
+   at Parser.Table.Read(ReadOnlySpan`1 data) in /src/Parser/Table.cs:line 18
+   at Parser.Fuzz.TestInput(ReadOnlySpan`1 data) in /src/Parser.Fuzz/Program.cs:line 12
+   at SharpFuzz.Fuzzer.LibFuzzer.Run(ReadOnlySpanAction action)
+==4182== ERROR: libFuzzer: deadly signal
+NOTE: libFuzzer has rudimentary signal handlers.
+      Combine libFuzzer with AddressSanitizer or similar for better crash reports.
+SUMMARY: libFuzzer: deadly signal
+";
+        let parsed = CrashLog::parse(text.to_owned()).unwrap();
+
+        assert_eq!(parsed.sanitizer, ".NET");
+        assert_eq!(parsed.fault_type, "Unhandled exception");
+        assert_eq!(
+            parsed.summary,
+            "System.InvalidOperationException: unable to read row \
+             ---> System.ArgumentOutOfRangeException: Index was out of range. (Parameter 'index')"
+        );
+
+        // Of the outer exception only.
+        assert_eq!(
+            parsed.full_stack_names,
+            [
+                "Parser.Table.ReadRow",
+                "Parser.Table.Read",
+                "Parser.Fuzz.TestInput",
+                "SharpFuzz.Fuzzer.LibFuzzer.Run",
+            ]
+        );
+        let frame = &parsed.full_stack_details[0];
+        assert_eq!(frame.source_file_name.as_deref(), Some("Table.cs"));
+        assert_eq!(frame.source_file_line, Some(34));
+    }
 }
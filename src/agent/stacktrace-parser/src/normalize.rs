@@ -0,0 +1,402 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use anyhow::{Context, Result};
+use regex::{Regex, RegexSet};
+use serde::{Deserialize, Serialize};
+
+use crate::StackEntry;
+
+lazy_static::lazy_static! {
+    // The operators whose names are of `<` and `>`, which are not of template
+    // arguments, such as `operator<<` and `operator->`.
+    static ref OPERATOR_REGEX: Regex =
+        Regex::new(r"^operator\s*(?:<=>|<<=|>>=|<<|>>|<=|>=|->\*?|<|>)").unwrap();
+
+    // The address of a frame, "#0 0x4abe12 in", and the offsets of functions
+    // and modules, "foo+0x1a" and "(libc.so.6+0x29d90)".
+    static ref OFFSET_REGEX: Regex = Regex::new(r"[\s+]0x[0-9a-fA-F]+\b").unwrap();
+
+    static ref FRAME_INDEX_REGEX: Regex = Regex::new(r"^(\s*)#\d+").unwrap();
+}
+
+/// Options normalizing the frames of minimized stacks before they are hashed,
+/// to deduplicate crashes whose stacks differ in only the normalized details.
+/// The frames of a normalized stack are renumbered from `#0`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StackNormalization {
+    /// Strip the template arguments of function names, as of
+    /// `std::vector<int>::push_back`, to `std::vector::push_back`.
+    #[serde(default)]
+    pub strip_template_args: bool,
+
+    /// Strip the addresses of frames, and the offsets of their functions and
+    /// modules.
+    #[serde(default)]
+    pub strip_offsets: bool,
+
+    /// Collapse consecutive frames of the same function, such as of inlined
+    /// calls or of recursion, to the first.
+    #[serde(default)]
+    pub collapse_repeated_frames: bool,
+
+    /// Collapse the frames of inlined calls to the outermost frame they were
+    /// inlined into. Symbolizers log the inlined calls of a frame as frames of
+    /// its address, before it, whichever function they are of.
+    #[serde(default)]
+    pub collapse_inlined_frames: bool,
+
+    /// Rewrite the source paths of frames with the `source_path_map` of the
+    /// task before hashing them, so that builds at different paths hash alike.
+    #[serde(default)]
+    pub map_source_paths: bool,
+
+    /// Also normalize the call stack, recorded in reports as
+    /// `normalized_call_stack`, whose hash deduplicates unique reports in place
+    /// of `call_stack_sha256`.
+    #[serde(default)]
+    pub normalize_call_stack: bool,
+
+    /// Regexes of the function names, or module paths, of frames to drop, such
+    /// as of interceptors.
+    #[serde(default)]
+    pub ignore_frames: Vec<String>,
+}
+
+impl StackNormalization {
+    /// Whether stacks are unchanged by the options.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Check that the regexes of `ignore_frames` are valid.
+    pub fn check(&self) -> Result<()> {
+        self.ignore_frames_set()?;
+        Ok(())
+    }
+
+    fn ignore_frames_set(&self) -> Result<RegexSet> {
+        RegexSet::new(&self.ignore_frames).context("invalid ignore_frames regex")
+    }
+
+    pub(crate) fn normalize(&self, stack: &[StackEntry]) -> Result<Vec<StackEntry>> {
+        let ignore_frames = self.ignore_frames_set()?;
+
+        let mut normalized: Vec<StackEntry> = vec![];
+        for (i, entry) in stack.iter().enumerate() {
+            if self.collapse_inlined_frames && is_inlined(entry, stack.get(i + 1)) {
+                continue;
+            }
+
+            let ignored = [&entry.function_name, &entry.module_path]
+                .into_iter()
+                .flatten()
+                .any(|name| ignore_frames.is_match(name));
+            if ignored {
+                continue;
+            }
+
+            let mut entry = entry.clone();
+            if self.strip_template_args {
+                strip_function_template_args(&mut entry);
+            }
+            if self.strip_offsets {
+                strip_offsets(&mut entry);
+            }
+
+            let repeated = normalized.last().is_some_and(|last| {
+                entry.function_name.is_some() && last.function_name == entry.function_name
+            });
+            if self.collapse_repeated_frames && repeated {
+                continue;
+            }
+
+            normalized.push(entry);
+        }
+
+        for (i, entry) in normalized.iter_mut().enumerate() {
+            entry.line = FRAME_INDEX_REGEX
+                .replace(&entry.line, format!("${{1}}#{i}"))
+                .into_owned();
+        }
+
+        Ok(normalized)
+    }
+}
+
+// Whether `entry` is of a call inlined into the frame after it, `caller`, of
+// the same address.
+fn is_inlined(entry: &StackEntry, caller: Option<&StackEntry>) -> bool {
+    entry.address.is_some() && caller.is_some_and(|caller| caller.address == entry.address)
+}
+
+fn strip_function_template_args(entry: &mut StackEntry) {
+    let Some(name) = &entry.function_name else {
+        return;
+    };
+
+    let stripped = strip_template_args(name);
+    entry.line = entry.line.replacen(name.as_str(), &stripped, 1);
+    entry.function_name = Some(stripped);
+}
+
+fn strip_offsets(entry: &mut StackEntry) {
+    entry.address = None;
+    entry.function_offset = None;
+    entry.module_offset = None;
+    entry.line = OFFSET_REGEX.replace_all(&entry.line, "").into_owned();
+}
+
+// Strip the `<...>` of a name, as nested. Names whose brackets don't balance
+// are kept as they are.
+fn strip_template_args(name: &str) -> String {
+    let mut stripped = String::with_capacity(name.len());
+    let mut depth = 0usize;
+    let mut rest = name;
+
+    while let Some(c) = rest.chars().next() {
+        let in_identifier = stripped.ends_with(|c: char| c.is_alphanumeric() || c == '_');
+        if depth == 0 && !in_identifier {
+            if let Some(operator) = OPERATOR_REGEX.find(rest) {
+                stripped.push_str(operator.as_str());
+                rest = &rest[operator.end()..];
+                continue;
+            }
+        }
+
+        match c {
+            '<' => depth += 1,
+            '>' if depth > 0 => depth -= 1,
+            _ if depth == 0 => stripped.push(c),
+            _ => {}
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+
+    if depth > 0 {
+        return name.to_string();
+    }
+
+    stripped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CrashLog;
+    use pretty_assertions::assert_eq;
+
+    fn crash(frames: &[&str]) -> CrashLog {
+        let mut text = "==1==ERROR: AddressSanitizer: heap-use-after-free on address 0x602000000010 at pc 0x4abe12 bp 0x7ffd sp 0x7ffd\nREAD of size 4 at 0x602000000010 thread T0\n".to_string();
+        for (i, frame) in frames.iter().enumerate() {
+            text.push_str(&format!("    #{i} {frame}\n"));
+        }
+        text.push_str("SUMMARY: AddressSanitizer: heap-use-after-free /src/a.cc:3:5 in f\n");
+        CrashLog::parse(text).unwrap()
+    }
+
+    fn normalized(log: &CrashLog, normalization: &StackNormalization) -> CrashLog {
+        let mut log = log.clone();
+        log.normalize_minimized_stack(normalization).unwrap();
+        log
+    }
+
+    #[test]
+    fn test_strip_template_args() {
+        assert_eq!(
+            strip_template_args("std::vector<std::pair<int, char>>::push_back(int)"),
+            "std::vector::push_back(int)"
+        );
+        assert_eq!(
+            strip_template_args("Foo<int>::operator<<(Foo<int> const&)"),
+            "Foo::operator<<(Foo const&)"
+        );
+        assert_eq!(
+            strip_template_args("Ptr<Node>::operator->() const"),
+            "Ptr::operator->() const"
+        );
+        assert_eq!(strip_template_args("operator<"), "operator<");
+        assert_eq!(strip_template_args("my_operator<int>"), "my_operator");
+        assert_eq!(strip_template_args("broken<int"), "broken<int");
+    }
+
+    #[test]
+    fn test_template_args_bucket_together() {
+        let a = crash(&[
+            "0x4abe12 in Table<int>::lookup(Key<int> const&) /src/table.h:40:7",
+            "0x4abf00 in LLVMFuzzerTestOneInput /src/fuzz.cc:12:3",
+        ]);
+        let b = crash(&[
+            "0x4abe12 in Table<long>::lookup(Key<long> const&) /src/table.h:40:7",
+            "0x4abf00 in LLVMFuzzerTestOneInput /src/fuzz.cc:12:3",
+        ]);
+        assert_ne!(
+            a.minimized_stack_sha256(None),
+            b.minimized_stack_sha256(None)
+        );
+
+        let normalization = StackNormalization {
+            strip_template_args: true,
+            ..Default::default()
+        };
+        let (a, b) = (
+            normalized(&a, &normalization),
+            normalized(&b, &normalization),
+        );
+        assert_eq!(a.minimized_stack, b.minimized_stack);
+        assert_eq!(
+            a.minimized_stack[0],
+            "#0 0x4abe12 in Table::lookup(Key const&) /src/table.h:40:7"
+        );
+        assert_eq!(
+            a.minimized_stack_sha256(None),
+            b.minimized_stack_sha256(None)
+        );
+        assert_eq!(
+            a.minimized_stack_function_names_sha256(None),
+            b.minimized_stack_function_names_sha256(None)
+        );
+        assert_eq!(
+            a.minimized_stack_function_lines_sha256(None),
+            b.minimized_stack_function_lines_sha256(None)
+        );
+    }
+
+    #[test]
+    fn test_strip_offsets() {
+        let a = crash(&[
+            "0x4abe12 in lookup (/out/fuzz+0x4abe12)",
+            "0x4abf00 in LLVMFuzzerTestOneInput /src/fuzz.cc:12:3",
+        ]);
+        let b = crash(&[
+            "0x5bcf23 in lookup (/out/fuzz+0x5bcf23)",
+            "0x5bd011 in LLVMFuzzerTestOneInput /src/fuzz.cc:12:3",
+        ]);
+
+        let normalization = StackNormalization {
+            strip_offsets: true,
+            ..Default::default()
+        };
+        let (a, b) = (
+            normalized(&a, &normalization),
+            normalized(&b, &normalization),
+        );
+        assert_eq!(a.minimized_stack[0], "#0 in lookup (/out/fuzz)");
+        assert_eq!(a.minimized_stack_details[0].module_offset, None);
+        assert_eq!(
+            a.minimized_stack_sha256(None),
+            b.minimized_stack_sha256(None)
+        );
+    }
+
+    #[test]
+    fn test_collapse_and_ignore_frames() {
+        let log = crash(&[
+            "0x4abe00 in my_memcpy_hook /src/hooks.c:8:3",
+            "0x4abe12 in visit /src/tree.c:20:5",
+            "0x4abe34 in visit /src/tree.c:22:5",
+            "0x4abe34 in visit /src/tree.c:22:5",
+            "0x4abf00 in LLVMFuzzerTestOneInput /src/fuzz.cc:12:3",
+        ]);
+
+        let normalization = StackNormalization {
+            collapse_repeated_frames: true,
+            ignore_frames: vec!["_hook$".to_string()],
+            ..Default::default()
+        };
+        let log = normalized(&log, &normalization);
+        assert_eq!(
+            log.minimized_stack,
+            [
+                "#0 0x4abe12 in visit /src/tree.c:20:5",
+                "#1 0x4abf00 in LLVMFuzzerTestOneInput /src/fuzz.cc:12:3",
+            ]
+        );
+        assert_eq!(log.minimized_stack_function_names, ["visit", "fuzz.cc"]);
+    }
+
+    #[test]
+    fn test_collapse_inlined_frames() {
+        let log = crash(&[
+            "0x4abe12 in read_u32 /src/buf.h:12:10",
+            "0x4abe12 in parse_header /src/parse.c:40:7",
+            "0x4abf00 in LLVMFuzzerTestOneInput /src/fuzz.cc:12:3",
+        ]);
+
+        let normalization = StackNormalization {
+            collapse_inlined_frames: true,
+            ..Default::default()
+        };
+        let log = normalized(&log, &normalization);
+        assert_eq!(
+            log.minimized_stack,
+            [
+                "#0 0x4abe12 in parse_header /src/parse.c:40:7",
+                "#1 0x4abf00 in LLVMFuzzerTestOneInput /src/fuzz.cc:12:3",
+            ]
+        );
+    }
+
+    // Two captures of the same crash, of a PIE target run with different ASLR
+    // bases.
+    #[test]
+    fn test_normalized_call_stack_of_aslr_runs() {
+        let a = crash(&[
+            "0x55d0c1a2be12 in read_u32 /src/buf.h:12:10",
+            "0x55d0c1a2be12 in parse_header /src/parse.c:40:7",
+            "0x55d0c1a2bf00 in LLVMFuzzerTestOneInput /src/fuzz.cc:12:3",
+            "0x55d0c1a10c44  (/out/fuzz+0x10c44)",
+            "0x7f3a9c829d8f in __libc_start_main (/lib/x86_64-linux-gnu/libc.so.6+0x29d8f)",
+        ]);
+        let b = crash(&[
+            "0x5612f3e4be12 in read_u32 /src/buf.h:12:10",
+            "0x5612f3e4be12 in parse_header /src/parse.c:40:7",
+            "0x5612f3e4bf00 in LLVMFuzzerTestOneInput /src/fuzz.cc:12:3",
+            "0x5612f3e30c44  (/out/fuzz+0x10c44)",
+            "0x7fe0d1c29d8f in __libc_start_main (/lib/x86_64-linux-gnu/libc.so.6+0x29d8f)",
+        ]);
+        assert_ne!(a.call_stack_sha256(), b.call_stack_sha256());
+
+        let normalization = StackNormalization {
+            strip_offsets: true,
+            collapse_inlined_frames: true,
+            normalize_call_stack: true,
+            ..Default::default()
+        };
+        let normalized_a = a.normalized_call_stack(&normalization).unwrap().unwrap();
+        let normalized_b = b.normalized_call_stack(&normalization).unwrap().unwrap();
+        assert_eq!(normalized_a, normalized_b);
+        assert_eq!(
+            normalized_a,
+            [
+                "#0 in parse_header /src/parse.c:40:7",
+                "#1 in LLVMFuzzerTestOneInput /src/fuzz.cc:12:3",
+                "#2  (/out/fuzz)",
+                "#3 in __libc_start_main (/lib/x86_64-linux-gnu/libc.so.6)",
+            ]
+        );
+
+        // The raw stack is kept, and only normalized when asked.
+        assert_eq!(a.call_stack.len(), 5);
+        assert_eq!(
+            a.normalized_call_stack(&StackNormalization::default())
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_default_is_unchanged() {
+        let log = crash(&["0x4abe12 in Table<int>::lookup() /src/table.h:40:7"]);
+        assert_eq!(normalized(&log, &StackNormalization::default()), log);
+    }
+
+    #[test]
+    fn test_invalid_ignore_frames() {
+        let normalization = StackNormalization {
+            ignore_frames: vec!["(".to_string()],
+            ..Default::default()
+        };
+        assert!(normalization.check().is_err());
+    }
+}
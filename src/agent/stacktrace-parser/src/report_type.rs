@@ -0,0 +1,173 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use serde::{Deserialize, Serialize};
+
+use crate::CrashLog;
+
+const LIBFUZZER: &str = "libFuzzer";
+const LEAK_SANITIZER: &str = "LeakSanitizer";
+
+// Fault types of exhausting memory, rather than of a bug in the use of memory,
+// as summarized by ASan and libFuzzer.
+const OOM_FAULT_TYPES: &[&str] = &[
+    "out-of-memory",
+    "allocation-size-too-big",
+    "rss-limit-exceeded",
+];
+
+/// The type of finding a report is of, so that findings other than crashes,
+/// such as OOMs and leaks, can be counted and triaged apart.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportType {
+    #[default]
+    Crash,
+    Oom,
+    Leak,
+    Timeout,
+
+    /// A failure reported by libFuzzer other than a crash, such as the target
+    /// exiting.
+    Other,
+}
+
+pub(crate) fn classify(log: &CrashLog) -> ReportType {
+    if OOM_FAULT_TYPES.contains(&log.fault_type.as_str()) {
+        return ReportType::Oom;
+    }
+
+    // Leaks are summarized by their size, such as `AddressSanitizer: 24
+    // byte(s) leaked in 1 allocation(s).`, when LSan runs within ASan.
+    if log.sanitizer == LEAK_SANITIZER || log.summary.contains(" leaked in ") {
+        return ReportType::Leak;
+    }
+
+    if log.sanitizer == LIBFUZZER {
+        return match log.fault_type.as_str() {
+            "timeout" => ReportType::Timeout,
+            "deadly signal" => ReportType::Crash,
+            _ => ReportType::Other,
+        };
+    }
+
+    ReportType::Crash
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use super::*;
+
+    fn parse_file(name: &str) -> CrashLog {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("data/stack-traces")
+            .join(name);
+        let text = fs::read_to_string(path).unwrap().replace("\r\n", "\n");
+        CrashLog::parse(text).unwrap()
+    }
+
+    fn parse(text: &str) -> CrashLog {
+        CrashLog::parse(text.to_string()).unwrap()
+    }
+
+    #[test]
+    fn test_asan_oom() {
+        let allocator = "\
+==4242==ERROR: AddressSanitizer: allocator is out of memory trying to allocate 0x100000000 bytes
+    #0 0x4c5c4d in malloc (/setup/fuzz+0x4c5c4d)
+    #1 0x4f1e9a in LLVMFuzzerTestOneInput /src/fuzz.c:10:3
+SUMMARY: AddressSanitizer: out-of-memory (/setup/fuzz+0x4c5c4d) in malloc
+";
+        let too_big = "\
+==4242==ERROR: AddressSanitizer: requested allocation size 0xffffffffffffffff (0x800 after adjustments for alignment, red zones etc.) exceeds maximum supported size of 0x10000000000 (thread T0)
+    #0 0x4c5c4d in malloc (/setup/fuzz+0x4c5c4d)
+    #1 0x4f1e9a in LLVMFuzzerTestOneInput /src/fuzz.c:10:3
+SUMMARY: AddressSanitizer: allocation-size-too-big (/setup/fuzz+0x4c5c4d) in malloc
+";
+        let rss = "\
+==4242==ERROR: AddressSanitizer: hard rss limit exhausted (2048Mb vs 2113Mb)
+SUMMARY: AddressSanitizer: rss-limit-exceeded
+";
+
+        for text in [allocator, too_big, rss] {
+            assert_eq!(parse(text).report_type(), ReportType::Oom, "{text}");
+        }
+    }
+
+    #[test]
+    fn test_libfuzzer_oom() {
+        for name in [
+            "libfuzzer-linux-llvm10-out-of-memory-malloc.txt",
+            "libfuzzer-linux-llvm10-out-of-memory-rss.txt",
+            "libfuzzer-windows-llvm10-out-of-memory-malloc.txt",
+            "libfuzzer-windows-llvm10-out-of-memory-rss.txt",
+        ] {
+            assert_eq!(parse_file(name).report_type(), ReportType::Oom, "{name}");
+        }
+    }
+
+    #[test]
+    fn test_leak() {
+        let asan = "\
+==4242==ERROR: LeakSanitizer: detected memory leaks
+
+Direct leak of 64 byte(s) in 1 object(s) allocated from:
+    #0 0x4c5c4d in malloc (/setup/fuzz+0x4c5c4d)
+    #1 0x4f1e9a in LLVMFuzzerTestOneInput /src/fuzz.c:10:3
+
+SUMMARY: AddressSanitizer: 64 byte(s) leaked in 1 allocation(s).
+";
+        let standalone = "\
+==4242==ERROR: LeakSanitizer: detected memory leaks
+
+Direct leak of 64 byte(s) in 1 object(s) allocated from:
+    #0 0x4c5c4d in malloc (/setup/fuzz+0x4c5c4d)
+
+SUMMARY: LeakSanitizer: 64 byte(s) leaked in 1 allocation(s).
+";
+
+        for text in [asan, standalone] {
+            assert_eq!(parse(text).report_type(), ReportType::Leak, "{text}");
+        }
+    }
+
+    #[test]
+    fn test_libfuzzer_failures() {
+        let timeout = "\
+==4242== ERROR: libFuzzer: timeout after 25 seconds
+SUMMARY: libFuzzer: timeout
+";
+        let exited = "\
+==4242== ERROR: libFuzzer: fuzz target exited
+SUMMARY: libFuzzer: fuzz target exited
+";
+
+        assert_eq!(parse(timeout).report_type(), ReportType::Timeout);
+        assert_eq!(parse(exited).report_type(), ReportType::Other);
+        assert_eq!(
+            parse_file("libfuzzer-deadly-signal.txt").report_type(),
+            ReportType::Crash
+        );
+    }
+
+    #[test]
+    fn test_crash() {
+        assert_eq!(
+            parse_file("libfuzzer-asan-log.txt").report_type(),
+            ReportType::Crash
+        );
+        assert_eq!(
+            parse_file("tsan-linux-data-races.txt").report_type(),
+            ReportType::Crash
+        );
+    }
+
+    #[test]
+    fn test_serialize() {
+        assert_eq!(serde_json::to_string(&ReportType::Oom).unwrap(), r#""oom""#);
+        assert_eq!(ReportType::default(), ReportType::Crash);
+    }
+}
@@ -0,0 +1,512 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use anyhow::Result;
+use regex::{Regex, RegexSet};
+
+use crate::{asan, CrashLogSummary, StackEntry};
+
+pub(crate) const SANITIZER: &str = "Rust";
+
+// The kind of a panic without a known message, such as of `expect()`.
+const GENERIC_KIND: &str = "panic";
+
+// The frames of backtraces before this one are of printing the backtrace, and
+// after `__rust_begin_short_backtrace`, of starting the thread. Short
+// backtraces omit them.
+const END_SHORT_BACKTRACE: &str = "std::sys_common::backtrace::__rust_end_short_backtrace";
+const BEGIN_SHORT_BACKTRACE: &str = "std::sys_common::backtrace::__rust_begin_short_backtrace";
+
+/// Frames of the Rust runtime, and of catching and reporting panics, which are
+/// on the stacks of most Rust crashes.
+pub(crate) const STACK_FRAME_IGNORE_REGEXES: &[&str] = &[
+    r"^__rust_",
+    r"^rust_panic",
+    r"^core::panicking::",
+    r"^core::result::unwrap_failed",
+    r"^core::option::(?:expect|unwrap)_failed",
+    r"^core::result::Result.*::(?:expect|unwrap)",
+    r"^core::option::Option.*::(?:expect|unwrap)",
+    r"^core::ops::function::",
+    r"^<.* as core::ops::function::Fn",
+    r"^std::panicking::",
+    r"^std::rt::",
+    r"^std::sys::",
+    r"^std::sys_common::",
+    r"^std::thread::",
+    r"^libfuzzer_sys::",
+];
+
+lazy_static::lazy_static! {
+    pub(crate) static ref STACK_FILTER: RegexSet =
+        RegexSet::new(STACK_FRAME_IGNORE_REGEXES).unwrap();
+
+    // "thread 'main' panicked at src/main.rs:4:5:", followed by the message,
+    // as of Rust 1.73.
+    static ref PANIC_REGEX: Regex = Regex::new(
+        r"(?m)^thread '[^']*' panicked at (?P<location>[^\s']+:\d+:\d+):$"
+    )
+    .unwrap();
+
+    // "thread 'main' panicked at 'message', src/main.rs:4:5", of which the
+    // message may span lines.
+    static ref OLD_PANIC_REGEX: Regex = Regex::new(
+        r"(?ms)^thread '[^']*' panicked at '(?P<message>.*?)', (?P<location>[^\s']+:\d+(?::\d+)?)$"
+    )
+    .unwrap();
+
+    // "  3: fuzz::check", or "  3:     0x55d1c8a2c5ac - fuzz::check::h1f2e3d4c5b6a7980"
+    static ref FRAME_REGEX: Regex = Regex::new(
+        r"^\s*(?P<frame>\d+):\s+(?:0x(?P<address>[0-9a-fA-F]+) - )?(?P<function>\S.*?)\s*$"
+    )
+    .unwrap();
+
+    // "             at ./src/lib.rs:4:5"
+    static ref LOCATION_REGEX: Regex = Regex::new(
+        r"^\s+at (?P<file_path>.+?):(?P<file_line>\d+)(?::(?P<column>\d+))?\s*$"
+    )
+    .unwrap();
+
+    // The hash of legacy symbols, as printed in full backtraces.
+    static ref HASH_REGEX: Regex = Regex::new(r"::h[0-9a-f]{16}$").unwrap();
+
+    static ref KIND_SET: RegexSet = RegexSet::new(KINDS.iter().map(|(pattern, _)| pattern)).unwrap();
+}
+
+// Messages of panics of the standard library, and the kinds of their crashes.
+// The first match is used.
+const KINDS: &[(&str, &str)] = &[
+    (r"^index out of bounds", "index-out-of-bounds"),
+    (
+        r"^(?:range (?:start|end) index|slice index starts at)",
+        "slice-index-out-of-bounds",
+    ),
+    (
+        r"^byte index \d+ is (?:not a char boundary|out of bounds)",
+        "str-index-out-of-bounds",
+    ),
+    (r"^attempt to [\w ]+ with overflow", "arithmetic-overflow"),
+    (
+        r"^attempt to (?:divide by zero|calculate the remainder with a divisor of zero)",
+        "divide-by-zero",
+    ),
+    (
+        r"^called `Option::unwrap\(\)` on a `None` value",
+        "unwrap-none",
+    ),
+    (
+        r"^called `Result::unwrap\(\)` on an `Err` value",
+        "unwrap-err",
+    ),
+    (r"^assertion", "assertion-failed"),
+    (r"^internal error: entered unreachable code", "unreachable"),
+    (r"^not (?:yet )?implemented", "unimplemented"),
+    (r"^explicit panic", "explicit-panic"),
+    (r"^capacity overflow", "capacity-overflow"),
+    (r"^already (?:mutably )?borrowed", "already-borrowed"),
+];
+
+/// The first panic of a log, and where it was raised.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Panic {
+    pub message: String,
+    pub location: String,
+}
+
+impl Panic {
+    fn headline(&self) -> &str {
+        self.message.lines().next().unwrap_or_default()
+    }
+
+    pub fn kind(&self) -> &'static str {
+        KIND_SET
+            .matches(self.headline())
+            .iter()
+            .next()
+            .map(|i| KINDS[i].1)
+            .unwrap_or(GENERIC_KIND)
+    }
+
+    pub fn summary(&self) -> String {
+        format!("panicked at {}: {}", self.location, self.headline())
+    }
+}
+
+/// The panic of `text`, if it is the crash of the log. A panic aborts
+/// targets run by libFuzzer, which sanitizers report as the crash.
+pub(crate) fn parse_panic(text: &str) -> Option<Panic> {
+    if asan::is_reported_crash(text) {
+        return None;
+    }
+
+    parse_panic_message(text)
+}
+
+/// The sanitizer summary of a panic, for [`CrashLog::parse()`].
+///
+/// [`CrashLog::parse()`]: crate::CrashLog::parse
+pub(crate) fn summary(panic: &Panic) -> CrashLogSummary {
+    CrashLogSummary {
+        summary: panic.summary(),
+        sanitizer: SANITIZER.to_string(),
+        fault_type: panic.kind().to_string(),
+    }
+}
+
+fn parse_panic_message(text: &str) -> Option<Panic> {
+    if let Some(captures) = PANIC_REGEX.captures(text) {
+        let location = captures["location"].to_string();
+        let rest = &text[captures.get(0)?.end()..];
+
+        let message: Vec<&str> = rest
+            .lines()
+            .skip(1)
+            .take_while(|line| {
+                !(line.is_empty() || line.starts_with("note: ") || *line == "stack backtrace:")
+            })
+            .collect();
+
+        return Some(Panic {
+            message: message.join("\n"),
+            location,
+        });
+    }
+
+    let captures = OLD_PANIC_REGEX.captures(text)?;
+    Some(Panic {
+        message: captures["message"].to_string(),
+        location: captures["location"].to_string(),
+    })
+}
+
+struct Frame<'a> {
+    address: Option<&'a str>,
+    function: String,
+    location: Option<(&'a str, &'a str, Option<&'a str>)>,
+}
+
+/// The frames of the first backtrace of `text`, as printed with
+/// `RUST_BACKTRACE=1`, or in full with `RUST_BACKTRACE=full`.
+///
+/// Frames of printing the backtrace and of starting the thread, which only
+/// full backtraces print, are omitted, and frames are numbered and logged
+/// without their address, so that both are of the same stack.
+pub(crate) fn parse_backtrace(text: &str) -> Result<Vec<StackEntry>> {
+    let Some((_, backtrace)) = text.split_once("\nstack backtrace:\n") else {
+        return Ok(vec![]);
+    };
+
+    let mut frames: Vec<Frame> = vec![];
+    for line in backtrace.lines() {
+        if let Some(captures) = FRAME_REGEX.captures(line) {
+            frames.push(Frame {
+                address: captures.name("address").map(|x| x.as_str()),
+                function: demangle(&captures["function"]),
+                location: None,
+            });
+        } else if let Some(captures) = LOCATION_REGEX.captures(line) {
+            // Of inlined frames, only the first location is of the frame.
+            if let Some(frame) = frames.last_mut().filter(|frame| frame.location.is_none()) {
+                frame.location = Some((
+                    captures.name("file_path").unwrap().as_str(),
+                    captures.name("file_line").unwrap().as_str(),
+                    captures.name("column").map(|x| x.as_str()),
+                ));
+            }
+        } else {
+            break;
+        }
+    }
+
+    if let Some(end) = frames
+        .iter()
+        .position(|f| f.function == END_SHORT_BACKTRACE)
+    {
+        frames.drain(..=end);
+    }
+    if let Some(begin) = frames
+        .iter()
+        .position(|f| f.function == BEGIN_SHORT_BACKTRACE)
+    {
+        frames.truncate(begin);
+    }
+
+    let mut stack = vec![];
+    for (i, frame) in frames.into_iter().enumerate() {
+        let mut line = format!("#{i} {}", frame.function);
+
+        let mut entry = StackEntry {
+            address: match frame.address {
+                Some(x) => Some(u64::from_str_radix(x, 16)?),
+                None => None,
+            },
+            ..StackEntry::default()
+        };
+
+        if let Some((path, file_line, column)) = frame.location {
+            line.push_str(&format!(" {path}:{file_line}"));
+            entry.source_file_path = Some(path.to_string());
+            entry.source_file_name = Some(asan::get_call_stack_file_name(path));
+            entry.source_file_line = Some(file_line.parse()?);
+
+            if let Some(column) = column {
+                line.push_str(&format!(":{column}"));
+
+                // The column, as the asan parser records it.
+                entry.function_offset = Some(column.parse()?);
+            }
+        }
+
+        entry.line = line;
+        entry.function_name = Some(frame.function);
+        stack.push(entry);
+    }
+
+    Ok(stack)
+}
+
+/// The name of a Rust or C++ symbol, as logged by backtraces of Rust, which
+/// only demangle Rust symbols, and print the hashes of legacy symbols in full.
+pub(crate) fn demangle(name: &str) -> String {
+    const RUST_PREFIXES: &[&str] = &["_ZN", "__ZN", "_R", "__R"];
+    const CPP_PREFIXES: &[&str] = &["_Z", "__Z"];
+
+    if RUST_PREFIXES.iter().any(|prefix| name.starts_with(prefix)) {
+        if let Ok(demangled) = rustc_demangle::try_demangle(name) {
+            // Without the hash, as in short backtraces.
+            return format!("{demangled:#}");
+        }
+    }
+
+    if CPP_PREFIXES.iter().any(|prefix| name.starts_with(prefix)) {
+        if let Ok(symbol) = cpp_demangle::Symbol::new(name) {
+            return symbol.to_string();
+        }
+    }
+
+    HASH_REGEX.replace(name, "").into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CrashLog;
+    use pretty_assertions::assert_eq;
+
+    const SHORT_BACKTRACE: &str =
+        include_str!("../data/stack-traces/rust-1.75-cargo-fuzz-panic-backtrace.txt");
+    const FULL_BACKTRACE: &str =
+        include_str!("../data/stack-traces/rust-1.75-cargo-fuzz-panic-backtrace-full.txt");
+    const OLD_BACKTRACE: &str = include_str!("../data/stack-traces/rust-1.65-panic-backtrace.txt");
+
+    fn parse(text: &str) -> CrashLog {
+        CrashLog::parse(text.to_owned()).unwrap()
+    }
+
+    #[test]
+    fn test_demangle() {
+        let test_cases = [
+            (
+                "_ZN4core9panicking9panic_fmt17h6b7d8a0c3a5e1f2bE",
+                "core::panicking::panic_fmt",
+            ),
+            ("_RNvNtCs1234_7mycrate3foo3bar", "mycrate::foo::bar"),
+            (
+                "_ZN6fuzzer6Fuzzer15ExecuteCallbackEPKhm",
+                "fuzzer::Fuzzer::ExecuteCallback(unsigned char const*, unsigned long)",
+            ),
+            (
+                "tokenizer::Lexer::next_token::hf5061728394a5b6c",
+                "tokenizer::Lexer::next_token",
+            ),
+            (
+                "libfuzzer_sys::test_input_wrap::{{closure}}",
+                "libfuzzer_sys::test_input_wrap::{{closure}}",
+            ),
+            ("Run", "Run"),
+            ("main", "main"),
+        ];
+
+        for (name, expected) in test_cases {
+            assert_eq!(demangle(name), expected, "{name}");
+        }
+    }
+
+    #[test]
+    fn test_panic_kinds() {
+        let test_cases = [
+            (
+                "index out of bounds: the len is 3 but the index is 5",
+                "index-out-of-bounds",
+            ),
+            (
+                "range end index 9 out of range for slice of length 4",
+                "slice-index-out-of-bounds",
+            ),
+            (
+                "byte index 2 is not a char boundary; it is inside 'é' (bytes 1..3) of `hé`",
+                "str-index-out-of-bounds",
+            ),
+            ("attempt to subtract with overflow", "arithmetic-overflow"),
+            ("attempt to shift left with overflow", "arithmetic-overflow"),
+            ("attempt to divide by zero", "divide-by-zero"),
+            ("called `Option::unwrap()` on a `None` value", "unwrap-none"),
+            (
+                "called `Result::unwrap()` on an `Err` value: Utf8Error { valid_up_to: 0, error_len: Some(1) }",
+                "unwrap-err",
+            ),
+            ("assertion failed: len <= MAX_LEN", "assertion-failed"),
+            (
+                "assertion `left == right` failed\n  left: 1\n right: 2",
+                "assertion-failed",
+            ),
+            (
+                "internal error: entered unreachable code: bad state",
+                "unreachable",
+            ),
+            ("not yet implemented", "unimplemented"),
+            ("explicit panic", "explicit-panic"),
+            ("malformed header", GENERIC_KIND),
+        ];
+
+        for (message, expected) in test_cases {
+            let panic = Panic {
+                message: message.to_string(),
+                location: "src/lib.rs:1:1".to_string(),
+            };
+            assert_eq!(panic.kind(), expected, "{message}");
+        }
+    }
+
+    #[test]
+    fn test_panic_messages() {
+        // As of Rust 1.73, the message follows the location.
+        let panic = parse_panic_message(
+            "thread 'main' panicked at src/main.rs:4:5:\nassertion `left == right` failed\n  left: 1\n right: 2\nnote: run with `RUST_BACKTRACE=1` environment variable to display a backtrace\n",
+        )
+        .unwrap();
+        assert_eq!(panic.location, "src/main.rs:4:5");
+        assert_eq!(
+            panic.message,
+            "assertion `left == right` failed\n  left: 1\n right: 2"
+        );
+        assert_eq!(
+            panic.summary(),
+            "panicked at src/main.rs:4:5: assertion `left == right` failed"
+        );
+
+        let panic = parse_panic_message(
+            "thread '<unnamed>' panicked at 'explicit panic', src/lib.rs:10:9\n",
+        )
+        .unwrap();
+        assert_eq!(panic.location, "src/lib.rs:10:9");
+        assert_eq!(panic.message, "explicit panic");
+
+        // Before Rust 1.42, without a column.
+        let panic =
+            parse_panic_message("thread 'main' panicked at 'not implemented', src/main.rs:2\n")
+                .unwrap();
+        assert_eq!(panic.location, "src/main.rs:2");
+
+        assert!(parse_panic_message("thread 'main' has overflowed its stack\n").is_none());
+    }
+
+    #[test]
+    fn test_short_backtrace() {
+        let log = parse(SHORT_BACKTRACE);
+        assert_eq!(log.sanitizer, SANITIZER);
+        assert_eq!(log.fault_type, "unwrap-none");
+        assert_eq!(
+            log.summary,
+            "panicked at src/lib.rs:23:29: called `Option::unwrap()` on a `None` value"
+        );
+
+        assert_eq!(log.call_stack.len(), 21);
+        assert_eq!(
+            log.call_stack[4],
+            "#4 tokenizer::Lexer::next_token /src/tokenizer/src/lib.rs:23:29"
+        );
+        assert_eq!(log.call_stack[20], "#20 _start");
+
+        // The frames of the runtime, libfuzzer-sys and libFuzzer are not of
+        // the minimized stack.
+        assert_eq!(
+            log.minimized_stack_function_names,
+            [
+                "tokenizer::Lexer::next_token",
+                "tokenizer::tokenize",
+                "fuzz_target_1::_::__libfuzzer_sys_run",
+                "lib.rs",
+            ]
+            .map(String::from)
+            .to_vec()
+        );
+    }
+
+    #[test]
+    fn test_full_backtrace() {
+        let full = parse(FULL_BACKTRACE);
+        let short = parse(SHORT_BACKTRACE);
+
+        // Without the frames of printing itself, a full backtrace is of the
+        // same stack as the short one.
+        assert_eq!(full.call_stack, short.call_stack);
+        assert_eq!(full.call_stack_sha256(), short.call_stack_sha256());
+        assert_eq!(full.summary, short.summary);
+
+        assert_eq!(
+            full.full_stack_details[4].address,
+            Some(0x55c4e0a3a1d9),
+            "{:?}",
+            full.full_stack_details[4]
+        );
+        assert_eq!(short.full_stack_details[4].address, None);
+    }
+
+    #[test]
+    fn test_old_backtrace() {
+        let log = parse(OLD_BACKTRACE);
+        assert_eq!(log.fault_type, "arithmetic-overflow");
+        assert_eq!(
+            log.summary,
+            "panicked at src/header.rs:17:13: attempt to subtract with overflow"
+        );
+        assert_eq!(
+            log.minimized_stack_function_lines,
+            [
+                "parse_header::header::field_len header.rs:17:13",
+                "parse_header::header::parse header.rs:42:22",
+                "parse_header::main main.rs:11:5",
+            ]
+            .map(String::from)
+            .to_vec()
+        );
+    }
+
+    #[test]
+    fn test_panic_of_sanitizer_abort() {
+        // The panic, which the sanitizer reports as an abort, is the crash.
+        let log = parse(include_str!(
+            "../../libclusterfuzz/data/stack-traces/rust_assert.txt"
+        ));
+        assert_eq!(log.sanitizer, SANITIZER);
+        assert_eq!(log.fault_type, GENERIC_KIND);
+        assert_eq!(
+            log.summary,
+            "panicked at config/src/utils.rs:27:5: Error: could not find an available port"
+        );
+
+        // Without a backtrace, the stack is of the sanitizer.
+        assert_eq!(
+            log.minimized_stack_function_names[0],
+            "libra_config::utils::get_available_port::h7d7baacfb554bae8"
+        );
+
+        // But other crashes of a log are not of an earlier panic.
+        let text = format!(
+            "thread 'main' panicked at 'explicit panic', src/lib.rs:10:9\n{}",
+            include_str!("../data/stack-traces/libfuzzer-asan-log.txt")
+        );
+        assert_eq!(parse(&text).sanitizer, "AddressSanitizer");
+    }
+}
@@ -0,0 +1,395 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use anyhow::Result;
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+
+use crate::{asan, digest_iter, minimize_stack, stack_lines, StackEntry};
+
+pub(crate) const SANITIZER: &str = "ThreadSanitizer";
+pub(crate) const DATA_RACE: &str = "data-race";
+
+const DATA_RACE_HEADER: &str = "WARNING: ThreadSanitizer: data race";
+
+lazy_static::lazy_static! {
+    static ref REPORT_REGEX: Regex =
+        Regex::new(r"(?m)^WARNING: ThreadSanitizer: ").unwrap();
+
+    // "Write of size 4 at 0x000001109278 by thread T1:"
+    // "Previous atomic read of size 8 at 0x7b0c00000020 by main thread (mutexes: write M1):"
+    static ref ACCESS_REGEX: Regex = Regex::new(
+        r"^\s*(?:Previous )?(?P<kind>(?:[Aa]tomic )?(?:[Rr]ead|[Ww]rite)) of size (?P<size>\d+) at 0x(?P<address>[0-9a-fA-F]+) by (?:thread (?P<thread>T\d+)|(?P<main>main) thread)"
+    )
+    .unwrap();
+
+    // "#0 Thread1 /src/race.c:4:10 (race+0x4ac607)"
+    // "#1 <null> <null> (libfoo.so+0x1234) (BuildId: 0123abcd)"
+    static ref FRAME_REGEX: Regex = Regex::new(
+        r"^#(?P<frame>\d+) (?P<function>.+?)(?: (?P<file_path>\S+?)(?::(?P<file_line>\d+)(?::(?P<column>\d+))?)?)? \((?:(?P<module_path>[^+()]+)\+0x(?P<module_offset>[0-9a-fA-F]+)|<unknown module>)\)(?:\s*\(BuildId:[^)]*\))?$"
+    )
+    .unwrap();
+}
+
+/// The conflicting accesses of a ThreadSanitizer data race report.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DataRace {
+    /// The access reported, then the earlier access it races with.
+    pub accesses: Vec<RaceAccess>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RaceAccess {
+    /// Such as `write` or `atomic read`.
+    pub kind: String,
+    pub size: u64,
+    pub address: u64,
+
+    /// The id of the thread, such as `T1`, or `main`.
+    pub thread: String,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stack: Vec<StackEntry>,
+}
+
+impl RaceAccess {
+    pub fn is_write(&self) -> bool {
+        self.kind.ends_with("write")
+    }
+}
+
+impl DataRace {
+    /// The access which wrote, as one of them must have. If both did, the
+    /// reported one.
+    pub fn write(&self) -> Option<&RaceAccess> {
+        self.accesses
+            .iter()
+            .find(|access| access.is_write())
+            .or_else(|| self.accesses.first())
+    }
+
+    /// Hash of the minimized stacks of the accesses, in either order, so that
+    /// a race is the same whichever of its accesses was reported first.
+    pub fn stacks_sha256(&self) -> String {
+        let mut digests: Vec<String> = self
+            .accesses
+            .iter()
+            .map(|access| digest_iter(stack_lines(&minimize_stack(&access.stack)), None))
+            .collect();
+        digests.sort();
+
+        digest_iter(digests, None)
+    }
+}
+
+/// Each ThreadSanitizer report of `text`, of which there are several if the
+/// target kept running after the first.
+pub(crate) fn reports(text: &str) -> Vec<&str> {
+    let starts: Vec<usize> = REPORT_REGEX.find_iter(text).map(|m| m.start()).collect();
+
+    starts
+        .iter()
+        .enumerate()
+        .map(|(i, &start)| {
+            let end = starts.get(i + 1).copied().unwrap_or(text.len());
+            &text[start..end]
+        })
+        .collect()
+}
+
+/// The data race of the first ThreadSanitizer report of `text`, if it is one.
+pub(crate) fn parse_data_race(text: &str) -> Result<Option<DataRace>> {
+    let Some(report) = reports(text).first().copied() else {
+        return Ok(None);
+    };
+    if !report.starts_with(DATA_RACE_HEADER) {
+        return Ok(None);
+    }
+
+    let mut accesses = vec![];
+    let mut lines = report.lines().peekable();
+
+    while let Some(line) = lines.next() {
+        let Some(captures) = ACCESS_REGEX.captures(line) else {
+            continue;
+        };
+
+        let mut frames = vec![];
+        while let Some(frame) = lines.next_if(|line| line.trim_start().starts_with('#')) {
+            frames.push(frame);
+        }
+
+        let thread = captures
+            .name("thread")
+            .or_else(|| captures.name("main"))
+            .map(|x| x.as_str().to_string())
+            .unwrap_or_default();
+
+        accesses.push(RaceAccess {
+            kind: captures["kind"].to_lowercase(),
+            size: captures["size"].parse()?,
+            address: u64::from_str_radix(&captures["address"], 16)?,
+            thread,
+            stack: parse_stack(&frames.join("\n"))?,
+        });
+
+        // Only the reported and previous access are of the race. Other stacks
+        // are of the threads and mutexes involved.
+        if accesses.len() == 2 {
+            break;
+        }
+    }
+
+    if accesses.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(DataRace { accesses }))
+}
+
+fn parse_stack(text: &str) -> Result<Vec<StackEntry>> {
+    // Frames symbolized as by ASan, with their addresses, such as with
+    // `stack_trace_format` set as ClusterFuzz does.
+    let stack = asan::parse_asan_call_stack(text)?;
+    if !stack.is_empty() {
+        return Ok(stack);
+    }
+
+    text.lines()
+        .filter_map(|line| FRAME_REGEX.captures(line.trim()))
+        .map(|captures| parse_frame(&captures))
+        .collect()
+}
+
+fn parse_frame(captures: &Captures) -> Result<StackEntry> {
+    // Unknown functions and files are logged as `<null>`.
+    let known = |name: &str| {
+        captures
+            .name(name)
+            .map(|x| x.as_str())
+            .filter(|x| *x != "<null>")
+            .map(|x| x.to_string())
+    };
+
+    let source_file_path = known("file_path");
+    let source_file_name = source_file_path
+        .as_deref()
+        .map(asan::get_call_stack_file_name);
+
+    let source_file_line = match captures.name("file_line") {
+        Some(x) => Some(x.as_str().parse()?),
+        None => None,
+    };
+
+    // The column, as the asan parser records it.
+    let function_offset = match captures.name("column") {
+        Some(x) => Some(x.as_str().parse()?),
+        None => None,
+    };
+
+    let module_offset = match captures.name("module_offset") {
+        Some(x) => Some(u64::from_str_radix(x.as_str(), 16)?),
+        None => None,
+    };
+
+    Ok(StackEntry {
+        line: captures[0].to_string(),
+        address: None,
+        function_name: known("function"),
+        function_offset,
+        source_file_name,
+        source_file_path,
+        source_file_line,
+        module_path: known("module_path"),
+        module_offset,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CrashLog;
+    use pretty_assertions::assert_eq;
+
+    fn parse(text: &str) -> CrashLog {
+        CrashLog::parse(text.to_owned()).unwrap()
+    }
+
+    fn race(log: &CrashLog) -> &DataRace {
+        log.data_race.as_ref().expect("data race")
+    }
+
+    fn functions(access: &RaceAccess) -> Vec<Option<&str>> {
+        access
+            .stack
+            .iter()
+            .map(|frame| frame.function_name.as_deref())
+            .collect()
+    }
+
+    #[test]
+    fn test_data_race() {
+        let log = parse(include_str!(
+            "../data/stack-traces/tsan-linux-llvm10-data-race.txt"
+        ));
+        assert_eq!(log.sanitizer, SANITIZER);
+        assert_eq!(log.fault_type, DATA_RACE);
+        assert_eq!(
+            log.summary,
+            "ThreadSanitizer: data-race /home/user/fuzz-targets/tiny_race.c:4:10 in Thread1"
+        );
+
+        let race = race(&log);
+        let accesses: Vec<_> = race
+            .accesses
+            .iter()
+            .map(|a| (a.kind.as_str(), a.size, a.address, a.thread.as_str()))
+            .collect();
+        assert_eq!(
+            accesses,
+            [
+                ("write", 4, 0x1109278, "T1"),
+                ("write", 4, 0x1109278, "main")
+            ]
+        );
+
+        // Not the stacks of the thread creation.
+        assert_eq!(functions(&race.accesses[0]), [Some("Thread1")]);
+        assert_eq!(functions(&race.accesses[1]), [Some("main")]);
+
+        let frame = &race.accesses[0].stack[0];
+        assert_eq!(frame.source_file_name.as_deref(), Some("tiny_race.c"));
+        assert_eq!(frame.source_file_line, Some(4));
+        assert_eq!(frame.function_offset, Some(10));
+        assert_eq!(frame.module_path.as_deref(), Some("tiny_race.exe"));
+        assert_eq!(frame.module_offset, Some(0x4ac607));
+
+        assert_eq!(log.call_stack, [frame.line.clone()]);
+    }
+
+    #[test]
+    fn test_data_race_asan_frames() {
+        let log = parse(include_str!(
+            "../../libclusterfuzz/data/stack-traces/tsan_data_race.txt"
+        ));
+        assert_eq!(log.fault_type, DATA_RACE);
+        assert_eq!(
+            log.summary,
+            "ThreadSanitizer: data-race third_party/sqlite/amalgamation/sqlite3.c:12693:3 in sqlite3StatusSet"
+        );
+
+        let race = race(&log);
+        assert_eq!(race.accesses[0].thread, "T11");
+        assert_eq!(race.accesses[1].thread, "T22");
+        assert_eq!(race.accesses[0].stack.len(), 39);
+        assert_eq!(race.accesses[1].stack.len(), 36);
+        assert_eq!(
+            race.accesses[1].stack[16].function_name.as_deref(),
+            Some("history::URLDatabase::CreateURLTable(bool)")
+        );
+    }
+
+    #[test]
+    fn test_data_race_read_write() {
+        let log = parse(include_str!(
+            "../data/stack-traces/tsan-linux-llvm15-data-race-read.txt"
+        ));
+        let race = race(&log);
+
+        let kinds: Vec<_> = race.accesses.iter().map(|a| a.kind.as_str()).collect();
+        assert_eq!(kinds, ["read", "write"]);
+
+        // The site and stack are of the write, the previous access.
+        let write = race.write().unwrap();
+        assert_eq!(write.thread, "main");
+        assert_eq!(
+            log.summary,
+            "ThreadSanitizer: data-race /src/counter/counter.cc:18:12 in Counter::Increment()"
+        );
+        assert_eq!(log.call_stack.len(), write.stack.len());
+        assert_eq!(
+            log.minimized_stack_function_names,
+            ["Counter::Increment", "fuzz.cc"].map(String::from).to_vec()
+        );
+    }
+
+    #[test]
+    fn test_data_race_missing_symbols() {
+        let log = parse(include_str!(
+            "../data/stack-traces/tsan-linux-data-race-unsymbolized.txt"
+        ));
+        let race = race(&log);
+
+        let frame = &race.accesses[0].stack[0];
+        assert_eq!(frame.function_name, None);
+        assert_eq!(frame.source_file_path, None);
+        assert_eq!(frame.module_path.as_deref(), Some("/setup/libparse.so"));
+        assert_eq!(frame.module_offset, Some(0x1a2b));
+
+        assert_eq!(race.accesses[1].kind, "atomic write");
+        assert_eq!(
+            race.accesses[1].stack[0].function_name.as_deref(),
+            Some("Parser::Reset()")
+        );
+
+        // Sited at the module offset of the unsymbolized frame.
+        assert_eq!(
+            log.summary,
+            "ThreadSanitizer: data-race (/setup/libparse.so+0x1a2b)"
+        );
+    }
+
+    #[test]
+    fn test_stacks_sha256_order() {
+        let log = parse(include_str!(
+            "../data/stack-traces/tsan-linux-llvm15-data-race-read.txt"
+        ));
+        let mut swapped = race(&log).clone();
+        swapped.accesses.reverse();
+        assert_eq!(swapped.stacks_sha256(), race(&log).stacks_sha256());
+        assert_eq!(log.call_stack_sha256(), race(&log).stacks_sha256());
+
+        // But not of a race of different stacks.
+        let other = parse(include_str!(
+            "../data/stack-traces/tsan-linux-data-race-unsymbolized.txt"
+        ));
+        assert_ne!(other.call_stack_sha256(), log.call_stack_sha256());
+    }
+
+    #[test]
+    fn test_parse_all_races() {
+        let text = include_str!("../data/stack-traces/tsan-linux-data-races.txt");
+        let logs = CrashLog::parse_all(text.to_owned()).unwrap();
+
+        // The third race is the first, reported from its other access.
+        assert_eq!(logs.len(), 2);
+        assert_eq!(
+            logs[0].summary,
+            "ThreadSanitizer: data-race /src/cache/cache.cc:41:5 in Cache::Insert(int)"
+        );
+        assert_eq!(
+            logs[1].summary,
+            "ThreadSanitizer: data-race /src/cache/cache.cc:57:14 in Cache::Evict()"
+        );
+        assert!(logs.iter().all(|log| log.data_race.is_some()));
+    }
+
+    #[test]
+    fn test_other_reports() {
+        // Not races, which are parsed as before.
+        let log = parse(include_str!(
+            "../../libclusterfuzz/data/stack-traces/tsan_use_after_free.txt"
+        ));
+        assert_eq!(log.fault_type, "heap-use-after-free");
+        assert!(log.data_race.is_none());
+
+        assert_eq!(
+            reports(include_str!(
+                "../../libclusterfuzz/data/stack-traces/tsan_use_after_free.txt"
+            ))
+            .len(),
+            3
+        );
+    }
+}
@@ -0,0 +1,414 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use regex::{Regex, RegexSet};
+
+use crate::CrashLogSummary;
+
+const UBSAN: &str = "UndefinedBehaviorSanitizer";
+
+// The check of a summary not naming one, unless UBSan is run with
+// `report_error_type=1`.
+const GENERIC_CHECK: &str = "undefined-behavior";
+
+lazy_static::lazy_static! {
+    // "path/to/file.c:12:3: runtime error: signed integer overflow: ..."
+    static ref RUNTIME_ERROR_REGEX: Regex = Regex::new(
+        r"(?m)^(?P<location>[^\r\n]*?:\d+(?::\d+)?): runtime error: (?P<message>[^\r\n]*)"
+    )
+    .unwrap();
+
+    static ref SUMMARY_CHECK_REGEX: Regex =
+        Regex::new(r"(?m)^SUMMARY: UndefinedBehaviorSanitizer: (?P<check>[\w-]+)").unwrap();
+
+    // The reports of other sanitizers, which are the crash of a log that also
+    // has UBSan findings, such as of targets built with ASan and UBSan.
+    static ref OTHER_SANITIZER_REGEX: Regex = Regex::new(
+        r"(?m)(?:==\d+==\s*(?:ERROR|WARNING): |^SUMMARY: )(?P<sanitizer>\w+Sanitizer|libFuzzer)"
+    )
+    .unwrap();
+
+    static ref CHECK_SET: RegexSet = RegexSet::new(CHECKS.iter().map(|(pattern, _)| pattern)).unwrap();
+}
+
+// Messages of runtime errors, and the names of their checks, as named by UBSan
+// in summaries with `report_error_type=1`. The first match is used.
+const CHECKS: &[(&str, &str)] = &[
+    (r"^signed integer overflow", "signed-integer-overflow"),
+    (r"^unsigned integer overflow", "unsigned-integer-overflow"),
+    (
+        r"^negation of .* cannot be represented in type '?unsigned",
+        "unsigned-integer-overflow",
+    ),
+    (
+        r"^(?:negation|division) of .* cannot be represented",
+        "signed-integer-overflow",
+    ),
+    (r"^division by zero", "integer-divide-by-zero"),
+    (r"^shift exponent", "invalid-shift-exponent"),
+    (r"^left shift of", "invalid-shift-base"),
+    (r"^index .* out of bounds", "out-of-bounds-index"),
+    (
+        r"^applying zero offset to null pointer",
+        "nullptr-with-offset",
+    ),
+    (
+        r"^applying non-zero offset .* to null pointer",
+        "nullptr-with-nonzero-offset",
+    ),
+    (
+        r"^applying non-zero offset to non-null pointer .* produced null pointer",
+        "nullptr-after-nonzero-offset",
+    ),
+    (
+        r"^(?:pointer index expression with base|(?:addition|subtraction) of unsigned offset)",
+        "pointer-overflow",
+    ),
+    (r"null pointer of type", "null-pointer-use"),
+    (r"misaligned address", "misaligned-pointer-use"),
+    (
+        r"with insufficient space for an object",
+        "insufficient-object-size",
+    ),
+    (
+        r"which does not point to an object of type",
+        "dynamic-type-mismatch",
+    ),
+    (
+        r"through pointer to incorrect function type",
+        "function-type-mismatch",
+    ),
+    (
+        r"^variable length array bound evaluates to non-positive value",
+        "non-positive-vla-index",
+    ),
+    (r"is not a valid value for type '?bool", "invalid-bool-load"),
+    (r"is not a valid value for type", "invalid-enum-load"),
+    (
+        r"is outside the range of representable values",
+        "float-cast-overflow",
+    ),
+    (
+        r"^execution reached the end of a value-returning function",
+        "missing-return",
+    ),
+    (
+        r"^execution reached an unreachable program point",
+        "unreachable-call",
+    ),
+    (
+        r"^null pointer passed as argument .* declared to never be null",
+        "invalid-null-argument",
+    ),
+    (
+        r"^null pointer returned from function declared to never return null",
+        "invalid-null-return",
+    ),
+    (r"^implicit conversion from", "implicit-conversion"),
+    (
+        r"^passing zero to .*, which is not a valid argument",
+        "invalid-builtin-use",
+    ),
+];
+
+struct RuntimeError<'a> {
+    // Offset of the error's line in the log.
+    start: usize,
+    location: &'a str,
+    message: &'a str,
+}
+
+// The runtime errors of `text`, unless another sanitizer reported the crash.
+//
+// Control flow integrity violations are also logged as runtime errors, but are
+// left to the summaries of CFI.
+fn runtime_errors(text: &str) -> Vec<RuntimeError<'_>> {
+    let other_sanitizer = OTHER_SANITIZER_REGEX
+        .captures_iter(text)
+        .any(|captures| &captures["sanitizer"] != UBSAN);
+    if other_sanitizer {
+        return vec![];
+    }
+
+    RUNTIME_ERROR_REGEX
+        .captures_iter(text)
+        .filter(|captures| !captures["message"].starts_with("control flow integrity check"))
+        .map(|captures| RuntimeError {
+            start: captures.get(0).map(|m| m.start()).unwrap_or_default(),
+            location: captures
+                .name("location")
+                .map(|m| m.as_str())
+                .unwrap_or_default(),
+            message: captures
+                .name("message")
+                .map(|m| m.as_str())
+                .unwrap_or_default(),
+        })
+        .collect()
+}
+
+/// The check of the runtime error `message`.
+pub(crate) fn check_kind(message: &str) -> &'static str {
+    CHECK_SET
+        .matches(message)
+        .iter()
+        .next()
+        .map(|index| CHECKS[index].1)
+        .unwrap_or(GENERIC_CHECK)
+}
+
+/// Splits `text` into the UBSan findings it logs, each from its runtime error
+/// to the next, with its stack and summary. Logs of a single finding, or of
+/// the crash of another sanitizer, are not split.
+pub(crate) fn findings(text: &str) -> Vec<&str> {
+    let errors = runtime_errors(text);
+    if errors.len() < 2 {
+        return vec![];
+    }
+
+    let ends = errors
+        .iter()
+        .skip(1)
+        .map(|error| error.start)
+        .chain([text.len()]);
+
+    errors
+        .iter()
+        .zip(ends)
+        .map(|(error, end)| &text[error.start..end])
+        .collect()
+}
+
+/// The summary of the first UBSan finding of `text`, named by its check, and
+/// located at its runtime error.
+pub(crate) fn parse_summary(text: &str) -> Option<CrashLogSummary> {
+    let errors = runtime_errors(text);
+    let error = errors.first()?;
+
+    // Prefer the check named by UBSan, if any, to one found by its message.
+    let fault_type = SUMMARY_CHECK_REGEX
+        .captures(&text[error.start..])
+        .map(|captures| captures["check"].to_string())
+        .filter(|check| check != GENERIC_CHECK)
+        .unwrap_or_else(|| check_kind(error.message).to_string());
+
+    Some(CrashLogSummary {
+        summary: format!("{UBSAN}: {fault_type} {}", error.location),
+        sanitizer: UBSAN.to_string(),
+        fault_type,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::CrashLog;
+
+    // Several findings, as logged with
+    // `UBSAN_OPTIONS=print_stacktrace=1:halt_on_error=0`.
+    const MULTIPLE_FINDINGS: &str = r"INFO: Seed: 1977842421
+fuzz.c:12:9: runtime error: signed integer overflow: 2147483647 + 1 cannot be represented in type 'int'
+    #0 0x55a6e6c1b2d4 in check /src/fuzz.c:12:9
+    #1 0x55a6e6c1b3f1 in LLVMFuzzerTestOneInput /src/fuzz.c:24:3
+    #2 0x55a6e6b1a2c3 in fuzzer::Fuzzer::ExecuteCallback(unsigned char const*, unsigned long) (/out/fuzz+0x4a2c3)
+
+SUMMARY: UndefinedBehaviorSanitizer: undefined-behavior fuzz.c:12:9 in
+fuzz.c:16:10: runtime error: index 8 out of bounds for type 'int[8]'
+    #0 0x55a6e6c1b31a in lookup /src/fuzz.c:16:10
+    #1 0x55a6e6c1b402 in LLVMFuzzerTestOneInput /src/fuzz.c:25:3
+    #2 0x55a6e6b1a2c3 in fuzzer::Fuzzer::ExecuteCallback(unsigned char const*, unsigned long) (/out/fuzz+0x4a2c3)
+
+SUMMARY: UndefinedBehaviorSanitizer: undefined-behavior fuzz.c:16:10 in
+fuzz.c:12:9: runtime error: signed integer overflow: 2147483647 + 1 cannot be represented in type 'int'
+    #0 0x55a6e6c1b2d4 in check /src/fuzz.c:12:9
+    #1 0x55a6e6c1b3f1 in LLVMFuzzerTestOneInput /src/fuzz.c:24:3
+    #2 0x55a6e6b1a2c3 in fuzzer::Fuzzer::ExecuteCallback(unsigned char const*, unsigned long) (/out/fuzz+0x4a2c3)
+
+SUMMARY: UndefinedBehaviorSanitizer: undefined-behavior fuzz.c:12:9 in
+";
+
+    fn parse(name: &str, text: &str) -> CrashLog {
+        CrashLog::parse(text.to_string()).unwrap_or_else(|err| panic!("parsing {name}: {err:?}"))
+    }
+
+    #[test]
+    fn test_check_kinds() {
+        let test_cases = [
+            (
+                "ubsan_integer_overflow_addition",
+                include_str!(
+                    "../../libclusterfuzz/data/stack-traces/ubsan_integer_overflow_addition.txt"
+                ),
+                "signed-integer-overflow",
+                "../../ui/gfx/geometry/point.h:64:8",
+            ),
+            (
+                "ubsan_unsigned_integer_overflow",
+                include_str!(
+                    "../../libclusterfuzz/data/stack-traces/ubsan_unsigned_integer_overflow.txt"
+                ),
+                "unsigned-integer-overflow",
+                "hash.c:93:36",
+            ),
+            (
+                "ubsan_bad_cast_downcast",
+                include_str!("../../libclusterfuzz/data/stack-traces/ubsan_bad_cast_downcast.txt"),
+                "dynamic-type-mismatch",
+                "../../third_party/WebKit/Source/core/accessibility/AXMenuList.h:58:1",
+            ),
+            (
+                "ubsan_null_pointer_member_call",
+                include_str!(
+                    "../../libclusterfuzz/data/stack-traces/ubsan_null_pointer_member_call.txt"
+                ),
+                "null-pointer-use",
+                "../../base/trace_event/heap_profiler_heap_dump_writer.cc:205:53",
+            ),
+            (
+                "ubsan_object_size",
+                include_str!("../../libclusterfuzz/data/stack-traces/ubsan_object_size.txt"),
+                "insufficient-object-size",
+                "../../testing/libfuzzer/fuzzers/zlib_uncompress_fuzzer.cc:13:11",
+            ),
+            (
+                "ubsan_pointer_overflow",
+                include_str!("../../libclusterfuzz/data/stack-traces/ubsan_pointer_overflow.txt"),
+                "pointer-overflow",
+                "../../third_party/skia/src/core/SkRasterPipelineBlitter.cpp:480:54",
+            ),
+            (
+                "ubsan_non_positive_vla_bound_value",
+                include_str!(
+                    "../../libclusterfuzz/data/stack-traces/ubsan_non_positive_vla_bound_value.txt"
+                ),
+                "non-positive-vla-index",
+                "../../testing/libfuzzer/fuzzers/zlib_uncompress_fuzzer.cc:13:11",
+            ),
+            (
+                "ubsan_index_oob",
+                include_str!("../../libclusterfuzz/data/stack-traces/ubsan_index_oob.txt"),
+                "out-of-bounds-index",
+                "../../third_party/pdfium/core/fpdfapi/fpdf_page/fpdf_page_parser_old.cpp:298:3",
+            ),
+            (
+                "ubsan_misaligned_address",
+                include_str!("../../libclusterfuzz/data/stack-traces/ubsan_misaligned_address.txt"),
+                "misaligned-pointer-use",
+                "libavcodec/pnmdec.c:175:25",
+            ),
+            (
+                "ubsan_invalid_bool_value",
+                include_str!("../../libclusterfuzz/data/stack-traces/ubsan_invalid_bool_value.txt"),
+                "invalid-bool-load",
+                "src/tsm/tsm-screen.c:1321:8",
+            ),
+        ];
+
+        for (name, text, check, location) in test_cases {
+            let log = parse(name, text);
+
+            assert_eq!(log.sanitizer, UBSAN, "{name}");
+            assert_eq!(log.fault_type, check, "{name}");
+            assert_eq!(
+                log.summary,
+                format!("{UBSAN}: {check} {location}"),
+                "{name}"
+            );
+
+            // Each of these has a symbolized stack of its finding.
+            assert!(!log.call_stack.is_empty(), "{name}");
+            assert!(!log.minimized_stack.is_empty(), "{name}");
+        }
+    }
+
+    #[test]
+    fn test_check_kind() {
+        for (message, check) in [
+            ("division by zero", "integer-divide-by-zero"),
+            ("shift exponent 40 is too large for 32-bit type 'int'", "invalid-shift-exponent"),
+            ("left shift of negative value -1", "invalid-shift-base"),
+            ("negation of -2147483648 cannot be represented in type 'int'; cast to an unsigned type to negate this value to itself", "signed-integer-overflow"),
+            ("applying zero offset to null pointer", "nullptr-with-offset"),
+            ("load of value 7, which is not a valid value for type 'enum E'", "invalid-enum-load"),
+            ("execution reached the end of a value-returning function without returning a value", "missing-return"),
+            ("unsupported ubsan error that needs a new signature", GENERIC_CHECK),
+        ] {
+            assert_eq!(check_kind(message), check, "{message}");
+        }
+    }
+
+    #[test]
+    fn test_summary_check() {
+        // With `report_error_type=1`, the summary names the check.
+        let text =
+            "a.c:3:5: runtime error: load of value 2, which is not a valid value for type 'bool'\n\
+                    SUMMARY: UndefinedBehaviorSanitizer: invalid-bool-load a.c:3:5 in\n";
+        let log = parse("report_error_type", text);
+        assert_eq!(log.fault_type, "invalid-bool-load");
+        assert_eq!(log.summary, format!("{UBSAN}: invalid-bool-load a.c:3:5"));
+    }
+
+    #[test]
+    fn test_other_sanitizer() {
+        // The crash of the log is the ASan report, not the earlier finding.
+        let text = "a.c:3:5: runtime error: signed integer overflow: 2147483647 + 1 cannot be represented in type 'int'\n\
+                    ==42==ERROR: AddressSanitizer: heap-buffer-overflow on address 0x602000000011\n\
+                    SUMMARY: AddressSanitizer: heap-buffer-overflow a.c:9:3 in main\n";
+        assert!(parse_summary(text).is_none());
+        assert!(findings(text).is_empty());
+
+        let log = parse("asan", text);
+        assert_eq!(log.fault_type, "heap-buffer-overflow");
+    }
+
+    #[test]
+    fn test_multiple_findings() -> anyhow::Result<()> {
+        assert_eq!(findings(MULTIPLE_FINDINGS).len(), 3);
+
+        // The first finding is the crash of the log, with its own stack.
+        let log = parse("multiple", MULTIPLE_FINDINGS);
+        assert_eq!(log.fault_type, "signed-integer-overflow");
+        assert_eq!(
+            log.summary,
+            format!("{UBSAN}: signed-integer-overflow fuzz.c:12:9")
+        );
+        assert_eq!(log.call_stack.len(), 3);
+        assert_eq!(log.minimized_stack_function_names, ["check", "fuzz.c"]);
+        assert_eq!(log.text.as_deref(), Some(MULTIPLE_FINDINGS));
+
+        // The repeated finding is deduplicated.
+        let logs = CrashLog::parse_all(MULTIPLE_FINDINGS.to_string())?;
+        let found: Vec<_> = logs
+            .iter()
+            .map(|log| (log.fault_type.as_str(), log.summary.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    "signed-integer-overflow",
+                    "UndefinedBehaviorSanitizer: signed-integer-overflow fuzz.c:12:9"
+                ),
+                (
+                    "out-of-bounds-index",
+                    "UndefinedBehaviorSanitizer: out-of-bounds-index fuzz.c:16:10"
+                ),
+            ]
+        );
+        assert_eq!(logs[1].minimized_stack_function_names, ["lookup", "fuzz.c"]);
+        assert!(logs[1]
+            .text
+            .as_deref()
+            .unwrap()
+            .starts_with("fuzz.c:16:10: runtime error: index 8"));
+
+        // Logs of a single finding are parsed as a whole.
+        let text = include_str!("../../libclusterfuzz/data/stack-traces/ubsan_index_oob.txt");
+        let logs = CrashLog::parse_all(text.to_string())?;
+        assert_eq!(logs, [parse("ubsan_index_oob", text)]);
+
+        Ok(())
+    }
+}
@@ -5,7 +5,7 @@ use anyhow::{Context, Result};
 use base64::Engine;
 use bytes::Buf;
 use reqwest::{Client, Url};
-use reqwest_retry::SendRetry;
+use reqwest_retry::{circuit, RetryBudget, RetryPolicy, SendRetry};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::time::Duration;
 use uuid::Uuid;
@@ -14,6 +14,11 @@ pub const EMPTY_QUEUE_DELAY: Duration = Duration::from_secs(10);
 
 const BASE64: base64::engine::GeneralPurpose = base64::engine::general_purpose::STANDARD;
 
+// Shares the circuit of the storage queues of the process.
+fn retry_policy() -> RetryPolicy {
+    RetryPolicy::transient().with_circuit_breaker(circuit::storage().clone())
+}
+
 // <QueueMessagesList>
 // 	<QueueMessage>
 // 		<MessageId>7d35e47d-f58e-42da-ba4a-9e6ac7e1214d</MessageId>
@@ -65,9 +70,9 @@ impl AzureQueueMessage {
             url.query_pairs_mut()
                 .append_pair("popreceipt", &self.pop_receipt);
 
-            let http = Client::new();
+            let http = reqwest_retry::proxy::client();
             http.delete(url)
-                .send_retry_default()
+                .send_retry_policy(&retry_policy())
                 .await
                 .context("AzureQueueMessage.claim")?
                 .error_for_status()
@@ -86,9 +91,9 @@ impl AzureQueueMessage {
             url.query_pairs_mut()
                 .append_pair("popreceipt", &self.pop_receipt);
 
-            let http = Client::new();
+            let http = reqwest_retry::proxy::client();
             http.delete(url)
-                .send_retry_default()
+                .send_retry_policy(&retry_policy())
                 .await
                 .context("storage queue delete failed")?
                 .error_for_status()
@@ -121,7 +126,7 @@ pub struct AzureQueueClient {
 
 impl AzureQueueClient {
     pub fn new(queue_url: Url) -> Self {
-        let http = Client::new();
+        let http = reqwest_retry::proxy::client();
         let messages_url = {
             let queue_path = queue_url.path();
             let messages_path = format!("{queue_path}/messages");
@@ -133,6 +138,14 @@ impl AzureQueueClient {
     }
 
     pub async fn enqueue(&self, data: impl Serialize) -> Result<()> {
+        self.enqueue_with_budget(data, RetryBudget::STANDARD).await
+    }
+
+    pub async fn enqueue_with_budget(
+        &self,
+        data: impl Serialize,
+        budget: RetryBudget,
+    ) -> Result<()> {
         let serialized = serde_json::to_string(&data).unwrap();
         let body = quick_xml::se::to_string(&AzureQueueMessageSend {
             message_text: BASE64.encode(&serialized),
@@ -142,7 +155,7 @@ impl AzureQueueClient {
         self.http
             .post(self.messages_url.clone())
             .body(body)
-            .send_retry_default()
+            .send_retry_policy(&retry_policy().with_budget(budget))
             .await
             .context("storage queue enqueue failed")?
             .error_for_status()
@@ -154,7 +167,7 @@ impl AzureQueueClient {
         let response = self
             .http
             .get(self.messages_url.clone())
-            .send_retry_default()
+            .send_retry_policy(&retry_policy())
             .await
             .context("storage queue pop failed")?
             .error_for_status()
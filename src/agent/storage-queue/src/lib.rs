@@ -3,6 +3,7 @@
 
 use anyhow::{anyhow, Context, Result};
 use reqwest::Url;
+use reqwest_retry::RetryBudget;
 use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize};
 use std::time::Duration;
 use uuid::Uuid;
@@ -57,8 +58,19 @@ impl QueueClient {
     }
 
     pub async fn enqueue(&self, data: impl Serialize) -> Result<()> {
+        self.enqueue_with_budget(data, RetryBudget::STANDARD).await
+    }
+
+    /// Enqueue `data`, retrying within `budget`, if the queue is remote.
+    pub async fn enqueue_with_budget(
+        &self,
+        data: impl Serialize,
+        budget: RetryBudget,
+    ) -> Result<()> {
         match self {
-            QueueClient::AzureQueue(queue_client) => queue_client.enqueue(data).await,
+            QueueClient::AzureQueue(queue_client) => {
+                queue_client.enqueue_with_budget(data, budget).await
+            }
             QueueClient::FileQueueClient(queue_client) => queue_client.enqueue(data).await,
             QueueClient::Channel(queue_client) => queue_client.enqueue(data).await,
         }
@@ -0,0 +1,335 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Job objects, to treat a process and its descendants as one process tree.
+
+use std::{
+    collections::HashMap,
+    mem::{size_of, MaybeUninit},
+    ptr,
+    thread::JoinHandle,
+};
+
+use anyhow::{Context, Result};
+use windows::{
+    core::PCWSTR,
+    Win32::{
+        Foundation::{CloseHandle, HANDLE, INVALID_HANDLE_VALUE},
+        System::{
+            Diagnostics::ToolHelp::{
+                CreateToolhelp32Snapshot, Thread32First, Thread32Next, TH32CS_SNAPTHREAD,
+                THREADENTRY32,
+            },
+            JobObjects::{
+                AssignProcessToJobObject, CreateJobObjectW,
+                JobObjectAssociateCompletionPortInformation, JobObjectExtendedLimitInformation,
+                SetInformationJobObject, TerminateJobObject, JOBOBJECT_ASSOCIATE_COMPLETION_PORT,
+                JOBOBJECT_EXTENDED_LIMIT_INFORMATION, JOB_OBJECT_LIMIT_JOB_MEMORY,
+                JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE,
+            },
+            Threading::{
+                GetExitCodeProcess, OpenProcess, OpenThread, ResumeThread, INFINITE,
+                PROCESS_QUERY_LIMITED_INFORMATION, THREAD_SUSPEND_RESUME,
+            },
+            IO::{CreateIoCompletionPort, GetQueuedCompletionStatus, OVERLAPPED},
+        },
+    },
+};
+
+use crate::{handle::Handle, last_os_error};
+
+// Messages posted to the completion port of a job. For the process messages,
+// the overlapped pointer is the process ID.
+//
+// https://learn.microsoft.com/en-us/windows/win32/api/winnt/ns-winnt-jobobject_associate_completion_port
+const JOB_OBJECT_MSG_ACTIVE_PROCESS_ZERO: u32 = 4;
+const JOB_OBJECT_MSG_NEW_PROCESS: u32 = 6;
+const JOB_OBJECT_MSG_EXIT_PROCESS: u32 = 7;
+const JOB_OBJECT_MSG_ABNORMAL_EXIT_PROCESS: u32 = 8;
+const JOB_OBJECT_MSG_JOB_MEMORY_LIMIT: u32 = 10;
+
+// The exit code of the processes of a job killed for its memory limit.
+const MEMORY_LIMIT_EXIT_CODE: u32 = 1;
+
+/// The exit of a process of a job.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ProcessExit {
+    pub process_id: u32,
+
+    /// The exit code, unless the process exited before it could be opened.
+    pub exit_code: Option<u32>,
+
+    /// If the exit code is one the kernel considers abnormal, such as the
+    /// code of an unhandled exception.
+    pub abnormal: bool,
+}
+
+/// The processes of a job, once there are none left.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct JobReport {
+    pub exits: Vec<ProcessExit>,
+
+    /// If the job was killed for exceeding its memory limit.
+    pub memory_limit_exceeded: bool,
+}
+
+/// A job object, whose processes are all killed when it is terminated or
+/// dropped. Processes started by a process of the job are in the job too.
+pub struct Job {
+    job: Handle,
+    port: Handle,
+}
+
+impl Job {
+    pub fn new() -> Result<Self> {
+        let job = unsafe { CreateJobObjectW(None, PCWSTR::null()) }.context("CreateJobObjectW")?;
+        let job = Handle(job);
+        set_limits(&job, None)?;
+
+        let port = unsafe { CreateIoCompletionPort(INVALID_HANDLE_VALUE, HANDLE::default(), 0, 1) }
+            .context("CreateIoCompletionPort")?;
+        let port = Handle(port);
+
+        let association = JOBOBJECT_ASSOCIATE_COMPLETION_PORT {
+            CompletionKey: ptr::null_mut(),
+            CompletionPort: port.0,
+        };
+        unsafe {
+            SetInformationJobObject(
+                job.0,
+                JobObjectAssociateCompletionPortInformation,
+                ptr::addr_of!(association).cast(),
+                size_of::<JOBOBJECT_ASSOCIATE_COMPLETION_PORT>() as u32,
+            )
+        }
+        .ok()
+        .context("associating job completion port")?;
+
+        Ok(Self { job, port })
+    }
+
+    /// Limit the memory committed by all of the processes of the job together,
+    /// killing them all once it's exceeded.
+    pub fn set_memory_limit(&self, bytes: u64) -> Result<()> {
+        set_limits(&self.job, Some(bytes))
+    }
+
+    /// Add a process, and so the processes it starts from then on, to the job.
+    pub fn assign(&self, process_handle: HANDLE) -> Result<()> {
+        unsafe { AssignProcessToJobObject(self.job.0, process_handle) }
+            .ok()
+            .context("AssignProcessToJobObject")
+    }
+
+    /// Kill every process of the job.
+    pub fn terminate(&self, exit_code: u32) -> Result<()> {
+        unsafe { TerminateJobObject(self.job.0, exit_code) }
+            .ok()
+            .context("TerminateJobObject")
+    }
+
+    /// Collect the exits of the processes of the job, until there are none
+    /// left. The processes must be assigned before they can exit, so this
+    /// should be called after assigning a suspended process, and before
+    /// resuming it.
+    pub fn monitor(&self) -> JoinHandle<JobReport> {
+        let job = self.job.clone();
+        let port = self.port.clone();
+        std::thread::spawn(move || monitor_port(job, port))
+    }
+}
+
+fn set_limits(job: &Handle, memory: Option<u64>) -> Result<()> {
+    let mut limits = JOBOBJECT_EXTENDED_LIMIT_INFORMATION::default();
+    limits.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+    if let Some(memory) = memory {
+        limits.BasicLimitInformation.LimitFlags |= JOB_OBJECT_LIMIT_JOB_MEMORY;
+        limits.JobMemoryLimit = usize::try_from(memory).unwrap_or(usize::MAX);
+    }
+
+    unsafe {
+        SetInformationJobObject(
+            job.0,
+            JobObjectExtendedLimitInformation,
+            ptr::addr_of!(limits).cast(),
+            size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+        )
+    }
+    .ok()
+    .context("setting job limits")
+}
+
+fn monitor_port(job: Handle, port: Handle) -> JobReport {
+    // Opened when started, so that the exit code can be read once exited.
+    let mut processes: HashMap<u32, Handle> = HashMap::new();
+    let mut report = JobReport::default();
+
+    loop {
+        let mut message = 0;
+        let mut key = 0;
+        let mut overlapped: *mut OVERLAPPED = ptr::null_mut();
+        let ok = unsafe {
+            GetQueuedCompletionStatus(port.0, &mut message, &mut key, &mut overlapped, INFINITE)
+        };
+        if !ok.as_bool() {
+            log::error!("error reading job messages: {}", last_os_error());
+            return report;
+        }
+
+        let process_id = overlapped as usize as u32;
+        match message {
+            JOB_OBJECT_MSG_NEW_PROCESS => {
+                match unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, process_id) } {
+                    Ok(handle) => {
+                        processes.insert(process_id, Handle(handle));
+                    }
+                    Err(err) => log::debug!("unable to open job process {}: {}", process_id, err),
+                }
+            }
+            JOB_OBJECT_MSG_EXIT_PROCESS | JOB_OBJECT_MSG_ABNORMAL_EXIT_PROCESS => {
+                let abnormal = message == JOB_OBJECT_MSG_ABNORMAL_EXIT_PROCESS;
+
+                // In case both messages are posted for the same exit.
+                if let Some(exit) = report.exits.iter_mut().find(|e| e.process_id == process_id) {
+                    exit.abnormal |= abnormal;
+                    continue;
+                }
+
+                let exit_code = processes.remove(&process_id).and_then(|handle| {
+                    let mut code = 0;
+                    unsafe { GetExitCodeProcess(handle.0, &mut code) }
+                        .as_bool()
+                        .then_some(code)
+                });
+                report.exits.push(ProcessExit {
+                    process_id,
+                    exit_code,
+                    abnormal,
+                });
+            }
+            // Allocations past the limit only fail, which the target may
+            // survive, so the job is killed instead.
+            JOB_OBJECT_MSG_JOB_MEMORY_LIMIT => {
+                report.memory_limit_exceeded = true;
+                if let Err(err) = unsafe { TerminateJobObject(job.0, MEMORY_LIMIT_EXIT_CODE) }.ok()
+                {
+                    log::error!("unable to kill job over its memory limit: {}", err);
+                }
+            }
+            JOB_OBJECT_MSG_ACTIVE_PROCESS_ZERO => return report,
+            _ => {}
+        }
+    }
+}
+
+/// Resume every thread of a process started suspended, such as to first
+/// assign it to a job.
+pub fn resume_process(process_id: u32) -> Result<()> {
+    let snapshot = unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPTHREAD, 0) }
+        .context("CreateToolhelp32Snapshot")?;
+    let snapshot = Handle(snapshot);
+
+    let mut entry = MaybeUninit::<THREADENTRY32>::zeroed();
+    unsafe { (*entry.as_mut_ptr()).dwSize = size_of::<THREADENTRY32>() as u32 };
+
+    let mut more = unsafe { Thread32First(snapshot.0, entry.as_mut_ptr()) }.as_bool();
+    let mut resumed = 0;
+    while more {
+        let thread = unsafe { entry.assume_init_ref() };
+        if thread.th32OwnerProcessID == process_id {
+            let handle = unsafe { OpenThread(THREAD_SUSPEND_RESUME, false, thread.th32ThreadID) }
+                .context("OpenThread")?;
+            let result = unsafe { ResumeThread(handle) };
+            unsafe { CloseHandle(handle) };
+            if result == u32::MAX {
+                return Err(last_os_error()).context("ResumeThread");
+            }
+            resumed += 1;
+        }
+        more = unsafe { Thread32Next(snapshot.0, entry.as_mut_ptr()) }.as_bool();
+    }
+
+    if resumed == 0 {
+        anyhow::bail!("no threads found to resume for process {process_id}");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        os::windows::{io::AsRawHandle, process::CommandExt},
+        process::{Command, Stdio},
+        time::{Duration, Instant},
+    };
+
+    use windows::Win32::System::Threading::CREATE_SUSPENDED;
+
+    use super::*;
+
+    fn spawn_in_job(job: &Job, script: &str) -> Result<std::process::Child> {
+        let child = Command::new("cmd.exe")
+            .args(["/D", "/C", script])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .creation_flags(CREATE_SUSPENDED.0)
+            .spawn()?;
+        job.assign(HANDLE(child.as_raw_handle() as isize))?;
+        Ok(child)
+    }
+
+    #[test]
+    fn test_terminate_kills_descendants() -> Result<()> {
+        let job = Job::new()?;
+        let child = spawn_in_job(&job, "ping -n 60 127.0.0.1")?;
+        let monitor = job.monitor();
+        resume_process(child.id())?;
+
+        std::thread::sleep(Duration::from_secs(1));
+        let start = Instant::now();
+        job.terminate(1)?;
+
+        // Both `cmd` and `ping` are killed.
+        let exits = monitor.join().unwrap().exits;
+        assert!(start.elapsed() < Duration::from_secs(30));
+        assert_eq!(exits.len(), 2, "{exits:?}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_abnormal_exit_of_descendant() -> Result<()> {
+        let job = Job::new()?;
+        let child = spawn_in_job(&job, "cmd.exe /D /C exit -1073741819")?;
+        let monitor = job.monitor();
+        resume_process(child.id())?;
+
+        let exits = monitor.join().unwrap().exits;
+        let abnormal: Vec<_> = exits.iter().filter(|exit| exit.abnormal).collect();
+        assert_eq!(abnormal.len(), 1, "{exits:?}");
+        assert_eq!(abnormal[0].exit_code, Some(0xC0000005));
+        assert_ne!(abnormal[0].process_id, child.id());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_memory_limit_kills_job() -> Result<()> {
+        let job = Job::new()?;
+        job.set_memory_limit(256 * 1024 * 1024)?;
+        let child = spawn_in_job(
+            &job,
+            "powershell -NoProfile -Command \"$a = New-Object byte[] 1GB; Start-Sleep 60\"",
+        )?;
+        let monitor = job.monitor();
+        resume_process(child.id())?;
+
+        let start = Instant::now();
+        let report = monitor.join().unwrap();
+        assert!(report.memory_limit_exceeded, "{report:?}");
+        assert!(start.elapsed() < Duration::from_secs(30));
+
+        Ok(())
+    }
+}
@@ -5,6 +5,7 @@
 
 pub mod file;
 pub mod handle;
+pub mod job;
 pub mod memory;
 pub mod pipe_handle;
 pub mod process;
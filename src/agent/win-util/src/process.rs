@@ -13,13 +13,21 @@ use windows::Win32::{
     Foundation::{CloseHandle, FALSE, HANDLE, INVALID_HANDLE_VALUE},
     Security::{GetTokenInformation, TokenElevation, TOKEN_ELEVATION, TOKEN_QUERY},
     System::{
-        Diagnostics::Debug::{FlushInstructionCache, ReadProcessMemory, WriteProcessMemory},
+        Diagnostics::{
+            Debug::{FlushInstructionCache, ReadProcessMemory, WriteProcessMemory},
+            ToolHelp::{
+                CreateToolhelp32Snapshot, Process32FirstW, Process32NextW, PROCESSENTRY32W,
+                TH32CS_SNAPPROCESS,
+            },
+        },
         Threading::{
             GetCurrentProcess, GetProcessId, IsWow64Process, OpenProcessToken, TerminateProcess,
         },
     },
 };
 
+use crate::handle::Handle;
+
 pub fn is_elevated() -> bool {
     fn is_elevated_impl() -> Result<bool> {
         let mut process_token = INVALID_HANDLE_VALUE;
@@ -178,6 +186,28 @@ pub fn id(process_handle: HANDLE) -> u32 {
     unsafe { GetProcessId(process_handle) }
 }
 
+/// The ID of the process which created the process `process_id`, which may
+/// have since exited.
+pub fn parent_id(process_id: u32) -> Result<u32> {
+    let snapshot = unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0) }
+        .context("CreateToolhelp32Snapshot")?;
+    let snapshot = Handle(snapshot);
+
+    let mut entry = MaybeUninit::<PROCESSENTRY32W>::zeroed();
+    unsafe { (*entry.as_mut_ptr()).dwSize = size_of::<PROCESSENTRY32W>() as u32 };
+
+    let mut more = unsafe { Process32FirstW(snapshot.0, entry.as_mut_ptr()) }.as_bool();
+    while more {
+        let process = unsafe { entry.assume_init_ref() };
+        if process.th32ProcessID == process_id {
+            return Ok(process.th32ParentProcessID);
+        }
+        more = unsafe { Process32NextW(snapshot.0, entry.as_mut_ptr()) }.as_bool();
+    }
+
+    anyhow::bail!("no process found with ID {process_id}");
+}
+
 pub fn is_wow64_process(process_handle: HANDLE) -> bool {
     #[cfg(target_arch = "x86_64")] // break build on ARM64
     fn is_wow64_process_impl(process_handle: HANDLE) -> Result<bool> {